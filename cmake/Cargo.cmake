@@ -89,6 +89,11 @@ function(add_cargo_library target package)
 	set(multiValueArgs FEATURES)
 	cmake_parse_arguments(PARSE_ARGV 2 ARG "${options}" "${singleValueArgs}" "${multiValueArgs}")
 
+	# The Rust bindings have to agree with the C code about how big tiles are.
+	if(DRAWDANCE_TILE_SIZE AND NOT DRAWDANCE_TILE_SIZE EQUAL 64)
+		list(APPEND ARG_FEATURES "drawdance/tile-${DRAWDANCE_TILE_SIZE}")
+	endif()
+
 	if(CARGO_TRIPLE)
 		set(triple "${CARGO_TRIPLE}")
 	elseif(CMAKE_CROSSCOMPILING)
@@ -25,3 +25,9 @@ endif()
 add_feature_info("Image library implementation (IMAGE_IMPL)" ON ${IMAGE_IMPL})
 add_feature_info("File I/O implementation (FILE_IO_IMPL)" ON ${FILE_IO_IMPL})
 add_feature_info("ZIP implementation (ZIP_IMPL)" ON ${ZIP_IMPL})
+
+set(DRAWDANCE_TILE_SIZE 64 CACHE STRING "Tile size in pixels, builds with different sizes can't share sessions or recordings (32, 64)")
+if(NOT DRAWDANCE_TILE_SIZE MATCHES "^(32|64)$")
+    message(FATAL_ERROR "DRAWDANCE_TILE_SIZE must be 32 or 64, not '${DRAWDANCE_TILE_SIZE}'")
+endif()
+add_feature_info("Tile size (DRAWDANCE_TILE_SIZE)" ON ${DRAWDANCE_TILE_SIZE})
@@ -31,6 +31,9 @@ if(NOT ANDROID)
 
 	option(TESTS "Build unit tests" OFF)
 	add_feature_info("Unit tests (TESTS)" TESTS "")
+
+	option(FUZZ "Build fuzz targets (needs Clang)" OFF)
+	add_feature_info("Fuzz targets (FUZZ)" FUZZ "")
 else()
 	# CMake allows unexposed options to be enabled
 	set(SERVER OFF CACHE BOOL "" FORCE)
@@ -38,6 +41,7 @@ else()
 	set(BUILTINSERVER OFF CACHE BOOL "" FORCE)
 	set(TOOLS OFF CACHE BOOL "" FORCE)
 	set(TESTS OFF CACHE BOOL "" FORCE)
+	set(FUZZ OFF CACHE BOOL "" FORCE)
 endif()
 
 if(UNIX AND NOT APPLE AND NOT ANDROID)
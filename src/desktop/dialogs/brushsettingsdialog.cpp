@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 #include "desktop/dialogs/brushsettingsdialog.h"
 #include "desktop/utils/widgetutils.h"
+#include "desktop/widgets/colorbutton.h"
 #include "desktop/widgets/curvewidget.h"
 #include "desktop/widgets/kis_slider_spin_box.h"
 #include "desktop/widgets/toolmessage.h"
@@ -38,12 +39,19 @@ struct BrushSettingsDialog::Private {
 	QComboBox *brushModeCombo;
 	QLabel *eraseModeLabel;
 	QComboBox *eraseModeCombo;
+	QLabel *eraseTargetLabel;
+	QComboBox *eraseTargetCombo;
+	widgets::ColorButton *eraseColorButton;
 	QLabel *paintModeLabel;
 	QComboBox *paintModeCombo;
 	QCheckBox *eraseModeBox;
 	QCheckBox *colorPickBox;
 	QCheckBox *lockAlphaBox;
+	QCheckBox *pixelPerfectBox;
+	QCheckBox *pickupMergedBox;
 	KisSliderSpinBox *spacingSpinner;
+	KisSliderSpinBox *airbrushRateSpinner;
+	KisSliderSpinBox *airbrushFlowSpinner;
 	QComboBox *stabilizationModeCombo;
 	KisSliderSpinBox *stabilizerSpinner;
 	KisSliderSpinBox *smoothingSpinner;
@@ -65,12 +73,15 @@ struct BrushSettingsDialog::Private {
 	Dynamics classicSmudgeDynamics;
 	KisSliderSpinBox *classicSmudgingMinSpinner;
 	widgets::CurveWidget *classicSmudgingCurve;
+	Dynamics classicColorDynamics;
+	widgets::ColorButton *classicColor2Button;
 	MyPaintPage myPaintPages[MYPAINT_BRUSH_SETTINGS_COUNT];
 	int generalPageIndex;
 	int classicSizePageIndex;
 	int classicOpacityPageIndex;
 	int classicHardnessPageIndex;
 	int classicSmudgePageIndex;
+	int classicColorPageIndex;
 	int mypaintPageIndexes[MYPAINT_BRUSH_SETTINGS_COUNT];
 	DP_BrushShape lastShape;
 	brushes::ActiveBrush brush;
@@ -138,8 +149,11 @@ void BrushSettingsDialog::updateUiFromActiveBrush(
 	case DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND:
 	case DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE:
 	case DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND:
+	case DP_BRUSH_SHAPE_CLASSIC_STAMP:
 		if(shapeChanged) {
-			addClassicCategories(shape == DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND);
+			addClassicCategories(
+				shape != DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND &&
+				shape != DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE);
 		}
 		updateUiFromClassicBrush();
 		break;
@@ -215,6 +229,8 @@ void BrushSettingsDialog::buildDialogUi()
 		d->stackedWidget->addWidget(buildClassicHardnessPageUi());
 	d->classicSmudgePageIndex =
 		d->stackedWidget->addWidget(buildClassicSmudgingPageUi());
+	d->classicColorPageIndex =
+		d->stackedWidget->addWidget(buildClassicColorPageUi());
 	for(int setting = 0; setting < MYPAINT_BRUSH_SETTINGS_COUNT; ++setting) {
 		d->mypaintPageIndexes[setting] =
 			shouldIncludeMyPaintSetting(setting)
@@ -297,6 +313,33 @@ QWidget *BrushSettingsDialog::buildGeneralPageUi()
 			emitChange();
 		});
 
+	d->eraseTargetLabel = new QLabel{tr("Erase To:"), widget};
+	QHBoxLayout *eraseTargetLayout = new QHBoxLayout;
+	layout->addRow(d->eraseTargetLabel, eraseTargetLayout);
+	d->eraseTargetCombo = new QComboBox{widget};
+	eraseTargetLayout->addWidget(d->eraseTargetCombo, 1);
+	d->eraseTargetCombo->addItem(
+		tr("Transparency"), int(DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT));
+	d->eraseTargetCombo->addItem(
+		tr("Color"), int(DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR));
+	connect(
+		d->eraseTargetCombo,
+		QOverload<int>::of(&QComboBox::currentIndexChanged), [this](int index) {
+			d->brush.classic().erase_target = DP_ClassicBrushEraseTarget(
+				d->eraseTargetCombo->itemData(index).toInt());
+			emitChange();
+		});
+	d->eraseColorButton = new widgets::ColorButton{widget, Qt::white};
+	eraseTargetLayout->addWidget(d->eraseColorButton);
+	d->eraseColorButton->setToolTip(
+		tr("Color to paint with instead of erasing to transparency."));
+	connect(
+		d->eraseColorButton, &widgets::ColorButton::colorChanged,
+		[this](const QColor &color) {
+			d->brush.classic().setEraseQColor(color);
+			emitChange();
+		});
+
 	d->paintModeLabel = new QLabel{tr("Paint Mode:"), widget};
 	d->paintModeCombo = new QComboBox{widget};
 	layout->addRow(d->paintModeLabel, d->paintModeCombo);
@@ -331,6 +374,13 @@ QWidget *BrushSettingsDialog::buildGeneralPageUi()
 		emitChange();
 	});
 
+	d->pixelPerfectBox = new QCheckBox{tr("Pixel-Perfect Lines"), widget};
+	layout->addRow(d->pixelPerfectBox);
+	connect(d->pixelPerfectBox, &QCheckBox::stateChanged, [this](int state) {
+		d->brush.classic().pixel_perfect = state != Qt::Unchecked;
+		emitChange();
+	});
+
 	d->lockAlphaBox = new QCheckBox{tr("Lock Alpha (Recolor Mode)"), widget};
 	layout->addRow(d->lockAlphaBox);
 	connect(d->lockAlphaBox, &QCheckBox::stateChanged, [this](int state) {
@@ -338,6 +388,18 @@ QWidget *BrushSettingsDialog::buildGeneralPageUi()
 		emitChange();
 	});
 
+	d->pickupMergedBox =
+		new QCheckBox{tr("Pick Up Color from Merged Image"), widget};
+	layout->addRow(d->pickupMergedBox);
+	connect(d->pickupMergedBox, &QCheckBox::stateChanged, [this](int state) {
+		DP_BrushPickupMode pickupMode = state == Qt::Unchecked
+											? DP_BRUSH_PICKUP_MODE_LAYER
+											: DP_BRUSH_PICKUP_MODE_MERGED;
+		d->brush.classic().pickup_mode = pickupMode;
+		d->brush.myPaint().brush().pickup_mode = pickupMode;
+		emitChange();
+	});
+
 	d->spacingSpinner = new KisSliderSpinBox{widget};
 	layout->addRow(d->spacingSpinner);
 	d->spacingSpinner->setRange(1, 999);
@@ -352,6 +414,32 @@ QWidget *BrushSettingsDialog::buildGeneralPageUi()
 			emitChange();
 		});
 
+	d->airbrushRateSpinner = new KisSliderSpinBox{widget};
+	layout->addRow(d->airbrushRateSpinner);
+	d->airbrushRateSpinner->setRange(0, 200);
+	d->airbrushRateSpinner->setSingleStep(1);
+	d->airbrushRateSpinner->setPrefix(tr("Airbrush Rate: "));
+	d->airbrushRateSpinner->setSuffix(tr(" dabs/s"));
+	connect(
+		d->airbrushRateSpinner, QOverload<int>::of(&QSpinBox::valueChanged),
+		[this](int value) {
+			d->brush.classic().airbrush_rate = value;
+			d->airbrushFlowSpinner->setEnabled(value > 0);
+			emitChange();
+		});
+
+	d->airbrushFlowSpinner = new KisSliderSpinBox{widget};
+	layout->addRow(d->airbrushFlowSpinner);
+	d->airbrushFlowSpinner->setRange(1, 100);
+	d->airbrushFlowSpinner->setPrefix(tr("Airbrush Flow: "));
+	d->airbrushFlowSpinner->setSuffix(tr("%"));
+	connect(
+		d->airbrushFlowSpinner, QOverload<int>::of(&QSpinBox::valueChanged),
+		[this](int value) {
+			d->brush.classic().airbrush_flow = value / 100.0;
+			emitChange();
+		});
+
 	d->stabilizationModeCombo = new QComboBox{widget};
 	layout->addRow(tr("Stabilization Mode:"), d->stabilizationModeCombo);
 	d->stabilizationModeCombo->addItem(
@@ -638,6 +726,44 @@ QWidget *BrushSettingsDialog::buildClassicSmudgingPageUi()
 	return scroll;
 }
 
+QWidget *BrushSettingsDialog::buildClassicColorPageUi()
+{
+	QScrollArea *scroll = new QScrollArea{this};
+	QWidget *widget = new QWidget{scroll};
+	scroll->setWidget(widget);
+	scroll->setWidgetResizable(true);
+	utils::initKineticScrolling(scroll);
+
+	QVBoxLayout *layout = new QVBoxLayout;
+	widget->setLayout(layout);
+
+	d->classicColorDynamics = buildClassicDynamics(
+		layout, &brushes::ClassicBrush::setColorDynamicType,
+		&brushes::ClassicBrush::setColorMaxVelocity,
+		&brushes::ClassicBrush::setColorMaxDistance);
+
+	QHBoxLayout *color2Layout = new QHBoxLayout;
+	layout->addLayout(color2Layout);
+	color2Layout->addWidget(new QLabel{tr("Secondary Color:"), widget});
+	d->classicColor2Button = new widgets::ColorButton{widget, Qt::transparent};
+	color2Layout->addWidget(d->classicColor2Button);
+	d->classicColor2Button->setAlpha(true);
+	d->classicColor2Button->setToolTip(
+		tr("Color to blend towards, make it transparent to fade out instead."));
+	color2Layout->addStretch();
+	connect(
+		d->classicColor2Button, &widgets::ColorButton::colorChanged,
+		[this](const QColor &color) {
+			d->brush.classic().setQColor2(color);
+			emitChange();
+		});
+
+	layout->addSpacerItem(
+		new QSpacerItem{0, 0, QSizePolicy::Minimum, QSizePolicy::Expanding});
+
+	return scroll;
+}
+
 BrushSettingsDialog::Dynamics BrushSettingsDialog ::buildClassicDynamics(
 	QVBoxLayout *layout,
 	void (brushes::ClassicBrush::*setType)(DP_ClassicBrushDynamicType),
@@ -652,6 +778,9 @@ BrushSettingsDialog::Dynamics BrushSettingsDialog ::buildClassicDynamics(
 		tr("Velocity dynamics"), int(DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY));
 	typeCombo->addItem(
 		tr("Distance dynamics"), int(DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE));
+	typeCombo->addItem(
+		tr("Pressure and velocity dynamics"),
+		int(DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY));
 	connect(
 		typeCombo, QOverload<int>::of(&QComboBox::currentIndexChanged),
 		[this, typeCombo, setType](int index) {
@@ -684,8 +813,8 @@ BrushSettingsDialog::Dynamics BrushSettingsDialog ::buildClassicDynamics(
 	QPushButton *applyVelocityToAllButton =
 		new QPushButton(QIcon::fromTheme("fill-color"), tr("Apply to All"));
 	applyVelocityToAllButton->setToolTip(
-		tr("Set the maximum velocity for Size, Opacity, Hardness and Smudging "
-		   "at once."));
+		tr("Set the maximum velocity for Size, Opacity, Hardness, Smudging and "
+		   "Color at once."));
 	connect(applyVelocityToAllButton, &QPushButton::clicked, [=]() {
 		brushes::ClassicBrush &b = d->brush.classic();
 		float maxVelocity = float(velocitySlider->value()) / 100.0f;
@@ -693,6 +822,7 @@ BrushSettingsDialog::Dynamics BrushSettingsDialog ::buildClassicDynamics(
 		b.setOpacityMaxVelocity(maxVelocity);
 		b.setHardnessMaxVelocity(maxVelocity);
 		b.setSmudgeMaxVelocity(maxVelocity);
+		b.setColorMaxVelocity(maxVelocity);
 		emitChange();
 		ToolMessage::showText(
 			tr("Maximum velocity set for all settings in this brush."));
@@ -701,8 +831,8 @@ BrushSettingsDialog::Dynamics BrushSettingsDialog ::buildClassicDynamics(
 	QPushButton *applyDistanceToAllButton =
 		new QPushButton(QIcon::fromTheme("fill-color"), tr("Apply to All"));
 	applyDistanceToAllButton->setToolTip(
-		tr("Set the maximum distance for Size, Opacity, Hardness and Smudging "
-		   "at once."));
+		tr("Set the maximum distance for Size, Opacity, Hardness, Smudging and "
+		   "Color at once."));
 	connect(applyDistanceToAllButton, &QPushButton::clicked, [=]() {
 		brushes::ClassicBrush &b = d->brush.classic();
 		float maxDistance = float(distanceSlider->value());
@@ -710,6 +840,7 @@ BrushSettingsDialog::Dynamics BrushSettingsDialog ::buildClassicDynamics(
 		b.setOpacityMaxDistance(maxDistance);
 		b.setHardnessMaxDistance(maxDistance);
 		b.setSmudgeMaxDistance(maxDistance);
+		b.setColorMaxDistance(maxDistance);
 		emitChange();
 		ToolMessage::showText(
 			tr("Maximum distance set for all settings in this brush."));
@@ -858,6 +989,9 @@ void BrushSettingsDialog::addClassicCategories(bool withHardness)
 	addCategory(
 		tr("Smudging"), tr("Blending of colors on the layer being drawn on."),
 		d->classicSmudgePageIndex);
+	addCategory(
+		tr("Color"), tr("Blending the brush color towards a secondary color."),
+		d->classicColorPageIndex);
 }
 
 void BrushSettingsDialog::addMyPaintCategories()
@@ -884,6 +1018,15 @@ void BrushSettingsDialog::updateUiFromClassicBrush()
 	d->eraseModeLabel->setVisible(classic.erase);
 	d->eraseModeCombo->setVisible(classic.erase);
 	setComboBoxIndexByData(d->eraseModeCombo, int(classic.erase_mode));
+	bool eraseToColor =
+		classic.erase_target == DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR;
+	d->eraseTargetLabel->setVisible(classic.erase);
+	d->eraseTargetCombo->setVisible(classic.erase);
+	setComboBoxIndexByData(d->eraseTargetCombo, int(classic.erase_target));
+	d->eraseColorButton->setVisible(classic.erase);
+	d->eraseColorButton->setEnabled(eraseToColor);
+	d->eraseColorButton->setColor(classic.eraseQColor());
+	d->eraseModeCombo->setEnabled(!eraseToColor);
 
 	d->eraseModeBox->setChecked(classic.erase);
 
@@ -891,7 +1034,13 @@ void BrushSettingsDialog::updateUiFromClassicBrush()
 	d->colorPickBox->setEnabled(
 		DP_classic_brush_blend_mode(&classic) != DP_BLEND_MODE_ERASE);
 	d->colorPickBox->setVisible(true);
+	d->pixelPerfectBox->setChecked(classic.pixel_perfect);
+	d->pixelPerfectBox->setVisible(
+		classic.shape == DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND ||
+		classic.shape == DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE);
 	d->lockAlphaBox->setVisible(false);
+	d->pickupMergedBox->setChecked(
+		classic.pickup_mode == DP_BRUSH_PICKUP_MODE_MERGED);
 
 	bool haveSmudge = classic.smudge.max > 0.0f;
 	d->paintModeCombo->setCurrentIndex(
@@ -901,6 +1050,12 @@ void BrushSettingsDialog::updateUiFromClassicBrush()
 	d->spacingSpinner->setValue(classic.spacing * 100.0 + 0.5);
 	d->spacingSpinner->setVisible(true);
 
+	d->airbrushRateSpinner->setValue(classic.airbrush_rate + 0.5);
+	d->airbrushRateSpinner->setVisible(true);
+	d->airbrushFlowSpinner->setValue(classic.airbrush_flow * 100.0 + 0.5);
+	d->airbrushFlowSpinner->setEnabled(classic.airbrush_rate > 0.0f);
+	d->airbrushFlowSpinner->setVisible(true);
+
 	d->classicSizeSpinner->setValue(classic.size.max);
 	bool haveSizeDynamics = updateClassicBrushDynamics(
 		d->classicSizeDynamics, classic.size_dynamic);
@@ -933,6 +1088,11 @@ void BrushSettingsDialog::updateUiFromClassicBrush()
 	d->classicSmudgingMinSpinner->setEnabled(haveSmudgeDynamics);
 	d->classicSmudgingCurve->setCurve(classic.smudgeCurve());
 	d->classicSmudgingCurve->setEnabled(haveSmudgeDynamics);
+
+	bool haveColorDynamics = updateClassicBrushDynamics(
+		d->classicColorDynamics, classic.color_dynamic);
+	d->classicColor2Button->setColor(classic.qColor2());
+	d->classicColor2Button->setEnabled(haveColorDynamics);
 }
 
 bool BrushSettingsDialog::updateClassicBrushDynamics(
@@ -942,7 +1102,8 @@ bool BrushSettingsDialog::updateClassicBrushDynamics(
 	setComboBoxIndexByData(dynamics.typeCombo, type);
 
 	dynamics.velocitySlider->setValue(int(brush.max_velocity * 100.0f + 0.5));
-	bool isVelocity = type == DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY;
+	bool isVelocity = type == DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY ||
+					  type == DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY;
 	dynamics.velocitySlider->setEnabled(isVelocity);
 	dynamics.velocitySlider->setVisible(isVelocity);
 	dynamics.applyVelocityToAllButton->setEnabled(isVelocity);
@@ -964,13 +1125,21 @@ void BrushSettingsDialog::updateUiFromMyPaintBrush()
 
 	d->brushModeLabel->setVisible(false);
 	d->brushModeCombo->setVisible(false);
+	d->eraseTargetLabel->setVisible(false);
+	d->eraseTargetCombo->setVisible(false);
+	d->eraseColorButton->setVisible(false);
 	d->colorPickBox->setVisible(false);
+	d->pixelPerfectBox->setVisible(false);
 	d->spacingSpinner->setVisible(false);
+	d->airbrushRateSpinner->setVisible(false);
+	d->airbrushFlowSpinner->setVisible(false);
 
 	d->paintModeCombo->setCurrentIndex(brush.incremental ? 0 : 1);
 	d->eraseModeBox->setChecked(brush.erase);
 	d->lockAlphaBox->setChecked(brush.lock_alpha);
 	d->lockAlphaBox->setVisible(true);
+	d->pickupMergedBox->setChecked(
+		brush.pickup_mode == DP_BRUSH_PICKUP_MODE_MERGED);
 
 	for(int setting = 0; setting < MYPAINT_BRUSH_SETTINGS_COUNT; ++setting) {
 		if(shouldIncludeMyPaintSetting(setting)) {
@@ -50,6 +50,7 @@ private:
 	QWidget *buildClassicOpacityPageUi();
 	QWidget *buildClassicHardnessPageUi();
 	QWidget *buildClassicSmudgingPageUi();
+	QWidget *buildClassicColorPageUi();
 	Dynamics buildClassicDynamics(
 		QVBoxLayout *layout,
 		void (brushes::ClassicBrush::*setType)(DP_ClassicBrushDynamicType),
@@ -1,7 +1,6 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 extern "C" {
-#include <dpcommon/memory_pool.h>
 #include <dpengine/tile.h>
 }
 
@@ -34,11 +33,11 @@ NetStats::~NetStats()
 
 void NetStats::updateMemoryUsage()
 {
-	DP_MemoryPoolStatistics mps = DP_tile_memory_usage();
-	size_t tileElementsTotal = mps.buckets_len * mps.bucket_el_count;
-	size_t tileElementsUsed = tileElementsTotal - mps.el_free;
-	size_t tileBytesTotal = tileElementsTotal * mps.el_size;
-	size_t tileBytesUsed = tileElementsUsed * mps.el_size;
+	DP_TileMemoryStatistics tms = DP_tile_memory_usage();
+	size_t tileElementsUsed = tms.tiles_used;
+	size_t tileElementsTotal = tms.tiles_used + tms.tiles_cached;
+	size_t tileBytesTotal = tileElementsTotal * tms.tile_size;
+	size_t tileBytesUsed = tileElementsUsed * tms.tile_size;
 	m_ui->tilesLabel->setText(
 		QStringLiteral("%1 / %2").arg(tileElementsUsed).arg(tileElementsTotal));
 	m_ui->tileMemoryLabel->setText(QStringLiteral("%1 / %2").arg(
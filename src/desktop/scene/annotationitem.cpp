@@ -12,10 +12,12 @@ AnnotationItem::AnnotationItem(int id, QGraphicsItem *parent)
 	: QGraphicsItem(parent),
 	  m_id(id),
 	  m_valign(0),
+	  m_border(0),
 	  m_color(Qt::transparent),
 	  m_highlight(false),
 	  m_showborder(false),
-	  m_protect(false)
+	  m_protect(false),
+	  m_autoGrow(false)
 {
 }
 
@@ -50,6 +52,14 @@ void AnnotationItem::setValign(int valign)
 	}
 }
 
+void AnnotationItem::setBorder(int border)
+{
+	if(m_border != border) {
+		m_border = border;
+		update();
+	}
+}
+
 /**
  * Highlight is used to indicate the selected annotation.
  * @param hl
@@ -89,6 +99,17 @@ void AnnotationItem::paint(QPainter *painter, const QStyleOptionGraphicsItem *op
 
 	painter->fillRect(m_rect, m_color);
 
+	if(m_border > 0) {
+		QColor borderColor = m_color;
+		borderColor.setAlpha(255);
+		qreal half = m_border / 2.0;
+		QPen pen(borderColor, m_border);
+		pen.setJoinStyle(Qt::MiterJoin);
+		painter->setPen(pen);
+		painter->setBrush(Qt::NoBrush);
+		painter->drawRect(m_rect.adjusted(half, half, -half, -half));
+	}
+
 	paintHiddenBorder(painter);
 
 	if(m_highlight) {
@@ -150,7 +171,7 @@ QImage AnnotationItem::toImage() const
 	img.fill(0);
 	QPainter painter(&img);
 	utils::paintAnnotation(
-		&painter, m_rect.size().toSize(), m_color, text(), m_valign);
+		&painter, m_rect.size().toSize(), m_color, text(), m_valign, m_border);
 	return img;
 }
 
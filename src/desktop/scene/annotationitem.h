@@ -44,10 +44,19 @@ public:
 
 	int valign() const { return m_valign; }
 
+	//! Set border width, drawn in the opaque background color
+	void setBorder(int border);
+
+	int border() const { return m_border; }
+
 	//! Set the "protected" flag
 	void setProtect(bool protect) { m_protect = protect; }
 	bool protect() const { return m_protect; }
 
+	//! Set the "auto-grow" flag, fitting the height to the content on edit
+	void setAutoGrow(bool autoGrow) { m_autoGrow = autoGrow; }
+	bool autoGrow() const { return m_autoGrow; }
+
 	//! Highlight this item
 	void setHighlight(bool h);
 
@@ -69,6 +78,7 @@ private:
 
 	int m_id;
 	int m_valign;
+	int m_border;
 	QRectF m_rect;
 	QColor m_color;
 	QTextDocument m_doc;
@@ -76,6 +86,7 @@ private:
 	bool m_highlight;
 	bool m_showborder;
 	bool m_protect;
+	bool m_autoGrow;
 };
 
 }
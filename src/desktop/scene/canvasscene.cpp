@@ -17,6 +17,7 @@
 #include <QDebug>
 #include <QGraphicsItemGroup>
 #include <QTimer>
+#include <QVector>
 #include <utility>
 #ifdef HAVE_EMULATED_BITMAP_CURSOR
 #	include "desktop/scene/cursoritem.h"
@@ -329,6 +330,8 @@ void CanvasScene::annotationsChanged(const drawdance::AnnotationList &al)
 	}
 
 	int count = al.count();
+	QVector<AnnotationItem *> stack;
+	stack.reserve(count);
 	for(int i = 0; i < count; ++i) {
 		drawdance::Annotation a = al.at(i);
 		int id = a.id();
@@ -347,6 +350,14 @@ void CanvasScene::annotationsChanged(const drawdance::AnnotationList &al)
 		ai->setColor(a.backgroundColor());
 		ai->setProtect(a.protect());
 		ai->setValign(a.valign());
+		ai->setBorder(a.border());
+		ai->setAutoGrow(a.autoGrow());
+		stack.append(ai);
+	}
+
+	// Stack the items in list order, later annotations go on top.
+	for(int i = count - 1; i > 0; --i) {
+		stack[i - 1]->stackBefore(stack[i]);
 	}
 
 	for(AnnotationItem *ai : annotationItems) {
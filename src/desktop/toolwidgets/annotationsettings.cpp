@@ -8,6 +8,7 @@
 #include "libclient/canvas/userlist.h"
 #include "libclient/net/client.h"
 #include "libclient/tools/toolcontroller.h"
+#include "libclient/utils/annotations.h"
 #include "ui_textsettings.h"
 #include <QActionGroup>
 #include <QIcon>
@@ -19,6 +20,7 @@ namespace tools {
 
 static const char *HALIGN_PROP = "HALIGN";
 static const char *VALIGN_PROP = "VALIGN";
+static const char *ORDER_PROP = "ORDER";
 
 AnnotationSettings::AnnotationSettings(ToolController *ctrl, QObject *parent)
 	: ToolSettings(ctrl, parent)
@@ -59,6 +61,22 @@ QWidget *AnnotationSettings::createUiWidget(QWidget *parent)
 		new QAction(QIcon::fromTheme("list-remove"), tr("Delete"), this);
 	m_ui->deleteButton->setDefaultAction(deleteAction);
 
+	// Stacking order options
+	QMenu *orderMenu = new QMenu(parent);
+	orderMenu->addAction(QIcon::fromTheme("go-top"), tr("Bring to Front"))
+		->setProperty(ORDER_PROP, DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT);
+	orderMenu->addAction(QIcon::fromTheme("go-up"), tr("Raise"))
+		->setProperty(ORDER_PROP, DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE);
+	orderMenu->addAction(QIcon::fromTheme("go-down"), tr("Lower"))
+		->setProperty(ORDER_PROP, DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER);
+	orderMenu->addAction(QIcon::fromTheme("go-bottom"), tr("Send to Back"))
+		->setProperty(ORDER_PROP, DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK);
+	m_ui->orderButton->setIcon(QIcon::fromTheme("go-top"));
+	m_ui->orderButton->setMenu(orderMenu);
+	connect(
+		orderMenu, &QMenu::triggered, this,
+		&AnnotationSettings::changeOrder);
+
 	m_editActions = new QActionGroup(this);
 	m_editActions->addAction(mergeAction);
 	m_editActions->addAction(deleteAction);
@@ -107,6 +125,11 @@ QWidget *AnnotationSettings::createUiWidget(QWidget *parent)
 		&AnnotationSettings::changeAlignment);
 	m_ui->valign->setMenu(valignMenu);
 
+	m_autoGrowAction = new QAction(
+		QIcon::fromTheme("zoom-fit-height"), tr("Grow to fit text"), this);
+	m_autoGrowAction->setCheckable(true);
+	m_ui->autoGrow->setDefaultAction(m_autoGrowAction);
+
 	m_ui->btnTextColor->setColor(Qt::black);
 	m_ui->btnBackground->setColor(Qt::transparent);
 
@@ -124,6 +147,9 @@ QWidget *AnnotationSettings::createUiWidget(QWidget *parent)
 	connect(
 		m_ui->btnBackground, &widgets::ColorButton::colorChanged, this,
 		&AnnotationSettings::applyChanges);
+	connect(
+		m_ui->border, QOverload<int>::of(&QSpinBox::valueChanged), this,
+		&AnnotationSettings::applyChanges);
 
 	connect(
 		deleteAction, &QAction::triggered, this,
@@ -132,6 +158,9 @@ QWidget *AnnotationSettings::createUiWidget(QWidget *parent)
 	connect(
 		m_protectedAction, &QAction::triggered, this,
 		&AnnotationSettings::saveChanges);
+	connect(
+		m_autoGrowAction, &QAction::triggered, this,
+		&AnnotationSettings::saveChanges);
 
 	connect(
 		m_ui->font, SIGNAL(currentIndexChanged(int)), this,
@@ -184,16 +213,18 @@ QWidget *AnnotationSettings::getHeaderWidget()
 void AnnotationSettings::setUiEnabled(bool enabled)
 {
 	QWidget *widgets[] = {
-		m_ui->content, m_ui->btnBackground, m_ui->btnTextColor,
-		m_ui->halign,  m_ui->valign,		m_ui->bold,
-		m_ui->italic,  m_ui->underline,		m_ui->strikethrough,
-		m_ui->font,	   m_ui->size};
+		m_ui->content,		 m_ui->btnBackground, m_ui->border,
+		m_ui->btnTextColor,	 m_ui->halign,		  m_ui->valign,
+		m_ui->autoGrow,		 m_ui->bold,		  m_ui->italic,
+		m_ui->underline,	 m_ui->strikethrough, m_ui->font,
+		m_ui->size,			 m_ui->orderButton};
 	for(QWidget *w : widgets) {
 		w->setEnabled(enabled);
 	}
 	m_editActions->setEnabled(enabled);
 	if(!enabled) {
 		m_protectedAction->setChecked(false);
+		m_autoGrowAction->setChecked(false);
 		m_ui->creatorLabel->setText(QString{});
 	}
 }
@@ -374,6 +405,7 @@ void AnnotationSettings::setSelectionId(uint16_t id)
 		m_ui->content->setHtml(text);
 		m_ui->btnBackground->setColor(a->color());
 		setEditorBackgroundColor(a->color());
+		m_ui->border->setValue(a->border());
 		if(text.isEmpty()) {
 			resetContentFormat();
 		}
@@ -396,6 +428,7 @@ void AnnotationSettings::setSelectionId(uint16_t id)
 			break;
 		}
 		m_ui->valign->setProperty(VALIGN_PROP, align);
+		m_autoGrowAction->setChecked(a->autoGrow());
 
 		m_ui->creatorLabel->setText(
 			controller()->model()->userlist()->getUsername(a->userId()));
@@ -451,13 +484,36 @@ void AnnotationSettings::saveChanges()
 
 		net::Client *client = controller()->client();
 		uint8_t contextId = client->myId();
+		bool autoGrow = m_autoGrowAction->isChecked();
 		uint8_t flags = (m_protectedAction->isChecked()
 							 ? DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT
 							 : 0) |
+						(autoGrow ? DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW : 0) |
 						uint8_t(m_ui->valign->property(VALIGN_PROP).toInt());
-		client->sendMessage(net::makeAnnotationEditMessage(
+		net::Message edit = net::makeAnnotationEditMessage(
 			contextId, selected(), m_ui->btnBackground->color().rgba(), flags,
-			0, content));
+			uint8_t(m_ui->border->value()), content);
+
+		// Other clients can't be trusted to lay out the text the same way we
+		// do, so we fit the annotation to its content and send that along.
+		const auto *a = m_scene->getAnnotationItem(selected());
+		if(autoGrow && a) {
+			QRect rect = a->rect().toRect();
+			int height = qBound(
+				1, utils::annotationContentHeight(rect.width(), content),
+				int(UINT16_MAX));
+			if(height != rect.height()) {
+				net::Message messages[] = {
+					edit,
+					net::makeAnnotationReshapeMessage(
+						contextId, selected(), rect.x(), rect.y(),
+						rect.width(), height),
+				};
+				client->sendMessages(DP_ARRAY_LENGTH(messages), messages);
+				return;
+			}
+		}
+		client->sendMessage(edit);
 	}
 }
 
@@ -473,6 +529,19 @@ void AnnotationSettings::removeAnnotation()
 	client->sendMessages(DP_ARRAY_LENGTH(messages), messages);
 }
 
+void AnnotationSettings::changeOrder(const QAction *action)
+{
+	Q_ASSERT(selected());
+	net::Client *client = controller()->client();
+	uint8_t contextId = client->myId();
+	uint8_t direction = uint8_t(action->property(ORDER_PROP).toInt());
+	net::Message messages[] = {
+		net::makeUndoPointMessage(contextId),
+		net::makeAnnotationOrderMessage(contextId, selected(), direction),
+	};
+	client->sendMessages(DP_ARRAY_LENGTH(messages), messages);
+}
+
 void AnnotationSettings::bake()
 {
 	Q_ASSERT(selected());
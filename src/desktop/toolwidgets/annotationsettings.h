@@ -65,6 +65,7 @@ private slots:
 	void applyChanges();
 	void saveChanges();
 	void removeAnnotation();
+	void changeOrder(const QAction *action);
 	void bake();
 
 	void updateFontIfUniform();
@@ -82,6 +83,7 @@ private:
 	QWidget *m_headerWidget;
 	QActionGroup *m_editActions;
 	QAction *m_protectedAction;
+	QAction *m_autoGrowAction;
 
 	uint16_t m_selectionId;
 
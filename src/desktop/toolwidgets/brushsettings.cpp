@@ -667,7 +667,9 @@ void BrushSettings::updateUi()
 	d->ui.modeEraser->setChecked(brush.isEraser());
 	d->ui.modeEraser->setEnabled(d->current != ERASER_SLOT);
 
-	int mode = DP_classic_brush_blend_mode(&classic);
+	// Erasing to a color paints in normal mode, but the combo box only lists
+	// erase modes, so pick the configured one there.
+	int mode = classic.erase ? classic.erase_mode : classic.brush_mode;
 	d->ui.modeColorpick->setEnabled(mode != DP_BLEND_MODE_ERASE);
 	for(int i = 0; i < d->ui.blendmode->model()->rowCount(); ++i) {
 		if(d->ui.blendmode->model()->index(i, 0).data(Qt::UserRole) == mode) {
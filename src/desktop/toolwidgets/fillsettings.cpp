@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 #include "desktop/toolwidgets/fillsettings.h"
+#include "libclient/canvas/blendmodes.h"
 #include "libclient/canvas/layerlist.h"
 #include "libclient/tools/toolcontroller.h"
 #include "libclient/tools/toolproperties.h"
@@ -14,11 +15,13 @@ namespace tools {
 
 namespace props {
 	static const ToolProperties::RangedValue<int>
-		expand { QStringLiteral("expand"), 0, 0, 100 },
+		expand { QStringLiteral("expand"), 0, -100, 100 },
 		featherRadius { QStringLiteral("featherRadius"), 0, 0, 40 },
 		mode { QStringLiteral("mode"), 0, 0, 2},
 		size { QStringLiteral("size"), 500, 10, 9999 },
-		gap { QStringLiteral("gap"), 0, 0, 32};
+		gap { QStringLiteral("gap"), 0, 0, 64},
+		blendMode {
+			QStringLiteral("blendMode"), -1, -1, DP_BLEND_MODE_COUNT - 1};
 	static const ToolProperties::RangedValue<double>
 		tolerance { QStringLiteral("tolerance"), 0.0, 0.0, 1.0 };
 }
@@ -27,7 +30,8 @@ class FillSettings::FillSourceModel final : public QAbstractItemModel {
 public:
 	static constexpr int CURRENT_LAYER_ROW = 0;
 	static constexpr int MERGED_IMAGE_ROW = 1;
-	static constexpr int PREFIX_ROWS = 2;
+	static constexpr int MERGED_WITHOUT_BACKGROUND_ROW = 2;
+	static constexpr int PREFIX_ROWS = 3;
 
 	FillSourceModel(QObject *parent)
 		: QAbstractItemModel{parent}
@@ -42,7 +46,7 @@ public:
 	{
 		if(parent.isValid() || column != 0) {
 			return QModelIndex{};
-		} else if(row == CURRENT_LAYER_ROW || row == MERGED_IMAGE_ROW) {
+		} else if(row >= 0 && row < PREFIX_ROWS) {
 			return createIndex(row, column, quintptr(0));
 		} else {
 			const Layer *layer = layerAt(row);
@@ -98,6 +102,18 @@ public:
 		endResetModel();
 	}
 
+	static DP_FloodFillSample sampleForRow(int row)
+	{
+		switch(row) {
+		case MERGED_IMAGE_ROW:
+			return DP_FLOOD_FILL_SAMPLE_MERGED;
+		case MERGED_WITHOUT_BACKGROUND_ROW:
+			return DP_FLOOD_FILL_SAMPLE_MERGED_WITHOUT_BACKGROUND;
+		default:
+			return DP_FLOOD_FILL_SAMPLE_LAYER;
+		}
+	}
+
 	int searchRowByLayerId(int layerId)
 	{
 		int count = m_layers.size();
@@ -123,6 +139,8 @@ private:
 			return FillSettings::tr("Current Layer");
 		} else if(row == MERGED_IMAGE_ROW) {
 			return FillSettings::tr("Merged Image");
+		} else if(row == MERGED_WITHOUT_BACKGROUND_ROW) {
+			return FillSettings::tr("Merged Without Background");
 		} else {
 			const Layer *layer = layerAt(row);
 			return layer ? layer->displayText : QString{};
@@ -135,6 +153,7 @@ private:
 		case CURRENT_LAYER_ROW:
 			return m_activeLayer;
 		case MERGED_IMAGE_ROW:
+		case MERGED_WITHOUT_BACKGROUND_ROW:
 			return 0;
 		default:
 			return int(index.internalId());
@@ -165,7 +184,7 @@ private:
 
 	const Layer *layerAt(int row) const
 	{
-		int i = row - 2;
+		int i = row - PREFIX_ROWS;
 		return i >= 0 && i < m_layers.size() ? &m_layers[i] : nullptr;
 	}
 
@@ -180,6 +199,7 @@ FillSettings::FillSettings(ToolController *ctrl, QObject *parent)
 	: ToolSettings(ctrl, parent)
 	, m_ui{nullptr}
 	, m_fillSourceModel{new FillSourceModel{this}}
+	, m_previousMode{DP_BLEND_MODE_NORMAL}
 {
 }
 
@@ -196,6 +216,16 @@ QWidget *FillSettings::createUiWidget(QWidget *parent)
 	m_ui->source->setModel(m_fillSourceModel);
 	m_ui->size->setExponentRatio(3.0);
 
+	for(const canvas::blendmode::Named &m :
+		canvas::blendmode::brushModeNames()) {
+		m_ui->mode->addItem(m.name, int(m.mode));
+	}
+	m_ui->mode->insertSeparator(m_ui->mode->count());
+	for(const canvas::blendmode::Named &m :
+		canvas::blendmode::eraserModeNames()) {
+		m_ui->mode->addItem(m.name, int(m.mode));
+	}
+
 	connect(m_ui->size, QOverload<int>::of(&QSpinBox::valueChanged), this, [this](int size) {
 		emit pixelSizeChanged(size * 2);
 	});
@@ -217,18 +247,30 @@ void FillSettings::pushSettings()
 	tool->setFeatherRadius(m_ui->feather->value());
 	tool->setSize(m_ui->size->value());
 	tool->setGap(m_ui->gap->value());
+	tool->setSample(
+		FillSourceModel::sampleForRow(m_ui->source->currentIndex()));
 	tool->setLayerId(m_ui->source->currentData().toInt());
-	const auto mode = static_cast<Mode>(m_ui->mode->currentIndex());
-	tool->setBlendMode(modeIndexToBlendMode(mode));
-	if(mode != Erase) {
+	const int mode = m_ui->mode->currentData().toInt();
+	tool->setBlendMode(mode);
+	if(!canvas::blendmode::isValidEraseMode(DP_BlendMode(mode))) {
 		m_previousMode = mode;
 	}
 }
 
 void FillSettings::toggleEraserMode()
 {
-	int mode = m_ui->mode->currentIndex();
-	m_ui->mode->setCurrentIndex(mode == Erase ? m_previousMode : Erase);
+	const int mode = m_ui->mode->currentData().toInt();
+	if(canvas::blendmode::isValidEraseMode(DP_BlendMode(mode))) {
+		setBlendMode(m_previousMode);
+	} else {
+		setBlendMode(DP_BLEND_MODE_ERASE);
+	}
+}
+
+void FillSettings::setBlendMode(int blendMode)
+{
+	int index = m_ui->mode->findData(blendMode);
+	m_ui->mode->setCurrentIndex(index < 0 ? 0 : index);
 }
 
 ToolProperties FillSettings::saveToolSettings()
@@ -239,7 +281,7 @@ ToolProperties FillSettings::saveToolSettings()
 	cfg.setValue(props::featherRadius, m_ui->feather->value());
 	cfg.setValue(props::size, m_ui->size->value());
 	cfg.setValue(props::gap, m_ui->gap->value());
-	cfg.setValue(props::mode, m_ui->mode->currentIndex());
+	cfg.setValue(props::blendMode, m_ui->mode->currentData().toInt());
 	return cfg;
 }
 
@@ -279,7 +321,12 @@ void FillSettings::restoreToolSettings(const ToolProperties &cfg)
 	m_ui->feather->setValue(cfg.value(props::featherRadius));
 	m_ui->size->setValue(cfg.value(props::size));
 	m_ui->gap->setValue(cfg.value(props::gap));
-	m_ui->mode->setCurrentIndex(cfg.value(props::mode));
+	const int blendMode = cfg.value(props::blendMode);
+	if(blendMode < 0) {
+		setBlendMode(modeIndexToBlendMode(cfg.value(props::mode)));
+	} else {
+		setBlendMode(blendMode);
+	}
 	pushSettings();
 }
 
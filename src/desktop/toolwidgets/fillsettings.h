@@ -39,6 +39,7 @@ public:
 
 	void setLayerList(canvas::LayerListModel *layerlist);
 
+	//! Maps the mode index saved by older versions to a blend mode
 	static int modeIndexToBlendMode(int mode);
 
 signals:
@@ -56,15 +57,17 @@ protected:
 
 private:
 	class FillSourceModel;
-	enum Mode {
+	enum LegacyMode {
 		Normal,
 		Behind,
 		Erase,
 	};
 
+	void setBlendMode(int blendMode);
+
 	Ui_FillSettings *m_ui;
 	FillSourceModel *m_fillSourceModel;
-	Mode m_previousMode = Normal;
+	int m_previousMode;
 	qreal m_quickAdjust1 = 0.0;
 };
 
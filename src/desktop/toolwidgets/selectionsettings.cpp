@@ -40,6 +40,8 @@ QWidget *SelectionSettings::createUiWidget(QWidget *parent)
 		tr("Nearest"), DP_MSG_TRANSFORM_REGION_MODE_NEAREST);
 	m_ui->interpolationCombo->addItem(
 		tr("Bilinear"), DP_MSG_TRANSFORM_REGION_MODE_BILINEAR);
+	m_ui->interpolationCombo->addItem(
+		tr("Bicubic"), DP_MSG_TRANSFORM_REGION_MODE_BICUBIC);
 	connect(
 		m_ui->interpolationCombo,
 		QOverload<int>::of(&QComboBox::currentIndexChanged), this,
@@ -19,6 +19,7 @@ bindgen \
     --allowlist-function '(DP|json)_.*' \
     --allowlist-type '(DP|JSON)_.*' \
     --allowlist-var 'DP_.*' \
+    --blocklist-item 'DP_TILE_(SIZE|LENGTH)' \
     --no-prepend-enum-name \
     wrapper.h \
     -- \
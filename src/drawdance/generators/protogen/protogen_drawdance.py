@@ -151,6 +151,13 @@ class DrawdanceFieldType:
         else:
             raise RuntimeError(f"Unknown uint subfield format '{fmt}'")
 
+    def parse_subfield_argb(self, f, index):
+        fmt = f.format
+        if not fmt:
+            return f"DP_text_reader_get_subfield_argb_color(reader, i, {index})"
+        else:
+            raise RuntimeError(f"Unknown argb subfield format '{fmt}'")
+
     def write_text_raw(self, f, subject, fn):
         fmt = f.format
         if fmt:
@@ -218,6 +225,15 @@ class DrawdanceFieldType:
             raise RuntimeError(f"Unknown uint subfield format '{fmt}'")
 
 
+    def write_subfield_text_argb(self, f, subject):
+        a = self.access(f, subject)
+        fmt = f.format
+        if not fmt:
+            return f"DP_text_writer_write_subfield_argb_color(writer, {a})"
+        else:
+            raise RuntimeError(f"Unknown argb subfield format '{fmt}'")
+
+
 class DrawdancePlainFieldType(DrawdanceFieldType):
     def __init__(
         self,
@@ -695,8 +711,10 @@ DrawdancePlainFieldType.declare(
     payload_length=4,
     serialize_payload_fn="DP_write_bigendian_uint32",
     write_payload_text_fn="DP_text_writer_write_argb_color",
+    write_subfield_payload_text_fn=DrawdanceFieldType.write_subfield_text_argb,
     deserialize_payload_fn="read_uint32",
     parse_field_fn="DP_text_reader_get_argb_color",
+    parse_subfield_fn=DrawdanceFieldType.parse_subfield_argb,
 )
 
 DrawdancePlainFieldType.declare(
@@ -1009,6 +1027,14 @@ class DrawdanceField:
             self.min = field.min_len
             self.max = field.max_len
 
+        # The length prefix counts towards the field's length, but not
+        # towards the length of the value that's passed around.
+        prefix_type = getattr(field, "prefix_type", None)
+        if prefix_type:
+            prefix_len = int(prefix_type[1:]) // 8
+            self.min -= prefix_len
+            self.max -= prefix_len
+
     def access(self, subject):
         return self.type.access(self, subject)
 
@@ -60,6 +60,10 @@
 #    define DP_ALIGNAS_SIMD // nothing
 #endif
 
+#if !defined(RUST_BINDGEN) && (defined(_M_ARM64) || defined(__aarch64__))
+#    define DP_CPU_ARM64
+#endif
+
 #ifdef __GNUC__
 #    define DP_TRAP() __builtin_trap()
 #    define DP_UNUSED __attribute__((__unused__))
@@ -62,6 +62,12 @@ static DP_CpuSupport get_env_cpu_support(void)
         DP_warn("Restricting CPU support to at most AVX2");
         return DP_CPU_SUPPORT_AVX2;
     }
+#endif
+#ifdef DP_CPU_ARM64
+    else if (DP_str_equal_lowercase(value, "neon")) {
+        DP_warn("Restricting CPU support to at most NEON");
+        return DP_CPU_SUPPORT_NEON;
+    }
 #endif
     else {
         DP_warn("Unknown DP_CPU_SUPPORT value '%s', ignoring it", value);
@@ -134,6 +140,10 @@ void DP_cpu_support_init(void)
     else {
         DP_cpu_support_value = DP_CPU_SUPPORT_DEFAULT;
     }
+#elif defined(DP_CPU_ARM64)
+    DP_cpu_support_value = max_support >= DP_CPU_SUPPORT_NEON
+                             ? DP_CPU_SUPPORT_NEON
+                             : DP_CPU_SUPPORT_DEFAULT;
 #else
     (void)max_support;
     DP_cpu_support_value = DP_CPU_SUPPORT_DEFAULT;
@@ -84,6 +84,8 @@
 #    else
 #        include <intrin.h>
 #    endif
+#elif defined(DP_CPU_ARM64)
+#    include <arm_neon.h>
 #endif
 
 #define DP_DO_PRAGMA_(x) _Pragma(#x)
@@ -109,12 +111,20 @@ typedef enum DP_CpuSupport {
     DP_CPU_SUPPORT_SSE42,
     DP_CPU_SUPPORT_AVX,
     DP_CPU_SUPPORT_AVX2,
+#endif
+#ifdef DP_CPU_ARM64
+    DP_CPU_SUPPORT_NEON, // always there on 64 bit ARM
 #endif
     DP_CPU_SUPPORT_COUNT,
 } DP_CpuSupport;
 
 void DP_cpu_support_init(void);
 
+// The detected support level. Tests overwrite this to run the same code
+// through each of the instruction sets in turn, which only takes effect if
+// the level wasn't fixed at compile-time, see below.
+extern DP_CpuSupport DP_cpu_support_value;
+
 // If AVX2, AVX or SSE 4.2 are requested at compile-time, we switch to those at
 // compile-time instead of doing a dynamic check. If your processor supports
 // AVX2 but you ask for SSE 4.2 at compile-time then you only get the latter.
@@ -126,7 +136,6 @@ void DP_cpu_support_init(void);
 #    elif defined(DP_CPU_X64) && defined(__SSE4_2__)
 #        define DP_cpu_support DP_CPU_SUPPORT_SSE42
 #    else
-extern DP_CpuSupport DP_cpu_support_value;
 #        define DP_cpu_support DP_cpu_support_value
 #    endif
 #else
@@ -301,16 +301,19 @@ static void free_pthread_errror_state(void *arg)
 static DP_PthreadErrorState *get_pthread_error_state(void)
 {
     DP_ATOMIC_DECLARE_STATIC_SPIN_LOCK(lock);
+    // Zero is a valid key, so whether it was created is tracked separately.
+    static DP_Atomic key_created;
     static pthread_key_t key;
 
-    if (key == 0) {
+    if (!DP_atomic_get(&key_created)) {
         DP_atomic_lock(&lock);
-        if (key == 0) {
+        if (!DP_atomic_get(&key_created)) {
             int error = pthread_key_create(&key, free_pthread_errror_state);
             if (error != 0) {
                 DP_panic("Error creating thread-local key: %s",
                          strerror(error));
             }
+            DP_atomic_set(&key_created, 1);
         }
         DP_atomic_unlock(&lock);
     }
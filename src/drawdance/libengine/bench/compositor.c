@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/affected_area.h>
+#include <dpengine/brush.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/compositor.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+
+
+// Draws single dabs onto canvases of increasing size and compares how long it
+// takes to update the composite incrementally with flattening the whole
+// canvas. The incremental update should take about the same time regardless
+// of the canvas size. Usage: dpengine_compositor_bench [DAB_COUNT]
+
+#define LAYER_COUNT 4
+
+static void handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        DP_panic("Error handling %s: %s",
+                 DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+}
+
+static void handle_decref(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                          DP_Message *msg)
+{
+    handle(ch, dc, msg);
+    DP_message_decref(msg);
+}
+
+static void set_dab(int count, DP_ClassicDab *dabs, DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(dabs, i, 0, 0, 20 * 256, 128, 255);
+    }
+}
+
+static double now_ms(void)
+{
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (double)ts.tv_sec * 1000.0 + (double)ts.tv_nsec / 1000000.0;
+}
+
+static void bench(int size, int dab_count)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle_decref(ch, dc, DP_msg_canvas_resize_new(1, 0, size, size, 0));
+    for (int i = 0; i < LAYER_COUNT; ++i) {
+        uint16_t layer_id = DP_int_to_uint16(0x100 + i);
+        handle_decref(ch, dc,
+                      DP_msg_layer_tree_create_new(1, layer_id, 0, 0, 0, 0,
+                                                   "", 0));
+        handle_decref(ch, dc,
+                      DP_msg_fill_rect_new(1, layer_id, DP_BLEND_MODE_NORMAL,
+                                           0, 0, DP_int_to_uint32(size),
+                                           DP_int_to_uint32(size),
+                                           0x40000000u | 0x102030u * 3u));
+    }
+
+    DP_Compositor *c = DP_compositor_new(DP_FLAT_IMAGE_RENDER_FLAGS);
+    DP_AffectedIndirectAreas aia;
+    DP_affected_indirect_areas_clear(&aia);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_AffectedArea everything = {DP_AFFECTED_DOMAIN_EVERYTHING, 0,
+                                  DP_rect_make(0, 0, size, size)};
+    double start = now_ms();
+    DP_compositor_update(c, cs, &everything, NULL);
+    double full_ms = now_ms() - start;
+    DP_canvas_state_decref(cs);
+
+    double update_ms = 0.0;
+    for (int i = 0; i < dab_count; ++i) {
+        uint16_t layer_id = DP_int_to_uint16(0x100 + i % LAYER_COUNT);
+        int x = (i * 7919) % size;
+        int y = (i * 104729) % size;
+        DP_Message *msg = DP_msg_draw_dabs_classic_new(
+            1, layer_id, x * 4, y * 4, 0x336699u,
+            DP_BLEND_MODE_NORMAL, set_dab, 1, NULL);
+        handle(ch, dc, msg);
+        cs = DP_canvas_history_get(ch);
+        DP_AffectedArea aa = DP_affected_area_make_visible(msg, &aia);
+        start = now_ms();
+        DP_compositor_update(c, cs, &aa, NULL);
+        update_ms += now_ms() - start;
+        DP_canvas_state_decref(cs);
+        DP_message_decref(msg);
+    }
+
+    printf("%5dx%-5d full flatten %9.2f ms, single dab update %7.3f ms\n",
+           size, size, full_ms, update_ms / (double)dab_count);
+    DP_compositor_free(c);
+    DP_draw_context_free(dc);
+    DP_canvas_history_free(ch);
+}
+
+int main(int argc, char **argv)
+{
+    int dab_count = argc > 1 ? atoi(argv[1]) : 500;
+    if (dab_count < 1) {
+        fprintf(stderr, "Usage: %s [DAB_COUNT]\n", argv[0]);
+        return 2;
+    }
+
+    printf("%d layers, average of %d dabs\n", LAYER_COUNT, dab_count);
+    for (int size = 512; size <= 4096; size *= 2) {
+        bench(size, dab_count);
+    }
+    return 0;
+}
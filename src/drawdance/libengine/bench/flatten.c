@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/cpu.h>
+#include <dpcommon/threading.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+
+
+// Flattens a full canvas made of a stack of translucent Normal layers with
+// each instruction set the CPU supports on a single thread, then with the
+// best one on all cores, and prints how long it took. Usage:
+// dpengine_flatten_bench [LAYER_COUNT [ROUNDS]]
+
+#define WIDTH  4096
+#define HEIGHT 4096
+
+static void handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        DP_panic("Error handling %s: %s",
+                 DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static DP_CanvasState *make_canvas(int layer_count)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(ch, dc, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    for (int i = 0; i < layer_count; ++i) {
+        uint16_t layer_id = DP_int_to_uint16(0x100 + i);
+        handle(ch, dc,
+               DP_msg_layer_tree_create_new(1, layer_id, 0, 0, 0, 0, "", 0));
+        // Translucent, so that every layer gets blended onto the ones below.
+        uint32_t rgb = DP_int_to_uint32(0x102030 + i * 0x0a1b2c) & 0xffffffu;
+        uint32_t color = 0x80000000u | rgb;
+        handle(ch, dc,
+               DP_msg_fill_rect_new(1, layer_id, DP_BLEND_MODE_NORMAL, 0, 0,
+                                    WIDTH, HEIGHT, color));
+        // A stripe through every tile, otherwise they're all uniform and get
+        // blended as a single pixel instead of going through the slow path.
+        for (int x = i % DP_TILE_SIZE; x < WIDTH; x += DP_TILE_SIZE) {
+            handle(ch, dc,
+                   DP_msg_fill_rect_new(1, layer_id, DP_BLEND_MODE_NORMAL,
+                                        DP_int_to_uint32(x), 0, 1, HEIGHT,
+                                        ~color | 0xff000000u));
+        }
+    }
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+    return cs;
+}
+
+static double now_ms(void)
+{
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (double)ts.tv_sec * 1000.0 + (double)ts.tv_nsec / 1000000.0;
+}
+
+static double time_flatten(DP_CanvasState *cs, unsigned int flags, int rounds)
+{
+    double best = -1.0;
+    for (int i = 0; i < rounds; ++i) {
+        double start = now_ms();
+        DP_TransientLayerContent *tlc =
+            DP_canvas_state_to_flat_layer(cs, flags, NULL, NULL);
+        double elapsed = now_ms() - start;
+        DP_transient_layer_content_decref(tlc);
+        if (best < 0.0 || elapsed < best) {
+            best = elapsed;
+        }
+    }
+    return best;
+}
+
+int main(int argc, char **argv)
+{
+    int layer_count = argc > 1 ? atoi(argv[1]) : 32;
+    int rounds = argc > 2 ? atoi(argv[2]) : 5;
+    if (layer_count < 1 || rounds < 1) {
+        fprintf(stderr, "Usage: %s [LAYER_COUNT [ROUNDS]]\n", argv[0]);
+        return 2;
+    }
+
+    DP_cpu_support_init();
+    DP_CpuSupport detected = DP_cpu_support_value;
+    DP_CanvasState *cs = make_canvas(layer_count);
+    printf("Flattening %dx%d, %d layers, best of %d\n", WIDTH, HEIGHT,
+           layer_count, rounds);
+
+    double scalar_ms = -1.0;
+    for (int i = DP_CPU_SUPPORT_DEFAULT; i <= (int)detected; ++i) {
+        DP_cpu_support_value = (DP_CpuSupport)i;
+        if (DP_cpu_support != (DP_CpuSupport)i) {
+            printf("CPU support %d: fixed at compile-time, skipped\n", i);
+            continue;
+        }
+        double ms = time_flatten(
+            cs, DP_FLAT_IMAGE_RENDER_FLAGS | DP_FLAT_IMAGE_SINGLE_THREADED,
+            rounds);
+        if (scalar_ms < 0.0) {
+            scalar_ms = ms;
+        }
+        printf("CPU support %d: %8.2f ms, %5.2fx\n", i, ms, scalar_ms / ms);
+    }
+
+    DP_cpu_support_value = detected;
+    double parallel_ms = time_flatten(cs, DP_FLAT_IMAGE_RENDER_FLAGS, rounds);
+    printf("%d threads:     %8.2f ms, %5.2fx\n", DP_thread_cpu_count(128),
+           parallel_ms, scalar_ms / parallel_ms);
+
+    DP_canvas_state_decref(cs);
+    return 0;
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/threading.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_list.h>
+#include <dpengine/load.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+
+
+// Loads an ORA file on a single thread, then with the layers decoded by a
+// worker on all cores, and prints how long it took. Decoding the layer PNGs
+// is what takes the time, so pick a file with lots of layers. Usage:
+// dpengine_load_ora_bench PATH [ROUNDS]
+
+static double now_ms(void)
+{
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (double)ts.tv_sec * 1000.0 + (double)ts.tv_nsec / 1000000.0;
+}
+
+static double time_load(DP_DrawContext *dc, const char *path,
+                        unsigned int flags, int rounds, int *out_layer_count)
+{
+    double best = -1.0;
+    for (int i = 0; i < rounds; ++i) {
+        double start = now_ms();
+        DP_CanvasState *cs = DP_load_ora(dc, path, flags, NULL, NULL, NULL);
+        double elapsed = now_ms() - start;
+        if (!cs) {
+            DP_panic("Error loading %s: %s", path, DP_error());
+        }
+        *out_layer_count =
+            DP_layer_list_count(DP_canvas_state_layers_noinc(cs));
+        DP_canvas_state_decref(cs);
+        if (best < 0.0 || elapsed < best) {
+            best = elapsed;
+        }
+    }
+    return best;
+}
+
+int main(int argc, char **argv)
+{
+    int rounds = argc > 2 ? atoi(argv[2]) : 5;
+    if (argc < 2 || rounds < 1) {
+        fprintf(stderr, "Usage: %s PATH [ROUNDS]\n", argv[0]);
+        return 2;
+    }
+
+    const char *path = argv[1];
+    DP_DrawContext *dc = DP_draw_context_new();
+    int layer_count;
+    double serial_ms =
+        time_load(dc, path, DP_LOAD_FLAG_SINGLE_THREAD, rounds, &layer_count);
+    printf("Loading %s, %d top-level layers, best of %d\n", path, layer_count,
+           rounds);
+    printf("1 thread:      %8.2f ms\n", serial_ms);
+
+    double parallel_ms =
+        time_load(dc, path, DP_LOAD_FLAG_NONE, rounds, &layer_count);
+    printf("%d threads:     %8.2f ms, %5.2fx\n", DP_thread_cpu_count(32),
+           parallel_ms, serial_ms / parallel_ms);
+
+    DP_draw_context_free(dc);
+    return 0;
+}
@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+
+
+// Replays a bunch of brush strokes with undos and redos in between, first
+// with the tile memory cache and then without it, and prints how often tile
+// memory got allocated and how long it took. Usage:
+// dpengine_tile_churn_bench [STROKE_COUNT]
+
+#define WIDTH         2048
+#define HEIGHT        2048
+#define LAYER_ID      0x100
+#define STROKE_DABS   200
+#define DABS_PER_MSG  10
+#define DAB_SPACING   16
+#define UNDO_INTERVAL 4
+
+static void handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        DP_panic("Error handling %s: %s",
+                 DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void set_dabs(int count, DP_ClassicDab *dabs, DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(dabs, i, i == 0 ? 0 : DAB_SPACING, DAB_SPACING / 2,
+                            40 * 256, 128, 200);
+    }
+}
+
+// A diagonal stroke across the canvas, translucent so that it gets drawn onto
+// a sublayer and merged at the end, like a real one would be.
+static void stroke(DP_CanvasHistory *ch, DP_DrawContext *dc, int i)
+{
+    handle(ch, dc, DP_msg_undo_point_new(1));
+    int x = (i * 173) % WIDTH * 4;
+    int y = (i * 97) % (HEIGHT / 2) * 4;
+    uint32_t color = 0x80000000u | (DP_int_to_uint32(i * 0x1f2e3d) & 0xffffffu);
+    for (int j = 0; j < STROKE_DABS; j += DABS_PER_MSG) {
+        handle(ch, dc,
+               DP_msg_draw_dabs_classic_new(
+                   1, LAYER_ID, x, y, color, DP_BLEND_MODE_NORMAL, set_dabs,
+                   DABS_PER_MSG, NULL));
+        x += DAB_SPACING * (DABS_PER_MSG - 1);
+        y += DAB_SPACING / 2 * DABS_PER_MSG;
+    }
+    handle(ch, dc, DP_msg_pen_up_new(1));
+}
+
+static double now_ms(void)
+{
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (double)ts.tv_sec * 1000.0 + (double)ts.tv_nsec / 1000000.0;
+}
+
+static void replay(const char *title, int stroke_count)
+{
+    DP_tile_memory_cache_drain();
+    size_t allocations = DP_tile_memory_usage().allocations;
+    double start = now_ms();
+
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(ch, dc, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "", 0));
+    for (int i = 0; i < stroke_count; ++i) {
+        stroke(ch, dc, i);
+        if (i % UNDO_INTERVAL == UNDO_INTERVAL - 1) {
+            handle(ch, dc, DP_msg_undo_new(1, 0, false));
+            handle(ch, dc, DP_msg_undo_new(1, 0, false));
+            handle(ch, dc, DP_msg_undo_new(1, 0, true));
+        }
+    }
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+
+    double elapsed = now_ms() - start;
+    DP_TileMemoryStatistics tms = DP_tile_memory_usage();
+    printf("%-12s %10zu allocations, %6zu tiles cached, %8.2f ms\n", title,
+           tms.allocations - allocations, tms.tiles_cached, elapsed);
+}
+
+int main(int argc, char **argv)
+{
+    int stroke_count = argc > 1 ? atoi(argv[1]) : 200;
+    if (stroke_count < 1) {
+        fprintf(stderr, "Usage: %s [STROKE_COUNT]\n", argv[0]);
+        return 2;
+    }
+
+    printf("Replaying %d strokes of %d dabs on %dx%d\n", stroke_count,
+           STROKE_DABS, WIDTH, HEIGHT);
+    replay("With cache:", stroke_count);
+    DP_tile_memory_cache_limit_set(0);
+    replay("No cache:", stroke_count);
+    return 0;
+}
@@ -128,6 +128,20 @@ static DP_Rect classic_dabs_bounds(DP_MsgDrawDabsClassic *mddc)
     return s.bounds;
 }
 
+static DP_Rect soft_dabs_bounds(DP_MsgDrawDabsSoft *mdds)
+{
+    struct SubpixelDabs s = subpixel_dabs_init(DP_msg_draw_dabs_soft_x(mdds),
+                                               DP_msg_draw_dabs_soft_y(mdds));
+    int count;
+    const DP_SoftDab *dabs = DP_msg_draw_dabs_soft_dabs(mdds, &count);
+    for (int i = 0; i < count; ++i) {
+        const DP_SoftDab *dab = DP_soft_dab_at(dabs, i);
+        subpixel_dabs_update(&s, DP_soft_dab_x(dab), DP_soft_dab_y(dab),
+                             DP_soft_dab_size(dab));
+    }
+    return s.bounds;
+}
+
 static DP_Rect mypaint_dabs_bounds(DP_MsgDrawDabsMyPaint *mddmp)
 {
     struct SubpixelDabs s = subpixel_dabs_init(
@@ -142,6 +156,24 @@ static DP_Rect mypaint_dabs_bounds(DP_MsgDrawDabsMyPaint *mddmp)
     return s.bounds;
 }
 
+static DP_Rect stamp_dabs_bounds(DP_MsgDrawDabsStamp *mdds)
+{
+    struct SubpixelDabs s = subpixel_dabs_init(DP_msg_draw_dabs_stamp_x(mdds),
+                                               DP_msg_draw_dabs_stamp_y(mdds));
+    int count;
+    const DP_StampDab *dabs = DP_msg_draw_dabs_stamp_dabs(mdds, &count);
+    for (int i = 0; i < count; ++i) {
+        const DP_StampDab *dab = DP_stamp_dab_at(dabs, i);
+        // A rotated stamp reaches out to its corners, which are up to a factor
+        // of the square root of two further out than its nominal size.
+        uint16_t size = DP_double_to_uint16(
+            DP_min_double(DP_stamp_dab_size(dab) * 1.415, UINT16_MAX));
+        subpixel_dabs_update(&s, DP_stamp_dab_x(dab), DP_stamp_dab_y(dab),
+                             size);
+    }
+    return s.bounds;
+}
+
 static DP_Rect pixel_dabs_bounds(DP_MsgDrawDabsPixel *mddp)
 {
     int last_x = DP_msg_draw_dabs_pixel_x(mddp);
@@ -206,7 +238,8 @@ static DP_Rect move_rect_bounds(DP_MsgMoveRect *mrr)
 
 static DP_AffectedArea update_indirect_area(DP_AffectedIndirectAreas *aia,
                                             unsigned int context_id,
-                                            int layer_id, DP_Rect bounds)
+                                            int layer_id, DP_Rect bounds,
+                                            bool visible)
 {
     DP_ASSERT(context_id < DP_AFFECTED_INDIRECT_AREAS_COUNT);
     DP_IndirectArea *ia = &aia->areas[context_id];
@@ -224,7 +257,9 @@ static DP_AffectedArea update_indirect_area(DP_AffectedIndirectAreas *aia,
         ia->layer_id = layer_id;
         ia->bounds = bounds;
     }
-    return make_user_attrs();
+    // The pixels land on a sublayer, which is visible, but only conflict
+    // with anything once they get merged at pen up.
+    return visible ? make_pixels(layer_id, bounds) : make_user_attrs();
 }
 
 static DP_AffectedArea take_indirect_area(DP_AffectedIndirectAreas *aia,
@@ -244,8 +279,9 @@ static DP_AffectedArea take_indirect_area(DP_AffectedIndirectAreas *aia,
     }
 }
 
-DP_AffectedArea DP_affected_area_make(DP_Message *msg,
-                                      DP_AffectedIndirectAreas *aia)
+static DP_AffectedArea make_affected_area(DP_Message *msg,
+                                          DP_AffectedIndirectAreas *aia,
+                                          bool visible)
 {
     DP_MessageType type = DP_message_type(msg);
     switch (type) {
@@ -264,6 +300,9 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
     case DP_MSG_LAYER_RETITLE:
         return make_layer_attrs(
             DP_msg_layer_retitle_id(DP_msg_layer_retitle_cast(msg)));
+    case DP_MSG_LAYER_METADATA:
+        return make_layer_attrs(
+            DP_msg_layer_metadata_id(DP_msg_layer_metadata_cast(msg)));
     case DP_MSG_LAYER_ORDER:
     case DP_MSG_LAYER_TREE_MOVE:
         // Moving a layer is dependent on the state of the source, parent and
@@ -311,7 +350,8 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
                 : DP_rect_make(DP_msg_put_tile_col(mpt) * DP_TILE_SIZE, y,
                                DP_TILE_SIZE, DP_TILE_SIZE);
         if (sublayer_id != 0) {
-            return update_indirect_area(aia, sublayer_id, layer_id, bounds);
+            return update_indirect_area(aia, sublayer_id, layer_id, bounds,
+                                        visible);
         }
         else {
             return make_pixels(layer_id, bounds);
@@ -323,7 +363,7 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
         DP_Rect bounds = classic_dabs_bounds(mddc);
         if (DP_msg_draw_dabs_classic_indirect(mddc)) {
             return update_indirect_area(aia, DP_message_context_id(msg),
-                                        layer_id, bounds);
+                                        layer_id, bounds, visible);
         }
         else {
             return make_pixels(layer_id, bounds);
@@ -336,7 +376,31 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
         DP_Rect bounds = pixel_dabs_bounds(mddp);
         if (DP_msg_draw_dabs_pixel_indirect(mddp)) {
             return update_indirect_area(aia, DP_message_context_id(msg),
-                                        layer_id, bounds);
+                                        layer_id, bounds, visible);
+        }
+        else {
+            return make_pixels(layer_id, bounds);
+        }
+    }
+    case DP_MSG_DRAW_DABS_STAMP: {
+        DP_MsgDrawDabsStamp *mdds = DP_msg_draw_dabs_stamp_cast(msg);
+        int layer_id = DP_msg_draw_dabs_stamp_layer(mdds);
+        DP_Rect bounds = stamp_dabs_bounds(mdds);
+        if (DP_msg_draw_dabs_stamp_indirect(mdds)) {
+            return update_indirect_area(aia, DP_message_context_id(msg),
+                                        layer_id, bounds, visible);
+        }
+        else {
+            return make_pixels(layer_id, bounds);
+        }
+    }
+    case DP_MSG_DRAW_DABS_SOFT: {
+        DP_MsgDrawDabsSoft *mdds = DP_msg_draw_dabs_soft_cast(msg);
+        int layer_id = DP_msg_draw_dabs_soft_layer(mdds);
+        DP_Rect bounds = soft_dabs_bounds(mdds);
+        if (DP_msg_draw_dabs_soft_indirect(mdds)) {
+            return update_indirect_area(aia, DP_message_context_id(msg),
+                                        layer_id, bounds, visible);
         }
         else {
             return make_pixels(layer_id, bounds);
@@ -364,6 +428,24 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
                          DP_uint32_to_int(DP_msg_fill_rect_w(mfr)),
                          DP_uint32_to_int(DP_msg_fill_rect_h(mfr))));
     }
+    case DP_MSG_FILL_GRADIENT: {
+        DP_MsgFillGradient *mfg = DP_msg_fill_gradient_cast(msg);
+        return make_pixels(
+            DP_msg_fill_gradient_layer(mfg),
+            DP_rect_make(DP_uint32_to_int(DP_msg_fill_gradient_x(mfg)),
+                         DP_uint32_to_int(DP_msg_fill_gradient_y(mfg)),
+                         DP_uint32_to_int(DP_msg_fill_gradient_w(mfg)),
+                         DP_uint32_to_int(DP_msg_fill_gradient_h(mfg))));
+    }
+    case DP_MSG_FILTER_REGION: {
+        DP_MsgFilterRegion *mfr = DP_msg_filter_region_cast(msg);
+        return make_pixels(
+            DP_msg_filter_region_layer(mfr),
+            DP_rect_make(DP_uint32_to_int(DP_msg_filter_region_x(mfr)),
+                         DP_uint32_to_int(DP_msg_filter_region_y(mfr)),
+                         DP_uint32_to_int(DP_msg_filter_region_w(mfr)),
+                         DP_uint32_to_int(DP_msg_filter_region_h(mfr))));
+    }
     case DP_MSG_ANNOTATION_CREATE:
         return make_annotations(
             DP_msg_annotation_create_id(DP_msg_annotation_create_cast(msg)));
@@ -376,6 +458,9 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
     case DP_MSG_ANNOTATION_DELETE:
         return make_annotations(
             DP_msg_annotation_delete_id(DP_msg_annotation_delete_cast(msg)));
+    case DP_MSG_ANNOTATION_ORDER:
+        return make_annotations(
+            DP_msg_annotation_order_id(DP_msg_annotation_order_cast(msg)));
     case DP_MSG_MOVE_REGION: {
         DP_MsgMoveRegion *mmr = DP_msg_move_region_cast(msg);
         return make_pixels(
@@ -418,6 +503,7 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
                                          DP_msg_transform_region_y4(mtr)));
     }
     case DP_MSG_UNDO_POINT:
+    case DP_MSG_STAMP_MASK:
         return make_user_attrs();
     case DP_MSG_CANVAS_BACKGROUND:
         return make_canvas_background();
@@ -466,7 +552,9 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
         return make_timeline(single_track_id ? track_id : ALL_IDS);
     }
     case DP_MSG_UNDO:
-        return make_user_attrs();
+        // Undo doesn't conflict with anything, since it's limited to the
+        // user's own actions, but those actions could have been anywhere.
+        return visible ? make_everything() : make_user_attrs();
     default:
         DP_debug("Unhandled message of type %d (%s) affects everything",
                  (int)type, DP_message_type_enum_name(type));
@@ -474,6 +562,18 @@ DP_AffectedArea DP_affected_area_make(DP_Message *msg,
     }
 }
 
+DP_AffectedArea DP_affected_area_make(DP_Message *msg,
+                                      DP_AffectedIndirectAreas *aia)
+{
+    return make_affected_area(msg, aia, false);
+}
+
+DP_AffectedArea DP_affected_area_make_visible(DP_Message *msg,
+                                              DP_AffectedIndirectAreas *aia)
+{
+    return make_affected_area(msg, aia, true);
+}
+
 static bool domains_conflict(DP_AffectedDomain a, DP_AffectedDomain b)
 {
     // Affecting everything means being concurrent with nothing. The timeline
@@ -511,6 +611,76 @@ bool DP_affected_area_concurrent_with(const DP_AffectedArea *a,
                 && !DP_rect_intersects(a->bounds, b->bounds)));
 }
 
+bool DP_affected_area_bounds(const DP_AffectedArea *aa, int canvas_width,
+                             int canvas_height, DP_Rect *out_bounds)
+{
+    DP_ASSERT(aa);
+    DP_ASSERT(out_bounds);
+    DP_Rect canvas = DP_rect_make(0, 0, canvas_width, canvas_height);
+    DP_Rect bounds;
+    switch (aa->domain) {
+    case DP_AFFECTED_DOMAIN_PIXELS:
+        bounds = DP_rect_intersection(canvas, aa->bounds);
+        break;
+    case DP_AFFECTED_DOMAIN_LAYER_ATTRS:
+    case DP_AFFECTED_DOMAIN_CANVAS_BACKGROUND:
+    case DP_AFFECTED_DOMAIN_EVERYTHING:
+        bounds = canvas;
+        break;
+    default:
+        return false;
+    }
+
+    if (DP_rect_valid(bounds)) {
+        *out_bounds = bounds;
+        return true;
+    }
+    else {
+        return false;
+    }
+}
+
+DP_AffectedArea DP_affected_area_merge(const DP_AffectedArea *a,
+                                       const DP_AffectedArea *b)
+{
+    DP_ASSERT(a);
+    DP_ASSERT(b);
+    DP_AffectedDomain domain = a->domain;
+    if (domain != b->domain) {
+        // Local user changes don't affect anything else, so they vanish.
+        if (domain == DP_AFFECTED_DOMAIN_USER_ATTRS) {
+            return *b;
+        }
+        else if (b->domain == DP_AFFECTED_DOMAIN_USER_ATTRS) {
+            return *a;
+        }
+        else {
+            return make_everything();
+        }
+    }
+
+    int affected_id =
+        a->affected_id == b->affected_id ? a->affected_id : ALL_IDS;
+    if (domain == DP_AFFECTED_DOMAIN_PIXELS) {
+        return (DP_AffectedArea){domain, affected_id,
+                                 DP_rect_union(a->bounds, b->bounds)};
+    }
+    else {
+        return (DP_AffectedArea){domain, affected_id, INVALID_BOUNDS};
+    }
+}
+
+DP_TileIterator DP_affected_area_tile_iterator_make(const DP_AffectedArea *aa,
+                                                    int canvas_width,
+                                                    int canvas_height)
+{
+    DP_Rect bounds;
+    if (!DP_affected_area_bounds(aa, canvas_width, canvas_height, &bounds)) {
+        bounds = INVALID_BOUNDS;
+    }
+    return DP_tile_iterator_make(canvas_width, canvas_height, bounds);
+}
+
 void DP_affected_indirect_areas_clear(DP_AffectedIndirectAreas *aia)
 {
     DP_ASSERT(aia);
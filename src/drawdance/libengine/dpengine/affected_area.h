@@ -21,6 +21,7 @@
  */
 #ifndef DP_AFFECTED_AREA
 #define DP_AFFECTED_AREA
+#include "tile_iterator.h"
 #include <dpcommon/common.h>
 #include <dpcommon/geom.h>
 
@@ -106,9 +107,37 @@ typedef struct DP_AffectedIndirectAreas {
 DP_AffectedArea DP_affected_area_make(DP_Message *msg,
                                       DP_AffectedIndirectAreas *aia);
 
+// Like DP_affected_area_make, but for what the message changes on the canvas
+// as it's displayed rather than what it conflicts with. Indirect strokes
+// affect the pixels of their sublayer right away and undos affect everything,
+// since whatever is being undone might have been anywhere.
+DP_AffectedArea DP_affected_area_make_visible(DP_Message *msg,
+                                              DP_AffectedIndirectAreas *aia);
+
 bool DP_affected_area_concurrent_with(const DP_AffectedArea *aa,
                                       const DP_AffectedArea *other);
 
+// Pixel bounds of the affected area, clamped to the given canvas size. Areas
+// that affect everything, the background or layer attributes cover the whole
+// canvas, since changing a layer's opacity, visibility or blend mode changes
+// pixels anywhere on it. Returns false if the area doesn't touch any pixels
+// on the canvas.
+bool DP_affected_area_bounds(const DP_AffectedArea *aa, int canvas_width,
+                             int canvas_height, DP_Rect *out_bounds);
+
+// Combines two affected areas into one that covers both. Pixel areas on the
+// same layer get their bounds unioned, areas in the same domain but with
+// different ids collapse to all ids of that domain and areas in different
+// domains collapse to everything. Merging is commutative.
+DP_AffectedArea DP_affected_area_merge(const DP_AffectedArea *a,
+                                       const DP_AffectedArea *b);
+
+// Iterates over the tiles touched by the affected area's bounds, see
+// DP_affected_area_bounds. The iterator is empty if there are none.
+DP_TileIterator DP_affected_area_tile_iterator_make(const DP_AffectedArea *aa,
+                                                    int canvas_width,
+                                                    int canvas_height);
+
 void DP_affected_indirect_areas_clear(DP_AffectedIndirectAreas *aia);
 
 
@@ -45,6 +45,8 @@ struct DP_Annotation {
     const uint32_t background_color;
     const bool protect;
     const int valign;
+    const int border;
+    const bool auto_grow;
     DP_Text *const text;
 };
 
@@ -59,6 +61,8 @@ struct DP_TransientAnnotation {
     uint32_t background_color;
     bool protect;
     int valign;
+    int border;
+    bool auto_grow;
     DP_Text *text;
 };
 
@@ -75,6 +79,8 @@ struct DP_Annotation {
     uint32_t background_color;
     bool protect;
     int valign;
+    int border;
+    bool auto_grow;
     DP_Text *text;
 };
 
@@ -85,8 +91,8 @@ static DP_TransientAnnotation *
 allocate_annotation(bool transient, int id, int x, int y, int width, int height)
 {
     DP_TransientAnnotation *ta = DP_malloc(sizeof(*ta));
-    *ta = (DP_TransientAnnotation){
-        DP_ATOMIC_INIT(1), transient, id, x, y, width, height, 0, 0, 0, NULL};
+    *ta = (DP_TransientAnnotation){DP_ATOMIC_INIT(1), transient, id, x, y,
+                                   width, height, 0, 0, 0, 0, false, NULL};
     return ta;
 }
 
@@ -195,6 +201,20 @@ int DP_annotation_valign(DP_Annotation *a)
     return a->valign;
 }
 
+int DP_annotation_border(DP_Annotation *a)
+{
+    DP_ASSERT(a);
+    DP_ASSERT(DP_atomic_get(&a->refcount) > 0);
+    return a->border;
+}
+
+bool DP_annotation_auto_grow(DP_Annotation *a)
+{
+    DP_ASSERT(a);
+    DP_ASSERT(DP_atomic_get(&a->refcount) > 0);
+    return a->auto_grow;
+}
+
 const char *DP_annotation_text(DP_Annotation *a, size_t *out_length)
 {
     DP_ASSERT(a);
@@ -214,6 +234,7 @@ bool DP_annotation_equal(DP_Annotation *a, DP_Annotation *b)
             && a->width == b->width && a->height == b->height
             && a->background_color == b->background_color
             && a->protect == b->protect && a->valign == b->valign
+            && a->border == b->border && a->auto_grow == b->auto_grow
             && DP_text_equal(a->text, b->text));
 }
 
@@ -233,6 +254,8 @@ DP_TransientAnnotation *DP_transient_annotation_new(DP_Annotation *a)
                                    a->background_color,
                                    a->protect,
                                    a->valign,
+                                   a->border,
+                                   a->auto_grow,
                                    DP_text_incref_nullable(a->text)};
     return ta;
 }
@@ -320,6 +343,16 @@ int DP_transient_annotation_valign(DP_TransientAnnotation *ta)
     return DP_annotation_valign((DP_Annotation *)ta);
 }
 
+int DP_transient_annotation_border(DP_TransientAnnotation *ta)
+{
+    return DP_annotation_border((DP_Annotation *)ta);
+}
+
+bool DP_transient_annotation_auto_grow(DP_TransientAnnotation *ta)
+{
+    return DP_annotation_auto_grow((DP_Annotation *)ta);
+}
+
 const char *DP_transient_annotation_text(DP_TransientAnnotation *ta,
                                          size_t *out_length)
 {
@@ -402,6 +435,29 @@ void DP_transient_annotation_valign_set(DP_TransientAnnotation *ta, int valign)
     }
 }
 
+void DP_transient_annotation_border_set(DP_TransientAnnotation *ta, int border)
+{
+    DP_ASSERT(ta);
+    DP_ASSERT(DP_atomic_get(&ta->refcount) > 0);
+    DP_ASSERT(ta->transient);
+    if (border >= 0 && border <= DP_ANNOTATION_BORDER_MAX) {
+        ta->border = border;
+    }
+    else {
+        DP_warn("Invalid border width %d", border);
+        ta->border = DP_clamp_int(border, 0, DP_ANNOTATION_BORDER_MAX);
+    }
+}
+
+void DP_transient_annotation_auto_grow_set(DP_TransientAnnotation *ta,
+                                           bool auto_grow)
+{
+    DP_ASSERT(ta);
+    DP_ASSERT(DP_atomic_get(&ta->refcount) > 0);
+    DP_ASSERT(ta->transient);
+    ta->auto_grow = auto_grow;
+}
+
 void DP_transient_annotation_text_set(DP_TransientAnnotation *ta,
                                       const char *text, size_t length)
 {
@@ -27,6 +27,10 @@
 #define DP_ANNOTATION_VALIGN_CENTER 1
 #define DP_ANNOTATION_VALIGN_BOTTOM 2
 
+// Border width in pixels, drawn inside the annotation's rectangle in its
+// background color at full opacity. Zero means no border.
+#define DP_ANNOTATION_BORDER_MAX 255
+
 #ifdef DP_NO_STRICT_ALIASING
 typedef struct DP_Annotation DP_Annotation;
 typedef struct DP_TransientAnnotation DP_TransientAnnotation;
@@ -66,6 +70,13 @@ bool DP_annotation_protect(DP_Annotation *a);
 
 int DP_annotation_valign(DP_Annotation *a);
 
+int DP_annotation_border(DP_Annotation *a);
+
+// Auto-grow annotations get their height fitted to their content whenever
+// they're edited. The engine can't lay out text, so it just stores the flag,
+// the editing client measures the content and sends the reshape.
+bool DP_annotation_auto_grow(DP_Annotation *a);
+
 const char *DP_annotation_text(DP_Annotation *a, size_t *out_length);
 
 int DP_annotation_user_id(DP_Annotation *a);
@@ -109,6 +120,10 @@ bool DP_transient_annotation_protect(DP_TransientAnnotation *ta);
 
 int DP_transient_annotation_valign(DP_TransientAnnotation *ta);
 
+int DP_transient_annotation_border(DP_TransientAnnotation *ta);
+
+bool DP_transient_annotation_auto_grow(DP_TransientAnnotation *ta);
+
 const char *DP_transient_annotation_text(DP_TransientAnnotation *ta,
                                          size_t *out_length);
 
@@ -130,6 +145,11 @@ void DP_transient_annotation_protect_set(DP_TransientAnnotation *ta,
 
 void DP_transient_annotation_valign_set(DP_TransientAnnotation *ta, int valign);
 
+void DP_transient_annotation_border_set(DP_TransientAnnotation *ta, int border);
+
+void DP_transient_annotation_auto_grow_set(DP_TransientAnnotation *ta,
+                                           bool auto_grow);
+
 void DP_transient_annotation_text_set(DP_TransientAnnotation *ta,
                                       const char *text, size_t length);
 
@@ -162,6 +162,26 @@ int DP_annotation_list_index_by_id(DP_AnnotationList *al, int annotation_id)
     return -1;
 }
 
+int DP_annotation_list_index_at(DP_AnnotationList *al, int x, int y,
+                                int expand)
+{
+    DP_ASSERT(al);
+    DP_ASSERT(DP_atomic_get(&al->refcount) > 0);
+    for (int i = al->count - 1; i >= 0; --i) {
+        DP_Annotation *a = al->elements[i].annotation;
+        if (a) {
+            int left = DP_annotation_x(a) - expand;
+            int top = DP_annotation_y(a) - expand;
+            int right = DP_annotation_x(a) + DP_annotation_width(a) + expand;
+            int bottom = DP_annotation_y(a) + DP_annotation_height(a) + expand;
+            if (x >= left && x < right && y >= top && y < bottom) {
+                return i;
+            }
+        }
+    }
+    return -1;
+}
+
 
 DP_TransientAnnotationList *
 DP_transient_annotation_list_new(DP_AnnotationList *al, int reserve)
@@ -304,3 +324,27 @@ void DP_transient_annotation_list_delete_at(DP_TransientAnnotationList *tal,
     memmove(&tal->elements[index], &tal->elements[index + 1],
             DP_int_to_size(new_count - index) * sizeof(tal->elements[0]));
 }
+
+void DP_transient_annotation_list_move(DP_TransientAnnotationList *tal,
+                                       int from_index, int to_index)
+{
+    DP_ASSERT(tal);
+    DP_ASSERT(DP_atomic_get(&tal->refcount) > 0);
+    DP_ASSERT(tal->transient);
+    DP_ASSERT(from_index >= 0);
+    DP_ASSERT(from_index < tal->count);
+    DP_ASSERT(to_index >= 0);
+    DP_ASSERT(to_index < tal->count);
+    DP_Annotation *a = tal->elements[from_index].annotation;
+    if (from_index < to_index) {
+        memmove(&tal->elements[from_index], &tal->elements[from_index + 1],
+                DP_int_to_size(to_index - from_index)
+                    * sizeof(tal->elements[0]));
+    }
+    else if (from_index > to_index) {
+        memmove(&tal->elements[to_index + 1], &tal->elements[to_index],
+                DP_int_to_size(from_index - to_index)
+                    * sizeof(tal->elements[0]));
+    }
+    tal->elements[to_index].annotation = a;
+}
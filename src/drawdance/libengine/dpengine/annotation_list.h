@@ -57,6 +57,14 @@ DP_Annotation *DP_annotation_list_at_noinc(DP_AnnotationList *al, int index);
 
 int DP_annotation_list_index_by_id(DP_AnnotationList *al, int annotation_id);
 
+// Returns the index of the topmost annotation whose rectangle, grown by expand
+// pixels on each side, contains the given point, or -1 if there's none. The
+// right and bottom edges are exclusive, so a point on an edge shared between
+// two annotations only hits the one to the right or below. Annotations with
+// zero width or height can only be hit with a positive expand.
+int DP_annotation_list_index_at(DP_AnnotationList *al, int x, int y,
+                                int expand);
+
 
 DP_TransientAnnotationList *
 DP_transient_annotation_list_new(DP_AnnotationList *al, int reserve);
@@ -94,5 +102,9 @@ void DP_transient_annotation_list_insert_noinc(DP_TransientAnnotationList *tal,
 void DP_transient_annotation_list_delete_at(DP_TransientAnnotationList *tal,
                                             int index);
 
+// Moves the annotation at from_index to to_index, shifting the ones in between.
+void DP_transient_annotation_list_move(DP_TransientAnnotationList *tal,
+                                       int from_index, int to_index);
+
 
 #endif
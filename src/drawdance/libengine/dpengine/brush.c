@@ -22,6 +22,7 @@
 #include "brush.h"
 #include <dpcommon/common.h>
 #include <dpcommon/conversions.h>
+#include <math.h>
 #include <helpers.h> // CLAMP
 
 
@@ -50,39 +51,60 @@ static float lerp_range(const DP_ClassicBrushRange *cbr, float input)
     return max;
 }
 
-static float lerp_range_dynamic(const DP_ClassicBrushRange *cbr, float pressure,
-                                float velocity, float distance,
-                                const DP_ClassicBrushDynamic *dynamic)
+static float velocity_input(float velocity,
+                            const DP_ClassicBrushDynamic *dynamic)
+{
+    DP_ASSERT(velocity >= 0.0f);
+    float max_velocity = dynamic->max_velocity;
+    return velocity < max_velocity ? velocity / max_velocity : 1.0f;
+}
+
+// Input between 0 and 1 for the given dynamic, or 1 if there is none.
+static float dynamic_input(float pressure, float velocity, float distance,
+                           const DP_ClassicBrushDynamic *dynamic)
 {
     switch (dynamic->type) {
     case DP_CLASSIC_BRUSH_DYNAMIC_NONE:
-        return cbr->max;
+        return 1.0f;
     case DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE:
-        return lerp_range(cbr, pressure);
-    case DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY: {
-        DP_ASSERT(velocity >= 0.0f);
-        float max_velocity = dynamic->max_velocity;
-        float vinput = velocity < max_velocity ? velocity / max_velocity : 1.0f;
-        return lerp_range(cbr, vinput);
-    }
+        return pressure;
+    case DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY:
+        return velocity_input(velocity, dynamic);
     case DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE: {
         DP_ASSERT(distance >= 0.0f);
         float max_distance = dynamic->max_distance;
-        float dinput = distance < max_distance ? distance / max_distance : 1.0f;
-        return lerp_range(cbr, dinput);
+        return distance < max_distance ? distance / max_distance : 1.0f;
     }
+    case DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY:
+        return pressure * (1.0f - velocity_input(velocity, dynamic));
     }
     DP_UNREACHABLE();
 }
 
+static float lerp_range_dynamic(const DP_ClassicBrushRange *cbr, float pressure,
+                                float velocity, float distance,
+                                const DP_ClassicBrushDynamic *dynamic)
+{
+    if (dynamic->type == DP_CLASSIC_BRUSH_DYNAMIC_NONE) {
+        return cbr->max;
+    }
+    else {
+        return lerp_range(
+            cbr, dynamic_input(pressure, velocity, distance, dynamic));
+    }
+}
+
 float DP_classic_brush_spacing_at(const DP_ClassicBrush *cb, float pressure,
                                   float velocity, float distance)
 {
     DP_ASSERT(cb);
     DP_ASSERT(pressure >= 0.0f);
     DP_ASSERT(pressure <= 1.0f);
-    return cb->spacing
-         * DP_classic_brush_size_at(cb, pressure, velocity, distance);
+    // Written this way around so that a NaN spacing gets clamped too.
+    float spacing = cb->spacing >= DP_CLASSIC_BRUSH_SPACING_MIN
+                      ? cb->spacing
+                      : DP_CLASSIC_BRUSH_SPACING_MIN;
+    return spacing * DP_classic_brush_size_at(cb, pressure, velocity, distance);
 }
 
 float DP_classic_brush_size_at(const DP_ClassicBrush *cb, float pressure,
@@ -125,10 +147,49 @@ float DP_classic_brush_smudge_at(const DP_ClassicBrush *cb, float pressure,
                               &cb->smudge_dynamic);
 }
 
+float DP_classic_brush_color_mix_at(const DP_ClassicBrush *cb, float pressure,
+                                    float velocity, float distance)
+{
+    DP_ASSERT(cb);
+    DP_ASSERT(pressure >= 0.0f);
+    DP_ASSERT(pressure <= 1.0f);
+    if (cb->color_dynamic.type == DP_CLASSIC_BRUSH_DYNAMIC_NONE) {
+        return 0.0f;
+    }
+    else {
+        float input =
+            dynamic_input(pressure, velocity, distance, &cb->color_dynamic);
+        return CLAMP(input, 0.0f, 1.0f);
+    }
+}
+
+DP_UPixelFloat DP_classic_brush_color_at(const DP_ClassicBrush *cb,
+                                         DP_UPixelFloat color, float pressure,
+                                         float velocity, float distance)
+{
+    float mix = DP_classic_brush_color_mix_at(cb, pressure, velocity, distance);
+    if (mix > 0.0f) {
+        DP_UPixelFloat color2 = cb->color2;
+        color2.a = color.a;
+        return DP_upixel_float_mix_linear(color, color2, mix);
+    }
+    else {
+        return color;
+    }
+}
+
 DP_BlendMode DP_classic_brush_blend_mode(const DP_ClassicBrush *cb)
 {
     DP_ASSERT(cb);
-    return cb->erase ? cb->erase_mode : cb->brush_mode;
+    if (!cb->erase) {
+        return cb->brush_mode;
+    }
+    else if (cb->erase_target == DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR) {
+        return DP_BLEND_MODE_NORMAL;
+    }
+    else {
+        return cb->erase_mode;
+    }
 }
 
 
@@ -155,9 +216,12 @@ uint8_t DP_classic_brush_dab_opacity_at(const DP_ClassicBrush *cb,
                                         float pressure, float velocity,
                                         float distance)
 {
-    float value =
-        DP_classic_brush_opacity_at(cb, pressure, velocity, distance) * 255.0f
-        + 0.5f;
+    float opacity =
+        DP_classic_brush_opacity_at(cb, pressure, velocity, distance);
+    float mix = DP_classic_brush_color_mix_at(cb, pressure, velocity, distance);
+    // Mixing towards a transparent color fades the dabs out.
+    float fade = 1.0f + (cb->color2.a - 1.0f) * mix;
+    float value = opacity * CLAMP(fade, 0.0f, 1.0f) * 255.0f + 0.5f;
     return DP_float_to_uint8(CLAMP(value, 0, UINT8_MAX));
 }
 
@@ -171,6 +235,29 @@ uint8_t DP_classic_brush_dab_hardness_at(const DP_ClassicBrush *cb,
     return DP_float_to_uint8(CLAMP(value, 0, UINT8_MAX));
 }
 
+uint8_t DP_classic_brush_dab_stamp_angle(const DP_ClassicBrush *cb,
+                                         float turns)
+{
+    DP_ASSERT(cb);
+    float angle = cb->stamp_angle + turns;
+    float wrapped = angle - floorf(angle);
+    return (uint8_t)(DP_float_to_int(wrapped * 256.0f + 0.5f) & 0xff);
+}
+
+uint16_t DP_classic_brush_dab_grain_scale(const DP_ClassicBrush *cb)
+{
+    DP_ASSERT(cb);
+    float value = cb->grain_scale * 256.0f + 0.5f;
+    return DP_float_to_uint16(CLAMP(value, 1, UINT16_MAX));
+}
+
+uint8_t DP_classic_brush_dab_grain_strength(const DP_ClassicBrush *cb)
+{
+    DP_ASSERT(cb);
+    float value = cb->grain_strength * 255.0f + 0.5f;
+    return DP_float_to_uint8(CLAMP(value, 0, UINT8_MAX));
+}
+
 
 void DP_mypaint_brush_mode_extract(uint8_t mode, int *out_blend_mode,
                                    bool *out_indirect,
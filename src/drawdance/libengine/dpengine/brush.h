@@ -36,15 +36,57 @@
 #define DP_MYPAINT_BRUSH_MODE_ERASE       0x3
 #define DP_MYPAINT_BRUSH_MODE_MASK        0x3
 
+// Smallest spacing between dabs as a fraction of their diameter, so that a
+// spacing of zero doesn't emit an unbounded number of dabs.
+#define DP_CLASSIC_BRUSH_SPACING_MIN 0.01f
+
 
 typedef enum DP_BrushShape {
     DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND,
     DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE,
     DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND,
     DP_BRUSH_SHAPE_MYPAINT,
+    DP_BRUSH_SHAPE_CLASSIC_STAMP,
     DP_BRUSH_SHAPE_COUNT,
 } DP_BrushShape;
 
+// Profile of the hardness falloff of soft round classic brush dabs. All of
+// them cover about the same area at the same hardness, they only differ in how
+// the edge fades out. Sent along in the falloff field of classic dabs.
+typedef enum DP_ClassicBrushFalloff {
+    DP_CLASSIC_BRUSH_FALLOFF_RAMP,
+    DP_CLASSIC_BRUSH_FALLOFF_GAUSSIAN,
+    DP_CLASSIC_BRUSH_FALLOFF_SMOOTHSTEP,
+    DP_CLASSIC_BRUSH_FALLOFF_COUNT,
+} DP_ClassicBrushFalloff;
+
+// Where brushes that mix with the canvas pick up color from, either the layer
+// being drawn on or everything that's visible, not including strokes that are
+// still in progress. The picked colors end up in the dabs, so this only
+// matters on the painting client.
+typedef enum DP_BrushPickupMode {
+    DP_BRUSH_PICKUP_MODE_LAYER,
+    DP_BRUSH_PICKUP_MODE_MERGED,
+} DP_BrushPickupMode;
+
+// What stamp dabs get rotated by in addition to the stamp angle. Direction is
+// the direction the stroke is going in, averaged over a short distance so that
+// jittery input doesn't make the dabs flicker around. Tilt is the direction
+// the stylus is leaning in, if the device reports it.
+typedef enum DP_ClassicBrushAngleMode {
+    DP_CLASSIC_BRUSH_ANGLE_MODE_FIXED,
+    DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION,
+    DP_CLASSIC_BRUSH_ANGLE_MODE_TILT,
+} DP_ClassicBrushAngleMode;
+
+// What erasing classic brushes leave behind. Transparent uses the erase blend
+// mode. Color paints over with the erase color in the normal blend mode, which
+// is mostly useful on background layers that shouldn't get holes in them.
+typedef enum DP_ClassicBrushEraseTarget {
+    DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT,
+    DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR,
+} DP_ClassicBrushEraseTarget;
+
 typedef struct DP_ClassicBrushCurve {
     float values[DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT];
 } DP_ClassicBrushCurve;
@@ -55,11 +97,15 @@ typedef struct DP_ClassicBrushRange {
     DP_ClassicBrushCurve curve;
 } DP_ClassicBrushRange;
 
+// Pressure and velocity takes the pressure and lets less of it through the
+// faster the stroke goes, down to none at the maximum velocity. That makes for
+// lines that follow the pen, but get thinner or fainter when moving quickly.
 typedef enum DP_ClassicBrushDynamicType {
     DP_CLASSIC_BRUSH_DYNAMIC_NONE,
     DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE,
     DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY,
     DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE,
+    DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY,
 } DP_ClassicBrushDynamicType;
 
 typedef struct DP_ClassicBrushDynamic {
@@ -73,19 +119,48 @@ typedef struct DP_ClassicBrush {
     DP_ClassicBrushRange hardness;
     DP_ClassicBrushRange opacity;
     DP_ClassicBrushRange smudge;
+    // Distance between dabs along the stroke as a fraction of the diameter.
     float spacing;
     int resmudge;
+    // Dabs per second that keep getting deposited at the current position
+    // while a stroke is active, even if the brush doesn't move. Zero turns
+    // that off. Those dabs have their opacity multiplied by the flow.
+    float airbrush_rate;
+    float airbrush_flow;
+    DP_BrushPickupMode pickup_mode;
     DP_UPixelFloat color;
+    // Color that the dabs get blended towards as the color dynamic's input
+    // goes up. If its alpha is below one, the dabs fade out along with it.
+    DP_UPixelFloat color2;
     DP_BrushShape shape;
+    DP_ClassicBrushFalloff falloff;
+    // Id of a registered stamp mask for DP_BRUSH_SHAPE_CLASSIC_STAMP, see
+    // stamp_mask.h. The angle is in turns, so 1.0 would be a full rotation.
+    uint32_t stamp_mask;
+    float stamp_angle;
+    DP_ClassicBrushAngleMode stamp_angle_mode;
+    // Id of a registered stamp mask that gets tiled across the canvas as paper
+    // grain, zero for none. The scale is the size of a grain pixel in canvas
+    // pixels, the strength how much coverage the grain can take away, between
+    // 0 and 1. Pixel brush shapes don't get any grain.
+    uint32_t grain;
+    float grain_scale;
+    float grain_strength;
     DP_BlendMode brush_mode;
     DP_BlendMode erase_mode;
+    DP_ClassicBrushEraseTarget erase_target;
+    DP_UPixelFloat erase_color;
     bool erase;
     bool incremental;
     bool colorpick;
+    // Drop the corner pixels of L shapes in diagonal pixel brush strokes, so
+    // that one pixel lines come out clean. Only affects pixel brush shapes.
+    bool pixel_perfect;
     DP_ClassicBrushDynamic size_dynamic;
     DP_ClassicBrushDynamic hardness_dynamic;
     DP_ClassicBrushDynamic opacity_dynamic;
     DP_ClassicBrushDynamic smudge_dynamic;
+    DP_ClassicBrushDynamic color_dynamic;
 } DP_ClassicBrush;
 
 
@@ -109,6 +184,7 @@ typedef struct DP_MyPaintBrush {
     bool lock_alpha;
     bool erase;
     bool incremental;
+    DP_BrushPickupMode pickup_mode;
 } DP_MyPaintBrush;
 
 
@@ -127,6 +203,16 @@ float DP_classic_brush_opacity_at(const DP_ClassicBrush *cb, float pressure,
 float DP_classic_brush_smudge_at(const DP_ClassicBrush *cb, float pressure,
                                  float velocity, float distance);
 
+// How far to mix towards the secondary color, between 0 and 1.
+float DP_classic_brush_color_mix_at(const DP_ClassicBrush *cb, float pressure,
+                                    float velocity, float distance);
+
+// The given color mixed towards the secondary color in linear light. Keeps the
+// alpha of the given color, the secondary alpha affects dab opacity instead.
+DP_UPixelFloat DP_classic_brush_color_at(const DP_ClassicBrush *cb,
+                                         DP_UPixelFloat color, float pressure,
+                                         float velocity, float distance);
+
 DP_BlendMode DP_classic_brush_blend_mode(const DP_ClassicBrush *cb);
 
 
@@ -146,6 +232,17 @@ uint8_t DP_classic_brush_dab_hardness_at(const DP_ClassicBrush *cb,
                                          float pressure, float velocity,
                                          float distance);
 
+// Stamp angle plus the given number of turns, wrapped into a single turn, in
+// 256ths of a turn.
+uint8_t DP_classic_brush_dab_stamp_angle(const DP_ClassicBrush *cb,
+                                         float turns);
+
+// Grain scale multiplied by 256, at least 1.
+uint16_t DP_classic_brush_dab_grain_scale(const DP_ClassicBrush *cb);
+
+// Grain strength between 0 and 255.
+uint8_t DP_classic_brush_dab_grain_strength(const DP_ClassicBrush *cb);
+
 
 void DP_mypaint_brush_mode_extract(uint8_t mode, int *out_blend_mode,
                                    bool *out_indirect,
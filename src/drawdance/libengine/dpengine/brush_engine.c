@@ -5,6 +5,8 @@
 #include "draw_context.h"
 #include "layer_content.h"
 #include "layer_routes.h"
+#include "stamp_mask.h"
+#include "tile.h"
 #include <dpcommon/atomic.h>
 #include <dpcommon/common.h>
 #include <dpcommon/conversions.h>
@@ -13,10 +15,11 @@
 #include <dpcommon/queue.h>
 #include <dpcommon/vector.h>
 #include <dpmsg/message.h>
+#include <limits.h>
 #include <math.h>
 #include <mypaint-brush.h>
 #include <mypaint.h>
-#include <helpers.h> // RGB <-> HSV conversion, CLAMP, mod_arith
+#include <helpers.h> // RGB <-> HSV conversion, CLAMP, mod_arith, M_PI
 
 #define DP_PERF_CONTEXT "brush_engine"
 
@@ -35,7 +38,13 @@
 // Same amount of smudge buckets that MyPaint uses.
 #define SMUDGE_BUCKET_COUNT 256
 #define MIN_DABS_CAPACITY   1024
+// Soft dabs go out as either classicdabs or softdabs, so both limits apply.
+#define SOFT_DABS_MAX \
+    DP_MIN(DP_MSG_DRAW_DABS_CLASSIC_DABS_MAX, DP_MSG_DRAW_DABS_SOFT_DABS_MAX)
 #define MAX_XY_DELTA        127
+// Dabs get held back and merged into as few messages as possible, but no
+// longer than this, so that others still get to see the stroke as it's drawn.
+#define DABS_FLUSH_DELAY_MSEC 80
 
 // Based on MyPaint's velocity calculations for fine speed.
 #define CLASSIC_VELOCITY_GAMMA    54.598148f
@@ -43,9 +52,18 @@
 #define CLASSIC_VELOCITY_Q        -6.373980f
 #define CLASSIC_VELOCITY_SLOWNESS 0.04f
 
+// Stroke direction for rotating dabs is averaged over about this many pixels.
+#define CLASSIC_DIRECTION_DISTANCE 16.0f
+// Tilt of less than this many degrees doesn't have a meaningful direction.
+#define CLASSIC_TILT_MIN           1.0f
+// One airbrush dab per millisecond at most, more fine-grained timing than that
+// isn't available anyway.
+#define AIRBRUSH_RATE_MAX          1000.0f
+
 typedef enum DP_BrushEngineActiveType {
     DP_BRUSH_ENGINE_ACTIVE_PIXEL,
     DP_BRUSH_ENGINE_ACTIVE_SOFT,
+    DP_BRUSH_ENGINE_ACTIVE_STAMP,
     DP_BRUSH_ENGINE_ACTIVE_MYPAINT,
 } DP_BrushEngineActiveType;
 
@@ -64,6 +82,14 @@ typedef struct DP_BrushEngineClassicDab {
     uint8_t opacity;
 } DP_BrushEngineClassicDab;
 
+typedef struct DP_BrushEngineStampDab {
+    int8_t x;
+    int8_t y;
+    uint16_t size;
+    uint8_t angle;
+    uint8_t opacity;
+} DP_BrushEngineStampDab;
+
 typedef struct DP_BrushEngineMyPaintDab {
     int8_t x;
     int8_t y;
@@ -84,9 +110,16 @@ struct DP_BrushEngine {
     int layer_id;
     DP_LayerContent *lc;
     DP_CanvasState *cs;
+    DP_BrushPickupMode pickup_mode;
+    struct {
+        DP_TransientLayerContent *tlc;
+        bool *flattened;
+    } merged;
     DP_BrushStampBuffer stamp_buffer;
     int last_diameter;
     DP_BrushEngineActiveType active;
+    DP_StampMask *stamp_mask;
+    DP_StampMask *grain_mask;
     MyPaintBrush *mypaint_brush;
     MyPaintSurface2 mypaint_surface2;
     struct {
@@ -118,6 +151,17 @@ struct DP_BrushEngine {
         DP_Vector points;
         DP_BrushPoint last_point;
     } stabilizer;
+    struct {
+        float distance;
+        bool active;
+        DP_BrushPoint brush;
+        DP_BrushPoint target;
+    } pull_string;
+    struct {
+        DP_StrokeSymmetry params;
+        size_t capacity;
+        void *buffer;
+    } symmetry;
     union {        // Active type decides which of these is relevant.
         int dummy; // Make this initializable without the compiler whining.
         struct {
@@ -134,9 +178,25 @@ struct DP_BrushEngine {
             float last_pressure;
             float last_velocity;
             float last_distance;
+            float direction_x;
+            float direction_y;
+            float angle;
+            float flow;
+            long long airbrush_next_msec;
             int32_t dab_x;
             int32_t dab_y;
             uint32_t dab_color;
+            struct {
+                bool have_last;
+                bool have_pending;
+                int last_x;
+                int last_y;
+                int pending_x;
+                int pending_y;
+                uint8_t pending_size;
+                uint8_t pending_opacity;
+                uint32_t pending_color;
+            } pixel_perfect;
         } classic;
         struct {
             bool lock_alpha;
@@ -155,6 +215,8 @@ struct DP_BrushEngine {
         int32_t last_x;
         int32_t last_y;
         int used;
+        long long now_msec;
+        long long first_msec;
         size_t capacity;
         void *buffer;
     } dabs;
@@ -317,6 +379,9 @@ static void *get_dab_buffer(DP_BrushEngine *be, size_t element_size)
 {
     size_t capacity = be->dabs.capacity;
     int used = be->dabs.used;
+    if (used == 0) {
+        be->dabs.first_msec = be->dabs.now_msec;
+    }
     int max_used = DP_size_to_int(capacity / element_size);
     if (used == max_used) {
         size_t new_capacity = DP_max_size(capacity * 2, MIN_DABS_CAPACITY);
@@ -350,21 +415,141 @@ static uint8_t get_uint8(float input)
 }
 
 
+static uint32_t get_classic_dab_color(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                                      float pressure, float velocity,
+                                      float distance)
+{
+    return combine_upixel_float(DP_classic_brush_color_at(
+        cb, be->classic.smudge_color, pressure, velocity, distance));
+}
+
+// Airbrush dabs get their regular opacity scaled by the flow, everything else
+// has a flow of 1 and so is unaffected.
+static uint8_t get_classic_dab_opacity(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                                       float pressure, float velocity,
+                                       float distance)
+{
+    uint8_t opacity =
+        DP_classic_brush_dab_opacity_at(cb, pressure, velocity, distance);
+    float flow = be->classic.flow;
+    if (flow < 1.0f) {
+        float value = DP_uint8_to_float(opacity) * flow + 0.5f;
+        return DP_float_to_uint8(CLAMP(value, 0, UINT8_MAX));
+    }
+    else {
+        return opacity;
+    }
+}
+
+static void push_dab_pixel(DP_BrushEngine *be, int x, int y, uint8_t dab_size,
+                           uint8_t dab_opacity, uint32_t dab_color)
+{
+    int32_t dab_x = DP_int_to_int32(x);
+    int32_t dab_y = DP_int_to_int32(y);
+
+    int used = be->dabs.used;
+    int8_t dx, dy;
+    bool can_append = used != 0 && used < DP_MSG_DRAW_DABS_PIXEL_DABS_MAX
+                   && be->classic.dab_color == dab_color
+                   && delta_xy(be, dab_x, dab_y, &dx, &dy);
+    be->dabs.last_x = dab_x;
+    be->dabs.last_y = dab_y;
+
+    if (!can_append) {
+        DP_brush_engine_dabs_flush(be);
+        be->classic.dab_x = dab_x;
+        be->classic.dab_y = dab_y;
+        be->classic.dab_color = dab_color;
+        dx = 0;
+        dy = 0;
+    }
+
+    DP_BrushEnginePixelDab *dabs = get_dab_buffer(be, sizeof(*dabs));
+    dabs[be->dabs.used++] =
+        (DP_BrushEnginePixelDab){dx, dy, dab_size, dab_opacity};
+}
+
+static void push_pending_dab_pixel(DP_BrushEngine *be)
+{
+    if (be->classic.pixel_perfect.have_pending) {
+        int x = be->classic.pixel_perfect.pending_x;
+        int y = be->classic.pixel_perfect.pending_y;
+        push_dab_pixel(be, x, y, be->classic.pixel_perfect.pending_size,
+                       be->classic.pixel_perfect.pending_opacity,
+                       be->classic.pixel_perfect.pending_color);
+        be->classic.pixel_perfect.have_last = true;
+        be->classic.pixel_perfect.last_x = x;
+        be->classic.pixel_perfect.last_y = y;
+        be->classic.pixel_perfect.have_pending = false;
+    }
+}
+
+// Pixel-perfect mode holds back each dab until the next one comes in. If that
+// one is diagonal to the dab before the held one, the held one is the corner
+// of an L shape and gets dropped. This only makes sense with the one pixel
+// spacing of a small pixel brush, otherwise dabs are never adjacent anyway.
+static void place_dab_pixel_perfect(DP_BrushEngine *be, int x, int y,
+                                    uint8_t dab_size, uint8_t dab_opacity,
+                                    uint32_t dab_color)
+{
+    if (be->classic.pixel_perfect.have_pending) {
+        bool corner = be->classic.pixel_perfect.have_last
+                   && abs(x - be->classic.pixel_perfect.last_x) == 1
+                   && abs(y - be->classic.pixel_perfect.last_y) == 1;
+        if (corner) {
+            be->classic.pixel_perfect.have_pending = false;
+        }
+        else {
+            push_pending_dab_pixel(be);
+        }
+    }
+    be->classic.pixel_perfect.have_pending = true;
+    be->classic.pixel_perfect.pending_x = x;
+    be->classic.pixel_perfect.pending_y = y;
+    be->classic.pixel_perfect.pending_size = dab_size;
+    be->classic.pixel_perfect.pending_opacity = dab_opacity;
+    be->classic.pixel_perfect.pending_color = dab_color;
+}
+
 static void add_dab_pixel(DP_BrushEngine *be, DP_ClassicBrush *cb, int x, int y,
                           float pressure, float velocity, float distance)
 {
     uint8_t dab_size =
         DP_classic_brush_pixel_dab_size_at(cb, pressure, velocity, distance);
     uint8_t dab_opacity =
-        DP_classic_brush_dab_opacity_at(cb, pressure, velocity, distance);
+        get_classic_dab_opacity(be, cb, pressure, velocity, distance);
     if (dab_size > 0 && dab_opacity > 0) {
-        int32_t dab_x = DP_int_to_int32(x);
-        int32_t dab_y = DP_int_to_int32(y);
-        uint32_t dab_color = combine_upixel_float(be->classic.smudge_color);
+        uint32_t dab_color =
+            get_classic_dab_color(be, cb, pressure, velocity, distance);
+        if (cb->pixel_perfect) {
+            place_dab_pixel_perfect(be, x, y, dab_size, dab_opacity,
+                                    dab_color);
+        }
+        else {
+            push_dab_pixel(be, x, y, dab_size, dab_opacity, dab_color);
+        }
+    }
+}
+
+
+static void add_dab_soft(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
+                         float y, float pressure, float velocity,
+                         float distance)
+{
+    uint16_t dab_size =
+        DP_classic_brush_soft_dab_size_at(cb, pressure, velocity, distance);
+    uint8_t dab_opacity =
+        get_classic_dab_opacity(be, cb, pressure, velocity, distance);
+    // Disregard infinitesimal or fully opaque dabs. 26 is a radius of 0.1.
+    if (dab_size >= 26 && dab_opacity > 0) {
+        int32_t dab_x = DP_float_to_int32(x * 4.0f);
+        int32_t dab_y = DP_float_to_int32(y * 4.0f);
+        uint32_t dab_color =
+            get_classic_dab_color(be, cb, pressure, velocity, distance);
 
         int used = be->dabs.used;
         int8_t dx, dy;
-        bool can_append = used != 0 && used < DP_MSG_DRAW_DABS_PIXEL_DABS_MAX
+        bool can_append = used != 0 && used < SOFT_DABS_MAX
                        && be->classic.dab_color == dab_color
                        && delta_xy(be, dab_x, dab_y, &dx, &dy);
         be->dabs.last_x = dab_x;
@@ -379,30 +564,32 @@ static void add_dab_pixel(DP_BrushEngine *be, DP_ClassicBrush *cb, int x, int y,
             dy = 0;
         }
 
-        DP_BrushEnginePixelDab *dabs = get_dab_buffer(be, sizeof(*dabs));
-        dabs[be->dabs.used++] =
-            (DP_BrushEnginePixelDab){dx, dy, dab_size, dab_opacity};
+        DP_BrushEngineClassicDab *dabs = get_dab_buffer(be, sizeof(*dabs));
+        dabs[be->dabs.used++] = (DP_BrushEngineClassicDab){
+            dx, dy, dab_size,
+            DP_classic_brush_dab_hardness_at(cb, pressure, velocity, distance),
+            dab_opacity};
     }
 }
 
-
-static void add_dab_soft(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
-                         float y, float pressure, float velocity,
-                         float distance)
+static void add_dab_stamp(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
+                          float y, float pressure, float velocity,
+                          float distance)
 {
     uint16_t dab_size =
         DP_classic_brush_soft_dab_size_at(cb, pressure, velocity, distance);
     uint8_t dab_opacity =
-        DP_classic_brush_dab_opacity_at(cb, pressure, velocity, distance);
-    // Disregard infinitesimal or fully opaque dabs. 26 is a radius of 0.1.
+        get_classic_dab_opacity(be, cb, pressure, velocity, distance);
+    // Same limits as for soft dabs, the size is the diameter in both cases.
     if (dab_size >= 26 && dab_opacity > 0) {
         int32_t dab_x = DP_float_to_int32(x * 4.0f);
         int32_t dab_y = DP_float_to_int32(y * 4.0f);
-        uint32_t dab_color = combine_upixel_float(be->classic.smudge_color);
+        uint32_t dab_color =
+            get_classic_dab_color(be, cb, pressure, velocity, distance);
 
         int used = be->dabs.used;
         int8_t dx, dy;
-        bool can_append = used != 0 && used < DP_MSG_DRAW_DABS_CLASSIC_DABS_MAX
+        bool can_append = used != 0 && used < DP_MSG_DRAW_DABS_STAMP_DABS_MAX
                        && be->classic.dab_color == dab_color
                        && delta_xy(be, dab_x, dab_y, &dx, &dy);
         be->dabs.last_x = dab_x;
@@ -417,10 +604,10 @@ static void add_dab_soft(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
             dy = 0;
         }
 
-        DP_BrushEngineClassicDab *dabs = get_dab_buffer(be, sizeof(*dabs));
-        dabs[be->dabs.used++] = (DP_BrushEngineClassicDab){
+        DP_BrushEngineStampDab *dabs = get_dab_buffer(be, sizeof(*dabs));
+        dabs[be->dabs.used++] = (DP_BrushEngineStampDab){
             dx, dy, dab_size,
-            DP_classic_brush_dab_hardness_at(cb, pressure, velocity, distance),
+            DP_classic_brush_dab_stamp_angle(cb, be->classic.angle),
             dab_opacity};
     }
 }
@@ -616,29 +803,85 @@ static int add_dab_mypaint(MyPaintSurface *self, float x, float y, float radius,
                                    lock_alpha, colorize, 0.0f, 0.0f, 0.0f);
 }
 
+static void dispose_merged(DP_BrushEngine *be)
+{
+    if (be->merged.tlc) {
+        DP_transient_layer_content_decref(be->merged.tlc);
+        DP_free(be->merged.flattened);
+        be->merged.tlc = NULL;
+        be->merged.flattened = NULL;
+    }
+}
+
+// Merged pickup only flattens the tiles that actually get sampled and keeps
+// them around until the canvas state changes. Sublayers aren't included, so
+// the brush doesn't pick up its own indirect stroke that's still in progress.
+static DP_TransientLayerContent *flatten_merged_area(DP_BrushEngine *be, int x,
+                                                     int y, int diameter)
+{
+    DP_CanvasState *cs = be->cs;
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    if (!be->merged.tlc) {
+        be->merged.tlc =
+            DP_transient_layer_content_new_init(width, height, NULL);
+        size_t tile_total = DP_int_to_size(DP_tile_total_round(width, height));
+        be->merged.flattened =
+            DP_malloc_zeroed(sizeof(*be->merged.flattened) * tile_total);
+    }
+
+    int radius = diameter / 2 + 1;
+    int xtiles = DP_tile_count_round(width);
+    int left = DP_max_int(x - radius, 0) / DP_TILE_SIZE;
+    int top = DP_max_int(y - radius, 0) / DP_TILE_SIZE;
+    int right = DP_min_int(x + radius, width - 1) / DP_TILE_SIZE;
+    int bottom = DP_min_int(y + radius, height - 1) / DP_TILE_SIZE;
+    for (int ty = top; ty <= bottom; ++ty) {
+        for (int tx = left; tx <= right; ++tx) {
+            int i = ty * xtiles + tx;
+            if (!be->merged.flattened[i]) {
+                DP_TransientTile *tt =
+                    DP_canvas_state_flatten_tile(cs, i, 0, NULL);
+                DP_transient_layer_content_transient_tile_set_noinc(
+                    be->merged.tlc, tt, i);
+                be->merged.flattened[i] = true;
+            }
+        }
+    }
+    return be->merged.tlc;
+}
+
+static DP_UPixelFloat sample_color_at(DP_BrushEngine *be, DP_LayerContent *lc,
+                                      int x, int y, int diameter, bool opaque)
+{
+    if (be->pickup_mode == DP_BRUSH_PICKUP_MODE_MERGED && be->cs) {
+        DP_TransientLayerContent *tlc = flatten_merged_area(be, x, y, diameter);
+        return DP_transient_layer_content_sample_color_at(
+            tlc, be->stamp_buffer, x, y, diameter, opaque, &be->last_diameter);
+    }
+    else if (lc) {
+        return DP_layer_content_sample_color_at(
+            lc, be->stamp_buffer, x, y, diameter, opaque, &be->last_diameter);
+    }
+    else {
+        return DP_upixel_float_zero();
+    }
+}
+
 static void get_color_mypaint_pigment(MyPaintSurface2 *self, float x, float y,
                                       float radius, float *color_r,
                                       float *color_g, float *color_b,
                                       float *color_a, DP_UNUSED float paint)
 {
     DP_BrushEngine *be = get_mypaint_surface_brush_engine(self);
-    DP_LayerContent *lc = be->lc;
-    if (lc) {
-        int diameter = DP_min_int(DP_float_to_int(radius * 2.0f + 0.5f), 255);
-        DP_UPixelFloat color = DP_layer_content_sample_color_at(
-            lc, be->stamp_buffer, DP_float_to_int(x + 0.5f),
-            DP_float_to_int(y + 0.5f), diameter, false, &be->last_diameter);
-        *color_r = color.r;
-        *color_g = color.g;
-        *color_b = color.b;
-        *color_a = color.a;
-    }
-    else {
-        *color_r = 0.0f;
-        *color_g = 0.0f;
-        *color_b = 0.0f;
-        *color_a = 0.0f;
-    }
+    int diameter = DP_min_int(DP_float_to_int(radius * 2.0f + 0.5f), 255);
+    DP_UPixelFloat color =
+        sample_color_at(be, be->lc, DP_float_to_int(x + 0.5f),
+                        DP_float_to_int(y + 0.5f), diameter, false);
+    *color_r = color.r;
+    *color_g = color.g;
+    *color_b = color.b;
+    *color_a = color.a;
 }
 
 static void get_color_mypaint(MyPaintSurface *self, float x, float y,
@@ -661,9 +904,13 @@ DP_brush_engine_new(DP_BrushEnginePushMessageFn push_message,
         0,
         NULL,
         NULL,
+        DP_BRUSH_PICKUP_MODE_LAYER,
+        {NULL, NULL},
         {0},
         -1,
         DP_BRUSH_ENGINE_ACTIVE_PIXEL,
+        NULL,
+        NULL,
         mypaint_brush_new_with_buckets(SMUDGE_BUCKET_COUNT),
         {{add_dab_mypaint, get_color_mypaint, NULL, NULL, NULL, NULL, 0},
          add_dab_mypaint_pigment,
@@ -683,8 +930,13 @@ DP_brush_engine_new(DP_BrushEnginePushMessageFn push_message,
          DP_QUEUE_NULL,
          DP_VECTOR_NULL,
          {0.0f, 0.0f, 0.0f, 0.0f, 0.0f, 0.0f, 0}},
+        {0.0f,
+         false,
+         {0.0f, 0.0f, 0.0f, 0.0f, 0.0f, 0.0f, 0},
+         {0.0f, 0.0f, 0.0f, 0.0f, 0.0f, 0.0f, 0}},
+        {{DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}, 0, NULL},
         {0},
-        {0, 0, 0, 0, NULL},
+        {0, 0, 0, 0, 0, 0, NULL},
         push_message,
         poll_control_or_null,
         user};
@@ -696,8 +948,12 @@ DP_brush_engine_new(DP_BrushEnginePushMessageFn push_message,
 void DP_brush_engine_free(DP_BrushEngine *be)
 {
     if (be) {
+        DP_free(be->symmetry.buffer);
         DP_free(be->dabs.buffer);
+        DP_stamp_mask_decref_nullable(be->grain_mask);
+        DP_stamp_mask_decref_nullable(be->stamp_mask);
         mypaint_brush_unref(be->mypaint_brush);
+        dispose_merged(be);
         DP_layer_content_decref_nullable(be->lc);
         DP_free(be->smoother.points);
         DP_vector_dispose(&be->stabilizer.points);
@@ -714,6 +970,9 @@ static void set_common_stroke_params(DP_BrushEngine *be,
     be->spline.enabled = stroke->interpolate;
     be->stabilizer.sample_count = stroke->stabilizer_sample_count;
     be->stabilizer.finish_strokes = stroke->stabilizer_finish_strokes;
+    be->pull_string.distance =
+        DP_int_to_float(DP_max_int(0, stroke->pull_string_distance));
+    be->symmetry.params = stroke->symmetry;
 
     int smoothing = DP_max_int(0, stroke->smoothing);
     be->smoother.size = smoothing;
@@ -738,24 +997,58 @@ void DP_brush_engine_classic_brush_set(DP_BrushEngine *be,
     DP_ASSERT(stroke->layer_id >= 0);
     DP_ASSERT(stroke->layer_id <= UINT16_MAX);
 
+    DP_brush_engine_dabs_flush(be);
     set_common_stroke_params(be, stroke);
+    be->pickup_mode = brush->pickup_mode;
+    DP_stamp_mask_decref_nullable(be->stamp_mask);
+    be->stamp_mask = NULL;
+    DP_stamp_mask_decref_nullable(be->grain_mask);
+    be->grain_mask = NULL;
 
     switch (brush->shape) {
     case DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND:
     case DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE:
         be->active = DP_BRUSH_ENGINE_ACTIVE_PIXEL;
         break;
+    case DP_BRUSH_SHAPE_CLASSIC_STAMP:
+        // Without a mask to stamp, fall back to a soft round brush.
+        be->stamp_mask = DP_stamp_mask_search_inc(brush->stamp_mask);
+        be->active = be->stamp_mask ? DP_BRUSH_ENGINE_ACTIVE_STAMP
+                                    : DP_BRUSH_ENGINE_ACTIVE_SOFT;
+        break;
     default:
         be->active = DP_BRUSH_ENGINE_ACTIVE_SOFT;
         break;
     }
 
+    // Grain that isn't registered is left out, so that dabs never refer to
+    // a mask that nobody else can resolve.
+    if (be->active != DP_BRUSH_ENGINE_ACTIVE_PIXEL && brush->grain != 0) {
+        be->grain_mask = DP_stamp_mask_search_inc(brush->grain);
+    }
+
     be->classic.brush = *brush;
     DP_ClassicBrush *cb = &be->classic.brush;
-    DP_UPixelFloat color = color_override ? *color_override : brush->color;
-    if (cb->incremental || cb->smudge.max > 0.0f) {
+    if (eraser_override) {
+        cb->erase = true;
+        cb->erase_mode = DP_BLEND_MODE_ERASE;
+        cb->erase_target = DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT;
+    }
+
+    DP_UPixelFloat color;
+    if (cb->erase && cb->erase_target == DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR) {
+        // Erasing to a color paints it on in the normal blend mode instead.
+        color = cb->erase_color;
+    }
+    else {
+        color = color_override ? *color_override : brush->color;
+    }
+
+    if (cb->incremental || cb->smudge.max > 0.0f
+        || cb->color_dynamic.type != DP_CLASSIC_BRUSH_DYNAMIC_NONE) {
         // Incremental mode must be used when smudging, because color is not
-        // picked up from sublayers
+        // picked up from sublayers. The same goes for color dynamics, since
+        // indirect strokes can't change color midway.
         color.a = 0.0f;
     }
     else {
@@ -769,11 +1062,6 @@ void DP_brush_engine_classic_brush_set(DP_BrushEngine *be,
         }
     }
     be->classic.brush_color = color;
-
-    if (eraser_override) {
-        cb->erase = true;
-        cb->erase_mode = DP_BLEND_MODE_ERASE;
-    }
 }
 
 static void disable_mypaint_dynamics(MyPaintBrush *mb, MyPaintBrushSetting s)
@@ -803,7 +1091,13 @@ void DP_brush_engine_mypaint_brush_set(DP_BrushEngine *be,
     DP_ASSERT(stroke->layer_id >= 0);
     DP_ASSERT(stroke->layer_id <= UINT16_MAX);
 
+    DP_brush_engine_dabs_flush(be);
     set_common_stroke_params(be, stroke);
+    be->pickup_mode = brush->pickup_mode;
+    DP_stamp_mask_decref_nullable(be->stamp_mask);
+    be->stamp_mask = NULL;
+    DP_stamp_mask_decref_nullable(be->grain_mask);
+    be->grain_mask = NULL;
     be->active = DP_BRUSH_ENGINE_ACTIVE_MYPAINT;
 
     MyPaintBrush *mb = be->mypaint_brush;
@@ -880,7 +1174,8 @@ static void set_pixel_dabs(int count, DP_PixelDab *out, void *user)
     }
 }
 
-static void flush_pixel_dabs(DP_BrushEngine *be, int used)
+static void flush_pixel_dabs(DP_BrushEngine *be, int32_t x, int32_t y,
+                             int used, void *buffer)
 {
     DP_Message *(*new_fn)(unsigned int, uint16_t, int32_t, int32_t, uint32_t,
                           uint8_t, void (*)(int, DP_PixelDab *, void *), int,
@@ -893,13 +1188,13 @@ static void flush_pixel_dabs(DP_BrushEngine *be, int used)
     }
     be->push_message(
         be->user,
-        new_fn(be->stroke.context_id, DP_int_to_uint16(be->layer_id),
-               be->classic.dab_x, be->classic.dab_y, be->classic.dab_color,
+        new_fn(be->stroke.context_id, DP_int_to_uint16(be->layer_id), x, y,
+               be->classic.dab_color,
                (uint8_t)DP_classic_brush_blend_mode(&be->classic.brush),
-               set_pixel_dabs, used, be->dabs.buffer));
+               set_pixel_dabs, used, buffer));
 }
 
-static void set_soft_dabs(int count, DP_ClassicDab *out, void *user)
+static void set_classic_dabs(int count, DP_ClassicDab *out, void *user)
 {
     DP_BrushEngineClassicDab *dabs = user;
     for (int i = 0; i < count; ++i) {
@@ -909,15 +1204,70 @@ static void set_soft_dabs(int count, DP_ClassicDab *out, void *user)
     }
 }
 
-static void flush_soft_dabs(DP_BrushEngine *be, int used)
+static void set_soft_dabs(int count, DP_SoftDab *out, void *user)
+{
+    DP_BrushEngineClassicDab *dabs = user;
+    for (int i = 0; i < count; ++i) {
+        DP_BrushEngineClassicDab *dab = &dabs[i];
+        DP_soft_dab_init(out, i, dab->x, dab->y, dab->size, dab->hardness,
+                         dab->opacity);
+    }
+}
+
+static uint32_t get_grain_id(DP_BrushEngine *be)
+{
+    return be->grain_mask ? DP_stamp_mask_id(be->grain_mask) : 0;
+}
+
+static void flush_soft_dabs(DP_BrushEngine *be, int32_t x, int32_t y,
+                            int used, void *buffer)
+{
+    DP_ClassicBrush *cb = &be->classic.brush;
+    uint16_t layer_id = DP_int_to_uint16(be->layer_id);
+    uint8_t blend_mode = (uint8_t)DP_classic_brush_blend_mode(cb);
+    uint32_t grain_id = get_grain_id(be);
+    DP_Message *msg;
+    // Plain classicdabs when they can express the brush, so that older clients
+    // can see it.
+    if (cb->falloff == DP_CLASSIC_BRUSH_FALLOFF_RAMP && grain_id == 0) {
+        msg = DP_msg_draw_dabs_classic_new(
+            be->stroke.context_id, layer_id, x, y, be->classic.dab_color,
+            blend_mode, set_classic_dabs, used, buffer);
+    }
+    else {
+        msg = DP_msg_draw_dabs_soft_new(
+            be->stroke.context_id, layer_id, x, y, be->classic.dab_color,
+            blend_mode, (uint8_t)cb->falloff, grain_id,
+            DP_classic_brush_dab_grain_scale(cb),
+            DP_classic_brush_dab_grain_strength(cb), set_soft_dabs, used,
+            buffer);
+    }
+    be->push_message(be->user, msg);
+}
+
+static void set_stamp_dabs(int count, DP_StampDab *out, void *user)
 {
+    DP_BrushEngineStampDab *dabs = user;
+    for (int i = 0; i < count; ++i) {
+        DP_BrushEngineStampDab *dab = &dabs[i];
+        DP_stamp_dab_init(out, i, dab->x, dab->y, dab->size, dab->angle,
+                          dab->opacity);
+    }
+}
+
+static void flush_stamp_dabs(DP_BrushEngine *be, int32_t x, int32_t y,
+                             int used, void *buffer)
+{
+    DP_ClassicBrush *cb = &be->classic.brush;
     be->push_message(
         be->user,
-        DP_msg_draw_dabs_classic_new(
-            be->stroke.context_id, DP_int_to_uint16(be->layer_id),
-            be->classic.dab_x, be->classic.dab_y, be->classic.dab_color,
-            (uint8_t)DP_classic_brush_blend_mode(&be->classic.brush),
-            set_soft_dabs, used, be->dabs.buffer));
+        DP_msg_draw_dabs_stamp_new(
+            be->stroke.context_id, DP_int_to_uint16(be->layer_id), x, y,
+            be->classic.dab_color, (uint8_t)DP_classic_brush_blend_mode(cb),
+            DP_stamp_mask_id(be->stamp_mask), get_grain_id(be),
+            DP_classic_brush_dab_grain_scale(cb),
+            DP_classic_brush_dab_grain_strength(cb), set_stamp_dabs, used,
+            buffer));
 }
 
 static void set_mypaint_dabs(int count, DP_MyPaintDab *out, void *user)
@@ -930,16 +1280,265 @@ static void set_mypaint_dabs(int count, DP_MyPaintDab *out, void *user)
     }
 }
 
-static void flush_mypaint_dabs(DP_BrushEngine *be, int used)
+static void flush_mypaint_dabs(DP_BrushEngine *be, int32_t x, int32_t y,
+                               int used, void *buffer)
+{
+    be->push_message(
+        be->user,
+        DP_msg_draw_dabs_mypaint_new(
+            be->stroke.context_id, DP_int_to_uint16(be->layer_id), x, y,
+            be->mypaint.dab_color, be->mypaint.dab_lock_alpha,
+            be->mypaint.dab_colorize, be->mypaint.dab_posterize,
+            be->mypaint.dab_mode, set_mypaint_dabs, used, buffer));
+}
+
+static void flush_dabs(DP_BrushEngine *be, int32_t x, int32_t y, int used,
+                       void *buffer)
 {
-    be->push_message(be->user,
-                     DP_msg_draw_dabs_mypaint_new(
-                         be->stroke.context_id, DP_int_to_uint16(be->layer_id),
-                         be->mypaint.dab_x, be->mypaint.dab_y,
-                         be->mypaint.dab_color, be->mypaint.dab_lock_alpha,
-                         be->mypaint.dab_colorize, be->mypaint.dab_posterize,
-                         be->mypaint.dab_mode, set_mypaint_dabs, used,
-                         be->dabs.buffer));
+    switch (be->active) {
+    case DP_BRUSH_ENGINE_ACTIVE_PIXEL:
+        flush_pixel_dabs(be, x, y, used, buffer);
+        break;
+    case DP_BRUSH_ENGINE_ACTIVE_SOFT:
+        flush_soft_dabs(be, x, y, used, buffer);
+        break;
+    case DP_BRUSH_ENGINE_ACTIVE_STAMP:
+        flush_stamp_dabs(be, x, y, used, buffer);
+        break;
+    case DP_BRUSH_ENGINE_ACTIVE_MYPAINT:
+        flush_mypaint_dabs(be, x, y, used, buffer);
+        break;
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+
+// Maps positions and angles from the original stroke onto one of its copies.
+typedef struct DP_SymmetryCopy {
+    double xx, xy, yx, yy;
+    double tx, ty;
+    // Angles in turns get multiplied by the sign and then offset.
+    double angle_sign;
+    double angle_offset;
+} DP_SymmetryCopy;
+
+typedef struct DP_SymmetryDabs {
+    DP_BrushEngine *be;
+    DP_SymmetryCopy copy;
+    // Pixel dabs are placed at whole pixels, everything else at quarter ones.
+    double unit;
+    int32_t source_x, source_y;
+    int32_t origin_x, origin_y;
+    int32_t last_x, last_y;
+    int used;
+} DP_SymmetryDabs;
+
+static int get_symmetry_copy_count(const DP_StrokeSymmetry *symmetry)
+{
+    switch (symmetry->mode) {
+    case DP_SYMMETRY_VERTICAL:
+    case DP_SYMMETRY_HORIZONTAL:
+        return 1;
+    case DP_SYMMETRY_ROTATIONAL:
+        return DP_max_int(0, symmetry->count - 1);
+    default:
+        return 0;
+    }
+}
+
+static DP_SymmetryCopy get_symmetry_copy(const DP_StrokeSymmetry *symmetry,
+                                         int index)
+{
+    double cx = symmetry->x;
+    double cy = symmetry->y;
+    switch (symmetry->mode) {
+    case DP_SYMMETRY_VERTICAL:
+        // Something pointing right now points left, so the angle goes from
+        // theta to half a turn minus theta.
+        return (DP_SymmetryCopy){-1.0, 0.0, 0.0, 1.0, cx * 2.0, 0.0, -1.0, 0.5};
+    case DP_SYMMETRY_HORIZONTAL:
+        return (DP_SymmetryCopy){1.0, 0.0, 0.0, -1.0, 0.0, cy * 2.0, -1.0, 0.0};
+    case DP_SYMMETRY_ROTATIONAL: {
+        double turns = DP_int_to_double(index)
+                     / DP_int_to_double(symmetry->count);
+        double c = cos(turns * 2.0 * M_PI);
+        double s = sin(turns * 2.0 * M_PI);
+        return (DP_SymmetryCopy){
+            c, -s, s, c, cx - c * cx + s * cy, cy - s * cx - c * cy,
+            1.0, turns};
+    }
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+static double symmetry_angle(const DP_SymmetryCopy *copy, double turns)
+{
+    double angle = copy->angle_sign * turns + copy->angle_offset;
+    return angle - floor(angle);
+}
+
+static uint8_t symmetry_stamp_angle(const DP_SymmetryCopy *copy,
+                                    uint8_t angle)
+{
+    double turns = symmetry_angle(copy, DP_uint8_to_double(angle) / 256.0);
+    return (uint8_t)(DP_double_to_int(turns * 256.0 + 0.5) & 0xff);
+}
+
+// MyPaint angles are in 255ths of a turn, so a copy's angle usually falls
+// between two of them. Elliptical dabs look the same after half a turn though,
+// so this picks whichever of the two equivalent angles rounds more closely.
+// Otherwise even copies that are exactly half a turn apart would end up tilted
+// differently from the original.
+static uint8_t symmetry_mypaint_angle(const DP_SymmetryCopy *copy,
+                                      uint8_t angle)
+{
+    double turns = symmetry_angle(copy, DP_uint8_to_double(angle) / 255.0);
+    double value = turns * 255.0;
+    double opposite = (turns < 0.5 ? turns + 0.5 : turns - 0.5) * 255.0;
+    if (fabs(opposite - floor(opposite + 0.5))
+        < fabs(value - floor(value + 0.5))) {
+        value = opposite;
+    }
+    return (uint8_t)(DP_double_to_int(value + 0.5) % 255);
+}
+
+// Moves along to the next dab of the original stroke and puts out the delta
+// to its copy. If that doesn't fit, the copied dabs so far get pushed and a
+// new message starts at the copy. The center offset is how far the middle of
+// the dab is from its position.
+static void symmetry_dabs_next(DP_SymmetryDabs *sd, int8_t dx, int8_t dy,
+                               double center_offset, int8_t *out_dx,
+                               int8_t *out_dy)
+{
+    sd->source_x += dx;
+    sd->source_y += dy;
+    double unit = sd->unit;
+    double x = DP_int32_to_double(sd->source_x) / unit + center_offset;
+    double y = DP_int32_to_double(sd->source_y) / unit + center_offset;
+    const DP_SymmetryCopy *copy = &sd->copy;
+    double copy_x = copy->xx * x + copy->xy * y + copy->tx - center_offset;
+    double copy_y = copy->yx * x + copy->yy * y + copy->ty - center_offset;
+    int32_t dab_x = DP_double_to_int32(floor(copy_x * unit + 0.5));
+    int32_t dab_y = DP_double_to_int32(floor(copy_y * unit + 0.5));
+
+    int delta_x = dab_x - sd->last_x;
+    int delta_y = dab_y - sd->last_y;
+    sd->last_x = dab_x;
+    sd->last_y = dab_y;
+    if (sd->used != 0 && abs(delta_x) <= MAX_XY_DELTA
+        && abs(delta_y) <= MAX_XY_DELTA) {
+        *out_dx = DP_int_to_int8(delta_x);
+        *out_dy = DP_int_to_int8(delta_y);
+    }
+    else {
+        if (sd->used != 0) {
+            flush_dabs(sd->be, sd->origin_x, sd->origin_y, sd->used,
+                       sd->be->symmetry.buffer);
+            sd->used = 0;
+        }
+        sd->origin_x = dab_x;
+        sd->origin_y = dab_y;
+        *out_dx = 0;
+        *out_dy = 0;
+    }
+}
+
+static void copy_symmetry_dabs(DP_SymmetryDabs *sd, int used)
+{
+    DP_BrushEngine *be = sd->be;
+    switch (be->active) {
+    case DP_BRUSH_ENGINE_ACTIVE_PIXEL: {
+        DP_BrushEnginePixelDab *src = be->dabs.buffer;
+        DP_BrushEnginePixelDab *dst = be->symmetry.buffer;
+        for (int i = 0; i < used; ++i) {
+            DP_BrushEnginePixelDab dab = src[i];
+            // Pixel dabs of odd sizes are centered on the middle of a pixel.
+            symmetry_dabs_next(sd, dab.x, dab.y, dab.size % 2 == 0 ? 0.0 : 0.5,
+                               &dab.x, &dab.y);
+            dst[sd->used++] = dab;
+        }
+        break;
+    }
+    case DP_BRUSH_ENGINE_ACTIVE_SOFT: {
+        DP_BrushEngineClassicDab *src = be->dabs.buffer;
+        DP_BrushEngineClassicDab *dst = be->symmetry.buffer;
+        for (int i = 0; i < used; ++i) {
+            DP_BrushEngineClassicDab dab = src[i];
+            symmetry_dabs_next(sd, dab.x, dab.y, 0.0, &dab.x, &dab.y);
+            dst[sd->used++] = dab;
+        }
+        break;
+    }
+    case DP_BRUSH_ENGINE_ACTIVE_STAMP: {
+        DP_BrushEngineStampDab *src = be->dabs.buffer;
+        DP_BrushEngineStampDab *dst = be->symmetry.buffer;
+        for (int i = 0; i < used; ++i) {
+            DP_BrushEngineStampDab dab = src[i];
+            symmetry_dabs_next(sd, dab.x, dab.y, 0.0, &dab.x, &dab.y);
+            dab.angle = symmetry_stamp_angle(&sd->copy, dab.angle);
+            dst[sd->used++] = dab;
+        }
+        break;
+    }
+    case DP_BRUSH_ENGINE_ACTIVE_MYPAINT: {
+        DP_BrushEngineMyPaintDab *src = be->dabs.buffer;
+        DP_BrushEngineMyPaintDab *dst = be->symmetry.buffer;
+        for (int i = 0; i < used; ++i) {
+            DP_BrushEngineMyPaintDab dab = src[i];
+            symmetry_dabs_next(sd, dab.x, dab.y, 0.0, &dab.x, &dab.y);
+            dab.angle = symmetry_mypaint_angle(&sd->copy, dab.angle);
+            dst[sd->used++] = dab;
+        }
+        break;
+    }
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+static size_t get_dab_element_size(DP_BrushEngine *be)
+{
+    switch (be->active) {
+    case DP_BRUSH_ENGINE_ACTIVE_PIXEL:
+        return sizeof(DP_BrushEnginePixelDab);
+    case DP_BRUSH_ENGINE_ACTIVE_SOFT:
+        return sizeof(DP_BrushEngineClassicDab);
+    case DP_BRUSH_ENGINE_ACTIVE_STAMP:
+        return sizeof(DP_BrushEngineStampDab);
+    case DP_BRUSH_ENGINE_ACTIVE_MYPAINT:
+        return sizeof(DP_BrushEngineMyPaintDab);
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+// Copies never have more dabs than the original, but they can be spread out
+// farther, in which case they get split across multiple messages.
+static void flush_symmetry_dabs(DP_BrushEngine *be, int32_t x, int32_t y,
+                                int used)
+{
+    const DP_StrokeSymmetry *symmetry = &be->symmetry.params;
+    int copy_count = get_symmetry_copy_count(symmetry);
+    if (copy_count != 0) {
+        size_t size = get_dab_element_size(be) * DP_int_to_size(used);
+        if (be->symmetry.capacity < size) {
+            be->symmetry.buffer = DP_realloc(be->symmetry.buffer, size);
+            be->symmetry.capacity = size;
+        }
+
+        double unit = be->active == DP_BRUSH_ENGINE_ACTIVE_PIXEL ? 1.0 : 4.0;
+        for (int i = 1; i <= copy_count; ++i) {
+            DP_SymmetryDabs sd = {
+                be, get_symmetry_copy(symmetry, i), unit, x, y, 0, 0, 0, 0, 0};
+            copy_symmetry_dabs(&sd, used);
+            if (sd.used != 0) {
+                flush_dabs(be, sd.origin_x, sd.origin_y, sd.used,
+                           be->symmetry.buffer);
+            }
+        }
+    }
 }
 
 void DP_brush_engine_dabs_flush(DP_BrushEngine *be)
@@ -947,23 +1546,29 @@ void DP_brush_engine_dabs_flush(DP_BrushEngine *be)
     DP_ASSERT(be);
     int used = be->dabs.used;
     if (used != 0) {
-        switch (be->active) {
-        case DP_BRUSH_ENGINE_ACTIVE_PIXEL:
-            flush_pixel_dabs(be, used);
-            break;
-        case DP_BRUSH_ENGINE_ACTIVE_SOFT:
-            flush_soft_dabs(be, used);
-            break;
-        case DP_BRUSH_ENGINE_ACTIVE_MYPAINT:
-            flush_mypaint_dabs(be, used);
-            break;
-        default:
-            DP_UNREACHABLE();
+        int32_t x, y;
+        if (be->active == DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+            x = be->mypaint.dab_x;
+            y = be->mypaint.dab_y;
+        }
+        else {
+            x = be->classic.dab_x;
+            y = be->classic.dab_y;
         }
+        flush_dabs(be, x, y, used, be->dabs.buffer);
+        flush_symmetry_dabs(be, x, y, used);
     }
     be->dabs.used = 0;
 }
 
+static void dabs_flush_if_due(DP_BrushEngine *be, long long time_msec)
+{
+    if (be->dabs.used != 0
+        && time_msec - be->dabs.first_msec >= DABS_FLUSH_DELAY_MSEC) {
+        DP_brush_engine_dabs_flush(be);
+    }
+}
+
 
 void DP_brush_engine_stroke_begin(DP_BrushEngine *be, unsigned int context_id,
                                   bool push_undo_point, float zoom)
@@ -979,6 +1584,16 @@ void DP_brush_engine_stroke_begin(DP_BrushEngine *be, unsigned int context_id,
     if (push_undo_point) {
         be->push_message(be->user, DP_msg_undo_point_new(context_id));
     }
+    if (be->active == DP_BRUSH_ENGINE_ACTIVE_STAMP) {
+        // Send the mask along with every stroke, so that anyone who joined
+        // after it was first used still gets to see it.
+        be->push_message(be->user,
+                         DP_stamp_mask_to_message(be->stamp_mask, context_id));
+    }
+    if (be->grain_mask) {
+        be->push_message(be->user,
+                         DP_stamp_mask_to_message(be->grain_mask, context_id));
+    }
     be->spline.fill = 0;
     be->spline.offset = 0;
     be->smoother.fill = 0;
@@ -1006,9 +1621,8 @@ static DP_UPixelFloat sample_classic_smudge(DP_BrushEngine *be,
 {
     int diameter =
         get_classic_smudge_diameter(cb, pressure, velocity, distance);
-    return DP_layer_content_sample_color_at(
-        lc, be->stamp_buffer, DP_float_to_int(x), DP_float_to_int(y), diameter,
-        true, &be->last_diameter);
+    return sample_color_at(be, lc, DP_float_to_int(x), DP_float_to_int(y),
+                           diameter, true);
 }
 
 static void update_classic_smudge(DP_BrushEngine *be, DP_ClassicBrush *cb,
@@ -1021,6 +1635,8 @@ static void update_classic_smudge(DP_BrushEngine *be, DP_ClassicBrush *cb,
     if (smudge > 0.0f && smudge_distance > cb->resmudge && lc) {
         DP_UPixelFloat sample = sample_classic_smudge(
             be, cb, lc, x, y, pressure, velocity, distance);
+        // Weighted by the sample's opacity, so that dragging the brush over
+        // transparent areas doesn't wash out the held color.
         if (sample.a > 0.0f) {
             float a = sample.a * smudge;
             DP_UPixelFloat *sp = &be->classic.smudge_color;
@@ -1061,6 +1677,8 @@ static void first_dab_pixel(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
                             float y, float pressure)
 {
     be->classic.pixel_length = 0;
+    be->classic.pixel_perfect.have_last = false;
+    be->classic.pixel_perfect.have_pending = false;
     add_dab_pixel(be, cb, DP_float_to_int(x), DP_float_to_int(y), pressure,
                   0.0f, 0.0f);
 }
@@ -1148,9 +1766,46 @@ static void first_dab_soft(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
     add_dab_soft(be, cb, x, y, pressure, 0.0f, 0.0f);
 }
 
-static void stroke_soft(DP_BrushEngine *be, DP_ClassicBrush *cb,
-                        DP_LayerContent *lc, float x, float y, float pressure,
-                        float delta_sec)
+// Averages the direction of the stroke with the given segment and turns it into
+// the angle of the dabs, weighting the segment by how long it is.
+static void update_classic_direction(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                                     float dx, float dy, float dist)
+{
+    if (cb->stamp_angle_mode == DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION) {
+        float direction_x = be->classic.direction_x;
+        float direction_y = be->classic.direction_y;
+        if (direction_x == 0.0f && direction_y == 0.0f) {
+            direction_x = dx;
+            direction_y = dy;
+        }
+        else {
+            float t = DP_min_float(dist / CLASSIC_DIRECTION_DISTANCE, 1.0f);
+            direction_x += (dx - direction_x) * t;
+            direction_y += (dy - direction_y) * t;
+        }
+        be->classic.direction_x = direction_x;
+        be->classic.direction_y = direction_y;
+        if (hypotf(direction_x, direction_y) >= 0.001f) {
+            be->classic.angle =
+                atan2f(direction_y, direction_x) / (2.0f * (float)M_PI);
+        }
+    }
+}
+
+static void update_classic_tilt(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                                float xtilt, float ytilt)
+{
+    if (cb->stamp_angle_mode == DP_CLASSIC_BRUSH_ANGLE_MODE_TILT
+        && hypotf(xtilt, ytilt) >= CLASSIC_TILT_MIN) {
+        be->classic.angle = atan2f(ytilt, xtilt) / (2.0f * (float)M_PI);
+    }
+}
+
+static void stroke_spaced(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                          DP_LayerContent *lc, float x, float y,
+                          float pressure, float delta_sec,
+                          void (*add_dab)(DP_BrushEngine *, DP_ClassicBrush *,
+                                          float, float, float, float, float))
 {
     float last_x = be->classic.last_x;
     float last_y = be->classic.last_y;
@@ -1160,6 +1815,7 @@ static void stroke_soft(DP_BrushEngine *be, DP_ClassicBrush *cb,
     if (dist >= 0.001f) {
         float dx = diff_x / dist;
         float dy = diff_y / dist;
+        update_classic_direction(be, cb, dx, dy, dist);
         float last_pressure = be->classic.last_pressure;
         float dp = (pressure - last_pressure) / dist;
 
@@ -1188,7 +1844,7 @@ static void stroke_soft(DP_BrushEngine *be, DP_ClassicBrush *cb,
 
             update_classic_smudge(be, cb, lc, dab_x, dab_y, dab_p, dab_v,
                                   dab_d);
-            add_dab_soft(be, cb, dab_x, dab_y, dab_p, dab_v, dab_d);
+            add_dab(be, cb, dab_x, dab_y, dab_p, dab_v, dab_d);
 
             dab_x += dx * spacing;
             dab_y += dy * spacing;
@@ -1201,23 +1857,103 @@ static void stroke_soft(DP_BrushEngine *be, DP_ClassicBrush *cb,
     }
 }
 
+static void stroke_soft(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                        DP_LayerContent *lc, float x, float y, float pressure,
+                        float delta_sec)
+{
+    stroke_spaced(be, cb, lc, x, y, pressure, delta_sec, add_dab_soft);
+}
+
+static void first_dab_stamp(DP_BrushEngine *be, DP_ClassicBrush *cb, float x,
+                            float y, float pressure)
+{
+    be->classic.soft_length = 0.0f;
+    add_dab_stamp(be, cb, x, y, pressure, 0.0f, 0.0f);
+}
+
+static void stroke_stamp(DP_BrushEngine *be, DP_ClassicBrush *cb,
+                         DP_LayerContent *lc, float x, float y, float pressure,
+                         float delta_sec)
+{
+    stroke_spaced(be, cb, lc, x, y, pressure, delta_sec, add_dab_stamp);
+}
+
+static long long get_airbrush_interval_msec(const DP_ClassicBrush *cb)
+{
+    float rate = cb->airbrush_rate;
+    if (rate > 0.0f) {
+        float value = 1000.0f / DP_min_float(rate, AIRBRUSH_RATE_MAX) + 0.5f;
+        return DP_float_to_llong(value);
+    }
+    else {
+        return 0;
+    }
+}
+
+// Deposits dabs at the current brush position for every airbrush interval up
+// to the given time, regardless of whether the brush has moved in between.
+static void add_airbrush_dabs(DP_BrushEngine *be, long long time_msec)
+{
+    DP_ClassicBrush *cb = &be->classic.brush;
+    long long interval_msec = get_airbrush_interval_msec(cb);
+    if (interval_msec > 0) {
+        float x = be->classic.last_x;
+        float y = be->classic.last_y;
+        float pressure = be->classic.last_pressure;
+        float velocity = classic_velocity(be->classic.last_velocity);
+        float distance = be->classic.last_distance;
+        be->classic.flow = CLAMP(cb->airbrush_flow, 0.0f, 1.0f);
+        while (be->classic.airbrush_next_msec <= time_msec) {
+            switch (be->active) {
+            case DP_BRUSH_ENGINE_ACTIVE_PIXEL:
+                add_dab_pixel(be, cb, DP_float_to_int(x), DP_float_to_int(y),
+                              pressure, velocity, distance);
+                break;
+            case DP_BRUSH_ENGINE_ACTIVE_SOFT:
+                add_dab_soft(be, cb, x, y, pressure, velocity, distance);
+                break;
+            case DP_BRUSH_ENGINE_ACTIVE_STAMP:
+                add_dab_stamp(be, cb, x, y, pressure, velocity, distance);
+                break;
+            default:
+                DP_UNREACHABLE();
+            }
+            be->classic.airbrush_next_msec += interval_msec;
+        }
+        be->classic.flow = 1.0f;
+    }
+}
+
 static void stroke_to_classic(
-    DP_BrushEngine *be, float x, float y, float pressure, long long time_msec,
+    DP_BrushEngine *be, DP_BrushPoint bp,
     void (*first_dab)(DP_BrushEngine *, DP_ClassicBrush *, float, float, float),
     void (*stroke)(DP_BrushEngine *, DP_ClassicBrush *, DP_LayerContent *,
                    float, float, float, float))
 {
     DP_ClassicBrush *cb = &be->classic.brush;
     DP_LayerContent *lc = be->lc;
+    float x = bp.x;
+    float y = bp.y;
+    float pressure = bp.pressure;
     if (be->stroke.in_progress) {
+        add_airbrush_dabs(be, bp.time_msec);
         float delta_sec = DP_max_float(
-            DP_llong_to_float(time_msec - be->stroke.last_time_msec) / 1000.0f,
+            DP_llong_to_float(bp.time_msec - be->stroke.last_time_msec)
+                / 1000.0f,
             0.0001f);
+        update_classic_tilt(be, cb, bp.xtilt, bp.ytilt);
         stroke(be, cb, lc, x, y, pressure, delta_sec);
     }
     else {
         be->classic.last_velocity = 0.0f;
         be->classic.last_distance = 0.0f;
+        be->classic.direction_x = 0.0f;
+        be->classic.direction_y = 0.0f;
+        be->classic.angle = 0.0f;
+        be->classic.flow = 1.0f;
+        be->classic.airbrush_next_msec =
+            bp.time_msec + get_airbrush_interval_msec(cb);
+        update_classic_tilt(be, cb, bp.xtilt, bp.ytilt);
         be->stroke.in_progress = true;
         bool colorpick = cb->colorpick
                       && DP_classic_brush_blend_mode(cb) != DP_BLEND_MODE_ERASE
@@ -1297,6 +2033,7 @@ static void stroke_to(DP_BrushEngine *be, DP_BrushPoint bp,
         be->cs = cs_or_null;
         DP_layer_content_decref_nullable(be->lc);
         be->lc = cs_or_null ? search_layer(cs_or_null, be->layer_id) : NULL;
+        dispose_merged(be);
         DP_PERF_END(search_layer);
     }
 
@@ -1305,15 +2042,19 @@ static void stroke_to(DP_BrushEngine *be, DP_BrushPoint bp,
         DP_EVENT_LOG(
             "stroke_to active=pixel x=%f y=%f pressure=%f time_msec=%lld", bp.x,
             bp.y, bp.pressure, bp.time_msec);
-        stroke_to_classic(be, bp.x, bp.y, bp.pressure, bp.time_msec,
-                          first_dab_pixel, stroke_pixel);
+        stroke_to_classic(be, bp, first_dab_pixel, stroke_pixel);
         break;
     case DP_BRUSH_ENGINE_ACTIVE_SOFT:
         DP_EVENT_LOG(
             "stroke_to active=soft x=%f y=%f pressure=%f time_msec=%lld", bp.x,
             bp.y, bp.pressure, bp.time_msec);
-        stroke_to_classic(be, bp.x, bp.y, bp.pressure, bp.time_msec,
-                          first_dab_soft, stroke_soft);
+        stroke_to_classic(be, bp, first_dab_soft, stroke_soft);
+        break;
+    case DP_BRUSH_ENGINE_ACTIVE_STAMP:
+        DP_EVENT_LOG(
+            "stroke_to active=stamp x=%f y=%f pressure=%f time_msec=%lld", bp.x,
+            bp.y, bp.pressure, bp.time_msec);
+        stroke_to_classic(be, bp, first_dab_stamp, stroke_stamp);
         break;
     case DP_BRUSH_ENGINE_ACTIVE_MYPAINT:
         DP_EVENT_LOG("stroke_to active=mypaint x=%f y=%f pressure=%f xtilt=%f "
@@ -1331,6 +2072,54 @@ static void stroke_to(DP_BrushEngine *be, DP_BrushPoint bp,
 }
 
 
+// The brush hangs off the input by a string, which only drags it along once it
+// pulls taut. The first point goes straight through so that there's no delay
+// until the stroke shows up.
+static void handle_stroke_pull_string(DP_BrushEngine *be, DP_BrushPoint bp,
+                                      DP_CanvasState *cs_or_null)
+{
+    float distance = be->pull_string.distance;
+    if (distance <= 0.0f) {
+        stroke_to(be, bp, cs_or_null);
+    }
+    else if (be->pull_string.active) {
+        be->pull_string.target = bp;
+        DP_BrushPoint *brush = &be->pull_string.brush;
+        float dx = bp.x - brush->x;
+        float dy = bp.y - brush->y;
+        float length = hypotf(dx, dy);
+        if (length > distance) {
+            float t = (length - distance) / length;
+            brush->x += dx * t;
+            brush->y += dy * t;
+            brush->pressure += (bp.pressure - brush->pressure) * t;
+            brush->xtilt += (bp.xtilt - brush->xtilt) * t;
+            brush->ytilt += (bp.ytilt - brush->ytilt) * t;
+            brush->rotation = bp.rotation;
+            brush->time_msec = bp.time_msec;
+            stroke_to(be, *brush, cs_or_null);
+        }
+    }
+    else {
+        be->pull_string.active = true;
+        be->pull_string.brush = bp;
+        be->pull_string.target = bp;
+        stroke_to(be, bp, cs_or_null);
+    }
+}
+
+static void pull_string_finish(DP_BrushEngine *be, DP_CanvasState *cs_or_null)
+{
+    if (be->pull_string.active) {
+        DP_BrushPoint brush = be->pull_string.brush;
+        DP_BrushPoint target = be->pull_string.target;
+        if (brush.x != target.x || brush.y != target.y) {
+            stroke_to(be, target, cs_or_null);
+        }
+        be->pull_string.active = false;
+    }
+}
+
 static void handle_stroke_stabilizer(DP_BrushEngine *be, DP_BrushPoint bp,
                                      DP_CanvasState *cs_or_null)
 {
@@ -1338,7 +2127,7 @@ static void handle_stroke_stabilizer(DP_BrushEngine *be, DP_BrushPoint bp,
         stabilizer_stroke_to(be, bp);
     }
     else {
-        stroke_to(be, bp, cs_or_null);
+        handle_stroke_pull_string(be, bp, cs_or_null);
     }
 }
 
@@ -1576,18 +2365,21 @@ void DP_brush_engine_stroke_to(DP_BrushEngine *be, DP_BrushPoint bp,
                                DP_CanvasState *cs_or_null)
 {
     DP_ASSERT(be);
+    be->dabs.now_msec = bp.time_msec;
     if (be->spline.enabled) {
         handle_stroke_spline(be, bp, cs_or_null);
     }
     else {
         handle_stroke_smoother(be, bp, cs_or_null);
     }
+    dabs_flush_if_due(be, bp.time_msec);
 }
 
 void DP_brush_engine_poll(DP_BrushEngine *be, long long time_msec,
                           DP_CanvasState *cs_or_null)
 {
     DP_ASSERT(be);
+    be->dabs.now_msec = time_msec;
 
     if (be->spline.fill == 3) {
         DP_SplinePoint *sp = spline_at(be, 2);
@@ -1605,12 +2397,622 @@ void DP_brush_engine_poll(DP_BrushEngine *be, long long time_msec,
 
         for (long long i = 0; i < elapsed; ++i) {
             DP_BrushPoint bp = stabilizer_points_get(be, i, alpha);
-            stroke_to(be, stabilizer_stabilize(be, bp), cs_or_null);
+            handle_stroke_pull_string(be, stabilizer_stabilize(be, bp),
+                                      cs_or_null);
             stabilizer_queue_replace(be, bp);
         }
 
         stabilizer_points_clear(be, time_msec);
     }
+
+    if (be->stroke.in_progress
+        && be->active != DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+        add_airbrush_dabs(be, time_msec);
+    }
+
+    dabs_flush_if_due(be, time_msec);
+}
+
+// Shapes get cut into segments no longer than this, since MyPaint brushes
+// misbehave with delta times that are very short or very long. Every pixel
+// along the way advances the time by the given number of milliseconds.
+#define SHAPE_SEGMENT_LENGTH 10.0f
+#define SHAPE_MSEC_PER_PIXEL 10.0f
+// Ellipse outlines are drawn as polygons that stray at most this many pixels
+// from the actual curve.
+#define SHAPE_ELLIPSE_TOLERANCE  0.1
+#define SHAPE_ELLIPSE_POINTS_MIN 8
+#define SHAPE_ELLIPSE_POINTS_MAX 4096
+
+typedef struct DP_ShapeStroke {
+    DP_BrushEngine *be;
+    DP_CanvasState *cs_or_null;
+    float x, y;
+    float pressure;
+    long long time_msec;
+} DP_ShapeStroke;
+
+static void shape_stroke_point_pressure(DP_ShapeStroke *ss, float x, float y,
+                                        float pressure)
+{
+    ss->x = x;
+    ss->y = y;
+    ss->pressure = pressure;
+    stroke_to(ss->be,
+              (DP_BrushPoint){x, y, pressure, 0.0f, 0.0f, 0.0f, ss->time_msec},
+              ss->cs_or_null);
+}
+
+static void shape_stroke_point(DP_ShapeStroke *ss, float x, float y)
+{
+    shape_stroke_point_pressure(ss, x, y, 1.0f);
+}
+
+static void shape_stroke_line_to_pressure(DP_ShapeStroke *ss, float x, float y,
+                                          float pressure)
+{
+    float x0 = ss->x;
+    float y0 = ss->y;
+    float p0 = ss->pressure;
+    float length = hypotf(x - x0, y - y0);
+    if (length > 0.0f) {
+        float countf = ceilf(length / SHAPE_SEGMENT_LENGTH);
+        int count = DP_float_to_int(countf);
+        long long delta_msec = DP_float_to_llong(
+            length / countf * SHAPE_MSEC_PER_PIXEL + 0.5f);
+        for (int i = 1; i <= count; ++i) {
+            float t = DP_int_to_float(i) / countf;
+            ss->time_msec += delta_msec < 1 ? 1 : delta_msec;
+            if (i == count) {
+                shape_stroke_point_pressure(ss, x, y, pressure);
+            }
+            else {
+                shape_stroke_point_pressure(ss, lerpf(x0, x, t),
+                                            lerpf(y0, y, t),
+                                            lerpf(p0, pressure, t));
+            }
+        }
+    }
+}
+
+static void shape_stroke_line_to(DP_ShapeStroke *ss, float x, float y)
+{
+    shape_stroke_line_to_pressure(ss, x, y, 1.0f);
+}
+
+// Shapes and curves are drawn all at once, so there's no time for an airbrush
+// to linger anywhere, not even at the end of the stroke.
+static float shape_airbrush_suspend(DP_BrushEngine *be)
+{
+    float airbrush_rate = 0.0f;
+    if (be->active != DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+        airbrush_rate = be->classic.brush.airbrush_rate;
+        be->classic.brush.airbrush_rate = 0.0f;
+    }
+    return airbrush_rate;
+}
+
+static void shape_airbrush_resume(DP_BrushEngine *be, float airbrush_rate)
+{
+    if (be->active != DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+        be->classic.brush.airbrush_rate = airbrush_rate;
+        be->classic.airbrush_next_msec = LLONG_MAX;
+    }
+}
+
+static int shape_ellipse_point_count(float radius)
+{
+    double r = DP_float_to_double(radius);
+    if (r > SHAPE_ELLIPSE_TOLERANCE) {
+        double step = 2.0 * acos(1.0 - SHAPE_ELLIPSE_TOLERANCE / r);
+        double count = ceil(2.0 * M_PI / step);
+        return DP_double_to_int(CLAMP(count, SHAPE_ELLIPSE_POINTS_MIN,
+                                      SHAPE_ELLIPSE_POINTS_MAX));
+    }
+    else {
+        return SHAPE_ELLIPSE_POINTS_MIN;
+    }
+}
+
+static void stroke_shape_outline(DP_ShapeStroke *ss, DP_StrokeShape shape,
+                                 float x1, float y1, float x2, float y2)
+{
+    switch (shape) {
+    case DP_STROKE_SHAPE_LINE:
+        shape_stroke_point(ss, x1, y1);
+        shape_stroke_line_to(ss, x2, y2);
+        break;
+    case DP_STROKE_SHAPE_RECTANGLE:
+        shape_stroke_point(ss, x1, y1);
+        shape_stroke_line_to(ss, x1, y2);
+        shape_stroke_line_to(ss, x2, y2);
+        shape_stroke_line_to(ss, x2, y1);
+        shape_stroke_line_to(ss, x1, y1);
+        break;
+    case DP_STROKE_SHAPE_ELLIPSE: {
+        float cx = (x1 + x2) / 2.0f;
+        float cy = (y1 + y2) / 2.0f;
+        float a = fabsf(x2 - x1) / 2.0f;
+        float b = fabsf(y2 - y1) / 2.0f;
+        int count = shape_ellipse_point_count(DP_max_float(a, b));
+        shape_stroke_point(ss, cx + a, cy);
+        for (int i = 1; i < count; ++i) {
+            double angle = 2.0 * M_PI * DP_int_to_double(i)
+                         / DP_int_to_double(count);
+            shape_stroke_line_to(ss, cx + a * DP_double_to_float(cos(angle)),
+                                 cy + b * DP_double_to_float(sin(angle)));
+        }
+        shape_stroke_line_to(ss, cx + a, cy);
+        break;
+    }
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+// Shapes that get rasterized instead of stroked snap to the pixel grid, with
+// the box spanned by the two points taking up the pixels from left to right and
+// top to bottom, inclusive.
+typedef struct DP_ShapeBox {
+    int left, top, right, bottom;
+} DP_ShapeBox;
+
+static int shape_pixel_coordinate(float value)
+{
+    return DP_float_to_int(floorf(value));
+}
+
+static DP_ShapeBox shape_box_make(float x1, float y1, float x2, float y2)
+{
+    int px1 = shape_pixel_coordinate(x1);
+    int py1 = shape_pixel_coordinate(y1);
+    int px2 = shape_pixel_coordinate(x2);
+    int py2 = shape_pixel_coordinate(y2);
+    return (DP_ShapeBox){DP_min_int(px1, px2), DP_min_int(py1, py2),
+                         DP_max_int(px1, px2), DP_max_int(py1, py2)};
+}
+
+// Range of pixels in the given row whose centers lie inside of the ellipse that
+// fills the box, if there are any.
+static bool shape_ellipse_span(const DP_ShapeBox *box, int y, int *out_left,
+                               int *out_right)
+{
+    if (y < box->top || y > box->bottom) {
+        return false;
+    }
+    double a = DP_int_to_double(box->right - box->left + 1) / 2.0;
+    double b = DP_int_to_double(box->bottom - box->top + 1) / 2.0;
+    double cx = DP_int_to_double(box->left) + a;
+    double cy = DP_int_to_double(box->top) + b;
+    double dy = (DP_int_to_double(y) + 0.5 - cy) / b;
+    double d = 1.0 - dy * dy;
+    if (d < 0.0) {
+        return false;
+    }
+    double half = a * sqrt(d);
+    int left = DP_double_to_int(ceil(cx - half - 0.5));
+    int right = DP_double_to_int(floor(cx + half - 0.5));
+    if (left > right) {
+        return false;
+    }
+    *out_left = left;
+    *out_right = right;
+    return true;
+}
+
+static void push_shape_pixel(DP_BrushEngine *be, int x, int y,
+                             uint8_t opacity, uint32_t color)
+{
+    push_dab_pixel(be, x, y, 1, opacity, color);
+}
+
+static void rasterize_shape_line(DP_BrushEngine *be, int x0, int y0, int x1,
+                                 int y1, uint8_t opacity, uint32_t color)
+{
+    int dx = x1 > x0 ? x1 - x0 : x0 - x1;
+    int dy = y1 > y0 ? y0 - y1 : y1 - y0;
+    int step_x = x0 < x1 ? 1 : -1;
+    int step_y = y0 < y1 ? 1 : -1;
+    int error = dx + dy;
+    while (true) {
+        push_shape_pixel(be, x0, y0, opacity, color);
+        if (x0 == x1 && y0 == y1) {
+            break;
+        }
+        int error2 = error * 2;
+        if (error2 >= dy) {
+            error += dy;
+            x0 += step_x;
+        }
+        if (error2 <= dx) {
+            error += dx;
+            y0 += step_y;
+        }
+    }
+}
+
+static void rasterize_shape_rectangle(DP_BrushEngine *be,
+                                      const DP_ShapeBox *box, uint8_t opacity,
+                                      uint32_t color)
+{
+    for (int x = box->left; x <= box->right; ++x) {
+        push_shape_pixel(be, x, box->top, opacity, color);
+    }
+    if (box->bottom != box->top) {
+        for (int y = box->top + 1; y <= box->bottom; ++y) {
+            push_shape_pixel(be, box->right, y, opacity, color);
+        }
+        for (int x = box->right - 1; x >= box->left; --x) {
+            push_shape_pixel(be, x, box->bottom, opacity, color);
+        }
+        if (box->right != box->left) {
+            for (int y = box->bottom - 1; y > box->top; --y) {
+                push_shape_pixel(be, box->left, y, opacity, color);
+            }
+        }
+    }
+}
+
+// Pixels of the row that aren't surrounded by the ellipse above and below. If
+// there are none, the range from left to right comes out empty.
+static void shape_ellipse_inner_span(const DP_ShapeBox *box, int y, int left,
+                                     int right, int *out_inner_left,
+                                     int *out_inner_right)
+{
+    int above_left, above_right, below_left, below_right;
+    if (shape_ellipse_span(box, y - 1, &above_left, &above_right)
+        && shape_ellipse_span(box, y + 1, &below_left, &below_right)) {
+        *out_inner_left =
+            DP_max_int(left + 1, DP_max_int(above_left, below_left));
+        *out_inner_right =
+            DP_min_int(right - 1, DP_min_int(above_right, below_right));
+    }
+    else {
+        *out_inner_left = right + 1;
+        *out_inner_right = right;
+    }
+}
+
+// Goes down the left side of the ellipse and back up the right side, so that
+// consecutive pixels stay close and the dabs pack together well.
+static void rasterize_shape_ellipse(DP_BrushEngine *be, const DP_ShapeBox *box,
+                                    uint8_t opacity, uint32_t color)
+{
+    for (int y = box->top; y <= box->bottom; ++y) {
+        int left, right, inner_left, inner_right;
+        if (shape_ellipse_span(box, y, &left, &right)) {
+            shape_ellipse_inner_span(box, y, left, right, &inner_left,
+                                     &inner_right);
+            int end = inner_left <= inner_right ? inner_left - 1 : right;
+            for (int x = left; x <= end; ++x) {
+                push_shape_pixel(be, x, y, opacity, color);
+            }
+        }
+    }
+    for (int y = box->bottom; y >= box->top; --y) {
+        int left, right, inner_left, inner_right;
+        if (shape_ellipse_span(box, y, &left, &right)) {
+            shape_ellipse_inner_span(box, y, left, right, &inner_left,
+                                     &inner_right);
+            if (inner_left <= inner_right) {
+                for (int x = inner_right + 1; x <= right; ++x) {
+                    push_shape_pixel(be, x, y, opacity, color);
+                }
+            }
+        }
+    }
+}
+
+static bool shape_rasterizes_pixels(DP_BrushEngine *be)
+{
+    return be->active == DP_BRUSH_ENGINE_ACTIVE_PIXEL
+        && DP_classic_brush_pixel_dab_size_at(&be->classic.brush, 1.0f, 0.0f,
+                                              0.0f)
+               == 1;
+}
+
+static void rasterize_shape_pixels(DP_BrushEngine *be, DP_StrokeShape shape,
+                                   float x1, float y1, float x2, float y2)
+{
+    DP_ClassicBrush *cb = &be->classic.brush;
+    be->classic.flow = 1.0f;
+    be->classic.smudge_color = be->classic.brush_color;
+    uint8_t opacity = get_classic_dab_opacity(be, cb, 1.0f, 0.0f, 0.0f);
+    if (opacity == 0) {
+        return;
+    }
+
+    uint32_t color = get_classic_dab_color(be, cb, 1.0f, 0.0f, 0.0f);
+    DP_ShapeBox box = shape_box_make(x1, y1, x2, y2);
+    switch (shape) {
+    case DP_STROKE_SHAPE_LINE:
+        rasterize_shape_line(be, shape_pixel_coordinate(x1),
+                             shape_pixel_coordinate(y1),
+                             shape_pixel_coordinate(x2),
+                             shape_pixel_coordinate(y2), opacity, color);
+        break;
+    case DP_STROKE_SHAPE_RECTANGLE:
+        rasterize_shape_rectangle(be, &box, opacity, color);
+        break;
+    case DP_STROKE_SHAPE_ELLIPSE:
+        rasterize_shape_ellipse(be, &box, opacity, color);
+        break;
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+static uint32_t get_shape_fill_color(DP_BrushEngine *be)
+{
+    DP_ClassicBrush *cb = &be->classic.brush;
+    DP_UPixelFloat brush_color = be->classic.brush_color;
+    DP_UPixelFloat color =
+        DP_classic_brush_color_at(cb, brush_color, 1.0f, 0.0f, 0.0f);
+    // Indirect strokes carry their opacity in the brush color instead.
+    float opacity = DP_classic_brush_opacity_at(cb, 1.0f, 0.0f, 0.0f)
+                  * (brush_color.a > 0.0f ? brush_color.a : 1.0f);
+    return combine_rgba(color.r, color.g, color.b, CLAMP(opacity, 0.0f, 1.0f));
+}
+
+// Right and bottom are exclusive here, like in the fill rect message. The
+// rectangle gets clipped to the canvas, since filling nothing is an error.
+static void push_shape_fill_rect(DP_BrushEngine *be, DP_CanvasState *cs_or_null,
+                                 int blend_mode, uint32_t color, int left,
+                                 int top, int right, int bottom)
+{
+    int l = DP_max_int(left, 0);
+    int t = DP_max_int(top, 0);
+    int r = cs_or_null ? DP_min_int(right, DP_canvas_state_width(cs_or_null))
+                       : right;
+    int b = cs_or_null ? DP_min_int(bottom, DP_canvas_state_height(cs_or_null))
+                       : bottom;
+    if (l < r && t < b) {
+        be->push_message(
+            be->user,
+            DP_msg_fill_rect_new(
+                be->stroke.context_id, DP_int_to_uint16(be->layer_id),
+                DP_int_to_uint8(blend_mode), DP_int_to_uint32(l),
+                DP_int_to_uint32(t), DP_int_to_uint32(r - l),
+                DP_int_to_uint32(b - t), color));
+    }
+}
+
+static void fill_shape(DP_BrushEngine *be, DP_CanvasState *cs_or_null,
+                       DP_StrokeShape shape, float x1, float y1, float x2,
+                       float y2)
+{
+    // Keep the messages in order if anything was drawn before.
+    DP_brush_engine_dabs_flush(be);
+
+    int blend_mode = (int)DP_classic_brush_blend_mode(&be->classic.brush);
+    uint32_t color = get_shape_fill_color(be);
+    if ((color & 0xff000000u) == 0) {
+        return;
+    }
+
+    DP_ShapeBox box = shape_box_make(x1, y1, x2, y2);
+    switch (shape) {
+    case DP_STROKE_SHAPE_RECTANGLE:
+        push_shape_fill_rect(be, cs_or_null, blend_mode, color, box.left,
+                             box.top, box.right + 1, box.bottom + 1);
+        break;
+    case DP_STROKE_SHAPE_ELLIPSE: {
+        // One rectangle for every run of rows that cover the same pixels.
+        int top = box.top;
+        int left, right;
+        bool have_span = shape_ellipse_span(&box, top, &left, &right);
+        for (int y = box.top + 1; y <= box.bottom + 1; ++y) {
+            int next_left, next_right;
+            bool have_next = shape_ellipse_span(&box, y, &next_left,
+                                                &next_right);
+            if (have_next != have_span
+                || (have_next && (next_left != left || next_right != right))) {
+                if (have_span) {
+                    push_shape_fill_rect(be, cs_or_null, blend_mode, color,
+                                         left, top, right + 1, y);
+                }
+                top = y;
+                have_span = have_next;
+                left = next_left;
+                right = next_right;
+            }
+        }
+        if (have_span) {
+            push_shape_fill_rect(be, cs_or_null, blend_mode, color, left, top,
+                                 right + 1, box.bottom + 1);
+        }
+        break;
+    }
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+long long DP_brush_engine_stroke_shape(DP_BrushEngine *be, DP_StrokeShape shape,
+                                       float x1, float y1, float x2, float y2,
+                                       bool fill, long long time_msec,
+                                       DP_CanvasState *cs_or_null)
+{
+    DP_ASSERT(be);
+    DP_PERF_BEGIN_DETAIL(fn, "stroke_shape", "active=%d shape=%d",
+                         (int)be->active, (int)shape);
+    DP_EVENT_LOG("stroke_shape shape=%d x1=%f y1=%f x2=%f y2=%f fill=%d",
+                 (int)shape, x1, y1, x2, y2, (int)fill);
+
+    DP_ShapeStroke ss = {be, cs_or_null, x1, y1, 1.0f, time_msec};
+    if (be->active == DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+        stroke_shape_outline(&ss, shape, x1, y1, x2, y2);
+    }
+    else if (fill && shape != DP_STROKE_SHAPE_LINE) {
+        fill_shape(be, cs_or_null, shape, x1, y1, x2, y2);
+    }
+    else if (shape_rasterizes_pixels(be)) {
+        rasterize_shape_pixels(be, shape, x1, y1, x2, y2);
+    }
+    else {
+        float airbrush_rate = shape_airbrush_suspend(be);
+        stroke_shape_outline(&ss, shape, x1, y1, x2, y2);
+        shape_airbrush_resume(be, airbrush_rate);
+    }
+
+    DP_PERF_END(fn);
+    return ss.time_msec;
+}
+
+// Curves get cut into straight pieces until those stray no farther than this
+// fraction of the brush size from the curve, within the given limits in
+// pixels. Big brushes hide small deviations, small brushes don't.
+// MyPaint brushes don't offer a size to go by, so they get the finest cuts.
+#define CURVE_TOLERANCE_SIZE_FRACTION 0.05f
+#define CURVE_TOLERANCE_MIN           0.05f
+#define CURVE_TOLERANCE_MAX           0.5f
+#define CURVE_SUBDIVISION_DEPTH_MAX   16
+
+static float curve_tolerance(DP_BrushEngine *be)
+{
+    if (be->active == DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+        return CURVE_TOLERANCE_MIN;
+    }
+    else {
+        float size =
+            DP_classic_brush_size_at(&be->classic.brush, 1.0f, 0.0f, 0.0f);
+        float tolerance = size * CURVE_TOLERANCE_SIZE_FRACTION;
+        return CLAMP(tolerance, CURVE_TOLERANCE_MIN, CURVE_TOLERANCE_MAX);
+    }
+}
+
+static float curve_pressure(float pressure)
+{
+    return CLAMP(pressure, 0.0f, 1.0f);
+}
+
+// A Bezier curve stays inside of the hull of its points, so if the control
+// points are close enough to the line between the end points, so is the curve.
+static bool curve_flat_enough(const float xs[4], const float ys[4],
+                              float tolerance)
+{
+    float dx = xs[3] - xs[0];
+    float dy = ys[3] - ys[0];
+    float length_sq = dx * dx + dy * dy;
+    float max_sq = tolerance * tolerance;
+    for (int i = 1; i < 3; ++i) {
+        float ex = xs[i] - xs[0];
+        float ey = ys[i] - ys[0];
+        float dot = ex * dx + ey * dy;
+        float distance_sq;
+        if (dot <= 0.0f) {
+            distance_sq = ex * ex + ey * ey;
+        }
+        else if (dot >= length_sq) {
+            float fx = xs[i] - xs[3];
+            float fy = ys[i] - ys[3];
+            distance_sq = fx * fx + fy * fy;
+        }
+        else {
+            float cross = ex * dy - ey * dx;
+            distance_sq = cross * cross / length_sq;
+        }
+        if (distance_sq > max_sq) {
+            return false;
+        }
+    }
+    return true;
+}
+
+static void stroke_curve_bezier(DP_ShapeStroke *ss, const float xs[4],
+                                const float ys[4], float pressure0,
+                                float pressure3, float tolerance, int depth)
+{
+    if (depth >= CURVE_SUBDIVISION_DEPTH_MAX
+        || curve_flat_enough(xs, ys, tolerance)) {
+        shape_stroke_line_to_pressure(ss, xs[3], ys[3], pressure3);
+    }
+    else {
+        // Split the curve in half at t = 0.5 with de Casteljau's algorithm.
+        float x01 = (xs[0] + xs[1]) * 0.5f;
+        float y01 = (ys[0] + ys[1]) * 0.5f;
+        float x12 = (xs[1] + xs[2]) * 0.5f;
+        float y12 = (ys[1] + ys[2]) * 0.5f;
+        float x23 = (xs[2] + xs[3]) * 0.5f;
+        float y23 = (ys[2] + ys[3]) * 0.5f;
+        float x012 = (x01 + x12) * 0.5f;
+        float y012 = (y01 + y12) * 0.5f;
+        float x123 = (x12 + x23) * 0.5f;
+        float y123 = (y12 + y23) * 0.5f;
+        float x0123 = (x012 + x123) * 0.5f;
+        float y0123 = (y012 + y123) * 0.5f;
+        float pressure = (pressure0 + pressure3) * 0.5f;
+        stroke_curve_bezier(ss, (float[4]){xs[0], x01, x012, x0123},
+                            (float[4]){ys[0], y01, y012, y0123}, pressure0,
+                            pressure, tolerance, depth + 1);
+        stroke_curve_bezier(ss, (float[4]){x0123, x123, x23, xs[3]},
+                            (float[4]){y0123, y123, y23, ys[3]}, pressure,
+                            pressure3, tolerance, depth + 1);
+    }
+}
+
+// Every piece of a Catmull-Rom spline between two points is a Bezier curve
+// with control points a sixth of the way along the neighboring tangents. The
+// first and last point stand in for the missing neighbors at the ends.
+static void stroke_curve_catmull_rom(DP_ShapeStroke *ss,
+                                     const DP_CurvePoint *points, int count,
+                                     float tolerance)
+{
+    for (int i = 0; i < count - 1; ++i) {
+        const DP_CurvePoint *prev = &points[i == 0 ? 0 : i - 1];
+        const DP_CurvePoint *p1 = &points[i];
+        const DP_CurvePoint *p2 = &points[i + 1];
+        const DP_CurvePoint *next = &points[i + 2 < count ? i + 2 : count - 1];
+        float xs[4] = {p1->x, p1->x + (p2->x - prev->x) / 6.0f,
+                       p2->x - (next->x - p1->x) / 6.0f, p2->x};
+        float ys[4] = {p1->y, p1->y + (p2->y - prev->y) / 6.0f,
+                       p2->y - (next->y - p1->y) / 6.0f, p2->y};
+        stroke_curve_bezier(ss, xs, ys, curve_pressure(p1->pressure),
+                            curve_pressure(p2->pressure), tolerance, 0);
+    }
+}
+
+long long DP_brush_engine_stroke_curve(DP_BrushEngine *be, DP_StrokeCurve curve,
+                                       const DP_CurvePoint *points, int count,
+                                       long long time_msec,
+                                       DP_CanvasState *cs_or_null)
+{
+    DP_ASSERT(be);
+    DP_ASSERT(points || count == 0);
+    if (count <= 0) {
+        return time_msec;
+    }
+
+    DP_PERF_BEGIN_DETAIL(fn, "stroke_curve", "active=%d curve=%d count=%d",
+                         (int)be->active, (int)curve, count);
+    DP_EVENT_LOG("stroke_curve curve=%d count=%d", (int)curve, count);
+
+    float tolerance = curve_tolerance(be);
+    float pressure = curve_pressure(points[0].pressure);
+    DP_ShapeStroke ss = {be, cs_or_null, points[0].x, points[0].y, pressure,
+                         time_msec};
+    float airbrush_rate = shape_airbrush_suspend(be);
+    shape_stroke_point_pressure(&ss, points[0].x, points[0].y, pressure);
+    switch (curve) {
+    case DP_STROKE_CURVE_BEZIER:
+        for (int i = 0; i + 3 < count; i += 3) {
+            const DP_CurvePoint *p = &points[i];
+            float xs[4] = {p[0].x, p[1].x, p[2].x, p[3].x};
+            float ys[4] = {p[0].y, p[1].y, p[2].y, p[3].y};
+            stroke_curve_bezier(&ss, xs, ys, curve_pressure(p[0].pressure),
+                                curve_pressure(p[3].pressure), tolerance, 0);
+        }
+        break;
+    case DP_STROKE_CURVE_CATMULL_ROM:
+        stroke_curve_catmull_rom(&ss, points, count, tolerance);
+        break;
+    default:
+        DP_UNREACHABLE();
+    }
+    shape_airbrush_resume(be, airbrush_rate);
+
+    DP_PERF_END(fn);
+    return ss.time_msec;
 }
 
 void DP_brush_engine_stroke_end(DP_BrushEngine *be, long long time_msec,
@@ -1626,7 +3028,16 @@ void DP_brush_engine_stroke_end(DP_BrushEngine *be, long long time_msec,
     if (be->stabilizer.active) {
         stabilizer_finish(be, time_msec, cs_or_null);
     }
+    pull_string_finish(be, cs_or_null);
+    if (be->stroke.in_progress
+        && be->active != DP_BRUSH_ENGINE_ACTIVE_MYPAINT) {
+        add_airbrush_dabs(be, time_msec);
+    }
+    if (be->active == DP_BRUSH_ENGINE_ACTIVE_PIXEL) {
+        push_pending_dab_pixel(be);
+    }
 
+    dispose_merged(be);
     DP_layer_content_decref_nullable(be->lc);
     be->lc = NULL;
     be->cs = NULL;
@@ -1646,6 +3057,7 @@ void DP_brush_engine_offset_add(DP_BrushEngine *be, float x, float y)
     switch (be->active) {
     case DP_BRUSH_ENGINE_ACTIVE_PIXEL:
     case DP_BRUSH_ENGINE_ACTIVE_SOFT:
+    case DP_BRUSH_ENGINE_ACTIVE_STAMP:
         be->classic.last_x += x;
         be->classic.last_y += y;
         break;
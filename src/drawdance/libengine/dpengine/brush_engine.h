@@ -38,6 +38,28 @@ typedef struct DP_BrushPoint {
     long long time_msec;
 } DP_BrushPoint;
 
+typedef enum DP_SymmetryMode {
+    DP_SYMMETRY_NONE,
+    // Mirrored across the vertical line at x.
+    DP_SYMMETRY_VERTICAL,
+    // Mirrored across the horizontal line at y.
+    DP_SYMMETRY_HORIZONTAL,
+    // Repeated count times in even steps around the point at x, y.
+    DP_SYMMETRY_ROTATIONAL,
+} DP_SymmetryMode;
+
+// Every dab gets duplicated at its mirrored or rotated positions, so the draw
+// dabs messages already contain all of the copies. Dab angles get mirrored or
+// rotated along with them. Stamp images themselves can only be rotated, not
+// flipped, so mirroring a lopsided stamp turns it, but doesn't flip it over.
+typedef struct DP_StrokeSymmetry {
+    DP_SymmetryMode mode;
+    float x, y;
+    // Number of rotational copies, including the original. Less than 2 turns
+    // rotational symmetry off.
+    int count;
+} DP_StrokeSymmetry;
+
 typedef struct DP_StrokeParams {
     int layer_id;
     bool interpolate;
@@ -45,8 +67,29 @@ typedef struct DP_StrokeParams {
     bool smoothing_finish_strokes;
     int stabilizer_sample_count;
     bool stabilizer_finish_strokes;
+    // Length of the string in canvas pixels that the brush gets pulled along
+    // by, zero to turn it off. The brush only moves when the input gets
+    // farther away than that and the end of the stroke snaps to the input.
+    int pull_string_distance;
+    DP_StrokeSymmetry symmetry;
 } DP_StrokeParams;
 
+typedef enum DP_StrokeShape {
+    DP_STROKE_SHAPE_LINE,
+    DP_STROKE_SHAPE_RECTANGLE,
+    DP_STROKE_SHAPE_ELLIPSE,
+} DP_StrokeShape;
+
+typedef enum DP_StrokeCurve {
+    DP_STROKE_CURVE_BEZIER,
+    DP_STROKE_CURVE_CATMULL_ROM,
+} DP_StrokeCurve;
+
+typedef struct DP_CurvePoint {
+    float x, y;
+    float pressure;
+} DP_CurvePoint;
+
 
 typedef void (*DP_BrushEnginePushMessageFn)(void *user, DP_Message *msg);
 typedef void (*DP_BrushEnginePollControlFn)(void *user, bool enable);
@@ -73,6 +116,11 @@ void DP_brush_engine_mypaint_brush_set(DP_BrushEngine *be,
                                        const DP_UPixelFloat *color_override,
                                        bool eraser_override);
 
+// Pushes any dabs that are being held back. Consecutive dabs get merged into
+// as few draw dabs messages as possible, which happens across stroke_to calls
+// too. They get pushed when the brush changes, when the stroke ends, when they
+// don't fit into a message anymore or when the oldest of them has been
+// waiting for a bit, checked on stroke_to and poll.
 void DP_brush_engine_dabs_flush(DP_BrushEngine *be);
 
 // Sets the context id for this stroke, optionally pushes an undo point message.
@@ -83,6 +131,32 @@ void DP_brush_engine_stroke_begin(DP_BrushEngine *be, unsigned int context_id,
 void DP_brush_engine_stroke_to(DP_BrushEngine *be, DP_BrushPoint bp,
                                DP_CanvasState *cs_or_null);
 
+// Draws a line from (x1, y1) to (x2, y2) or the outline of a rectangle or an
+// ellipse inside of the box they span, at full pressure. Goes straight to the
+// brush, without any smoothing or stabilizing. One pixel pixel brushes put down
+// each pixel of the shape exactly once. If fill is set, rectangles and ellipses
+// get drawn as fill rect messages with the brush color and blend mode instead,
+// except for MyPaint brushes, which only ever draw the outline. Call this
+// between stroke_begin and stroke_end, it returns the time of the last point.
+long long DP_brush_engine_stroke_shape(DP_BrushEngine *be, DP_StrokeShape shape,
+                                       float x1, float y1, float x2, float y2,
+                                       bool fill, long long time_msec,
+                                       DP_CanvasState *cs_or_null);
+
+// Draws a curve through the given points, cut into straight pieces finely
+// enough for the brush size that it doesn't come out looking polygonal. Bezier
+// curves take a point on the curve followed by two control points and the next
+// point on the curve, repeating, so 3 * n + 1 points in total. The pressure of
+// the control points is ignored. Catmull-Rom splines pass through every point.
+// The pressure is interpolated between the points along the curve. Only plain
+// arithmetic goes into this, so it comes out the same on every platform. Call
+// this between stroke_begin and stroke_end, it returns the time of the last
+// point.
+long long DP_brush_engine_stroke_curve(DP_BrushEngine *be, DP_StrokeCurve curve,
+                                       const DP_CurvePoint *points, int count,
+                                       long long time_msec,
+                                       DP_CanvasState *cs_or_null);
+
 // Pushes draw dabs messages if stabilizing.
 void DP_brush_engine_poll(DP_BrushEngine *be, long long time_msec,
                           DP_CanvasState *cs_or_null);
@@ -30,6 +30,7 @@
 #include "layer_routes.h"
 #include "paint.h"
 #include "pixels.h"
+#include "stamp_mask.h"
 #include "tile.h"
 #include <dpcommon/binary.h>
 #include <dpcommon/common.h>
@@ -294,7 +295,8 @@ void render_brush_preview(
 static void set_preview_classic_brush(void *user, DP_BrushEngine *be,
                                       DP_UPixelFloat color)
 {
-    DP_StrokeParams stroke = {1, false, 0, false, 0, false};
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
     DP_brush_engine_classic_brush_set(be, user, &stroke, &color, false);
 }
 
@@ -315,7 +317,8 @@ static void set_preview_mypaint_brush(void *user, DP_BrushEngine *be,
 {
     const DP_MyPaintBrush *brush = ((void **)user)[0];
     const DP_MyPaintSettings *settings = ((void **)user)[1];
-    DP_StrokeParams stroke = {1, false, 0, false, 0, false};
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
     DP_brush_engine_mypaint_brush_set(be, brush, settings, &stroke, &color,
                                       false);
 }
@@ -369,6 +372,42 @@ static void set_preview_classic_dab(DP_UNUSED int count, DP_ClassicDab *cds,
         DP_classic_brush_dab_opacity_at(cb, 1.0f, HUGE_VALF, HUGE_VALF));
 }
 
+static void set_preview_soft_dab(DP_UNUSED int count, DP_SoftDab *sds,
+                                 void *user)
+{
+    DP_ASSERT(count == 1);
+    const DP_ClassicBrush *cb = user;
+    DP_soft_dab_init(
+        sds, 0, 0, 0,
+        DP_classic_brush_soft_dab_size_at(cb, 1.0f, HUGE_VALF, HUGE_VALF),
+        DP_classic_brush_dab_hardness_at(cb, 1.0f, HUGE_VALF, HUGE_VALF),
+        DP_classic_brush_dab_opacity_at(cb, 1.0f, HUGE_VALF, HUGE_VALF));
+}
+
+static void set_preview_stamp_dab(DP_UNUSED int count, DP_StampDab *sds,
+                                  void *user)
+{
+    DP_ASSERT(count == 1);
+    const DP_ClassicBrush *cb = user;
+    DP_stamp_dab_init(
+        sds, 0, 0, 0,
+        DP_classic_brush_soft_dab_size_at(cb, 1.0f, HUGE_VALF, HUGE_VALF),
+        DP_classic_brush_dab_stamp_angle(cb, 0.0f),
+        DP_classic_brush_dab_opacity_at(cb, 1.0f, HUGE_VALF, HUGE_VALF));
+}
+
+static bool have_stamp_mask(uint32_t id)
+{
+    DP_StampMask *sm = DP_stamp_mask_search_inc(id);
+    if (sm) {
+        DP_stamp_mask_decref(sm);
+        return true;
+    }
+    else {
+        return false;
+    }
+}
+
 static DP_Message *get_preview_draw_dab_message(const DP_ClassicBrush *cb,
                                                 int width, int height,
                                                 uint32_t color)
@@ -382,8 +421,28 @@ static DP_Message *get_preview_draw_dab_message(const DP_ClassicBrush *cb,
         return DP_msg_draw_dabs_pixel_square_new(
             0, 1, DP_int_to_int32(width / 2), DP_int_to_int32(height / 2),
             color, DP_BLEND_MODE_NORMAL, set_preview_pixel_dab, 1, (void *)cb);
+    case DP_BRUSH_SHAPE_CLASSIC_STAMP:
+        if (have_stamp_mask(cb->stamp_mask)) {
+            return DP_msg_draw_dabs_stamp_new(
+                0, 1, DP_int_to_int32(width * 4 / 2),
+                DP_int_to_int32(height * 4 / 2), color, DP_BLEND_MODE_NORMAL,
+                cb->stamp_mask, cb->grain, DP_classic_brush_dab_grain_scale(cb),
+                DP_classic_brush_dab_grain_strength(cb), set_preview_stamp_dab,
+                1, (void *)cb);
+        }
+        DP_FALLTHROUGH();
     default:
-        DP_ASSERT(cb->shape == DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND);
+        DP_ASSERT(cb->shape == DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND
+                  || cb->shape == DP_BRUSH_SHAPE_CLASSIC_STAMP);
+        if (cb->falloff != DP_CLASSIC_BRUSH_FALLOFF_RAMP || cb->grain != 0) {
+            return DP_msg_draw_dabs_soft_new(
+                0, 1, DP_int_to_int32(width * 4 / 2),
+                DP_int_to_int32(height * 4 / 2), color, DP_BLEND_MODE_NORMAL,
+                (uint8_t)cb->falloff, cb->grain,
+                DP_classic_brush_dab_grain_scale(cb),
+                DP_classic_brush_dab_grain_strength(cb), set_preview_soft_dab,
+                1, (void *)cb);
+        }
         return DP_msg_draw_dabs_classic_new(
             0, 1, DP_int_to_int32(width * 4 / 2),
             DP_int_to_int32(height * 4 / 2), color, DP_BLEND_MODE_NORMAL,
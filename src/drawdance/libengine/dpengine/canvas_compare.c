@@ -0,0 +1,500 @@
+// SPDX-License-Identifier: MIT
+#include "canvas_compare.h"
+#include "canvas_state.h"
+#include "layer_content.h"
+#include "layer_group.h"
+#include "layer_list.h"
+#include "layer_props.h"
+#include "layer_props_list.h"
+#include "layer_routes.h"
+#include "tile.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpcommon/vector.h>
+#include <limits.h>
+#include <string.h>
+
+
+typedef struct DP_LayerCompareEntry {
+    DP_LayerCompare lc;
+    bool *tile_changes;
+} DP_LayerCompareEntry;
+
+struct DP_CanvasCompare {
+    unsigned int flags;
+    int width, height;
+    int xtiles, ytiles;
+    DP_Rect bounds;
+    DP_Vector layers;
+};
+
+typedef struct DP_CanvasCompareContext {
+    DP_CanvasCompare *cc;
+    DP_CanvasState *cs;
+    DP_CanvasState *prev;
+    bool size_changed;
+} DP_CanvasCompareContext;
+
+
+static void add_bounds(DP_CanvasCompare *cc, DP_Rect rect)
+{
+    DP_Rect canvas = DP_rect_make(0, 0, cc->width, cc->height);
+    DP_Rect clamped = DP_rect_intersection(canvas, rect);
+    if (DP_rect_valid(clamped)) {
+        cc->bounds = DP_rect_valid(cc->bounds)
+                       ? DP_rect_union(cc->bounds, clamped)
+                       : clamped;
+    }
+}
+
+static void add_tile_bounds(DP_CanvasCompare *cc, int tile_x, int tile_y)
+{
+    add_bounds(cc, DP_rect_make(tile_x * DP_TILE_SIZE, tile_y * DP_TILE_SIZE,
+                                DP_TILE_SIZE, DP_TILE_SIZE));
+}
+
+static DP_LayerCompareEntry *push_layer(DP_CanvasCompare *cc, int layer_id,
+                                        unsigned int flags)
+{
+    DP_LayerCompareEntry *lce =
+        DP_vector_push(&cc->layers, sizeof(DP_LayerCompareEntry));
+    *lce = (DP_LayerCompareEntry){{layer_id, flags, 0}, NULL};
+    return lce;
+}
+
+static void mark_tile(DP_CanvasCompare *cc, DP_LayerCompareEntry *lce,
+                      int tile_x, int tile_y)
+{
+    if (!lce->tile_changes) {
+        size_t count = DP_int_to_size(cc->xtiles * cc->ytiles);
+        size_t size = sizeof(*lce->tile_changes) * count;
+        lce->tile_changes = DP_malloc_zeroed(size);
+    }
+    bool *tile_change = &lce->tile_changes[tile_y * cc->xtiles + tile_x];
+    if (!*tile_change) {
+        *tile_change = true;
+        ++lce->lc.changed_tile_count;
+        add_tile_bounds(cc, tile_x, tile_y);
+    }
+}
+
+
+static bool props_attributes_differ(DP_LayerProps *lp, DP_LayerProps *prev_lp)
+{
+    if (DP_layer_props_differ(lp, prev_lp)) {
+        return true;
+    }
+
+    size_t title_length, prev_title_length;
+    const char *title = DP_layer_props_title(lp, &title_length);
+    const char *prev_title = DP_layer_props_title(prev_lp, &prev_title_length);
+    return title_length != prev_title_length
+        || (title_length != 0 && memcmp(title, prev_title, title_length) != 0);
+}
+
+static void mark_content_tiles(DP_CanvasCompare *cc, DP_LayerCompareEntry *lce,
+                               DP_LayerContent *lc)
+{
+    int xtiles = DP_tile_count_round(DP_layer_content_width(lc));
+    int ytiles = DP_tile_count_round(DP_layer_content_height(lc));
+    int max_x = DP_min_int(xtiles, cc->xtiles);
+    int max_y = DP_min_int(ytiles, cc->ytiles);
+    for (int y = 0; y < max_y; ++y) {
+        for (int x = 0; x < max_x; ++x) {
+            if (DP_layer_content_tile_at_noinc(lc, x, y)) {
+                mark_tile(cc, lce, x, y);
+            }
+        }
+    }
+}
+
+static void add_content_bounds(DP_CanvasCompare *cc, DP_LayerContent *lc)
+{
+    int xtiles = DP_tile_count_round(DP_layer_content_width(lc));
+    int ytiles = DP_tile_count_round(DP_layer_content_height(lc));
+    for (int y = 0; y < ytiles; ++y) {
+        for (int x = 0; x < xtiles; ++x) {
+            if (DP_layer_content_tile_at_noinc(lc, x, y)) {
+                add_tile_bounds(cc, x, y);
+            }
+        }
+    }
+}
+
+static void add_entry_bounds(DP_CanvasCompare *cc, DP_LayerListEntry *lle)
+{
+    if (DP_layer_list_entry_is_group(lle)) {
+        DP_LayerList *ll =
+            DP_layer_group_children_noinc(DP_layer_list_entry_group_noinc(lle));
+        int count = DP_layer_list_count(ll);
+        for (int i = 0; i < count; ++i) {
+            add_entry_bounds(cc, DP_layer_list_at_noinc(ll, i));
+        }
+    }
+    else {
+        add_content_bounds(cc, DP_layer_list_entry_content_noinc(lle));
+    }
+}
+
+static void compare_content(DP_CanvasCompareContext *ccc,
+                            DP_LayerCompareEntry *lce, DP_LayerContent *lc,
+                            DP_LayerContent *prev_lc)
+{
+    DP_CanvasCompare *cc = ccc->cc;
+    if (ccc->size_changed) {
+        // Tile indexes don't line up anymore, so everything counts as changed.
+        mark_content_tiles(cc, lce, lc);
+    }
+    else {
+        int xtiles = cc->xtiles;
+        int ytiles = cc->ytiles;
+        for (int y = 0; y < ytiles; ++y) {
+            for (int x = 0; x < xtiles; ++x) {
+                if (DP_layer_content_tile_at_noinc(lc, x, y)
+                    != DP_layer_content_tile_at_noinc(prev_lc, x, y)) {
+                    mark_tile(cc, lce, x, y);
+                }
+            }
+        }
+    }
+}
+
+static void compare_layer_list(DP_CanvasCompareContext *ccc, DP_LayerList *ll,
+                               DP_LayerPropsList *lpl,
+                               DP_LayerPropsList *prev_lpl_or_null);
+
+static void compare_added(DP_CanvasCompareContext *ccc, DP_LayerListEntry *lle,
+                          DP_LayerProps *lp)
+{
+    DP_LayerCompareEntry *lce =
+        push_layer(ccc->cc, DP_layer_props_id(lp), DP_LAYER_COMPARE_ADDED);
+    if (DP_layer_list_entry_is_group(lle)) {
+        compare_layer_list(
+            ccc,
+            DP_layer_group_children_noinc(DP_layer_list_entry_group_noinc(lle)),
+            DP_layer_props_children_noinc(lp), NULL);
+    }
+    else {
+        mark_content_tiles(ccc->cc, lce,
+                           DP_layer_list_entry_content_noinc(lle));
+    }
+}
+
+static void compare_entry(DP_CanvasCompareContext *ccc, DP_LayerListEntry *lle,
+                          DP_LayerProps *lp, bool moved)
+{
+    DP_CanvasCompare *cc = ccc->cc;
+    int layer_id = DP_layer_props_id(lp);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(
+        DP_canvas_state_layer_routes_noinc(ccc->prev), layer_id);
+    if (!lre) {
+        compare_added(ccc, lle, lp);
+        return;
+    }
+
+    DP_LayerListEntry *prev_lle = DP_layer_routes_entry_layer(lre, ccc->prev);
+    DP_LayerProps *prev_lp = DP_layer_routes_entry_props(lre, ccc->prev);
+    bool is_group = DP_layer_list_entry_is_group(lle);
+    bool was_group = DP_layer_list_entry_is_group(prev_lle);
+    if (is_group != was_group) {
+        // Changing the type of a layer is effectively replacing it.
+        push_layer(cc, layer_id, DP_LAYER_COMPARE_REMOVED);
+        add_entry_bounds(cc, prev_lle);
+        compare_added(ccc, lle, lp);
+        return;
+    }
+
+    if (is_group) {
+        DP_LayerGroup *lg = DP_layer_list_entry_group_noinc(lle);
+        DP_LayerGroup *prev_lg = DP_layer_list_entry_group_noinc(prev_lle);
+        bool props_changed = moved || props_attributes_differ(lp, prev_lp);
+        if (props_changed) {
+            push_layer(cc, layer_id, DP_LAYER_COMPARE_PROPS);
+            if (moved || DP_layer_props_differ(lp, prev_lp)) {
+                add_entry_bounds(cc, lle);
+            }
+        }
+        if (lg != prev_lg || lp != prev_lp) {
+            compare_layer_list(ccc, DP_layer_group_children_noinc(lg),
+                               DP_layer_props_children_noinc(lp),
+                               DP_layer_props_children_noinc(prev_lp));
+        }
+    }
+    else {
+        DP_LayerContent *lc = DP_layer_list_entry_content_noinc(lle);
+        DP_LayerContent *prev_lc = DP_layer_list_entry_content_noinc(prev_lle);
+        bool props_changed = moved || props_attributes_differ(lp, prev_lp);
+        if (props_changed || lc != prev_lc || ccc->size_changed) {
+            DP_LayerCompareEntry *lce = push_layer(cc, layer_id, 0);
+            if (props_changed) {
+                lce->lc.flags |= DP_LAYER_COMPARE_PROPS;
+                if (moved || DP_layer_props_differ(lp, prev_lp)) {
+                    add_content_bounds(cc, lc);
+                }
+            }
+            if (lc != prev_lc || ccc->size_changed) {
+                compare_content(ccc, lce, lc, prev_lc);
+                if (lce->lc.changed_tile_count != 0) {
+                    lce->lc.flags |= DP_LAYER_COMPARE_CONTENT;
+                }
+            }
+            if (lce->lc.flags == 0) {
+                DP_free(lce->tile_changes);
+                DP_vector_pop(&cc->layers);
+            }
+        }
+    }
+}
+
+// Figures out which layers in the list were moved. Layers that came from a
+// different parent were moved into it. Of the ones that were already in the
+// list before, the longest run that kept its relative order stays put and
+// the rest were moved around it. That way moving a single layer only reports
+// that one, not all the siblings whose indexes shifted because of it.
+static bool *find_moved_layers(DP_CanvasCompareContext *ccc,
+                               DP_LayerPropsList *lpl,
+                               DP_LayerPropsList *prev_lpl_or_null, int count)
+{
+    if (count == 0) {
+        return NULL;
+    }
+
+    size_t size = DP_int_to_size(count);
+    bool *moved = DP_malloc_zeroed(sizeof(*moved) * size);
+    int *prev_indexes = DP_malloc(sizeof(*prev_indexes) * size);
+    DP_LayerRoutes *prev_lr = DP_canvas_state_layer_routes_noinc(ccc->prev);
+    for (int i = 0; i < count; ++i) {
+        int layer_id = DP_layer_props_id(DP_layer_props_list_at_noinc(lpl, i));
+        int prev_index =
+            prev_lpl_or_null
+                ? DP_layer_props_list_index_by_id(prev_lpl_or_null, layer_id)
+                : -1;
+        prev_indexes[i] = prev_index;
+        if (prev_index == -1) {
+            moved[i] = DP_layer_routes_search(prev_lr, layer_id) != NULL;
+        }
+    }
+
+    // Longest increasing run of previous indexes, quadratic, but layer lists
+    // are short and only get here when they actually changed.
+    int *lengths = DP_malloc(sizeof(*lengths) * size);
+    int *links = DP_malloc(sizeof(*links) * size);
+    int best = -1;
+    for (int i = 0; i < count; ++i) {
+        lengths[i] = 0;
+        links[i] = -1;
+        if (prev_indexes[i] != -1) {
+            lengths[i] = 1;
+            for (int j = 0; j < i; ++j) {
+                if (prev_indexes[j] != -1 && prev_indexes[j] < prev_indexes[i]
+                    && lengths[j] + 1 > lengths[i]) {
+                    lengths[i] = lengths[j] + 1;
+                    links[i] = j;
+                }
+            }
+            if (best == -1 || lengths[i] > lengths[best]) {
+                best = i;
+            }
+        }
+    }
+
+    for (int i = 0; i < count; ++i) {
+        moved[i] = moved[i] || prev_indexes[i] != -1;
+    }
+    for (int i = best; i != -1; i = links[i]) {
+        moved[i] = false;
+    }
+
+    DP_free(links);
+    DP_free(lengths);
+    DP_free(prev_indexes);
+    return moved;
+}
+
+static void compare_layer_list(DP_CanvasCompareContext *ccc, DP_LayerList *ll,
+                               DP_LayerPropsList *lpl,
+                               DP_LayerPropsList *prev_lpl_or_null)
+{
+    int count = DP_layer_list_count(ll);
+    bool *moved = find_moved_layers(ccc, lpl, prev_lpl_or_null, count);
+    for (int i = 0; i < count; ++i) {
+        compare_entry(ccc, DP_layer_list_at_noinc(ll, i),
+                      DP_layer_props_list_at_noinc(lpl, i), moved[i]);
+    }
+    DP_free(moved);
+}
+
+static void compare_removed(DP_CanvasCompareContext *ccc,
+                            DP_LayerList *prev_ll,
+                            DP_LayerPropsList *prev_lpl)
+{
+    DP_CanvasCompare *cc = ccc->cc;
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(ccc->cs);
+    int count = DP_layer_list_count(prev_ll);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *prev_lle = DP_layer_list_at_noinc(prev_ll, i);
+        DP_LayerProps *prev_lp = DP_layer_props_list_at_noinc(prev_lpl, i);
+        int layer_id = DP_layer_props_id(prev_lp);
+        if (!DP_layer_routes_search(lr, layer_id)) {
+            push_layer(cc, layer_id, DP_LAYER_COMPARE_REMOVED);
+            add_entry_bounds(cc, prev_lle);
+        }
+
+        if (DP_layer_list_entry_is_group(prev_lle)) {
+            compare_removed(ccc,
+                            DP_layer_group_children_noinc(
+                                DP_layer_list_entry_group_noinc(prev_lle)),
+                            DP_layer_props_children_noinc(prev_lp));
+        }
+    }
+}
+
+static void compare_layers(DP_CanvasCompareContext *ccc)
+{
+    DP_CanvasState *cs = ccc->cs;
+    DP_CanvasState *prev = ccc->prev;
+    DP_LayerList *ll = DP_canvas_state_layers_noinc(cs);
+    DP_LayerPropsList *lpl = DP_canvas_state_layer_props_noinc(cs);
+    DP_LayerList *prev_ll = DP_canvas_state_layers_noinc(prev);
+    DP_LayerPropsList *prev_lpl = DP_canvas_state_layer_props_noinc(prev);
+    if (ll != prev_ll || lpl != prev_lpl || ccc->size_changed) {
+        compare_layer_list(ccc, ll, lpl, prev_lpl);
+        compare_removed(ccc, prev_ll, prev_lpl);
+        if (lpl != prev_lpl || ccc->cc->layers.used != 0) {
+            ccc->cc->flags |= DP_CANVAS_COMPARE_LAYERS;
+        }
+    }
+}
+
+DP_CanvasCompare *DP_canvas_compare_new(DP_CanvasState *cs,
+                                        DP_CanvasState *prev)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(prev);
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    DP_CanvasCompare *cc = DP_malloc(sizeof(*cc));
+    *cc = (DP_CanvasCompare){0,
+                             width,
+                             height,
+                             DP_tile_count_round(width),
+                             DP_tile_count_round(height),
+                             {INT_MAX, INT_MAX, INT_MIN, INT_MIN},
+                             DP_VECTOR_NULL};
+    DP_VECTOR_INIT_TYPE(&cc->layers, DP_LayerCompareEntry, 8);
+
+    if (cs == prev) {
+        return cc;
+    }
+
+    bool size_changed = width != DP_canvas_state_width(prev)
+                     || height != DP_canvas_state_height(prev);
+    if (size_changed) {
+        cc->flags |= DP_CANVAS_COMPARE_SIZE;
+    }
+
+    if (DP_canvas_state_background_tile_noinc(cs)
+        != DP_canvas_state_background_tile_noinc(prev)) {
+        cc->flags |= DP_CANVAS_COMPARE_BACKGROUND;
+    }
+
+    if (size_changed || (cc->flags & DP_CANVAS_COMPARE_BACKGROUND)) {
+        add_bounds(cc, DP_rect_make(0, 0, width, height));
+    }
+
+    if (DP_canvas_state_annotations_noinc(cs)
+        != DP_canvas_state_annotations_noinc(prev)) {
+        cc->flags |= DP_CANVAS_COMPARE_ANNOTATIONS;
+    }
+
+    if (DP_canvas_state_metadata_noinc(cs)
+        != DP_canvas_state_metadata_noinc(prev)) {
+        cc->flags |= DP_CANVAS_COMPARE_METADATA;
+    }
+
+    if (DP_canvas_state_timeline_noinc(cs)
+        != DP_canvas_state_timeline_noinc(prev)) {
+        cc->flags |= DP_CANVAS_COMPARE_TIMELINE;
+    }
+
+    DP_CanvasCompareContext ccc = {cc, cs, prev, size_changed};
+    compare_layers(&ccc);
+    return cc;
+}
+
+static void dispose_layer_compare_entry(void *element)
+{
+    DP_LayerCompareEntry *lce = element;
+    DP_free(lce->tile_changes);
+}
+
+void DP_canvas_compare_free(DP_CanvasCompare *cc)
+{
+    if (cc) {
+        DP_VECTOR_CLEAR_DISPOSE_TYPE(&cc->layers, DP_LayerCompareEntry,
+                                     dispose_layer_compare_entry);
+        DP_free(cc);
+    }
+}
+
+unsigned int DP_canvas_compare_flags(DP_CanvasCompare *cc)
+{
+    DP_ASSERT(cc);
+    return cc->flags;
+}
+
+int DP_canvas_compare_xtiles(DP_CanvasCompare *cc)
+{
+    DP_ASSERT(cc);
+    return cc->xtiles;
+}
+
+int DP_canvas_compare_ytiles(DP_CanvasCompare *cc)
+{
+    DP_ASSERT(cc);
+    return cc->ytiles;
+}
+
+int DP_canvas_compare_layer_count(DP_CanvasCompare *cc)
+{
+    DP_ASSERT(cc);
+    return DP_size_to_int(cc->layers.used);
+}
+
+static DP_LayerCompareEntry *layer_entry_at(DP_CanvasCompare *cc, int index)
+{
+    DP_ASSERT(cc);
+    DP_ASSERT(index >= 0);
+    DP_ASSERT(index < DP_canvas_compare_layer_count(cc));
+    return &DP_VECTOR_AT_TYPE(&cc->layers, DP_LayerCompareEntry,
+                              DP_int_to_size(index));
+}
+
+const DP_LayerCompare *DP_canvas_compare_layer_at(DP_CanvasCompare *cc,
+                                                  int index)
+{
+    return &layer_entry_at(cc, index)->lc;
+}
+
+bool DP_canvas_compare_layer_tile_changed(DP_CanvasCompare *cc, int index,
+                                          int tile_x, int tile_y)
+{
+    DP_LayerCompareEntry *lce = layer_entry_at(cc, index);
+    return lce->tile_changes && tile_x >= 0 && tile_x < cc->xtiles
+        && tile_y >= 0 && tile_y < cc->ytiles
+        && lce->tile_changes[tile_y * cc->xtiles + tile_x];
+}
+
+bool DP_canvas_compare_bounds(DP_CanvasCompare *cc, DP_Rect *out_bounds)
+{
+    DP_ASSERT(cc);
+    DP_ASSERT(out_bounds);
+    if (DP_rect_valid(cc->bounds)) {
+        *out_bounds = cc->bounds;
+        return true;
+    }
+    else {
+        return false;
+    }
+}
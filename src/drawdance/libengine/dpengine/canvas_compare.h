@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_CANVAS_COMPARE_H
+#define DPENGINE_CANVAS_COMPARE_H
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+typedef struct DP_CanvasState DP_CanvasState;
+
+
+#define DP_CANVAS_COMPARE_SIZE        (1u << 0)
+#define DP_CANVAS_COMPARE_BACKGROUND  (1u << 1)
+#define DP_CANVAS_COMPARE_ANNOTATIONS (1u << 2)
+#define DP_CANVAS_COMPARE_METADATA    (1u << 3)
+#define DP_CANVAS_COMPARE_TIMELINE    (1u << 4)
+#define DP_CANVAS_COMPARE_LAYERS      (1u << 5)
+
+#define DP_LAYER_COMPARE_ADDED   (1u << 0)
+#define DP_LAYER_COMPARE_REMOVED (1u << 1)
+#define DP_LAYER_COMPARE_PROPS   (1u << 2)
+#define DP_LAYER_COMPARE_CONTENT (1u << 3)
+
+typedef struct DP_CanvasCompare DP_CanvasCompare;
+
+typedef struct DP_LayerCompare {
+    int layer_id;
+    unsigned int flags;
+    int changed_tile_count;
+} DP_LayerCompare;
+
+
+// Compares two canvas states and records what changed between them. Since
+// unchanged parts of the canvas share the same refcounted objects, this only
+// has to look at the parts that actually differ. Layers are compared by id,
+// so moving a layer around is reported as a properties change on the moved
+// layer and its content counts towards the bounds, since it now composites
+// differently. Siblings that only shifted because of the move aren't reported.
+// Group contents are reported on the leaf layers inside of them.
+DP_CanvasCompare *DP_canvas_compare_new(DP_CanvasState *cs,
+                                        DP_CanvasState *prev);
+
+void DP_canvas_compare_free(DP_CanvasCompare *cc);
+
+// Combination of DP_CANVAS_COMPARE_* flags, zero if nothing changed.
+unsigned int DP_canvas_compare_flags(DP_CanvasCompare *cc);
+
+int DP_canvas_compare_xtiles(DP_CanvasCompare *cc);
+
+int DP_canvas_compare_ytiles(DP_CanvasCompare *cc);
+
+// Number of layers that were added, removed or changed in some way.
+int DP_canvas_compare_layer_count(DP_CanvasCompare *cc);
+
+const DP_LayerCompare *DP_canvas_compare_layer_at(DP_CanvasCompare *cc,
+                                                  int index);
+
+bool DP_canvas_compare_layer_tile_changed(DP_CanvasCompare *cc, int index,
+                                          int tile_x, int tile_y);
+
+// Pixel bounds of all changes that affect the canvas image, clamped to the
+// current canvas size. Returns false if no pixels changed.
+bool DP_canvas_compare_bounds(DP_CanvasCompare *cc, DP_Rect *out_bounds);
+
+
+#endif
@@ -20,9 +20,15 @@
  * License, version 3. See 3rdparty/licenses/drawpile/COPYING for details.
  */
 #include "canvas_history.h"
+#include "canvas_diff.h"
 #include "canvas_state.h"
+#include "layer_content.h"
+#include "layer_group.h"
+#include "layer_list.h"
+#include "memory_usage.h"
 #include "recorder.h"
 #include "snapshots.h"
+#include "tile.h"
 #include <dpcommon/atomic.h>
 #include <dpcommon/binary.h>
 #include <dpcommon/conversions.h>
@@ -48,6 +54,10 @@
 // some reasonable size to store plenty of messages for that purpose.
 #define REPLAY_BUFFER_CAPACITY 1024
 
+// Pixels added around affected areas when figuring out which tiles a partial
+// replay has to restore, in case antialiasing spills over the bounds.
+#define PARTIAL_REPLAY_MARGIN 2
+
 typedef enum DP_ForkAction {
     DP_FORK_ACTION_CONCURRENT,
     DP_FORK_ACTION_ALREADY_DONE,
@@ -57,6 +67,8 @@ typedef enum DP_ForkAction {
 struct DP_CanvasHistory {
     DP_Mutex *mutex;
     DP_CanvasState *current_state;
+    long long change_count;
+    long long saved_change_count;
     DP_UserCursors ucs;
     DP_EffectiveUserCursors eucs;
     int undo_depth_limit;
@@ -66,15 +78,22 @@ struct DP_CanvasHistory {
     DP_CanvasHistoryEntry *entries;
     DP_AffectedIndirectAreas aia;
     bool mark_command_done;
+    bool partial_replay;
+    DP_CanvasHistorySizeLimits size_limits;
     struct {
         bool starts_at_undo_point;
         int start;
         int fallbehind;
+        bool fell_behind;
         DP_Queue queue;
     } fork;
     struct {
         DP_CanvasHistorySavePointFn fn;
         void *user;
+        DP_CanvasHistorySavePointPolicy policy;
+        long long last_time_ms;
+        DP_CanvasDiff *diff;
+        bool pack;
     } save_point;
     struct {
         int used;
@@ -422,6 +441,19 @@ static bool is_undo_point_entry(DP_CanvasHistoryEntry *entry)
     return DP_message_type(entry->msg) == DP_MSG_UNDO_POINT;
 }
 
+static bool save_point_interval_enabled(DP_CanvasHistory *ch)
+{
+    const DP_CanvasHistorySavePointPolicy *policy = &ch->save_point.policy;
+    return policy->interval_ms > 0 && policy->get_time_fn;
+}
+
+static bool save_point_at_every_undo_point(DP_CanvasHistory *ch)
+{
+    const DP_CanvasHistorySavePointPolicy *policy = &ch->save_point.policy;
+    return policy->message_count <= 0 && policy->changed_tiles <= 0
+        && !save_point_interval_enabled(ch);
+}
+
 static bool is_valid_save_point_entry(DP_CanvasHistoryEntry *entry)
 {
     switch (entry->undo) {
@@ -498,6 +530,8 @@ DP_CanvasHistory *DP_canvas_history_new_inc(
     *ch = (DP_CanvasHistory){
         mutex,
         cs,
+        0,
+        0,
         {0},
         {0},
         DP_UNDO_DEPTH_DEFAULT,
@@ -507,8 +541,10 @@ DP_CanvasHistory *DP_canvas_history_new_inc(
         DP_malloc(entries_size),
         {0},
         true,
-        {false, 0, 0, DP_QUEUE_NULL},
-        {save_point_fn, save_point_user},
+        true,
+        DP_CANVAS_HISTORY_SIZE_LIMITS_DEFAULT,
+        {false, 0, 0, false, DP_QUEUE_NULL},
+        {save_point_fn, save_point_user, {0, 0, 0, NULL, NULL}, 0, NULL, true},
         {0, {0}},
         DP_ATOMIC_INIT(0),
         {want_dump, DP_strdup(dump_dir), NULL, 0, NULL},
@@ -564,6 +600,7 @@ void DP_canvas_history_free(DP_CanvasHistory *ch)
         truncate_history(ch, ch->used);
         DP_free(ch->entries);
         DP_canvas_state_decref(ch->current_state);
+        DP_canvas_diff_free(ch->save_point.diff);
         DP_mutex_free(ch->mutex);
         DP_free(ch->dump.buffer);
         DP_output_free(ch->dump.output);
@@ -629,11 +666,54 @@ DP_canvas_history_compare_and_get(DP_CanvasHistory *ch, DP_CanvasState *prev,
     return cs;
 }
 
+long long DP_canvas_history_change_count(DP_CanvasHistory *ch)
+{
+    DP_ASSERT(ch);
+    DP_Mutex *mutex = ch->mutex;
+    DP_MUTEX_MUST_LOCK(mutex);
+    long long change_count = ch->change_count;
+    DP_MUTEX_MUST_UNLOCK(mutex);
+    return change_count;
+}
+
+void DP_canvas_history_saved_at_set(DP_CanvasHistory *ch,
+                                    long long change_count)
+{
+    DP_ASSERT(ch);
+    DP_Mutex *mutex = ch->mutex;
+    DP_MUTEX_MUST_LOCK(mutex);
+    ch->saved_change_count = change_count;
+    DP_MUTEX_MUST_UNLOCK(mutex);
+}
+
+long long DP_canvas_history_saved_at(DP_CanvasHistory *ch)
+{
+    DP_ASSERT(ch);
+    DP_Mutex *mutex = ch->mutex;
+    DP_MUTEX_MUST_LOCK(mutex);
+    long long saved_change_count = ch->saved_change_count;
+    DP_MUTEX_MUST_UNLOCK(mutex);
+    return saved_change_count;
+}
+
+bool DP_canvas_history_dirty(DP_CanvasHistory *ch)
+{
+    DP_ASSERT(ch);
+    DP_Mutex *mutex = ch->mutex;
+    DP_MUTEX_MUST_LOCK(mutex);
+    bool dirty = ch->change_count != ch->saved_change_count;
+    DP_MUTEX_MUST_UNLOCK(mutex);
+    return dirty;
+}
+
 static void set_current_state_noinc(DP_CanvasHistory *ch, DP_CanvasState *next)
 {
     DP_CanvasState *current = ch->current_state;
     DP_Mutex *mutex = ch->mutex;
     DP_MUTEX_MUST_LOCK(mutex);
+    if (next != current) {
+        ++ch->change_count;
+    }
     ch->current_state = next;
     DP_MUTEX_MUST_UNLOCK(mutex);
     DP_canvas_state_decref(current);
@@ -645,6 +725,9 @@ static void set_current_state_with_cursors_noinc(DP_CanvasHistory *ch,
     DP_CanvasState *current = ch->current_state;
     DP_Mutex *mutex = ch->mutex;
     DP_MUTEX_MUST_LOCK(mutex);
+    if (next != current) {
+        ++ch->change_count;
+    }
     ch->current_state = next;
     DP_effective_user_cursors_apply(&ch->eucs, &ch->ucs);
     DP_MUTEX_MUST_UNLOCK(mutex);
@@ -658,6 +741,7 @@ static void reset_to_state_noinc(DP_CanvasHistory *ch, DP_CanvasState *cs,
     set_current_state_noinc(ch, cs);
     if (clear_fork) {
         clear_fork_entries(ch);
+        ch->fork.fell_behind = false;
         truncate_history(ch, ch->used);
     }
     else {
@@ -704,6 +788,8 @@ static bool is_draw_dabs_message_type(DP_MessageType type)
     case DP_MSG_DRAW_DABS_PIXEL:
     case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
     case DP_MSG_DRAW_DABS_MYPAINT:
+    case DP_MSG_DRAW_DABS_STAMP:
+    case DP_MSG_DRAW_DABS_SOFT:
         return true;
     default:
         return false;
@@ -814,6 +900,7 @@ static void replay_from_inc(DP_CanvasHistory *ch, DP_DrawContext *dc,
     DP_ASSERT(start_cs);
     DP_CanvasHistoryEntry *entries = ch->entries;
     DP_CanvasState *cs = DP_canvas_state_incref(start_cs);
+    bool every_undo_point = save_point_at_every_undo_point(ch);
 
     int used = ch->used;
     for (int i = start_index + 1; i < used; ++i) {
@@ -822,14 +909,17 @@ static void replay_from_inc(DP_CanvasHistory *ch, DP_DrawContext *dc,
         if (undo != DP_UNDO_GONE) {
             DP_Message *msg = entry->msg;
             DP_MessageType type = DP_message_type(msg);
-            // Update undo points even when they're undone so
-            // they can serve as a starting point for redos.
+            // Update undo points even when they're undone so they can serve
+            // as a starting point for redos. If save points are spread out,
+            // only the ones that already have a state get updated.
             if (type == DP_MSG_UNDO_POINT) {
-                if (ch->replay.used != 0) {
-                    cs = flush_replay_buffer(ch, cs, dc);
+                if (every_undo_point || entry->state) {
+                    if (ch->replay.used != 0) {
+                        cs = flush_replay_buffer(ch, cs, dc);
+                    }
+                    DP_canvas_state_decref_nullable(entry->state);
+                    entry->state = DP_canvas_state_incref(cs);
                 }
-                DP_canvas_state_decref_nullable(entry->state);
-                entry->state = DP_canvas_state_incref(cs);
             }
             else if (undo == DP_UNDO_DONE) {
                 cs = replay_drawing_command_dec(ch, cs, dc, msg, type);
@@ -862,6 +952,299 @@ static bool search_and_replay_from(DP_CanvasHistory *ch, DP_DrawContext *dc,
     }
 }
 
+// Messages that only ever change pixels within their affected area and only
+// read pixels from within it. Indirect strokes are left out, since they go
+// through sublayers and only land on the layer with the pen up.
+static bool is_partial_replay_message(DP_Message *msg)
+{
+    switch (DP_message_type(msg)) {
+    case DP_MSG_UNDO_POINT:
+    case DP_MSG_FILL_RECT:
+    case DP_MSG_PUT_IMAGE:
+        return true;
+    case DP_MSG_PUT_TILE:
+        return DP_msg_put_tile_sublayer(DP_msg_put_tile_cast(msg)) == 0;
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        return !DP_msg_draw_dabs_classic_indirect(
+            DP_msg_draw_dabs_classic_cast(msg));
+    case DP_MSG_DRAW_DABS_PIXEL:
+    case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+        return !DP_msg_draw_dabs_pixel_indirect(DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_STAMP:
+        return !DP_msg_draw_dabs_stamp_indirect(
+            DP_msg_draw_dabs_stamp_cast(msg));
+    case DP_MSG_DRAW_DABS_SOFT:
+        return !DP_msg_draw_dabs_soft_indirect(
+            DP_msg_draw_dabs_soft_cast(msg));
+    default:
+        return false;
+    }
+}
+
+// Range of tiles touched by the message, invalid if it doesn't touch any.
+static DP_Rect get_partial_replay_tiles(DP_Message *msg, int width,
+                                        int height)
+{
+    // None of the partial replay messages are indirect, so they don't need
+    // the indirect areas.
+    DP_AffectedArea aa = DP_affected_area_make(msg, NULL);
+    DP_Rect bounds;
+    if (DP_affected_area_bounds(&aa, width, height, &bounds)) {
+        return (DP_Rect){
+            DP_max_int(0, bounds.x1 - PARTIAL_REPLAY_MARGIN) / DP_TILE_SIZE,
+            DP_max_int(0, bounds.y1 - PARTIAL_REPLAY_MARGIN) / DP_TILE_SIZE,
+            DP_min_int(width - 1, bounds.x2 + PARTIAL_REPLAY_MARGIN)
+                / DP_TILE_SIZE,
+            DP_min_int(height - 1, bounds.y2 + PARTIAL_REPLAY_MARGIN)
+                / DP_TILE_SIZE,
+        };
+    }
+    else {
+        return (DP_Rect){0, 0, -1, -1};
+    }
+}
+
+static bool partial_replay_tiles_dirty(const bool *dirty, int xtiles,
+                                       DP_Rect tiles)
+{
+    for (int y = tiles.y1; y <= tiles.y2; ++y) {
+        for (int x = tiles.x1; x <= tiles.x2; ++x) {
+            if (dirty[y * xtiles + x]) {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+static bool partial_replay_tiles_mark(bool *dirty, int xtiles, DP_Rect tiles)
+{
+    bool marked = false;
+    for (int y = tiles.y1; y <= tiles.y2; ++y) {
+        for (int x = tiles.x1; x <= tiles.x2; ++x) {
+            bool *d = &dirty[y * xtiles + x];
+            if (!*d) {
+                *d = true;
+                marked = true;
+            }
+        }
+    }
+    return marked;
+}
+
+static bool content_tiles_differ(DP_LayerContent *lc, DP_LayerContent *from,
+                                 const bool *dirty, int xtiles, int total)
+{
+    for (int i = 0; i < total; ++i) {
+        if (dirty[i]) {
+            int x = i % xtiles;
+            int y = i / xtiles;
+            if (DP_layer_content_tile_at_noinc(lc, x, y)
+                != DP_layer_content_tile_at_noinc(from, x, y)) {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+static void restore_content_tiles(DP_TransientLayerContent *tlc,
+                                  DP_LayerContent *from, const bool *dirty,
+                                  int xtiles, int total)
+{
+    for (int i = 0; i < total; ++i) {
+        if (dirty[i]) {
+            DP_Tile *t =
+                DP_layer_content_tile_at_noinc(from, i % xtiles, i / xtiles);
+            DP_transient_layer_content_tile_set_noinc(
+                tlc, DP_tile_incref_nullable(t), i);
+        }
+    }
+}
+
+// Returns NULL if nothing in the list needed restoring.
+static DP_TransientLayerList *restore_list_tiles(DP_LayerList *ll,
+                                                 DP_LayerList *from,
+                                                 const bool *dirty, int xtiles,
+                                                 int total)
+{
+    if (ll == from) {
+        return NULL;
+    }
+
+    DP_TransientLayerList *tll = NULL;
+    int count = DP_layer_list_count(ll);
+    DP_ASSERT(DP_layer_list_count(from) == count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerListEntry *from_lle = DP_layer_list_at_noinc(from, i);
+        DP_ASSERT(DP_layer_list_entry_is_group(lle)
+                  == DP_layer_list_entry_is_group(from_lle));
+        if (DP_layer_list_entry_is_group(lle)) {
+            DP_TransientLayerList *children = restore_list_tiles(
+                DP_layer_group_children_noinc(
+                    DP_layer_list_entry_group_noinc(lle)),
+                DP_layer_group_children_noinc(
+                    DP_layer_list_entry_group_noinc(from_lle)),
+                dirty, xtiles, total);
+            if (children) {
+                if (!tll) {
+                    tll = DP_transient_layer_list_new(ll, 0);
+                }
+                DP_transient_layer_list_transient_group_at_with_children_noinc(
+                    tll, i, children);
+            }
+        }
+        else {
+            DP_LayerContent *from_lc =
+                DP_layer_list_entry_content_noinc(from_lle);
+            if (content_tiles_differ(DP_layer_list_entry_content_noinc(lle),
+                                     from_lc, dirty, xtiles, total)) {
+                if (!tll) {
+                    tll = DP_transient_layer_list_new(ll, 0);
+                }
+                restore_content_tiles(
+                    DP_transient_layer_list_transient_content_at_noinc(tll, i),
+                    from_lc, dirty, xtiles, total);
+            }
+        }
+    }
+    return tll;
+}
+
+// Takes the dirty tiles of every layer from the other state, which must have
+// the same layer structure.
+static DP_CanvasState *restore_tiles_dec(DP_CanvasState *cs,
+                                         DP_CanvasState *from,
+                                         const bool *dirty, int xtiles,
+                                         int total)
+{
+    DP_TransientLayerList *tll = restore_list_tiles(
+        DP_canvas_state_layers_noinc(cs), DP_canvas_state_layers_noinc(from),
+        dirty, xtiles, total);
+    if (tll) {
+        DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+        DP_canvas_state_decref(cs);
+        DP_transient_canvas_state_transient_layers_set_noinc(tcs, tll);
+        return DP_transient_canvas_state_persist(tcs);
+    }
+    else {
+        return cs;
+    }
+}
+
+// Undoing or redoing actions that only touched a small part of the canvas
+// doesn't need to replay everything since the last save point. Instead, the
+// tiles that the toggled actions touched, plus those of every action that
+// overlaps them in turn, get restored from the save point and only the actions
+// touching them are replayed. Returns false if the history contains anything
+// that this can't deal with, in which case a full replay is required.
+static bool partial_replay_from(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                                int target_index, unsigned int context_id)
+{
+    if (!ch->partial_replay || have_local_fork(ch)) {
+        return false;
+    }
+
+    int start_index = search_save_point_index(ch, target_index);
+    if (start_index < 0) {
+        return false;
+    }
+
+    DP_CanvasHistoryEntry *entries = ch->entries;
+    DP_CanvasState *start_cs = entries[start_index].state;
+    int width = DP_canvas_state_width(start_cs);
+    int height = DP_canvas_state_height(start_cs);
+    DP_TileCounts counts = DP_tile_counts_round(width, height);
+    int total = counts.x * counts.y;
+    bool every_undo_point = save_point_at_every_undo_point(ch);
+
+    int used = ch->used;
+    int window = used - start_index - 1;
+    DP_Rect *areas = DP_malloc(sizeof(*areas) * DP_int_to_size(window));
+    bool *dirty = DP_malloc_zeroed(sizeof(*dirty) * DP_int_to_size(total));
+    bool ok = true;
+
+    // Anything that the toggled entries touched must be replayed. The search
+    // only goes by user, which may include actions that were undone before,
+    // but those just end up as a few more tiles to replay.
+    for (int i = 0; i < window && ok; ++i) {
+        DP_CanvasHistoryEntry *entry = &entries[start_index + 1 + i];
+        DP_Message *msg = entry->msg;
+        if (entry->undo == DP_UNDO_GONE) {
+            areas[i] = (DP_Rect){0, 0, -1, -1};
+        }
+        else if (!is_partial_replay_message(msg)
+                 || (every_undo_point && is_undo_point_entry(entry)
+                     && !entry->state)) {
+            ok = false;
+        }
+        else {
+            DP_Rect tiles = get_partial_replay_tiles(msg, width, height);
+            areas[i] = tiles;
+            if (start_index + 1 + i >= target_index
+                && DP_message_context_id(msg) == context_id
+                && DP_rect_valid(tiles)) {
+                partial_replay_tiles_mark(dirty, counts.x, tiles);
+            }
+        }
+    }
+
+    // Actions overlapping those tiles end up differently, so their tiles need
+    // replaying too, until everything that needs replaying is covered.
+    bool changed = ok;
+    while (changed) {
+        changed = false;
+        for (int i = 0; i < window; ++i) {
+            DP_Rect tiles = areas[i];
+            if (entries[start_index + 1 + i].undo == DP_UNDO_DONE
+                && DP_rect_valid(tiles)
+                && partial_replay_tiles_dirty(dirty, counts.x, tiles)
+                && partial_replay_tiles_mark(dirty, counts.x, tiles)) {
+                changed = true;
+            }
+        }
+    }
+
+    if (ok) {
+        HISTORY_DEBUG("Partial replay from target %d, start %d", target_index,
+                      start_index);
+        DP_CanvasState *cs =
+            restore_tiles_dec(DP_canvas_state_incref(ch->current_state),
+                              start_cs, dirty, counts.x, total);
+        for (int i = 0; i < window; ++i) {
+            DP_CanvasHistoryEntry *entry = &entries[start_index + 1 + i];
+            DP_Undo undo = entry->undo;
+            if (undo == DP_UNDO_GONE) {
+                continue;
+            }
+            DP_Message *msg = entry->msg;
+            DP_MessageType type = DP_message_type(msg);
+            // Outside of the dirty tiles, save points are still accurate.
+            if (type == DP_MSG_UNDO_POINT) {
+                if (entry->state) {
+                    if (ch->replay.used != 0) {
+                        cs = flush_replay_buffer(ch, cs, dc);
+                    }
+                    entry->state = restore_tiles_dec(entry->state, cs, dirty,
+                                                     counts.x, total);
+                }
+            }
+            else if (undo == DP_UNDO_DONE && DP_rect_valid(areas[i])
+                     && partial_replay_tiles_dirty(dirty, counts.x,
+                                                   areas[i])) {
+                cs = replay_drawing_command_dec(ch, cs, dc, msg, type);
+            }
+        }
+        finish_replay(ch, cs, dc);
+        validate_history(ch, true);
+    }
+
+    DP_free(dirty);
+    DP_free(areas);
+    return ok;
+}
+
 void DP_canvas_history_soft_reset(DP_CanvasHistory *ch, DP_DrawContext *dc,
                                   unsigned int context_id,
                                   DP_CanvasHistorySoftResetFn fn, void *user)
@@ -893,6 +1276,64 @@ void DP_canvas_history_soft_reset(DP_CanvasHistory *ch, DP_DrawContext *dc,
     }
 }
 
+static void dump_fork_entry(void *element, void *user)
+{
+    DP_ForkEntry *fe = element;
+    dump_message(user, fe->msg, DP_DUMP_LOCAL_MESSAGE);
+}
+
+void DP_canvas_history_take_over(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                                 DP_CanvasHistory *other, int fork_skip)
+{
+    DP_ASSERT(ch);
+    DP_ASSERT(dc);
+    DP_ASSERT(other);
+    DP_ASSERT(ch != other);
+    DP_ASSERT(!have_local_fork(other));
+    DP_ASSERT(fork_skip >= 0);
+    HISTORY_DEBUG("Take over history");
+    dump_internal(ch, DP_DUMP_RESET);
+
+    for (int i = 0; i < fork_skip && have_local_fork(ch); ++i) {
+        DP_Message *msg = peek_fork_entry_message(ch);
+        shift_fork_entry_nodec(ch);
+        DP_message_decref(msg);
+    }
+    ch->fork.fallbehind = 0;
+    ch->fork.fell_behind = false;
+
+    // Swap the entries over so that the other history disposes of ours.
+    truncate_history_without_fork_check(ch, ch->used);
+    DP_CanvasHistoryEntry *entries = ch->entries;
+    int capacity = ch->capacity;
+    ch->entries = other->entries;
+    ch->capacity = other->capacity;
+    ch->used = other->used;
+    ch->offset = other->offset;
+    other->entries = entries;
+    other->capacity = capacity;
+    other->used = 0;
+    ch->aia = other->aia;
+    ch->mark_command_done = other->mark_command_done;
+    ch->undo_depth_limit = other->undo_depth_limit;
+
+    DP_CanvasState *cs = DP_canvas_state_incref(other->current_state);
+    dump_init(ch);
+    dump_snapshot(ch, cs);
+    dump_undo_depth_limit(ch, ch->undo_depth_limit);
+
+    // The current state only gets replaced once the fork is on top of it.
+    if (have_local_fork(ch)) {
+        DP_queue_each(&ch->fork.queue, sizeof(DP_ForkEntry), dump_fork_entry,
+                      ch);
+        finish_replay(ch, replay_fork_dec(ch, cs, dc), dc);
+    }
+    else {
+        set_current_state_noinc(ch, cs);
+    }
+    validate_history(ch, true);
+}
+
 int DP_canvas_history_undo_depth_limit(DP_CanvasHistory *ch)
 {
     DP_ASSERT(ch);
@@ -941,6 +1382,133 @@ static int find_save_point_index(DP_CanvasHistory *ch)
     DP_UNREACHABLE(); // The history can't be totally gone.
 }
 
+static int content_tile_total(DP_LayerContent *lc)
+{
+    DP_TileCounts counts = DP_tile_counts_round(DP_layer_content_width(lc),
+                                                DP_layer_content_height(lc));
+    return counts.x * counts.y;
+}
+
+// Returns NULL if none of the tiles were worth packing.
+static DP_Tile **pack_content_tiles(DP_LayerContent *lc)
+{
+    DP_Tile **packed = NULL;
+    int xtiles = DP_tile_count_round(DP_layer_content_width(lc));
+    int total = content_tile_total(lc);
+    for (int i = 0; i < total; ++i) {
+        DP_Tile *t =
+            DP_layer_content_tile_at_noinc(lc, i % xtiles, i / xtiles);
+        // Tiles referenced from elsewhere are still in use by a newer state,
+        // packing those would only add a compressed copy on top. Ones that
+        // were packed before may have been unpacked since, by undoing back to
+        // this save point, so with nothing else using them those pixels get
+        // dropped again.
+        DP_Tile *pt = NULL;
+        if (t && DP_tile_refcount(t) == 1) {
+            if (DP_tile_packed(t)) {
+                DP_tile_evict(t);
+            }
+            else {
+                pt = DP_tile_pack(t);
+            }
+        }
+        if (pt) {
+            if (!packed) {
+                packed = DP_malloc_zeroed(sizeof(*packed)
+                                          * DP_int_to_size(total));
+            }
+            packed[i] = pt;
+        }
+    }
+    return packed;
+}
+
+// Returns NULL if nothing in the list got packed. Creating the transient list
+// references each of its entries once more, so after that, entries that only
+// this list held onto have a refcount of two.
+static DP_TransientLayerList *pack_list_tiles(DP_LayerList *ll)
+{
+    DP_TransientLayerList *tll = NULL;
+    int count = DP_layer_list_count(ll);
+    for (int i = 0; i < count; ++i) {
+        int unshared = tll ? 2 : 1;
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        if (DP_layer_list_entry_is_group(lle)) {
+            DP_LayerGroup *lg = DP_layer_list_entry_group_noinc(lle);
+            DP_LayerList *children = DP_layer_group_children_noinc(lg);
+            DP_TransientLayerList *packed_children =
+                DP_layer_group_refcount(lg) == unshared
+                        && DP_layer_list_refcount(children) == 1
+                    ? pack_list_tiles(children)
+                    : NULL;
+            if (packed_children) {
+                if (!tll) {
+                    tll = DP_transient_layer_list_new(ll, 0);
+                }
+                DP_transient_layer_list_transient_group_at_with_children_noinc(
+                    tll, i, packed_children);
+            }
+        }
+        else {
+            DP_LayerContent *lc = DP_layer_list_entry_content_noinc(lle);
+            DP_Tile **packed = DP_layer_content_refcount(lc) == unshared
+                                 ? pack_content_tiles(lc)
+                                 : NULL;
+            if (packed) {
+                int total = content_tile_total(lc);
+                if (!tll) {
+                    tll = DP_transient_layer_list_new(ll, 0);
+                }
+                DP_TransientLayerContent *tlc =
+                    DP_transient_layer_list_transient_content_at_noinc(tll, i);
+                for (int j = 0; j < total; ++j) {
+                    if (packed[j]) {
+                        DP_transient_layer_content_tile_set_noinc(
+                            tlc, packed[j], j);
+                    }
+                }
+                DP_free(packed);
+            }
+        }
+    }
+    return tll;
+}
+
+// Save points older than the newest one are unlikely to be looked at again,
+// since that requires undoing past the newest. The tiles that only they hold
+// onto get packed, which unpack themselves again if they are needed after
+// all. Save points held elsewhere too, like by the reset snapshot queue, are
+// left alone, packing them would keep both versions around.
+static DP_CanvasState *pack_save_point_dec(DP_CanvasState *cs)
+{
+    DP_LayerList *ll = DP_canvas_state_layers_noinc(cs);
+    if (DP_canvas_state_refcount(cs) != 1 || DP_layer_list_refcount(ll) != 1) {
+        return cs;
+    }
+
+    DP_PERF_BEGIN(fn, "pack_save_point");
+    DP_TransientLayerList *tll = pack_list_tiles(ll);
+    DP_PERF_END(fn);
+    if (tll) {
+        DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+        DP_canvas_state_decref(cs);
+        DP_transient_canvas_state_transient_layers_set_noinc(tcs, tll);
+        return DP_transient_canvas_state_persist(tcs);
+    }
+    else {
+        return cs;
+    }
+}
+
+static void pack_previous_save_point(DP_CanvasHistory *ch, int index)
+{
+    int prev_index = search_save_point_index(ch, index - 1);
+    if (prev_index >= 0) {
+        DP_CanvasHistoryEntry *entry = &ch->entries[prev_index];
+        entry->state = pack_save_point_dec(entry->state);
+    }
+}
+
 static void make_save_point(DP_CanvasHistory *ch, int index,
                             bool snapshot_requested)
 {
@@ -958,13 +1526,64 @@ static void make_save_point(DP_CanvasHistory *ch, int index,
         DP_CanvasState *cs = ch->current_state;
         entry->state = DP_canvas_state_incref(cs);
         call_save_point_fn(ch, cs, snapshot_requested);
+        if (ch->save_point.pack) {
+            pack_previous_save_point(ch, index);
+        }
+    }
+}
+
+static long long get_save_point_time(DP_CanvasHistory *ch)
+{
+    if (save_point_interval_enabled(ch)) {
+        const DP_CanvasHistorySavePointPolicy *policy = &ch->save_point.policy;
+        return policy->get_time_fn(policy->get_time_user);
+    }
+    else {
+        return 0;
     }
 }
 
+void DP_canvas_history_save_point_policy_set(
+    DP_CanvasHistory *ch, const DP_CanvasHistorySavePointPolicy *policy)
+{
+    DP_ASSERT(ch);
+    DP_ASSERT(policy);
+    ch->save_point.policy = *policy;
+    ch->save_point.last_time_ms = get_save_point_time(ch);
+}
+
+DP_CanvasHistorySizeLimits DP_canvas_history_size_limits(DP_CanvasHistory *ch)
+{
+    DP_ASSERT(ch);
+    return ch->size_limits;
+}
+
+void DP_canvas_history_size_limits_set(
+    DP_CanvasHistory *ch, const DP_CanvasHistorySizeLimits *limits)
+{
+    DP_ASSERT(ch);
+    DP_ASSERT(limits);
+    ch->size_limits = *limits;
+}
+
+void DP_canvas_history_partial_replay_set(DP_CanvasHistory *ch, bool enabled)
+{
+    DP_ASSERT(ch);
+    ch->partial_replay = enabled;
+}
+
+void DP_canvas_history_save_point_packing_set(DP_CanvasHistory *ch,
+                                              bool enabled)
+{
+    DP_ASSERT(ch);
+    ch->save_point.pack = enabled;
+}
+
 bool DP_canvas_history_save_point_make(DP_CanvasHistory *ch)
 {
     if (!have_local_fork(ch)) {
         make_save_point(ch, find_save_point_index(ch), true);
+        ch->save_point.last_time_ms = get_save_point_time(ch);
         return true;
     }
     else {
@@ -973,6 +1592,24 @@ bool DP_canvas_history_save_point_make(DP_CanvasHistory *ch)
     }
 }
 
+void DP_canvas_history_memory_usage_add(DP_CanvasHistory *ch,
+                                        DP_MemoryUsage *mu)
+{
+    DP_ASSERT(ch);
+    DP_ASSERT(mu);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_memory_usage_add_canvas_state(mu, cs);
+    DP_canvas_state_decref(cs);
+
+    int used = ch->used;
+    for (int i = 0; i < used; ++i) {
+        DP_CanvasState *entry_cs = ch->entries[i].state;
+        if (entry_cs) {
+            DP_memory_usage_add_canvas_state(mu, entry_cs);
+        }
+    }
+}
+
 static bool handle_internal(DP_CanvasHistory *ch, DP_MsgInternal *mi)
 {
     DP_MsgInternalType internal_type = DP_msg_internal_type(mi);
@@ -1082,12 +1719,64 @@ static void truncate_unreachable(DP_CanvasHistory *ch, int i, int depth)
     }
 }
 
+static void count_changed_tile(void *user, DP_UNUSED int tile_index)
+{
+    ++*(int *)user;
+}
+
+static int count_changed_tiles(DP_CanvasHistory *ch, DP_CanvasState *prev)
+{
+    DP_CanvasDiff *diff = ch->save_point.diff;
+    int count = 0;
+    if (!diff) {
+        // A fresh diff contains garbage, diffing against nothing and then
+        // resetting it clears that out.
+        diff = DP_canvas_diff_new();
+        ch->save_point.diff = diff;
+        DP_canvas_state_diff(prev, NULL, diff);
+        DP_canvas_diff_each_index_reset(diff, count_changed_tile, &count);
+        count = 0;
+    }
+    DP_canvas_state_diff(ch->current_state, prev, diff);
+    DP_canvas_diff_each_index_reset(diff, count_changed_tile, &count);
+    return count;
+}
+
+static bool should_make_save_point(DP_CanvasHistory *ch, int index)
+{
+    if (save_point_at_every_undo_point(ch)) {
+        return true;
+    }
+
+    int prev_index = search_save_point_index(ch, index - 1);
+    if (prev_index < 0) {
+        return true;
+    }
+
+    const DP_CanvasHistorySavePointPolicy *policy = &ch->save_point.policy;
+    if (policy->message_count > 0
+        && index - prev_index >= policy->message_count) {
+        return true;
+    }
+
+    if (save_point_interval_enabled(ch)
+        && get_save_point_time(ch) - ch->save_point.last_time_ms
+               >= policy->interval_ms) {
+        return true;
+    }
+
+    return policy->changed_tiles > 0
+        && count_changed_tiles(ch, ch->entries[prev_index].state)
+               >= policy->changed_tiles;
+}
+
 static void handle_undo_point(DP_CanvasHistory *ch, int index)
 {
     // Don't make save points while a local fork is present, since the local
     // state may be incongruent with what the server thinks is happening.
-    if (!have_local_fork(ch)) {
+    if (!have_local_fork(ch) && should_make_save_point(ch, index)) {
         make_save_point(ch, index, false);
+        ch->save_point.last_time_ms = get_save_point_time(ch);
     }
     int depth;
     int i = mark_undone_actions_gone(ch, index, &depth);
@@ -1095,6 +1784,82 @@ static void handle_undo_point(DP_CanvasHistory *ch, int index)
 }
 
 
+static size_t add_save_point_usage(DP_MemoryUsage *mu, DP_CanvasState *cs)
+{
+    size_t before = DP_memory_usage_totals(mu).unique_bytes;
+    DP_memory_usage_add_canvas_state(mu, cs);
+    return DP_memory_usage_totals(mu).unique_bytes - before;
+}
+
+void DP_canvas_history_usage_get(DP_CanvasHistory *ch,
+                                 DP_CanvasHistoryUsage *out_usage)
+{
+    DP_ASSERT(ch);
+    DP_ASSERT(out_usage);
+    *out_usage = (DP_CanvasHistoryUsage){0};
+
+    DP_MemoryUsage *mu = DP_memory_usage_new();
+    DP_memory_usage_add_canvas_state(mu, ch->current_state);
+    for (int i = ch->used - 1; i >= 0; --i) {
+        DP_CanvasHistoryEntry *entry = &ch->entries[i];
+        size_t length = DP_message_length(entry->msg);
+        unsigned int context_id = DP_message_context_id(entry->msg);
+        DP_ASSERT(context_id < DP_CANVAS_HISTORY_USAGE_USER_COUNT);
+        DP_CanvasHistoryUserUsage *uu = &out_usage->users[context_id];
+        ++uu->message_count;
+        uu->message_bytes += length;
+        ++out_usage->message_count;
+        out_usage->message_bytes += length;
+        if (entry->state) {
+            ++out_usage->save_point_count;
+            out_usage->save_point_bytes +=
+                add_save_point_usage(mu, entry->state);
+        }
+    }
+    DP_memory_usage_free(mu);
+}
+
+size_t DP_canvas_history_trim(DP_CanvasHistory *ch, size_t max_bytes)
+{
+    DP_ASSERT(ch);
+    truncate_unreachable(ch, ch->used - 1, 0);
+
+    // The first entry holds the save point at or before the furthest
+    // reachable undo point, it has to stay around. What dropping any other
+    // save point frees depends on which ones are left. They get dropped oldest
+    // first, so measure each against the first one and the newer ones.
+    DP_ASSERT(ch->entries[0].state);
+    int used = ch->used;
+    size_t *save_point_bytes = DP_malloc(sizeof(*save_point_bytes)
+                                         * DP_int_to_size(used));
+    DP_MemoryUsage *mu = DP_memory_usage_new();
+    DP_memory_usage_add_canvas_state(mu, ch->current_state);
+    size_t total = add_save_point_usage(mu, ch->entries[0].state)
+                 + DP_message_length(ch->entries[0].msg);
+    for (int i = used - 1; i > 0; --i) {
+        DP_CanvasHistoryEntry *entry = &ch->entries[i];
+        DP_CanvasState *cs = entry->state;
+        save_point_bytes[i] = cs ? add_save_point_usage(mu, cs) : 0;
+        total += DP_message_length(entry->msg) + save_point_bytes[i];
+    }
+    DP_memory_usage_free(mu);
+
+    for (int i = 1; i < used && total > max_bytes; ++i) {
+        DP_CanvasHistoryEntry *entry = &ch->entries[i];
+        if (entry->state) {
+            HISTORY_DEBUG("Trim save point at %d", i);
+            DP_canvas_state_decref(entry->state);
+            entry->state = NULL;
+            total -= save_point_bytes[i];
+        }
+    }
+    DP_free(save_point_bytes);
+
+    validate_history(ch, true);
+    return total;
+}
+
+
 void DP_canvas_history_cleanup(DP_CanvasHistory *ch, DP_DrawContext *dc,
                                void (*push_message)(void *, DP_Message *),
                                void *user)
@@ -1117,6 +1882,20 @@ void DP_canvas_history_cleanup(DP_CanvasHistory *ch, DP_DrawContext *dc,
 }
 
 
+int DP_canvas_history_local_fork_count(DP_CanvasHistory *ch)
+{
+    DP_ASSERT(ch);
+    return DP_size_to_int(ch->fork.queue.used);
+}
+
+bool DP_canvas_history_local_fork_fell_behind_reset(DP_CanvasHistory *ch)
+{
+    DP_ASSERT(ch);
+    bool fell_behind = ch->fork.fell_behind;
+    ch->fork.fell_behind = false;
+    return fell_behind;
+}
+
 bool DP_canvas_history_local_fork_clear(DP_CanvasHistory *ch,
                                         DP_DrawContext *dc)
 {
@@ -1201,7 +1980,8 @@ static bool undo(DP_CanvasHistory *ch, DP_DrawContext *dc,
     }
     else {
         mark_entries_undone(ch, context_id, undo_start);
-        return search_and_replay_from(ch, dc, undo_start, true);
+        return partial_replay_from(ch, dc, undo_start, context_id)
+            || search_and_replay_from(ch, dc, undo_start, true);
     }
 }
 
@@ -1211,6 +1991,7 @@ static int find_oldest_redo_point(DP_CanvasHistory *ch, unsigned int context_id,
 {
     DP_CanvasHistoryEntry *entries = ch->entries;
     int redo_start = -1;
+    int redo_depth = 0;
     int depth = 0;
     int undo_depth_limit = ch->undo_depth_limit;
     for (int i = ch->used - 1; i >= 0 && depth <= undo_depth_limit; --i) {
@@ -1221,6 +2002,7 @@ static int find_oldest_redo_point(DP_CanvasHistory *ch, unsigned int context_id,
                 DP_Undo undo = entry->undo;
                 if (undo == DP_UNDO_UNDONE) {
                     redo_start = i;
+                    redo_depth = depth;
                 }
                 else if (undo == DP_UNDO_DONE) {
                     break;
@@ -1228,7 +2010,10 @@ static int find_oldest_redo_point(DP_CanvasHistory *ch, unsigned int context_id,
             }
         }
     }
-    *out_depth = depth;
+    // The search keeps going past the redo point to find where the user's
+    // done actions begin, which may well lie beyond the limit. That doesn't
+    // matter, only the depth of the redo point itself does.
+    *out_depth = redo_start < 0 ? depth : redo_depth;
     return redo_start;
 }
 
@@ -1270,7 +2055,8 @@ static bool redo(DP_CanvasHistory *ch, DP_DrawContext *dc,
     }
     else {
         mark_entries_redone(ch, context_id, redo_start);
-        return search_and_replay_from(ch, dc, redo_start, true);
+        return partial_replay_from(ch, dc, redo_start, context_id)
+            || search_and_replay_from(ch, dc, redo_start, true);
     }
 }
 
@@ -1330,7 +2116,10 @@ static void clear_fork_fallbehind(DP_CanvasHistory *ch)
     int index = ch->fork.start - ch->offset;
     DP_ASSERT(index >= 0 && index < ch->used);
     DP_CanvasHistoryEntry *entry = &ch->entries[index];
-    if (!ch->fork.starts_at_undo_point && !is_undo_point_entry(entry)) {
+    // If the history got truncated up to the fork start, its state is the
+    // oldest one left. Since save points may be spread out, it has to stay.
+    if (!ch->fork.starts_at_undo_point && !is_undo_point_entry(entry)
+        && index != 0) {
         DP_canvas_state_decref_nullable(entry->state);
         entry->state = NULL;
     }
@@ -1379,6 +2168,7 @@ static DP_ForkAction reconcile_remote_command(DP_CanvasHistory *ch,
         DP_warn("Rollback at %d: fork fallbehind %d >= max fallbehind %d",
                 ch->offset + ch->used, ch->fork.fallbehind, MAX_FALLBEHIND);
         ch->fork.fallbehind = 0;
+        ch->fork.fell_behind = true;
         clear_fork_entries(ch);
         return DP_FORK_ACTION_ROLLBACK;
     }
@@ -1427,6 +2217,92 @@ static bool handle_remote_command(DP_CanvasHistory *ch, DP_DrawContext *dc,
     }
 }
 
+static bool check_canvas_resize(DP_CanvasHistory *ch, DP_MsgCanvasResize *mcr)
+{
+    const DP_CanvasHistorySizeLimits *limits = &ch->size_limits;
+    long long width = (long long)DP_canvas_state_width(ch->current_state)
+                    + (long long)DP_msg_canvas_resize_left(mcr)
+                    + (long long)DP_msg_canvas_resize_right(mcr);
+    long long height = (long long)DP_canvas_state_height(ch->current_state)
+                     + (long long)DP_msg_canvas_resize_top(mcr)
+                     + (long long)DP_msg_canvas_resize_bottom(mcr);
+    if (width < limits->min_width || height < limits->min_height
+        || width > limits->max_width || height > limits->max_height) {
+        DP_error_set("Canvas resize to %lldx%lld outside of limits %dx%d to "
+                     "%dx%d",
+                     width, height, limits->min_width, limits->min_height,
+                     limits->max_width, limits->max_height);
+        return false;
+    }
+    else if (width * height > limits->max_pixels) {
+        DP_error_set("Canvas resize to %lldx%lld exceeds %lld pixels", width,
+                     height, limits->max_pixels);
+        return false;
+    }
+    else {
+        return true;
+    }
+}
+
+static bool check_put_image(DP_CanvasHistory *ch, DP_MsgPutImage *mpi)
+{
+    const DP_CanvasHistorySizeLimits *limits = &ch->size_limits;
+    long long x = DP_msg_put_image_x(mpi);
+    long long y = DP_msg_put_image_y(mpi);
+    long long w = DP_msg_put_image_w(mpi);
+    long long h = DP_msg_put_image_h(mpi);
+    int width = DP_canvas_state_width(ch->current_state);
+    int height = DP_canvas_state_height(ch->current_state);
+    if (x >= width || y >= height) {
+        DP_error_set("Put image at %lld,%lld outside of %dx%d canvas", x, y,
+                     width, height);
+        return false;
+    }
+    else if (w > limits->max_width || h > limits->max_height
+             || w * h > limits->max_pixels) {
+        DP_error_set("Put image of %lldx%lld exceeds canvas size limits", w,
+                     h);
+        return false;
+    }
+    else {
+        return true;
+    }
+}
+
+static bool check_put_tile(DP_CanvasHistory *ch, DP_MsgPutTile *mpt)
+{
+    int col = DP_msg_put_tile_col(mpt);
+    int row = DP_msg_put_tile_row(mpt);
+    DP_CanvasState *cs = ch->current_state;
+    DP_TileCounts tile_counts = DP_tile_counts_round(
+        DP_canvas_state_width(cs), DP_canvas_state_height(cs));
+    if (col >= tile_counts.x || row >= tile_counts.y) {
+        DP_error_set("Put tile at %d,%d outside of %dx%d tiles", col, row,
+                     tile_counts.x, tile_counts.y);
+        return false;
+    }
+    else {
+        return true;
+    }
+}
+
+// Rejects messages that would make the canvas too big or write outside of it,
+// before they end up in the history or the local fork.
+static bool check_size_limits(DP_CanvasHistory *ch, DP_Message *msg,
+                              DP_MessageType type)
+{
+    switch (type) {
+    case DP_MSG_CANVAS_RESIZE:
+        return check_canvas_resize(ch, DP_message_internal(msg));
+    case DP_MSG_PUT_IMAGE:
+        return check_put_image(ch, DP_message_internal(msg));
+    case DP_MSG_PUT_TILE:
+        return check_put_tile(ch, DP_message_internal(msg));
+    default:
+        return true;
+    }
+}
+
 static bool handle_remote_message(DP_CanvasHistory *ch, DP_DrawContext *dc,
                                   DP_Message *msg, DP_MessageType type,
                                   bool local_drawing_in_progress)
@@ -1477,6 +2353,10 @@ bool DP_canvas_history_handle(DP_CanvasHistory *ch, DP_DrawContext *dc,
                      : DP_DUMP_REMOTE_MESSAGE);
 
     DP_MessageType type = DP_message_type(msg);
+    if (!check_size_limits(ch, msg, type)) {
+        return false;
+    }
+
     DP_PERF_BEGIN_DETAIL(fn, "handle", "type=%d,local_drawing=%d", (int)type,
                          local_drawing_in_progress);
     bool ok =
@@ -1498,6 +2378,10 @@ bool DP_canvas_history_handle_local(DP_CanvasHistory *ch, DP_DrawContext *dc,
     dump_message(ch, msg, DP_DUMP_LOCAL_MESSAGE);
 
     DP_MessageType type = DP_message_type(msg);
+    if (!check_size_limits(ch, msg, type)) {
+        return false;
+    }
+
     DP_PERF_BEGIN_DETAIL(fn, "handle_local", "type=%d", (int)type);
 
     if (!have_local_fork(ch)) {
@@ -1673,6 +2557,43 @@ bool DP_canvas_history_reset_image_new(
 }
 
 
+struct DP_CanvasHistoryCompactParams {
+    unsigned int context_id;
+    void (*push_message)(void *, DP_Message *);
+    void *user;
+};
+
+static bool accept_compact_state(void *user, DP_CanvasState *cs)
+{
+    struct DP_CanvasHistoryCompactParams *params = user;
+    DP_reset_image_build(cs, params->context_id, params->push_message,
+                         params->user);
+    return true;
+}
+
+static bool accept_compact_message(void *user, DP_Message *msg)
+{
+    struct DP_CanvasHistoryCompactParams *params = user;
+    params->push_message(params->user, msg);
+    return true;
+}
+
+void DP_canvas_history_compact(DP_CanvasHistory *ch, unsigned int context_id,
+                               void (*push_message)(void *, DP_Message *),
+                               void *user)
+{
+    DP_ASSERT(ch);
+    DP_ASSERT(push_message);
+    HISTORY_DEBUG("Compact history");
+    truncate_unreachable(ch, ch->used - 1, 0);
+    validate_history(ch, true);
+    struct DP_CanvasHistoryCompactParams params = {context_id, push_message,
+                                                   user};
+    DP_canvas_history_reset_image_new(ch, accept_compact_state,
+                                      accept_compact_message, &params);
+}
+
+
 struct DP_CanvasHistoryRecorderParams {
     DP_CanvasHistory *ch;
     DP_RecorderType type;
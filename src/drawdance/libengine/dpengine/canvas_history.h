@@ -28,6 +28,7 @@
 #include <dpcommon/common.h>
 
 typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_MemoryUsage DP_MemoryUsage;
 typedef struct DP_Message DP_Message;
 typedef struct json_value_t JSON_Value;
 
@@ -35,8 +36,14 @@ typedef struct json_value_t JSON_Value;
 #define DP_CANVAS_HISTORY_UNDO_DEPTH_MIN 3
 #define DP_CANVAS_HISTORY_UNDO_DEPTH_MAX 255
 
+#define DP_CANVAS_HISTORY_SIZE_LIMITS_DEFAULT \
+    ((DP_CanvasHistorySizeLimits){1, 1, INT16_MAX, INT16_MAX, \
+                                  (long long)INT16_MAX * (long long)INT16_MAX})
+
 #define DP_USER_CURSOR_COUNT 256
 
+#define DP_CANVAS_HISTORY_USAGE_USER_COUNT 256
+
 typedef struct DP_CanvasHistory DP_CanvasHistory;
 
 typedef struct DP_CanvasHistorySnapshot DP_CanvasHistorySnapshot;
@@ -53,6 +60,22 @@ typedef struct DP_CanvasHistoryEntry {
     DP_CanvasState *state;
 } DP_CanvasHistoryEntry;
 
+typedef struct DP_CanvasHistoryUserUsage {
+    int message_count;
+    size_t message_bytes;
+} DP_CanvasHistoryUserUsage;
+
+// Memory held by the history on top of the current canvas state. Save point
+// bytes only count tiles that aren't shared with the current state or with a
+// newer save point, so they're what dropping all save points would free.
+typedef struct DP_CanvasHistoryUsage {
+    int message_count;
+    size_t message_bytes;
+    int save_point_count;
+    size_t save_point_bytes;
+    DP_CanvasHistoryUserUsage users[DP_CANVAS_HISTORY_USAGE_USER_COUNT];
+} DP_CanvasHistoryUsage;
+
 typedef struct DP_ForkEntry {
     DP_Message *msg;
     DP_AffectedArea aa;
@@ -67,6 +90,31 @@ typedef bool (*DP_CanvasHistoryAcceptResetStateFn)(void *user,
 typedef bool (*DP_CanvasHistoryAcceptResetMessageFn)(void *user,
                                                      DP_Message *msg);
 
+// Decides which undo points get a save point. If all thresholds are zero, which
+// is the default, every undo point gets one. Otherwise an undo point only gets
+// one when any of the thresholds has been reached since the previous save
+// point: a number of history entries, an elapsed time according to the given
+// time function or a number of changed canvas tiles. Fewer save points use less
+// memory, but undos have to replay more messages.
+typedef struct DP_CanvasHistorySavePointPolicy {
+    int message_count;
+    long long interval_ms;
+    int changed_tiles;
+    DP_RecorderGetTimeMsFn get_time_fn;
+    void *get_time_user;
+} DP_CanvasHistorySavePointPolicy;
+
+// Bounds for the canvas size. Resizes that end up outside of them are rejected,
+// as are put images and put tiles that don't start inside the canvas, and put
+// images bigger than the largest canvas allowed.
+typedef struct DP_CanvasHistorySizeLimits {
+    int min_width;
+    int min_height;
+    int max_width;
+    int max_height;
+    long long max_pixels;
+} DP_CanvasHistorySizeLimits;
+
 typedef enum DP_DumpType {
     DP_DUMP_REMOTE_MESSAGE,
     DP_DUMP_REMOTE_MESSAGE_LOCAL_DRAWING_IN_PROGRESS,
@@ -104,6 +152,21 @@ DP_CanvasState *
 DP_canvas_history_compare_and_get(DP_CanvasHistory *ch, DP_CanvasState *prev,
                                   DP_UserCursorBuffer *out_user_cursors);
 
+// Bumped every time the canvas state changes, be it from drawing, undo or a
+// reset. View-only changes don't go through the history, so they don't count.
+// Safe to call from any thread, like the saved and dirty functions below.
+long long DP_canvas_history_change_count(DP_CanvasHistory *ch);
+
+// Records that the canvas was saved when the change count was at the given
+// value, so it's no longer dirty until the next change.
+void DP_canvas_history_saved_at_set(DP_CanvasHistory *ch,
+                                    long long change_count);
+
+long long DP_canvas_history_saved_at(DP_CanvasHistory *ch);
+
+// Whether the change count moved away from where it was last saved at.
+bool DP_canvas_history_dirty(DP_CanvasHistory *ch);
+
 void DP_canvas_history_reset(DP_CanvasHistory *ch);
 
 void DP_canvas_history_reset_to_state_noinc(DP_CanvasHistory *ch,
@@ -113,14 +176,63 @@ void DP_canvas_history_soft_reset(DP_CanvasHistory *ch, DP_DrawContext *dc,
                                   unsigned int context_id,
                                   DP_CanvasHistorySoftResetFn fn, void *user);
 
+// Replaces this history's entries and canvas with those of the other one, which
+// is left empty and should be freed afterwards. The first fork_skip entries of
+// the local fork are thrown away, the rest is kept and replayed on top. The
+// canvas is only swapped once that's done, so nothing in between is visible.
+void DP_canvas_history_take_over(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                                 DP_CanvasHistory *other, int fork_skip);
+
 int DP_canvas_history_undo_depth_limit(DP_CanvasHistory *ch);
 
 void DP_canvas_history_undo_depth_limit_set(DP_CanvasHistory *ch,
                                             DP_DrawContext *dc,
                                             int undo_depth_limit);
 
+void DP_canvas_history_save_point_policy_set(
+    DP_CanvasHistory *ch, const DP_CanvasHistorySavePointPolicy *policy);
+
+DP_CanvasHistorySizeLimits
+DP_canvas_history_size_limits(DP_CanvasHistory *ch);
+
+// Sets the canvas size limits, meant for session setup. Messages that violate
+// them are rejected before they make it into the history, so changing them
+// doesn't affect replaying what's already there.
+void DP_canvas_history_size_limits_set(
+    DP_CanvasHistory *ch, const DP_CanvasHistorySizeLimits *limits);
+
+// Undos and redos of actions that only touch pixels replay just the tiles they
+// affect. Enabled by default, turning it off always replays everything since
+// the last save point instead, which should give the exact same result.
+void DP_canvas_history_partial_replay_set(DP_CanvasHistory *ch, bool enabled);
+
+// When a new save point is made, the tiles that only the previous one holds
+// onto get packed, which compresses them until something looks at them again.
+// Enabled by default, turning it off keeps all save points fully unpacked.
+void DP_canvas_history_save_point_packing_set(DP_CanvasHistory *ch,
+                                              bool enabled);
+
+// Explicitly requests a save point at the newest history entry, regardless of
+// the save point policy. Fails if a local fork is present.
 bool DP_canvas_history_save_point_make(DP_CanvasHistory *ch);
 
+// Adds the current state and all save points to the given memory usage. Tiles
+// shared between them are only counted once. Must be called from the thread
+// that handles messages, since it looks at the history entries.
+void DP_canvas_history_memory_usage_add(DP_CanvasHistory *ch,
+                                        DP_MemoryUsage *mu);
+
+// Fills in the memory used by history messages and save points, with messages
+// broken down by user. Must be called from the thread that handles messages.
+void DP_canvas_history_usage_get(DP_CanvasHistory *ch,
+                                 DP_CanvasHistoryUsage *out_usage);
+
+// Frees history memory until message and save point bytes are at most the
+// given amount or nothing more can go. Messages beyond the undo depth get
+// dropped first, then save points from oldest to newest, except for the one
+// that undos within the depth limit replay from. Returns the bytes left.
+size_t DP_canvas_history_trim(DP_CanvasHistory *ch, size_t max_bytes);
+
 // Cleans up after disconnecting from a remote session: the local fork is merged
 // into the mainline history and all sublayers are merged into their parents.
 // The messages are appended to the remote queue so they can be recorded.
@@ -128,6 +240,16 @@ void DP_canvas_history_cleanup(DP_CanvasHistory *ch, DP_DrawContext *dc,
                                void (*push_message)(void *, DP_Message *),
                                void *user);
 
+// Local messages that have been applied, but not yet echoed back by the server.
+int DP_canvas_history_local_fork_count(DP_CanvasHistory *ch);
+
+// Whether the local fork got thrown away because too many remote messages came
+// in without any of them acknowledging it, which means the local state can't
+// be trusted to match the server anymore and a full reset is needed. Clears
+// the flag, as does resetting the history.
+bool DP_canvas_history_local_fork_fell_behind_reset(DP_CanvasHistory *ch);
+
+// Throws away the local fork and replays the history without it.
 bool DP_canvas_history_local_fork_clear(DP_CanvasHistory *ch,
                                         DP_DrawContext *dc);
 
@@ -149,6 +271,13 @@ bool DP_canvas_history_reset_image_new(
     DP_CanvasHistory *ch, DP_CanvasHistoryAcceptResetStateFn accept_state,
     DP_CanvasHistoryAcceptResetMessageFn accept_message, void *user);
 
+// Squashes the history into a reset image: everything before the first state
+// that undo can still reach is dropped and that state is pushed as the messages
+// that rebuild it, followed by the still undoable tail of the history.
+void DP_canvas_history_compact(DP_CanvasHistory *ch, unsigned int context_id,
+                               void (*push_message)(void *, DP_Message *),
+                               void *user);
+
 // May return NULL if something goes wrong. Takes ownership of the output and
 // header, so no matter the return value, the caller must not free it.
 DP_Recorder *DP_canvas_history_recorder_new(
@@ -24,9 +24,12 @@
 #include "annotation_list.h"
 #include "brush.h"
 #include "canvas_diff.h"
+#include "checksum.h"
 #include "compress.h"
 #include "document_metadata.h"
 #include "draw_context.h"
+#include "filter.h"
+#include "gradient.h"
 #include "image.h"
 #include "key_frame.h"
 #include "layer_content.h"
@@ -37,6 +40,7 @@
 #include "layer_routes.h"
 #include "ops.h"
 #include "paint.h"
+#include "stamp_mask.h"
 #include "tile.h"
 #include "tile_iterator.h"
 #include "timeline.h"
@@ -47,6 +51,9 @@
 #include <dpcommon/conversions.h>
 #include <dpcommon/geom.h>
 #include <dpcommon/perf.h>
+#include <dpcommon/threading.h>
+#include <dpcommon/worker.h>
+#include <dpmsg/acl.h>
 #include <dpmsg/blend_mode.h>
 #include <dpmsg/message.h>
 #include <limits.h>
@@ -380,11 +387,14 @@ static DP_CanvasState *handle_layer_attr(DP_CanvasState *cs,
     unsigned int flags = DP_msg_layer_attributes_flags(mla);
     bool censored = flags & DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR;
     bool isolated = flags & DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED;
+    bool alpha_lock = flags & DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK;
+    bool fixed = flags & DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED;
 
     return DP_ops_layer_attributes(
         cs, layer_id, DP_msg_layer_attributes_sublayer(mla),
         DP_channel8_to_15(DP_msg_layer_attributes_opacity(mla)),
-        DP_msg_layer_attributes_blend(mla), censored, isolated);
+        DP_msg_layer_attributes_blend(mla), censored, isolated, alpha_lock,
+        fixed);
 }
 
 
@@ -419,6 +429,24 @@ static DP_CanvasState *handle_layer_retitle(DP_CanvasState *cs,
     return DP_ops_layer_retitle(cs, layer_id, title, title_length);
 }
 
+static DP_CanvasState *handle_layer_metadata(DP_CanvasState *cs,
+                                             DP_MsgLayerMetadata *mlm)
+{
+    int layer_id = DP_msg_layer_metadata_id(mlm);
+    if (layer_id == 0) {
+        DP_error_set("Layer metadata: layer id 0 is invalid");
+        return NULL;
+    }
+
+    size_t metadata_size;
+    const unsigned char *metadata =
+        DP_msg_layer_metadata_metadata(mlm, &metadata_size);
+
+    return DP_ops_layer_metadata(cs, layer_id,
+                                 DP_msg_layer_metadata_color_tag(mlm),
+                                 metadata, metadata_size);
+}
+
 static DP_CanvasState *handle_layer_delete(DP_CanvasState *cs,
                                            DP_DrawContext *dc,
                                            unsigned int context_id,
@@ -503,6 +531,168 @@ static DP_CanvasState *handle_fill_rect(DP_CanvasState *cs,
                             right, bottom, pixel);
 }
 
+static void set_gradient_stops(DP_GradientStop *stops,
+                               const DP_FillGradientStop *fgss, int count)
+{
+    for (int i = 0; i < count; ++i) {
+        const DP_FillGradientStop *fgs = DP_fill_gradient_stop_at(fgss, i);
+        DP_UPixel8 color = {.color = DP_fill_gradient_stop_color(fgs)};
+        stops[i] = (DP_GradientStop){
+            DP_uint16_to_float(DP_fill_gradient_stop_position(fgs)) / 65535.0f,
+            DP_upixel8_to_float(color)};
+    }
+}
+
+static bool fill_gradient_stops_sorted(const DP_FillGradientStop *fgss,
+                                       int count)
+{
+    for (int i = 1; i < count; ++i) {
+        if (DP_fill_gradient_stop_position(DP_fill_gradient_stop_at(fgss, i))
+            < DP_fill_gradient_stop_position(
+                DP_fill_gradient_stop_at(fgss, i - 1))) {
+            return false;
+        }
+    }
+    return true;
+}
+
+static DP_CanvasState *handle_fill_gradient(DP_CanvasState *cs,
+                                            DP_DrawContext *dc,
+                                            DP_UserCursors *ucs_or_null,
+                                            unsigned int context_id,
+                                            DP_MsgFillGradient *mfg)
+{
+    int blend_mode = DP_msg_fill_gradient_mode(mfg);
+    if (!DP_blend_mode_exists(blend_mode)) {
+        DP_error_set("Fill gradient: unknown blend mode %d", blend_mode);
+        return NULL;
+    }
+    else if (!DP_blend_mode_valid_for_brush(blend_mode)) {
+        DP_error_set("Fill gradient: blend mode %s not applicable to brushes",
+                     DP_blend_mode_enum_name_unprefixed(blend_mode));
+        return NULL;
+    }
+
+    int shape = DP_msg_fill_gradient_shape(mfg);
+    if (!DP_msg_fill_gradient_shape_variant_name((unsigned int)shape)) {
+        DP_error_set("Fill gradient: unknown shape %d", shape);
+        return NULL;
+    }
+
+    int32_t radius = DP_msg_fill_gradient_radius(mfg);
+    if (radius < 0) {
+        DP_error_set("Fill gradient: negative radius %d", (int)radius);
+        return NULL;
+    }
+
+    int stop_count;
+    const DP_FillGradientStop *fgss =
+        DP_msg_fill_gradient_stops(mfg, &stop_count);
+    if (stop_count == 0) {
+        DP_error_set("Fill gradient: no stops");
+        return NULL;
+    }
+    else if (!fill_gradient_stops_sorted(fgss, stop_count)) {
+        DP_error_set("Fill gradient: stops out of order");
+        return NULL;
+    }
+
+    int x = DP_uint32_to_int(DP_msg_fill_gradient_x(mfg));
+    int y = DP_uint32_to_int(DP_msg_fill_gradient_y(mfg));
+    int width = DP_uint32_to_int(DP_msg_fill_gradient_w(mfg));
+    int height = DP_uint32_to_int(DP_msg_fill_gradient_h(mfg));
+    int left = DP_max_int(x, 0);
+    int top = DP_max_int(y, 0);
+    int right = DP_min_int(x + width, cs->width);
+    int bottom = DP_min_int(y + height, cs->height);
+    if (left >= right || top >= bottom) {
+        DP_error_set("Fill gradient: effective area to fill is zero");
+        return NULL;
+    }
+
+    DP_GradientStop *stops = DP_draw_context_pool_require(
+        dc, DP_int_to_size(stop_count) * sizeof(*stops));
+    set_gradient_stops(stops, fgss, stop_count);
+    DP_Gradient gradient = {
+        shape == DP_MSG_FILL_GRADIENT_SHAPE_RADIAL ? DP_GRADIENT_SHAPE_RADIAL
+                                                   : DP_GRADIENT_SHAPE_LINEAR,
+        DP_int32_to_double(DP_msg_fill_gradient_x1(mfg)) / 4.0,
+        DP_int32_to_double(DP_msg_fill_gradient_y1(mfg)) / 4.0,
+        DP_int32_to_double(DP_msg_fill_gradient_x2(mfg)) / 4.0,
+        DP_int32_to_double(DP_msg_fill_gradient_y2(mfg)) / 4.0,
+        DP_int32_to_double(radius) / 4.0,
+        stop_count,
+        stops,
+        DP_msg_fill_gradient_flags(mfg) & DP_MSG_FILL_GRADIENT_FLAGS_DITHER,
+    };
+
+    DP_Rect rect = DP_rect_make(left, top, right - left, bottom - top);
+    return DP_ops_fill_gradient(cs, ucs_or_null, context_id,
+                                DP_msg_fill_gradient_layer(mfg), blend_mode,
+                                &gradient, &rect);
+}
+
+static float filter_region_param(int32_t param)
+{
+    return (float)((double)param / 65536.0);
+}
+
+static void apply_filter_region(void *user, DP_TransientLayerContent *tlc,
+                                unsigned int context_id, const DP_Rect *rect)
+{
+    DP_MsgFilterRegion *mfr = user;
+    switch (DP_msg_filter_region_filter(mfr)) {
+    case DP_MSG_FILTER_REGION_FILTER_BRIGHTNESS_CONTRAST:
+        DP_filter_brightness_contrast(
+            tlc, context_id, rect,
+            filter_region_param(DP_msg_filter_region_param1(mfr)),
+            filter_region_param(DP_msg_filter_region_param2(mfr)));
+        break;
+    case DP_MSG_FILTER_REGION_FILTER_HSL:
+        DP_filter_hsl(tlc, context_id, rect,
+                      filter_region_param(DP_msg_filter_region_param1(mfr)),
+                      filter_region_param(DP_msg_filter_region_param2(mfr)),
+                      filter_region_param(DP_msg_filter_region_param3(mfr)));
+        break;
+    case DP_MSG_FILTER_REGION_FILTER_INVERT:
+        DP_filter_invert(tlc, context_id, rect,
+                         DP_msg_filter_region_param1(mfr) != 0);
+        break;
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+static DP_CanvasState *handle_filter_region(DP_CanvasState *cs,
+                                            DP_UserCursors *ucs_or_null,
+                                            unsigned int context_id,
+                                            DP_MsgFilterRegion *mfr)
+{
+    int filter = DP_msg_filter_region_filter(mfr);
+    if (!DP_msg_filter_region_filter_variant_name((unsigned int)filter)) {
+        DP_error_set("Filter region: unknown filter %d", filter);
+        return NULL;
+    }
+
+    int x = DP_uint32_to_int(DP_msg_filter_region_x(mfr));
+    int y = DP_uint32_to_int(DP_msg_filter_region_y(mfr));
+    int width = DP_uint32_to_int(DP_msg_filter_region_w(mfr));
+    int height = DP_uint32_to_int(DP_msg_filter_region_h(mfr));
+    int left = DP_max_int(x, 0);
+    int top = DP_max_int(y, 0);
+    int right = DP_min_int(x + width, cs->width);
+    int bottom = DP_min_int(y + height, cs->height);
+    if (left >= right || top >= bottom) {
+        DP_error_set("Filter region: effective area to filter is zero");
+        return NULL;
+    }
+
+    DP_Rect rect = DP_rect_make(left, top, right - left, bottom - top);
+    return DP_ops_filter_region(cs, ucs_or_null, context_id,
+                                DP_msg_filter_region_layer(mfr), &rect,
+                                apply_filter_region, mfr);
+}
+
 static DP_CanvasState *
 handle_region(DP_CanvasState *cs, DP_DrawContext *dc,
               DP_UserCursors *ucs_or_null, unsigned int context_id,
@@ -678,11 +868,13 @@ static DP_CanvasState *handle_annotation_edit(DP_CanvasState *cs,
     int flags = DP_msg_annotation_edit_flags(mae);
     bool protect = flags & DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT;
     int valign = annotation_valign_from_flags(flags);
+    bool auto_grow = flags & DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW;
     size_t text_length;
     const char *text = DP_msg_annotation_edit_text(mae, &text_length);
     return DP_ops_annotation_edit(cs, DP_msg_annotation_edit_id(mae),
                                   DP_msg_annotation_edit_bg(mae), protect,
-                                  valign, text, text_length);
+                                  valign, DP_msg_annotation_edit_border(mae),
+                                  auto_grow, text, text_length);
 }
 
 static DP_CanvasState *handle_annotation_delete(DP_CanvasState *cs,
@@ -691,6 +883,40 @@ static DP_CanvasState *handle_annotation_delete(DP_CanvasState *cs,
     return DP_ops_annotation_delete(cs, DP_msg_annotation_delete_id(mad));
 }
 
+static DP_CanvasState *handle_annotation_order(DP_CanvasState *cs,
+                                               DP_MsgAnnotationOrder *mao)
+{
+    int annotation_id = DP_msg_annotation_order_id(mao);
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
+    int index = DP_annotation_list_index_by_id(al, annotation_id);
+    if (index < 0) {
+        DP_error_set("Annotation order: id %d not found", annotation_id);
+        return NULL;
+    }
+
+    int to_index;
+    int direction = DP_msg_annotation_order_direction(mao);
+    switch (direction) {
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE:
+        to_index = DP_min_int(index + 1, DP_annotation_list_count(al) - 1);
+        break;
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER:
+        to_index = DP_max_int(index - 1, 0);
+        break;
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT:
+        to_index = DP_annotation_list_count(al) - 1;
+        break;
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK:
+        to_index = 0;
+        break;
+    default:
+        DP_error_set("Annotation order: unknown direction %d", direction);
+        return NULL;
+    }
+
+    return DP_ops_annotation_order(cs, annotation_id, to_index);
+}
+
 
 struct DP_NextDabContext {
     int i, count;
@@ -713,6 +939,7 @@ get_draw_dabs_classic_params(unsigned int context_id,
                                     DP_msg_draw_dabs_classic_indirect(mddc),
                                     indirect_compat,
                                     dab_count,
+                                    NULL,
                                     {.classic = {dabs}}};
 }
 
@@ -732,9 +959,58 @@ get_draw_dabs_pixel_params(DP_MessageType type, unsigned int context_id,
                                     DP_msg_draw_dabs_pixel_indirect(mddp),
                                     indirect_compat,
                                     dab_count,
+                                    NULL,
                                     {.pixel = {dabs}}};
 }
 
+static DP_PaintDrawDabsParams
+get_draw_dabs_stamp_params(unsigned int context_id, DP_MsgDrawDabsStamp *mdds,
+                           bool indirect_compat)
+{
+    int dab_count;
+    const DP_StampDab *dabs = DP_msg_draw_dabs_stamp_dabs(mdds, &dab_count);
+    DP_PaintGrain grain = {DP_msg_draw_dabs_stamp_grain(mdds),
+                           DP_msg_draw_dabs_stamp_grainscale(mdds),
+                           DP_msg_draw_dabs_stamp_grainstrength(mdds)};
+    return (DP_PaintDrawDabsParams){
+        DP_MSG_DRAW_DABS_STAMP,
+        context_id,
+        DP_msg_draw_dabs_stamp_layer(mdds),
+        DP_msg_draw_dabs_stamp_x(mdds),
+        DP_msg_draw_dabs_stamp_y(mdds),
+        DP_msg_draw_dabs_stamp_color(mdds),
+        DP_msg_draw_dabs_stamp_mode(mdds),
+        DP_msg_draw_dabs_stamp_indirect(mdds),
+        indirect_compat,
+        dab_count,
+        NULL,
+        {.stamp = {dabs, DP_msg_draw_dabs_stamp_mask(mdds), grain}}};
+}
+
+static DP_PaintDrawDabsParams
+get_draw_dabs_soft_params(unsigned int context_id, DP_MsgDrawDabsSoft *mdds,
+                          bool indirect_compat)
+{
+    int dab_count;
+    const DP_SoftDab *dabs = DP_msg_draw_dabs_soft_dabs(mdds, &dab_count);
+    DP_PaintGrain grain = {DP_msg_draw_dabs_soft_grain(mdds),
+                           DP_msg_draw_dabs_soft_grainscale(mdds),
+                           DP_msg_draw_dabs_soft_grainstrength(mdds)};
+    return (DP_PaintDrawDabsParams){
+        DP_MSG_DRAW_DABS_SOFT,
+        context_id,
+        DP_msg_draw_dabs_soft_layer(mdds),
+        DP_msg_draw_dabs_soft_x(mdds),
+        DP_msg_draw_dabs_soft_y(mdds),
+        DP_msg_draw_dabs_soft_color(mdds),
+        DP_msg_draw_dabs_soft_mode(mdds),
+        DP_msg_draw_dabs_soft_indirect(mdds),
+        indirect_compat,
+        dab_count,
+        NULL,
+        {.soft = {dabs, DP_msg_draw_dabs_soft_falloff(mdds), grain}}};
+}
+
 static DP_PaintDrawDabsParams
 get_draw_dabs_mypaint_params(unsigned int context_id,
                              DP_MsgDrawDabsMyPaint *mddmp)
@@ -758,6 +1034,7 @@ get_draw_dabs_mypaint_params(unsigned int context_id,
         indirect,
         false,
         dab_count,
+        NULL,
         {.mypaint = {dabs, DP_msg_draw_dabs_mypaint_lock_alpha(mddmp),
                      DP_msg_draw_dabs_mypaint_colorize(mddmp),
                      DP_msg_draw_dabs_mypaint_posterize(mddmp),
@@ -792,10 +1069,20 @@ static bool next_dab(void *user, DP_PaintDrawDabsParams *out_params)
                     DP_message_internal(msg),
                     DP_message_compat_flag_indirect(msg));
                 break;
+            case DP_MSG_DRAW_DABS_STAMP:
+                *out_params = get_draw_dabs_stamp_params(
+                    context_id, DP_message_internal(msg),
+                    DP_message_compat_flag_indirect(msg));
+                break;
             case DP_MSG_DRAW_DABS_MYPAINT:
                 *out_params = get_draw_dabs_mypaint_params(
                     context_id, DP_msg_draw_dabs_mypaint_cast(msg));
                 break;
+            case DP_MSG_DRAW_DABS_SOFT:
+                *out_params = get_draw_dabs_soft_params(
+                    context_id, DP_message_internal(msg),
+                    DP_message_compat_flag_indirect(msg));
+                break;
             default:
                 DP_UNREACHABLE();
             }
@@ -816,6 +1103,24 @@ static DP_CanvasState *handle_draw_dabs(DP_CanvasState *cs, DP_DrawContext *dc,
 }
 
 
+static DP_CanvasState *handle_stamp_mask(DP_CanvasState *cs,
+                                         DP_MsgStampMask *msm)
+{
+    // Masks are kept outside of the canvas state, identified by their
+    // contents, so defining one doesn't actually change anything here.
+    DP_StampMask *sm = DP_stamp_mask_search_inc(DP_msg_stamp_mask_mask(msm));
+    if (!sm) {
+        sm = DP_stamp_mask_new_from_message(msm);
+        if (!sm) {
+            return NULL;
+        }
+    }
+    DP_stamp_mask_register(sm);
+    DP_stamp_mask_decref(sm);
+    return DP_canvas_state_incref(cs);
+}
+
+
 static DP_CanvasState *handle_move_rect(DP_CanvasState *cs,
                                         DP_UserCursors *ucs_or_null,
                                         unsigned int context_id,
@@ -1180,6 +1485,8 @@ static DP_CanvasState *handle(DP_CanvasState *cs, DP_DrawContext *dc,
         return handle_layer_order(cs, dc, DP_msg_layer_order_cast(msg));
     case DP_MSG_LAYER_RETITLE:
         return handle_layer_retitle(cs, DP_msg_layer_retitle_cast(msg));
+    case DP_MSG_LAYER_METADATA:
+        return handle_layer_metadata(cs, DP_msg_layer_metadata_cast(msg));
     case DP_MSG_LAYER_DELETE:
         return handle_layer_delete(cs, dc, DP_message_context_id(msg),
                                    DP_msg_layer_delete_cast(msg));
@@ -1191,6 +1498,14 @@ static DP_CanvasState *handle(DP_CanvasState *cs, DP_DrawContext *dc,
     case DP_MSG_FILL_RECT:
         return handle_fill_rect(cs, ucs_or_null, DP_message_context_id(msg),
                                 DP_msg_fill_rect_cast(msg));
+    case DP_MSG_FILL_GRADIENT:
+        return handle_fill_gradient(cs, dc, ucs_or_null,
+                                    DP_message_context_id(msg),
+                                    DP_msg_fill_gradient_cast(msg));
+    case DP_MSG_FILTER_REGION:
+        return handle_filter_region(cs, ucs_or_null,
+                                    DP_message_context_id(msg),
+                                    DP_msg_filter_region_cast(msg));
     case DP_MSG_MOVE_REGION:
         return handle_move_region(cs, dc, ucs_or_null,
                                   DP_message_context_id(msg),
@@ -1212,11 +1527,17 @@ static DP_CanvasState *handle(DP_CanvasState *cs, DP_DrawContext *dc,
         return handle_annotation_edit(cs, DP_msg_annotation_edit_cast(msg));
     case DP_MSG_ANNOTATION_DELETE:
         return handle_annotation_delete(cs, DP_msg_annotation_delete_cast(msg));
+    case DP_MSG_ANNOTATION_ORDER:
+        return handle_annotation_order(cs, DP_msg_annotation_order_cast(msg));
     case DP_MSG_DRAW_DABS_CLASSIC:
     case DP_MSG_DRAW_DABS_PIXEL:
     case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
     case DP_MSG_DRAW_DABS_MYPAINT:
+    case DP_MSG_DRAW_DABS_STAMP:
+    case DP_MSG_DRAW_DABS_SOFT:
         return handle_draw_dabs(cs, dc, ucs_or_null, 1, &msg);
+    case DP_MSG_STAMP_MASK:
+        return handle_stamp_mask(cs, DP_msg_stamp_mask_cast(msg));
     case DP_MSG_MOVE_RECT:
         return handle_move_rect(cs, ucs_or_null, DP_message_context_id(msg),
                                 DP_msg_move_rect_cast(msg));
@@ -1319,6 +1640,366 @@ int DP_canvas_state_search_change_bounds(DP_CanvasState *cs,
     return 0;
 }
 
+static int pick_layer_list(DP_LayerList *ll, DP_LayerPropsList *lpl, int x,
+                           int y, uint16_t parent_opacity, uint16_t min_alpha,
+                           bool reveal_censored);
+
+static bool pick_layer_content(DP_LayerContent *lc, int x, int y,
+                               uint16_t opacity, uint16_t min_alpha)
+{
+    DP_Pixel15 pixel = DP_layer_content_pixel_at(lc, x, y);
+    if (DP_fix15_mul(pixel.a, opacity) > min_alpha) {
+        return true;
+    }
+
+    // Sublayers hold strokes in progress, which are also visible on canvas.
+    DP_LayerList *sub_ll = DP_layer_content_sub_contents_noinc(lc);
+    DP_LayerPropsList *sub_lpl = DP_layer_content_sub_props_noinc(lc);
+    int sub_count = DP_layer_list_count(sub_ll);
+    for (int i = 0; i < sub_count; ++i) {
+        DP_LayerProps *sub_lp = DP_layer_props_list_at_noinc(sub_lpl, i);
+        if (DP_layer_props_visible(sub_lp)) {
+            DP_LayerContent *sub_lc = DP_layer_list_entry_content_noinc(
+                DP_layer_list_at_noinc(sub_ll, i));
+            uint16_t sub_opacity =
+                DP_fix15_mul(opacity, DP_layer_props_opacity(sub_lp));
+            if (pick_layer_content(sub_lc, x, y, sub_opacity, min_alpha)) {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+static int pick_layer_list(DP_LayerList *ll, DP_LayerPropsList *lpl, int x,
+                           int y, uint16_t parent_opacity, uint16_t min_alpha,
+                           bool reveal_censored)
+{
+    int count = DP_layer_list_count(ll);
+    DP_ASSERT(DP_layer_props_list_count(lpl) == count);
+    for (int i = count - 1; i >= 0; --i) {
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        bool pickable = DP_layer_props_visible(lp)
+                     && (reveal_censored || !DP_layer_props_censored(lp));
+        if (pickable) {
+            DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+            uint16_t opacity =
+                DP_fix15_mul(parent_opacity, DP_layer_props_opacity(lp));
+            if (DP_layer_list_entry_is_group(lle)) {
+                int layer_id = pick_layer_list(
+                    DP_layer_group_children_noinc(
+                        DP_layer_list_entry_group_noinc(lle)),
+                    DP_layer_props_children_noinc(lp), x, y, opacity,
+                    min_alpha, reveal_censored);
+                if (layer_id != 0) {
+                    return layer_id;
+                }
+            }
+            else if (pick_layer_content(DP_layer_list_entry_content_noinc(lle),
+                                        x, y, opacity, min_alpha)) {
+                return DP_layer_props_id(lp);
+            }
+        }
+    }
+    return 0;
+}
+
+int DP_canvas_state_pick_layer(DP_CanvasState *cs, int x, int y,
+                               uint8_t min_alpha, bool reveal_censored)
+{
+    DP_ASSERT(cs);
+    bool in_bounds = x >= 0 && y >= 0 && x < DP_canvas_state_width(cs)
+                  && y < DP_canvas_state_height(cs);
+    if (in_bounds) {
+        return pick_layer_list(cs->layers, cs->layer_props, x, y, DP_BIT15,
+                               DP_channel8_to_15(min_alpha), reveal_censored);
+    }
+    else {
+        return 0;
+    }
+}
+
+struct DP_TouchedByContext {
+    int col, row;
+    uint8_t *users;
+    int count;
+};
+
+static void touched_by_layer_list(struct DP_TouchedByContext *c,
+                                  DP_LayerList *ll, DP_LayerPropsList *lpl);
+
+static void touched_by_layer_content(struct DP_TouchedByContext *c,
+                                     DP_LayerContent *lc)
+{
+    unsigned int context_id;
+    if (DP_layer_content_tile_context_id_at(lc, c->col, c->row, &context_id)) {
+        uint8_t user_id = DP_uint_to_uint8(context_id);
+        if (!DP_user_bit_get(c->users, user_id)) {
+            DP_user_bit_set(c->users, user_id);
+            ++c->count;
+        }
+    }
+    touched_by_layer_list(c, DP_layer_content_sub_contents_noinc(lc),
+                          DP_layer_content_sub_props_noinc(lc));
+}
+
+static void touched_by_layer_list(struct DP_TouchedByContext *c,
+                                  DP_LayerList *ll, DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_list_count(ll);
+    DP_ASSERT(DP_layer_props_list_count(lpl) == count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        if (DP_layer_props_visible(lp)) {
+            DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+            if (DP_layer_list_entry_is_group(lle)) {
+                touched_by_layer_list(c,
+                                      DP_layer_group_children_noinc(
+                                          DP_layer_list_entry_group_noinc(lle)),
+                                      DP_layer_props_children_noinc(lp));
+            }
+            else {
+                touched_by_layer_content(
+                    c, DP_layer_list_entry_content_noinc(lle));
+            }
+        }
+    }
+}
+
+int DP_canvas_state_touched_by(DP_CanvasState *cs, const DP_Rect *area,
+                               uint8_t *out_users)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(area);
+    DP_ASSERT(out_users);
+    memset(out_users, 0, sizeof(DP_UserBits));
+    struct DP_TouchedByContext c = {0, 0, out_users, 0};
+    DP_TileIterator ti = DP_tile_iterator_make(cs->width, cs->height, *area);
+    while (DP_tile_iterator_next(&ti)) {
+        c.col = ti.col;
+        c.row = ti.row;
+        touched_by_layer_list(&c, cs->layers, cs->layer_props);
+    }
+    return c.count;
+}
+
+
+static uint64_t checksum_layer_props(uint64_t checksum, DP_LayerProps *lp)
+{
+    size_t title_length;
+    const char *title = DP_layer_props_title(lp, &title_length);
+    checksum = DP_checksum_int(checksum, DP_layer_props_id(lp));
+    checksum = DP_checksum_bytes(checksum, title, title_length);
+    checksum = DP_checksum_uint16(checksum, DP_layer_props_opacity(lp));
+    checksum = DP_checksum_int(checksum, DP_layer_props_blend_mode(lp));
+    checksum = DP_checksum_bool(checksum, DP_layer_props_censored(lp));
+    checksum = DP_checksum_bool(checksum, DP_layer_props_isolated(lp));
+    checksum = DP_checksum_bool(checksum, DP_layer_props_alpha_lock(lp));
+    return DP_checksum_bool(checksum, DP_layer_props_fixed(lp));
+}
+
+static uint64_t checksum_layer_list(uint64_t checksum, DP_LayerList *ll,
+                                    DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_list_count(ll);
+    DP_ASSERT(DP_layer_props_list_count(lpl) == count);
+    checksum = DP_checksum_int(checksum, count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        checksum = checksum_layer_props(checksum, lp);
+        if (DP_layer_list_entry_is_group(lle)) {
+            checksum = DP_checksum_bool(checksum, true);
+            checksum = checksum_layer_list(
+                checksum,
+                DP_layer_group_children_noinc(
+                    DP_layer_list_entry_group_noinc(lle)),
+                DP_layer_props_children_noinc(lp));
+        }
+        else {
+            checksum = DP_checksum_bool(checksum, false);
+            checksum = DP_checksum_uint64(
+                checksum, DP_layer_content_checksum(
+                              DP_layer_list_entry_content_noinc(lle)));
+        }
+    }
+    return checksum;
+}
+
+static uint64_t checksum_annotations(uint64_t checksum, DP_AnnotationList *al)
+{
+    int count = DP_annotation_list_count(al);
+    checksum = DP_checksum_int(checksum, count);
+    for (int i = 0; i < count; ++i) {
+        DP_Annotation *a = DP_annotation_list_at_noinc(al, i);
+        size_t text_length;
+        const char *text = DP_annotation_text(a, &text_length);
+        checksum = DP_checksum_int(checksum, DP_annotation_id(a));
+        checksum = DP_checksum_int(checksum, DP_annotation_x(a));
+        checksum = DP_checksum_int(checksum, DP_annotation_y(a));
+        checksum = DP_checksum_int(checksum, DP_annotation_width(a));
+        checksum = DP_checksum_int(checksum, DP_annotation_height(a));
+        checksum =
+            DP_checksum_uint32(checksum, DP_annotation_background_color(a));
+        checksum = DP_checksum_bool(checksum, DP_annotation_protect(a));
+        checksum = DP_checksum_int(checksum, DP_annotation_valign(a));
+        // Only mixed in when set, so that checksums of canvases without
+        // borders or auto-grow stay the same as before those existed.
+        int border = DP_annotation_border(a);
+        if (border != 0) {
+            checksum = DP_checksum_int(checksum, border);
+        }
+        if (DP_annotation_auto_grow(a)) {
+            checksum = DP_checksum_bool(checksum, true);
+        }
+        checksum = DP_checksum_bytes(checksum, text, text_length);
+    }
+    return checksum;
+}
+
+uint64_t DP_canvas_state_checksum(DP_CanvasState *cs)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(DP_atomic_get(&cs->refcount) > 0);
+    uint64_t checksum = DP_CHECKSUM_INIT;
+    checksum = DP_checksum_int(checksum, cs->width);
+    checksum = DP_checksum_int(checksum, cs->height);
+    checksum =
+        DP_checksum_uint64(checksum, DP_tile_checksum(cs->background_tile));
+    checksum = checksum_layer_list(checksum, cs->layers, cs->layer_props);
+    return checksum_annotations(checksum, cs->annotations);
+}
+
+static void tile_checksums_layer_list(uint64_t *checksums, DP_LayerList *ll,
+                                      DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_list_count(ll);
+    DP_ASSERT(DP_layer_props_list_count(lpl) == count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        if (DP_layer_list_entry_is_group(lle)) {
+            tile_checksums_layer_list(
+                checksums,
+                DP_layer_group_children_noinc(
+                    DP_layer_list_entry_group_noinc(lle)),
+                DP_layer_props_children_noinc(lp));
+        }
+        else {
+            DP_LayerContent *lc = DP_layer_list_entry_content_noinc(lle);
+            int layer_id = DP_layer_props_id(lp);
+            DP_TileCounts tile_counts = DP_tile_counts_round(
+                DP_layer_content_width(lc), DP_layer_content_height(lc));
+            for (int y = 0; y < tile_counts.y; ++y) {
+                for (int x = 0; x < tile_counts.x; ++x) {
+                    uint64_t tile_checksum = DP_tile_checksum(
+                        DP_layer_content_tile_at_noinc(lc, x, y));
+                    if (tile_checksum != DP_TILE_CHECKSUM_BLANK) {
+                        uint64_t *checksum =
+                            &checksums[y * tile_counts.x + x];
+                        *checksum = DP_checksum_int(*checksum, layer_id);
+                        *checksum =
+                            DP_checksum_uint64(*checksum, tile_checksum);
+                    }
+                }
+            }
+        }
+    }
+}
+
+void DP_canvas_state_tile_checksums(DP_CanvasState *cs,
+                                    uint64_t *out_checksums)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(DP_atomic_get(&cs->refcount) > 0);
+    DP_ASSERT(out_checksums);
+    uint64_t background_checksum = DP_checksum_uint64(
+        DP_CHECKSUM_INIT, DP_tile_checksum(cs->background_tile));
+    int count = DP_tile_total_round(cs->width, cs->height);
+    for (int i = 0; i < count; ++i) {
+        out_checksums[i] = background_checksum;
+    }
+    tile_checksums_layer_list(out_checksums, cs->layers, cs->layer_props);
+}
+
+
+DP_CanvasState *DP_canvas_state_sketch_inc(DP_CanvasState *cs, int count,
+                                           const DP_SketchLayer *sketches)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(DP_atomic_get(&cs->refcount) > 0);
+    DP_ASSERT(count >= 0);
+    DP_ASSERT(count == 0 || sketches);
+    DP_TransientCanvasState *tcs = NULL;
+    for (int i = 0; i < count; ++i) {
+        DP_LayerRoutesEntry *lre =
+            DP_layer_routes_search(cs->layer_routes, sketches[i].layer_id);
+        if (lre) {
+            if (!tcs) {
+                tcs = DP_transient_canvas_state_new(cs);
+            }
+            DP_TransientLayerProps *tlp =
+                DP_layer_routes_entry_transient_props(lre, tcs);
+            DP_transient_layer_props_opacity_set(tlp, sketches[i].opacity);
+        }
+    }
+    return tcs ? DP_transient_canvas_state_persist(tcs)
+               : DP_canvas_state_incref(cs);
+}
+
+
+static bool any_layer_censored(DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_props_list_count(lpl);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        DP_LayerPropsList *child_lpl = DP_layer_props_children_noinc(lp);
+        if (DP_layer_props_censored(lp)
+            || (child_lpl && any_layer_censored(child_lpl))) {
+            return true;
+        }
+    }
+    return false;
+}
+
+static void reveal_censored_layers(DP_TransientLayerPropsList *tlpl)
+{
+    int count = DP_transient_layer_props_list_count(tlpl);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerProps *lp = DP_transient_layer_props_list_at_noinc(tlpl, i);
+        DP_LayerPropsList *child_lpl = DP_layer_props_children_noinc(lp);
+        bool censored = DP_layer_props_censored(lp);
+        bool children_censored = child_lpl && any_layer_censored(child_lpl);
+        if (censored || children_censored) {
+            DP_TransientLayerProps *tlp =
+                DP_transient_layer_props_list_transient_at_noinc(tlpl, i);
+            if (censored) {
+                DP_transient_layer_props_censored_set(tlp, false);
+            }
+            if (children_censored) {
+                reveal_censored_layers(
+                    DP_transient_layer_props_transient_children(tlp, 0));
+            }
+        }
+    }
+}
+
+// Censoring is part of the layer props, so revealing censored layers means
+// flattening a copy of the canvas state with the flag cleared everywhere.
+static DP_CanvasState *get_flat_canvas_state_inc(DP_CanvasState *cs,
+                                                 unsigned int flags)
+{
+    bool reveal_censored = flags & DP_FLAT_IMAGE_REVEAL_CENSORED;
+    if (reveal_censored && any_layer_censored(cs->layer_props)) {
+        DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+        reveal_censored_layers(
+            DP_transient_canvas_state_transient_layer_props(tcs, 0));
+        return DP_transient_canvas_state_persist(tcs);
+    }
+    else {
+        return DP_canvas_state_incref(cs);
+    }
+}
 
 static DP_Tile *get_flat_background_tile_or_null(DP_CanvasState *cs,
                                                  unsigned int flags)
@@ -1327,45 +2008,146 @@ static DP_Tile *get_flat_background_tile_or_null(DP_CanvasState *cs,
     return include_background ? cs->background_tile : NULL;
 }
 
+// Copying and clearing keep track of uniform tiles, so that a solid
+// background gets blended with solid layer tiles through the fast path.
 static void init_flattening_tile(DP_TransientTile *tt, DP_Tile *background_tile)
 {
     if (background_tile) {
-        memcpy(DP_transient_tile_pixels(tt), DP_tile_pixels(background_tile),
-               DP_TILE_BYTES);
+        DP_transient_tile_copy(tt, background_tile);
     }
     else {
-        memset(DP_transient_tile_pixels(tt), 0, DP_TILE_BYTES);
+        DP_transient_tile_clear(tt);
     }
 }
 
+typedef void (*DP_FlattenTileFn)(void *user, DP_TileIterator *ti,
+                                 int tile_index, int thread_index);
+
+typedef struct DP_FlattenTileJob {
+    DP_FlattenTileFn fn;
+    void *user;
+    DP_TileIterator ti;
+    int tile_index;
+} DP_FlattenTileJob;
+
+typedef struct DP_FlattenContext {
+    DP_CanvasState *cs;
+    DP_Tile *background_tile;
+    bool include_sublayers;
+    DP_ViewModeFilter vmf;
+} DP_FlattenContext;
+
+static DP_FlattenContext flatten_context_make(DP_CanvasState *cs,
+                                              unsigned int flags,
+                                              const DP_ViewModeFilter *vmf)
+{
+    return (DP_FlattenContext){
+        get_flat_canvas_state_inc(cs, flags),
+        get_flat_background_tile_or_null(cs, flags),
+        flags & DP_FLAT_IMAGE_INCLUDE_SUBLAYERS,
+        vmf ? *vmf : DP_view_mode_filter_make_default()};
+}
+
+static void flatten_context_dispose(DP_FlattenContext *fc)
+{
+    DP_canvas_state_decref(fc->cs);
+}
+
+static void flatten_context_tile_to(DP_FlattenContext *fc, int tile_index,
+                                    DP_TransientTile *tt)
+{
+    init_flattening_tile(tt, fc->background_tile);
+    DP_canvas_state_flatten_tile_to(fc->cs, tile_index, tt,
+                                    fc->include_sublayers, &fc->vmf);
+}
+
+static int flatten_thread_count(unsigned int flags, DP_TileIterator *ti)
+{
+    DP_Rect tile_area = ti->tile_area;
+    bool single_tile = tile_area.x1 == tile_area.x2
+                    && tile_area.y1 == tile_area.y2;
+    if (single_tile || (flags & DP_FLAT_IMAGE_SINGLE_THREADED)) {
+        return 1;
+    }
+    else {
+        return DP_thread_cpu_count(128);
+    }
+}
+
+static void flatten_tile_job(void *element, int thread_index)
+{
+    DP_FlattenTileJob *job = element;
+    job->fn(job->user, &job->ti, job->tile_index, thread_index);
+}
+
+// Tiles don't affect each other and the canvas state is immutable, so they
+// can all get flattened in parallel. The given function must only write to
+// the part of the result belonging to its tile and may use thread-specific
+// scratch space through the thread index, which is below the thread count.
+static void flatten_tiles(DP_CanvasState *cs, DP_Rect area, int thread_count,
+                          DP_FlattenTileFn fn, void *user)
+{
+    DP_Worker *worker = NULL;
+    if (thread_count > 1) {
+        worker = DP_worker_new(64, sizeof(DP_FlattenTileJob), thread_count,
+                               flatten_tile_job);
+        if (!worker) {
+            DP_warn("Error creating flatten worker: %s", DP_error());
+        }
+    }
+
+    int wt = DP_tile_count_round(cs->width);
+    DP_TileIterator ti = DP_tile_iterator_make(cs->width, cs->height, area);
+    while (DP_tile_iterator_next(&ti)) {
+        int tile_index = ti.row * wt + ti.col;
+        if (worker) {
+            DP_FlattenTileJob job = {fn, user, ti, tile_index};
+            DP_worker_push(worker, &job);
+        }
+        else {
+            fn(user, &ti, tile_index, 0);
+        }
+    }
+
+    DP_worker_free_join(worker);
+}
+
+struct DP_FlattenToLayerParams {
+    DP_FlattenContext fc;
+    DP_TransientLayerContent *tlc;
+};
+
+static void flatten_tile_to_layer(void *user, DP_UNUSED DP_TileIterator *ti,
+                                  int tile_index, DP_UNUSED int thread_index)
+{
+    struct DP_FlattenToLayerParams *params = user;
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    flatten_context_tile_to(&params->fc, tile_index, tt);
+    DP_transient_layer_content_transient_tile_set_noinc(params->tlc, tt,
+                                                        tile_index);
+}
+
 DP_TransientLayerContent *
 DP_canvas_state_to_flat_layer(DP_CanvasState *cs, unsigned int flags,
+                              const DP_Rect *area_or_null,
                               const DP_ViewModeFilter *vmf_or_null)
 {
     DP_ASSERT(cs);
     DP_ASSERT(DP_atomic_get(&cs->refcount) > 0);
     int width = cs->width;
     int height = cs->height;
-    DP_TransientLayerContent *tlc =
-        DP_transient_layer_content_new_init(width, height, NULL);
-
-    DP_Tile *background_tile = get_flat_background_tile_or_null(cs, flags);
-    int wt = DP_tile_count_round(width);
-    bool include_sublayers = flags & DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
-    DP_ViewModeFilter vmf =
-        vmf_or_null ? *vmf_or_null : DP_view_mode_filter_make_default();
+    struct DP_FlattenToLayerParams params = {
+        flatten_context_make(cs, flags, vmf_or_null),
+        DP_transient_layer_content_new_init(width, height, NULL)};
 
-    DP_TileIterator ti = DP_tile_iterator_make(
-        cs->width, cs->height, DP_rect_make(0, 0, width, height));
-    while (DP_tile_iterator_next(&ti)) {
-        DP_TransientTile *tt = DP_transient_tile_new_blank(0);
-        init_flattening_tile(tt, background_tile);
-        int i = ti.row * wt + ti.col;
-        DP_canvas_state_flatten_tile_to(cs, i, tt, include_sublayers, &vmf);
-        DP_transient_layer_content_transient_tile_set_noinc(tlc, tt, i);
-    }
+    DP_Rect area =
+        area_or_null ? *area_or_null : DP_rect_make(0, 0, width, height);
+    DP_TileIterator ti = DP_tile_iterator_make(width, height, area);
+    flatten_tiles(cs, area, flatten_thread_count(flags, &ti),
+                  flatten_tile_to_layer, &params);
 
-    return tlc;
+    flatten_context_dispose(&params.fc);
+    return params.tlc;
 }
 
 static DP_TransientTile *
@@ -1397,21 +2179,18 @@ flatten_onion_skin(int tile_index, DP_TransientTile *tt, DP_LayerListEntry *lle,
     }
 }
 
-DP_TransientTile *DP_canvas_state_flatten_tile_to(DP_CanvasState *cs,
-                                                  int tile_index,
-                                                  DP_TransientTile *tt_or_null,
-                                                  bool include_sublayers,
-                                                  const DP_ViewModeFilter *vmf)
+static DP_TransientTile *
+flatten_tile_range_to(DP_CanvasState *cs, int tile_index, DP_TransientTile *tt,
+                      bool include_sublayers,
+                      const DP_ViewModeContextRoot *vmcr, int start, int end)
 {
-    DP_ViewModeContextRoot vmcr = DP_view_mode_context_root_init(vmf, cs);
-    DP_TransientTile *tt = tt_or_null;
-    for (int i = 0; i < vmcr.count; ++i) {
+    for (int i = start; i < end; ++i) {
         DP_LayerListEntry *lle;
         DP_LayerProps *lp;
         const DP_OnionSkin *os;
         uint16_t parent_opacity;
         DP_ViewModeContext vmc = DP_view_mode_context_root_at(
-            &vmcr, cs, i, &lle, &lp, &os, &parent_opacity);
+            vmcr, cs, i, &lle, &lp, &os, &parent_opacity);
         if (!DP_view_mode_context_excludes_everything(&vmc)) {
             if (os) {
                 tt = flatten_onion_skin(tile_index, tt, lle, lp, parent_opacity,
@@ -1427,6 +2206,33 @@ DP_TransientTile *DP_canvas_state_flatten_tile_to(DP_CanvasState *cs,
     return tt;
 }
 
+DP_TransientTile *DP_canvas_state_flatten_tile_to(DP_CanvasState *cs,
+                                                  int tile_index,
+                                                  DP_TransientTile *tt_or_null,
+                                                  bool include_sublayers,
+                                                  const DP_ViewModeFilter *vmf)
+{
+    DP_ViewModeContextRoot vmcr = DP_view_mode_context_root_init(vmf, cs);
+    return flatten_tile_range_to(cs, tile_index, tt_or_null, include_sublayers,
+                                 &vmcr, 0, vmcr.count);
+}
+
+struct DP_FlattenToBufferParams {
+    DP_FlattenContext fc;
+    void (*to_buffer)(void *, DP_TransientTile *, DP_TileIterator *);
+    void *buffer;
+    DP_TransientTile **tts;
+};
+
+static void flatten_tile_to_buffer(void *user, DP_TileIterator *ti,
+                                   int tile_index, int thread_index)
+{
+    struct DP_FlattenToBufferParams *params = user;
+    DP_TransientTile *tt = params->tts[thread_index];
+    flatten_context_tile_to(&params->fc, tile_index, tt);
+    params->to_buffer(params->buffer, tt, ti);
+}
+
 static void *flatten_canvas(
     DP_CanvasState *cs, unsigned int flags, const DP_Rect *area_or_null,
     const DP_ViewModeFilter *vmf_or_null, void *(*get_buffer)(void *, int, int),
@@ -1440,41 +2246,107 @@ static void *flatten_canvas(
         return NULL;
     }
 
-    DP_Tile *background_tile = get_flat_background_tile_or_null(cs, flags);
-    int wt = DP_tile_count_round(cs->width);
-    bool include_sublayers = flags & DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
-    DP_ViewModeFilter vmf =
-        vmf_or_null ? *vmf_or_null : DP_view_mode_filter_make_default();
-
-    void *buffer = get_buffer(user, DP_rect_width(area), DP_rect_height(area));
-    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
     DP_TileIterator ti = DP_tile_iterator_make(cs->width, cs->height, area);
-    while (DP_tile_iterator_next(&ti)) {
-        init_flattening_tile(tt, background_tile);
-        int i = ti.row * wt + ti.col;
-        DP_canvas_state_flatten_tile_to(cs, i, tt, include_sublayers, &vmf);
-        to_buffer(buffer, tt, &ti);
+    int thread_count = flatten_thread_count(flags, &ti);
+    // Each thread flattens into its own scratch tile.
+    DP_TransientTile **tts =
+        DP_malloc(sizeof(*tts) * DP_int_to_size(thread_count));
+    for (int i = 0; i < thread_count; ++i) {
+        tts[i] = DP_transient_tile_new_blank(0);
+    }
+
+    struct DP_FlattenToBufferParams params = {
+        flatten_context_make(cs, flags, vmf_or_null), to_buffer,
+        get_buffer(user, DP_rect_width(area), DP_rect_height(area)), tts};
+    flatten_tiles(cs, area, thread_count, flatten_tile_to_buffer, &params);
+    flatten_context_dispose(&params.fc);
+
+    for (int i = 0; i < thread_count; ++i) {
+        DP_transient_tile_decref(tts[i]);
     }
-    DP_transient_tile_decref(tt);
-    return buffer;
+    DP_free(tts);
+    return params.buffer;
 }
 
-static void *to_flat_image_get_buffer(DP_UNUSED void *user, int width,
-                                      int height)
+struct DP_FlattenIntoParams {
+    DP_Pixel8 *pixels;
+    size_t stride;
+};
+
+static void *flatten_into_get_buffer(void *user, DP_UNUSED int width,
+                                     DP_UNUSED int height)
 {
-    return DP_image_new(width, height);
+    return user;
 }
 
-static void to_flat_image_to_buffer(void *buffer, DP_TransientTile *tt,
-                                    DP_TileIterator *ti)
+static void flatten_into_to_buffer(void *buffer, DP_TransientTile *tt,
+                                   DP_TileIterator *ti)
 {
-    DP_Image *img = buffer;
+    struct DP_FlattenIntoParams *params = buffer;
     DP_TileIntoDstIterator tidi = DP_tile_into_dst_iterator_make(ti);
     while (DP_tile_into_dst_iterator_next(&tidi)) {
-        DP_image_pixel_at_set(img, tidi.dst_x, tidi.dst_y,
-                              DP_pixel15_to_8(DP_transient_tile_pixel_at(
-                                  tt, tidi.tile_x, tidi.tile_y)));
+        size_t i = DP_int_to_size(tidi.dst_y) * params->stride
+                 + DP_int_to_size(tidi.dst_x);
+        params->pixels[i] = DP_pixel15_to_8(
+            DP_transient_tile_pixel_at(tt, tidi.tile_x, tidi.tile_y));
+    }
+}
+
+// Flattening only touches pixels on the canvas, anything in the area beyond
+// that has to be cleared explicitly, since the buffer may contain anything.
+static void clear_outside_canvas(DP_CanvasState *cs, DP_Rect area,
+                                 DP_Pixel8 *pixels, size_t stride)
+{
+    DP_Rect canvas = DP_rect_make(0, 0, cs->width, cs->height);
+    DP_Rect inside = DP_rect_intersection(area, canvas);
+    if (!DP_rect_valid(inside) || inside.x1 != area.x1 || inside.y1 != area.y1
+        || inside.x2 != area.x2 || inside.y2 != area.y2) {
+        size_t width = DP_int_to_size(DP_rect_width(area));
+        int height = DP_rect_height(area);
+        for (int y = 0; y < height; ++y) {
+            memset(pixels + DP_int_to_size(y) * stride, 0,
+                   sizeof(*pixels) * width);
+        }
+    }
+}
+
+bool DP_canvas_state_flatten_into(DP_CanvasState *cs, unsigned int flags,
+                                  const DP_Rect *area_or_null,
+                                  const DP_ViewModeFilter *vmf_or_null,
+                                  DP_Pixel8 *pixels, size_t length,
+                                  size_t stride)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(DP_atomic_get(&cs->refcount) > 0);
+    DP_ASSERT(pixels);
+    DP_Rect area = area_or_null ? *area_or_null
+                                : DP_rect_make(0, 0, cs->width, cs->height);
+    if (!DP_rect_valid(area)) {
+        DP_error_set("Can't flatten zero pixels");
+        return false;
+    }
+
+    size_t width = DP_int_to_size(DP_rect_width(area));
+    size_t height = DP_int_to_size(DP_rect_height(area));
+    if (stride < width) {
+        DP_error_set("Stride %zu is less than width %zu", stride, width);
+        return false;
     }
+
+    // The last row doesn't need to be padded out to the full stride.
+    size_t required = (height - 1) * stride + width;
+    if (length < required) {
+        DP_error_set("Buffer of %zu pixels too small for %zux%zu with stride "
+                     "%zu, needs %zu",
+                     length, width, height, stride, required);
+        return false;
+    }
+
+    clear_outside_canvas(cs, area, pixels, stride);
+    struct DP_FlattenIntoParams params = {pixels, stride};
+    flatten_canvas(cs, flags, &area, vmf_or_null, flatten_into_get_buffer,
+                   flatten_into_to_buffer, &params);
+    return true;
 }
 
 DP_Image *DP_canvas_state_to_flat_image(DP_CanvasState *cs, unsigned int flags,
@@ -1483,9 +2355,26 @@ DP_Image *DP_canvas_state_to_flat_image(DP_CanvasState *cs, unsigned int flags,
 {
     DP_ASSERT(cs);
     DP_ASSERT(DP_atomic_get(&cs->refcount) > 0);
-    return flatten_canvas(cs, flags, area_or_null, vmf_or_null,
-                          to_flat_image_get_buffer, to_flat_image_to_buffer,
-                          NULL);
+    DP_Rect area = area_or_null ? *area_or_null
+                                : DP_rect_make(0, 0, cs->width, cs->height);
+    if (!DP_rect_valid(area)) {
+        DP_error_set("Can't create a flat image with zero pixels");
+        return NULL;
+    }
+
+    int width = DP_rect_width(area);
+    int height = DP_rect_height(area);
+    DP_Image *img = DP_image_new(width, height);
+    size_t length = DP_int_to_size(width) * DP_int_to_size(height);
+    if (DP_canvas_state_flatten_into(cs, flags, &area, vmf_or_null,
+                                     DP_image_pixels(img), length,
+                                     DP_int_to_size(width))) {
+        return img;
+    }
+    else {
+        DP_image_free(img);
+        return NULL;
+    }
 }
 
 static void *to_flat_separated_urgba8_get_buffer(void *user,
@@ -1540,8 +2429,38 @@ DP_canvas_state_flatten_tile(DP_CanvasState *cs, int tile_index,
     bool include_sublayers = flags & DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
     DP_ViewModeFilter vmf =
         vmf_or_null ? *vmf_or_null : DP_view_mode_filter_make_default();
-    return DP_canvas_state_flatten_tile_to(cs, tile_index, tt,
-                                           include_sublayers, &vmf);
+    DP_CanvasState *flat_cs = get_flat_canvas_state_inc(cs, flags);
+    tt = DP_canvas_state_flatten_tile_to(flat_cs, tile_index, tt,
+                                         include_sublayers, &vmf);
+    DP_canvas_state_decref(flat_cs);
+    return tt;
+}
+
+DP_TransientTile *DP_canvas_state_flatten_tile_layers(
+    DP_CanvasState *cs, int tile_index, DP_TransientTile *tt_or_null,
+    unsigned int flags, int start, int end)
+{
+    DP_ASSERT(cs);
+    DP_ASSERT(tile_index >= 0);
+    DP_ASSERT(tile_index < DP_tile_total_round(cs->width, cs->height));
+    DP_ASSERT(start >= 0);
+    DP_ASSERT(start <= end);
+    DP_ASSERT(end <= DP_layer_list_count(cs->layers));
+    DP_TransientTile *tt = tt_or_null;
+    if (!tt) {
+        DP_Tile *background_tile = get_flat_background_tile_or_null(cs, flags);
+        tt = background_tile ? DP_transient_tile_new(background_tile, 0)
+                             : DP_transient_tile_new_blank(0);
+    }
+    DP_CanvasState *flat_cs = get_flat_canvas_state_inc(cs, flags);
+    DP_ViewModeFilter vmf = DP_view_mode_filter_make_default();
+    DP_ViewModeContextRoot vmcr =
+        DP_view_mode_context_root_init(&vmf, flat_cs);
+    tt = flatten_tile_range_to(flat_cs, tile_index, tt,
+                               flags & DP_FLAT_IMAGE_INCLUDE_SUBLAYERS, &vmcr,
+                               start, end);
+    DP_canvas_state_decref(flat_cs);
+    return tt;
 }
 
 DP_TransientTile *
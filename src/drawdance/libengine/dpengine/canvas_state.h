@@ -43,9 +43,20 @@ typedef struct DP_ViewModeFilter DP_ViewModeFilter;
 
 #define DP_FLAT_IMAGE_INCLUDE_BACKGROUND (1 << 0)
 #define DP_FLAT_IMAGE_INCLUDE_SUBLAYERS  (1 << 1)
+// Flattening is spread over all CPU cores unless this flag is given. The
+// output is the same either way.
+#define DP_FLAT_IMAGE_SINGLE_THREADED    (1 << 2)
+// Censored layers get flattened with their actual content instead of the
+// censor pattern, like for someone who chose to reveal them.
+#define DP_FLAT_IMAGE_REVEAL_CENSORED    (1 << 3)
 #define DP_FLAT_IMAGE_RENDER_FLAGS \
     (DP_FLAT_IMAGE_INCLUDE_BACKGROUND | DP_FLAT_IMAGE_INCLUDE_SUBLAYERS)
 
+typedef struct DP_SketchLayer {
+    int layer_id;
+    uint16_t opacity;
+} DP_SketchLayer;
+
 typedef struct DP_CanvasState DP_CanvasState;
 
 #ifdef DP_NO_STRICT_ALIASING
@@ -128,14 +139,62 @@ int DP_canvas_state_search_change_bounds(DP_CanvasState *cs,
                                          int *out_y, int *out_width,
                                          int *out_height);
 
+// Returns the id of the topmost visible layer whose pixel at the given point
+// has an alpha greater than min_alpha, taking layer and group opacity into
+// account. Censored layers are skipped unless reveal_censored is set. Always
+// returns a leaf layer, never a group. Returns 0 if nothing was found or the
+// point is outside of the canvas.
+int DP_canvas_state_pick_layer(DP_CanvasState *cs, int x, int y,
+                               uint8_t min_alpha, bool reveal_censored);
+
+// Fills the given user bits, which must have room for 256 users, with who last
+// changed the tiles of visible layers touching the given area, including
+// strokes in progress. Tiles only remember who touched them as a whole, so
+// this works in tile granularity. Bit 0 stands for tiles that came out of
+// merging the work of several users. Returns how many bits were set.
+int DP_canvas_state_touched_by(DP_CanvasState *cs, const DP_Rect *area,
+                               uint8_t *out_users);
+
+// Checksum over the canvas size, background, layer tree with the attributes
+// of each layer and the annotations, for comparing canvas states between
+// clients. The result doesn't depend on the platform or memory addresses.
+// Local-only properties like layer visibility aren't included.
+uint64_t DP_canvas_state_checksum(DP_CanvasState *cs);
+
+// Fills the given buffer, which must have room for DP_tile_total_round(width,
+// height) elements, with a checksum for each tile position in row-major order.
+// Each one covers the background and the tiles of all layers at that position,
+// which allows localizing where two canvas states differ.
+void DP_canvas_state_tile_checksums(DP_CanvasState *cs,
+                                    uint64_t *out_checksums);
+
+// Returns the canvas state with the given layers at the given opacities
+// instead, for flattening layers in sketch mode without changing them for
+// anyone else. Unknown layer ids are skipped. If nothing changes, that's just
+// the canvas state with its refcount incremented.
+DP_CanvasState *DP_canvas_state_sketch_inc(DP_CanvasState *cs, int count,
+                                           const DP_SketchLayer *sketches);
+
+// Only tiles touching the given area get flattened, the rest stay blank.
 DP_TransientLayerContent *
 DP_canvas_state_to_flat_layer(DP_CanvasState *cs, unsigned int flags,
+                              const DP_Rect *area_or_null,
                               const DP_ViewModeFilter *vmf_or_null);
 
 DP_Image *DP_canvas_state_to_flat_image(DP_CanvasState *cs, unsigned int flags,
                                         const DP_Rect *area_or_null,
                                         const DP_ViewModeFilter *vmf_or_null);
 
+// Flattens into the given buffer of at least length pixels, with rows that
+// are stride pixels apart. Only the pixels of the area are written to, any
+// padding in between rows is left alone. Parts of the area outside of the
+// canvas are made transparent. Returns false if the buffer is too small.
+bool DP_canvas_state_flatten_into(DP_CanvasState *cs, unsigned int flags,
+                                  const DP_Rect *area_or_null,
+                                  const DP_ViewModeFilter *vmf_or_null,
+                                  DP_Pixel8 *pixels, size_t length,
+                                  size_t stride);
+
 bool DP_canvas_state_to_flat_separated_urgba8(
     DP_CanvasState *cs, unsigned int flags, const DP_Rect *area_or_null,
     const DP_ViewModeFilter *vmf_or_null, unsigned char *buffer);
@@ -151,6 +210,14 @@ DP_canvas_state_flatten_tile(DP_CanvasState *cs, int tile_index,
                              unsigned int flags,
                              const DP_ViewModeFilter *vmf_or_null);
 
+// Flattens the top-level layers from start up to, but not including, end onto
+// the given tile, or onto a copy of the background if it's NULL. Flattening
+// the bottom layers and then the ones above onto the result gives the same
+// tile as flattening them all at once. Always uses the default view mode.
+DP_TransientTile *DP_canvas_state_flatten_tile_layers(
+    DP_CanvasState *cs, int tile_index, DP_TransientTile *tt_or_null,
+    unsigned int flags, int start, int end);
+
 DP_TransientTile *
 DP_canvas_state_flatten_tile_at(DP_CanvasState *cs, int x, int y,
                                 unsigned int flags,
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: MIT
+#include "catchup.h"
+#include "canvas_history.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/queue.h>
+#include <dpcommon/threading.h>
+#include <dpmsg/message.h>
+#include <dpmsg/message_queue.h>
+
+
+struct DP_Catchup {
+    DP_Mutex *mutex;
+    DP_Queue queue;
+    int handled;
+};
+
+
+DP_Catchup *DP_catchup_new(void)
+{
+    DP_Mutex *mutex = DP_mutex_new();
+    if (!mutex) {
+        return NULL;
+    }
+    DP_Catchup *c = DP_malloc(sizeof(*c));
+    *c = (DP_Catchup){mutex, DP_QUEUE_NULL, 0};
+    DP_message_queue_init(&c->queue, 64);
+    return c;
+}
+
+void DP_catchup_free(DP_Catchup *c)
+{
+    if (c) {
+        DP_message_queue_dispose(&c->queue);
+        DP_mutex_free(c->mutex);
+        DP_free(c);
+    }
+}
+
+
+void DP_catchup_push_noinc(DP_Catchup *c, DP_Message *msg)
+{
+    DP_ASSERT(c);
+    DP_ASSERT(msg);
+    DP_MUTEX_MUST_LOCK(c->mutex);
+    // A new catch-up starts when the previous one was already done.
+    if (c->queue.used == 0) {
+        c->handled = 0;
+    }
+    DP_message_queue_push_noinc(&c->queue, msg);
+    DP_MUTEX_MUST_UNLOCK(c->mutex);
+}
+
+void DP_catchup_push_inc(DP_Catchup *c, DP_Message *msg)
+{
+    DP_catchup_push_noinc(c, DP_message_incref(msg));
+}
+
+
+int DP_catchup_queued(DP_Catchup *c)
+{
+    DP_ASSERT(c);
+    DP_MUTEX_MUST_LOCK(c->mutex);
+    int queued = DP_size_to_int(c->queue.used);
+    DP_MUTEX_MUST_UNLOCK(c->mutex);
+    return queued;
+}
+
+int DP_catchup_handled(DP_Catchup *c)
+{
+    DP_ASSERT(c);
+    DP_MUTEX_MUST_LOCK(c->mutex);
+    int handled = c->handled;
+    DP_MUTEX_MUST_UNLOCK(c->mutex);
+    return handled;
+}
+
+int DP_catchup_progress(DP_Catchup *c)
+{
+    DP_ASSERT(c);
+    DP_MUTEX_MUST_LOCK(c->mutex);
+    long long queued = DP_size_to_llong(c->queue.used);
+    long long handled = c->handled;
+    DP_MUTEX_MUST_UNLOCK(c->mutex);
+    return queued == 0 ? 100
+                       : DP_llong_to_int(handled * 100LL / (handled + queued));
+}
+
+
+static void handle_message(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                           DP_Message *msg)
+{
+    DP_MessageType type = DP_message_type(msg);
+    if (type == DP_MSG_UNDO_DEPTH) {
+        DP_MsgUndoDepth *mud = DP_message_internal(msg);
+        DP_canvas_history_undo_depth_limit_set(ch, dc,
+                                               DP_msg_undo_depth_depth(mud));
+    }
+    else if (type == DP_MSG_SOFT_RESET) {
+        DP_canvas_history_soft_reset(ch, dc, DP_message_context_id(msg), NULL,
+                                     NULL);
+    }
+    else if (type != DP_MSG_INTERNAL) {
+        if (!DP_canvas_history_handle(ch, dc, msg)) {
+            DP_warn("Handle catch-up command: %s", DP_error());
+        }
+    }
+}
+
+int DP_catchup_step(DP_Catchup *c, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                    int max_count)
+{
+    DP_ASSERT(c);
+    DP_ASSERT(ch);
+    DP_ASSERT(dc);
+    int count = 0;
+    while (count < max_count) {
+        // Only hold the lock to take out the next message, so that pushing
+        // doesn't have to wait for it to be handled.
+        DP_MUTEX_MUST_LOCK(c->mutex);
+        DP_Message *msg = DP_message_queue_shift(&c->queue);
+        if (msg) {
+            ++c->handled;
+        }
+        DP_MUTEX_MUST_UNLOCK(c->mutex);
+        if (!msg) {
+            break;
+        }
+        handle_message(ch, dc, msg);
+        DP_message_decref(msg);
+        ++count;
+    }
+    return count;
+}
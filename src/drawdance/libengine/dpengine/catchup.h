@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_CATCHUP_H
+#define DPENGINE_CATCHUP_H
+#include <dpcommon/common.h>
+
+typedef struct DP_CanvasHistory DP_CanvasHistory;
+typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_Message DP_Message;
+
+
+// Queue of messages to catch up on, like when joining a long session or
+// opening a big recording. Messages can be pushed from any thread. Stepping
+// handles them in bounded batches, so whoever drives it can interleave other
+// work, like rendering a progress bar. The queue lock is only held to take
+// messages out, the canvas history's lock only for swapping in new states.
+typedef struct DP_Catchup DP_Catchup;
+
+DP_Catchup *DP_catchup_new(void);
+
+void DP_catchup_free(DP_Catchup *c);
+
+void DP_catchup_push_noinc(DP_Catchup *c, DP_Message *msg);
+
+void DP_catchup_push_inc(DP_Catchup *c, DP_Message *msg);
+
+// Messages that haven't been handled yet.
+int DP_catchup_queued(DP_Catchup *c);
+
+// Messages taken out of the queue to be handled since it last ran dry.
+int DP_catchup_handled(DP_Catchup *c);
+
+// Percentage of the catch-up that's done, from 0 to 100. An empty queue is
+// always done. Pushing more messages while catching up lowers it again.
+int DP_catchup_progress(DP_Catchup *c);
+
+// Handles up to max_count messages in the given history, undo depth messages
+// are handled like the paint engine does. Must only be called from one thread
+// at a time. Returns how many messages were handled, 0 once caught up.
+int DP_catchup_step(DP_Catchup *c, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                    int max_count);
+
+
+#endif
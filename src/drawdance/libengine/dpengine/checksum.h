@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_CHECKSUM_H
+#define DPENGINE_CHECKSUM_H
+#include <dpcommon/common.h>
+
+
+// 64 bit FNV-1a, used to checksum canvas contents so that clients can compare
+// their states. Values are fed in byte by byte in little-endian order, so the
+// results are the same on every platform. This is not a cryptographic hash.
+
+#define DP_CHECKSUM_INIT  0xcbf29ce484222325u
+#define DP_CHECKSUM_PRIME 0x100000001b3u
+
+DP_INLINE uint64_t DP_checksum_uint8(uint64_t checksum, uint8_t value)
+{
+    return (checksum ^ value) * DP_CHECKSUM_PRIME;
+}
+
+DP_INLINE uint64_t DP_checksum_uint16(uint64_t checksum, uint16_t value)
+{
+    checksum = DP_checksum_uint8(checksum, (uint8_t)(value & 0xffu));
+    return DP_checksum_uint8(checksum, (uint8_t)(value >> 8u));
+}
+
+DP_INLINE uint64_t DP_checksum_uint32(uint64_t checksum, uint32_t value)
+{
+    checksum = DP_checksum_uint16(checksum, (uint16_t)(value & 0xffffu));
+    return DP_checksum_uint16(checksum, (uint16_t)(value >> 16u));
+}
+
+DP_INLINE uint64_t DP_checksum_uint64(uint64_t checksum, uint64_t value)
+{
+    checksum = DP_checksum_uint32(checksum, (uint32_t)(value & 0xffffffffu));
+    return DP_checksum_uint32(checksum, (uint32_t)(value >> 32u));
+}
+
+DP_INLINE uint64_t DP_checksum_int(uint64_t checksum, int value)
+{
+    return DP_checksum_uint32(checksum, (uint32_t)value);
+}
+
+DP_INLINE uint64_t DP_checksum_bool(uint64_t checksum, bool value)
+{
+    return DP_checksum_uint8(checksum, value ? 1u : 0u);
+}
+
+DP_INLINE uint64_t DP_checksum_bytes(uint64_t checksum, const void *data,
+                                     size_t length)
+{
+    const unsigned char *bytes = data;
+    checksum = DP_checksum_uint64(checksum, (uint64_t)length);
+    for (size_t i = 0; i < length; ++i) {
+        checksum = DP_checksum_uint8(checksum, bytes[i]);
+    }
+    return checksum;
+}
+
+
+#endif
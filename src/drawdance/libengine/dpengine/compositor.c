@@ -0,0 +1,608 @@
+// SPDX-License-Identifier: MIT
+#include "compositor.h"
+#include "affected_area.h"
+#include "canvas_state.h"
+#include "layer_content.h"
+#include "layer_list.h"
+#include "layer_routes.h"
+#include "pixels.h"
+#include "tile.h"
+#include "tile_iterator.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+
+// Pixels added around affected areas when figuring out which tiles to
+// recomposite, in case antialiasing spills over the bounds.
+#define UPDATE_MARGIN 2
+
+// Scales 2, 4, 8 and 16 get cached, a scale of 1 is the composite itself.
+#define SCALE_LEVEL_COUNT 4
+
+// A composited tile downscaled into a block of DP_TILE_SIZE / scale pixels
+// on each side. Kept in a least recently used list, the first one being the
+// one used most recently.
+typedef struct DP_ScaledTile DP_ScaledTile;
+struct DP_ScaledTile {
+    DP_ScaledTile *prev;
+    DP_ScaledTile *next;
+    int entry_index;
+    size_t size;
+    DP_Pixel8 pixels[];
+};
+
+typedef struct DP_ScaleCache {
+    size_t budget;
+    size_t used;
+    int count;
+    int tile_count;
+    DP_ScaledTile **entries;
+    DP_ScaledTile *first;
+    DP_ScaledTile *last;
+} DP_ScaleCache;
+
+// The background and the bottom top-level layers flattened, for each tile.
+// Tiles get flattened onto it lazily, a NULL tile with valid set is blank.
+typedef struct DP_BaseTile {
+    bool valid;
+    DP_Tile *t;
+} DP_BaseTile;
+
+typedef struct DP_BaseCache {
+    int wanted;
+    int layer_count;
+    int tile_count;
+    DP_BaseTile *tiles;
+} DP_BaseCache;
+
+struct DP_Compositor {
+    unsigned int flags;
+    bool invalid;
+    DP_TransientLayerContent *tlc;
+    DP_ScaleCache scale_cache;
+    DP_BaseCache base_cache;
+};
+
+DP_Compositor *DP_compositor_new(unsigned int flags)
+{
+    DP_Compositor *c = DP_malloc(sizeof(*c));
+    *c = (DP_Compositor){flags,
+                         true,
+                         NULL,
+                         {0, 0, 0, 0, NULL, NULL, NULL},
+                         {0, 0, 0, NULL}};
+    return c;
+}
+
+
+static void scale_cache_unlink(DP_ScaleCache *sc, DP_ScaledTile *st)
+{
+    if (st->prev) {
+        st->prev->next = st->next;
+    }
+    else {
+        sc->first = st->next;
+    }
+    if (st->next) {
+        st->next->prev = st->prev;
+    }
+    else {
+        sc->last = st->prev;
+    }
+}
+
+static void scale_cache_push(DP_ScaleCache *sc, DP_ScaledTile *st)
+{
+    st->prev = NULL;
+    st->next = sc->first;
+    if (sc->first) {
+        sc->first->prev = st;
+    }
+    else {
+        sc->last = st;
+    }
+    sc->first = st;
+}
+
+static void scale_cache_remove(DP_ScaleCache *sc, DP_ScaledTile *st)
+{
+    scale_cache_unlink(sc, st);
+    sc->entries[st->entry_index] = NULL;
+    sc->used -= st->size;
+    --sc->count;
+    DP_free(st);
+}
+
+static void scale_cache_evict(DP_ScaleCache *sc, size_t budget)
+{
+    while (sc->last && sc->used > budget) {
+        scale_cache_remove(sc, sc->last);
+    }
+}
+
+static void scale_cache_clear(DP_ScaleCache *sc)
+{
+    scale_cache_evict(sc, 0);
+    DP_free(sc->entries);
+    sc->entries = NULL;
+    sc->tile_count = 0;
+}
+
+static void scale_cache_invalidate(DP_ScaleCache *sc, int tile_index)
+{
+    if (sc->entries) {
+        for (int i = 0; i < SCALE_LEVEL_COUNT; ++i) {
+            DP_ScaledTile *st = sc->entries[i * sc->tile_count + tile_index];
+            if (st) {
+                scale_cache_remove(sc, st);
+            }
+        }
+    }
+}
+
+
+static void base_cache_invalidate(DP_BaseCache *bc, int tile_index)
+{
+    DP_BaseTile *bt = &bc->tiles[tile_index];
+    if (bt->valid) {
+        DP_tile_decref_nullable(bt->t);
+        *bt = (DP_BaseTile){false, NULL};
+    }
+}
+
+static void base_cache_invalidate_all(DP_BaseCache *bc)
+{
+    for (int i = 0; i < bc->tile_count; ++i) {
+        base_cache_invalidate(bc, i);
+    }
+}
+
+static void base_cache_clear(DP_BaseCache *bc)
+{
+    if (bc->tiles) {
+        base_cache_invalidate_all(bc);
+        DP_free(bc->tiles);
+        bc->tiles = NULL;
+    }
+    bc->layer_count = 0;
+    bc->tile_count = 0;
+}
+
+// Starts over with nothing cached yet. In automatic mode, every layer is part
+// of the base until one of them gets changed.
+static void base_cache_reset(DP_BaseCache *bc, DP_CanvasState *cs)
+{
+    base_cache_clear(bc);
+    int layer_count = DP_layer_list_count(DP_canvas_state_layers_noinc(cs));
+    int tile_count = DP_tile_total_round(DP_canvas_state_width(cs),
+                                         DP_canvas_state_height(cs));
+    if (bc->wanted != 0 && layer_count > 0 && tile_count > 0) {
+        bc->layer_count = bc->wanted == DP_COMPOSITOR_BASE_LAYERS_AUTO
+                            ? layer_count
+                            : DP_min_int(bc->wanted, layer_count);
+        bc->tile_count = tile_count;
+        bc->tiles = DP_malloc_zeroed(sizeof(*bc->tiles)
+                                     * DP_int_to_size(tile_count));
+    }
+}
+
+// Index of the top-level layer the given layer is in, or 0 if it's not found,
+// since then there's no telling which layers got changed.
+static int top_level_index(DP_CanvasState *cs, int layer_id)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return lre ? DP_layer_routes_entry_index_at(lre, 0) : 0;
+}
+
+// A change to pixels of a layer in the base means the base of the affected
+// tiles has to be flattened again. In automatic mode, the base gets shrunk
+// down to the layers below the changed one instead, which changes it for
+// every tile.
+static bool base_cache_affected(DP_BaseCache *bc, DP_CanvasState *cs,
+                                int layer_id)
+{
+    if (bc->layer_count == 0) {
+        return false;
+    }
+    int index = top_level_index(cs, layer_id);
+    if (index >= bc->layer_count) {
+        return false;
+    }
+    else if (bc->wanted == DP_COMPOSITOR_BASE_LAYERS_AUTO) {
+        base_cache_invalidate_all(bc);
+        bc->layer_count = index;
+        return false;
+    }
+    else {
+        return true;
+    }
+}
+
+static DP_TransientTile *flatten_tile(DP_Compositor *c, DP_CanvasState *cs,
+                                      int tile_index)
+{
+    DP_BaseCache *bc = &c->base_cache;
+    int base_layer_count = bc->layer_count;
+    if (base_layer_count == 0) {
+        return DP_canvas_state_flatten_tile(cs, tile_index, c->flags, NULL);
+    }
+
+    DP_BaseTile *bt = &bc->tiles[tile_index];
+    if (!bt->valid) {
+        DP_TransientTile *base_tt = DP_canvas_state_flatten_tile_layers(
+            cs, tile_index, NULL, c->flags, 0, base_layer_count);
+        DP_Tile *t = DP_transient_tile_persist(base_tt);
+        if (DP_tile_blank(t)) {
+            DP_tile_decref(t);
+            t = NULL;
+        }
+        *bt = (DP_BaseTile){true, t};
+    }
+
+    int layer_count = DP_layer_list_count(DP_canvas_state_layers_noinc(cs));
+    DP_TransientTile *tt = bt->t ? DP_transient_tile_new(bt->t, 0)
+                                 : DP_transient_tile_new_blank(0);
+    return DP_canvas_state_flatten_tile_layers(cs, tile_index, tt, c->flags,
+                                               base_layer_count, layer_count);
+}
+
+
+void DP_compositor_free(DP_Compositor *c)
+{
+    if (c) {
+        base_cache_clear(&c->base_cache);
+        scale_cache_clear(&c->scale_cache);
+        if (c->tlc) {
+            DP_transient_layer_content_decref(c->tlc);
+        }
+        DP_free(c);
+    }
+}
+
+int DP_compositor_width(DP_Compositor *c)
+{
+    DP_ASSERT(c);
+    return c->tlc ? DP_transient_layer_content_width(c->tlc) : 0;
+}
+
+int DP_compositor_height(DP_Compositor *c)
+{
+    DP_ASSERT(c);
+    return c->tlc ? DP_transient_layer_content_height(c->tlc) : 0;
+}
+
+void DP_compositor_invalidate(DP_Compositor *c)
+{
+    DP_ASSERT(c);
+    c->invalid = true;
+}
+
+static bool needs_full_update(DP_Compositor *c, DP_CanvasState *cs,
+                              const DP_AffectedArea *aa)
+{
+    if (c->invalid || !c->tlc
+        || DP_canvas_state_width(cs) != DP_compositor_width(c)
+        || DP_canvas_state_height(cs) != DP_compositor_height(c)) {
+        return true;
+    }
+    switch (aa->domain) {
+    case DP_AFFECTED_DOMAIN_LAYER_ATTRS:
+    case DP_AFFECTED_DOMAIN_CANVAS_BACKGROUND:
+    case DP_AFFECTED_DOMAIN_EVERYTHING:
+        return true;
+    default:
+        return false;
+    }
+}
+
+static bool update_full(DP_Compositor *c, DP_CanvasState *cs,
+                        DP_Rect *out_bounds_or_null)
+{
+    if (c->tlc) {
+        DP_transient_layer_content_decref(c->tlc);
+    }
+    c->tlc = DP_canvas_state_to_flat_layer(cs, c->flags, NULL, NULL);
+    c->invalid = false;
+    scale_cache_clear(&c->scale_cache);
+    base_cache_reset(&c->base_cache, cs);
+
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    if (width > 0 && height > 0) {
+        if (out_bounds_or_null) {
+            *out_bounds_or_null = DP_rect_make(0, 0, width, height);
+        }
+        return true;
+    }
+    else {
+        return false;
+    }
+}
+
+static bool update_area(DP_Compositor *c, DP_CanvasState *cs,
+                        const DP_AffectedArea *aa, DP_Rect *out_bounds_or_null)
+{
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    DP_Rect bounds;
+    if (aa->domain != DP_AFFECTED_DOMAIN_PIXELS
+        || !DP_affected_area_bounds(aa, width, height, &bounds)) {
+        return false;
+    }
+
+    DP_TileIterator ti = DP_tile_iterator_make(
+        width, height,
+        (DP_Rect){bounds.x1 - UPDATE_MARGIN, bounds.y1 - UPDATE_MARGIN,
+                  bounds.x2 + UPDATE_MARGIN, bounds.y2 + UPDATE_MARGIN});
+    bool base_affected =
+        base_cache_affected(&c->base_cache, cs, aa->affected_id);
+    int xtiles = DP_tile_count_round(width);
+    while (DP_tile_iterator_next(&ti)) {
+        int tile_index = ti.row * xtiles + ti.col;
+        if (base_affected) {
+            base_cache_invalidate(&c->base_cache, tile_index);
+        }
+        DP_TransientTile *tt = flatten_tile(c, cs, tile_index);
+        DP_transient_layer_content_transient_tile_set_noinc(c->tlc, tt,
+                                                            tile_index);
+        scale_cache_invalidate(&c->scale_cache, tile_index);
+    }
+
+    if (out_bounds_or_null) {
+        DP_Rect tile_area = ti.tile_area;
+        DP_Rect tile_bounds = {
+            tile_area.x1 * DP_TILE_SIZE, tile_area.y1 * DP_TILE_SIZE,
+            (tile_area.x2 + 1) * DP_TILE_SIZE - 1,
+            (tile_area.y2 + 1) * DP_TILE_SIZE - 1};
+        *out_bounds_or_null = DP_rect_intersection(
+            tile_bounds, DP_rect_make(0, 0, width, height));
+    }
+    return true;
+}
+
+bool DP_compositor_update(DP_Compositor *c, DP_CanvasState *cs,
+                          const DP_AffectedArea *aa,
+                          DP_Rect *out_bounds_or_null)
+{
+    DP_ASSERT(c);
+    DP_ASSERT(cs);
+    DP_ASSERT(aa);
+    if (needs_full_update(c, cs, aa)) {
+        return update_full(c, cs, out_bounds_or_null);
+    }
+    else {
+        return update_area(c, cs, aa, out_bounds_or_null);
+    }
+}
+
+DP_Tile *DP_compositor_tile_at_noinc(DP_Compositor *c, int x, int y)
+{
+    DP_ASSERT(c);
+    DP_TransientLayerContent *tlc = c->tlc;
+    if (tlc && x >= 0 && y >= 0
+        && x < DP_tile_count_round(DP_transient_layer_content_width(tlc))
+        && y < DP_tile_count_round(DP_transient_layer_content_height(tlc))) {
+        return DP_transient_layer_content_tile_at_noinc(tlc, x, y);
+    }
+    else {
+        return NULL;
+    }
+}
+
+DP_Pixel8 *DP_compositor_to_pixels8(DP_Compositor *c, int x, int y,
+                                    int width, int height)
+{
+    DP_ASSERT(c);
+    DP_ASSERT(width > 0);
+    DP_ASSERT(height > 0);
+    if (c->tlc) {
+        return DP_layer_content_to_pixels8((DP_LayerContent *)c->tlc, x, y,
+                                           width, height);
+    }
+    else {
+        return DP_malloc_zeroed(sizeof(DP_Pixel8) * DP_int_to_size(width)
+                                * DP_int_to_size(height));
+    }
+}
+
+
+void DP_compositor_base_layers_set(DP_Compositor *c, int count)
+{
+    DP_ASSERT(c);
+    DP_ASSERT(count >= 0 || count == DP_COMPOSITOR_BASE_LAYERS_AUTO);
+    if (c->base_cache.wanted != count) {
+        c->base_cache.wanted = count;
+        base_cache_clear(&c->base_cache);
+        c->invalid = true;
+    }
+}
+
+int DP_compositor_base_layers(DP_Compositor *c)
+{
+    DP_ASSERT(c);
+    return c->base_cache.layer_count;
+}
+
+
+void DP_compositor_scale_cache_budget_set(DP_Compositor *c, size_t budget)
+{
+    DP_ASSERT(c);
+    c->scale_cache.budget = budget;
+    scale_cache_evict(&c->scale_cache, budget);
+}
+
+size_t DP_compositor_scale_cache_bytes(DP_Compositor *c)
+{
+    DP_ASSERT(c);
+    return c->scale_cache.used;
+}
+
+int DP_compositor_scale_cache_count(DP_Compositor *c)
+{
+    DP_ASSERT(c);
+    return c->scale_cache.count;
+}
+
+static int scale_level(int scale)
+{
+    int level = 0;
+    for (int s = scale; s > 2; s /= 2) {
+        ++level;
+    }
+    return level;
+}
+
+static void fill_pixels8(DP_Pixel8 *pixels, int count, DP_Pixel8 pixel)
+{
+    for (int i = 0; i < count; ++i) {
+        pixels[i] = pixel;
+    }
+}
+
+// Averages each block of pixels, the ones within the tile but outside of the
+// canvas, past the given width and height, count as transparent.
+static void scale_tile(DP_Pixel8 *dst, DP_Tile *t, int scale, int width,
+                       int height)
+{
+    const DP_Pixel15 *src = DP_tile_pixels(t);
+    int size = DP_TILE_SIZE / scale;
+    uint32_t area = DP_int_to_uint32(scale * scale);
+    for (int by = 0; by < size; ++by) {
+        int y1 = by * scale;
+        int y2 = DP_min_int(y1 + scale, height);
+        for (int bx = 0; bx < size; ++bx) {
+            int x1 = bx * scale;
+            int x2 = DP_min_int(x1 + scale, width);
+            uint32_t b = 0, g = 0, r = 0, a = 0;
+            for (int y = y1; y < y2; ++y) {
+                for (int x = x1; x < x2; ++x) {
+                    DP_Pixel15 pixel = src[y * DP_TILE_SIZE + x];
+                    b += pixel.b;
+                    g += pixel.g;
+                    r += pixel.r;
+                    a += pixel.a;
+                }
+            }
+            uint32_t half = area / 2;
+            dst[by * size + bx] = DP_pixel15_to_8((DP_Pixel15){
+                DP_uint32_to_uint16((b + half) / area),
+                DP_uint32_to_uint16((g + half) / area),
+                DP_uint32_to_uint16((r + half) / area),
+                DP_uint32_to_uint16((a + half) / area),
+            });
+        }
+    }
+}
+
+static void scale_cache_ensure(DP_ScaleCache *sc, int tile_count)
+{
+    if (!sc->entries) {
+        size_t count = DP_int_to_size(SCALE_LEVEL_COUNT * tile_count);
+        sc->entries = DP_malloc_zeroed(sizeof(*sc->entries) * count);
+        sc->tile_count = tile_count;
+    }
+    DP_ASSERT(sc->tile_count == tile_count);
+}
+
+// Returns a block of scaled pixels for the given tile, either out of the
+// cache or scaled into the given buffer.
+static const DP_Pixel8 *get_scaled_tile(DP_Compositor *c, int scale, int col,
+                                        int row, DP_Pixel8 *buffer)
+{
+    int canvas_width = DP_transient_layer_content_width(c->tlc);
+    int canvas_height = DP_transient_layer_content_height(c->tlc);
+    int width = DP_min_int(canvas_width - col * DP_TILE_SIZE, DP_TILE_SIZE);
+    int height = DP_min_int(canvas_height - row * DP_TILE_SIZE, DP_TILE_SIZE);
+    int size = DP_TILE_SIZE / scale;
+    DP_Tile *t = DP_transient_layer_content_tile_at_noinc(c->tlc, col, row);
+
+    // Uniform tiles average out to the same pixel, not worth caching.
+    DP_Pixel15 pixel;
+    if (!t) {
+        fill_pixels8(buffer, size * size, (DP_Pixel8){0});
+        return buffer;
+    }
+    else if (width == DP_TILE_SIZE && height == DP_TILE_SIZE
+             && DP_tile_same_pixel(t, &pixel)) {
+        fill_pixels8(buffer, size * size, DP_pixel15_to_8(pixel));
+        return buffer;
+    }
+
+    DP_ScaleCache *sc = &c->scale_cache;
+    size_t bytes =
+        DP_FLEX_SIZEOF(DP_ScaledTile, pixels, DP_int_to_size(size * size));
+    if (bytes > sc->budget) {
+        scale_tile(buffer, t, scale, width, height);
+        return buffer;
+    }
+
+    int xtiles = DP_tile_count_round(canvas_width);
+    int tile_count = xtiles * DP_tile_count_round(canvas_height);
+    scale_cache_ensure(sc, tile_count);
+    int entry_index = scale_level(scale) * tile_count + row * xtiles + col;
+    DP_ScaledTile *st = sc->entries[entry_index];
+    if (st) {
+        scale_cache_unlink(sc, st);
+    }
+    else {
+        scale_cache_evict(sc, sc->budget - bytes);
+        st = DP_malloc(bytes);
+        st->entry_index = entry_index;
+        st->size = bytes;
+        scale_tile(st->pixels, t, scale, width, height);
+        sc->entries[entry_index] = st;
+        sc->used += bytes;
+        ++sc->count;
+    }
+    scale_cache_push(sc, st);
+    return st->pixels;
+}
+
+DP_Pixel8 *DP_compositor_to_scaled_pixels8(DP_Compositor *c, int scale, int x,
+                                           int y, int width, int height)
+{
+    DP_ASSERT(c);
+    DP_ASSERT(scale > 0);
+    DP_ASSERT(scale <= DP_COMPOSITOR_SCALE_MAX);
+    DP_ASSERT((scale & (scale - 1)) == 0);
+    DP_ASSERT(width > 0);
+    DP_ASSERT(height > 0);
+    if (scale == 1) {
+        return DP_compositor_to_pixels8(c, x, y, width, height);
+    }
+
+    DP_Pixel8 *pixels = DP_malloc_zeroed(
+        sizeof(*pixels) * DP_int_to_size(width) * DP_int_to_size(height));
+    if (!c->tlc) {
+        return pixels;
+    }
+
+    int canvas_width = DP_transient_layer_content_width(c->tlc);
+    int canvas_height = DP_transient_layer_content_height(c->tlc);
+    int scaled_width = (canvas_width + scale - 1) / scale;
+    int scaled_height = (canvas_height + scale - 1) / scale;
+    int left = DP_max_int(x, 0);
+    int top = DP_max_int(y, 0);
+    int right = DP_min_int(x + width, scaled_width);
+    int bottom = DP_min_int(y + height, scaled_height);
+    int size = DP_TILE_SIZE / scale;
+    DP_Pixel8 buffer[(DP_TILE_SIZE / 2) * (DP_TILE_SIZE / 2)];
+
+    for (int row = top / size; row * size < bottom; ++row) {
+        int y1 = DP_max_int(row * size, top);
+        int y2 = DP_min_int((row + 1) * size, bottom);
+        for (int col = left / size; col * size < right; ++col) {
+            int x1 = DP_max_int(col * size, left);
+            int x2 = DP_min_int((col + 1) * size, right);
+            const DP_Pixel8 *src = get_scaled_tile(c, scale, col, row, buffer);
+            for (int py = y1; py < y2; ++py) {
+                memcpy(&pixels[(py - y) * width + (x1 - x)],
+                       &src[(py - row * size) * size + (x1 - col * size)],
+                       sizeof(*pixels) * DP_int_to_size(x2 - x1));
+            }
+        }
+    }
+
+    return pixels;
+}
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_COMPOSITOR_H
+#define DPENGINE_COMPOSITOR_H
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+typedef struct DP_AffectedArea DP_AffectedArea;
+typedef struct DP_CanvasState DP_CanvasState;
+typedef struct DP_Tile DP_Tile;
+typedef union DP_Pixel8 DP_Pixel8;
+
+// Largest scale that can be passed to DP_compositor_to_scaled_pixels8.
+#define DP_COMPOSITOR_SCALE_MAX 16
+
+// Pass to DP_compositor_base_layers_set to put every top-level layer below
+// the lowest one whose pixels changed since the last full recomposite into
+// the base.
+#define DP_COMPOSITOR_BASE_LAYERS_AUTO (-1)
+
+// Keeps a flattened copy of the canvas around and only recomposites the tiles
+// that an update says have changed. Feed it the visible affected area of
+// every message handled, see DP_affected_area_make_visible, along with the
+// resulting canvas state. The first update, a change in canvas size and
+// areas affecting layer attributes, the background or everything recomposite
+// the whole canvas. The flags are the same as for flattening, see
+// DP_canvas_state_to_flat_layer.
+typedef struct DP_Compositor DP_Compositor;
+
+DP_Compositor *DP_compositor_new(unsigned int flags);
+
+void DP_compositor_free(DP_Compositor *c);
+
+int DP_compositor_width(DP_Compositor *c);
+
+int DP_compositor_height(DP_Compositor *c);
+
+// Makes the next update recomposite everything, for changes that don't come
+// from messages, like toggling layer visibility locally.
+void DP_compositor_invalidate(DP_Compositor *c);
+
+// Returns true if anything was recomposited, the affected pixel bounds are
+// put into the out parameter if given. Those are rounded out to whole tiles.
+bool DP_compositor_update(DP_Compositor *c, DP_CanvasState *cs,
+                          const DP_AffectedArea *aa,
+                          DP_Rect *out_bounds_or_null);
+
+// Tile of the composite at the given tile coordinates, or NULL if there is
+// none or it was never composited because it's blank.
+DP_Tile *DP_compositor_tile_at_noinc(DP_Compositor *c, int x, int y);
+
+// Copies the given region of the composite into a newly allocated buffer.
+// Anything outside of the canvas is transparent.
+DP_Pixel8 *DP_compositor_to_pixels8(DP_Compositor *c, int x, int y,
+                                    int width, int height);
+
+// Keeps the background and the given number of bottom top-level layers
+// flattened for each tile, so that recompositing a tile only has to blend the
+// layers above onto that. 0 turns this off, which is the default. Changes to
+// pixels of layers in the base flatten the base of those tiles again, anything
+// that recomposites the whole canvas drops all of it. Changing the count
+// makes the next update recomposite everything.
+void DP_compositor_base_layers_set(DP_Compositor *c, int count);
+
+// Number of top-level layers currently in the base, which may be less than
+// what was asked for if there aren't that many or in automatic mode.
+int DP_compositor_base_layers(DP_Compositor *c);
+
+// Byte budget for caching downscaled versions of composited tiles, 0 turns
+// the cache off, which is the default. Shrinking it evicts the least recently
+// used tiles right away. Recompositing a tile drops its scaled versions.
+void DP_compositor_scale_cache_budget_set(DP_Compositor *c, size_t budget);
+
+size_t DP_compositor_scale_cache_bytes(DP_Compositor *c);
+
+int DP_compositor_scale_cache_count(DP_Compositor *c);
+
+// Like DP_compositor_to_pixels8, but the composite gets scaled down by the
+// given factor first, which must be a power of two up to
+// DP_COMPOSITOR_SCALE_MAX. The region is in scaled coordinates and every
+// pixel is the average of the block of pixels it covers, anything outside of
+// the canvas counting as transparent. Scaled tiles are cached if there's a
+// budget for it, otherwise they get scaled on every call. A scale of 1 is
+// the same as DP_compositor_to_pixels8.
+DP_Pixel8 *DP_compositor_to_scaled_pixels8(DP_Compositor *c, int scale, int x,
+                                           int y, int width, int height);
+
+
+#endif
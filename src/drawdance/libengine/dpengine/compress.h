@@ -24,13 +24,35 @@
 #include <dpcommon/common.h>
 
 
+// Compressed data is in the same format as Qt's qCompress: the uncompressed
+// size as a 32 bit big endian integer, followed by a zlib stream. Image
+// payloads in messages, like those of put image and tile messages, use it.
+
+// The get_output_buffer function is called with the size from the header and
+// should return NULL if that size isn't what it expects, which is what caps
+// the decompressed size. Data that inflates to anything other than that size
+// is an error, so it can't write past the buffer or leave parts of it unset.
 bool DP_compress_inflate(const unsigned char *in, size_t in_size,
                          unsigned char *(*get_output_buffer)(size_t, void *),
                          void *user);
 
+// Returns the compressed size including the header, or 0 on error.
 size_t DP_compress_deflate(const unsigned char *in, size_t in_size,
                            unsigned char *(*get_output_buffer)(size_t, void *),
                            void *user);
 
+// Same as above, but with a zlib compression level from 1 to 9 instead of
+// always using the best one, for when speed matters more than size.
+size_t
+DP_compress_deflate_level(const unsigned char *in, size_t in_size, int level,
+                          unsigned char *(*get_output_buffer)(size_t, void *),
+                          void *user);
+
+// Largest size that compressing the given number of bytes can come out to,
+// including the header. Same as zlib's deflateBound, but usable at compile
+// time.
+#define DP_COMPRESS_DEFLATE_BOUND(SIZE)                                  \
+    ((SIZE) + ((SIZE) >> 12) + ((SIZE) >> 14) + ((SIZE) >> 25) + 13 + 4)
+
 
 #endif
@@ -0,0 +1,1158 @@
+// SPDX-License-Identifier: MIT
+#include "filter.h"
+#include "image.h"
+#include "layer_content.h"
+#include "pixels.h"
+#include "tile.h"
+#include "tile_iterator.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <limits.h>
+#include <math.h>
+#include <string.h>
+
+
+// Takes unpremultiplied channels from 0 to 1 and returns the filtered ones,
+// which get clamped to that range afterwards.
+typedef DP_UPixelFloat (*DP_FilterColorFn)(DP_UPixelFloat color, void *user);
+
+static DP_Rect filter_area(DP_TransientLayerContent *tlc,
+                           const DP_Rect *rect_or_null)
+{
+    DP_Rect layer_rect =
+        DP_rect_make(0, 0, DP_transient_layer_content_width(tlc),
+                     DP_transient_layer_content_height(tlc));
+    return rect_or_null ? DP_rect_intersection(layer_rect, *rect_or_null)
+                        : layer_rect;
+}
+
+static float clamp_float(float c)
+{
+    if (c > 0.0f) {
+        return c < 1.0f ? c : 1.0f;
+    }
+    else {
+        return 0.0f;
+    }
+}
+
+static uint16_t clamp_channel(float c)
+{
+    // Written so that NaN ends up as zero too.
+    if (c > 0.0f) {
+        return c < 1.0f ? DP_channel_float_to_15(c) : (uint16_t)DP_BIT15;
+    }
+    else {
+        return 0;
+    }
+}
+
+static DP_Rect tile_area_at(const DP_TileIterator *ti, DP_Rect area)
+{
+    return DP_rect_intersection(
+        area, DP_rect_make(ti->col * DP_TILE_SIZE, ti->row * DP_TILE_SIZE,
+                           DP_TILE_SIZE, DP_TILE_SIZE));
+}
+
+// Makes the tile at the iterator position writable, putting a new one there if
+// it's currently blank.
+static DP_TransientTile *transient_tile_at(DP_TransientLayerContent *tlc,
+                                           unsigned int context_id,
+                                           const DP_TileIterator *ti,
+                                           DP_Tile *t_or_null)
+{
+    if (t_or_null && DP_tile_transient(t_or_null)) {
+        return (DP_TransientTile *)t_or_null;
+    }
+    else {
+        DP_TransientTile *tt =
+            t_or_null ? DP_transient_tile_new(t_or_null, context_id)
+                      : DP_transient_tile_new_blank(context_id);
+        DP_transient_layer_content_transient_tile_at_set_noinc(tlc, ti->col,
+                                                               ti->row, tt);
+        return tt;
+    }
+}
+
+static bool filter_pixel(bool include_transparent, DP_FilterColorFn fn,
+                         void *user, DP_Pixel15 *pixel)
+{
+    // Transparent pixels usually don't have any color to adjust.
+    if (pixel->a == 0 && !include_transparent) {
+        return false;
+    }
+
+    DP_UPixel15 up = DP_pixel15_unpremultiply(*pixel);
+    DP_UPixelFloat color = fn(DP_upixel15_to_float(up), user);
+    DP_UPixel15 result = {
+        clamp_channel(color.b),
+        clamp_channel(color.g),
+        clamp_channel(color.r),
+        clamp_channel(color.a),
+    };
+    if (result.b == up.b && result.g == up.g && result.r == up.r
+        && result.a == up.a) {
+        return false;
+    }
+
+    DP_Pixel15 filtered = DP_pixel15_premultiply(result);
+    if (DP_pixel15_equal(filtered, *pixel)) {
+        return false;
+    }
+    else {
+        *pixel = filtered;
+        return true;
+    }
+}
+
+static bool filter_tile(DP_TransientLayerContent *tlc, unsigned int context_id,
+                        DP_TileIterator *ti, DP_Rect tile_area,
+                        bool include_transparent, DP_FilterColorFn fn,
+                        void *user)
+{
+    DP_Tile *t =
+        DP_transient_layer_content_tile_at_noinc(tlc, ti->col, ti->row);
+    if (!t && !include_transparent) {
+        return false;
+    }
+
+    // A null source stands for a blank tile.
+    const DP_Pixel15 *src = t ? DP_tile_pixels(t) : NULL;
+    // Getting the transient pixels invalidates the tile's cached state, so
+    // it only happens once, when the first pixel changes.
+    DP_Pixel15 *dst = NULL;
+    int tile_x = ti->col * DP_TILE_SIZE;
+    int tile_y = ti->row * DP_TILE_SIZE;
+    for (int y = tile_area.y1; y <= tile_area.y2; ++y) {
+        for (int x = tile_area.x1; x <= tile_area.x2; ++x) {
+            int i = (y - tile_y) * DP_TILE_SIZE + (x - tile_x);
+            DP_Pixel15 pixel = src ? src[i] : DP_pixel15_zero();
+            if (filter_pixel(include_transparent, fn, user, &pixel)) {
+                if (!dst) {
+                    dst = DP_transient_tile_pixels(
+                        transient_tile_at(tlc, context_id, ti, t));
+                    src = dst;
+                }
+                dst[i] = pixel;
+            }
+        }
+    }
+
+    return dst != NULL;
+}
+
+static DP_Rect filter_color(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null,
+                            bool include_transparent, DP_FilterColorFn fn,
+                            void *user)
+{
+    DP_ASSERT(tlc);
+    DP_Rect area = filter_area(tlc, rect_or_null);
+    DP_Rect changed = {0, 0, -1, -1};
+    if (!DP_rect_valid(area)) {
+        return changed;
+    }
+
+    DP_TileIterator ti =
+        DP_tile_iterator_make(DP_transient_layer_content_width(tlc),
+                              DP_transient_layer_content_height(tlc), area);
+    while (DP_tile_iterator_next(&ti)) {
+        DP_Rect tile_area = tile_area_at(&ti, area);
+        if (filter_tile(tlc, context_id, &ti, tile_area, include_transparent,
+                        fn, user)) {
+            changed = DP_rect_valid(changed)
+                        ? DP_rect_union(changed, tile_area)
+                        : tile_area;
+        }
+    }
+    return changed;
+}
+
+
+typedef struct DP_FilterBrightnessContrast {
+    float brightness;
+    float contrast;
+} DP_FilterBrightnessContrast;
+
+static float brightness_contrast(float c, float brightness, float contrast)
+{
+    return (c + brightness - 0.5f) * contrast + 0.5f;
+}
+
+static DP_UPixelFloat brightness_contrast_color(DP_UPixelFloat color,
+                                                void *user)
+{
+    const DP_FilterBrightnessContrast *params = user;
+    float brightness = params->brightness;
+    float contrast = params->contrast;
+    return (DP_UPixelFloat){
+        brightness_contrast(color.b, brightness, contrast),
+        brightness_contrast(color.g, brightness, contrast),
+        brightness_contrast(color.r, brightness, contrast),
+        color.a,
+    };
+}
+
+DP_Rect DP_filter_brightness_contrast(DP_TransientLayerContent *tlc,
+                                      unsigned int context_id,
+                                      const DP_Rect *rect_or_null,
+                                      float brightness, float contrast)
+{
+    DP_FilterBrightnessContrast params = {brightness, contrast};
+    return filter_color(tlc, context_id, rect_or_null, false,
+                        brightness_contrast_color, &params);
+}
+
+
+typedef struct DP_FilterHsl {
+    float hue_shift; // in sixths of a turn, from 0 to 6
+    float saturation_scale;
+    float lightness_offset;
+} DP_FilterHsl;
+
+static float hsl_channel(float m1, float m2, float h)
+{
+    if (h >= 6.0f) {
+        h -= 6.0f;
+    }
+    else if (h < 0.0f) {
+        h += 6.0f;
+    }
+
+    if (h < 1.0f) {
+        return m1 + (m2 - m1) * h;
+    }
+    else if (h < 3.0f) {
+        return m2;
+    }
+    else if (h < 4.0f) {
+        return m1 + (m2 - m1) * (4.0f - h);
+    }
+    else {
+        return m1;
+    }
+}
+
+static DP_UPixelFloat hsl_color(DP_UPixelFloat color, void *user)
+{
+    const DP_FilterHsl *params = user;
+    float r = color.r;
+    float g = color.g;
+    float b = color.b;
+    float max = DP_max_float(r, DP_max_float(g, b));
+    float min = DP_min_float(r, DP_min_float(g, b));
+    float l = (max + min) * 0.5f;
+    float d = max - min;
+
+    float h, s;
+    if (d == 0.0f) {
+        h = 0.0f;
+        s = 0.0f;
+    }
+    else {
+        s = l <= 0.5f ? d / (max + min) : d / (2.0f - max - min);
+        if (r == max) {
+            h = (g - b) / d;
+        }
+        else if (g == max) {
+            h = 2.0f + (b - r) / d;
+        }
+        else {
+            h = 4.0f + (r - g) / d;
+        }
+        h += params->hue_shift;
+        if (h >= 6.0f) {
+            h -= 6.0f;
+        }
+        else if (h < 0.0f) {
+            h += 6.0f;
+        }
+    }
+
+    s = clamp_float(s * params->saturation_scale);
+    l = clamp_float(l + params->lightness_offset);
+    if (s == 0.0f) {
+        return (DP_UPixelFloat){l, l, l, color.a};
+    }
+    else {
+        float m2 = l <= 0.5f ? l * (1.0f + s) : l + s - l * s;
+        float m1 = 2.0f * l - m2;
+        return (DP_UPixelFloat){
+            hsl_channel(m1, m2, h - 2.0f),
+            hsl_channel(m1, m2, h),
+            hsl_channel(m1, m2, h + 2.0f),
+            color.a,
+        };
+    }
+}
+
+DP_Rect DP_filter_hsl(DP_TransientLayerContent *tlc, unsigned int context_id,
+                      const DP_Rect *rect_or_null, float hue_shift_degrees,
+                      float saturation_scale, float lightness_offset)
+{
+    float turns = isfinite(hue_shift_degrees) ? hue_shift_degrees / 360.0f
+                                                : 0.0f;
+    DP_FilterHsl params = {(turns - floorf(turns)) * 6.0f, saturation_scale,
+                           lightness_offset};
+    // Rounding can push tiny negative shifts all the way up to 6.
+    if (params.hue_shift >= 6.0f) {
+        params.hue_shift = 0.0f;
+    }
+    return filter_color(tlc, context_id, rect_or_null, false, hsl_color,
+                        &params);
+}
+
+
+static DP_UPixelFloat invert_color(DP_UPixelFloat color, DP_UNUSED void *user)
+{
+    return (DP_UPixelFloat){1.0f - color.b, 1.0f - color.g, 1.0f - color.r,
+                            color.a};
+}
+
+static DP_UPixelFloat invert_color_alpha(DP_UPixelFloat color,
+                                         DP_UNUSED void *user)
+{
+    return (DP_UPixelFloat){1.0f - color.b, 1.0f - color.g, 1.0f - color.r,
+                            1.0f - color.a};
+}
+
+DP_Rect DP_filter_invert(DP_TransientLayerContent *tlc,
+                         unsigned int context_id, const DP_Rect *rect_or_null,
+                         bool invert_alpha)
+{
+    // Inverting alpha turns transparent pixels opaque, so blank tiles can't
+    // be skipped in that case.
+    if (invert_alpha) {
+        return filter_color(tlc, context_id, rect_or_null, true,
+                            invert_color_alpha, NULL);
+    }
+    else {
+        return filter_color(tlc, context_id, rect_or_null, false, invert_color,
+                            NULL);
+    }
+}
+
+
+static DP_UPixelFloat desaturate_color(DP_UPixelFloat color, void *user)
+{
+    float gray = DP_upixel_float_gray(color, *(DP_GrayscaleMethod *)user);
+    return (DP_UPixelFloat){gray, gray, gray, color.a};
+}
+
+DP_Rect DP_filter_desaturate(DP_TransientLayerContent *tlc,
+                             unsigned int context_id,
+                             const DP_Rect *rect_or_null,
+                             DP_GrayscaleMethod method)
+{
+    DP_ASSERT(method < DP_GRAYSCALE_METHOD_COUNT);
+    return filter_color(tlc, context_id, rect_or_null, false,
+                        desaturate_color, &method);
+}
+
+
+typedef struct DP_FilterColorToAlpha {
+    DP_UPixelFloat color;
+    float transparency_threshold;
+    float opacity_threshold;
+} DP_FilterColorToAlpha;
+
+// How opaque a channel has to be for it to be composited from the removed
+// one, based on its distance from it relative to how far it could go.
+static float color_to_alpha_channel(float c, float removed,
+                                    float transparency_threshold,
+                                    float opacity_threshold)
+{
+    float d = fabsf(c - removed);
+    if (d <= transparency_threshold) {
+        return 0.0f;
+    }
+    else if (d >= opacity_threshold) {
+        return 1.0f;
+    }
+    else {
+        float extreme = c < removed ? removed : 1.0f - removed;
+        float limit = DP_min_float(opacity_threshold, extreme);
+        return limit > transparency_threshold
+                 ? clamp_float((d - transparency_threshold)
+                               / (limit - transparency_threshold))
+                 : 1.0f;
+    }
+}
+
+static float color_to_alpha_unblend(float c, float removed, float alpha)
+{
+    return clamp_float((c - removed) / alpha + removed);
+}
+
+static DP_UPixelFloat color_to_alpha_color(DP_UPixelFloat color, void *user)
+{
+    DP_FilterColorToAlpha *params = user;
+    DP_UPixelFloat removed = params->color;
+    float tt = params->transparency_threshold;
+    float ot = params->opacity_threshold;
+    float alpha = DP_max_float(
+        color_to_alpha_channel(color.b, removed.b, tt, ot),
+        DP_max_float(color_to_alpha_channel(color.g, removed.g, tt, ot),
+                     color_to_alpha_channel(color.r, removed.r, tt, ot)));
+    if (alpha > 0.0f) {
+        return (DP_UPixelFloat){
+            color_to_alpha_unblend(color.b, removed.b, alpha),
+            color_to_alpha_unblend(color.g, removed.g, alpha),
+            color_to_alpha_unblend(color.r, removed.r, alpha),
+            color.a * alpha,
+        };
+    }
+    else {
+        return DP_upixel_float_zero();
+    }
+}
+
+DP_Rect DP_filter_color_to_alpha(DP_TransientLayerContent *tlc,
+                                 unsigned int context_id,
+                                 const DP_Rect *rect_or_null,
+                                 DP_UPixelFloat color, float threshold,
+                                 float falloff)
+{
+    float transparency_threshold = clamp_float(threshold);
+    DP_FilterColorToAlpha params = {
+        color,
+        transparency_threshold,
+        transparency_threshold + DP_max_float(falloff, 0.0f),
+    };
+    return filter_color(tlc, context_id, rect_or_null, false,
+                        color_to_alpha_color, &params);
+}
+
+
+typedef struct DP_FilterThreshold {
+    float cut;
+    float smooth;
+    bool threshold_alpha;
+} DP_FilterThreshold;
+
+// Values from 0 to 255 at or above the cut become 1, ones below it 0, with a
+// linear ramp of the smoothing width centered on the cut in between.
+static float threshold_value(float value, float cut, float smooth)
+{
+    if (smooth > 0.0f) {
+        return clamp_float((value - cut) / smooth + 0.5f);
+    }
+    else {
+        return value >= cut ? 1.0f : 0.0f;
+    }
+}
+
+static DP_UPixelFloat threshold_color(DP_UPixelFloat color, void *user)
+{
+    DP_FilterThreshold *params = user;
+    float gray = DP_upixel_float_gray(color, DP_GRAYSCALE_METHOD_LUMINANCE);
+    float value = threshold_value(gray * 255.0f, params->cut, params->smooth);
+    float alpha = params->threshold_alpha ? threshold_value(color.a * 255.0f,
+                                                            params->cut,
+                                                            params->smooth)
+                                          : color.a;
+    return (DP_UPixelFloat){value, value, value, alpha};
+}
+
+DP_Rect DP_filter_threshold(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null, uint8_t level,
+                            uint8_t smooth, bool threshold_alpha)
+{
+    // Half a step down so that the 8 bit level itself always makes the cut,
+    // regardless of how the gray value rounds.
+    DP_FilterThreshold params = {DP_uint8_to_float(level) - 0.5f,
+                                 DP_uint8_to_float(smooth), threshold_alpha};
+    return filter_color(tlc, context_id, rect_or_null, false, threshold_color,
+                        &params);
+}
+
+
+typedef struct DP_FilterPosterize {
+    float steps;
+    bool posterize_alpha;
+} DP_FilterPosterize;
+
+static float posterize_channel(float c, float steps)
+{
+    return roundf(clamp_float(c) * steps) / steps;
+}
+
+static DP_UPixelFloat posterize_color(DP_UPixelFloat color, void *user)
+{
+    DP_FilterPosterize *params = user;
+    float steps = params->steps;
+    return (DP_UPixelFloat){
+        posterize_channel(color.b, steps),
+        posterize_channel(color.g, steps),
+        posterize_channel(color.r, steps),
+        params->posterize_alpha ? posterize_channel(color.a, steps) : color.a,
+    };
+}
+
+DP_Rect DP_filter_posterize(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null,
+                            uint8_t levels_per_channel, bool posterize_alpha)
+{
+    DP_FilterPosterize params = {
+        DP_uint8_to_float(levels_per_channel < 2 ? 2 : levels_per_channel)
+            - 1.0f,
+        posterize_alpha};
+    return filter_color(tlc, context_id, rect_or_null, false, posterize_color,
+                        &params);
+}
+
+
+// The standard deviation is half the radius and the kernel reaches out to
+// three of them, beyond that the weights are too small to matter.
+static float *blur_kernel(float radius, int *out_reach)
+{
+    float sigma = radius * 0.5f;
+    int reach = DP_float_to_int(ceilf(sigma * 3.0f));
+    int length = reach * 2 + 1;
+    float *kernel = DP_malloc(sizeof(*kernel) * DP_int_to_size(length));
+    float sum = 0.0f;
+    for (int i = 0; i < length; ++i) {
+        float d = DP_int_to_float(i - reach);
+        kernel[i] = expf(-(d * d) / (2.0f * sigma * sigma));
+        sum += kernel[i];
+    }
+    for (int i = 0; i < length; ++i) {
+        kernel[i] /= sum;
+    }
+    *out_reach = reach;
+    return kernel;
+}
+
+// Reads the premultiplied pixels of the given area into a buffer of floats
+// in b, g, r, a order. Returns false if they're all blank.
+static bool read_area(DP_TransientLayerContent *tlc, DP_Rect src,
+                      float *buffer)
+{
+    int width = DP_rect_width(src);
+    bool any = false;
+    DP_TileIterator ti =
+        DP_tile_iterator_make(DP_transient_layer_content_width(tlc),
+                              DP_transient_layer_content_height(tlc), src);
+    while (DP_tile_iterator_next(&ti)) {
+        DP_Tile *t =
+            DP_transient_layer_content_tile_at_noinc(tlc, ti.col, ti.row);
+        const DP_Pixel15 *pixels = t ? DP_tile_pixels(t) : NULL;
+        DP_Rect tile_area = tile_area_at(&ti, src);
+        int tile_x = ti.col * DP_TILE_SIZE;
+        int tile_y = ti.row * DP_TILE_SIZE;
+        for (int y = tile_area.y1; y <= tile_area.y2; ++y) {
+            float *dst = buffer
+                       + ((y - src.y1) * width + (tile_area.x1 - src.x1)) * 4;
+            for (int x = tile_area.x1; x <= tile_area.x2; ++x) {
+                DP_Pixel15 pixel =
+                    pixels ? pixels[(y - tile_y) * DP_TILE_SIZE + (x - tile_x)]
+                           : DP_pixel15_zero();
+                *(dst++) = pixel.b;
+                *(dst++) = pixel.g;
+                *(dst++) = pixel.r;
+                *(dst++) = pixel.a;
+            }
+        }
+        any = any || pixels;
+    }
+    return any;
+}
+
+// Convolves count lines along one axis. Each output line is length pixels
+// long and starts at offset within the input line, which is src_length pixels
+// long. Pixels past the ends of the input line get clamped to them, it only
+// ends early at the boundaries of the layer. Stride is the distance between
+// pixels within a line and the pitches are the distances between lines, all
+// of them in floats.
+static void blur_pass(const float *DP_RESTRICT src, int src_pitch,
+                      float *DP_RESTRICT dst, int dst_pitch, int stride,
+                      int count, int src_length, int offset, int length,
+                      const float *kernel, int reach)
+{
+    for (int line = 0; line < count; ++line) {
+        const float *src_line = src + line * src_pitch;
+        float *dst_line = dst + line * dst_pitch;
+        for (int i = 0; i < length; ++i) {
+            float b = 0.0f, g = 0.0f, r = 0.0f, a = 0.0f;
+            for (int k = -reach; k <= reach; ++k) {
+                int j = DP_clamp_int(offset + i + k, 0, src_length - 1);
+                const float *pixel = src_line + j * stride;
+                float weight = kernel[k + reach];
+                b += pixel[0] * weight;
+                g += pixel[1] * weight;
+                r += pixel[2] * weight;
+                a += pixel[3] * weight;
+            }
+            float *out = dst_line + i * stride;
+            out[0] = b;
+            out[1] = g;
+            out[2] = r;
+            out[3] = a;
+        }
+    }
+}
+
+static DP_Pixel15 buffer_pixel(const float *pixel)
+{
+    return (DP_Pixel15){
+        DP_float_to_uint16(pixel[0]),
+        DP_float_to_uint16(pixel[1]),
+        DP_float_to_uint16(pixel[2]),
+        DP_float_to_uint16(pixel[3]),
+    };
+}
+
+static void buffer_pixel_set(float *pixel, DP_Pixel15 value)
+{
+    pixel[0] = value.b;
+    pixel[1] = value.g;
+    pixel[2] = value.r;
+    pixel[3] = value.a;
+}
+
+static uint16_t blur_channel(float c, uint16_t max)
+{
+    if (c > 0.0f) {
+        float rounded = c + 0.5f;
+        return rounded < max ? (uint16_t)rounded : max;
+    }
+    else {
+        return 0;
+    }
+}
+
+static DP_Rect write_area(DP_TransientLayerContent *tlc,
+                          unsigned int context_id, DP_Rect area,
+                          const float *buffer)
+{
+    int width = DP_rect_width(area);
+    DP_Rect changed = {0, 0, -1, -1};
+    DP_TileIterator ti =
+        DP_tile_iterator_make(DP_transient_layer_content_width(tlc),
+                              DP_transient_layer_content_height(tlc), area);
+    while (DP_tile_iterator_next(&ti)) {
+        DP_Tile *t =
+            DP_transient_layer_content_tile_at_noinc(tlc, ti.col, ti.row);
+        const DP_Pixel15 *pixels = t ? DP_tile_pixels(t) : NULL;
+        DP_Pixel15 *dst = NULL;
+        DP_Rect tile_area = tile_area_at(&ti, area);
+        int tile_x = ti.col * DP_TILE_SIZE;
+        int tile_y = ti.row * DP_TILE_SIZE;
+        for (int y = tile_area.y1; y <= tile_area.y2; ++y) {
+            const float *src =
+                buffer
+                + ((y - area.y1) * width + (tile_area.x1 - area.x1)) * 4;
+            for (int x = tile_area.x1; x <= tile_area.x2; ++x) {
+                uint16_t a = blur_channel(src[3], DP_BIT15);
+                DP_Pixel15 pixel = {
+                    blur_channel(src[0], a),
+                    blur_channel(src[1], a),
+                    blur_channel(src[2], a),
+                    a,
+                };
+                src += 4;
+                int i = (y - tile_y) * DP_TILE_SIZE + (x - tile_x);
+                DP_Pixel15 original = pixels ? pixels[i] : DP_pixel15_zero();
+                if (!DP_pixel15_equal(pixel, original)) {
+                    if (!dst) {
+                        dst = DP_transient_tile_pixels(
+                            transient_tile_at(tlc, context_id, &ti, t));
+                        pixels = dst;
+                    }
+                    dst[i] = pixel;
+                }
+            }
+        }
+        if (dst) {
+            changed = DP_rect_valid(changed)
+                        ? DP_rect_union(changed, tile_area)
+                        : tile_area;
+        }
+    }
+    return changed;
+}
+
+// Blurs the given area, which must be valid and within the layer. Returns a
+// buffer of its pixels like read_area does, or NULL if the area and its
+// surroundings are all blank.
+static float *blur_area(DP_TransientLayerContent *tlc, DP_Rect area,
+                        float radius)
+{
+    int reach;
+    float *kernel = blur_kernel(
+        DP_min_float(radius, DP_FILTER_GAUSSIAN_BLUR_RADIUS_MAX), &reach);
+
+    // The area plus however far the kernel reaches, clipped to the layer.
+    int width = DP_rect_width(area);
+    int height = DP_rect_height(area);
+    DP_Rect reached = DP_rect_make(area.x1 - reach, area.y1 - reach,
+                                   width + reach * 2, height + reach * 2);
+    DP_Rect src = filter_area(tlc, &reached);
+    int src_width = DP_rect_width(src);
+    int src_height = DP_rect_height(src);
+    float *pixels = DP_malloc(sizeof(*pixels) * 4 * DP_int_to_size(src_width)
+                              * DP_int_to_size(src_height));
+
+    if (read_area(tlc, src, pixels)) {
+        // First horizontally across all rows of the source into rows as wide
+        // as the area, then vertically from those right back into the source
+        // buffer, which is always big enough to hold the result.
+        float *rows = DP_malloc(sizeof(*rows) * 4 * DP_int_to_size(width)
+                                * DP_int_to_size(src_height));
+        blur_pass(pixels, src_width * 4, rows, width * 4, 4, src_height,
+                  src_width, area.x1 - src.x1, width, kernel, reach);
+        blur_pass(rows, 4, pixels, 4, width * 4, width, src_height,
+                  area.y1 - src.y1, height, kernel, reach);
+        DP_free(rows);
+    }
+    else {
+        DP_free(pixels);
+        pixels = NULL;
+    }
+
+    DP_free(kernel);
+    return pixels;
+}
+
+DP_Rect DP_filter_gaussian_blur(DP_TransientLayerContent *tlc,
+                                unsigned int context_id,
+                                const DP_Rect *rect_or_null, float radius)
+{
+    DP_ASSERT(tlc);
+    DP_Rect changed = {0, 0, -1, -1};
+    DP_Rect area = filter_area(tlc, rect_or_null);
+    // Written so that NaN doesn't blur anything either.
+    if (!(radius > 0.0f) || !DP_rect_valid(area)) {
+        return changed;
+    }
+
+    float *pixels = blur_area(tlc, area, radius);
+    if (pixels) {
+        changed = write_area(tlc, context_id, area, pixels);
+        DP_free(pixels);
+    }
+    return changed;
+}
+
+
+// Bounds of the pixels that aren't fully transparent, or an invalid rectangle
+// if there aren't any.
+static DP_Rect alpha_bounds(DP_LayerContent *lc)
+{
+    int width = DP_layer_content_width(lc);
+    int height = DP_layer_content_height(lc);
+    int x1 = INT_MAX, y1 = INT_MAX, x2 = INT_MIN, y2 = INT_MIN;
+    DP_TileIterator ti =
+        DP_tile_iterator_make(width, height, DP_rect_make(0, 0, width, height));
+    while (DP_tile_iterator_next(&ti)) {
+        DP_Tile *t = DP_layer_content_tile_at_noinc(lc, ti.col, ti.row);
+        if (t) {
+            const DP_Pixel15 *pixels = DP_tile_pixels(t);
+            DP_Rect tile_area = tile_area_at(&ti, ti.area);
+            int tile_x = ti.col * DP_TILE_SIZE;
+            int tile_y = ti.row * DP_TILE_SIZE;
+            for (int y = tile_area.y1; y <= tile_area.y2; ++y) {
+                for (int x = tile_area.x1; x <= tile_area.x2; ++x) {
+                    int i = (y - tile_y) * DP_TILE_SIZE + (x - tile_x);
+                    if (pixels[i].a != 0) {
+                        x1 = DP_min_int(x1, x);
+                        y1 = DP_min_int(y1, y);
+                        x2 = DP_max_int(x2, x);
+                        y2 = DP_max_int(y2, y);
+                    }
+                }
+            }
+        }
+    }
+    return x1 <= x2 ? (DP_Rect){x1, y1, x2, y2} : (DP_Rect){0, 0, -1, -1};
+}
+
+// Reads the alpha of the source at the given area moved back by the offset,
+// colored in with the shadow color, into a buffer like read_area does.
+// Everything outside of the source is transparent.
+static void read_shadow(DP_LayerContent *source, DP_Rect dst, int offset_x,
+                        int offset_y, DP_UPixelFloat color, float *buffer)
+{
+    float a = clamp_float(color.a) * (float)DP_BIT15;
+    float b = clamp_float(color.b) * a;
+    float g = clamp_float(color.g) * a;
+    float r = clamp_float(color.r) * a;
+    int width = DP_rect_width(dst);
+    size_t count = DP_int_to_size(width) * DP_int_to_size(DP_rect_height(dst));
+    memset(buffer, 0, sizeof(*buffer) * 4 * count);
+    DP_TileIterator ti = DP_tile_iterator_make(
+        DP_layer_content_width(source), DP_layer_content_height(source),
+        DP_rect_translate(dst, -offset_x, -offset_y));
+    while (DP_tile_iterator_next(&ti)) {
+        DP_Tile *t = DP_layer_content_tile_at_noinc(source, ti.col, ti.row);
+        if (t) {
+            const DP_Pixel15 *pixels = DP_tile_pixels(t);
+            DP_Rect tile_area = tile_area_at(&ti, ti.area);
+            int tile_x = ti.col * DP_TILE_SIZE;
+            int tile_y = ti.row * DP_TILE_SIZE;
+            for (int y = tile_area.y1; y <= tile_area.y2; ++y) {
+                float *out = buffer
+                           + ((y + offset_y - dst.y1) * width
+                              + (tile_area.x1 + offset_x - dst.x1))
+                                 * 4;
+                for (int x = tile_area.x1; x <= tile_area.x2; ++x) {
+                    uint16_t alpha =
+                        pixels[(y - tile_y) * DP_TILE_SIZE + (x - tile_x)].a;
+                    float k = DP_uint16_to_float(alpha) / (float)DP_BIT15;
+                    *(out++) = b * k;
+                    *(out++) = g * k;
+                    *(out++) = r * k;
+                    *(out++) = a * k;
+                }
+            }
+        }
+    }
+}
+
+static uint8_t shadow_channel(float c, uint16_t opacity, uint8_t max)
+{
+    uint8_t value = DP_channel15_to_8(
+        blur_channel(c * DP_uint16_to_float(opacity) / (float)DP_BIT15,
+                     DP_BIT15));
+    return value < max ? value : max;
+}
+
+DP_Rect DP_filter_drop_shadow(DP_TransientLayerContent *tlc,
+                              unsigned int context_id, int blend_mode,
+                              DP_LayerContent *source, int offset_x,
+                              int offset_y, float blur_radius,
+                              DP_UPixelFloat color, uint16_t opacity)
+{
+    DP_ASSERT(tlc);
+    DP_ASSERT(source);
+    DP_Rect changed = {0, 0, -1, -1};
+    DP_Rect bounds = alpha_bounds(source);
+    if (opacity == 0 || !DP_rect_valid(bounds)) {
+        return changed;
+    }
+
+    int reach = 0;
+    float *kernel = NULL;
+    // Written so that NaN doesn't blur anything either.
+    if (blur_radius > 0.0f) {
+        kernel = blur_kernel(
+            DP_min_float(blur_radius, DP_FILTER_GAUSSIAN_BLUR_RADIUS_MAX),
+            &reach);
+    }
+
+    // The blur spreads the shadow out by as far as the kernel reaches.
+    DP_Rect shadow = DP_rect_translate(bounds, offset_x, offset_y);
+    DP_Rect grown = {shadow.x1 - reach, shadow.y1 - reach, shadow.x2 + reach,
+                     shadow.y2 + reach};
+    DP_Rect area = filter_area(tlc, &grown);
+    if (DP_rect_valid(area)) {
+        // Blurring needs everything within reach of the area too. It's not
+        // clipped to the layer, the source may be sticking out past it.
+        int width = DP_rect_width(area);
+        int height = DP_rect_height(area);
+        DP_Rect src = {area.x1 - reach, area.y1 - reach, area.x2 + reach,
+                       area.y2 + reach};
+        int src_width = DP_rect_width(src);
+        int src_height = DP_rect_height(src);
+        float *pixels = DP_malloc(sizeof(*pixels) * 4
+                                  * DP_int_to_size(src_width)
+                                  * DP_int_to_size(src_height));
+        read_shadow(source, src, offset_x, offset_y, color, pixels);
+
+        // Blurring ends up with just the area, without the margin around it.
+        // Without blurring, there's no margin in the first place.
+        if (kernel) {
+            float *rows = DP_malloc(sizeof(*rows) * 4 * DP_int_to_size(width)
+                                    * DP_int_to_size(src_height));
+            blur_pass(pixels, src_width * 4, rows, width * 4, 4, src_height,
+                      src_width, reach, width, kernel, reach);
+            blur_pass(rows, 4, pixels, 4, width * 4, width, src_height, reach,
+                      height, kernel, reach);
+            DP_free(rows);
+        }
+
+        DP_Image *img = DP_image_new(width, height);
+        DP_Pixel8 *dst = DP_image_pixels(img);
+        for (int i = 0; i < width * height; ++i) {
+            const float *pixel = pixels + i * 4;
+            uint8_t a = shadow_channel(pixel[3], opacity, 255);
+            dst[i] = (DP_Pixel8){
+                .b = shadow_channel(pixel[0], opacity, a),
+                .g = shadow_channel(pixel[1], opacity, a),
+                .r = shadow_channel(pixel[2], opacity, a),
+                .a = a,
+            };
+        }
+        DP_free(pixels);
+
+        DP_transient_layer_content_put_image(tlc, context_id, blend_mode,
+                                             area.x1, area.y1, img);
+        DP_image_free(img);
+        changed = area;
+    }
+
+    DP_free(kernel);
+    return changed;
+}
+
+
+static float sharpen_channel(float c, float blurred, float amount,
+                             float threshold)
+{
+    float difference = c - blurred;
+    if (fabsf(difference) < threshold) {
+        return c;
+    }
+    else {
+        return clamp_float(c + difference * amount);
+    }
+}
+
+DP_Rect DP_filter_sharpen(DP_TransientLayerContent *tlc,
+                          unsigned int context_id, const DP_Rect *rect_or_null,
+                          float amount, float radius, uint8_t threshold)
+{
+    DP_ASSERT(tlc);
+    DP_Rect changed = {0, 0, -1, -1};
+    DP_Rect area = filter_area(tlc, rect_or_null);
+    if (!(radius > 0.0f) || !(amount != 0.0f) || !isfinite(amount)
+        || !DP_rect_valid(area)) {
+        return changed;
+    }
+
+    float *blurred = blur_area(tlc, area, radius);
+    if (!blurred) {
+        return changed;
+    }
+
+    int count = DP_rect_width(area) * DP_rect_height(area);
+    float *pixels = DP_malloc(sizeof(*pixels) * 4 * DP_int_to_size(count));
+    read_area(tlc, area, pixels);
+
+    float t = DP_uint8_to_float(threshold) / 255.0f;
+    for (int i = 0; i < count; ++i) {
+        float *pixel = pixels + i * 4;
+        const float *blurred_pixel = blurred + i * 4;
+        float a = pixel[3];
+        float blurred_a = blurred_pixel[3];
+        // The blurred alpha can't be zero where the original isn't, but
+        // checking both keeps rounding errors from dividing by zero.
+        if (a != 0.0f && blurred_a > 0.0f) {
+            // Compare colors without alpha, so that the transparent pixels
+            // along the edges don't darken anything.
+            DP_UPixel15 up = DP_pixel15_unpremultiply(buffer_pixel(pixel));
+            DP_UPixelFloat color = DP_upixel15_to_float(up);
+            DP_UPixel15 result = {
+                clamp_channel(sharpen_channel(
+                    color.b, blurred_pixel[0] / blurred_a, amount, t)),
+                clamp_channel(sharpen_channel(
+                    color.g, blurred_pixel[1] / blurred_a, amount, t)),
+                clamp_channel(sharpen_channel(
+                    color.r, blurred_pixel[2] / blurred_a, amount, t)),
+                up.a,
+            };
+            if (result.b != up.b || result.g != up.g || result.r != up.r) {
+                buffer_pixel_set(pixel, DP_pixel15_premultiply(result));
+            }
+        }
+    }
+    DP_free(blurred);
+
+    changed = write_area(tlc, context_id, area, pixels);
+    DP_free(pixels);
+    return changed;
+}
+
+
+// Keeps track of the median of the values in a sliding window, which moves
+// only a little each time a value gets added or removed.
+typedef struct DP_MedianHistogram {
+    int bins[256];
+    int count;
+    int median;
+    int below; // number of values less than the median
+} DP_MedianHistogram;
+
+static void median_histogram_add(DP_MedianHistogram *mh, uint8_t value)
+{
+    ++mh->bins[value];
+    ++mh->count;
+    if (value < mh->median) {
+        ++mh->below;
+    }
+}
+
+static void median_histogram_remove(DP_MedianHistogram *mh, uint8_t value)
+{
+    --mh->bins[value];
+    --mh->count;
+    if (value < mh->median) {
+        --mh->below;
+    }
+}
+
+static uint8_t median_histogram_get(DP_MedianHistogram *mh)
+{
+    DP_ASSERT(mh->count > 0);
+    // The lower median if there's an even number of values.
+    int half = (mh->count - 1) / 2;
+    while (mh->below > half) {
+        --mh->median;
+        mh->below -= mh->bins[mh->median];
+    }
+    while (mh->below + mh->bins[mh->median] <= half) {
+        mh->below += mh->bins[mh->median];
+        ++mh->median;
+    }
+    return DP_int_to_uint8(mh->median);
+}
+
+// One histogram per channel, in b, g, r, a order. Transparent pixels don't
+// have a color, so they only count towards alpha.
+static void median_window_update(DP_MedianHistogram *mhs, DP_UPixel8 pixel,
+                                 bool add)
+{
+    void (*fn)(DP_MedianHistogram *, uint8_t) =
+        add ? median_histogram_add : median_histogram_remove;
+    fn(&mhs[3], pixel.a);
+    if (pixel.a != 0) {
+        fn(&mhs[0], pixel.b);
+        fn(&mhs[1], pixel.g);
+        fn(&mhs[2], pixel.r);
+    }
+}
+
+static void median_window_column(DP_MedianHistogram *mhs,
+                                 const DP_UPixel8 *samples, int src_width,
+                                 int src_height, int column, int row,
+                                 int radius, bool add)
+{
+    for (int dy = -radius; dy <= radius; ++dy) {
+        int y = DP_clamp_int(row + dy, 0, src_height - 1);
+        median_window_update(mhs, samples[y * src_width + column], add);
+    }
+}
+
+static uint16_t median_channel(uint16_t original, uint8_t median)
+{
+    // Keep the full precision of channels that don't change.
+    return DP_channel15_to_8(original) == median ? original
+                                                 : DP_channel8_to_15(median);
+}
+
+static bool despeckle_differs(DP_UPixel8 a, DP_UPixel8 b, int threshold)
+{
+    return abs(a.b - b.b) > threshold || abs(a.g - b.g) > threshold
+        || abs(a.r - b.r) > threshold || abs(a.a - b.a) > threshold;
+}
+
+// Replaces pixels with the median of their neighborhood. If the threshold is
+// negative, that happens to every pixel, otherwise only ones that differ from
+// the median by more than the threshold in any channel.
+static DP_Rect median_filter(DP_TransientLayerContent *tlc,
+                             unsigned int context_id,
+                             const DP_Rect *rect_or_null, int radius,
+                             int threshold)
+{
+    DP_ASSERT(tlc);
+    DP_Rect changed = {0, 0, -1, -1};
+    DP_Rect area = filter_area(tlc, rect_or_null);
+    if (radius <= 0 || !DP_rect_valid(area)) {
+        return changed;
+    }
+
+    radius = DP_min_int(radius, DP_FILTER_MEDIAN_RADIUS_MAX);
+    int width = DP_rect_width(area);
+    int height = DP_rect_height(area);
+    DP_Rect reached = DP_rect_make(area.x1 - radius, area.y1 - radius,
+                                   width + radius * 2, height + radius * 2);
+    DP_Rect src = filter_area(tlc, &reached);
+    int src_width = DP_rect_width(src);
+    int src_height = DP_rect_height(src);
+    size_t src_count = DP_int_to_size(src_width) * DP_int_to_size(src_height);
+
+    float *buffer = DP_malloc(sizeof(*buffer) * 4 * src_count);
+    if (!read_area(tlc, src, buffer)) {
+        DP_free(buffer);
+        return changed;
+    }
+
+    DP_UPixel8 *samples = DP_malloc(sizeof(*samples) * src_count);
+    for (size_t i = 0; i < src_count; ++i) {
+        samples[i] = DP_upixel15_to_8(
+            DP_pixel15_unpremultiply(buffer_pixel(buffer + i * 4)));
+    }
+
+    // The area's pixels go into the start of the buffer, it's big enough.
+    read_area(tlc, area, buffer);
+    DP_MedianHistogram mhs[4];
+    for (int y = 0; y < height; ++y) {
+        int row = area.y1 + y - src.y1;
+        memset(mhs, 0, sizeof(mhs));
+        int first = area.x1 - src.x1;
+        for (int dx = -radius; dx <= radius; ++dx) {
+            median_window_column(mhs, samples, src_width, src_height,
+                                 DP_clamp_int(first + dx, 0, src_width - 1),
+                                 row, radius, true);
+        }
+
+        for (int x = 0; x < width; ++x) {
+            int column = first + x;
+            if (x != 0) {
+                median_window_column(
+                    mhs, samples, src_width, src_height,
+                    DP_clamp_int(column - radius - 1, 0, src_width - 1), row,
+                    radius, false);
+                median_window_column(
+                    mhs, samples, src_width, src_height,
+                    DP_clamp_int(column + radius, 0, src_width - 1), row,
+                    radius, true);
+            }
+
+            uint8_t a = median_histogram_get(&mhs[3]);
+            DP_UPixel8 median =
+                a == 0 ? (DP_UPixel8){0}
+                       : (DP_UPixel8){.b = median_histogram_get(&mhs[0]),
+                                      .g = median_histogram_get(&mhs[1]),
+                                      .r = median_histogram_get(&mhs[2]),
+                                      .a = a};
+            DP_UPixel8 sample = samples[row * src_width + column];
+            if (threshold < 0 || despeckle_differs(sample, median, threshold)) {
+                float *pixel = buffer + (y * width + x) * 4;
+                DP_UPixel15 up =
+                    DP_pixel15_unpremultiply(buffer_pixel(pixel));
+                DP_UPixel15 result = {
+                    median_channel(up.b, median.b),
+                    median_channel(up.g, median.g),
+                    median_channel(up.r, median.r),
+                    median_channel(up.a, median.a),
+                };
+                buffer_pixel_set(pixel, DP_pixel15_premultiply(result));
+            }
+        }
+    }
+    DP_free(samples);
+
+    changed = write_area(tlc, context_id, area, buffer);
+    DP_free(buffer);
+    return changed;
+}
+
+DP_Rect DP_filter_median(DP_TransientLayerContent *tlc,
+                         unsigned int context_id, const DP_Rect *rect_or_null,
+                         int radius)
+{
+    return median_filter(tlc, context_id, rect_or_null, radius, -1);
+}
+
+DP_Rect DP_filter_despeckle(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null, int radius,
+                            uint8_t threshold)
+{
+    return median_filter(tlc, context_id, rect_or_null, radius, threshold);
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_FILTER_H
+#define DPENGINE_FILTER_H
+#include "pixels.h"
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+typedef struct DP_LayerContent DP_LayerContent;
+
+#ifdef DP_NO_STRICT_ALIASING
+typedef struct DP_TransientLayerContent DP_TransientLayerContent;
+#else
+typedef struct DP_LayerContent DP_TransientLayerContent;
+#endif
+
+
+// Filters change the pixels of a layer within the given rectangle, or all of
+// the layer if it's NULL. Only tiles intersecting that area get looked at,
+// blank ones are skipped and ones where no pixel ends up different are left
+// alone entirely. They return the area of the tiles that changed, clipped to
+// the given rectangle and the layer bounds, or an invalid rectangle if
+// nothing changed at all.
+
+// Adds the brightness to each color channel, then scales the result away
+// from or towards middle gray by the contrast factor. A brightness of 0 and a
+// contrast of 1 don't change anything. Works on unpremultiplied colors, so
+// the translucent edges of line art keep their hue, and leaves alpha alone.
+DP_Rect DP_filter_brightness_contrast(DP_TransientLayerContent *tlc,
+                                      unsigned int context_id,
+                                      const DP_Rect *rect_or_null,
+                                      float brightness, float contrast);
+
+// Converts unpremultiplied colors to hue, saturation and lightness, rotates
+// the hue by the given number of degrees, multiplies the saturation and adds
+// to the lightness, then converts them back. Saturation and lightness get
+// clamped, the hue wraps around. A saturation of 0 results in the gray that
+// the lightness method of desaturation gives. Alpha stays the same.
+DP_Rect DP_filter_hsl(DP_TransientLayerContent *tlc, unsigned int context_id,
+                      const DP_Rect *rect_or_null, float hue_shift_degrees,
+                      float saturation_scale, float lightness_offset);
+
+// Inverts the unpremultiplied color channels and, if invert_alpha is set, the
+// alpha channel too. In that case transparent pixels become opaque white, so
+// blank tiles get filled in rather than skipped.
+DP_Rect DP_filter_invert(DP_TransientLayerContent *tlc,
+                         unsigned int context_id, const DP_Rect *rect_or_null,
+                         bool invert_alpha);
+
+// Replaces unpremultiplied colors with their gray value according to the
+// given method, see DP_GrayscaleMethod. Luminance is the one to use if there's
+// no reason to pick something else. Alpha stays the same.
+DP_Rect DP_filter_desaturate(DP_TransientLayerContent *tlc,
+                             unsigned int context_id,
+                             const DP_Rect *rect_or_null,
+                             DP_GrayscaleMethod method);
+
+// Makes the given color transparent, like for getting line art out of a white
+// background. Each pixel loses as much opacity as it can while its color gets
+// pushed away from the removed one, so that compositing the result over that
+// color gives back the original. Pixels closer to it than the threshold in
+// every channel become fully transparent, ones further than the threshold plus
+// falloff in any channel keep their opacity. Both are from 0 to 1, a threshold
+// of 0 and a falloff of 1 result in the smoothest edges. Alpha of the given
+// color is ignored.
+DP_Rect DP_filter_color_to_alpha(DP_TransientLayerContent *tlc,
+                                 unsigned int context_id,
+                                 const DP_Rect *rect_or_null,
+                                 DP_UPixelFloat color, float threshold,
+                                 float falloff);
+
+// Turns pixels with a luminance at or above the level, out of 255, white and
+// ones below it black. A smoothing width above 0 turns the ones within half of
+// it around the level gray instead, so edges don't get jagged. If
+// threshold_alpha is set, alpha gets cut off at the same level, otherwise it
+// stays the same.
+DP_Rect DP_filter_threshold(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null, uint8_t level,
+                            uint8_t smooth, bool threshold_alpha);
+
+// Rounds each unpremultiplied channel to the nearest of the given number of
+// evenly spaced levels, including 0 and 1. Fewer than 2 levels are treated as
+// 2. Alpha gets rounded too if posterize_alpha is set.
+DP_Rect DP_filter_posterize(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null,
+                            uint8_t levels_per_channel, bool posterize_alpha);
+
+// Blurs premultiplied pixels, so transparent areas don't bleed any color into
+// their surroundings. Pixels outside of the rectangle get read from, so there
+// are no seams along its edges, only the edges of the layer get extended
+// outwards. A radius of 0 or less does nothing, ones above the maximum are
+// clamped to it. Since blurring spreads out into transparent pixels, this
+// fills in blank tiles near non-blank ones.
+#define DP_FILTER_GAUSSIAN_BLUR_RADIUS_MAX 64.0f
+
+DP_Rect DP_filter_gaussian_blur(DP_TransientLayerContent *tlc,
+                                unsigned int context_id,
+                                const DP_Rect *rect_or_null, float radius);
+
+// Unlike the other filters, this one draws into the layer rather than changing
+// it in place. Takes the alpha of the source layer, moves it by the offset,
+// blurs it with the given radius like the gaussian blur does, colors it in
+// with the unpremultiplied color and blends it into the layer with the given
+// opacity and blend mode. The source can be any layer of the same canvas,
+// typically the one above, and doesn't get changed. Returns the area drawn
+// to, which is the bounds of the source's non-transparent pixels, moved by the
+// offset and grown by however far the blur reaches, clipped to the layer.
+// That's an invalid rectangle if the source is blank or the opacity is 0.
+DP_Rect DP_filter_drop_shadow(DP_TransientLayerContent *tlc,
+                              unsigned int context_id, int blend_mode,
+                              DP_LayerContent *source, int offset_x,
+                              int offset_y, float blur_radius,
+                              DP_UPixelFloat color, uint16_t opacity);
+
+// Unsharp masking: blurs the unpremultiplied colors with the given radius,
+// then pushes them further away from the blurred ones by the given amount.
+// Differences smaller than the threshold, out of 255, get left alone so that
+// noise in flat areas doesn't get amplified. Since the blur is premultiplied,
+// pixels next to transparent ones don't get dark halos. Alpha stays the same.
+DP_Rect DP_filter_sharpen(DP_TransientLayerContent *tlc,
+                          unsigned int context_id, const DP_Rect *rect_or_null,
+                          float amount, float radius, uint8_t threshold);
+
+// Replaces each channel of every unpremultiplied pixel with its median over
+// the square window reaching out the given radius in each direction, which
+// gets rid of specks and noise. Alpha gets filtered too, transparent pixels
+// only count towards it. Colors don't lose precision where the median is the
+// same as the original at 8 bits. A radius of 0 or less does nothing, ones
+// above the maximum are clamped to it.
+#define DP_FILTER_MEDIAN_RADIUS_MAX 32
+
+DP_Rect DP_filter_median(DP_TransientLayerContent *tlc,
+                         unsigned int context_id, const DP_Rect *rect_or_null,
+                         int radius);
+
+// Like the median filter, but only replaces pixels that differ from the
+// median by more than the threshold, out of 255, in any channel. Leaves
+// everything that isn't a speck alone.
+DP_Rect DP_filter_despeckle(DP_TransientLayerContent *tlc,
+                            unsigned int context_id,
+                            const DP_Rect *rect_or_null, int radius,
+                            uint8_t threshold);
+
+
+#endif
@@ -33,18 +33,26 @@
 #include "layer_props.h"
 #include "layer_routes.h"
 #include "pixels.h"
+#include "selection.h"
+#include "tile.h"
 #include <dpcommon/common.h>
 #include <dpcommon/conversions.h>
 #include <dpcommon/geom.h>
 #include <dpcommon/queue.h>
+#include <dpcommon/threading.h>
+#include <dpcommon/worker.h>
 #include <limits.h>
 #include <math.h>
 #include <helpers.h> // M_PI
 
+#define PROGRESS_INTERVAL 4096
+
 
 // Flood fill algorithm based on: Smith, Alvy Ray (1979). Tint Fill. SIGGRAPH
 // '79: Proceedings of the 6th annual conference on Computer graphics and
-// interactive techniques. pp. 276–283.
+// interactive techniques. pp. 276–283. Using the span filling improvements
+// from: Heckbert, Paul S. (1990). A Seed Fill Algorithm. Graphics Gems.
+// pp. 275–277.
 // See https://en.wikipedia.org/wiki/Flood_fill#Span_Filling
 
 typedef struct DP_FillContext {
@@ -52,26 +60,76 @@ typedef struct DP_FillContext {
     unsigned char *output;
     int width, height;
     DP_Rect area;
+    size_t area_pixels;
     DP_LayerContent *lc;
+    DP_Selection *sel;
     DP_UPixelFloat reference_color;
-    double tolerance_squared;
+    double tolerance;
+    DP_FloodFillMetric metric;
     int min_x, min_y, max_x, max_y;
+    size_t pixels;
+    size_t max_pixels;
+    size_t next_progress;
+    DP_FloodFillStage stage;
     DP_Queue queue;
     bool cancelled;
-    DP_FloodFillShouldCancelFn should_cancel;
+    bool too_large;
+    DP_FloodFillProgressFn progress;
     void *user;
 } DP_FillContext;
 
-typedef struct DP_FillSeed {
-    int x, y;
-} DP_FillSeed;
+// A horizontal run of pixels from x1 to x2 on row y that may need filling,
+// reached from the row at y - dy.
+typedef struct DP_FillSpan {
+    int x1, x2, y, dy;
+} DP_FillSpan;
+
+typedef struct DP_FillScanJob {
+    DP_FillContext *c;
+    DP_Rect rect;
+} DP_FillScanJob;
+
+static DP_FillContext
+make_fill_context(DP_Selection *sel_or_null, double tolerance,
+                  DP_FloodFillMetric metric, size_t max_pixels,
+                  DP_FloodFillProgressFn progress, void *user)
+{
+    return (DP_FillContext){
+        NULL,
+        NULL,
+        0,
+        0,
+        {0, 0, 0, 0},
+        0,
+        NULL,
+        sel_or_null,
+        {0.0f, 0.0f, 0.0f, 0.0f},
+        tolerance,
+        metric,
+        INT_MAX,
+        INT_MAX,
+        INT_MIN,
+        INT_MIN,
+        0,
+        max_pixels,
+        PROGRESS_INTERVAL,
+        DP_FLOOD_FILL_STAGE_PREPARE,
+        DP_QUEUE_NULL,
+        false,
+        false,
+        progress,
+        user,
+    };
+}
 
+// Reports progress and checks for cancellation in one go.
 static bool is_cancelled(DP_FillContext *c)
 {
     if (c->cancelled) {
         return true;
     }
-    else if (c->should_cancel && c->should_cancel(c->user)) {
+    else if (c->progress
+             && c->progress(c->user, c->stage, c->pixels, c->area_pixels)) {
         c->cancelled = true;
         return true;
     }
@@ -80,6 +138,23 @@ static bool is_cancelled(DP_FillContext *c)
     }
 }
 
+// Reporting progress on every pixel would be a waste of time, since the
+// callback probably involves some atomic operation or other.
+static bool should_stop_filling(DP_FillContext *c)
+{
+    if (c->max_pixels != 0 && c->pixels > c->max_pixels) {
+        c->too_large = true;
+        return true;
+    }
+    else if (c->pixels >= c->next_progress) {
+        c->next_progress = c->pixels + PROGRESS_INTERVAL;
+        return is_cancelled(c);
+    }
+    else {
+        return false;
+    }
+}
+
 static unsigned char *buffer_at(unsigned char *buffer, DP_Rect area, int x,
                                 int y)
 {
@@ -101,111 +176,250 @@ static void buffer_set(unsigned char *buffer, DP_Rect area, int x, int y,
     *buffer_at(buffer, area, x, y) = value;
 }
 
-static DP_UPixelFloat get_color_at(DP_LayerContent *lc, int x, int y)
+static DP_UPixelFloat pixel_to_color(DP_Pixel15 pixel)
+{
+    return DP_upixel15_to_float(DP_pixel15_unpremultiply(pixel));
+}
+
+static bool should_flood_alpha(DP_FillContext *c, DP_Pixel15 pixel)
+{
+    double a = DP_channel15_to_float(pixel.a);
+    return a <= c->tolerance;
+}
+
+static bool should_flood_perceptual(DP_FillContext *c, DP_Pixel15 pixel)
 {
-    return DP_upixel15_to_float(
-        DP_pixel15_unpremultiply(DP_layer_content_pixel_at(lc, x, y)));
+    // A transparent pixel has no color to compare against.
+    if (c->reference_color.a == 0.0f) {
+        return should_flood_alpha(c, pixel);
+    }
+    else {
+        DP_UPixelFloat color = pixel_to_color(pixel);
+        return DP_upixel_float_distance(color, c->reference_color)
+            <= c->tolerance;
+    }
 }
 
-static bool should_flood(DP_FillContext *c, int x, int y)
+static bool should_flood_channels(DP_FillContext *c, DP_Pixel15 pixel)
 {
     DP_UPixelFloat reference_color = c->reference_color;
-    // TODO: we could use better functions for color distance than this.
     // Guess if we're supposed to fill a transparent-ish pixel.
     if (reference_color.a < 0.05f) {
-        double a =
-            DP_channel15_to_float(DP_layer_content_pixel_at(c->lc, x, y).a);
-        return a * a <= c->tolerance_squared;
+        return should_flood_alpha(c, pixel);
     }
     else {
-        DP_UPixelFloat color = get_color_at(c->lc, x, y);
+        DP_UPixelFloat color = pixel_to_color(pixel);
         double b = color.b - reference_color.b;
         double g = color.g - reference_color.g;
         double r = color.r - reference_color.r;
         double a = color.a - reference_color.a;
-        return b * b + g * g + r * r + a * a <= c->tolerance_squared;
+        return b * b + g * g + r * r + a * a <= c->tolerance * c->tolerance;
     }
 }
 
-static void flood(DP_FillContext *c)
+static bool should_flood(DP_FillContext *c, DP_Pixel15 pixel)
+{
+    switch (c->metric) {
+    case DP_FLOOD_FILL_METRIC_CHANNELS:
+        return should_flood_channels(c, pixel);
+    default:
+        return should_flood_perceptual(c, pixel);
+    }
+}
+
+static bool is_selected(DP_FillContext *c, int x, int y)
+{
+    return !c->sel || DP_selection_contains(c->sel, x, y) != 0;
+}
+
+// Reads the pixels straight out of the tile, null tiles are transparent.
+// Neighboring pixels are usually the same color, especially on empty or
+// flat areas, so the last comparison gets reused until the color changes.
+static void flood_tile(DP_FillContext *c, DP_Tile *t_or_null, int tile_x,
+                       int tile_y, DP_Rect rect)
 {
     DP_Rect area = c->area;
-    for (int y = area.y1; y <= area.y2; ++y) {
-        for (int x = area.x1; x <= area.x2; ++x) {
-            buffer_set(c->input, area, x, y, should_flood(c, x, y) ? 1 : 0);
+    const DP_Pixel15 *pixels = t_or_null ? DP_tile_pixels(t_or_null) : NULL;
+    DP_Pixel15 last = DP_pixel15_zero();
+    bool last_value = should_flood(c, last);
+    if (!pixels && !c->sel) {
+        for (int y = rect.y1; y <= rect.y2; ++y) {
+            memset(buffer_at(c->input, area, rect.x1, y), last_value ? 1 : 0,
+                   DP_int_to_size(DP_rect_width(rect)));
+        }
+        return;
+    }
+
+    for (int y = rect.y1; y <= rect.y2; ++y) {
+        unsigned char *dst = buffer_at(c->input, area, rect.x1, y);
+        for (int x = rect.x1; x <= rect.x2; ++x) {
+            if (pixels) {
+                DP_Pixel15 pixel =
+                    pixels[(y - tile_y) * DP_TILE_SIZE + (x - tile_x)];
+                if (!DP_pixel15_equal(pixel, last)) {
+                    last = pixel;
+                    last_value = should_flood(c, pixel);
+                }
+            }
+            bool value = last_value && is_selected(c, x, y);
+            dst[x - rect.x1] = value ? 1 : 0;
         }
     }
 }
 
-static void dilate_erode_pixel(DP_Rect area, unsigned char *src,
-                               unsigned char *dst, int gap, int x0, int y0)
+static void flood_rect(DP_FillContext *c, DP_Rect rect)
 {
-    int x1 = DP_max_int(area.x1, x0 - gap);
-    int y1 = DP_max_int(area.y1, y0 - gap);
-    int x2 = DP_min_int(area.x2, x0 + gap);
-    int y2 = DP_min_int(area.y2, y0 + gap);
-    for (int y = y1; y <= y2; ++y) {
-        for (int x = x1; x <= x2; ++x) {
-            if (buffer_get(src, area, x, y) == 0) {
-                buffer_set(dst, area, x0, y0, 1);
-                return;
-            }
+    for (int ty = rect.y1 / DP_TILE_SIZE; ty <= rect.y2 / DP_TILE_SIZE; ++ty) {
+        for (int tx = rect.x1 / DP_TILE_SIZE; tx <= rect.x2 / DP_TILE_SIZE;
+             ++tx) {
+            int tile_x = tx * DP_TILE_SIZE;
+            int tile_y = ty * DP_TILE_SIZE;
+            DP_Rect tile_rect = DP_rect_intersection(
+                DP_rect_make(tile_x, tile_y, DP_TILE_SIZE, DP_TILE_SIZE), rect);
+            flood_tile(c, DP_layer_content_tile_at_noinc(c->lc, tx, ty),
+                       tile_x, tile_y, tile_rect);
         }
     }
 }
 
-static void dilate_erode(DP_FillContext *c, unsigned char *src,
-                         unsigned char *dst, int gap)
+static void flood(DP_FillContext *c)
 {
     DP_Rect area = c->area;
-    for (int y = area.y1; y <= area.y2; ++y) {
-        for (int x = area.x1; x <= area.x2; ++x) {
-            if (is_cancelled(c)) {
-                return;
-            }
-            dilate_erode_pixel(area, src, dst, gap, x, y);
-        }
+    for (int y = area.y1; y <= area.y2 && !is_cancelled(c); ++y) {
+        flood_rect(c, (DP_Rect){area.x1, y, area.x2, y});
     }
 }
 
-static void gap_fill(DP_FillContext *c, int gap, size_t buffer_size)
+static void flood_tile_job(void *element, DP_UNUSED int thread_index)
 {
-    // Classic, simple gap-filling algorithm: dilate the outlines into the
-    // output buffer, erode them back into the input buffer, then clear the
-    // output buffer again. Erosion just means dilation of transparent pixels,
-    // so we can use a single algorithm for these. Unlike with the fill
-    // expansion stuff below, we use a trivial square kernel, the round kernel
-    // gives worse results with more corners remaining unfilled.
-    dilate_erode(c, c->input, c->output, gap);
-    if (is_cancelled(c)) {
+    DP_FillScanJob *job = element;
+    flood_rect(job->c, job->rect);
+}
+
+// Each tile writes to a disjoint part of the input buffer and the layer
+// content is immutable, so they can all get looked at in parallel. The
+// progress callback isn't necessarily thread-safe, so it only gets called
+// once everything is done.
+static void flood_tiles(DP_FillContext *c)
+{
+    DP_Worker *worker = DP_worker_new(64, sizeof(DP_FillScanJob),
+                                      DP_thread_cpu_count(32), flood_tile_job);
+    if (!worker) {
+        DP_warn("Error creating flood fill worker: %s", DP_error());
+        flood(c);
         return;
     }
 
-    memset(c->input, 0, buffer_size);
-    if (is_cancelled(c)) {
-        return;
+    DP_Rect area = c->area;
+    int top = area.y1 / DP_TILE_SIZE * DP_TILE_SIZE;
+    int left = area.x1 / DP_TILE_SIZE * DP_TILE_SIZE;
+    for (int y = top; y <= area.y2; y += DP_TILE_SIZE) {
+        for (int x = left; x <= area.x2; x += DP_TILE_SIZE) {
+            DP_Rect tile_rect = DP_rect_make(x, y, DP_TILE_SIZE, DP_TILE_SIZE);
+            DP_FillScanJob job = {c, DP_rect_intersection(tile_rect, area)};
+            DP_worker_push(worker, &job);
+        }
     }
+    DP_worker_free_join(worker);
+}
 
-    dilate_erode(c, c->output, c->input, gap);
-    if (is_cancelled(c)) {
-        return;
+// Sets each destination pixel if there's any zero source pixel in the row
+// within the given radius around it. Keeps a running count of the zeroes in
+// the window, so this is linear in the radius rather than quadratic.
+static void dilate_erode_horizontally(DP_Rect area, unsigned char *src,
+                                      unsigned char *dst, int radius, int y)
+{
+    int zeroes = 0;
+    for (int x = area.x1; x <= DP_min_int(area.x2, area.x1 + radius); ++x) {
+        zeroes += buffer_get(src, area, x, y) == 0;
+    }
+    for (int x = area.x1; x <= area.x2; ++x) {
+        buffer_set(dst, area, x, y, zeroes > 0 ? 1 : 0);
+        int next_x = x + radius + 1;
+        if (next_x <= area.x2) {
+            zeroes += buffer_get(src, area, next_x, y) == 0;
+        }
+        int prev_x = x - radius;
+        if (prev_x >= area.x1) {
+            zeroes -= buffer_get(src, area, prev_x, y) == 0;
+        }
+    }
+}
+
+// Same as above, but in a column and counting set pixels instead of zeroes.
+static void dilate_erode_vertically(DP_Rect area, unsigned char *src,
+                                    unsigned char *dst, int radius, int x)
+{
+    int count = 0;
+    for (int y = area.y1; y <= DP_min_int(area.y2, area.y1 + radius); ++y) {
+        count += buffer_get(src, area, x, y) != 0;
+    }
+    for (int y = area.y1; y <= area.y2; ++y) {
+        buffer_set(dst, area, x, y, count > 0 ? 1 : 0);
+        int next_y = y + radius + 1;
+        if (next_y <= area.y2) {
+            count += buffer_get(src, area, x, next_y) != 0;
+        }
+        int prev_y = y - radius;
+        if (prev_y >= area.y1) {
+            count -= buffer_get(src, area, x, prev_y) != 0;
+        }
     }
+}
+
+// Sets each destination pixel if there's any zero source pixel in the square
+// of the given radius around it. Dilation with a square kernel is separable,
+// so this does a horizontal pass into the temporary buffer and then a
+// vertical pass into the destination.
+static void dilate_erode(DP_FillContext *c, unsigned char *src,
+                         unsigned char *dst, unsigned char *tmp, int radius)
+{
+    DP_Rect area = c->area;
+    for (int y = area.y1; y <= area.y2; ++y) {
+        if (is_cancelled(c)) {
+            return;
+        }
+        dilate_erode_horizontally(area, src, tmp, radius, y);
+    }
+    for (int x = area.x1; x <= area.x2; ++x) {
+        if (is_cancelled(c)) {
+            return;
+        }
+        dilate_erode_vertically(area, tmp, dst, radius, x);
+    }
+}
 
+static void gap_fill(DP_FillContext *c, int gap, size_t buffer_size)
+{
+    // Classic, simple gap-filling algorithm: dilate the outlines into the
+    // output buffer, then erode them back into the input buffer. That's a
+    // morphological close, which plugs holes of up to twice the radius while
+    // leaving the rest of the outlines where they were, so the fill still
+    // reaches right up to them. Erosion just means dilation of transparent
+    // pixels, so we can use a single algorithm for these. Unlike with the
+    // fill expansion stuff below, we use a trivial square kernel, the round
+    // kernel gives worse results with more corners remaining unfilled.
+    int radius = (gap + 1) / 2;
+    unsigned char *tmp = DP_malloc(buffer_size);
+    dilate_erode(c, c->input, c->output, tmp, radius);
+    if (!is_cancelled(c)) {
+        dilate_erode(c, c->output, c->input, tmp, radius);
+    }
+    DP_free(tmp);
     memset(c->output, 0, buffer_size);
 }
 
-static void add_seed(DP_Queue *s, int x, int y)
+static void add_span(DP_Queue *q, int x1, int x2, int y, int dy)
 {
-    DP_FillSeed *fs = DP_queue_push(s, sizeof(DP_FillSeed));
-    (*fs) = (DP_FillSeed){x, y};
+    DP_FillSpan *fs = DP_queue_push(q, sizeof(DP_FillSpan));
+    (*fs) = (DP_FillSpan){x1, x2, y, dy};
 }
 
-static void shift_seed(DP_Queue *s, int *out_x, int *out_y)
+static DP_FillSpan shift_span(DP_Queue *q)
 {
-    DP_FillSeed *fs = DP_queue_peek(s, sizeof(DP_FillSeed));
-    *out_x = fs->x;
-    *out_y = fs->y;
-    DP_queue_shift(s);
+    DP_FillSpan fs = *(DP_FillSpan *)DP_queue_peek(q, sizeof(DP_FillSpan));
+    DP_queue_shift(q);
+    return fs;
 }
 
 static bool inside(DP_FillContext *c, int x, int y)
@@ -216,9 +430,8 @@ static bool inside(DP_FillContext *c, int x, int y)
         && buffer_get(c->input, c->area, x, y) != 0;
 }
 
-static void set_pixel(DP_FillContext *c, int x, int y)
+static void extend_bounds(DP_FillContext *c, int x, int y)
 {
-    buffer_set(c->output, c->area, x, y, 1);
     if (x < c->min_x) {
         c->min_x = x;
     }
@@ -233,38 +446,81 @@ static void set_pixel(DP_FillContext *c, int x, int y)
     }
 }
 
-static void scan(DP_FillContext *c, DP_Queue *s, int lx, int rx, int y)
+static void set_pixel(DP_FillContext *c, int x, int y)
 {
-    bool added = false;
-    for (int x = lx; x <= rx; ++x) {
-        if (!inside(c, x, y)) {
-            added = false;
-        }
-        else if (!added) {
-            add_seed(s, x, y);
-            added = true;
-        }
+    buffer_set(c->output, c->area, x, y, 1);
+    extend_bounds(c, x, y);
+    ++c->pixels;
+}
+
+static void set_run(DP_FillContext *c, int x1, int x2, int y)
+{
+    if (x1 <= x2) {
+        memset(buffer_at(c->output, c->area, x1, y), 1,
+               DP_int_to_size(x2 - x1 + 1));
+        extend_bounds(c, x1, y);
+        extend_bounds(c, x2, y);
+        c->pixels += DP_int_to_size(x2 - x1 + 1);
     }
 }
 
+// Each span gets filled as far as it goes in both directions, then only the
+// parts of the rows above and below that haven't been looked at yet get
+// queued, so every pixel gets visited about once. Runs of pixels get found
+// first and then filled all at once, rather than pixel by pixel.
 static void fill(DP_FillContext *c, int x0, int y0)
 {
-    DP_Queue *s = &c->queue;
-    add_seed(s, x0, y0);
-    while (s->used != 0 && !is_cancelled(c)) {
-        int x, y;
-        shift_seed(s, &x, &y);
-        int lx = x;
-        while (inside(c, lx - 1, y)) {
-            set_pixel(c, lx - 1, y);
-            lx = lx - 1;
+    DP_Queue *q = &c->queue;
+    if (!inside(c, x0, y0)) {
+        return;
+    }
+    add_span(q, x0, x0, y0, 1);
+    add_span(q, x0, x0, y0 - 1, -1);
+    while (q->used != 0 && !should_stop_filling(c)) {
+        DP_FillSpan fs = shift_span(q);
+        int x1 = fs.x1, x2 = fs.x2, y = fs.y, dy = fs.dy;
+        int x = x1;
+        if (inside(c, x, y)) {
+            while (inside(c, x - 1, y)) {
+                --x;
+            }
+            set_run(c, x, x1 - 1, y);
+            if (x < x1) {
+                add_span(q, x, x1 - 1, y - dy, -dy);
+            }
         }
-        while (inside(c, x, y)) {
-            set_pixel(c, x, y);
-            x = x + 1;
+        while (x1 <= x2) {
+            int start = x1;
+            while (inside(c, x1, y)) {
+                ++x1;
+            }
+            set_run(c, start, x1 - 1, y);
+            if (x1 > x) {
+                add_span(q, x, x1 - 1, y + dy, dy);
+            }
+            if (x1 - 1 > x2) {
+                add_span(q, x2 + 1, x1 - 1, y - dy, -dy);
+            }
+            ++x1;
+            while (x1 < x2 && !inside(c, x1, y)) {
+                ++x1;
+            }
+            x = x1;
+        }
+    }
+}
+
+// Instead of only filling what's connected to the initial point, take every
+// pixel that matches, wherever it is.
+static void fill_all(DP_FillContext *c)
+{
+    DP_Rect area = c->area;
+    for (int y = area.y1; y <= area.y2 && !should_stop_filling(c); ++y) {
+        for (int x = area.x1; x <= area.x2; ++x) {
+            if (buffer_get(c->input, area, x, y) != 0) {
+                set_pixel(c, x, y);
+            }
         }
-        scan(c, s, lx, x - 1, y + 1);
-        scan(c, s, lx, x - 1, y - 1);
     }
 }
 
@@ -314,6 +570,61 @@ static void apply_expansion_kernel(float *mask, int mask_width,
     }
 }
 
+static bool survives_shrink(DP_FillContext *c, unsigned char *kernel,
+                            int shrink, int x0, int y0)
+{
+    // Pixels beyond the edge of the canvas count as filled, otherwise a fill
+    // going right up to the edge would pull away from it.
+    DP_Rect area = c->area;
+    int start_x0 = x0 - shrink;
+    int start_y0 = y0 - shrink;
+    int start_x = DP_max_int(start_x0, 0);
+    int start_y = DP_max_int(start_y0, 0);
+    int end_x = DP_min_int(x0 + shrink, c->width - 1);
+    int end_y = DP_min_int(y0 + shrink, c->height - 1);
+    int diameter = get_kernel_diameter(shrink);
+    for (int y = start_y; y <= end_y; ++y) {
+        for (int x = start_x; x <= end_x; ++x) {
+            int kernel_x = x - start_x0;
+            int kernel_y = y - start_y0;
+            if (kernel[kernel_y * diameter + kernel_x]
+                && (!DP_rect_contains(area, x, y)
+                    || buffer_get(c->output, area, x, y) == 0)) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+static void shrink_output(DP_FillContext *c, int shrink, size_t buffer_size)
+{
+    // Erosion of the filled pixels with the same round kernel that expansion
+    // uses. The result goes into a fresh buffer, since eroding in place would
+    // keep eating into pixels that were just cleared.
+    unsigned char *kernel = generate_expansion_kernel(shrink);
+    unsigned char *shrunk = DP_malloc_zeroed(buffer_size);
+    DP_Rect area = c->area;
+    int min_x = c->min_x, min_y = c->min_y;
+    int max_x = c->max_x, max_y = c->max_y;
+    c->min_x = INT_MAX;
+    c->min_y = INT_MAX;
+    c->max_x = INT_MIN;
+    c->max_y = INT_MIN;
+    for (int y = min_y; y <= max_y && !is_cancelled(c); ++y) {
+        for (int x = min_x; x <= max_x; ++x) {
+            if (buffer_get(c->output, area, x, y) != 0
+                && survives_shrink(c, kernel, shrink, x, y)) {
+                buffer_set(shrunk, area, x, y, 1);
+                extend_bounds(c, x, y);
+            }
+        }
+    }
+    DP_free(kernel);
+    DP_free(c->output);
+    c->output = shrunk;
+}
+
 static float *generate_gaussian_kernel(int radius)
 {
     // Based on Krita's Gaussian kernel generation, see license above.
@@ -456,8 +767,56 @@ static float *make_mask(DP_FillContext *c, int expand, int feather_radius,
     return mask;
 }
 
-DP_Image *mask_to_image(DP_FillContext *c, const float *mask, int img_width,
-                        int img_height, DP_UPixelFloat fill_color)
+// Expansion and feathering happen without regard for the selection, so the
+// mask gets clipped to its coverage here at the end.
+static void clip_mask_to_selection(DP_FillContext *c, float *mask, int img_x,
+                                   int img_y, int img_width, int img_height)
+{
+    DP_Selection *sel = c->sel;
+    for (int y = 0; y < img_height; ++y) {
+        if (is_cancelled(c)) {
+            return;
+        }
+        for (int x = 0; x < img_width; ++x) {
+            float *m = &mask[y * img_width + x];
+            if (*m > 0.0f) {
+                uint8_t coverage =
+                    DP_selection_contains(sel, img_x + x, img_y + y);
+                *m = DP_min_float(*m, 1.0f) * DP_uint8_to_float(coverage)
+                   / 255.0f;
+            }
+        }
+    }
+}
+
+DP_FloodFillSource DP_flood_fill_source_color(DP_UPixelFloat color)
+{
+    return (DP_FloodFillSource){
+        DP_FLOOD_FILL_SOURCE_COLOR, color, {NULL, 0, 0, 0, 0}};
+}
+
+DP_FloodFillSource DP_flood_fill_source_pattern(const DP_Pixel8 *pixels,
+                                                int width, int height,
+                                                int offset_x, int offset_y)
+{
+    DP_ASSERT(pixels);
+    DP_ASSERT(width > 0);
+    DP_ASSERT(height > 0);
+    return (DP_FloodFillSource){DP_FLOOD_FILL_SOURCE_PATTERN,
+                                {0.0f, 0.0f, 0.0f, 0.0f},
+                                {pixels, width, height, offset_x, offset_y}};
+}
+
+DP_FloodFillSource DP_flood_fill_source_erase(void)
+{
+    return (DP_FloodFillSource){DP_FLOOD_FILL_SOURCE_ERASE,
+                                {0.0f, 0.0f, 0.0f, 1.0f},
+                                {NULL, 0, 0, 0, 0}};
+}
+
+static DP_Image *mask_to_color_image(DP_FillContext *c, const float *mask,
+                                     int img_width, int img_height,
+                                     DP_UPixelFloat fill_color)
 {
     DP_Image *img = DP_image_new(img_width, img_height);
     DP_Pixel8 *pixels = DP_image_pixels(img);
@@ -484,56 +843,131 @@ DP_Image *mask_to_image(DP_FillContext *c, const float *mask, int img_width,
     return img;
 }
 
-DP_FloodFillResult
-DP_flood_fill(DP_CanvasState *cs, int x, int y, DP_UPixelFloat fill_color,
-              double tolerance, int layer_id, int size, int gap, int expand,
-              int feather_radius, DP_ViewMode view_mode, int active_layer_id,
-              int active_frame_index, DP_Image **out_img, int *out_x,
-              int *out_y, DP_FloodFillShouldCancelFn should_cancel, void *user)
+static int wrap_pattern(int i, int size)
 {
-    DP_ASSERT(cs);
+    int r = i % size;
+    return r < 0 ? r + size : r;
+}
 
-    DP_FillContext c = {
-        NULL,
-        NULL,
-        0,
-        0,
-        {0, 0, 0, 0},
-        NULL,
-        {0.0f, 0.0f, 0.0f, 0.0f},
-        tolerance * tolerance,
-        INT_MAX,
-        INT_MAX,
-        INT_MIN,
-        INT_MIN,
-        DP_QUEUE_NULL,
-        false,
-        should_cancel,
-        user,
-    };
-    if (is_cancelled(&c)) {
+static uint8_t scale_channel8(uint8_t channel, float m)
+{
+    return DP_float_to_uint8(DP_uint8_to_float(channel) * m + 0.5f);
+}
+
+// The pattern gets sampled at canvas coordinates rather than relative to
+// the image, so it doesn't matter where the filled area starts.
+static DP_Image *mask_to_pattern_image(DP_FillContext *c, const float *mask,
+                                       int img_x, int img_y, int img_width,
+                                       int img_height,
+                                       const DP_FloodFillSource *source)
+{
+    DP_Image *img = DP_image_new(img_width, img_height);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    const DP_Pixel8 *pattern = source->pattern.pixels;
+    int stride = source->pattern.width;
+    int pattern_width =
+        DP_min_int(source->pattern.width, DP_FLOOD_FILL_PATTERN_MAX_SIZE);
+    int pattern_height =
+        DP_min_int(source->pattern.height, DP_FLOOD_FILL_PATTERN_MAX_SIZE);
+
+    for (int y = 0; y < img_height; ++y) {
+        if (is_cancelled(c)) {
+            return img;
+        }
+        int py = wrap_pattern(img_y + y - source->pattern.offset_y,
+                              pattern_height);
+        for (int x = 0; x < img_width; ++x) {
+            int i = y * img_width + x;
+            float m = mask[i];
+            if (m > 0.0f) {
+                int px = wrap_pattern(img_x + x - source->pattern.offset_x,
+                                      pattern_width);
+                DP_Pixel8 p = pattern[py * stride + px];
+                if (m < 1.0f) {
+                    p.b = scale_channel8(p.b, m);
+                    p.g = scale_channel8(p.g, m);
+                    p.r = scale_channel8(p.r, m);
+                    p.a = scale_channel8(p.a, m);
+                }
+                pixels[i] = p;
+            }
+        }
+    }
+
+    return img;
+}
+
+static DP_Image *mask_to_image(DP_FillContext *c, const float *mask,
+                               int img_x, int img_y, int img_width,
+                               int img_height, const DP_FloodFillSource *source)
+{
+    switch (source->type) {
+    case DP_FLOOD_FILL_SOURCE_PATTERN:
+        return mask_to_pattern_image(c, mask, img_x, img_y, img_width,
+                                     img_height, source);
+    case DP_FLOOD_FILL_SOURCE_ERASE:
+        return mask_to_color_image(c, mask, img_width, img_height,
+                                   (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f});
+    default:
+        return mask_to_color_image(c, mask, img_width, img_height,
+                                   source->color);
+    }
+}
+
+static DP_FloodFillResult
+flood_mask(DP_FillContext *c, DP_CanvasState *cs, int x, int y,
+           DP_FloodFillSample sample, int layer_id, int size, bool contiguous,
+           int gap, int expand, int feather_radius, DP_ViewMode view_mode,
+           int active_layer_id, int active_frame_index,
+           DP_FloodFillStats *out_stats_or_null, float **out_mask,
+           int *out_img_x, int *out_img_y, int *out_img_width,
+           int *out_img_height)
+{
+    if (is_cancelled(c)) {
         return DP_FLOOD_FILL_CANCELLED;
     }
 
-    c.width = DP_canvas_state_width(cs);
-    c.height = DP_canvas_state_height(cs);
-    c.area.x1 = DP_max_int(0, x - size);
-    c.area.y1 = DP_max_int(0, y - size);
-    c.area.x2 = DP_min_int(c.width - 1, x + size);
-    c.area.y2 = DP_min_int(c.height - 1, y + size);
-    if (x < 0 || y < 0 || x >= c.width || y >= c.height
-        || !DP_rect_valid(c.area)) {
+    c->width = DP_canvas_state_width(cs);
+    c->height = DP_canvas_state_height(cs);
+    if (contiguous) {
+        c->area.x1 = DP_max_int(0, x - size);
+        c->area.y1 = DP_max_int(0, y - size);
+        c->area.x2 = DP_min_int(c->width - 1, x + size);
+        c->area.y2 = DP_min_int(c->height - 1, y + size);
+    }
+    else {
+        c->area = DP_rect_make(0, 0, c->width, c->height);
+    }
+    if (x < 0 || y < 0 || x >= c->width || y >= c->height
+        || !DP_rect_valid(c->area)) {
         DP_error_set("Flood fill: initial point out of bounds");
         return DP_FLOOD_FILL_OUT_OF_BOUNDS;
     }
 
-    if (layer_id == 0) {
+    DP_Selection *sel = c->sel;
+    if (sel) {
+        if (!is_selected(c, x, y)) {
+            DP_error_set("Flood fill: initial point outside of selection");
+            return DP_FLOOD_FILL_NOTHING_TO_FILL;
+        }
+        // Nothing outside of the selection can get filled, so there's no
+        // point in looking at any of it.
+        c->area = DP_rect_intersection(c->area, DP_selection_bounds(sel));
+    }
+    c->area_pixels = DP_int_to_size(DP_rect_width(c->area))
+                   * DP_int_to_size(DP_rect_height(c->area));
+
+    if (sample != DP_FLOOD_FILL_SAMPLE_LAYER) {
+        // Only the area we're going to look at gets flattened.
+        unsigned int flags = sample == DP_FLOOD_FILL_SAMPLE_MERGED
+                               ? DP_FLAT_IMAGE_RENDER_FLAGS
+                               : DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
         DP_ViewModeBuffer vmb;
         DP_view_mode_buffer_init(&vmb);
         DP_ViewModeFilter vmf = DP_view_mode_filter_make(
             &vmb, view_mode, cs, active_layer_id, active_frame_index, NULL);
-        c.lc = (DP_LayerContent *)DP_canvas_state_to_flat_layer(
-            cs, DP_FLAT_IMAGE_RENDER_FLAGS, &vmf);
+        c->lc = (DP_LayerContent *)DP_canvas_state_to_flat_layer(
+            cs, flags, &c->area, &vmf);
         DP_view_mode_buffer_dispose(&vmb);
     }
     else {
@@ -547,67 +981,139 @@ DP_flood_fill(DP_CanvasState *cs, int x, int y, DP_UPixelFloat fill_color,
             DP_LayerGroup *lg = DP_layer_routes_entry_group(lre, cs);
             DP_LayerProps *lp = DP_layer_routes_entry_props(lre, cs);
             DP_TransientLayerContent *tlc = DP_layer_group_merge(lg, lp);
-            c.lc = (DP_LayerContent *)tlc;
+            c->lc = (DP_LayerContent *)tlc;
         }
         else {
-            c.lc =
+            c->lc =
                 DP_layer_content_incref(DP_layer_routes_entry_content(lre, cs));
         }
     }
 
-    if (is_cancelled(&c)) {
-        DP_layer_content_decref(c.lc);
+    if (is_cancelled(c)) {
+        DP_layer_content_decref(c->lc);
         return DP_FLOOD_FILL_CANCELLED;
     }
 
-    size_t buffer_size = DP_int_to_size(DP_rect_width(c.area))
-                       * DP_int_to_size(DP_rect_height(c.area));
-    c.input = DP_malloc(buffer_size);
-    c.reference_color = get_color_at(c.lc, x, y);
-    flood(&c);
-    DP_layer_content_decref(c.lc);
-    if (is_cancelled(&c)) {
-        DP_free(c.input);
+    size_t buffer_size = c->area_pixels;
+    c->input = DP_malloc(buffer_size);
+    c->reference_color = pixel_to_color(DP_layer_content_pixel_at(c->lc, x, y));
+    c->stage = DP_FLOOD_FILL_STAGE_SCAN;
+    if (contiguous) {
+        flood(c);
+    }
+    else {
+        flood_tiles(c);
+    }
+    DP_layer_content_decref(c->lc);
+    if (is_cancelled(c)) {
+        DP_free(c->input);
         return DP_FLOOD_FILL_CANCELLED;
     }
 
-    c.output = DP_malloc_zeroed(buffer_size);
+    c->output = DP_malloc_zeroed(buffer_size);
     if (gap > 0) {
-        gap_fill(&c, gap, buffer_size);
-        if (is_cancelled(&c)) {
-            DP_free(c.input);
-            DP_free(c.output);
+        c->stage = DP_FLOOD_FILL_STAGE_GAP;
+        gap_fill(c, gap, buffer_size);
+        if (is_cancelled(c)) {
+            DP_free(c->input);
+            DP_free(c->output);
             return DP_FLOOD_FILL_CANCELLED;
         }
     }
 
-    DP_queue_init(&c.queue, 1024, sizeof(DP_FillSeed));
-    fill(&c, x, y);
-    DP_queue_dispose(&c.queue);
-    DP_free(c.input);
+    c->stage = DP_FLOOD_FILL_STAGE_FILL;
+    if (contiguous) {
+        DP_queue_init(&c->queue, 1024, sizeof(DP_FillSpan));
+        fill(c, x, y);
+        DP_queue_dispose(&c->queue);
+    }
+    else {
+        fill_all(c);
+    }
+    DP_free(c->input);
+
+    if (out_stats_or_null) {
+        *out_stats_or_null = (DP_FloodFillStats){
+            c->pixels, {c->min_x, c->min_y, c->max_x, c->max_y}};
+    }
+
+    if (c->too_large) {
+        DP_error_set("Flood fill: more than %zu pixels filled", c->max_pixels);
+        DP_free(c->output);
+        return DP_FLOOD_FILL_TOO_LARGE;
+    }
 
-    if (is_cancelled(&c)) {
-        DP_free(c.output);
+    if (is_cancelled(c)) {
+        DP_free(c->output);
         return DP_FLOOD_FILL_CANCELLED;
     }
+    c->stage = DP_FLOOD_FILL_STAGE_FINISH;
 
-    if (c.min_x > c.max_x || c.min_y > c.max_y) {
+    if (c->min_x > c->max_x || c->min_y > c->max_y) {
         DP_error_set("Flood fill: nothing to fill");
-        DP_free(c.output);
+        DP_free(c->output);
         return DP_FLOOD_FILL_NOTHING_TO_FILL;
     }
 
+    if (expand < 0) {
+        shrink_output(c, -expand, buffer_size);
+        if (is_cancelled(c)) {
+            DP_free(c->output);
+            return DP_FLOOD_FILL_CANCELLED;
+        }
+        else if (c->min_x > c->max_x || c->min_y > c->max_y) {
+            DP_error_set("Flood fill: nothing left to fill after shrinking");
+            DP_free(c->output);
+            return DP_FLOOD_FILL_NOTHING_TO_FILL;
+        }
+    }
+
     int img_x, img_y, img_width, img_height;
     float *mask =
-        make_mask(&c, DP_max_int(expand, 0), DP_max_int(feather_radius, 0),
+        make_mask(c, DP_max_int(expand, 0), DP_max_int(feather_radius, 0),
                   &img_x, &img_y, &img_width, &img_height);
-    DP_free(c.output);
-    if (is_cancelled(&c)) {
+    DP_free(c->output);
+    if (sel && !is_cancelled(c)) {
+        clip_mask_to_selection(c, mask, img_x, img_y, img_width, img_height);
+    }
+    if (is_cancelled(c)) {
         DP_free(mask);
         return DP_FLOOD_FILL_CANCELLED;
     }
 
-    DP_Image *img = mask_to_image(&c, mask, img_width, img_height, fill_color);
+    *out_mask = mask;
+    *out_img_x = img_x;
+    *out_img_y = img_y;
+    *out_img_width = img_width;
+    *out_img_height = img_height;
+    return DP_FLOOD_FILL_SUCCESS;
+}
+
+DP_FloodFillResult
+DP_flood_fill(DP_CanvasState *cs, int x, int y, DP_FloodFillSource source,
+              double tolerance, DP_FloodFillMetric metric,
+              DP_FloodFillSample sample, int layer_id,
+              DP_Selection *sel_or_null, int size, int gap, int expand,
+              int feather_radius, DP_ViewMode view_mode, int active_layer_id,
+              int active_frame_index, size_t max_pixels, DP_Image **out_img,
+              int *out_x, int *out_y, DP_FloodFillStats *out_stats_or_null,
+              DP_FloodFillProgressFn progress, void *user)
+{
+    DP_ASSERT(cs);
+    DP_FillContext c = make_fill_context(sel_or_null, tolerance, metric,
+                                         max_pixels, progress, user);
+    float *mask;
+    int img_x, img_y, img_width, img_height;
+    DP_FloodFillResult result = flood_mask(
+        &c, cs, x, y, sample, layer_id, size, true, gap, expand,
+        feather_radius, view_mode, active_layer_id, active_frame_index,
+        out_stats_or_null, &mask, &img_x, &img_y, &img_width, &img_height);
+    if (result != DP_FLOOD_FILL_SUCCESS) {
+        return result;
+    }
+
+    DP_Image *img = mask_to_image(&c, mask, img_x, img_y, img_width,
+                                  img_height, &source);
     DP_free(mask);
 
     if (out_x) {
@@ -624,3 +1130,66 @@ DP_flood_fill(DP_CanvasState *cs, int x, int y, DP_UPixelFloat fill_color,
     }
     return DP_FLOOD_FILL_SUCCESS;
 }
+
+// Feathering can spill past the edges of the canvas, but there's nothing
+// there to select, so the mask gets cropped to it.
+static DP_Selection *mask_to_selection(const float *mask, int img_x,
+                                       int img_y, int img_width,
+                                       int img_height, int canvas_width,
+                                       int canvas_height)
+{
+    DP_Rect img_rect = DP_rect_make(img_x, img_y, img_width, img_height);
+    DP_Rect rect = DP_rect_intersection(
+        img_rect, DP_rect_make(0, 0, canvas_width, canvas_height));
+    int width = DP_rect_width(rect);
+    int height = DP_rect_height(rect);
+    uint8_t *coverage =
+        DP_malloc(DP_int_to_size(width) * DP_int_to_size(height));
+    for (int y = 0; y < height; ++y) {
+        const float *src =
+            mask + (rect.y1 - img_y + y) * img_width + (rect.x1 - img_x);
+        for (int x = 0; x < width; ++x) {
+            float m = DP_max_float(0.0f, DP_min_float(src[x], 1.0f));
+            coverage[y * width + x] = DP_float_to_uint8(m * 255.0f + 0.5f);
+        }
+    }
+    DP_Selection *sel =
+        DP_selection_new_from_mask(rect.x1, rect.y1, width, height, coverage);
+    DP_free(coverage);
+    return sel;
+}
+
+DP_FloodFillResult
+DP_flood_select(DP_CanvasState *cs, int x, int y, double tolerance,
+                DP_FloodFillMetric metric, DP_FloodFillSample sample,
+                int layer_id, int size, bool contiguous, int gap, int expand,
+                int feather_radius, DP_ViewMode view_mode, int active_layer_id,
+                int active_frame_index, size_t max_pixels,
+                DP_Selection **out_sel, DP_FloodFillStats *out_stats_or_null,
+                DP_FloodFillProgressFn progress, void *user)
+{
+    DP_ASSERT(cs);
+    DP_FillContext c = make_fill_context(NULL, tolerance, metric, max_pixels,
+                                         progress, user);
+    float *mask;
+    int img_x, img_y, img_width, img_height;
+    DP_FloodFillResult result = flood_mask(
+        &c, cs, x, y, sample, layer_id, size, contiguous, gap, expand,
+        feather_radius, view_mode, active_layer_id, active_frame_index,
+        out_stats_or_null, &mask, &img_x, &img_y, &img_width, &img_height);
+    if (result != DP_FLOOD_FILL_SUCCESS) {
+        return result;
+    }
+
+    DP_Selection *sel = mask_to_selection(mask, img_x, img_y, img_width,
+                                          img_height, c.width, c.height);
+    DP_free(mask);
+
+    if (out_sel) {
+        *out_sel = sel;
+    }
+    else {
+        DP_selection_decref(sel);
+    }
+    return DP_FLOOD_FILL_SUCCESS;
+}
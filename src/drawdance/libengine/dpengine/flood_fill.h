@@ -24,9 +24,11 @@
 #include "pixels.h"
 #include "view_mode.h"
 #include <dpcommon/common.h>
+#include <dpcommon/geom.h>
 
 typedef struct DP_CanvasState DP_CanvasState;
 typedef struct DP_Image DP_Image;
+typedef struct DP_Selection DP_Selection;
 
 typedef enum DP_FloodFillResult {
     DP_FLOOD_FILL_SUCCESS,
@@ -34,16 +36,135 @@ typedef enum DP_FloodFillResult {
     DP_FLOOD_FILL_INVALID_LAYER,
     DP_FLOOD_FILL_NOTHING_TO_FILL,
     DP_FLOOD_FILL_CANCELLED,
+    DP_FLOOD_FILL_TOO_LARGE,
 } DP_FloodFillResult;
 
-typedef bool (*DP_FloodFillShouldCancelFn)(void *user);
+typedef struct DP_FloodFillStats {
+    // Number of pixels filled, before any expansion or shrinking.
+    size_t pixels;
+    // Bounds of those pixels, invalid if there weren't any.
+    DP_Rect bounds;
+} DP_FloodFillStats;
 
+// How the tolerance gets compared against the colors in the area.
+typedef enum DP_FloodFillMetric {
+    // Perceptual color distance, see DP_upixel_float_distance. Transparent
+    // reference colors get compared by alpha alone.
+    DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+    // Euclidean distance between the color channels. This is how it used to
+    // work and treats different hues very unevenly.
+    DP_FLOOD_FILL_METRIC_CHANNELS,
+} DP_FloodFillMetric;
+
+// Where the colors get read from. The fill itself always ends up wherever
+// the caller puts the resulting image.
+typedef enum DP_FloodFillSample {
+    // The layer or group with the given id.
+    DP_FLOOD_FILL_SAMPLE_LAYER,
+    // Everything visible on the canvas, as filtered by the view mode. Hidden
+    // layers are left out and censored ones are censored, like on screen.
+    DP_FLOOD_FILL_SAMPLE_MERGED,
+    // Same as above, but without the canvas background.
+    DP_FLOOD_FILL_SAMPLE_MERGED_WITHOUT_BACKGROUND,
+} DP_FloodFillSample;
+
+// Patterns larger than this get cropped to their top-left corner.
+#define DP_FLOOD_FILL_PATTERN_MAX_SIZE 1024
+
+// What the filled area gets filled with.
+typedef enum DP_FloodFillSourceType {
+    DP_FLOOD_FILL_SOURCE_COLOR,
+    // Premultiplied pixels repeating across the canvas. They're placed in
+    // canvas space, with their top-left corner at the offset, so that
+    // separate fills with the same pattern line up with each other.
+    DP_FLOOD_FILL_SOURCE_PATTERN,
+    // Opaque black, meant to be put onto the layer with DP_BLEND_MODE_ERASE.
+    DP_FLOOD_FILL_SOURCE_ERASE,
+} DP_FloodFillSourceType;
+
+typedef struct DP_FloodFillSource {
+    DP_FloodFillSourceType type;
+    DP_UPixelFloat color;
+    struct {
+        const DP_Pixel8 *pixels;
+        int width, height;
+        int offset_x, offset_y;
+    } pattern;
+} DP_FloodFillSource;
+
+// Steps a fill goes through, in this order. Those that don't apply, like
+// closing gaps when the gap is zero, get skipped.
+typedef enum DP_FloodFillStage {
+    // Getting the layer to sample from or flattening the canvas.
+    DP_FLOOD_FILL_STAGE_PREPARE,
+    // Comparing the colors in the area against the reference color.
+    DP_FLOOD_FILL_STAGE_SCAN,
+    // Closing gaps in the outlines.
+    DP_FLOOD_FILL_STAGE_GAP,
+    // Filling the matching pixels, this is where the filled count goes up.
+    DP_FLOOD_FILL_STAGE_FILL,
+    // Shrinking, expanding, feathering and clipping to the selection.
+    DP_FLOOD_FILL_STAGE_FINISH,
+} DP_FloodFillStage;
+
+// Gets the current stage, the number of pixels filled so far and the number
+// of pixels in the area the fill looks at, which is as many as it can fill.
+// The area is 0 until it's known. Returning true cancels the fill.
+typedef bool (*DP_FloodFillProgressFn)(void *user, DP_FloodFillStage stage,
+                                       size_t filled, size_t area);
+
+
+DP_FloodFillSource DP_flood_fill_source_color(DP_UPixelFloat color);
+
+// The pixels aren't copied, so they must live as long as the fill runs.
+DP_FloodFillSource DP_flood_fill_source_pattern(const DP_Pixel8 *pixels,
+                                                int width, int height,
+                                                int offset_x, int offset_y);
+
+DP_FloodFillSource DP_flood_fill_source_erase(void);
+
+
+// The tolerance ranges from 0, where only exactly the color at the given
+// coordinates gets filled, to 1, where everything does. The gap is the width
+// in pixels of holes in the outlines that the fill shouldn't leak through.
+// Afterwards, the filled area grows by expand pixels, or shrinks if it's
+// negative, and then gets its edges blurred by the feather radius. Expansion
+// stops at the edges of the canvas, the resulting image and its position
+// cover the grown area plus the feather radius on each side. Everything only
+// happens within the size limit around the given coordinates, the rest of
+// the canvas never gets looked at. If more than max_pixels get filled, the
+// fill stops and returns DP_FLOOD_FILL_TOO_LARGE, 0 means there's no limit.
+// The progress callback gets called between stages and every few thousand
+// pixels within them. Returning true from it stops the fill and returns
+// DP_FLOOD_FILL_CANCELLED. Either way, if given, the stats will say how much
+// got filled before stopping. With a selection, pixels outside of it act as
+// boundaries and the resulting image gets multiplied by its coverage, so
+// anti-aliased selection edges carry over.
+// The image's pixels come from the given source, see above.
 DP_FloodFillResult
-DP_flood_fill(DP_CanvasState *cs, int x, int y, DP_UPixelFloat fill_color,
-              double tolerance, int layer_id, int size, int gap, int expand,
+DP_flood_fill(DP_CanvasState *cs, int x, int y, DP_FloodFillSource source,
+              double tolerance, DP_FloodFillMetric metric,
+              DP_FloodFillSample sample, int layer_id,
+              DP_Selection *sel_or_null, int size, int gap, int expand,
               int feather_radius, DP_ViewMode view_mode, int active_layer_id,
-              int active_frame_index, DP_Image **out_img, int *out_x,
-              int *out_y, DP_FloodFillShouldCancelFn should_cancel, void *user);
+              int active_frame_index, size_t max_pixels, DP_Image **out_img,
+              int *out_x, int *out_y, DP_FloodFillStats *out_stats_or_null,
+              DP_FloodFillProgressFn progress, void *user);
+
+// Magic wand, works like the above, but produces a selection instead of an
+// image. The feathered edges end up as the selection's coverage, cropped to
+// the canvas. If contiguous is false, every matching pixel on the canvas
+// gets selected instead of only those connected to the given coordinates,
+// so the size limit doesn't apply. That scan gets spread across threads,
+// tile by tile, so progress only gets reported before and after it.
+DP_FloodFillResult
+DP_flood_select(DP_CanvasState *cs, int x, int y, double tolerance,
+                DP_FloodFillMetric metric, DP_FloodFillSample sample,
+                int layer_id, int size, bool contiguous, int gap, int expand,
+                int feather_radius, DP_ViewMode view_mode, int active_layer_id,
+                int active_frame_index, size_t max_pixels,
+                DP_Selection **out_sel, DP_FloodFillStats *out_stats_or_null,
+                DP_FloodFillProgressFn progress, void *user);
 
 
 #endif
@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: MIT
+#include "gradient.h"
+#include "image.h"
+#include "layer_content.h"
+#include "pixels.h"
+#include "selection.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <math.h>
+
+
+// 8x8 Bayer matrix, spreads the thresholds evenly so that the dither pattern
+// doesn't clump together.
+static const uint8_t bayer_matrix[8][8] = {
+    {0, 32, 8, 40, 2, 34, 10, 42},  {48, 16, 56, 24, 50, 18, 58, 26},
+    {12, 44, 4, 36, 14, 46, 6, 38}, {60, 28, 52, 20, 62, 30, 54, 22},
+    {3, 35, 11, 43, 1, 33, 9, 41},  {51, 19, 59, 27, 49, 17, 57, 25},
+    {15, 47, 7, 39, 13, 45, 5, 37}, {63, 31, 55, 23, 61, 29, 53, 21},
+};
+
+DP_UPixelFloat DP_gradient_color_at(const DP_Gradient *gradient,
+                                    float position)
+{
+    DP_ASSERT(gradient);
+    DP_ASSERT(gradient->stop_count > 0);
+    DP_ASSERT(gradient->stops);
+    const DP_GradientStop *stops = gradient->stops;
+    int count = gradient->stop_count;
+
+    // Find the first stop past the position. With stops at the same position,
+    // that skips over all of them, so the last one's color applies at it.
+    int i = 0;
+    while (i < count && stops[i].position <= position) {
+        ++i;
+    }
+
+    if (i == 0) {
+        return stops[0].color;
+    }
+    else if (i == count) {
+        return stops[count - 1].color;
+    }
+    else {
+        const DP_GradientStop *prev = &stops[i - 1];
+        const DP_GradientStop *next = &stops[i];
+        float amount =
+            (position - prev->position) / (next->position - prev->position);
+        return DP_upixel_float_mix_linear(prev->color, next->color, amount);
+    }
+}
+
+float DP_gradient_position_at(const DP_Gradient *gradient, int x, int y)
+{
+    DP_ASSERT(gradient);
+    double px = DP_int_to_double(x) + 0.5;
+    double py = DP_int_to_double(y) + 0.5;
+    switch (gradient->shape) {
+    case DP_GRADIENT_SHAPE_LINEAR: {
+        double dx = gradient->x2 - gradient->x1;
+        double dy = gradient->y2 - gradient->y1;
+        double length_squared = dx * dx + dy * dy;
+        if (length_squared > 0.0) {
+            return (float)(((px - gradient->x1) * dx + (py - gradient->y1) * dy)
+                           / length_squared);
+        }
+        else {
+            return 1.0f;
+        }
+    }
+    case DP_GRADIENT_SHAPE_RADIAL: {
+        double radius = gradient->radius;
+        if (radius > 0.0) {
+            return (float)(hypot(px - gradient->x1, py - gradient->y1)
+                           / radius);
+        }
+        else {
+            return 1.0f;
+        }
+    }
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
+static uint8_t round_channel(float c, float offset, uint8_t max)
+{
+    float value = floorf(c * 255.0f + offset);
+    if (value <= 0.0f) {
+        return 0;
+    }
+    else {
+        uint8_t result = (uint8_t)DP_min_float(value, 255.0f);
+        return result < max ? result : max;
+    }
+}
+
+static DP_Pixel8 render_pixel(const DP_Gradient *gradient, int x, int y,
+                              uint8_t coverage)
+{
+    DP_UPixelFloat color =
+        DP_gradient_color_at(gradient, DP_gradient_position_at(gradient, x, y));
+    float a = DP_max_float(0.0f, DP_min_float(color.a, 1.0f))
+            * DP_uint8_to_float(coverage) / 255.0f;
+    // Rounding to nearest is flooring with an offset of a half. Dithering
+    // moves that threshold around from pixel to pixel instead.
+    float offset = gradient->dither
+                     ? (DP_uint8_to_float(bayer_matrix[y & 7][x & 7]) + 0.5f)
+                           / 64.0f
+                     : 0.5f;
+    uint8_t a8 = round_channel(a, offset, 255);
+    return (DP_Pixel8){
+        .b = round_channel(color.b * a, offset, a8),
+        .g = round_channel(color.g * a, offset, a8),
+        .r = round_channel(color.r * a, offset, a8),
+        .a = a8,
+    };
+}
+
+DP_Image *DP_gradient_render(const DP_Gradient *gradient, DP_Rect rect,
+                             DP_Selection *sel_or_null)
+{
+    DP_ASSERT(gradient);
+    DP_ASSERT(DP_rect_valid(rect));
+    int width = DP_rect_width(rect);
+    int height = DP_rect_height(rect);
+    DP_Image *img = DP_image_new(width, height);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            int canvas_x = rect.x1 + x;
+            int canvas_y = rect.y1 + y;
+            uint8_t coverage =
+                sel_or_null
+                    ? DP_selection_contains(sel_or_null, canvas_x, canvas_y)
+                    : 255;
+            if (coverage != 0) {
+                pixels[y * width + x] =
+                    render_pixel(gradient, canvas_x, canvas_y, coverage);
+            }
+        }
+    }
+    return img;
+}
+
+DP_Rect DP_gradient_fill(DP_TransientLayerContent *tlc, unsigned int context_id,
+                         int blend_mode, const DP_Gradient *gradient,
+                         const DP_Rect *rect_or_null,
+                         DP_Selection *sel_or_null)
+{
+    DP_ASSERT(tlc);
+    DP_ASSERT(gradient);
+    DP_Rect area =
+        DP_rect_make(0, 0, DP_transient_layer_content_width(tlc),
+                     DP_transient_layer_content_height(tlc));
+    if (rect_or_null) {
+        area = DP_rect_intersection(area, *rect_or_null);
+    }
+    if (sel_or_null) {
+        area = DP_rect_intersection(area, DP_selection_bounds(sel_or_null));
+    }
+
+    if (DP_rect_valid(area)) {
+        DP_Image *img = DP_gradient_render(gradient, area, sel_or_null);
+        DP_transient_layer_content_put_image(tlc, context_id, blend_mode,
+                                             area.x1, area.y1, img);
+        DP_image_free(img);
+    }
+    return area;
+}
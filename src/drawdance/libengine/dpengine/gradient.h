@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_GRADIENT_H
+#define DPENGINE_GRADIENT_H
+#include "pixels.h"
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+typedef struct DP_Image DP_Image;
+typedef struct DP_Selection DP_Selection;
+
+#ifdef DP_NO_STRICT_ALIASING
+typedef struct DP_TransientLayerContent DP_TransientLayerContent;
+#else
+typedef struct DP_LayerContent DP_TransientLayerContent;
+#endif
+
+
+typedef enum DP_GradientShape {
+    DP_GRADIENT_SHAPE_LINEAR,
+    DP_GRADIENT_SHAPE_RADIAL,
+    DP_GRADIENT_SHAPE_COUNT,
+} DP_GradientShape;
+
+// Unpremultiplied color at a position from 0 at the start of the gradient to
+// 1 at its end.
+typedef struct DP_GradientStop {
+    float position;
+    DP_UPixelFloat color;
+} DP_GradientStop;
+
+// Stops must be sorted by position and there must be at least one of them.
+// Consecutive stops at the same position make a hard edge, the first one's
+// color applies before it, the second one's at and after it. Coordinates are
+// in canvas pixels and may lie outside of the area being filled.
+typedef struct DP_Gradient {
+    DP_GradientShape shape;
+    // Start and end point of linear gradients. Radial ones only use the start
+    // as their center.
+    double x1, y1, x2, y2;
+    // How far radial gradients reach out from their center.
+    double radius;
+    int stop_count;
+    const DP_GradientStop *stops;
+    // Adds an ordered dither when rounding to 8 bits, which hides banding in
+    // subtle gradients. It's based on the canvas position, so it's the same
+    // whenever the gradient gets drawn.
+    bool dither;
+} DP_Gradient;
+
+
+// Color of the first stop before it and of the last one past it, in between
+// adjacent stops get mixed in linear light.
+DP_UPixelFloat DP_gradient_color_at(const DP_Gradient *gradient,
+                                    float position);
+
+// Position along the gradient at the center of the given pixel, from 0 at the
+// start to 1 at the end. It's not clamped, so it goes below 0 before the start
+// of a linear gradient and above 1 past the end. A linear gradient with its
+// start and end at the same point or a radial one with a radius of 0 or less
+// puts every pixel at its end.
+float DP_gradient_position_at(const DP_Gradient *gradient, int x, int y);
+
+// Renders the gradient into a premultiplied 8 bit image covering the given
+// rectangle, multiplied by the coverage of the selection if one is given.
+DP_Image *DP_gradient_render(const DP_Gradient *gradient, DP_Rect rect,
+                             DP_Selection *sel_or_null);
+
+// Blends the gradient into the layer within the given rectangle, or all of the
+// layer if it's NULL, as well as the selection if one is given. Returns the
+// area that got drawn to, or an invalid rectangle if there's none.
+DP_Rect DP_gradient_fill(DP_TransientLayerContent *tlc, unsigned int context_id,
+                         int blend_mode, const DP_Gradient *gradient,
+                         const DP_Rect *rect_or_null,
+                         DP_Selection *sel_or_null);
+
+
+#endif
@@ -354,6 +354,32 @@ DP_Image *DP_image_new_subimage(DP_Image *img, int x, int y, int width,
 }
 
 
+static long long quad_turn(int ax, int ay, int bx, int by, int cx, int cy)
+{
+    return DP_int_to_llong(bx - ax) * DP_int_to_llong(cy - by)
+         - DP_int_to_llong(by - ay) * DP_int_to_llong(cx - bx);
+}
+
+// A projective transform always maps the source rectangle onto a convex quad.
+// Self-intersecting, concave or flattened quads don't have a transform that
+// gets there, so trying anyway would only spit out garbage.
+static bool quad_convex(DP_Quad quad)
+{
+    long long turns[4] = {
+        quad_turn(quad.x1, quad.y1, quad.x2, quad.y2, quad.x3, quad.y3),
+        quad_turn(quad.x2, quad.y2, quad.x3, quad.y3, quad.x4, quad.y4),
+        quad_turn(quad.x3, quad.y3, quad.x4, quad.y4, quad.x1, quad.y1),
+        quad_turn(quad.x4, quad.y4, quad.x1, quad.y1, quad.x2, quad.y2),
+    };
+    bool clockwise = turns[0] > 0;
+    for (int i = 0; i < 4; ++i) {
+        if (turns[i] == 0 || (turns[i] > 0) != clockwise) {
+            return false;
+        }
+    }
+    return true;
+}
+
 DP_Image *DP_image_transform_pixels(int src_width, int src_height,
                                     const DP_Pixel8 *src_pixels,
                                     DP_DrawContext *dc, const DP_Quad *dst_quad,
@@ -362,6 +388,11 @@ DP_Image *DP_image_transform_pixels(int src_width, int src_height,
 {
     DP_ASSERT(src_pixels);
     DP_ASSERT(dst_quad);
+    if (!quad_convex(*dst_quad)) {
+        DP_error_set("Image transform destination is degenerate");
+        return NULL;
+    }
+
     DP_Quad src_quad =
         DP_quad_make(0, 0, src_width, 0, src_width, src_height, 0, src_height);
 
@@ -389,8 +420,10 @@ DP_Image *DP_image_transform_pixels(int src_width, int src_height,
     }
 
     DP_Image *dst_img = DP_image_new(dst_width, dst_height);
-    if (!DP_image_transform_draw(src_width, src_height, src_pixels, dc, dst_img,
-                                 mtf.tf, interpolation)) {
+    if (!DP_image_transform_draw_prefiltered(src_width, src_height, src_pixels,
+                                             dc, dst_img, mtf.tf,
+                                             translated_dst_quad,
+                                             interpolation)) {
         DP_image_free(dst_img);
         return NULL;
     }
@@ -468,6 +501,21 @@ bool DP_image_thumbnail(DP_Image *img, DP_DrawContext *dc, int max_width,
     }
 }
 
+void DP_image_thumbnail_size(int width, int height, int max_width,
+                             int max_height, int *out_width, int *out_height)
+{
+    DP_ASSERT(out_width);
+    DP_ASSERT(out_height);
+    if (width > max_width || height > max_height) {
+        thumbnail_scale(width, height, max_width, max_height, out_width,
+                        out_height);
+    }
+    else {
+        *out_width = width;
+        *out_height = height;
+    }
+}
+
 bool DP_image_same_pixel(DP_Image *img, DP_Pixel8 *out_pixel)
 {
     DP_Pixel8 *pixels = DP_image_pixels(img);
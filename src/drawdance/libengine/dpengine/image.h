@@ -80,6 +80,9 @@ DP_Pixel8 DP_image_pixel_at(DP_Image *img, int x, int y);
 void DP_image_pixel_at_set(DP_Image *img, int x, int y, DP_Pixel8 pixel);
 
 
+// Maps the source pixels onto the destination quad with a projective
+// transform. Fails if the quad isn't convex, since no such transform gets a
+// rectangle there.
 DP_Image *DP_image_transform_pixels(int src_width, int src_height,
                                     const DP_Pixel8 *src_pixels,
                                     DP_DrawContext *dc, const DP_Quad *dst_quad,
@@ -101,6 +104,11 @@ DP_Image *DP_image_transform(DP_Image *img, DP_DrawContext *dc,
 bool DP_image_thumbnail(DP_Image *img, DP_DrawContext *dc, int max_width,
                         int max_height, DP_Image **out_thumb) DP_MUST_CHECK;
 
+// Dimensions that DP_image_thumbnail scales an image of the given size to, or
+// the size itself if it already fits.
+void DP_image_thumbnail_size(int width, int height, int max_width,
+                             int max_height, int *out_width, int *out_height);
+
 bool DP_image_same_pixel(DP_Image *img, DP_Pixel8 *out_pixel);
 
 DP_UPixelFloat DP_image_sample_color_at_with(int width, int height,
@@ -31,6 +31,7 @@
 #include <dpmsg/messages.h>
 #include <qgrayraster_inc.h>
 #include <helpers.h> // CLAMP
+#include <math.h>
 
 
 struct DP_RenderSpansData {
@@ -113,6 +114,60 @@ static uint32_t fetch_transformed_pixel_bilinear(int width, int height,
                                 s2[x2].color, distx, disty);
 }
 
+// Keys' cubic convolution kernel with a = -0.5, the same as Catmull-Rom.
+static void bicubic_weights(double t, double *out_weights)
+{
+    double t2 = t * t;
+    double t3 = t2 * t;
+    out_weights[0] = -0.5 * t3 + t2 - 0.5 * t;
+    out_weights[1] = 1.5 * t3 - 2.5 * t2 + 1.0;
+    out_weights[2] = -1.5 * t3 + 2.0 * t2 + 0.5 * t;
+    out_weights[3] = 0.5 * t3 - 0.5 * t2;
+}
+
+static uint8_t bicubic_channel(double c, double max)
+{
+    return DP_double_to_uint8(CLAMP(c, 0.0, max) + 0.5);
+}
+
+static uint32_t fetch_transformed_pixel_bicubic(int width, int height,
+                                                const DP_Pixel8 *pixels,
+                                                double px, double py)
+{
+    double fx = floor(px);
+    double fy = floor(py);
+    int x0 = DP_double_to_int(fx);
+    int y0 = DP_double_to_int(fy);
+    double wx[4], wy[4];
+    bicubic_weights(px - fx, wx);
+    bicubic_weights(py - fy, wy);
+
+    double b = 0.0, g = 0.0, r = 0.0, a = 0.0;
+    for (int j = 0; j < 4; ++j) {
+        int y = CLAMP(y0 + j - 1, 0, height - 1);
+        const DP_Pixel8 *row = pixels + y * width;
+        for (int i = 0; i < 4; ++i) {
+            int x = CLAMP(x0 + i - 1, 0, width - 1);
+            double w = wx[i] * wy[j];
+            DP_Pixel8 pixel = row[x];
+            b += DP_uint8_to_double(pixel.b) * w;
+            g += DP_uint8_to_double(pixel.g) * w;
+            r += DP_uint8_to_double(pixel.r) * w;
+            a += DP_uint8_to_double(pixel.a) * w;
+        }
+    }
+
+    // The negative lobes can overshoot, clamping the color channels to alpha
+    // keeps the result validly premultiplied.
+    uint8_t a8 = bicubic_channel(a, 255.0);
+    double max = DP_uint8_to_double(a8);
+    DP_Pixel8 result = {.b = bicubic_channel(b, max),
+                        .g = bicubic_channel(g, max),
+                        .r = bicubic_channel(r, max),
+                        .a = a8};
+    return result.color;
+}
+
 static uint32_t fetch_transformed_pixel(int interpolation, int width,
                                         int height, const DP_Pixel8 *pixels,
                                         double px, double py)
@@ -120,6 +175,8 @@ static uint32_t fetch_transformed_pixel(int interpolation, int width,
     switch (interpolation) {
     case DP_MSG_TRANSFORM_REGION_MODE_NEAREST:
         return fetch_transformed_pixel_nearest(width, height, pixels, px, py);
+    case DP_MSG_TRANSFORM_REGION_MODE_BICUBIC:
+        return fetch_transformed_pixel_bicubic(width, height, pixels, px, py);
     default:
         return fetch_transformed_pixel_bilinear(width, height, pixels, px, py);
     }
@@ -343,3 +400,117 @@ bool DP_image_transform_draw(int src_width, int src_height,
 
     return done;
 }
+
+static double quad_edge_length(int xa, int ya, int xb, int yb)
+{
+    double dx = DP_int_to_double(xb - xa);
+    double dy = DP_int_to_double(yb - ya);
+    return sqrt(dx * dx + dy * dy);
+}
+
+// How much the transform to the destination quad scales the source along its
+// own x and y axes, averaging the opposite edges of the quad.
+static void quad_axis_scales(DP_Quad quad, int src_width, int src_height,
+                             double *out_scale_x, double *out_scale_y)
+{
+    double top = quad_edge_length(quad.x1, quad.y1, quad.x2, quad.y2);
+    double bottom = quad_edge_length(quad.x4, quad.y4, quad.x3, quad.y3);
+    double left = quad_edge_length(quad.x1, quad.y1, quad.x4, quad.y4);
+    double right = quad_edge_length(quad.x2, quad.y2, quad.x3, quad.y3);
+    *out_scale_x = (top + bottom) * 0.5 / DP_int_to_double(src_width);
+    *out_scale_y = (left + right) * 0.5 / DP_int_to_double(src_height);
+}
+
+// Averages each pair of pixels along the halved axes into one. Odd sizes get
+// rounded up, with the last pixel repeated to fill up the final pair.
+static DP_Pixel8 *halve_pixels(int width, int height, const DP_Pixel8 *pixels,
+                               bool halve_x, bool halve_y, int *out_width,
+                               int *out_height)
+{
+    int step_x = halve_x ? 2 : 1;
+    int step_y = halve_y ? 2 : 1;
+    int half_width = (width + step_x - 1) / step_x;
+    int half_height = (height + step_y - 1) / step_y;
+    unsigned int count = DP_int_to_uint(step_x * step_y);
+    DP_Pixel8 *half = DP_malloc(DP_int_to_size(half_width)
+                                * DP_int_to_size(half_height) * sizeof(*half));
+    for (int y = 0; y < half_height; ++y) {
+        for (int x = 0; x < half_width; ++x) {
+            unsigned int b = 0, g = 0, r = 0, a = 0;
+            for (int j = 0; j < step_y; ++j) {
+                int sy = DP_min_int(y * step_y + j, height - 1);
+                for (int i = 0; i < step_x; ++i) {
+                    int sx = DP_min_int(x * step_x + i, width - 1);
+                    DP_Pixel8 pixel = pixels[sy * width + sx];
+                    b += pixel.b;
+                    g += pixel.g;
+                    r += pixel.r;
+                    a += pixel.a;
+                }
+            }
+            half[y * half_width + x] = (DP_Pixel8){
+                .b = DP_uint_to_uint8((b + count / 2u) / count),
+                .g = DP_uint_to_uint8((g + count / 2u) / count),
+                .r = DP_uint_to_uint8((r + count / 2u) / count),
+                .a = DP_uint_to_uint8((a + count / 2u) / count),
+            };
+        }
+    }
+    *out_width = half_width;
+    *out_height = half_height;
+    return half;
+}
+
+bool DP_image_transform_draw_prefiltered(int src_width, int src_height,
+                                         const DP_Pixel8 *src_pixels,
+                                         DP_DrawContext *dc, DP_Image *dst_img,
+                                         DP_Transform tf, DP_Quad dst_quad,
+                                         int interpolation)
+{
+    if (interpolation != DP_MSG_TRANSFORM_REGION_MODE_BICUBIC) {
+        return DP_image_transform_draw(src_width, src_height, src_pixels, dc,
+                                       dst_img, tf, interpolation);
+    }
+
+    double scale_x, scale_y;
+    quad_axis_scales(dst_quad, src_width, src_height, &scale_x, &scale_y);
+    int width = src_width;
+    int height = src_height;
+    const DP_Pixel8 *pixels = src_pixels;
+    DP_Pixel8 *buffer = NULL;
+    double factor_x = 1.0;
+    double factor_y = 1.0;
+    while (true) {
+        bool halve_x = scale_x <= 0.5 && width > 1;
+        bool halve_y = scale_y <= 0.5 && height > 1;
+        if (!halve_x && !halve_y) {
+            break;
+        }
+
+        int half_width, half_height;
+        DP_Pixel8 *half = halve_pixels(width, height, pixels, halve_x, halve_y,
+                                       &half_width, &half_height);
+        DP_free(buffer);
+        pixels = buffer = half;
+        // Rounding up odd sizes makes the halved image cover a little more
+        // than the original, so scale it by exactly as much as it shrank to
+        // keep the edges where they were.
+        double shrink_x =
+            DP_int_to_double(width) / DP_int_to_double(half_width);
+        double shrink_y =
+            DP_int_to_double(height) / DP_int_to_double(half_height);
+        scale_x *= shrink_x;
+        scale_y *= shrink_y;
+        factor_x *= shrink_x;
+        factor_y *= shrink_y;
+        width = half_width;
+        height = half_height;
+    }
+
+    bool ok = DP_image_transform_draw(
+        width, height, pixels, dc, dst_img,
+        DP_transform_mul(DP_transform_scaling(factor_x, factor_y), tf),
+        interpolation);
+    DP_free(buffer);
+    return ok;
+}
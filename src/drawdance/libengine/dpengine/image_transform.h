@@ -35,5 +35,17 @@ bool DP_image_transform_draw(int src_width, int src_height,
                              DP_Image *dst_img, DP_Transform tf,
                              int interpolation) DP_MUST_CHECK;
 
+// Like the above, but for bicubic interpolation it first halves the source
+// image along each axis for as long as the transform to the given destination
+// quad shrinks that axis down to half its size or less. Otherwise,
+// interpolating only between the nearest few source pixels would skip over the
+// rest of them and alias. Nearest neighbor and bilinear get passed through
+// unchanged, since clients without the prefilter render them that way too.
+bool DP_image_transform_draw_prefiltered(int src_width, int src_height,
+                                         const DP_Pixel8 *src_pixels,
+                                         DP_DrawContext *dc, DP_Image *dst_img,
+                                         DP_Transform tf, DP_Quad dst_quad,
+                                         int interpolation) DP_MUST_CHECK;
+
 
 #endif
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: MIT
+#include "laser_overlay.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/vector.h>
+#include <dpmsg/message.h>
+
+#define POINTER_COUNT 256
+
+
+typedef struct DP_LaserPointer {
+    bool valid;
+    double x, y;
+    long long time_ms;
+} DP_LaserPointer;
+
+typedef struct DP_LaserTrail {
+    unsigned int context_id;
+    uint32_t color;
+    long long persistence_ms;
+    long long last_modified_ms;
+    bool active;
+    DP_Vector points;
+} DP_LaserTrail;
+
+struct DP_LaserOverlay {
+    int max_trail_points;
+    DP_LaserPointer pointers[POINTER_COUNT];
+    DP_Vector trails;
+};
+
+
+DP_LaserOverlay *DP_laser_overlay_new(int max_trail_points)
+{
+    DP_ASSERT(max_trail_points > 0);
+    DP_LaserOverlay *lo = DP_malloc(sizeof(*lo));
+    lo->max_trail_points = max_trail_points;
+    for (int i = 0; i < POINTER_COUNT; ++i) {
+        lo->pointers[i] = (DP_LaserPointer){false, 0.0, 0.0, 0};
+    }
+    DP_VECTOR_INIT_TYPE(&lo->trails, DP_LaserTrail, 8);
+    return lo;
+}
+
+static void dispose_trail(void *element)
+{
+    DP_LaserTrail *trail = element;
+    DP_vector_dispose(&trail->points);
+}
+
+void DP_laser_overlay_free(DP_LaserOverlay *lo)
+{
+    if (lo) {
+        DP_VECTOR_CLEAR_DISPOSE_TYPE(&lo->trails, DP_LaserTrail, dispose_trail);
+        DP_free(lo);
+    }
+}
+
+
+static DP_LaserTrail *trail_at(DP_LaserOverlay *lo, size_t i)
+{
+    return &DP_VECTOR_AT_TYPE(&lo->trails, DP_LaserTrail, i);
+}
+
+static double trail_opacity(DP_LaserTrail *trail, long long time_ms)
+{
+    long long fade_start_ms = trail->last_modified_ms + trail->persistence_ms;
+    if (time_ms <= fade_start_ms) {
+        return 1.0;
+    }
+    else {
+        double faded = DP_llong_to_double(time_ms - fade_start_ms)
+                     / (double)DP_LASER_OVERLAY_FADE_MS;
+        return faded < 1.0 ? 1.0 - faded : 0.0;
+    }
+}
+
+static DP_LaserTrail *search_active_trail(DP_LaserOverlay *lo,
+                                          unsigned int context_id)
+{
+    for (size_t i = lo->trails.used; i > 0; --i) {
+        DP_LaserTrail *trail = trail_at(lo, i - 1);
+        if (trail->active && trail->context_id == context_id) {
+            return trail;
+        }
+    }
+    return NULL;
+}
+
+static void handle_laser_trail(DP_LaserOverlay *lo, unsigned int context_id,
+                               DP_MsgLaserTrail *mlt, long long time_ms)
+{
+    DP_LaserTrail *previous = search_active_trail(lo, context_id);
+    if (previous) {
+        previous->active = false;
+    }
+
+    int persistence = DP_msg_laser_trail_persistence(mlt);
+    if (persistence > 0) {
+        int persistence_ms =
+            DP_min_int(persistence * 1000, DP_LASER_OVERLAY_MAX_PERSISTENCE_MS);
+        uint32_t color = DP_msg_laser_trail_color(mlt);
+        DP_LaserTrail trail = {context_id, color, persistence_ms, time_ms, true,
+                               DP_VECTOR_NULL};
+        DP_VECTOR_INIT_TYPE(&trail.points, DP_LaserTrailPoint, 64);
+        DP_VECTOR_PUSH_TYPE(&lo->trails, DP_LaserTrail, trail);
+    }
+}
+
+static void add_trail_point(DP_LaserOverlay *lo, DP_LaserTrail *trail,
+                            double x, double y, long long time_ms)
+{
+    // A trail that faded out entirely doesn't come back, same as when the
+    // user stops drawing it.
+    if (trail_opacity(trail, time_ms) <= 0.0) {
+        trail->active = false;
+    }
+    else {
+        if (DP_size_to_int(trail->points.used) >= lo->max_trail_points) {
+            DP_VECTOR_SHIFT_TYPE(&trail->points, DP_LaserTrailPoint);
+        }
+        DP_LaserTrailPoint point = {x, y};
+        DP_VECTOR_PUSH_TYPE(&trail->points, DP_LaserTrailPoint, point);
+        trail->last_modified_ms = time_ms;
+    }
+}
+
+static void handle_move_pointer(DP_LaserOverlay *lo, unsigned int context_id,
+                                DP_MsgMovePointer *mmp, long long time_ms)
+{
+    // Pointer coordinates are in quarter pixels.
+    double x = DP_msg_move_pointer_x(mmp) / 4.0;
+    double y = DP_msg_move_pointer_y(mmp) / 4.0;
+    lo->pointers[context_id] = (DP_LaserPointer){true, x, y, time_ms};
+
+    DP_LaserTrail *trail = search_active_trail(lo, context_id);
+    if (trail) {
+        add_trail_point(lo, trail, x, y, time_ms);
+    }
+}
+
+bool DP_laser_overlay_handle(DP_LaserOverlay *lo, DP_Message *msg,
+                             long long time_ms)
+{
+    DP_ASSERT(lo);
+    DP_ASSERT(msg);
+    unsigned int context_id = DP_message_context_id(msg);
+    DP_ASSERT(context_id < POINTER_COUNT);
+    switch (DP_message_type(msg)) {
+    case DP_MSG_LASER_TRAIL:
+        handle_laser_trail(lo, context_id, DP_message_internal(msg), time_ms);
+        return true;
+    case DP_MSG_MOVE_POINTER:
+        handle_move_pointer(lo, context_id, DP_message_internal(msg),
+                            time_ms);
+        return true;
+    default:
+        return false;
+    }
+}
+
+
+bool DP_laser_overlay_expire(DP_LaserOverlay *lo, long long time_ms)
+{
+    DP_ASSERT(lo);
+    bool expired = false;
+    size_t i = 0;
+    while (i < lo->trails.used) {
+        DP_LaserTrail *trail = trail_at(lo, i);
+        if (trail_opacity(trail, time_ms) <= 0.0) {
+            dispose_trail(trail);
+            DP_VECTOR_REMOVE_TYPE(&lo->trails, DP_LaserTrail, i);
+            expired = true;
+        }
+        else {
+            ++i;
+        }
+    }
+    return expired;
+}
+
+void DP_laser_overlay_pointers(DP_LaserOverlay *lo, DP_LaserOverlayPointerFn fn,
+                               void *user)
+{
+    DP_ASSERT(lo);
+    DP_ASSERT(fn);
+    for (unsigned int i = 0; i < POINTER_COUNT; ++i) {
+        DP_LaserPointer *lp = &lo->pointers[i];
+        if (lp->valid) {
+            fn(user, i, lp->x, lp->y, lp->time_ms);
+        }
+    }
+}
+
+void DP_laser_overlay_trails_at(DP_LaserOverlay *lo, long long time_ms,
+                                DP_LaserOverlayTrailFn fn, void *user)
+{
+    DP_ASSERT(lo);
+    DP_ASSERT(fn);
+    size_t count = lo->trails.used;
+    for (size_t i = 0; i < count; ++i) {
+        DP_LaserTrail *trail = trail_at(lo, i);
+        double opacity = trail_opacity(trail, time_ms);
+        size_t point_count = trail->points.used;
+        if (opacity > 0.0 && point_count != 0) {
+            fn(user, trail->context_id, trail->color, opacity,
+               DP_size_to_int(point_count), trail->points.elements);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_LASER_OVERLAY_H
+#define DPENGINE_LASER_OVERLAY_H
+#include <dpcommon/common.h>
+
+typedef struct DP_Message DP_Message;
+
+
+// Laser trails stay at full opacity for their persistence after their last
+// point was added, then fade out over this long.
+#define DP_LASER_OVERLAY_FADE_MS 1000
+// Longest persistence a laser trail can ask for, anything above is clamped.
+#define DP_LASER_OVERLAY_MAX_PERSISTENCE_MS 15000
+
+typedef struct DP_LaserTrailPoint {
+    double x, y;
+} DP_LaserTrailPoint;
+
+typedef void (*DP_LaserOverlayPointerFn)(void *user, unsigned int context_id,
+                                         double x, double y,
+                                         long long time_ms);
+
+typedef void (*DP_LaserOverlayTrailFn)(void *user, unsigned int context_id,
+                                       uint32_t color, double opacity,
+                                       int count,
+                                       const DP_LaserTrailPoint *points);
+
+// Keeps track of where users are pointing and the laser trails they draw, for
+// rendering on top of the canvas. None of this is part of the canvas itself,
+// so it doesn't go into the canvas history. Coordinates are in canvas pixels.
+// Times are in milliseconds and come from the caller, which picks the clock.
+typedef struct DP_LaserOverlay DP_LaserOverlay;
+
+// Each trail keeps at most max_trail_points points, older ones get dropped.
+DP_LaserOverlay *DP_laser_overlay_new(int max_trail_points);
+
+void DP_laser_overlay_free(DP_LaserOverlay *lo);
+
+// Handles laser trail and move pointer messages, returns false for any other
+// kind of message. A laser trail message with a persistence starts a new trail
+// for the user, one without ends it. While a trail is active, the user's
+// pointer movements get added to it.
+bool DP_laser_overlay_handle(DP_LaserOverlay *lo, DP_Message *msg,
+                             long long time_ms);
+
+// Gets rid of faded out trails, returns whether there were any.
+bool DP_laser_overlay_expire(DP_LaserOverlay *lo, long long time_ms);
+
+// Calls the function for each user's last known pointer position.
+void DP_laser_overlay_pointers(DP_LaserOverlay *lo, DP_LaserOverlayPointerFn fn,
+                               void *user);
+
+// Calls the function for each trail that's still visible at the given time,
+// with its opacity between 0 and 1, from oldest to newest trail.
+void DP_laser_overlay_trails_at(DP_LaserOverlay *lo, long long time_ms,
+                                DP_LaserOverlayTrailFn fn, void *user);
+
+
+#endif
@@ -21,8 +21,10 @@
  */
 #include "layer_content.h"
 #include "canvas_diff.h"
+#include "checksum.h"
 #include "draw_context.h"
 #include "image.h"
+#include "image_transform.h"
 #include "layer_list.h"
 #include "layer_props.h"
 #include "layer_props_list.h"
@@ -307,6 +309,54 @@ DP_Tile *DP_layer_content_tile_at_noinc(DP_LayerContent *lc, int x, int y)
     return lc->elements[y * DP_tile_count_round(lc->width) + x].tile;
 }
 
+bool DP_layer_content_tile_context_id_at(DP_LayerContent *lc, int x, int y,
+                                         unsigned int *out_context_id)
+{
+    DP_ASSERT(out_context_id);
+    DP_Tile *t = DP_layer_content_tile_at_noinc(lc, x, y);
+    if (t) {
+        *out_context_id = DP_tile_context_id(t);
+        return true;
+    }
+    else {
+        return false;
+    }
+}
+
+static int next_nonblank_tile_index(DP_LayerContent *lc, int *in_out_index,
+                                    int *out_x, int *out_y)
+{
+    DP_ASSERT(in_out_index);
+    DP_ASSERT(*in_out_index >= 0);
+    int xtiles = DP_tile_count_round(lc->width);
+    int count = xtiles * DP_tile_count_round(lc->height);
+    for (int i = *in_out_index; i < count; ++i) {
+        DP_Tile *t = lc->elements[i].tile;
+        if (t && !DP_tile_blank(t)) {
+            *in_out_index = i + 1;
+            if (out_x) {
+                *out_x = i % xtiles;
+            }
+            if (out_y) {
+                *out_y = i / xtiles;
+            }
+            return i;
+        }
+    }
+    *in_out_index = count;
+    return -1;
+}
+
+DP_Tile *DP_layer_content_next_nonblank_tile_noinc(DP_LayerContent *lc,
+                                                   int *in_out_index,
+                                                   int *out_x, int *out_y)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    int i = next_nonblank_tile_index(lc, in_out_index, out_x, out_y);
+    return i == -1 ? NULL : lc->elements[i].tile;
+}
+
 DP_Pixel15 DP_layer_content_pixel_at(DP_LayerContent *lc, int x, int y)
 {
     DP_ASSERT(lc);
@@ -493,6 +543,28 @@ bool DP_layer_content_same_pixel(DP_LayerContent *lc, DP_Pixel15 *out_pixel)
     }
 }
 
+uint64_t DP_layer_content_checksum(DP_LayerContent *lc)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    uint64_t checksum = DP_CHECKSUM_INIT;
+    checksum = DP_checksum_int(checksum, lc->width);
+    checksum = DP_checksum_int(checksum, lc->height);
+    DP_TileCounts tile_counts = DP_tile_counts_round(lc->width, lc->height);
+    for (int y = 0; y < tile_counts.y; ++y) {
+        for (int x = 0; x < tile_counts.x; ++x) {
+            uint64_t tile_checksum =
+                DP_tile_checksum(lc->elements[y * tile_counts.x + x].tile);
+            if (tile_checksum != DP_TILE_CHECKSUM_BLANK) {
+                checksum = DP_checksum_int(checksum, x);
+                checksum = DP_checksum_int(checksum, y);
+                checksum = DP_checksum_uint64(checksum, tile_checksum);
+            }
+        }
+    }
+    return checksum;
+}
+
 static bool layer_content_tile_bounds(DP_LayerContent *lc, int *out_left,
                                       int *out_top, int *out_right,
                                       int *out_bottom)
@@ -902,7 +974,11 @@ static DP_TransientTile *get_transient_tile(DP_TransientLayerContent *tlc,
     DP_Tile *t = tlc->elements[i].tile;
     DP_ASSERT(t);
     if (DP_tile_transient(t)) {
-        return (DP_TransientTile *)t;
+        // Dabs from several users may get drawn in one go, so the tile may
+        // have been made transient by someone else.
+        DP_TransientTile *tt = (DP_TransientTile *)t;
+        DP_transient_tile_context_id_set(tt, context_id);
+        return tt;
     }
     else {
         DP_TransientTile *tt = DP_transient_tile_new(t, context_id);
@@ -931,6 +1007,10 @@ get_or_create_transient_tile(DP_TransientLayerContent *tlc,
             DP_transient_tile_new(tile, context_id);
         DP_tile_decref(tile);
     }
+    else {
+        DP_transient_tile_context_id_set(tlc->elements[i].transient_tile,
+                                         context_id);
+    }
     return tlc->elements[i].transient_tile;
 }
 
@@ -975,6 +1055,49 @@ void DP_transient_layer_content_pixel_at_set(DP_TransientLayerContent *tlc,
                                    y - yt * DP_TILE_SIZE, pixel);
 }
 
+static void swap_pixels(DP_TransientLayerContent *tlc, unsigned int context_id,
+                        int x1, int y1, int x2, int y2)
+{
+    DP_Pixel15 a = DP_layer_content_pixel_at((DP_LayerContent *)tlc, x1, y1);
+    DP_Pixel15 b = DP_layer_content_pixel_at((DP_LayerContent *)tlc, x2, y2);
+    if (a.b != b.b || a.g != b.g || a.r != b.r || a.a != b.a) {
+        DP_transient_layer_content_pixel_at_set(tlc, context_id, x1, y1, b);
+        DP_transient_layer_content_pixel_at_set(tlc, context_id, x2, y2, a);
+    }
+}
+
+DP_Rect DP_transient_layer_content_flip_region(DP_TransientLayerContent *tlc,
+                                               unsigned int context_id,
+                                               const DP_Rect *rect,
+                                               bool horizontal)
+{
+    DP_ASSERT(tlc);
+    DP_ASSERT(DP_atomic_get(&tlc->refcount) > 0);
+    DP_ASSERT(tlc->transient);
+    DP_ASSERT(rect);
+    DP_Rect area = DP_rect_intersection(
+        DP_rect_make(0, 0, tlc->width, tlc->height), *rect);
+    if (!DP_rect_valid(area)) {
+        return area;
+    }
+
+    if (horizontal) {
+        for (int y = area.y1; y <= area.y2; ++y) {
+            for (int x1 = area.x1, x2 = area.x2; x1 < x2; ++x1, --x2) {
+                swap_pixels(tlc, context_id, x1, y, x2, y);
+            }
+        }
+    }
+    else {
+        for (int y1 = area.y1, y2 = area.y2; y1 < y2; ++y1, --y2) {
+            for (int x = area.x1; x <= area.x2; ++x) {
+                swap_pixels(tlc, context_id, x, y1, x, y2);
+            }
+        }
+    }
+    return area;
+}
+
 
 static DP_TransientLayerContent *
 resize_layer_content_aligned(DP_LayerContent *lc, int top, int left, int width,
@@ -1089,7 +1212,8 @@ DP_TransientLayerContent *DP_layer_content_resize(DP_LayerContent *lc,
     if (DP_layer_list_count(sub_ll) != 0) {
         DP_layer_list_decref(tlc->sub.contents);
         tlc->sub.transient_contents =
-            DP_layer_list_resize(sub_ll, context_id, top, right, bottom, left);
+            DP_layer_list_resize(sub_ll, lc->sub.props, context_id, top, right,
+                                 bottom, left);
         DP_layer_props_list_decref(tlc->sub.props);
         tlc->sub.props = DP_layer_props_list_incref(lc->sub.props);
     }
@@ -1097,6 +1221,387 @@ DP_TransientLayerContent *DP_layer_content_resize(DP_LayerContent *lc,
     return tlc;
 }
 
+// Lossless ways of moving pixels around. The rotations are the number of
+// clockwise quarter turns.
+#define REMAP_ROTATE_CLOCKWISE        1
+#define REMAP_ROTATE_HALF             2
+#define REMAP_ROTATE_COUNTERCLOCKWISE 3
+#define REMAP_FLIP_HORIZONTAL         4
+#define REMAP_FLIP_VERTICAL           5
+
+// Where the pixel at x, y of the remapped layer comes from in the original one,
+// which is width by height pixels large.
+static void remapped_source_position(int remap, int width, int height, int x,
+                                     int y, int *out_x, int *out_y)
+{
+    switch (remap) {
+    case REMAP_ROTATE_CLOCKWISE:
+        *out_x = y;
+        *out_y = height - 1 - x;
+        break;
+    case REMAP_ROTATE_HALF:
+        *out_x = width - 1 - x;
+        *out_y = height - 1 - y;
+        break;
+    case REMAP_ROTATE_COUNTERCLOCKWISE:
+        *out_x = width - 1 - y;
+        *out_y = x;
+        break;
+    case REMAP_FLIP_HORIZONTAL:
+        *out_x = width - 1 - x;
+        *out_y = y;
+        break;
+    default:
+        DP_ASSERT(remap == REMAP_FLIP_VERTICAL);
+        *out_x = x;
+        *out_y = height - 1 - y;
+        break;
+    }
+}
+
+// Gathers the pixels of a tile of the remapped layer. When the layer size
+// isn't a multiple of the tile size, they come from up to four different tiles.
+static DP_TransientTile *remap_tile(DP_LayerContent *lc,
+                                    unsigned int context_id, int remap,
+                                    int left, int top, int right, int bottom)
+{
+    int width = lc->width;
+    int height = lc->height;
+    int xtiles = DP_tile_count_round(width);
+    DP_TransientTile *tt = NULL;
+    for (int y = top; y < bottom; ++y) {
+        for (int x = left; x < right; ++x) {
+            int src_x, src_y;
+            remapped_source_position(remap, width, height, x, y, &src_x,
+                                     &src_y);
+            DP_Tile *tile = lc->elements[src_y / DP_TILE_SIZE * xtiles
+                                         + src_x / DP_TILE_SIZE]
+                                .tile;
+            if (tile) {
+                DP_Pixel15 pixel = DP_tile_pixel_at(tile, src_x % DP_TILE_SIZE,
+                                                    src_y % DP_TILE_SIZE);
+                if (pixel.b != 0 || pixel.g != 0 || pixel.r != 0
+                    || pixel.a != 0) {
+                    if (!tt) {
+                        tt = DP_transient_tile_new_blank(context_id);
+                    }
+                    DP_transient_tile_pixel_at_set(tt, x - left, y - top,
+                                                   pixel);
+                }
+            }
+        }
+    }
+    return tt;
+}
+
+static DP_TransientLayerContent *remap_layer_content(DP_LayerContent *lc,
+                                                     unsigned int context_id,
+                                                     int remap)
+{
+    bool swap = remap == REMAP_ROTATE_CLOCKWISE
+             || remap == REMAP_ROTATE_COUNTERCLOCKWISE;
+    int width = swap ? lc->height : lc->width;
+    int height = swap ? lc->width : lc->height;
+    if (has_content(lc)) {
+        DP_TransientLayerContent *tlc = alloc_layer_content(width, height);
+        DP_TileCounts counts = DP_tile_counts_round(width, height);
+        for (int y = 0; y < counts.y; ++y) {
+            for (int x = 0; x < counts.x; ++x) {
+                int left = x * DP_TILE_SIZE;
+                int top = y * DP_TILE_SIZE;
+                tlc->elements[y * counts.x + x].transient_tile = remap_tile(
+                    lc, context_id, remap, left, top,
+                    DP_min_int(left + DP_TILE_SIZE, width),
+                    DP_min_int(top + DP_TILE_SIZE, height));
+            }
+        }
+        tlc->sub.contents = DP_layer_list_new();
+        tlc->sub.props = DP_layer_props_list_new();
+        return tlc;
+    }
+    else {
+        DP_debug("Remap: layer is blank");
+        return DP_transient_layer_content_new_init(width, height, NULL);
+    }
+}
+
+static void set_remapped_sublayers_noinc(DP_TransientLayerContent *tlc,
+                                         DP_LayerContent *lc,
+                                         DP_TransientLayerList *sub_tll)
+{
+    DP_layer_list_decref(tlc->sub.contents);
+    tlc->sub.transient_contents = sub_tll;
+    DP_layer_props_list_decref(tlc->sub.props);
+    tlc->sub.props = DP_layer_props_list_incref(lc->sub.props);
+}
+
+DP_TransientLayerContent *DP_layer_content_rotate(DP_LayerContent *lc,
+                                                  unsigned int context_id,
+                                                  int quarter_turns)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    DP_ASSERT(quarter_turns >= 1 && quarter_turns <= 3);
+    DP_TransientLayerContent *tlc =
+        remap_layer_content(lc, context_id, quarter_turns);
+    DP_LayerList *sub_ll = lc->sub.contents;
+    if (DP_layer_list_count(sub_ll) != 0) {
+        set_remapped_sublayers_noinc(
+            tlc, lc, DP_layer_list_rotate(sub_ll, context_id, quarter_turns));
+    }
+    return tlc;
+}
+
+DP_TransientLayerContent *DP_layer_content_flip(DP_LayerContent *lc,
+                                                unsigned int context_id,
+                                                bool horizontal)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    DP_TransientLayerContent *tlc = remap_layer_content(
+        lc, context_id,
+        horizontal ? REMAP_FLIP_HORIZONTAL : REMAP_FLIP_VERTICAL);
+    DP_LayerList *sub_ll = lc->sub.contents;
+    if (DP_layer_list_count(sub_ll) != 0) {
+        set_remapped_sublayers_noinc(
+            tlc, lc, DP_layer_list_flip(sub_ll, context_id, horizontal));
+    }
+    return tlc;
+}
+
+static int wrap_offset(int offset, int size)
+{
+    int wrapped = offset % size;
+    return wrapped < 0 ? wrapped + size : wrapped;
+}
+
+// Copies a tile of the offset layer together a row at a time. Rows get split
+// where they cross a source tile boundary or wrap around the layer's edge.
+static DP_TransientTile *offset_wrap_tile(DP_LayerContent *lc,
+                                          unsigned int context_id, int offset_x,
+                                          int offset_y, int left, int top,
+                                          int right, int bottom)
+{
+    int width = lc->width;
+    int height = lc->height;
+    int xtiles = DP_tile_count_round(width);
+    DP_TransientTile *tt = NULL;
+    for (int y = top; y < bottom; ++y) {
+        int src_y = wrap_offset(y - offset_y, height);
+        int row = src_y / DP_TILE_SIZE * xtiles;
+        int x = left;
+        while (x < right) {
+            int src_x = wrap_offset(x - offset_x, width);
+            int tile_x = src_x % DP_TILE_SIZE;
+            int run = DP_min_int(right - x, DP_min_int(DP_TILE_SIZE - tile_x,
+                                                       width - src_x));
+            DP_Tile *tile = lc->elements[row + src_x / DP_TILE_SIZE].tile;
+            if (tile) {
+                if (!tt) {
+                    tt = DP_transient_tile_new_blank(context_id);
+                }
+                int tile_y = src_y % DP_TILE_SIZE;
+                memcpy(DP_transient_tile_pixels(tt) + (y - top) * DP_TILE_SIZE
+                           + (x - left),
+                       DP_tile_pixels(tile) + tile_y * DP_TILE_SIZE + tile_x,
+                       DP_int_to_size(run) * sizeof(DP_Pixel15));
+            }
+            x += run;
+        }
+    }
+    return tt;
+}
+
+// When the offset and the layer size are both multiples of the tile size, the
+// tiles just get shuffled around and can be reused as they are.
+static void offset_wrap_tiles_aligned(DP_TransientLayerContent *tlc,
+                                      DP_LayerContent *lc, int offset_x,
+                                      int offset_y, DP_TileCounts counts)
+{
+    int tile_offset_x = offset_x / DP_TILE_SIZE;
+    int tile_offset_y = offset_y / DP_TILE_SIZE;
+    for (int y = 0; y < counts.y; ++y) {
+        int src_y = wrap_offset(y - tile_offset_y, counts.y);
+        for (int x = 0; x < counts.x; ++x) {
+            int src_x = wrap_offset(x - tile_offset_x, counts.x);
+            tlc->elements[y * counts.x + x].tile = DP_tile_incref_nullable(
+                lc->elements[src_y * counts.x + src_x].tile);
+        }
+    }
+}
+
+DP_TransientLayerContent *DP_layer_content_offset_wrap(DP_LayerContent *lc,
+                                                       unsigned int context_id,
+                                                       int offset_x,
+                                                       int offset_y)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    int width = lc->width;
+    int height = lc->height;
+    offset_x = wrap_offset(offset_x, width);
+    offset_y = wrap_offset(offset_y, height);
+
+    DP_TransientLayerContent *tlc;
+    if (has_content(lc)) {
+        tlc = alloc_layer_content(width, height);
+        DP_TileCounts counts = DP_tile_counts_round(width, height);
+        if (width % DP_TILE_SIZE == 0 && height % DP_TILE_SIZE == 0
+            && offset_x % DP_TILE_SIZE == 0 && offset_y % DP_TILE_SIZE == 0) {
+            offset_wrap_tiles_aligned(tlc, lc, offset_x, offset_y, counts);
+        }
+        else {
+            for (int y = 0; y < counts.y; ++y) {
+                for (int x = 0; x < counts.x; ++x) {
+                    int left = x * DP_TILE_SIZE;
+                    int top = y * DP_TILE_SIZE;
+                    tlc->elements[y * counts.x + x].transient_tile =
+                        offset_wrap_tile(
+                            lc, context_id, offset_x, offset_y, left, top,
+                            DP_min_int(left + DP_TILE_SIZE, width),
+                            DP_min_int(top + DP_TILE_SIZE, height));
+                }
+            }
+        }
+        tlc->sub.contents = DP_layer_list_new();
+        tlc->sub.props = DP_layer_props_list_new();
+    }
+    else {
+        DP_debug("Offset wrap: layer is blank");
+        tlc = DP_transient_layer_content_new_init(width, height, NULL);
+    }
+
+    DP_LayerList *sub_ll = lc->sub.contents;
+    if (DP_layer_list_count(sub_ll) != 0) {
+        set_remapped_sublayers_noinc(
+            tlc, lc,
+            DP_layer_list_offset_wrap(sub_ll, context_id, offset_x, offset_y));
+    }
+    return tlc;
+}
+
+DP_TransientLayerContent *
+DP_layer_content_transform(DP_LayerContent *lc, DP_DrawContext *dc,
+                           unsigned int context_id, const DP_Transform *tf,
+                           int width, int height, int interpolation)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    DP_ASSERT(tf);
+
+    DP_TransientLayerContent *tlc;
+    if (has_content(lc)) {
+        DP_Image *img = DP_layer_content_to_image(lc);
+        DP_Image *dst_img = DP_image_new(width, height);
+        bool ok = DP_image_transform_draw(
+            DP_image_width(img), DP_image_height(img), DP_image_pixels(img), dc,
+            dst_img, *tf, interpolation);
+        DP_image_free(img);
+        if (!ok) {
+            DP_image_free(dst_img);
+            return NULL;
+        }
+        tlc = DP_transient_layer_content_new_init(width, height, NULL);
+        DP_transient_layer_content_put_image(tlc, context_id,
+                                             DP_BLEND_MODE_REPLACE, 0, 0,
+                                             dst_img);
+        DP_image_free(dst_img);
+    }
+    else {
+        DP_debug("Transform: layer is blank");
+        tlc = DP_transient_layer_content_new_init(width, height, NULL);
+    }
+
+    DP_LayerList *sub_ll = lc->sub.contents;
+    if (DP_layer_list_count(sub_ll) != 0) {
+        DP_TransientLayerList *sub_tll =
+            DP_layer_list_transform(sub_ll, lc->sub.props, dc, context_id, tf,
+                                    width, height, interpolation);
+        if (!sub_tll) {
+            DP_transient_layer_content_decref(tlc);
+            return NULL;
+        }
+        DP_layer_list_decref(tlc->sub.contents);
+        tlc->sub.transient_contents = sub_tll;
+        DP_layer_props_list_decref(tlc->sub.props);
+        tlc->sub.props = DP_layer_props_list_incref(lc->sub.props);
+    }
+
+    return tlc;
+}
+
+static bool tile_rows_equal(DP_Tile *a, DP_Tile *b, int width, int height)
+{
+    if (a == b) {
+        return true;
+    }
+    else if (!a || !b) {
+        return false;
+    }
+    else {
+        const DP_Pixel15 *pa = DP_tile_pixels(a);
+        const DP_Pixel15 *pb = DP_tile_pixels(b);
+        size_t row_size = DP_int_to_size(width) * sizeof(*pa);
+        for (int y = 0; y < height; ++y) {
+            int offset = y * DP_TILE_SIZE;
+            if (memcmp(pa + offset, pb + offset, row_size) != 0) {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+// If every tile of the layer looks the same within the layer's bounds, returns
+// that tile. Partial tiles at the right and bottom edge only get compared in
+// the part that's actually inside of the layer. A uniform color is just a
+// special case of this, where the tile consists of a single pixel value.
+static DP_Tile *get_repeating_tile(DP_LayerContent *lc)
+{
+    int width = lc->width;
+    int height = lc->height;
+    DP_Tile *first = lc->elements[0].tile;
+    if (width < DP_TILE_SIZE || height < DP_TILE_SIZE || !first
+        || DP_tile_blank(first)) {
+        return NULL;
+    }
+
+    DP_TileCounts tile_counts = DP_tile_counts_round(width, height);
+    for (int y = 0; y < tile_counts.y; ++y) {
+        int tile_height = DP_min_int(DP_TILE_SIZE, height - y * DP_TILE_SIZE);
+        for (int x = 0; x < tile_counts.x; ++x) {
+            int tile_width = DP_min_int(DP_TILE_SIZE, width - x * DP_TILE_SIZE);
+            DP_Tile *t = lc->elements[y * tile_counts.x + x].tile;
+            if (!tile_rows_equal(first, t, tile_width, tile_height)) {
+                return NULL;
+            }
+        }
+    }
+    return first;
+}
+
+DP_TransientLayerContent *DP_layer_content_resize_anchored(DP_LayerContent *lc,
+                                                           int top, int right,
+                                                           int bottom, int left)
+{
+    DP_ASSERT(lc);
+    DP_ASSERT(DP_atomic_get(&lc->refcount) > 0);
+    if (DP_layer_list_count(lc->sub.contents) != 0) {
+        return NULL;
+    }
+
+    DP_Tile *t = get_repeating_tile(lc);
+    if (t) {
+        int width = lc->width + left + right;
+        int height = lc->height + top + bottom;
+        DP_debug("Resize: anchoring repeating tile pattern");
+        return DP_transient_layer_content_new_init(width, height, t);
+    }
+    else {
+        return NULL;
+    }
+}
+
 DP_LayerContent *DP_layer_content_merge_sublayers(DP_LayerContent *lc)
 {
     DP_ASSERT(lc);
@@ -1185,6 +1690,29 @@ int DP_transient_layer_content_refcount(DP_TransientLayerContent *tlc)
     return DP_layer_content_refcount((DP_LayerContent *)tlc);
 }
 
+// Uniform tiles that come out of the same edit, like the ones from a flood
+// fill, get replaced by the previous one of the same color and context id, so
+// that they share a single buffer. Editing them later copies them like with
+// any other persistent tile.
+static DP_Tile *persist_shared_solid(DP_Tile *t, DP_Tile **in_out_solid)
+{
+    DP_Pixel15 pixel;
+    if (DP_tile_same_pixel(t, &pixel)) {
+        DP_Tile *solid = *in_out_solid;
+        DP_Pixel15 solid_pixel;
+        if (solid && DP_tile_context_id(solid) == DP_tile_context_id(t)
+            && DP_tile_same_pixel(solid, &solid_pixel)
+            && DP_pixel15_equal(pixel, solid_pixel)) {
+            DP_tile_decref(t);
+            return DP_tile_incref(solid);
+        }
+        else {
+            *in_out_solid = t;
+        }
+    }
+    return t;
+}
+
 DP_LayerContent *
 DP_transient_layer_content_persist(DP_TransientLayerContent *tlc)
 {
@@ -1193,6 +1721,7 @@ DP_transient_layer_content_persist(DP_TransientLayerContent *tlc)
     DP_ASSERT(tlc->transient);
     tlc->transient = false;
     int count = DP_tile_total_round(tlc->width, tlc->height);
+    DP_Tile *solid = NULL;
     for (int i = 0; i < count; ++i) {
         DP_Tile *tile = tlc->elements[i].tile;
         if (tile && DP_tile_transient(tile)) {
@@ -1202,7 +1731,8 @@ DP_transient_layer_content_persist(DP_TransientLayerContent *tlc)
                 tlc->elements[i].transient_tile = NULL;
             }
             else {
-                DP_transient_tile_persist(tt);
+                tlc->elements[i].tile =
+                    persist_shared_solid(DP_transient_tile_persist(tt), &solid);
             }
         }
     }
@@ -1231,6 +1761,18 @@ int DP_transient_layer_content_height(DP_TransientLayerContent *tlc)
     return DP_layer_content_height((DP_LayerContent *)tlc);
 }
 
+DP_UPixelFloat DP_transient_layer_content_sample_color_at(
+    DP_TransientLayerContent *tlc, uint16_t *stamp_buffer, int x, int y,
+    int diameter, bool opaque, int *in_out_last_diameter)
+{
+    DP_ASSERT(tlc);
+    DP_ASSERT(DP_atomic_get(&tlc->refcount) > 0);
+    DP_ASSERT(tlc->transient);
+    return DP_layer_content_sample_color_at((DP_LayerContent *)tlc,
+                                            stamp_buffer, x, y, diameter,
+                                            opaque, in_out_last_diameter);
+}
+
 DP_Tile *DP_transient_layer_content_tile_at_noinc(DP_TransientLayerContent *tlc,
                                                   int x, int y)
 {
@@ -1240,6 +1782,18 @@ DP_Tile *DP_transient_layer_content_tile_at_noinc(DP_TransientLayerContent *tlc,
     return DP_layer_content_tile_at_noinc((DP_LayerContent *)tlc, x, y);
 }
 
+DP_TransientTile *DP_transient_layer_content_next_nonblank_transient_tile(
+    DP_TransientLayerContent *tlc, unsigned int context_id, int *in_out_index,
+    int *out_x, int *out_y)
+{
+    DP_ASSERT(tlc);
+    DP_ASSERT(DP_atomic_get(&tlc->refcount) > 0);
+    DP_ASSERT(tlc->transient);
+    int i = next_nonblank_tile_index((DP_LayerContent *)tlc, in_out_index,
+                                     out_x, out_y);
+    return i == -1 ? NULL : get_transient_tile(tlc, context_id, i);
+}
+
 void DP_transient_layer_content_transient_tile_at_set_noinc(
     DP_TransientLayerContent *tlc, int x, int y, DP_TransientTile *tt)
 {
@@ -1521,6 +2075,12 @@ static bool can_blend_blank_pixel(int blend_mode, uint16_t opacity,
             || pixel.a != 0);
 }
 
+static bool is_fill_replacement(int blend_mode, DP_UPixel15 pixel)
+{
+    return blend_mode == DP_BLEND_MODE_REPLACE
+        || (blend_mode == DP_BLEND_MODE_NORMAL && pixel.a == DP_BIT15);
+}
+
 static void fill_rect(DP_TransientLayerContent *tlc, unsigned int context_id,
                       int blend_mode, int left, int top, int right, int bottom,
                       DP_UPixel15 pixel)
@@ -1528,6 +2088,12 @@ static void fill_rect(DP_TransientLayerContent *tlc, unsigned int context_id,
     const uint16_t *mask = DP_tile_opaque_mask();
     uint16_t opacity = pixel.a;
     bool blend_blank = can_blend_blank_pixel(blend_mode, opacity, pixel);
+    // Tiles that get covered entirely by a replacing fill all end up with the
+    // same color, so they share a single tile instead of each getting their
+    // own buffer. Transparent fills are left to the regular path, since those
+    // result in blank tiles that get dropped when persisting anyway.
+    bool share_covered = pixel.a != 0 && is_fill_replacement(blend_mode, pixel);
+    DP_Tile *covered_tile = NULL;
 
     int tx_min = left / DP_TILE_SIZE;
     int tx_max = (right - 1) / DP_TILE_SIZE;
@@ -1545,6 +2111,18 @@ static void fill_rect(DP_TransientLayerContent *tlc, unsigned int context_id,
             int h = DP_min_int((ty + 1) * DP_TILE_SIZE, bottom) - cy - y;
             int i = ty * xtiles + tx;
 
+            if (share_covered && w == DP_TILE_SIZE && h == DP_TILE_SIZE) {
+                if (covered_tile) {
+                    DP_tile_incref(covered_tile);
+                }
+                else {
+                    covered_tile = DP_tile_new_from_upixel15(context_id, pixel);
+                }
+                DP_tile_decref_nullable(tlc->elements[i].tile);
+                tlc->elements[i].tile = covered_tile;
+                continue;
+            }
+
             DP_TransientTile *tt;
             if (tlc->elements[i].tile) {
                 tt = get_or_create_transient_tile(tlc, context_id, i);
@@ -1566,10 +2144,7 @@ static void fill_entirely(DP_TransientLayerContent *tld,
                           unsigned int context_id, int blend_mode,
                           DP_UPixel15 pixel)
 {
-    bool is_replacement =
-        blend_mode == DP_BLEND_MODE_REPLACE
-        || (blend_mode == DP_BLEND_MODE_NORMAL && pixel.a == DP_BIT15);
-    if (is_replacement) {
+    if (is_fill_replacement(blend_mode, pixel)) {
         DP_Tile *tile = DP_tile_new_from_upixel15(context_id, pixel);
         int tile_count = DP_tile_total_round(tld->width, tld->height);
         DP_tile_incref_by(tile, tile_count - 1);
@@ -1654,6 +2229,19 @@ typedef void (*DP_ApplyBrushStampFn)(DP_TransientTile *tt, const uint16_t *mask,
                                      uint16_t opacity, int x, int y, int w,
                                      int h, int skip, void *user);
 
+static bool mask_blank(const uint16_t *mask, int w, int h, int skip)
+{
+    for (int y = 0; y < h; ++y) {
+        for (int x = 0; x < w; ++x) {
+            if (mask[x] != 0) {
+                return false;
+            }
+        }
+        mask += w + skip;
+    }
+    return true;
+}
+
 static void apply_brush_stamp_with(DP_TransientLayerContent *tlc,
                                    unsigned int context_id, uint16_t opacity,
                                    DP_BrushStamp *stamp, bool blend_blank,
@@ -1697,6 +2285,12 @@ static void apply_brush_stamp_with(DP_TransientLayerContent *tlc,
             x = (xindex + 1) * DP_TILE_SIZE;
             xb = xb + wb;
 
+            // Parts of the stamp that don't have anything in them, such as
+            // those outside of a selection, don't need to touch the tile.
+            if (mask_blank(mask + mask_offset, wb, hb, d - wb)) {
+                continue;
+            }
+
             DP_TransientTile *tt;
             if (tlc->elements[i].tile) {
                 tt = get_transient_tile(tlc, context_id, i);
@@ -27,9 +27,11 @@
 typedef struct DP_BrushStamp DP_BrushStamp;
 typedef struct DP_CanvasDiff DP_CanvasDiff;
 typedef struct DP_CanvasState DP_CanvasState;
+typedef struct DP_DrawContext DP_DrawContext;
 typedef struct DP_Image DP_Image;
 typedef struct DP_Rect DP_Rect;
 typedef struct DP_Tile DP_Tile;
+typedef struct DP_Transform DP_Transform;
 typedef struct DP_ViewModeFilter DP_ViewModeFilter;
 
 #ifdef DP_NO_STRICT_ALIASING
@@ -79,6 +81,21 @@ int DP_layer_content_height(DP_LayerContent *lc);
 
 DP_Tile *DP_layer_content_tile_at_noinc(DP_LayerContent *lc, int x, int y);
 
+// Gets the id of the user who last changed the tile at the given tile
+// coordinates, see DP_tile_context_id. Tiles that got erased still count as
+// changed. Returns false if there's no tile there at all.
+bool DP_layer_content_tile_context_id_at(DP_LayerContent *lc, int x, int y,
+                                         unsigned int *out_context_id);
+
+// Finds the next tile that has any pixels in it, going in row-major order.
+// Start with the index at 0 and keep calling until this returns NULL, the
+// index gets moved past each tile found. Null and blank tiles are skipped.
+// Blankness comes from the tile's cached solid state, so going over the same
+// layer again doesn't look at the pixels of unchanged tiles another time.
+DP_Tile *DP_layer_content_next_nonblank_tile_noinc(DP_LayerContent *lc,
+                                                   int *in_out_index,
+                                                   int *out_x, int *out_y);
+
 DP_Pixel15 DP_layer_content_pixel_at(DP_LayerContent *lc, int x, int y);
 
 bool DP_layer_content_pick_at(DP_LayerContent *lc, int x, int y,
@@ -96,6 +113,11 @@ DP_LayerPropsList *DP_layer_content_sub_props_noinc(DP_LayerContent *lc);
 
 bool DP_layer_content_same_pixel(DP_LayerContent *lc, DP_Pixel15 *out_pixel);
 
+// Checksum of the layer's size and all of its non-blank tiles along with their
+// positions. Doesn't include sublayers, since those are just strokes that are
+// still in progress.
+uint64_t DP_layer_content_checksum(DP_LayerContent *lc);
+
 bool DP_layer_content_search_change_bounds(DP_LayerContent *lc,
                                            unsigned int context_id, int *out_x,
                                            int *out_y, int *out_width,
@@ -122,6 +144,43 @@ DP_TransientLayerContent *DP_layer_content_resize(DP_LayerContent *lc,
                                                   int top, int right,
                                                   int bottom, int left);
 
+// Resizes a layer whose content is a uniform color or a repeating tile pattern
+// by extending that pattern to the new size, keeping it anchored to the canvas
+// origin instead of translating it. Returns NULL if the content isn't such a
+// pattern, in which case the caller should use a regular resize instead.
+DP_TransientLayerContent *DP_layer_content_resize_anchored(DP_LayerContent *lc,
+                                                           int top, int right,
+                                                           int bottom,
+                                                           int left);
+
+// Rotates the layer and its sublayers clockwise by the given number of quarter
+// turns, 1 to 3. Every pixel gets moved over as-is, without any resampling.
+DP_TransientLayerContent *DP_layer_content_rotate(DP_LayerContent *lc,
+                                                  unsigned int context_id,
+                                                  int quarter_turns);
+
+// Mirrors the layer and its sublayers left to right if horizontal is set,
+// otherwise top to bottom. Every pixel gets moved over as-is.
+DP_TransientLayerContent *DP_layer_content_flip(DP_LayerContent *lc,
+                                                unsigned int context_id,
+                                                bool horizontal);
+
+// Shifts the layer and its sublayers by the given offset, pixels that go out
+// one side come back in on the opposite one. Offsets larger than the layer or
+// negative ones wrap around as well, so undoing an offset is exact.
+DP_TransientLayerContent *DP_layer_content_offset_wrap(DP_LayerContent *lc,
+                                                       unsigned int context_id,
+                                                       int offset_x,
+                                                       int offset_y);
+
+// Maps the layer and its sublayers through the given transform onto a new
+// layer of the given size, resampling them with the given interpolation mode.
+// Returns NULL on error.
+DP_TransientLayerContent *
+DP_layer_content_transform(DP_LayerContent *lc, DP_DrawContext *dc,
+                           unsigned int context_id, const DP_Transform *tf,
+                           int width, int height, int interpolation);
+
 DP_LayerContent *DP_layer_content_merge_sublayers(DP_LayerContent *lc);
 
 DP_TransientTile *DP_layer_content_flatten_tile_to(
@@ -156,6 +215,17 @@ int DP_transient_layer_content_height(DP_TransientLayerContent *tlc);
 DP_Tile *DP_transient_layer_content_tile_at_noinc(DP_TransientLayerContent *tlc,
                                                   int x, int y);
 
+DP_UPixelFloat DP_transient_layer_content_sample_color_at(
+    DP_TransientLayerContent *tlc, uint16_t *stamp_buffer, int x, int y,
+    int diameter, bool opaque, int *in_out_last_diameter);
+
+// Like DP_layer_content_next_nonblank_tile_noinc, but makes the tile found
+// transient so that it can be modified in place, for operations that only
+// care about the parts of the layer that have something in them.
+DP_TransientTile *DP_transient_layer_content_next_nonblank_transient_tile(
+    DP_TransientLayerContent *tlc, unsigned int context_id, int *in_out_index,
+    int *out_x, int *out_y);
+
 void DP_transient_layer_content_transient_tile_at_set_noinc(
     DP_TransientLayerContent *tlc, int x, int y, DP_TransientTile *tt);
 
@@ -184,6 +254,14 @@ void DP_transient_layer_content_pixel_at_set(DP_TransientLayerContent *tlc,
                                              unsigned int context_id, int x,
                                              int y, DP_Pixel15 pixel);
 
+// Mirrors the pixels inside of the given rectangle in place, for flipping a
+// selection. Returns the area that got flipped, which is the rectangle clipped
+// to the layer bounds.
+DP_Rect DP_transient_layer_content_flip_region(DP_TransientLayerContent *tlc,
+                                               unsigned int context_id,
+                                               const DP_Rect *rect,
+                                               bool horizontal);
+
 void DP_transient_layer_content_put_image(DP_TransientLayerContent *tlc,
                                           unsigned int context_id,
                                           int blend_mode, int left, int top,
@@ -0,0 +1,477 @@
+// SPDX-License-Identifier: MIT
+#include "layer_diff.h"
+#include "canvas_state.h"
+#include "layer_content.h"
+#include "layer_group.h"
+#include "layer_list.h"
+#include "layer_props.h"
+#include "layer_props_list.h"
+#include "layer_routes.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/vector.h>
+#include <limits.h>
+#include <string.h>
+#include <uthash_inc.h>
+
+
+// The previous layer tree gets built up as a model of nodes, which is then
+// rearranged into the current tree, recording an event for each step.
+typedef struct DP_LayerDiffNode DP_LayerDiffNode;
+
+struct DP_LayerDiffNode {
+    int layer_id;
+    bool group;
+    bool placed;
+    bool stays;
+    bool unchanged;
+    // Both NULL if the layer was added.
+    DP_LayerProps *prev_lp;
+    void *prev_data;
+    DP_LayerDiffNode *parent;
+    DP_Vector children;
+    UT_hash_handle hh;
+};
+
+typedef struct DP_LayerDiffOrder {
+    DP_LayerDiffNode *node;
+    int model_index;
+    int length;
+} DP_LayerDiffOrder;
+
+struct DP_LayerDiff {
+    DP_LayerDiffNode root;
+    DP_LayerDiffNode *nodes;
+    DP_Vector events;
+    DP_Vector changes;
+    DP_Vector order;
+};
+
+
+DP_LayerDiff *DP_layer_diff_new(void)
+{
+    DP_LayerDiff *ld = DP_malloc(sizeof(*ld));
+    ld->root = (DP_LayerDiffNode){0};
+    ld->root.group = true;
+    DP_VECTOR_INIT_TYPE(&ld->root.children, DP_LayerDiffNode *, 8);
+    ld->nodes = NULL;
+    DP_VECTOR_INIT_TYPE(&ld->events, DP_LayerDiffEvent, 8);
+    DP_VECTOR_INIT_TYPE(&ld->changes, DP_LayerDiffEvent, 8);
+    DP_VECTOR_INIT_TYPE(&ld->order, DP_LayerDiffOrder, 8);
+    return ld;
+}
+
+void DP_layer_diff_free(DP_LayerDiff *ld)
+{
+    if (ld) {
+        DP_ASSERT(!ld->nodes);
+        DP_vector_dispose(&ld->order);
+        DP_vector_dispose(&ld->changes);
+        DP_vector_dispose(&ld->events);
+        DP_vector_dispose(&ld->root.children);
+        DP_free(ld);
+    }
+}
+
+
+static void push_event(DP_Vector *events, DP_LayerDiffType type, int layer_id,
+                       int from_parent_id, int from_index, int to_parent_id,
+                       int to_index)
+{
+    DP_VECTOR_PUSH_TYPE(events, DP_LayerDiffEvent,
+                        ((DP_LayerDiffEvent){type, layer_id, from_parent_id,
+                                             from_index, to_parent_id,
+                                             to_index}));
+}
+
+static DP_LayerDiffNode *node_new(DP_LayerDiff *ld, int layer_id, bool group,
+                                  DP_LayerProps *prev_lp, void *prev_data)
+{
+    DP_LayerDiffNode *node = DP_malloc(sizeof(*node));
+    *node = (DP_LayerDiffNode){layer_id,
+                               group,
+                               false,
+                               false,
+                               false,
+                               prev_lp,
+                               prev_data,
+                               NULL,
+                               DP_VECTOR_NULL,
+                               {0}};
+    if (group) {
+        DP_VECTOR_INIT_TYPE(&node->children, DP_LayerDiffNode *, 4);
+    }
+    HASH_ADD_INT(ld->nodes, layer_id, node);
+    return node;
+}
+
+static DP_LayerDiffNode *node_search(DP_LayerDiff *ld, int layer_id)
+{
+    DP_LayerDiffNode *node;
+    HASH_FIND_INT(ld->nodes, &layer_id, node);
+    return node;
+}
+
+static int node_index(DP_LayerDiffNode *node)
+{
+    DP_LayerDiffNode *parent = node->parent;
+    int count = DP_size_to_int(parent->children.used);
+    for (int i = 0; i < count; ++i) {
+        if (DP_VECTOR_AT_TYPE(&parent->children, DP_LayerDiffNode *, i)
+            == node) {
+            return i;
+        }
+    }
+    DP_UNREACHABLE();
+}
+
+static void node_attach(DP_LayerDiffNode *node, DP_LayerDiffNode *parent,
+                        int index)
+{
+    *(DP_LayerDiffNode **)DP_vector_insert(&parent->children,
+                                           sizeof(DP_LayerDiffNode *),
+                                           DP_int_to_size(index)) = node;
+    node->parent = parent;
+}
+
+static void node_detach(DP_LayerDiffNode *node, int index)
+{
+    DP_VECTOR_REMOVE_TYPE(&node->parent->children, DP_LayerDiffNode *, index);
+    node->parent = NULL;
+}
+
+static void node_free(DP_LayerDiff *ld, DP_LayerDiffNode *node)
+{
+    int count = DP_size_to_int(node->children.used);
+    for (int i = 0; i < count; ++i) {
+        node_free(ld,
+                  DP_VECTOR_AT_TYPE(&node->children, DP_LayerDiffNode *, i));
+    }
+    HASH_DEL(ld->nodes, node);
+    DP_vector_dispose(&node->children);
+    DP_free(node);
+}
+
+static void node_remove(DP_LayerDiff *ld, DP_LayerDiffNode *node, int index)
+{
+    push_event(&ld->events, DP_LAYER_DIFF_REMOVED, node->layer_id,
+               node->parent->layer_id, index, -1, -1);
+    node_detach(node, index);
+    node_free(ld, node);
+}
+
+
+static void build_model(DP_LayerDiff *ld, DP_LayerDiffNode *parent,
+                        DP_LayerList *ll, DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_list_count(ll);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        int layer_id = DP_layer_props_id(lp);
+        DP_LayerDiffNode *node;
+        if (DP_layer_list_entry_is_group(lle)) {
+            DP_LayerGroup *lg = DP_layer_list_entry_group_noinc(lle);
+            node = node_new(ld, layer_id, true, lp, lg);
+            build_model(ld, node, DP_layer_group_children_noinc(lg),
+                        DP_layer_props_children_noinc(lp));
+        }
+        else {
+            node = node_new(ld, layer_id, false, lp,
+                            DP_layer_list_entry_content_noinc(lle));
+        }
+        node_attach(node, parent, i);
+    }
+}
+
+static bool node_gone(DP_LayerDiffNode *node, DP_LayerRoutes *lr)
+{
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, node->layer_id);
+    return !lre || DP_layer_routes_entry_is_group(lre) != node->group;
+}
+
+static bool node_keeps_any(DP_LayerDiffNode *node, DP_LayerRoutes *lr)
+{
+    int count = DP_size_to_int(node->children.used);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerDiffNode *child =
+            DP_VECTOR_AT_TYPE(&node->children, DP_LayerDiffNode *, i);
+        if (!node_gone(child, lr) || node_keeps_any(child, lr)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Removes layers that are gone up front, so that they don't get in the way of
+// moving the others around. Groups that still contain layers that remain have
+// to wait until those have been moved out of them.
+static void remove_gone(DP_LayerDiff *ld, DP_LayerDiffNode *parent,
+                        DP_LayerRoutes *lr)
+{
+    for (int i = DP_size_to_int(parent->children.used) - 1; i >= 0; --i) {
+        DP_LayerDiffNode *node =
+            DP_VECTOR_AT_TYPE(&parent->children, DP_LayerDiffNode *, i);
+        if (node_gone(node, lr) && !node_keeps_any(node, lr)) {
+            node_remove(ld, node, i);
+        }
+        else if (node->group) {
+            remove_gone(ld, node, lr);
+        }
+    }
+}
+
+static bool texts_differ(const char *a, size_t a_length, const char *b,
+                         size_t b_length)
+{
+    return a_length != b_length
+        || (a_length != 0 && memcmp(a, b, a_length) != 0);
+}
+
+static bool attributes_differ(DP_LayerProps *lp, DP_LayerProps *prev_lp)
+{
+    if (lp == prev_lp) {
+        return false;
+    }
+
+    if (DP_layer_props_differ(lp, prev_lp)
+        || DP_layer_props_alpha_lock(lp) != DP_layer_props_alpha_lock(prev_lp)
+        || DP_layer_props_fixed(lp) != DP_layer_props_fixed(prev_lp)) {
+        return true;
+    }
+
+    size_t length, prev_length;
+    const char *title = DP_layer_props_title(lp, &length);
+    const char *prev_title = DP_layer_props_title(prev_lp, &prev_length);
+    return texts_differ(title, length, prev_title, prev_length);
+}
+
+static void check_changes(DP_LayerDiff *ld, DP_LayerDiffNode *node,
+                          DP_LayerProps *lp, void *data)
+{
+    if (attributes_differ(lp, node->prev_lp)) {
+        push_event(&ld->changes, DP_LAYER_DIFF_ATTRIBUTES_CHANGED,
+                   node->layer_id, -1, -1, -1, -1);
+    }
+    if (!node->group && data != node->prev_data) {
+        push_event(&ld->changes, DP_LAYER_DIFF_CONTENT_CHANGED, node->layer_id,
+                   -1, -1, -1, -1);
+    }
+}
+
+// Marks the layers that are already in the given parent and can stay where they
+// are, which is the longest run of them that's still in the right order. If
+// there's several such runs, the one with the layers further down is kept.
+static void mark_staying(DP_LayerDiff *ld, DP_LayerDiffNode *parent,
+                         DP_LayerList *ll, DP_LayerPropsList *lpl)
+{
+    DP_Vector *order = &ld->order;
+    order->used = 0;
+    bool sorted = true;
+    int last_index = -1;
+    int count = DP_layer_list_count(ll);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        DP_LayerDiffNode *node = node_search(ld, DP_layer_props_id(lp));
+        if (node && node->parent == parent
+            && node->group == DP_layer_list_entry_is_group(lle)) {
+            int model_index = node_index(node);
+            sorted = sorted && model_index > last_index;
+            last_index = model_index;
+            DP_VECTOR_PUSH_TYPE(order, DP_LayerDiffOrder,
+                                ((DP_LayerDiffOrder){node, model_index, 1}));
+        }
+    }
+
+    int order_count = DP_size_to_int(order->used);
+    if (sorted) {
+        for (int i = 0; i < order_count; ++i) {
+            DP_VECTOR_AT_TYPE(order, DP_LayerDiffOrder, i).node->stays = true;
+        }
+        return;
+    }
+
+    // Length of the longest run in order starting at each layer.
+    int length = order_count == 0 ? 0 : 1;
+    for (int i = order_count - 2; i >= 0; --i) {
+        DP_LayerDiffOrder *a = &DP_VECTOR_AT_TYPE(order, DP_LayerDiffOrder, i);
+        for (int j = i + 1; j < order_count; ++j) {
+            DP_LayerDiffOrder *b =
+                &DP_VECTOR_AT_TYPE(order, DP_LayerDiffOrder, j);
+            if (b->model_index > a->model_index && b->length >= a->length) {
+                a->length = b->length + 1;
+            }
+        }
+        if (a->length > length) {
+            length = a->length;
+        }
+    }
+
+    int start = 0;
+    int picked_index = -1;
+    while (length > 0) {
+        int picked = -1;
+        int min_index = INT_MAX;
+        for (int i = start; i < order_count; ++i) {
+            DP_LayerDiffOrder *o =
+                &DP_VECTOR_AT_TYPE(order, DP_LayerDiffOrder, i);
+            if (o->length == length && o->model_index > picked_index
+                && o->model_index < min_index) {
+                picked = i;
+                min_index = o->model_index;
+            }
+        }
+        DP_ASSERT(picked != -1);
+        DP_LayerDiffOrder *o =
+            &DP_VECTOR_AT_TYPE(order, DP_LayerDiffOrder, picked);
+        o->node->stays = true;
+        picked_index = o->model_index;
+        start = picked + 1;
+        --length;
+    }
+}
+
+static int next_index(DP_LayerDiffNode *prev_node)
+{
+    return prev_node ? node_index(prev_node) + 1 : 0;
+}
+
+// Puts the children of the given parent into place, then recurses into the
+// groups among them. Once this is done, the children of the parent in the model
+// match the current tree, interspersed with layers that are either moved
+// elsewhere later or get removed.
+static void place_children(DP_LayerDiff *ld, DP_LayerDiffNode *parent,
+                           DP_LayerList *ll, DP_LayerPropsList *lpl)
+{
+    mark_staying(ld, parent, ll, lpl);
+
+    DP_LayerDiffNode *prev_node = NULL;
+    int count = DP_layer_list_count(ll);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        int layer_id = DP_layer_props_id(lp);
+        bool group = DP_layer_list_entry_is_group(lle);
+        void *data = group ? (void *)DP_layer_list_entry_group_noinc(lle)
+                           : (void *)DP_layer_list_entry_content_noinc(lle);
+
+        DP_LayerDiffNode *node = node_search(ld, layer_id);
+        if (node && node->group != group) {
+            node_remove(ld, node, node_index(node));
+            node = NULL;
+        }
+
+        if (node) {
+            if (!node->stays) {
+                DP_LayerDiffNode *from_parent = node->parent;
+                int from_index = node_index(node);
+                node_detach(node, from_index);
+                int to_index = next_index(prev_node);
+                push_event(&ld->events, DP_LAYER_DIFF_MOVED, layer_id,
+                           from_parent->layer_id, from_index, parent->layer_id,
+                           to_index);
+                node_attach(node, parent, to_index);
+            }
+            check_changes(ld, node, lp, data);
+            // Nothing can have been moved into or out of an identical group,
+            // since the layers inside of it are still in the same place.
+            node->unchanged = lp == node->prev_lp && data == node->prev_data;
+        }
+        else {
+            int to_index = next_index(prev_node);
+            push_event(&ld->events, DP_LAYER_DIFF_ADDED, layer_id, -1, -1,
+                       parent->layer_id, to_index);
+            node = node_new(ld, layer_id, group, NULL, NULL);
+            node_attach(node, parent, to_index);
+        }
+
+        node->placed = true;
+        prev_node = node;
+    }
+
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        if (DP_layer_list_entry_is_group(lle)) {
+            DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+            DP_LayerDiffNode *node = node_search(ld, DP_layer_props_id(lp));
+            if (!node->unchanged) {
+                place_children(
+                    ld, node,
+                    DP_layer_group_children_noinc(
+                        DP_layer_list_entry_group_noinc(lle)),
+                    DP_layer_props_children_noinc(lp));
+            }
+        }
+    }
+}
+
+// Removes the groups that remain from the first pass, everything else in them
+// has been moved out by now. Goes back to front to keep the indexes valid.
+static void remove_unplaced(DP_LayerDiff *ld, DP_LayerDiffNode *parent)
+{
+    for (int i = DP_size_to_int(parent->children.used) - 1; i >= 0; --i) {
+        DP_LayerDiffNode *node =
+            DP_VECTOR_AT_TYPE(&parent->children, DP_LayerDiffNode *, i);
+        if (!node->placed) {
+            node_remove(ld, node, i);
+        }
+        else if (node->group && !node->unchanged) {
+            remove_unplaced(ld, node);
+        }
+    }
+}
+
+static void clear_model(DP_LayerDiff *ld)
+{
+    DP_LayerDiffNode *node, *tmp;
+    HASH_ITER(hh, ld->nodes, node, tmp) {
+        HASH_DEL(ld->nodes, node);
+        DP_vector_dispose(&node->children);
+        DP_free(node);
+    }
+    ld->root.children.used = 0;
+}
+
+int DP_layer_diff_update(DP_LayerDiff *ld, DP_CanvasState *cs,
+                         DP_CanvasState *prev)
+{
+    DP_ASSERT(ld);
+    DP_ASSERT(cs);
+    DP_ASSERT(prev);
+    ld->events.used = 0;
+    ld->changes.used = 0;
+
+    DP_LayerList *ll = DP_canvas_state_layers_noinc(cs);
+    DP_LayerPropsList *lpl = DP_canvas_state_layer_props_noinc(cs);
+    DP_LayerList *prev_ll = DP_canvas_state_layers_noinc(prev);
+    DP_LayerPropsList *prev_lpl = DP_canvas_state_layer_props_noinc(prev);
+    if (ll != prev_ll || lpl != prev_lpl) {
+        build_model(ld, &ld->root, prev_ll, prev_lpl);
+        remove_gone(ld, &ld->root, DP_canvas_state_layer_routes_noinc(cs));
+        place_children(ld, &ld->root, ll, lpl);
+        remove_unplaced(ld, &ld->root);
+        clear_model(ld);
+
+        size_t change_count = ld->changes.used;
+        for (size_t i = 0; i < change_count; ++i) {
+            DP_VECTOR_PUSH_TYPE(
+                &ld->events, DP_LayerDiffEvent,
+                DP_VECTOR_AT_TYPE(&ld->changes, DP_LayerDiffEvent, i));
+        }
+    }
+
+    return DP_size_to_int(ld->events.used);
+}
+
+int DP_layer_diff_count(DP_LayerDiff *ld)
+{
+    DP_ASSERT(ld);
+    return DP_size_to_int(ld->events.used);
+}
+
+const DP_LayerDiffEvent *DP_layer_diff_events(DP_LayerDiff *ld)
+{
+    DP_ASSERT(ld);
+    return ld->events.elements;
+}
@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_LAYER_DIFF_H
+#define DPENGINE_LAYER_DIFF_H
+#include <dpcommon/common.h>
+
+typedef struct DP_CanvasState DP_CanvasState;
+
+
+typedef enum DP_LayerDiffType {
+    DP_LAYER_DIFF_ADDED,
+    DP_LAYER_DIFF_REMOVED,
+    DP_LAYER_DIFF_MOVED,
+    DP_LAYER_DIFF_ATTRIBUTES_CHANGED,
+    DP_LAYER_DIFF_CONTENT_CHANGED,
+} DP_LayerDiffType;
+
+// Parents are given as layer ids, 0 being the top level. Indexes are into the
+// parent's children, bottom to top, same as in the layer lists. Fields that
+// don't apply to the type of event are -1.
+typedef struct DP_LayerDiffEvent {
+    DP_LayerDiffType type;
+    int layer_id;
+    // Where the layer was before a removal or move.
+    int from_parent_id;
+    int from_index;
+    // Where the layer is after an addition or move. For moves, the index is
+    // into the children after the layer was taken out from its old spot.
+    int to_parent_id;
+    int to_index;
+} DP_LayerDiffEvent;
+
+typedef struct DP_LayerDiff DP_LayerDiff;
+
+
+DP_LayerDiff *DP_layer_diff_new(void);
+
+void DP_layer_diff_free(DP_LayerDiff *ld);
+
+// Compares the layer trees of the two canvas states and replaces the events
+// in the diff with the changes that turn the previous tree into the current
+// one. Returns the number of events, zero if nothing changed.
+//
+// The structural events, additions, removals and moves, come first. Each one
+// refers to the tree as it is after applying all the events before it, so a
+// model of the previous tree can take them one by one and end up with the
+// current one. Layers that are gone get removed first, then layers are moved
+// and added into place parent by parent, starting from the top level, bottom
+// to top. Within a parent, the longest run of layers that are still in the
+// right order relative to each other stays where it is, only the others get
+// moved. An added group is empty, its children are added or moved into it by
+// subsequent events. A group that is gone but still contains layers that
+// remain is only removed at the end, after those have been moved out of it.
+// Removing a group removes everything still inside of it. A layer that turns
+// into a group or vice versa is removed and added again.
+//
+// Attribute and content changes follow, only referring to layers by id and
+// only for layers that weren't added, since those are new to the model anyway.
+// Content changes are reported on the layers whose content was replaced, not
+// on the groups around them. Attributes are everything in the layer properties
+// apart from the children of groups.
+int DP_layer_diff_update(DP_LayerDiff *ld, DP_CanvasState *cs,
+                         DP_CanvasState *prev);
+
+int DP_layer_diff_count(DP_LayerDiff *ld);
+
+const DP_LayerDiffEvent *DP_layer_diff_events(DP_LayerDiff *ld);
+
+
+#endif
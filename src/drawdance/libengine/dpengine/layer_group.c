@@ -211,38 +211,80 @@ int DP_layer_group_search_change_bounds(DP_LayerGroup *lg, DP_LayerProps *lp,
 }
 
 DP_TransientLayerGroup *DP_layer_group_resize(DP_LayerGroup *lg,
+                                              DP_LayerProps *lp,
                                               unsigned int context_id, int top,
                                               int right, int bottom, int left)
 {
     DP_ASSERT(lg);
     DP_ASSERT(DP_atomic_get(&lg->refcount) > 0);
+    DP_ASSERT(lp);
     int width = lg->width + left + right;
     int height = lg->height + top + bottom;
-    DP_LayerList *ll = lg->children;
-    int count = DP_layer_list_count(ll);
+    DP_TransientLayerList *tll =
+        DP_layer_list_resize(lg->children, DP_layer_props_children_noinc(lp),
+                             context_id, top, right, bottom, left);
+    return DP_transient_layer_group_new_init_with_transient_children_noinc(
+        width, height, tll);
+}
 
-    DP_TransientLayerGroup *tlg =
-        DP_transient_layer_group_new_init(width, height, count);
-    DP_TransientLayerList *tll = tlg->transient_children;
+DP_TransientLayerGroup *DP_layer_group_rotate(DP_LayerGroup *lg,
+                                              unsigned int context_id,
+                                              int quarter_turns)
+{
+    DP_ASSERT(lg);
+    DP_ASSERT(DP_atomic_get(&lg->refcount) > 0);
+    bool swap = quarter_turns != 2;
+    int width = swap ? lg->height : lg->width;
+    int height = swap ? lg->width : lg->height;
+    DP_TransientLayerList *tll =
+        DP_layer_list_rotate(lg->children, context_id, quarter_turns);
+    return DP_transient_layer_group_new_init_with_transient_children_noinc(
+        width, height, tll);
+}
 
-    for (int i = 0; i < count; ++i) {
-        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
-        if (DP_layer_list_entry_is_group(lle)) {
-            DP_TransientLayerGroup *ctlg =
-                DP_layer_group_resize(DP_layer_list_entry_group_noinc(lle),
-                                      context_id, top, right, bottom, left);
-            DP_transient_layer_list_insert_transient_group_noinc(tll, ctlg, i);
-        }
-        else {
-            DP_TransientLayerContent *ctlc =
-                DP_layer_content_resize(DP_layer_list_entry_content_noinc(lle),
-                                        context_id, top, right, bottom, left);
-            DP_transient_layer_list_insert_transient_content_noinc(tll, ctlc,
-                                                                   i);
-        }
-    }
+DP_TransientLayerGroup *DP_layer_group_flip(DP_LayerGroup *lg,
+                                            unsigned int context_id,
+                                            bool horizontal)
+{
+    DP_ASSERT(lg);
+    DP_ASSERT(DP_atomic_get(&lg->refcount) > 0);
+    DP_TransientLayerList *tll =
+        DP_layer_list_flip(lg->children, context_id, horizontal);
+    return DP_transient_layer_group_new_init_with_transient_children_noinc(
+        lg->width, lg->height, tll);
+}
 
-    return tlg;
+DP_TransientLayerGroup *DP_layer_group_offset_wrap(DP_LayerGroup *lg,
+                                                   unsigned int context_id,
+                                                   int offset_x, int offset_y)
+{
+    DP_ASSERT(lg);
+    DP_ASSERT(DP_atomic_get(&lg->refcount) > 0);
+    DP_TransientLayerList *tll = DP_layer_list_offset_wrap(
+        lg->children, context_id, offset_x, offset_y);
+    return DP_transient_layer_group_new_init_with_transient_children_noinc(
+        lg->width, lg->height, tll);
+}
+
+DP_TransientLayerGroup *
+DP_layer_group_transform(DP_LayerGroup *lg, DP_LayerProps *lp,
+                         DP_DrawContext *dc, unsigned int context_id,
+                         const DP_Transform *tf, int width, int height,
+                         int interpolation)
+{
+    DP_ASSERT(lg);
+    DP_ASSERT(DP_atomic_get(&lg->refcount) > 0);
+    DP_ASSERT(lp);
+    DP_TransientLayerList *tll = DP_layer_list_transform(
+        lg->children, DP_layer_props_children_noinc(lp), dc, context_id, tf,
+        width, height, interpolation);
+    if (tll) {
+        return DP_transient_layer_group_new_init_with_transient_children_noinc(
+            width, height, tll);
+    }
+    else {
+        return NULL;
+    }
 }
 
 DP_Pixel8 *DP_layer_group_to_pixels8(DP_LayerGroup *lg, DP_LayerProps *lp,
@@ -24,6 +24,8 @@
 #include <dpcommon/common.h>
 
 typedef struct DP_CanvasDiff DP_CanvasDiff;
+typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_Transform DP_Transform;
 typedef struct DP_ViewModeContext DP_ViewModeContext;
 typedef union DP_Pixel8 DP_Pixel8;
 
@@ -76,9 +78,28 @@ int DP_layer_group_search_change_bounds(DP_LayerGroup *lg, DP_LayerProps *lp,
                                         int *out_height);
 
 DP_TransientLayerGroup *DP_layer_group_resize(DP_LayerGroup *lg,
+                                              DP_LayerProps *lp,
                                               unsigned int context_id, int top,
                                               int right, int bottom, int left);
 
+DP_TransientLayerGroup *DP_layer_group_rotate(DP_LayerGroup *lg,
+                                              unsigned int context_id,
+                                              int quarter_turns);
+
+DP_TransientLayerGroup *DP_layer_group_flip(DP_LayerGroup *lg,
+                                            unsigned int context_id,
+                                            bool horizontal);
+
+DP_TransientLayerGroup *DP_layer_group_offset_wrap(DP_LayerGroup *lg,
+                                                   unsigned int context_id,
+                                                   int offset_x, int offset_y);
+
+DP_TransientLayerGroup *
+DP_layer_group_transform(DP_LayerGroup *lg, DP_LayerProps *lp,
+                         DP_DrawContext *dc, unsigned int context_id,
+                         const DP_Transform *tf, int width, int height,
+                         int interpolation);
+
 DP_Pixel8 *DP_layer_group_to_pixels8(DP_LayerGroup *lg, DP_LayerProps *lp,
                                      int x, int y, int width, int height);
 
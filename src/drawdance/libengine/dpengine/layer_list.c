@@ -324,24 +324,87 @@ DP_LayerGroup *DP_layer_list_group_at_noinc(DP_LayerList *ll, int index)
 }
 
 DP_TransientLayerList *DP_layer_list_resize(DP_LayerList *ll,
+                                            DP_LayerPropsList *lpl,
                                             unsigned int context_id, int top,
                                             int right, int bottom, int left)
 {
     DP_ASSERT(ll);
     DP_ASSERT(DP_atomic_get(&ll->refcount) > 0);
+    DP_ASSERT(lpl);
+    DP_ASSERT(ll->count == DP_layer_props_list_count(lpl));
     int count = ll->count;
     DP_TransientLayerList *tll = allocate_layer_list(true, count);
     for (int i = 0; i < count; ++i) {
         DP_LayerListEntry *lle = &ll->elements[i];
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
         if (lle->is_group) {
             DP_TransientLayerGroup *tlg = DP_layer_group_resize(
-                lle->group, context_id, top, right, bottom, left);
+                lle->group, lp, context_id, top, right, bottom, left);
+            tll->elements[i] =
+                (DP_LayerListEntry){true, {.transient_group = tlg}};
+        }
+        else {
+            DP_TransientLayerContent *tlc =
+                DP_layer_props_fixed(lp)
+                    ? DP_layer_content_resize_anchored(lle->content, top,
+                                                       right, bottom, left)
+                    : NULL;
+            if (!tlc) {
+                tlc = DP_layer_content_resize(lle->content, context_id, top,
+                                              right, bottom, left);
+            }
+            tll->elements[i] =
+                (DP_LayerListEntry){false, {.transient_content = tlc}};
+        }
+    }
+    return tll;
+}
+
+DP_TransientLayerList *DP_layer_list_rotate(DP_LayerList *ll,
+                                            unsigned int context_id,
+                                            int quarter_turns)
+{
+    DP_ASSERT(ll);
+    DP_ASSERT(DP_atomic_get(&ll->refcount) > 0);
+    int count = ll->count;
+    DP_TransientLayerList *tll = allocate_layer_list(true, count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = &ll->elements[i];
+        if (lle->is_group) {
+            DP_TransientLayerGroup *tlg =
+                DP_layer_group_rotate(lle->group, context_id, quarter_turns);
+            tll->elements[i] =
+                (DP_LayerListEntry){true, {.transient_group = tlg}};
+        }
+        else {
+            DP_TransientLayerContent *tlc = DP_layer_content_rotate(
+                lle->content, context_id, quarter_turns);
+            tll->elements[i] =
+                (DP_LayerListEntry){false, {.transient_content = tlc}};
+        }
+    }
+    return tll;
+}
+
+DP_TransientLayerList *DP_layer_list_flip(DP_LayerList *ll,
+                                          unsigned int context_id,
+                                          bool horizontal)
+{
+    DP_ASSERT(ll);
+    DP_ASSERT(DP_atomic_get(&ll->refcount) > 0);
+    int count = ll->count;
+    DP_TransientLayerList *tll = allocate_layer_list(true, count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = &ll->elements[i];
+        if (lle->is_group) {
+            DP_TransientLayerGroup *tlg =
+                DP_layer_group_flip(lle->group, context_id, horizontal);
             tll->elements[i] =
                 (DP_LayerListEntry){true, {.transient_group = tlg}};
         }
         else {
-            DP_TransientLayerContent *tlc = DP_layer_content_resize(
-                lle->content, context_id, top, right, bottom, left);
+            DP_TransientLayerContent *tlc =
+                DP_layer_content_flip(lle->content, context_id, horizontal);
             tll->elements[i] =
                 (DP_LayerListEntry){false, {.transient_content = tlc}};
         }
@@ -349,6 +412,82 @@ DP_TransientLayerList *DP_layer_list_resize(DP_LayerList *ll,
     return tll;
 }
 
+DP_TransientLayerList *DP_layer_list_offset_wrap(DP_LayerList *ll,
+                                                 unsigned int context_id,
+                                                 int offset_x, int offset_y)
+{
+    DP_ASSERT(ll);
+    DP_ASSERT(DP_atomic_get(&ll->refcount) > 0);
+    int count = ll->count;
+    DP_TransientLayerList *tll = allocate_layer_list(true, count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = &ll->elements[i];
+        if (lle->is_group) {
+            DP_TransientLayerGroup *tlg = DP_layer_group_offset_wrap(
+                lle->group, context_id, offset_x, offset_y);
+            tll->elements[i] =
+                (DP_LayerListEntry){true, {.transient_group = tlg}};
+        }
+        else {
+            DP_TransientLayerContent *tlc = DP_layer_content_offset_wrap(
+                lle->content, context_id, offset_x, offset_y);
+            tll->elements[i] =
+                (DP_LayerListEntry){false, {.transient_content = tlc}};
+        }
+    }
+    return tll;
+}
+
+DP_TransientLayerList *
+DP_layer_list_transform(DP_LayerList *ll, DP_LayerPropsList *lpl,
+                        DP_DrawContext *dc, unsigned int context_id,
+                        const DP_Transform *tf, int width, int height,
+                        int interpolation)
+{
+    DP_ASSERT(ll);
+    DP_ASSERT(DP_atomic_get(&ll->refcount) > 0);
+    DP_ASSERT(lpl);
+    DP_ASSERT(ll->count == DP_layer_props_list_count(lpl));
+    int count = ll->count;
+    DP_TransientLayerList *tll = allocate_layer_list(true, count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = &ll->elements[i];
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        bool ok;
+        if (lle->is_group) {
+            DP_TransientLayerGroup *tlg =
+                DP_layer_group_transform(lle->group, lp, dc, context_id, tf,
+                                         width, height, interpolation);
+            tll->elements[i] =
+                (DP_LayerListEntry){true, {.transient_group = tlg}};
+            ok = tlg != NULL;
+        }
+        else {
+            DP_LayerContent *lc = lle->content;
+            DP_TransientLayerContent *tlc =
+                DP_layer_props_fixed(lp)
+                    ? DP_layer_content_resize_anchored(
+                        lc, 0, width - DP_layer_content_width(lc),
+                        height - DP_layer_content_height(lc), 0)
+                    : NULL;
+            if (!tlc) {
+                tlc = DP_layer_content_transform(lc, dc, context_id, tf, width,
+                                                 height, interpolation);
+            }
+            tll->elements[i] =
+                (DP_LayerListEntry){false, {.transient_content = tlc}};
+            ok = tlc != NULL;
+        }
+
+        if (!ok) {
+            tll->count = i;
+            DP_transient_layer_list_decref(tll);
+            return NULL;
+        }
+    }
+    return tll;
+}
+
 void DP_layer_list_merge_to_flat_image(DP_LayerList *ll, DP_LayerPropsList *lpl,
                                        DP_TransientLayerContent *tlc,
                                        uint16_t parent_opacity,
@@ -25,8 +25,10 @@
 #include <dpcommon/common.h>
 
 typedef struct DP_CanvasDiff DP_CanvasDiff;
+typedef struct DP_DrawContext DP_DrawContext;
 typedef struct DP_LayerListEntry DP_LayerListEntry;
 typedef struct DP_LayerProps DP_LayerProps;
+typedef struct DP_Transform DP_Transform;
 typedef struct DP_ViewModeContext DP_ViewModeContext;
 
 #ifdef DP_NO_STRICT_ALIASING
@@ -92,9 +94,29 @@ DP_LayerContent *DP_layer_list_content_at_noinc(DP_LayerList *ll, int index);
 DP_LayerGroup *DP_layer_list_group_at_noinc(DP_LayerList *ll, int index);
 
 DP_TransientLayerList *DP_layer_list_resize(DP_LayerList *ll,
+                                            DP_LayerPropsList *lpl,
                                             unsigned int context_id, int top,
                                             int right, int bottom, int left);
 
+DP_TransientLayerList *DP_layer_list_rotate(DP_LayerList *ll,
+                                            unsigned int context_id,
+                                            int quarter_turns);
+
+DP_TransientLayerList *DP_layer_list_flip(DP_LayerList *ll,
+                                          unsigned int context_id,
+                                          bool horizontal);
+
+DP_TransientLayerList *DP_layer_list_offset_wrap(DP_LayerList *ll,
+                                                 unsigned int context_id,
+                                                 int offset_x, int offset_y);
+
+// Fixed layers with a repeating pattern get extended to the new size, the same
+// as when resizing the canvas, everything else gets transformed.
+DP_TransientLayerList *
+DP_layer_list_transform(DP_LayerList *ll, DP_LayerPropsList *lpl,
+                        DP_DrawContext *dc, unsigned int context_id,
+                        const DP_Transform *tf, int width, int height,
+                        int interpolation);
 
 void DP_layer_list_merge_to_flat_image(DP_LayerList *ll, DP_LayerPropsList *lpl,
                                        DP_TransientLayerContent *tlc,
@@ -25,9 +25,21 @@
 #include "text.h"
 #include <dpcommon/atomic.h>
 #include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
 #include <dpmsg/blend_mode.h>
 
 
+typedef struct DP_LayerPropsMetadataEntry {
+    DP_Text *key;
+    DP_Text *value;
+} DP_LayerPropsMetadataEntry;
+
+typedef struct DP_LayerPropsMetadata {
+    DP_Atomic refcount;
+    int count;
+    DP_LayerPropsMetadataEntry entries[];
+} DP_LayerPropsMetadata;
+
 #ifdef DP_NO_STRICT_ALIASING
 
 struct DP_LayerProps {
@@ -39,7 +51,11 @@ struct DP_LayerProps {
     const bool hidden;
     const bool censored;
     const bool isolated;
+    const bool alpha_lock;
+    const bool fixed;
     DP_Text *const title;
+    const int color_tag;
+    DP_LayerPropsMetadata *const metadata;
     struct {
         DP_LayerPropsList *const children;
     };
@@ -54,7 +70,11 @@ struct DP_TransientLayerProps {
     bool hidden;
     bool censored;
     bool isolated;
+    bool alpha_lock;
+    bool fixed;
     DP_Text *title;
+    int color_tag;
+    DP_LayerPropsMetadata *metadata;
     union {
         DP_LayerPropsList *children;
         DP_TransientLayerPropsList *transient_children;
@@ -72,7 +92,11 @@ struct DP_LayerProps {
     bool hidden;
     bool censored;
     bool isolated;
+    bool alpha_lock;
+    bool fixed;
     DP_Text *title;
+    int color_tag;
+    DP_LayerPropsMetadata *metadata;
     union {
         DP_LayerPropsList *children;
         DP_TransientLayerPropsList *transient_children;
@@ -82,6 +106,110 @@ struct DP_LayerProps {
 #endif
 
 
+static DP_LayerPropsMetadata *metadata_new(int count)
+{
+    DP_ASSERT(count > 0);
+    DP_LayerPropsMetadata *md = DP_malloc(
+        DP_FLEX_SIZEOF(DP_LayerPropsMetadata, entries, DP_int_to_size(count)));
+    DP_atomic_set(&md->refcount, 1);
+    md->count = count;
+    return md;
+}
+
+static DP_LayerPropsMetadata *
+metadata_incref_nullable(DP_LayerPropsMetadata *md_or_null)
+{
+    if (md_or_null) {
+        DP_ASSERT(DP_atomic_get(&md_or_null->refcount) > 0);
+        DP_atomic_inc(&md_or_null->refcount);
+    }
+    return md_or_null;
+}
+
+static void metadata_decref_nullable(DP_LayerPropsMetadata *md_or_null)
+{
+    if (md_or_null) {
+        DP_ASSERT(DP_atomic_get(&md_or_null->refcount) > 0);
+        if (DP_atomic_dec(&md_or_null->refcount)) {
+            for (int i = 0; i < md_or_null->count; ++i) {
+                DP_text_decref_nullable(md_or_null->entries[i].key);
+                DP_text_decref_nullable(md_or_null->entries[i].value);
+            }
+            DP_free(md_or_null);
+        }
+    }
+}
+
+static int metadata_compare_key(DP_Text *text, const char *key,
+                                size_t key_length)
+{
+    size_t length;
+    const char *string = DP_text_string(text, &length);
+    int c = memcmp(string, key, length < key_length ? length : key_length);
+    if (c == 0) {
+        return length < key_length ? -1 : length > key_length ? 1 : 0;
+    }
+    else {
+        return c;
+    }
+}
+
+// Returns the index of the entry with the given key if it exists. Otherwise
+// returns the index it would be inserted at, encoded as -(index + 1).
+static int metadata_search(DP_LayerPropsMetadata *md_or_null, const char *key,
+                           size_t key_length)
+{
+    int lo = 0;
+    int hi = md_or_null ? md_or_null->count : 0;
+    while (lo < hi) {
+        int mid = lo + (hi - lo) / 2;
+        int c = metadata_compare_key(md_or_null->entries[mid].key, key,
+                                     key_length);
+        if (c < 0) {
+            lo = mid + 1;
+        }
+        else if (c > 0) {
+            hi = mid;
+        }
+        else {
+            return mid;
+        }
+    }
+    return -(lo + 1);
+}
+
+static bool texts_equal(DP_Text *a, DP_Text *b)
+{
+    size_t a_length, b_length;
+    const char *a_string = DP_text_string(a, &a_length);
+    const char *b_string = DP_text_string(b, &b_length);
+    return a_length == b_length
+        && (a_length == 0 || memcmp(a_string, b_string, a_length) == 0);
+}
+
+static bool metadata_equal(DP_LayerPropsMetadata *a_or_null,
+                           DP_LayerPropsMetadata *b_or_null)
+{
+    if (a_or_null == b_or_null) {
+        return true;
+    }
+    else if (!a_or_null || !b_or_null || a_or_null->count != b_or_null->count) {
+        return false;
+    }
+    else {
+        for (int i = 0; i < a_or_null->count; ++i) {
+            DP_LayerPropsMetadataEntry *a = &a_or_null->entries[i];
+            DP_LayerPropsMetadataEntry *b = &b_or_null->entries[i];
+            if (!texts_equal(a->key, b->key)
+                || !texts_equal(a->value, b->value)) {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+
 DP_LayerProps *DP_layer_props_incref(DP_LayerProps *lp)
 {
     DP_ASSERT(lp);
@@ -101,6 +229,7 @@ void DP_layer_props_decref(DP_LayerProps *lp)
     DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
     if (DP_atomic_dec(&lp->refcount)) {
         DP_layer_props_list_decref_nullable(lp->children);
+        metadata_decref_nullable(lp->metadata);
         DP_text_decref_nullable(lp->title);
         DP_free(lp);
     }
@@ -169,6 +298,20 @@ bool DP_layer_props_isolated(DP_LayerProps *lp)
     return lp->isolated;
 }
 
+bool DP_layer_props_alpha_lock(DP_LayerProps *lp)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    return lp->alpha_lock;
+}
+
+bool DP_layer_props_fixed(DP_LayerProps *lp)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    return lp->fixed;
+}
+
 bool DP_layer_props_visible(DP_LayerProps *lp)
 {
     DP_ASSERT(lp);
@@ -183,6 +326,55 @@ const char *DP_layer_props_title(DP_LayerProps *lp, size_t *out_length)
     return DP_text_string(lp->title, out_length);
 }
 
+int DP_layer_props_color_tag(DP_LayerProps *lp)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    return lp->color_tag;
+}
+
+int DP_layer_props_metadata_count(DP_LayerProps *lp)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    DP_LayerPropsMetadata *md = lp->metadata;
+    return md ? md->count : 0;
+}
+
+const char *DP_layer_props_metadata_key_at(DP_LayerProps *lp, int index,
+                                           size_t *out_length)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    DP_ASSERT(index >= 0);
+    DP_ASSERT(index < DP_layer_props_metadata_count(lp));
+    return DP_text_string(lp->metadata->entries[index].key, out_length);
+}
+
+const char *DP_layer_props_metadata_value_at(DP_LayerProps *lp, int index,
+                                             size_t *out_length)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    DP_ASSERT(index >= 0);
+    DP_ASSERT(index < DP_layer_props_metadata_count(lp));
+    return DP_text_string(lp->metadata->entries[index].value, out_length);
+}
+
+const char *DP_layer_props_metadata_get(DP_LayerProps *lp, const char *key,
+                                        size_t key_length, size_t *out_length)
+{
+    DP_ASSERT(lp);
+    DP_ASSERT(DP_atomic_get(&lp->refcount) > 0);
+    int index = metadata_search(lp->metadata, key, key_length);
+    if (index >= 0) {
+        return DP_text_string(lp->metadata->entries[index].value, out_length);
+    }
+    else {
+        return NULL;
+    }
+}
+
 DP_LayerPropsList *DP_layer_props_children_noinc(DP_LayerProps *lp)
 {
     DP_ASSERT(lp);
@@ -197,7 +389,9 @@ bool DP_layer_props_differ(DP_LayerProps *lp, DP_LayerProps *prev_lp)
             || lp->blend_mode != prev_lp->blend_mode
             || lp->hidden != prev_lp->hidden
             || lp->censored != prev_lp->censored
-            || lp->isolated != prev_lp->isolated);
+            || lp->isolated != prev_lp->isolated
+            || lp->color_tag != prev_lp->color_tag
+            || !metadata_equal(lp->metadata, prev_lp->metadata));
 }
 
 
@@ -213,7 +407,11 @@ static DP_TransientLayerProps *alloc_transient_layer_props(DP_LayerProps *lp)
         lp->hidden,
         lp->censored,
         lp->isolated,
+        lp->alpha_lock,
+        lp->fixed,
         DP_text_incref_nullable(lp->title),
+        lp->color_tag,
+        metadata_incref_nullable(lp->metadata),
         {NULL},
     };
     return tlp;
@@ -274,6 +472,10 @@ DP_transient_layer_props_new_init_with_transient_children_noinc(
         false,
         false,
         tlpl_or_null != NULL,
+        false,
+        false,
+        NULL,
+        DP_LAYER_COLOR_TAG_NONE,
         NULL,
         {.transient_children = tlpl_or_null},
     };
@@ -367,6 +569,22 @@ bool DP_transient_layer_props_isolated(DP_TransientLayerProps *tlp)
     return DP_layer_props_isolated((DP_LayerProps *)tlp);
 }
 
+bool DP_transient_layer_props_alpha_lock(DP_TransientLayerProps *tlp)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    return DP_layer_props_alpha_lock((DP_LayerProps *)tlp);
+}
+
+bool DP_transient_layer_props_fixed(DP_TransientLayerProps *tlp)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    return DP_layer_props_fixed((DP_LayerProps *)tlp);
+}
+
 bool DP_transient_layer_props_visible(DP_TransientLayerProps *tlp)
 {
     DP_ASSERT(tlp);
@@ -467,6 +685,24 @@ void DP_transient_layer_props_isolated_set(DP_TransientLayerProps *tlp,
     tlp->isolated = isolated;
 }
 
+void DP_transient_layer_props_alpha_lock_set(DP_TransientLayerProps *tlp,
+                                             bool alpha_lock)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    tlp->alpha_lock = alpha_lock;
+}
+
+void DP_transient_layer_props_fixed_set(DP_TransientLayerProps *tlp,
+                                        bool fixed)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    tlp->fixed = fixed;
+}
+
 void DP_transient_layer_props_title_set(DP_TransientLayerProps *tlp,
                                         const char *title, size_t length)
 {
@@ -476,3 +712,81 @@ void DP_transient_layer_props_title_set(DP_TransientLayerProps *tlp,
     DP_text_decref_nullable(tlp->title);
     tlp->title = DP_text_new(title, length);
 }
+
+void DP_transient_layer_props_color_tag_set(DP_TransientLayerProps *tlp,
+                                            int color_tag)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    tlp->color_tag = color_tag >= 0 && color_tag < DP_LAYER_COLOR_TAG_COUNT
+                       ? color_tag
+                       : DP_LAYER_COLOR_TAG_NONE;
+}
+
+void DP_transient_layer_props_metadata_set(DP_TransientLayerProps *tlp,
+                                           const char *key, size_t key_length,
+                                           const char *value_or_null,
+                                           size_t value_length)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    DP_ASSERT(key);
+    DP_ASSERT(key_length != 0);
+    DP_LayerPropsMetadata *md = tlp->metadata;
+    int old_count = md ? md->count : 0;
+    int index = metadata_search(md, key, key_length);
+    bool erase = !value_or_null || value_length == 0;
+    if (index < 0 && erase) {
+        return; // Nothing to remove.
+    }
+
+    // Metadata may be shared with other layer props, so we always make a new
+    // copy with the change applied instead of modifying it in place.
+    int new_count = old_count;
+    if (erase) {
+        --new_count;
+    }
+    else if (index < 0) {
+        ++new_count;
+    }
+    DP_LayerPropsMetadata *new_md =
+        new_count == 0 ? NULL : metadata_new(new_count);
+    int insert_index = index < 0 ? -(index + 1) : index;
+    int j = 0;
+    for (int i = 0; i < old_count; ++i) {
+        if (i == insert_index) {
+            if (!erase) {
+                new_md->entries[j++] = (DP_LayerPropsMetadataEntry){
+                    index < 0 ? DP_text_new(key, key_length)
+                              : DP_text_incref_nullable(md->entries[i].key),
+                    DP_text_new(value_or_null, value_length)};
+            }
+            if (index >= 0) {
+                continue; // Entry replaced or removed.
+            }
+        }
+        new_md->entries[j++] = (DP_LayerPropsMetadataEntry){
+            DP_text_incref_nullable(md->entries[i].key),
+            DP_text_incref_nullable(md->entries[i].value)};
+    }
+    if (insert_index == old_count && !erase) {
+        new_md->entries[j++] = (DP_LayerPropsMetadataEntry){
+            DP_text_new(key, key_length),
+            DP_text_new(value_or_null, value_length)};
+    }
+    DP_ASSERT(j == new_count);
+
+    metadata_decref_nullable(md);
+    tlp->metadata = new_md;
+}
+
+void DP_transient_layer_props_metadata_clear(DP_TransientLayerProps *tlp)
+{
+    DP_ASSERT(tlp);
+    DP_ASSERT(DP_atomic_get(&tlp->refcount) > 0);
+    DP_ASSERT(tlp->transient);
+    metadata_decref_nullable(tlp->metadata);
+    tlp->metadata = NULL;
+}
@@ -36,6 +36,21 @@ typedef struct DP_LayerPropsList DP_LayerPropsList;
 typedef struct DP_LayerPropsList DP_TransientLayerPropsList;
 #endif
 
+// Color labels for organizing layers, these don't affect rendering. The order
+// and values match Krita's layer color labels.
+typedef enum DP_LayerColorTag {
+    DP_LAYER_COLOR_TAG_NONE,
+    DP_LAYER_COLOR_TAG_BLUE,
+    DP_LAYER_COLOR_TAG_GREEN,
+    DP_LAYER_COLOR_TAG_YELLOW,
+    DP_LAYER_COLOR_TAG_ORANGE,
+    DP_LAYER_COLOR_TAG_BROWN,
+    DP_LAYER_COLOR_TAG_RED,
+    DP_LAYER_COLOR_TAG_PURPLE,
+    DP_LAYER_COLOR_TAG_GRAY,
+    DP_LAYER_COLOR_TAG_COUNT,
+} DP_LayerColorTag;
+
 
 DP_LayerProps *DP_layer_props_incref(DP_LayerProps *lp);
 
@@ -61,13 +76,40 @@ bool DP_layer_props_censored(DP_LayerProps *lp);
 
 bool DP_layer_props_isolated(DP_LayerProps *lp);
 
+// Alpha-locked layers only get painted where they already have pixels. This
+// isn't enforced by the layer itself, the operations that paint check it.
+bool DP_layer_props_alpha_lock(DP_LayerProps *lp);
+
+// Fixed layers are backgrounds that aren't part of any particular frame. When
+// the canvas is resized, their content stays anchored to the canvas instead of
+// being translated if it's a uniform color or a repeating tile pattern.
+bool DP_layer_props_fixed(DP_LayerProps *lp);
+
 bool DP_layer_props_visible(DP_LayerProps *lp);
 
 const char *DP_layer_props_title(DP_LayerProps *lp, size_t *out_length);
 
+int DP_layer_props_color_tag(DP_LayerProps *lp);
+
+// Small key-value map of annotations on the layer, sorted by key. Like the
+// color tag, this doesn't affect rendering.
+int DP_layer_props_metadata_count(DP_LayerProps *lp);
+
+const char *DP_layer_props_metadata_key_at(DP_LayerProps *lp, int index,
+                                           size_t *out_length);
+
+const char *DP_layer_props_metadata_value_at(DP_LayerProps *lp, int index,
+                                             size_t *out_length);
+
+// Returns NULL if there's no entry with the given key.
+const char *DP_layer_props_metadata_get(DP_LayerProps *lp, const char *key,
+                                        size_t key_length, size_t *out_length);
+
 // Will return NULL if this is not a group.
 DP_LayerPropsList *DP_layer_props_children_noinc(DP_LayerProps *lp);
 
+// Compares opacity, blend mode, visibility, censoring, isolation, color tag and
+// metadata. Titles, alpha lock and fixed aren't part of it.
 bool DP_layer_props_differ(DP_LayerProps *lp, DP_LayerProps *prev_lp);
 
 
@@ -106,6 +148,10 @@ bool DP_transient_layer_props_censored(DP_TransientLayerProps *tlp);
 
 bool DP_transient_layer_props_isolated(DP_TransientLayerProps *tlp);
 
+bool DP_transient_layer_props_alpha_lock(DP_TransientLayerProps *tlp);
+
+bool DP_transient_layer_props_fixed(DP_TransientLayerProps *tlp);
+
 bool DP_transient_layer_props_visible(DP_TransientLayerProps *tlp);
 
 const char *DP_transient_layer_props_title(DP_TransientLayerProps *tlp,
@@ -136,8 +182,25 @@ void DP_transient_layer_props_hidden_set(DP_TransientLayerProps *tlp,
 void DP_transient_layer_props_isolated_set(DP_TransientLayerProps *tlp,
                                            bool isolated);
 
+void DP_transient_layer_props_alpha_lock_set(DP_TransientLayerProps *tlp,
+                                             bool alpha_lock);
+
+void DP_transient_layer_props_fixed_set(DP_TransientLayerProps *tlp,
+                                        bool fixed);
+
 void DP_transient_layer_props_title_set(DP_TransientLayerProps *tlp,
                                         const char *title, size_t length);
 
+void DP_transient_layer_props_color_tag_set(DP_TransientLayerProps *tlp,
+                                            int color_tag);
+
+// Passing a NULL or empty value removes the entry with the given key.
+void DP_transient_layer_props_metadata_set(DP_TransientLayerProps *tlp,
+                                           const char *key, size_t key_length,
+                                           const char *value_or_null,
+                                           size_t value_length);
+
+void DP_transient_layer_props_metadata_clear(DP_TransientLayerProps *tlp);
+
 
 #endif
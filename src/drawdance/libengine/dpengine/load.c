@@ -51,6 +51,7 @@
 #include <dpmsg/binary_reader.h>
 #include <dpmsg/blend_mode.h>
 #include <ctype.h>
+#include <parson.h>
 
 #define DP_PERF_CONTEXT "load"
 
@@ -462,6 +463,44 @@ static DP_TransientLayerProps *ora_make_layer_props(DP_XmlElement *element,
         DP_transient_layer_props_censored_set(tlp, true);
     }
 
+    const char *alpha_lock =
+        DP_xml_element_attribute(element, DRAWPILE_NAMESPACE, "alpha-lock");
+    if (DP_str_equal_lowercase(alpha_lock, "true")) {
+        DP_transient_layer_props_alpha_lock_set(tlp, true);
+    }
+
+    const char *fixed =
+        DP_xml_element_attribute(element, DRAWPILE_NAMESPACE, "fixed");
+    if (DP_str_equal_lowercase(fixed, "true")) {
+        DP_transient_layer_props_fixed_set(tlp, true);
+    }
+
+    int color_tag;
+    if (ora_read_int_attribute(element, DRAWPILE_NAMESPACE, "colorlabel", 0,
+                               DP_LAYER_COLOR_TAG_COUNT - 1, &color_tag)) {
+        DP_transient_layer_props_color_tag_set(tlp, color_tag);
+    }
+
+    const char *metadata =
+        DP_xml_element_attribute(element, DRAWPILE_NAMESPACE, "metadata");
+    if (metadata) {
+        JSON_Value *value = json_parse_string(metadata);
+        JSON_Object *object = json_value_get_object(value);
+        size_t count = json_object_get_count(object);
+        for (size_t i = 0; i < count; ++i) {
+            const char *key = json_object_get_name(object, i);
+            const char *string = json_object_get_string(object, key);
+            size_t key_length = key ? strlen(key) : 0;
+            if (key_length != 0 && string) {
+                DP_transient_layer_props_metadata_set(tlp, key, key_length,
+                                                      string, strlen(string));
+            }
+        }
+        if (value) {
+            json_value_free(value);
+        }
+    }
+
     return tlp;
 }
 
@@ -696,6 +735,11 @@ static bool ora_handle_annotation(DP_ReadOraContext *c, DP_XmlElement *element)
         DP_transient_annotation_new_init(annotation_id, x, y, width, height);
     DP_transient_annotation_background_color_set(ta, background_color);
 
+    int border = 0;
+    ora_read_int_attribute(element, NULL, "border", 0, DP_ANNOTATION_BORDER_MAX,
+                           &border);
+    DP_transient_annotation_border_set(ta, border);
+
     DP_TransientAnnotation **pp =
         DP_queue_push(&c->annotations, sizeof(DP_TransientAnnotation *));
     *pp = ta;
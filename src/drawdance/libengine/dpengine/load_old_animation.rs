@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 use drawdance::{
+    dp_error_set_panic,
     engine::{
         AttachedLayerPropsList, BaseCanvasState, BaseLayerList, BaseLayerProps, BaseLayerPropsList,
         CArc, CanvasState, DetachedCanvasState, LayerPropsList, Persister, TransientCanvasState,
@@ -7,11 +8,13 @@ use drawdance::{
         TransientLayerPropsList, TransientTimeline, TransientTrack,
     },
     DP_CanvasState, DP_DrawContext, DP_LoadResult, DP_TransientLayerProps, DP_TransientTrack,
-    DP_load_ora,
+    DP_load_ora, DP_LOAD_RESULT_INTERNAL_ERROR,
 };
 use std::{
     collections::HashSet,
-    ffi::{c_char, c_int, c_uint, c_void},
+    ffi::c_void,
+    os::raw::{c_char, c_int, c_uint},
+    panic::catch_unwind,
     ptr::null_mut,
 };
 
@@ -197,8 +200,12 @@ fn convert_animation(
     tcs.persist().leak()
 }
 
+/// # Safety
+///
+/// `dc` must point to a draw context and `path` to a nul-terminated string.
+/// `out_result` must be null or point to writable memory.
 #[no_mangle]
-pub extern "C" fn DP_load_old_animation(
+pub unsafe extern "C" fn DP_load_old_animation(
     dc: *mut DP_DrawContext,
     path: *const c_char,
     hold_time: c_int,
@@ -208,7 +215,7 @@ pub extern "C" fn DP_load_old_animation(
     set_track_title: LoadOldAnimationSetTrackTitleFn,
     out_result: *mut DP_LoadResult,
 ) -> *mut DP_CanvasState {
-    match load_ora(dc, path, flags, out_result) {
+    let result = catch_unwind(|| match load_ora(dc, path, flags, out_result) {
         (Some(cs), fixed_layer_ids) => convert_animation(
             &cs,
             dc,
@@ -219,5 +226,15 @@ pub extern "C" fn DP_load_old_animation(
             fixed_layer_ids,
         ),
         (None, _) => null_mut(),
+    });
+    match result {
+        Ok(cs) => cs,
+        Err(payload) => {
+            dp_error_set_panic("loading old animation", payload.as_ref());
+            if let Some(r) = unsafe { out_result.as_mut() } {
+                *r = DP_LOAD_RESULT_INTERNAL_ERROR;
+            }
+            null_mut()
+        }
     }
 }
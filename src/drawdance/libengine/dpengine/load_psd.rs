@@ -34,8 +34,12 @@ fn decode_utf16be(tlp: *mut DP_TransientLayerProps, be: &[u16]) {
     unsafe { DP_transient_layer_props_title_set(tlp, bytes.as_ptr().cast(), bytes.len()) };
 }
 
+/// # Safety
+///
+/// `tlp` must point to transient layer props and `be` must be null or point to
+/// a zero-terminated UTF-16 string.
 #[no_mangle]
-pub extern "C" fn DP_psd_read_utf16be_layer_title(
+pub unsafe extern "C" fn DP_psd_read_utf16be_layer_title(
     tlp: *mut DP_TransientLayerProps,
     be: *const u16,
 ) -> bool {
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: MIT
+#include "memory_usage.h"
+#include "canvas_state.h"
+#include "layer_content.h"
+#include "layer_group.h"
+#include "layer_list.h"
+#include "layer_props.h"
+#include "layer_props_list.h"
+#include "tile.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/vector.h>
+#include <uthash_inc.h>
+
+
+typedef struct DP_MemoryUsageTile {
+    DP_Tile *tile;
+    UT_hash_handle hh;
+} DP_MemoryUsageTile;
+
+struct DP_MemoryUsage {
+    size_t tile_size;
+    DP_MemoryUsageTile *tiles;
+    DP_MemoryUsageCounts totals;
+    DP_Vector layers;
+};
+
+
+DP_MemoryUsage *DP_memory_usage_new(void)
+{
+    DP_MemoryUsage *mu = DP_malloc(sizeof(*mu));
+    *mu = (DP_MemoryUsage){DP_tile_memory_usage().tile_size, NULL,
+                           {0, 0, 0, 0, 0, 0, 0}, DP_VECTOR_NULL};
+    DP_VECTOR_INIT_TYPE(&mu->layers, DP_LayerMemoryUsage, 8);
+    return mu;
+}
+
+void DP_memory_usage_free(DP_MemoryUsage *mu)
+{
+    if (mu) {
+        DP_MemoryUsageTile *mut, *tmp;
+        HASH_ITER(hh, mu->tiles, mut, tmp) {
+            HASH_DEL(mu->tiles, mut);
+            DP_free(mut);
+        }
+        DP_vector_dispose(&mu->layers);
+        DP_free(mu);
+    }
+}
+
+
+static bool is_layer_id(void *element, void *user)
+{
+    return ((DP_LayerMemoryUsage *)element)->layer_id == *(int *)user;
+}
+
+static int search_layer_index(DP_MemoryUsage *mu, int layer_id)
+{
+    return DP_VECTOR_SEARCH_INDEX_TYPE(&mu->layers, DP_LayerMemoryUsage,
+                                       is_layer_id, &layer_id);
+}
+
+static DP_MemoryUsageCounts *get_layer_counts(DP_MemoryUsage *mu,
+                                              int layer_id)
+{
+    int index = search_layer_index(mu, layer_id);
+    DP_LayerMemoryUsage *lmu;
+    if (index < 0) {
+        lmu = DP_vector_push(&mu->layers, sizeof(*lmu));
+        *lmu = (DP_LayerMemoryUsage){layer_id, {0, 0, 0, 0, 0, 0, 0}};
+    }
+    else {
+        lmu = &DP_VECTOR_AT_TYPE(&mu->layers, DP_LayerMemoryUsage, index);
+    }
+    return &lmu->counts;
+}
+
+static void count_tile(DP_MemoryUsage *mu, DP_MemoryUsageCounts *counts,
+                       DP_Tile *t_or_null)
+{
+    DP_MemoryUsageCounts *totals = &mu->totals;
+    if (!t_or_null || DP_tile_blank(t_or_null)) {
+        ++counts->blank_tile_count;
+        ++totals->blank_tile_count;
+    }
+
+    if (t_or_null) {
+        ++counts->tile_count;
+        ++totals->tile_count;
+        DP_MemoryUsageTile *mut;
+        HASH_FIND_PTR(mu->tiles, &t_or_null, mut);
+        if (mut) {
+            ++counts->shared_tile_count;
+            ++totals->shared_tile_count;
+        }
+        else {
+            mut = DP_malloc(sizeof(*mut));
+            mut->tile = t_or_null;
+            HASH_ADD_PTR(mu->tiles, tile, mut);
+            ++counts->unique_tile_count;
+            ++totals->unique_tile_count;
+            size_t packed_bytes = DP_tile_packed_size(t_or_null);
+            if (packed_bytes != 0) {
+                ++counts->packed_tile_count;
+                ++totals->packed_tile_count;
+                counts->packed_bytes += packed_bytes;
+                totals->packed_bytes += packed_bytes;
+            }
+            size_t bytes = packed_bytes
+                         + (DP_tile_resident(t_or_null) ? mu->tile_size : 0);
+            counts->unique_bytes += bytes;
+            totals->unique_bytes += bytes;
+        }
+    }
+}
+
+static void count_layer_content(DP_MemoryUsage *mu,
+                                DP_MemoryUsageCounts *counts,
+                                DP_LayerContent *lc)
+{
+    DP_TileCounts tile_counts = DP_tile_counts_round(
+        DP_layer_content_width(lc), DP_layer_content_height(lc));
+    for (int y = 0; y < tile_counts.y; ++y) {
+        for (int x = 0; x < tile_counts.x; ++x) {
+            count_tile(mu, counts, DP_layer_content_tile_at_noinc(lc, x, y));
+        }
+    }
+
+    DP_LayerList *sub_ll = DP_layer_content_sub_contents_noinc(lc);
+    int sub_count = DP_layer_list_count(sub_ll);
+    for (int i = 0; i < sub_count; ++i) {
+        DP_LayerListEntry *sub_lle = DP_layer_list_at_noinc(sub_ll, i);
+        count_layer_content(mu, counts,
+                            DP_layer_list_entry_content_noinc(sub_lle));
+    }
+}
+
+static void count_layer_list(DP_MemoryUsage *mu, DP_LayerList *ll,
+                             DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_list_count(ll);
+    DP_ASSERT(DP_layer_props_list_count(lpl) == count);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerListEntry *lle = DP_layer_list_at_noinc(ll, i);
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        if (DP_layer_list_entry_is_group(lle)) {
+            count_layer_list(mu,
+                             DP_layer_group_children_noinc(
+                                 DP_layer_list_entry_group_noinc(lle)),
+                             DP_layer_props_children_noinc(lp));
+        }
+        else {
+            DP_MemoryUsageCounts *counts =
+                get_layer_counts(mu, DP_layer_props_id(lp));
+            count_layer_content(mu, counts,
+                                DP_layer_list_entry_content_noinc(lle));
+        }
+    }
+}
+
+void DP_memory_usage_add_canvas_state(DP_MemoryUsage *mu, DP_CanvasState *cs)
+{
+    DP_ASSERT(mu);
+    DP_ASSERT(cs);
+    DP_Tile *background_tile = DP_canvas_state_background_tile_noinc(cs);
+    if (background_tile) {
+        // Doesn't belong to any layer, so only count it towards the totals.
+        DP_MemoryUsageCounts background_counts = {0, 0, 0, 0, 0, 0, 0};
+        count_tile(mu, &background_counts, background_tile);
+    }
+    count_layer_list(mu, DP_canvas_state_layers_noinc(cs),
+                     DP_canvas_state_layer_props_noinc(cs));
+}
+
+
+DP_MemoryUsageCounts DP_memory_usage_totals(DP_MemoryUsage *mu)
+{
+    DP_ASSERT(mu);
+    return mu->totals;
+}
+
+int DP_memory_usage_layer_count(DP_MemoryUsage *mu)
+{
+    DP_ASSERT(mu);
+    return DP_size_to_int(mu->layers.used);
+}
+
+const DP_LayerMemoryUsage *DP_memory_usage_layer_at(DP_MemoryUsage *mu,
+                                                    int index)
+{
+    DP_ASSERT(mu);
+    DP_ASSERT(index >= 0);
+    DP_ASSERT(index < DP_memory_usage_layer_count(mu));
+    return &DP_VECTOR_AT_TYPE(&mu->layers, DP_LayerMemoryUsage, index);
+}
+
+const DP_LayerMemoryUsage *DP_memory_usage_layer_search(DP_MemoryUsage *mu,
+                                                        int layer_id)
+{
+    DP_ASSERT(mu);
+    int index = search_layer_index(mu, layer_id);
+    return index < 0 ? NULL : DP_memory_usage_layer_at(mu, index);
+}
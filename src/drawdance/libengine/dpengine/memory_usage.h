@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_MEMORY_USAGE_H
+#define DPENGINE_MEMORY_USAGE_H
+#include <dpcommon/common.h>
+
+typedef struct DP_CanvasState DP_CanvasState;
+
+
+typedef struct DP_MemoryUsage DP_MemoryUsage;
+
+typedef struct DP_MemoryUsageCounts {
+    // Bytes of tiles that weren't already counted before. For packed tiles,
+    // that's their compressed size plus their pixels if they're unpacked.
+    size_t unique_bytes;
+    // Tile references encountered, not counting empty tile positions.
+    int tile_count;
+    // Tiles that were encountered for the first time.
+    int unique_tile_count;
+    // References to tiles that were already counted, e.g. from a duplicated
+    // layer or from a savepoint that shares its tiles with the current state.
+    int shared_tile_count;
+    // Empty tile positions and tiles that are entirely transparent.
+    int blank_tile_count;
+    // The compressed part of the unique bytes, the rest is resident pixels.
+    size_t packed_bytes;
+    // Unique tiles that are packed, whether they've been unpacked or not.
+    int packed_tile_count;
+} DP_MemoryUsageCounts;
+
+typedef struct DP_LayerMemoryUsage {
+    int layer_id;
+    DP_MemoryUsageCounts counts;
+} DP_LayerMemoryUsage;
+
+
+// Accumulates the memory used by the tiles of canvas states. Tiles are
+// refcounted and shared between states, layers and savepoints, so each one is
+// only counted as unique the first time it's encountered, by its address. The
+// counts are plain values, so they can be copied out and displayed as-is.
+DP_MemoryUsage *DP_memory_usage_new(void);
+
+void DP_memory_usage_free(DP_MemoryUsage *mu);
+
+// Adds the tiles of the given canvas state. Sublayers are attributed to the
+// layer they're on and the background tile only counts towards the totals.
+// When adding multiple canvas states, the per-layer counts are merged by id.
+void DP_memory_usage_add_canvas_state(DP_MemoryUsage *mu, DP_CanvasState *cs);
+
+DP_MemoryUsageCounts DP_memory_usage_totals(DP_MemoryUsage *mu);
+
+int DP_memory_usage_layer_count(DP_MemoryUsage *mu);
+
+const DP_LayerMemoryUsage *DP_memory_usage_layer_at(DP_MemoryUsage *mu,
+                                                    int index);
+
+// Returns NULL if there's no layer with the given id.
+const DP_LayerMemoryUsage *DP_memory_usage_layer_search(DP_MemoryUsage *mu,
+                                                        int layer_id);
+
+
+#endif
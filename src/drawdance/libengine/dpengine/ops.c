@@ -25,6 +25,7 @@
 #include "canvas_state.h"
 #include "document_metadata.h"
 #include "draw_context.h"
+#include "gradient.h"
 #include "image.h"
 #include "key_frame.h"
 #include "layer_content.h"
@@ -42,6 +43,9 @@
 #include <dpcommon/conversions.h>
 #include <dpcommon/geom.h>
 #include <dpmsg/blend_mode.h>
+#include <math.h>
+
+#define RADIANS_PER_DEGREE (3.14159265358979323846 / 180.0)
 
 
 DP_CanvasState *DP_ops_canvas_resize(DP_CanvasState *cs,
@@ -70,16 +74,342 @@ DP_CanvasState *DP_ops_canvas_resize(DP_CanvasState *cs,
     DP_transient_canvas_state_height_set(tcs, height);
     DP_transient_canvas_state_offsets_add(tcs, -left, -top);
 
+    DP_LayerList *ll = DP_transient_canvas_state_layers_noinc(tcs);
+    if (DP_layer_list_count(ll) > 0) {
+        DP_LayerPropsList *lpl =
+            DP_transient_canvas_state_layer_props_noinc(tcs);
+        DP_TransientLayerList *tll = DP_layer_list_resize(
+            ll, lpl, context_id, top, right, bottom, left);
+        DP_transient_canvas_state_transient_layers_set_noinc(tcs, tll);
+    }
+
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+
+static DP_Tile *rotate_background_tile(DP_Tile *tile, unsigned int context_id,
+                                       int quarter_turns)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(context_id);
+    int last = DP_TILE_SIZE - 1;
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            DP_Pixel15 pixel;
+            switch (quarter_turns) {
+            case 1:
+                pixel = DP_tile_pixel_at(tile, y, last - x);
+                break;
+            case 2:
+                pixel = DP_tile_pixel_at(tile, last - x, last - y);
+                break;
+            default:
+                pixel = DP_tile_pixel_at(tile, last - y, x);
+                break;
+            }
+            DP_transient_tile_pixel_at_set(tt, x, y, pixel);
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+static void rotate_annotations(DP_TransientCanvasState *tcs, int width,
+                               int height, int quarter_turns)
+{
+    DP_TransientAnnotationList *tal =
+        DP_transient_canvas_state_transient_annotations(tcs, 0);
+    int count = DP_transient_annotation_list_count(tal);
+    for (int i = 0; i < count; ++i) {
+        DP_TransientAnnotation *ta =
+            DP_transient_annotation_list_transient_at_noinc(tal, i);
+        int x = DP_transient_annotation_x(ta);
+        int y = DP_transient_annotation_y(ta);
+        int w = DP_transient_annotation_width(ta);
+        int h = DP_transient_annotation_height(ta);
+        switch (quarter_turns) {
+        case 1:
+            DP_transient_annotation_x_set(ta, height - y - h);
+            DP_transient_annotation_y_set(ta, x);
+            break;
+        case 2:
+            DP_transient_annotation_x_set(ta, width - x - w);
+            DP_transient_annotation_y_set(ta, height - y - h);
+            break;
+        default:
+            DP_transient_annotation_x_set(ta, y);
+            DP_transient_annotation_y_set(ta, width - x - w);
+            break;
+        }
+        if (quarter_turns != 2) {
+            DP_transient_annotation_width_set(ta, h);
+            DP_transient_annotation_height_set(ta, w);
+        }
+    }
+}
+
+static DP_CanvasState *rotate_canvas(DP_CanvasState *cs,
+                                     unsigned int context_id,
+                                     int quarter_turns)
+{
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    DP_debug("Rotate: %d quarter turns", quarter_turns);
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    if (quarter_turns != 2) {
+        DP_transient_canvas_state_width_set(tcs, height);
+        DP_transient_canvas_state_height_set(tcs, width);
+    }
+
+    DP_Tile *background_tile = DP_canvas_state_background_tile_noinc(cs);
+    if (background_tile) {
+        DP_transient_canvas_state_background_tile_set_noinc(
+            tcs,
+            rotate_background_tile(background_tile, context_id, quarter_turns),
+            DP_canvas_state_background_opaque(cs));
+    }
+
+    DP_LayerList *ll = DP_transient_canvas_state_layers_noinc(tcs);
+    if (DP_layer_list_count(ll) > 0) {
+        DP_TransientLayerList *tll =
+            DP_layer_list_rotate(ll, context_id, quarter_turns);
+        DP_transient_canvas_state_transient_layers_set_noinc(tcs, tll);
+    }
+
+    if (DP_annotation_list_count(DP_canvas_state_annotations_noinc(cs)) > 0) {
+        rotate_annotations(tcs, width, height, quarter_turns);
+    }
+
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+DP_CanvasState *DP_ops_canvas_rotate90(DP_CanvasState *cs,
+                                       unsigned int context_id, bool clockwise)
+{
+    return rotate_canvas(cs, context_id, clockwise ? 1 : 3);
+}
+
+DP_CanvasState *DP_ops_canvas_rotate180(DP_CanvasState *cs,
+                                        unsigned int context_id)
+{
+    return rotate_canvas(cs, context_id, 2);
+}
+
+static DP_Tile *flip_background_tile(DP_Tile *tile, unsigned int context_id,
+                                     bool horizontal)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(context_id);
+    int last = DP_TILE_SIZE - 1;
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            DP_Pixel15 pixel = horizontal
+                                 ? DP_tile_pixel_at(tile, last - x, y)
+                                 : DP_tile_pixel_at(tile, x, last - y);
+            DP_transient_tile_pixel_at_set(tt, x, y, pixel);
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+static void flip_annotations(DP_TransientCanvasState *tcs, int width,
+                             int height, bool horizontal)
+{
+    DP_TransientAnnotationList *tal =
+        DP_transient_canvas_state_transient_annotations(tcs, 0);
+    int count = DP_transient_annotation_list_count(tal);
+    for (int i = 0; i < count; ++i) {
+        DP_TransientAnnotation *ta =
+            DP_transient_annotation_list_transient_at_noinc(tal, i);
+        if (horizontal) {
+            DP_transient_annotation_x_set(
+                ta, width - DP_transient_annotation_x(ta)
+                        - DP_transient_annotation_width(ta));
+        }
+        else {
+            DP_transient_annotation_y_set(
+                ta, height - DP_transient_annotation_y(ta)
+                        - DP_transient_annotation_height(ta));
+        }
+    }
+}
+
+DP_CanvasState *DP_ops_canvas_flip(DP_CanvasState *cs, unsigned int context_id,
+                                   bool horizontal)
+{
+    DP_debug("Flip: %s", horizontal ? "horizontal" : "vertical");
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+
+    DP_Tile *background_tile = DP_canvas_state_background_tile_noinc(cs);
+    if (background_tile) {
+        DP_transient_canvas_state_background_tile_set_noinc(
+            tcs, flip_background_tile(background_tile, context_id, horizontal),
+            DP_canvas_state_background_opaque(cs));
+    }
+
     DP_LayerList *ll = DP_transient_canvas_state_layers_noinc(tcs);
     if (DP_layer_list_count(ll) > 0) {
         DP_TransientLayerList *tll =
-            DP_layer_list_resize(ll, context_id, top, right, bottom, left);
+            DP_layer_list_flip(ll, context_id, horizontal);
         DP_transient_canvas_state_transient_layers_set_noinc(tcs, tll);
     }
 
+    if (DP_annotation_list_count(DP_canvas_state_annotations_noinc(cs)) > 0) {
+        flip_annotations(tcs, DP_canvas_state_width(cs),
+                         DP_canvas_state_height(cs), horizontal);
+    }
+
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+DP_CanvasState *DP_ops_canvas_offset_wrap(DP_CanvasState *cs,
+                                          unsigned int context_id, int offset_x,
+                                          int offset_y)
+{
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    DP_LayerList *ll = DP_canvas_state_layers_noinc(cs);
+    if (width <= 0 || height <= 0 || DP_layer_list_count(ll) == 0
+        || (offset_x % width == 0 && offset_y % height == 0)) {
+        DP_debug("Offset wrap: nothing to do");
+        return DP_canvas_state_incref(cs);
+    }
+
+    DP_debug("Offset wrap: %d, %d", offset_x, offset_y);
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_transient_canvas_state_transient_layers_set_noinc(
+        tcs, DP_layer_list_offset_wrap(ll, context_id, offset_x, offset_y));
     return DP_transient_canvas_state_persist(tcs);
 }
 
+static void rotate_annotation_centers(DP_TransientCanvasState *tcs,
+                                      const DP_Transform *tf)
+{
+    DP_TransientAnnotationList *tal =
+        DP_transient_canvas_state_transient_annotations(tcs, 0);
+    int count = DP_transient_annotation_list_count(tal);
+    for (int i = 0; i < count; ++i) {
+        DP_TransientAnnotation *ta =
+            DP_transient_annotation_list_transient_at_noinc(tal, i);
+        double half_width =
+            DP_int_to_double(DP_transient_annotation_width(ta)) / 2.0;
+        double half_height =
+            DP_int_to_double(DP_transient_annotation_height(ta)) / 2.0;
+        DP_Vec2 center = DP_transform_xy(
+            *tf, DP_int_to_double(DP_transient_annotation_x(ta)) + half_width,
+            DP_int_to_double(DP_transient_annotation_y(ta)) + half_height);
+        DP_transient_annotation_x_set(
+            ta, DP_double_to_int(round(center.x - half_width)));
+        DP_transient_annotation_y_set(
+            ta, DP_double_to_int(round(center.y - half_height)));
+    }
+}
+
+static DP_CanvasState *rotate_canvas_resampled(DP_CanvasState *cs,
+                                               DP_DrawContext *dc,
+                                               unsigned int context_id,
+                                               double degrees,
+                                               int interpolation, bool expand)
+{
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    double radians = degrees * RADIANS_PER_DEGREE;
+    int new_width, new_height;
+    if (expand) {
+        // A tiny bit of leeway so that rounding errors in the sine and cosine
+        // don't add an extra row or column of pixels.
+        double c = fabs(cos(radians));
+        double s = fabs(sin(radians));
+        double w = DP_int_to_double(width);
+        double h = DP_int_to_double(height);
+        new_width = DP_double_to_int(ceil(w * c + h * s - 0.001));
+        new_height = DP_double_to_int(ceil(w * s + h * c - 0.001));
+        if (new_width > INT16_MAX || new_height > INT16_MAX) {
+            DP_error_set("Invalid rotation: %dx%d", new_width, new_height);
+            return NULL;
+        }
+    }
+    else {
+        new_width = width;
+        new_height = height;
+    }
+
+    DP_debug("Rotate: %f degrees, width %d, height %d", degrees, new_width,
+             new_height);
+    DP_Transform tf = DP_transform_translate(
+        DP_transform_rotate(
+            DP_transform_translation(DP_int_to_double(width) / -2.0,
+                                     DP_int_to_double(height) / -2.0),
+            radians),
+        DP_int_to_double(new_width) / 2.0, DP_int_to_double(new_height) / 2.0);
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_transient_canvas_state_width_set(tcs, new_width);
+    DP_transient_canvas_state_height_set(tcs, new_height);
+
+    DP_LayerList *ll = DP_transient_canvas_state_layers_noinc(tcs);
+    if (DP_layer_list_count(ll) > 0) {
+        DP_LayerPropsList *lpl =
+            DP_transient_canvas_state_layer_props_noinc(tcs);
+        DP_TransientLayerList *tll =
+            DP_layer_list_transform(ll, lpl, dc, context_id, &tf, new_width,
+                                    new_height, interpolation);
+        if (!tll) {
+            DP_transient_canvas_state_decref(tcs);
+            return NULL;
+        }
+        DP_transient_canvas_state_transient_layers_set_noinc(tcs, tll);
+    }
+
+    if (DP_annotation_list_count(DP_canvas_state_annotations_noinc(cs)) > 0) {
+        rotate_annotation_centers(tcs, &tf);
+    }
+
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+DP_CanvasState *DP_ops_canvas_rotate(DP_CanvasState *cs, DP_DrawContext *dc,
+                                     unsigned int context_id, double degrees,
+                                     int interpolation, bool expand,
+                                     int *out_width, int *out_height)
+{
+    if (!isfinite(degrees)) {
+        DP_error_set("Invalid rotation angle");
+        return NULL;
+    }
+
+    double normalized = fmod(degrees, 360.0);
+    if (normalized < 0.0) {
+        normalized += 360.0;
+    }
+
+    // Right angles can be done losslessly. Quarter turns of canvases that
+    // aren't square change the size though, so only if expanding is allowed.
+    DP_CanvasState *result;
+    bool square = DP_canvas_state_width(cs) == DP_canvas_state_height(cs);
+    if (normalized == 0.0) {
+        result = DP_canvas_state_incref(cs);
+    }
+    else if (normalized == 180.0) {
+        result = rotate_canvas(cs, context_id, 2);
+    }
+    else if ((normalized == 90.0 || normalized == 270.0)
+             && (expand || square)) {
+        result = rotate_canvas(cs, context_id, normalized == 90.0 ? 1 : 3);
+    }
+    else {
+        result = rotate_canvas_resampled(cs, dc, context_id, normalized,
+                                         interpolation, expand);
+    }
+
+    if (result) {
+        if (out_width) {
+            *out_width = DP_canvas_state_width(result);
+        }
+        if (out_height) {
+            *out_height = DP_canvas_state_height(result);
+        }
+    }
+    return result;
+}
+
 
 static void mark_used_layer_ids(DP_DrawContext *dc, int masked_id,
                                 DP_LayerPropsList *lpl)
@@ -311,7 +641,8 @@ static DP_TransientLayerProps *get_layer_props(DP_TransientCanvasState *tcs,
 DP_CanvasState *DP_ops_layer_attributes(DP_CanvasState *cs, int layer_id,
                                         int sublayer_id, uint16_t opacity,
                                         int blend_mode, bool censored,
-                                        bool isolated)
+                                        bool isolated, bool alpha_lock,
+                                        bool fixed)
 {
     DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
     DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
@@ -333,6 +664,8 @@ DP_CanvasState *DP_ops_layer_attributes(DP_CanvasState *cs, int layer_id,
     DP_transient_layer_props_blend_mode_set(tlp, blend_mode);
     DP_transient_layer_props_censored_set(tlp, censored);
     DP_transient_layer_props_isolated_set(tlp, isolated);
+    DP_transient_layer_props_alpha_lock_set(tlp, alpha_lock);
+    DP_transient_layer_props_fixed_set(tlp, fixed);
 
     return DP_transient_canvas_state_persist(tcs);
 }
@@ -593,6 +926,56 @@ DP_CanvasState *DP_ops_layer_retitle(DP_CanvasState *cs, int layer_id,
 }
 
 
+// Returns the length of the NUL-terminated string at the start of the buffer,
+// or the whole remaining size if the terminator is missing.
+static size_t metadata_string_length(const unsigned char *metadata,
+                                     size_t size)
+{
+    const unsigned char *end = memchr(metadata, '\0', size);
+    return end ? (size_t)(end - metadata) : size;
+}
+
+DP_CanvasState *DP_ops_layer_metadata(DP_CanvasState *cs, int layer_id,
+                                      int color_tag,
+                                      const unsigned char *metadata,
+                                      size_t metadata_size)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    if (!lre) {
+        DP_error_set("Layer metadata: id %d not found", layer_id);
+        return NULL;
+    }
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_TransientLayerProps *tlp = get_layer_props(tcs, lre, 0);
+    DP_transient_layer_props_color_tag_set(tlp, color_tag);
+    DP_transient_layer_props_metadata_clear(tlp);
+
+    size_t pos = 0;
+    while (pos < metadata_size) {
+        const char *key = (const char *)metadata + pos;
+        size_t key_length =
+            metadata_string_length(metadata + pos, metadata_size - pos);
+        pos += key_length + 1;
+        if (pos >= metadata_size) {
+            break; // Trailing key without a value.
+        }
+
+        const char *value = (const char *)metadata + pos;
+        size_t value_length =
+            metadata_string_length(metadata + pos, metadata_size - pos);
+        pos += value_length + 1;
+        if (key_length != 0 && value_length != 0) {
+            DP_transient_layer_props_metadata_set(tlp, key, key_length, value,
+                                                  value_length);
+        }
+    }
+
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+
 DP_CanvasState *DP_ops_layer_delete(DP_CanvasState *cs, DP_DrawContext *dc,
                                     unsigned int context_id, int layer_id,
                                     bool merge)
@@ -652,6 +1035,8 @@ static void delete_layer(DP_TransientCanvasState *tcs, unsigned int context_id,
     DP_LayerProps *delete_lp =
         DP_transient_layer_props_list_at_noinc(delete_tlpl, delete_last_index);
 
+    // Merging ignores the target layer's alpha lock, the merged layer's
+    // content should end up on the target unchanged.
     if (merge_lre) {
         DP_TransientLayerContent *merge_tlc =
             DP_layer_routes_entry_transient_content(merge_lre, tcs);
@@ -748,6 +1133,17 @@ DP_CanvasState *DP_ops_layer_visibility(DP_CanvasState *cs, int layer_id,
 }
 
 
+// Layers with alpha lock enabled only allow blend modes that don't change the
+// alpha channel. Returns a negative value if the operation should be skipped.
+static int layer_blend_mode(DP_CanvasState *cs, DP_LayerRoutesEntry *lre,
+                            int blend_mode)
+{
+    DP_LayerProps *lp = DP_layer_routes_entry_props(lre, cs);
+    return DP_layer_props_alpha_lock(lp)
+             ? DP_blend_mode_to_alpha_preserving(blend_mode)
+             : blend_mode;
+}
+
 DP_CanvasState *DP_ops_put_image(DP_CanvasState *cs,
                                  DP_UserCursors *ucs_or_null,
                                  unsigned int context_id, int layer_id,
@@ -778,11 +1174,17 @@ DP_CanvasState *DP_ops_put_image(DP_CanvasState *cs,
                              y + height / 2);
     }
 
+    int effective_blend_mode = layer_blend_mode(cs, lre, blend_mode);
+    if (effective_blend_mode < 0) {
+        DP_image_free(img);
+        return DP_canvas_state_incref(cs);
+    }
+
     DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
     DP_TransientLayerContent *tlc =
         DP_layer_routes_entry_transient_content(lre, tcs);
-    DP_transient_layer_content_put_image(tlc, context_id, blend_mode, x, y,
-                                         img);
+    DP_transient_layer_content_put_image(tlc, context_id, effective_blend_mode,
+                                         x, y, img);
     DP_image_free(img);
     return DP_transient_canvas_state_persist(tcs);
 }
@@ -967,11 +1369,87 @@ DP_CanvasState *DP_ops_fill_rect(DP_CanvasState *cs,
                              (left + right) / 2, (top + bottom) / 2);
     }
 
+    int effective_blend_mode = layer_blend_mode(cs, lre, blend_mode);
+    if (effective_blend_mode < 0) {
+        return DP_canvas_state_incref(cs);
+    }
+
     DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
     DP_TransientLayerContent *tlc =
         DP_layer_routes_entry_transient_content(lre, tcs);
-    DP_transient_layer_content_fill_rect(tlc, context_id, blend_mode, left, top,
-                                         right, bottom, pixel);
+    DP_transient_layer_content_fill_rect(tlc, context_id, effective_blend_mode,
+                                         left, top, right, bottom, pixel);
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+
+DP_CanvasState *DP_ops_fill_gradient(DP_CanvasState *cs,
+                                     DP_UserCursors *ucs_or_null,
+                                     unsigned int context_id, int layer_id,
+                                     int blend_mode,
+                                     const DP_Gradient *gradient,
+                                     const DP_Rect *rect)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    if (!lre) {
+        DP_error_set("Fill gradient: id %d not found", layer_id);
+        return NULL;
+    }
+    else if (DP_layer_routes_entry_is_group(lre)) {
+        DP_error_set("Fill gradient: id %d is a group", layer_id);
+        return NULL;
+    }
+
+    if (ucs_or_null) {
+        DP_user_cursors_activate(ucs_or_null, context_id);
+        DP_user_cursors_move(ucs_or_null, context_id, layer_id,
+                             (rect->x1 + rect->x2) / 2,
+                             (rect->y1 + rect->y2) / 2);
+    }
+
+    int effective_blend_mode = layer_blend_mode(cs, lre, blend_mode);
+    if (effective_blend_mode < 0) {
+        return DP_canvas_state_incref(cs);
+    }
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_TransientLayerContent *tlc =
+        DP_layer_routes_entry_transient_content(lre, tcs);
+    DP_gradient_fill(tlc, context_id, effective_blend_mode, gradient, rect,
+                     NULL);
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+DP_CanvasState *DP_ops_filter_region(
+    DP_CanvasState *cs, DP_UserCursors *ucs_or_null, unsigned int context_id,
+    int layer_id, const DP_Rect *rect,
+    void (*apply)(void *, DP_TransientLayerContent *, unsigned int,
+                  const DP_Rect *),
+    void *user)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    if (!lre) {
+        DP_error_set("Filter region: id %d not found", layer_id);
+        return NULL;
+    }
+    else if (DP_layer_routes_entry_is_group(lre)) {
+        DP_error_set("Filter region: id %d is a group", layer_id);
+        return NULL;
+    }
+
+    if (ucs_or_null) {
+        DP_user_cursors_activate(ucs_or_null, context_id);
+        DP_user_cursors_move(ucs_or_null, context_id, layer_id,
+                             (rect->x1 + rect->x2) / 2,
+                             (rect->y1 + rect->y2) / 2);
+    }
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_TransientLayerContent *tlc =
+        DP_layer_routes_entry_transient_content(lre, tcs);
+    apply(user, tlc, context_id, rect);
     return DP_transient_canvas_state_persist(tcs);
 }
 
@@ -1147,8 +1625,8 @@ DP_CanvasState *DP_ops_annotation_reshape(DP_CanvasState *cs, int annotation_id,
 
 DP_CanvasState *DP_ops_annotation_edit(DP_CanvasState *cs, int annotation_id,
                                        uint32_t background_color, bool protect,
-                                       int valign, const char *text,
-                                       size_t text_length)
+                                       int valign, int border, bool auto_grow,
+                                       const char *text, size_t text_length)
 {
     DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
     int index = DP_annotation_list_index_by_id(al, annotation_id);
@@ -1166,6 +1644,8 @@ DP_CanvasState *DP_ops_annotation_edit(DP_CanvasState *cs, int annotation_id,
     DP_transient_annotation_background_color_set(ta, background_color);
     DP_transient_annotation_protect_set(ta, protect);
     DP_transient_annotation_valign_set(ta, valign);
+    DP_transient_annotation_border_set(ta, border);
+    DP_transient_annotation_auto_grow_set(ta, auto_grow);
     DP_transient_annotation_text_set(ta, text, text_length);
 
     return DP_transient_canvas_state_persist(tcs);
@@ -1188,6 +1668,30 @@ DP_CanvasState *DP_ops_annotation_delete(DP_CanvasState *cs, int annotation_id)
     return DP_transient_canvas_state_persist(tcs);
 }
 
+DP_CanvasState *DP_ops_annotation_order(DP_CanvasState *cs, int annotation_id,
+                                        int to_index)
+{
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
+    int index = DP_annotation_list_index_by_id(al, annotation_id);
+    if (index < 0) {
+        DP_error_set("Annotation order: id %d not found", annotation_id);
+        return NULL;
+    }
+
+    DP_ASSERT(to_index >= 0);
+    DP_ASSERT(to_index < DP_annotation_list_count(al));
+    if (index == to_index) {
+        return DP_canvas_state_incref(cs);
+    }
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_TransientAnnotationList *tal =
+        DP_transient_canvas_state_transient_annotations(tcs, 0);
+    DP_transient_annotation_list_move(tal, index, to_index);
+
+    return DP_transient_canvas_state_persist(tcs);
+}
+
 
 /*
 DP_CanvasStateChange DP_ops_draw_dabs(DP_CanvasState *cs, int sublayer_id,
@@ -1255,6 +1759,7 @@ DP_CanvasState *DP_ops_draw_dabs(DP_CanvasState *cs, DP_DrawContext *dc,
     DP_TransientLayerContent *sub_tlc = NULL;
     int last_layer_id = -1;
     int last_sublayer_id = -1;
+    bool alpha_lock = false;
 
     DP_PaintDrawDabsParams params;
     while (next(user, &params)) {
@@ -1275,6 +1780,8 @@ DP_CanvasState *DP_ops_draw_dabs(DP_CanvasState *cs, DP_DrawContext *dc,
             if (lre && !DP_layer_routes_entry_is_group(lre)) {
                 last_layer_id = layer_id;
                 last_sublayer_id = -1;
+                alpha_lock = DP_layer_props_alpha_lock(
+                    DP_layer_routes_entry_props(lre, cs));
                 if (!tcs) {
                     tcs = DP_transient_canvas_state_new(cs);
                 }
@@ -1286,6 +1793,14 @@ DP_CanvasState *DP_ops_draw_dabs(DP_CanvasState *cs, DP_DrawContext *dc,
             }
         }
 
+        if (alpha_lock) {
+            blend_mode = DP_blend_mode_to_alpha_preserving(blend_mode);
+            if (blend_mode < 0) {
+                continue;
+            }
+            params.blend_mode = blend_mode;
+        }
+
         DP_TransientLayerContent *target;
         if (params.indirect) {
             params.blend_mode = params.indirect_compat
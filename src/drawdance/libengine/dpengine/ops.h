@@ -26,6 +26,7 @@
 
 typedef struct DP_CanvasState DP_CanvasState;
 typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_Gradient DP_Gradient;
 typedef struct DP_Image DP_Image;
 typedef struct DP_KeyFrameLayer DP_KeyFrameLayer;
 typedef struct DP_PaintDrawDabsParams DP_PaintDrawDabsParams;
@@ -34,6 +35,12 @@ typedef struct DP_Rect DP_Rect;
 typedef struct DP_Tile DP_Tile;
 typedef struct DP_UserCursors DP_UserCursors;
 
+#ifdef DP_NO_STRICT_ALIASING
+typedef struct DP_TransientLayerContent DP_TransientLayerContent;
+#else
+typedef struct DP_LayerContent DP_TransientLayerContent;
+#endif
+
 struct DP_LayerOrderPair {
     int child_count;
     int layer_id;
@@ -44,6 +51,41 @@ DP_CanvasState *DP_ops_canvas_resize(DP_CanvasState *cs,
                                      unsigned int context_id, int top,
                                      int right, int bottom, int left);
 
+// Rotates the whole canvas by a right angle, which is lossless. Dimensions get
+// swapped for quarter turns, layers, the background tile and annotations get
+// moved along. The timeline and document metadata stay as they are.
+DP_CanvasState *DP_ops_canvas_rotate90(DP_CanvasState *cs,
+                                       unsigned int context_id, bool clockwise);
+
+DP_CanvasState *DP_ops_canvas_rotate180(DP_CanvasState *cs,
+                                        unsigned int context_id);
+
+// Mirrors the whole canvas left to right if horizontal is set, otherwise top
+// to bottom. Lossless like the right-angle rotations, flipping twice gives
+// back the original exactly.
+DP_CanvasState *DP_ops_canvas_flip(DP_CanvasState *cs, unsigned int context_id,
+                                   bool horizontal);
+
+// Shifts every layer by the given offset with wrap-around at the canvas edges,
+// for painting over the seams of tileable textures. The background, which
+// repeats anyway, and annotations stay where they are.
+DP_CanvasState *DP_ops_canvas_offset_wrap(DP_CanvasState *cs,
+                                          unsigned int context_id, int offset_x,
+                                          int offset_y);
+
+// Rotates the whole canvas clockwise around its center by an arbitrary angle
+// in degrees, resampling each layer with the given transform region mode. If
+// expand is set, the canvas grows or shrinks to fit the rotated bounds, the
+// new area is transparent and shows the background. Otherwise the canvas keeps
+// its size and the corners get cut off. Annotations stay axis-aligned, only
+// their centers get rotated. Angles that can be done losslessly go through the
+// right-angle rotations above. The new dimensions get written to the out
+// parameters if they're not NULL.
+DP_CanvasState *DP_ops_canvas_rotate(DP_CanvasState *cs, DP_DrawContext *dc,
+                                     unsigned int context_id, double degrees,
+                                     int interpolation, bool expand,
+                                     int *out_width, int *out_height);
+
 DP_CanvasState *
 DP_ops_layer_tree_create(DP_CanvasState *cs, DP_DrawContext *dc, int layer_id,
                          int source_id, int target_id, DP_Tile *tile, bool into,
@@ -52,7 +94,8 @@ DP_ops_layer_tree_create(DP_CanvasState *cs, DP_DrawContext *dc, int layer_id,
 DP_CanvasState *DP_ops_layer_attributes(DP_CanvasState *cs, int layer_id,
                                         int sublayer_id, uint16_t opacity,
                                         int blend_mode, bool censored,
-                                        bool isolated);
+                                        bool isolated, bool alpha_lock,
+                                        bool fixed);
 
 DP_CanvasState *DP_ops_layer_order(DP_CanvasState *cs, DP_DrawContext *dc,
                                    int layer_id_count,
@@ -66,6 +109,11 @@ DP_CanvasState *DP_ops_layer_tree_move(DP_CanvasState *cs, DP_DrawContext *dc,
 DP_CanvasState *DP_ops_layer_retitle(DP_CanvasState *cs, int layer_id,
                                      const char *title, size_t title_length);
 
+DP_CanvasState *DP_ops_layer_metadata(DP_CanvasState *cs, int layer_id,
+                                      int color_tag,
+                                      const unsigned char *metadata,
+                                      size_t metadata_size);
+
 DP_CanvasState *DP_ops_layer_delete(DP_CanvasState *cs, DP_DrawContext *dc,
                                     unsigned int context_id, int layer_id,
                                     bool merge);
@@ -103,6 +151,20 @@ DP_CanvasState *DP_ops_fill_rect(DP_CanvasState *cs,
                                  int blend_mode, int left, int top, int right,
                                  int bottom, DP_UPixel15 pixel);
 
+DP_CanvasState *DP_ops_fill_gradient(DP_CanvasState *cs,
+                                     DP_UserCursors *ucs_or_null,
+                                     unsigned int context_id, int layer_id,
+                                     int blend_mode,
+                                     const DP_Gradient *gradient,
+                                     const DP_Rect *rect);
+
+DP_CanvasState *DP_ops_filter_region(
+    DP_CanvasState *cs, DP_UserCursors *ucs_or_null, unsigned int context_id,
+    int layer_id, const DP_Rect *rect,
+    void (*apply)(void *, DP_TransientLayerContent *, unsigned int,
+                  const DP_Rect *),
+    void *user);
+
 DP_CanvasState *DP_ops_put_tile(DP_CanvasState *cs, DP_Tile *tile, int layer_id,
                                 int sublayer_id, int x, int y, int repeat);
 
@@ -118,11 +180,16 @@ DP_CanvasState *DP_ops_annotation_reshape(DP_CanvasState *cs, int annotation_id,
 
 DP_CanvasState *DP_ops_annotation_edit(DP_CanvasState *cs, int annotation_id,
                                        uint32_t background_color, bool protect,
-                                       int valign, const char *text,
-                                       size_t text_length);
+                                       int valign, int border, bool auto_grow,
+                                       const char *text, size_t text_length);
 
 DP_CanvasState *DP_ops_annotation_delete(DP_CanvasState *cs, int annotation_id);
 
+// Moves the annotation to the given index in the stacking order, 0 being the
+// bottom. The index must be within the annotation list.
+DP_CanvasState *DP_ops_annotation_order(DP_CanvasState *cs, int annotation_id,
+                                        int to_index);
+
 DP_CanvasState *DP_ops_draw_dabs(DP_CanvasState *cs, DP_DrawContext *dc,
                                  DP_UserCursors *ucs_or_null,
                                  bool (*next)(void *, DP_PaintDrawDabsParams *),
@@ -32,9 +32,12 @@
  *
  */
 #include "paint.h"
+#include "brush.h"
 #include "draw_context.h"
 #include "layer_content.h"
 #include "pixels.h"
+#include "selection.h"
+#include "stamp_mask.h"
 #include "user_cursors.h"
 #include <dpcommon/atomic.h>
 #include <dpcommon/common.h>
@@ -43,7 +46,7 @@
 #include <dpmsg/blend_mode.h>
 #include <dpmsg/message.h>
 #include <math.h>
-#include <helpers.h> // CLAMP
+#include <helpers.h> // CLAMP, M_PI
 
 
 // These "classic" brush stamps are based on GIMP, see license above.
@@ -57,37 +60,153 @@
 
 #define HIGH_RES_MASK_OPACITY (((double)DP_BIT15) / 4.0)
 
-static float *generate_classic_lut(int index)
+// The classic ramp falloff, 1 - (r^e)^2 for a distance r between 0 and 1.
+// Covers e / (e + 1) of the dab's area, the other falloffs match that.
+static double classic_ramp_exponent(int index)
 {
-    DP_debug("Generating classic dab lookup table for index %d", index);
-    float *cl = DP_malloc(sizeof(*cl) * CLASSIC_LUT_SIZE);
     double h = 1.0 - (index / 100.0);
-    double exponent = h < 0.0000004 ? 1000000.0 : 0.4 / h;
+    return h < 0.0000004 ? 1000000.0 : 0.4 / h;
+}
+
+static double classic_ramp_coverage(int index)
+{
+    double exponent = classic_ramp_exponent(index);
+    return exponent / (exponent + 1.0);
+}
+
+// Gaussian falloff exp(-r^2 / (2 * sigma^2)), clipped at r = 1.
+static double classic_gaussian_coverage(double sigma)
+{
+    double ss2 = 2.0 * sigma * sigma;
+    return ss2 * (1.0 - exp(-1.0 / ss2));
+}
+
+static double classic_gaussian_sigma(int index)
+{
+    double coverage = classic_ramp_coverage(index);
+    double lo = 0.001;
+    double hi = 1000.0;
+    for (int i = 0; i < 64; ++i) {
+        double mid = (lo + hi) / 2.0;
+        if (classic_gaussian_coverage(mid) < coverage) {
+            lo = mid;
+        }
+        else {
+            hi = mid;
+        }
+    }
+    return (lo + hi) / 2.0;
+}
+
+// Smoothstep falloff, fully opaque up to the core distance c, then a cosine
+// fade out to zero at r = 1.
+static double classic_smoothstep_coverage(double c)
+{
+    double w = 1.0 - c;
+    return c * c + w * c + w * w * (0.5 - 2.0 / (M_PI * M_PI));
+}
+
+static double classic_smoothstep_core(int index)
+{
+    double coverage = classic_ramp_coverage(index);
+    double lo = 0.0;
+    double hi = 1.0;
+    if (classic_smoothstep_coverage(lo) >= coverage) {
+        return lo; // Softest smoothstep is still a bit harder than the ramp.
+    }
+    for (int i = 0; i < 64; ++i) {
+        double mid = (lo + hi) / 2.0;
+        if (classic_smoothstep_coverage(mid) < coverage) {
+            lo = mid;
+        }
+        else {
+            hi = mid;
+        }
+    }
+    return (lo + hi) / 2.0;
+}
+
+static void generate_classic_ramp_lut(float *cl, int index)
+{
+    double exponent = classic_ramp_exponent(index);
     double radius = CLASSIC_LUT_RADIUS;
     for (int i = 0; i < CLASSIC_LUT_SIZE; ++i) {
         double d = 1.0 - pow(pow(sqrt(i) / radius, exponent), 2.0);
         cl[i] = DP_double_to_float(d);
     }
+}
+
+static void generate_classic_gaussian_lut(float *cl, int index)
+{
+    double sigma = classic_gaussian_sigma(index);
+    double ss2 = 2.0 * sigma * sigma;
+    double radius = CLASSIC_LUT_RADIUS;
+    for (int i = 0; i < CLASSIC_LUT_SIZE; ++i) {
+        double rr = i / (radius * radius);
+        cl[i] = DP_double_to_float(exp(-rr / ss2));
+    }
+}
+
+static void generate_classic_smoothstep_lut(float *cl, int index)
+{
+    double c = classic_smoothstep_core(index);
+    double w = 1.0 - c;
+    double radius = CLASSIC_LUT_RADIUS;
+    for (int i = 0; i < CLASSIC_LUT_SIZE; ++i) {
+        double r = sqrt(i) / radius;
+        double d = r <= c || w <= 0.0 ? 1.0
+                                       : 0.5 * (1.0 + cos(M_PI * (r - c) / w));
+        cl[i] = DP_double_to_float(d);
+    }
+}
+
+static float *generate_classic_lut(int falloff, int index)
+{
+    DP_debug("Generating classic dab lookup table for falloff %d index %d",
+             falloff, index);
+    float *cl = DP_malloc(sizeof(*cl) * CLASSIC_LUT_SIZE);
+    // At full hardness, all falloffs are the same hard circle.
+    if (index == CLASSIC_LUT_MAX_HARDNESS) {
+        generate_classic_ramp_lut(cl, index);
+    }
+    else {
+        switch (falloff) {
+        case DP_CLASSIC_BRUSH_FALLOFF_GAUSSIAN:
+            generate_classic_gaussian_lut(cl, index);
+            break;
+        case DP_CLASSIC_BRUSH_FALLOFF_SMOOTHSTEP:
+            generate_classic_smoothstep_lut(cl, index);
+            break;
+        default:
+            generate_classic_ramp_lut(cl, index);
+            break;
+        }
+    }
     return cl;
 }
 
-static const float *get_classic_lut(double hardness)
+static const float *get_classic_lut(int falloff, double hardness)
 {
     DP_ATOMIC_DECLARE_STATIC_SPIN_LOCK(lock);
-    static float *classic_luts[CLASSIC_LUT_COUNT];
+    static float *classic_luts[DP_CLASSIC_BRUSH_FALLOFF_COUNT]
+                              [CLASSIC_LUT_COUNT];
+    // Unknown falloffs, e.g. from a newer client, are drawn as the ramp.
+    if (falloff < 0 || falloff >= DP_CLASSIC_BRUSH_FALLOFF_COUNT) {
+        falloff = DP_CLASSIC_BRUSH_FALLOFF_RAMP;
+    }
     int index = DP_double_to_int(hardness * 100.0);
     DP_ASSERT(index >= CLASSIC_LUT_MIN_HARDNESS);
     DP_ASSERT(index <= CLASSIC_LUT_MAX_HARDNESS);
-    float *cl = classic_luts[index];
+    float *cl = classic_luts[falloff][index];
     if (cl) {
         return cl;
     }
     else {
         DP_atomic_lock(&lock);
-        cl = classic_luts[index];
+        cl = classic_luts[falloff][index];
         if (!cl) {
-            cl = generate_classic_lut(index);
-            classic_luts[index] = cl;
+            cl = generate_classic_lut(falloff, index);
+            classic_luts[falloff][index] = cl;
         }
         DP_atomic_unlock(&lock);
         return cl;
@@ -105,9 +224,43 @@ static DP_BrushStamp make_brush_stamp2(DP_DrawContext *dc)
     return (DP_BrushStamp){0, 0, 0, DP_draw_context_stamp_buffer2(dc)};
 }
 
+// Multiplies the stamp by the selection's coverage and puts the result into
+// the output stamp, which may be the same one. Returns the stamp that should
+// be drawn, or NULL if it lies entirely outside of the selection.
+static DP_BrushStamp *select_stamp(DP_Selection *sel_or_null,
+                                   DP_BrushStamp *stamp, DP_BrushStamp *out)
+{
+    if (!sel_or_null) {
+        return stamp;
+    }
+
+    int top = stamp->top;
+    int left = stamp->left;
+    int diameter = stamp->diameter;
+    if (!DP_rect_intersects(DP_rect_make(left, top, diameter, diameter),
+                            DP_selection_bounds(sel_or_null))) {
+        return NULL;
+    }
 
-static void prepare_stamp(DP_BrushStamp *stamp, double hardness, double radius,
-                          int diameter, const float **out_lut,
+    const uint16_t *src = stamp->data;
+    uint16_t *dst = out->data;
+    for (int y = 0; y < diameter; ++y) {
+        for (int x = 0; x < diameter; ++x) {
+            int i = y * diameter + x;
+            uint32_t coverage =
+                DP_selection_contains(sel_or_null, left + x, top + y);
+            dst[i] = DP_uint32_to_uint16((src[i] * coverage + 127u) / 255u);
+        }
+    }
+    out->top = top;
+    out->left = left;
+    out->diameter = diameter;
+    return out;
+}
+
+
+static void prepare_stamp(DP_BrushStamp *stamp, int falloff, double hardness,
+                          double radius, int diameter, const float **out_lut,
                           float *out_lut_scale)
 {
     DP_ASSERT(diameter <= DP_DRAW_CONTEXT_STAMP_MAX_DIAMETER);
@@ -118,12 +271,13 @@ static void prepare_stamp(DP_BrushStamp *stamp, double hardness, double radius,
     stamp->top = stamp_offsets;
     stamp->left = stamp_offsets;
 
-    *out_lut = get_classic_lut(hardness);
+    *out_lut = get_classic_lut(falloff, hardness);
     *out_lut_scale = DP_double_to_float(
         DP_square_double((CLASSIC_LUT_RADIUS - 1.0) / radius));
 }
 
-static void get_mask(DP_BrushStamp *stamp, double radius, double hardness)
+static void get_mask(DP_BrushStamp *stamp, int falloff, double radius,
+                     double hardness)
 {
     double r = radius / 2.0;
 
@@ -156,7 +310,7 @@ static void get_mask(DP_BrushStamp *stamp, double radius, double hardness)
 
         const float *lut;
         float lut_scale;
-        prepare_stamp(stamp, hardness, r, diameter, &lut, &lut_scale);
+        prepare_stamp(stamp, falloff, hardness, r, diameter, &lut, &lut_scale);
         uint16_t *d = stamp->data;
 
         for (int y = 0; y < diameter; ++y) {
@@ -174,7 +328,7 @@ static void get_mask(DP_BrushStamp *stamp, double radius, double hardness)
     }
 }
 
-static void get_high_res_mask(DP_BrushStamp *stamp, double radius,
+static void get_high_res_mask(DP_BrushStamp *stamp, int falloff, double radius,
                               double hardness)
 {
 
@@ -193,7 +347,8 @@ static void get_high_res_mask(DP_BrushStamp *stamp, double radius,
 
     const float *lut;
     float lut_scale;
-    prepare_stamp(stamp, hardness, radius, diameter, &lut, &lut_scale);
+    prepare_stamp(stamp, falloff, hardness, radius, diameter, &lut,
+                  &lut_scale);
     uint16_t *ptr = stamp->data;
 
     for (int y = 0; y < diameter; ++y) {
@@ -246,15 +401,15 @@ static void offset_mask(DP_BrushStamp *dst_stamp, DP_BrushStamp *src_stamp,
     }
 }
 
-static void get_classic_mask_stamp(DP_BrushStamp *stamp, double radius,
-                                   double hardness)
+static void get_classic_mask_stamp(DP_BrushStamp *stamp, int falloff,
+                                   double radius, double hardness)
 {
     // Don't bother with a high-resolution mask for large brushes.
     if (radius < 8.0) {
-        get_high_res_mask(stamp, radius, hardness);
+        get_high_res_mask(stamp, falloff, radius, hardness);
     }
     else {
-        get_mask(stamp, radius, hardness);
+        get_mask(stamp, falloff, radius, hardness);
     }
 }
 
@@ -291,44 +446,117 @@ static void get_classic_offset_stamp(DP_BrushStamp *offset_stamp,
     offset_mask(offset_stamp, mask_stamp, xfrac, yfrac);
 }
 
-static void draw_dabs_classic(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
-                              DP_PaintDrawDabsParams *params,
-                              DP_TransientLayerContent *tlc)
+static DP_StampMask *search_grain_inc(DP_PaintGrain grain)
+{
+    // Grain that's unknown or wouldn't do anything just gets left out.
+    return grain.mask_id != 0 && grain.scale != 0 && grain.strength != 0
+             ? DP_stamp_mask_search_inc(grain.mask_id)
+             : NULL;
+}
+
+// The grain gets sampled at the center of each pixel in canvas space, so all
+// dabs on the canvas line up with the same grain, no matter which stroke or
+// user they came from. Layers always cover the whole canvas, so there's no
+// layer offset to account for here.
+static void apply_grain(DP_BrushStamp *stamp, DP_StampMask *sm,
+                        DP_PaintGrain grain)
+{
+    float scale = DP_uint16_to_float(grain.scale) / 256.0f;
+    float strength = DP_uint8_to_float(grain.strength) / 255.0f;
+    int diameter = stamp->diameter;
+    uint16_t *d = stamp->data;
+    for (int y = 0; y < diameter; ++y) {
+        float gy = (DP_int_to_float(stamp->top + y) + 0.5f) / scale;
+        for (int x = 0; x < diameter; ++x) {
+            if (*d != 0) {
+                float gx = (DP_int_to_float(stamp->left + x) + 0.5f) / scale;
+                float value = DP_stamp_mask_sample_tiled(sm, gx, gy) / 255.0f;
+                float factor = 1.0f - strength * (1.0f - value);
+                *d = DP_float_to_uint16(DP_uint16_to_float(*d) * factor
+                                        + 0.5f);
+            }
+            ++d;
+        }
+    }
+}
+
+// Classic and soft dabs only differ in what the message carries alongside them,
+// the dabs themselves are the same.
+struct DP_PaintClassicDab {
+    int x, y;
+    uint16_t size;
+    uint8_t hardness;
+    uint8_t opacity;
+};
+
+static struct DP_PaintClassicDab get_classic_dab(DP_PaintDrawDabsParams *params,
+                                                 int i)
+{
+    const DP_ClassicDab *dab = DP_classic_dab_at(params->classic.dabs, i);
+    return (struct DP_PaintClassicDab){
+        DP_classic_dab_x(dab), DP_classic_dab_y(dab), DP_classic_dab_size(dab),
+        DP_classic_dab_hardness(dab), DP_classic_dab_opacity(dab)};
+}
+
+static struct DP_PaintClassicDab get_soft_dab(DP_PaintDrawDabsParams *params,
+                                              int i)
+{
+    const DP_SoftDab *dab = DP_soft_dab_at(params->soft.dabs, i);
+    return (struct DP_PaintClassicDab){
+        DP_soft_dab_x(dab), DP_soft_dab_y(dab), DP_soft_dab_size(dab),
+        DP_soft_dab_hardness(dab), DP_soft_dab_opacity(dab)};
+}
+
+static void
+draw_dabs_classic(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
+                  DP_PaintDrawDabsParams *params, DP_TransientLayerContent *tlc,
+                  int falloff, DP_PaintGrain grain,
+                  struct DP_PaintClassicDab (*get_dab)(DP_PaintDrawDabsParams *,
+                                                       int))
 {
     unsigned int context_id = params->context_id;
     DP_UPixel15 pixel = DP_upixel15_from_color(params->color);
     int blend_mode = params->blend_mode;
     int dab_count = params->dab_count;
-    const DP_ClassicDab *dabs = params->classic.dabs;
+    DP_Selection *sel_or_null = params->sel_or_null;
+    DP_StampMask *grain_sm = search_grain_inc(grain);
 
     int last_x = params->origin_x;
     int last_y = params->origin_y;
     DP_BrushStamp mask_stamp = make_brush_stamp1(dc);
     DP_BrushStamp offset_stamp = make_brush_stamp2(dc);
     for (int i = 0; i < dab_count; ++i) {
-        const DP_ClassicDab *dab = DP_classic_dab_at(dabs, i);
+        struct DP_PaintClassicDab dab = get_dab(params, i);
 
-        int x = last_x + DP_classic_dab_x(dab);
-        int y = last_y + DP_classic_dab_y(dab);
-        double radius = DP_classic_dab_size(dab) / 256.0;
-        uint8_t opacity = DP_classic_dab_opacity(dab);
+        int x = last_x + dab.x;
+        int y = last_y + dab.y;
+        double radius = dab.size / 256.0;
+        uint8_t opacity = dab.opacity;
 
         // Don't try to draw infinitesimal or fully opaque dabs.
         if (radius >= 0.1 && opacity != 0) {
-            get_classic_mask_stamp(&mask_stamp, radius,
-                                   DP_classic_dab_hardness(dab) / 255.0);
+            get_classic_mask_stamp(&mask_stamp, falloff, radius,
+                                   dab.hardness / 255.0);
 
             get_classic_offset_stamp(&offset_stamp, &mask_stamp, x / 4.0,
                                      y / 4.0);
+            if (grain_sm) {
+                apply_grain(&offset_stamp, grain_sm, grain);
+            }
 
-            DP_transient_layer_content_brush_stamp_apply(
-                tlc, context_id, pixel, DP_channel8_to_15(opacity), blend_mode,
-                &offset_stamp);
+            DP_BrushStamp *stamp =
+                select_stamp(sel_or_null, &offset_stamp, &offset_stamp);
+            if (stamp) {
+                DP_transient_layer_content_brush_stamp_apply(
+                    tlc, context_id, pixel, DP_channel8_to_15(opacity),
+                    blend_mode, stamp);
+            }
         }
 
         last_x = x;
         last_y = y;
     }
+    DP_stamp_mask_decref_nullable(grain_sm);
 
     if (ucs_or_null) {
         DP_user_cursors_activate(ucs_or_null, context_id);
@@ -381,10 +609,13 @@ static void draw_dabs_pixel(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
     int blend_mode = params->blend_mode;
     int dab_count = params->dab_count;
     const DP_PixelDab *dabs = params->pixel.dabs;
+    DP_Selection *sel_or_null = params->sel_or_null;
 
     int last_x = params->origin_x;
     int last_y = params->origin_y;
     DP_BrushStamp stamp = make_brush_stamp1(dc);
+    // The stamp gets reused between dabs, so selecting has to go elsewhere.
+    DP_BrushStamp selected_stamp = make_brush_stamp2(dc);
 
     int last_size = -1;
     for (int i = 0; i < dab_count; ++i) {
@@ -405,9 +636,13 @@ static void draw_dabs_pixel(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
             stamp.left = x - offset;
             stamp.top = y - offset;
 
-            DP_transient_layer_content_brush_stamp_apply(
-                tlc, context_id, pixel, DP_channel8_to_15(opacity), blend_mode,
-                &stamp);
+            DP_BrushStamp *s =
+                select_stamp(sel_or_null, &stamp, &selected_stamp);
+            if (s) {
+                DP_transient_layer_content_brush_stamp_apply(
+                    tlc, context_id, pixel, DP_channel8_to_15(opacity),
+                    blend_mode, s);
+            }
         }
 
         last_x = x;
@@ -422,6 +657,109 @@ static void draw_dabs_pixel(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
 }
 
 
+static void get_stamp_mask_stamp(DP_BrushStamp *stamp, DP_StampMask *sm,
+                                 double diameter, uint8_t angle)
+{
+    double radians = angle / 256.0 * 2.0 * M_PI;
+    double c = cos(radians);
+    double s = sin(radians);
+    // The rotated mask has to fit into the stamp buffer, including the extra
+    // pixels needed for subpixel offsetting.
+    double extent = fabs(c) + fabs(s);
+    double max_diameter = (DP_DRAW_CONTEXT_STAMP_MAX_DIAMETER - 3) / extent;
+    if (diameter > max_diameter) {
+        diameter = max_diameter;
+    }
+
+    int stamp_diameter = DP_double_to_int(ceil(diameter * extent)) + 2;
+    if (stamp_diameter % 2 == 0) {
+        ++stamp_diameter;
+    }
+    DP_ASSERT(stamp_diameter <= DP_DRAW_CONTEXT_STAMP_MAX_DIAMETER);
+    stamp->diameter = stamp_diameter;
+    int half = stamp_diameter / 2;
+    stamp->top = -half;
+    stamp->left = -half;
+
+    float lod = DP_stamp_mask_lod(
+        sm, DP_double_to_float(DP_stamp_mask_size(sm) / diameter));
+    float fc = DP_double_to_float(c / diameter);
+    float fs = DP_double_to_float(s / diameter);
+    uint16_t *d = stamp->data;
+    for (int y = 0; y < stamp_diameter; ++y) {
+        float dy = DP_int_to_float(y - half);
+        for (int x = 0; x < stamp_diameter; ++x) {
+            float dx = DP_int_to_float(x - half);
+            float value = DP_stamp_mask_sample(sm, lod, fc * dx + fs * dy,
+                                               fc * dy - fs * dx);
+            *(d++) = DP_float_to_uint16(value * ((float)DP_BIT15 / 255.0f)
+                                        + 0.5f);
+        }
+    }
+}
+
+static void draw_dabs_stamp(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
+                            DP_PaintDrawDabsParams *params,
+                            DP_TransientLayerContent *tlc)
+{
+    unsigned int context_id = params->context_id;
+    DP_UPixel15 pixel = DP_upixel15_from_color(params->color);
+    int blend_mode = params->blend_mode;
+    int dab_count = params->dab_count;
+    const DP_StampDab *dabs = params->stamp.dabs;
+    DP_Selection *sel_or_null = params->sel_or_null;
+    // Dabs with a mask we don't know about don't get drawn, but the cursor
+    // still gets moved along with them.
+    DP_StampMask *sm = DP_stamp_mask_search_inc(params->stamp.mask_id);
+    DP_PaintGrain grain = params->stamp.grain;
+    DP_StampMask *grain_sm = sm ? search_grain_inc(grain) : NULL;
+
+    int last_x = params->origin_x;
+    int last_y = params->origin_y;
+    DP_BrushStamp mask_stamp = make_brush_stamp1(dc);
+    DP_BrushStamp offset_stamp = make_brush_stamp2(dc);
+    for (int i = 0; i < dab_count; ++i) {
+        const DP_StampDab *dab = DP_stamp_dab_at(dabs, i);
+
+        int x = last_x + DP_stamp_dab_x(dab);
+        int y = last_y + DP_stamp_dab_y(dab);
+        double diameter = DP_stamp_dab_size(dab) / 256.0;
+        uint8_t opacity = DP_stamp_dab_opacity(dab);
+
+        // Don't try to draw infinitesimal or fully opaque dabs.
+        if (sm && diameter >= 0.1 && opacity != 0) {
+            get_stamp_mask_stamp(&mask_stamp, sm, diameter,
+                                 DP_stamp_dab_angle(dab));
+
+            get_classic_offset_stamp(&offset_stamp, &mask_stamp, x / 4.0,
+                                     y / 4.0);
+            if (grain_sm) {
+                apply_grain(&offset_stamp, grain_sm, grain);
+            }
+
+            DP_BrushStamp *stamp =
+                select_stamp(sel_or_null, &offset_stamp, &offset_stamp);
+            if (stamp) {
+                DP_transient_layer_content_brush_stamp_apply(
+                    tlc, context_id, pixel, DP_channel8_to_15(opacity),
+                    blend_mode, stamp);
+            }
+        }
+
+        last_x = x;
+        last_y = y;
+    }
+    DP_stamp_mask_decref_nullable(grain_sm);
+    DP_stamp_mask_decref_nullable(sm);
+
+    if (ucs_or_null) {
+        DP_user_cursors_activate(ucs_or_null, context_id);
+        DP_user_cursors_move(ucs_or_null, context_id, params->layer_id,
+                             last_x / 4, last_y / 4);
+    }
+}
+
+
 static float aspect_ratio_from_uint8(uint8_t aspect_ratio)
 {
     if (aspect_ratio == 0) {
@@ -952,6 +1290,7 @@ static void draw_dabs_mypaint(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
     DP_UPixel15 pixel = DP_upixel15_from_color(params->color);
     int dab_count = params->dab_count;
     const DP_MyPaintDab *dabs = params->mypaint.dabs;
+    DP_Selection *sel_or_null = params->sel_or_null;
 
     float lock_alpha = DP_uint8_to_float(params->mypaint.lock_alpha) / 255.0f;
     float colorize = DP_uint8_to_float(params->mypaint.colorize) / 255.0f;
@@ -977,9 +1316,14 @@ static void draw_dabs_mypaint(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
     float radius =
         get_mypaint_brush_stamp(&stamp, dc, last_x, last_y, last_size,
                                 last_hardness, last_aspect_ratio, last_angle);
-    apply_mypaint_dab(tlc, context_id, indirect, pixel, normal, lock_alpha,
-                      colorize, posterize, posterize_num, &stamp,
-                      DP_mypaint_dab_opacity(first_dab));
+    // Like with pixel dabs, the mask may get reused for the next dab.
+    DP_BrushStamp selected_stamp = make_brush_stamp2(dc);
+    DP_BrushStamp *s = select_stamp(sel_or_null, &stamp, &selected_stamp);
+    if (s) {
+        apply_mypaint_dab(tlc, context_id, indirect, pixel, normal, lock_alpha,
+                          colorize, posterize, posterize_num, s,
+                          DP_mypaint_dab_opacity(first_dab));
+    }
     if (ucs_or_null) {
         DP_user_cursors_activate(ucs_or_null, context_id);
         DP_user_cursors_move_smooth(ucs_or_null, context_id, params->layer_id,
@@ -1014,9 +1358,12 @@ static void draw_dabs_mypaint(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
             get_mypaint_brush_stamp_offsets(&stamp, xf, yf, radius);
         }
 
-        apply_mypaint_dab(tlc, context_id, indirect, pixel, normal, lock_alpha,
-                          colorize, posterize, posterize_num, &stamp,
-                          DP_mypaint_dab_opacity(dab));
+        s = select_stamp(sel_or_null, &stamp, &selected_stamp);
+        if (s) {
+            apply_mypaint_dab(tlc, context_id, indirect, pixel, normal,
+                              lock_alpha, colorize, posterize, posterize_num, s,
+                              DP_mypaint_dab_opacity(dab));
+        }
 
         if (ucs_or_null) {
             DP_user_cursors_move_smooth(ucs_or_null, context_id,
@@ -1040,7 +1387,9 @@ void DP_paint_draw_dabs(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
     int type = params->type;
     switch (type) {
     case DP_MSG_DRAW_DABS_CLASSIC:
-        draw_dabs_classic(dc, ucs_or_null, params, tlc);
+        draw_dabs_classic(dc, ucs_or_null, params, tlc,
+                          DP_CLASSIC_BRUSH_FALLOFF_RAMP,
+                          (DP_PaintGrain){0, 0, 0}, get_classic_dab);
         break;
     case DP_MSG_DRAW_DABS_PIXEL:
         draw_dabs_pixel(dc, ucs_or_null, params, tlc,
@@ -1050,15 +1399,120 @@ void DP_paint_draw_dabs(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
         draw_dabs_pixel(dc, ucs_or_null, params, tlc,
                         get_square_pixel_mask_stamp);
         break;
+    case DP_MSG_DRAW_DABS_STAMP:
+        draw_dabs_stamp(dc, ucs_or_null, params, tlc);
+        break;
     case DP_MSG_DRAW_DABS_MYPAINT:
         draw_dabs_mypaint(dc, ucs_or_null, params, tlc);
         break;
+    case DP_MSG_DRAW_DABS_SOFT:
+        draw_dabs_classic(dc, ucs_or_null, params, tlc, params->soft.falloff,
+                          params->soft.grain, get_soft_dab);
+        break;
     default:
         DP_UNREACHABLE();
     }
 }
 
 
+static DP_BrushOutline make_brush_outline(DP_BrushStamp *stamp)
+{
+    int diameter = stamp->diameter;
+    int left = diameter;
+    int top = diameter;
+    int right = -1;
+    int bottom = -1;
+    const uint16_t *data = stamp->data;
+    for (int y = 0; y < diameter; ++y) {
+        for (int x = 0; x < diameter; ++x) {
+            if (data[y * diameter + x] != 0) {
+                left = DP_min_int(left, x);
+                top = DP_min_int(top, y);
+                right = DP_max_int(right, x);
+                bottom = DP_max_int(bottom, y);
+            }
+        }
+    }
+
+    DP_Rect bounds;
+    if (right < 0) {
+        bounds = DP_rect_make(0, 0, 0, 0);
+    }
+    else {
+        bounds = (DP_Rect){stamp->left + left, stamp->top + top,
+                           stamp->left + right, stamp->top + bottom};
+    }
+    return (DP_BrushOutline){*stamp, bounds};
+}
+
+DP_BrushOutline DP_paint_classic_brush_outline(DP_DrawContext *dc,
+                                               const DP_ClassicBrush *cb,
+                                               float x, float y,
+                                               float pressure)
+{
+    DP_ASSERT(dc);
+    DP_ASSERT(cb);
+    // Mirrors what the brush engine puts into the dabs and what drawing them
+    // does with it, so that the outline matches the actual dab exactly.
+    DP_BrushStamp mask_stamp = make_brush_stamp1(dc);
+    DP_BrushStamp offset_stamp = make_brush_stamp2(dc);
+    switch (cb->shape) {
+    case DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND:
+    case DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE: {
+        int size = DP_classic_brush_pixel_dab_size_at(cb, pressure, 0.0f, 0.0f);
+        if (size != 0) {
+            if (cb->shape == DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND) {
+                get_round_pixel_mask_stamp(&mask_stamp, size);
+            }
+            else {
+                get_square_pixel_mask_stamp(&mask_stamp, size);
+            }
+            int offset = size / 2;
+            mask_stamp.left = DP_float_to_int(x) - offset;
+            mask_stamp.top = DP_float_to_int(y) - offset;
+            return make_brush_outline(&mask_stamp);
+        }
+        break;
+    }
+    case DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND: {
+        uint16_t size =
+            DP_classic_brush_soft_dab_size_at(cb, pressure, 0.0f, 0.0f);
+        if (size >= 26) {
+            uint8_t hardness =
+                DP_classic_brush_dab_hardness_at(cb, pressure, 0.0f, 0.0f);
+            get_classic_mask_stamp(&mask_stamp, (int)cb->falloff, size / 256.0,
+                                   hardness / 255.0);
+            get_classic_offset_stamp(&offset_stamp, &mask_stamp,
+                                     DP_float_to_int32(x * 4.0f) / 4.0,
+                                     DP_float_to_int32(y * 4.0f) / 4.0);
+            return make_brush_outline(&offset_stamp);
+        }
+        break;
+    }
+    case DP_BRUSH_SHAPE_CLASSIC_STAMP: {
+        uint16_t size =
+            DP_classic_brush_soft_dab_size_at(cb, pressure, 0.0f, 0.0f);
+        DP_StampMask *sm =
+            size >= 26 ? DP_stamp_mask_search_inc(cb->stamp_mask) : NULL;
+        if (sm) {
+            get_stamp_mask_stamp(&mask_stamp, sm, size / 256.0,
+                                 DP_classic_brush_dab_stamp_angle(cb, 0.0f));
+            DP_stamp_mask_decref(sm);
+            get_classic_offset_stamp(&offset_stamp, &mask_stamp,
+                                     DP_float_to_int32(x * 4.0f) / 4.0,
+                                     DP_float_to_int32(y * 4.0f) / 4.0);
+            return make_brush_outline(&offset_stamp);
+        }
+        break;
+    }
+    default:
+        break;
+    }
+    mask_stamp.diameter = 0;
+    return (DP_BrushOutline){mask_stamp, DP_rect_make(0, 0, 0, 0)};
+}
+
+
 DP_BrushStamp DP_paint_color_sampling_stamp_make(uint16_t *data, int diameter,
                                                  int left, int top,
                                                  int last_diameter)
@@ -1067,7 +1521,7 @@ DP_BrushStamp DP_paint_color_sampling_stamp_make(uint16_t *data, int diameter,
     DP_ASSERT(diameter > 0);
     DP_ASSERT(diameter <= DP_DRAW_CONTEXT_STAMP_MAX_DIAMETER);
 
-    const float *lut = get_classic_lut(0.5);
+    const float *lut = get_classic_lut(DP_CLASSIC_BRUSH_FALLOFF_RAMP, 0.5);
     int radius = diameter / 2;
 
     // Optimization, no need to recalculate the mask for the same diameter.
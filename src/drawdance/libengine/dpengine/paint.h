@@ -23,12 +23,17 @@
 #define DPENGINE_PAINT_H
 #include "pixels.h"
 #include <dpcommon/common.h>
+#include <dpcommon/geom.h>
 
 typedef struct DP_CanvasState DP_CanvasState;
+typedef struct DP_ClassicBrush DP_ClassicBrush;
 typedef struct DP_ClassicDab DP_ClassicDab;
 typedef struct DP_DrawContext DP_DrawContext;
 typedef struct DP_MyPaintDab DP_MyPaintDab;
 typedef struct DP_PixelDab DP_PixelDab;
+typedef struct DP_Selection DP_Selection;
+typedef struct DP_SoftDab DP_SoftDab;
+typedef struct DP_StampDab DP_StampDab;
 typedef struct DP_UserCursors DP_UserCursors;
 
 #ifdef DP_NO_STRICT_ALIASING
@@ -45,6 +50,24 @@ typedef struct DP_BrushStamp {
     uint16_t *data;
 } DP_BrushStamp;
 
+// A single classic brush dab, for showing the brush outline under the cursor.
+// The mask is the same one that gets stamped when the dab is drawn, the bounds
+// are the smallest rectangle around its nonzero pixels, in canvas coordinates.
+// If the brush wouldn't leave a dab, the mask has a diameter of zero and the
+// bounds are not valid, see DP_rect_valid.
+typedef struct DP_BrushOutline {
+    DP_BrushStamp mask;
+    DP_Rect bounds;
+} DP_BrushOutline;
+
+// Paper grain multiplied into soft and stamp dabs, see the softdabs
+// message for what the fields mean. A mask id of zero means no grain.
+typedef struct DP_PaintGrain {
+    uint32_t mask_id;
+    uint16_t scale;
+    uint8_t strength;
+} DP_PaintGrain;
+
 typedef struct DP_PaintDrawDabsParams {
     int type;
     unsigned int context_id;
@@ -56,6 +79,8 @@ typedef struct DP_PaintDrawDabsParams {
     bool indirect;
     bool indirect_compat;
     int dab_count;
+    // If given, each dab's mask gets multiplied by the selection's coverage.
+    DP_Selection *sel_or_null;
     union {
         struct {
             const DP_ClassicDab *dabs;
@@ -63,6 +88,11 @@ typedef struct DP_PaintDrawDabsParams {
         struct {
             const DP_PixelDab *dabs;
         } pixel;
+        struct {
+            const DP_StampDab *dabs;
+            uint32_t mask_id;
+            DP_PaintGrain grain;
+        } stamp;
         struct {
             const DP_MyPaintDab *dabs;
             uint8_t lock_alpha;
@@ -70,6 +100,11 @@ typedef struct DP_PaintDrawDabsParams {
             uint8_t posterize;
             uint8_t posterize_num;
         } mypaint;
+        struct {
+            const DP_SoftDab *dabs;
+            uint8_t falloff;
+            DP_PaintGrain grain;
+        } soft;
     };
 } DP_PaintDrawDabsParams;
 
@@ -78,6 +113,16 @@ void DP_paint_draw_dabs(DP_DrawContext *dc, DP_UserCursors *ucs_or_null,
                         DP_PaintDrawDabsParams *params,
                         DP_TransientLayerContent *tlc);
 
+// Builds the outline of a dab at the given position and pressure, with zero
+// velocity and distance. Only classic brush shapes are supported, stamp brushes
+// use the stamp angle without any direction or tilt added. The mask lives in
+// the draw context's stamp buffer, so the next paint operation on the same draw
+// context clobbers it. Cheap enough to call on every pointer move.
+DP_BrushOutline DP_paint_classic_brush_outline(DP_DrawContext *dc,
+                                               const DP_ClassicBrush *cb,
+                                               float x, float y,
+                                               float pressure);
+
 DP_BrushStamp DP_paint_color_sampling_stamp_make(uint16_t *data, int diameter,
                                                  int left, int top,
                                                  int last_diameter);
@@ -27,6 +27,7 @@
 #include "draw_context.h"
 #include "image.h"
 #include "layer_content.h"
+#include "layer_diff.h"
 #include "layer_group.h"
 #include "layer_list.h"
 #include "layer_props.h"
@@ -38,6 +39,7 @@
 #include "preview.h"
 #include "recorder.h"
 #include "renderer.h"
+#include "thumbnailer.h"
 #include "tile.h"
 #include "timeline.h"
 #include "track.h"
@@ -77,6 +79,13 @@
 #define PLAYBACK_STEP_UNDO_POINTS 1
 #define PLAYBACK_STEP_MSECS       2
 
+// Measuring history memory means going through all save point tiles, so it
+// only happens every this many undo points.
+#define HISTORY_TRIM_INTERVAL 64
+
+// Tiles of a thumbnail to scale after each message while more are queued.
+#define THUMBNAIL_TILES_PER_STEP 16
+
 #define NO_PUSH               0
 #define PUSH_MESSAGE          1
 #define PUSH_CLEAR_LOCAL_FORK 2
@@ -129,6 +138,7 @@ struct DP_PaintEngine {
     DP_AtomicPtr next_previews[DP_PREVIEW_COUNT];
     DP_Atomic preview_rerendered;
     DP_PreviewRenderer *preview_renderer;
+    DP_Preview *transform_preview;
     DP_Queue local_queue;
     DP_Queue remote_queue;
     DP_Semaphore *queue_sem;
@@ -137,6 +147,14 @@ struct DP_PaintEngine {
     DP_Atomic catchup;
     DP_Atomic default_layer_id;
     DP_Atomic undo_depth_limit;
+    DP_Atomic history_soft_limit_mib;
+    int history_trim_countdown;
+    long long message_index;
+    struct {
+        DP_Thumbnailer *current;
+        DP_Thumbnailer *next;
+        bool changed;
+    } thumbnailer;
     DP_Atomic just_reset;
     bool catching_up;
     bool reset_locked;
@@ -145,6 +163,7 @@ struct DP_PaintEngine {
     struct {
         uint8_t acl_change_flags;
         DP_Vector cursor_changes;
+        DP_Vector chat_messages;
         DP_UserCursorBuffer ucb;
     } meta;
     struct {
@@ -163,6 +182,23 @@ struct DP_PaintEngine {
         DP_PaintEngineDumpPlaybackFn dump_fn;
         void *user;
     } playback;
+    struct {
+        long long interval_ms;
+        long long last_ms;
+        DP_RecorderGetTimeMsFn get_time_fn;
+        void *get_time_user;
+        DP_PaintEngineAutosaveFn fn;
+        void *user;
+    } autosave;
+    struct {
+        DP_PaintEngineAreaChangedFn fn;
+        void *user;
+    } area_changed;
+    struct {
+        DP_LayerDiff *ld;
+        DP_PaintEngineLayerDiffFn fn;
+        void *user;
+    } layer_diff;
 };
 
 
@@ -396,6 +432,34 @@ static int get_classic_dabs_area(DP_MsgDrawDabsClassic *mddc, int dabs_area)
     return dabs_area;
 }
 
+static int get_soft_dabs_area(DP_MsgDrawDabsSoft *mdds, int dabs_area)
+{
+    int count;
+    const DP_SoftDab *sds = DP_msg_draw_dabs_soft_dabs(mdds, &count);
+    for (int i = 0; i < count && dabs_area < MAX_MULTIDAB_AREA; ++i) {
+        int radius = DP_soft_dab_size(DP_soft_dab_at(sds, i)) / 256;
+        int diameter = radius * 2;
+        int area = DP_max_int(1, diameter * diameter);
+        dabs_area += area;
+    }
+    return dabs_area;
+}
+
+static int get_stamp_dabs_area(DP_MsgDrawDabsStamp *mdds, int dabs_area)
+{
+    int count;
+    const DP_StampDab *sds = DP_msg_draw_dabs_stamp_dabs(mdds, &count);
+    for (int i = 0; i < count && dabs_area < MAX_MULTIDAB_AREA; ++i) {
+        // Rotated stamps can take up more space than their nominal size, so
+        // overestimate along with the classic dabs above.
+        int radius = DP_stamp_dab_size(DP_stamp_dab_at(sds, i)) / 256;
+        int diameter = radius * 2;
+        int area = DP_max_int(1, diameter * diameter);
+        dabs_area += area;
+    }
+    return dabs_area;
+}
+
 static int get_pixel_dabs_area(DP_MsgDrawDabsPixel *mddp, int dabs_area)
 {
     int count;
@@ -434,6 +498,10 @@ static int get_dabs_area(DP_Message *msg, DP_MessageType type, int dabs_area)
         return get_pixel_dabs_area(DP_message_internal(msg), dabs_area);
     case DP_MSG_DRAW_DABS_MYPAINT:
         return get_mypaint_dabs_area(DP_message_internal(msg), dabs_area);
+    case DP_MSG_DRAW_DABS_STAMP:
+        return get_stamp_dabs_area(DP_message_internal(msg), dabs_area);
+    case DP_MSG_DRAW_DABS_SOFT:
+        return get_soft_dabs_area(DP_message_internal(msg), dabs_area);
     default:
         return MAX_MULTIDAB_AREA + 1;
     }
@@ -482,6 +550,20 @@ static int maybe_shift_more_messages(DP_PaintEngine *pe, bool local,
     }
 }
 
+static void maybe_trim_history(DP_PaintEngine *pe)
+{
+    int limit_mib = DP_atomic_get(&pe->history_soft_limit_mib);
+    if (limit_mib > 0 && --pe->history_trim_countdown <= 0) {
+        pe->history_trim_countdown = HISTORY_TRIM_INTERVAL;
+        size_t limit = DP_int_to_size(limit_mib) * (size_t)1024 * (size_t)1024;
+        size_t bytes = DP_canvas_history_trim(pe->ch, limit);
+        if (bytes > limit) {
+            DP_debug("History uses %zu bytes after trimming, limit is %zu",
+                     bytes, limit);
+        }
+    }
+}
+
 static void handle_single_message(DP_PaintEngine *pe, DP_DrawContext *dc,
                                   bool local, DP_MessageType type,
                                   DP_Message *msg)
@@ -520,6 +602,9 @@ static void handle_single_message(DP_PaintEngine *pe, DP_DrawContext *dc,
             DP_atomic_set(&pe->just_reset, false);
         }
     }
+    if (type == DP_MSG_UNDO_POINT) {
+        maybe_trim_history(pe);
+    }
     DP_message_decref(msg);
 }
 
@@ -534,6 +619,32 @@ static void handle_multidab(DP_PaintEngine *pe, DP_DrawContext *dc, bool local,
     }
 }
 
+// Must be called with the queue mutex locked, returns the thumbnailer to free.
+static DP_Thumbnailer *swap_thumbnailer(DP_PaintEngine *pe)
+{
+    if (pe->thumbnailer.changed) {
+        DP_Thumbnailer *prev = pe->thumbnailer.current;
+        pe->thumbnailer.current = pe->thumbnailer.next;
+        pe->thumbnailer.next = NULL;
+        pe->thumbnailer.changed = false;
+        return prev;
+    }
+    else {
+        return NULL;
+    }
+}
+
+// Thumbnails get finished when the queue runs dry. While there's more messages
+// to handle, only a few tiles get done at a time to not hold them up.
+static void step_thumbnailer(DP_PaintEngine *pe, bool queue_empty)
+{
+    DP_Thumbnailer *t = pe->thumbnailer.current;
+    if (t && (queue_empty || DP_thumbnailer_in_progress(t))) {
+        DP_thumbnailer_step(t, pe->ch, pe->message_index,
+                            queue_empty ? INT_MAX : THUMBNAIL_TILES_PER_STEP);
+    }
+}
+
 static void handle_message(DP_PaintEngine *pe, DP_DrawContext *dc,
                            DP_Message **msgs)
 {
@@ -542,7 +653,11 @@ static void handle_message(DP_PaintEngine *pe, DP_DrawContext *dc,
     DP_Message *first = msgs[0];
     DP_MessageType type = DP_message_type(first);
     int count = maybe_shift_more_messages(pe, local, type, msgs);
+    bool queue_empty =
+        pe->local_queue.used == 0 && pe->remote_queue.used == 0;
+    DP_Thumbnailer *prev_thumbnailer = swap_thumbnailer(pe);
     DP_MUTEX_MUST_UNLOCK(pe->queue_mutex);
+    DP_thumbnailer_free(prev_thumbnailer);
 
     DP_ASSERT(count > 0);
     DP_ASSERT(count <= MAX_MULTIDAB_MESSAGES);
@@ -552,6 +667,8 @@ static void handle_message(DP_PaintEngine *pe, DP_DrawContext *dc,
     else {
         handle_multidab(pe, dc, local, count, msgs);
     }
+    pe->message_index += count;
+    step_thumbnailer(pe, queue_empty);
 }
 
 static void run_paint_engine(void *user)
@@ -684,6 +801,7 @@ DP_PaintEngine *DP_paint_engine_new_inc(
     DP_atomic_set(&pe->preview_rerendered, false);
     pe->preview_renderer = DP_preview_renderer_new(
         preview_dc, preview_rendered, preview_rerendered, preview_clear, pe);
+    pe->transform_preview = NULL;
     DP_message_queue_init(&pe->local_queue, INITIAL_QUEUE_CAPACITY);
     DP_message_queue_init(&pe->remote_queue, INITIAL_QUEUE_CAPACITY);
     pe->queue_sem = DP_semaphore_new(0);
@@ -693,6 +811,12 @@ DP_PaintEngine *DP_paint_engine_new_inc(
     DP_atomic_set(&pe->default_layer_id, -1);
     DP_atomic_set(&pe->undo_depth_limit,
                   DP_canvas_history_undo_depth_limit(pe->ch));
+    DP_atomic_set(&pe->history_soft_limit_mib, 0);
+    pe->history_trim_countdown = HISTORY_TRIM_INTERVAL;
+    pe->message_index = 0;
+    pe->thumbnailer.current = NULL;
+    pe->thumbnailer.next = NULL;
+    pe->thumbnailer.changed = false;
     DP_atomic_set(&pe->just_reset, false);
     pe->catching_up = false;
     pe->reset_locked = false;
@@ -703,6 +827,7 @@ DP_PaintEngine *DP_paint_engine_new_inc(
     pe->meta.acl_change_flags = 0;
     DP_VECTOR_INIT_TYPE(&pe->meta.cursor_changes, DP_PaintEngineCursorChange,
                         8);
+    DP_VECTOR_INIT_TYPE(&pe->meta.chat_messages, DP_Message *, 4);
     pe->record.path = NULL;
     pe->record.recorder = NULL;
     pe->record.start_sem = DP_semaphore_new(0);
@@ -715,6 +840,17 @@ DP_PaintEngine *DP_paint_engine_new_inc(
     pe->playback.fn = playback_fn;
     pe->playback.dump_fn = dump_playback_fn;
     pe->playback.user = playback_user;
+    pe->autosave.interval_ms = 0;
+    pe->autosave.last_ms = 0;
+    pe->autosave.get_time_fn = NULL;
+    pe->autosave.get_time_user = NULL;
+    pe->autosave.fn = NULL;
+    pe->autosave.user = NULL;
+    pe->area_changed.fn = NULL;
+    pe->area_changed.user = NULL;
+    pe->layer_diff.ld = NULL;
+    pe->layer_diff.fn = NULL;
+    pe->layer_diff.user = NULL;
     return pe;
 }
 
@@ -725,8 +861,11 @@ void DP_paint_engine_free_join(DP_PaintEngine *pe)
         DP_atomic_set(&pe->running, false);
         DP_SEMAPHORE_MUST_POST(pe->queue_sem);
         DP_thread_free_join(pe->paint_thread);
+        DP_thumbnailer_free(pe->thumbnailer.current);
+        DP_thumbnailer_free(pe->thumbnailer.next);
         DP_player_free(pe->playback.player);
         DP_semaphore_free(pe->record.start_sem);
+        DP_vector_dispose(&pe->meta.chat_messages);
         DP_vector_dispose(&pe->meta.cursor_changes);
         DP_renderer_free(pe->renderer);
         DP_mutex_free(pe->queue_mutex);
@@ -759,6 +898,7 @@ void DP_paint_engine_free_join(DP_PaintEngine *pe)
         }
         DP_message_queue_dispose(&pe->local_queue);
         DP_preview_renderer_free(pe->preview_renderer);
+        DP_preview_decref_nullable(pe->transform_preview);
         for (int i = 0; i < DP_PREVIEW_COUNT; ++i) {
             free_preview(DP_atomic_ptr_xch(&pe->next_previews[i], NULL));
             DP_preview_decref_nullable(pe->previews[i]);
@@ -772,6 +912,7 @@ void DP_paint_engine_free_join(DP_PaintEngine *pe)
         DP_canvas_state_decref_nullable(pe->history_cs);
         DP_canvas_state_decref_nullable(pe->view_cs);
         DP_tile_decref(pe->checker);
+        DP_layer_diff_free(pe->layer_diff.ld);
         DP_canvas_diff_free(pe->diff);
         DP_canvas_history_free(pe->ch);
         DP_free(pe);
@@ -784,6 +925,80 @@ int DP_paint_engine_render_thread_count(DP_PaintEngine *pe)
     return DP_renderer_thread_count(pe->renderer);
 }
 
+long long DP_paint_engine_change_count(DP_PaintEngine *pe)
+{
+    DP_ASSERT(pe);
+    return DP_canvas_history_change_count(pe->ch);
+}
+
+void DP_paint_engine_saved_at_set(DP_PaintEngine *pe, long long change_count)
+{
+    DP_ASSERT(pe);
+    DP_canvas_history_saved_at_set(pe->ch, change_count);
+}
+
+bool DP_paint_engine_dirty(DP_PaintEngine *pe)
+{
+    DP_ASSERT(pe);
+    return DP_canvas_history_dirty(pe->ch);
+}
+
+void DP_paint_engine_autosave_set(DP_PaintEngine *pe, long long interval_ms,
+                                  DP_RecorderGetTimeMsFn get_time_fn,
+                                  void *get_time_user,
+                                  DP_PaintEngineAutosaveFn fn, void *user)
+{
+    DP_ASSERT(pe);
+    DP_ASSERT(!fn || get_time_fn);
+    pe->autosave.interval_ms = interval_ms;
+    pe->autosave.get_time_fn = get_time_fn;
+    pe->autosave.get_time_user = get_time_user;
+    pe->autosave.fn = fn;
+    pe->autosave.user = user;
+    pe->autosave.last_ms = fn ? get_time_fn(get_time_user) : 0;
+}
+
+void DP_paint_engine_area_changed_set(DP_PaintEngine *pe,
+                                      DP_PaintEngineAreaChangedFn fn,
+                                      void *user)
+{
+    DP_ASSERT(pe);
+    pe->area_changed.fn = fn;
+    pe->area_changed.user = user;
+}
+
+void DP_paint_engine_layer_diff_set(DP_PaintEngine *pe,
+                                    DP_PaintEngineLayerDiffFn fn, void *user)
+{
+    DP_ASSERT(pe);
+    if (fn && !pe->layer_diff.ld) {
+        pe->layer_diff.ld = DP_layer_diff_new();
+    }
+    pe->layer_diff.fn = fn;
+    pe->layer_diff.user = user;
+}
+
+void DP_paint_engine_thumbnailer_set_noinc(DP_PaintEngine *pe,
+                                           DP_Thumbnailer *t_or_null)
+{
+    DP_ASSERT(pe);
+    DP_MUTEX_MUST_LOCK(pe->queue_mutex);
+    DP_Thumbnailer *prev = pe->thumbnailer.next;
+    pe->thumbnailer.next = t_or_null;
+    pe->thumbnailer.changed = true;
+    DP_MUTEX_MUST_UNLOCK(pe->queue_mutex);
+    DP_thumbnailer_free(prev);
+}
+
+int DP_paint_engine_queued_message_count(DP_PaintEngine *pe)
+{
+    DP_ASSERT(pe);
+    DP_MUTEX_MUST_LOCK(pe->queue_mutex);
+    size_t used = pe->local_queue.used + pe->remote_queue.used;
+    DP_MUTEX_MUST_UNLOCK(pe->queue_mutex);
+    return DP_size_to_int(used);
+}
+
 void DP_paint_engine_local_drawing_in_progress_set(
     DP_PaintEngine *pe, bool local_drawing_in_progress)
 {
@@ -805,6 +1020,12 @@ void DP_paint_engine_want_canvas_history_dump_set(DP_PaintEngine *pe,
     DP_canvas_history_want_dump_set(pe->ch, want_canvas_history_dump);
 }
 
+void DP_paint_engine_history_soft_limit_set(DP_PaintEngine *pe, int limit_mib)
+{
+    DP_ASSERT(pe);
+    DP_atomic_set(&pe->history_soft_limit_mib, DP_max_int(limit_mib, 0));
+}
+
 
 bool DP_paint_engine_local_state_reset_image_build(
     DP_PaintEngine *pe, DP_LocalStateAcceptResetMessageFn fn, void *user)
@@ -1021,20 +1242,26 @@ static long long guess_message_msecs(DP_Message *msg, DP_MessageType type,
     case DP_MSG_DRAW_DABS_PIXEL:
     case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
     case DP_MSG_DRAW_DABS_MYPAINT:
+    case DP_MSG_DRAW_DABS_STAMP:
+    case DP_MSG_DRAW_DABS_SOFT:
         return 5;
     case DP_MSG_PUT_IMAGE:
     case DP_MSG_MOVE_REGION:
     case DP_MSG_MOVE_RECT:
+    case DP_MSG_FILL_GRADIENT:
+    case DP_MSG_FILTER_REGION:
     case DP_MSG_MOVE_POINTER:
         return 10;
     case DP_MSG_LAYER_CREATE:
     case DP_MSG_LAYER_ATTRIBUTES:
     case DP_MSG_LAYER_RETITLE:
+    case DP_MSG_LAYER_METADATA:
     case DP_MSG_LAYER_ORDER:
     case DP_MSG_LAYER_DELETE:
     case DP_MSG_LAYER_VISIBILITY:
     case DP_MSG_ANNOTATION_RESHAPE:
     case DP_MSG_ANNOTATION_EDIT:
+    case DP_MSG_ANNOTATION_ORDER:
         return 20;
     case DP_MSG_CANVAS_RESIZE:
         return 60;
@@ -1671,6 +1898,12 @@ static void handle_move_pointer(DP_PaintEngine *pe, DP_Message *msg)
                         change);
 }
 
+static void handle_chat(DP_PaintEngine *pe, DP_Message *msg)
+{
+    DP_VECTOR_PUSH_TYPE(&pe->meta.chat_messages, DP_Message *,
+                        DP_message_incref(msg));
+}
+
 static int should_push_message_remote(DP_PaintEngine *pe, DP_Message *msg,
                                       bool override_acls)
 {
@@ -1712,6 +1945,10 @@ static int should_push_message_remote(DP_PaintEngine *pe, DP_Message *msg,
             }
             return NO_PUSH;
         }
+        else if (type == DP_MSG_CHAT && !DP_message_opaque(msg)) {
+            handle_chat(pe, msg);
+            return NO_PUSH;
+        }
         else {
             return NO_PUSH;
         }
@@ -1798,7 +2035,7 @@ int DP_paint_engine_handle_inc(DP_PaintEngine *pe, bool local,
                                DP_PaintEngineAclsChangedFn acls_changed,
                                DP_PaintEngineLaserTrailFn laser_trail,
                                DP_PaintEngineMovePointerFn move_pointer,
-                               void *user)
+                               DP_PaintEngineChatFn chat, void *user)
 {
     DP_ASSERT(pe);
     DP_ASSERT(msgs);
@@ -1810,6 +2047,8 @@ int DP_paint_engine_handle_inc(DP_PaintEngine *pe, bool local,
     pe->meta.acl_change_flags = 0;
     DP_Vector *cursor_changes = &pe->meta.cursor_changes;
     cursor_changes->used = 0;
+    DP_Vector *chat_messages = &pe->meta.chat_messages;
+    chat_messages->used = 0;
 
     // Don't lock anything until we actually find a message to push.
     int pushed = 0;
@@ -1848,6 +2087,19 @@ int DP_paint_engine_handle_inc(DP_PaintEngine *pe, bool local,
         }
     }
 
+    size_t chat_count = chat_messages->used;
+    for (size_t i = 0; i < chat_count; ++i) {
+        DP_Message *msg = DP_VECTOR_AT_TYPE(chat_messages, DP_Message *, i);
+        if (chat) {
+            DP_MsgChat *mc = DP_message_internal(msg);
+            size_t length;
+            const char *text = DP_msg_chat_message(mc, &length);
+            chat(user, DP_message_context_id(msg), DP_msg_chat_tflags(mc),
+                 DP_msg_chat_oflags(mc), text, length);
+        }
+        DP_message_decref(msg);
+    }
+
     DP_PERF_END(fn);
     return pushed;
 }
@@ -2195,6 +2447,15 @@ static DP_CanvasState *apply_local_background_tile(DP_PaintEngine *pe,
     }
 }
 
+static void unite_changed_tile(void *data, int tile_x, int tile_y)
+{
+    DP_Rect *area = data;
+    DP_Rect tile_area = DP_rect_make(tile_x * DP_TILE_SIZE,
+                                     tile_y * DP_TILE_SIZE, DP_TILE_SIZE,
+                                     DP_TILE_SIZE);
+    *area = DP_rect_valid(*area) ? DP_rect_union(*area, tile_area) : tile_area;
+}
+
 static void
 emit_changes(DP_PaintEngine *pe, DP_CanvasState *prev, DP_CanvasState *cs,
              bool catching_up, bool catchup_done, DP_Rect tile_bounds,
@@ -2207,10 +2468,39 @@ emit_changes(DP_PaintEngine *pe, DP_CanvasState *prev, DP_CanvasState *cs,
 {
     DP_CanvasDiff *diff = pe->diff;
     DP_canvas_state_diff(cs, prev, diff);
+    // The renderer resets the diff, so the changed area is collected first.
+    DP_Rect area = {0, 0, -1, -1};
+    DP_PaintEngineAreaChangedFn area_changed = pe->area_changed.fn;
+    if (area_changed) {
+        DP_canvas_diff_each_pos(diff, unite_changed_tile, &area);
+    }
+
     DP_renderer_apply(pe->renderer, cs, pe->local_state, diff,
                       pe->local_view.layers_can_decrease_opacity, tile_bounds,
                       render_outside_tile_bounds, DP_RENDERER_CONTINUOUS);
 
+    if (DP_rect_valid(area)) {
+        DP_Rect canvas_area = DP_rect_make(0, 0, DP_canvas_state_width(cs),
+                                           DP_canvas_state_height(cs));
+        area = DP_rect_intersection(area, canvas_area);
+        if (DP_rect_valid(area)) {
+            area_changed(pe->area_changed.user, DP_rect_x(area),
+                         DP_rect_y(area), DP_rect_width(area),
+                         DP_rect_height(area));
+        }
+    }
+
+    DP_PaintEngineLayerDiffFn layer_diff_fn = pe->layer_diff.fn;
+    if (layer_diff_fn) {
+        DP_LayerDiff *ld = pe->layer_diff.ld;
+        int count = DP_layer_diff_update(ld, cs, prev);
+        if (count != 0) {
+            layer_diff_fn(pe->layer_diff.user,
+                          DP_canvas_state_layer_props_noinc(cs), count,
+                          DP_layer_diff_events(ld));
+        }
+    }
+
     if (!catching_up) {
         if (DP_canvas_diff_layer_props_changed_reset(diff) || catchup_done) {
             layer_props_changed(user, DP_canvas_state_layer_props_noinc(cs));
@@ -2242,6 +2532,20 @@ emit_changes(DP_PaintEngine *pe, DP_CanvasState *prev, DP_CanvasState *cs,
     ucb->count = 0;
 }
 
+static void maybe_autosave(DP_PaintEngine *pe)
+{
+    DP_PaintEngineAutosaveFn autosave_fn = pe->autosave.fn;
+    if (autosave_fn) {
+        long long now = pe->autosave.get_time_fn(pe->autosave.get_time_user);
+        if (now - pe->autosave.last_ms >= pe->autosave.interval_ms
+            && DP_canvas_history_dirty(pe->ch)) {
+            pe->autosave.last_ms = now;
+            autosave_fn(pe->autosave.user,
+                        DP_canvas_history_change_count(pe->ch));
+        }
+    }
+}
+
 void DP_paint_engine_tick(
     DP_PaintEngine *pe, DP_Rect tile_bounds, bool render_outside_tile_bounds,
     DP_PaintEngineCatchupFn catchup,
@@ -2377,6 +2681,10 @@ void DP_paint_engine_tick(
         undo_depth_limit_set(user, undo_depth_limit);
     }
 
+    if (!reset_locked) {
+        maybe_autosave(pe);
+    }
+
     DP_PERF_END(fn);
 }
 
@@ -2427,6 +2735,8 @@ void DP_paint_engine_preview_transform(
         DP_Preview *pv = DP_preview_new_transform(
             offset_x, offset_y, layer_id, x, y, width, height, dst_quad,
             interpolation, get_pixels, dispose_pixels, user);
+        DP_preview_decref_nullable(pe->transform_preview);
+        pe->transform_preview = DP_preview_incref(pv);
         DP_preview_renderer_push_noinc(
             pe->preview_renderer, pv, DP_canvas_state_width(cs),
             DP_canvas_state_height(cs), offset_x, offset_y);
@@ -2437,6 +2747,30 @@ void DP_paint_engine_preview_transform(
     }
 }
 
+bool DP_paint_engine_preview_transform_move(DP_PaintEngine *pe, int x, int y,
+                                            const DP_Quad *dst_quad,
+                                            int interpolation)
+{
+    DP_ASSERT(pe);
+    DP_Preview *prev = pe->transform_preview;
+    if (prev) {
+        DP_CanvasState *cs = pe->view_cs;
+        int offset_x = DP_canvas_state_offset_x(cs);
+        int offset_y = DP_canvas_state_offset_y(cs);
+        DP_Preview *pv = DP_preview_new_transform_moved(
+            prev, offset_x, offset_y, x, y, dst_quad, interpolation);
+        DP_preview_decref(prev);
+        pe->transform_preview = DP_preview_incref(pv);
+        DP_preview_renderer_push_noinc(
+            pe->preview_renderer, pv, DP_canvas_state_width(cs),
+            DP_canvas_state_height(cs), offset_x, offset_y);
+        return true;
+    }
+    else {
+        return false;
+    }
+}
+
 void DP_paint_engine_preview_dabs_inc(DP_PaintEngine *pe, int layer_id,
                                       int count, DP_Message **messages)
 {
@@ -2461,6 +2795,10 @@ void DP_paint_engine_preview_clear(DP_PaintEngine *pe, DP_PreviewType type)
     DP_ASSERT(pe);
     DP_ASSERT(type >= 0);
     DP_ASSERT(type < DP_PREVIEW_COUNT);
+    if (type == DP_PREVIEW_TRANSFORM) {
+        DP_preview_decref_nullable(pe->transform_preview);
+        pe->transform_preview = NULL;
+    }
     DP_preview_renderer_cancel(pe->preview_renderer, type);
 }
 
@@ -22,6 +22,7 @@
 #ifndef DPENGINE_PAINT_ENGINE
 #define DPENGINE_PAINT_ENGINE
 #include "canvas_history.h"
+#include "layer_diff.h"
 #include "local_state.h"
 #include "player.h"
 #include "preview.h"
@@ -39,6 +40,7 @@ typedef struct DP_Image DP_Image;
 typedef struct DP_LayerPropsList DP_LayerPropsList;
 typedef struct DP_Message DP_Message;
 typedef struct DP_Quad DP_Quad;
+typedef struct DP_Thumbnailer DP_Thumbnailer;
 typedef union DP_Pixel8 DP_Pixel8;
 
 #ifdef DP_NO_STRICT_ALIASING
@@ -55,6 +57,9 @@ typedef void (*DP_PaintEngineLaserTrailFn)(void *user, unsigned int context_id,
                                            int persistence, uint32_t color);
 typedef void (*DP_PaintEngineMovePointerFn)(void *user, unsigned int context_id,
                                             int x, int y);
+typedef void (*DP_PaintEngineChatFn)(void *user, unsigned int context_id,
+                                     unsigned int tflags, unsigned int oflags,
+                                     const char *text, size_t length);
 typedef void (*DP_PaintEngineDefaultLayerSetFn)(void *user, int layer_id);
 typedef void (*DP_PaintEngineUndoDepthLimitSetFn)(void *user,
                                                   int undo_depth_limit);
@@ -73,10 +78,23 @@ typedef void (*DP_PaintEngineCursorMovedFn)(void *user, unsigned int flags,
                                             unsigned int context_id,
                                             int layer_id, int x, int y);
 typedef void (*DP_PaintEnginePushMessageFn)(void *user, DP_Message *msg);
+typedef void (*DP_PaintEngineAutosaveFn)(void *user, long long change_count);
+typedef void (*DP_PaintEngineAreaChangedFn)(void *user, int x, int y,
+                                            int width, int height);
+typedef void (*DP_PaintEngineLayerDiffFn)(void *user, DP_LayerPropsList *lpl,
+                                          int count,
+                                          const DP_LayerDiffEvent *events);
 
 
 typedef struct DP_PaintEngine DP_PaintEngine;
 
+// The paint engine owns a paint thread that works through the queued messages
+// and render threads that call the renderer functions. Everything else is for
+// the thread that created the engine: it handles messages, ticks the engine and
+// reads its canvas states. Those functions must not be called concurrently from
+// other threads, the ones that are fine to call from anywhere say so. The
+// canvas states handed out are immutable and atomically reference-counted, so
+// any thread may read and release them.
 DP_PaintEngine *DP_paint_engine_new_inc(
     DP_DrawContext *paint_dc, DP_DrawContext *main_dc,
     DP_DrawContext *preview_dc, DP_AclState *acls, DP_CanvasState *cs_or_null,
@@ -93,6 +111,55 @@ void DP_paint_engine_free_join(DP_PaintEngine *pe);
 
 int DP_paint_engine_render_thread_count(DP_PaintEngine *pe);
 
+// Counter that goes up whenever the canvas changes, see the canvas history
+// functions of the same name. Safe to call from any thread.
+long long DP_paint_engine_change_count(DP_PaintEngine *pe);
+
+void DP_paint_engine_saved_at_set(DP_PaintEngine *pe, long long change_count);
+
+bool DP_paint_engine_dirty(DP_PaintEngine *pe);
+
+// Calls the given function on tick when the canvas is dirty, at most once per
+// interval. It gets passed the change count to record as saved once it's done
+// saving. Not called while a reset or catch-up is in progress. Pass a NULL
+// function to turn it off again.
+void DP_paint_engine_autosave_set(DP_PaintEngine *pe, long long interval_ms,
+                                  DP_RecorderGetTimeMsFn get_time_fn,
+                                  void *get_time_user,
+                                  DP_PaintEngineAutosaveFn fn, void *user);
+
+// Calls the given function on tick with the canvas area that changed since the
+// previous tick, all changes in between coalesced into a single rectangle of
+// the affected tiles, clipped to the canvas size. Layer list, annotation and
+// resize changes still go through the tick and renderer callbacks. Only call
+// this on the thread that ticks the paint engine, the function gets called on
+// that same thread. Pass a NULL function to turn it off again.
+void DP_paint_engine_area_changed_set(DP_PaintEngine *pe,
+                                      DP_PaintEngineAreaChangedFn fn,
+                                      void *user);
+
+// Calls the given function on tick with the changes to the layer tree since
+// the previous tick, see DP_layer_diff_update for the events and their order.
+// It also gets the current layer properties to look up the attributes of
+// added and changed layers. Unlike the layer properties tick callback, this
+// also gets called while catching up, so that applying every batch of events
+// in turn keeps a model of the layers in sync. The first events are relative
+// to the view canvas state at the time of registering, so start off the model
+// from that. Same threading as above. Pass a NULL function to turn it off.
+void DP_paint_engine_layer_diff_set(DP_PaintEngine *pe,
+                                    DP_PaintEngineLayerDiffFn fn, void *user);
+
+// Takes ownership of the thumbnailer, NULL turns thumbnails off again. It gets
+// picked up by the paint thread with the next message and stepped after each
+// one, so its function gets called on the paint thread. The message index it
+// gets is the number of messages the paint engine has handled.
+void DP_paint_engine_thumbnailer_set_noinc(DP_PaintEngine *pe,
+                                           DP_Thumbnailer *t_or_null);
+
+// Local and remote messages waiting for the paint thread to handle them. Only
+// a snapshot, since the paint thread keeps working through them concurrently.
+int DP_paint_engine_queued_message_count(DP_PaintEngine *pe);
+
 void DP_paint_engine_local_drawing_in_progress_set(
     DP_PaintEngine *pe, bool local_drawing_in_progress);
 
@@ -101,6 +168,11 @@ bool DP_paint_engine_want_canvas_history_dump(DP_PaintEngine *pe);
 void DP_paint_engine_want_canvas_history_dump_set(
     DP_PaintEngine *pe, bool want_canvas_history_dump);
 
+// Soft limit for the memory used by history messages and save points in MiB,
+// zero means no limit. Every so often on undo points, the paint thread checks
+// if the limit is exceeded and trims the history down if so.
+void DP_paint_engine_history_soft_limit_set(DP_PaintEngine *pe, int limit_mib);
+
 bool DP_paint_engine_local_state_reset_image_build(
     DP_PaintEngine *pe, DP_LocalStateAcceptResetMessageFn fn, void *user);
 
@@ -194,12 +266,14 @@ bool DP_paint_engine_playback_flush(DP_PaintEngine *pe,
 bool DP_paint_engine_playback_close(DP_PaintEngine *pe);
 
 // Returns the number of drawing commands actually pushed to the paint engine.
+// Chat messages that got past the ACLs are passed to the chat callback, which
+// may be NULL if the caller deals with chat on its own.
 int DP_paint_engine_handle_inc(DP_PaintEngine *pe, bool local,
                                bool override_acls, int count, DP_Message **msgs,
                                DP_PaintEngineAclsChangedFn acls_changed,
                                DP_PaintEngineLaserTrailFn laser_trail,
                                DP_PaintEngineMovePointerFn move_pointer,
-                               void *user);
+                               DP_PaintEngineChatFn chat, void *user);
 
 void DP_paint_engine_tick(
     DP_PaintEngine *pe, DP_Rect tile_bounds, bool render_outside_tile_bounds,
@@ -230,6 +304,12 @@ void DP_paint_engine_preview_transform(
     DP_PreviewTransformGetPixelsFn get_pixels,
     DP_PreviewTransformDisposePixelsFn dispose_pixels, void *user);
 
+// Moves the current transform preview to a new destination without copying the
+// source pixels again. Returns false if there's no transform preview to move.
+bool DP_paint_engine_preview_transform_move(DP_PaintEngine *pe, int x, int y,
+                                            const DP_Quad *dst_quad,
+                                            int interpolation);
+
 void DP_paint_engine_preview_dabs_inc(DP_PaintEngine *pe, int layer_id,
                                       int count, DP_Message **messages);
 
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: MIT
+#include "pattern.h"
+#include "image.h"
+#include "layer_content.h"
+#include "pixels.h"
+#include "selection.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+
+
+// Modulo that stays positive for negative values, so that the pattern keeps
+// repeating the same way to the left of and above its origin.
+static int wrap(int value, int size)
+{
+    int result = value % size;
+    return result < 0 ? result + size : result;
+}
+
+static uint8_t scale_channel(uint8_t c, uint32_t factor)
+{
+    return (uint8_t)((c * factor + DP_BIT15 / 2) / DP_BIT15);
+}
+
+DP_Image *DP_pattern_render(DP_Image *pattern, int offset_x, int offset_y,
+                            uint16_t opacity, DP_Rect rect,
+                            DP_Selection *sel_or_null)
+{
+    DP_ASSERT(pattern);
+    DP_ASSERT(opacity <= DP_BIT15);
+    DP_ASSERT(DP_rect_valid(rect));
+    int pattern_width = DP_image_width(pattern);
+    int pattern_height = DP_image_height(pattern);
+    DP_Pixel8 *pattern_pixels = DP_image_pixels(pattern);
+    int width = DP_rect_width(rect);
+    int height = DP_rect_height(rect);
+    DP_Image *img = DP_image_new(width, height);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    // Sampling goes by the canvas position rather than by stepping through
+    // the pattern, so it can't drift when the pattern size doesn't line up
+    // with tiles or the rectangle.
+    for (int y = 0; y < height; ++y) {
+        int canvas_y = rect.y1 + y;
+        int pattern_y = wrap(canvas_y - offset_y, pattern_height);
+        for (int x = 0; x < width; ++x) {
+            int canvas_x = rect.x1 + x;
+            uint32_t factor =
+                sel_or_null
+                    ? (opacity
+                           * DP_selection_contains(sel_or_null, canvas_x,
+                                                   canvas_y)
+                       + 127u)
+                          / 255u
+                    : opacity;
+            if (factor != 0) {
+                int pattern_x = wrap(canvas_x - offset_x, pattern_width);
+                DP_Pixel8 pixel =
+                    pattern_pixels[pattern_y * pattern_width + pattern_x];
+                pixels[y * width + x] = (DP_Pixel8){
+                    .b = scale_channel(pixel.b, factor),
+                    .g = scale_channel(pixel.g, factor),
+                    .r = scale_channel(pixel.r, factor),
+                    .a = scale_channel(pixel.a, factor),
+                };
+            }
+        }
+    }
+    return img;
+}
+
+DP_Rect DP_pattern_fill(DP_TransientLayerContent *tlc, unsigned int context_id,
+                        int blend_mode, uint16_t opacity, DP_Image *pattern,
+                        int offset_x, int offset_y, const DP_Rect *rect_or_null,
+                        DP_Selection *sel_or_null)
+{
+    DP_ASSERT(tlc);
+    DP_ASSERT(pattern);
+    DP_Rect area =
+        DP_rect_make(0, 0, DP_transient_layer_content_width(tlc),
+                     DP_transient_layer_content_height(tlc));
+    if (rect_or_null) {
+        area = DP_rect_intersection(area, *rect_or_null);
+    }
+    if (sel_or_null) {
+        area = DP_rect_intersection(area, DP_selection_bounds(sel_or_null));
+    }
+
+    if (opacity == 0) {
+        return (DP_Rect){0, 0, -1, -1};
+    }
+    else if (DP_rect_valid(area)) {
+        DP_Image *img = DP_pattern_render(pattern, offset_x, offset_y, opacity,
+                                          area, sel_or_null);
+        DP_transient_layer_content_put_image(tlc, context_id, blend_mode,
+                                             area.x1, area.y1, img);
+        DP_image_free(img);
+    }
+    return area;
+}
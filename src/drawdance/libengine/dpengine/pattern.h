@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_PATTERN_H
+#define DPENGINE_PATTERN_H
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+typedef struct DP_Image DP_Image;
+typedef struct DP_Selection DP_Selection;
+
+#ifdef DP_NO_STRICT_ALIASING
+typedef struct DP_TransientLayerContent DP_TransientLayerContent;
+#else
+typedef struct DP_LayerContent DP_TransientLayerContent;
+#endif
+
+
+// Renders the pattern repeated across the given rectangle into a new image.
+// The pattern is tiled in canvas space with its top-left corner at the given
+// offset, so separate fills with the same offset line up without seams.
+// Pixels get multiplied by the 15 bit opacity and by the coverage of the
+// selection if one is given.
+DP_Image *DP_pattern_render(DP_Image *pattern, int offset_x, int offset_y,
+                            uint16_t opacity, DP_Rect rect,
+                            DP_Selection *sel_or_null);
+
+// Blends the repeated pattern into the layer within the given rectangle, or
+// all of the layer if it's NULL, as well as the selection if one is given.
+// Returns the area that got drawn to, or an invalid rectangle if there's none.
+DP_Rect DP_pattern_fill(DP_TransientLayerContent *tlc, unsigned int context_id,
+                        int blend_mode, uint16_t opacity, DP_Image *pattern,
+                        int offset_x, int offset_y, const DP_Rect *rect_or_null,
+                        DP_Selection *sel_or_null);
+
+
+#endif
@@ -419,6 +419,88 @@ DP_UPixel8 DP_upixel_float_to_8(DP_UPixelFloat pixel)
     };
 }
 
+static float srgb_to_linear(float c)
+{
+    return c <= 0.04045f ? c / 12.92f : powf((c + 0.055f) / 1.055f, 2.4f);
+}
+
+static float linear_to_srgb(float c)
+{
+    return c <= 0.0031308f ? c * 12.92f
+                           : 1.055f * powf(c, 1.0f / 2.4f) - 0.055f;
+}
+
+static float mix_channel_linear(float a, float b, float amount)
+{
+    float la = srgb_to_linear(a);
+    float lb = srgb_to_linear(b);
+    return linear_to_srgb(la + (lb - la) * amount);
+}
+
+DP_UPixelFloat DP_upixel_float_mix_linear(DP_UPixelFloat a, DP_UPixelFloat b,
+                                          float amount)
+{
+    return (DP_UPixelFloat){
+        .b = mix_channel_linear(a.b, b.b, amount),
+        .g = mix_channel_linear(a.g, b.g, amount),
+        .r = mix_channel_linear(a.r, b.r, amount),
+        .a = a.a + (b.a - a.a) * amount,
+    };
+}
+
+// See https://bottosson.github.io/posts/oklab/ for where the numbers come from.
+static void upixel_float_to_oklab(DP_UPixelFloat pixel, double *out_l,
+                                  double *out_a, double *out_b)
+{
+    double r = srgb_to_linear(pixel.r);
+    double g = srgb_to_linear(pixel.g);
+    double b = srgb_to_linear(pixel.b);
+    double l = cbrt(0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b);
+    double m = cbrt(0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b);
+    double s = cbrt(0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b);
+    *out_l = 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s;
+    *out_a = 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s;
+    *out_b = 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s;
+}
+
+double DP_upixel_float_distance(DP_UPixelFloat a, DP_UPixelFloat b)
+{
+    double l1, a1, b1, l2, a2, b2;
+    upixel_float_to_oklab(a, &l1, &a1, &b1);
+    upixel_float_to_oklab(b, &l2, &a2, &b2);
+    double dl = l1 - l2;
+    double da = a1 - a2;
+    double db = b1 - b2;
+    double weight = DP_min_double(a.a, b.a);
+    double dalpha = a.a - b.a;
+    double distance = sqrt((dl * dl + da * da + db * db) * weight * weight
+                           + dalpha * dalpha);
+    return DP_min_double(distance, 1.0);
+}
+
+float DP_upixel_float_gray(DP_UPixelFloat pixel, DP_GrayscaleMethod method)
+{
+    float r = pixel.r;
+    float g = pixel.g;
+    float b = pixel.b;
+    switch (method) {
+    case DP_GRAYSCALE_METHOD_LUMINANCE:
+        return linear_to_srgb(0.2126f * srgb_to_linear(r)
+                              + 0.7152f * srgb_to_linear(g)
+                              + 0.0722f * srgb_to_linear(b));
+    case DP_GRAYSCALE_METHOD_LUMA:
+        return 0.299f * r + 0.587f * g + 0.114f * b;
+    case DP_GRAYSCALE_METHOD_AVERAGE:
+        return (r + g + b) / 3.0f;
+    case DP_GRAYSCALE_METHOD_LIGHTNESS:
+        return (DP_max_float(r, DP_max_float(g, b))
+                + DP_min_float(r, DP_min_float(g, b)))
+             * 0.5f;
+    default:
+        DP_UNREACHABLE();
+    }
+}
+
 void DP_pixels8_to_15(DP_Pixel15 *dst, const DP_Pixel8 *src, int count)
 {
     DP_ASSERT(count <= 0 || dst);
@@ -841,10 +923,10 @@ static void blend_tile_normal_sse42(DP_Pixel15 *DP_RESTRICT dst,
         __m128i srcAO = mul_sse42(srcA, o);
         __m128i as1 = _mm_sub_epi32(_mm_set1_epi32(DP_BIT15), srcAO); // as1 = DP_BIT15 - srcA * o
 
-        dstB = _mm_add_epi32(mul_sse42(dstB, as1), mul_sse42(srcB, o)); // dstB = (dstB * as1) + (srcB * o)
-        dstG = _mm_add_epi32(mul_sse42(dstG, as1), mul_sse42(srcG, o)); // dstG = (dstG * as1) + (srcG * o)
-        dstR = _mm_add_epi32(mul_sse42(dstR, as1), mul_sse42(srcR, o)); // dstR = (dstR * as1) + (srcR * o)
-        dstA = _mm_add_epi32(mul_sse42(dstA, as1), srcAO);              // dstA = (dstA * as1) + (srcA * o)
+        dstB = sumprods_sse42(dstB, as1, srcB, o); // dstB = (dstB * as1) + (srcB * o)
+        dstG = sumprods_sse42(dstG, as1, srcG, o); // dstG = (dstG * as1) + (srcG * o)
+        dstR = sumprods_sse42(dstR, as1, srcR, o); // dstR = (dstR * as1) + (srcR * o)
+        dstA = sumprods_sse42(dstA, as1, srcA, o); // dstA = (dstA * as1) + (srcA * o)
 
         // store
         store_aligned_sse42(dstB, dstG, dstR, dstA, &dst[i]);
@@ -911,10 +993,10 @@ static void blend_mask_pixels_normal_sse42(DP_Pixel15 *dst, DP_UPixel15 src,
         __m128i srcAO = mul_sse42(srcA, o);
         __m128i as1 = _mm_sub_epi32(_mm_set1_epi32(DP_BIT15), srcAO); // as1 = DP_BIT15 - srcA * o
 
-        dstB = _mm_add_epi32(mul_sse42(dstB, as1), mul_sse42(srcB, o)); // dstB = (dstB * as1) + (srcB * o)
-        dstG = _mm_add_epi32(mul_sse42(dstG, as1), mul_sse42(srcG, o)); // dstG = (dstG * as1) + (srcG * o)
-        dstR = _mm_add_epi32(mul_sse42(dstR, as1), mul_sse42(srcR, o)); // dstR = (dstR * as1) + (srcR * o)
-        dstA = _mm_add_epi32(mul_sse42(dstA, as1), srcAO);              // dstA = (dstA * as1) + (srcA * o)
+        dstB = sumprods_sse42(dstB, as1, srcB, o); // dstB = (dstB * as1) + (srcB * o)
+        dstG = sumprods_sse42(dstG, as1, srcG, o); // dstG = (dstG * as1) + (srcG * o)
+        dstR = sumprods_sse42(dstR, as1, srcR, o); // dstR = (dstR * as1) + (srcR * o)
+        dstA = sumprods_sse42(dstA, as1, srcA, o); // dstA = (dstA * as1) + (srcA * o)
 
         store_unaligned_sse42(dstB, dstG, dstR, dstA, dst);
     }
@@ -1121,10 +1203,10 @@ static void blend_tile_normal_avx2(DP_Pixel15 *DP_RESTRICT dst,
         __m256i srcAO = mul_avx2(srcA, o);
         __m256i as1 = _mm256_sub_epi32(_mm256_set1_epi32(1 << 15), srcAO); // as1 = 1 - srcA * o
 
-        dstB = _mm256_add_epi32(mul_avx2(dstB, as1), mul_avx2(srcB, o)); // dstB = (dstB * as1) + (srcB * o)
-        dstG = _mm256_add_epi32(mul_avx2(dstG, as1), mul_avx2(srcG, o)); // dstG = (dstG * as1) + (srcG * o)
-        dstR = _mm256_add_epi32(mul_avx2(dstR, as1), mul_avx2(srcR, o)); // dstR = (dstR * as1) + (srcR * o)
-        dstA = _mm256_add_epi32(mul_avx2(dstA, as1), srcAO);             // dstA = (dstA * as1) + (srcA * o)
+        dstB = sumprods_avx2(dstB, as1, srcB, o); // dstB = (dstB * as1) + (srcB * o)
+        dstG = sumprods_avx2(dstG, as1, srcG, o); // dstG = (dstG * as1) + (srcG * o)
+        dstR = sumprods_avx2(dstR, as1, srcR, o); // dstR = (dstR * as1) + (srcR * o)
+        dstA = sumprods_avx2(dstA, as1, srcA, o); // dstA = (dstA * as1) + (srcA * o)
 
         // store
         store_aligned_avx2(dstB, dstG, dstR, dstA, &dst[i]);
@@ -1195,10 +1277,10 @@ static void blend_mask_pixels_normal_avx2(DP_Pixel15 *dst, DP_UPixel15 src,
         __m256i srcAO = mul_avx2(srcA, o);
         __m256i as1 = _mm256_sub_epi32(_mm256_set1_epi32(1 << 15), srcAO); // as1 = 1 - srcA * o
 
-        dstB = _mm256_add_epi32(mul_avx2(dstB, as1), mul_avx2(srcB, o)); // dstB = (dstB * as1) + (srcB * o)
-        dstG = _mm256_add_epi32(mul_avx2(dstG, as1), mul_avx2(srcG, o)); // dstG = (dstG * as1) + (srcG * o)
-        dstR = _mm256_add_epi32(mul_avx2(dstR, as1), mul_avx2(srcR, o)); // dstR = (dstR * as1) + (srcR * o)
-        dstA = _mm256_add_epi32(mul_avx2(dstA, as1), srcAO);             // dstA = (dstA * as1) + (srcA * o)
+        dstB = sumprods_avx2(dstB, as1, srcB, o); // dstB = (dstB * as1) + (srcB * o)
+        dstG = sumprods_avx2(dstG, as1, srcG, o); // dstG = (dstG * as1) + (srcG * o)
+        dstR = sumprods_avx2(dstR, as1, srcR, o); // dstR = (dstR * as1) + (srcR * o)
+        dstA = sumprods_avx2(dstA, as1, srcA, o); // dstA = (dstA * as1) + (srcA * o)
 
         store_unaligned_avx2(dstB, dstG, dstR, dstA, dst);
     }
@@ -1293,6 +1375,66 @@ static void blend_mask_pixels_recolor_avx2(DP_Pixel15 *dst, DP_UPixel15 src,
 DP_TARGET_END
 #endif
 
+#ifdef DP_CPU_ARM64
+// NEON loads de-interleave 4 pixels into one register per channel, which get
+// widened to 32 bits so that the products fit. The rounding is the same as in
+// the scalar blend_normal, pixel for pixel.
+static uint32x4_t mul_neon(uint32x4_t a, uint32x4_t b)
+{
+    return vshrq_n_u32(vmulq_u32(a, b), 15);
+}
+
+static uint32x4_t sumprods_neon(uint32x4_t a1, uint32x4_t a2, uint32x4_t b1,
+                                uint32x4_t b2)
+{
+    return vshrq_n_u32(vmlaq_u32(vmulq_u32(a1, a2), b1, b2), 15);
+}
+
+static void blend_tile_normal_neon(DP_Pixel15 *DP_RESTRICT dst,
+                                   const DP_Pixel15 *DP_RESTRICT src,
+                                   uint16_t opacity)
+{
+    uint32x4_t o = vdupq_n_u32(opacity);
+    uint32x4_t bit15 = vdupq_n_u32(DP_BIT15);
+
+    for (int i = 0; i < DP_TILE_LENGTH; i += 4) {
+        uint16x4x4_t s = vld4_u16((const uint16_t *)&src[i]);
+        uint16x4x4_t d = vld4_u16((const uint16_t *)&dst[i]);
+
+        uint32x4_t as1 = vsubq_u32(bit15, mul_neon(vmovl_u16(s.val[3]), o));
+        for (int c = 0; c < 4; ++c) {
+            d.val[c] = vmovn_u32(sumprods_neon(vmovl_u16(d.val[c]), as1,
+                                               vmovl_u16(s.val[c]), o));
+        }
+
+        vst4_u16((uint16_t *)&dst[i], d);
+    }
+}
+
+static void blend_mask_pixels_normal_neon(DP_Pixel15 *dst, DP_UPixel15 src,
+                                          const uint16_t *mask, Fix15 opacity,
+                                          int count)
+{
+    DP_ASSERT(count % 4 == 0);
+    uint32x4_t bit15 = vdupq_n_u32(DP_BIT15);
+    uint32x4_t color[4] = {vdupq_n_u32(src.b), vdupq_n_u32(src.g),
+                           vdupq_n_u32(src.r), bit15};
+    uint32x4_t opacity4 = vdupq_n_u32((uint32_t)opacity);
+
+    for (int x = 0; x < count; x += 4, dst += 4, mask += 4) {
+        uint32x4_t o = mul_neon(vmovl_u16(vld1_u16(mask)), opacity4);
+        uint32x4_t as1 = vsubq_u32(bit15, mul_neon(bit15, o));
+
+        uint16x4x4_t d = vld4_u16((const uint16_t *)dst);
+        for (int c = 0; c < 4; ++c) {
+            d.val[c] = vmovn_u32(
+                sumprods_neon(vmovl_u16(d.val[c]), as1, color[c], o));
+        }
+        vst4_u16((uint16_t *)dst, d);
+    }
+}
+#endif
+
 static BGRA15 blend_normal(BGR15 cb, BGR15 cs, Fix15 ab, Fix15 as, Fix15 o)
 {
     Fix15 as1 = BIT15_FIX - fix15_mul(as, o);
@@ -1761,6 +1903,22 @@ static void blend_mask_normal(DP_Pixel15 *dst, DP_UPixel15 src,
         dst += base_skip;
         mask += mask_skip;
     }
+#elif defined(DP_CPU_ARM64)
+    for (int y = 0; y < h; ++y) {
+        int remaining = w;
+
+        if (DP_cpu_support >= DP_CPU_SUPPORT_NEON) {
+            int neon_width = remaining - remaining % 4;
+            blend_mask_pixels_normal_neon(dst, src, mask, opacity, neon_width);
+            remaining -= neon_width;
+            dst += neon_width;
+            mask += neon_width;
+        }
+
+        blend_mask_pixels_normal(dst, src, mask, opacity, remaining);
+        dst += remaining + base_skip;
+        mask += remaining + mask_skip;
+    }
 #else
     for (int y = 0; y < h; ++y) {
         blend_mask_pixels_normal(dst, src, mask, opacity, w);
@@ -2236,6 +2394,7 @@ void DP_blend_tile(DP_Pixel15 *DP_RESTRICT dst,
         case DP_CPU_SUPPORT_AVX2:
             blend_tile_normal_avx2(aligned_dst, aligned_src, opacity);
             return;
+        case DP_CPU_SUPPORT_AVX:
         case DP_CPU_SUPPORT_SSE42:
             blend_tile_normal_sse42(aligned_dst, aligned_src, opacity);
             return;
@@ -2248,6 +2407,7 @@ void DP_blend_tile(DP_Pixel15 *DP_RESTRICT dst,
         case DP_CPU_SUPPORT_AVX2:
             blend_tile_behind_avx2(aligned_dst, aligned_src, opacity);
             return;
+        case DP_CPU_SUPPORT_AVX:
         case DP_CPU_SUPPORT_SSE42:
             blend_tile_behind_sse42(aligned_dst, aligned_src, opacity);
             return;
@@ -2258,6 +2418,12 @@ void DP_blend_tile(DP_Pixel15 *DP_RESTRICT dst,
     default:
         break;
     }
+#elif defined(DP_CPU_ARM64)
+    if (blend_mode == DP_BLEND_MODE_NORMAL
+        && DP_cpu_support >= DP_CPU_SUPPORT_NEON) {
+        blend_tile_normal_neon(aligned_dst, aligned_src, opacity);
+        return;
+    }
 #endif
     DP_blend_pixels(aligned_dst, aligned_src, DP_TILE_LENGTH, opacity,
                     blend_mode);
@@ -29,9 +29,20 @@
 
 #define DP_BIT15 (1 << 15)
 
-#define DP_TILE_SIZE   64
+// Tiles are 64 pixels square unless the build picks another size through the
+// DRAWDANCE_TILE_SIZE CMake option. Tiles go over the network and into
+// recordings as they are, so builds with different sizes can't talk to each
+// other. Larger tiles don't fit into a put tile message when they don't
+// compress well, see the check in snapshots.c.
+#ifndef DP_TILE_SIZE
+#    define DP_TILE_SIZE 64
+#endif
 #define DP_TILE_LENGTH (DP_TILE_SIZE * DP_TILE_SIZE)
 
+#if DP_TILE_SIZE != 32 && DP_TILE_SIZE != 64
+#    error "DP_TILE_SIZE must be 32 or 64"
+#endif
+
 // Premultiplied 8 bit pixel
 typedef union DP_Pixel8 {
     uint32_t color;
@@ -79,6 +90,19 @@ typedef struct DP_UPixelFloat {
     float b, g, r, a;
 } DP_UPixelFloat;
 
+// Ways of turning a color into a gray value. Luminance weighs the channels by
+// how bright they appear in linear light, which keeps saturated colors from
+// coming out too dark or too bright. Luma uses the older Rec. 601 weights on
+// the gamma-encoded channels, average counts them all the same and lightness
+// takes the middle between the brightest and darkest one.
+typedef enum DP_GrayscaleMethod {
+    DP_GRAYSCALE_METHOD_LUMINANCE,
+    DP_GRAYSCALE_METHOD_LUMA,
+    DP_GRAYSCALE_METHOD_AVERAGE,
+    DP_GRAYSCALE_METHOD_LIGHTNESS,
+    DP_GRAYSCALE_METHOD_COUNT,
+} DP_GrayscaleMethod;
+
 
 uint16_t DP_fix15_mul(uint16_t a, uint16_t b);
 
@@ -98,6 +122,22 @@ DP_UPixelFloat DP_upixel15_to_float(DP_UPixel15 pixel);
 DP_UPixelFloat DP_upixel15_to_float_round(DP_UPixel15 pixel);
 DP_UPixel8 DP_upixel_float_to_8(DP_UPixelFloat pixel);
 
+// Mixes the color channels in linear light, so that a blend between two
+// saturated colors doesn't come out dark and muddy in the middle. Alpha gets
+// mixed as-is. An amount of 0 gives a, 1 gives b.
+DP_UPixelFloat DP_upixel_float_mix_linear(DP_UPixelFloat a, DP_UPixelFloat b,
+                                          float amount);
+
+// Perceptual distance between two unpremultiplied colors, from 0 if they're
+// the same to 1 for black and white or transparent and opaque. The colors
+// get compared in the OKLab color space, weighted by the alpha of the more
+// transparent one, so that the color of barely visible pixels doesn't count
+// for much. Alpha differences then get added on top.
+double DP_upixel_float_distance(DP_UPixelFloat a, DP_UPixelFloat b);
+
+// Gray value of an unpremultiplied color from 0 to 1, ignoring alpha.
+float DP_upixel_float_gray(DP_UPixelFloat pixel, DP_GrayscaleMethod method);
+
 void DP_pixels8_to_15(DP_Pixel15 *dst, const DP_Pixel8 *src, int count);
 
 // Checks the color channel of each source pixel if it's less than or equal to
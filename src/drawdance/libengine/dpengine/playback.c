@@ -0,0 +1,403 @@
+// SPDX-License-Identifier: MIT
+#include "playback.h"
+#include "canvas_history.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpmsg/message.h>
+#include <math.h>
+
+
+// Slack for rounding errors when scaling time by the speed, so that waiting
+// exactly as long as told always finishes the interval.
+#define TIME_EPSILON 0.000001
+
+struct DP_Playback {
+    DP_Player *player;
+    DP_CanvasHistory *ch;
+    bool playing;
+    bool finished;
+    double speed;
+    double pending_ms;
+    double canvas_time_ms;
+};
+
+
+DP_Playback *DP_playback_new(DP_Player *player)
+{
+    DP_ASSERT(player);
+    // Seeking resets the player's permissions, which would make the same
+    // message get filtered or not depending on how the position was reached.
+    DP_player_acl_override_set(player, true);
+    DP_Playback *pb = DP_malloc(sizeof(*pb));
+    *pb = (DP_Playback){player,
+                        DP_canvas_history_new(NULL, NULL, false, NULL),
+                        false,
+                        false,
+                        1.0,
+                        0.0,
+                        0.0};
+    return pb;
+}
+
+void DP_playback_free(DP_Playback *pb)
+{
+    if (pb) {
+        DP_canvas_history_free(pb->ch);
+        DP_player_free(pb->player);
+        DP_free(pb);
+    }
+}
+
+
+DP_Player *DP_playback_player(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return pb->player;
+}
+
+DP_CanvasHistory *DP_playback_canvas_history(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return pb->ch;
+}
+
+bool DP_playback_playing(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return pb->playing;
+}
+
+bool DP_playback_finished(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return pb->finished;
+}
+
+double DP_playback_speed(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return pb->speed;
+}
+
+long long DP_playback_message_index(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return DP_player_position(pb->player);
+}
+
+long long DP_playback_canvas_time_ms(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    return DP_double_to_llong(pb->canvas_time_ms + TIME_EPSILON);
+}
+
+
+void DP_playback_play(DP_Playback *pb, double speed)
+{
+    DP_ASSERT(pb);
+    DP_ASSERT(speed > 0.0);
+    pb->speed = speed;
+    pb->playing = !pb->finished;
+}
+
+void DP_playback_pause(DP_Playback *pb)
+{
+    DP_ASSERT(pb);
+    pb->playing = false;
+}
+
+
+static void handle_message(DP_Playback *pb, DP_DrawContext *dc,
+                           DP_Message *msg, DP_MessageType type)
+{
+    DP_CanvasHistory *ch = pb->ch;
+    if (type == DP_MSG_UNDO_DEPTH) {
+        DP_MsgUndoDepth *mud = DP_message_internal(msg);
+        DP_canvas_history_undo_depth_limit_set(ch, dc,
+                                               DP_msg_undo_depth_depth(mud));
+    }
+    else if (type == DP_MSG_SOFT_RESET) {
+        DP_canvas_history_soft_reset(ch, dc, DP_message_context_id(msg), NULL,
+                                     NULL);
+    }
+    else if (DP_message_type_command(type)) {
+        if (!DP_canvas_history_handle(ch, dc, msg)) {
+            DP_warn("Handle playback command: %s", DP_error());
+        }
+    }
+}
+
+// Reads and handles the next message. Intervals become pending instead.
+static DP_PlayerResult read_next(DP_Playback *pb, DP_DrawContext *dc,
+                                 DP_MessageType *out_type)
+{
+    DP_Message *msg;
+    DP_PlayerResult result = DP_player_step(pb->player, &msg);
+    if (result == DP_PLAYER_SUCCESS) {
+        DP_MessageType type = DP_message_type(msg);
+        if (type == DP_MSG_INTERVAL) {
+            DP_MsgInterval *mi = DP_message_internal(msg);
+            pb->pending_ms = DP_msg_interval_msecs(mi);
+        }
+        else {
+            handle_message(pb, dc, msg, type);
+        }
+        DP_message_decref(msg);
+        *out_type = type;
+        return DP_PLAYER_SUCCESS;
+    }
+    else if (result == DP_PLAYER_ERROR_PARSE) {
+        DP_warn("Can't play back message: %s", DP_error());
+        *out_type = DP_MSG_TYPE_COUNT;
+        return DP_PLAYER_SUCCESS;
+    }
+    else {
+        pb->playing = false;
+        pb->finished = true;
+        return result;
+    }
+}
+
+static void skip_pending(DP_Playback *pb)
+{
+    pb->canvas_time_ms += pb->pending_ms;
+    pb->pending_ms = 0.0;
+}
+
+static long long wait_ms(DP_Playback *pb)
+{
+    double wait = ceil(pb->pending_ms / pb->speed - TIME_EPSILON);
+    return wait < 0.0 ? 0 : DP_double_to_llong(wait);
+}
+
+DP_PlayerResult DP_playback_poll(DP_Playback *pb, DP_DrawContext *dc,
+                                 long long elapsed_ms, long long *out_wait_ms)
+{
+    DP_ASSERT(pb);
+    DP_ASSERT(dc);
+    DP_ASSERT(elapsed_ms >= 0);
+    DP_ASSERT(out_wait_ms);
+    if (!pb->playing) {
+        *out_wait_ms = -1;
+        return pb->finished ? DP_PLAYER_RECORDING_END : DP_PLAYER_SUCCESS;
+    }
+
+    double budget_ms = DP_llong_to_double(elapsed_ms) * pb->speed;
+    while (true) {
+        if (pb->pending_ms > 0.0) {
+            if (budget_ms + TIME_EPSILON < pb->pending_ms) {
+                pb->pending_ms -= budget_ms;
+                pb->canvas_time_ms += budget_ms;
+                break;
+            }
+            else {
+                budget_ms -= pb->pending_ms;
+                skip_pending(pb);
+            }
+        }
+
+        DP_MessageType type;
+        DP_PlayerResult result = read_next(pb, dc, &type);
+        if (result != DP_PLAYER_SUCCESS) {
+            *out_wait_ms = -1;
+            return result;
+        }
+    }
+
+    *out_wait_ms = wait_ms(pb);
+    return DP_PLAYER_SUCCESS;
+}
+
+DP_PlayerResult DP_playback_step_one(DP_Playback *pb, DP_DrawContext *dc)
+{
+    DP_ASSERT(pb);
+    DP_ASSERT(dc);
+    skip_pending(pb);
+    DP_MessageType type;
+    DP_PlayerResult result = read_next(pb, dc, &type);
+    skip_pending(pb);
+    return result;
+}
+
+DP_PlayerResult DP_playback_next_stop(DP_Playback *pb, DP_DrawContext *dc)
+{
+    DP_ASSERT(pb);
+    DP_ASSERT(dc);
+    skip_pending(pb);
+    while (true) {
+        DP_MessageType type;
+        DP_PlayerResult result = read_next(pb, dc, &type);
+        skip_pending(pb);
+        if (result != DP_PLAYER_SUCCESS || type == DP_MSG_UNDO_POINT
+            || type == DP_MSG_INTERVAL) {
+            return result;
+        }
+    }
+}
+
+static DP_PlayerResult replay_to(DP_Playback *pb, DP_DrawContext *dc,
+                                 long long position)
+{
+    while (DP_player_position(pb->player) < position) {
+        DP_MessageType type;
+        skip_pending(pb);
+        DP_PlayerResult result = read_next(pb, dc, &type);
+        skip_pending(pb);
+        if (result != DP_PLAYER_SUCCESS) {
+            return result;
+        }
+    }
+    return DP_PLAYER_SUCCESS;
+}
+
+// Index entries hold the canvas after handling the message at their index, so
+// they're one message ahead of that. An entry without a snapshot is the start
+// of the recording instead.
+static long long snapshot_position(DP_PlayerIndexEntry entry)
+{
+    return entry.snapshot_offset == 0 ? 0 : entry.message_index + 1;
+}
+
+static DP_PlayerResult load_snapshot(DP_Playback *pb, DP_DrawContext *dc,
+                                     DP_PlayerIndexEntry entry)
+{
+    DP_Player *player = pb->player;
+    DP_PlayerIndexEntrySnapshot *snapshot =
+        DP_player_index_entry_load(player, dc, entry);
+    if (!snapshot) {
+        return DP_PLAYER_ERROR_INPUT;
+    }
+
+    if (!DP_player_seek(player, entry.message_index, entry.message_offset)) {
+        DP_player_index_entry_snapshot_free(snapshot);
+        return DP_PLAYER_ERROR_INPUT;
+    }
+
+    // The offset points at the message the snapshot was taken after, which
+    // is already in there, so it has to be skipped.
+    if (entry.snapshot_offset != 0) {
+        DP_Message *msg;
+        DP_PlayerResult result = DP_player_step(player, &msg);
+        if (result == DP_PLAYER_SUCCESS) {
+            DP_message_decref(msg);
+        }
+        // The final entry points at the end of the recording, so there's
+        // nothing left to skip there.
+        else if (result != DP_PLAYER_ERROR_PARSE
+                 && result != DP_PLAYER_RECORDING_END) {
+            DP_player_index_entry_snapshot_free(snapshot);
+            return result;
+        }
+    }
+
+    DP_canvas_history_reset_to_state_noinc(
+        pb->ch, DP_player_index_entry_snapshot_canvas_state_inc(snapshot));
+    pb->canvas_time_ms = DP_llong_to_double(entry.elapsed_ms);
+    pb->pending_ms = 0.0;
+    int count = DP_player_index_entry_snapshot_message_count(snapshot);
+    for (int i = 0; i < count; ++i) {
+        DP_Message *msg =
+            DP_player_index_entry_snapshot_message_at_inc(snapshot, i);
+        if (msg) {
+            handle_message(pb, dc, msg, DP_message_type(msg));
+            DP_message_decref(msg);
+        }
+    }
+    DP_player_index_entry_snapshot_free(snapshot);
+    return DP_PLAYER_SUCCESS;
+}
+
+DP_PlayerResult DP_playback_seek_to(DP_Playback *pb, DP_DrawContext *dc,
+                                    long long position,
+                                    long long *out_replayed_or_null)
+{
+    DP_ASSERT(pb);
+    DP_ASSERT(dc);
+    DP_Player *player = pb->player;
+    if (!DP_player_index_loaded(player)) {
+        DP_error_set("No index loaded");
+        return DP_PLAYER_ERROR_OPERATION;
+    }
+
+    long long message_count =
+        DP_uint_to_llong(DP_player_index_message_count(player));
+    long long target = position;
+    if (target < 0) {
+        target = 0;
+    }
+    else if (target > message_count) {
+        target = message_count;
+    }
+
+    DP_PlayerIndexEntry entry =
+        DP_player_index_entry_search(player, target - 1, false);
+
+    // If we're already between the snapshot and the target, loading the
+    // snapshot would just replay stuff that's already on the canvas.
+    long long start = DP_player_position(player);
+    bool inside_snapshot = !pb->finished
+                        && start >= snapshot_position(entry) && start <= target;
+    if (!inside_snapshot) {
+        DP_PlayerResult result = load_snapshot(pb, dc, entry);
+        if (result != DP_PLAYER_SUCCESS) {
+            pb->playing = false;
+            pb->finished = true;
+            return result;
+        }
+        start = DP_player_position(player);
+        pb->finished = false;
+    }
+
+    DP_PlayerResult result = replay_to(pb, dc, target);
+    if (out_replayed_or_null) {
+        *out_replayed_or_null = DP_player_position(player) - start;
+    }
+    return result;
+}
+
+DP_PlayerResult DP_playback_replay_to(DP_Playback *pb, DP_DrawContext *dc,
+                                      long long position)
+{
+    DP_ASSERT(pb);
+    DP_ASSERT(dc);
+    if (position < 0) {
+        DP_error_set("Negative position %lld", position);
+        return DP_PLAYER_ERROR_OPERATION;
+    }
+
+    DP_Player *player = pb->player;
+    if (DP_player_index_loaded(player)) {
+        long long message_count =
+            DP_uint_to_llong(DP_player_index_message_count(player));
+        if (position > message_count) {
+            DP_error_set("Position %lld is outside of the recording, which has "
+                         "%lld message(s)",
+                         position, message_count);
+            return DP_PLAYER_ERROR_OPERATION;
+        }
+        DP_PlayerResult result = DP_playback_seek_to(pb, dc, position, NULL);
+        // Running into the end is fine, the position was checked above.
+        return result == DP_PLAYER_RECORDING_END ? DP_PLAYER_SUCCESS : result;
+    }
+    else if (position < DP_player_position(player)) {
+        DP_error_set("Can't go back to position %lld without an index",
+                     position);
+        return DP_PLAYER_ERROR_OPERATION;
+    }
+    else {
+        DP_PlayerResult result = replay_to(pb, dc, position);
+        if (result != DP_PLAYER_RECORDING_END) {
+            return result;
+        }
+        // Skipping over trailing markers runs into the end of the recording,
+        // but the position was reached on the way there.
+        else if (DP_player_position(player) >= position) {
+            return DP_PLAYER_SUCCESS;
+        }
+        else {
+            DP_error_set("Position %lld is past the end of the recording",
+                         position);
+            return result;
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_PLAYBACK_H
+#define DPENGINE_PLAYBACK_H
+#include "player.h"
+#include <dpcommon/common.h>
+
+typedef struct DP_CanvasHistory DP_CanvasHistory;
+typedef struct DP_DrawContext DP_DrawContext;
+
+
+// Plays a recording back onto a canvas history of its own, waiting out the
+// intervals in it scaled by the playback speed. There's no thread or timer in
+// here, the caller polls it with how much time passed and gets told how long
+// to wait until the next poll.
+typedef struct DP_Playback DP_Playback;
+
+// Takes ownership of the player and turns on its permission override.
+DP_Playback *DP_playback_new(DP_Player *player);
+
+void DP_playback_free(DP_Playback *pb);
+
+DP_Player *DP_playback_player(DP_Playback *pb);
+
+DP_CanvasHistory *DP_playback_canvas_history(DP_Playback *pb);
+
+bool DP_playback_playing(DP_Playback *pb);
+
+// Whether the end of the recording or an input error was reached.
+bool DP_playback_finished(DP_Playback *pb);
+
+double DP_playback_speed(DP_Playback *pb);
+
+// Number of messages read from the recording so far, intervals included.
+long long DP_playback_message_index(DP_Playback *pb);
+
+// How many milliseconds of intervals have been played through so far.
+long long DP_playback_canvas_time_ms(DP_Playback *pb);
+
+// Starts or continues playing at the given speed, 2.0 waits half as long as
+// the recording says, 0.5 twice as long. Poll right after to get going.
+void DP_playback_play(DP_Playback *pb, double speed);
+
+// Stops playing, the rest of the current interval remains for later.
+void DP_playback_pause(DP_Playback *pb);
+
+// Plays through the given amount of real time, handling messages until an
+// interval is hit that doesn't fit into it anymore. Sets out_wait_ms to the
+// milliseconds until the rest of that interval runs out, which is when the
+// caller should poll again. If not playing or finished, it's set to -1.
+DP_PlayerResult DP_playback_poll(DP_Playback *pb, DP_DrawContext *dc,
+                                 long long elapsed_ms, long long *out_wait_ms);
+
+// Skips the rest of the current interval and handles exactly one message. If
+// that message is an interval, it gets skipped entirely.
+DP_PlayerResult DP_playback_step_one(DP_Playback *pb, DP_DrawContext *dc);
+
+// Skips the rest of the current interval and handles messages up to and
+// including the next undo point or interval, skipping the latter entirely.
+DP_PlayerResult DP_playback_next_stop(DP_Playback *pb, DP_DrawContext *dc);
+
+// Moves to the given message index, forwards or backwards. Requires the
+// player's index to be loaded. Unless the position is a little bit ahead, the
+// canvas gets replaced by the nearest snapshot at or before it, then only the
+// messages after that are replayed. The canvas time is restored from the
+// snapshot and intervals in between are skipped, but still count towards it.
+// Sets out_replayed_or_null to how many messages were replayed.
+DP_PlayerResult DP_playback_seek_to(DP_Playback *pb, DP_DrawContext *dc,
+                                    long long position,
+                                    long long *out_replayed_or_null);
+
+// Gets the canvas to the given message index. If the index is loaded, this
+// seeks like above, otherwise it can only play forward from where it's at.
+// Messages that playback skips, like markers, get stepped over, so the player
+// may end up past the position, but the canvas is the same as at it. Unlike
+// seeking, positions outside of the recording are an error, which gives
+// DP_PLAYER_ERROR_OPERATION, or DP_PLAYER_RECORDING_END if the recording turns
+// out to be shorter while playing towards it.
+DP_PlayerResult DP_playback_replay_to(DP_Playback *pb, DP_DrawContext *dc,
+                                      long long position);
+
+
+#endif
@@ -61,7 +61,7 @@
 #define INDEX_EXTENSION       "dpidx"
 #define INDEX_MAGIC           "DPIDX"
 #define INDEX_MAGIC_LENGTH    6
-#define INDEX_VERSION         12
+#define INDEX_VERSION         16
 #define INDEX_VERSION_LENGTH  2
 #define INDEX_HEADER_LENGTH   (INDEX_MAGIC_LENGTH + INDEX_VERSION_LENGTH + 12)
 #define INITAL_ENTRY_CAPACITY 64
@@ -75,6 +75,8 @@ typedef struct DP_PlayerIndex {
     unsigned int message_count;
     DP_PlayerIndexEntry *entries;
     size_t entry_count;
+    DP_PlayerIndexMarker *markers;
+    size_t marker_count;
 } DP_PlayerIndex;
 
 typedef union DP_PlayerReader {
@@ -105,12 +107,21 @@ struct DP_PlayerIndexEntrySnapshot {
 };
 
 
+static void dispose_markers(DP_PlayerIndexMarker *markers, size_t count)
+{
+    for (size_t i = 0; i < count; ++i) {
+        DP_free(markers[i].text);
+    }
+    DP_free(markers);
+}
+
 static void player_index_dispose(DP_PlayerIndex *pi)
 {
     DP_ASSERT(pi);
     DP_free(pi->entries);
+    dispose_markers(pi->markers, pi->marker_count);
     DP_buffered_input_dispose(&pi->input);
-    *pi = (DP_PlayerIndex){DP_BUFFERED_INPUT_NULL, 0, NULL, 0};
+    *pi = (DP_PlayerIndex){DP_BUFFERED_INPUT_NULL, 0, NULL, 0, NULL, 0};
 }
 
 
@@ -202,7 +213,7 @@ static DP_Player *make_player(DP_PlayerType type, char *recording_path,
                           false,
                           false,
                           false,
-                          {DP_BUFFERED_INPUT_NULL, 0, NULL, 0}};
+                          {DP_BUFFERED_INPUT_NULL, 0, NULL, 0, NULL, 0}};
     return player;
 }
 
@@ -285,7 +296,7 @@ static DP_Player *new_debug_dump_player(DP_Input *input)
                           false,
                           false,
                           false,
-                          {DP_BUFFERED_INPUT_NULL, 0, NULL, 0}};
+                          {DP_BUFFERED_INPUT_NULL, 0, NULL, 0, NULL, 0}};
     return player;
 }
 
@@ -534,12 +545,20 @@ static DP_PlayerResult step_message(DP_Player *player, DP_Message **out_msg)
     return result;
 }
 
-static bool emit_message(DP_Message *msg, DP_Message **out_msg)
+static bool emit_message(DP_Message *msg, DP_Message **out_msg,
+                         bool keep_markers)
 {
     // When playing back a recording, we are a single user in offline mode, so
     // don't allow anything to escape that would mess up the real ACL state,
     // relates to other users in some way or is a control message of some sort.
+    // Markers only get let through for indexing, which never plays them back.
     switch (DP_message_type(msg)) {
+    case DP_MSG_MARKER:
+        if (keep_markers) {
+            *out_msg = msg;
+            return true;
+        }
+        return false;
     case DP_MSG_SERVER_COMMAND:
     case DP_MSG_DISCONNECT:
     case DP_MSG_PING:
@@ -550,7 +569,6 @@ static bool emit_message(DP_Message *msg, DP_Message **out_msg)
     case DP_MSG_CHAT:
     case DP_MSG_TRUSTED_USERS:
     case DP_MSG_PRIVATE_CHAT:
-    case DP_MSG_MARKER:
     case DP_MSG_USER_ACL:
     case DP_MSG_FEATURE_ACCESS_LEVELS:
     case DP_MSG_DATA:
@@ -572,7 +590,8 @@ static bool emit_message(DP_Message *msg, DP_Message **out_msg)
 }
 
 static DP_PlayerResult step_valid_message(DP_Player *player,
-                                          DP_Message **out_msg)
+                                          DP_Message **out_msg,
+                                          bool keep_markers)
 {
     while (true) {
         DP_Message *msg;
@@ -587,7 +606,7 @@ static DP_PlayerResult step_valid_message(DP_Player *player,
                     DP_message_type_enum_name_unprefixed(DP_message_type(msg)),
                     DP_message_context_id(msg));
             }
-            else if (emit_message(msg, out_msg)) {
+            else if (emit_message(msg, out_msg, keep_markers)) {
                 return result;
             }
             DP_message_decref(msg);
@@ -601,7 +620,30 @@ static DP_PlayerResult step_valid_message(DP_Player *player,
     }
 }
 
+static DP_PlayerResult step_checked_message(DP_Player *player,
+                                            DP_Message **out_msg,
+                                            bool keep_markers)
+{
+    if (player->input_error) {
+        DP_error_set("Player input in error state");
+        return DP_PLAYER_ERROR_INPUT;
+    }
+    else if (player->end) {
+        return DP_PLAYER_RECORDING_END;
+    }
+    else {
+        return step_valid_message(player, out_msg, keep_markers);
+    }
+}
+
 DP_PlayerResult DP_player_step(DP_Player *player, DP_Message **out_msg)
+{
+    DP_ASSERT(player);
+    DP_ASSERT(out_msg);
+    return step_checked_message(player, out_msg, false);
+}
+
+DP_PlayerResult DP_player_step_raw(DP_Player *player, DP_Message **out_msg)
 {
     DP_ASSERT(player);
     DP_ASSERT(out_msg);
@@ -613,7 +655,7 @@ DP_PlayerResult DP_player_step(DP_Player *player, DP_Message **out_msg)
         return DP_PLAYER_RECORDING_END;
     }
     else {
-        return step_valid_message(player, out_msg);
+        return step_message(player, out_msg);
     }
 }
 
@@ -733,10 +775,14 @@ bool DP_player_seek_dump(DP_Player *player, long long position)
 }
 
 
+// Tiles are found by pointer first and by their checksum second, so that
+// identical tiles in different places only get written once.
 typedef struct DP_BuildIndexTileMap {
     DP_Tile *t;
+    uint64_t checksum;
     size_t offset;
     UT_hash_handle hh;
+    UT_hash_handle hh_checksum;
 } DP_BuildIndexTileMap;
 
 typedef struct DP_BuildIndexLayerKey {
@@ -761,6 +807,7 @@ typedef struct DP_BuildIndexAnnotationMap {
 
 typedef struct DP_BuildIndexMaps {
     DP_BuildIndexTileMap *tiles;
+    DP_BuildIndexTileMap *checksums;
     DP_BuildIndexLayerMap *layers;
     DP_BuildIndexAnnotationMap *annotations;
     struct {
@@ -804,7 +851,9 @@ typedef struct DP_BuildIndexContext {
     DP_CanvasHistory *ch;
     DP_DrawContext *dc;
     long long message_count;
+    long long elapsed_ms;
     DP_Vector entries;
+    DP_Vector markers;
     DP_BuildIndexMaps last;
     DP_PlayerIndexShouldSnapshotFn should_snapshot_fn;
     DP_PlayerIndexProgressFn progress_fn;
@@ -913,11 +962,39 @@ static DP_BuildIndexTileMap *search_tile(DP_BuildIndexTileMap *tiles,
     return entry;
 }
 
+static DP_BuildIndexTileMap *search_tile_checksum(DP_BuildIndexTileMap *tiles,
+                                                  DP_Tile *t, uint64_t checksum)
+{
+    DP_BuildIndexTileMap *entry;
+    HASH_FIND(hh_checksum, tiles, &checksum, sizeof(checksum), entry);
+    // Checksums can collide, only reuse the tile if it's actually the same.
+    bool equal = entry
+              && memcmp(DP_tile_pixels(entry->t), DP_tile_pixels(t),
+                        DP_TILE_BYTES)
+                     == 0;
+    return equal ? entry : NULL;
+}
+
+static void add_tile_offset(DP_BuildIndexEntryContext *e, DP_Tile *t,
+                            uint64_t checksum, size_t offset)
+{
+    DP_BuildIndexTileMap *entry = DP_malloc(sizeof(*entry));
+    entry->t = DP_tile_incref(t);
+    entry->checksum = checksum;
+    entry->offset = offset;
+    HASH_ADD_PTR(e->current.tiles, t, entry);
+    HASH_ADD(hh_checksum, e->current.checksums, checksum,
+             sizeof(entry->checksum), entry);
+}
+
 static void move_tile_offset(DP_BuildIndexEntryContext *e,
                              DP_BuildIndexTileMap *entry)
 {
     HASH_DEL(e->last->tiles, entry);
+    HASH_DELETE(hh_checksum, e->last->checksums, entry);
     HASH_ADD_PTR(e->current.tiles, t, entry);
+    HASH_ADD(hh_checksum, e->current.checksums, checksum,
+             sizeof(entry->checksum), entry);
 }
 
 static void move_tile_offsets(DP_BuildIndexEntryContext *e, DP_LayerContent *lc)
@@ -974,20 +1051,34 @@ static bool maybe_write_index_tile(DP_BuildIndexEntryContext *e, DP_Tile *t,
         DP_BuildIndexTileMap *entry;
         if ((entry = search_tile(e->current.tiles, t)) != NULL) {
             *out_offset = entry->offset;
+            return true;
         }
         else if ((entry = search_tile(e->last->tiles, t)) != NULL) {
             move_tile_offset(e, entry);
             *out_offset = entry->offset;
+            return true;
+        }
+
+        uint64_t checksum = DP_tile_checksum(t);
+        size_t offset;
+        if ((entry = search_tile_checksum(e->current.checksums, t, checksum))
+            != NULL) {
+            offset = entry->offset;
+        }
+        else if ((entry = search_tile_checksum(e->last->checksums, t,
+                                               checksum))
+                 != NULL) {
+            move_tile_offset(e, entry);
+            offset = entry->offset;
         }
         else {
-            size_t offset = write_index_tile(e, t);
-            if (offset != 0) {
-                *out_offset = offset;
-            }
-            else {
+            offset = write_index_tile(e, t);
+            if (offset == 0) {
                 return false;
             }
         }
+        add_tile_offset(e, t, checksum, offset);
+        *out_offset = offset;
     }
     else {
         *out_offset = 0;
@@ -1270,6 +1361,8 @@ static size_t write_index_annotation(DP_BuildIndexEntryContext *e,
                   DP_OUTPUT_INT32(DP_annotation_height(a)),
                   DP_OUTPUT_UINT32(DP_annotation_background_color(a)),
                   DP_OUTPUT_UINT8(DP_annotation_valign(a)),
+                  DP_OUTPUT_UINT8(DP_annotation_border(a)),
+                  DP_OUTPUT_UINT8(DP_annotation_auto_grow(a) ? 1 : 0),
                   DP_OUTPUT_UINT16(text_length))
            && DP_output_write(output, text, text_length);
     if (!ok) {
@@ -1550,6 +1643,7 @@ static void dispose_index_maps(DP_BuildIndexMaps *maps)
     DP_BuildIndexTileMap *tile_entry, *tile_tmp;
     HASH_ITER(hh, maps->tiles, tile_entry, tile_tmp) {
         HASH_DEL(maps->tiles, tile_entry);
+        HASH_DELETE(hh_checksum, maps->checksums, tile_entry);
         DP_tile_decref(tile_entry->t);
         DP_free(tile_entry);
     }
@@ -1587,7 +1681,8 @@ static bool make_index_entry(DP_BuildIndexContext *c, long long message_index,
                                    c->ch,
                                    NULL,
                                    c->dc,
-                                   {NULL, NULL, NULL, {NULL, 0}, {NULL, 0}},
+                                   {NULL, NULL, NULL, NULL, {NULL, 0},
+                                    {NULL, 0}},
                                    &c->last,
                                    0,
                                    {0, 0, 0, 0, 0},
@@ -1599,7 +1694,8 @@ static bool make_index_entry(DP_BuildIndexContext *c, long long message_index,
     }
 
     DP_PlayerIndexEntry entry = {message_index, message_offset,
-                                 e.offset.snapshot, e.offset.thumbnail};
+                                 e.offset.snapshot, e.offset.thumbnail,
+                                 c->elapsed_ms};
     DP_VECTOR_PUSH_TYPE(&c->entries, DP_PlayerIndexEntry, entry);
 
     dispose_index_maps(&c->last);
@@ -1608,6 +1704,20 @@ static bool make_index_entry(DP_BuildIndexContext *c, long long message_index,
     return true;
 }
 
+// Message indexes count every message in the recording, same as the player
+// position does, so that seeking can use them directly.
+static void add_index_marker(DP_BuildIndexContext *c, long long message_index,
+                             DP_MsgMarker *mm)
+{
+    size_t length;
+    const char *text = DP_msg_marker_text(mm, &length);
+    char *copy = DP_malloc(length + 1);
+    memcpy(copy, text, length);
+    copy[length] = '\0';
+    DP_PlayerIndexMarker marker = {message_index, c->elapsed_ms, copy};
+    DP_VECTOR_PUSH_TYPE(&c->markers, DP_PlayerIndexMarker, marker);
+}
+
 static bool write_index_messages(DP_BuildIndexContext *c)
 {
     DP_Player *player = c->player;
@@ -1616,36 +1726,49 @@ static bool write_index_messages(DP_BuildIndexContext *c)
     DP_CanvasHistory *ch = c->ch;
     DP_DrawContext *dc = c->dc;
     int last_percent = 0;
-    long long last_written_message_index = 0;
+    long long last_written_message_index = -1;
+    size_t last_written_message_offset = DP_player_tell(player);
 
     while (true) {
         size_t message_offset = DP_player_tell(player);
         DP_Message *msg;
-        DP_PlayerResult result = DP_player_step(player, &msg);
+        DP_PlayerResult result = step_checked_message(player, &msg, true);
+        long long message_index = DP_player_position(player) - 1;
         if (result == DP_PLAYER_SUCCESS) {
+            DP_MessageType type = DP_message_type(msg);
             bool filtered = DP_acl_state_handle(acls, msg, false)
                           & DP_ACL_STATE_FILTERED_BIT;
             if (filtered) {
-                DP_debug(
-                    "ACL filtered recorded %s message from user %u",
-                    DP_message_type_enum_name_unprefixed(DP_message_type(msg)),
-                    DP_message_context_id(msg));
+                DP_debug("ACL filtered recorded %s message from user %u",
+                         DP_message_type_enum_name_unprefixed(type),
+                         DP_message_context_id(msg));
+            }
+            else if (type == DP_MSG_INTERVAL) {
+                DP_MsgInterval *mi = DP_message_internal(msg);
+                c->elapsed_ms += DP_msg_interval_msecs(mi);
+            }
+            else if (type == DP_MSG_MARKER) {
+                add_index_marker(c, message_index, DP_message_internal(msg));
             }
             else {
                 DP_local_state_handle(ls, dc, msg);
-                if (DP_message_type_command(DP_message_type(msg))) {
+                if (DP_message_type_command(type)) {
                     if (!DP_canvas_history_handle(ch, dc, msg)) {
                         DP_warn("Error handling message in index: %s",
                                 DP_error());
                     }
 
-                    long long message_index = c->message_count++;
-                    if (c->should_snapshot_fn(c->user)) {
+                    long long messages =
+                        message_index - last_written_message_index;
+                    size_t bytes = DP_player_tell(player)
+                                 - last_written_message_offset;
+                    if (c->should_snapshot_fn(c->user, messages, bytes)) {
                         if (!make_index_entry(c, message_index,
                                               message_offset)) {
                             return false;
                         }
                         last_written_message_index = message_index;
+                        last_written_message_offset = DP_player_tell(player);
                     }
                 }
             }
@@ -1671,6 +1794,7 @@ static bool write_index_messages(DP_BuildIndexContext *c)
         }
     }
 
+    c->message_count = DP_player_position(player);
     long long message_index = c->message_count - 1;
     if (message_index >= 0 && message_index != last_written_message_index) {
         return make_index_entry(c, message_index, DP_player_tell(player));
@@ -1687,7 +1811,18 @@ static bool write_index_entry(DP_BuildIndexContext *c,
         c->output, DP_OUTPUT_UINT32(entry->message_index),
         DP_OUTPUT_UINT64(entry->message_offset),
         DP_OUTPUT_UINT64(entry->snapshot_offset),
-        DP_OUTPUT_UINT64(entry->thumbnail_offset));
+        DP_OUTPUT_UINT64(entry->thumbnail_offset),
+        DP_OUTPUT_UINT64(entry->elapsed_ms));
+}
+
+static bool write_index_marker(DP_BuildIndexContext *c,
+                               DP_PlayerIndexMarker *marker)
+{
+    size_t length = strlen(marker->text);
+    return DP_OUTPUT_WRITE_LITTLEENDIAN(
+               c->output, DP_OUTPUT_UINT32(marker->message_index),
+               DP_OUTPUT_UINT64(marker->elapsed_ms), DP_OUTPUT_UINT16(length))
+        && DP_output_write(c->output, marker->text, length);
 }
 
 static bool write_index_finish(DP_BuildIndexContext *c)
@@ -1699,6 +1834,19 @@ static bool write_index_finish(DP_BuildIndexContext *c)
         return false;
     }
 
+    // Markers come first, since the entries go on until the end of the file.
+    size_t marker_count = c->markers.used;
+    if (!DP_OUTPUT_WRITE_LITTLEENDIAN(output,
+                                      DP_OUTPUT_UINT32(marker_count))) {
+        return false;
+    }
+    for (size_t i = 0; i < marker_count; ++i) {
+        if (!write_index_marker(
+                c, &DP_VECTOR_AT_TYPE(&c->markers, DP_PlayerIndexMarker, i))) {
+            return false;
+        }
+    }
+
     size_t count = c->entries.used;
     for (size_t i = 0; i < count; ++i) {
         if (!write_index_entry(
@@ -1774,15 +1922,19 @@ bool DP_player_index_build(DP_Player *player, DP_DrawContext *dc,
                               ch,
                               dc,
                               0,
+                              0,
                               DP_VECTOR_NULL,
-                              {NULL, NULL, NULL, {NULL, 0}, {NULL, 0}},
+                              DP_VECTOR_NULL,
+                              {NULL, NULL, NULL, NULL, {NULL, 0}, {NULL, 0}},
                               should_snapshot_fn,
                               progress_fn,
                               user};
     DP_VECTOR_INIT_TYPE(&c.entries, DP_PlayerIndexEntry, INITAL_ENTRY_CAPACITY);
+    DP_VECTOR_INIT_TYPE(&c.markers, DP_PlayerIndexMarker, 8);
     bool ok = write_index(&c);
     dispose_index_maps(&c.last);
     DP_vector_dispose(&c.entries);
+    dispose_markers(c.markers.elements, c.markers.used);
     DP_canvas_history_free(ch);
     DP_local_state_free(ls);
     DP_acl_state_free(acls);
@@ -1797,6 +1949,7 @@ typedef struct DP_ReadIndexContext {
     DP_BufferedInput input;
     unsigned int message_count;
     size_t index_offset;
+    DP_Vector markers;
     DP_Vector entries;
 } DP_ReadIndexContext;
 
@@ -1879,14 +2032,56 @@ static bool read_index_header(DP_ReadIndexContext *c)
         && READ_INDEX(input, uint32, c->message_count) && read_index_offset(c);
 }
 
-#define ENTRY_SIZE (sizeof(uint32_t) + sizeof(uint64_t) * (size_t)3)
+static bool read_index_marker(DP_ReadIndexContext *c)
+{
+    DP_BufferedInput *input = &c->input;
+    long long message_index;
+    uint64_t elapsed_ms;
+    size_t length;
+    bool ok = READ_INDEX(input, uint32, message_index)
+           && READ_INDEX(input, uint64, elapsed_ms)
+           && READ_INDEX(input, uint16, length)
+           && read_index_input(input, length);
+    if (ok) {
+        char *text = DP_malloc(length + 1);
+        memcpy(text, input->buffer, length);
+        text[length] = '\0';
+        DP_PlayerIndexMarker marker = {message_index,
+                                       DP_uint64_to_llong(elapsed_ms), text};
+        DP_debug("Read index marker %zu with message index %lld, elapsed "
+                 "%lldms: %s",
+                 c->markers.used, marker.message_index, marker.elapsed_ms,
+                 marker.text);
+        DP_VECTOR_PUSH_TYPE(&c->markers, DP_PlayerIndexMarker, marker);
+    }
+    return ok;
+}
 
-static bool read_index_entries(DP_ReadIndexContext *c)
+static bool read_index_markers(DP_ReadIndexContext *c)
 {
     if (!DP_buffered_input_seek(&c->input, c->index_offset)) {
         return false;
     }
 
+    size_t count;
+    if (!READ_INDEX(&c->input, uint32, count)) {
+        return false;
+    }
+
+    DP_VECTOR_INIT_TYPE(&c->markers, DP_PlayerIndexMarker,
+                        DP_max_size(count, 1));
+    for (size_t i = 0; i < count; ++i) {
+        if (!read_index_marker(c)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+#define ENTRY_SIZE (sizeof(uint32_t) + sizeof(uint64_t) * (size_t)4)
+
+static bool read_index_entries(DP_ReadIndexContext *c)
+{
     DP_VECTOR_INIT_TYPE(&c->entries, DP_PlayerIndexEntry,
                         INITAL_ENTRY_CAPACITY);
 
@@ -1902,11 +2097,15 @@ static bool read_index_entries(DP_ReadIndexContext *c)
                 read_littleendian_size(c->input.buffer + 4),
                 read_littleendian_size(c->input.buffer + 12),
                 read_littleendian_size(c->input.buffer + 20),
+                DP_uint64_to_llong(
+                    DP_read_littleendian_uint64(c->input.buffer + 28)),
             };
             DP_debug("Read index entry %zu with message index %lld, message "
-                     "offset %zu, snapshot offset %zu, thumbnail offset %zu",
+                     "offset %zu, snapshot offset %zu, thumbnail offset %zu, "
+                     "elapsed %lldms",
                      c->entries.used, entry.message_index, entry.message_offset,
-                     entry.snapshot_offset, entry.thumbnail_offset);
+                     entry.snapshot_offset, entry.thumbnail_offset,
+                     entry.elapsed_ms);
             DP_VECTOR_PUSH_TYPE(&c->entries, DP_PlayerIndexEntry, entry);
         }
         else if (read == 0) {
@@ -1942,16 +2141,22 @@ bool DP_player_index_load(DP_Player *player)
 
     DP_PERF_BEGIN_DETAIL(fn, "index_load", "path=%s", path);
     DP_ReadIndexContext c = {DP_buffered_input_init(input), 0, 0,
-                             DP_VECTOR_NULL};
+                             DP_VECTOR_NULL, DP_VECTOR_NULL};
 
-    bool ok = read_index_header(&c) && read_index_entries(&c);
+    bool ok = read_index_header(&c) && read_index_markers(&c)
+           && read_index_entries(&c);
     if (ok) {
         player_index_dispose(&player->index);
-        player->index = (DP_PlayerIndex){c.input, c.message_count,
-                                         c.entries.elements, c.entries.used};
+        player->index = (DP_PlayerIndex){c.input,
+                                         c.message_count,
+                                         c.entries.elements,
+                                         c.entries.used,
+                                         c.markers.elements,
+                                         c.markers.used};
     }
     else {
         DP_vector_dispose(&c.entries);
+        dispose_markers(c.markers.elements, c.markers.used);
         DP_buffered_input_dispose(&c.input);
     }
 
@@ -2080,7 +2285,7 @@ static DP_Annotation *read_index_annotation(DP_BufferedInput *input,
                                             size_t offset)
 {
     DP_debug("Read annotation at offset %zu", offset);
-    int id, x, y, width, height, valign;
+    int id, x, y, width, height, valign, border, auto_grow;
     uint32_t background_color;
     size_t text_length;
     bool ok = DP_buffered_input_seek(input, offset)
@@ -2089,6 +2294,8 @@ static DP_Annotation *read_index_annotation(DP_BufferedInput *input,
            && READ_INDEX(input, int32, height)
            && READ_INDEX(input, uint32, background_color)
            && READ_INDEX(input, uint8, valign)
+           && READ_INDEX(input, uint8, border)
+           && READ_INDEX(input, uint8, auto_grow)
            && READ_INDEX(input, uint16, text_length)
            && read_index_input(input, text_length);
     if (ok) {
@@ -2096,6 +2303,8 @@ static DP_Annotation *read_index_annotation(DP_BufferedInput *input,
             DP_transient_annotation_new_init(id, x, y, width, height);
         DP_transient_annotation_background_color_set(ta, background_color);
         DP_transient_annotation_valign_set(ta, valign);
+        DP_transient_annotation_border_set(ta, border);
+        DP_transient_annotation_auto_grow_set(ta, auto_grow != 0);
         DP_transient_annotation_text_set(ta, (const char *)input->buffer,
                                          text_length);
         return DP_transient_annotation_persist(ta);
@@ -2621,11 +2830,11 @@ DP_PlayerIndexEntry DP_player_index_entry_search(DP_Player *player,
 {
     DP_ASSERT(player);
     if (!check_index(player)) {
-        return (DP_PlayerIndexEntry){0, 0, 0, 0};
+        return (DP_PlayerIndexEntry){0, 0, 0, 0, 0};
     }
 
     size_t entry_count = player->index.entry_count;
-    DP_PlayerIndexEntry best_entry = (DP_PlayerIndexEntry){0, 0, 0, 0};
+    DP_PlayerIndexEntry best_entry = (DP_PlayerIndexEntry){0, 0, 0, 0, 0};
     long long best_message_index = -1;
     for (size_t i = 0; i < entry_count; ++i) {
         DP_PlayerIndexEntry entry = player->index.entries[i];
@@ -2656,6 +2865,37 @@ static void assign_out_error(bool error, bool *out_error)
     }
 }
 
+size_t DP_player_index_marker_count(DP_Player *player)
+{
+    DP_ASSERT(player);
+    return check_index(player) ? player->index.marker_count : 0;
+}
+
+DP_PlayerIndexMarker DP_player_index_marker_at(DP_Player *player,
+                                               size_t index)
+{
+    DP_ASSERT(player);
+    DP_ASSERT(index < DP_player_index_marker_count(player));
+    return player->index.markers[index];
+}
+
+bool DP_player_index_marker_search(DP_Player *player, const char *text,
+                                   DP_PlayerIndexMarker *out_marker)
+{
+    DP_ASSERT(player);
+    DP_ASSERT(text);
+    DP_ASSERT(out_marker);
+    size_t count = DP_player_index_marker_count(player);
+    for (size_t i = 0; i < count; ++i) {
+        DP_PlayerIndexMarker marker = player->index.markers[i];
+        if (DP_str_equal(marker.text, text)) {
+            *out_marker = marker;
+            return true;
+        }
+    }
+    return false;
+}
+
 DP_Image *DP_player_index_thumbnail_at(DP_Player *player, size_t index,
                                        bool *out_error)
 {
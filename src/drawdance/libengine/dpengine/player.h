@@ -62,11 +62,21 @@ typedef struct DP_PlayerIndexEntry {
     size_t message_offset;
     size_t snapshot_offset;
     size_t thumbnail_offset;
+    long long elapsed_ms;
 } DP_PlayerIndexEntry;
 
+typedef struct DP_PlayerIndexMarker {
+    long long message_index;
+    long long elapsed_ms;
+    char *text;
+} DP_PlayerIndexMarker;
+
 typedef struct DP_PlayerIndexEntrySnapshot DP_PlayerIndexEntrySnapshot;
 
-typedef bool (*DP_PlayerIndexShouldSnapshotFn)(void *user);
+// Called after each drawing command while building the index, with how many
+// messages and bytes of recording there have been since the last snapshot.
+typedef bool (*DP_PlayerIndexShouldSnapshotFn)(void *user, long long messages,
+                                               size_t bytes);
 typedef void (*DP_PlayerIndexProgressFn)(void *user, int percent);
 
 
@@ -96,6 +106,11 @@ long long DP_player_position(DP_Player *player);
 
 DP_PlayerResult DP_player_step(DP_Player *player, DP_Message **out_msg);
 
+// Gives the next message exactly as it was recorded, without any ACL filtering
+// or dropping of messages that playback doesn't let through. Messages that
+// can't be parsed aren't skipped, they give DP_PLAYER_ERROR_PARSE instead.
+DP_PlayerResult DP_player_step_raw(DP_Player *player, DP_Message **out_msg);
+
 DP_PlayerResult DP_player_step_dump(DP_Player *player, DP_DumpType *out_type,
                                     int *out_count, DP_Message ***out_msgs);
 
@@ -138,5 +153,18 @@ void DP_player_index_entry_snapshot_free(DP_PlayerIndexEntrySnapshot *snapshot);
 DP_Image *DP_player_index_thumbnail_at(DP_Player *player, size_t index,
                                        bool *out_error);
 
+// Markers placed in the recording, in the order they appear in it. These are
+// part of the index, so they can be listed without playing anything back. The
+// text is owned by the player and stays valid until the index is reloaded.
+size_t DP_player_index_marker_count(DP_Player *player);
+
+DP_PlayerIndexMarker DP_player_index_marker_at(DP_Player *player,
+                                               size_t index);
+
+// Finds the first marker with exactly the given text. Returns false if there's
+// no such marker or no index loaded.
+bool DP_player_index_marker_search(DP_Player *player, const char *text,
+                                   DP_PlayerIndexMarker *out_marker);
+
 
 #endif
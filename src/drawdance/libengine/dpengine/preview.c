@@ -268,39 +268,90 @@ DP_Preview *DP_preview_new_cut(int initial_offset_x, int initial_offset_y,
 }
 
 
+// The source pixels are shared between all transform previews made from the
+// same original one, so moving the preview around only means swapping out the
+// destination quad. They're fetched lazily on the render thread.
+typedef struct DP_PreviewTransformSource {
+    DP_Atomic refcount;
+    int width, height;
+    const DP_Pixel8 *pixels;
+    struct {
+        DP_PreviewTransformGetPixelsFn get;
+        DP_PreviewTransformDisposePixelsFn dispose;
+        void *user;
+    } fn;
+} DP_PreviewTransformSource;
+
 typedef struct DP_PreviewTransform {
     DP_Preview parent;
-    int x, y, width, height;
+    int x, y;
     DP_Quad dst_quad;
     int interpolation;
     DP_Image *img;
-    struct {
-        DP_PreviewTransformGetPixelsFn get;
-        DP_PreviewTransformDisposePixelsFn dispose;
-        void *user;
-    } pixels;
+    bool failed;
+    DP_PreviewTransformSource *source;
 } DP_PreviewTransform;
 
+static DP_PreviewTransformSource *
+preview_transform_source_new(int width, int height,
+                             DP_PreviewTransformGetPixelsFn get_pixels,
+                             DP_PreviewTransformDisposePixelsFn dispose_pixels,
+                             void *user)
+{
+    DP_PreviewTransformSource *pvts = DP_malloc(sizeof(*pvts));
+    DP_atomic_set(&pvts->refcount, 1);
+    pvts->width = width;
+    pvts->height = height;
+    pvts->pixels = NULL;
+    pvts->fn.get = get_pixels;
+    pvts->fn.dispose = dispose_pixels;
+    pvts->fn.user = user;
+    return pvts;
+}
+
+static DP_PreviewTransformSource *
+preview_transform_source_incref(DP_PreviewTransformSource *pvts)
+{
+    DP_ASSERT(DP_atomic_get(&pvts->refcount) > 0);
+    DP_atomic_inc(&pvts->refcount);
+    return pvts;
+}
+
+static void preview_transform_source_decref(DP_PreviewTransformSource *pvts)
+{
+    DP_ASSERT(DP_atomic_get(&pvts->refcount) > 0);
+    if (DP_atomic_dec(&pvts->refcount)) {
+        pvts->fn.dispose(pvts->fn.user);
+        DP_free(pvts);
+    }
+}
+
+// Only ever called from the single render thread, so no locking needed.
+static const DP_Pixel8 *
+preview_transform_source_pixels(DP_PreviewTransformSource *pvts)
+{
+    const DP_Pixel8 *pixels = pvts->pixels;
+    if (!pixels) {
+        pixels = pvts->fn.get(pvts->fn.user);
+        pvts->pixels = pixels;
+    }
+    return pixels;
+}
+
 static bool preview_transform_prepare_image(DP_PreviewTransform *pvtf,
                                             DP_DrawContext *dc)
 {
     if (pvtf->img) {
         return true; // Image alread transformed successfully.
     }
-
-    DP_PreviewTransformGetPixelsFn get_pixels = pvtf->pixels.get;
-    if (!get_pixels) {
+    else if (pvtf->failed) {
         return false; // Transform already attempted, but failed.
     }
 
-    void *user = pvtf->pixels.user;
-    const DP_Pixel8 *pixels = get_pixels(user);
-    pvtf->pixels.get = NULL;
-    DP_Image *img = DP_image_transform_pixels(pvtf->width, pvtf->height, pixels,
-                                              dc, &pvtf->dst_quad,
-                                              pvtf->interpolation, NULL, NULL);
-    pvtf->pixels.dispose(user);
-    pvtf->pixels.dispose = NULL;
+    DP_PreviewTransformSource *pvts = pvtf->source;
+    DP_Image *img = DP_image_transform_pixels(
+        pvts->width, pvts->height, preview_transform_source_pixels(pvts), dc,
+        &pvtf->dst_quad, pvtf->interpolation, NULL, NULL);
 
     if (img) {
         pvtf->img = img;
@@ -308,6 +359,7 @@ static bool preview_transform_prepare_image(DP_PreviewTransform *pvtf,
     }
     else {
         DP_warn("Error transforming preview: %s", DP_error());
+        pvtf->failed = true;
         return false;
     }
 }
@@ -328,41 +380,63 @@ static void preview_transform_render(DP_Preview *pv, DP_DrawContext *dc,
 static void preview_transform_dispose(DP_Preview *pv)
 {
     DP_PreviewTransform *pvtf = (DP_PreviewTransform *)pv;
-    DP_PreviewTransformDisposePixelsFn dispose = pvtf->pixels.dispose;
-    if (dispose) {
-        dispose(pvtf->pixels.user);
-    }
+    preview_transform_source_decref(pvtf->source);
     DP_image_free(pvtf->img);
 }
 
-DP_Preview *DP_preview_new_transform(
-    int initial_offset_x, int initial_offset_y, int layer_id, int x, int y,
-    int width, int height, const DP_Quad *dst_quad, int interpolation,
-    DP_PreviewTransformGetPixelsFn get_pixels,
-    DP_PreviewTransformDisposePixelsFn dispose_pixels, void *user)
+static DP_Preview *new_transform_preview(int initial_offset_x,
+                                         int initial_offset_y, int layer_id,
+                                         int x, int y, const DP_Quad *dst_quad,
+                                         int interpolation,
+                                         DP_PreviewTransformSource *pvts)
 {
-    DP_ASSERT(width > 0);
-    DP_ASSERT(height > 0);
     DP_ASSERT(dst_quad);
-    DP_ASSERT(get_pixels);
-    DP_ASSERT(dispose_pixels);
     DP_PreviewTransform *pvtf = DP_malloc(sizeof(*pvtf));
     init_preview(&pvtf->parent, DP_PREVIEW_TRANSFORM, DP_BLEND_MODE_NORMAL,
                  layer_id, initial_offset_x, initial_offset_y,
                  preview_transform_render, preview_transform_dispose);
     pvtf->x = x;
     pvtf->y = y;
-    pvtf->width = width;
-    pvtf->height = height;
     pvtf->dst_quad = *dst_quad;
     pvtf->interpolation = interpolation;
-    pvtf->pixels.get = get_pixels;
-    pvtf->pixels.dispose = dispose_pixels;
-    pvtf->pixels.user = user;
     pvtf->img = NULL;
+    pvtf->failed = false;
+    pvtf->source = pvts;
     return &pvtf->parent;
 }
 
+DP_Preview *DP_preview_new_transform(
+    int initial_offset_x, int initial_offset_y, int layer_id, int x, int y,
+    int width, int height, const DP_Quad *dst_quad, int interpolation,
+    DP_PreviewTransformGetPixelsFn get_pixels,
+    DP_PreviewTransformDisposePixelsFn dispose_pixels, void *user)
+{
+    DP_ASSERT(width > 0);
+    DP_ASSERT(height > 0);
+    DP_ASSERT(get_pixels);
+    DP_ASSERT(dispose_pixels);
+    return new_transform_preview(
+        initial_offset_x, initial_offset_y, layer_id, x, y, dst_quad,
+        interpolation,
+        preview_transform_source_new(width, height, get_pixels,
+                                     dispose_pixels, user));
+}
+
+DP_Preview *DP_preview_new_transform_moved(DP_Preview *pv,
+                                           int initial_offset_x,
+                                           int initial_offset_y, int x, int y,
+                                           const DP_Quad *dst_quad,
+                                           int interpolation)
+{
+    DP_ASSERT(pv);
+    DP_ASSERT(DP_atomic_get(&pv->refcount) > 0);
+    DP_ASSERT(pv->type == DP_PREVIEW_TRANSFORM);
+    DP_PreviewTransform *pvtf = (DP_PreviewTransform *)pv;
+    return new_transform_preview(
+        initial_offset_x, initial_offset_y, pv->layer_id, x, y, dst_quad,
+        interpolation, preview_transform_source_incref(pvtf->source));
+}
+
 
 typedef struct DP_PreviewDabs {
     DP_Preview parent;
@@ -378,6 +452,10 @@ static int preview_dabs_blend_mode(DP_Message *msg)
     case DP_MSG_DRAW_DABS_PIXEL:
     case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
         return DP_msg_draw_dabs_pixel_mode(DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_STAMP:
+        return DP_msg_draw_dabs_stamp_mode(DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_SOFT:
+        return DP_msg_draw_dabs_soft_mode(DP_message_internal(msg));
     case DP_MSG_DRAW_DABS_MYPAINT: {
         DP_MsgDrawDabsMyPaint *mddmp = DP_message_internal(msg);
         int blend_mode;
@@ -401,11 +479,33 @@ static int preview_dabs_blend_mode(DP_Message *msg)
             return blend_mode;
         }
     }
+    case DP_MSG_FILL_RECT:
+        return DP_msg_fill_rect_mode(DP_message_internal(msg));
     default:
         DP_UNREACHABLE();
     }
 }
 
+// Filled shapes come as rectangles in among the dabs.
+static void preview_fill_rect(DP_MsgFillRect *mfr, int offset_x, int offset_y,
+                              DP_TransientLayerContent *tlc)
+{
+    int x = DP_uint32_to_int(DP_msg_fill_rect_x(mfr)) + offset_x;
+    int y = DP_uint32_to_int(DP_msg_fill_rect_y(mfr)) + offset_y;
+    int left = DP_max_int(x, 0);
+    int top = DP_max_int(y, 0);
+    int right = DP_min_int(x + DP_uint32_to_int(DP_msg_fill_rect_w(mfr)),
+                           DP_transient_layer_content_width(tlc));
+    int bottom = DP_min_int(y + DP_uint32_to_int(DP_msg_fill_rect_h(mfr)),
+                            DP_transient_layer_content_height(tlc));
+    if (left < right && top < bottom) {
+        DP_UPixel15 pixel =
+            DP_upixel15_from_color(DP_msg_fill_rect_color(mfr));
+        DP_transient_layer_content_fill_rect(tlc, 0, DP_BLEND_MODE_NORMAL,
+                                             left, top, right, bottom, pixel);
+    }
+}
+
 static void preview_dabs_render(DP_Preview *pv, DP_DrawContext *dc,
                                 int offset_x, int offset_y,
                                 DP_TransientLayerContent *tlc)
@@ -441,6 +541,36 @@ static void preview_dabs_render(DP_Preview *pv, DP_DrawContext *dc,
                 DP_msg_draw_dabs_pixel_dabs(mddp, &params.dab_count);
             break;
         }
+        case DP_MSG_DRAW_DABS_STAMP: {
+            DP_MsgDrawDabsStamp *mdds = DP_message_internal(msg);
+            params.origin_x = DP_msg_draw_dabs_stamp_x(mdds);
+            params.origin_y = DP_msg_draw_dabs_stamp_y(mdds);
+            params.color = DP_msg_draw_dabs_stamp_color(mdds);
+            params.indirect = DP_msg_draw_dabs_stamp_indirect(mdds);
+            params.stamp.dabs =
+                DP_msg_draw_dabs_stamp_dabs(mdds, &params.dab_count);
+            params.stamp.mask_id = DP_msg_draw_dabs_stamp_mask(mdds);
+            params.stamp.grain = (DP_PaintGrain){
+                DP_msg_draw_dabs_stamp_grain(mdds),
+                DP_msg_draw_dabs_stamp_grainscale(mdds),
+                DP_msg_draw_dabs_stamp_grainstrength(mdds)};
+            break;
+        }
+        case DP_MSG_DRAW_DABS_SOFT: {
+            DP_MsgDrawDabsSoft *mdds = DP_message_internal(msg);
+            params.origin_x = DP_msg_draw_dabs_soft_x(mdds);
+            params.origin_y = DP_msg_draw_dabs_soft_y(mdds);
+            params.color = DP_msg_draw_dabs_soft_color(mdds);
+            params.indirect = DP_msg_draw_dabs_soft_indirect(mdds);
+            params.soft.dabs =
+                DP_msg_draw_dabs_soft_dabs(mdds, &params.dab_count);
+            params.soft.falloff = DP_msg_draw_dabs_soft_falloff(mdds);
+            params.soft.grain = (DP_PaintGrain){
+                DP_msg_draw_dabs_soft_grain(mdds),
+                DP_msg_draw_dabs_soft_grainscale(mdds),
+                DP_msg_draw_dabs_soft_grainstrength(mdds)};
+            break;
+        }
         case DP_MSG_DRAW_DABS_MYPAINT: {
             DP_MsgDrawDabsMyPaint *mddmp = DP_message_internal(msg);
             params.origin_x = DP_msg_draw_dabs_mypaint_x(mddmp);
@@ -456,6 +586,10 @@ static void preview_dabs_render(DP_Preview *pv, DP_DrawContext *dc,
                                           NULL, &params.indirect, NULL);
             break;
         }
+        case DP_MSG_FILL_RECT:
+            preview_fill_rect(DP_message_internal(msg), offset_x, offset_y,
+                              tlc);
+            continue;
         default:
             continue;
         }
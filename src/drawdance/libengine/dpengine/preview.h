@@ -63,6 +63,14 @@ DP_Preview *DP_preview_new_transform(
     DP_PreviewTransformGetPixelsFn get_pixels,
     DP_PreviewTransformDisposePixelsFn dispose_pixels, void *user);
 
+// Makes a new transform preview with the same layer and source pixels as the
+// given one, but a different destination. The pixels aren't fetched again.
+DP_Preview *DP_preview_new_transform_moved(DP_Preview *pv,
+                                           int initial_offset_x,
+                                           int initial_offset_y, int x, int y,
+                                           const DP_Quad *dst_quad,
+                                           int interpolation);
+
 DP_Preview *DP_preview_new_dabs_inc(int initial_offset_x, int initial_offset_y,
                                     int layer_id, int count,
                                     DP_Message **messages);
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: MIT
+#include "put_image.h"
+#include "compress.h"
+#include "image.h"
+#include "pixels.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpmsg/message.h>
+
+#define MAX_IMAGE_SIZE \
+    (DP_MESSAGE_MAX_PAYLOAD_LENGTH - DP_MSG_PUT_IMAGE_STATIC_LENGTH)
+
+
+typedef struct DP_PutImageContext {
+    unsigned int context_id;
+    uint16_t layer_id;
+    uint8_t blend_mode;
+    DP_Image *img;
+    DP_PutImagePushMessageFn push_message;
+    void *user;
+    unsigned char *buffer;
+    size_t capacity;
+} DP_PutImageContext;
+
+static unsigned char *get_buffer(size_t size, void *user)
+{
+    DP_PutImageContext *c = user;
+    if (size > c->capacity) {
+        c->buffer = DP_realloc(c->buffer, size);
+        c->capacity = size;
+    }
+    return c->buffer;
+}
+
+static void set_image(size_t size, unsigned char *out, void *user)
+{
+    memcpy(out, user, size);
+}
+
+static size_t compress_region(DP_PutImageContext *c, int src_x, int src_y,
+                              int width, int height)
+{
+    size_t size = DP_int_to_size(width) * DP_int_to_size(height)
+                * sizeof(DP_Pixel8);
+    bool whole = src_x == 0 && src_y == 0 && width == DP_image_width(c->img)
+              && height == DP_image_height(c->img);
+    if (whole) {
+        return DP_compress_deflate(
+            (const unsigned char *)DP_image_pixels(c->img), size, get_buffer,
+            c);
+    }
+    else {
+        DP_Image *sub =
+            DP_image_new_subimage(c->img, src_x, src_y, width, height);
+        size_t compressed_size = DP_compress_deflate(
+            (const unsigned char *)DP_image_pixels(sub), size, get_buffer, c);
+        DP_image_free(sub);
+        return compressed_size;
+    }
+}
+
+static bool push_image(DP_PutImageContext *c, int x, int y, int width,
+                       int height, size_t size)
+{
+    DP_Message *msg = DP_msg_put_image_new(
+        c->context_id, c->layer_id, c->blend_mode, DP_int_to_uint32(x),
+        DP_int_to_uint32(y), DP_int_to_uint32(width), DP_int_to_uint32(height),
+        set_image, size, c->buffer);
+    return c->push_message(c->user, msg);
+}
+
+static bool put_image_recursive(DP_PutImageContext *c, int x, int y,
+                                int src_x, int src_y, int width, int height,
+                                size_t estimated_size)
+{
+    if (width <= 0 || height <= 0) {
+        return true;
+    }
+
+    // If the estimate looks like it fits, try compressing. Otherwise assume
+    // that the region is too big to fit into a message and split it up.
+    size_t compressed_size;
+    if (estimated_size <= MAX_IMAGE_SIZE) {
+        compressed_size = compress_region(c, src_x, src_y, width, height);
+        if (compressed_size == 0) {
+            return false;
+        }
+        else if (compressed_size <= MAX_IMAGE_SIZE) {
+            return push_image(c, x, y, width, height, compressed_size);
+        }
+    }
+    else {
+        compressed_size = estimated_size;
+    }
+
+    // Too big, slice it in half along the longest axis.
+    size_t estimated_slice_size = compressed_size / 2;
+    if (width > height) {
+        int left = width / 2;
+        return put_image_recursive(c, x, y, src_x, src_y, left, height,
+                                   estimated_slice_size)
+            && put_image_recursive(c, x + left, y, src_x + left, src_y,
+                                   width - left, height, estimated_slice_size);
+    }
+    else {
+        int top = height / 2;
+        return put_image_recursive(c, x, y, src_x, src_y, width, top,
+                                   estimated_slice_size)
+            && put_image_recursive(c, x, y + top, src_x, src_y + top, width,
+                                   height - top, estimated_slice_size);
+    }
+}
+
+bool DP_put_image_messages(unsigned int context_id, int layer_id,
+                           int blend_mode, int x, int y, DP_Image *img,
+                           DP_PutImagePushMessageFn push_message, void *user)
+{
+    DP_ASSERT(img);
+    DP_ASSERT(push_message);
+    // Crop off anything at negative coordinates.
+    int src_x = x < 0 ? -x : 0;
+    int src_y = y < 0 ? -y : 0;
+    int width = DP_image_width(img) - src_x;
+    int height = DP_image_height(img) - src_y;
+    if (width <= 0 || height <= 0) {
+        return true;
+    }
+
+    DP_PutImageContext c = {context_id,
+                            DP_int_to_uint16(layer_id),
+                            DP_int_to_uint8(blend_mode),
+                            img,
+                            push_message,
+                            user,
+                            NULL,
+                            0};
+    bool ok = push_message(user, DP_msg_undo_point_new(context_id))
+           && put_image_recursive(&c, x + src_x, y + src_y, src_x, src_y,
+                                  width, height, 0);
+    DP_free(c.buffer);
+    return ok;
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_PUT_IMAGE_H
+#define DPENGINE_PUT_IMAGE_H
+#include <dpcommon/common.h>
+
+typedef struct DP_Image DP_Image;
+typedef struct DP_Message DP_Message;
+
+
+// Takes ownership of the message, returns false to stop.
+typedef bool (*DP_PutImagePushMessageFn)(void *user, DP_Message *msg);
+
+// Turns the image into an undo point followed by as many put image messages as
+// it takes to fit it at the given position, each compressed on its own and
+// within the maximum message size. Parts of the image at negative coordinates
+// are cropped off, since the protocol can't express them. Applying all of them
+// puts exactly the image's pixels onto the layer and a single undo takes them
+// back off again. Returns false if compression fails or push_message does.
+bool DP_put_image_messages(unsigned int context_id, int layer_id,
+                           int blend_mode, int x, int y, DP_Image *img,
+                           DP_PutImagePushMessageFn push_message, void *user);
+
+
+#endif
@@ -340,7 +340,13 @@ JSON_Value *DP_recorder_header(DP_Recorder *r)
 
 static bool push_message(DP_Recorder *r, DP_Message *msg, bool inc)
 {
-    if (DP_atomic_get(&r->running)) {
+    if (DP_message_bypasses_recording(msg)) {
+        if (!inc) {
+            DP_message_decref(msg);
+        }
+        return true;
+    }
+    else if (DP_atomic_get(&r->running)) {
         long long timestamp = get_timestamp(r);
         long long interval = timestamp - r->last_timestamp;
         DP_Mutex *mutex = r->mutex;
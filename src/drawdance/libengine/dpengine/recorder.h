@@ -58,6 +58,7 @@ DP_RecorderType DP_recorder_type(DP_Recorder *r);
 
 JSON_Value *DP_recorder_header(DP_Recorder *r);
 
+// Messages that bypass recording are accepted, but not written.
 bool DP_recorder_message_push_inc(DP_Recorder *r, DP_Message *msg);
 
 bool DP_recorder_message_push_noinc(DP_Recorder *r, DP_Message *msg);
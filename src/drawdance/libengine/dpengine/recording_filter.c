@@ -0,0 +1,393 @@
+// SPDX-License-Identifier: MIT
+#include "recording_filter.h"
+#include "canvas_history.h"
+#include "player.h"
+#include "recorder.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/vector.h>
+#include <dpmsg/message.h>
+
+
+// Stands in for a canvas history entry, without the message and canvas state.
+// Playback makes a save point at every undo point, so those are the entries
+// that have states, unless they're gone.
+typedef struct DP_FilterEntry {
+    long long position; // Of the message in the recording, -1 if synthetic.
+    unsigned int context_id;
+    bool undo_point;
+    bool has_state;
+    DP_Undo undo;
+} DP_FilterEntry;
+
+// Entries before first have been truncated or reset away, so they can't
+// change anymore. They're kept around for the second pass to look at.
+typedef struct DP_FilterHistory {
+    DP_Vector entries;
+    int first;
+    int undo_depth_limit;
+    bool mark_command_done;
+} DP_FilterHistory;
+
+
+static DP_FilterEntry *entry_at(DP_FilterHistory *fh, int i)
+{
+    return &DP_VECTOR_AT_TYPE(&fh->entries, DP_FilterEntry, i);
+}
+
+static int used(DP_FilterHistory *fh)
+{
+    return DP_size_to_int(fh->entries.used);
+}
+
+static void push_entry(DP_FilterHistory *fh, long long position,
+                       unsigned int context_id, bool undo_point, DP_Undo undo)
+{
+    DP_FilterEntry entry = {position, context_id, undo_point,
+                            undo_point && undo == DP_UNDO_DONE, undo};
+    DP_VECTOR_PUSH_TYPE(&fh->entries, DP_FilterEntry, entry);
+}
+
+static void reset_history(DP_FilterHistory *fh)
+{
+    fh->first = used(fh);
+    fh->mark_command_done = true;
+    push_entry(fh, -1, 0, true, DP_UNDO_DONE);
+}
+
+
+static int mark_undone_actions_gone(DP_FilterHistory *fh, int index,
+                                    int *out_depth)
+{
+    unsigned int context_id = entry_at(fh, index)->context_id;
+    int i = index - 1;
+    int depth = 1;
+    for (; i >= fh->first && depth < fh->undo_depth_limit; --i) {
+        DP_FilterEntry *entry = entry_at(fh, i);
+        if (entry->undo_point) {
+            ++depth;
+        }
+
+        if (entry->context_id == context_id) {
+            if (entry->undo == DP_UNDO_GONE) {
+                break;
+            }
+            else if (entry->undo == DP_UNDO_UNDONE) {
+                entry->undo = DP_UNDO_GONE;
+                entry->has_state = false;
+            }
+        }
+    }
+    *out_depth = depth;
+    return i;
+}
+
+static void truncate_unreachable(DP_FilterHistory *fh, int i, int depth)
+{
+    for (; i >= fh->first && depth < fh->undo_depth_limit; --i) {
+        if (entry_at(fh, i)->undo_point) {
+            ++depth;
+        }
+    }
+    while (i > fh->first && !entry_at(fh, i)->has_state) {
+        --i;
+    }
+    if (i > fh->first) {
+        fh->first = i;
+    }
+}
+
+static void handle_undo_point(DP_FilterHistory *fh, int index)
+{
+    int depth;
+    int i = mark_undone_actions_gone(fh, index, &depth);
+    truncate_unreachable(fh, i, depth);
+}
+
+
+static bool is_done_entry_by(DP_FilterEntry *entry, unsigned int context_id)
+{
+    return entry->undo == DP_UNDO_DONE && entry->context_id == context_id;
+}
+
+static void undo(DP_FilterHistory *fh, unsigned int context_id)
+{
+    int i;
+    int depth = 0;
+    for (i = used(fh) - 1; i >= fh->first && depth <= fh->undo_depth_limit;
+         --i) {
+        DP_FilterEntry *entry = entry_at(fh, i);
+        if (entry->undo_point) {
+            ++depth;
+            if (is_done_entry_by(entry, context_id)) {
+                break;
+            }
+        }
+    }
+
+    if (depth <= fh->undo_depth_limit && i >= fh->first) {
+        int count = used(fh);
+        for (; i < count; ++i) {
+            DP_FilterEntry *entry = entry_at(fh, i);
+            if (is_done_entry_by(entry, context_id)) {
+                entry->undo = DP_UNDO_UNDONE;
+            }
+        }
+    }
+}
+
+static void redo(DP_FilterHistory *fh, unsigned int context_id)
+{
+    int redo_start = -1;
+    int redo_depth = 0;
+    int depth = 0;
+    for (int i = used(fh) - 1; i >= fh->first && depth <= fh->undo_depth_limit;
+         --i) {
+        DP_FilterEntry *entry = entry_at(fh, i);
+        if (entry->undo_point) {
+            ++depth;
+            if (entry->context_id == context_id) {
+                if (entry->undo == DP_UNDO_UNDONE) {
+                    redo_start = i;
+                    redo_depth = depth;
+                }
+                else if (entry->undo == DP_UNDO_DONE) {
+                    break;
+                }
+            }
+        }
+    }
+
+    if (redo_start >= 0 && redo_depth <= fh->undo_depth_limit) {
+        entry_at(fh, redo_start)->undo = DP_UNDO_DONE;
+        int count = used(fh);
+        for (int i = redo_start + 1; i < count; ++i) {
+            DP_FilterEntry *entry = entry_at(fh, i);
+            if (entry->context_id == context_id) {
+                if (entry->undo_point && entry->undo != DP_UNDO_GONE) {
+                    break;
+                }
+                else if (entry->undo == DP_UNDO_UNDONE) {
+                    entry->undo = DP_UNDO_DONE;
+                }
+            }
+        }
+    }
+}
+
+static void handle_undo(DP_FilterHistory *fh, DP_Message *msg)
+{
+    DP_MsgUndo *mu = DP_message_internal(msg);
+    unsigned int override_id = DP_msg_undo_override_user(mu);
+    unsigned int context_id =
+        override_id == 0 ? DP_message_context_id(msg) : override_id;
+    if (context_id == 0) {
+        // The next command gets appended as undone, see the canvas history.
+        fh->mark_command_done = false;
+    }
+    else if (DP_msg_undo_redo(mu)) {
+        redo(fh, context_id);
+    }
+    else {
+        undo(fh, context_id);
+    }
+}
+
+static void handle_command(DP_FilterHistory *fh, DP_Message *msg,
+                           DP_MessageType type, long long position)
+{
+    DP_Undo undo = fh->mark_command_done ? DP_UNDO_DONE : DP_UNDO_UNDONE;
+    bool undo_point = type == DP_MSG_UNDO_POINT;
+    push_entry(fh, position, DP_message_context_id(msg), undo_point, undo);
+    if (undo == DP_UNDO_DONE) {
+        if (undo_point) {
+            handle_undo_point(fh, used(fh) - 1);
+        }
+    }
+    else {
+        fh->mark_command_done = true;
+    }
+}
+
+static void handle_message(DP_FilterHistory *fh, DP_Message *msg,
+                           long long position)
+{
+    DP_MessageType type = DP_message_type(msg);
+    if (type == DP_MSG_UNDO_DEPTH) {
+        DP_MsgUndoDepth *mud = DP_message_internal(msg);
+        fh->undo_depth_limit = DP_clamp_int(DP_msg_undo_depth_depth(mud),
+                                            DP_CANVAS_HISTORY_UNDO_DEPTH_MIN,
+                                            DP_CANVAS_HISTORY_UNDO_DEPTH_MAX);
+        reset_history(fh);
+    }
+    else if (type == DP_MSG_SOFT_RESET) {
+        reset_history(fh);
+    }
+    else if (type == DP_MSG_UNDO) {
+        handle_undo(fh, msg);
+    }
+    else if (DP_message_type_command(type)) {
+        handle_command(fh, msg, type, position);
+    }
+}
+
+static bool track_undone(DP_Player *player, DP_FilterHistory *fh)
+{
+    while (true) {
+        DP_Message *msg;
+        DP_PlayerResult result = DP_player_step_raw(player, &msg);
+        if (result == DP_PLAYER_SUCCESS) {
+            handle_message(fh, msg, DP_player_position(player) - 1);
+            DP_message_decref(msg);
+        }
+        else if (result == DP_PLAYER_ERROR_PARSE) {
+            continue; // Gets warned about when writing.
+        }
+        else if (result == DP_PLAYER_RECORDING_END) {
+            return true;
+        }
+        else {
+            return false;
+        }
+    }
+}
+
+
+typedef struct DP_FilterContext {
+    const DP_RecordingFilterOptions *options;
+    DP_FilterHistory *fh;
+    int next_entry;
+    DP_Recorder *r;
+    unsigned int interval_context_id;
+    int interval_ms;
+} DP_FilterContext;
+
+static bool is_undone(DP_FilterContext *c, long long position)
+{
+    DP_FilterHistory *fh = c->fh;
+    int count = used(fh);
+    while (c->next_entry < count) {
+        DP_FilterEntry *entry = entry_at(fh, c->next_entry);
+        if (entry->position < position) {
+            ++c->next_entry;
+        }
+        else {
+            return entry->position == position
+                && entry->undo != DP_UNDO_DONE;
+        }
+    }
+    return false;
+}
+
+static bool should_remove(DP_FilterContext *c, DP_MessageType type,
+                          long long position)
+{
+    const DP_RecordingFilterOptions *options = c->options;
+    switch (type) {
+    case DP_MSG_SERVER_COMMAND:
+    case DP_MSG_DISCONNECT:
+    case DP_MSG_PING:
+    case DP_MSG_KEEP_ALIVE:
+    case DP_MSG_CHAT:
+    case DP_MSG_PRIVATE_CHAT:
+        return options->remove_chat;
+    case DP_MSG_LASER_TRAIL:
+    case DP_MSG_MOVE_POINTER:
+        return options->remove_lasers;
+    case DP_MSG_UNDO:
+        return options->remove_undone;
+    default:
+        return options->remove_undone && DP_message_type_command(type)
+            && is_undone(c, position);
+    }
+}
+
+static bool flush_interval(DP_FilterContext *c)
+{
+    int interval_ms = c->interval_ms;
+    if (interval_ms > 0) {
+        c->interval_ms = 0;
+        return DP_recorder_message_push_noinc(
+            c->r, DP_msg_interval_new(c->interval_context_id,
+                                      DP_int_to_uint16(interval_ms)));
+    }
+    else {
+        return true;
+    }
+}
+
+static void collect_interval(DP_FilterContext *c, DP_Message *msg)
+{
+    if (c->interval_ms == 0) {
+        c->interval_context_id = DP_message_context_id(msg);
+    }
+    DP_MsgInterval *mi = DP_message_internal(msg);
+    int max_interval_ms = DP_min_int(c->options->max_interval_ms, UINT16_MAX);
+    c->interval_ms = DP_min_int(
+        c->interval_ms + DP_msg_interval_msecs(mi), max_interval_ms);
+}
+
+static bool filter_message(DP_FilterContext *c, DP_Message *msg,
+                           long long position)
+{
+    DP_MessageType type = DP_message_type(msg);
+    if (should_remove(c, type, position)) {
+        DP_message_decref(msg);
+        return true;
+    }
+    else if (type == DP_MSG_INTERVAL && c->options->max_interval_ms > 0) {
+        collect_interval(c, msg);
+        DP_message_decref(msg);
+        return true;
+    }
+    else {
+        return flush_interval(c) && DP_recorder_message_push_noinc(c->r, msg);
+    }
+}
+
+static bool write_filtered(DP_Player *player, DP_FilterContext *c)
+{
+    while (true) {
+        DP_Message *msg;
+        DP_PlayerResult result = DP_player_step_raw(player, &msg);
+        if (result == DP_PLAYER_SUCCESS) {
+            if (!filter_message(c, msg, DP_player_position(player) - 1)) {
+                return false;
+            }
+        }
+        else if (result == DP_PLAYER_ERROR_PARSE) {
+            DP_warn("Can't filter message: %s", DP_error());
+        }
+        else if (result == DP_PLAYER_RECORDING_END) {
+            return flush_interval(c);
+        }
+        else {
+            return false;
+        }
+    }
+}
+
+
+bool DP_recording_filter(DP_Player *player, DP_Recorder *r,
+                         const DP_RecordingFilterOptions *options)
+{
+    DP_ASSERT(player);
+    DP_ASSERT(r);
+    DP_ASSERT(options);
+    DP_FilterHistory fh = {DP_VECTOR_NULL, 0, DP_UNDO_DEPTH_DEFAULT, true};
+    DP_VECTOR_INIT_TYPE(&fh.entries, DP_FilterEntry, 1024);
+    reset_history(&fh);
+
+    bool ok = true;
+    if (options->remove_undone) {
+        ok = track_undone(player, &fh) && DP_player_rewind(player);
+    }
+
+    if (ok) {
+        DP_FilterContext c = {options, &fh, 0, r, 0, 0};
+        ok = write_filtered(player, &c);
+    }
+
+    DP_vector_dispose(&fh.entries);
+    return ok;
+}
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_RECORDING_FILTER_H
+#define DPENGINE_RECORDING_FILTER_H
+#include <dpcommon/common.h>
+
+typedef struct DP_Player DP_Player;
+typedef struct DP_Recorder DP_Recorder;
+
+
+typedef struct DP_RecordingFilterOptions {
+    // Drops everything that ended up undone, along with all undos and redos.
+    bool remove_undone;
+    // Drops chat messages, private ones too, and server chatter like pings.
+    bool remove_chat;
+    // Drops laser trails and pointer movements.
+    bool remove_lasers;
+    // Merges consecutive intervals and caps them at this many milliseconds,
+    // so that long pauses don't drag out playback. Zero leaves them alone.
+    int max_interval_ms;
+} DP_RecordingFilterOptions;
+
+// Pushes the messages of the player's recording into the recorder, leaving out
+// the ones the options say to. Figuring out what ended up undone takes going
+// through the recording twice, so the player must be able to rewind. Undos and
+// redos are tracked the same way the canvas history does it during playback,
+// so the result replays to the same canvas, just with less stuff to get there.
+bool DP_recording_filter(DP_Player *player, DP_Recorder *r,
+                         const DP_RecordingFilterOptions *options);
+
+
+#endif
@@ -0,0 +1,244 @@
+// SPDX-License-Identifier: MIT
+#include "render_frames.h"
+#include "canvas_history.h"
+#include "canvas_state.h"
+#include "image.h"
+#include "image_transform.h"
+#include "playback.h"
+#include "player.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/output.h>
+#include <dpmsg/message.h>
+#include <math.h>
+
+
+typedef struct DP_RenderFramesContext {
+    DP_Playback *pb;
+    DP_DrawContext *dc;
+    const DP_RenderFramesOptions *options;
+    DP_RenderFramesFrameFn on_frame;
+    void *user;
+    DP_CanvasState *last_cs;
+    DP_Image *last_img;
+} DP_RenderFramesContext;
+
+static long long position(DP_RenderFramesContext *c)
+{
+    return DP_playback_message_index(c->pb);
+}
+
+static bool at_end(DP_RenderFramesContext *c)
+{
+    long long end = c->options->end;
+    return end >= 0 && position(c) >= end;
+}
+
+// Canvas states are immutable, so if the history still has the same one as
+// the last frame, nothing about the canvas changed since.
+static bool canvas_changed(DP_RenderFramesContext *c)
+{
+    DP_CanvasState *cs =
+        DP_canvas_history_get(DP_playback_canvas_history(c->pb));
+    bool changed = cs != c->last_cs;
+    DP_canvas_state_decref(cs);
+    return changed;
+}
+
+static DP_Image *scale_frame(DP_DrawContext *dc, DP_Image *img, int width,
+                             int height)
+{
+    int img_width = DP_image_width(img);
+    int img_height = DP_image_height(img);
+    if (img_width == width && img_height == height) {
+        return img;
+    }
+
+    double ratio = DP_min_double(DP_int_to_double(width) / img_width,
+                                 DP_int_to_double(height) / img_height);
+    int scaled_width = DP_max_int(
+        1, DP_min_int(width, DP_double_to_int(round(img_width * ratio))));
+    int scaled_height = DP_max_int(
+        1, DP_min_int(height, DP_double_to_int(round(img_height * ratio))));
+    DP_Image *scaled = DP_image_new(scaled_width, scaled_height);
+    DP_Transform tf = DP_transform_scale(
+        DP_transform_identity(),
+        DP_int_to_double(scaled_width) / DP_int_to_double(img_width),
+        DP_int_to_double(scaled_height) / DP_int_to_double(img_height));
+    bool ok = DP_image_transform_draw(img_width, img_height,
+                                      DP_image_pixels(img), dc, scaled, tf,
+                                      DP_MSG_TRANSFORM_REGION_MODE_BILINEAR);
+    DP_image_free(img);
+    if (!ok) {
+        DP_image_free(scaled);
+        return NULL;
+    }
+
+    if (scaled_width == width && scaled_height == height) {
+        return scaled;
+    }
+    else {
+        DP_Image *boxed = DP_image_new_subimage(
+            scaled, -(width - scaled_width) / 2, -(height - scaled_height) / 2,
+            width, height);
+        DP_image_free(scaled);
+        return boxed;
+    }
+}
+
+static DP_Image *render_frame(DP_RenderFramesContext *c, DP_CanvasState *cs)
+{
+    const DP_RenderFramesOptions *options = c->options;
+    DP_Rect crop = options->crop;
+    DP_Image *img = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, DP_rect_valid(crop) ? &crop : NULL,
+        NULL);
+    if (img && options->width > 0 && options->height > 0) {
+        return scale_frame(c->dc, img, options->width, options->height);
+    }
+    else {
+        return img;
+    }
+}
+
+static bool emit_frame(DP_RenderFramesContext *c, bool allow_duplicate)
+{
+    DP_CanvasState *cs =
+        DP_canvas_history_get(DP_playback_canvas_history(c->pb));
+    if (DP_canvas_state_width(cs) == 0 || DP_canvas_state_height(cs) == 0) {
+        DP_canvas_state_decref(cs);
+        return true;
+    }
+
+    if (cs == c->last_cs) {
+        DP_canvas_state_decref(cs);
+        if (allow_duplicate && c->options->duplicate_unchanged) {
+            return c->on_frame(c->user, c->last_img, position(c), true);
+        }
+        else {
+            return true;
+        }
+    }
+
+    DP_Image *img = render_frame(c, cs);
+    DP_canvas_state_decref_nullable(c->last_cs);
+    DP_image_free(c->last_img);
+    c->last_cs = cs;
+    c->last_img = img;
+    return img && c->on_frame(c->user, img, position(c), false);
+}
+
+static DP_PlayerResult skip_to_start(DP_RenderFramesContext *c)
+{
+    while (position(c) < c->options->start) {
+        DP_PlayerResult result = DP_playback_step_one(c->pb, c->dc);
+        if (result != DP_PLAYER_SUCCESS) {
+            return result;
+        }
+    }
+    return DP_PLAYER_SUCCESS;
+}
+
+static DP_PlayerResult advance_messages(DP_RenderFramesContext *c)
+{
+    const DP_RenderFramesOptions *options = c->options;
+    long long target = position(c) + options->step;
+    if (options->end >= 0 && target > options->end) {
+        target = options->end;
+    }
+    while (position(c) < target) {
+        DP_PlayerResult result = DP_playback_step_one(c->pb, c->dc);
+        if (result != DP_PLAYER_SUCCESS) {
+            return result;
+        }
+    }
+    return DP_PLAYER_SUCCESS;
+}
+
+static DP_PlayerResult advance_ms(DP_RenderFramesContext *c)
+{
+    long long wait_ms;
+    DP_PlayerResult result =
+        DP_playback_poll(c->pb, c->dc, c->options->step, &wait_ms);
+    // Stepping skips the rest of the interval it's in the middle of.
+    if (c->options->skip_pauses) {
+        while (result == DP_PLAYER_SUCCESS && !canvas_changed(c)
+               && !at_end(c)) {
+            result = DP_playback_step_one(c->pb, c->dc);
+        }
+    }
+    return result;
+}
+
+static bool render_frames(DP_RenderFramesContext *c)
+{
+    bool by_messages = c->options->every == DP_RENDER_FRAMES_EVERY_MESSAGES;
+    DP_playback_play(c->pb, 1.0);
+    while (!at_end(c)) {
+        DP_PlayerResult result =
+            by_messages ? advance_messages(c) : advance_ms(c);
+        if (result == DP_PLAYER_SUCCESS) {
+            if (!emit_frame(c, true)) {
+                return false;
+            }
+        }
+        else if (result == DP_PLAYER_RECORDING_END) {
+            return emit_frame(c, false);
+        }
+        else {
+            return false;
+        }
+    }
+    return true;
+}
+
+bool DP_render_frames(DP_Player *player, DP_DrawContext *dc,
+                      const DP_RenderFramesOptions *options,
+                      DP_RenderFramesFrameFn on_frame, void *user)
+{
+    DP_ASSERT(player);
+    DP_ASSERT(dc);
+    DP_ASSERT(options);
+    DP_ASSERT(options->step > 0);
+    DP_ASSERT(on_frame);
+    DP_RenderFramesContext c = {
+        DP_playback_new(player), dc, options, on_frame, user, NULL, NULL};
+
+    DP_PlayerResult result = skip_to_start(&c);
+    bool ok = result == DP_PLAYER_RECORDING_END
+           || (result == DP_PLAYER_SUCCESS && render_frames(&c));
+
+    DP_image_free(c.last_img);
+    DP_canvas_state_decref_nullable(c.last_cs);
+    DP_playback_free(c.pb);
+    return ok;
+}
+
+
+typedef struct DP_RenderFramesPngContext {
+    const char *path_prefix;
+    long long count;
+} DP_RenderFramesPngContext;
+
+static bool write_png(void *user, DP_Image *img, DP_UNUSED long long position,
+                      DP_UNUSED bool duplicate)
+{
+    DP_RenderFramesPngContext *c = user;
+    char *path = DP_format("%s%06lld.png", c->path_prefix, ++c->count);
+    DP_Output *output = DP_file_output_new_from_path(path);
+    DP_free(path);
+    if (!output) {
+        return false;
+    }
+    bool written = DP_image_write_png(img, output);
+    return DP_output_free(output) && written;
+}
+
+bool DP_render_frames_to_png(DP_Player *player, DP_DrawContext *dc,
+                             const DP_RenderFramesOptions *options,
+                             const char *path_prefix)
+{
+    DP_ASSERT(path_prefix);
+    DP_RenderFramesPngContext c = {path_prefix, 0};
+    return DP_render_frames(player, dc, options, write_png, &c);
+}
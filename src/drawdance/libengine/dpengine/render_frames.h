@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_RENDER_FRAMES_H
+#define DPENGINE_RENDER_FRAMES_H
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_Image DP_Image;
+typedef struct DP_Player DP_Player;
+
+
+typedef enum DP_RenderFramesEvery {
+    DP_RENDER_FRAMES_EVERY_MESSAGES,
+    DP_RENDER_FRAMES_EVERY_MS,
+} DP_RenderFramesEvery;
+
+typedef struct DP_RenderFramesOptions {
+    // Whether frames are spaced out by message count or by interval time.
+    DP_RenderFramesEvery every;
+    // How many messages or milliseconds of interval time go into each frame.
+    long long step;
+    // Frames get scaled to fit into this size, keeping their aspect ratio and
+    // leaving transparent bars on the sides. Zero keeps the cropped size.
+    int width, height;
+    // Part of the canvas to render, an invalid rectangle renders all of it.
+    DP_Rect crop;
+    // When going by interval time, jumps straight to the next change instead
+    // of rendering frames through a pause.
+    bool skip_pauses;
+    // Message position to start at, everything before gets played through
+    // without rendering anything.
+    long long start;
+    // Message position to stop at, negative to render until the end. When
+    // going by interval time, the last frame may end up a bit past it.
+    long long end;
+    // Frames where the canvas didn't change are skipped by default. This
+    // passes the previous frame again instead, to keep a steady frame rate.
+    bool duplicate_unchanged;
+} DP_RenderFramesOptions;
+
+// The image is only borrowed for the duration of the call. The position is the
+// message index the frame was rendered at. Return false to stop.
+typedef bool (*DP_RenderFramesFrameFn)(void *user, DP_Image *img,
+                                       long long position, bool duplicate);
+
+// Plays the recording back through a canvas of its own and renders flattened
+// frames from it as it goes. The canvas isn't rendered before it's been given
+// a size, and a final frame gets rendered when the recording ends partway
+// through a step. Takes ownership of the player. Returns false on errors or if
+// on_frame asks to stop.
+bool DP_render_frames(DP_Player *player, DP_DrawContext *dc,
+                      const DP_RenderFramesOptions *options,
+                      DP_RenderFramesFrameFn on_frame, void *user);
+
+// Renders frames as numbered PNG files, starting at 000001 and going up by one
+// for each frame, so the result can be fed to ffmpeg or similar. The number
+// and extension get appended to the given path prefix.
+bool DP_render_frames_to_png(DP_Player *player, DP_DrawContext *dc,
+                             const DP_RenderFramesOptions *options,
+                             const char *path_prefix);
+
+
+#endif
@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: MIT
+#include "reset_tracker.h"
+#include "canvas_history.h"
+#include <dpcommon/common.h>
+#include <dpmsg/message.h>
+
+
+struct DP_ResetTracker {
+    DP_CanvasHistory *ch;
+    DP_CanvasHistory *snapshot_ch;
+    DP_ResetTrackerState state;
+    int fork_skip;
+    DP_ResetTrackerStateChangedFn fn;
+    void *user;
+};
+
+
+DP_ResetTracker *DP_reset_tracker_new(DP_CanvasHistory *ch,
+                                      DP_ResetTrackerStateChangedFn fn,
+                                      void *user)
+{
+    DP_ASSERT(ch);
+    DP_ResetTracker *rt = DP_malloc(sizeof(*rt));
+    *rt = (DP_ResetTracker){ch, NULL, DP_RESET_TRACKER_NORMAL, 0, fn, user};
+    return rt;
+}
+
+void DP_reset_tracker_free(DP_ResetTracker *rt)
+{
+    if (rt) {
+        DP_canvas_history_free(rt->snapshot_ch);
+        DP_free(rt);
+    }
+}
+
+DP_ResetTrackerState DP_reset_tracker_state(DP_ResetTracker *rt)
+{
+    DP_ASSERT(rt);
+    return rt->state;
+}
+
+
+static void set_state(DP_ResetTracker *rt, DP_ResetTrackerState state)
+{
+    if (rt->state != state) {
+        rt->state = state;
+        if (rt->fn) {
+            rt->fn(rt->user, state);
+        }
+    }
+}
+
+static void discard_snapshot(DP_ResetTracker *rt)
+{
+    DP_canvas_history_free(rt->snapshot_ch);
+    rt->snapshot_ch = NULL;
+}
+
+void DP_reset_tracker_begin(DP_ResetTracker *rt)
+{
+    DP_ASSERT(rt);
+    if (rt->state == DP_RESET_TRACKER_NORMAL) {
+        rt->fork_skip = DP_canvas_history_local_fork_count(rt->ch);
+    }
+    else if (rt->state == DP_RESET_TRACKER_RECEIVING_SNAPSHOT) {
+        DP_warn("Reset began again while receiving snapshot");
+        discard_snapshot(rt);
+    }
+    set_state(rt, DP_RESET_TRACKER_PENDING);
+}
+
+
+static bool handle_message(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                           DP_Message *msg, DP_MessageType type)
+{
+    if (type == DP_MSG_UNDO_DEPTH) {
+        DP_MsgUndoDepth *mud = DP_message_internal(msg);
+        DP_canvas_history_undo_depth_limit_set(ch, dc,
+                                               DP_msg_undo_depth_depth(mud));
+        return true;
+    }
+    else if (type == DP_MSG_SOFT_RESET) {
+        DP_canvas_history_soft_reset(ch, dc, DP_message_context_id(msg), NULL,
+                                     NULL);
+        return true;
+    }
+    else if (DP_message_type_command(type)) {
+        return DP_canvas_history_handle(ch, dc, msg);
+    }
+    else {
+        return true;
+    }
+}
+
+static void start_snapshot(DP_ResetTracker *rt)
+{
+    DP_ASSERT(!rt->snapshot_ch);
+    rt->snapshot_ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_CanvasHistorySizeLimits limits = DP_canvas_history_size_limits(rt->ch);
+    DP_canvas_history_size_limits_set(rt->snapshot_ch, &limits);
+    set_state(rt, DP_RESET_TRACKER_RECEIVING_SNAPSHOT);
+}
+
+bool DP_reset_tracker_handle(DP_ResetTracker *rt, DP_DrawContext *dc,
+                             DP_Message *msg)
+{
+    DP_ASSERT(rt);
+    DP_ASSERT(dc);
+    DP_ASSERT(msg);
+    DP_MessageType type = DP_message_type(msg);
+    switch (rt->state) {
+    case DP_RESET_TRACKER_NORMAL:
+        return handle_message(rt->ch, dc, msg, type);
+    case DP_RESET_TRACKER_PENDING:
+        // Snapshots always start with a canvas resize.
+        if (type != DP_MSG_CANVAS_RESIZE) {
+            return true;
+        }
+        start_snapshot(rt);
+        return handle_message(rt->snapshot_ch, dc, msg, type);
+    case DP_RESET_TRACKER_RECEIVING_SNAPSHOT:
+        return handle_message(rt->snapshot_ch, dc, msg, type);
+    }
+    DP_UNREACHABLE();
+}
+
+bool DP_reset_tracker_handle_local(DP_ResetTracker *rt, DP_DrawContext *dc,
+                                   DP_Message *msg)
+{
+    DP_ASSERT(rt);
+    DP_ASSERT(dc);
+    DP_ASSERT(msg);
+    return DP_canvas_history_handle_local(rt->ch, dc, msg);
+}
+
+bool DP_reset_tracker_end(DP_ResetTracker *rt, DP_DrawContext *dc)
+{
+    DP_ASSERT(rt);
+    DP_ASSERT(dc);
+    switch (rt->state) {
+    case DP_RESET_TRACKER_NORMAL:
+        return true;
+    case DP_RESET_TRACKER_PENDING:
+        DP_error_set("Reset ended without a snapshot");
+        set_state(rt, DP_RESET_TRACKER_NORMAL);
+        return false;
+    case DP_RESET_TRACKER_RECEIVING_SNAPSHOT:
+        DP_canvas_history_take_over(rt->ch, dc, rt->snapshot_ch,
+                                    rt->fork_skip);
+        discard_snapshot(rt);
+        set_state(rt, DP_RESET_TRACKER_NORMAL);
+        return true;
+    }
+    DP_UNREACHABLE();
+}
+
+void DP_reset_tracker_abort(DP_ResetTracker *rt)
+{
+    DP_ASSERT(rt);
+    discard_snapshot(rt);
+    set_state(rt, DP_RESET_TRACKER_NORMAL);
+}
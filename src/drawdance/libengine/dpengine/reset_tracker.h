@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_RESET_TRACKER_H
+#define DPENGINE_RESET_TRACKER_H
+#include <dpcommon/common.h>
+
+typedef struct DP_CanvasHistory DP_CanvasHistory;
+typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_Message DP_Message;
+
+
+typedef enum DP_ResetTrackerState {
+    DP_RESET_TRACKER_NORMAL,
+    DP_RESET_TRACKER_PENDING,
+    DP_RESET_TRACKER_RECEIVING_SNAPSHOT,
+} DP_ResetTrackerState;
+
+// Called whenever the state changes, so that the UI can show that the session
+// is being reset while it's not in the normal state.
+typedef void (*DP_ResetTrackerStateChangedFn)(void *user,
+                                              DP_ResetTrackerState state);
+
+// Handles session resets, where the history gets replaced by a snapshot sent
+// by the server. The snapshot is built on a canvas history of its own while
+// the current canvas stays visible, then swapped in once it's complete. Local
+// strokes made during the reset are kept on top of it.
+typedef struct DP_ResetTracker DP_ResetTracker;
+
+// Doesn't take ownership of the canvas history.
+DP_ResetTracker *DP_reset_tracker_new(DP_CanvasHistory *ch,
+                                      DP_ResetTrackerStateChangedFn fn,
+                                      void *user);
+
+void DP_reset_tracker_free(DP_ResetTracker *rt);
+
+DP_ResetTrackerState DP_reset_tracker_state(DP_ResetTracker *rt);
+
+// The reset began, the snapshot starts with the next canvas resize. Remote
+// messages before that are from the old session, which the snapshot already
+// contains, so they're discarded. If a snapshot was already being received,
+// it was cut off and gets thrown away in favor of the new one.
+void DP_reset_tracker_begin(DP_ResetTracker *rt);
+
+// Handles a remote message according to the current state.
+bool DP_reset_tracker_handle(DP_ResetTracker *rt, DP_DrawContext *dc,
+                             DP_Message *msg);
+
+// Local messages always go onto the current canvas.
+bool DP_reset_tracker_handle_local(DP_ResetTracker *rt, DP_DrawContext *dc,
+                                   DP_Message *msg);
+
+// The snapshot is complete, swaps it in. Local strokes made before the reset
+// began are dropped, since the server either put them into the snapshot or
+// sends them after it. If no snapshot was received, the rest of the reset
+// went missing. The current canvas is kept then and this returns false.
+bool DP_reset_tracker_end(DP_ResetTracker *rt, DP_DrawContext *dc);
+
+// Throws away a reset in progress, like when the connection was lost. The
+// current canvas stays, but since the server doesn't have it anymore, the
+// caller should ask for another reset or reconnect.
+void DP_reset_tracker_abort(DP_ResetTracker *rt);
+
+
+#endif
@@ -22,6 +22,7 @@
 #include "save.h"
 #include "annotation.h"
 #include "annotation_list.h"
+#include "canvas_history.h"
 #include "canvas_state.h"
 #include "document_metadata.h"
 #include "draw_context.h"
@@ -33,6 +34,7 @@
 #include "layer_list.h"
 #include "layer_props.h"
 #include "layer_props_list.h"
+#include "playback.h"
 #include "rust.h"
 #include "tile.h"
 #include "timeline.h"
@@ -53,6 +55,7 @@
 #include <jo_gifx.h>
 #include <limits.h>
 #include <math.h>
+#include <parson.h>
 #include <uthash_inc.h>
 
 #define DP_PERF_CONTEXT "save"
@@ -338,8 +341,52 @@ static bool ora_store_background(DP_SaveOraContext *c, DP_CanvasState *cs)
     return true;
 }
 
+static void blend_annotation(DP_Pixel8 *restrict dst, DP_Rect dst_rect,
+                             const DP_Pixel8 *restrict src, DP_Rect src_rect)
+{
+    int dst_width = DP_rect_width(dst_rect);
+    int src_x = DP_rect_x(src_rect);
+    int src_y = DP_rect_y(src_rect);
+    int src_width = DP_rect_width(src_rect);
+    DP_Rect rect = DP_rect_intersection(dst_rect, src_rect);
+    int stride = DP_rect_width(rect);
+    for (int y = rect.y1; y <= rect.y2; ++y) {
+        DP_blend_pixels8(dst + dst_width * y + rect.x1,
+                         src + (y - src_y) * src_width + (rect.x1 - src_x),
+                         stride, 255);
+    }
+}
+
+static void bake_annotations(DP_AnnotationList *al, DP_DrawContext *dc,
+                             DP_Image *img,
+                             DP_SaveBakeAnnotationFn bake_annotation,
+                             void *user)
+{
+    int annotation_count = DP_annotation_list_count(al);
+    DP_Rect img_rect =
+        DP_rect_make(0, 0, DP_image_width(img), DP_image_height(img));
+    for (int i = 0; i < annotation_count; ++i) {
+        DP_Annotation *a = DP_annotation_list_at_noinc(al, i);
+        int width = DP_annotation_width(a);
+        int height = DP_annotation_height(a);
+        DP_Rect annotation_rect =
+            DP_rect_make(DP_annotation_x(a), DP_annotation_y(a), width, height);
+        if (DP_rect_intersects(img_rect, annotation_rect)) {
+            size_t size = DP_int_to_size(width) * DP_int_to_size(height)
+                        * sizeof(DP_Pixel8);
+            void *buffer = DP_draw_context_pool_require(dc, size);
+            if (bake_annotation(user, a, buffer)) {
+                blend_annotation(DP_image_pixels(img), img_rect, buffer,
+                                 annotation_rect);
+            }
+        }
+    }
+}
+
 static bool ora_store_merged(DP_SaveOraContext *c, DP_CanvasState *cs,
-                             DP_DrawContext *dc)
+                             DP_DrawContext *dc,
+                             DP_SaveBakeAnnotationFn bake_annotation,
+                             void *user)
 {
     DP_Image *img = DP_canvas_state_to_flat_image(
         cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
@@ -347,6 +394,13 @@ static bool ora_store_merged(DP_SaveOraContext *c, DP_CanvasState *cs,
         return false;
     }
 
+    // Other programs only look at the merged image and the thumbnail made
+    // from it, so the annotations go in there. The layers don't get them.
+    if (bake_annotation) {
+        bake_annotations(DP_canvas_state_annotations_noinc(cs), dc, img,
+                         bake_annotation, user);
+    }
+
     if (!ora_store_png_image(c, img, "mergedimage.png")) {
         DP_image_free(img);
         return false;
@@ -435,6 +489,36 @@ static void ora_write_layer_props_xml(DP_SaveOraContext *c, DP_Output *output,
     if (DP_layer_props_censored(lp)) {
         DP_OUTPUT_PRINT_LITERAL(output, " drawpile:censored=\"true\"");
     }
+
+    if (DP_layer_props_alpha_lock(lp)) {
+        DP_OUTPUT_PRINT_LITERAL(output, " drawpile:alpha-lock=\"true\"");
+    }
+
+    if (DP_layer_props_fixed(lp)) {
+        DP_OUTPUT_PRINT_LITERAL(output, " drawpile:fixed=\"true\"");
+    }
+
+    int color_tag = DP_layer_props_color_tag(lp);
+    if (color_tag != DP_LAYER_COLOR_TAG_NONE) {
+        ORA_APPEND_ATTR(c, output, "drawpile:colorlabel", "%d", color_tag);
+    }
+
+    int metadata_count = DP_layer_props_metadata_count(lp);
+    if (metadata_count != 0) {
+        JSON_Value *value = json_value_init_object();
+        JSON_Object *object = json_value_get_object(value);
+        for (int i = 0; i < metadata_count; ++i) {
+            json_object_set_string(
+                object, DP_layer_props_metadata_key_at(lp, i, NULL),
+                DP_layer_props_metadata_value_at(lp, i, NULL));
+        }
+        char *json = json_serialize_to_string(value);
+        if (json) {
+            ORA_APPEND_ATTR(c, output, "drawpile:metadata", "%s", json);
+            json_free_serialized_string(json);
+        }
+        json_value_free(value);
+    }
 }
 
 static void ora_write_layers_xml(DP_SaveOraContext *c, DP_Output *output,
@@ -519,6 +603,10 @@ static void ora_write_annotations_xml(DP_SaveOraContext *c, DP_Output *output,
             ORA_APPEND_ATTR(
                 c, output, "bg", "#%x",
                 DP_uint32_to_uint(DP_annotation_background_color(a)));
+            int border = DP_annotation_border(a);
+            if (border != 0) {
+                ORA_APPEND_ATTR(c, output, "border", "%d", border);
+            }
             DP_OUTPUT_PRINT_LITERAL(output, "><![CDATA[");
             ora_write_cdata_content(output, DP_annotation_text(a, NULL));
             DP_OUTPUT_PRINT_LITERAL(output, "]]></drawpile:a>");
@@ -655,7 +743,9 @@ static bool ora_store_xml(DP_SaveOraContext *c, DP_CanvasState *cs)
 }
 
 static DP_SaveResult save_ora(DP_CanvasState *cs, const char *path,
-                              DP_DrawContext *dc)
+                              DP_DrawContext *dc,
+                              DP_SaveBakeAnnotationFn bake_annotation,
+                              void *user)
 {
     DP_ZipWriter *zw = DP_zip_writer_new(path);
     if (!zw) {
@@ -675,7 +765,8 @@ static DP_SaveResult save_ora(DP_CanvasState *cs, const char *path,
     bool content_ok =
         ora_store_layers(&c, &next_index, DP_canvas_state_layers_noinc(cs),
                          DP_canvas_state_layer_props_noinc(cs))
-        && ora_store_background(&c, cs) && ora_store_merged(&c, cs, dc)
+        && ora_store_background(&c, cs)
+        && ora_store_merged(&c, cs, dc, bake_annotation, user)
         && ora_store_xml(&c, cs);
     save_ora_context_dispose(&c);
     if (!content_ok) {
@@ -717,55 +808,12 @@ static DP_SaveResult save_jpeg(DP_Image *img, DP_Output *output)
     }
 }
 
-static void blend_annotation(DP_Pixel8 *restrict dst, DP_Rect dst_rect,
-                             const DP_Pixel8 *restrict src, DP_Rect src_rect)
-{
-    int dst_width = DP_rect_width(dst_rect);
-    int src_x = DP_rect_x(src_rect);
-    int src_y = DP_rect_y(src_rect);
-    int src_width = DP_rect_width(src_rect);
-    DP_Rect rect = DP_rect_intersection(dst_rect, src_rect);
-    int stride = DP_rect_width(rect);
-    for (int y = rect.y1; y <= rect.y2; ++y) {
-        DP_blend_pixels8(dst + dst_width * y + rect.x1,
-                         src + (y - src_y) * src_width + (rect.x1 - src_x),
-                         stride, 255);
-    }
-}
-
-static void bake_annotations(DP_AnnotationList *al, DP_DrawContext *dc,
-                             DP_Image *img,
-                             DP_SaveBakeAnnotationFn bake_annotation,
-                             void *user)
-{
-    int annotation_count = DP_annotation_list_count(al);
-    DP_Rect img_rect =
-        DP_rect_make(0, 0, DP_image_width(img), DP_image_height(img));
-    for (int i = 0; i < annotation_count; ++i) {
-        DP_Annotation *a = DP_annotation_list_at_noinc(al, i);
-        int width = DP_annotation_width(a);
-        int height = DP_annotation_height(a);
-        DP_Rect annotation_rect =
-            DP_rect_make(DP_annotation_x(a), DP_annotation_y(a), width, height);
-        if (DP_rect_intersects(img_rect, annotation_rect)) {
-            size_t size = DP_int_to_size(width) * DP_int_to_size(height)
-                        * sizeof(DP_Pixel8);
-            void *buffer = DP_draw_context_pool_require(dc, size);
-            if (bake_annotation(user, a, buffer)) {
-                blend_annotation(DP_image_pixels(img), img_rect, buffer,
-                                 annotation_rect);
-            }
-        }
-    }
-}
-
 static DP_SaveResult save_flat_image(
     DP_CanvasState *cs, DP_DrawContext *dc, DP_Rect *crop, const char *path,
-    DP_SaveResult (*save_fn)(DP_Image *, DP_Output *), DP_ViewModeFilter vmf,
-    DP_SaveBakeAnnotationFn bake_annotation, void *user)
+    DP_SaveResult (*save_fn)(DP_Image *, DP_Output *), unsigned int flags,
+    DP_ViewModeFilter vmf, DP_SaveBakeAnnotationFn bake_annotation, void *user)
 {
-    DP_Image *img = DP_canvas_state_to_flat_image(
-        cs, DP_FLAT_IMAGE_RENDER_FLAGS, crop, &vmf);
+    DP_Image *img = DP_canvas_state_to_flat_image(cs, flags, crop, &vmf);
     if (!img) {
         DP_warn("Save: %s", DP_error());
         return DP_SAVE_RESULT_FLATTEN_ERROR;
@@ -796,13 +844,15 @@ static DP_SaveResult save(DP_CanvasState *cs, DP_DrawContext *dc,
 {
     switch (type) {
     case DP_SAVE_IMAGE_ORA:
-        return save_ora(cs, path, dc);
+        return save_ora(cs, path, dc, bake_annotation, user);
     case DP_SAVE_IMAGE_PNG:
         return save_flat_image(cs, dc, NULL, path, save_png,
+                               DP_FLAT_IMAGE_RENDER_FLAGS,
                                DP_view_mode_filter_make_default(),
                                bake_annotation, user);
     case DP_SAVE_IMAGE_JPEG:
         return save_flat_image(cs, dc, NULL, path, save_jpeg,
+                               DP_FLAT_IMAGE_RENDER_FLAGS,
                                DP_view_mode_filter_make_default(),
                                bake_annotation, user);
     case DP_SAVE_IMAGE_PSD:
@@ -828,6 +878,94 @@ DP_SaveResult DP_save(DP_CanvasState *cs, DP_DrawContext *dc,
     }
 }
 
+static DP_PlayerCompatibility recording_compatibility(DP_Player *player)
+{
+    if (DP_player_type(player) == DP_PLAYER_TYPE_DEBUG_DUMP) {
+        return DP_PLAYER_COMPATIBLE;
+    }
+    else {
+        return DP_player_compatibility(player);
+    }
+}
+
+static bool resolve_recording_position(DP_Player *player, long long position,
+                                       const char *marker_or_null,
+                                       long long *out_position)
+{
+    if (!marker_or_null) {
+        *out_position = position;
+        return true;
+    }
+
+    DP_PlayerIndexMarker marker;
+    if (!DP_player_index_loaded(player)) {
+        DP_error_set("Finding marker '%s' requires an index", marker_or_null);
+        return false;
+    }
+    else if (!DP_player_index_marker_search(player, marker_or_null, &marker)) {
+        DP_error_set("Marker '%s' not found", marker_or_null);
+        return false;
+    }
+    else {
+        // Markers don't change the canvas, so stopping right before it is
+        // the same as playing through it.
+        *out_position = marker.message_index;
+        return true;
+    }
+}
+
+static DP_SaveResult save_recording_at(DP_Playback *pb, DP_DrawContext *dc,
+                                       long long position,
+                                       DP_SaveImageType type, const char *path,
+                                       DP_SaveBakeAnnotationFn bake_annotation,
+                                       void *user)
+{
+    DP_PlayerResult result = DP_playback_replay_to(pb, dc, position);
+    if (result == DP_PLAYER_SUCCESS) {
+        DP_CanvasState *cs =
+            DP_canvas_history_get(DP_playback_canvas_history(pb));
+        DP_SaveResult save_result =
+            DP_save(cs, dc, type, path, bake_annotation, user);
+        DP_canvas_state_decref(cs);
+        return save_result;
+    }
+    else if (result == DP_PLAYER_ERROR_OPERATION
+             || result == DP_PLAYER_RECORDING_END) {
+        return DP_SAVE_RESULT_BAD_ARGUMENTS;
+    }
+    else {
+        return DP_SAVE_RESULT_INTERNAL_ERROR;
+    }
+}
+
+DP_SaveResult DP_save_recording_at(
+    DP_Player *player, DP_DrawContext *dc, long long position,
+    const char *marker_or_null, DP_SaveImageType type, const char *path,
+    DP_SaveBakeAnnotationFn bake_annotation, void *user)
+{
+    DP_ASSERT(player);
+    DP_ASSERT(dc);
+    long long target;
+    if (recording_compatibility(player) == DP_PLAYER_INCOMPATIBLE) {
+        DP_error_set("Recording is incompatible");
+        DP_player_free(player);
+        return DP_SAVE_RESULT_BAD_ARGUMENTS;
+    }
+    else if (!resolve_recording_position(player, position, marker_or_null,
+                                         &target)) {
+        DP_player_free(player);
+        return DP_SAVE_RESULT_BAD_ARGUMENTS;
+    }
+
+    DP_PERF_BEGIN_DETAIL(fn, "recording", "position=%lld", target);
+    DP_Playback *pb = DP_playback_new(player);
+    DP_SaveResult result = save_recording_at(pb, dc, target, type, path,
+                                             bake_annotation, user);
+    DP_playback_free(pb);
+    DP_PERF_END(fn);
+    return result;
+}
+
 
 #if defined(_WIN32)
 #    define PREFERRED_PATH_SEPARATOR "\\"
@@ -892,8 +1030,10 @@ static char *save_frame(struct DP_SaveFrameContext *c, DP_ViewModeBuffer *vmb,
 {
     DP_CanvasState *cs = c->cs;
     char *path = format_frame_path(c, frame_index);
+    // Frames already get saved in parallel, don't multiply the threads.
     DP_SaveResult result = save_flat_image(
         cs, NULL, c->crop, path, save_png,
+        DP_FLAT_IMAGE_RENDER_FLAGS | DP_FLAT_IMAGE_SINGLE_THREADED,
         DP_view_mode_filter_make_frame(vmb, cs, frame_index, NULL), NULL, NULL);
     set_error_result(c, result);
     return path;
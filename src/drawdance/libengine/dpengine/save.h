@@ -26,6 +26,7 @@
 typedef struct DP_Annotation DP_Annotation;
 typedef struct DP_CanvasState DP_CanvasState;
 typedef struct DP_DrawContext DP_DrawContext;
+typedef struct DP_Player DP_Player;
 typedef struct DP_Rect DP_Rect;
 
 
@@ -37,6 +38,11 @@ typedef struct DP_SaveFormat {
 // Returns supported formats for saving, terminated by a {NULL, NULL} element.
 const DP_SaveFormat *DP_save_supported_formats(void);
 
+// Renders the annotation's background and text into the given buffer of
+// width times height premultiplied pixels, returns false to leave it out. The
+// engine has no fonts, so the host does the text layout and alignment. Baked
+// annotations get blended into flat images and the ORA merged image, in list
+// order, so later annotations end up on top.
 typedef bool (*DP_SaveBakeAnnotationFn)(void *user, DP_Annotation *a,
                                         unsigned char *out);
 
@@ -67,6 +73,18 @@ DP_SaveResult DP_save(DP_CanvasState *cs, DP_DrawContext *dc,
                       DP_SaveImageType type, const char *path,
                       DP_SaveBakeAnnotationFn bake_annotation, void *user);
 
+// Plays the recording back to the given message index, or through the marker
+// with the given text if one is given, and saves the canvas as it was there.
+// Messages are read from the recording as they're played, so it's never
+// loaded whole. If the player's index is loaded, it gets used to seek, markers
+// require it. Incompatible recordings, unknown markers and positions outside
+// of the recording give DP_SAVE_RESULT_BAD_ARGUMENTS, check the compatibility
+// beforehand to tell the user why. Takes ownership of the player.
+DP_SaveResult DP_save_recording_at(
+    DP_Player *player, DP_DrawContext *dc, long long position,
+    const char *marker_or_null, DP_SaveImageType type, const char *path,
+    DP_SaveBakeAnnotationFn bake_annotation, void *user);
+
 
 typedef bool (*DP_SaveAnimationProgressFn)(void *user, double progress);
 
@@ -2,11 +2,11 @@
 use anyhow::Result;
 use drawdance::{
     common::Output,
-    dp_error_set,
+    dp_error_set_panic,
     engine::{
         BaseCanvasState, BaseLayerContent, BaseLayerGroup, BaseLayerList, BaseLayerProps,
-        BaseLayerPropsList, CanvasState, LayerContent, LayerList, LayerProps, LayerPropsList,
-        TransientLayerContent,
+        BaseLayerPropsList, CanvasState, FlattenOptions, LayerContent, LayerList, LayerProps,
+        LayerPropsList, TransientLayerContent,
     },
     DP_BlendMode, DP_CanvasState, DP_DrawContext, DP_SaveResult, DP_UPixel8,
     DP_draw_context_pool_require, DP_BLEND_MODE_ADD, DP_BLEND_MODE_BURN, DP_BLEND_MODE_COLOR,
@@ -20,7 +20,8 @@ use drawdance::{
 };
 use std::{
     collections::HashMap,
-    ffi::{c_char, c_int, c_void},
+    ffi::c_void,
+    os::raw::{c_char, c_int},
     panic::catch_unwind,
     ptr::null_mut,
     slice::from_raw_parts_mut,
@@ -352,6 +353,7 @@ fn rle_compress(src: &[u8], dst: &mut [u8]) -> Result<usize> {
 }
 // SPDX-SnippetEnd
 
+#[allow(clippy::too_many_arguments)]
 fn write_channel(
     out: &mut Output,
     rows: usize,
@@ -410,6 +412,7 @@ fn write_pixel_channels(
     Ok((a, r, g, b))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn write_pixel_data(
     dc: *mut DP_DrawContext,
     out: &mut Output,
@@ -630,7 +633,7 @@ fn write_merged_image(cs: &CanvasState, dc: *mut DP_DrawContext, mut out: Output
     out.write_bytes(counts)?;
 
     let size = rows * stride;
-    let buffer = cs.to_flat_separated_urgba8()?;
+    let buffer = cs.to_flat_separated_urgba8(&FlattenOptions::default())?;
     for i in 0..4 {
         let counts_start = i * counts_stride;
         let counts_end = counts_start + counts_stride;
@@ -701,10 +704,11 @@ pub extern "C" fn DP_save_psd(
     path: *const c_char,
     dc: *mut DP_DrawContext,
 ) -> DP_SaveResult {
-    if let Ok(save_result) = catch_unwind(|| save_psd(cs, path, dc)) {
-        save_result
-    } else {
-        dp_error_set("Panic while saving PSD");
-        DP_SAVE_RESULT_INTERNAL_ERROR
+    match catch_unwind(|| save_psd(cs, path, dc)) {
+        Ok(save_result) => save_result,
+        Err(payload) => {
+            dp_error_set_panic("saving PSD", payload.as_ref());
+            DP_SAVE_RESULT_INTERNAL_ERROR
+        }
     }
 }
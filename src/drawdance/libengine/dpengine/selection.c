@@ -0,0 +1,586 @@
+// SPDX-License-Identifier: MIT
+#include "selection.h"
+#include <dpcommon/atomic.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <limits.h>
+#include <math.h>
+#include <stdlib.h>
+
+
+// Number of sub-scanlines per pixel row when rasterizing shapes. Coverage is
+// exact horizontally, so this only matters for edges that are close to flat.
+#define SUBSAMPLES 16
+
+struct DP_Selection {
+    DP_Atomic refcount;
+    DP_Rect bounds;
+    uint8_t coverage[];
+};
+
+typedef struct DP_SelectionCrossing {
+    double x;
+    int direction;
+} DP_SelectionCrossing;
+
+
+static DP_Selection *alloc_selection(DP_Rect bounds)
+{
+    size_t count =
+        DP_rect_valid(bounds)
+            ? DP_int_to_size(DP_rect_width(bounds))
+                  * DP_int_to_size(DP_rect_height(bounds))
+            : 0;
+    DP_Selection *sel =
+        DP_malloc(DP_FLEX_SIZEOF(DP_Selection, coverage, count));
+    DP_atomic_set(&sel->refcount, 1);
+    sel->bounds = bounds;
+    return sel;
+}
+
+DP_Selection *DP_selection_new_empty(void)
+{
+    return alloc_selection((DP_Rect){0, 0, -1, -1});
+}
+
+DP_Selection *DP_selection_new_from_mask(int x, int y, int width, int height,
+                                         const uint8_t *coverage)
+{
+    DP_ASSERT(width <= 0 || height <= 0 || coverage);
+    int min_x = INT_MAX, min_y = INT_MAX, max_x = INT_MIN, max_y = INT_MIN;
+    for (int my = 0; my < height; ++my) {
+        for (int mx = 0; mx < width; ++mx) {
+            if (coverage[my * width + mx] != 0) {
+                min_x = DP_min_int(min_x, mx);
+                min_y = DP_min_int(min_y, my);
+                max_x = DP_max_int(max_x, mx);
+                max_y = DP_max_int(max_y, my);
+            }
+        }
+    }
+
+    if (min_x > max_x) {
+        return DP_selection_new_empty();
+    }
+
+    DP_Selection *sel =
+        alloc_selection((DP_Rect){x + min_x, y + min_y, x + max_x, y + max_y});
+    int trimmed_width = max_x - min_x + 1;
+    for (int my = min_y; my <= max_y; ++my) {
+        memcpy(sel->coverage + (my - min_y) * trimmed_width,
+               coverage + my * width + min_x, DP_int_to_size(trimmed_width));
+    }
+    return sel;
+}
+
+DP_Selection *DP_selection_new_rect(DP_Rect rect)
+{
+    if (DP_rect_valid(rect)) {
+        DP_Selection *sel = alloc_selection(rect);
+        memset(sel->coverage, 255,
+               DP_int_to_size(DP_rect_width(rect))
+                   * DP_int_to_size(DP_rect_height(rect)));
+        return sel;
+    }
+    else {
+        return DP_selection_new_empty();
+    }
+}
+
+
+// Adds the horizontal span from xa to xb, weighted by the given amount, to a
+// row of coverage accumulators starting at x0. Pixels only partially touched
+// by the span get the fraction of them it covers.
+static void add_span(float *row, int x0, int width, double xa, double xb,
+                     float weight)
+{
+    xa = DP_max_double(xa, x0);
+    xb = DP_min_double(xb, x0 + width);
+    if (xb > xa) {
+        int ia = DP_double_to_int(floor(xa));
+        int ib = DP_double_to_int(floor(xb));
+        if (ia == ib) {
+            row[ia - x0] += DP_double_to_float(xb - xa) * weight;
+        }
+        else {
+            row[ia - x0] += DP_double_to_float(ia + 1 - xa) * weight;
+            for (int i = ia + 1; i < ib; ++i) {
+                row[i - x0] += weight;
+            }
+            if (ib < x0 + width) {
+                row[ib - x0] += DP_double_to_float(xb - ib) * weight;
+            }
+        }
+    }
+}
+
+// Without anti-aliasing, a pixel is either in the span or not, depending on
+// whether its center is.
+static void add_aliased_span(float *row, int x0, int width, double xa,
+                             double xb)
+{
+    int ia = DP_max_int(DP_double_to_int(ceil(xa - 0.5)), x0);
+    int ib = DP_min_int(DP_double_to_int(ceil(xb - 0.5)), x0 + width);
+    for (int i = ia; i < ib; ++i) {
+        row[i - x0] += 1.0f;
+    }
+}
+
+static bool is_inside(int winding, bool even_odd)
+{
+    return even_odd ? winding % 2 != 0 : winding != 0;
+}
+
+typedef int (*DP_SelectionSpansFn)(void *user, double y,
+                                    DP_SelectionCrossing *crossings);
+
+// Rasterizes a shape within the given bounds. The callback gets each
+// sub-scanline from top to bottom and puts the points where the shape's
+// edges cross it into the given buffer, returning how many there are. They
+// must be sorted from left to right, the direction says whether an edge goes
+// up or down. Without anti-aliasing, there's only one sub-scanline through
+// the middle of each row.
+static DP_Selection *rasterize(DP_Rect bounds, DP_SelectionSpansFn get_spans,
+                               void *user, DP_SelectionCrossing *crossings,
+                               bool even_odd, bool antialias)
+{
+    if (!DP_rect_valid(bounds)) {
+        return DP_selection_new_empty();
+    }
+
+    int width = DP_rect_width(bounds);
+    int height = DP_rect_height(bounds);
+    size_t count = DP_int_to_size(width) * DP_int_to_size(height);
+    uint8_t *coverage = DP_malloc(count);
+    float *row = DP_malloc(sizeof(*row) * DP_int_to_size(width));
+    int samples = antialias ? SUBSAMPLES : 1;
+    float weight = 1.0f / (float)samples;
+
+    for (int y = 0; y < height; ++y) {
+        memset(row, 0, sizeof(*row) * DP_int_to_size(width));
+        for (int s = 0; s < samples; ++s) {
+            double sy = bounds.y1 + y + (s + 0.5) / (double)samples;
+            int crossing_count = get_spans(user, sy, crossings);
+            int winding = 0;
+            double start = 0.0;
+            for (int i = 0; i < crossing_count; ++i) {
+                bool was_inside = is_inside(winding, even_odd);
+                winding += crossings[i].direction;
+                bool now_inside = is_inside(winding, even_odd);
+                if (!was_inside && now_inside) {
+                    start = crossings[i].x;
+                }
+                else if (was_inside && !now_inside) {
+                    if (antialias) {
+                        add_span(row, bounds.x1, width, start, crossings[i].x,
+                                 weight);
+                    }
+                    else {
+                        add_aliased_span(row, bounds.x1, width, start,
+                                         crossings[i].x);
+                    }
+                }
+            }
+        }
+        for (int x = 0; x < width; ++x) {
+            coverage[y * width + x] =
+                DP_float_to_uint8(roundf(DP_min_float(row[x], 1.0f) * 255.0f));
+        }
+    }
+
+    DP_free(row);
+    DP_Selection *sel = DP_selection_new_from_mask(bounds.x1, bounds.y1, width,
+                                                   height, coverage);
+    DP_free(coverage);
+    return sel;
+}
+
+static DP_Rect shape_bounds(double x1, double y1, double x2, double y2)
+{
+    return (DP_Rect){
+        DP_double_to_int(floor(x1)),
+        DP_double_to_int(floor(y1)),
+        DP_double_to_int(ceil(x2)) - 1,
+        DP_double_to_int(ceil(y2)) - 1,
+    };
+}
+
+
+typedef struct DP_SelectionEllipse {
+    double cx, cy, rx, ry;
+} DP_SelectionEllipse;
+
+static int get_ellipse_spans(void *user, double y,
+                             DP_SelectionCrossing *crossings)
+{
+    DP_SelectionEllipse *e = user;
+    double dy = (y - e->cy) / e->ry;
+    if (dy > -1.0 && dy < 1.0) {
+        double half = e->rx * sqrt(1.0 - dy * dy);
+        crossings[0] = (DP_SelectionCrossing){e->cx - half, 1};
+        crossings[1] = (DP_SelectionCrossing){e->cx + half, -1};
+        return 2;
+    }
+    else {
+        return 0;
+    }
+}
+
+DP_Selection *DP_selection_new_ellipse(double x, double y, double width,
+                                       double height)
+{
+    if (width > 0.0 && height > 0.0) {
+        DP_SelectionEllipse e = {x + width / 2.0, y + height / 2.0,
+                                 width / 2.0, height / 2.0};
+        DP_SelectionCrossing crossings[2];
+        return rasterize(shape_bounds(x, y, x + width, y + height),
+                         get_ellipse_spans, &e, crossings, false, true);
+    }
+    else {
+        return DP_selection_new_empty();
+    }
+}
+
+
+// Polygons get rasterized with an active edge table: the edges are sorted by
+// their top end and only the ones crossing the current sub-scanline get
+// looked at. Those stay roughly sorted by their crossing from one line to
+// the next, so re-sorting them is cheap. That keeps lassos with thousands
+// of points from being quadratic.
+typedef struct DP_SelectionEdge {
+    double top, bottom;
+    double x, dxdy;
+    int direction;
+} DP_SelectionEdge;
+
+typedef struct DP_SelectionEdgeTable {
+    DP_SelectionEdge *edges;
+    int edge_count;
+    int next_edge;
+    int *active;
+    int active_count;
+} DP_SelectionEdgeTable;
+
+static int compare_edges(const void *a, const void *b)
+{
+    double ta = ((const DP_SelectionEdge *)a)->top;
+    double tb = ((const DP_SelectionEdge *)b)->top;
+    return ta < tb ? -1 : ta > tb ? 1 : 0;
+}
+
+static void edge_table_init(DP_SelectionEdgeTable *et, const DP_Vec2 *points,
+                            int count)
+{
+    et->edges = DP_malloc(sizeof(*et->edges) * DP_int_to_size(count));
+    et->edge_count = 0;
+    for (int i = 0; i < count; ++i) {
+        DP_Vec2 a = points[i];
+        DP_Vec2 b = points[(i + 1) % count];
+        // Horizontal edges never cross a scanline.
+        if (a.y != b.y) {
+            bool down = b.y > a.y;
+            DP_Vec2 top = down ? a : b;
+            DP_Vec2 bottom = down ? b : a;
+            et->edges[et->edge_count++] = (DP_SelectionEdge){
+                top.y, bottom.y, top.x, (bottom.x - top.x) / (bottom.y - top.y),
+                down ? 1 : -1};
+        }
+    }
+    qsort(et->edges, DP_int_to_size(et->edge_count), sizeof(*et->edges),
+          compare_edges);
+    et->next_edge = 0;
+    et->active = DP_malloc(sizeof(*et->active) * DP_int_to_size(count));
+    et->active_count = 0;
+}
+
+static void edge_table_dispose(DP_SelectionEdgeTable *et)
+{
+    DP_free(et->active);
+    DP_free(et->edges);
+}
+
+static int get_polygon_spans(void *user, double y,
+                             DP_SelectionCrossing *crossings)
+{
+    DP_SelectionEdgeTable *et = user;
+    DP_SelectionEdge *edges = et->edges;
+    int *active = et->active;
+
+    // Edges are half-open on the y axis, so that a vertex shared by two of
+    // them doesn't get counted twice.
+    int active_count = 0;
+    for (int i = 0; i < et->active_count; ++i) {
+        if (edges[active[i]].bottom > y) {
+            active[active_count++] = active[i];
+        }
+    }
+    while (et->next_edge < et->edge_count && edges[et->next_edge].top <= y) {
+        if (edges[et->next_edge].bottom > y) {
+            active[active_count++] = et->next_edge;
+        }
+        ++et->next_edge;
+    }
+    et->active_count = active_count;
+
+    for (int i = 0; i < active_count; ++i) {
+        DP_SelectionEdge *e = &edges[active[i]];
+        DP_SelectionCrossing c = {e->x + (y - e->top) * e->dxdy, e->direction};
+        // Insertion sort, sorting the active edges along with the crossings
+        // so that they're already in order on the next line.
+        int edge = active[i];
+        int j = i;
+        while (j > 0 && crossings[j - 1].x > c.x) {
+            crossings[j] = crossings[j - 1];
+            active[j] = active[j - 1];
+            --j;
+        }
+        crossings[j] = c;
+        active[j] = edge;
+    }
+    return active_count;
+}
+
+static DP_Selection *rasterize_polygon(const DP_Vec2 *points, int count,
+                                       const DP_Rect *clip_or_null,
+                                       bool even_odd, bool antialias)
+{
+    double x1 = points[0].x, y1 = points[0].y;
+    double x2 = points[0].x, y2 = points[0].y;
+    for (int i = 1; i < count; ++i) {
+        x1 = DP_min_double(x1, points[i].x);
+        y1 = DP_min_double(y1, points[i].y);
+        x2 = DP_max_double(x2, points[i].x);
+        y2 = DP_max_double(y2, points[i].y);
+    }
+
+    // Clip before converting to integers, the points may be way out there.
+    if (clip_or_null) {
+        double cx1 = clip_or_null->x1, cy1 = clip_or_null->y1;
+        double cx2 = clip_or_null->x2 + 1.0, cy2 = clip_or_null->y2 + 1.0;
+        if (x2 <= cx1 || y2 <= cy1 || x1 >= cx2 || y1 >= cy2) {
+            return DP_selection_new_empty();
+        }
+        x1 = DP_max_double(x1, cx1);
+        y1 = DP_max_double(y1, cy1);
+        x2 = DP_min_double(x2, cx2);
+        y2 = DP_min_double(y2, cy2);
+    }
+    DP_Rect bounds = shape_bounds(x1, y1, x2, y2);
+
+    DP_SelectionEdgeTable et;
+    edge_table_init(&et, points, count);
+    DP_SelectionCrossing *crossings =
+        DP_malloc(sizeof(*crossings) * DP_int_to_size(count));
+    DP_Selection *sel = rasterize(bounds, get_polygon_spans, &et, crossings,
+                                  even_odd, antialias);
+    DP_free(crossings);
+    edge_table_dispose(&et);
+    return sel;
+}
+
+DP_Selection *DP_selection_new_polygon(const DP_Vec2 *points, int count)
+{
+    DP_ASSERT(count <= 0 || points);
+    if (count < 3) {
+        return DP_selection_new_empty();
+    }
+    else {
+        return rasterize_polygon(points, count, NULL, false, true);
+    }
+}
+
+DP_Selection *DP_selection_new_lasso(const DP_Vec2 *points, int count,
+                                     int width, int height, bool antialias)
+{
+    DP_ASSERT(count <= 0 || points);
+    if (count < 3 || width <= 0 || height <= 0) {
+        return DP_selection_new_empty();
+    }
+    else {
+        DP_Rect clip = DP_rect_make(0, 0, width, height);
+        return rasterize_polygon(points, count, &clip, true, antialias);
+    }
+}
+
+
+DP_Selection *DP_selection_incref(DP_Selection *sel)
+{
+    DP_ASSERT(sel);
+    DP_ASSERT(DP_atomic_get(&sel->refcount) > 0);
+    DP_atomic_inc(&sel->refcount);
+    return sel;
+}
+
+DP_Selection *DP_selection_incref_nullable(DP_Selection *sel_or_null)
+{
+    return sel_or_null ? DP_selection_incref(sel_or_null) : NULL;
+}
+
+void DP_selection_decref(DP_Selection *sel)
+{
+    DP_ASSERT(sel);
+    DP_ASSERT(DP_atomic_get(&sel->refcount) > 0);
+    if (DP_atomic_dec(&sel->refcount)) {
+        DP_free(sel);
+    }
+}
+
+void DP_selection_decref_nullable(DP_Selection *sel_or_null)
+{
+    if (sel_or_null) {
+        DP_selection_decref(sel_or_null);
+    }
+}
+
+DP_Rect DP_selection_bounds(DP_Selection *sel)
+{
+    DP_ASSERT(sel);
+    DP_ASSERT(DP_atomic_get(&sel->refcount) > 0);
+    return sel->bounds;
+}
+
+bool DP_selection_empty(DP_Selection *sel)
+{
+    DP_ASSERT(sel);
+    DP_ASSERT(DP_atomic_get(&sel->refcount) > 0);
+    return !DP_rect_valid(sel->bounds);
+}
+
+uint8_t DP_selection_contains(DP_Selection *sel, int x, int y)
+{
+    DP_ASSERT(sel);
+    DP_ASSERT(DP_atomic_get(&sel->refcount) > 0);
+    DP_Rect bounds = sel->bounds;
+    if (DP_rect_contains(bounds, x, y)) {
+        return sel->coverage[(y - bounds.y1) * DP_rect_width(bounds)
+                             + (x - bounds.x1)];
+    }
+    else {
+        return 0;
+    }
+}
+
+
+typedef uint8_t (*DP_SelectionCombineFn)(uint8_t a, uint8_t b);
+
+static DP_Selection *combine(DP_Selection *a, DP_Selection *b, DP_Rect area,
+                             DP_SelectionCombineFn fn)
+{
+    if (!DP_rect_valid(area)) {
+        return DP_selection_new_empty();
+    }
+
+    int width = DP_rect_width(area);
+    int height = DP_rect_height(area);
+    uint8_t *coverage =
+        DP_malloc(DP_int_to_size(width) * DP_int_to_size(height));
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            int cx = area.x1 + x;
+            int cy = area.y1 + y;
+            coverage[y * width + x] = fn(DP_selection_contains(a, cx, cy),
+                                         DP_selection_contains(b, cx, cy));
+        }
+    }
+
+    DP_Selection *sel =
+        DP_selection_new_from_mask(area.x1, area.y1, width, height, coverage);
+    DP_free(coverage);
+    return sel;
+}
+
+static uint8_t combine_union(uint8_t a, uint8_t b)
+{
+    return a > b ? a : b;
+}
+
+static uint8_t combine_intersect(uint8_t a, uint8_t b)
+{
+    return a < b ? a : b;
+}
+
+static uint8_t combine_subtract(uint8_t a, uint8_t b)
+{
+    return combine_intersect(a, (uint8_t)(255 - b));
+}
+
+DP_Selection *DP_selection_union(DP_Selection *a, DP_Selection *b)
+{
+    DP_ASSERT(a);
+    DP_ASSERT(b);
+    if (DP_selection_empty(a)) {
+        return DP_selection_incref(b);
+    }
+    else if (DP_selection_empty(b)) {
+        return DP_selection_incref(a);
+    }
+    else {
+        return combine(a, b, DP_rect_union(a->bounds, b->bounds),
+                       combine_union);
+    }
+}
+
+DP_Selection *DP_selection_intersect(DP_Selection *a, DP_Selection *b)
+{
+    DP_ASSERT(a);
+    DP_ASSERT(b);
+    return combine(a, b, DP_rect_intersection(a->bounds, b->bounds),
+                   combine_intersect);
+}
+
+DP_Selection *DP_selection_subtract(DP_Selection *a, DP_Selection *b)
+{
+    DP_ASSERT(a);
+    DP_ASSERT(b);
+    if (DP_selection_empty(a)) {
+        return DP_selection_incref(a);
+    }
+    else if (!DP_rect_intersects(a->bounds, b->bounds)) {
+        return DP_selection_incref(a);
+    }
+    else {
+        return combine(a, b, a->bounds, combine_subtract);
+    }
+}
+
+
+DP_SelectionSpanIterator DP_selection_span_iterator_make(DP_Selection *sel)
+{
+    DP_ASSERT(sel);
+    DP_ASSERT(DP_atomic_get(&sel->refcount) > 0);
+    return (DP_SelectionSpanIterator){sel, 0, 0, {0, 0, 0, NULL}};
+}
+
+bool DP_selection_span_iterator_next(DP_SelectionSpanIterator *ssi)
+{
+    DP_ASSERT(ssi);
+    DP_Selection *sel = ssi->sel;
+    DP_Rect bounds = sel->bounds;
+    if (!DP_rect_valid(bounds)) {
+        return false;
+    }
+
+    int width = DP_rect_width(bounds);
+    int height = DP_rect_height(bounds);
+    while (ssi->y < height) {
+        const uint8_t *row = sel->coverage + ssi->y * width;
+        int x = ssi->x;
+        while (x < width && row[x] == 0) {
+            ++x;
+        }
+        if (x < width) {
+            int start = x;
+            while (x < width && row[x] != 0) {
+                ++x;
+            }
+            ssi->x = x;
+            ssi->span = (DP_SelectionSpan){
+                bounds.x1 + start, bounds.y1 + ssi->y, x - start, row + start};
+            return true;
+        }
+        ++ssi->y;
+        ssi->x = 0;
+    }
+    return false;
+}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_SELECTION_H
+#define DPENGINE_SELECTION_H
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+
+
+typedef struct DP_Selection DP_Selection;
+
+// A horizontal run of pixels with non-zero coverage, one value per pixel.
+typedef struct DP_SelectionSpan {
+    int x, y, width;
+    const uint8_t *coverage;
+} DP_SelectionSpan;
+
+typedef struct DP_SelectionSpanIterator {
+    DP_Selection *sel;
+    int x, y;
+    DP_SelectionSpan span;
+} DP_SelectionSpanIterator;
+
+
+// Selections are immutable 8 bit coverage masks, with 255 being fully
+// selected and 0 not at all, so that edges can be anti-aliased. They only
+// store the area within their bounds, the smallest rectangle around all
+// pixels with non-zero coverage. An empty selection has invalid bounds.
+DP_Selection *DP_selection_new_empty(void);
+
+// Fully selects the pixels within the given rectangle, which is inclusive
+// on all sides like all DP_Rects. An invalid rectangle selects nothing.
+DP_Selection *DP_selection_new_rect(DP_Rect rect);
+
+// Ellipse inscribed into the given rectangle in canvas coordinates, with
+// anti-aliased edges. Pixel centers are at 0.5 offsets, so an ellipse from
+// 0,0 with a size of 10,10 exactly fits the pixels from 0,0 to 9,9.
+DP_Selection *DP_selection_new_ellipse(double x, double y, double width,
+                                       double height);
+
+// Polygon with anti-aliased edges, closed by connecting the last point to
+// the first one. Self-intersections get filled according to the non-zero
+// winding rule. Fewer than three points select nothing.
+DP_Selection *DP_selection_new_polygon(const DP_Vec2 *points, int count);
+
+// Freehand lasso or polygon selection, closed like above, but filled with
+// the even-odd rule, so loops that cross over themselves cut holes. Only
+// the part within a canvas of the given size gets selected, points may lie
+// outside of it. Without anti-aliasing, every pixel gets selected fully or
+// not at all, depending on whether its center is inside.
+DP_Selection *DP_selection_new_lasso(const DP_Vec2 *points, int count,
+                                     int width, int height, bool antialias);
+
+// Takes a copy of width * height coverage values placed at the given
+// position, then trims the bounds down to what's actually selected.
+DP_Selection *DP_selection_new_from_mask(int x, int y, int width, int height,
+                                         const uint8_t *coverage);
+
+DP_Selection *DP_selection_incref(DP_Selection *sel);
+
+DP_Selection *DP_selection_incref_nullable(DP_Selection *sel_or_null);
+
+void DP_selection_decref(DP_Selection *sel);
+
+void DP_selection_decref_nullable(DP_Selection *sel_or_null);
+
+DP_Rect DP_selection_bounds(DP_Selection *sel);
+
+bool DP_selection_empty(DP_Selection *sel);
+
+// Coverage at the given pixel, 0 outside of the bounds.
+uint8_t DP_selection_contains(DP_Selection *sel, int x, int y);
+
+// Boolean operations, all of which return a new selection with its bounds
+// trimmed to fit its contents. Union takes the larger coverage of the two,
+// intersection the smaller one and subtraction removes the coverage of b
+// from a, so they also work sensibly on anti-aliased edges.
+DP_Selection *DP_selection_union(DP_Selection *a, DP_Selection *b);
+
+DP_Selection *DP_selection_intersect(DP_Selection *a, DP_Selection *b);
+
+DP_Selection *DP_selection_subtract(DP_Selection *a, DP_Selection *b);
+
+
+// Goes through all the spans of the selection row by row, left to right. The
+// selection must outlive the iterator and the span is only valid until the
+// next call to next.
+DP_SelectionSpanIterator DP_selection_span_iterator_make(DP_Selection *sel);
+
+bool DP_selection_span_iterator_next(DP_SelectionSpanIterator *ssi);
+
+
+#endif
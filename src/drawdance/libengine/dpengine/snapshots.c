@@ -19,6 +19,7 @@
 #include "annotation_list.h"
 #include "canvas_history.h"
 #include "canvas_state.h"
+#include "compress.h"
 #include "document_metadata.h"
 #include "key_frame.h"
 #include "layer_content.h"
@@ -35,6 +36,8 @@
 #include <dpcommon/queue.h>
 #include <dpcommon/threading.h>
 #include <dpmsg/message.h>
+#include <string.h>
+#include <uthash_inc.h>
 
 
 #define ELEMENT_SIZE (sizeof(DP_Snapshot))
@@ -215,6 +218,17 @@ void DP_snapshot_queue_get_with(DP_SnapshotQueue *sq, DP_SnapshotsGetFn get_fn,
 }
 
 
+// Tiles already compressed, by checksum. Identical tiles found later reuse
+// those bytes instead of getting compressed all over again. Each message still
+// carries the whole image, since reset images get sent to other clients.
+typedef struct DP_ResetImageTileMap {
+    uint64_t checksum;
+    DP_Tile *t;
+    UT_hash_handle hh;
+    size_t size;
+    unsigned char data[];
+} DP_ResetImageTileMap;
+
 struct DP_ResetImageContext {
     unsigned int context_id;
     void (*push_message)(void *, DP_Message *);
@@ -222,6 +236,7 @@ struct DP_ResetImageContext {
     DP_Pixel8 *pixel_buffer;
     size_t capacity;
     void *output_buffer;
+    DP_ResetImageTileMap *tiles;
 };
 
 static void reset_image_push(struct DP_ResetImageContext *c, DP_Message *msg)
@@ -245,6 +260,13 @@ static void set_tile_data(size_t size, unsigned char *out, void *bytes)
     memcpy(out, bytes, size);
 }
 
+// Reset images put every tile into a message of its own, so even a tile full
+// of noise that doesn't compress at all has to fit. That's what limits how
+// large tiles can get without changing the protocol.
+static_assert(DP_COMPRESS_DEFLATE_BOUND(DP_TILE_COMPRESSED_BYTES)
+                  <= DP_MSG_PUT_TILE_IMAGE_MAX_SIZE,
+              "compressed tile fits into a put tile message");
+
 static size_t reset_image_maybe_compress_tile(struct DP_ResetImageContext *c,
                                               DP_Tile *tile_or_null)
 {
@@ -281,12 +303,66 @@ static void layer_props_to_reset_image(struct DP_ResetImageContext *c,
                 DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR);
     SET_FLAG_IF(attr_flags, group && DP_layer_props_isolated(lp),
                 DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED);
+    SET_FLAG_IF(attr_flags, DP_layer_props_alpha_lock(lp),
+                DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK);
+    SET_FLAG_IF(attr_flags, DP_layer_props_fixed(lp),
+                DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED);
     reset_image_push(c, DP_msg_layer_attributes_new(
                             c->context_id, layer_id, sublayer_id, attr_flags,
                             DP_channel15_to_8(DP_layer_props_opacity(lp)),
                             (uint8_t)DP_layer_props_blend_mode(lp)));
 }
 
+// Entries that would overflow the message get dropped.
+static size_t get_layer_metadata_size(DP_LayerProps *lp)
+{
+    size_t size = 0;
+    int count = DP_layer_props_metadata_count(lp);
+    for (int i = 0; i < count; ++i) {
+        size_t key_length, value_length;
+        DP_layer_props_metadata_key_at(lp, i, &key_length);
+        DP_layer_props_metadata_value_at(lp, i, &value_length);
+        size_t entry_size = key_length + value_length + 2;
+        if (size + entry_size > DP_MSG_LAYER_METADATA_METADATA_MAX_SIZE) {
+            break;
+        }
+        size += entry_size;
+    }
+    return size;
+}
+
+static void set_layer_metadata(size_t size, unsigned char *out, void *user)
+{
+    DP_LayerProps *lp = user;
+    size_t written = 0;
+    for (int i = 0; written < size; ++i) {
+        size_t key_length, value_length;
+        const char *key = DP_layer_props_metadata_key_at(lp, i, &key_length);
+        const char *value =
+            DP_layer_props_metadata_value_at(lp, i, &value_length);
+        memcpy(out + written, key, key_length);
+        written += key_length;
+        out[written++] = '\0';
+        memcpy(out + written, value, value_length);
+        written += value_length;
+        out[written++] = '\0';
+    }
+    DP_ASSERT(written == size);
+}
+
+static void layer_metadata_to_reset_image(struct DP_ResetImageContext *c,
+                                          DP_LayerProps *lp, uint16_t layer_id)
+{
+    int color_tag = DP_layer_props_color_tag(lp);
+    if (color_tag != DP_LAYER_COLOR_TAG_NONE
+        || DP_layer_props_metadata_count(lp) != 0) {
+        reset_image_push(c, DP_msg_layer_metadata_new(
+                                c->context_id, layer_id,
+                                DP_int_to_uint8(color_tag), set_layer_metadata,
+                                get_layer_metadata_size(lp), lp));
+    }
+}
+
 static uint16_t layer_to_reset_image(struct DP_ResetImageContext *c,
                                      uint16_t target_id, DP_LayerProps *lp,
                                      bool group, uint32_t fill)
@@ -302,32 +378,106 @@ static uint16_t layer_to_reset_image(struct DP_ResetImageContext *c,
         c, DP_msg_layer_tree_create_new(c->context_id, layer_id, 0, target_id,
                                         fill, create_flags, name, name_len));
     layer_props_to_reset_image(c, lp, group, layer_id, 0);
+    layer_metadata_to_reset_image(c, lp, layer_id);
     return layer_id;
 }
 
+static DP_Tile *tile_at_index(DP_LayerContent *lc, DP_TileCounts counts,
+                              int i)
+{
+    return DP_layer_content_tile_at_noinc(lc, i % counts.x, i / counts.x);
+}
+
+static bool reset_image_tiles_equal(DP_Tile *a, DP_Tile *b)
+{
+    return a == b
+        || (b && memcmp(DP_tile_pixels(a), DP_tile_pixels(b), DP_TILE_BYTES)
+                     == 0);
+}
+
+static DP_ResetImageTileMap *
+reset_image_search_tile(struct DP_ResetImageContext *c, DP_Tile *t,
+                        uint64_t checksum)
+{
+    DP_ResetImageTileMap *entry;
+    HASH_FIND(hh, c->tiles, &checksum, sizeof(checksum), entry);
+    // Checksums can collide, only reuse the bytes if it's the same tile.
+    return entry && reset_image_tiles_equal(t, entry->t) ? entry : NULL;
+}
+
+static void reset_image_add_tile(struct DP_ResetImageContext *c, DP_Tile *t,
+                                 uint64_t checksum, size_t size)
+{
+    DP_ResetImageTileMap *entry =
+        DP_malloc(DP_FLEX_SIZEOF(DP_ResetImageTileMap, data, size));
+    entry->checksum = checksum;
+    entry->t = t;
+    entry->size = size;
+    memcpy(entry->data, c->output_buffer, size);
+    HASH_ADD(hh, c->tiles, checksum, sizeof(entry->checksum), entry);
+}
+
+static void reset_image_dispose_tiles(struct DP_ResetImageContext *c)
+{
+    DP_ResetImageTileMap *entry, *tmp;
+    HASH_ITER(hh, c->tiles, entry, tmp) {
+        HASH_DEL(c->tiles, entry);
+        DP_free(entry);
+    }
+}
+
 static bool tiles_to_reset_image(struct DP_ResetImageContext *c,
                                  DP_LayerContent *lc, uint16_t layer_id,
                                  uint8_t sublayer_id)
 {
-    // TODO: use tile runs and layer fill to optimize this.
+    // TODO: use layer fill to optimize this.
     DP_TileCounts counts = DP_tile_counts_round(DP_layer_content_width(lc),
                                                 DP_layer_content_height(lc));
+    int total = counts.x * counts.y;
     bool pushed = false;
-    for (int y = 0; y < counts.y; ++y) {
-        for (int x = 0; x < counts.x; ++x) {
-            DP_Tile *t = DP_layer_content_tile_at_noinc(lc, x, y);
-            if (t && !DP_tile_blank(t)) {
-                size_t size = reset_image_maybe_compress_tile(c, t);
-                if (size != 0) {
-                    pushed = true;
-                    reset_image_push(
-                        c, DP_msg_put_tile_new(
-                               c->context_id, layer_id, sublayer_id,
-                               DP_int_to_uint16(x), DP_int_to_uint16(y), 0,
-                               set_tile_data, size, c->output_buffer));
-                }
+    int i = 0;
+    while (i < total) {
+        DP_Tile *t = tile_at_index(lc, counts, i);
+        if (!t || DP_tile_blank(t)) {
+            ++i;
+            continue;
+        }
+
+        // Identical tiles in a row, like in flat areas, go into a single
+        // message. Runs continue across the ends of tile rows.
+        int run = 1;
+        while (i + run < total && run <= UINT16_MAX
+               && reset_image_tiles_equal(
+                   t, tile_at_index(lc, counts, i + run))) {
+            ++run;
+        }
+
+        uint64_t checksum = DP_tile_checksum(t);
+        DP_ResetImageTileMap *entry = reset_image_search_tile(c, t, checksum);
+        size_t size;
+        void *data;
+        if (entry) {
+            size = entry->size;
+            data = entry->data;
+        }
+        else {
+            size = reset_image_maybe_compress_tile(c, t);
+            data = c->output_buffer;
+            if (size != 0) {
+                reset_image_add_tile(c, t, checksum, size);
             }
         }
+
+        if (size != 0) {
+            pushed = true;
+            reset_image_push(
+                c, DP_msg_put_tile_new(
+                       c->context_id, layer_id, sublayer_id,
+                       DP_int_to_uint16(i % counts.x),
+                       DP_int_to_uint16(i / counts.x),
+                       DP_int_to_uint16(run - 1), set_tile_data, size, data));
+        }
+        i += run;
     }
     return pushed;
 }
@@ -393,7 +543,7 @@ static uint8_t get_annotation_valign_flags(DP_Annotation *a)
     case DP_ANNOTATION_VALIGN_CENTER:
         return DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER;
     case DP_ANNOTATION_VALIGN_BOTTOM:
-        return DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER;
+        return DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM;
     default:
         return 0;
     }
@@ -418,10 +568,13 @@ static void annotations_to_reset_image(struct DP_ResetImageContext *c,
         uint8_t edit_flags = get_annotation_valign_flags(a);
         SET_FLAG_IF(edit_flags, DP_annotation_protect(a),
                     DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT);
+        SET_FLAG_IF(edit_flags, DP_annotation_auto_grow(a),
+                    DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW);
+        uint8_t border = DP_int_to_uint8(DP_annotation_border(a));
         reset_image_push(
             c, DP_msg_annotation_edit_new(c->context_id, annotation_id,
                                           DP_annotation_background_color(a),
-                                          edit_flags, 0, text, text_len));
+                                          edit_flags, border, text, text_len));
     }
 }
 
@@ -545,10 +698,15 @@ void DP_reset_image_build(DP_CanvasState *cs, unsigned int context_id,
                           void *user)
 {
     struct DP_ResetImageContext c = {
-        context_id, push_message,
-        user,       DP_malloc(sizeof(*c.pixel_buffer) * DP_TILE_LENGTH),
-        0,          NULL};
+        context_id,
+        push_message,
+        user,
+        DP_malloc(sizeof(*c.pixel_buffer) * DP_TILE_LENGTH),
+        0,
+        NULL,
+        NULL};
     canvas_state_to_reset_image(&c, cs);
+    reset_image_dispose_tiles(&c);
     DP_free(c.output_buffer);
     DP_free(c.pixel_buffer);
 }
@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: MIT
+#include "stamp_mask.h"
+#include "checksum.h"
+#include "compress.h"
+#include "image.h"
+#include "pixels.h"
+#include <dpcommon/atomic.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpmsg/message.h>
+#include <math.h>
+#include <uthash_inc.h>
+
+
+// A 256 pixel mask halves down to a single pixel in 9 levels.
+#define MAX_LEVELS    9
+#define REGISTRY_SIZE 64
+
+struct DP_StampMask {
+    DP_Atomic refcount;
+    uint32_t id;
+    int level_count;
+    int level_sizes[MAX_LEVELS];
+    uint8_t *levels[MAX_LEVELS];
+    size_t compressed_size;
+    unsigned char *compressed;
+    uint8_t data[];
+};
+
+typedef struct DP_StampMaskEntry {
+    uint32_t id;
+    DP_StampMask *sm;
+    UT_hash_handle hh;
+} DP_StampMaskEntry;
+
+DP_ATOMIC_DECLARE_STATIC_SPIN_LOCK(registry_lock);
+static DP_StampMaskEntry *registry;
+
+
+uint32_t DP_stamp_mask_checksum(int size, const uint8_t *data)
+{
+    DP_ASSERT(size > 0);
+    DP_ASSERT(data);
+    uint64_t checksum = DP_checksum_int(DP_CHECKSUM_INIT, size);
+    checksum = DP_checksum_bytes(checksum, data, DP_int_to_size(size * size));
+    uint32_t id = (uint32_t)(checksum ^ (checksum >> (uint64_t)32));
+    // Zero means no mask, so don't hand that out as an id.
+    return id == 0 ? 1 : id;
+}
+
+
+static int count_levels(int size, int *out_total_length)
+{
+    int count = 0;
+    int total_length = 0;
+    while (true) {
+        ++count;
+        total_length += size * size;
+        if (size == 1) {
+            break;
+        }
+        size = (size + 1) / 2;
+    }
+    *out_total_length = total_length;
+    return count;
+}
+
+static uint8_t level_value_at(const uint8_t *level, int size, int x, int y)
+{
+    return x < size && y < size ? level[y * size + x] : 0;
+}
+
+static void generate_mipmaps(DP_StampMask *sm)
+{
+    for (int i = 1; i < sm->level_count; ++i) {
+        const uint8_t *src = sm->levels[i - 1];
+        int src_size = sm->level_sizes[i - 1];
+        uint8_t *dst = sm->levels[i];
+        int dst_size = sm->level_sizes[i];
+        for (int y = 0; y < dst_size; ++y) {
+            for (int x = 0; x < dst_size; ++x) {
+                int sx = x * 2;
+                int sy = y * 2;
+                int sum = level_value_at(src, src_size, sx, sy)
+                        + level_value_at(src, src_size, sx + 1, sy)
+                        + level_value_at(src, src_size, sx, sy + 1)
+                        + level_value_at(src, src_size, sx + 1, sy + 1);
+                dst[y * dst_size + x] = (uint8_t)((sum + 2) / 4);
+            }
+        }
+    }
+}
+
+static DP_StampMask *alloc_stamp_mask(int size)
+{
+    int total_length;
+    int level_count = count_levels(size, &total_length);
+    DP_StampMask *sm = DP_malloc(
+        DP_FLEX_SIZEOF(DP_StampMask, data, DP_int_to_size(total_length)));
+    DP_atomic_set(&sm->refcount, 1);
+    sm->id = 0;
+    sm->level_count = level_count;
+    uint8_t *level = sm->data;
+    int level_size = size;
+    for (int i = 0; i < level_count; ++i) {
+        sm->level_sizes[i] = level_size;
+        sm->levels[i] = level;
+        level += level_size * level_size;
+        level_size = (level_size + 1) / 2;
+    }
+    sm->compressed_size = 0;
+    sm->compressed = NULL;
+    return sm;
+}
+
+static void free_stamp_mask(DP_StampMask *sm)
+{
+    DP_free(sm->compressed);
+    DP_free(sm);
+}
+
+static bool check_size(int size)
+{
+    if (size > 0 && size <= DP_STAMP_MASK_MAX_SIZE) {
+        return true;
+    }
+    else {
+        DP_error_set("Stamp mask size %d out of bounds", size);
+        return false;
+    }
+}
+
+static unsigned char *get_compressed_buffer(size_t size, void *user)
+{
+    DP_StampMask *sm = user;
+    sm->compressed = DP_malloc(size);
+    return sm->compressed;
+}
+
+DP_StampMask *DP_stamp_mask_new(int size, const uint8_t *data)
+{
+    DP_ASSERT(data);
+    if (!check_size(size)) {
+        return NULL;
+    }
+
+    DP_StampMask *sm = alloc_stamp_mask(size);
+    size_t length = DP_int_to_size(size * size);
+    memcpy(sm->levels[0], data, length);
+    sm->compressed_size =
+        DP_compress_deflate(data, length, get_compressed_buffer, sm);
+    if (sm->compressed_size == 0) {
+        free_stamp_mask(sm);
+        return NULL;
+    }
+    else if (sm->compressed_size > DP_MSG_STAMP_MASK_IMAGE_MAX_SIZE) {
+        DP_error_set("Stamp mask compresses to %zu bytes, maximum is %d",
+                     sm->compressed_size, DP_MSG_STAMP_MASK_IMAGE_MAX_SIZE);
+        free_stamp_mask(sm);
+        return NULL;
+    }
+
+    sm->id = DP_stamp_mask_checksum(size, data);
+    generate_mipmaps(sm);
+    return sm;
+}
+
+
+static int image_coverage_at(DP_Pixel8 *pixels, int width, int x, int y)
+{
+    // Pixels are premultiplied, so transparent pixels end up with no coverage.
+    DP_Pixel8 pixel = pixels[y * width + x];
+    int luma = (pixel.r * 299 + pixel.g * 587 + pixel.b * 114 + 500) / 1000;
+    return DP_clamp_int(pixel.a - luma, 0, 255);
+}
+
+DP_StampMask *DP_stamp_mask_new_from_image(DP_Image *img)
+{
+    DP_ASSERT(img);
+    int width = DP_image_width(img);
+    int height = DP_image_height(img);
+    if (width <= 0 || height <= 0) {
+        DP_error_set("Can't make a stamp mask from an empty image");
+        return NULL;
+    }
+
+    int max_dimension = DP_max_int(width, height);
+    int scaled_width, scaled_height;
+    if (max_dimension > DP_STAMP_MASK_MAX_SIZE) {
+        scaled_width = DP_max_int(
+            1, (width * DP_STAMP_MASK_MAX_SIZE + max_dimension / 2)
+                   / max_dimension);
+        scaled_height = DP_max_int(
+            1, (height * DP_STAMP_MASK_MAX_SIZE + max_dimension / 2)
+                   / max_dimension);
+    }
+    else {
+        scaled_width = width;
+        scaled_height = height;
+    }
+
+    int size = DP_max_int(scaled_width, scaled_height);
+    int offset_x = (size - scaled_width) / 2;
+    int offset_y = (size - scaled_height) / 2;
+    uint8_t *data = DP_malloc_zeroed(DP_int_to_size(size * size));
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    for (int y = 0; y < scaled_height; ++y) {
+        // Box filter over the source pixels covered by the target pixel.
+        int src_y1 = y * height / scaled_height;
+        int src_y2 = DP_max_int(src_y1 + 1, (y + 1) * height / scaled_height);
+        for (int x = 0; x < scaled_width; ++x) {
+            int src_x1 = x * width / scaled_width;
+            int src_x2 =
+                DP_max_int(src_x1 + 1, (x + 1) * width / scaled_width);
+            int sum = 0;
+            for (int sy = src_y1; sy < src_y2; ++sy) {
+                for (int sx = src_x1; sx < src_x2; ++sx) {
+                    sum += image_coverage_at(pixels, width, sx, sy);
+                }
+            }
+            int count = (src_x2 - src_x1) * (src_y2 - src_y1);
+            data[(y + offset_y) * size + x + offset_x] =
+                (uint8_t)((sum + count / 2) / count);
+        }
+    }
+
+    DP_StampMask *sm = DP_stamp_mask_new(size, data);
+    DP_free(data);
+    return sm;
+}
+
+
+struct DP_StampMaskInflateArgs {
+    DP_StampMask *sm;
+    size_t expected_size;
+};
+
+static unsigned char *get_inflate_buffer(size_t size, void *user)
+{
+    struct DP_StampMaskInflateArgs *args = user;
+    if (size == args->expected_size) {
+        return args->sm->levels[0];
+    }
+    else {
+        DP_error_set("Stamp mask decompression needs size %zu, but got %zu",
+                     args->expected_size, size);
+        return NULL;
+    }
+}
+
+DP_StampMask *DP_stamp_mask_new_from_message(DP_MsgStampMask *msm)
+{
+    DP_ASSERT(msm);
+    int size = DP_msg_stamp_mask_size(msm);
+    if (!check_size(size)) {
+        return NULL;
+    }
+
+    size_t image_size;
+    const unsigned char *image = DP_msg_stamp_mask_image(msm, &image_size);
+    DP_StampMask *sm = alloc_stamp_mask(size);
+    struct DP_StampMaskInflateArgs args = {sm, DP_int_to_size(size * size)};
+    if (!DP_compress_inflate(image, image_size, get_inflate_buffer, &args)) {
+        free_stamp_mask(sm);
+        return NULL;
+    }
+
+    uint32_t id = DP_stamp_mask_checksum(size, sm->levels[0]);
+    uint32_t expected_id = DP_msg_stamp_mask_mask(msm);
+    if (id != expected_id) {
+        DP_error_set("Stamp mask checksum %x doesn't match id %x", id,
+                     expected_id);
+        free_stamp_mask(sm);
+        return NULL;
+    }
+
+    sm->id = id;
+    sm->compressed_size = image_size;
+    sm->compressed = DP_malloc(image_size);
+    memcpy(sm->compressed, image, image_size);
+    generate_mipmaps(sm);
+    return sm;
+}
+
+
+DP_StampMask *DP_stamp_mask_incref(DP_StampMask *sm)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    DP_atomic_inc(&sm->refcount);
+    return sm;
+}
+
+DP_StampMask *DP_stamp_mask_incref_nullable(DP_StampMask *sm_or_null)
+{
+    return sm_or_null ? DP_stamp_mask_incref(sm_or_null) : NULL;
+}
+
+void DP_stamp_mask_decref(DP_StampMask *sm)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    if (DP_atomic_dec(&sm->refcount)) {
+        free_stamp_mask(sm);
+    }
+}
+
+void DP_stamp_mask_decref_nullable(DP_StampMask *sm_or_null)
+{
+    if (sm_or_null) {
+        DP_stamp_mask_decref(sm_or_null);
+    }
+}
+
+uint32_t DP_stamp_mask_id(DP_StampMask *sm)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    return sm->id;
+}
+
+int DP_stamp_mask_size(DP_StampMask *sm)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    return sm->level_sizes[0];
+}
+
+const uint8_t *DP_stamp_mask_data(DP_StampMask *sm)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    return sm->levels[0];
+}
+
+
+static void set_compressed_image(size_t size, unsigned char *out, void *user)
+{
+    DP_StampMask *sm = user;
+    memcpy(out, sm->compressed, size);
+}
+
+DP_Message *DP_stamp_mask_to_message(DP_StampMask *sm,
+                                     unsigned int context_id)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    return DP_msg_stamp_mask_new(
+        context_id, sm->id, DP_int_to_uint16(sm->level_sizes[0]),
+        set_compressed_image, sm->compressed_size, sm);
+}
+
+
+float DP_stamp_mask_lod(DP_StampMask *sm, float scale)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    float max_lod = DP_int_to_float(sm->level_count - 1);
+    if (scale <= 1.0f) {
+        return 0.0f;
+    }
+    else {
+        float lod = log2f(scale);
+        return lod < max_lod ? lod : max_lod;
+    }
+}
+
+static float sample_level(DP_StampMask *sm, int level, float u, float v)
+{
+    const uint8_t *data = sm->levels[level];
+    int size = sm->level_sizes[level];
+    float fx = (u + 0.5f) * DP_int_to_float(size) - 0.5f;
+    float fy = (v + 0.5f) * DP_int_to_float(size) - 0.5f;
+    float floor_x = floorf(fx);
+    float floor_y = floorf(fy);
+    int x = DP_float_to_int(floor_x);
+    int y = DP_float_to_int(floor_y);
+    if (x < -1 || y < -1 || x >= size || y >= size) {
+        return 0.0f;
+    }
+
+    float tx = fx - floor_x;
+    float ty = fy - floor_y;
+    float values[4];
+    for (int i = 0; i < 4; ++i) {
+        int sx = x + i % 2;
+        int sy = y + i / 2;
+        values[i] = sx >= 0 && sy >= 0 && sx < size && sy < size
+                      ? (float)data[sy * size + sx]
+                      : 0.0f;
+    }
+    float top = values[0] + (values[1] - values[0]) * tx;
+    float bottom = values[2] + (values[3] - values[2]) * tx;
+    return top + (bottom - top) * ty;
+}
+
+float DP_stamp_mask_sample(DP_StampMask *sm, float lod, float u, float v)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    DP_ASSERT(lod >= 0.0f);
+    int level = DP_float_to_int(lod);
+    float t = lod - DP_int_to_float(level);
+    float value = sample_level(sm, level, u, v);
+    if (t > 0.0f && level + 1 < sm->level_count) {
+        float next_value = sample_level(sm, level + 1, u, v);
+        value += (next_value - value) * t;
+    }
+    return value;
+}
+
+static int wrap_coordinate(int c, int size)
+{
+    int wrapped = c % size;
+    return wrapped < 0 ? wrapped + size : wrapped;
+}
+
+float DP_stamp_mask_sample_tiled(DP_StampMask *sm, float x, float y)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    const uint8_t *data = sm->levels[0];
+    int size = sm->level_sizes[0];
+    float fx = x - 0.5f;
+    float fy = y - 0.5f;
+    float floor_x = floorf(fx);
+    float floor_y = floorf(fy);
+    int x0 = wrap_coordinate(DP_float_to_int(floor_x), size);
+    int y0 = wrap_coordinate(DP_float_to_int(floor_y), size);
+    int x1 = x0 + 1 == size ? 0 : x0 + 1;
+    int y1 = y0 + 1 == size ? 0 : y0 + 1;
+
+    float v00 = (float)data[y0 * size + x0];
+    float v01 = (float)data[y0 * size + x1];
+    float v10 = (float)data[y1 * size + x0];
+    float v11 = (float)data[y1 * size + x1];
+    float tx = fx - floor_x;
+    float ty = fy - floor_y;
+    float top = v00 + (v01 - v00) * tx;
+    float bottom = v10 + (v11 - v10) * tx;
+    return top + (bottom - top) * ty;
+}
+
+
+void DP_stamp_mask_register(DP_StampMask *sm)
+{
+    DP_ASSERT(sm);
+    DP_ASSERT(DP_atomic_get(&sm->refcount) > 0);
+    uint32_t id = sm->id;
+    DP_atomic_lock(&registry_lock);
+
+    DP_StampMaskEntry *sme;
+    HASH_FIND(hh, registry, &id, sizeof(id), sme);
+    if (sme) {
+        // Already known, move it to the back so it gets evicted last.
+        HASH_DEL(registry, sme);
+    }
+    else {
+        sme = DP_malloc(sizeof(*sme));
+        sme->id = id;
+        sme->sm = DP_stamp_mask_incref(sm);
+    }
+    HASH_ADD(hh, registry, id, sizeof(sme->id), sme);
+
+    if (HASH_COUNT(registry) > REGISTRY_SIZE) {
+        DP_StampMaskEntry *oldest = registry;
+        HASH_DEL(registry, oldest);
+        // The mask stays alive for any brushes that are still using it.
+        DP_stamp_mask_decref(oldest->sm);
+        DP_free(oldest);
+    }
+
+    DP_atomic_unlock(&registry_lock);
+}
+
+DP_StampMask *DP_stamp_mask_search_inc(uint32_t id)
+{
+    DP_atomic_lock(&registry_lock);
+    DP_StampMaskEntry *sme;
+    HASH_FIND(hh, registry, &id, sizeof(id), sme);
+    DP_StampMask *sm = sme ? DP_stamp_mask_incref(sme->sm) : NULL;
+    DP_atomic_unlock(&registry_lock);
+    return sm;
+}
@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_STAMP_MASK_H
+#define DPENGINE_STAMP_MASK_H
+#include <dpcommon/common.h>
+
+typedef struct DP_Image DP_Image;
+typedef struct DP_Message DP_Message;
+typedef struct DP_MsgStampMask DP_MsgStampMask;
+
+
+// Masks wider than this get scaled down when created from an image.
+#define DP_STAMP_MASK_MAX_SIZE 256
+
+typedef struct DP_StampMask DP_StampMask;
+
+
+// Stamp masks are square 8 bit coverage images that stamp brush dabs get
+// scaled and rotated from. They're identified by a checksum of their contents,
+// so the same mask always gets the same id, no matter who defined it.
+uint32_t DP_stamp_mask_checksum(int size, const uint8_t *data);
+
+// Takes a copy of size*size coverage values. Returns NULL and sets an error if
+// the size is out of range or the mask doesn't fit into a stampmask message.
+DP_StampMask *DP_stamp_mask_new(int size, const uint8_t *data);
+
+// Converts an image into a mask, opaque black being full coverage and white or
+// transparent being none. Images larger than DP_STAMP_MASK_MAX_SIZE get scaled
+// down and rectangular images are centered on a square.
+DP_StampMask *DP_stamp_mask_new_from_image(DP_Image *img);
+
+// Returns NULL and sets an error if the image doesn't decompress or its
+// contents don't match the id in the message.
+DP_StampMask *DP_stamp_mask_new_from_message(DP_MsgStampMask *msm);
+
+DP_StampMask *DP_stamp_mask_incref(DP_StampMask *sm);
+
+DP_StampMask *DP_stamp_mask_incref_nullable(DP_StampMask *sm_or_null);
+
+void DP_stamp_mask_decref(DP_StampMask *sm);
+
+void DP_stamp_mask_decref_nullable(DP_StampMask *sm_or_null);
+
+uint32_t DP_stamp_mask_id(DP_StampMask *sm);
+
+int DP_stamp_mask_size(DP_StampMask *sm);
+
+const uint8_t *DP_stamp_mask_data(DP_StampMask *sm);
+
+DP_Message *DP_stamp_mask_to_message(DP_StampMask *sm,
+                                     unsigned int context_id);
+
+// Picks the mipmap level of detail for drawing the mask at the given number of
+// mask pixels per destination pixel, fractional values blend between levels.
+float DP_stamp_mask_lod(DP_StampMask *sm, float scale);
+
+// Samples the mask with a bilinear filter at the given level of detail. The
+// coordinates are normalized to [-0.5, 0.5] across the mask, anything outside
+// of that is zero. Returns a coverage value between 0 and 255.
+float DP_stamp_mask_sample(DP_StampMask *sm, float lod, float u, float v);
+
+// Samples the full resolution mask with a bilinear filter, repeating it in
+// every direction, for using it as a tiling texture. The coordinates are in
+// mask pixels, with the center of the first one at 0.5, 0.5. Returns a value
+// between 0 and 255.
+float DP_stamp_mask_sample_tiled(DP_StampMask *sm, float x, float y);
+
+
+// Makes the mask known to dabs that refer to it by id. The registry is shared
+// by the whole process, since ids are derived from the mask contents. Masks
+// that have been registered the longest get evicted when the registry is full,
+// clients send their masks at the start of every stroke to keep them around.
+void DP_stamp_mask_register(DP_StampMask *sm);
+
+// Returns NULL if there's no mask registered under the given id.
+DP_StampMask *DP_stamp_mask_search_inc(uint32_t id);
+
+
+#endif
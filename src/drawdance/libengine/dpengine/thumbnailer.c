@@ -0,0 +1,215 @@
+// SPDX-License-Identifier: MIT
+#include "thumbnailer.h"
+#include "canvas_history.h"
+#include "canvas_state.h"
+#include "image.h"
+#include "pixels.h"
+#include "tile.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+
+
+typedef struct DP_ThumbnailerSum {
+    uint64_t b, g, r, a;
+    uint64_t count;
+} DP_ThumbnailerSum;
+
+struct DP_Thumbnailer {
+    int max_width;
+    int max_height;
+    unsigned int flags;
+    long long interval_ms;
+    DP_RecorderGetTimeMsFn get_time_fn;
+    void *get_time_user;
+    DP_ThumbnailerFn fn;
+    void *user;
+    bool have_last;
+    long long last_ms;
+    long long last_change_count;
+    struct {
+        DP_CanvasState *cs;
+        long long message_index;
+        long long time_ms;
+        int thumb_width;
+        int thumb_height;
+        int next_tile;
+        DP_ThumbnailerSum *sums;
+    } job;
+};
+
+
+DP_Thumbnailer *DP_thumbnailer_new(int max_width, int max_height,
+                                   unsigned int flags, long long interval_ms,
+                                   DP_RecorderGetTimeMsFn get_time_fn,
+                                   void *get_time_user, DP_ThumbnailerFn fn,
+                                   void *user)
+{
+    DP_ASSERT(max_width > 0);
+    DP_ASSERT(max_height > 0);
+    DP_ASSERT(get_time_fn);
+    DP_ASSERT(fn);
+    DP_Thumbnailer *t = DP_malloc(sizeof(*t));
+    *t = (DP_Thumbnailer){max_width,
+                          max_height,
+                          flags,
+                          interval_ms,
+                          get_time_fn,
+                          get_time_user,
+                          fn,
+                          user,
+                          false,
+                          0,
+                          0,
+                          {NULL, 0, 0, 0, 0, 0, NULL}};
+    return t;
+}
+
+static void clear_job(DP_Thumbnailer *t)
+{
+    DP_canvas_state_decref_nullable(t->job.cs);
+    DP_free(t->job.sums);
+    t->job.cs = NULL;
+    t->job.sums = NULL;
+}
+
+void DP_thumbnailer_free(DP_Thumbnailer *t)
+{
+    if (t) {
+        clear_job(t);
+        DP_free(t);
+    }
+}
+
+
+bool DP_thumbnailer_in_progress(DP_Thumbnailer *t)
+{
+    DP_ASSERT(t);
+    return t->job.cs != NULL;
+}
+
+
+static bool start_job(DP_Thumbnailer *t, DP_CanvasHistory *ch,
+                      long long message_index)
+{
+    long long change_count = DP_canvas_history_change_count(ch);
+    if (t->have_last && change_count == t->last_change_count) {
+        return false;
+    }
+
+    long long now = t->get_time_fn(t->get_time_user);
+    if (t->have_last && now - t->last_ms < t->interval_ms) {
+        return false;
+    }
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    if (width <= 0 || height <= 0) {
+        DP_canvas_state_decref(cs);
+        return false;
+    }
+
+    t->have_last = true;
+    t->last_ms = now;
+    t->last_change_count = change_count;
+
+    int thumb_width, thumb_height;
+    DP_image_thumbnail_size(width, height, t->max_width, t->max_height,
+                            &thumb_width, &thumb_height);
+    size_t count = DP_int_to_size(thumb_width) * DP_int_to_size(thumb_height);
+    t->job.cs = cs;
+    t->job.message_index = message_index;
+    t->job.time_ms = now;
+    t->job.thumb_width = thumb_width;
+    t->job.thumb_height = thumb_height;
+    t->job.next_tile = 0;
+    t->job.sums = DP_malloc_zeroed(sizeof(*t->job.sums) * count);
+    return true;
+}
+
+// Every canvas pixel gets added to the thumbnail pixel it falls into, which
+// averages them over the area they cover.
+static void add_tile(DP_Thumbnailer *t, int tile_index)
+{
+    DP_CanvasState *cs = t->job.cs;
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    int thumb_width = t->job.thumb_width;
+    int thumb_height = t->job.thumb_height;
+    DP_TileCounts tile_counts = DP_tile_counts_round(width, height);
+    int tile_x = tile_index % tile_counts.x * DP_TILE_SIZE;
+    int tile_y = tile_index / tile_counts.x * DP_TILE_SIZE;
+    int end_x = DP_min_int(tile_x + DP_TILE_SIZE, width);
+    int end_y = DP_min_int(tile_y + DP_TILE_SIZE, height);
+
+    DP_TransientTile *tt =
+        DP_canvas_state_flatten_tile(cs, tile_index, t->flags, NULL);
+    const DP_Pixel15 *pixels = DP_transient_tile_pixels(tt);
+    for (int y = tile_y; y < end_y; ++y) {
+        int thumb_y = DP_llong_to_int((long long)y * thumb_height / height);
+        DP_ThumbnailerSum *row = t->job.sums + thumb_y * thumb_width;
+        const DP_Pixel15 *src = pixels + (y - tile_y) * DP_TILE_SIZE;
+        for (int x = tile_x; x < end_x; ++x) {
+            int thumb_x = DP_llong_to_int((long long)x * thumb_width / width);
+            DP_ThumbnailerSum *sum = row + thumb_x;
+            DP_Pixel15 pixel = src[x - tile_x];
+            sum->b += pixel.b;
+            sum->g += pixel.g;
+            sum->r += pixel.r;
+            sum->a += pixel.a;
+            ++sum->count;
+        }
+    }
+    DP_transient_tile_decref(tt);
+}
+
+static uint16_t average(uint64_t sum, uint64_t count)
+{
+    return DP_uint64_to_uint16((sum + count / 2) / count);
+}
+
+static void finish_job(DP_Thumbnailer *t)
+{
+    int thumb_width = t->job.thumb_width;
+    int thumb_height = t->job.thumb_height;
+    DP_Image *thumb = DP_image_new(thumb_width, thumb_height);
+    DP_Pixel8 *pixels = DP_image_pixels(thumb);
+    int count = thumb_width * thumb_height;
+    for (int i = 0; i < count; ++i) {
+        DP_ThumbnailerSum *sum = &t->job.sums[i];
+        if (sum->count != 0) {
+            pixels[i] = DP_pixel15_to_8((DP_Pixel15){
+                average(sum->b, sum->count), average(sum->g, sum->count),
+                average(sum->r, sum->count), average(sum->a, sum->count)});
+        }
+    }
+    long long message_index = t->job.message_index;
+    long long time_ms = t->job.time_ms;
+    clear_job(t);
+    t->fn(t->user, thumb, message_index, time_ms);
+    DP_image_free(thumb);
+}
+
+void DP_thumbnailer_step(DP_Thumbnailer *t, DP_CanvasHistory *ch,
+                         long long message_index, int max_tiles)
+{
+    DP_ASSERT(t);
+    DP_ASSERT(ch);
+    DP_ASSERT(max_tiles > 0);
+    if (t->job.cs || start_job(t, ch, message_index)) {
+        DP_CanvasState *cs = t->job.cs;
+        int tile_total = DP_tile_total_round(DP_canvas_state_width(cs),
+                                             DP_canvas_state_height(cs));
+        int end = t->job.next_tile + DP_min_int(max_tiles, tile_total);
+        if (end > tile_total) {
+            end = tile_total;
+        }
+        for (int i = t->job.next_tile; i < end; ++i) {
+            add_tile(t, i);
+        }
+        t->job.next_tile = end;
+        if (end == tile_total) {
+            finish_job(t);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPENGINE_THUMBNAILER_H
+#define DPENGINE_THUMBNAILER_H
+#include "recorder.h"
+#include <dpcommon/common.h>
+
+typedef struct DP_CanvasHistory DP_CanvasHistory;
+typedef struct DP_Image DP_Image;
+
+
+typedef void (*DP_ThumbnailerFn)(void *user, DP_Image *thumb,
+                                 long long message_index, long long time_ms);
+
+// Makes small previews of the canvas every so often, like for session browsers
+// or a recording index. The canvas is scaled down one tile at a time instead
+// of flattening it in one go, so the work can be spread out over multiple
+// steps in between handling messages. The flags are the DP_FLAT_IMAGE_* ones
+// used for flattening each tile, usually DP_FLAT_IMAGE_RENDER_FLAGS.
+typedef struct DP_Thumbnailer DP_Thumbnailer;
+
+DP_Thumbnailer *DP_thumbnailer_new(int max_width, int max_height,
+                                   unsigned int flags, long long interval_ms,
+                                   DP_RecorderGetTimeMsFn get_time_fn,
+                                   void *get_time_user, DP_ThumbnailerFn fn,
+                                   void *user);
+
+void DP_thumbnailer_free(DP_Thumbnailer *t);
+
+// Whether a thumbnail has been started, but not finished yet.
+bool DP_thumbnailer_in_progress(DP_Thumbnailer *t);
+
+// If no thumbnail is in progress, starts one when the minimum interval since
+// the last one has passed and the history's change count moved since then.
+// Then scales up to max_tiles tiles of it. Once all tiles are done, the
+// function is called with the thumbnail and the message index and time from
+// when it was started. The image is freed after the call returns.
+void DP_thumbnailer_step(DP_Thumbnailer *t, DP_CanvasHistory *ch,
+                         long long message_index, int max_tiles);
+
+
+#endif
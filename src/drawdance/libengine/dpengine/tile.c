@@ -26,6 +26,7 @@
  *
  */
 #include "tile.h"
+#include "checksum.h"
 #include "compress.h"
 #include "draw_context.h"
 #include "image.h"
@@ -35,44 +36,85 @@
 #include <dpcommon/common.h>
 #include <dpcommon/conversions.h>
 #include <dpcommon/cpu.h>
-#include <dpcommon/memory_pool.h>
 #include <dpcommon/threading.h>
 #include <dpmsg/blend_mode.h>
+#include <limits.h>
 
 
+// Caches whether all pixels of a tile are the same, in which case that color
+// is the first pixel. Persistent tiles are immutable, so theirs only ever goes
+// from unknown to a fixed answer, which makes it safe to compute lazily from
+// multiple threads. Transient tiles reset it whenever they get written to.
+typedef enum DP_TileSolid {
+    DP_TILE_SOLID_UNKNOWN,
+    DP_TILE_SOLID_NO,
+    DP_TILE_SOLID_YES,
+} DP_TileSolid;
+
+// The header comes before the pixels, since packed tiles share it but keep
+// their pixels compressed elsewhere.
 #ifdef DP_NO_STRICT_ALIASING
 
 struct DP_Tile {
-    DP_ALIGNAS_SIMD DP_Pixel15 pixels[DP_TILE_LENGTH];
     DP_Atomic refcount;
+    DP_Atomic solid;
     const bool transient;
     const bool maybe_blank;
+    const bool packed;
     const unsigned int context_id;
+    DP_ALIGNAS_SIMD DP_Pixel15 pixels[DP_TILE_LENGTH];
 };
 
 struct DP_TransientTile {
-    DP_ALIGNAS_SIMD DP_Pixel15 pixels[DP_TILE_LENGTH];
     DP_Atomic refcount;
+    DP_Atomic solid;
     bool transient;
     bool maybe_blank;
+    bool packed;
     unsigned int context_id;
+    DP_ALIGNAS_SIMD DP_Pixel15 pixels[DP_TILE_LENGTH];
 };
 
 #else
 
 struct DP_Tile {
-    DP_ALIGNAS_SIMD DP_Pixel15 pixels[DP_TILE_LENGTH];
     DP_Atomic refcount;
+    DP_Atomic solid;
     bool transient;
     bool maybe_blank;
+    bool packed;
     unsigned int context_id;
+    DP_ALIGNAS_SIMD DP_Pixel15 pixels[DP_TILE_LENGTH];
 };
 
 #endif
 
+// A persistent tile whose pixels are deflated or run-length encoded. They get
+// unpacked into a regular tile the first time anything looks at them, which is
+// then kept around, since the pixels handed out have to stay valid for as long
+// as the caller holds onto the tile. While that's the case, the packed tile
+// costs its compressed bytes on top of a regular one. The history evicts those
+// pixels again once nothing but an old save point holds onto the tile. Packed
+// tiles are never solid, those aren't worth packing, so the solid check doesn't
+// need the pixels.
+typedef struct DP_PackedTile {
+    DP_Atomic refcount;
+    DP_Atomic solid;
+    bool transient;
+    bool maybe_blank;
+    bool packed;
+    unsigned int context_id;
+    DP_Atomic lock;
+    DP_AtomicPtr unpacked;
+    bool rle;
+    size_t size;
+    unsigned char data[];
+} DP_PackedTile;
 
-// We want to initialize a static buffer with the same value 4096 times, so this
-// is a goofy way to achieve that at compile time without spelling it all out.
+
+// We want to initialize a static buffer with the same value for every pixel of
+// a tile, so this is a goofy way to achieve that at compile time without
+// spelling it all out.
 #define DP_BIT15_4    DP_BIT15, DP_BIT15, DP_BIT15, DP_BIT15
 #define DP_BIT15_16   DP_BIT15_4, DP_BIT15_4, DP_BIT15_4, DP_BIT15_4
 #define DP_BIT15_64   DP_BIT15_16, DP_BIT15_16, DP_BIT15_16, DP_BIT15_16
@@ -80,56 +122,336 @@ struct DP_Tile {
 #define DP_BIT15_1024 DP_BIT15_256, DP_BIT15_256, DP_BIT15_256, DP_BIT15_256
 #define DP_BIT15_4096 DP_BIT15_1024, DP_BIT15_1024, DP_BIT15_1024, DP_BIT15_1024
 
+#if DP_TILE_LENGTH == 1024
+#    define DP_BIT15_TILE DP_BIT15_1024
+#elif DP_TILE_LENGTH == 4096
+#    define DP_BIT15_TILE DP_BIT15_4096
+#else
+#    error "No opaque mask initializer for this tile size"
+#endif
+
 const uint16_t *DP_tile_opaque_mask(void)
 {
-    static const uint16_t opaque_mask[DP_TILE_LENGTH] = {DP_BIT15_4096};
+    static const uint16_t opaque_mask[DP_TILE_LENGTH] = {DP_BIT15_TILE};
     return opaque_mask;
 }
 
-static DP_MemoryPool tile_memory_pool;
-static DP_Mutex *tile_memory_pool_lock = NULL;
+// Freed tiles are kept around for reuse instead of going back to the system
+// right away, since strokes and undo churn through them constantly. They're
+// spread over a few free lists, picked by hashing the thread id, so that
+// threads don't contend on a single lock. Worker threads come and go, so the
+// lists aren't owned by any particular thread and an allocation takes from
+// the others when its own list is empty. The total is bounded by the cache
+// limit, anything past that is freed immediately.
+#define TILE_CACHE_SHARD_COUNT 8
+
+typedef struct DP_TileCacheNode {
+    struct DP_TileCacheNode *next;
+} DP_TileCacheNode;
+
+typedef struct DP_TileCacheShard {
+    DP_Atomic lock;
+    DP_TileCacheNode *first;
+    size_t cached;
+    size_t allocated;
+    size_t released;
+} DP_TileCacheShard;
+
+static DP_TileCacheShard tile_cache_shards[TILE_CACHE_SHARD_COUNT];
+static DP_Atomic tile_cache_count;
+static DP_Atomic tile_cache_limit = DP_ATOMIC_INIT(
+    (int)(DP_TILE_MEMORY_CACHE_LIMIT_DEFAULT / sizeof(DP_TransientTile)));
+
+static DP_TileCacheShard *tile_cache_shard_at(size_t i)
+{
+    return &tile_cache_shards[i % TILE_CACHE_SHARD_COUNT];
+}
+
+static size_t tile_cache_shard_index(void)
+{
+    // Thread ids tend to be aligned pointers, so mix the bits up first.
+    uint64_t h = (uint64_t)(uintptr_t)DP_thread_current_id();
+    h *= UINT64_C(0x9e3779b97f4a7c15);
+    return (size_t)(h >> 61);
+}
 
-static void *alloc_tile(bool transient, bool maybe_blank,
-                        unsigned int context_id)
+static DP_TransientTile *take_cached_tile(void)
 {
-    DP_ATOMIC_DECLARE_STATIC_SPIN_LOCK(tile_memory_pool_spinlock);
-    if (!tile_memory_pool_lock) {
-        DP_atomic_lock(&tile_memory_pool_spinlock);
-        if (!tile_memory_pool_lock) {
-            tile_memory_pool = DP_memory_pool_new_type(DP_TransientTile, 1024);
-            tile_memory_pool_lock = DP_mutex_new();
+    if (DP_atomic_get(&tile_cache_count) > 0) {
+        size_t start = tile_cache_shard_index();
+        for (size_t i = 0; i < TILE_CACHE_SHARD_COUNT; ++i) {
+            DP_TileCacheShard *shard = tile_cache_shard_at(start + i);
+            DP_atomic_lock(&shard->lock);
+            DP_TileCacheNode *node = shard->first;
+            if (node) {
+                shard->first = node->next;
+                --shard->cached;
+                DP_atomic_add(&tile_cache_count, -1);
+            }
+            DP_atomic_unlock(&shard->lock);
+            if (node) {
+                return (DP_TransientTile *)node;
+            }
         }
-        DP_atomic_unlock(&tile_memory_pool_spinlock);
     }
+    return NULL;
+}
+
+static DP_TransientTile *allocate_tile(void)
+{
+    DP_TileCacheShard *shard = tile_cache_shard_at(tile_cache_shard_index());
+    DP_atomic_lock(&shard->lock);
+    ++shard->allocated;
+    DP_atomic_unlock(&shard->lock);
+    return DP_malloc_simd(sizeof(DP_TransientTile));
+}
+
+static bool reserve_cached_tile(void)
+{
+    int limit = DP_atomic_get(&tile_cache_limit);
+    while (true) {
+        int count = DP_atomic_get(&tile_cache_count);
+        if (count >= limit) {
+            return false;
+        }
+        else if (DP_atomic_compare_exchange(&tile_cache_count, count,
+                                            count + 1)) {
+            return true;
+        }
+    }
+}
+
+static void free_tile(void *tile)
+{
+    DP_TileCacheShard *shard = tile_cache_shard_at(tile_cache_shard_index());
+    bool cache = reserve_cached_tile();
+    DP_atomic_lock(&shard->lock);
+    if (cache) {
+        DP_TileCacheNode *node = tile;
+        node->next = shard->first;
+        shard->first = node;
+        ++shard->cached;
+    }
+    else {
+        ++shard->released;
+    }
+    DP_atomic_unlock(&shard->lock);
 
-    DP_MUTEX_MUST_LOCK(tile_memory_pool_lock);
-    DP_TransientTile *tt = DP_memory_pool_alloc_el(&tile_memory_pool);
-    DP_MUTEX_MUST_UNLOCK(tile_memory_pool_lock);
+    if (!cache) {
+        DP_free_simd(tile);
+    }
+}
+
+static void *alloc_tile(bool transient, bool maybe_blank,
+                        unsigned int context_id)
+{
+    DP_TransientTile *tt = take_cached_tile();
+    if (!tt) {
+        tt = allocate_tile();
+    }
 
     DP_atomic_set(&tt->refcount, 1);
+    DP_atomic_set(&tt->solid, DP_TILE_SOLID_UNKNOWN);
     tt->transient = transient;
     tt->maybe_blank = maybe_blank;
+    tt->packed = false;
     tt->context_id = context_id;
 
     return tt;
 }
 
+static void set_solid(DP_TransientTile *tt, DP_TileSolid solid)
+{
+    DP_atomic_set(&tt->solid, (int)solid);
+}
+
+// Writes can happen pixel by pixel, so avoid the atomic store if possible.
+static void invalidate_solid(DP_TransientTile *tt)
+{
+    if (DP_atomic_get(&tt->solid) != DP_TILE_SOLID_UNKNOWN) {
+        set_solid(tt, DP_TILE_SOLID_UNKNOWN);
+    }
+}
+
+static bool search_solid(DP_Tile *tile)
+{
+    DP_Pixel15 *pixels = tile->pixels;
+    DP_Pixel15 pixel = pixels[0];
+    for (int i = 1; i < DP_TILE_LENGTH; ++i) {
+        if (!DP_pixel15_equal(pixel, pixels[i])) {
+            return false;
+        }
+    }
+    return true;
+}
 
-DP_MemoryPoolStatistics DP_tile_memory_usage(void)
+static bool is_solid(DP_Tile *tile)
 {
-    if (tile_memory_pool_lock) {
-        DP_MUTEX_MUST_LOCK(tile_memory_pool_lock);
-        DP_MemoryPoolStatistics mps =
-            DP_memory_pool_statistics(&tile_memory_pool);
-        DP_MUTEX_MUST_UNLOCK(tile_memory_pool_lock);
-        return mps;
+    int solid = DP_atomic_get(&tile->solid);
+    if (solid == DP_TILE_SOLID_UNKNOWN) {
+        bool result = search_solid(tile);
+        set_solid((DP_TransientTile *)tile,
+                  result ? DP_TILE_SOLID_YES : DP_TILE_SOLID_NO);
+        return result;
     }
     else {
-        return (DP_MemoryPoolStatistics){sizeof(DP_TransientTile), 0, 0, 0};
+        return solid == DP_TILE_SOLID_YES;
     }
 }
 
 
+// Packed tiles are allocated individually, they vary in size.
+DP_ATOMIC_DECLARE_STATIC_SPIN_LOCK(packed_stats_lock);
+static size_t packed_tile_count;
+static size_t packed_tile_bytes;
+static size_t unpacked_tile_count;
+
+static void packed_stats_update(long long count, long long bytes,
+                                long long unpacked)
+{
+    DP_atomic_lock(&packed_stats_lock);
+    packed_tile_count = (size_t)((long long)packed_tile_count + count);
+    packed_tile_bytes = (size_t)((long long)packed_tile_bytes + bytes);
+    unpacked_tile_count = (size_t)((long long)unpacked_tile_count + unpacked);
+    DP_atomic_unlock(&packed_stats_lock);
+}
+
+static size_t packed_tile_size(DP_PackedTile *pt)
+{
+    return DP_FLEX_SIZEOF(DP_PackedTile, data, pt->size);
+}
+
+static unsigned char *get_unpack_output_buffer(size_t out_size, void *user)
+{
+    if (out_size == DP_TILE_BYTES) {
+        return user;
+    }
+    else {
+        DP_error_set("Packed tile needs size %zu, but got %zu",
+                     (size_t)DP_TILE_BYTES, out_size);
+        return NULL;
+    }
+}
+
+static bool decode_tile(const unsigned char *data, size_t size,
+                        DP_Pixel15 *pixels);
+
+static void unpack_pixels(DP_PackedTile *pt, DP_Pixel15 *pixels)
+{
+    // This is data we packed ourselves, failing to unpack it means memory got
+    // corrupted, there's no sensible way to continue from that.
+    bool ok = pt->rle ? decode_tile(pt->data, pt->size, pixels)
+                      : DP_compress_inflate(pt->data, pt->size,
+                                            get_unpack_output_buffer, pixels);
+    if (!ok) {
+        DP_panic("Error unpacking tile: %s", DP_error());
+    }
+}
+
+static DP_Tile *unpack(DP_PackedTile *pt)
+{
+    DP_Tile *t = DP_atomic_ptr_get(&pt->unpacked);
+    if (!t) {
+        DP_atomic_lock(&pt->lock);
+        t = DP_atomic_ptr_get(&pt->unpacked);
+        if (!t) {
+            DP_TransientTile *tt =
+                alloc_tile(false, pt->maybe_blank, pt->context_id);
+            unpack_pixels(pt, tt->pixels);
+            set_solid(tt, DP_TILE_SOLID_NO);
+            t = (DP_Tile *)tt;
+            DP_atomic_ptr_set(&pt->unpacked, t);
+            packed_stats_update(0, 0, 1);
+        }
+        DP_atomic_unlock(&pt->lock);
+    }
+    return t;
+}
+
+// Everything that needs the pixels of a persistent tile goes through here.
+static DP_Tile *unpacked(DP_Tile *tile)
+{
+    return tile->packed ? unpack((DP_PackedTile *)tile) : tile;
+}
+
+static void free_packed(DP_PackedTile *pt)
+{
+    DP_Tile *t = DP_atomic_ptr_get(&pt->unpacked);
+    packed_stats_update(-1, -(long long)packed_tile_size(pt), t ? -1 : 0);
+    DP_tile_decref_nullable(t);
+    DP_free(pt);
+}
+
+
+DP_TileMemoryStatistics DP_tile_memory_usage(void)
+{
+    size_t cached = 0;
+    size_t allocated = 0;
+    size_t released = 0;
+    for (size_t i = 0; i < TILE_CACHE_SHARD_COUNT; ++i) {
+        DP_TileCacheShard *shard = tile_cache_shard_at(i);
+        DP_atomic_lock(&shard->lock);
+        cached += shard->cached;
+        allocated += shard->allocated;
+        released += shard->released;
+        DP_atomic_unlock(&shard->lock);
+    }
+    // Tiles can be allocated on one thread and freed on another, so the
+    // shards only add up when taken together. Since they're not all locked at
+    // once, concurrent changes can skew the numbers, so clamp them.
+    size_t total = allocated > released ? allocated - released : 0;
+    size_t used = total > cached ? total - cached : 0;
+    DP_TileMemoryStatistics tms = {sizeof(DP_TransientTile), used, cached,
+                                   allocated, 0, 0, 0};
+    DP_atomic_lock(&packed_stats_lock);
+    tms.tiles_packed = packed_tile_count;
+    tms.packed_bytes = packed_tile_bytes;
+    tms.tiles_unpacked = unpacked_tile_count;
+    DP_atomic_unlock(&packed_stats_lock);
+    return tms;
+}
+
+static void release_cached_tiles(int keep)
+{
+    for (size_t i = 0; i < TILE_CACHE_SHARD_COUNT; ++i) {
+        DP_TileCacheShard *shard = tile_cache_shard_at(i);
+        DP_atomic_lock(&shard->lock);
+        DP_TileCacheNode *first = NULL;
+        int excess = DP_atomic_get(&tile_cache_count) - keep;
+        while (excess > 0 && shard->first) {
+            DP_TileCacheNode *node = shard->first;
+            shard->first = node->next;
+            node->next = first;
+            first = node;
+            --shard->cached;
+            ++shard->released;
+            DP_atomic_add(&tile_cache_count, -1);
+            --excess;
+        }
+        DP_atomic_unlock(&shard->lock);
+
+        while (first) {
+            DP_TileCacheNode *next = first->next;
+            DP_free_simd(first);
+            first = next;
+        }
+    }
+}
+
+void DP_tile_memory_cache_limit_set(size_t bytes)
+{
+    size_t count = bytes / sizeof(DP_TransientTile);
+    int limit = count < (size_t)INT_MAX ? DP_size_to_int(count) : INT_MAX;
+    DP_atomic_set(&tile_cache_limit, limit);
+    release_cached_tiles(limit);
+}
+
+void DP_tile_memory_cache_drain(void)
+{
+    release_cached_tiles(0);
+}
+
+
 DP_Tile *DP_tile_new(unsigned int context_id)
 {
     return DP_tile_new_from_bgra(context_id, 0);
@@ -141,6 +463,7 @@ DP_Tile *DP_tile_new_from_pixel15(unsigned int context_id, DP_Pixel15 pixel)
     for (int i = 0; i < DP_TILE_LENGTH; ++i) {
         tt->pixels[i] = pixel;
     }
+    set_solid(tt, DP_TILE_SOLID_YES);
     return (DP_Tile *)tt;
 }
 
@@ -225,6 +548,8 @@ DP_Tile *DP_tile_new_checker(unsigned int context_id, DP_Pixel15 pixel1,
             DP_transient_tile_pixel_at_set(tt, x + half, y + half, pixel1);
         }
     }
+    set_solid(tt, DP_pixel15_equal(pixel1, pixel2) ? DP_TILE_SOLID_YES
+                                                   : DP_TILE_SOLID_NO);
     return DP_transient_tile_persist(tt);
 }
 
@@ -238,6 +563,8 @@ DP_Tile *DP_tile_new_zebra(unsigned int context_id, DP_Pixel15 pixel1,
                           ? pixel1
                           : pixel2;
     }
+    set_solid(tt, DP_pixel15_equal(pixel1, pixel2) ? DP_TILE_SOLID_YES
+                                                   : DP_TILE_SOLID_NO);
     return DP_transient_tile_persist(tt);
 }
 
@@ -263,6 +590,97 @@ DP_Tile *DP_tile_censored_inc(void)
 }
 
 
+// Packing only pays off if it saves a good chunk of the tile.
+#define PACKED_SIZE_MAX (DP_TILE_BYTES / 4 * 3)
+
+// Tiles made of few enough runs, like a stroke or two on an otherwise empty
+// area, get run-length encoded instead. That's bigger than deflating them,
+// but a lot faster to pack and unpack.
+#define RLE_PACKED_SIZE_MAX (DP_TILE_BYTES / 8)
+
+static unsigned char *get_pack_output_buffer(size_t out_size, void *user)
+{
+    DP_PackedTile **out_pt = user;
+    DP_PackedTile *pt =
+        DP_malloc(DP_FLEX_SIZEOF(DP_PackedTile, data, out_size));
+    *out_pt = pt;
+    return pt->data;
+}
+
+DP_Tile *DP_tile_pack(DP_Tile *tile)
+{
+    DP_ASSERT(tile);
+    DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
+    DP_ASSERT(!tile->transient);
+    if (tile->packed || is_solid(tile)) {
+        return NULL;
+    }
+
+    DP_PackedTile *pt = NULL;
+    bool rle = DP_tile_encoded_size(tile) <= RLE_PACKED_SIZE_MAX;
+    size_t size =
+        rle ? DP_tile_encode(tile, get_pack_output_buffer, &pt)
+            : DP_compress_deflate_level((const unsigned char *)tile->pixels,
+                                        DP_TILE_BYTES, 1,
+                                        get_pack_output_buffer, &pt);
+    if (size == 0 || size > PACKED_SIZE_MAX) {
+        DP_free(pt);
+        return NULL;
+    }
+
+    pt = DP_realloc(pt, DP_FLEX_SIZEOF(DP_PackedTile, data, size));
+    DP_atomic_set(&pt->refcount, 1);
+    DP_atomic_set(&pt->solid, DP_TILE_SOLID_NO);
+    pt->transient = false;
+    pt->maybe_blank = tile->maybe_blank;
+    pt->packed = true;
+    pt->context_id = tile->context_id;
+    DP_atomic_set(&pt->lock, 0);
+    DP_atomic_ptr_set(&pt->unpacked, NULL);
+    pt->rle = rle;
+    pt->size = size;
+    packed_stats_update(1, (long long)packed_tile_size(pt), 0);
+    return (DP_Tile *)pt;
+}
+
+bool DP_tile_packed(DP_Tile *tile)
+{
+    DP_ASSERT(tile);
+    DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
+    return tile->packed;
+}
+
+size_t DP_tile_packed_size(DP_Tile *tile)
+{
+    DP_ASSERT(tile);
+    DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
+    return tile->packed ? packed_tile_size((DP_PackedTile *)tile) : 0;
+}
+
+void DP_tile_evict(DP_Tile *tile)
+{
+    DP_ASSERT(tile);
+    DP_ASSERT(DP_atomic_get(&tile->refcount) == 1);
+    if (tile->packed) {
+        DP_PackedTile *pt = (DP_PackedTile *)tile;
+        DP_Tile *t = DP_atomic_ptr_get(&pt->unpacked);
+        if (t) {
+            DP_atomic_ptr_set(&pt->unpacked, NULL);
+            packed_stats_update(0, 0, -1);
+            DP_tile_decref(t);
+        }
+    }
+}
+
+bool DP_tile_resident(DP_Tile *tile)
+{
+    DP_ASSERT(tile);
+    DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
+    return !tile->packed
+        || DP_atomic_ptr_get(&((DP_PackedTile *)tile)->unpacked);
+}
+
+
 DP_Tile *DP_tile_incref(DP_Tile *tile)
 {
     DP_ASSERT(tile);
@@ -295,9 +713,12 @@ void DP_tile_decref(DP_Tile *tile)
     DP_ASSERT(tile);
     DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
     if (DP_atomic_dec(&tile->refcount)) {
-        DP_MUTEX_MUST_LOCK(tile_memory_pool_lock);
-        DP_memory_pool_free_el(&tile_memory_pool, tile);
-        DP_MUTEX_MUST_UNLOCK(tile_memory_pool_lock);
+        if (tile->packed) {
+            free_packed((DP_PackedTile *)tile);
+        }
+        else {
+            free_tile(tile);
+        }
     }
 }
 
@@ -334,7 +755,7 @@ const DP_Pixel15 *DP_tile_pixels(DP_Tile *tile)
 {
     DP_ASSERT(tile);
     DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
-    return tile->pixels;
+    return unpacked(tile)->pixels;
 }
 
 DP_Pixel15 DP_tile_pixel_at(DP_Tile *tile, int x, int y)
@@ -345,19 +766,21 @@ DP_Pixel15 DP_tile_pixel_at(DP_Tile *tile, int x, int y)
     DP_ASSERT(y >= 0);
     DP_ASSERT(x < DP_TILE_SIZE);
     DP_ASSERT(y < DP_TILE_SIZE);
-    return tile->pixels[y * DP_TILE_SIZE + x];
+    return unpacked(tile)->pixels[y * DP_TILE_SIZE + x];
 }
 
 bool DP_tile_blank(DP_Tile *tile)
 {
-    static const DP_Pixel15 blank_pixels[DP_TILE_LENGTH] = {0};
-    return memcmp(tile->pixels, blank_pixels, DP_TILE_BYTES) == 0;
+    DP_ASSERT(tile);
+    DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
+    return is_solid(tile)
+        && DP_pixel15_equal(tile->pixels[0], DP_pixel15_zero());
 }
 
 bool DP_tile_opaque(DP_Tile *tile_or_null)
 {
     if (tile_or_null) {
-        DP_Pixel15 *pixels = tile_or_null->pixels;
+        DP_Pixel15 *pixels = unpacked(tile_or_null)->pixels;
         for (int i = 1; i < DP_TILE_LENGTH; ++i) {
             if (pixels[i].a < DP_BIT15) {
                 return false;
@@ -374,14 +797,10 @@ bool DP_tile_same_pixel(DP_Tile *tile_or_null, DP_Pixel15 *out_pixel)
 {
     DP_Pixel15 pixel;
     if (tile_or_null) {
-        DP_Pixel15 *pixels = tile_or_null->pixels;
-        pixel = pixels[0];
-        for (int i = 1; i < DP_TILE_LENGTH; ++i) {
-            DP_Pixel15 q = pixels[i];
-            if (!DP_pixel15_equal(pixel, q)) {
-                return false;
-            }
+        if (!is_solid(tile_or_null)) {
+            return false;
         }
+        pixel = tile_or_null->pixels[0];
     }
     else {
         pixel = DP_pixel15_zero();
@@ -393,6 +812,24 @@ bool DP_tile_same_pixel(DP_Tile *tile_or_null, DP_Pixel15 *out_pixel)
     return true;
 }
 
+uint64_t DP_tile_checksum(DP_Tile *tile_or_null)
+{
+    if (!tile_or_null || DP_tile_blank(tile_or_null)) {
+        return DP_TILE_CHECKSUM_BLANK;
+    }
+
+    uint64_t checksum = DP_CHECKSUM_INIT;
+    DP_Pixel15 *pixels = unpacked(tile_or_null)->pixels;
+    for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+        DP_Pixel15 pixel = pixels[i];
+        checksum = DP_checksum_uint16(checksum, pixel.b);
+        checksum = DP_checksum_uint16(checksum, pixel.g);
+        checksum = DP_checksum_uint16(checksum, pixel.r);
+        checksum = DP_checksum_uint16(checksum, pixel.a);
+    }
+    return checksum;
+}
+
 
 size_t DP_tile_compress(DP_Tile *tile, DP_Pixel8 *pixel_buffer,
                         unsigned char *(*get_output_buffer)(size_t, void *),
@@ -401,13 +838,257 @@ size_t DP_tile_compress(DP_Tile *tile, DP_Pixel8 *pixel_buffer,
     DP_ASSERT(tile);
     DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
     DP_ASSERT(pixel_buffer);
-    DP_pixels15_to_8(pixel_buffer, tile->pixels, DP_TILE_LENGTH);
+    DP_Tile *t = tile->packed
+                   ? DP_atomic_ptr_get(&((DP_PackedTile *)tile)->unpacked)
+                   : tile;
+    if (t) {
+        DP_pixels15_to_8(pixel_buffer, t->pixels, DP_TILE_LENGTH);
+    }
+    else {
+        // Reset images get built from old save points, which are mostly made
+        // of packed tiles that nobody looks at otherwise. Don't keep them
+        // unpacked afterwards, that would undo the point of packing them.
+        DP_Pixel15 *pixels = DP_malloc_simd(DP_TILE_BYTES);
+        unpack_pixels((DP_PackedTile *)tile, pixels);
+        DP_pixels15_to_8(pixel_buffer, pixels, DP_TILE_LENGTH);
+        DP_free_simd(pixels);
+    }
     return DP_compress_deflate((const unsigned char *)pixel_buffer,
                                DP_TILE_COMPRESSED_BYTES, get_output_buffer,
                                user);
 }
 
 
+// Pixels are encoded as their four 15 bit channels in little endian, runs as
+// their length minus one in a byte followed by the pixel. Runs never cross the
+// end of a row, so they can't get longer than DP_TILE_SIZE.
+#define ENCODED_PIXEL_BYTES 8
+#define ENCODED_RUN_BYTES   (1 + ENCODED_PIXEL_BYTES)
+#define ENCODED_RAW_BYTES   (1 + DP_TILE_LENGTH * ENCODED_PIXEL_BYTES)
+
+static_assert(DP_TILE_SIZE <= UINT8_MAX + 1,
+              "tile encoding run length fits into a byte");
+
+static size_t count_runs(const DP_Pixel15 *pixels)
+{
+    size_t runs = 0;
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        const DP_Pixel15 *row = pixels + y * DP_TILE_SIZE;
+        ++runs;
+        for (int x = 1; x < DP_TILE_SIZE; ++x) {
+            if (!DP_pixel15_equal(row[x], row[x - 1])) {
+                ++runs;
+            }
+        }
+    }
+    return runs;
+}
+
+static size_t encode_pixel(DP_Pixel15 pixel, unsigned char *out)
+{
+    size_t written = 0;
+    written += DP_write_littleendian_uint16(pixel.b, out + written);
+    written += DP_write_littleendian_uint16(pixel.g, out + written);
+    written += DP_write_littleendian_uint16(pixel.r, out + written);
+    written += DP_write_littleendian_uint16(pixel.a, out + written);
+    return written;
+}
+
+static size_t encode_runs(const DP_Pixel15 *pixels, unsigned char *out)
+{
+    size_t written = 0;
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        const DP_Pixel15 *row = pixels + y * DP_TILE_SIZE;
+        int start = 0;
+        for (int x = 1; x <= DP_TILE_SIZE; ++x) {
+            if (x == DP_TILE_SIZE || !DP_pixel15_equal(row[x], row[start])) {
+                written += DP_write_littleendian_uint8(
+                    DP_int_to_uint8(x - start - 1), out + written);
+                written += encode_pixel(row[start], out + written);
+                start = x;
+            }
+        }
+    }
+    return written;
+}
+
+size_t DP_tile_encoded_size(DP_Tile *tile_or_null)
+{
+    DP_Pixel15 pixel;
+    if (DP_tile_same_pixel(tile_or_null, &pixel)) {
+        return DP_pixel15_equal(pixel, DP_pixel15_zero())
+                 ? 1
+                 : 1 + ENCODED_PIXEL_BYTES;
+    }
+    else {
+        size_t rle_size =
+            1 + count_runs(unpacked(tile_or_null)->pixels) * ENCODED_RUN_BYTES;
+        return DP_min_size(rle_size, ENCODED_RAW_BYTES);
+    }
+}
+
+size_t DP_tile_encode(DP_Tile *tile_or_null,
+                      unsigned char *(*get_output_buffer)(size_t, void *),
+                      void *user)
+{
+    DP_ASSERT(get_output_buffer);
+    size_t size = DP_tile_encoded_size(tile_or_null);
+    unsigned char *out = get_output_buffer(size, user);
+    if (!out) {
+        return 0;
+    }
+
+    DP_Pixel15 pixel;
+    if (size == 1) {
+        out[0] = DP_TILE_ENCODING_BLANK;
+    }
+    else if (DP_tile_same_pixel(tile_or_null, &pixel)) {
+        out[0] = DP_TILE_ENCODING_SOLID;
+        encode_pixel(pixel, out + 1);
+    }
+    else {
+        const DP_Pixel15 *pixels = unpacked(tile_or_null)->pixels;
+        if (size < ENCODED_RAW_BYTES) {
+            out[0] = DP_TILE_ENCODING_RLE;
+            encode_runs(pixels, out + 1);
+        }
+        else {
+            out[0] = DP_TILE_ENCODING_RAW;
+            for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+                encode_pixel(pixels[i], out + 1 + i * ENCODED_PIXEL_BYTES);
+            }
+        }
+    }
+    return size;
+}
+
+static bool decode_pixel(const unsigned char *in, DP_Pixel15 *out_pixel)
+{
+    DP_Pixel15 pixel = {
+        DP_read_littleendian_uint16(in),
+        DP_read_littleendian_uint16(in + 2),
+        DP_read_littleendian_uint16(in + 4),
+        DP_read_littleendian_uint16(in + 6),
+    };
+    if (pixel.b > DP_BIT15 || pixel.g > DP_BIT15 || pixel.r > DP_BIT15
+        || pixel.a > DP_BIT15) {
+        DP_error_set("Encoded tile pixel out of range");
+        return false;
+    }
+    *out_pixel = pixel;
+    return true;
+}
+
+static bool decode_runs(const unsigned char *in, size_t size,
+                        DP_Pixel15 *pixels)
+{
+    size_t offset = 0;
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        DP_Pixel15 *row = pixels + y * DP_TILE_SIZE;
+        int x = 0;
+        while (x < DP_TILE_SIZE) {
+            if (size - offset < ENCODED_RUN_BYTES) {
+                DP_error_set("Encoded tile runs end in row %d", y);
+                return false;
+            }
+            int length = DP_read_littleendian_uint8(in + offset) + 1;
+            if (length > DP_TILE_SIZE - x) {
+                DP_error_set("Encoded tile run of %d at %d crosses row %d",
+                             length, x, y);
+                return false;
+            }
+            DP_Pixel15 pixel;
+            if (!decode_pixel(in + offset + 1, &pixel)) {
+                return false;
+            }
+            for (int i = 0; i < length; ++i) {
+                row[x + i] = pixel;
+            }
+            x += length;
+            offset += ENCODED_RUN_BYTES;
+        }
+    }
+
+    if (offset != size) {
+        DP_error_set("Encoded tile has %zu bytes left over after runs",
+                     size - offset);
+        return false;
+    }
+    return true;
+}
+
+static bool decode_raw(const unsigned char *in, size_t size,
+                       DP_Pixel15 *pixels)
+{
+    if (size != ENCODED_RAW_BYTES - 1) {
+        DP_error_set("Raw encoded tile has size %zu, should be %zu", size,
+                     (size_t)ENCODED_RAW_BYTES - 1);
+        return false;
+    }
+    for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+        if (!decode_pixel(in + i * ENCODED_PIXEL_BYTES, &pixels[i])) {
+            return false;
+        }
+    }
+    return true;
+}
+
+static bool decode_tile(const unsigned char *data, size_t size,
+                        DP_Pixel15 *pixels)
+{
+    switch (data[0]) {
+    case DP_TILE_ENCODING_RLE:
+        return decode_runs(data + 1, size - 1, pixels);
+    case DP_TILE_ENCODING_RAW:
+        return decode_raw(data + 1, size - 1, pixels);
+    default:
+        DP_error_set("Unknown tile encoding %d", (int)data[0]);
+        return false;
+    }
+}
+
+DP_Tile *DP_tile_new_from_encoded(unsigned int context_id,
+                                  const unsigned char *data, size_t size)
+{
+    if (size == 0 || !data) {
+        DP_error_set("Encoded tile is empty");
+        return NULL;
+    }
+
+    switch (data[0]) {
+    case DP_TILE_ENCODING_BLANK:
+        if (size != 1) {
+            DP_error_set("Blank encoded tile has size %zu, should be 1", size);
+            return NULL;
+        }
+        return DP_tile_new_from_pixel15(context_id, DP_pixel15_zero());
+    case DP_TILE_ENCODING_SOLID: {
+        DP_Pixel15 pixel;
+        if (size != 1 + ENCODED_PIXEL_BYTES) {
+            DP_error_set("Solid encoded tile has size %zu, should be %zu",
+                         size, (size_t)1 + ENCODED_PIXEL_BYTES);
+            return NULL;
+        }
+        if (!decode_pixel(data + 1, &pixel)) {
+            return NULL;
+        }
+        return DP_tile_new_from_pixel15(context_id, pixel);
+    }
+    default: {
+        // The runs could be crafted to make up a blank tile.
+        DP_TransientTile *tt = alloc_tile(false, true, context_id);
+        if (decode_tile(data, size, tt->pixels)) {
+            return (DP_Tile *)tt;
+        }
+        else {
+            DP_tile_decref((DP_Tile *)tt);
+            return NULL;
+        }
+    }
+    }
+}
+
+
 void DP_tile_copy_to_image(DP_Tile *tile_or_null, DP_Image *img, int x, int y)
 {
     DP_ASSERT(img);
@@ -419,7 +1100,7 @@ void DP_tile_copy_to_image(DP_Tile *tile_or_null, DP_Image *img, int x, int y)
 
     if (tile_or_null) {
         DP_ASSERT(DP_atomic_get(&tile_or_null->refcount) > 0);
-        DP_Pixel15 *src = tile_or_null->pixels;
+        DP_Pixel15 *src = unpacked(tile_or_null)->pixels;
         for (int i = 0; i < height; ++i) {
             DP_pixels15_to_8(dst + i * img_width, src + i * DP_TILE_SIZE,
                              width);
@@ -443,7 +1124,7 @@ void DP_tile_copy_to_upixels8(DP_Tile *tile_or_null, DP_UPixel8 *pixels, int x,
 
     if (tile_or_null) {
         DP_ASSERT(DP_atomic_get(&tile_or_null->refcount) > 0);
-        DP_Pixel15 *src = tile_or_null->pixels;
+        DP_Pixel15 *src = unpacked(tile_or_null)->pixels;
         for (int i = 0; i < height; ++i) {
             DP_pixels15_to_8_unpremultiply(dst + i * pixels_width,
                                            src + i * DP_TILE_SIZE, width);
@@ -517,7 +1198,8 @@ void DP_tile_sample(DP_Tile *tile_or_null, const uint16_t *mask, int x, int y,
                     float *in_out_alpha)
 {
     if (tile_or_null) {
-        DP_Pixel15 *src = tile_or_null->pixels + y * DP_TILE_SIZE + x;
+        DP_Pixel15 *src =
+            unpacked(tile_or_null)->pixels + y * DP_TILE_SIZE + x;
         sample_tile(src, mask, width, height, skip, DP_TILE_SIZE - width,
                     opaque, in_out_weight, in_out_red, in_out_green,
                     in_out_blue, in_out_alpha);
@@ -533,7 +1215,8 @@ DP_TransientTile *DP_transient_tile_new(DP_Tile *tile, unsigned int context_id)
     DP_ASSERT(tile);
     DP_ASSERT(DP_atomic_get(&tile->refcount) > 0);
     DP_TransientTile *tt = alloc_tile(true, tile->maybe_blank, context_id);
-    memcpy(tt->pixels, tile->pixels, DP_TILE_BYTES);
+    memcpy(tt->pixels, unpacked(tile)->pixels, DP_TILE_BYTES);
+    set_solid(tt, (DP_TileSolid)DP_atomic_get(&tile->solid));
     return tt;
 }
 
@@ -541,6 +1224,7 @@ DP_TransientTile *DP_transient_tile_new_blank(unsigned int context_id)
 {
     DP_TransientTile *tt = alloc_tile(true, true, context_id);
     memset(tt->pixels, 0, sizeof(tt->pixels));
+    set_solid(tt, DP_TILE_SOLID_YES);
 
     return tt;
 }
@@ -607,12 +1291,22 @@ unsigned int DP_transient_tile_context_id(DP_Tile *tt)
     return tt->context_id;
 }
 
+void DP_transient_tile_context_id_set(DP_TransientTile *tt,
+                                      unsigned int context_id)
+{
+    DP_ASSERT(tt);
+    DP_ASSERT(DP_atomic_get(&tt->refcount) > 0);
+    DP_ASSERT(tt->transient);
+    tt->context_id = context_id;
+}
+
 DP_Pixel15 *DP_transient_tile_pixels(DP_TransientTile *tt)
 {
     DP_ASSERT(tt);
     DP_ASSERT(DP_atomic_get(&tt->refcount) > 0);
     DP_ASSERT(tt->transient);
     tt->maybe_blank = true;
+    invalidate_solid(tt);
     return tt->pixels;
 }
 
@@ -635,6 +1329,7 @@ void DP_transient_tile_pixel_at_set(DP_TransientTile *tt, int x, int y,
     DP_ASSERT(x < DP_TILE_SIZE);
     DP_ASSERT(y < DP_TILE_SIZE);
     tt->maybe_blank = pixel.a == 0;
+    invalidate_solid(tt);
     tt->pixels[y * DP_TILE_SIZE + x] = pixel;
 }
 
@@ -651,6 +1346,7 @@ void DP_transient_tile_pixel_at_put(DP_TransientTile *tt, int blend_mode, int x,
     if (DP_blend_mode_can_decrease_opacity(blend_mode)) {
         tt->maybe_blank = true;
     }
+    invalidate_solid(tt);
     DP_blend_pixels(&tt->pixels[y * DP_TILE_SIZE + x], &pixel, 1, DP_BIT15,
                     blend_mode);
 }
@@ -662,6 +1358,7 @@ void DP_transient_tile_clear(DP_TransientTile *tt)
     DP_ASSERT(tt->transient);
     memset(tt->pixels, 0, DP_TILE_BYTES);
     tt->maybe_blank = true;
+    set_solid(tt, DP_TILE_SOLID_YES);
 }
 
 void DP_transient_tile_copy(DP_TransientTile *tt, DP_Tile *t)
@@ -671,8 +1368,9 @@ void DP_transient_tile_copy(DP_TransientTile *tt, DP_Tile *t)
     DP_ASSERT(tt->transient);
     DP_ASSERT(t);
     DP_ASSERT(DP_atomic_get(&t->refcount) > 0);
-    memcpy(tt->pixels, t->pixels, DP_TILE_BYTES);
+    memcpy(tt->pixels, unpacked(t)->pixels, DP_TILE_BYTES);
     tt->maybe_blank = t->maybe_blank;
+    set_solid(tt, (DP_TileSolid)DP_atomic_get(&t->solid));
 }
 
 bool DP_transient_tile_blank(DP_TransientTile *tt)
@@ -706,7 +1404,28 @@ void DP_transient_tile_merge(DP_TransientTile *DP_RESTRICT tt,
     if (DP_blend_mode_can_decrease_opacity(blend_mode)) {
         tt->maybe_blank = true;
     }
-    DP_blend_tile(tt->pixels, t->pixels, opacity, blend_mode);
+    if (is_solid(t) && is_solid((DP_Tile *)tt)) {
+        // Blending works on each pixel on its own, so two uniform tiles give
+        // another uniform one. Only blend a single pixel and fill with that.
+        DP_Pixel15 pixel = tt->pixels[0];
+        DP_blend_pixels(&pixel, &t->pixels[0], 1, opacity, blend_mode);
+        for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+            tt->pixels[i] = pixel;
+        }
+    }
+    else if (blend_mode == DP_BLEND_MODE_NORMAL && opacity == DP_BIT15
+             && is_solid(t) && t->pixels[0].a == DP_BIT15) {
+        // An opaque uniform tile covers whatever is below it completely.
+        DP_Pixel15 pixel = t->pixels[0];
+        for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+            tt->pixels[i] = pixel;
+        }
+        set_solid(tt, DP_TILE_SOLID_YES);
+    }
+    else {
+        invalidate_solid(tt);
+        DP_blend_tile(tt->pixels, unpacked(t)->pixels, opacity, blend_mode);
+    }
 }
 
 DP_TransientTile *
@@ -738,6 +1457,7 @@ void DP_transient_tile_brush_apply(DP_TransientTile *tt, DP_UPixel15 src,
     if (DP_blend_mode_can_decrease_opacity(blend_mode)) {
         tt->maybe_blank = true;
     }
+    invalidate_solid(tt);
     DP_blend_mask(tt->pixels + y * DP_TILE_SIZE + x, src, blend_mode, mask,
                   opacity, w, h, skip, DP_TILE_SIZE - w);
 }
@@ -758,6 +1478,7 @@ void DP_transient_tile_brush_apply_posterize(DP_TransientTile *tt,
     DP_ASSERT(y < DP_TILE_SIZE);
     DP_ASSERT(x + w <= DP_TILE_SIZE);
     DP_ASSERT(y + h <= DP_TILE_SIZE);
+    invalidate_solid(tt);
     DP_posterize_mask(tt->pixels + y * DP_TILE_SIZE + x, posterize_num, mask,
                       opacity, w, h, skip, DP_TILE_SIZE - w);
 }
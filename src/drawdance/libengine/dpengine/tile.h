@@ -23,13 +23,13 @@
 #define DPENGINE_TILE_H
 #include "pixels.h"
 #include <dpcommon/common.h>
-#include <dpcommon/memory_pool.h>
 
 typedef struct DP_DrawContext DP_DrawContext;
 typedef struct DP_Image DP_Image;
 
 #define DP_TILE_BYTES            (DP_TILE_LENGTH * sizeof(DP_Pixel15))
 #define DP_TILE_COMPRESSED_BYTES (DP_TILE_LENGTH * sizeof(DP_Pixel8))
+#define DP_TILE_CHECKSUM_BLANK   0u
 
 typedef struct DP_TileCounts {
     int x, y;
@@ -81,7 +81,31 @@ DP_INLINE int DP_tile_total_round(int width, int height)
 const uint16_t *DP_tile_opaque_mask(void);
 
 
-DP_MemoryPoolStatistics DP_tile_memory_usage(void);
+// Freed tile memory is cached for reuse up to this many bytes.
+#define DP_TILE_MEMORY_CACHE_LIMIT_DEFAULT ((size_t)128 * 1024 * 1024)
+
+typedef struct DP_TileMemoryStatistics {
+    size_t tile_size;
+    size_t tiles_used;
+    size_t tiles_cached;
+    size_t allocations;
+    size_t tiles_packed;
+    size_t packed_bytes;
+    size_t tiles_unpacked;
+} DP_TileMemoryStatistics;
+
+// Returns how many tiles are in use, how many freed ones are being held onto
+// for reuse and how many times tile memory had to be allocated in total. Also
+// how many packed tiles there are, the bytes they take up and how many of
+// them have been unpacked, those unpacked pixels count as tiles in use.
+DP_TileMemoryStatistics DP_tile_memory_usage(void);
+
+// Sets how many bytes of freed tiles may be cached, releasing any excess.
+// Setting it to zero disables the cache entirely.
+void DP_tile_memory_cache_limit_set(size_t bytes);
+
+// Releases all cached tiles back to the system, for when memory is tight.
+void DP_tile_memory_cache_drain(void);
 
 
 DP_Tile *DP_tile_new(unsigned int context_id);
@@ -110,6 +134,29 @@ DP_Tile *DP_tile_censored_noinc(void);
 
 DP_Tile *DP_tile_censored_inc(void);
 
+// Returns a packed copy of the given persistent tile, which keeps its pixels
+// deflated or run-length encoded until something needs them. Every function
+// that reads pixels unpacks it transparently and keeps the result around until
+// the tile is freed or evicted. Returns NULL if the tile is already packed, is
+// solid or doesn't compress well enough.
+DP_Tile *DP_tile_pack(DP_Tile *tile);
+
+bool DP_tile_packed(DP_Tile *tile);
+
+// The bytes a packed tile takes up on top of its unpacked pixels, or 0 if
+// the tile isn't packed.
+size_t DP_tile_packed_size(DP_Tile *tile);
+
+// Drops the unpacked pixels of a packed tile, they get unpacked again if
+// anything needs them later. Pixels handed out before become invalid, so the
+// caller must hold the only reference to the tile. Does nothing to tiles that
+// aren't packed or haven't been unpacked.
+void DP_tile_evict(DP_Tile *tile);
+
+// Whether the tile's pixels are in memory, which is always the case unless
+// it's packed and nothing has looked at its pixels yet.
+bool DP_tile_resident(DP_Tile *tile);
+
 DP_Tile *DP_tile_incref(DP_Tile *tile);
 
 DP_Tile *DP_tile_incref_nullable(DP_Tile *tile_or_null);
@@ -127,23 +174,59 @@ int DP_tile_refcount(DP_Tile *tile);
 bool DP_tile_transient(DP_Tile *tile);
 
 
+// The id of the user who last changed the tile, or 0 if it came out of
+// flattening several layers together, which mixes everyone's work. Copying a
+// tile to change it hands it to whoever is changing it, shared tiles keep the
+// user they had. Not part of the tile checksum, since it doesn't affect
+// pixels.
 unsigned int DP_tile_context_id(DP_Tile *tile);
 
 const DP_Pixel15 *DP_tile_pixels(DP_Tile *tile);
 
 DP_Pixel15 DP_tile_pixel_at(DP_Tile *tile, int x, int y);
 
+// Whether every pixel in the tile is fully transparent. Goes through the same
+// cached answer as DP_tile_same_pixel, so it's cheap to ask repeatedly.
 bool DP_tile_blank(DP_Tile *tile);
 
 bool DP_tile_opaque(DP_Tile *tile_or_null);
 
+// Whether all pixels in the tile are the same, with that premultiplied color
+// put into out_pixel if so. Null tiles count as blank. The answer is cached,
+// so asking again is cheap until the tile gets written to.
 bool DP_tile_same_pixel(DP_Tile *tile_or_null, DP_Pixel15 *out_pixel);
 
+// Checksum over the tile's pixels, the same on every platform. Null and blank
+// tiles always give DP_TILE_CHECKSUM_BLANK.
+uint64_t DP_tile_checksum(DP_Tile *tile_or_null);
+
 
 size_t DP_tile_compress(DP_Tile *tile, DP_Pixel8 *pixel_buffer,
                         unsigned char *(*get_output_buffer)(size_t, void *),
                         void *user);
 
+// Lossless encoding of a tile's pixels. The first byte is the kind of
+// encoding: blank tiles are just that, solid ones add their pixel, other tiles
+// get their rows run-length encoded or their pixels stored as they are,
+// whichever is smaller. Null tiles are encoded as blank.
+#define DP_TILE_ENCODING_BLANK 0
+#define DP_TILE_ENCODING_SOLID 1
+#define DP_TILE_ENCODING_RLE   2
+#define DP_TILE_ENCODING_RAW   3
+
+size_t DP_tile_encoded_size(DP_Tile *tile_or_null);
+
+// Returns the encoded size, or 0 if get_output_buffer returned NULL.
+size_t DP_tile_encode(DP_Tile *tile_or_null,
+                      unsigned char *(*get_output_buffer)(size_t, void *),
+                      void *user);
+
+// Validates everything about the encoded data, so it's safe to use on input
+// from outside. Returns NULL and sets an error if it's invalid. Blank tiles
+// are returned as such rather than as NULL.
+DP_Tile *DP_tile_new_from_encoded(unsigned int context_id,
+                                  const unsigned char *data, size_t size);
+
 
 void DP_tile_copy_to_image(DP_Tile *tile_or_null, DP_Image *img, int x, int y);
 
@@ -181,6 +264,9 @@ DP_Tile *DP_transient_tile_persist(DP_TransientTile *tt);
 
 unsigned int DP_transient_tile_context_id(DP_Tile *tt);
 
+void DP_transient_tile_context_id_set(DP_TransientTile *tt,
+                                      unsigned int context_id);
+
 DP_Pixel15 *DP_transient_tile_pixels(DP_TransientTile *tt);
 
 DP_Pixel15 DP_transient_tile_pixel_at(DP_TransientTile *tt, int x, int y);
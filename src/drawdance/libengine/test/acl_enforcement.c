@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/annotation.h>
+#include <dpengine/annotation_list.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/acl.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define SERVER  0
+#define OP      1
+#define TRUSTED 2
+#define GUEST   3
+
+#define SHARED_LAYER_ID 0x101
+#define GUEST_LAYER_ID  0x301
+
+#define TRUSTED_ANNOTATION_ID 0x201
+
+#define CELL_SIZE  8
+#define CELL_COUNT 16
+
+typedef struct DP_AclTestState {
+    DP_AclState *acls;
+    DP_CanvasHistory *ch;
+    DP_DrawContext *dc;
+    // Color expected in each cell at the end, 0 for cells that got filtered.
+    uint32_t expected[CELL_COUNT];
+    int next_cell;
+} DP_AclTestState;
+
+static void set_ids(int count, uint8_t *out, void *user)
+{
+    if (count > 0) {
+        memcpy(out, user, DP_int_to_size(count));
+    }
+}
+
+// Runs the message through the ACL state first, the same way the paint engine
+// does it, and only passes it on to the canvas if it wasn't filtered.
+static bool handle(DP_AclTestState *state, DP_Message *msg)
+{
+    uint8_t result = DP_acl_state_handle(state->acls, msg, false);
+    bool filtered = result & DP_ACL_STATE_FILTERED_BIT;
+    if (!filtered && DP_message_type(msg) >= 128) {
+        DP_canvas_history_handle(state->ch, state->dc, msg);
+    }
+    DP_message_decref(msg);
+    return !filtered;
+}
+
+static DP_Message *user_list(unsigned int context_id, int count,
+                             const uint8_t *ids,
+                             DP_Message *(*make)(unsigned int,
+                                                 void (*)(int, uint8_t *,
+                                                          void *),
+                                                 int, void *))
+{
+    return make(context_id, set_ids, count, (void *)ids);
+}
+
+static DP_Message *layer_acl(unsigned int context_id, int layer_id,
+                             uint8_t flags, int count, const uint8_t *ids)
+{
+    return DP_msg_layer_acl_new(context_id, DP_int_to_uint16(layer_id), flags,
+                                set_ids, count, (void *)ids);
+}
+
+static DP_Message *feature_tier(unsigned int context_id, DP_Feature feature,
+                                DP_AccessTier tier)
+{
+    uint8_t tiers[DP_FEATURE_COUNT];
+    memset(tiers, 255, sizeof(tiers));
+    tiers[feature] = (uint8_t)tier;
+    return DP_msg_feature_access_levels_new(context_id, set_ids,
+                                            DP_FEATURE_COUNT, tiers);
+}
+
+// Fills the next free cell of the canvas, asserting whether it should land.
+static void draw(TEST_PARAMS, DP_AclTestState *state, unsigned int context_id,
+                 int layer_id, bool should_land, const char *title)
+{
+    int cell = state->next_cell++;
+    DP_ASSERT(cell < CELL_COUNT);
+    uint32_t color = 0xff000000u | (0x100u * DP_int_to_uint32(cell + 1))
+                   | context_id;
+    DP_Message *msg = DP_msg_fill_rect_new(
+        context_id, DP_int_to_uint16(layer_id), DP_BLEND_MODE_NORMAL,
+        DP_int_to_uint32(cell * CELL_SIZE), 0, CELL_SIZE, CELL_SIZE, color);
+    bool landed = handle(state, msg);
+    OK(landed == should_land, "%s %s", title,
+       should_land ? "lands" : "is filtered");
+    state->expected[cell] = should_land ? color : 0;
+}
+
+static void check_canvas(TEST_PARAMS, DP_AclTestState *state)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(state->ch);
+    DP_Image *img = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+    if (NOT_NULL_OK(img, "render canvas")) {
+        for (int i = 0; i < state->next_cell; ++i) {
+            uint32_t actual =
+                DP_image_pixel_at(img, i * CELL_SIZE + CELL_SIZE / 2,
+                                  CELL_SIZE / 2)
+                    .color;
+            UINT_EQ_OK(actual, state->expected[i], "cell %d on canvas", i);
+        }
+    }
+    DP_image_free(img);
+    DP_canvas_state_decref(cs);
+}
+
+
+static void acl_enforcement(TEST_PARAMS)
+{
+    DP_AclTestState state = {DP_acl_state_new(), NULL, DP_draw_context_new(),
+                             {0}, 0};
+    state.ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+
+    static const uint8_t ops[] = {OP};
+    static const uint8_t trusted[] = {TRUSTED};
+    static const uint8_t guests[] = {GUEST};
+    handle(&state, user_list(SERVER, 1, ops, DP_msg_session_owner_new));
+    handle(&state, user_list(SERVER, 1, trusted, DP_msg_trusted_users_new));
+
+    NOK(handle(&state, DP_msg_canvas_resize_new(GUEST, 0, 1000, 1000, 0)),
+        "guest resize is filtered");
+    OK(handle(&state, DP_msg_canvas_resize_new(OP, 0, CELL_SIZE * CELL_COUNT,
+                                               CELL_SIZE, 0)),
+       "operator resize goes through");
+    NOK(handle(&state, DP_msg_layer_tree_create_new(GUEST, SHARED_LAYER_ID, 0,
+                                                    0, 0, 0, "Stolen", 6)),
+        "guest can't create layers with someone else's id");
+    OK(handle(&state, DP_msg_layer_tree_create_new(OP, SHARED_LAYER_ID, 0, 0,
+                                                   0, 0, "Shared", 6)),
+       "operator creates shared layer");
+    OK(handle(&state, DP_msg_layer_tree_create_new(GUEST, GUEST_LAYER_ID, 0, 0,
+                                                   0, 0, "Guest", 5)),
+       "guest creates own layer");
+
+    draw(TEST_ARGS, &state, OP, SHARED_LAYER_ID, true, "operator stroke");
+    draw(TEST_ARGS, &state, TRUSTED, SHARED_LAYER_ID, true, "trusted stroke");
+    draw(TEST_ARGS, &state, GUEST, SHARED_LAYER_ID, true, "guest stroke");
+
+    // Exclusive access for the trusted user only, which locks out operators.
+    OK(handle(&state, layer_acl(OP, SHARED_LAYER_ID, DP_ACCESS_TIER_GUEST, 1,
+                                trusted)),
+       "operator makes layer exclusive");
+    draw(TEST_ARGS, &state, TRUSTED, SHARED_LAYER_ID, true,
+         "exclusive user stroke");
+    draw(TEST_ARGS, &state, GUEST, SHARED_LAYER_ID, false,
+         "non-exclusive guest stroke");
+    draw(TEST_ARGS, &state, OP, SHARED_LAYER_ID, false,
+         "non-exclusive operator stroke");
+
+    NOK(handle(&state, layer_acl(GUEST, SHARED_LAYER_ID, DP_ACCESS_TIER_GUEST,
+                                 0, NULL)),
+        "guest can't unlock someone else's layer");
+    OK(handle(&state, layer_acl(OP, SHARED_LAYER_ID, DP_ACCESS_TIER_TRUSTED, 0,
+                                NULL)),
+       "operator restricts layer to trusted tier");
+    draw(TEST_ARGS, &state, TRUSTED, SHARED_LAYER_ID, true,
+         "trusted tier stroke");
+    draw(TEST_ARGS, &state, GUEST, SHARED_LAYER_ID, false, "guest tier stroke");
+
+    NOK(handle(&state,
+               feature_tier(GUEST, DP_FEATURE_PUT_IMAGE, DP_ACCESS_TIER_GUEST)),
+        "guest can't change feature tiers");
+    OK(handle(&state, feature_tier(OP, DP_FEATURE_PUT_IMAGE,
+                                   DP_ACCESS_TIER_TRUSTED)),
+       "operator restricts fills to trusted tier");
+    draw(TEST_ARGS, &state, GUEST, GUEST_LAYER_ID, false,
+         "guest fill on own layer");
+
+    NOK(handle(&state, user_list(GUEST, 1, trusted, DP_msg_user_acl_new)),
+        "guest can't lock users");
+    OK(handle(&state, user_list(OP, 1, trusted, DP_msg_user_acl_new)),
+       "operator locks trusted user");
+    draw(TEST_ARGS, &state, TRUSTED, SHARED_LAYER_ID, false,
+         "locked user stroke");
+
+    OK(handle(&state, layer_acl(OP, 0, DP_ACL_ALL_LOCKED_BIT, 0, NULL)),
+       "operator locks the canvas");
+    draw(TEST_ARGS, &state, OP, GUEST_LAYER_ID, false,
+         "operator stroke on locked canvas");
+    OK(handle(&state, layer_acl(OP, 0, 0, 0, NULL)),
+       "operator unlocks the canvas");
+    OK(handle(&state, user_list(OP, 1, guests, DP_msg_user_acl_new)),
+       "operator locks guest instead");
+    draw(TEST_ARGS, &state, TRUSTED, SHARED_LAYER_ID, true,
+         "unlocked user stroke");
+    draw(TEST_ARGS, &state, OP, GUEST_LAYER_ID, true,
+         "operator stroke on unlocked canvas");
+
+    check_canvas(TEST_ARGS, &state);
+
+    DP_canvas_history_free(state.ch);
+    DP_draw_context_free(state.dc);
+    DP_acl_state_free(state.acls);
+}
+
+static DP_Message *annotation_edit(unsigned int context_id, bool protect,
+                                   const char *text)
+{
+    return DP_msg_annotation_edit_new(
+        context_id, TRUSTED_ANNOTATION_ID, 0xffffffffu,
+        protect ? DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT : 0, 0, text,
+        strlen(text));
+}
+
+static void check_annotation(TEST_PARAMS, DP_AclTestState *state,
+                             bool should_exist, int width, bool protect,
+                             const char *text, const char *title)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(state->ch);
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
+    int index = DP_annotation_list_index_by_id(al, TRUSTED_ANNOTATION_ID);
+    if (should_exist) {
+        if (OK(index != -1, "%s: annotation exists", title)) {
+            DP_Annotation *a = DP_annotation_list_at_noinc(al, index);
+            INT_EQ_OK(DP_annotation_width(a), width, "%s: width", title);
+            OK(DP_annotation_protect(a) == protect, "%s: %s", title,
+               protect ? "protected" : "not protected");
+            size_t length;
+            const char *actual = DP_annotation_text(a, &length);
+            OK(length == strlen(text) && memcmp(actual, text, length) == 0,
+               "%s: text", title);
+        }
+    }
+    else {
+        INT_EQ_OK(index, -1, "%s: annotation is gone", title);
+    }
+    DP_canvas_state_decref(cs);
+}
+
+static void acl_enforcement_protected_annotation(TEST_PARAMS)
+{
+    DP_AclTestState state = {DP_acl_state_new(), NULL, DP_draw_context_new(),
+                             {0}, 0};
+    state.ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+
+    static const uint8_t ops[] = {OP};
+    handle(&state, user_list(SERVER, 1, ops, DP_msg_session_owner_new));
+    OK(handle(&state, DP_msg_canvas_resize_new(OP, 0, 100, 100, 0)),
+       "operator resize goes through");
+
+    OK(handle(&state, DP_msg_annotation_create_new(
+                          TRUSTED, TRUSTED_ANNOTATION_ID, 0, 0, 50, 50)),
+       "user creates annotation");
+    OK(handle(&state, annotation_edit(TRUSTED, true, "rules")),
+       "owner protects annotation");
+    check_annotation(TEST_ARGS, &state, true, 50, true, "rules", "protected");
+
+    NOK(handle(&state, DP_msg_annotation_reshape_new(
+                           GUEST, TRUSTED_ANNOTATION_ID, 10, 10, 20, 20)),
+        "guest can't reshape protected annotation");
+    NOK(handle(&state, annotation_edit(GUEST, false, "vandalized")),
+        "guest can't edit or unprotect protected annotation");
+    NOK(handle(&state,
+               DP_msg_annotation_delete_new(GUEST, TRUSTED_ANNOTATION_ID)),
+        "guest can't delete protected annotation");
+    check_annotation(TEST_ARGS, &state, true, 50, true, "rules",
+                     "after guest attempts");
+
+    OK(handle(&state, DP_msg_annotation_reshape_new(
+                          OP, TRUSTED_ANNOTATION_ID, 0, 0, 60, 60)),
+       "operator reshapes protected annotation");
+    OK(handle(&state, annotation_edit(OP, true, "new rules")),
+       "operator edits protected annotation");
+    check_annotation(TEST_ARGS, &state, true, 60, true, "new rules",
+                     "after operator changes");
+
+    OK(handle(&state, annotation_edit(OP, false, "new rules")),
+       "operator unprotects annotation");
+    OK(handle(&state, DP_msg_annotation_reshape_new(
+                          GUEST, TRUSTED_ANNOTATION_ID, 0, 0, 70, 70)),
+       "guest reshapes unprotected annotation");
+    check_annotation(TEST_ARGS, &state, true, 70, false, "new rules",
+                     "unprotected");
+
+    OK(handle(&state, annotation_edit(OP, true, "new rules")),
+       "operator protects annotation again");
+    NOK(handle(&state,
+               DP_msg_annotation_delete_new(GUEST, TRUSTED_ANNOTATION_ID)),
+        "guest can't delete reprotected annotation");
+    check_annotation(TEST_ARGS, &state, true, 70, true, "new rules",
+                     "reprotected");
+    OK(handle(&state, DP_msg_annotation_delete_new(OP, TRUSTED_ANNOTATION_ID)),
+       "operator deletes protected annotation");
+    check_annotation(TEST_ARGS, &state, false, 0, false, "", "deleted");
+
+    DP_canvas_history_free(state.ch);
+    DP_draw_context_free(state.dc);
+    DP_acl_state_free(state.acls);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(acl_enforcement);
+    REGISTER_TEST(acl_enforcement_protected_annotation);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+#include <dpengine/affected_area.h>
+#include <dpengine/tile.h>
+#include <dpengine/tile_iterator.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define CANVAS_WIDTH  300
+#define CANVAS_HEIGHT 200
+#define AREA_COUNT    8
+
+static DP_AffectedArea make_area(int i)
+{
+    switch (i) {
+    case 0:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_PIXELS, 1,
+                                 DP_rect_make(0, 0, 10, 10)};
+    case 1:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_PIXELS, 1,
+                                 DP_rect_make(100, 50, 80, 120)};
+    case 2:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_PIXELS, 2,
+                                 DP_rect_make(-20, 150, 400, 100)};
+    case 3:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_PIXELS, 2,
+                                 DP_rect_make(500, 500, 10, 10)};
+    case 4:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_LAYER_ATTRS, 1,
+                                 DP_rect_make(0, 0, 0, 0)};
+    case 5:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_USER_ATTRS, 0,
+                                 DP_rect_make(0, 0, 0, 0)};
+    case 6:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_CANVAS_BACKGROUND, 0,
+                                 DP_rect_make(0, 0, 0, 0)};
+    default:
+        return (DP_AffectedArea){DP_AFFECTED_DOMAIN_EVERYTHING, 0,
+                                 DP_rect_make(0, 0, 0, 0)};
+    }
+}
+
+static bool areas_equal(const DP_AffectedArea *a, const DP_AffectedArea *b)
+{
+    if (a->domain != b->domain || a->affected_id != b->affected_id) {
+        return false;
+    }
+    else if (a->domain == DP_AFFECTED_DOMAIN_PIXELS) {
+        return a->bounds.x1 == b->bounds.x1 && a->bounds.y1 == b->bounds.y1
+            && a->bounds.x2 == b->bounds.x2 && a->bounds.y2 == b->bounds.y2;
+    }
+    else {
+        return true;
+    }
+}
+
+static bool bounds_are_canvas(const DP_AffectedArea *aa, int width, int height)
+{
+    DP_Rect bounds;
+    return DP_affected_area_bounds(aa, width, height, &bounds)
+        && bounds.x1 == 0 && bounds.y1 == 0 && bounds.x2 == width - 1
+        && bounds.y2 == height - 1;
+}
+
+static int count_tiles(const DP_AffectedArea *aa, int width, int height)
+{
+    DP_TileIterator ti = DP_affected_area_tile_iterator_make(aa, width, height);
+    int count = 0;
+    while (DP_tile_iterator_next(&ti)) {
+        ++count;
+    }
+    return count;
+}
+
+
+static void affected_area_merge_commutative(TEST_PARAMS)
+{
+    for (int i = 0; i < AREA_COUNT; ++i) {
+        for (int j = 0; j < AREA_COUNT; ++j) {
+            DP_AffectedArea a = make_area(i);
+            DP_AffectedArea b = make_area(j);
+            DP_AffectedArea ab = DP_affected_area_merge(&a, &b);
+            DP_AffectedArea ba = DP_affected_area_merge(&b, &a);
+            OK(areas_equal(&ab, &ba), "merging %d with %d is commutative", i,
+               j);
+        }
+    }
+}
+
+static void affected_area_merge_different_domains(TEST_PARAMS)
+{
+    DP_AffectedArea pixels = make_area(0);
+    DP_AffectedArea layer_attrs = make_area(4);
+    DP_AffectedArea merged = DP_affected_area_merge(&pixels, &layer_attrs);
+    INT_EQ_OK(merged.domain, DP_AFFECTED_DOMAIN_EVERYTHING,
+              "merging different domains affects everything");
+}
+
+static void affected_area_bounds_cover_tiles(TEST_PARAMS)
+{
+    for (int i = 0; i < AREA_COUNT; ++i) {
+        for (int j = 0; j < AREA_COUNT; ++j) {
+            DP_AffectedArea a = make_area(i);
+            DP_AffectedArea b = make_area(j);
+            DP_AffectedArea aa = DP_affected_area_merge(&a, &b);
+
+            DP_Rect bounds;
+            bool have_bounds = DP_affected_area_bounds(&aa, CANVAS_WIDTH,
+                                                       CANVAS_HEIGHT, &bounds);
+            DP_TileIterator ti = DP_affected_area_tile_iterator_make(
+                &aa, CANVAS_WIDTH, CANVAS_HEIGHT);
+            int count = 0;
+            while (DP_tile_iterator_next(&ti)) {
+                ++count;
+                DP_Rect tile_rect =
+                    DP_rect_make(ti.col * DP_TILE_SIZE, ti.row * DP_TILE_SIZE,
+                                 DP_TILE_SIZE, DP_TILE_SIZE);
+                OK(have_bounds && DP_rect_intersects(bounds, tile_rect),
+                   "bounds of %d merged with %d cover tile (%d, %d)", i, j,
+                   ti.col, ti.row);
+            }
+
+            if (have_bounds) {
+                OK(count > 0, "bounds of %d merged with %d have tiles", i, j);
+            }
+        }
+    }
+}
+
+
+static void affected_area_layer_attrs_cover_canvas(TEST_PARAMS)
+{
+    DP_AffectedArea layer_attrs = make_area(4);
+    OK(bounds_are_canvas(&layer_attrs, CANVAS_WIDTH, CANVAS_HEIGHT),
+       "layer attributes cover the whole canvas");
+    INT_EQ_OK(count_tiles(&layer_attrs, CANVAS_WIDTH, CANVAS_HEIGHT),
+              DP_tile_total_round(CANVAS_WIDTH, CANVAS_HEIGHT),
+              "layer attributes touch every tile");
+
+    DP_AffectedArea user_attrs = make_area(5);
+    DP_Rect bounds;
+    NOK(DP_affected_area_bounds(&user_attrs, CANVAS_WIDTH, CANVAS_HEIGHT,
+                                &bounds),
+        "user attributes don't touch any pixels");
+}
+
+static void affected_area_resize_affects_everything(TEST_PARAMS)
+{
+    DP_AffectedIndirectAreas aia;
+    DP_affected_indirect_areas_clear(&aia);
+    DP_Message *msg = DP_msg_canvas_resize_new(1, 0, 100, 50, 0);
+
+    DP_AffectedArea aa = DP_affected_area_make(msg, &aia);
+    INT_EQ_OK(aa.domain, DP_AFFECTED_DOMAIN_EVERYTHING,
+              "resize affects everything");
+    OK(bounds_are_canvas(&aa, CANVAS_WIDTH + 100, CANVAS_HEIGHT + 50),
+       "resize covers the whole resized canvas");
+
+    DP_AffectedArea visible = DP_affected_area_make_visible(msg, &aia);
+    INT_EQ_OK(visible.domain, DP_AFFECTED_DOMAIN_EVERYTHING,
+              "visible resize affects everything");
+
+    DP_AffectedArea pixels = make_area(0);
+    DP_AffectedArea merged = DP_affected_area_merge(&pixels, &aa);
+    INT_EQ_OK(merged.domain, DP_AFFECTED_DOMAIN_EVERYTHING,
+              "merging with a resize affects everything");
+
+    DP_message_decref(msg);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(affected_area_merge_commutative);
+    REGISTER_TEST(affected_area_merge_different_domains);
+    REGISTER_TEST(affected_area_bounds_cover_tiles);
+    REGISTER_TEST(affected_area_layer_attrs_cover_canvas);
+    REGISTER_TEST(affected_area_resize_affects_everything);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
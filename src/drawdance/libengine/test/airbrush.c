@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  32
+#define MAX_MESSAGES 64
+#define MAX_DABS     256
+
+typedef struct DP_AirbrushTest {
+    int message_count;
+    DP_Message *msgs[MAX_MESSAGES];
+    int dab_count;
+    uint8_t opacities[MAX_DABS];
+} DP_AirbrushTest;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_AirbrushTest *at = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_CLASSIC) {
+        int count;
+        const DP_ClassicDab *dabs =
+            DP_msg_draw_dabs_classic_dabs(DP_message_internal(msg), &count);
+        for (int i = 0; i < count && at->dab_count < MAX_DABS; ++i) {
+            at->opacities[at->dab_count++] =
+                DP_classic_dab_opacity(DP_classic_dab_at(dabs, i));
+        }
+    }
+    if (at->message_count < MAX_MESSAGES) {
+        at->msgs[at->message_count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void init_brush(DP_ClassicBrush *cb, float rate, float flow)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 8.0f;
+    cb->size.max = 8.0f;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 0.1f;
+    cb->spacing = 0.25f;
+    cb->airbrush_rate = rate;
+    cb->airbrush_flow = flow;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+// Puts the brush down in the middle of the canvas and holds it there, polling
+// the engine at the given times. Polling after the stroke ended is allowed.
+static void hold_brush(const DP_ClassicBrush *cb, const long long *polls,
+                       int poll_count, long long end_msec,
+                       DP_AirbrushTest *at)
+{
+    at->message_count = 0;
+    at->dab_count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, at);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    float mid = CANVAS_SIZE / 2.0f;
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){mid, mid, 1.0f, 0.0f, 0.0f, 0.0f, 0}, NULL);
+    bool ended = false;
+    for (int i = 0; i < poll_count; ++i) {
+        if (!ended && polls[i] > end_msec) {
+            DP_brush_engine_stroke_end(be, end_msec, NULL, true);
+            ended = true;
+        }
+        DP_brush_engine_poll(be, polls[i], NULL);
+    }
+    if (!ended) {
+        DP_brush_engine_stroke_end(be, end_msec, NULL, true);
+    }
+    DP_brush_engine_free(be);
+}
+
+// Draws the collected messages onto an empty canvas and returns the highest
+// alpha value on it, which is in the middle of the dabs.
+static double draw_max_alpha(TEST_PARAMS, DP_DrawContext *dc,
+                             DP_AirbrushTest *at)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    for (int i = 0; i < at->message_count; ++i) {
+        handle(TEST_ARGS, ch, dc, at->msgs[i]);
+    }
+    at->message_count = 0;
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    uint16_t max_alpha = 0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            uint16_t a = DP_layer_content_pixel_at(lc, x, y).a;
+            if (a > max_alpha) {
+                max_alpha = a;
+            }
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    return max_alpha / (double)DP_BIT15;
+}
+
+static void free_messages(DP_AirbrushTest *at)
+{
+    for (int i = 0; i < at->message_count; ++i) {
+        DP_message_decref(at->msgs[i]);
+    }
+    at->message_count = 0;
+}
+
+
+static void airbrush_off_holds_still(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, 0.0f, 1.0f);
+    long long polls[] = {250, 500, 750, 1000};
+    DP_AirbrushTest at;
+    hold_brush(&cb, polls, DP_ARRAY_LENGTH(polls), 1000, &at);
+    INT_EQ_OK(at.dab_count, 1, "holding still without airbrush makes one dab");
+    free_messages(&at);
+}
+
+static void airbrush_accumulates(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    init_brush(&cb, 50.0f, 0.5f);
+    // Irregular polling, including the same time twice, mustn't matter.
+    long long polls[] = {15, 30, 30, 333, 334, 700, 1000};
+    DP_AirbrushTest at;
+    hold_brush(&cb, polls, DP_ARRAY_LENGTH(polls), 1000, &at);
+
+    INT_EQ_OK(at.dab_count, 51,
+              "one second at 50 dabs per second makes 50 dabs after the first");
+    bool flow_ok = at.dab_count > 0;
+    double expected = 1.0;
+    for (int i = 0; i < at.dab_count; ++i) {
+        // 10% opacity, so 26. Airbrush dabs get half of that for the flow.
+        if (at.opacities[i] != (i == 0 ? 26 : 13)) {
+            flow_ok = false;
+        }
+        expected *= 1.0 - at.opacities[i] / 255.0;
+    }
+    expected = 1.0 - expected;
+    OK(flow_ok, "airbrush dabs have their opacity scaled by the flow");
+
+    double alpha = draw_max_alpha(TEST_ARGS, dc, &at);
+    OK(alpha > expected - 0.03 && alpha < expected + 0.03,
+       "accumulated alpha %g is about %g", alpha, expected);
+    OK(alpha > 0.5, "holding the airbrush builds up paint");
+    DP_draw_context_free(dc);
+}
+
+static void airbrush_stops_with_stroke(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, 50.0f, 1.0f);
+    long long polls[] = {100, 200, 300, 1000};
+    DP_AirbrushTest at;
+    hold_brush(&cb, polls, DP_ARRAY_LENGTH(polls), 200, &at);
+    INT_EQ_OK(at.dab_count, 11, "no more dabs after the stroke ends");
+    free_messages(&at);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(airbrush_off_holds_still);
+    REGISTER_TEST(airbrush_accumulates);
+    REGISTER_TEST(airbrush_stops_with_stroke);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
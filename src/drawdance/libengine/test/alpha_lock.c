@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_props.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID 257
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+static void draw_pixel(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                       int x, int y, uint32_t color, int blend_mode)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_pixel_square_new(1, LAYER_ID, x, y, color,
+                                             (uint8_t)blend_mode, set_pixel_dab,
+                                             1, NULL));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+}
+
+static DP_Pixel15 pixel_at(DP_CanvasHistory *ch, int x, int y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_Pixel15 pixel =
+        DP_layer_content_pixel_at(DP_layer_routes_entry_content(lre, cs), x, y);
+    DP_canvas_state_decref(cs);
+    return pixel;
+}
+
+static bool layer_alpha_locked(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    bool alpha_lock =
+        DP_layer_props_alpha_lock(DP_layer_routes_entry_props(lre, cs));
+    DP_canvas_state_decref(cs);
+    return alpha_lock;
+}
+
+static DP_CanvasHistory *make_locked_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, 64, 64, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    draw_pixel(TEST_ARGS, ch, dc, 10, 10, 0xffff0000, DP_BLEND_MODE_NORMAL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(
+               1, LAYER_ID, 0, DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK, 255,
+               DP_BLEND_MODE_NORMAL));
+    return ch;
+}
+
+
+static void alpha_lock_set_by_attributes(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_locked_history(TEST_ARGS, dc);
+    OK(layer_alpha_locked(ch), "layer is alpha locked");
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(1, LAYER_ID, 0, 0, 255,
+                                       DP_BLEND_MODE_NORMAL));
+    NOK(layer_alpha_locked(ch), "layer is no longer alpha locked");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void alpha_lock_erase_is_no_op(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_locked_history(TEST_ARGS, dc);
+    draw_pixel(TEST_ARGS, ch, dc, 10, 10, 0xff000000, DP_BLEND_MODE_ERASE);
+    DP_Pixel15 pixel = pixel_at(ch, 10, 10);
+    UINT_EQ_OK(pixel.a, DP_BIT15, "erased pixel keeps its alpha");
+    UINT_EQ_OK(pixel.r, DP_BIT15, "erased pixel keeps its color");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void alpha_lock_paint_recolors(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_locked_history(TEST_ARGS, dc);
+    draw_pixel(TEST_ARGS, ch, dc, 10, 10, 0xff0000ff, DP_BLEND_MODE_NORMAL);
+    draw_pixel(TEST_ARGS, ch, dc, 20, 20, 0xff0000ff, DP_BLEND_MODE_NORMAL);
+
+    DP_Pixel15 painted = pixel_at(ch, 10, 10);
+    UINT_EQ_OK(painted.a, DP_BIT15, "painted pixel keeps its alpha");
+    UINT_EQ_OK(painted.r, 0, "painted pixel lost its old color");
+    UINT_EQ_OK(painted.b, DP_BIT15, "painted pixel got the new color");
+
+    DP_Pixel15 blank = pixel_at(ch, 20, 20);
+    UINT_EQ_OK(blank.a, 0, "blank pixel stays transparent");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(alpha_lock_set_by_attributes);
+    REGISTER_TEST(alpha_lock_erase_is_no_op);
+    REGISTER_TEST(alpha_lock_paint_recolors);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
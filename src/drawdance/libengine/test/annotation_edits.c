@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/annotation.h>
+#include <dpengine/annotation_list.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define ALICE         1
+#define BOB           2
+#define ANNOTATION_ID 257
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static bool edit(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                 unsigned int context_id, uint32_t bg, const char *text)
+{
+    handle(ch, dc, DP_msg_undo_point_new(context_id));
+    return handle(ch, dc,
+                  DP_msg_annotation_edit_new(context_id, ANNOTATION_ID, bg, 0,
+                                             0, text, strlen(text)));
+}
+
+static int annotation_count(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int count =
+        DP_annotation_list_count(DP_canvas_state_annotations_noinc(cs));
+    DP_canvas_state_decref(cs);
+    return count;
+}
+
+// Returns the annotation with a reference, or NULL if it doesn't exist.
+static DP_Annotation *get_annotation(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
+    int index = DP_annotation_list_index_by_id(al, ANNOTATION_ID);
+    DP_Annotation *a = NULL;
+    if (index >= 0) {
+        a = DP_annotation_incref(DP_annotation_list_at_noinc(al, index));
+    }
+    DP_canvas_state_decref(cs);
+    return a;
+}
+
+static void check_annotation(TEST_PARAMS, DP_CanvasHistory *ch,
+                             const char *title, int x, int y, uint32_t bg,
+                             const char *text)
+{
+    DP_Annotation *a = get_annotation(ch);
+    if (NOT_NULL_OK(a, "%s: annotation exists", title)) {
+        INT_EQ_OK(DP_annotation_x(a), x, "%s: x", title);
+        INT_EQ_OK(DP_annotation_y(a), y, "%s: y", title);
+        UINT_EQ_OK(DP_annotation_background_color(a), bg, "%s: background",
+                   title);
+        size_t length;
+        const char *actual = DP_annotation_text(a, &length);
+        OK(length == strlen(text) && memcmp(actual, text, length) == 0,
+           "%s: text is '%s'", title, text);
+        DP_annotation_decref(a);
+    }
+}
+
+
+static void annotation_edits_and_undone_delete(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(ch, dc, DP_msg_canvas_resize_new(ALICE, 0, 100, 100, 0));
+
+    handle(ch, dc, DP_msg_undo_point_new(ALICE));
+    OK(handle(ch, dc,
+              DP_msg_annotation_create_new(ALICE, ANNOTATION_ID, 10, 10, 50,
+                                           20)),
+       "create annotation");
+
+    handle(ch, dc, DP_msg_undo_point_new(BOB));
+    OK(handle(ch, dc,
+              DP_msg_annotation_reshape_new(BOB, ANNOTATION_ID, 30, 40, 50,
+                                            20)),
+       "move annotation");
+
+    // Edits to the same annotation don't merge, whichever one comes later in
+    // the message stream replaces the earlier one wholesale.
+    OK(edit(ch, dc, ALICE, 0xffff0000u, "alice"), "alice edits annotation");
+    OK(edit(ch, dc, BOB, 0xff0000ffu, "bob"), "bob edits annotation");
+    check_annotation(TEST_ARGS, ch, "after edits", 30, 40, 0xff0000ffu, "bob");
+
+    handle(ch, dc, DP_msg_undo_point_new(ALICE));
+    OK(handle(ch, dc, DP_msg_annotation_delete_new(ALICE, ANNOTATION_ID)),
+       "delete annotation");
+    INT_EQ_OK(annotation_count(ch), 0, "annotation is gone");
+
+    OK(handle(ch, dc, DP_msg_undo_new(ALICE, 0, false)), "undo delete");
+    INT_EQ_OK(annotation_count(ch), 1, "annotation is back");
+    check_annotation(TEST_ARGS, ch, "after undo", 30, 40, 0xff0000ffu, "bob");
+
+    // Undoing Bob's edit brings back Alice's, since it came before it.
+    OK(handle(ch, dc, DP_msg_undo_new(BOB, 0, false)), "undo bob's edit");
+    check_annotation(TEST_ARGS, ch, "after undoing bob", 30, 40, 0xffff0000u,
+                     "alice");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(annotation_edits_and_undone_delete);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,185 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/annotation.h>
+#include <dpengine/annotation_list.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define USER 1
+#define A    0x101
+#define B    0x102
+#define C    0x103
+#define FLAT 0x104
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void create(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   int id, int x, int y, int width, int height)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_create_new(USER, DP_int_to_uint16(id), x, y,
+                                        DP_int_to_uint16(width),
+                                        DP_int_to_uint16(height)));
+}
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    return DP_draw_context_pool_require(user, length);
+}
+
+// Sends the order message through serialization and back, the way it would
+// arrive from another client.
+static void order(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                  int id, int direction)
+{
+    DP_Message *msg = DP_msg_annotation_order_new(USER, DP_int_to_uint16(id),
+                                                  DP_int_to_uint8(direction));
+    size_t length = DP_message_serialize(msg, true, get_buffer, dc);
+    DP_Message *received =
+        length == 0 ? NULL
+                    : DP_message_deserialize(DP_draw_context_pool(dc), length,
+                                             true);
+    if (NOT_NULL_OK(received, "order message round trip")) {
+        OK(DP_message_equals(msg, received), "order message equal");
+        handle(TEST_ARGS, ch, dc, received);
+    }
+    DP_message_decref(msg);
+}
+
+static int id_at(DP_CanvasHistory *ch, int x, int y, int expand)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
+    int index = DP_annotation_list_index_at(al, x, y, expand);
+    int id = index == -1
+               ? 0
+               : DP_annotation_id(DP_annotation_list_at_noinc(al, index));
+    DP_canvas_state_decref(cs);
+    return id;
+}
+
+static void check_order(TEST_PARAMS, DP_CanvasHistory *ch, int first,
+                        int second, int third, const char *title)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(cs);
+    int expected[] = {first, second, third};
+    if (INT_EQ_OK(DP_annotation_list_count(al), 3, "%s: count", title)) {
+        for (int i = 0; i < 3; ++i) {
+            INT_EQ_OK(DP_annotation_id(DP_annotation_list_at_noinc(al, i)),
+                      expected[i], "%s: annotation %d", title, i);
+        }
+    }
+    DP_canvas_state_decref(cs);
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(USER, 0, 100, 100, 0));
+    create(TEST_ARGS, ch, dc, A, 0, 0, 40, 40);
+    create(TEST_ARGS, ch, dc, B, 20, 20, 40, 40);
+    create(TEST_ARGS, ch, dc, C, 40, 0, 20, 20);
+    return ch;
+}
+
+
+static void annotation_hit_test(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    create(TEST_ARGS, ch, dc, FLAT, 80, 80, 0, 10);
+
+    INT_EQ_OK(id_at(ch, 5, 5, 0), A, "inside only the first annotation");
+    INT_EQ_OK(id_at(ch, 30, 30, 0), B, "overlap hits the topmost annotation");
+    INT_EQ_OK(id_at(ch, 90, 10, 0), 0, "nothing outside of annotations");
+    INT_EQ_OK(id_at(ch, 39, 10, 0), A, "last column is inside");
+    INT_EQ_OK(id_at(ch, 40, 10, 0), C,
+              "shared edge belongs to the annotation on the right");
+    INT_EQ_OK(id_at(ch, 50, 20, 0), B,
+              "shared edge belongs to the annotation below");
+    INT_EQ_OK(id_at(ch, 80, 85, 0), 0, "zero-width annotation isn't hit");
+    INT_EQ_OK(id_at(ch, 80, 85, 2), FLAT,
+              "zero-width annotation is hit when expanded");
+    INT_EQ_OK(id_at(ch, 62, 30, 0), 0, "point beside annotation misses");
+    INT_EQ_OK(id_at(ch, 62, 30, 3), B, "expand grows the hit area");
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_reshape_new(USER, B, 60, 60, 10, 10));
+    INT_EQ_OK(id_at(ch, 30, 30, 0), A,
+              "reshaped annotation no longer covers its old area");
+    INT_EQ_OK(id_at(ch, 65, 65, 0), B,
+              "reshaped annotation is hit at its new position");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void annotation_order_messages(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    check_order(TEST_ARGS, ch, A, B, C, "creation order");
+
+    order(TEST_ARGS, ch, dc, A, DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE);
+    check_order(TEST_ARGS, ch, B, A, C, "raise");
+    INT_EQ_OK(id_at(ch, 30, 30, 0), A, "raised annotation is hit on overlap");
+
+    order(TEST_ARGS, ch, dc, A, DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT);
+    check_order(TEST_ARGS, ch, B, C, A, "to front");
+
+    order(TEST_ARGS, ch, dc, A, DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE);
+    check_order(TEST_ARGS, ch, B, C, A, "raise at the top does nothing");
+
+    order(TEST_ARGS, ch, dc, C, DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK);
+    check_order(TEST_ARGS, ch, C, B, A, "to back");
+
+    order(TEST_ARGS, ch, dc, C, DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER);
+    check_order(TEST_ARGS, ch, C, B, A, "lower at the bottom does nothing");
+
+    order(TEST_ARGS, ch, dc, A, DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER);
+    check_order(TEST_ARGS, ch, C, A, B, "lower");
+    INT_EQ_OK(id_at(ch, 30, 30, 0), B, "lowered annotation is covered");
+
+    DP_Message *msg = DP_msg_annotation_order_new(USER, 0x1ff, 0);
+    NOK(DP_canvas_history_handle(ch, dc, msg), "ordering unknown id fails");
+    DP_message_decref(msg);
+    msg = DP_msg_annotation_order_new(USER, A, 99);
+    NOK(DP_canvas_history_handle(ch, dc, msg), "unknown direction fails");
+    DP_message_decref(msg);
+    check_order(TEST_ARGS, ch, C, A, B, "after failed orders");
+
+    handle(TEST_ARGS, ch, dc, DP_msg_undo_point_new(USER));
+    order(TEST_ARGS, ch, dc, C, DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT);
+    check_order(TEST_ARGS, ch, A, B, C, "to front before undo");
+    handle(TEST_ARGS, ch, dc, DP_msg_undo_new(USER, 0, false));
+    check_order(TEST_ARGS, ch, C, A, B, "undone");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(annotation_hit_test);
+    REGISTER_TEST(annotation_order_messages);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
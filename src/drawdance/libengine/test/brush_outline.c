@@ -0,0 +1,214 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/paint.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  64
+#define MAX_MESSAGES 16
+
+typedef struct DP_OutlineTestMessages {
+    int count;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_OutlineTestMessages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_OutlineTestMessages *otms = user;
+    if (otms->count < MAX_MESSAGES) {
+        otms->msgs[otms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void init_brush(DP_ClassicBrush *cb, DP_BrushShape shape, float size,
+                       float hardness)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = size;
+    cb->size.max = size;
+    cb->hardness.max = hardness;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = shape;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+// Puts down a single dab through the brush engine and returns the smallest
+// rectangle around the pixels it touched, or an invalid one if it didn't.
+static DP_Rect draw_dab_bounds(TEST_PARAMS, DP_DrawContext *dc,
+                               const DP_ClassicBrush *cb, float x, float y,
+                               float pressure)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+
+    DP_OutlineTestMessages otms = {0, {0}};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, &otms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){x, y, pressure, 0.0f, 0.0f, 0.0f, 0}, NULL);
+    DP_brush_engine_stroke_end(be, 10, NULL, true);
+    DP_brush_engine_free(be);
+    for (int i = 0; i < otms.count; ++i) {
+        handle(TEST_ARGS, ch, dc, otms.msgs[i]);
+    }
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    DP_Rect bounds = DP_rect_make(0, 0, 0, 0);
+    bool empty = true;
+    for (int py = 0; py < CANVAS_SIZE; ++py) {
+        for (int px = 0; px < CANVAS_SIZE; ++px) {
+            if (DP_layer_content_pixel_at(lc, px, py).a != 0) {
+                if (empty) {
+                    bounds = (DP_Rect){px, py, px, py};
+                    empty = false;
+                }
+                else {
+                    bounds.x1 = DP_min_int(bounds.x1, px);
+                    bounds.y1 = DP_min_int(bounds.y1, py);
+                    bounds.x2 = DP_max_int(bounds.x2, px);
+                    bounds.y2 = DP_max_int(bounds.y2, py);
+                }
+            }
+        }
+    }
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    return bounds;
+}
+
+static void check_outline(TEST_PARAMS, DP_DrawContext *dc,
+                          const DP_ClassicBrush *cb, float x, float y,
+                          float pressure)
+{
+    DP_Rect expected = draw_dab_bounds(TEST_ARGS, dc, cb, x, y, pressure);
+    DP_BrushOutline bo = DP_paint_classic_brush_outline(dc, cb, x, y, pressure);
+    DP_Rect actual = bo.bounds;
+    if (DP_rect_valid(expected)) {
+        OK(DP_rect_valid(actual)
+               && (actual.x1 == expected.x1 && actual.y1 == expected.y1
+                   && actual.x2 == expected.x2 && actual.y2 == expected.y2),
+           "shape %d size %.1f hardness %.2f at %.2f,%.2f pressure %.2f: "
+           "outline %d,%d to %d,%d matches dab %d,%d to %d,%d",
+           (int)cb->shape, (double)cb->size.max, (double)cb->hardness.max,
+           (double)x, (double)y, (double)pressure, actual.x1, actual.y1,
+           actual.x2, actual.y2, expected.x1, expected.y1, expected.x2,
+           expected.y2);
+    }
+    else {
+        OK(!DP_rect_valid(actual) && bo.mask.diameter == 0,
+           "shape %d size %.1f pressure %.2f: no dab, no outline",
+           (int)cb->shape, (double)cb->size.max, (double)pressure);
+    }
+}
+
+
+static void outline_soft_round_matches_dab(TEST_PARAMS)
+{
+    static const float sizes[] = {1.0f, 3.5f, 7.0f, 12.0f, 25.0f};
+    static const float hardnesses[] = {0.0f, 0.4f, 0.8f, 1.0f};
+    DP_DrawContext *dc = DP_draw_context_new();
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(sizes); ++i) {
+        for (int j = 0; j < (int)DP_ARRAY_LENGTH(hardnesses); ++j) {
+            DP_ClassicBrush cb;
+            init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, sizes[i],
+                       hardnesses[j]);
+            check_outline(TEST_ARGS, dc, &cb, 32.0f, 32.0f, 1.0f);
+            check_outline(TEST_ARGS, dc, &cb, 31.3f, 32.8f, 1.0f);
+        }
+    }
+    DP_draw_context_free(dc);
+}
+
+static void outline_follows_pressure(TEST_PARAMS)
+{
+    static const float pressures[] = {0.1f, 0.33f, 0.5f, 0.9f};
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 30.0f, 0.6f);
+    cb.size.min = 2.0f;
+    cb.size_dynamic.type = DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
+    cb.hardness.min = 0.1f;
+    cb.hardness_dynamic.type = DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(pressures); ++i) {
+        check_outline(TEST_ARGS, dc, &cb, 30.6f, 33.1f, pressures[i]);
+    }
+    DP_draw_context_free(dc);
+}
+
+static void outline_pixel_matches_dab(TEST_PARAMS)
+{
+    static const float sizes[] = {1.0f, 2.0f, 5.0f, 8.0f, 17.0f};
+    DP_DrawContext *dc = DP_draw_context_new();
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(sizes); ++i) {
+        DP_ClassicBrush cb;
+        init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, sizes[i], 1.0f);
+        check_outline(TEST_ARGS, dc, &cb, 32.0f, 32.0f, 1.0f);
+        check_outline(TEST_ARGS, dc, &cb, 30.7f, 33.2f, 1.0f);
+        init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE, sizes[i], 1.0f);
+        check_outline(TEST_ARGS, dc, &cb, 32.0f, 32.0f, 1.0f);
+        check_outline(TEST_ARGS, dc, &cb, 30.7f, 33.2f, 1.0f);
+    }
+    DP_draw_context_free(dc);
+}
+
+static void outline_empty_without_dab(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 0.05f, 1.0f);
+    check_outline(TEST_ARGS, dc, &cb, 32.0f, 32.0f, 1.0f);
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 0.0f, 1.0f);
+    check_outline(TEST_ARGS, dc, &cb, 32.0f, 32.0f, 1.0f);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(outline_soft_round_matches_dab);
+    REGISTER_TEST(outline_follows_pressure);
+    REGISTER_TEST(outline_pixel_matches_dab);
+    REGISTER_TEST(outline_empty_without_dab);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
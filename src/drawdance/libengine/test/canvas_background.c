@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/compress.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID 257
+#define USER     1
+#define RED      0xffff0000u
+#define GREEN    0xff00ff00u
+#define BLUE     0xff0000ffu
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_Message *solid_background(uint32_t color)
+{
+    return DP_msg_canvas_background_new(USER, set_color, 4, &color);
+}
+
+static void set_bytes(size_t size, unsigned char *out, void *user)
+{
+    memcpy(out, user, size);
+}
+
+static unsigned char *get_buffer(size_t size, void *user)
+{
+    unsigned char **buffer = user;
+    *buffer = DP_malloc(size);
+    return *buffer;
+}
+
+// Left half of the tile in one color, right half in the other.
+static DP_Message *split_background(uint32_t left, uint32_t right)
+{
+    DP_Pixel8 pixels[DP_TILE_LENGTH];
+    for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+        pixels[i].color = i % DP_TILE_SIZE < DP_TILE_SIZE / 2 ? left : right;
+    }
+    unsigned char *buffer = NULL;
+    size_t size = DP_compress_deflate((const unsigned char *)pixels,
+                                      sizeof(pixels), get_buffer, &buffer);
+    DP_Message *msg =
+        size == 0 ? NULL
+                  : DP_msg_canvas_background_new(USER, set_bytes, size, buffer);
+    DP_free(buffer);
+    return msg;
+}
+
+static void set_up_canvas(TEST_PARAMS, DP_CanvasHistory *ch,
+                          DP_DrawContext *dc)
+{
+    OK(handle(ch, dc, DP_msg_canvas_resize_new(USER, 0, 64, 64, 0)),
+       "resize canvas");
+    OK(handle(ch, dc,
+              DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0,
+                                           "Layer", 5)),
+       "create layer");
+}
+
+static uint32_t flat_pixel_at(DP_CanvasHistory *ch, int x, int y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_Image *img = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+    uint32_t color = img ? DP_image_pixel_at(img, x, y).color : 0;
+    DP_image_free(img);
+    DP_canvas_state_decref(cs);
+    return color;
+}
+
+
+// Points in the left and right half of the background tile.
+#define LEFT_X  (DP_TILE_SIZE / 4)
+#define RIGHT_X (DP_TILE_SIZE * 3 / 4)
+
+static void background_change_is_undoable(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    set_up_canvas(TEST_ARGS, ch, dc);
+
+    handle(ch, dc, DP_msg_undo_point_new(USER));
+    OK(handle(ch, dc, solid_background(RED)), "set red background");
+    UINT_EQ_OK(flat_pixel_at(ch, 50, 50), RED, "background is red");
+
+    handle(ch, dc, DP_msg_undo_point_new(USER));
+    OK(handle(ch, dc,
+              DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                   16, 16, GREEN)),
+       "draw on layer");
+
+    handle(ch, dc, DP_msg_undo_point_new(USER));
+    OK(handle(ch, dc, split_background(BLUE, RED)), "set tile background");
+    UINT_EQ_OK(flat_pixel_at(ch, 8, 8), GREEN, "drawing is on top");
+    UINT_EQ_OK(flat_pixel_at(ch, LEFT_X, 50), BLUE,
+               "tile background left half");
+    UINT_EQ_OK(flat_pixel_at(ch, RIGHT_X, 50), RED,
+               "tile background right half");
+
+    OK(handle(ch, dc, DP_msg_undo_new(USER, 0, false)),
+       "undo background change");
+    UINT_EQ_OK(flat_pixel_at(ch, 8, 8), GREEN, "drawing is still there");
+    UINT_EQ_OK(flat_pixel_at(ch, LEFT_X, 50), RED, "background is red again");
+
+    OK(handle(ch, dc, DP_msg_undo_new(USER, 0, true)),
+       "redo background change");
+    UINT_EQ_OK(flat_pixel_at(ch, LEFT_X, 50), BLUE, "tile background is back");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void bad_background_is_rejected(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    set_up_canvas(TEST_ARGS, ch, dc);
+    handle(ch, dc, solid_background(RED));
+
+    unsigned char garbage[100];
+    memset(garbage, 0xaa, sizeof(garbage));
+    NOK(handle(ch, dc,
+               DP_msg_canvas_background_new(USER, set_bytes, sizeof(garbage),
+                                            garbage)),
+        "garbage background rejected");
+
+    // A compressed buffer that inflates to something other than a tile.
+    unsigned char *buffer = NULL;
+    size_t size =
+        DP_compress_deflate(garbage, sizeof(garbage), get_buffer, &buffer);
+    NOK(handle(ch, dc,
+               DP_msg_canvas_background_new(USER, set_bytes, size, buffer)),
+        "background of the wrong size rejected");
+    DP_free(buffer);
+
+    UINT_EQ_OK(flat_pixel_at(ch, 50, 50), RED, "background is unchanged");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(background_change_is_undoable);
+    REGISTER_TEST(bad_background_is_rejected);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_compare.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+static DP_CanvasHistory *make_layered_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, 256, 256, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, 257, 0, 0, 0, 0, "Layer 1", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, 258, 0, 0, 0, 0, "Layer 2", 7));
+    return ch;
+}
+
+
+static void canvas_compare_same_state(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_layered_history(TEST_ARGS, dc);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+
+    DP_CanvasCompare *cc = DP_canvas_compare_new(cs, cs);
+    UINT_EQ_OK(DP_canvas_compare_flags(cc), 0u, "nothing changed");
+    INT_EQ_OK(DP_canvas_compare_layer_count(cc), 0, "no layers changed");
+    DP_Rect bounds;
+    NOK(DP_canvas_compare_bounds(cc, &bounds), "no bounds");
+    DP_canvas_compare_free(cc);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void canvas_compare_single_dab(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_layered_history(TEST_ARGS, dc);
+    DP_CanvasState *prev = DP_canvas_history_get(ch);
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_pixel_square_new(
+               1, 258, DP_TILE_SIZE + 6, DP_TILE_SIZE * 2 + 2, 0xff000000,
+               DP_BLEND_MODE_NORMAL, set_pixel_dab, 1, NULL));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+
+    DP_CanvasCompare *cc = DP_canvas_compare_new(cs, prev);
+    UINT_EQ_OK(DP_canvas_compare_flags(cc), DP_CANVAS_COMPARE_LAYERS,
+               "only layers changed");
+    if (INT_EQ_OK(DP_canvas_compare_layer_count(cc), 1,
+                  "one layer changed")) {
+        const DP_LayerCompare *lc = DP_canvas_compare_layer_at(cc, 0);
+        INT_EQ_OK(lc->layer_id, 258, "changed layer is the drawn one");
+        UINT_EQ_OK(lc->flags, DP_LAYER_COMPARE_CONTENT,
+                   "only layer content changed");
+        INT_EQ_OK(lc->changed_tile_count, 1, "one tile changed");
+        int xtiles = DP_canvas_compare_xtiles(cc);
+        int ytiles = DP_canvas_compare_ytiles(cc);
+        for (int y = 0; y < ytiles; ++y) {
+            for (int x = 0; x < xtiles; ++x) {
+                bool expected = x == 1 && y == 2;
+                OK(DP_canvas_compare_layer_tile_changed(cc, 0, x, y)
+                       == expected,
+                   "tile (%d, %d) %s", x, y,
+                   expected ? "changed" : "unchanged");
+            }
+        }
+    }
+
+    DP_Rect bounds;
+    if (OK(DP_canvas_compare_bounds(cc, &bounds), "got bounds")) {
+        INT_EQ_OK(DP_rect_x(bounds), DP_TILE_SIZE, "bounds x");
+        INT_EQ_OK(DP_rect_y(bounds), DP_TILE_SIZE * 2, "bounds y");
+        INT_EQ_OK(DP_rect_width(bounds), DP_TILE_SIZE, "bounds width");
+        INT_EQ_OK(DP_rect_height(bounds), DP_TILE_SIZE, "bounds height");
+    }
+    DP_canvas_compare_free(cc);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(prev);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void check_moved(TEST_PARAMS, DP_CanvasState *cs, DP_CanvasState *prev,
+                        int layer_id, const char *what)
+{
+    DP_CanvasCompare *cc = DP_canvas_compare_new(cs, prev);
+    UINT_EQ_OK(DP_canvas_compare_flags(cc), DP_CANVAS_COMPARE_LAYERS,
+               "%s: only layers changed", what);
+    if (INT_EQ_OK(DP_canvas_compare_layer_count(cc), 1,
+                  "%s: one layer changed", what)) {
+        const DP_LayerCompare *lc = DP_canvas_compare_layer_at(cc, 0);
+        INT_EQ_OK(lc->layer_id, layer_id, "%s: changed layer is the moved one",
+                  what);
+        UINT_EQ_OK(lc->flags, DP_LAYER_COMPARE_PROPS,
+                   "%s: move is a properties change", what);
+    }
+
+    DP_Rect bounds;
+    if (OK(DP_canvas_compare_bounds(cc, &bounds), "%s: got bounds", what)) {
+        INT_EQ_OK(DP_rect_x(bounds), DP_TILE_SIZE, "%s: bounds x", what);
+        INT_EQ_OK(DP_rect_y(bounds), DP_TILE_SIZE * 2, "%s: bounds y", what);
+        INT_EQ_OK(DP_rect_width(bounds), DP_TILE_SIZE, "%s: bounds width",
+                  what);
+        INT_EQ_OK(DP_rect_height(bounds), DP_TILE_SIZE, "%s: bounds height",
+                  what);
+    }
+    DP_canvas_compare_free(cc);
+}
+
+static void canvas_compare_layer_move(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_layered_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, 259, 0, 0, 0, 0, "Layer 3", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, 260, 0, 0, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, 257, DP_BLEND_MODE_NORMAL, DP_TILE_SIZE + 3,
+                                DP_TILE_SIZE * 2 + 5, 10, 10, 0xff0000ff));
+    DP_CanvasState *prev = DP_canvas_history_get(ch);
+
+    // Moving the bottom layer to the top shifts the indexes of everything
+    // else, but only the moved layer should be reported.
+    handle(TEST_ARGS, ch, dc, DP_msg_layer_tree_move_new(1, 257, 0, 0));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    check_moved(TEST_ARGS, cs, prev, 257, "reorder");
+    DP_canvas_state_decref(prev);
+    prev = cs;
+
+    handle(TEST_ARGS, ch, dc, DP_msg_layer_tree_move_new(1, 257, 260, 0));
+    cs = DP_canvas_history_get(ch);
+    check_moved(TEST_ARGS, cs, prev, 257, "move into group");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(prev);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(canvas_compare_same_state);
+    REGISTER_TEST(canvas_compare_single_dab);
+    REGISTER_TEST(canvas_compare_layer_move);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
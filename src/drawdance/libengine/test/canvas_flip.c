@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/annotation.h>
+#include <dpengine/annotation_list.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/ops.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// Neither dimension is a multiple of the tile size, so the partial tiles at
+// the right and bottom end up on the other side.
+#define WIDTH    150
+#define HEIGHT   100
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+static DP_Tile *make_background_tile(void)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            uint16_t b = DP_int_to_uint16(x * 500);
+            uint16_t g = DP_int_to_uint16(y * 500);
+            DP_transient_tile_pixel_at_set(tt, x, y,
+                                           (DP_Pixel15){b, g, 0, DP_BIT15});
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 3, 5, 70, 20, 0xffff0000);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 100, 60, 45, 37, 0x800000ff);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, WIDTH - 1, HEIGHT - 1, 1, 1,
+              0xff00ff00);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, GROUP_ID, 0, LAYER_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    fill_rect(TEST_ARGS, ch, dc, CHILD_ID, 60, 30, 9, 50, 0xff123456);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_create_new(1, 1, 10, 20, 30, 40));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_canvas_state_decref(cs);
+    DP_transient_canvas_state_background_tile_set_noinc(
+        tcs, make_background_tile(), true);
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+static DP_LayerContent *layer_content(DP_CanvasState *cs, int layer_id)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+static bool pixels_equal(DP_Pixel15 a, DP_Pixel15 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+static int count_unflipped(DP_LayerContent *lc, DP_LayerContent *original,
+                           bool horizontal)
+{
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            int src_x = horizontal ? WIDTH - 1 - x : x;
+            int src_y = horizontal ? y : HEIGHT - 1 - y;
+            if (!pixels_equal(DP_layer_content_pixel_at(lc, x, y),
+                              DP_layer_content_pixel_at(original, src_x,
+                                                        src_y))) {
+                ++wrong;
+            }
+        }
+    }
+    return wrong;
+}
+
+
+static void flip_twice(TEST_PARAMS)
+{
+    for (int i = 0; i < 2; ++i) {
+        bool horizontal = i == 0;
+        const char *name = horizontal ? "horizontal" : "vertical";
+        DP_CanvasState *original = make_canvas(TEST_ARGS);
+        DP_CanvasState *flipped = DP_ops_canvas_flip(original, 1, horizontal);
+        DP_CanvasState *cs = DP_ops_canvas_flip(flipped, 1, horizontal);
+        OK(DP_canvas_state_checksum(flipped)
+               != DP_canvas_state_checksum(original),
+           "%s flip changes the canvas", name);
+        OK(DP_canvas_state_checksum(cs) == DP_canvas_state_checksum(original),
+           "flipping %s twice gives back the original", name);
+        INT_EQ_OK(count_unflipped(layer_content(cs, LAYER_ID),
+                                  layer_content(flipped, LAYER_ID),
+                                  horizontal),
+                  0, "%s flip back is bit-exact", name);
+        DP_canvas_state_decref(cs);
+        DP_canvas_state_decref(flipped);
+        DP_canvas_state_decref(original);
+    }
+}
+
+static void flip_moves_content(TEST_PARAMS)
+{
+    for (int i = 0; i < 2; ++i) {
+        bool horizontal = i == 0;
+        const char *name = horizontal ? "horizontal" : "vertical";
+        DP_CanvasState *original = make_canvas(TEST_ARGS);
+        DP_CanvasState *cs = DP_ops_canvas_flip(original, 1, horizontal);
+        INT_EQ_OK(DP_canvas_state_width(cs), WIDTH, "%s flip keeps width",
+                  name);
+        INT_EQ_OK(DP_canvas_state_height(cs), HEIGHT, "%s flip keeps height",
+                  name);
+        INT_EQ_OK(count_unflipped(layer_content(cs, LAYER_ID),
+                                  layer_content(original, LAYER_ID),
+                                  horizontal),
+                  0, "%s flip mirrors the layer", name);
+        INT_EQ_OK(count_unflipped(layer_content(cs, CHILD_ID),
+                                  layer_content(original, CHILD_ID),
+                                  horizontal),
+                  0, "%s flip mirrors layers inside of groups", name);
+
+        DP_Annotation *a = DP_annotation_list_at_noinc(
+            DP_canvas_state_annotations_noinc(cs), 0);
+        int expected_x = horizontal ? WIDTH - 10 - 30 : 10;
+        int expected_y = horizontal ? 20 : HEIGHT - 20 - 40;
+        OK(DP_annotation_x(a) == expected_x && DP_annotation_y(a) == expected_y
+               && DP_annotation_width(a) == 30
+               && DP_annotation_height(a) == 40,
+           "%s flip mirrors the annotation", name);
+
+        DP_Tile *background = DP_canvas_state_background_tile_noinc(cs);
+        DP_Tile *original_background =
+            DP_canvas_state_background_tile_noinc(original);
+        int last = DP_TILE_SIZE - 1;
+        OK(pixels_equal(DP_tile_pixel_at(background, 3, 5),
+                        DP_tile_pixel_at(original_background,
+                                         horizontal ? last - 3 : 3,
+                                         horizontal ? 5 : last - 5)),
+           "%s flip mirrors the background tile", name);
+
+        DP_canvas_state_decref(cs);
+        DP_canvas_state_decref(original);
+    }
+}
+
+static void flip_region(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    DP_LayerContent *original = layer_content(cs, LAYER_ID);
+    DP_TransientLayerContent *tlc = DP_transient_layer_content_new(original);
+
+    // Straddles the tile boundary at x = 64 and has an odd width, so the
+    // middle column stays where it is.
+    DP_Rect rect = DP_rect_make(50, 10, 31, 60);
+    DP_Rect area =
+        DP_transient_layer_content_flip_region(tlc, 1, &rect, true);
+    OK(area.x1 == 50 && area.y1 == 10 && area.x2 == 80 && area.y2 == 69,
+       "flipped area is the rectangle");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            int src_x = DP_rect_contains(rect, x, y) ? 130 - x : x;
+            if (!pixels_equal(
+                    DP_layer_content_pixel_at((DP_LayerContent *)tlc, x, y),
+                    DP_layer_content_pixel_at(original, src_x, y))) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "only the inside of the rectangle got mirrored");
+
+    DP_transient_layer_content_flip_region(tlc, 1, &rect, true);
+    rect = DP_rect_make(120, 80, 100, 100);
+    area = DP_transient_layer_content_flip_region(tlc, 1, &rect, false);
+    OK(area.x1 == 120 && area.y1 == 80 && area.x2 == WIDTH - 1
+           && area.y2 == HEIGHT - 1,
+       "flipped area gets clipped to the layer");
+    DP_transient_layer_content_flip_region(tlc, 1, &rect, false);
+    DP_LayerContent *lc = DP_transient_layer_content_persist(tlc);
+    UINT_EQ_OK(DP_layer_content_checksum(lc),
+               DP_layer_content_checksum(original),
+               "flipping regions twice gives back the original");
+
+    rect = DP_rect_make(WIDTH, 0, 10, 10);
+    tlc = DP_transient_layer_content_new(lc);
+    area = DP_transient_layer_content_flip_region(tlc, 1, &rect, true);
+    NOK(DP_rect_valid(area), "rectangle outside of the layer flips nothing");
+
+    DP_transient_layer_content_decref(tlc);
+    DP_layer_content_decref(lc);
+    DP_canvas_state_decref(cs);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(flip_twice);
+    REGISTER_TEST(flip_moves_content);
+    REGISTER_TEST(flip_region);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
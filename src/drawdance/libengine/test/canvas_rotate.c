@@ -0,0 +1,380 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/output.h>
+#include <dpengine/annotation.h>
+#include <dpengine/annotation_list.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/ops.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+// Neither dimension is a multiple of the tile size, so pixels have to move
+// between tiles when rotating.
+#define WIDTH    150
+#define HEIGHT   100
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+// A lopsided background tile, so that rotating it is observable. The pattern
+// repeats every 32 pixels, so it looks the same with any tile size.
+static DP_Tile *make_background_tile(void)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            uint16_t value = DP_int_to_uint16(x % 32 * 600 + y % 32 * 34);
+            DP_transient_tile_pixel_at_set(
+                tt, x, y, (DP_Pixel15){value, 0, 0, DP_BIT15});
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 3, 5, 70, 20, 0xffff0000);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 100, 60, 45, 37, 0x800000ff);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 0, 0, 1, 1, 0xff00ff00);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, WIDTH - 1, 0, 1, 1, 0xff0000ff);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 0, HEIGHT - 1, 1, 1, 0xffffff00);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, GROUP_ID, 0, LAYER_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    fill_rect(TEST_ARGS, ch, dc, CHILD_ID, 60, 30, 9, 50, 0xff123456);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_create_new(1, 1, 10, 20, 30, 40));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+
+    DP_TransientCanvasState *tcs = DP_transient_canvas_state_new(cs);
+    DP_canvas_state_decref(cs);
+    DP_transient_canvas_state_background_tile_set_noinc(
+        tcs, make_background_tile(), true);
+    return DP_transient_canvas_state_persist(tcs);
+}
+
+static DP_Pixel15 pixel_at(DP_CanvasState *cs, int layer_id, int x, int y)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return DP_layer_content_pixel_at(DP_layer_routes_entry_content(lre, cs), x,
+                                     y);
+}
+
+static bool pixels_equal(DP_Pixel15 a, DP_Pixel15 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+static DP_CanvasState *rotate90(DP_CanvasState *cs, bool clockwise)
+{
+    DP_CanvasState *rotated = DP_ops_canvas_rotate90(cs, 1, clockwise);
+    DP_canvas_state_decref(cs);
+    return rotated;
+}
+
+
+static void rotate_four_times(TEST_PARAMS)
+{
+    for (int i = 0; i < 2; ++i) {
+        bool clockwise = i == 0;
+        DP_CanvasState *original = make_canvas(TEST_ARGS);
+        DP_CanvasState *cs = DP_canvas_state_incref(original);
+        for (int j = 0; j < 4; ++j) {
+            cs = rotate90(cs, clockwise);
+            bool odd = j % 2 == 0;
+            INT_EQ_OK(DP_canvas_state_width(cs), odd ? HEIGHT : WIDTH,
+                      "width after %d turns", j + 1);
+            INT_EQ_OK(DP_canvas_state_height(cs), odd ? WIDTH : HEIGHT,
+                      "height after %d turns", j + 1);
+        }
+        OK(DP_canvas_state_checksum(cs) == DP_canvas_state_checksum(original),
+           "four %s turns give back the original",
+           clockwise ? "clockwise" : "counterclockwise");
+
+        int wrong = 0;
+        for (int y = 0; y < HEIGHT; ++y) {
+            for (int x = 0; x < WIDTH; ++x) {
+                if (!pixels_equal(pixel_at(cs, LAYER_ID, x, y),
+                                  pixel_at(original, LAYER_ID, x, y))
+                    || !pixels_equal(pixel_at(cs, CHILD_ID, x, y),
+                                     pixel_at(original, CHILD_ID, x, y))) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "every pixel is back where it was");
+        DP_canvas_state_decref(cs);
+        DP_canvas_state_decref(original);
+    }
+}
+
+static void rotate_moves_content(TEST_PARAMS)
+{
+    DP_CanvasState *original = make_canvas(TEST_ARGS);
+    DP_CanvasState *cs = DP_ops_canvas_rotate90(original, 1, true);
+
+    // Clockwise, the top-left corner ends up at the top-right and so on.
+    OK(pixels_equal(pixel_at(cs, LAYER_ID, HEIGHT - 1, 0),
+                    pixel_at(original, LAYER_ID, 0, 0)),
+       "top-left corner moved to top-right");
+    OK(pixels_equal(pixel_at(cs, LAYER_ID, HEIGHT - 1, WIDTH - 1),
+                    pixel_at(original, LAYER_ID, WIDTH - 1, 0)),
+       "top-right corner moved to bottom-right");
+    OK(pixels_equal(pixel_at(cs, LAYER_ID, 0, 0),
+                    pixel_at(original, LAYER_ID, 0, HEIGHT - 1)),
+       "bottom-left corner moved to top-left");
+
+    int wrong = 0;
+    for (int y = 0; y < WIDTH; ++y) {
+        for (int x = 0; x < HEIGHT; ++x) {
+            DP_Pixel15 expected =
+                pixel_at(original, CHILD_ID, y, HEIGHT - 1 - x);
+            if (!pixels_equal(pixel_at(cs, CHILD_ID, x, y), expected)) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "layers inside of groups get rotated");
+
+    DP_Annotation *a = DP_annotation_list_at_noinc(
+        DP_canvas_state_annotations_noinc(cs), 0);
+    OK(DP_annotation_x(a) == HEIGHT - 20 - 40 && DP_annotation_y(a) == 10
+           && DP_annotation_width(a) == 40 && DP_annotation_height(a) == 30,
+       "annotation got moved and its dimensions swapped");
+
+    DP_Tile *background = DP_canvas_state_background_tile_noinc(cs);
+    DP_Tile *original_background =
+        DP_canvas_state_background_tile_noinc(original);
+    OK(pixels_equal(DP_tile_pixel_at(background, DP_TILE_SIZE - 1, 0),
+                    DP_tile_pixel_at(original_background, 0, 0))
+           && pixels_equal(DP_tile_pixel_at(background, 0, 0),
+                           DP_tile_pixel_at(original_background, 0,
+                                            DP_TILE_SIZE - 1)),
+       "background tile got rotated");
+    OK(DP_canvas_state_background_opaque(cs), "background is still opaque");
+
+    OK(DP_canvas_state_timeline_noinc(cs)
+           == DP_canvas_state_timeline_noinc(original),
+       "timeline is preserved");
+    OK(DP_canvas_state_metadata_noinc(cs)
+           == DP_canvas_state_metadata_noinc(original),
+       "metadata is preserved");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(original);
+}
+
+static void rotate_half_turn(TEST_PARAMS)
+{
+    DP_CanvasState *original = make_canvas(TEST_ARGS);
+    DP_CanvasState *half = DP_ops_canvas_rotate180(original, 1);
+    INT_EQ_OK(DP_canvas_state_width(half), WIDTH, "half turn keeps width");
+    INT_EQ_OK(DP_canvas_state_height(half), HEIGHT, "half turn keeps height");
+
+    DP_CanvasState *twice = rotate90(
+        rotate90(DP_canvas_state_incref(original), true), true);
+    OK(DP_canvas_state_checksum(half) == DP_canvas_state_checksum(twice),
+       "half turn is the same as two quarter turns");
+
+    DP_CanvasState *back = DP_ops_canvas_rotate180(half, 1);
+    OK(DP_canvas_state_checksum(back) == DP_canvas_state_checksum(original),
+       "two half turns give back the original");
+
+    DP_CanvasState *there_and_back =
+        rotate90(rotate90(DP_canvas_state_incref(original), true), false);
+    OK(DP_canvas_state_checksum(there_and_back)
+           == DP_canvas_state_checksum(original),
+       "clockwise and counterclockwise turns cancel out");
+
+    DP_canvas_state_decref(there_and_back);
+    DP_canvas_state_decref(back);
+    DP_canvas_state_decref(twice);
+    DP_canvas_state_decref(half);
+    DP_canvas_state_decref(original);
+}
+
+
+static void rotate_arbitrary_angle(TEST_PARAMS)
+{
+    static const struct {
+        const char *name;
+        double degrees;
+        int interpolation;
+        bool expand;
+        int width, height;
+    } cases[] = {
+        {"expand_bilinear", 30.0, DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, true,
+         180, 162},
+        {"expand_bicubic", 30.0, DP_MSG_TRANSFORM_REGION_MODE_BICUBIC, true,
+         180, 162},
+        {"crop_bilinear", 30.0, DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, false,
+         WIDTH, HEIGHT},
+        {"counterclockwise_bicubic", -30.0,
+         DP_MSG_TRANSFORM_REGION_MODE_BICUBIC, true, 180, 162},
+    };
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *original = make_canvas(TEST_ARGS);
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        int width, height;
+        DP_CanvasState *cs = DP_ops_canvas_rotate(
+            original, dc, 1, cases[i].degrees, cases[i].interpolation,
+            cases[i].expand, &width, &height);
+        if (!NOT_NULL_OK(cs, "rotate %s", cases[i].name)) {
+            continue;
+        }
+        INT_EQ_OK(width, cases[i].width, "%s width", cases[i].name);
+        INT_EQ_OK(height, cases[i].height, "%s height", cases[i].name);
+        INT_EQ_OK(DP_canvas_state_width(cs), width, "%s canvas width",
+                  cases[i].name);
+        INT_EQ_OK(DP_canvas_state_height(cs), height, "%s canvas height",
+                  cases[i].name);
+
+        DP_Image *img = DP_canvas_state_to_flat_image(
+            cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+        char *actual_path =
+            DP_format("test/tmp/canvas_rotate_%s.png", cases[i].name);
+        DP_Output *output = DP_file_output_new_from_path(actual_path);
+        FATAL(NOT_NULL_OK(output, "got output for %s", actual_path));
+        OK(DP_image_write_png(img, output), "write %s", actual_path);
+        DP_output_free(output);
+        DP_image_free(img);
+
+        char *expected_path =
+            DP_format("test/data/canvas_rotate/%s.png", cases[i].name);
+        IMAGE_FILE_EQ_OK(actual_path, expected_path, "%s matches expected",
+                         cases[i].name);
+        DP_free(expected_path);
+        DP_free(actual_path);
+        DP_canvas_state_decref(cs);
+    }
+    DP_canvas_state_decref(original);
+    DP_draw_context_free(dc);
+}
+
+static void rotate_arbitrary_annotations(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *original = make_canvas(TEST_ARGS);
+    DP_CanvasState *cs =
+        DP_ops_canvas_rotate(original, dc, 1, 30.0,
+                             DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, false,
+                             NULL, NULL);
+
+    // The center at 25, 40 is -50, -10 away from the canvas center, rotating
+    // that by 30 degrees clockwise puts it at -38.30, -33.66.
+    DP_Annotation *a = DP_annotation_list_at_noinc(
+        DP_canvas_state_annotations_noinc(cs), 0);
+    INT_EQ_OK(DP_annotation_x(a), 22, "annotation x follows its center");
+    INT_EQ_OK(DP_annotation_y(a), -4, "annotation y follows its center");
+    INT_EQ_OK(DP_annotation_width(a), 30, "annotation keeps its width");
+    INT_EQ_OK(DP_annotation_height(a), 40, "annotation keeps its height");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(original);
+    DP_draw_context_free(dc);
+}
+
+static void rotate_arbitrary_right_angles(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *original = make_canvas(TEST_ARGS);
+    DP_CanvasState *cs = DP_ops_canvas_rotate90(original, 1, true);
+    uint64_t clockwise = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    cs = DP_ops_canvas_rotate90(original, 1, false);
+    uint64_t counterclockwise = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+
+    int interpolation = DP_MSG_TRANSFORM_REGION_MODE_BICUBIC;
+    cs = DP_ops_canvas_rotate(original, dc, 1, 90.0, interpolation, true, NULL,
+                              NULL);
+    OK(DP_canvas_state_checksum(cs) == clockwise,
+       "90 degrees is a lossless quarter turn");
+    DP_canvas_state_decref(cs);
+
+    cs = DP_ops_canvas_rotate(original, dc, 1, -450.0, interpolation, true,
+                              NULL, NULL);
+    OK(DP_canvas_state_checksum(cs) == counterclockwise,
+       "-450 degrees is a lossless counterclockwise quarter turn");
+    DP_canvas_state_decref(cs);
+
+    cs = DP_ops_canvas_rotate(original, dc, 1, 360.0, interpolation, false,
+                              NULL, NULL);
+    OK(cs == original, "a full turn doesn't change anything");
+    DP_canvas_state_decref(cs);
+
+    int width, height;
+    cs = DP_ops_canvas_rotate(original, dc, 1, 90.0, interpolation, false,
+                              &width, &height);
+    OK(width == WIDTH && height == HEIGHT,
+       "cropped quarter turn of non-square canvas keeps the size");
+    DP_canvas_state_decref(cs);
+
+    NULL_OK(DP_ops_canvas_rotate(original, dc, 1, NAN, interpolation, true,
+                                 NULL, NULL),
+            "rotating by NaN fails");
+
+    DP_canvas_state_decref(original);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(rotate_four_times);
+    REGISTER_TEST(rotate_moves_content);
+    REGISTER_TEST(rotate_half_turn);
+    REGISTER_TEST(rotate_arbitrary_angle);
+    REGISTER_TEST(rotate_arbitrary_annotations);
+    REGISTER_TEST(rotate_arbitrary_right_angles);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
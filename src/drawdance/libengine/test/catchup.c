@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/catchup.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <limits.h>
+
+
+#define WIDTH         200
+#define HEIGHT        150
+#define LAYER_ID      257
+#define MESSAGE_COUNT 10000
+#define BATCH_SIZE    97
+
+// A session's worth of messages: a few users drawing, undoing and redoing.
+static void push_session(DP_Catchup *c)
+{
+    DP_catchup_push_noinc(c, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    DP_catchup_push_noinc(c, DP_msg_undo_depth_new(1, 10));
+    DP_catchup_push_noinc(c, DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0,
+                                                          0, "Layer", 5));
+    for (int i = 3; i < MESSAGE_COUNT; ++i) {
+        unsigned int user = DP_int_to_uint(i % 3 + 1);
+        DP_Message *msg;
+        if (i % 11 == 0 || i % 22 == 1) {
+            msg = DP_msg_undo_new(1, 0, i % 11 != 0);
+        }
+        else if (i % 2 == 0) {
+            msg = DP_msg_undo_point_new(user);
+        }
+        else {
+            msg = DP_msg_fill_rect_new(
+                user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                DP_int_to_uint32(i * 37 % WIDTH),
+                DP_int_to_uint32(i * 13 % HEIGHT), 30, 30,
+                0x80000000u | DP_int_to_uint32(i * 0x10305 % 0xffffff));
+        }
+        DP_catchup_push_noinc(c, msg);
+    }
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+
+static void batches_match_one_shot(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+
+    DP_Catchup *one_shot = DP_catchup_new();
+    push_session(one_shot);
+    DP_CanvasHistory *expected = DP_canvas_history_new(NULL, NULL, false, NULL);
+    INT_EQ_OK(DP_catchup_step(one_shot, expected, dc, INT_MAX), MESSAGE_COUNT,
+              "one shot handles everything");
+
+    DP_Catchup *c = DP_catchup_new();
+    push_session(c);
+    INT_EQ_OK(DP_catchup_queued(c), MESSAGE_COUNT, "everything is queued");
+    INT_EQ_OK(DP_catchup_progress(c), 0, "nothing is done yet");
+
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    int steps = 0;
+    int total = 0;
+    int last_progress = 0;
+    bool batches_ok = true;
+    bool progress_ok = true;
+    int count;
+    while ((count = DP_catchup_step(c, ch, dc, BATCH_SIZE)) != 0) {
+        total += count;
+        ++steps;
+        if (count != BATCH_SIZE && total != MESSAGE_COUNT) {
+            batches_ok = false;
+        }
+        int progress = DP_catchup_progress(c);
+        if (progress < last_progress || progress != total * 100 / MESSAGE_COUNT
+            || DP_catchup_queued(c) != MESSAGE_COUNT - total) {
+            progress_ok = false;
+        }
+        last_progress = progress;
+    }
+    INT_EQ_OK(total, MESSAGE_COUNT, "batches handle everything");
+    INT_EQ_OK(steps, (MESSAGE_COUNT + BATCH_SIZE - 1) / BATCH_SIZE,
+              "in the expected number of steps");
+    OK(batches_ok, "every batch but the last is full");
+    OK(progress_ok, "progress follows the handled messages");
+    INT_EQ_OK(DP_catchup_progress(c), 100, "catch-up is done");
+    INT_EQ_OK(DP_catchup_handled(c), MESSAGE_COUNT, "all messages handled");
+    INT_EQ_OK(DP_canvas_history_undo_depth_limit(ch), 10,
+              "undo depth message was handled");
+    OK(checksum(ch) == checksum(expected),
+       "batched catch-up gives the same canvas as one shot");
+
+    DP_canvas_history_free(ch);
+    DP_catchup_free(c);
+    DP_canvas_history_free(expected);
+    DP_catchup_free(one_shot);
+    DP_draw_context_free(dc);
+}
+
+static void pushing_during_catchup(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_Catchup *c = DP_catchup_new();
+    INT_EQ_OK(DP_catchup_progress(c), 100, "empty catch-up is done");
+
+    for (int i = 0; i < 4; ++i) {
+        DP_catchup_push_noinc(c, DP_msg_undo_point_new(1));
+    }
+    INT_EQ_OK(DP_catchup_step(c, ch, dc, 2), 2, "half step");
+    INT_EQ_OK(DP_catchup_progress(c), 50, "half done");
+    for (int i = 0; i < 4; ++i) {
+        DP_catchup_push_noinc(c, DP_msg_undo_point_new(1));
+    }
+    INT_EQ_OK(DP_catchup_progress(c), 2 * 100 / 8,
+              "more messages lower the progress");
+    INT_EQ_OK(DP_catchup_step(c, ch, dc, 100), 6, "step handles the rest");
+    INT_EQ_OK(DP_catchup_step(c, ch, dc, 100), 0, "nothing left to step");
+
+    DP_catchup_push_noinc(c, DP_msg_undo_point_new(1));
+    INT_EQ_OK(DP_catchup_handled(c), 0, "next catch-up starts over");
+    INT_EQ_OK(DP_catchup_progress(c), 0, "and isn't done");
+
+    DP_catchup_free(c);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(batches_match_one_shot);
+    REGISTER_TEST(pushing_during_catchup);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/local_state.h>
+#include <dpengine/view_mode.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID 257
+#define USER     1
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_Message *fill_rect(uint32_t color)
+{
+    return DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, 5, 5, 50,
+                                50, color);
+}
+
+#define BUMPS(EXPR, WHAT)                                                  \
+    do {                                                                   \
+        long long _before = DP_canvas_history_change_count(ch);            \
+        OK((EXPR), "%s", WHAT);                                            \
+        OK(DP_canvas_history_change_count(ch) > _before, "%s bumps", WHAT); \
+    } while (0)
+
+#define KEEPS(EXPR, WHAT)                                                  \
+    do {                                                                   \
+        long long _before = DP_canvas_history_change_count(ch);            \
+        (void)(EXPR);                                                      \
+        OK(DP_canvas_history_change_count(ch) == _before,                  \
+           "%s doesn't bump", WHAT);                                       \
+    } while (0)
+
+
+static void drawing_and_undo_bump(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    OK(DP_canvas_history_change_count(ch) == 0, "new history is at 0");
+    NOK(DP_canvas_history_dirty(ch), "new history isn't dirty");
+
+    BUMPS(handle(ch, dc, DP_msg_canvas_resize_new(USER, 0, 100, 100, 0)),
+          "resize");
+    BUMPS(handle(ch, dc,
+                 DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0,
+                                              "Layer", 5)),
+          "layer create");
+    KEEPS(handle(ch, dc, DP_msg_undo_point_new(USER)), "undo point");
+    BUMPS(handle(ch, dc, fill_rect(0xff336699)), "fill rect");
+    BUMPS(handle(ch, dc, DP_msg_undo_new(USER, 0, false)), "undo");
+    KEEPS(handle(ch, dc, DP_msg_undo_new(USER, 0, false)), "failed undo");
+    BUMPS(handle(ch, dc, DP_msg_undo_new(USER, 0, true)), "redo");
+    KEEPS(handle(ch, dc, DP_msg_fill_rect_new(USER, 999, DP_BLEND_MODE_NORMAL,
+                                              0, 0, 10, 10, 0xff000000)),
+          "fill rect on a nonexistent layer");
+
+    uint32_t background = 0xffeeddcc;
+    BUMPS(handle(ch, dc,
+                 DP_msg_canvas_background_new(USER, set_color, 4,
+                                              &background)),
+          "background change");
+    BUMPS(handle(ch, dc, DP_msg_annotation_create_new(USER, 0x101, 10, 20,
+                                                      100, 50)),
+          "annotation create");
+    BUMPS(handle(ch, dc,
+                 DP_msg_annotation_edit_new(USER, 0x101, 0, 0, 0, "Hi", 2)),
+          "annotation edit");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void saving_clears_dirty(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(ch, dc, DP_msg_canvas_resize_new(USER, 0, 100, 100, 0));
+    handle(ch, dc,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                        5));
+    OK(DP_canvas_history_dirty(ch), "changed history is dirty");
+
+    long long saved = DP_canvas_history_change_count(ch);
+    DP_canvas_history_saved_at_set(ch, saved);
+    OK(DP_canvas_history_saved_at(ch) == saved, "saved at is recorded");
+    NOK(DP_canvas_history_dirty(ch), "saved history isn't dirty");
+
+    handle(ch, dc, DP_msg_undo_point_new(USER));
+    handle(ch, dc, fill_rect(0xff336699));
+    OK(DP_canvas_history_dirty(ch), "drawing after saving is dirty");
+    // Undoing gets back to the saved canvas, but it's still a change. Telling
+    // them apart would mean comparing the canvas, which is too expensive.
+    handle(ch, dc, DP_msg_undo_new(USER, 0, false));
+    OK(DP_canvas_history_dirty(ch), "undoing after saving is dirty too");
+
+    DP_canvas_history_saved_at_set(ch, DP_canvas_history_change_count(ch));
+    NOK(DP_canvas_history_dirty(ch), "saving again clears dirty");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void view_changes_dont_bump(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(ch, dc, DP_msg_canvas_resize_new(USER, 0, 100, 100, 0));
+    handle(ch, dc,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                        5));
+    DP_canvas_history_saved_at_set(ch, DP_canvas_history_change_count(ch));
+
+    // View options are local state, the paint engine never passes them on to
+    // the history, so they leave the canvas and the change count alone.
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LocalState *ls = DP_local_state_new(cs, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    DP_Message *msgs[] = {
+        DP_local_state_msg_view_mode_new(DP_VIEW_MODE_FRAME),
+        DP_local_state_msg_layer_visibility_new(LAYER_ID, true),
+        DP_local_state_msg_track_onion_skin_new(1, true),
+        DP_local_state_msg_active_layer_new(LAYER_ID),
+    };
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(msgs); ++i) {
+        KEEPS(DP_local_state_handle(ls, dc, msgs[i]), "view change");
+        DP_message_decref(msgs[i]);
+    }
+    NOK(DP_canvas_history_dirty(ch), "view changes don't make it dirty");
+
+    DP_local_state_free(ls);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(drawing_and_undo_bump);
+    REGISTER_TEST(saving_clears_dirty);
+    REGISTER_TEST(view_changes_dont_bump);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID    257
+#define CANVAS_SIZE 256
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+static void draw_pixel(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                       int x, int y)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_pixel_square_new(1, LAYER_ID, x, y, 0xff000000,
+                                             DP_BLEND_MODE_NORMAL,
+                                             set_pixel_dab, 1, NULL));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+}
+
+static DP_LayerContent *layer_content(DP_CanvasState *cs)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+static DP_CanvasHistory *make_checksum_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    draw_pixel(TEST_ARGS, ch, dc, 10, 10);
+    return ch;
+}
+
+
+static void checksum_identical_states(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch1 = make_checksum_history(TEST_ARGS, dc);
+    DP_CanvasHistory *ch2 = make_checksum_history(TEST_ARGS, dc);
+    DP_CanvasState *cs1 = DP_canvas_history_get(ch1);
+    DP_CanvasState *cs2 = DP_canvas_history_get(ch2);
+
+    OK(DP_canvas_state_checksum(cs1) == DP_canvas_state_checksum(cs2),
+       "identical canvases have the same checksum");
+    OK(DP_layer_content_checksum(layer_content(cs1))
+           == DP_layer_content_checksum(layer_content(cs2)),
+       "identical layers have the same checksum");
+
+    DP_canvas_state_decref(cs2);
+    DP_canvas_state_decref(cs1);
+    DP_canvas_history_free(ch2);
+    DP_canvas_history_free(ch1);
+    DP_draw_context_free(dc);
+}
+
+static void checksum_single_pixel_change(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_checksum_history(TEST_ARGS, dc);
+    DP_CanvasState *prev = DP_canvas_history_get(ch);
+    draw_pixel(TEST_ARGS, ch, dc, DP_TILE_SIZE + 6, DP_TILE_SIZE * 2 + 2);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+
+    OK(DP_canvas_state_checksum(cs) != DP_canvas_state_checksum(prev),
+       "canvas checksum changed");
+    OK(DP_layer_content_checksum(layer_content(cs))
+           != DP_layer_content_checksum(layer_content(prev)),
+       "layer checksum changed");
+
+    int total = DP_tile_total_round(CANVAS_SIZE, CANVAS_SIZE);
+    uint64_t *checksums = DP_malloc(sizeof(*checksums) * (size_t)total);
+    uint64_t *prev_checksums = DP_malloc(sizeof(*checksums) * (size_t)total);
+    DP_canvas_state_tile_checksums(cs, checksums);
+    DP_canvas_state_tile_checksums(prev, prev_checksums);
+    int xtiles = DP_tile_count_round(CANVAS_SIZE);
+    for (int i = 0; i < total; ++i) {
+        bool expected = i == 2 * xtiles + 1;
+        OK((checksums[i] != prev_checksums[i]) == expected,
+           "tile checksum %d %s", i, expected ? "changed" : "unchanged");
+    }
+    DP_free(prev_checksums);
+    DP_free(checksums);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(prev);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void checksum_blank_tile(TEST_PARAMS)
+{
+    UINT_EQ_OK(DP_tile_checksum(NULL), DP_TILE_CHECKSUM_BLANK,
+               "null tile has blank checksum");
+    DP_Tile *t = DP_tile_new_from_bgra(0, 0);
+    UINT_EQ_OK(DP_tile_checksum(t), DP_TILE_CHECKSUM_BLANK,
+               "transparent tile has blank checksum");
+    DP_tile_decref(t);
+    t = DP_tile_new_from_bgra(0, 0xff000000);
+    OK(DP_tile_checksum(t) != DP_TILE_CHECKSUM_BLANK,
+       "opaque tile doesn't have blank checksum");
+    DP_tile_decref(t);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(checksum_identical_states);
+    REGISTER_TEST(checksum_single_pixel_change);
+    REGISTER_TEST(checksum_blank_tile);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID    257
+#define CANVAS_SIZE 64
+
+typedef struct DP_FalloffTestDab {
+    uint16_t size;
+    uint8_t hardness;
+} DP_FalloffTestDab;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_classic_dab(DP_UNUSED int count, DP_ClassicDab *dabs,
+                            void *user)
+{
+    DP_FalloffTestDab *ftd = user;
+    DP_classic_dab_init(dabs, 0, 0, 0, ftd->size, ftd->hardness, 255);
+}
+
+static void set_soft_dab(DP_UNUSED int count, DP_SoftDab *dabs, void *user)
+{
+    DP_FalloffTestDab *ftd = user;
+    DP_soft_dab_init(dabs, 0, 0, 0, ftd->size, ftd->hardness, 255);
+}
+
+// Draws the given dabs message onto an empty canvas and returns the layer's
+// alpha values, CANVAS_SIZE * CANVAS_SIZE of them, to be freed by the caller.
+static uint16_t *draw_message(TEST_PARAMS, DP_DrawContext *dc, DP_Message *msg)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    handle(TEST_ARGS, ch, dc, msg);
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    uint16_t *alphas =
+        DP_malloc(sizeof(*alphas) * CANVAS_SIZE * CANVAS_SIZE);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            alphas[y * CANVAS_SIZE + x] = DP_layer_content_pixel_at(lc, x, y).a;
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    return alphas;
+}
+
+// Draws a single soft dab with the given falloff in the middle of the canvas.
+static uint16_t *draw_dab(TEST_PARAMS, DP_DrawContext *dc, int falloff,
+                          double diameter, uint8_t hardness)
+{
+    DP_FalloffTestDab ftd = {(uint16_t)(diameter * 256.0), hardness};
+    return draw_message(
+        TEST_ARGS, dc,
+        DP_msg_draw_dabs_soft_new(1, LAYER_ID, CANVAS_SIZE * 4 / 2,
+                                  CANVAS_SIZE * 4 / 2, 0xff000000,
+                                  DP_BLEND_MODE_NORMAL, (uint8_t)falloff, 0,
+                                  256, 0, set_soft_dab, 1, &ftd));
+}
+
+static uint16_t *draw_classic_dab(TEST_PARAMS, DP_DrawContext *dc,
+                                  double diameter, uint8_t hardness)
+{
+    DP_FalloffTestDab ftd = {(uint16_t)(diameter * 256.0), hardness};
+    return draw_message(
+        TEST_ARGS, dc,
+        DP_msg_draw_dabs_classic_new(
+            1, LAYER_ID, CANVAS_SIZE * 4 / 2, CANVAS_SIZE * 4 / 2, 0xff000000,
+            DP_BLEND_MODE_NORMAL, set_classic_dab, 1, &ftd));
+}
+
+static double sum_alphas(const uint16_t *alphas)
+{
+    double sum = 0.0;
+    for (int i = 0; i < CANVAS_SIZE * CANVAS_SIZE; ++i) {
+        sum += alphas[i] / (double)DP_BIT15;
+    }
+    return sum;
+}
+
+static bool alphas_differ(const uint16_t *a, const uint16_t *b)
+{
+    for (int i = 0; i < CANVAS_SIZE * CANVAS_SIZE; ++i) {
+        if (a[i] != b[i]) {
+            return true;
+        }
+    }
+    return false;
+}
+
+
+static void falloff_matches_ramp_coverage(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    double diameters[] = {16.0, 32.0, 48.0};
+    uint8_t hardnesses[] = {64, 128, 204};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(diameters); ++i) {
+        for (size_t j = 0; j < DP_ARRAY_LENGTH(hardnesses); ++j) {
+            uint16_t *ramp = draw_dab(TEST_ARGS, dc,
+                                      DP_CLASSIC_BRUSH_FALLOFF_RAMP,
+                                      diameters[i], hardnesses[j]);
+            double ramp_sum = sum_alphas(ramp);
+            for (int falloff = DP_CLASSIC_BRUSH_FALLOFF_GAUSSIAN;
+                 falloff < DP_CLASSIC_BRUSH_FALLOFF_COUNT; ++falloff) {
+                uint16_t *alphas = draw_dab(TEST_ARGS, dc, falloff,
+                                            diameters[i], hardnesses[j]);
+                double sum = sum_alphas(alphas);
+                OK(sum > ramp_sum * 0.9 && sum < ramp_sum * 1.1,
+                   "falloff %d diameter %g hardness %d covers %g, "
+                   "ramp covers %g",
+                   falloff, diameters[i], (int)hardnesses[j], sum, ramp_sum);
+                OK(alphas_differ(ramp, alphas),
+                   "falloff %d diameter %g hardness %d differs from ramp",
+                   falloff, diameters[i], (int)hardnesses[j]);
+                DP_free(alphas);
+            }
+            DP_free(ramp);
+        }
+    }
+    DP_draw_context_free(dc);
+}
+
+static void falloff_full_hardness_is_identical(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    uint16_t *ramp =
+        draw_dab(TEST_ARGS, dc, DP_CLASSIC_BRUSH_FALLOFF_RAMP, 32.0, 255);
+    for (int falloff = DP_CLASSIC_BRUSH_FALLOFF_GAUSSIAN;
+         falloff < DP_CLASSIC_BRUSH_FALLOFF_COUNT; ++falloff) {
+        uint16_t *alphas = draw_dab(TEST_ARGS, dc, falloff, 32.0, 255);
+        NOK(alphas_differ(ramp, alphas),
+            "falloff %d at full hardness is the same as ramp", falloff);
+        DP_free(alphas);
+    }
+    DP_free(ramp);
+    DP_draw_context_free(dc);
+}
+
+static void falloff_small_dabs_not_empty(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    double diameters[] = {1.0, 2.0, 2.5};
+    for (int falloff = 0; falloff < DP_CLASSIC_BRUSH_FALLOFF_COUNT; ++falloff) {
+        for (size_t i = 0; i < DP_ARRAY_LENGTH(diameters); ++i) {
+            uint16_t *alphas =
+                draw_dab(TEST_ARGS, dc, falloff, diameters[i], 128);
+            OK(sum_alphas(alphas) > 0.0,
+               "falloff %d diameter %g is not empty", falloff, diameters[i]);
+            DP_free(alphas);
+        }
+    }
+    DP_draw_context_free(dc);
+}
+
+static void falloff_unknown_is_ramp(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    uint16_t *ramp =
+        draw_dab(TEST_ARGS, dc, DP_CLASSIC_BRUSH_FALLOFF_RAMP, 32.0, 128);
+    uint16_t *alphas = draw_dab(TEST_ARGS, dc, 200, 32.0, 128);
+    NOK(alphas_differ(ramp, alphas), "unknown falloff is drawn as ramp");
+    DP_free(alphas);
+    DP_free(ramp);
+    DP_draw_context_free(dc);
+}
+
+static void soft_ramp_matches_classic(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    uint8_t hardnesses[] = {0, 128, 255};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(hardnesses); ++i) {
+        uint16_t *classic =
+            draw_classic_dab(TEST_ARGS, dc, 24.0, hardnesses[i]);
+        uint16_t *soft = draw_dab(TEST_ARGS, dc, DP_CLASSIC_BRUSH_FALLOFF_RAMP,
+                                  24.0, hardnesses[i]);
+        NOK(alphas_differ(classic, soft),
+            "soft ramp dab at hardness %d is the same as classic",
+            (int)hardnesses[i]);
+        DP_free(soft);
+        DP_free(classic);
+    }
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(falloff_matches_ramp_coverage);
+    REGISTER_TEST(falloff_full_hardness_is_identical);
+    REGISTER_TEST(falloff_small_dabs_not_empty);
+    REGISTER_TEST(falloff_unknown_is_ramp);
+    REGISTER_TEST(soft_ramp_matches_classic);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
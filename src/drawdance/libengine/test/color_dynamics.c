@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define MAX_DABS       1024
+#define STROKE_LENGTH  220.0f
+#define MAX_DISTANCE   200.0f
+#define STROKE_POINTS  23
+
+typedef struct DP_ColorTestDabs {
+    int count;
+    uint32_t colors[MAX_DABS];
+    uint8_t opacities[MAX_DABS];
+} DP_ColorTestDabs;
+
+static void collect_dabs(void *user, DP_Message *msg)
+{
+    DP_ColorTestDabs *ctds = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_CLASSIC) {
+        DP_MsgDrawDabsClassic *mddc = DP_message_internal(msg);
+        uint32_t color = DP_msg_draw_dabs_classic_color(mddc);
+        int count;
+        const DP_ClassicDab *dabs =
+            DP_msg_draw_dabs_classic_dabs(mddc, &count);
+        for (int i = 0; i < count && ctds->count < MAX_DABS; ++i) {
+            ctds->colors[ctds->count] = color;
+            ctds->opacities[ctds->count] =
+                DP_classic_dab_opacity(DP_classic_dab_at(dabs, i));
+            ++ctds->count;
+        }
+    }
+    DP_message_decref(msg);
+}
+
+static int color_red(uint32_t color)
+{
+    return (int)((color >> 16) & 0xffu);
+}
+
+static int color_blue(uint32_t color)
+{
+    return (int)(color & 0xffu);
+}
+
+static void init_brush(DP_ClassicBrush *cb, DP_UPixelFloat color2,
+                       DP_ClassicBrushDynamicType type)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 4.0f;
+    cb->size.max = 4.0f;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.5f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f};
+    cb->color2 = color2;
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+    cb->color_dynamic.type = type;
+    cb->color_dynamic.max_distance = MAX_DISTANCE;
+}
+
+// Strokes to the right over STROKE_LENGTH pixels, with the pressure going from
+// the given start to end value along the way.
+static void stroke_dabs(const DP_ClassicBrush *cb, float pressure_start,
+                        float pressure_end, DP_ColorTestDabs *ctds)
+{
+    ctds->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_dabs, NULL, ctds);
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    for (int i = 0; i < STROKE_POINTS; ++i) {
+        float t = DP_int_to_float(i) / DP_int_to_float(STROKE_POINTS - 1);
+        float pressure = pressure_start + (pressure_end - pressure_start) * t;
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){STROKE_LENGTH * t, 16.0f, pressure, 0.0f, 0.0f,
+                            0.0f, i * 10},
+            NULL);
+    }
+    DP_brush_engine_stroke_end(be, STROKE_POINTS * 10, NULL, false);
+    DP_brush_engine_free(be);
+}
+
+static bool red_to_blue_monotonic(const DP_ColorTestDabs *ctds)
+{
+    for (int i = 1; i < ctds->count; ++i) {
+        uint32_t prev = ctds->colors[i - 1];
+        uint32_t color = ctds->colors[i];
+        if (color_red(color) > color_red(prev)
+            || color_blue(color) < color_blue(prev)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+
+static void color_distance_gradient(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, (DP_UPixelFloat){1.0f, 0.0f, 0.0f, 1.0f},
+               DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE);
+    DP_ColorTestDabs ctds;
+    stroke_dabs(&cb, 1.0f, 1.0f, &ctds);
+
+    if (OK(ctds.count > 10, "stroke has %d dabs", ctds.count)) {
+        uint32_t first = ctds.colors[0];
+        uint32_t last = ctds.colors[ctds.count - 1];
+        OK(color_red(first) == 255 && color_blue(first) == 0,
+           "first dab is the primary color, got %06x",
+           (unsigned int)(first & 0xffffffu));
+        OK(color_red(last) == 0 && color_blue(last) == 255,
+           "last dab is the secondary color, got %06x",
+           (unsigned int)(last & 0xffffffu));
+        OK(red_to_blue_monotonic(&ctds),
+           "color goes from red to blue without reversing");
+
+        // Halfway through in linear light, both channels end up at about 188,
+        // where a plain lerp of the sRGB values would give a muddy 128.
+        bool bright_midpoint = false;
+        for (int i = 0; i < ctds.count; ++i) {
+            uint32_t color = ctds.colors[i];
+            int diff = color_red(color) - color_blue(color);
+            if (diff >= -16 && diff <= 16) {
+                bright_midpoint = color_red(color) > 160;
+                DIAG("midpoint dab color %06x",
+                     (unsigned int)(color & 0xffffffu));
+                break;
+            }
+        }
+        OK(bright_midpoint, "midpoint is mixed in linear light");
+    }
+}
+
+static void color_pressure_gradient(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, (DP_UPixelFloat){1.0f, 0.0f, 0.0f, 1.0f},
+               DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE);
+    DP_ColorTestDabs ctds;
+    stroke_dabs(&cb, 0.0f, 1.0f, &ctds);
+
+    if (OK(ctds.count > 10, "stroke has %d dabs", ctds.count)) {
+        uint32_t first = ctds.colors[0];
+        uint32_t last = ctds.colors[ctds.count - 1];
+        OK(color_red(first) == 255 && color_blue(first) == 0,
+           "first dab at no pressure is the primary color, got %06x",
+           (unsigned int)(first & 0xffffffu));
+        OK(color_red(last) < 16 && color_blue(last) > 240,
+           "last dab at full pressure is the secondary color, got %06x",
+           (unsigned int)(last & 0xffffffu));
+        OK(red_to_blue_monotonic(&ctds),
+           "color follows the rising pressure without reversing");
+    }
+}
+
+static void color_fades_to_transparent(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 0.0f},
+               DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE);
+    DP_ColorTestDabs ctds;
+    stroke_dabs(&cb, 1.0f, 1.0f, &ctds);
+
+    if (OK(ctds.count > 10, "stroke has %d dabs", ctds.count)) {
+        UINT_EQ_OK(ctds.opacities[0], 255u, "first dab is fully opaque");
+        bool non_increasing = true;
+        for (int i = 1; i < ctds.count; ++i) {
+            if (ctds.opacities[i] > ctds.opacities[i - 1]) {
+                non_increasing = false;
+            }
+        }
+        OK(non_increasing, "dabs fade out along the stroke");
+        OK(ctds.opacities[ctds.count - 1] < 16,
+           "last dab is close to transparent, got %u",
+           (unsigned int)ctds.opacities[ctds.count - 1]);
+    }
+}
+
+static void color_none_keeps_primary(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, (DP_UPixelFloat){1.0f, 0.0f, 0.0f, 1.0f},
+               DP_CLASSIC_BRUSH_DYNAMIC_NONE);
+    DP_ColorTestDabs ctds;
+    stroke_dabs(&cb, 0.0f, 1.0f, &ctds);
+
+    if (OK(ctds.count > 10, "stroke has %d dabs", ctds.count)) {
+        bool all_red = true;
+        for (int i = 0; i < ctds.count; ++i) {
+            if (color_red(ctds.colors[i]) != 255
+                || color_blue(ctds.colors[i]) != 0) {
+                all_red = false;
+            }
+        }
+        OK(all_red, "without a color dynamic, all dabs are the primary color");
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(color_distance_gradient);
+    REGISTER_TEST(color_pressure_gradient);
+    REGISTER_TEST(color_fades_to_transparent);
+    REGISTER_TEST(color_none_keeps_primary);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
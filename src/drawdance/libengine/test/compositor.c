@@ -0,0 +1,596 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/affected_area.h>
+#include <dpengine/brush.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/compositor.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    300
+#define HEIGHT   200
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+#define OTHER_ID 260
+#define STEPS    250
+
+static const int layer_ids[] = {LAYER_ID, CHILD_ID, OTHER_ID};
+
+static const int blend_modes[] = {
+    DP_BLEND_MODE_NORMAL,
+    DP_BLEND_MODE_MULTIPLY,
+    DP_BLEND_MODE_ERASE,
+    DP_BLEND_MODE_BEHIND,
+};
+
+// The compositor gets the visible affected area of every message handled and
+// gets compared against flattening the whole canvas from scratch.
+typedef struct Context {
+    DP_CanvasHistory *ch;
+    DP_DrawContext *dc;
+    DP_Compositor *c;
+    DP_AffectedIndirectAreas aia;
+    unsigned int flags;
+    uint32_t random;
+} Context;
+
+static uint32_t next_random(Context *ctx)
+{
+    // xorshift32, so that failures are reproducible.
+    uint32_t x = ctx->random;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    ctx->random = x;
+    return x;
+}
+
+static int random_int(Context *ctx, int min, int max)
+{
+    return min + (int)(next_random(ctx) % (uint32_t)(max - min + 1));
+}
+
+static bool tiles_equal(DP_Tile *a, DP_Tile *b)
+{
+    if (a && b) {
+        return memcmp(DP_tile_pixels(a), DP_tile_pixels(b), DP_TILE_BYTES)
+            == 0;
+    }
+    else {
+        return DP_tile_blank(a) && DP_tile_blank(b);
+    }
+}
+
+static int count_mismatches(Context *ctx)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ctx->ch);
+    DP_TransientLayerContent *expected =
+        DP_canvas_state_to_flat_layer(cs, ctx->flags, NULL, NULL);
+    int xtiles = DP_tile_count_round(DP_canvas_state_width(cs));
+    int ytiles = DP_tile_count_round(DP_canvas_state_height(cs));
+    int mismatches = 0;
+    for (int y = 0; y < ytiles; ++y) {
+        for (int x = 0; x < xtiles; ++x) {
+            DP_Tile *t = DP_layer_content_tile_at_noinc(
+                (DP_LayerContent *)expected, x, y);
+            if (!tiles_equal(DP_compositor_tile_at_noinc(ctx->c, x, y), t)) {
+                ++mismatches;
+            }
+        }
+    }
+    DP_transient_layer_content_decref(expected);
+    DP_canvas_state_decref(cs);
+    return mismatches;
+}
+
+// Scales the flattened canvas by averaging every block of pixels, the way
+// the compositor is supposed to.
+static DP_Pixel8 expected_scaled_pixel(DP_LayerContent *lc, int scale, int sx,
+                                       int sy)
+{
+    int width = DP_layer_content_width(lc);
+    int height = DP_layer_content_height(lc);
+    uint32_t b = 0, g = 0, r = 0, a = 0;
+    for (int y = sy * scale; y < DP_min_int((sy + 1) * scale, height); ++y) {
+        for (int x = sx * scale; x < DP_min_int((sx + 1) * scale, width);
+             ++x) {
+            DP_Pixel15 pixel = DP_layer_content_pixel_at(lc, x, y);
+            b += pixel.b;
+            g += pixel.g;
+            r += pixel.r;
+            a += pixel.a;
+        }
+    }
+    uint32_t area = DP_int_to_uint32(scale * scale);
+    return DP_pixel15_to_8((DP_Pixel15){
+        DP_uint32_to_uint16((b + area / 2) / area),
+        DP_uint32_to_uint16((g + area / 2) / area),
+        DP_uint32_to_uint16((r + area / 2) / area),
+        DP_uint32_to_uint16((a + area / 2) / area),
+    });
+}
+
+// Reads the whole scaled canvas, with a margin sticking out on each side.
+static int count_scaled_mismatches(Context *ctx, int scale)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ctx->ch);
+    DP_TransientLayerContent *expected =
+        DP_canvas_state_to_flat_layer(cs, ctx->flags, NULL, NULL);
+    int scaled_width = (DP_canvas_state_width(cs) + scale - 1) / scale;
+    int scaled_height = (DP_canvas_state_height(cs) + scale - 1) / scale;
+    int x = -3;
+    int y = -2;
+    int width = scaled_width + 7;
+    int height = scaled_height + 5;
+    DP_Pixel8 *pixels =
+        DP_compositor_to_scaled_pixels8(ctx->c, scale, x, y, width, height);
+
+    int mismatches = 0;
+    for (int py = 0; py < height; ++py) {
+        for (int px = 0; px < width; ++px) {
+            int sx = x + px;
+            int sy = y + py;
+            uint32_t color =
+                sx >= 0 && sy >= 0 && sx < scaled_width && sy < scaled_height
+                    ? expected_scaled_pixel((DP_LayerContent *)expected, scale,
+                                            sx, sy)
+                          .color
+                    : 0;
+            if (pixels[py * width + px].color != color) {
+                ++mismatches;
+            }
+        }
+    }
+
+    DP_free(pixels);
+    DP_transient_layer_content_decref(expected);
+    DP_canvas_state_decref(cs);
+    return mismatches;
+}
+
+static bool handle_update(Context *ctx, DP_Message *msg, DP_Rect *out_bounds)
+{
+    bool ok = DP_canvas_history_handle(ctx->ch, ctx->dc, msg);
+    DP_AffectedArea aa = DP_affected_area_make_visible(msg, &ctx->aia);
+    DP_CanvasState *cs = DP_canvas_history_get(ctx->ch);
+    bool updated = DP_compositor_update(ctx->c, cs, &aa, out_bounds);
+    DP_canvas_state_decref(cs);
+    DP_message_decref(msg);
+    return ok && (updated || !out_bounds);
+}
+
+static void handle(TEST_PARAMS, Context *ctx, DP_Message *msg)
+{
+    DP_MessageType type = DP_message_type(msg);
+    OK(handle_update(ctx, msg, NULL), "handle %s",
+       DP_message_type_enum_name(type));
+}
+
+static void init_context(TEST_PARAMS, Context *ctx, unsigned int flags,
+                         uint32_t seed)
+{
+    ctx->ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    ctx->dc = DP_draw_context_new();
+    ctx->c = DP_compositor_new(flags);
+    DP_affected_indirect_areas_clear(&ctx->aia);
+    ctx->flags = flags;
+    ctx->random = seed;
+    handle(TEST_ARGS, ctx, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ctx,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+    handle(TEST_ARGS, ctx,
+           DP_msg_layer_tree_create_new(1, GROUP_ID, 0, LAYER_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ctx,
+           DP_msg_layer_tree_create_new(1, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    handle(TEST_ARGS, ctx,
+           DP_msg_layer_tree_create_new(1, OTHER_ID, 0, GROUP_ID, 0, 0,
+                                        "Other", 5));
+}
+
+static void dispose_context(Context *ctx)
+{
+    DP_compositor_free(ctx->c);
+    DP_draw_context_free(ctx->dc);
+    DP_canvas_history_free(ctx->ch);
+}
+
+static uint16_t random_layer(Context *ctx)
+{
+    return DP_int_to_uint16(
+        layer_ids[random_int(ctx, 0, (int)DP_ARRAY_LENGTH(layer_ids) - 1)]);
+}
+
+static uint32_t random_color(Context *ctx)
+{
+    uint32_t alpha = DP_int_to_uint32(random_int(ctx, 0x20, 0xff));
+    return (next_random(ctx) & 0xffffffu) | alpha << 24u;
+}
+
+static DP_Message *random_fill_rect(Context *ctx, unsigned int user)
+{
+    // The canvas gets resized, so look at how big it currently is.
+    DP_CanvasState *cs = DP_canvas_history_get(ctx->ch);
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    DP_canvas_state_decref(cs);
+    int w = random_int(ctx, 1, 90);
+    int h = random_int(ctx, 1, 90);
+    // Can stick out over the right and bottom of the canvas.
+    int x = random_int(ctx, 0, width - 1);
+    int y = random_int(ctx, 0, height - 1);
+    int blend_mode =
+        blend_modes[random_int(ctx, 0, (int)DP_ARRAY_LENGTH(blend_modes) - 1)];
+    return DP_msg_fill_rect_new(user, random_layer(ctx),
+                                DP_int_to_uint8(blend_mode),
+                                DP_int_to_uint32(x), DP_int_to_uint32(y),
+                                DP_int_to_uint32(w), DP_int_to_uint32(h),
+                                random_color(ctx));
+}
+
+static void set_classic_dabs(int count, DP_ClassicDab *dabs, void *user)
+{
+    Context *ctx = user;
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(dabs, i, DP_int_to_int8(random_int(ctx, -80, 80)),
+                            DP_int_to_int8(random_int(ctx, -80, 80)),
+                            DP_int_to_uint16(random_int(ctx, 256, 40 * 256)),
+                            DP_int_to_uint8(random_int(ctx, 0, 255)),
+                            DP_int_to_uint8(random_int(ctx, 1, 255)));
+    }
+}
+
+// Direct dabs have no alpha in their color, indirect ones do.
+static DP_Message *random_classic_dabs(Context *ctx, unsigned int user,
+                                       uint16_t layer_id, bool indirect)
+{
+    uint32_t color = next_random(ctx) & 0xffffffu;
+    return DP_msg_draw_dabs_classic_new(
+        user, layer_id, random_int(ctx, 0, WIDTH * 4),
+        random_int(ctx, 0, HEIGHT * 4), indirect ? color | 0x80000000u : color,
+        DP_BLEND_MODE_NORMAL, set_classic_dabs, random_int(ctx, 1, 5), ctx);
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static void random_step(TEST_PARAMS, Context *ctx)
+{
+    unsigned int user = DP_int_to_uint(random_int(ctx, 1, 3));
+    int r = random_int(ctx, 0, 99);
+    if (r < 10) {
+        // Undo can fail if there's nothing to undo, that's fine.
+        handle_update(ctx, DP_msg_undo_new(user, 0, r < 6), NULL);
+    }
+    else if (r < 50) {
+        handle(TEST_ARGS, ctx, DP_msg_undo_point_new(user));
+        handle(TEST_ARGS, ctx, random_fill_rect(ctx, user));
+    }
+    else if (r < 70) {
+        handle(TEST_ARGS, ctx,
+               random_classic_dabs(ctx, user, random_layer(ctx), false));
+    }
+    else if (r < 88) {
+        // An indirect stroke, checked in the middle as well.
+        uint16_t layer_id = random_layer(ctx);
+        handle(TEST_ARGS, ctx, DP_msg_undo_point_new(user));
+        handle(TEST_ARGS, ctx, random_classic_dabs(ctx, user, layer_id, true));
+        INT_EQ_OK(count_mismatches(ctx), 0, "no mismatches mid-stroke");
+        handle(TEST_ARGS, ctx, random_classic_dabs(ctx, user, layer_id, true));
+        handle(TEST_ARGS, ctx, DP_msg_pen_up_new(user));
+    }
+    else if (r < 93) {
+        handle(TEST_ARGS, ctx,
+               DP_msg_layer_attributes_new(
+                   user, random_layer(ctx), 0, 0,
+                   DP_int_to_uint8(random_int(ctx, 0, 255)),
+                   DP_BLEND_MODE_NORMAL));
+    }
+    else if (r < 96) {
+        handle(TEST_ARGS, ctx,
+               DP_msg_layer_tree_move_new(user, OTHER_ID, GROUP_ID,
+                                          r % 2 == 0 ? CHILD_ID : 0));
+    }
+    else if (r < 98) {
+        uint32_t color = random_color(ctx);
+        handle(TEST_ARGS, ctx,
+               DP_msg_canvas_background_new(user, set_color, 4, &color));
+    }
+    else {
+        handle(TEST_ARGS, ctx,
+               DP_msg_canvas_resize_new(
+                   user, DP_int_to_int32(random_int(ctx, -20, 40)),
+                   DP_int_to_int32(random_int(ctx, -20, 40)),
+                   DP_int_to_int32(random_int(ctx, -20, 40)),
+                   DP_int_to_int32(random_int(ctx, -20, 40))));
+    }
+}
+
+static void run_random_steps(TEST_PARAMS, unsigned int flags, uint32_t seed)
+{
+    Context ctx;
+    init_context(TEST_ARGS, &ctx, flags, seed);
+    int failed_step = -1;
+    for (int i = 0; i < STEPS && failed_step == -1; ++i) {
+        random_step(TEST_ARGS, &ctx);
+        if (count_mismatches(&ctx) != 0) {
+            failed_step = i;
+        }
+    }
+    INT_EQ_OK(failed_step, -1, "composite matches flattening from scratch "
+                               "after every one of %d steps with seed %u",
+              STEPS, seed);
+    dispose_context(&ctx);
+}
+
+
+static void compositor_matches_flattening(TEST_PARAMS)
+{
+    uint32_t seeds[] = {0x1234567u, 0xdeadbeefu, 0xc0ffee11u};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(seeds); ++i) {
+        run_random_steps(TEST_ARGS, DP_FLAT_IMAGE_INCLUDE_BACKGROUND, seeds[i]);
+        run_random_steps(TEST_ARGS, DP_FLAT_IMAGE_RENDER_FLAGS, seeds[i]);
+    }
+}
+
+static void compositor_leaves_unaffected_tiles(TEST_PARAMS)
+{
+    Context ctx;
+    init_context(TEST_ARGS, &ctx, DP_FLAT_IMAGE_RENDER_FLAGS, 1);
+    int xtiles = DP_tile_count_round(WIDTH);
+    int ytiles = DP_tile_count_round(HEIGHT);
+    DP_Tile **before = DP_malloc(sizeof(*before) * (size_t)(xtiles * ytiles));
+    for (int i = 0; i < xtiles * ytiles; ++i) {
+        before[i] = DP_compositor_tile_at_noinc(ctx.c, i % xtiles, i / xtiles);
+    }
+
+    DP_Rect bounds;
+    OK(handle_update(&ctx,
+                     DP_msg_fill_rect_new(1, CHILD_ID, DP_BLEND_MODE_NORMAL,
+                                          DP_TILE_SIZE + 6, 10, 10, 10,
+                                          0xff336699u),
+                     &bounds),
+       "small fill recomposites something");
+    OK(DP_rect_x(bounds) == DP_TILE_SIZE && DP_rect_y(bounds) == 0
+           && DP_rect_width(bounds) == DP_TILE_SIZE
+           && DP_rect_height(bounds) == DP_TILE_SIZE,
+       "updated bounds are the single affected tile");
+
+    int changed = 0;
+    int wrong = 0;
+    for (int i = 0; i < xtiles * ytiles; ++i) {
+        DP_Tile *t = DP_compositor_tile_at_noinc(ctx.c, i % xtiles, i / xtiles);
+        if (t != before[i]) {
+            ++changed;
+            if (i != 1) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(changed, 1, "only one tile recomposited");
+    INT_EQ_OK(wrong, 0, "no unaffected tile recomposited");
+    DP_free(before);
+
+    DP_Pixel8 *pixels =
+        DP_compositor_to_pixels8(ctx.c, DP_TILE_SIZE + 11, 15, 10, 1);
+    OK(pixels[0].color == 0xff336699u, "filled pixel copied out");
+    OK(pixels[9].color == 0, "unfilled pixel copied out");
+    DP_free(pixels);
+
+    OK(!handle_update(&ctx, DP_msg_undo_point_new(1), &bounds),
+       "undo point doesn't recomposite anything");
+    INT_EQ_OK(count_mismatches(&ctx), 0, "composite matches in the end");
+    dispose_context(&ctx);
+}
+
+static void run_scaled_steps(TEST_PARAMS, size_t budget, uint32_t seed)
+{
+    Context ctx;
+    init_context(TEST_ARGS, &ctx, DP_FLAT_IMAGE_RENDER_FLAGS, seed);
+    DP_compositor_scale_cache_budget_set(ctx.c, budget);
+    int failed_step = -1;
+    for (int i = 0; i < STEPS / 5 && failed_step == -1; ++i) {
+        random_step(TEST_ARGS, &ctx);
+        for (int scale = 1; scale <= DP_COMPOSITOR_SCALE_MAX; scale *= 2) {
+            if (count_scaled_mismatches(&ctx, scale) != 0) {
+                DIAG("Mismatch at scale %d", scale);
+                failed_step = i;
+                break;
+            }
+        }
+    }
+    INT_EQ_OK(failed_step, -1, "scaled composite matches scaling a fresh "
+                               "flatten after every one of %d steps with "
+                               "budget %zu and seed %u",
+              STEPS / 5, budget, seed);
+    if (budget == 0) {
+        INT_EQ_OK(DP_compositor_scale_cache_count(ctx.c), 0,
+                  "nothing cached without a budget");
+    }
+    else {
+        OK(DP_compositor_scale_cache_count(ctx.c) > 0, "scaled tiles cached");
+    }
+    dispose_context(&ctx);
+}
+
+static void compositor_scaled_matches_flattening(TEST_PARAMS)
+{
+    run_scaled_steps(TEST_ARGS, 0, 0xabcdef1u);
+    run_scaled_steps(TEST_ARGS, 1024 * 1024, 0xabcdef1u);
+    run_scaled_steps(TEST_ARGS, 1024 * 1024, 0x7777aaau);
+}
+
+static void compositor_scaled_cache_eviction(TEST_PARAMS)
+{
+    Context ctx;
+    init_context(TEST_ARGS, &ctx, DP_FLAT_IMAGE_RENDER_FLAGS, 2);
+    // Something in every tile, so that none of them are uniform.
+    for (int y = 0; y < HEIGHT; y += DP_TILE_SIZE) {
+        for (int x = 0; x < WIDTH; x += DP_TILE_SIZE) {
+            handle(TEST_ARGS, &ctx,
+                   DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                        DP_int_to_uint32(x + 5),
+                                        DP_int_to_uint32(y + 1), 20, 5,
+                                        0xff112233u));
+        }
+    }
+
+    // Room for three tiles at half size, plus a bit for bookkeeping.
+    size_t half_bytes = sizeof(DP_Pixel8) * (DP_TILE_SIZE / 2)
+                      * (DP_TILE_SIZE / 2);
+    size_t budget = half_bytes * 3 + 256;
+    DP_compositor_scale_cache_budget_set(ctx.c, budget);
+    INT_EQ_OK(count_scaled_mismatches(&ctx, 2), 0, "half size matches");
+    INT_EQ_OK(DP_compositor_scale_cache_count(ctx.c), 3,
+              "three half size tiles cached");
+    OK(DP_compositor_scale_cache_bytes(ctx.c) <= budget,
+       "cache within budget after half size");
+
+    INT_EQ_OK(count_scaled_mismatches(&ctx, 16), 0, "sixteenth size matches");
+    OK(DP_compositor_scale_cache_count(ctx.c) > 3,
+       "more tiles cached at sixteenth size");
+    OK(DP_compositor_scale_cache_bytes(ctx.c) <= budget,
+       "cache within budget after sixteenth size");
+
+    INT_EQ_OK(count_scaled_mismatches(&ctx, 2), 0, "half size matches again");
+    OK(DP_compositor_scale_cache_bytes(ctx.c) <= budget,
+       "cache within budget after half size again");
+
+    DP_compositor_scale_cache_budget_set(ctx.c, half_bytes);
+    OK(DP_compositor_scale_cache_bytes(ctx.c) <= half_bytes,
+       "shrinking the budget evicts tiles");
+    INT_EQ_OK(count_scaled_mismatches(&ctx, 4), 0, "quarter size matches");
+    OK(DP_compositor_scale_cache_bytes(ctx.c) <= half_bytes,
+       "cache within shrunk budget");
+
+    DP_compositor_scale_cache_budget_set(ctx.c, 0);
+    INT_EQ_OK(DP_compositor_scale_cache_count(ctx.c), 0,
+              "no budget evicts everything");
+    OK(DP_compositor_scale_cache_bytes(ctx.c) == 0, "no bytes left cached");
+    INT_EQ_OK(count_scaled_mismatches(&ctx, 8), 0, "uncached eighth matches");
+    dispose_context(&ctx);
+}
+
+static void run_base_steps(TEST_PARAMS, unsigned int flags, int base_layers,
+                           uint32_t seed)
+{
+    Context ctx;
+    init_context(TEST_ARGS, &ctx, flags, seed);
+    DP_compositor_base_layers_set(ctx.c, base_layers);
+    int failed_step = -1;
+    for (int i = 0; i < STEPS && failed_step == -1; ++i) {
+        random_step(TEST_ARGS, &ctx);
+        if (count_mismatches(&ctx) != 0) {
+            failed_step = i;
+        }
+    }
+    INT_EQ_OK(failed_step, -1, "composite with %d base layers matches "
+                               "flattening from scratch after every one of "
+                               "%d steps with seed %u",
+              base_layers, STEPS, seed);
+    dispose_context(&ctx);
+}
+
+static void compositor_base_matches_flattening(TEST_PARAMS)
+{
+    // More base layers than there are top-level ones puts all of them in it.
+    int base_layers[] = {1, 2, 5, DP_COMPOSITOR_BASE_LAYERS_AUTO};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(base_layers); ++i) {
+        run_base_steps(TEST_ARGS, DP_FLAT_IMAGE_INCLUDE_BACKGROUND,
+                       base_layers[i], 0x5eed0001u);
+        run_base_steps(TEST_ARGS, DP_FLAT_IMAGE_RENDER_FLAGS, base_layers[i],
+                       0x5eed0002u);
+    }
+}
+
+static void fill(TEST_PARAMS, Context *ctx, int layer_id, uint32_t color)
+{
+    handle(TEST_ARGS, ctx,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, 10, 10, 100, 100,
+                                color));
+}
+
+static void compositor_base_follows_changes(TEST_PARAMS)
+{
+    Context ctx;
+    init_context(TEST_ARGS, &ctx, DP_FLAT_IMAGE_RENDER_FLAGS, 3);
+    DP_compositor_base_layers_set(ctx.c, DP_COMPOSITOR_BASE_LAYERS_AUTO);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 0, "no base before update");
+
+    // The layer at the bottom, the group with the child in it and the other
+    // layer on top.
+    fill(TEST_ARGS, &ctx, LAYER_ID, 0xff102030u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 3,
+              "every top-level layer in the base after full update");
+    fill(TEST_ARGS, &ctx, OTHER_ID, 0x80405060u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 2,
+              "changing the top layer takes it out of the base");
+    fill(TEST_ARGS, &ctx, CHILD_ID, 0x80708090u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 1,
+              "changing a layer in the group takes the group out of the base");
+    fill(TEST_ARGS, &ctx, OTHER_ID, 0x80a0b0c0u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 1,
+              "changing a layer above the base keeps it");
+    INT_EQ_OK(count_mismatches(&ctx), 0, "composite matches over base");
+
+    handle(TEST_ARGS, &ctx,
+           DP_msg_layer_attributes_new(1, OTHER_ID, 0, 0, 128,
+                                       DP_BLEND_MODE_NORMAL));
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 3,
+              "changing attributes puts every layer back in the base");
+    // Now the base of these tiles has the group's pixels in it.
+    fill(TEST_ARGS, &ctx, OTHER_ID, 0x80304050u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 2, "top layer out again");
+    fill(TEST_ARGS, &ctx, CHILD_ID, 0x80607080u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 1, "group out again");
+    INT_EQ_OK(count_mismatches(&ctx), 0, "composite matches shrunk base");
+    fill(TEST_ARGS, &ctx, LAYER_ID, 0xffa0b0c0u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 0,
+              "changing the bottom layer empties the base");
+    INT_EQ_OK(count_mismatches(&ctx), 0, "composite matches without base");
+
+    DP_compositor_base_layers_set(ctx.c, 1);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 0,
+              "changing the count drops the base");
+    fill(TEST_ARGS, &ctx, LAYER_ID, 0xff0000ffu);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 1, "fixed base of one layer");
+    fill(TEST_ARGS, &ctx, LAYER_ID, 0x8000ff00u);
+    INT_EQ_OK(DP_compositor_base_layers(ctx.c), 1,
+              "changing a fixed base layer keeps it in the base");
+    INT_EQ_OK(count_mismatches(&ctx), 0, "composite matches fixed base");
+    dispose_context(&ctx);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(compositor_matches_flattening);
+    REGISTER_TEST(compositor_leaves_unaffected_tiles);
+    REGISTER_TEST(compositor_scaled_matches_flattening);
+    REGISTER_TEST(compositor_scaled_cache_eviction);
+    REGISTER_TEST(compositor_base_matches_flattening);
+    REGISTER_TEST(compositor_base_follows_changes);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/compress.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dptest_engine.h>
+
+
+typedef struct DP_CompressTestBuffer {
+    unsigned char *data;
+    size_t size;
+} DP_CompressTestBuffer;
+
+static unsigned char *get_buffer(size_t size, void *user)
+{
+    DP_CompressTestBuffer *buffer = user;
+    buffer->data = DP_malloc(size);
+    return buffer->data;
+}
+
+static DP_CompressTestBuffer compress(TEST_PARAMS, const void *in,
+                                      size_t in_size)
+{
+    DP_CompressTestBuffer buffer = {NULL, 0};
+    buffer.size = DP_compress_deflate(in, in_size, get_buffer, &buffer);
+    OK(buffer.size != 0, "compress %zu bytes (error: %s)", in_size,
+       DP_error());
+    return buffer;
+}
+
+static DP_Image *make_image(int width, int height)
+{
+    DP_Image *img = DP_image_new(width, height);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    for (int i = 0; i < width * height; ++i) {
+        pixels[i].color = DP_int_to_uint32(i % 7 == 0 ? 0 : i) | 0xff000000u;
+    }
+    return img;
+}
+
+static bool images_equal(DP_Image *a, DP_Image *b)
+{
+    int width = DP_image_width(a);
+    int height = DP_image_height(a);
+    return width == DP_image_width(b) && height == DP_image_height(b)
+        && memcmp(DP_image_pixels(a), DP_image_pixels(b),
+                  DP_int_to_size(width * height) * sizeof(DP_Pixel8))
+               == 0;
+}
+
+
+static void compress_image_round_trip(TEST_PARAMS)
+{
+    DP_Image *img = make_image(300, 200);
+    size_t size = DP_int_to_size(300 * 200) * sizeof(DP_Pixel8);
+    DP_CompressTestBuffer buffer =
+        compress(TEST_ARGS, DP_image_pixels(img), size);
+    OK(buffer.size < size, "compressed %zu bytes down to %zu", size,
+       buffer.size);
+    UINT_EQ_OK(DP_read_bigendian_uint32(buffer.data), DP_size_to_uint32(size),
+               "header holds uncompressed size");
+
+    DP_Image *result =
+        DP_image_new_from_compressed(300, 200, buffer.data, buffer.size);
+    if (NOT_NULL_OK(result, "decompress image (error: %s)", DP_error())) {
+        OK(images_equal(img, result), "decompressed image matches");
+    }
+    DP_image_free(result);
+    DP_free(buffer.data);
+    DP_image_free(img);
+}
+
+// A 2x2 image the way the Qt client sends it, compressed with qCompress.
+static void compress_reads_qcompress_output(TEST_PARAMS)
+{
+    static const unsigned char fixture[] = {
+        0x00, 0x00, 0x00, 0x10, 0x78, 0x9c, 0x13, 0x54, 0x32,
+        0xfe, 0x2f, 0x88, 0x84, 0x01, 0x29, 0xc4, 0x05, 0x95,
+    };
+    DP_Image *img =
+        DP_image_new_from_compressed(2, 2, fixture, sizeof(fixture));
+    if (NOT_NULL_OK(img, "decompress fixture (error: %s)", DP_error())) {
+        for (int i = 0; i < 4; ++i) {
+            UINT_EQ_OK(DP_image_pixels(img)[i].color, 0xff332211u,
+                       "fixture pixel %d", i);
+        }
+    }
+    DP_image_free(img);
+}
+
+static void compress_rejects_bad_sizes(TEST_PARAMS)
+{
+    DP_Image *img = make_image(2, 2);
+    DP_CompressTestBuffer buffer =
+        compress(TEST_ARGS, DP_image_pixels(img), 4 * sizeof(DP_Pixel8));
+    NOK(DP_image_new_from_compressed(1, 1, buffer.data, buffer.size),
+        "image smaller than the header says is rejected");
+    NOK(DP_image_new_from_compressed(4, 4, buffer.data, buffer.size),
+        "image larger than the header says is rejected");
+    NOK(DP_image_new_from_compressed(2, 2, buffer.data, 3),
+        "input too short for a header is rejected");
+    DP_free(buffer.data);
+    DP_image_free(img);
+
+    // Output is capped to what the header claims, so data that inflates to
+    // more than that fails instead of allocating whatever it wants.
+    size_t bomb_size = 1024 * 1024;
+    unsigned char *zeroes = DP_malloc_zeroed(bomb_size);
+    buffer = compress(TEST_ARGS, zeroes, bomb_size);
+    DP_write_bigendian_uint32(DP_size_to_uint32(4 * sizeof(DP_Pixel8)),
+                              buffer.data);
+    NOK(DP_image_new_from_compressed(2, 2, buffer.data, buffer.size),
+        "data inflating beyond the header size is rejected (%s)", DP_error());
+    DP_free(buffer.data);
+    DP_free(zeroes);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(compress_image_round_trip);
+    REGISTER_TEST(compress_reads_qcompress_output);
+    REGISTER_TEST(compress_rejects_bad_sizes);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
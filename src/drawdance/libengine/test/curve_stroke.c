@@ -0,0 +1,284 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+#define MAX_DABS 1024
+
+// Positions are in pixels for pixel dabs and quarter pixels for classic ones.
+typedef struct DP_CurveTestDabs {
+    int count;
+    int x[MAX_DABS];
+    int y[MAX_DABS];
+    int size[MAX_DABS];
+} DP_CurveTestDabs;
+
+static void push_dab(DP_CurveTestDabs *ctds, int x, int y, int size)
+{
+    if (ctds->count < MAX_DABS) {
+        ctds->x[ctds->count] = x;
+        ctds->y[ctds->count] = y;
+        ctds->size[ctds->count] = size;
+        ++ctds->count;
+    }
+}
+
+static void collect_dabs(void *user, DP_Message *msg)
+{
+    DP_CurveTestDabs *ctds = user;
+    switch (DP_message_type(msg)) {
+    case DP_MSG_DRAW_DABS_CLASSIC: {
+        DP_MsgDrawDabsClassic *mddc = DP_message_internal(msg);
+        int x = DP_msg_draw_dabs_classic_x(mddc);
+        int y = DP_msg_draw_dabs_classic_y(mddc);
+        int count;
+        const DP_ClassicDab *dabs =
+            DP_msg_draw_dabs_classic_dabs(mddc, &count);
+        for (int i = 0; i < count; ++i) {
+            const DP_ClassicDab *cd = DP_classic_dab_at(dabs, i);
+            x += DP_classic_dab_x(cd);
+            y += DP_classic_dab_y(cd);
+            push_dab(ctds, x, y, DP_classic_dab_size(cd));
+        }
+        break;
+    }
+    case DP_MSG_DRAW_DABS_PIXEL: {
+        DP_MsgDrawDabsPixel *mddp = DP_message_internal(msg);
+        int x = DP_msg_draw_dabs_pixel_x(mddp);
+        int y = DP_msg_draw_dabs_pixel_y(mddp);
+        int count;
+        const DP_PixelDab *dabs = DP_msg_draw_dabs_pixel_dabs(mddp, &count);
+        for (int i = 0; i < count; ++i) {
+            const DP_PixelDab *pd = DP_pixel_dab_at(dabs, i);
+            x += DP_pixel_dab_x(pd);
+            y += DP_pixel_dab_y(pd);
+            push_dab(ctds, x, y, DP_pixel_dab_size(pd));
+        }
+        break;
+    }
+    default:
+        break;
+    }
+    DP_message_decref(msg);
+}
+
+static void init_brush(DP_ClassicBrush *cb, DP_BrushShape shape,
+                       float size_min, float size_max, float spacing)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = size_min;
+    cb->size.max = size_max;
+    for (int i = 0; i < DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT; ++i) {
+        cb->size.curve.values[i] =
+            DP_int_to_float(i)
+            / DP_int_to_float(DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT - 1);
+    }
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = spacing;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = shape;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+    cb->size_dynamic.type = size_min < size_max
+                              ? DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE
+                              : DP_CLASSIC_BRUSH_DYNAMIC_NONE;
+}
+
+static void stroke_curve(const DP_ClassicBrush *cb, DP_StrokeCurve curve,
+                         const DP_CurvePoint *points, int count,
+                         DP_CurveTestDabs *ctds)
+{
+    ctds->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_dabs, NULL, ctds);
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    long long time_msec =
+        DP_brush_engine_stroke_curve(be, curve, points, count, 0, NULL);
+    DP_brush_engine_stroke_end(be, time_msec + 10, NULL, false);
+    DP_brush_engine_free(be);
+}
+
+static float polygon_length(const DP_CurvePoint *points, int count)
+{
+    float length = 0.0f;
+    for (int i = 1; i < count; ++i) {
+        length += hypotf(points[i].x - points[i - 1].x,
+                         points[i].y - points[i - 1].y);
+    }
+    return length;
+}
+
+
+static void curve_straight_bezier_is_evenly_spaced(TEST_PARAMS)
+{
+    // 102 pixels long with a dab every 5 pixels, the last one at 100.
+    DP_CurvePoint points[] = {
+        {10.0f, 20.0f, 1.0f},
+        {44.0f, 20.0f, 1.0f},
+        {78.0f, 20.0f, 1.0f},
+        {112.0f, 20.0f, 1.0f},
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 10.0f, 10.0f, 0.5f);
+    DP_CurveTestDabs ctds;
+    stroke_curve(&cb, DP_STROKE_CURVE_BEZIER, points, DP_ARRAY_LENGTH(points),
+                 &ctds);
+    INT_EQ_OK(ctds.count, 21, "straight curve has as many dabs as a line");
+    bool even = true;
+    for (int i = 1; i < ctds.count; ++i) {
+        int step = ctds.x[i] - ctds.x[i - 1];
+        if (step < 19 || step > 21 || ctds.y[i] != 80) {
+            even = false;
+            DIAG("dab %d at %d, %d", i, ctds.x[i], ctds.y[i]);
+        }
+    }
+    OK(even, "dabs are 5 pixels apart in a straight line");
+}
+
+static void curve_dab_count_is_bounded(TEST_PARAMS)
+{
+    // The curve is longer than the line between its ends, but shorter than
+    // the polygon through its control points.
+    DP_CurvePoint points[] = {
+        {20.0f, 120.0f, 1.0f},
+        {20.0f, 40.0f, 1.0f},
+        {60.0f, 20.0f, 1.0f},
+        {140.0f, 20.0f, 1.0f},
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 10.0f, 10.0f, 0.5f);
+    DP_CurveTestDabs ctds;
+    stroke_curve(&cb, DP_STROKE_CURVE_BEZIER, points, DP_ARRAY_LENGTH(points),
+                 &ctds);
+    float chord = hypotf(points[3].x - points[0].x, points[3].y - points[0].y);
+    float polygon = polygon_length(points, DP_ARRAY_LENGTH(points));
+    int min = DP_float_to_int(chord / 5.0f) + 1;
+    int max = DP_float_to_int(polygon / 5.0f) + 1;
+    OK(ctds.count >= min && ctds.count <= max,
+       "curve has %d dabs, between %d and %d", ctds.count, min, max);
+}
+
+static void curve_hits_endpoints_exactly(TEST_PARAMS)
+{
+    DP_CurvePoint points[] = {
+        {3.5f, 4.5f, 1.0f},
+        {20.5f, 30.5f, 1.0f},
+        {40.5f, 10.5f, 1.0f},
+        {60.5f, 50.5f, 1.0f},
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, 1.0f, 0.1f);
+    DP_CurveTestDabs ctds;
+    stroke_curve(&cb, DP_STROKE_CURVE_CATMULL_ROM, points,
+                 DP_ARRAY_LENGTH(points), &ctds);
+
+    if (OK(ctds.count > 0, "curve has %d dabs", ctds.count)) {
+        int last = ctds.count - 1;
+        OK(ctds.x[0] == 3 && ctds.y[0] == 4, "first dab at 3, 4, got %d, %d",
+           ctds.x[0], ctds.y[0]);
+        OK(ctds.x[last] == 60 && ctds.y[last] == 50,
+           "last dab at 60, 50, got %d, %d", ctds.x[last], ctds.y[last]);
+        // Catmull-Rom splines go through all of their points.
+        bool hit_second = false;
+        bool hit_third = false;
+        for (int i = 0; i < ctds.count; ++i) {
+            hit_second = hit_second || (ctds.x[i] == 20 && ctds.y[i] == 30);
+            hit_third = hit_third || (ctds.x[i] == 40 && ctds.y[i] == 10);
+        }
+        OK(hit_second, "curve goes through the second point");
+        OK(hit_third, "curve goes through the third point");
+    }
+
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 10.0f, 10.0f, 0.5f);
+    stroke_curve(&cb, DP_STROKE_CURVE_BEZIER, points, DP_ARRAY_LENGTH(points),
+                 &ctds);
+    if (OK(ctds.count > 0, "soft curve has %d dabs", ctds.count)) {
+        OK(ctds.x[0] == 14 && ctds.y[0] == 18,
+           "first soft dab at 3.5, 4.5, got %d, %d quarter pixels",
+           ctds.x[0], ctds.y[0]);
+    }
+}
+
+static void curve_follows_tight_bends(TEST_PARAMS)
+{
+    // A quarter circle with a radius of 40 around 50, 50.
+    float k = 40.0f * 0.5522847f;
+    DP_CurvePoint points[] = {
+        {90.0f, 50.0f, 1.0f},
+        {90.0f, 50.0f + k, 1.0f},
+        {50.0f + k, 90.0f, 1.0f},
+        {50.0f, 90.0f, 1.0f},
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 10.0f, 10.0f, 0.1f);
+    DP_CurveTestDabs ctds;
+    stroke_curve(&cb, DP_STROKE_CURVE_BEZIER, points, DP_ARRAY_LENGTH(points),
+                 &ctds);
+
+    if (OK(ctds.count > 50, "quarter circle has %d dabs", ctds.count)) {
+        float worst = 0.0f;
+        for (int i = 0; i < ctds.count; ++i) {
+            float dx = DP_int_to_float(ctds.x[i]) / 4.0f - 50.0f;
+            float dy = DP_int_to_float(ctds.y[i]) / 4.0f - 50.0f;
+            float deviation = fabsf(hypotf(dx, dy) - 40.0f);
+            if (deviation > worst) {
+                worst = deviation;
+            }
+        }
+        OK(worst < 1.0f, "dabs stay on the circle, off by at most %f",
+           (double)worst);
+    }
+}
+
+static void curve_interpolates_pressure(TEST_PARAMS)
+{
+    DP_CurvePoint points[] = {
+        {10.0f, 20.0f, 0.0f},
+        {60.0f, 20.0f, 0.5f},
+        {110.0f, 20.0f, 1.0f},
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 1.0f, 21.0f, 0.1f);
+    DP_CurveTestDabs ctds;
+    stroke_curve(&cb, DP_STROKE_CURVE_CATMULL_ROM, points,
+                 DP_ARRAY_LENGTH(points), &ctds);
+
+    if (OK(ctds.count > 10, "curve has %d dabs", ctds.count)) {
+        bool non_decreasing = true;
+        for (int i = 1; i < ctds.count; ++i) {
+            if (ctds.size[i] < ctds.size[i - 1]) {
+                non_decreasing = false;
+            }
+        }
+        OK(non_decreasing, "dabs grow along with the pressure");
+        OK(ctds.size[0] < 2 * 256, "first dab is small, got size %d",
+           ctds.size[0]);
+        OK(ctds.size[ctds.count - 1] > 19 * 256,
+           "last dab is big, got size %d", ctds.size[ctds.count - 1]);
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(curve_straight_bezier_is_evenly_spaced);
+    REGISTER_TEST(curve_dab_count_is_bounded);
+    REGISTER_TEST(curve_hits_endpoints_exactly);
+    REGISTER_TEST(curve_follows_tight_bends);
+    REGISTER_TEST(curve_interpolates_pressure);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  64
+#define POINT_COUNT  1000
+#define MAX_MESSAGES 1024
+
+typedef struct DP_BatchingTestMessages {
+    int count;
+    int dab_messages;
+    int dabs;
+    int pen_ups;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_BatchingTestMessages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_BatchingTestMessages *btms = user;
+    switch (DP_message_type(msg)) {
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        ++btms->dab_messages;
+        btms->dabs +=
+            DP_msg_draw_dabs_classic_dabs_count(DP_message_internal(msg));
+        break;
+    case DP_MSG_PEN_UP:
+        ++btms->pen_ups;
+        break;
+    default:
+        break;
+    }
+    if (btms->count < MAX_MESSAGES) {
+        btms->msgs[btms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void free_messages(DP_BatchingTestMessages *btms)
+{
+    for (int i = 0; i < btms->count; ++i) {
+        DP_message_decref(btms->msgs[i]);
+    }
+    btms->count = 0;
+}
+
+static void init_brush(DP_ClassicBrush *cb)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 4.0f;
+    cb->size.max = 4.0f;
+    cb->hardness.max = 0.8f;
+    cb->opacity.max = 0.5f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+static DP_BrushEngine *begin_stroke(const DP_ClassicBrush *cb,
+                                    DP_BatchingTestMessages *btms)
+{
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, btms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    return be;
+}
+
+// A wavy line across the canvas, one point per millisecond. If flush_each is
+// set, dabs get flushed after every point, like it used to be done.
+static void draw_long_stroke(bool flush_each, DP_BatchingTestMessages *btms)
+{
+    *btms = (DP_BatchingTestMessages){0};
+    DP_ClassicBrush cb;
+    init_brush(&cb);
+    DP_BrushEngine *be = begin_stroke(&cb, btms);
+    for (int i = 0; i < POINT_COUNT; ++i) {
+        double t = i / (double)(POINT_COUNT - 1);
+        float x = (float)(8.0 + 48.0 * t);
+        float y = (float)(32.0 + 16.0 * sin(t * 12.0));
+        DP_brush_engine_stroke_to(
+            be, (DP_BrushPoint){x, y, 1.0f, 0.0f, 0.0f, 0.0f, i}, NULL);
+        if (flush_each) {
+            DP_brush_engine_dabs_flush(be);
+        }
+    }
+    DP_brush_engine_stroke_end(be, POINT_COUNT, NULL, true);
+    DP_brush_engine_free(be);
+}
+
+// Replays the messages onto an empty canvas. Returns the layer's pixels,
+// CANVAS_SIZE * CANVAS_SIZE of them, to be freed by the caller.
+static DP_Pixel15 *replay(TEST_PARAMS, DP_DrawContext *dc,
+                          DP_BatchingTestMessages *btms)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    for (int i = 0; i < btms->count; ++i) {
+        handle(TEST_ARGS, ch, dc, btms->msgs[i]);
+    }
+    btms->count = 0;
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    DP_Pixel15 *pixels =
+        DP_malloc(sizeof(*pixels) * CANVAS_SIZE * CANVAS_SIZE);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            pixels[y * CANVAS_SIZE + x] = DP_layer_content_pixel_at(lc, x, y);
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    return pixels;
+}
+
+
+static void batching_merges_long_stroke(TEST_PARAMS)
+{
+    DP_BatchingTestMessages batched, unbatched;
+    draw_long_stroke(false, &batched);
+    draw_long_stroke(true, &unbatched);
+
+    // A second of input with a flush at most every 80 milliseconds, plus one
+    // for whatever's left over at the end of the stroke.
+    OK(batched.dab_messages >= 11 && batched.dab_messages <= 14,
+       "1000 points make %d dab messages", batched.dab_messages);
+    OK(unbatched.dab_messages > 5 * batched.dab_messages,
+       "flushing every point makes %d dab messages",
+       unbatched.dab_messages);
+    INT_EQ_OK(batched.dabs, unbatched.dabs, "batching keeps all dabs");
+    INT_EQ_OK(batched.pen_ups, 1, "one pen up");
+    if (batched.count > 0) {
+        OK(DP_message_type(batched.msgs[batched.count - 1]) == DP_MSG_PEN_UP,
+           "pen up comes after all dabs");
+    }
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Pixel15 *batched_pixels = replay(TEST_ARGS, dc, &batched);
+    DP_Pixel15 *unbatched_pixels = replay(TEST_ARGS, dc, &unbatched);
+    bool painted = false;
+    bool matches = true;
+    for (int i = 0; i < CANVAS_SIZE * CANVAS_SIZE && matches; ++i) {
+        DP_Pixel15 a = batched_pixels[i];
+        DP_Pixel15 b = unbatched_pixels[i];
+        painted = painted || a.a != 0;
+        if (a.b != b.b || a.g != b.g || a.r != b.r || a.a != b.a) {
+            matches = false;
+            DIAG("pixel %d,%d differs", i % CANVAS_SIZE, i / CANVAS_SIZE);
+        }
+    }
+    OK(painted, "stroke painted something");
+    OK(matches, "batched pixels are identical to unbatched ones");
+
+    DP_free(unbatched_pixels);
+    DP_free(batched_pixels);
+    DP_draw_context_free(dc);
+}
+
+static void batching_flushes_after_delay(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb);
+    DP_BatchingTestMessages btms = {0};
+    DP_BrushEngine *be = begin_stroke(&cb, &btms);
+
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){10.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1000}, NULL);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){20.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1010}, NULL);
+    INT_EQ_OK(btms.dab_messages, 0, "fresh dabs are held back");
+
+    DP_brush_engine_poll(be, 1050, NULL);
+    INT_EQ_OK(btms.dab_messages, 0, "polling early keeps holding them");
+    DP_brush_engine_poll(be, 1080, NULL);
+    INT_EQ_OK(btms.dab_messages, 1, "polling after the delay flushes them");
+
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){30.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1100}, NULL);
+    INT_EQ_OK(btms.dab_messages, 1, "next dabs are held back again");
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){40.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1190}, NULL);
+    INT_EQ_OK(btms.dab_messages, 2, "late point flushes along with them");
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){50.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1200}, NULL);
+    INT_EQ_OK(btms.dab_messages, 2, "dabs after the flush are held back");
+
+    DP_brush_engine_stroke_end(be, 1210, NULL, true);
+    INT_EQ_OK(btms.dab_messages, 3, "stroke end flushes the rest");
+    DP_brush_engine_free(be);
+    free_messages(&btms);
+}
+
+static void batching_stops_at_stroke_end(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb);
+    DP_BatchingTestMessages btms = {0};
+    DP_BrushEngine *be = begin_stroke(&cb, &btms);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){10.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 0}, NULL);
+    DP_brush_engine_stroke_end(be, 10, NULL, true);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){11.0f, 10.0f, 1.0f, 0.0f, 0.0f, 0.0f, 20}, NULL);
+    DP_brush_engine_stroke_end(be, 30, NULL, true);
+    DP_brush_engine_free(be);
+
+    static const DP_MessageType expected[] = {
+        DP_MSG_DRAW_DABS_CLASSIC,
+        DP_MSG_PEN_UP,
+        DP_MSG_DRAW_DABS_CLASSIC,
+        DP_MSG_PEN_UP,
+    };
+    bool matches = btms.count == (int)DP_ARRAY_LENGTH(expected);
+    for (int i = 0; i < btms.count && matches; ++i) {
+        matches = DP_message_type(btms.msgs[i]) == expected[i];
+    }
+    OK(matches, "dabs aren't merged across a pen up");
+    free_messages(&btms);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(batching_merges_long_stroke);
+    REGISTER_TEST(batching_flushes_after_delay);
+    REGISTER_TEST(batching_stops_at_stroke_end);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
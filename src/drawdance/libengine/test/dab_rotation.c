@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/stamp_mask.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+#define LAYER_ID         257
+#define CANVAS_SIZE      64
+#define MASK_SIZE        32
+#define MAX_DABS         256
+// A MyPaint aspect ratio of about 3, see aspect_ratio_from_uint8 in paint.c.
+#define ELLIPSE_ASPECT   57
+#define ELLIPSE_DIAMETER 40
+
+typedef struct DP_RotationTestDabs {
+    int count;
+    uint8_t angles[MAX_DABS];
+} DP_RotationTestDabs;
+
+typedef struct DP_RotationTestShape {
+    double coverage;
+    double angle;
+    int partial;
+} DP_RotationTestShape;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+// A bar across the middle of the mask, a quarter of its height.
+static DP_StampMask *make_bar_mask(void)
+{
+    uint8_t data[MASK_SIZE * MASK_SIZE];
+    for (int y = 0; y < MASK_SIZE; ++y) {
+        for (int x = 0; x < MASK_SIZE; ++x) {
+            bool inside = y >= MASK_SIZE * 3 / 8 && y < MASK_SIZE * 5 / 8;
+            data[y * MASK_SIZE + x] = inside ? 255 : 0;
+        }
+    }
+    return DP_stamp_mask_new(MASK_SIZE, data);
+}
+
+static void set_stamp_dab(DP_UNUSED int count, DP_StampDab *dabs, void *user)
+{
+    uint8_t *angle = user;
+    DP_stamp_dab_init(dabs, 0, 0, 0, MASK_SIZE * 256, *angle, 255);
+}
+
+static void set_mypaint_dab(DP_UNUSED int count, DP_MyPaintDab *dabs,
+                            void *user)
+{
+    uint8_t *angle = user;
+    DP_mypaint_dab_init(dabs, 0, 0, 0, ELLIPSE_DIAMETER * 256, 204, 255,
+                        *angle, ELLIPSE_ASPECT);
+}
+
+// Draws the given dab message in the middle of an empty canvas and measures
+// the covered area, the angle of its long axis in degrees and the number of
+// partially covered pixels along its edge.
+static DP_RotationTestShape draw_dab(TEST_PARAMS, DP_DrawContext *dc,
+                                     DP_Message *msg)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    handle(TEST_ARGS, ch, dc, msg);
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+
+    double sum = 0.0, sum_x = 0.0, sum_y = 0.0;
+    double sum_xx = 0.0, sum_yy = 0.0, sum_xy = 0.0;
+    int partial = 0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            uint16_t alpha = DP_layer_content_pixel_at(lc, x, y).a;
+            if (alpha != 0 && alpha != DP_BIT15) {
+                ++partial;
+            }
+            double a = alpha / (double)DP_BIT15;
+            double px = x + 0.5;
+            double py = y + 0.5;
+            sum += a;
+            sum_x += a * px;
+            sum_y += a * py;
+            sum_xx += a * px * px;
+            sum_yy += a * py * py;
+            sum_xy += a * px * py;
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+
+    DP_RotationTestShape shape = {sum, 0.0, partial};
+    if (sum > 0.0) {
+        double mx = sum_x / sum;
+        double my = sum_y / sum;
+        double cxx = sum_xx / sum - mx * mx;
+        double cyy = sum_yy / sum - my * my;
+        double cxy = sum_xy / sum - mx * my;
+        shape.angle = 0.5 * atan2(2.0 * cxy, cxx - cyy) * 180.0 / M_PI;
+    }
+    return shape;
+}
+
+// Difference between the directions of two axes, which repeat every 180°.
+static double axis_difference(double a, double b)
+{
+    double d = fmod(a - b, 180.0);
+    if (d > 90.0) {
+        d -= 180.0;
+    }
+    else if (d < -90.0) {
+        d += 180.0;
+    }
+    return fabs(d);
+}
+
+static void check_rotations(TEST_PARAMS, const DP_RotationTestShape *shapes,
+                            const double *expected, int count,
+                            const char *title)
+{
+    for (int i = 0; i < count; ++i) {
+        OK(axis_difference(shapes[i].angle, expected[i]) < 2.0,
+           "%s at %g degrees has its long axis at %g", title, expected[i],
+           shapes[i].angle);
+        OK(shapes[i].coverage > shapes[0].coverage * 0.97
+               && shapes[i].coverage < shapes[0].coverage * 1.03,
+           "%s at %g degrees covers %g, unrotated covers %g", title,
+           expected[i], shapes[i].coverage, shapes[0].coverage);
+        if (i != 0) {
+            OK(shapes[i].partial >= 16,
+               "%s at %g degrees has %d anti-aliased edge pixels", title,
+               expected[i], shapes[i].partial);
+        }
+    }
+}
+
+
+static void rotated_stamp_dab(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_bar_mask();
+    DP_stamp_mask_register(sm);
+
+    uint8_t angles[] = {0, 32, 64};
+    double expected[] = {0.0, 45.0, 90.0};
+    DP_RotationTestShape shapes[DP_ARRAY_LENGTH(angles)];
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(angles); ++i) {
+        shapes[i] = draw_dab(
+            TEST_ARGS, dc,
+            DP_msg_draw_dabs_stamp_new(1, LAYER_ID, CANVAS_SIZE * 4 / 2,
+                                       CANVAS_SIZE * 4 / 2, 0xff000000,
+                                       DP_BLEND_MODE_NORMAL,
+                                       DP_stamp_mask_id(sm), 0, 256, 0,
+                                       set_stamp_dab, 1, &angles[i]));
+    }
+    check_rotations(TEST_ARGS, shapes, expected, DP_ARRAY_LENGTH(angles),
+                    "stamp dab");
+
+    DP_stamp_mask_decref(sm);
+    DP_draw_context_free(dc);
+}
+
+static void rotated_ellipse_dab(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+
+    // MyPaint dab angles are in 255ths of a turn, not 256ths.
+    uint8_t angles[] = {0, 32, 64};
+    double expected[DP_ARRAY_LENGTH(angles)];
+    DP_RotationTestShape shapes[DP_ARRAY_LENGTH(angles)];
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(angles); ++i) {
+        expected[i] = angles[i] / 255.0 * 360.0;
+        shapes[i] = draw_dab(
+            TEST_ARGS, dc,
+            DP_msg_draw_dabs_mypaint_new(1, LAYER_ID, CANVAS_SIZE * 4 / 2,
+                                         CANVAS_SIZE * 4 / 2, 0xff000000, 0, 0,
+                                         0, 0, set_mypaint_dab, 1,
+                                         &angles[i]));
+    }
+    check_rotations(TEST_ARGS, shapes, expected, DP_ARRAY_LENGTH(angles),
+                    "ellipse dab");
+
+    DP_draw_context_free(dc);
+}
+
+
+static void collect_angles(void *user, DP_Message *msg)
+{
+    DP_RotationTestDabs *rtds = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_STAMP) {
+        int count;
+        const DP_StampDab *dabs =
+            DP_msg_draw_dabs_stamp_dabs(DP_message_internal(msg), &count);
+        for (int i = 0; i < count && rtds->count < MAX_DABS; ++i) {
+            rtds->angles[rtds->count++] =
+                DP_stamp_dab_angle(DP_stamp_dab_at(dabs, i));
+        }
+    }
+    DP_message_decref(msg);
+}
+
+static void init_stamp_brush(DP_ClassicBrush *cb, DP_StampMask *sm,
+                             DP_ClassicBrushAngleMode angle_mode,
+                             float stamp_angle)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 8.0f;
+    cb->size.max = 8.0f;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_STAMP;
+    cb->stamp_mask = DP_stamp_mask_id(sm);
+    cb->stamp_angle = stamp_angle;
+    cb->stamp_angle_mode = angle_mode;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+// Strokes through the given points and collects the angles of the dabs.
+static void stroke_angles(const DP_ClassicBrush *cb, const DP_BrushPoint *bps,
+                          int count, DP_RotationTestDabs *rtds)
+{
+    rtds->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_angles, NULL, rtds);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    for (int i = 0; i < count; ++i) {
+        DP_brush_engine_stroke_to(be, bps[i], NULL);
+    }
+    DP_brush_engine_stroke_end(be, bps[count - 1].time_msec + 100, NULL,
+                               false);
+    DP_brush_engine_free(be);
+}
+
+static int angle_difference(uint8_t a, uint8_t b)
+{
+    return abs((int)(int8_t)(uint8_t)(a - b));
+}
+
+// Straight line of ten points from the origin in the given direction.
+static void line_points(DP_BrushPoint *bps, float dx, float dy, float xtilt,
+                        float ytilt)
+{
+    for (int i = 0; i < 10; ++i) {
+        float t = DP_int_to_float(i) * 4.0f;
+        bps[i] = (DP_BrushPoint){100.0f + dx * t, 100.0f + dy * t, 1.0f,
+                                 xtilt, ytilt, 0.0f, i * 10};
+    }
+}
+
+static bool all_angles_near(const DP_RotationTestDabs *rtds, int start,
+                            uint8_t expected, int tolerance)
+{
+    for (int i = start; i < rtds->count; ++i) {
+        if (angle_difference(rtds->angles[i], expected) > tolerance) {
+            return false;
+        }
+    }
+    return true;
+}
+
+
+static void angle_follows_direction(TEST_PARAMS)
+{
+    DP_StampMask *sm = make_bar_mask();
+    DP_stamp_mask_register(sm);
+
+    struct {
+        float dx, dy;
+        uint8_t expected;
+    } cases[] = {{1.0f, 0.0f, 0},
+                 {0.70710678f, 0.70710678f, 32},
+                 {0.0f, 1.0f, 64},
+                 {-1.0f, 0.0f, 128}};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        DP_ClassicBrush cb;
+        init_stamp_brush(&cb, sm, DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION, 0.0f);
+        DP_BrushPoint bps[10];
+        line_points(bps, cases[i].dx, cases[i].dy, 0.0f, 0.0f);
+        DP_RotationTestDabs rtds;
+        stroke_angles(&cb, bps, 10, &rtds);
+        // The first dab comes before there's any direction to go by.
+        if (OK(rtds.count > 2, "stroke has %d dabs", rtds.count)) {
+            OK(all_angles_near(&rtds, 1, cases[i].expected, 1),
+               "dabs follow direction %d", (int)cases[i].expected);
+        }
+    }
+
+    DP_ClassicBrush cb;
+    init_stamp_brush(&cb, sm, DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION, 0.25f);
+    DP_BrushPoint bps[10];
+    line_points(bps, 1.0f, 0.0f, 0.0f, 0.0f);
+    DP_RotationTestDabs rtds;
+    stroke_angles(&cb, bps, 10, &rtds);
+    OK(all_angles_near(&rtds, 1, 64, 1), "stamp angle is added to direction");
+
+    DP_stamp_mask_decref(sm);
+}
+
+static void angle_direction_is_smoothed(TEST_PARAMS)
+{
+    DP_StampMask *sm = make_bar_mask();
+    DP_stamp_mask_register(sm);
+    DP_ClassicBrush cb;
+    init_stamp_brush(&cb, sm, DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION, 0.0f);
+
+    // Horizontal stroke that jitters up and down by a pixel at every point,
+    // each segment on its own would be about 19 steps off horizontal.
+    DP_BrushPoint bps[26];
+    for (int i = 0; i < 26; ++i) {
+        bps[i] = (DP_BrushPoint){DP_int_to_float(i) * 4.0f,
+                                 i % 2 == 0 ? 1.0f : -1.0f,
+                                 1.0f,
+                                 0.0f,
+                                 0.0f,
+                                 0.0f,
+                                 i * 10};
+    }
+    DP_RotationTestDabs rtds;
+    stroke_angles(&cb, bps, 26, &rtds);
+    if (OK(rtds.count > 20, "stroke has %d dabs", rtds.count)) {
+        OK(all_angles_near(&rtds, rtds.count / 2, 0, 8),
+           "jitter gets smoothed out");
+    }
+
+    DP_stamp_mask_decref(sm);
+}
+
+static void angle_follows_tilt(TEST_PARAMS)
+{
+    DP_StampMask *sm = make_bar_mask();
+    DP_stamp_mask_register(sm);
+    DP_ClassicBrush cb;
+    DP_BrushPoint bps[10];
+    DP_RotationTestDabs rtds;
+
+    init_stamp_brush(&cb, sm, DP_CLASSIC_BRUSH_ANGLE_MODE_TILT, 0.0f);
+    line_points(bps, 1.0f, 0.0f, 0.0f, 30.0f);
+    stroke_angles(&cb, bps, 10, &rtds);
+    OK(all_angles_near(&rtds, 0, 64, 1), "dabs follow tilt");
+
+    line_points(bps, 1.0f, 0.0f, 0.0f, 0.0f);
+    stroke_angles(&cb, bps, 10, &rtds);
+    OK(all_angles_near(&rtds, 0, 0, 0), "no tilt leaves dabs unrotated");
+
+    init_stamp_brush(&cb, sm, DP_CLASSIC_BRUSH_ANGLE_MODE_FIXED, 0.125f);
+    line_points(bps, 0.0f, 1.0f, 0.0f, 30.0f);
+    stroke_angles(&cb, bps, 10, &rtds);
+    OK(all_angles_near(&rtds, 0, 32, 0),
+       "fixed angle ignores direction and tilt");
+
+    DP_stamp_mask_decref(sm);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(rotated_stamp_dab);
+    REGISTER_TEST(rotated_ellipse_dab);
+    REGISTER_TEST(angle_follows_direction);
+    REGISTER_TEST(angle_direction_is_smoothed);
+    REGISTER_TEST(angle_follows_tilt);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
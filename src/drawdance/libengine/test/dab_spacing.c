@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define STROKE_LENGTH 100.0f
+
+static void count_dabs(void *user, DP_Message *msg)
+{
+    int *count = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_CLASSIC) {
+        *count += DP_msg_draw_dabs_classic_dabs_count(
+            DP_message_internal(msg));
+    }
+    DP_message_decref(msg);
+}
+
+static void init_brush(DP_ClassicBrush *cb, float size_min, float size_max,
+                       float spacing)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = size_min;
+    cb->size.max = size_max;
+    for (int i = 0; i < DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT; ++i) {
+        cb->size.curve.values[i] =
+            DP_int_to_float(i)
+            / DP_int_to_float(DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT - 1);
+    }
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = spacing;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+    cb->size_dynamic.type = size_min < size_max
+                              ? DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE
+                              : DP_CLASSIC_BRUSH_DYNAMIC_NONE;
+}
+
+// Draws a straight horizontal stroke of STROKE_LENGTH pixels, with pressure
+// going from the first to the second value, and returns how many dabs it made.
+static int stroke_dab_count(const DP_ClassicBrush *cb, float pressure1,
+                            float pressure2)
+{
+    int count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(count_dabs, NULL, &count);
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){0.0f, 0.0f, pressure1, 0.0f, 0.0f, 0.0f, 0}, NULL);
+    DP_brush_engine_stroke_to(
+        be,
+        (DP_BrushPoint){STROKE_LENGTH, 0.0f, pressure2, 0.0f, 0.0f, 0.0f, 100},
+        NULL);
+    DP_brush_engine_stroke_end(be, 200, NULL, false);
+    DP_brush_engine_free(be);
+    return count;
+}
+
+
+static void spacing_constant_size(TEST_PARAMS)
+{
+    // One dab at the start, then one every spacing * diameter pixels.
+    struct {
+        float spacing;
+        int expected;
+    } cases[] = {{0.25f, 41}, {0.5f, 21}, {1.0f, 11}, {2.0f, 6}};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        DP_ClassicBrush cb;
+        init_brush(&cb, 10.0f, 10.0f, cases[i].spacing);
+        INT_EQ_OK(stroke_dab_count(&cb, 1.0f, 1.0f), cases[i].expected,
+                  "spacing %g with diameter 10", (double)cases[i].spacing);
+    }
+}
+
+static void spacing_zero_is_clamped(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, 200.0f, 200.0f, 0.0f);
+    INT_EQ_OK(stroke_dab_count(&cb, 1.0f, 1.0f), 51,
+              "zero spacing is clamped to the minimum fraction");
+
+    init_brush(&cb, 10.0f, 10.0f, 0.0f);
+    INT_EQ_OK(stroke_dab_count(&cb, 1.0f, 1.0f), 101,
+              "zero spacing on a small brush is clamped to a pixel");
+
+    init_brush(&cb, 10.0f, 10.0f, -1.0f);
+    INT_EQ_OK(stroke_dab_count(&cb, 1.0f, 1.0f), 101,
+              "negative spacing is clamped");
+}
+
+static void spacing_follows_pressure(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, 10.0f, 10.0f, 0.5f);
+    int small_count = stroke_dab_count(&cb, 1.0f, 1.0f);
+    init_brush(&cb, 30.0f, 30.0f, 0.5f);
+    int large_count = stroke_dab_count(&cb, 1.0f, 1.0f);
+
+    init_brush(&cb, 10.0f, 30.0f, 0.5f);
+    int growing_count = stroke_dab_count(&cb, 0.0f, 1.0f);
+    OK(growing_count < small_count,
+       "growing stroke has fewer dabs (%d) than small one (%d)", growing_count,
+       small_count);
+    OK(growing_count > large_count,
+       "growing stroke has more dabs (%d) than large one (%d)", growing_count,
+       large_count);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(spacing_constant_size);
+    REGISTER_TEST(spacing_zero_is_clamped);
+    REGISTER_TEST(spacing_follows_pressure);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
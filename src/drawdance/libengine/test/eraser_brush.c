@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  32
+#define MAX_MESSAGES 64
+
+typedef struct DP_EraserTestMessages {
+    int count;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_EraserTestMessages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_EraserTestMessages *etms = user;
+    if (etms->count < MAX_MESSAGES) {
+        etms->msgs[etms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void init_eraser(DP_ClassicBrush *cb, DP_BrushShape shape,
+                        float hardness, DP_ClassicBrushEraseTarget target)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 16.0f;
+    cb->size.max = 16.0f;
+    cb->hardness.max = hardness;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = shape;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->erase_target = target;
+    cb->erase_color = (DP_UPixelFloat){1.0f, 1.0f, 1.0f, 1.0f};
+    cb->erase = true;
+    cb->incremental = true;
+}
+
+// Layer filled with opaque green, optionally alpha locked.
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc,
+                                      bool alpha_lock)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                CANVAS_SIZE, CANVAS_SIZE, 0xff00ff00));
+    if (alpha_lock) {
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_layer_attributes_new(
+                   1, LAYER_ID, 0, DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK,
+                   255, DP_BLEND_MODE_NORMAL));
+    }
+    return ch;
+}
+
+// Puts down a single dab in the middle of the canvas with the given pressure
+// and returns the blend mode it got sent with, or -1 if there were no dabs.
+static int erase_dab(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                     const DP_ClassicBrush *cb, float pressure,
+                     bool eraser_override)
+{
+    DP_EraserTestMessages etms = {0, {0}};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, &etms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, eraser_override);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    DP_brush_engine_stroke_to(be,
+                              (DP_BrushPoint){CANVAS_SIZE / 2.0f,
+                                              CANVAS_SIZE / 2.0f, pressure,
+                                              0.0f, 0.0f, 0.0f, 0},
+                              NULL);
+    DP_brush_engine_stroke_end(be, 10, NULL, true);
+    DP_brush_engine_free(be);
+
+    int blend_mode = -1;
+    for (int i = 0; i < etms.count; ++i) {
+        DP_Message *msg = etms.msgs[i];
+        switch (DP_message_type(msg)) {
+        case DP_MSG_DRAW_DABS_CLASSIC:
+            blend_mode =
+                DP_msg_draw_dabs_classic_mode(DP_message_internal(msg));
+            break;
+        case DP_MSG_DRAW_DABS_PIXEL:
+        case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+            blend_mode = DP_msg_draw_dabs_pixel_mode(DP_message_internal(msg));
+            break;
+        default:
+            break;
+        }
+        handle(TEST_ARGS, ch, dc, msg);
+    }
+    return blend_mode;
+}
+
+static DP_Pixel15 pixel_at(DP_CanvasHistory *ch, int x, int y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_Pixel15 pixel =
+        DP_layer_content_pixel_at(DP_layer_routes_entry_content(lre, cs), x, y);
+    DP_canvas_state_decref(cs);
+    return pixel;
+}
+
+static DP_Pixel15 center_pixel(DP_CanvasHistory *ch)
+{
+    return pixel_at(ch, CANVAS_SIZE / 2, CANVAS_SIZE / 2);
+}
+
+
+static void erase_transparent_follows_falloff(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    init_eraser(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 0.5f,
+                DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT);
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, false);
+    INT_EQ_OK(erase_dab(TEST_ARGS, ch, dc, &cb, 1.0f, false),
+              DP_BLEND_MODE_ERASE, "erasing to transparency uses erase mode");
+
+    DP_Pixel15 center = center_pixel(ch);
+    DP_Pixel15 edge = pixel_at(ch, CANVAS_SIZE / 2 + 6, CANVAS_SIZE / 2);
+    DP_Pixel15 outside = pixel_at(ch, 1, 1);
+    OK(center.a < edge.a, "center is erased more (%u) than the edge (%u)",
+       (unsigned int)center.a, (unsigned int)edge.a);
+    OK(edge.a < DP_BIT15, "edge is partially erased, got %u",
+       (unsigned int)edge.a);
+    UINT_EQ_OK(outside.a, DP_BIT15, "outside the dab is untouched");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void erase_to_color_paints_color(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    init_eraser(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f,
+                DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR);
+
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, false);
+    INT_EQ_OK(erase_dab(TEST_ARGS, ch, dc, &cb, 1.0f, false),
+              DP_BLEND_MODE_NORMAL, "erasing to a color uses normal mode");
+    DP_Pixel15 center = center_pixel(ch);
+    UINT_EQ_OK(center.a, DP_BIT15, "erased pixel stays opaque");
+    OK(center.r == DP_BIT15 && center.g == DP_BIT15 && center.b == DP_BIT15,
+       "erased pixel has the erase color");
+    UINT_EQ_OK(pixel_at(ch, 1, 1).r, 0u, "outside the dab is untouched");
+    DP_canvas_history_free(ch);
+
+    ch = make_history(TEST_ARGS, dc, false);
+    INT_EQ_OK(erase_dab(TEST_ARGS, ch, dc, &cb, 1.0f, true),
+              DP_BLEND_MODE_ERASE, "eraser override erases to transparency");
+    UINT_EQ_OK(center_pixel(ch).a, 0u, "overridden eraser erases the pixel");
+    DP_canvas_history_free(ch);
+
+    DP_draw_context_free(dc);
+}
+
+static void erase_follows_pressure(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    DP_CanvasHistory *light_ch, *heavy_ch;
+
+    init_eraser(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f,
+                DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT);
+    cb.opacity_dynamic.type = DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
+    for (int i = 0; i < DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT; ++i) {
+        cb.opacity.curve.values[i] =
+            (float)i / (float)(DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT - 1);
+    }
+    light_ch = make_history(TEST_ARGS, dc, false);
+    erase_dab(TEST_ARGS, light_ch, dc, &cb, 0.25f, false);
+    heavy_ch = make_history(TEST_ARGS, dc, false);
+    erase_dab(TEST_ARGS, heavy_ch, dc, &cb, 1.0f, false);
+    DP_Pixel15 light = center_pixel(light_ch);
+    DP_Pixel15 heavy = center_pixel(heavy_ch);
+    OK(light.a < DP_BIT15 && light.a > heavy.a,
+       "light pressure erases less (%u) than heavy pressure (%u)",
+       (unsigned int)light.a, (unsigned int)heavy.a);
+    UINT_EQ_OK(heavy.a, 0u, "full pressure erases completely");
+    DP_canvas_history_free(heavy_ch);
+    DP_canvas_history_free(light_ch);
+
+    cb.erase_target = DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR;
+    light_ch = make_history(TEST_ARGS, dc, false);
+    erase_dab(TEST_ARGS, light_ch, dc, &cb, 0.25f, false);
+    heavy_ch = make_history(TEST_ARGS, dc, false);
+    erase_dab(TEST_ARGS, heavy_ch, dc, &cb, 1.0f, false);
+    light = center_pixel(light_ch);
+    heavy = center_pixel(heavy_ch);
+    OK(light.r > 0 && light.r < heavy.r,
+       "light pressure paints less of the color (%u) than heavy pressure (%u)",
+       (unsigned int)light.r, (unsigned int)heavy.r);
+    UINT_EQ_OK(heavy.r, DP_BIT15, "full pressure paints the color completely");
+    DP_canvas_history_free(heavy_ch);
+    DP_canvas_history_free(light_ch);
+
+    DP_draw_context_free(dc);
+}
+
+static void erase_on_alpha_lock_is_no_op(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    init_eraser(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 0.5f,
+                DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT);
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, true);
+    erase_dab(TEST_ARGS, ch, dc, &cb, 1.0f, false);
+    DP_Pixel15 center = center_pixel(ch);
+    UINT_EQ_OK(center.a, DP_BIT15, "erased pixel keeps its alpha");
+    UINT_EQ_OK(center.g, DP_BIT15, "erased pixel keeps its color");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(erase_transparent_follows_falloff);
+    REGISTER_TEST(erase_to_color_paints_color);
+    REGISTER_TEST(erase_follows_pressure);
+    REGISTER_TEST(erase_on_alpha_lock_is_no_op);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
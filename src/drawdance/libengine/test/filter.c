@@ -0,0 +1,1508 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/filter.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+#include <stdlib.h>
+#include <time.h>
+
+
+// Three by two tiles, the rightmost column is partial and stays blank.
+#define WIDTH    150
+#define HEIGHT   100
+#define LAYER_ID 257
+
+
+static bool rect_eq(DP_Rect a, DP_Rect b)
+{
+    return a.x1 == b.x1 && a.y1 == b.y1 && a.x2 == b.x2 && a.y2 == b.y2;
+}
+
+static bool rect_ok(TEST_PARAMS, DP_Rect rect, DP_Rect expected,
+                    const char *title)
+{
+    bool ok = OK(rect_eq(rect, expected), "%s", title);
+    if (!ok) {
+        DIAG("got %d,%d to %d,%d, expected %d,%d to %d,%d", rect.x1, rect.y1,
+             rect.x2, rect.y2, expected.x1, expected.y1, expected.x2,
+             expected.y2);
+    }
+    return ok;
+}
+
+// Colors vary across the pixels, alpha goes from fully transparent up to
+// opaque in steps, so there's translucent pixels everywhere.
+static DP_UPixel15 test_color(int x, int y)
+{
+    return (DP_UPixel15){
+        DP_int_to_uint16((x * 7 + y * 3) % 128 * 256),
+        DP_int_to_uint16(y * DP_BIT15 / HEIGHT),
+        DP_int_to_uint16(x * DP_BIT15 / WIDTH),
+        DP_int_to_uint16((x + y) % 5 * DP_BIT15 / 4),
+    };
+}
+
+static DP_TransientLayerContent *make_content(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE * 2; ++x) {
+            DP_UPixel15 color = test_color(x, y);
+            if (color.a != 0) {
+                DP_transient_layer_content_pixel_at_set(
+                    tlc, 1, x, y, DP_pixel15_premultiply(color));
+            }
+        }
+    }
+    return tlc;
+}
+
+static DP_Pixel15 pixel_at(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return DP_layer_content_pixel_at((DP_LayerContent *)tlc, x, y);
+}
+
+static int count_changed(DP_TransientLayerContent *a,
+                         DP_TransientLayerContent *b, DP_Rect rect)
+{
+    int changed = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            if (DP_rect_contains(rect, x, y)
+                && !DP_pixel15_equal(pixel_at(a, x, y), pixel_at(b, x, y))) {
+                ++changed;
+            }
+        }
+    }
+    return changed;
+}
+
+static bool upixel15_eq(DP_UPixel15 a, DP_UPixel15 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+// Counts pixels whose unpremultiplied color doesn't match the one the given
+// function expects from the original color. Transparent pixels must stay so.
+static int count_wrong(DP_TransientLayerContent *tlc,
+                       DP_UPixel15 (*expect)(DP_UPixel15))
+{
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            // What the filter sees after the color got premultiplied.
+            DP_UPixel15 original =
+                x < DP_TILE_SIZE * 2
+                    ? DP_pixel15_unpremultiply(
+                        DP_pixel15_premultiply(test_color(x, y)))
+                    : DP_upixel15_zero();
+            DP_UPixel15 expected = original.a == 0 ? DP_upixel15_zero()
+                                                   : expect(original);
+            DP_UPixel15 actual =
+                DP_pixel15_unpremultiply(pixel_at(tlc, x, y));
+            // Premultiplying loses precision on translucent pixels.
+            if (original.a != DP_BIT15 && original.a != 0) {
+                expected =
+                    DP_pixel15_unpremultiply(DP_pixel15_premultiply(expected));
+            }
+            if (!upixel15_eq(actual, expected)) {
+                ++wrong;
+            }
+        }
+    }
+    return wrong;
+}
+
+static DP_UPixel15 expect_white(DP_UPixel15 color)
+{
+    return (DP_UPixel15){DP_BIT15, DP_BIT15, DP_BIT15, color.a};
+}
+
+static DP_UPixel15 expect_black(DP_UPixel15 color)
+{
+    return (DP_UPixel15){0, 0, 0, color.a};
+}
+
+static DP_UPixel15 expect_gray(DP_UPixel15 color)
+{
+    return (DP_UPixel15){DP_BIT15 / 2, DP_BIT15 / 2, DP_BIT15 / 2, color.a};
+}
+
+static uint16_t threshold_channel(uint16_t c)
+{
+    if (c < DP_BIT15 / 2) {
+        return 0;
+    }
+    else if (c > DP_BIT15 / 2) {
+        return DP_BIT15;
+    }
+    else {
+        return c;
+    }
+}
+
+static DP_UPixel15 expect_threshold(DP_UPixel15 color)
+{
+    return (DP_UPixel15){threshold_channel(color.b),
+                         threshold_channel(color.g),
+                         threshold_channel(color.r), color.a};
+}
+
+
+static void brightness_contrast_identity(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect everything = DP_rect_make(0, 0, WIDTH, HEIGHT);
+
+    DP_Rect changed =
+        DP_filter_brightness_contrast(tlc, 1, NULL, 0.0f, 1.0f);
+    OK(!DP_rect_valid(changed), "identity changes nothing on the whole layer");
+    INT_EQ_OK(count_changed(original, tlc, everything), 0,
+              "identity leaves all pixels bit-exact");
+
+    DP_Rect rect = DP_rect_make(10, 20, 100, 50);
+    changed = DP_filter_brightness_contrast(tlc, 1, &rect, 0.0f, 1.0f);
+    OK(!DP_rect_valid(changed), "identity changes nothing in a rectangle");
+    INT_EQ_OK(count_changed(original, tlc, everything), 0,
+              "identity in a rectangle leaves all pixels bit-exact");
+
+    DP_transient_layer_content_decref(tlc);
+    DP_transient_layer_content_decref(original);
+}
+
+static void check_extreme(TEST_PARAMS, float brightness, float contrast,
+                          DP_UPixel15 (*expect)(DP_UPixel15), const char *title)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect changed =
+        DP_filter_brightness_contrast(tlc, 1, NULL, brightness, contrast);
+    OK(rect_eq(changed, DP_rect_make(0, 0, DP_TILE_SIZE * 2, HEIGHT)),
+       "%s changes the non-blank tiles", title);
+    INT_EQ_OK(count_wrong(tlc, expect), 0, "%s clamps as expected", title);
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 0)
+           && !DP_transient_layer_content_tile_at_noinc(tlc, 2, 1),
+       "%s leaves blank tiles blank", title);
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void brightness_contrast_extremes(TEST_PARAMS)
+{
+    check_extreme(TEST_ARGS, 1.0f, 1.0f, expect_white, "full brightness");
+    check_extreme(TEST_ARGS, INFINITY, 1.0f, expect_white,
+                  "infinite brightness");
+    check_extreme(TEST_ARGS, -1.0f, 1.0f, expect_black, "no brightness");
+    check_extreme(TEST_ARGS, 0.0f, 0.0f, expect_gray, "no contrast");
+    check_extreme(TEST_ARGS, 0.0f, 1000000.0f, expect_threshold,
+                  "huge contrast");
+}
+
+static void brightness_contrast_in_rect(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Tile *untouched = DP_transient_layer_content_tile_at_noinc(tlc, 0, 1);
+
+    DP_Rect rect = DP_rect_make(40, 10, 200, 30);
+    DP_Rect changed = DP_filter_brightness_contrast(tlc, 1, &rect, 0.25f, 1.5f);
+    rect_ok(TEST_ARGS, changed, DP_rect_make(40, 10, DP_TILE_SIZE * 2 - 40, 30),
+            "changed area is clipped to the rectangle and non-blank tiles");
+
+    DP_Rect everything = DP_rect_make(0, 0, WIDTH, HEIGHT);
+    OK(count_changed(original, tlc, rect) > 0, "pixels in rectangle changed");
+    INT_EQ_OK(count_changed(original, tlc, everything)
+                  - count_changed(original, tlc, rect),
+              0, "pixels outside of rectangle unchanged");
+    OK(DP_transient_layer_content_tile_at_noinc(tlc, 0, 1) == untouched,
+       "tile outside of rectangle not replaced");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 0),
+       "blank tile in rectangle stays blank");
+
+    DP_Rect outside = DP_rect_make(WIDTH, 0, 100, 100);
+    changed = DP_filter_brightness_contrast(tlc, 1, &outside, 0.25f, 1.5f);
+    OK(!DP_rect_valid(changed), "rectangle outside of layer changes nothing");
+
+    DP_transient_layer_content_decref(tlc);
+    DP_transient_layer_content_decref(original);
+}
+
+
+#define GRADIENT_WIDTH 8
+
+// Hue ramp in steps of 45 degrees, opaque in the first row and half
+// transparent in the second.
+static const DP_UPixel8 gradient[GRADIENT_WIDTH] = {
+    {.b = 0, .g = 0, .r = 255, .a = 255},
+    {.b = 0, .g = 191, .r = 255, .a = 255},
+    {.b = 0, .g = 255, .r = 128, .a = 255},
+    {.b = 64, .g = 255, .r = 0, .a = 255},
+    {.b = 255, .g = 255, .r = 0, .a = 255},
+    {.b = 255, .g = 64, .r = 0, .a = 255},
+    {.b = 255, .g = 0, .r = 127, .a = 255},
+    {.b = 191, .g = 0, .r = 255, .a = 255},
+};
+
+static DP_TransientLayerContent *make_gradient(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(GRADIENT_WIDTH, 2, NULL);
+    for (int x = 0; x < GRADIENT_WIDTH; ++x) {
+        DP_UPixel15 color = DP_upixel8_to_15(gradient[x]);
+        DP_transient_layer_content_pixel_at_set(tlc, 1, x, 0,
+                                                DP_pixel15_premultiply(color));
+        color.a = DP_BIT15 / 2;
+        DP_transient_layer_content_pixel_at_set(tlc, 1, x, 1,
+                                                DP_pixel15_premultiply(color));
+    }
+    return tlc;
+}
+
+static bool channel8_near(uint8_t actual, uint8_t expected)
+{
+    return abs((int)actual - (int)expected) <= 1;
+}
+
+static void hsl_golden(TEST_PARAMS)
+{
+    // Rotated by 90 degrees, at half the saturation and a bit lighter.
+    static const DP_UPixel8 expected[GRADIENT_WIDTH] = {
+        {.b = 102, .g = 204, .r = 153}, {.b = 127, .g = 204, .r = 102},
+        {.b = 204, .g = 204, .r = 102}, {.b = 204, .g = 127, .r = 102},
+        {.b = 204, .g = 102, .r = 153}, {.b = 179, .g = 102, .r = 204},
+        {.b = 102, .g = 102, .r = 204}, {.b = 102, .g = 179, .r = 204},
+    };
+    DP_TransientLayerContent *tlc = make_gradient();
+    DP_Rect changed = DP_filter_hsl(tlc, 1, NULL, 90.0f, 0.5f, 0.1f);
+    OK(rect_eq(changed, DP_rect_make(0, 0, GRADIENT_WIDTH, 2)),
+       "hsl changes the whole gradient");
+
+    int wrong_color = 0;
+    int wrong_alpha = 0;
+    for (int y = 0; y < 2; ++y) {
+        for (int x = 0; x < GRADIENT_WIDTH; ++x) {
+            DP_UPixel15 up = DP_pixel15_unpremultiply(pixel_at(tlc, x, y));
+            DP_UPixel8 actual = DP_upixel15_to_8(up);
+            if (!channel8_near(actual.b, expected[x].b)
+                || !channel8_near(actual.g, expected[x].g)
+                || !channel8_near(actual.r, expected[x].r)) {
+                DIAG("pixel %d,%d is %d,%d,%d, expected %d,%d,%d", x, y,
+                     actual.r, actual.g, actual.b, expected[x].r,
+                     expected[x].g, expected[x].b);
+                ++wrong_color;
+            }
+            if (up.a != (y == 0 ? DP_BIT15 : DP_BIT15 / 2)) {
+                ++wrong_alpha;
+            }
+        }
+    }
+    INT_EQ_OK(wrong_color, 0, "hsl colors match");
+    INT_EQ_OK(wrong_alpha, 0, "hsl preserves alpha exactly");
+
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void hsl_hue_wraps(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_gradient();
+    DP_Rect everything = DP_rect_make(0, 0, GRADIENT_WIDTH, 2);
+    static const float full_turns[] = {360.0f, -720.0f, 3600.0f};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(full_turns); ++i) {
+        DP_TransientLayerContent *tlc = make_gradient();
+        DP_filter_hsl(tlc, 1, NULL, full_turns[i], 1.0f, 0.0f);
+        INT_EQ_OK(count_changed(original, tlc, everything), 0,
+                  "hue shift of %g degrees changes nothing", full_turns[i]);
+        DP_transient_layer_content_decref(tlc);
+    }
+
+    DP_TransientLayerContent *expected = make_gradient();
+    DP_filter_hsl(expected, 1, NULL, 120.0f, 1.0f, 0.0f);
+    static const float same_turns[] = {480.0f, -240.0f, -600.0f};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(same_turns); ++i) {
+        DP_TransientLayerContent *tlc = make_gradient();
+        DP_filter_hsl(tlc, 1, NULL, same_turns[i], 1.0f, 0.0f);
+        INT_EQ_OK(count_changed(expected, tlc, everything), 0,
+                  "hue shift of %g degrees is the same as 120 degrees",
+                  same_turns[i]);
+        DP_transient_layer_content_decref(tlc);
+    }
+
+    OK(DP_upixel15_to_8(DP_pixel15_unpremultiply(pixel_at(expected, 0, 0))).g
+           == 255,
+       "red shifted by 120 degrees is green");
+
+    DP_transient_layer_content_decref(expected);
+    DP_transient_layer_content_decref(original);
+}
+
+static void hsl_desaturate(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_filter_hsl(tlc, 1, NULL, 45.0f, 0.0f, 0.0f);
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_UPixel15 original =
+                x < DP_TILE_SIZE * 2
+                    ? DP_pixel15_unpremultiply(
+                        DP_pixel15_premultiply(test_color(x, y)))
+                    : DP_upixel15_zero();
+            DP_UPixel15 actual =
+                DP_pixel15_unpremultiply(pixel_at(tlc, x, y));
+            int max =
+                DP_max_int(original.r, DP_max_int(original.g, original.b));
+            int min =
+                DP_min_int(original.r, DP_min_int(original.g, original.b));
+            uint16_t l = DP_int_to_uint16((max + min + 1) / 2);
+            DP_UPixel15 gray =
+                original.a == 0
+                    ? DP_upixel15_zero()
+                    : DP_pixel15_unpremultiply(DP_pixel15_premultiply(
+                        (DP_UPixel15){l, l, l, original.a}));
+            if (actual.r != actual.g || actual.g != actual.b
+                || abs(actual.r - gray.r) > 1 || actual.a != original.a) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "zero saturation gives the gray of the lightness");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 0),
+       "desaturating leaves blank tiles blank");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void hsl_leaves_transparent_pixels(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_filter_hsl(tlc, 1, NULL, 0.0f, 1.0f, 1.0f);
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            bool transparent =
+                x >= DP_TILE_SIZE * 2 || test_color(x, y).a == 0;
+            if (transparent ? !DP_pixel15_equal(pixel, DP_pixel15_zero())
+                            : pixel.r != pixel.a) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "full lightness makes pixels white, except "
+                        "transparent ones");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static double seconds_since(clock_t start)
+{
+    return (double)(clock() - start) / (double)CLOCKS_PER_SEC;
+}
+
+// Only reports the time it takes to run over a whole opaque layer, which is
+// only meaningful in an optimized build without sanitizers.
+static void hsl_benchmark(TEST_PARAMS)
+{
+    int size = 2048;
+    DP_Tile *t = DP_tile_new_from_bgra(0, 0xff336699);
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(size, size, t);
+    DP_tile_decref(t);
+
+    clock_t start = clock();
+    DP_Rect changed = DP_filter_hsl(tlc, 1, NULL, 30.0f, 1.2f, 0.05f);
+    double seconds = seconds_since(start);
+    OK(rect_eq(changed, DP_rect_make(0, 0, size, size)),
+       "hsl changes the whole layer");
+    NOTE("hsl on %dx%d pixels took %.3fs", size, size, seconds);
+
+    DP_transient_layer_content_decref(tlc);
+}
+
+
+static DP_UPixel15 expect_inverted(DP_UPixel15 color)
+{
+    return (DP_UPixel15){DP_BIT15 - color.b, DP_BIT15 - color.g,
+                         DP_BIT15 - color.r, color.a};
+}
+
+static void invert_colors(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect changed = DP_filter_invert(tlc, 1, NULL, false);
+    rect_ok(TEST_ARGS, changed, DP_rect_make(0, 0, DP_TILE_SIZE * 2, HEIGHT),
+            "inverting colors changes the tiles with content");
+    INT_EQ_OK(count_wrong(tlc, expect_inverted), 0,
+              "colors get inverted, alpha stays the same");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 0),
+       "inverting colors leaves blank tiles blank");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void invert_alpha_fills_blank_tiles(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect changed = DP_filter_invert(tlc, 1, NULL, true);
+    rect_ok(TEST_ARGS, changed, DP_rect_make(0, 0, WIDTH, HEIGHT),
+            "inverting alpha changes the whole layer");
+    OK(DP_transient_layer_content_tile_at_noinc(tlc, 2, 0),
+       "inverting alpha fills in blank tiles");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            uint16_t alpha =
+                x < DP_TILE_SIZE * 2
+                    ? DP_pixel15_premultiply(test_color(x, y)).a
+                    : 0;
+            if (alpha == 0) {
+                // Transparent pixels turn into opaque white.
+                if (pixel.b != DP_BIT15 || pixel.g != DP_BIT15
+                    || pixel.r != DP_BIT15 || pixel.a != DP_BIT15) {
+                    ++wrong;
+                }
+            }
+            else if (pixel.a != DP_BIT15 - alpha) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "alpha gets inverted");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void invert_alpha_in_rect(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect rect = DP_rect_make(120, 10, 100, 20);
+    DP_Rect changed = DP_filter_invert(tlc, 1, &rect, true);
+    DP_Rect expected = DP_rect_make(120, 10, WIDTH - 120, 20);
+    rect_ok(TEST_ARGS, changed, expected,
+            "inverting alpha in a rect is clipped to the layer");
+    INT_EQ_OK(count_changed(original, tlc, expected), (WIDTH - 120) * 20,
+              "every pixel in the rect changes");
+    INT_EQ_OK(count_changed(original, tlc, DP_rect_make(0, 0, WIDTH, HEIGHT)),
+              (WIDTH - 120) * 20, "no pixel outside of the rect changes");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 1),
+       "blank tiles outside of the rect stay blank");
+    DP_transient_layer_content_decref(tlc);
+    DP_transient_layer_content_decref(original);
+}
+
+static void desaturate_golden(TEST_PARAMS)
+{
+    // Gray values of pure red, green and blue under each method.
+    static const struct {
+        DP_GrayscaleMethod method;
+        const char *name;
+        uint8_t gray[3];
+    } cases[] = {
+        {DP_GRAYSCALE_METHOD_LUMINANCE, "luminance", {127, 220, 76}},
+        {DP_GRAYSCALE_METHOD_LUMA, "luma", {76, 150, 29}},
+        {DP_GRAYSCALE_METHOD_AVERAGE, "average", {85, 85, 85}},
+        {DP_GRAYSCALE_METHOD_LIGHTNESS, "lightness", {128, 128, 128}},
+    };
+    static const DP_UPixel15 primaries[3] = {
+        {0, 0, DP_BIT15, DP_BIT15},
+        {0, DP_BIT15, 0, DP_BIT15},
+        {DP_BIT15, 0, 0, DP_BIT15},
+    };
+
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(cases); ++i) {
+        DP_TransientLayerContent *tlc =
+            DP_transient_layer_content_new_init(3, 2, NULL);
+        for (int x = 0; x < 3; ++x) {
+            DP_UPixel15 translucent = primaries[x];
+            translucent.a = DP_BIT15 / 2;
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x, 0, DP_pixel15_premultiply(primaries[x]));
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x, 1, DP_pixel15_premultiply(translucent));
+        }
+
+        DP_Rect changed =
+            DP_filter_desaturate(tlc, 1, NULL, cases[i].method);
+        OK(rect_eq(changed, DP_rect_make(0, 0, 3, 2)),
+           "desaturate by %s changes all primaries", cases[i].name);
+
+        int wrong_color = 0;
+        int wrong_alpha = 0;
+        for (int y = 0; y < 2; ++y) {
+            for (int x = 0; x < 3; ++x) {
+                DP_UPixel15 up = DP_pixel15_unpremultiply(pixel_at(tlc, x, y));
+                DP_UPixel8 actual = DP_upixel15_to_8(up);
+                uint8_t gray = cases[i].gray[x];
+                if (!channel8_near(actual.b, gray)
+                    || !channel8_near(actual.g, gray)
+                    || !channel8_near(actual.r, gray)) {
+                    DIAG("pixel %d,%d is %d,%d,%d, expected %d", x, y,
+                         actual.r, actual.g, actual.b, gray);
+                    ++wrong_color;
+                }
+                if (up.a != (y == 0 ? DP_BIT15 : DP_BIT15 / 2)) {
+                    ++wrong_alpha;
+                }
+            }
+        }
+        INT_EQ_OK(wrong_color, 0, "%s gray values match", cases[i].name);
+        INT_EQ_OK(wrong_alpha, 0, "desaturate by %s preserves alpha",
+                  cases[i].name);
+        DP_transient_layer_content_decref(tlc);
+    }
+}
+
+static void desaturate_skips_blank_tiles(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect changed =
+        DP_filter_desaturate(tlc, 1, NULL, DP_GRAYSCALE_METHOD_LUMINANCE);
+    rect_ok(TEST_ARGS, changed, DP_rect_make(0, 0, DP_TILE_SIZE * 2, HEIGHT),
+            "desaturating leaves out blank tiles");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 0),
+       "desaturating leaves blank tiles blank");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            if (pixel.b != pixel.g || pixel.g != pixel.r) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "all pixels are gray");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static DP_UPixel8 over_white(DP_Pixel15 pixel)
+{
+    uint16_t rest = DP_uint_to_uint16(DP_BIT15 - pixel.a);
+    return (DP_UPixel8){
+        .b = DP_channel15_to_8(DP_uint_to_uint16(pixel.b + rest)),
+        .g = DP_channel15_to_8(DP_uint_to_uint16(pixel.g + rest)),
+        .r = DP_channel15_to_8(DP_uint_to_uint16(pixel.r + rest)),
+        .a = 255,
+    };
+}
+
+static bool channel8_within_one(uint8_t a, uint8_t b)
+{
+    return abs(a - b) <= 1;
+}
+
+static void color_to_alpha_round_trip(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *tlc = make_content();
+    DP_UPixelFloat white = {1.0f, 1.0f, 1.0f, 1.0f};
+    DP_filter_color_to_alpha(tlc, 1, NULL, white, 0.0f, 1.0f);
+
+    int out_of_range = 0;
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            if (pixel.a > DP_BIT15 || pixel.b > pixel.a || pixel.g > pixel.a
+                || pixel.r > pixel.a) {
+                ++out_of_range;
+            }
+            DP_UPixel8 expected = over_white(pixel_at(original, x, y));
+            DP_UPixel8 actual = over_white(pixel);
+            if (!channel8_within_one(actual.b, expected.b)
+                || !channel8_within_one(actual.g, expected.g)
+                || !channel8_within_one(actual.r, expected.r)) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(out_of_range, 0, "no channel values out of range");
+    INT_EQ_OK(wrong, 0, "compositing over white gives back the original");
+    DP_transient_layer_content_decref(tlc);
+    DP_transient_layer_content_decref(original);
+}
+
+static void color_to_alpha_line_art(TEST_PARAMS)
+{
+    DP_Tile *t = DP_tile_new_from_bgra(0, 0xffffffff);
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, t);
+    DP_tile_decref(t);
+    // A black line with a gray anti-aliased edge.
+    uint16_t gray = DP_BIT15 / 2;
+    for (int y = 0; y < HEIGHT; ++y) {
+        DP_transient_layer_content_pixel_at_set(
+            tlc, 1, 10, y, (DP_Pixel15){0, 0, 0, DP_BIT15});
+        DP_transient_layer_content_pixel_at_set(
+            tlc, 1, 11, y, (DP_Pixel15){gray, gray, gray, DP_BIT15});
+    }
+
+    DP_UPixelFloat white = {1.0f, 1.0f, 1.0f, 1.0f};
+    DP_filter_color_to_alpha(tlc, 1, NULL, white, 0.0f, 1.0f);
+    OK(DP_pixel15_equal(pixel_at(tlc, 10, 50), (DP_Pixel15){0, 0, 0, DP_BIT15}),
+       "black stays black");
+    DP_Pixel15 edge = pixel_at(tlc, 11, 50);
+    OK(edge.b == 0 && edge.g == 0 && edge.r == 0 && edge.a == gray,
+       "gray becomes translucent black");
+    OK(DP_pixel15_equal(pixel_at(tlc, 12, 50), DP_pixel15_zero()),
+       "white becomes transparent");
+    OK(DP_pixel15_equal(pixel_at(tlc, 100, 50), DP_pixel15_zero()),
+       "white tiles become transparent");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void color_to_alpha_threshold(TEST_PARAMS)
+{
+    DP_Tile *t = DP_tile_new_from_bgra(0, 0xfff8f8f8);
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, t);
+    DP_Pixel15 near_white = DP_tile_pixels(t)[0];
+    DP_tile_decref(t);
+    DP_UPixelFloat white = {1.0f, 1.0f, 1.0f, 1.0f};
+
+    DP_Rect changed =
+        DP_filter_color_to_alpha(tlc, 1, NULL, white, 0.0f, 0.0f);
+    OK(!DP_rect_valid(changed), "without falloff only exact matches go");
+    OK(DP_pixel15_equal(pixel_at(tlc, 0, 0), near_white),
+       "near white stays the same");
+
+    DP_filter_color_to_alpha(tlc, 1, NULL, white, 0.05f, 0.1f);
+    OK(DP_pixel15_equal(pixel_at(tlc, 0, 0), DP_pixel15_zero()),
+       "near white within the threshold becomes transparent");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void blur_radius_zero(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *tlc = make_content();
+    float radii[] = {0.0f, -1.0f, NAN};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(radii); ++i) {
+        DP_Rect changed = DP_filter_gaussian_blur(tlc, 1, NULL, radii[i]);
+        OK(!DP_rect_valid(changed), "blur with radius %f changes nothing",
+           (double)radii[i]);
+    }
+    INT_EQ_OK(count_changed(original, tlc, DP_rect_make(0, 0, WIDTH, HEIGHT)),
+              0, "no pixels changed");
+    DP_transient_layer_content_decref(tlc);
+    DP_transient_layer_content_decref(original);
+}
+
+static void blur_flat_color(TEST_PARAMS)
+{
+    uint32_t colors[] = {0xff336699, 0x80336699};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(colors); ++i) {
+        DP_Tile *t = DP_tile_new_from_bgra(0, colors[i]);
+        DP_TransientLayerContent *tlc =
+            DP_transient_layer_content_new_init(WIDTH, HEIGHT, t);
+        DP_tile_decref(t);
+        DP_Rect rect = DP_rect_make(50, 20, 60, 60);
+        DP_Rect changed = DP_filter_gaussian_blur(tlc, 1, &rect, 8.0f);
+        OK(!DP_rect_valid(changed),
+           "blurring flat color %08x changes nothing, not even at the edges "
+           "of the layer",
+           colors[i]);
+        changed = DP_filter_gaussian_blur(tlc, 1, NULL, 1000.0f);
+        OK(!DP_rect_valid(changed),
+           "huge blur radius gets clamped and changes nothing either");
+        DP_transient_layer_content_decref(tlc);
+    }
+}
+
+static void blur_keeps_color_of_transparent_edges(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Pixel15 red = {0, 0, DP_BIT15, DP_BIT15};
+    for (int y = 40; y < 60; ++y) {
+        for (int x = 50; x < 80; ++x) {
+            DP_transient_layer_content_pixel_at_set(tlc, 1, x, y, red);
+        }
+    }
+
+    DP_Rect changed = DP_filter_gaussian_blur(tlc, 1, NULL, 6.0f);
+    OK(DP_rect_valid(changed), "blur changes something");
+    int wrong = 0;
+    int translucent = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            if (pixel.a != 0 && pixel.a != DP_BIT15) {
+                ++translucent;
+            }
+            // Premultiplied red stays red, it doesn't get darkened by the
+            // transparent black around it.
+            if (pixel.b != 0 || pixel.g != 0 || pixel.r > pixel.a
+                || pixel.a - pixel.r > 1) {
+                ++wrong;
+            }
+        }
+    }
+    OK(translucent > 0, "blur makes translucent pixels");
+    INT_EQ_OK(wrong, 0, "translucent pixels stay red");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void blur_spreads_into_blank_tiles(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_content();
+    DP_Rect changed = DP_filter_gaussian_blur(tlc, 1, NULL, 4.0f);
+    rect_ok(TEST_ARGS, changed,
+            DP_rect_make(0, 0, DP_min_int(DP_TILE_SIZE * 3, WIDTH), HEIGHT),
+            "blur spreads into the blank tiles");
+    DP_Pixel15 pixel = pixel_at(tlc, DP_TILE_SIZE * 2, 10);
+    OK(pixel.a != 0, "blank pixel next to content got filled in");
+    pixel = pixel_at(tlc, WIDTH - 1, 10);
+    OK(DP_pixel15_equal(pixel, DP_pixel15_zero()),
+       "blank pixel far away from content stays blank");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void blur_rect_has_no_seams(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *whole = make_content();
+    DP_TransientLayerContent *part = make_content();
+    DP_filter_gaussian_blur(whole, 1, NULL, 5.0f);
+    DP_Rect rect = DP_rect_make(30, 20, 70, 50);
+    DP_filter_gaussian_blur(part, 1, &rect, 5.0f);
+
+    int wrong_inside = 0;
+    int wrong_outside = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(part, x, y);
+            if (DP_rect_contains(rect, x, y)) {
+                if (!DP_pixel15_equal(pixel, pixel_at(whole, x, y))) {
+                    ++wrong_inside;
+                }
+            }
+            else if (!DP_pixel15_equal(pixel, pixel_at(original, x, y))) {
+                ++wrong_outside;
+            }
+        }
+    }
+    INT_EQ_OK(wrong_inside, 0,
+              "blurring a rect gives the same result as blurring everything");
+    INT_EQ_OK(wrong_outside, 0, "pixels outside of the rect stay the same");
+
+    DP_transient_layer_content_decref(part);
+    DP_transient_layer_content_decref(whole);
+    DP_transient_layer_content_decref(original);
+}
+
+static DP_TransientLayerContent *make_shifted_content(int dx, int dy)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    for (int y = 0; y < 40; ++y) {
+        for (int x = 0; x < 40; ++x) {
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x + dx, y + dy,
+                DP_pixel15_premultiply(test_color(x + 1, y)));
+        }
+    }
+    return tlc;
+}
+
+static void blur_has_no_tile_seams(TEST_PARAMS)
+{
+    // The same pattern once within a single tile and once straddling the
+    // corner between four of them must come out the same when blurred.
+    DP_TransientLayerContent *inside = make_shifted_content(12, 12);
+    DP_TransientLayerContent *across = make_shifted_content(44, 44);
+    DP_filter_gaussian_blur(inside, 1, NULL, 3.0f);
+    DP_filter_gaussian_blur(across, 1, NULL, 3.0f);
+
+    int wrong = 0;
+    for (int y = 0; y < 64; ++y) {
+        for (int x = 0; x < 64; ++x) {
+            if (!DP_pixel15_equal(pixel_at(inside, x, y),
+                                  pixel_at(across, x + 32, y + 32))) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "blur across tile boundaries has no seams");
+
+    DP_transient_layer_content_decref(across);
+    DP_transient_layer_content_decref(inside);
+}
+
+// Only reports the time it takes, which is only meaningful in an optimized
+// build without sanitizers.
+static void blur_benchmark(TEST_PARAMS)
+{
+    int size = 1024;
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(size, size, NULL);
+    for (int y = 0; y < size; y += 2) {
+        for (int x = y % 4; x < size; x += 4) {
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x, y,
+                DP_pixel15_premultiply(test_color(x % WIDTH, y % HEIGHT)));
+        }
+    }
+
+    clock_t start = clock();
+    DP_Rect changed = DP_filter_gaussian_blur(tlc, 1, NULL, 10.0f);
+    double seconds = seconds_since(start);
+    OK(rect_eq(changed, DP_rect_make(0, 0, size, size)),
+       "blur changes the whole layer");
+    NOTE("blur with radius 10 on %dx%d pixels took %.3fs", size, size,
+         seconds);
+
+    DP_transient_layer_content_decref(tlc);
+}
+
+#define CHECKER_SIZE 32
+#define CHECKER_CELL 8
+
+static DP_TransientLayerContent *make_blurred_checkerboard(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(CHECKER_SIZE, CHECKER_SIZE, NULL);
+    for (int y = 0; y < CHECKER_SIZE; ++y) {
+        for (int x = 0; x < CHECKER_SIZE; ++x) {
+            bool white = (x / CHECKER_CELL + y / CHECKER_CELL) % 2 == 0;
+            uint16_t c = white ? DP_BIT15 : 0;
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x, y, (DP_Pixel15){c, c, c, DP_BIT15});
+        }
+    }
+    DP_filter_gaussian_blur(tlc, 1, NULL, 2.0f);
+    return tlc;
+}
+
+static uint8_t gray8_at(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return DP_upixel15_to_8(DP_pixel15_unpremultiply(pixel_at(tlc, x, y))).r;
+}
+
+static void sharpen_golden(TEST_PARAMS)
+{
+    // Across the middle of the first two cells of the checkerboard, going
+    // from white to black and back to the start of the next white cell.
+    static const uint8_t expected[16] = {
+        255, 255, 255, 255, 255, 255, 255, 194,
+        61,  0,   0,   0,   0,   0,   0,   61,
+    };
+    DP_TransientLayerContent *tlc = make_blurred_checkerboard();
+    int before = gray8_at(tlc, 7, 4) - gray8_at(tlc, 8, 4);
+    DP_Rect changed = DP_filter_sharpen(tlc, 1, NULL, 1.0f, 2.0f, 0);
+    OK(rect_eq(changed, DP_rect_make(0, 0, CHECKER_SIZE, CHECKER_SIZE)),
+       "sharpen changes the checkerboard");
+
+    int wrong = 0;
+    for (int x = 0; x < 16; ++x) {
+        uint8_t actual = gray8_at(tlc, x, 4);
+        if (!channel8_near(actual, expected[x])) {
+            DIAG("pixel %d,4 is %d, expected %d", x, actual, expected[x]);
+            ++wrong;
+        }
+    }
+    INT_EQ_OK(wrong, 0, "sharpened values match");
+
+    int after = gray8_at(tlc, 7, 4) - gray8_at(tlc, 8, 4);
+    OK(after > before, "edge contrast increases from %d to %d", before,
+       after);
+
+    // Clamping must not make the ramp between cells bumpy or lopsided.
+    int bumps = 0;
+    int lopsided = 0;
+    for (int x = 0; x < 12; ++x) {
+        if (gray8_at(tlc, x + 1, 4) > gray8_at(tlc, x, 4)) {
+            ++bumps;
+        }
+    }
+    for (int i = 0; i < 4; ++i) {
+        int sum = gray8_at(tlc, 7 - i, 4) + gray8_at(tlc, 8 + i, 4);
+        if (sum < 254 || sum > 256) {
+            ++lopsided;
+        }
+    }
+    INT_EQ_OK(bumps, 0, "no ringing along the edge");
+    INT_EQ_OK(lopsided, 0, "white and black sides are symmetrical");
+
+    int wrong_alpha = 0;
+    for (int y = 0; y < CHECKER_SIZE; ++y) {
+        for (int x = 0; x < CHECKER_SIZE; ++x) {
+            if (pixel_at(tlc, x, y).a != DP_BIT15) {
+                ++wrong_alpha;
+            }
+        }
+    }
+    INT_EQ_OK(wrong_alpha, 0, "sharpen leaves alpha alone");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void sharpen_threshold(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_blurred_checkerboard();
+    DP_Rect changed = DP_filter_sharpen(tlc, 1, NULL, 1.0f, 2.0f, 255);
+    OK(!DP_rect_valid(changed), "maximum threshold changes nothing");
+    // Differences in the flat middle of the cells are tiny, but they're
+    // there along the ramps.
+    changed = DP_filter_sharpen(tlc, 1, NULL, 1.0f, 2.0f, 20);
+    OK(DP_rect_valid(changed), "moderate threshold changes something");
+    INT_EQ_OK(gray8_at(tlc, 2, 2), 255, "flat white stays white");
+    INT_EQ_OK(gray8_at(tlc, 12, 4), 0, "flat black stays black");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static DP_TransientLayerContent *make_blurred_red_square(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Pixel15 red = {0, 0, DP_BIT15, DP_BIT15};
+    for (int y = 40; y < 60; ++y) {
+        for (int x = 50; x < 80; ++x) {
+            DP_transient_layer_content_pixel_at_set(tlc, 1, x, y, red);
+        }
+    }
+    DP_filter_gaussian_blur(tlc, 1, NULL, 3.0f);
+    return tlc;
+}
+
+static void sharpen_no_dark_halos(TEST_PARAMS)
+{
+    DP_TransientLayerContent *blurred = make_blurred_red_square();
+    DP_TransientLayerContent *tlc = make_blurred_red_square();
+    DP_Rect changed = DP_filter_sharpen(tlc, 1, NULL, 2.0f, 4.0f, 0);
+    OK(!DP_rect_valid(changed),
+       "sharpening a single color on transparency changes nothing");
+    INT_EQ_OK(count_changed(blurred, tlc, DP_rect_make(0, 0, WIDTH, HEIGHT)),
+              0, "translucent edges don't get darkened");
+    DP_transient_layer_content_decref(blurred);
+    DP_transient_layer_content_decref(tlc);
+}
+
+static bool is_noise(int x, int y)
+{
+    return (x * 7 + y * 13) % 17 == 0;
+}
+
+static DP_Pixel15 noise_pixel(int x, int y)
+{
+    switch ((x + y) % 3) {
+    case 0:
+        return (DP_Pixel15){DP_BIT15, DP_BIT15, DP_BIT15, DP_BIT15};
+    case 1:
+        return (DP_Pixel15){0, 0, 0, DP_BIT15};
+    default:
+        // Semi-transparent speck, premultiplied green.
+        return (DP_Pixel15){0, DP_BIT15 / 4, 0, DP_BIT15 / 4};
+    }
+}
+
+static void median_salt_and_pepper(TEST_PARAMS)
+{
+    DP_Tile *t = DP_tile_new_from_bgra(0, 0xff336699);
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, t);
+    DP_Pixel15 flat = DP_tile_pixels(t)[0];
+    DP_tile_decref(t);
+    int noise = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            if (is_noise(x, y)) {
+                DP_transient_layer_content_pixel_at_set(tlc, 1, x, y,
+                                                        noise_pixel(x, y));
+                ++noise;
+            }
+        }
+    }
+
+    DP_Rect changed = DP_filter_median(tlc, 1, NULL, 1);
+    OK(DP_rect_valid(changed), "median changes something");
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            if (!DP_pixel15_equal(pixel_at(tlc, x, y), flat)) {
+                ++wrong;
+            }
+        }
+    }
+    NOTE("%d noisy pixels", noise);
+    INT_EQ_OK(wrong, 0, "salt and pepper noise is fully removed");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void median_removes_transparent_specks(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            if (is_noise(x, y)) {
+                DP_transient_layer_content_pixel_at_set(tlc, 1, x, y,
+                                                        noise_pixel(x, y));
+            }
+        }
+    }
+
+    DP_Rect changed = DP_filter_median(tlc, 1, NULL, 1);
+    rect_ok(TEST_ARGS, changed, DP_rect_make(0, 0, DP_TILE_SIZE, HEIGHT),
+            "median changes the tiles with specks");
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            if (!DP_pixel15_equal(pixel_at(tlc, x, y), DP_pixel15_zero())) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "specks on transparency vanish");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 1, 0),
+       "blank tiles stay blank");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void median_has_no_seams(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_content();
+    DP_TransientLayerContent *whole = make_content();
+    DP_TransientLayerContent *part = make_content();
+    DP_filter_median(whole, 1, NULL, 2);
+    DP_Rect rect = DP_rect_make(40, 30, 60, 50);
+    DP_filter_median(part, 1, &rect, 2);
+
+    int wrong_inside = 0;
+    int wrong_outside = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(part, x, y);
+            if (DP_rect_contains(rect, x, y)) {
+                if (!DP_pixel15_equal(pixel, pixel_at(whole, x, y))) {
+                    ++wrong_inside;
+                }
+            }
+            else if (!DP_pixel15_equal(pixel, pixel_at(original, x, y))) {
+                ++wrong_outside;
+            }
+        }
+    }
+    INT_EQ_OK(wrong_inside, 0,
+              "median of a rect is the same as the median of everything");
+    INT_EQ_OK(wrong_outside, 0, "pixels outside of the rect stay the same");
+    DP_transient_layer_content_decref(part);
+    DP_transient_layer_content_decref(whole);
+    DP_transient_layer_content_decref(original);
+
+    DP_TransientLayerContent *inside = make_shifted_content(12, 12);
+    DP_TransientLayerContent *across = make_shifted_content(44, 44);
+    DP_filter_median(inside, 1, NULL, 2);
+    DP_filter_median(across, 1, NULL, 2);
+    int wrong = 0;
+    for (int y = 0; y < 64; ++y) {
+        for (int x = 0; x < 64; ++x) {
+            if (!DP_pixel15_equal(pixel_at(inside, x, y),
+                                  pixel_at(across, x + 32, y + 32))) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "median across tile boundaries has no seams");
+    DP_transient_layer_content_decref(across);
+    DP_transient_layer_content_decref(inside);
+}
+
+static DP_TransientLayerContent *make_noisy_gradient(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            uint8_t c = DP_int_to_uint8(x * 255 / (WIDTH - 1));
+            // Slight noise every few pixels.
+            if (x % 5 == 2 && y % 5 == 2) {
+                c = DP_int_to_uint8(c < 128 ? c + 8 : c - 8);
+            }
+            uint16_t c15 = DP_channel8_to_15(c);
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x, y, (DP_Pixel15){c15, c15, c15, DP_BIT15});
+        }
+    }
+    // One big speck.
+    DP_transient_layer_content_pixel_at_set(tlc, 1, 30, 40,
+                                            (DP_Pixel15){0, 0, 0, DP_BIT15});
+    return tlc;
+}
+
+static void despeckle_leaves_small_noise(TEST_PARAMS)
+{
+    DP_TransientLayerContent *original = make_noisy_gradient();
+    DP_TransientLayerContent *tlc = make_noisy_gradient();
+    DP_Rect everything = DP_rect_make(0, 0, WIDTH, HEIGHT);
+
+    DP_Rect changed = DP_filter_despeckle(tlc, 1, NULL, 1, 16);
+    rect_ok(TEST_ARGS, changed,
+            DP_rect_make(30 / DP_TILE_SIZE * DP_TILE_SIZE,
+                         40 / DP_TILE_SIZE * DP_TILE_SIZE, DP_TILE_SIZE,
+                         DP_TILE_SIZE),
+            "despeckle only changes the tile with the speck");
+    INT_EQ_OK(count_changed(original, tlc, everything), 1,
+              "despeckle only replaces the speck");
+    DP_Pixel15 pixel = pixel_at(tlc, 30, 40);
+    OK(pixel.r > 0 && pixel.r == pixel.g && pixel.g == pixel.b,
+       "speck gets replaced with the surrounding gray");
+
+    DP_filter_median(tlc, 1, NULL, 1);
+    OK(count_changed(original, tlc, everything) > 1,
+       "median gets rid of the small noise too");
+    DP_transient_layer_content_decref(tlc);
+    DP_transient_layer_content_decref(original);
+}
+
+#define RAMP_WIDTH  256
+#define RAMP_HEIGHT 128
+
+// Gray ramp from black to white, opaque in the first rows and with alpha
+// following the gray in the ones after. The bottom row of tiles stays blank.
+static DP_TransientLayerContent *make_gray_ramp(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(RAMP_WIDTH, RAMP_HEIGHT, NULL);
+    for (int y = 0; y < 8; ++y) {
+        for (int x = 0; x < RAMP_WIDTH; ++x) {
+            uint16_t c = DP_channel8_to_15(DP_int_to_uint8(x));
+            DP_UPixel15 color = {c, c, c, y < 4 ? (uint16_t)DP_BIT15 : c};
+            DP_transient_layer_content_pixel_at_set(
+                tlc, 1, x, y, DP_pixel15_premultiply(color));
+        }
+    }
+    return tlc;
+}
+
+static uint8_t alpha8_at(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return DP_channel15_to_8(pixel_at(tlc, x, y).a);
+}
+
+static int count_bands(DP_TransientLayerContent *tlc, int y)
+{
+    int bands = 1;
+    for (int x = 1; x < RAMP_WIDTH; ++x) {
+        if (gray8_at(tlc, x, y) != gray8_at(tlc, x - 1, y)) {
+            ++bands;
+        }
+    }
+    return bands;
+}
+
+static void threshold_golden(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_gray_ramp();
+    DP_filter_threshold(tlc, 1, NULL, 100, 0, false);
+    INT_EQ_OK(count_bands(tlc, 0), 2, "threshold gives two bands");
+    INT_EQ_OK(gray8_at(tlc, 99, 0), 0, "gray below the level becomes black");
+    INT_EQ_OK(gray8_at(tlc, 100, 0), 255, "gray at the level becomes white");
+    INT_EQ_OK(alpha8_at(tlc, 99, 4), 99, "alpha is preserved below the cut");
+    INT_EQ_OK(alpha8_at(tlc, 200, 4), 200, "alpha is preserved above the cut");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 0, 1),
+       "blank tiles stay blank");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void threshold_smooth(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_gray_ramp();
+    DP_filter_threshold(tlc, 1, NULL, 100, 20, false);
+    INT_EQ_OK(gray8_at(tlc, 89, 0), 0, "below the smoothing band is black");
+    INT_EQ_OK(gray8_at(tlc, 110, 0), 255, "above the smoothing band is white");
+    bool monotonic = true;
+    for (int x = 1; x < RAMP_WIDTH; ++x) {
+        if (gray8_at(tlc, x, 0) < gray8_at(tlc, x - 1, 0)) {
+            monotonic = false;
+        }
+    }
+    OK(monotonic, "smoothing band is a ramp");
+    INT_EQ_OK(count_bands(tlc, 0), 22, "smoothing band has a step per pixel");
+    OK(abs(gray8_at(tlc, 100, 0) - 134) <= 1,
+       "level is a bit past the middle of the ramp");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void threshold_alpha(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_gray_ramp();
+    DP_filter_threshold(tlc, 1, NULL, 128, 0, true);
+    INT_EQ_OK(alpha8_at(tlc, 127, 4), 0, "alpha below the level goes away");
+    INT_EQ_OK(alpha8_at(tlc, 128, 4), 255, "alpha at the level becomes opaque");
+    INT_EQ_OK(alpha8_at(tlc, 10, 0), 255, "opaque pixels stay opaque");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void posterize_golden(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_gray_ramp();
+    DP_filter_posterize(tlc, 1, NULL, 4, false);
+    INT_EQ_OK(count_bands(tlc, 0), 4, "posterize gives four bands");
+    static const int edges[] = {43, 128, 213};
+    static const uint8_t values[] = {0, 85, 170, 255};
+    for (int i = 0; i < 3; ++i) {
+        int x = edges[i];
+        INT_EQ_OK(gray8_at(tlc, x - 1, 0), values[i], "band %d ends at %d", i,
+                  x - 1);
+        INT_EQ_OK(gray8_at(tlc, x, 0), values[i + 1], "band %d starts at %d",
+                  i + 1, x);
+    }
+    INT_EQ_OK(alpha8_at(tlc, 100, 4), 100, "alpha is preserved");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 0, 1),
+       "blank tiles stay blank");
+    DP_transient_layer_content_decref(tlc);
+}
+
+static void posterize_alpha(TEST_PARAMS)
+{
+    DP_TransientLayerContent *tlc = make_gray_ramp();
+    DP_filter_posterize(tlc, 1, NULL, 2, true);
+    INT_EQ_OK(count_bands(tlc, 0), 2, "two levels give two bands");
+    INT_EQ_OK(alpha8_at(tlc, 127, 4), 0, "low alpha rounds down");
+    INT_EQ_OK(alpha8_at(tlc, 128, 4), 255, "high alpha rounds up");
+    DP_transient_layer_content_decref(tlc);
+}
+
+// An opaque red square in the middle of an otherwise blank layer.
+static DP_LayerContent *make_shadow_source(DP_Rect square)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Pixel15 red = {0, 0, DP_BIT15, DP_BIT15};
+    for (int y = square.y1; y <= square.y2; ++y) {
+        for (int x = square.x1; x <= square.x2; ++x) {
+            DP_transient_layer_content_pixel_at_set(tlc, 1, x, y, red);
+        }
+    }
+    return DP_transient_layer_content_persist(tlc);
+}
+
+static int count_source_changed(DP_LayerContent *source, DP_Rect square)
+{
+    int changed = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = DP_layer_content_pixel_at(source, x, y);
+            DP_Pixel15 expected = DP_rect_contains(square, x, y)
+                                    ? (DP_Pixel15){0, 0, DP_BIT15, DP_BIT15}
+                                    : DP_pixel15_zero();
+            if (!DP_pixel15_equal(pixel, expected)) {
+                ++changed;
+            }
+        }
+    }
+    return changed;
+}
+
+static void drop_shadow_placement(TEST_PARAMS)
+{
+    DP_Rect square = DP_rect_make(40, 30, 20, 20);
+    DP_LayerContent *source = make_shadow_source(square);
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_UPixelFloat black = {0.0f, 0.0f, 0.0f, 1.0f};
+    DP_Rect changed = DP_filter_drop_shadow(
+        tlc, 1, DP_BLEND_MODE_NORMAL, source, 10, 5, 0.0f, black, DP_BIT15);
+    DP_Rect expected = DP_rect_make(50, 35, 20, 20);
+    rect_ok(TEST_ARGS, changed, expected, "unblurred shadow is an offset copy");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            uint16_t a = DP_rect_contains(expected, x, y) ? DP_BIT15 : 0;
+            if (pixel.b != 0 || pixel.g != 0 || pixel.r != 0 || pixel.a != a) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "shadow is black where the offset square is");
+    INT_EQ_OK(count_source_changed(source, square), 0,
+              "source layer is unmodified");
+
+    DP_Rect outside = DP_filter_drop_shadow(tlc, 1, DP_BLEND_MODE_NORMAL,
+                                            source, 100, 0, 0.0f, black,
+                                            DP_BIT15);
+    rect_ok(TEST_ARGS, outside, DP_rect_make(140, 30, 10, 20),
+            "shadow sticking out of the layer gets clipped");
+    DP_Rect transparent = DP_filter_drop_shadow(
+        tlc, 1, DP_BLEND_MODE_NORMAL, source, 10, 5, 0.0f, black, 0);
+    OK(!DP_rect_valid(transparent), "zero opacity draws nothing");
+    DP_transient_layer_content_decref(tlc);
+    DP_layer_content_decref(source);
+}
+
+static void drop_shadow_softness(TEST_PARAMS)
+{
+    DP_Rect square = DP_rect_make(40, 30, 20, 20);
+    DP_LayerContent *source = make_shadow_source(square);
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_UPixelFloat blue = {1.0f, 0.0f, 0.0f, 1.0f};
+    DP_Rect changed =
+        DP_filter_drop_shadow(tlc, 1, DP_BLEND_MODE_NORMAL, source, -8, 12,
+                              6.0f, blue, DP_BIT15 / 2);
+    // A radius of 6 has a standard deviation of 3, so the kernel reaches out
+    // 9 pixels and the shadow grows by that in every direction.
+    rect_ok(TEST_ARGS, changed, DP_rect_make(23, 33, 38, 38),
+            "affected area grows by the blur's reach");
+
+    // The shadow spans 32,42 to 51,61, half opacity.
+    uint16_t center = pixel_at(tlc, 41, 51).a;
+    OK(abs(center - DP_BIT15 / 2) <= 64, "shadow center has half opacity (%d)",
+       (int)center);
+    uint16_t edge = pixel_at(tlc, 32, 51).a;
+    OK(edge > DP_BIT15 / 5 && edge < DP_BIT15 * 3 / 10,
+       "shadow edge is soft (%d)", (int)edge);
+    uint16_t inside = pixel_at(tlc, 35, 51).a;
+    uint16_t outside = pixel_at(tlc, 28, 51).a;
+    OK(inside > edge && edge > outside && outside > 0,
+       "shadow fades out across its edge (%d, %d, %d)", (int)inside,
+       (int)edge, (int)outside);
+    OK(abs(pixel_at(tlc, 51, 51).a - edge) <= 2
+           && abs(pixel_at(tlc, 41, 42).a - edge) <= 2
+           && abs(pixel_at(tlc, 41, 61).a - edge) <= 2,
+       "shadow edges are equally soft all around");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel15 pixel = pixel_at(tlc, x, y);
+            if (DP_rect_contains(changed, x, y)) {
+                // Premultiplied blue stays blue.
+                if (pixel.g != 0 || pixel.r != 0
+                    || abs(pixel.b - pixel.a) > 1) {
+                    ++wrong;
+                }
+            }
+            else if (pixel.a != 0) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "shadow is blue and stays within the affected area");
+    INT_EQ_OK(count_source_changed(source, square), 0,
+              "source layer is unmodified");
+
+    DP_layer_content_decref(source);
+    source = make_shadow_source(DP_rect_make(0, 0, 0, 0));
+    changed = DP_filter_drop_shadow(tlc, 1, DP_BLEND_MODE_NORMAL, source, 0, 0,
+                                    6.0f, blue, DP_BIT15);
+    OK(!DP_rect_valid(changed), "blank source casts no shadow");
+    DP_transient_layer_content_decref(tlc);
+    DP_layer_content_decref(source);
+}
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static DP_LayerContent *get_layer_content(DP_CanvasState *cs)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+static void filter_region_message(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 10, 10, 50,
+                                50, 0x80336699));
+
+    DP_CanvasState *before = DP_canvas_history_get(ch);
+    DP_TransientLayerContent *expected =
+        DP_transient_layer_content_new(get_layer_content(before));
+    DP_Rect rect = DP_rect_make(30, 0, 200, 40);
+    DP_filter_invert(expected, 1, &rect, true);
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_filter_region_new(1, LAYER_ID, 30, 0, 200, 40,
+                                    DP_MSG_FILTER_REGION_FILTER_INVERT, 1, 0,
+                                    0, 0));
+    DP_CanvasState *after = DP_canvas_history_get(ch);
+    DP_LayerContent *lc = get_layer_content(after);
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            if (!DP_pixel15_equal(DP_layer_content_pixel_at(lc, x, y),
+                                  pixel_at(expected, x, y))) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "message gives the same result as the filter");
+
+    DP_canvas_state_decref(after);
+    DP_transient_layer_content_decref(expected);
+    DP_canvas_state_decref(before);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(brightness_contrast_identity);
+    REGISTER_TEST(brightness_contrast_extremes);
+    REGISTER_TEST(brightness_contrast_in_rect);
+    REGISTER_TEST(hsl_golden);
+    REGISTER_TEST(hsl_hue_wraps);
+    REGISTER_TEST(hsl_desaturate);
+    REGISTER_TEST(hsl_leaves_transparent_pixels);
+    REGISTER_TEST(hsl_benchmark);
+    REGISTER_TEST(invert_colors);
+    REGISTER_TEST(invert_alpha_fills_blank_tiles);
+    REGISTER_TEST(invert_alpha_in_rect);
+    REGISTER_TEST(desaturate_golden);
+    REGISTER_TEST(desaturate_skips_blank_tiles);
+    REGISTER_TEST(color_to_alpha_round_trip);
+    REGISTER_TEST(color_to_alpha_line_art);
+    REGISTER_TEST(color_to_alpha_threshold);
+    REGISTER_TEST(blur_radius_zero);
+    REGISTER_TEST(blur_flat_color);
+    REGISTER_TEST(blur_keeps_color_of_transparent_edges);
+    REGISTER_TEST(blur_spreads_into_blank_tiles);
+    REGISTER_TEST(blur_rect_has_no_seams);
+    REGISTER_TEST(blur_has_no_tile_seams);
+    REGISTER_TEST(blur_benchmark);
+    REGISTER_TEST(sharpen_golden);
+    REGISTER_TEST(sharpen_threshold);
+    REGISTER_TEST(sharpen_no_dark_halos);
+    REGISTER_TEST(median_salt_and_pepper);
+    REGISTER_TEST(median_removes_transparent_specks);
+    REGISTER_TEST(median_has_no_seams);
+    REGISTER_TEST(despeckle_leaves_small_noise);
+    REGISTER_TEST(threshold_golden);
+    REGISTER_TEST(threshold_smooth);
+    REGISTER_TEST(threshold_alpha);
+    REGISTER_TEST(posterize_golden);
+    REGISTER_TEST(posterize_alpha);
+    REGISTER_TEST(drop_shadow_placement);
+    REGISTER_TEST(drop_shadow_softness);
+    REGISTER_TEST(filter_region_message);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
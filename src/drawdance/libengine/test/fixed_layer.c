@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define TEXTURE_LAYER_ID 257
+#define FILL_LAYER_ID    258
+#define CANVAS_SIZE      128
+#define GROW_LEFT        100
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+static void draw_pixel(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                       int x, int y)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_pixel_square_new(1, TEXTURE_LAYER_ID, x, y,
+                                             0xff000000, DP_BLEND_MODE_NORMAL,
+                                             set_pixel_dab, 1, NULL));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+}
+
+static void set_fixed(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, bool fixed)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(
+               1, (uint16_t)layer_id, 0,
+               fixed ? DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED : 0, 255,
+               DP_BLEND_MODE_NORMAL));
+}
+
+static DP_LayerContent *layer_content(DP_CanvasState *cs, int layer_id)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+// Builds a canvas with a layer that has the same "paper texture" pattern in
+// every tile and a layer that's filled with a uniform color.
+static DP_CanvasHistory *make_fixed_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, TEXTURE_LAYER_ID, 0, 0, 0, 0,
+                                        "Texture", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, FILL_LAYER_ID, 0, 0, 0xffffffff, 0,
+                                        "Fill", 4));
+    for (int y = 0; y < CANVAS_SIZE; y += DP_TILE_SIZE) {
+        for (int x = 0; x < CANVAS_SIZE; x += DP_TILE_SIZE) {
+            draw_pixel(TEST_ARGS, ch, dc, x + 5, y + 7);
+            draw_pixel(TEST_ARGS, ch, dc, x + DP_TILE_SIZE * 5 / 8,
+                       y + DP_TILE_SIZE * 5 / 16);
+            draw_pixel(TEST_ARGS, ch, dc, x + DP_TILE_SIZE - 1,
+                       y + DP_TILE_SIZE - 1);
+        }
+    }
+    return ch;
+}
+
+static bool pixels_equal(DP_Pixel15 a, DP_Pixel15 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+static bool texture_seamless(DP_LayerContent *lc)
+{
+    int width = DP_layer_content_width(lc);
+    int height = DP_layer_content_height(lc);
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            DP_Pixel15 expected = DP_layer_content_pixel_at(
+                lc, x % DP_TILE_SIZE, y % DP_TILE_SIZE);
+            if (!pixels_equal(DP_layer_content_pixel_at(lc, x, y), expected)) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+
+static void fixed_layer_anchored_on_resize(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_fixed_history(TEST_ARGS, dc);
+    set_fixed(TEST_ARGS, ch, dc, TEXTURE_LAYER_ID, true);
+    set_fixed(TEST_ARGS, ch, dc, FILL_LAYER_ID, true);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, 0, 0, GROW_LEFT));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    INT_EQ_OK(DP_canvas_state_width(cs), CANVAS_SIZE + GROW_LEFT,
+              "canvas grew leftward");
+
+    DP_LayerContent *texture_lc = layer_content(cs, TEXTURE_LAYER_ID);
+    OK(texture_seamless(texture_lc), "fixed texture layer is seamless");
+    UINT_EQ_OK(DP_layer_content_pixel_at(texture_lc, 5, 7).a, DP_BIT15,
+               "texture extends into new area");
+
+    DP_Pixel15 pixel;
+    OK(DP_layer_content_same_pixel(layer_content(cs, FILL_LAYER_ID), &pixel),
+       "fixed fill layer is still uniform");
+    UINT_EQ_OK(pixel.a, DP_BIT15, "fixed fill layer covers new area");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void unfixed_layer_translated_on_resize(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_fixed_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, 0, 0, GROW_LEFT));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerContent *texture_lc = layer_content(cs, TEXTURE_LAYER_ID);
+    UINT_EQ_OK(DP_layer_content_pixel_at(texture_lc, 5, 7).a, 0,
+               "new area of translated layer is transparent");
+    UINT_EQ_OK(DP_layer_content_pixel_at(texture_lc, GROW_LEFT + 5, 7).a,
+               DP_BIT15, "translated layer content moved over");
+    UINT_EQ_OK(
+        DP_layer_content_pixel_at(layer_content(cs, FILL_LAYER_ID), 0, 0).a, 0,
+        "new area of translated fill layer is transparent");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fixed_layer_irregular_content_translated(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_fixed_history(TEST_ARGS, dc);
+    draw_pixel(TEST_ARGS, ch, dc, 100, 100);
+    set_fixed(TEST_ARGS, ch, dc, TEXTURE_LAYER_ID, true);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, 0, 0, GROW_LEFT));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerContent *texture_lc = layer_content(cs, TEXTURE_LAYER_ID);
+    UINT_EQ_OK(DP_layer_content_pixel_at(texture_lc, 5, 7).a, 0,
+               "non-repeating fixed layer gets translated");
+    UINT_EQ_OK(DP_layer_content_pixel_at(texture_lc, GROW_LEFT + 100, 100).a,
+               DP_BIT15, "irregular pixel moved over");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(fixed_layer_anchored_on_resize);
+    REGISTER_TEST(unfixed_layer_translated_on_resize);
+    REGISTER_TEST(fixed_layer_irregular_content_translated);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,187 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// Partial tiles along the right and bottom edges.
+#define WIDTH    150
+#define HEIGHT   100
+#define LAYER_ID 0x101
+#define PADDING  0xab
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_color(DP_UNUSED size_t size, unsigned char *out, void *user)
+{
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    uint32_t background = 0xff224466u;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_background_new(1, set_color, 4, &background));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "", 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 10, 5, 130,
+                                90, 0x80ff8800u));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 60, 50, 90,
+                                50, 0xff0011eeu));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+    return cs;
+}
+
+static bool is_padding(DP_Pixel8 pixel)
+{
+    return pixel.b == PADDING && pixel.g == PADDING && pixel.r == PADDING
+        && pixel.a == PADDING;
+}
+
+static DP_Pixel8 *make_buffer(size_t length)
+{
+    DP_Pixel8 *pixels = DP_malloc(sizeof(*pixels) * length);
+    memset(pixels, PADDING, sizeof(*pixels) * length);
+    return pixels;
+}
+
+// Flattens the area into a buffer with wider rows than needed and some
+// slack at the end, then checks it against a flat image of the same area and
+// makes sure that nothing beyond the area got written to.
+static void check_flatten_into(TEST_PARAMS, DP_CanvasState *cs, DP_Rect area,
+                               size_t stride, const char *title)
+{
+    DP_Image *img = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, &area, NULL);
+    FATAL(NOT_NULL_OK(img, "%s flat image", title));
+
+    size_t width = DP_int_to_size(DP_rect_width(area));
+    size_t height = DP_int_to_size(DP_rect_height(area));
+    size_t length = height * stride + 7;
+    DP_Pixel8 *pixels = make_buffer(length);
+    OK(DP_canvas_state_flatten_into(cs, DP_FLAT_IMAGE_RENDER_FLAGS, &area,
+                                    NULL, pixels, length, stride),
+       "%s flatten into", title);
+
+    int mismatches = 0;
+    int clobbered = 0;
+    for (size_t i = 0; i < length; ++i) {
+        size_t x = i % stride;
+        size_t y = i / stride;
+        if (x < width && y < height) {
+            DP_Pixel8 expected = DP_image_pixel_at(img, DP_size_to_int(x),
+                                                   DP_size_to_int(y));
+            if (pixels[i].color != expected.color) {
+                ++mismatches;
+            }
+        }
+        else if (!is_padding(pixels[i])) {
+            ++clobbered;
+        }
+    }
+    INT_EQ_OK(mismatches, 0, "%s pixels equal the flat image", title);
+    INT_EQ_OK(clobbered, 0, "%s padding untouched", title);
+
+    DP_free(pixels);
+    DP_image_free(img);
+}
+
+
+static void flatten_into_strided_buffer(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    DP_Rect full = DP_rect_make(0, 0, WIDTH, HEIGHT);
+    check_flatten_into(TEST_ARGS, cs, full, WIDTH, "full, tight stride");
+    check_flatten_into(TEST_ARGS, cs, full, WIDTH + 13, "full, wide stride");
+    check_flatten_into(TEST_ARGS, cs, DP_rect_make(37, 21, 101, 70), 128,
+                       "sub-rect");
+    check_flatten_into(TEST_ARGS, cs, DP_rect_make(70, 70, 1, 1), 3,
+                       "single pixel");
+    check_flatten_into(TEST_ARGS, cs, DP_rect_make(-20, 80, 200, 40), 211,
+                       "outside");
+    DP_canvas_state_decref(cs);
+}
+
+static void flatten_into_partial_last_row(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    // The last row doesn't have to be padded out to the full stride.
+    size_t stride = WIDTH + 10;
+    size_t length = (HEIGHT - 1) * stride + WIDTH;
+    DP_Pixel8 *pixels = make_buffer(length);
+    OK(DP_canvas_state_flatten_into(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL,
+                                    pixels, length, stride),
+       "flatten into buffer without trailing padding");
+    OK(is_padding(pixels[(HEIGHT - 1) * stride - 1]),
+       "padding before the last row untouched");
+    DP_free(pixels);
+    DP_canvas_state_decref(cs);
+}
+
+static void flatten_into_errors(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    size_t length = WIDTH * HEIGHT;
+    DP_Pixel8 *pixels = make_buffer(length);
+
+    NOK(DP_canvas_state_flatten_into(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL,
+                                     pixels, length, WIDTH - 1),
+        "stride less than width fails");
+    NOK(DP_canvas_state_flatten_into(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL,
+                                     pixels, length - 1, WIDTH),
+        "buffer one pixel short fails");
+    NOK(DP_canvas_state_flatten_into(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL,
+                                     pixels, length, WIDTH + 1),
+        "buffer too short for stride fails");
+    DP_Rect empty = DP_rect_make(10, 10, 0, 5);
+    NOK(DP_canvas_state_flatten_into(cs, DP_FLAT_IMAGE_RENDER_FLAGS, &empty,
+                                     NULL, pixels, length, WIDTH),
+        "empty area fails");
+
+    int clobbered = 0;
+    for (size_t i = 0; i < length; ++i) {
+        if (!is_padding(pixels[i])) {
+            ++clobbered;
+        }
+    }
+    INT_EQ_OK(clobbered, 0, "failed flattens don't touch the buffer");
+
+    DP_free(pixels);
+    DP_canvas_state_decref(cs);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(flatten_into_strided_buffer);
+    REGISTER_TEST(flatten_into_partial_last_row);
+    REGISTER_TEST(flatten_into_errors);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,185 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_props.h>
+#include <dpengine/layer_props_list.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// Left tile: a red stripe, then bare background. Right tile: a blue layer
+// that's censored, nested in a group to make sure revealing goes deep.
+#define WIDTH       (DP_TILE_SIZE * 2)
+#define HEIGHT      DP_TILE_SIZE
+#define USER        1
+#define LAYER_ID    0x101
+#define GROUP_ID    0x102
+#define CENSORED_ID 0x103
+#define BACKGROUND  0xff224466u
+#define RED         0xffff0000u
+#define BLUE        0xff0000ffu
+
+#define PAINTED_X  (DP_TILE_SIZE / 4)
+#define BARE_X     (DP_TILE_SIZE * 3 / 4)
+#define CENSORED_X (DP_TILE_SIZE * 3 / 2)
+#define Y          (DP_TILE_SIZE / 2)
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_color(DP_UNUSED size_t size, unsigned char *out, void *user)
+{
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0));
+    uint32_t background = BACKGROUND;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_background_new(USER, set_color, 4, &background));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "", 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                DP_TILE_SIZE / 2, HEIGHT, RED));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, GROUP_ID, 0, 0, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "", 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, CENSORED_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "", 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(USER, CENSORED_ID, DP_BLEND_MODE_NORMAL,
+                                DP_TILE_SIZE, 0, DP_TILE_SIZE, HEIGHT, BLUE));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(USER, CENSORED_ID, 0,
+                                       DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR,
+                                       255, DP_BLEND_MODE_NORMAL));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+    return cs;
+}
+
+static uint32_t flat_pixel_at(DP_CanvasState *cs, unsigned int flags, int x,
+                              int y)
+{
+    DP_Image *img = DP_canvas_state_to_flat_image(cs, flags, NULL, NULL);
+    uint32_t color = img ? DP_image_pixel_at(img, x, y).color : 0;
+    DP_image_free(img);
+    return color;
+}
+
+static uint32_t tile_pixel_at(DP_CanvasState *cs, unsigned int flags, int x,
+                              int y)
+{
+    DP_TransientTile *tt = DP_canvas_state_flatten_tile_at(
+        cs, x / DP_TILE_SIZE, y / DP_TILE_SIZE, flags, NULL);
+    DP_Pixel15 pixel = DP_transient_tile_pixel_at(tt, x % DP_TILE_SIZE,
+                                                  y % DP_TILE_SIZE);
+    DP_transient_tile_decref(tt);
+    return DP_pixel15_to_8(pixel).color;
+}
+
+
+static void background_toggle(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    unsigned int without = DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
+
+    UINT_EQ_OK(flat_pixel_at(cs, DP_FLAT_IMAGE_RENDER_FLAGS, BARE_X, Y),
+               BACKGROUND, "bare pixel shows the background");
+    UINT_EQ_OK(flat_pixel_at(cs, without, BARE_X, Y), 0,
+               "bare pixel is transparent without background");
+    UINT_EQ_OK(tile_pixel_at(cs, without, BARE_X, Y), 0,
+               "same when flattening a single tile");
+    UINT_EQ_OK(flat_pixel_at(cs, DP_FLAT_IMAGE_RENDER_FLAGS, PAINTED_X, Y),
+               RED, "painted pixel with background");
+    UINT_EQ_OK(flat_pixel_at(cs, without, PAINTED_X, Y), RED,
+               "painted pixel is the same without background");
+
+    DP_canvas_state_decref(cs);
+}
+
+static void censor_toggle(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    unsigned int reveal =
+        DP_FLAT_IMAGE_RENDER_FLAGS | DP_FLAT_IMAGE_REVEAL_CENSORED;
+
+    OK(flat_pixel_at(cs, DP_FLAT_IMAGE_RENDER_FLAGS, CENSORED_X, Y) != BLUE,
+       "censored layer is hidden behind the censor pattern");
+    UINT_EQ_OK(flat_pixel_at(cs, reveal, CENSORED_X, Y), BLUE,
+               "revealing shows what's in the censored layer");
+    OK(tile_pixel_at(cs, DP_FLAT_IMAGE_RENDER_FLAGS, CENSORED_X, Y) != BLUE,
+       "single tile is censored");
+    UINT_EQ_OK(tile_pixel_at(cs, reveal, CENSORED_X, Y), BLUE,
+               "single tile can be revealed");
+    UINT_EQ_OK(flat_pixel_at(cs, reveal, PAINTED_X, Y), RED,
+               "uncensored layers are unaffected by revealing");
+
+    DP_LayerPropsList *lpl = DP_canvas_state_layer_props_noinc(cs);
+    DP_LayerProps *group_lp = DP_layer_props_list_at_noinc(
+        lpl, DP_layer_props_list_index_by_id(lpl, GROUP_ID));
+    DP_LayerProps *censored_lp =
+        DP_layer_props_list_at_noinc(DP_layer_props_children_noinc(group_lp),
+                                     0);
+    OK(DP_layer_props_censored(censored_lp),
+       "canvas state itself stays censored");
+
+    DP_canvas_state_decref(cs);
+}
+
+static void sketch_opacity(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    unsigned int flags = DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
+
+    DP_SketchLayer unknown = {0x999, DP_BIT15 / 2};
+    DP_CanvasState *unchanged = DP_canvas_state_sketch_inc(cs, 1, &unknown);
+    PTR_EQ_OK(unchanged, cs, "unknown layer leaves the canvas state alone");
+    DP_canvas_state_decref(unchanged);
+
+    DP_SketchLayer sketch = {LAYER_ID, DP_BIT15 / 2};
+    DP_CanvasState *sketch_cs = DP_canvas_state_sketch_inc(cs, 1, &sketch);
+    DP_Pixel8 pixel;
+    pixel.color = flat_pixel_at(sketch_cs, flags, PAINTED_X, Y);
+    OK(pixel.a >= 127 && pixel.a <= 128, "sketch layer is half opaque (%d)",
+       (int)pixel.a);
+    UINT_EQ_OK(flat_pixel_at(cs, flags, PAINTED_X, Y), RED,
+               "original canvas state still at full opacity");
+    DP_canvas_state_decref(sketch_cs);
+
+    DP_canvas_state_decref(cs);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(background_toggle);
+    REGISTER_TEST(censor_toggle);
+    REGISTER_TEST(sketch_opacity);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
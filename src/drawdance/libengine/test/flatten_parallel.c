@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// Several tiles in each direction, but not a multiple of the tile size.
+#define WIDTH    1000
+#define HEIGHT   700
+#define GROUP_ID 0x100
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, int mode, int x, int y, int w, int h,
+                      uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_int_to_uint8(mode), DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+static void set_color(DP_UNUSED size_t size, unsigned char *out, void *user)
+{
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    uint32_t background = 0xffeeddccu;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_background_new(1, set_color, 4, &background));
+
+    static const int modes[] = {DP_BLEND_MODE_NORMAL, DP_BLEND_MODE_MULTIPLY,
+                                DP_BLEND_MODE_SCREEN, DP_BLEND_MODE_BEHIND};
+    for (int i = 0; i < 8; ++i) {
+        int layer_id = GROUP_ID + 1 + i;
+        if (i == 4) {
+            handle(TEST_ARGS, ch, dc,
+                   DP_msg_layer_tree_create_new(
+                       1, GROUP_ID, 0, DP_int_to_uint16(GROUP_ID + i), 0,
+                       DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP, "Group", 5));
+        }
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_layer_tree_create_new(
+                   1, DP_int_to_uint16(layer_id), 0,
+                   DP_int_to_uint16(i < 4 ? 0 : GROUP_ID), 0,
+                   i < 4 ? 0 : DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO, "", 0));
+        uint32_t rgb = DP_int_to_uint32(0x3a5f17 * (i + 1)) & 0xffffffu;
+        fill_rect(TEST_ARGS, ch, dc, layer_id, DP_BLEND_MODE_NORMAL, i * 37,
+                  i * 29, WIDTH - i * 91, HEIGHT - i * 53, 0xa0000000u | rgb);
+        fill_rect(TEST_ARGS, ch, dc, layer_id, modes[i % 4], WIDTH / 3,
+                  i * 71, 211 + i, 97, 0xff000000u | ~rgb);
+    }
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+    return cs;
+}
+
+static void check_flat_image(TEST_PARAMS, DP_CanvasState *cs,
+                             const DP_Rect *area_or_null, const char *title)
+{
+    DP_Image *parallel = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, area_or_null, NULL);
+    DP_Image *serial = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS | DP_FLAT_IMAGE_SINGLE_THREADED,
+        area_or_null, NULL);
+    FATAL(NOT_NULL_OK(parallel, "%s parallel image", title));
+    FATAL(NOT_NULL_OK(serial, "%s serial image", title));
+
+    int width = DP_image_width(serial);
+    int height = DP_image_height(serial);
+    INT_EQ_OK(DP_image_width(parallel), width, "%s width", title);
+    INT_EQ_OK(DP_image_height(parallel), height, "%s height", title);
+    size_t size = sizeof(DP_Pixel8) * DP_int_to_size(width * height);
+    OK(memcmp(DP_image_pixels(parallel), DP_image_pixels(serial), size) == 0,
+       "%s parallel image equals serial one", title);
+
+    DP_image_free(serial);
+    DP_image_free(parallel);
+}
+
+static void check_flat_layer(TEST_PARAMS, DP_CanvasState *cs,
+                             const DP_Rect *area_or_null, const char *title)
+{
+    DP_TransientLayerContent *parallel = DP_canvas_state_to_flat_layer(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, area_or_null, NULL);
+    DP_TransientLayerContent *serial = DP_canvas_state_to_flat_layer(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS | DP_FLAT_IMAGE_SINGLE_THREADED,
+        area_or_null, NULL);
+
+    int mismatches = 0;
+    for (int y = 0; y < DP_tile_count_round(HEIGHT); ++y) {
+        for (int x = 0; x < DP_tile_count_round(WIDTH); ++x) {
+            DP_Tile *pt = DP_layer_content_tile_at_noinc(
+                (DP_LayerContent *)parallel, x, y);
+            DP_Tile *st = DP_layer_content_tile_at_noinc(
+                (DP_LayerContent *)serial, x, y);
+            bool equal = pt && st ? memcmp(DP_tile_pixels(pt),
+                                           DP_tile_pixels(st), DP_TILE_BYTES)
+                                        == 0
+                                  : pt == st;
+            if (!equal) {
+                ++mismatches;
+            }
+        }
+    }
+    INT_EQ_OK(mismatches, 0, "%s parallel layer tiles equal serial ones",
+              title);
+
+    DP_transient_layer_content_decref(serial);
+    DP_transient_layer_content_decref(parallel);
+}
+
+static void check_flat_separated(TEST_PARAMS, DP_CanvasState *cs,
+                                 const DP_Rect *area, const char *title)
+{
+    size_t size = DP_int_to_size(DP_rect_width(*area) * DP_rect_height(*area));
+    unsigned char *parallel = DP_malloc(size * 4);
+    unsigned char *serial = DP_malloc(size * 4);
+    OK(DP_canvas_state_to_flat_separated_urgba8(
+           cs, DP_FLAT_IMAGE_RENDER_FLAGS, area, NULL, parallel),
+       "%s parallel separated", title);
+    OK(DP_canvas_state_to_flat_separated_urgba8(
+           cs, DP_FLAT_IMAGE_RENDER_FLAGS | DP_FLAT_IMAGE_SINGLE_THREADED, area,
+           NULL, serial),
+       "%s serial separated", title);
+    OK(memcmp(parallel, serial, size * 4) == 0,
+       "%s parallel channels equal serial ones", title);
+    DP_free(serial);
+    DP_free(parallel);
+}
+
+
+static void flatten_parallel_matches_serial(TEST_PARAMS)
+{
+    DP_CanvasState *cs = make_canvas(TEST_ARGS);
+    // An area with partial tiles on every side, one that sticks out of the
+    // canvas and one within a single tile.
+    DP_Rect partial = DP_rect_make(33, 17, 700, 555);
+    DP_Rect outside = DP_rect_make(-50, 600, 1200, 200);
+    DP_Rect single = DP_rect_make(70, 70, 10, 10);
+
+    check_flat_image(TEST_ARGS, cs, NULL, "full");
+    check_flat_image(TEST_ARGS, cs, &partial, "partial");
+    check_flat_image(TEST_ARGS, cs, &outside, "outside");
+    check_flat_image(TEST_ARGS, cs, &single, "single");
+    check_flat_layer(TEST_ARGS, cs, NULL, "full");
+    check_flat_layer(TEST_ARGS, cs, &partial, "partial");
+    check_flat_separated(TEST_ARGS, cs, &partial, "partial");
+
+    DP_canvas_state_decref(cs);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(flatten_parallel_matches_serial);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,1699 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/compress.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/flood_fill.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/selection.h>
+#include <dpengine/view_mode.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <limits.h>
+#include <math.h>
+#include <string.h>
+#include <time.h>
+
+
+#define LAYER_ID    257
+#define LINE_ID     258
+#define HIDDEN_ID   259
+#define CANVAS_SIZE 48
+#define HUE_COUNT   6
+#define SUBSAMPLES  4
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    return ch;
+}
+
+static void fill_layer_rect(TEST_PARAMS, DP_CanvasHistory *ch,
+                            DP_DrawContext *dc, int layer_id, int x, int y,
+                            int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int x, int y, int w, int h, uint32_t color)
+{
+    fill_layer_rect(TEST_ARGS, ch, dc, LAYER_ID, x, y, w, h, color);
+}
+
+static double linear_to_srgb(double c)
+{
+    c = DP_max_double(0.0, DP_min_double(c, 1.0));
+    return c <= 0.0031308 ? c * 12.92 : 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+}
+
+static uint32_t channel_to_uint32(double c)
+{
+    return DP_double_to_uint32(round(c * 255.0));
+}
+
+// Opaque sRGB color from OKLab lightness, chroma and hue angle.
+static void oklch_to_rgb(double lightness, double chroma, double hue,
+                         double out[3])
+{
+    double a = chroma * cos(hue);
+    double b = chroma * sin(hue);
+    double l = pow(lightness + 0.3963377774 * a + 0.2158037573 * b, 3.0);
+    double m = pow(lightness - 0.1055613458 * a - 0.0638541728 * b, 3.0);
+    double s = pow(lightness - 0.0894841775 * a - 1.2914855480 * b, 3.0);
+    out[0] = linear_to_srgb(4.0767416621 * l - 3.3077115913 * m
+                            + 0.2309699292 * s);
+    out[1] = linear_to_srgb(-1.2684380046 * l + 2.6097574011 * m
+                            - 0.3413193965 * s);
+    out[2] = linear_to_srgb(-0.0041960863 * l - 0.7034186147 * m
+                            + 1.7076147010 * s);
+}
+
+static uint32_t mix_rgb(const double fg[3], const double bg[3],
+                        double coverage)
+{
+    uint32_t color = 0xff000000u;
+    for (int i = 0; i < 3; ++i) {
+        double c = coverage * fg[i] + (1.0 - coverage) * bg[i];
+        color |= channel_to_uint32(c) << (16 - i * 8);
+    }
+    return color;
+}
+
+static double disc_coverage(int x, int y)
+{
+    double center = CANVAS_SIZE / 2.0;
+    double radius = 14.0;
+    int inside = 0;
+    for (int sy = 0; sy < SUBSAMPLES; ++sy) {
+        for (int sx = 0; sx < SUBSAMPLES; ++sx) {
+            double dx = x + (sx + 0.5) / SUBSAMPLES - center;
+            double dy = y + (sy + 0.5) / SUBSAMPLES - center;
+            if (dx * dx + dy * dy <= radius * radius) {
+                ++inside;
+            }
+        }
+    }
+    return inside / (double)(SUBSAMPLES * SUBSAMPLES);
+}
+
+// Fills from the given point with opaque red and returns the resulting image,
+// or NULL if the fill failed.
+static DP_Image *flood_fill_image(TEST_PARAMS, DP_CanvasHistory *ch, int x,
+                                  int y, double tolerance,
+                                  DP_FloodFillMetric metric,
+                                  DP_FloodFillSample sample, int gap,
+                                  int expand, int *out_x, int *out_y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_Image *img;
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, x, y,
+        DP_flood_fill_source_color((DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f}),
+        tolerance, metric, sample, LAYER_ID, NULL, CANVAS_SIZE, gap, expand, 0,
+        DP_VIEW_MODE_NORMAL, 0, 0, 0, &img, out_x, out_y, NULL, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    if (OK(result == DP_FLOOD_FILL_SUCCESS, "flood fill succeeded")) {
+        return img;
+    }
+    else {
+        DIAG("%s", DP_error());
+        return NULL;
+    }
+}
+
+// Fills from the given point and returns whether each pixel got filled. The
+// result has CANVAS_SIZE * CANVAS_SIZE entries and must be freed by the
+// caller, or is NULL if the fill failed.
+static bool *flood_fill_sampled(TEST_PARAMS, DP_CanvasHistory *ch, int x, int y,
+                                double tolerance, DP_FloodFillMetric metric,
+                                DP_FloodFillSample sample, int gap)
+{
+    int img_x, img_y;
+    DP_Image *img = flood_fill_image(TEST_ARGS, ch, x, y, tolerance, metric,
+                                     sample, gap, 0, &img_x, &img_y);
+    if (!img) {
+        return NULL;
+    }
+
+    bool *filled =
+        DP_malloc_zeroed(sizeof(*filled) * CANVAS_SIZE * CANVAS_SIZE);
+    int width = DP_image_width(img);
+    int height = DP_image_height(img);
+    for (int py = 0; py < height; ++py) {
+        for (int px = 0; px < width; ++px) {
+            int cx = img_x + px;
+            int cy = img_y + py;
+            if (cx >= 0 && cy >= 0 && cx < CANVAS_SIZE && cy < CANVAS_SIZE) {
+                filled[cy * CANVAS_SIZE + cx] =
+                    DP_image_pixel_at(img, px, py).a != 0;
+            }
+        }
+    }
+    DP_image_free(img);
+    return filled;
+}
+
+static bool *flood_fill(TEST_PARAMS, DP_CanvasHistory *ch, int x, int y,
+                        double tolerance, DP_FloodFillMetric metric, int gap)
+{
+    return flood_fill_sampled(TEST_ARGS, ch, x, y, tolerance, metric,
+                              DP_FLOOD_FILL_SAMPLE_LAYER, gap);
+}
+
+
+// Anti-aliased discs with a bit of striping inside them on a darker
+// background of the same hue. The perceptual metric should fill all of them
+// the same way, regardless of hue, and not leak out into the background.
+static void fill_antialiased_hues(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    int counts[HUE_COUNT];
+    for (int h = 0; h < HUE_COUNT; ++h) {
+        double hue = h * 2.0 * M_PI / HUE_COUNT;
+        double stripe_a[3], stripe_b[3], background[3];
+        oklch_to_rgb(0.62, 0.08, hue, stripe_a);
+        oklch_to_rgb(0.58, 0.08, hue, stripe_b);
+        oklch_to_rgb(0.25, 0.08, hue, background);
+
+        DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+        for (int y = 0; y < CANVAS_SIZE; ++y) {
+            const double *stripe = (y / 2) % 2 == 0 ? stripe_a : stripe_b;
+            for (int x = 0; x < CANVAS_SIZE; ++x) {
+                fill_rect(TEST_ARGS, ch, dc, x, y, 1, 1,
+                          mix_rgb(stripe, background, disc_coverage(x, y)));
+            }
+        }
+
+        bool *filled =
+            flood_fill(TEST_ARGS, ch, CANVAS_SIZE / 2, CANVAS_SIZE / 2, 0.15,
+                       DP_FLOOD_FILL_METRIC_PERCEPTUAL, 0);
+        counts[h] = 0;
+        if (filled) {
+            int interior_missed = 0;
+            int leaked = 0;
+            for (int y = 0; y < CANVAS_SIZE; ++y) {
+                for (int x = 0; x < CANVAS_SIZE; ++x) {
+                    double coverage = disc_coverage(x, y);
+                    bool f = filled[y * CANVAS_SIZE + x];
+                    if (f) {
+                        ++counts[h];
+                    }
+                    if (coverage >= 0.75 && !f) {
+                        ++interior_missed;
+                    }
+                    else if (coverage <= 0.25 && f) {
+                        ++leaked;
+                    }
+                }
+            }
+            INT_EQ_OK(interior_missed, 0, "hue %d fills the whole disc", h);
+            INT_EQ_OK(leaked, 0, "hue %d doesn't leak into the background",
+                      h);
+            DP_free(filled);
+        }
+        DP_canvas_history_free(ch);
+    }
+
+    int min_count = counts[0];
+    int max_count = counts[0];
+    for (int h = 1; h < HUE_COUNT; ++h) {
+        min_count = DP_min_int(min_count, counts[h]);
+        max_count = DP_max_int(max_count, counts[h]);
+    }
+    OK(max_count - min_count <= 8,
+       "fill sizes are consistent across hues, from %d to %d pixels",
+       min_count, max_count);
+    DP_draw_context_free(dc);
+}
+
+// Starting on a transparent pixel, only alpha matters. The faint smudges of
+// color get filled over no matter their hue, the more opaque wall stops it.
+static void fill_transparent_by_alpha(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 4, 0, 6, CANVAS_SIZE, 0x1aff0000);
+    fill_rect(TEST_ARGS, ch, dc, 10, 0, 6, CANVAS_SIZE, 0x1a00ff00);
+    fill_rect(TEST_ARGS, ch, dc, 16, 0, 6, CANVAS_SIZE, 0x1a0000ff);
+    fill_rect(TEST_ARGS, ch, dc, 24, 0, 4, CANVAS_SIZE, 0x80ffff00);
+
+    bool *filled = flood_fill(TEST_ARGS, ch, 1, 1, 0.2,
+                              DP_FLOOD_FILL_METRIC_PERCEPTUAL, 0);
+    if (filled) {
+        int wrong = 0;
+        for (int y = 0; y < CANVAS_SIZE; ++y) {
+            for (int x = 0; x < CANVAS_SIZE; ++x) {
+                if (filled[y * CANVAS_SIZE + x] != (x < 24)) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "fill covers everything left of the wall");
+        DP_free(filled);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fill_flat_with_both_metrics(TEST_PARAMS)
+{
+    static const DP_FloodFillMetric metrics[] = {
+        DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_METRIC_CHANNELS,
+    };
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 8, 8, 16, 16, 0xff808080);
+
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(metrics); ++i) {
+        bool *filled = flood_fill(TEST_ARGS, ch, 12, 12, 0.1, metrics[i], 0);
+        if (filled) {
+            int wrong = 0;
+            for (int y = 0; y < CANVAS_SIZE; ++y) {
+                for (int x = 0; x < CANVAS_SIZE; ++x) {
+                    bool inside = x >= 8 && x < 24 && y >= 8 && y < 24;
+                    if (filled[y * CANVAS_SIZE + x] != inside) {
+                        ++wrong;
+                    }
+                }
+            }
+            INT_EQ_OK(wrong, 0, "metric %d fills exactly the square", i);
+            DP_free(filled);
+        }
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static double distance_from_center(int x, int y)
+{
+    double dx = x + 0.5 - CANVAS_SIZE / 2.0;
+    double dy = y + 0.5 - CANVAS_SIZE / 2.0;
+    return sqrt(dx * dx + dy * dy);
+}
+
+// Circle outline two pixels thick with a four pixel wide hole at the top.
+static DP_CanvasHistory *make_broken_circle(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xffffffff);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            double d = distance_from_center(x, y);
+            bool in_gap = y < CANVAS_SIZE / 2 && x >= CANVAS_SIZE / 2 - 2
+                       && x < CANVAS_SIZE / 2 + 2;
+            if (d >= 13.0 && d < 15.0 && !in_gap) {
+                fill_rect(TEST_ARGS, ch, dc, x, y, 1, 1, 0xff000000);
+            }
+        }
+    }
+    return ch;
+}
+
+static void fill_closes_gaps(TEST_PARAMS)
+{
+    static const int gaps[] = {0, 2, 4, 6};
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_broken_circle(TEST_ARGS, dc);
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(gaps); ++i) {
+        int gap = gaps[i];
+        bool *filled = flood_fill(TEST_ARGS, ch, CANVAS_SIZE / 2,
+                                  CANVAS_SIZE / 2, 0.1,
+                                  DP_FLOOD_FILL_METRIC_PERCEPTUAL, gap);
+        if (filled) {
+            int inside_missed = 0;
+            int outside_filled = 0;
+            for (int y = 0; y < CANVAS_SIZE; ++y) {
+                for (int x = 0; x < CANVAS_SIZE; ++x) {
+                    double d = distance_from_center(x, y);
+                    bool f = filled[y * CANVAS_SIZE + x];
+                    if (d < 12.0 && !f) {
+                        ++inside_missed;
+                    }
+                    else if (d >= 15.0 && f) {
+                        ++outside_filled;
+                    }
+                }
+            }
+            INT_EQ_OK(inside_missed, 0, "gap %d fills the inside", gap);
+            if (gap < 4) {
+                OK(outside_filled > 0, "gap %d leaks out of the circle", gap);
+            }
+            else {
+                INT_EQ_OK(outside_filled, 0, "gap %d stays inside the circle",
+                          gap);
+            }
+            DP_free(filled);
+        }
+    }
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+// Square outline two pixels thick from 10,10 to 29,29, so the inside goes
+// from 12,12 to 27,27.
+static void draw_square_outline(TEST_PARAMS, DP_CanvasHistory *ch,
+                                DP_DrawContext *dc, int layer_id)
+{
+    fill_layer_rect(TEST_ARGS, ch, dc, layer_id, 10, 10, 20, 2, 0xff000000);
+    fill_layer_rect(TEST_ARGS, ch, dc, layer_id, 10, 28, 20, 2, 0xff000000);
+    fill_layer_rect(TEST_ARGS, ch, dc, layer_id, 10, 12, 2, 16, 0xff000000);
+    fill_layer_rect(TEST_ARGS, ch, dc, layer_id, 28, 12, 2, 16, 0xff000000);
+}
+
+static bool in_square(int x, int y)
+{
+    return x >= 12 && x < 28 && y >= 12 && y < 28;
+}
+
+static void check_fill_bounds(TEST_PARAMS, DP_CanvasHistory *ch, int x, int y,
+                              int expand, int expected_x, int expected_y,
+                              int expected_width, int expected_height)
+{
+    int img_x, img_y;
+    DP_Image *img = flood_fill_image(
+        TEST_ARGS, ch, x, y, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_SAMPLE_LAYER, 0, expand, &img_x, &img_y);
+    if (img) {
+        INT_EQ_OK(img_x, expected_x, "expand %d image x", expand);
+        INT_EQ_OK(img_y, expected_y, "expand %d image y", expand);
+        INT_EQ_OK(DP_image_width(img), expected_width, "expand %d image width",
+                  expand);
+        INT_EQ_OK(DP_image_height(img), expected_height,
+                  "expand %d image height", expand);
+        int wrong = 0;
+        for (int py = 0; py < DP_image_height(img); ++py) {
+            for (int px = 0; px < DP_image_width(img); ++px) {
+                // The corners don't get filled because the kernel is round.
+                bool corner = (px == 0 || px == expected_width - 1)
+                           && (py == 0 || py == expected_height - 1);
+                bool filled = DP_image_pixel_at(img, px, py).a != 0;
+                if (expand > 0 && corner ? filled : !filled) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "expand %d fills its bounds", expand);
+        DP_image_free(img);
+    }
+}
+
+static void fill_expand_and_shrink(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xffffffff);
+    draw_square_outline(TEST_ARGS, ch, dc, LAYER_ID);
+    check_fill_bounds(TEST_ARGS, ch, 20, 20, 0, 12, 12, 16, 16);
+    check_fill_bounds(TEST_ARGS, ch, 20, 20, 1, 11, 11, 18, 18);
+    check_fill_bounds(TEST_ARGS, ch, 20, 20, -3, 15, 15, 10, 10);
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, 20, 20,
+        DP_flood_fill_source_color((DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f}),
+        0.1,
+        DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID,
+        NULL, CANVAS_SIZE, 0, -8, 0, DP_VIEW_MODE_NORMAL, 0, 0, 0, NULL, NULL,
+        NULL, NULL, NULL, NULL);
+    OK(result == DP_FLOOD_FILL_NOTHING_TO_FILL,
+       "shrinking away everything leaves nothing to fill");
+    DP_canvas_state_decref(cs);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fill_expand_clipped_at_edge(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 8, 0, 1, CANVAS_SIZE, 0xff000000);
+    fill_rect(TEST_ARGS, ch, dc, 0, 8, 8, 1, 0xff000000);
+
+    int img_x, img_y;
+    DP_Image *img = flood_fill_image(
+        TEST_ARGS, ch, 2, 2, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_SAMPLE_LAYER, 0, 4, &img_x, &img_y);
+    if (img) {
+        INT_EQ_OK(img_x, 0, "expansion is clipped at the left edge");
+        INT_EQ_OK(img_y, 0, "expansion is clipped at the top edge");
+        INT_EQ_OK(DP_image_width(img), 12, "expansion grows to the right");
+        INT_EQ_OK(DP_image_height(img), 12, "expansion grows downwards");
+        OK(DP_image_pixel_at(img, 0, 0).a != 0, "corner of canvas is filled");
+        OK(DP_image_pixel_at(img, 11, 11).a == 0,
+           "round kernel leaves the far corner alone");
+        DP_image_free(img);
+    }
+
+    // Shrinking treats the canvas edges as filled, so only the sides
+    // touching the outline pull back.
+    check_fill_bounds(TEST_ARGS, ch, 2, 2, -3, 0, 0, 5, 5);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+// Fill expanded under the outline and then put behind it. The outline should
+// stay as it is, with the fill tucked neatly underneath it.
+static void fill_expand_behind(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    draw_square_outline(TEST_ARGS, ch, dc, LAYER_ID);
+
+    int img_x, img_y;
+    DP_Image *img = flood_fill_image(
+        TEST_ARGS, ch, 20, 20, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_SAMPLE_LAYER, 0, 1, &img_x, &img_y);
+    if (img) {
+        DP_CanvasState *cs = DP_canvas_history_get(ch);
+        DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+        DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+        DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+        DP_TransientLayerContent *tlc = DP_transient_layer_content_new(lc);
+        DP_transient_layer_content_put_image(tlc, 1, DP_BLEND_MODE_BEHIND,
+                                             img_x, img_y, img);
+        DP_image_free(img);
+
+        int wrong = 0;
+        for (int y = 0; y < CANVAS_SIZE; ++y) {
+            for (int x = 0; x < CANVAS_SIZE; ++x) {
+                DP_Pixel15 pixel =
+                    DP_layer_content_pixel_at((DP_LayerContent *)tlc, x, y);
+                bool in_outline = x >= 10 && x < 30 && y >= 10 && y < 30;
+                DP_Pixel15 expected;
+                if (in_square(x, y)) {
+                    expected = (DP_Pixel15){0, 0, DP_BIT15, DP_BIT15};
+                }
+                else if (in_outline) {
+                    expected = (DP_Pixel15){0, 0, 0, DP_BIT15};
+                }
+                else {
+                    expected = (DP_Pixel15){0, 0, 0, 0};
+                }
+                if (pixel.b != expected.b || pixel.g != expected.g
+                    || pixel.r != expected.r || pixel.a != expected.a) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "fill goes behind the outline without a halo");
+
+        DP_transient_layer_content_decref(tlc);
+        DP_canvas_state_decref(cs);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+typedef struct DP_FillTestCompressedImage {
+    unsigned char *data;
+    size_t size;
+} DP_FillTestCompressedImage;
+
+static unsigned char *get_compressed_image_buffer(size_t size, void *user)
+{
+    DP_FillTestCompressedImage *ci = user;
+    ci->data = DP_malloc(size);
+    return ci->data;
+}
+
+static void set_compressed_image(size_t size, unsigned char *out, void *user)
+{
+    memcpy(out, user, size);
+}
+
+// Applies the fill result the way clients do, through a put image message,
+// so the blend mode goes through the protocol. Assumes little endian pixels.
+static void put_fill_image(TEST_PARAMS, DP_CanvasHistory *ch,
+                           DP_DrawContext *dc, int blend_mode, int x, int y,
+                           DP_Image *img)
+{
+    int width = DP_image_width(img);
+    int height = DP_image_height(img);
+    DP_FillTestCompressedImage ci = {NULL, 0};
+    ci.size = DP_compress_deflate(
+        (const unsigned char *)DP_image_pixels(img),
+        DP_int_to_size(width * height) * sizeof(DP_Pixel8),
+        get_compressed_image_buffer, &ci);
+    if (OK(ci.size != 0, "compress fill image")) {
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_put_image_new(
+                   1, LAYER_ID, DP_int_to_uint8(blend_mode),
+                   DP_int_to_uint32(x), DP_int_to_uint32(y),
+                   DP_int_to_uint32(width), DP_int_to_uint32(height),
+                   set_compressed_image, ci.size, ci.data));
+    }
+    DP_free(ci.data);
+}
+
+// Anti-aliased ring from radius 10 to 14 around the center of the canvas.
+static double ring_coverage(int x, int y)
+{
+    double center = CANVAS_SIZE / 2.0;
+    int inside = 0;
+    for (int sy = 0; sy < SUBSAMPLES; ++sy) {
+        for (int sx = 0; sx < SUBSAMPLES; ++sx) {
+            double dx = x + (sx + 0.5) / SUBSAMPLES - center;
+            double dy = y + (sy + 0.5) / SUBSAMPLES - center;
+            double d = sqrt(dx * dx + dy * dy);
+            if (d >= 10.0 && d <= 14.0) {
+                ++inside;
+            }
+        }
+    }
+    return inside / (double)(SUBSAMPLES * SUBSAMPLES);
+}
+
+static DP_CanvasHistory *make_line_art(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            uint32_t alpha = channel_to_uint32(ring_coverage(x, y));
+            if (alpha != 0) {
+                fill_rect(TEST_ARGS, ch, dc, x, y, 1, 1, alpha << 24);
+            }
+        }
+    }
+    return ch;
+}
+
+static bool pixel15_eq(DP_Pixel15 a, DP_Pixel15 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+// Counts the opaque line art pixels that applying the fill result changed.
+static int count_changed_line_pixels(DP_LayerContent *before,
+                                     DP_LayerContent *after)
+{
+    int changed = 0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            DP_Pixel15 pixel = DP_layer_content_pixel_at(before, x, y);
+            if (pixel.a == DP_BIT15
+                && !pixel15_eq(pixel, DP_layer_content_pixel_at(after, x, y))) {
+                ++changed;
+            }
+        }
+    }
+    return changed;
+}
+
+static DP_LayerContent *get_layer_content(DP_CanvasState *cs)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+static void fill_behind_line_art(TEST_PARAMS)
+{
+    static const int blend_modes[] = {DP_BLEND_MODE_NORMAL,
+                                      DP_BLEND_MODE_BEHIND};
+    DP_DrawContext *dc = DP_draw_context_new();
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(blend_modes); ++i) {
+        int blend_mode = blend_modes[i];
+        const char *name = DP_blend_mode_enum_name_unprefixed(blend_mode);
+        DP_CanvasHistory *ch = make_line_art(TEST_ARGS, dc);
+        // Expanding the fill makes it reach under the line art.
+        int img_x, img_y;
+        DP_Image *img = flood_fill_image(
+            TEST_ARGS, ch, CANVAS_SIZE / 2, CANVAS_SIZE / 2, 0.0,
+            DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER, 0, 2,
+            &img_x, &img_y);
+        if (!img) {
+            DP_canvas_history_free(ch);
+            continue;
+        }
+
+        DP_CanvasState *before = DP_canvas_history_get(ch);
+        put_fill_image(TEST_ARGS, ch, dc, blend_mode, img_x, img_y, img);
+        DP_image_free(img);
+        DP_CanvasState *after = DP_canvas_history_get(ch);
+
+        int changed = count_changed_line_pixels(get_layer_content(before),
+                                                get_layer_content(after));
+        if (blend_mode == DP_BLEND_MODE_BEHIND) {
+            INT_EQ_OK(changed, 0, "%s leaves line pixels untouched", name);
+        }
+        else {
+            OK(changed > 0, "%s paints over line pixels", name);
+        }
+
+        DP_Pixel15 center = DP_layer_content_pixel_at(
+            get_layer_content(after), CANVAS_SIZE / 2, CANVAS_SIZE / 2);
+        OK(pixel15_eq(center, (DP_Pixel15){0, 0, DP_BIT15, DP_BIT15}),
+           "%s fills the inside", name);
+
+        DP_canvas_state_decref(after);
+        DP_canvas_state_decref(before);
+        DP_canvas_history_free(ch);
+    }
+    DP_draw_context_free(dc);
+}
+
+static void fill_erase_flat_region(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xff00ff00);
+    fill_rect(TEST_ARGS, ch, dc, 8, 8, 16, 16, 0xff0000ff);
+
+    int img_x, img_y;
+    DP_Image *img = flood_fill_image(TEST_ARGS, ch, 10, 10, 0.0,
+                                     DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+                                     DP_FLOOD_FILL_SAMPLE_LAYER, 0, 0, &img_x,
+                                     &img_y);
+    if (img) {
+        put_fill_image(TEST_ARGS, ch, dc, DP_BLEND_MODE_ERASE, img_x, img_y,
+                       img);
+        DP_image_free(img);
+
+        DP_CanvasState *cs = DP_canvas_history_get(ch);
+        DP_LayerContent *lc = get_layer_content(cs);
+        int wrong = 0;
+        for (int y = 0; y < CANVAS_SIZE; ++y) {
+            for (int x = 0; x < CANVAS_SIZE; ++x) {
+                DP_Pixel15 pixel = DP_layer_content_pixel_at(lc, x, y);
+                bool in_region = x >= 8 && x < 24 && y >= 8 && y < 24;
+                if (in_region ? pixel.a != 0 : pixel.a != DP_BIT15) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "erase removes exactly the filled region");
+        DP_canvas_state_decref(cs);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void set_background(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+    (void)size;
+}
+
+static int count_wrong(bool *filled, bool (*expected)(int, int))
+{
+    int wrong = 0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            if (filled[y * CANVAS_SIZE + x] != expected(x, y)) {
+                ++wrong;
+            }
+        }
+    }
+    return wrong;
+}
+
+static bool everywhere(DP_UNUSED int x, DP_UNUSED int y)
+{
+    return true;
+}
+
+static bool in_square_around_patch(int x, int y)
+{
+    bool in_patch = x >= 16 && x < 20 && y >= 16 && y < 20;
+    return in_square(x, y) && !in_patch;
+}
+
+// The line art is on a different layer than the one being filled, with a
+// white patch inside of it. There's another hidden layer with a line right
+// through the middle that should be ignored.
+static void fill_samples_merged(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    uint32_t white = 0xffffffff;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_background_new(1, set_background, 4, &white));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LINE_ID, 0, 0, 0, 0, "Lines", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, HIDDEN_ID, 0, 0, 0, 0, "Hidden", 6));
+    draw_square_outline(TEST_ARGS, ch, dc, LINE_ID);
+    fill_layer_rect(TEST_ARGS, ch, dc, LINE_ID, 16, 16, 4, 4, 0xffffffff);
+    fill_layer_rect(TEST_ARGS, ch, dc, HIDDEN_ID, 0, 20, CANVAS_SIZE, 1,
+                    0xff000000);
+    handle(TEST_ARGS, ch, dc, DP_msg_layer_visibility_new(1, HIDDEN_ID, false));
+
+    bool *filled;
+    filled = flood_fill_sampled(TEST_ARGS, ch, 20, 22, 0.1,
+                                DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+                                DP_FLOOD_FILL_SAMPLE_LAYER, 0);
+    if (filled) {
+        INT_EQ_OK(count_wrong(filled, everywhere), 0,
+                  "sampling the empty target layer fills everything");
+        DP_free(filled);
+    }
+
+    filled = flood_fill_sampled(TEST_ARGS, ch, 20, 22, 0.1,
+                                DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+                                DP_FLOOD_FILL_SAMPLE_MERGED, 0);
+    if (filled) {
+        INT_EQ_OK(count_wrong(filled, in_square), 0,
+                  "sampling merged fills the inside of the line art, "
+                  "including the patch matching the background");
+        DP_free(filled);
+    }
+
+    filled = flood_fill_sampled(
+        TEST_ARGS, ch, 20, 22, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_SAMPLE_MERGED_WITHOUT_BACKGROUND, 0);
+    if (filled) {
+        INT_EQ_OK(count_wrong(filled, in_square_around_patch), 0,
+                  "sampling merged without background fills around the "
+                  "patch");
+        DP_free(filled);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+#define OPEN_CANVAS_SIZE 512
+
+static DP_CanvasHistory *make_open_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, OPEN_CANVAS_SIZE, OPEN_CANVAS_SIZE,
+                                    0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    return ch;
+}
+
+static DP_FloodFillResult
+fill_open_canvas(DP_CanvasHistory *ch, size_t max_pixels, DP_Image **out_img,
+                 DP_FloodFillStats *out_stats,
+                 DP_FloodFillProgressFn progress, void *user)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int img_x, img_y;
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, OPEN_CANVAS_SIZE / 2, OPEN_CANVAS_SIZE / 2,
+        DP_flood_fill_source_color((DP_UPixelFloat){1.0f, 0.0f, 0.0f, 1.0f}),
+        0.1,
+        DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID,
+        NULL, OPEN_CANVAS_SIZE, 0, 0, 0, DP_VIEW_MODE_NORMAL, 0, 0, max_pixels,
+        out_img, &img_x, &img_y, out_stats, progress, user);
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static void fill_size_limit(TEST_PARAMS)
+{
+    size_t total = OPEN_CANVAS_SIZE * OPEN_CANVAS_SIZE;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_open_canvas(TEST_ARGS, dc);
+
+    DP_Image *img = NULL;
+    DP_FloodFillStats stats;
+    DP_FloodFillResult result =
+        fill_open_canvas(ch, 10000, &img, &stats, NULL, NULL);
+    OK(result == DP_FLOOD_FILL_TOO_LARGE, "fill over the limit is too large");
+    OK(!img, "no image for a fill that's too large");
+    OK(stats.pixels > 10000 && stats.pixels < total,
+       "stopped shortly after the limit at %zu pixels", stats.pixels);
+    OK(DP_rect_valid(stats.bounds), "bounds of the aborted fill are valid");
+
+    result = fill_open_canvas(ch, total, &img, &stats, NULL, NULL);
+    OK(result == DP_FLOOD_FILL_SUCCESS, "fill right at the limit succeeds");
+    OK(stats.pixels == total, "stats count all %zu pixels", stats.pixels);
+    OK(stats.bounds.x1 == 0 && stats.bounds.y1 == 0
+           && stats.bounds.x2 == OPEN_CANVAS_SIZE - 1
+           && stats.bounds.y2 == OPEN_CANVAS_SIZE - 1,
+       "stats bounds cover the whole canvas");
+    DP_image_free(img);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+typedef struct DP_CancelTestCounter {
+    int calls;
+    int cancel_after;
+} DP_CancelTestCounter;
+
+static bool cancel_after_calls(void *user, DP_UNUSED DP_FloodFillStage stage,
+                               DP_UNUSED size_t filled, DP_UNUSED size_t area)
+{
+    DP_CancelTestCounter *counter = user;
+    return ++counter->calls > counter->cancel_after;
+}
+
+static void fill_cancel_midway(TEST_PARAMS)
+{
+    size_t total = OPEN_CANVAS_SIZE * OPEN_CANVAS_SIZE;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_open_canvas(TEST_ARGS, dc);
+
+    // Preparing the fill checks for cancellation once at the beginning, once
+    // after getting the layer and once per row while figuring out which
+    // pixels to fill. Let it get a bit into the actual filling after that.
+    DP_CancelTestCounter counter = {0, OPEN_CANVAS_SIZE + 4};
+    DP_Image *img = NULL;
+    DP_FloodFillStats stats;
+    DP_FloodFillResult result =
+        fill_open_canvas(ch, 0, &img, &stats, cancel_after_calls, &counter);
+    OK(result == DP_FLOOD_FILL_CANCELLED, "fill got cancelled");
+    OK(!img, "no image for a cancelled fill");
+    OK(stats.pixels > 0 && stats.pixels < total / 2,
+       "cancelled partway through at %zu pixels", stats.pixels);
+    OK((size_t)(counter.calls - OPEN_CANVAS_SIZE) < stats.pixels / 1000,
+       "cancellation checked only %d times while filling",
+       counter.calls - OPEN_CANVAS_SIZE);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+typedef struct DP_ProgressTestLog {
+    int calls;
+    bool stages_in_order;
+    bool filled_in_order;
+    bool seen[DP_FLOOD_FILL_STAGE_FINISH + 1];
+    DP_FloodFillStage stage;
+    size_t filled;
+    size_t area;
+    int fill_calls;
+    int cancel_stage;
+} DP_ProgressTestLog;
+
+static DP_ProgressTestLog make_progress_log(int cancel_stage)
+{
+    return (DP_ProgressTestLog){
+        0, true, true, {false}, DP_FLOOD_FILL_STAGE_PREPARE, 0, 0, 0,
+        cancel_stage};
+}
+
+static bool log_progress(void *user, DP_FloodFillStage stage, size_t filled,
+                         size_t area)
+{
+    DP_ProgressTestLog *log = user;
+    if (log->calls != 0) {
+        log->stages_in_order = log->stages_in_order && stage >= log->stage;
+        log->filled_in_order = log->filled_in_order && filled >= log->filled;
+    }
+    ++log->calls;
+    log->seen[stage] = true;
+    log->stage = stage;
+    log->filled = filled;
+    log->area = area;
+    if (stage == DP_FLOOD_FILL_STAGE_FILL) {
+        ++log->fill_calls;
+    }
+    return (int)stage == log->cancel_stage;
+}
+
+// Fills or selects the open canvas with a gap and expansion, so that the
+// fill goes through every stage.
+static DP_FloodFillResult progress_open_canvas(DP_CanvasHistory *ch,
+                                               bool select,
+                                               DP_ProgressTestLog *log,
+                                               DP_Image **out_img,
+                                               DP_Selection **out_sel)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int center = OPEN_CANVAS_SIZE / 2;
+    DP_FloodFillResult result;
+    if (select) {
+        result = DP_flood_select(
+            cs, center, center, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+            DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID, OPEN_CANVAS_SIZE, true, 2, 1,
+            0, DP_VIEW_MODE_NORMAL, 0, 0, 0, out_sel, NULL, log_progress, log);
+    }
+    else {
+        int img_x, img_y;
+        result = DP_flood_fill(
+            cs, center, center,
+            DP_flood_fill_source_color(
+                (DP_UPixelFloat){1.0f, 0.0f, 0.0f, 1.0f}),
+            0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER,
+            LAYER_ID, NULL, OPEN_CANVAS_SIZE, 2, 1, 0, DP_VIEW_MODE_NORMAL, 0,
+            0, 0, out_img, &img_x, &img_y, NULL, log_progress, log);
+    }
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static void fill_reports_progress(TEST_PARAMS)
+{
+    size_t total = OPEN_CANVAS_SIZE * OPEN_CANVAS_SIZE;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_open_canvas(TEST_ARGS, dc);
+
+    DP_ProgressTestLog log = make_progress_log(-1);
+    DP_Image *img = NULL;
+    DP_FloodFillResult result =
+        progress_open_canvas(ch, false, &log, &img, NULL);
+    OK(result == DP_FLOOD_FILL_SUCCESS, "fill with progress succeeded");
+    OK(log.stages_in_order, "stages never went backwards");
+    OK(log.filled_in_order, "filled count never went down");
+    OK(log.seen[DP_FLOOD_FILL_STAGE_PREPARE], "reported preparing");
+    OK(log.seen[DP_FLOOD_FILL_STAGE_SCAN], "reported scanning");
+    OK(log.seen[DP_FLOOD_FILL_STAGE_GAP], "reported closing gaps");
+    OK(log.seen[DP_FLOOD_FILL_STAGE_FILL], "reported filling");
+    OK(log.seen[DP_FLOOD_FILL_STAGE_FINISH], "reported finishing");
+    OK(log.stage == DP_FLOOD_FILL_STAGE_FINISH, "last report is finishing");
+    OK(log.filled == total, "last report has all %zu pixels filled (got %zu)",
+       total, log.filled);
+    OK(log.area == total, "area is the whole canvas (got %zu)", log.area);
+    OK(log.fill_calls > 1 && (size_t)log.fill_calls < total / 1000,
+       "filling reported every few thousand pixels (%d times)",
+       log.fill_calls);
+    DP_image_free(img);
+
+    log = make_progress_log(-1);
+    DP_Selection *sel = NULL;
+    result = progress_open_canvas(ch, true, &log, NULL, &sel);
+    OK(result == DP_FLOOD_FILL_SUCCESS, "select with progress succeeded");
+    OK(log.stages_in_order, "select stages never went backwards");
+    OK(log.seen[DP_FLOOD_FILL_STAGE_FILL] && log.filled == total,
+       "select reported filling all pixels");
+    DP_selection_decref(sel);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fill_cancel_at_each_stage(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_open_canvas(TEST_ARGS, dc);
+
+    for (int stage = DP_FLOOD_FILL_STAGE_PREPARE;
+         stage <= DP_FLOOD_FILL_STAGE_FINISH; ++stage) {
+        DP_ProgressTestLog log = make_progress_log(stage);
+        DP_Image *img = NULL;
+        DP_FloodFillResult result =
+            progress_open_canvas(ch, false, &log, &img, NULL);
+        OK(result == DP_FLOOD_FILL_CANCELLED, "fill cancelled in stage %d",
+           stage);
+        OK(!img, "no image for fill cancelled in stage %d", stage);
+        OK((int)log.stage == stage, "fill stopped reporting in stage %d",
+           stage);
+
+        log = make_progress_log(stage);
+        DP_Selection *sel = NULL;
+        result = progress_open_canvas(ch, true, &log, NULL, &sel);
+        OK(result == DP_FLOOD_FILL_CANCELLED, "select cancelled in stage %d",
+           stage);
+        OK(!sel, "no selection for select cancelled in stage %d", stage);
+        OK((int)log.stage == stage, "select stopped reporting in stage %d",
+           stage);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static DP_FloodFillResult fill_selected(DP_CanvasHistory *ch,
+                                        DP_Selection *sel, int x, int y,
+                                        int expand, DP_Image **out_img,
+                                        int *out_x, int *out_y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, x, y,
+        DP_flood_fill_source_color((DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f}),
+        0.1,
+        DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID,
+        sel, CANVAS_SIZE, 0, expand, 0, DP_VIEW_MODE_NORMAL, 0, 0, 0, out_img,
+        out_x, out_y, NULL, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static uint8_t image_alpha_at(DP_Image *img, int img_x, int img_y, int x,
+                              int y)
+{
+    int px = x - img_x;
+    int py = y - img_y;
+    if (px >= 0 && py >= 0 && px < DP_image_width(img)
+        && py < DP_image_height(img)) {
+        return DP_image_pixel_at(img, px, py).a;
+    }
+    else {
+        return 0;
+    }
+}
+
+// White canvas with a black line down the middle of an elliptical selection.
+// Filling on the left side should stop at the line and leave the selection's
+// anti-aliased edge on the fill.
+static void fill_clipped_to_selection(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 30, 0, 2, CANVAS_SIZE, 0xff000000);
+    DP_Selection *sel = DP_selection_new_ellipse(6.0, 8.0, 32.0, 28.0);
+
+    DP_Image *img;
+    int img_x, img_y;
+    DP_FloodFillResult result =
+        fill_selected(ch, sel, 16, 22, 0, &img, &img_x, &img_y);
+    if (OK(result == DP_FLOOD_FILL_SUCCESS, "fill in selection succeeded")) {
+        int wrong = 0;
+        int soft = 0;
+        for (int y = 0; y < CANVAS_SIZE; ++y) {
+            for (int x = 0; x < CANVAS_SIZE; ++x) {
+                int alpha = image_alpha_at(img, img_x, img_y, x, y);
+                int expected = x < 30 ? DP_selection_contains(sel, x, y) : 0;
+                if (abs(alpha - expected) > 1) {
+                    ++wrong;
+                }
+                if (alpha > 0 && alpha < 255) {
+                    ++soft;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "fill matches the selection's coverage");
+        OK(soft > 0, "fill has %d soft edge pixels", soft);
+        DP_image_free(img);
+    }
+
+    // Expansion grows under the line, but not out of the selection.
+    result = fill_selected(ch, sel, 16, 22, 3, &img, &img_x, &img_y);
+    if (OK(result == DP_FLOOD_FILL_SUCCESS, "expanded fill succeeded")) {
+        int wrong = 0;
+        for (int y = 0; y < CANVAS_SIZE; ++y) {
+            for (int x = 0; x < CANVAS_SIZE; ++x) {
+                int alpha = image_alpha_at(img, img_x, img_y, x, y);
+                int coverage = DP_selection_contains(sel, x, y);
+                if (x < 30 ? abs(alpha - coverage) > 1 : alpha > coverage + 1) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "expanded fill stays within the selection");
+        OK(image_alpha_at(img, img_x, img_y, 31, 22) != 0,
+           "expanded fill reaches under the line");
+        DP_image_free(img);
+    }
+
+    result = fill_selected(ch, sel, 2, 2, 0, &img, &img_x, &img_y);
+    OK(result == DP_FLOOD_FILL_NOTHING_TO_FILL,
+       "filling outside of the selection does nothing");
+
+    DP_selection_decref(sel);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+#define SELECT_SIZE 1000
+
+static DP_FloodFillResult fill_unselected(DP_CanvasHistory *ch, int x, int y,
+                                          int gap, int expand,
+                                          int feather_radius,
+                                          DP_Image **out_img, int *out_x,
+                                          int *out_y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, x, y,
+        DP_flood_fill_source_color((DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f}),
+        0.1,
+        DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID,
+        NULL, SELECT_SIZE, gap, expand, feather_radius, DP_VIEW_MODE_NORMAL, 0,
+        0, 0, out_img, out_x, out_y, NULL, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static DP_FloodFillResult select_at(DP_CanvasHistory *ch, int x, int y,
+                                    bool contiguous, int gap, int expand,
+                                    int feather_radius, DP_Selection **out_sel)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_FloodFillResult result = DP_flood_select(
+        cs, x, y, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID, SELECT_SIZE, contiguous, gap,
+        expand, feather_radius, DP_VIEW_MODE_NORMAL, 0, 0, 0, out_sel, NULL,
+        NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static DP_Rect selection_coverage_bounds(DP_Selection *sel, int width,
+                                         int height)
+{
+    DP_Rect bounds = {INT_MAX, INT_MAX, INT_MIN, INT_MIN};
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            if (DP_selection_contains(sel, x, y) != 0) {
+                bounds.x1 = DP_min_int(bounds.x1, x);
+                bounds.y1 = DP_min_int(bounds.y1, y);
+                bounds.x2 = DP_max_int(bounds.x2, x);
+                bounds.y2 = DP_max_int(bounds.y2, y);
+            }
+        }
+    }
+    return bounds;
+}
+
+static bool rect_eq(DP_Rect a, DP_Rect b)
+{
+    return a.x1 == b.x1 && a.y1 == b.y1 && a.x2 == b.x2 && a.y2 == b.y2;
+}
+
+// Outline of a box with a one pixel hole in its side, plus a disconnected
+// white area to the right of it. Gap filling, expansion and feathering
+// should all come out the same in the selection as in the fill.
+static void select_matches_fill(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, CANVAS_SIZE, CANVAS_SIZE, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 4, 4, 24, 24, 0xff000000);
+    fill_rect(TEST_ARGS, ch, dc, 6, 6, 20, 20, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 26, 14, 2, 1, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 34, 0, 2, CANVAS_SIZE, 0xff000000);
+
+    int params[][3] = {{0, 0, 0}, {2, 0, 0}, {2, 2, 0}, {2, -1, 0}, {2, 1, 3}};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(params); ++i) {
+        int gap = params[i][0];
+        int expand = params[i][1];
+        int feather_radius = params[i][2];
+        DP_Image *img;
+        int img_x, img_y;
+        DP_Selection *sel;
+        if (!OK(fill_unselected(ch, 16, 16, gap, expand, feather_radius, &img,
+                                &img_x, &img_y)
+                    == DP_FLOOD_FILL_SUCCESS,
+                "fill with gap %d, expand %d, feather %d succeeded", gap,
+                expand, feather_radius)) {
+            continue;
+        }
+
+        if (OK(select_at(ch, 16, 16, true, gap, expand, feather_radius, &sel)
+                   == DP_FLOOD_FILL_SUCCESS,
+               "select with gap %d, expand %d, feather %d succeeded", gap,
+               expand, feather_radius)) {
+            int wrong = 0;
+            for (int y = 0; y < CANVAS_SIZE; ++y) {
+                for (int x = 0; x < CANVAS_SIZE; ++x) {
+                    int alpha = image_alpha_at(img, img_x, img_y, x, y);
+                    int coverage = DP_selection_contains(sel, x, y);
+                    if (abs(alpha - coverage) > 1) {
+                        ++wrong;
+                    }
+                }
+            }
+            INT_EQ_OK(wrong, 0, "selection matches the filled pixels");
+            OK(rect_eq(DP_selection_bounds(sel),
+                       selection_coverage_bounds(sel, CANVAS_SIZE,
+                                                 CANVAS_SIZE)),
+               "selection bounds are tight");
+            DP_selection_decref(sel);
+        }
+        DP_image_free(img);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+// A canvas bigger than a single tile with a line down it. Selecting
+// contiguously only gets one side, non-contiguously gets both and should
+// match the two fills on them put together.
+static void select_non_contiguous(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, 100, 100, 0));
+    int size = CANVAS_SIZE + 100;
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, size, size, 0xffffffff);
+    fill_rect(TEST_ARGS, ch, dc, 70, 0, 3, size, 0xff000000);
+
+    DP_Image *left_img, *right_img;
+    int left_x, left_y, right_x, right_y;
+    DP_FloodFillResult left_result =
+        fill_unselected(ch, 10, 10, 0, 0, 0, &left_img, &left_x, &left_y);
+    DP_FloodFillResult right_result = fill_unselected(
+        ch, 100, 100, 0, 0, 0, &right_img, &right_x, &right_y);
+    OK(left_result == DP_FLOOD_FILL_SUCCESS, "left fill succeeded");
+    OK(right_result == DP_FLOOD_FILL_SUCCESS, "right fill succeeded");
+
+    DP_Selection *sel;
+    if (OK(select_at(ch, 10, 10, true, 0, 0, 0, &sel) == DP_FLOOD_FILL_SUCCESS,
+           "contiguous select succeeded")) {
+        OK(rect_eq(DP_selection_bounds(sel), (DP_Rect){0, 0, 69, size - 1}),
+           "contiguous selection only covers the left side");
+        DP_selection_decref(sel);
+    }
+
+    if (OK(select_at(ch, 10, 10, false, 0, 0, 0, &sel)
+               == DP_FLOOD_FILL_SUCCESS,
+           "non-contiguous select succeeded")
+        && left_result == DP_FLOOD_FILL_SUCCESS
+        && right_result == DP_FLOOD_FILL_SUCCESS) {
+        int wrong = 0;
+        for (int y = 0; y < size; ++y) {
+            for (int x = 0; x < size; ++x) {
+                int expected = DP_max_int(
+                    image_alpha_at(left_img, left_x, left_y, x, y),
+                    image_alpha_at(right_img, right_x, right_y, x, y));
+                if (DP_selection_contains(sel, x, y) != expected) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "selection matches both fills");
+        OK(rect_eq(DP_selection_bounds(sel),
+                   (DP_Rect){0, 0, size - 1, size - 1}),
+           "non-contiguous selection covers the whole canvas");
+        DP_selection_decref(sel);
+    }
+
+    if (left_result == DP_FLOOD_FILL_SUCCESS) {
+        DP_image_free(left_img);
+    }
+    if (right_result == DP_FLOOD_FILL_SUCCESS) {
+        DP_image_free(right_img);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+#define PATTERN_WIDTH  5
+#define PATTERN_HEIGHT 3
+
+static DP_FloodFillResult fill_with(DP_CanvasHistory *ch, int x, int y,
+                                    DP_FloodFillSource source,
+                                    int feather_radius, DP_Image **out_img,
+                                    int *out_x, int *out_y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, x, y, source, 0.1, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+        DP_FLOOD_FILL_SAMPLE_LAYER, LAYER_ID, NULL, CANVAS_SIZE, 0, 0,
+        feather_radius, DP_VIEW_MODE_NORMAL, 0, 0, 0, out_img, out_x, out_y,
+        NULL, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static int wrap(int i, int size)
+{
+    int r = i % size;
+    return r < 0 ? r + size : r;
+}
+
+// Counts the filled pixels that don't match the pattern at their position
+// on the canvas, scaled by the mask if the pixel is only partially filled.
+static int count_pattern_mismatches(DP_Image *img, int img_x, int img_y,
+                                    const DP_Pixel8 *pattern, int offset_x,
+                                    int offset_y, int *out_filled)
+{
+    int mismatches = 0;
+    int width = DP_image_width(img);
+    int height = DP_image_height(img);
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            DP_Pixel8 actual = DP_image_pixel_at(img, x, y);
+            if (actual.a != 0) {
+                ++*out_filled;
+                DP_Pixel8 expected =
+                    pattern[wrap(img_y + y - offset_y, PATTERN_HEIGHT)
+                                * PATTERN_WIDTH
+                            + wrap(img_x + x - offset_x, PATTERN_WIDTH)];
+                double m = actual.a / 255.0;
+                if (abs(actual.b - (int)round(expected.b * m)) > 1
+                    || abs(actual.g - (int)round(expected.g * m)) > 1
+                    || abs(actual.r - (int)round(expected.r * m)) > 1) {
+                    ++mismatches;
+                }
+            }
+        }
+    }
+    return mismatches;
+}
+
+// Two areas of different colors right next to each other, each filled
+// separately with an odd-sized pattern. The pattern should continue right
+// across the seam between them.
+static void fill_pattern_continuity(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, 0, 0, 23, CANVAS_SIZE, 0xffff0000);
+    fill_rect(TEST_ARGS, ch, dc, 23, 0, CANVAS_SIZE - 23, CANVAS_SIZE,
+              0xff0000ff);
+
+    DP_Pixel8 pattern[PATTERN_WIDTH * PATTERN_HEIGHT];
+    for (int i = 0; i < PATTERN_WIDTH * PATTERN_HEIGHT; ++i) {
+        pattern[i] = (DP_Pixel8){0};
+        pattern[i].b = DP_int_to_uint8(i * 16);
+        pattern[i].g = DP_int_to_uint8(255 - i * 16);
+        pattern[i].r = DP_int_to_uint8(i * 7);
+        pattern[i].a = 255;
+    }
+    DP_FloodFillSource source = DP_flood_fill_source_pattern(
+        pattern, PATTERN_WIDTH, PATTERN_HEIGHT, 2, 1);
+
+    int filled = 0;
+    int sides[] = {5, 40};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(sides); ++i) {
+        DP_Image *img;
+        int img_x, img_y;
+        if (OK(fill_with(ch, sides[i], 5, source, 0, &img, &img_x, &img_y)
+                   == DP_FLOOD_FILL_SUCCESS,
+               "pattern fill at %d succeeded", sides[i])) {
+            INT_EQ_OK(count_pattern_mismatches(img, img_x, img_y, pattern, 2,
+                                               1, &filled),
+                      0, "pattern at %d is in canvas space", sides[i]);
+            DP_image_free(img);
+        }
+    }
+    INT_EQ_OK(filled, CANVAS_SIZE * CANVAS_SIZE,
+              "both fills together cover the canvas");
+
+    DP_Image *img;
+    int img_x, img_y;
+    if (OK(fill_with(ch, 5, 5, source, 2, &img, &img_x, &img_y)
+               == DP_FLOOD_FILL_SUCCESS,
+           "feathered pattern fill succeeded")) {
+        int partial = 0;
+        for (int y = 0; y < DP_image_height(img); ++y) {
+            for (int x = 0; x < DP_image_width(img); ++x) {
+                uint8_t a = DP_image_pixel_at(img, x, y).a;
+                partial += a != 0 && a != 255;
+            }
+        }
+        OK(partial > 0, "feathered pattern fill has %d soft pixels", partial);
+        filled = 0;
+        INT_EQ_OK(count_pattern_mismatches(img, img_x, img_y, pattern, 2, 1,
+                                           &filled),
+                  0, "feathered pattern gets scaled by the mask");
+        DP_image_free(img);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fill_pattern_cropped(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+
+    // Only the first DP_FLOOD_FILL_PATTERN_MAX_SIZE columns should get used,
+    // so the pattern wraps around before reaching the last ones.
+    int width = DP_FLOOD_FILL_PATTERN_MAX_SIZE + 8;
+    DP_Pixel8 *pattern = DP_malloc(sizeof(*pattern) * (size_t)width);
+    for (int i = 0; i < width; ++i) {
+        pattern[i] = (DP_Pixel8){0};
+        pattern[i].r = i < DP_FLOOD_FILL_PATTERN_MAX_SIZE ? 255 : 0;
+        pattern[i].a = 255;
+    }
+    DP_FloodFillSource source = DP_flood_fill_source_pattern(
+        pattern, width, 1, 4 - DP_FLOOD_FILL_PATTERN_MAX_SIZE, 0);
+
+    DP_Image *img;
+    int img_x, img_y;
+    if (OK(fill_with(ch, 5, 5, source, 0, &img, &img_x, &img_y)
+               == DP_FLOOD_FILL_SUCCESS,
+           "oversized pattern fill succeeded")) {
+        int wrong = 0;
+        for (int y = 0; y < DP_image_height(img); ++y) {
+            for (int x = 0; x < DP_image_width(img); ++x) {
+                wrong += DP_image_pixel_at(img, x, y).r != 255;
+            }
+        }
+        INT_EQ_OK(wrong, 0, "oversized pattern gets cropped");
+        DP_image_free(img);
+    }
+    DP_free(pattern);
+
+    if (OK(fill_with(ch, 5, 5, DP_flood_fill_source_erase(), 0, &img, &img_x,
+                     &img_y)
+               == DP_FLOOD_FILL_SUCCESS,
+           "erase fill succeeded")) {
+        DP_Pixel8 p = DP_image_pixel_at(img, 5 - img_x, 5 - img_y);
+        OK(p.a == 255 && p.b == 0 && p.g == 0 && p.r == 0,
+           "erase fill is opaque black");
+        DP_image_free(img);
+    }
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static bool reference_should_fill(DP_LayerContent *lc, DP_UPixelFloat reference,
+                                  double tolerance, int x, int y)
+{
+    DP_Pixel15 pixel = DP_layer_content_pixel_at(lc, x, y);
+    if (reference.a == 0.0f) {
+        return DP_channel15_to_float(pixel.a) <= tolerance;
+    }
+    else {
+        DP_UPixelFloat color =
+            DP_upixel15_to_float(DP_pixel15_unpremultiply(pixel));
+        return DP_upixel_float_distance(color, reference) <= tolerance;
+    }
+}
+
+// The straightforward way to flood fill, one pixel at a time with a queue of
+// individual coordinates. Returns a buffer with a 1 for each filled pixel.
+static unsigned char *reference_fill(DP_CanvasHistory *ch, int x0, int y0,
+                                     double tolerance)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int width = DP_canvas_state_width(cs);
+    int height = DP_canvas_state_height(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(
+        DP_canvas_state_layer_routes_noinc(cs), LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    DP_UPixelFloat reference = DP_upixel15_to_float(
+        DP_pixel15_unpremultiply(DP_layer_content_pixel_at(lc, x0, y0)));
+
+    size_t count = (size_t)width * (size_t)height;
+    unsigned char *filled = DP_malloc_zeroed(count);
+    int *queue = DP_malloc(sizeof(*queue) * count);
+    size_t head = 0, tail = 0;
+    filled[y0 * width + x0] = 1;
+    queue[tail++] = y0 * width + x0;
+    while (head < tail) {
+        int i = queue[head++];
+        int x = i % width;
+        int y = i / width;
+        int neighbors[][2] = {{x - 1, y}, {x + 1, y}, {x, y - 1}, {x, y + 1}};
+        for (int j = 0; j < 4; ++j) {
+            int nx = neighbors[j][0];
+            int ny = neighbors[j][1];
+            if (nx >= 0 && ny >= 0 && nx < width && ny < height
+                && !filled[ny * width + nx]
+                && reference_should_fill(lc, reference, tolerance, nx, ny)) {
+                filled[ny * width + nx] = 1;
+                queue[tail++] = ny * width + nx;
+            }
+        }
+    }
+
+    DP_free(queue);
+    DP_canvas_state_decref(cs);
+    return filled;
+}
+
+static DP_FloodFillResult fill_plain(DP_CanvasHistory *ch, int x, int y,
+                                     double tolerance, int size,
+                                     DP_Image **out_img, int *out_x,
+                                     int *out_y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_FloodFillResult result = DP_flood_fill(
+        cs, x, y,
+        DP_flood_fill_source_color((DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f}),
+        tolerance, DP_FLOOD_FILL_METRIC_PERCEPTUAL, DP_FLOOD_FILL_SAMPLE_LAYER,
+        LAYER_ID, NULL, size, 0, 0, 0, DP_VIEW_MODE_NORMAL, 0, 0, 0, out_img,
+        out_x, out_y, NULL, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return result;
+}
+
+static int count_reference_mismatches(DP_Image *img, int img_x, int img_y,
+                                      const unsigned char *filled, int width,
+                                      int height)
+{
+    int mismatches = 0;
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            int expected = filled[y * width + x] ? 255 : 0;
+            if (image_alpha_at(img, img_x, img_y, x, y) != expected) {
+                ++mismatches;
+            }
+        }
+    }
+    return mismatches;
+}
+
+static uint32_t next_random(uint32_t *state)
+{
+    *state = *state * 1664525u + 1013904223u;
+    return *state >> 8;
+}
+
+// Lots of random overlapping blotches in colors close enough to each other
+// that the tolerance matters, across several tiles. Filling from anywhere
+// should touch exactly the same pixels as the naive algorithm.
+static void fill_matches_reference(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, 102, 82, 0));
+    int width = CANVAS_SIZE + 102;
+    int height = CANVAS_SIZE + 82;
+    uint32_t colors[] = {0xff000000, 0xff181818, 0xffcc2222, 0x60000000,
+                         0xffffffff};
+    uint32_t state = 12345;
+    for (int i = 0; i < 500; ++i) {
+        int w = DP_uint32_to_int(next_random(&state) % 12) + 1;
+        int h = DP_uint32_to_int(next_random(&state) % 12) + 1;
+        int x = DP_uint32_to_int(next_random(&state) % (uint32_t)width);
+        int y = DP_uint32_to_int(next_random(&state) % (uint32_t)height);
+        uint32_t color =
+            colors[next_random(&state) % DP_ARRAY_LENGTH(colors)];
+        fill_rect(TEST_ARGS, ch, dc, x, y, DP_min_int(w, width - x),
+                  DP_min_int(h, height - y), color);
+    }
+
+    double tolerances[] = {0.0, 0.05, 0.3};
+    int mismatches = 0;
+    for (int i = 0; i < 24; ++i) {
+        int x = DP_uint32_to_int(next_random(&state) % (uint32_t)width);
+        int y = DP_uint32_to_int(next_random(&state) % (uint32_t)height);
+        double tolerance = tolerances[i % (int)DP_ARRAY_LENGTH(tolerances)];
+        DP_Image *img;
+        int img_x, img_y;
+        if (OK(fill_plain(ch, x, y, tolerance, width, &img, &img_x, &img_y)
+                   == DP_FLOOD_FILL_SUCCESS,
+               "fill at %d, %d with tolerance %f succeeded", x, y,
+               tolerance)) {
+            unsigned char *filled = reference_fill(ch, x, y, tolerance);
+            mismatches += count_reference_mismatches(img, img_x, img_y,
+                                                     filled, width, height);
+            DP_free(filled);
+            DP_image_free(img);
+        }
+    }
+    INT_EQ_OK(mismatches, 0, "all fills match the reference");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static double seconds_since(clock_t start)
+{
+    return (double)(clock() - start) / (double)CLOCKS_PER_SEC;
+}
+
+// Not much of a test, the numbers just get reported to see how long filling
+// a large empty canvas takes compared to the naive algorithm. They're only
+// meaningful in an optimized build without sanitizers.
+static void fill_benchmark_large_canvas(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    int size = 4096;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, size - CANVAS_SIZE,
+                                    size - CANVAS_SIZE, 0));
+
+    clock_t start = clock();
+    DP_Image *img;
+    int img_x, img_y;
+    DP_FloodFillResult result =
+        fill_plain(ch, size / 2, size / 2, 0.1, size, &img, &img_x, &img_y);
+    double fill_seconds = seconds_since(start);
+
+    start = clock();
+    unsigned char *filled = reference_fill(ch, size / 2, size / 2, 0.1);
+    double reference_seconds = seconds_since(start);
+
+    if (OK(result == DP_FLOOD_FILL_SUCCESS, "large fill succeeded")) {
+        INT_EQ_OK(count_reference_mismatches(img, img_x, img_y, filled, size,
+                                             size),
+                  0, "large fill matches the reference");
+        DP_image_free(img);
+    }
+    DP_free(filled);
+    NOTE("%dx%d fill took %.3fs including the image, the naive traversal "
+         "alone took %.3fs",
+         size, size, fill_seconds, reference_seconds);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(fill_antialiased_hues);
+    REGISTER_TEST(fill_transparent_by_alpha);
+    REGISTER_TEST(fill_flat_with_both_metrics);
+    REGISTER_TEST(fill_closes_gaps);
+    REGISTER_TEST(fill_expand_and_shrink);
+    REGISTER_TEST(fill_expand_clipped_at_edge);
+    REGISTER_TEST(fill_expand_behind);
+    REGISTER_TEST(fill_behind_line_art);
+    REGISTER_TEST(fill_erase_flat_region);
+    REGISTER_TEST(fill_samples_merged);
+    REGISTER_TEST(fill_size_limit);
+    REGISTER_TEST(fill_cancel_midway);
+    REGISTER_TEST(fill_reports_progress);
+    REGISTER_TEST(fill_cancel_at_each_stage);
+    REGISTER_TEST(fill_clipped_to_selection);
+    REGISTER_TEST(select_matches_fill);
+    REGISTER_TEST(select_non_contiguous);
+    REGISTER_TEST(fill_pattern_continuity);
+    REGISTER_TEST(fill_pattern_cropped);
+    REGISTER_TEST(fill_matches_reference);
+    REGISTER_TEST(fill_benchmark_large_canvas);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
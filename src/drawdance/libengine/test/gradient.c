@@ -0,0 +1,423 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/gradient.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/selection.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+#include <stdlib.h>
+
+
+#define WIDTH    150
+#define HEIGHT   100
+#define LAYER_ID 257
+
+#define BLACK ((DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f})
+#define WHITE ((DP_UPixelFloat){1.0f, 1.0f, 1.0f, 1.0f})
+#define RED   ((DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f})
+#define BLUE  ((DP_UPixelFloat){1.0f, 0.0f, 0.0f, 1.0f})
+
+
+static bool color_near(DP_UPixelFloat a, DP_UPixelFloat b)
+{
+    return fabsf(a.b - b.b) < 0.001f && fabsf(a.g - b.g) < 0.001f
+        && fabsf(a.r - b.r) < 0.001f && fabsf(a.a - b.a) < 0.001f;
+}
+
+static bool color_ok(TEST_PARAMS, DP_UPixelFloat color,
+                     DP_UPixelFloat expected, const char *title)
+{
+    bool ok = OK(color_near(color, expected), "%s", title);
+    if (!ok) {
+        DIAG("got bgra %f %f %f %f, expected %f %f %f %f", (double)color.b,
+             (double)color.g, (double)color.r, (double)color.a,
+             (double)expected.b, (double)expected.g, (double)expected.r,
+             (double)expected.a);
+    }
+    return ok;
+}
+
+static bool position_ok(TEST_PARAMS, float position, double expected,
+                        const char *title)
+{
+    bool ok = OK(fabs((double)position - expected) < 0.0001, "%s", title);
+    if (!ok) {
+        DIAG("got position %f, expected %f", (double)position, expected);
+    }
+    return ok;
+}
+
+static DP_Gradient make_linear(double x1, double y1, double x2, double y2,
+                               int stop_count, const DP_GradientStop *stops)
+{
+    return (DP_Gradient){DP_GRADIENT_SHAPE_LINEAR,
+                         x1,
+                         y1,
+                         x2,
+                         y2,
+                         0.0,
+                         stop_count,
+                         stops,
+                         false};
+}
+
+static DP_Pixel8 pixel8_at(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return DP_pixel15_to_8(
+        DP_layer_content_pixel_at((DP_LayerContent *)tlc, x, y));
+}
+
+
+static void interpolate_stops(TEST_PARAMS)
+{
+    DP_GradientStop stops[] = {{0.0f, BLACK}, {1.0f, WHITE}};
+    DP_Gradient g = make_linear(0.0, 0.0, 100.0, 0.0, 2, stops);
+
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.0f), BLACK, "start");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 1.0f), WHITE, "end");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, -0.5f), BLACK,
+             "first color before the start");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 1.5f), WHITE,
+             "last color past the end");
+
+    // Half of the light in linear light is about 73.5% in sRGB, a plain mix
+    // of the gamma-encoded channels would give a muddy 50% instead.
+    float mid = 1.055f * powf(0.5f, 1.0f / 2.4f) - 0.055f;
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.5f),
+             (DP_UPixelFloat){mid, mid, mid, 1.0f},
+             "midpoint is mixed in linear light");
+
+    DP_GradientStop alpha_stops[] = {{0.0f, {0.0f, 0.0f, 0.0f, 0.0f}},
+                                     {1.0f, {0.0f, 0.0f, 0.0f, 1.0f}}};
+    DP_Gradient ga = make_linear(0.0, 0.0, 100.0, 0.0, 2, alpha_stops);
+    color_ok(TEST_ARGS, DP_gradient_color_at(&ga, 0.25f),
+             (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 0.25f}, "alpha mixes as-is");
+}
+
+static void interpolate_between_inner_stops(TEST_PARAMS)
+{
+    DP_GradientStop stops[] = {
+        {0.25f, RED}, {0.5f, BLACK}, {0.75f, BLUE}, {1.0f, WHITE}};
+    DP_Gradient g = make_linear(0.0, 0.0, 100.0, 0.0, 4, stops);
+
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.1f), RED,
+             "first stop color before it");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.5f), BLACK,
+             "inner stop color at it");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.75f), BLUE,
+             "another inner stop color at it");
+
+    float faded = 1.055f * powf(0.5f, 1.0f / 2.4f) - 0.055f;
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.375f),
+             (DP_UPixelFloat){0.0f, 0.0f, faded, 1.0f},
+             "between red and black only uses those two stops");
+}
+
+static void hard_edge(TEST_PARAMS)
+{
+    DP_GradientStop stops[] = {
+        {0.0f, RED}, {0.5f, RED}, {0.5f, BLUE}, {1.0f, BLUE}};
+    DP_Gradient g = make_linear(0.0, 0.0, 100.0, 0.0, 4, stops);
+
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.4999f), RED,
+             "first color right before the edge");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.5f), BLUE,
+             "second color at the edge");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&g, 0.5001f), BLUE,
+             "second color right after the edge");
+
+    // The edge lands between pixels 49 and 50, which have their centers at
+    // 49.5 and 50.5, so there's no pixel in between.
+    DP_Image *img = DP_gradient_render(&g, DP_rect_make(0, 0, 100, 1), NULL);
+    int wrong = 0;
+    for (int x = 0; x < 100; ++x) {
+        DP_Pixel8 pixel = DP_image_pixel_at(img, x, 0);
+        bool red = x < 50;
+        if (pixel.r != (red ? 255 : 0) || pixel.b != (red ? 0 : 255)
+            || pixel.g != 0 || pixel.a != 255) {
+            ++wrong;
+        }
+    }
+    INT_EQ_OK(wrong, 0, "rendered edge is sharp");
+    DP_image_free(img);
+
+    DP_GradientStop start_stops[] = {{0.0f, RED}, {0.0f, BLUE}};
+    DP_Gradient gs = make_linear(0.0, 0.0, 100.0, 0.0, 2, start_stops);
+    color_ok(TEST_ARGS, DP_gradient_color_at(&gs, -0.1f), RED,
+             "first color before an edge at the start");
+    color_ok(TEST_ARGS, DP_gradient_color_at(&gs, 0.0f), BLUE,
+             "second color at an edge at the start");
+}
+
+static void linear_geometry(TEST_PARAMS)
+{
+    DP_GradientStop stops[] = {{0.0f, BLACK}, {1.0f, WHITE}};
+    // Points outside of the region, the fill only sees the middle of it.
+    DP_Gradient g = make_linear(-100.0, 10.0, 300.0, 10.0, 2, stops);
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, -101, 0), -0.00125,
+                "before the start");
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, 99, 50), 0.49875,
+                "halfway through");
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, 301, 99), 1.00375,
+                "past the end");
+
+    DP_Gradient diagonal = make_linear(0.0, 0.0, 10.0, 10.0, 2, stops);
+    position_ok(TEST_ARGS, DP_gradient_position_at(&diagonal, 9, 0), 0.5,
+                "diagonal projects onto the line");
+    position_ok(TEST_ARGS, DP_gradient_position_at(&diagonal, 0, 9), 0.5,
+                "diagonal is the same across the line");
+
+    DP_Gradient degenerate = make_linear(5.0, 5.0, 5.0, 5.0, 2, stops);
+    position_ok(TEST_ARGS, DP_gradient_position_at(&degenerate, 0, 0), 1.0,
+                "start and end at the same point is at the end");
+}
+
+static void radial_geometry(TEST_PARAMS)
+{
+    DP_GradientStop stops[] = {{0.0f, BLACK}, {1.0f, WHITE}};
+    DP_Gradient g = {DP_GRADIENT_SHAPE_RADIAL,
+                     -20.5,
+                     50.5,
+                     0.0,
+                     0.0,
+                     40.0,
+                     2,
+                     stops,
+                     false};
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, -21, 50), 0.0,
+                "center outside of the canvas");
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, -1, 50), 0.5,
+                "half the radius");
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, -21, 70), 0.5,
+                "same distance in another direction");
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, 59, 50), 2.0,
+                "twice the radius");
+
+    DP_Image *img = DP_gradient_render(&g, DP_rect_make(0, 0, 100, 100), NULL);
+    DP_Pixel8 inside = DP_image_pixel_at(img, 0, 50);
+    DP_Pixel8 outside = DP_image_pixel_at(img, 60, 50);
+    OK(inside.r > 0 && inside.r < 255, "pixel inside radius is mixed");
+    UINT_EQ_OK(outside.color, (uint32_t)0xffffffff,
+               "pixel outside of radius is white");
+    DP_image_free(img);
+
+    g.radius = 0.0;
+    position_ok(TEST_ARGS, DP_gradient_position_at(&g, -21, 50), 1.0,
+                "zero radius is at the end");
+}
+
+static void dither(TEST_PARAMS)
+{
+    // A channel value of 100.25 out of 255 is a quarter of the way between
+    // 100 and 101, so a quarter of the dithered pixels round up.
+    float value = 100.25f / 255.0f;
+    DP_UPixelFloat color = {value, value, value, 1.0f};
+    DP_GradientStop stops[] = {{0.0f, color}};
+    DP_Gradient g = make_linear(0.0, 0.0, 0.0, 0.0, 1, stops);
+    DP_Rect rect = DP_rect_make(3, 5, 32, 32);
+
+    DP_Image *plain = DP_gradient_render(&g, rect, NULL);
+    g.dither = true;
+    DP_Image *dithered = DP_gradient_render(&g, rect, NULL);
+    DP_Image *again = DP_gradient_render(&g, rect, NULL);
+
+    int plain_wrong = 0, out_of_range = 0, different = 0, sum = 0;
+    for (int y = 0; y < 32; ++y) {
+        for (int x = 0; x < 32; ++x) {
+            DP_Pixel8 p = DP_image_pixel_at(plain, x, y);
+            DP_Pixel8 d = DP_image_pixel_at(dithered, x, y);
+            if (p.b != 100 || p.a != 255) {
+                ++plain_wrong;
+            }
+            if ((d.b != 100 && d.b != 101) || d.a != 255) {
+                ++out_of_range;
+            }
+            if (d.color != DP_image_pixel_at(again, x, y).color) {
+                ++different;
+            }
+            sum += d.b;
+        }
+    }
+    INT_EQ_OK(plain_wrong, 0, "without dither everything rounds down");
+    INT_EQ_OK(out_of_range, 0, "dither stays within one step");
+    INT_EQ_OK(different, 0, "dither is the same every time");
+    INT_EQ_OK(sum, 100 * 32 * 32 + 32 * 32 / 4,
+              "dither averages out to the exact value");
+
+    DP_image_free(again);
+    DP_image_free(dithered);
+    DP_image_free(plain);
+}
+
+static void fill_in_rect_and_selection(TEST_PARAMS)
+{
+    DP_GradientStop stops[] = {{0.0f, RED}, {1.0f, BLUE}};
+    DP_Gradient g = make_linear(-50.0, 0.0, 200.0, 0.0, 2, stops);
+
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Rect rect = DP_rect_make(100, 80, 100, 100);
+    DP_Rect area = DP_gradient_fill(tlc, 1, DP_BLEND_MODE_NORMAL, &g, &rect,
+                                    NULL);
+    INT_EQ_OK(area.x1, 100, "area clipped to canvas left");
+    INT_EQ_OK(area.y1, 80, "area clipped to canvas top");
+    INT_EQ_OK(area.x2, WIDTH - 1, "area clipped to canvas right");
+    INT_EQ_OK(area.y2, HEIGHT - 1, "area clipped to canvas bottom");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel8 pixel = pixel8_at(tlc, x, y);
+            bool inside = DP_rect_contains(area, x, y);
+            if (inside ? pixel.a != 255 : pixel.a != 0) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "only the rect got filled");
+    DP_transient_layer_content_decref(tlc);
+
+    tlc = DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Selection *sel = DP_selection_new_ellipse(10.0, 10.0, 60.0, 40.0);
+    area = DP_gradient_fill(tlc, 1, DP_BLEND_MODE_NORMAL, &g, NULL, sel);
+    wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            uint8_t coverage = DP_selection_contains(sel, x, y);
+            DP_Pixel8 pixel = pixel8_at(tlc, x, y);
+            if (abs(pixel.a - coverage) > 1) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "alpha follows selection coverage");
+    DP_selection_decref(sel);
+    DP_transient_layer_content_decref(tlc);
+}
+
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static DP_LayerContent *get_layer_content(DP_CanvasState *cs)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+static const uint16_t message_positions[] = {0, 20000, 20000, 65535};
+static const uint32_t message_colors[] = {0xffff0000, 0x80ffff00, 0xff00ff00,
+                                          0xff0000ff};
+
+static void set_message_stops(int count, DP_FillGradientStop *out,
+                              void *user)
+{
+    const uint16_t *positions = user;
+    for (int i = 0; i < count; ++i) {
+        DP_fill_gradient_stop_init(out, i, positions[i], message_colors[i]);
+    }
+}
+
+static void fill_gradient_message(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 10, 10, 50,
+                                50, 0x80336699));
+
+    DP_CanvasState *before = DP_canvas_history_get(ch);
+    DP_TransientLayerContent *expected =
+        DP_transient_layer_content_new(get_layer_content(before));
+    DP_GradientStop stops[DP_ARRAY_LENGTH(message_colors)];
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(message_colors); ++i) {
+        DP_UPixel8 color = {.color = message_colors[i]};
+        stops[i] = (DP_GradientStop){
+            DP_uint16_to_float(message_positions[i]) / 65535.0f,
+            DP_upixel8_to_float(color)};
+    }
+    DP_Gradient g = {DP_GRADIENT_SHAPE_RADIAL,
+                     -10.25,
+                     20.0,
+                     0.0,
+                     0.0,
+                     120.5,
+                     DP_ARRAY_LENGTH(stops),
+                     stops,
+                     true};
+    DP_Rect rect = DP_rect_make(30, 0, WIDTH - 30, 40);
+    DP_gradient_fill(expected, 1, DP_BLEND_MODE_NORMAL, &g, &rect, NULL);
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_gradient_new(
+               1, LAYER_ID, 30, 0, 200, 40, DP_BLEND_MODE_NORMAL,
+               DP_MSG_FILL_GRADIENT_SHAPE_RADIAL,
+               DP_MSG_FILL_GRADIENT_FLAGS_DITHER, -41, 80, 0, 0, 482,
+               set_message_stops, DP_ARRAY_LENGTH(message_colors),
+               (void *)message_positions));
+    DP_CanvasState *after = DP_canvas_history_get(ch);
+    DP_LayerContent *lc = get_layer_content(after);
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            if (!DP_pixel15_equal(
+                    DP_layer_content_pixel_at(lc, x, y),
+                    DP_layer_content_pixel_at((DP_LayerContent *)expected, x,
+                                              y))) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "message gives the same result as the fill");
+
+    uint16_t unsorted[] = {0, 40000, 20000, 65535};
+    DP_Message *msg = DP_msg_fill_gradient_new(
+        1, LAYER_ID, 0, 0, WIDTH, HEIGHT, DP_BLEND_MODE_NORMAL,
+        DP_MSG_FILL_GRADIENT_SHAPE_LINEAR, 0, 0, 0, 400, 0, 0,
+        set_message_stops, DP_ARRAY_LENGTH(unsorted), unsorted);
+    NOK(DP_canvas_history_handle(ch, dc, msg), "unsorted stops are rejected");
+    DP_message_decref(msg);
+
+    DP_canvas_state_decref(after);
+    DP_transient_layer_content_decref(expected);
+    DP_canvas_state_decref(before);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(interpolate_stops);
+    REGISTER_TEST(interpolate_between_inner_stops);
+    REGISTER_TEST(hard_edge);
+    REGISTER_TEST(linear_geometry);
+    REGISTER_TEST(radial_geometry);
+    REGISTER_TEST(dither);
+    REGISTER_TEST(fill_in_rect_and_selection);
+    REGISTER_TEST(fill_gradient_message);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
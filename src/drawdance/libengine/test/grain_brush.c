@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/stamp_mask.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  64
+#define GRAIN_SIZE   8
+#define MAX_MESSAGES 64
+
+typedef struct DP_GrainTestDab {
+    uint16_t size;
+} DP_GrainTestDab;
+
+typedef struct DP_GrainTestMessages {
+    int count;
+    int stamp_masks;
+    int classic_dabs;
+    int grain_dabs;
+    uint32_t grain_id;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_GrainTestMessages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_soft_dab(DP_UNUSED int count, DP_SoftDab *dabs, void *user)
+{
+    DP_GrainTestDab *gtd = user;
+    DP_soft_dab_init(dabs, 0, 0, 0, gtd->size, 255, 255);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_GrainTestMessages *gtms = user;
+    switch (DP_message_type(msg)) {
+    case DP_MSG_STAMP_MASK:
+        ++gtms->stamp_masks;
+        break;
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        ++gtms->classic_dabs;
+        break;
+    case DP_MSG_DRAW_DABS_SOFT:
+        if (DP_msg_draw_dabs_soft_grain(DP_message_internal(msg))
+            == gtms->grain_id) {
+            ++gtms->grain_dabs;
+        }
+        break;
+    default:
+        break;
+    }
+    if (gtms->count < MAX_MESSAGES) {
+        gtms->msgs[gtms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+// Diagonal stripes that repeat every GRAIN_SIZE pixels in both directions, so
+// the texture tiles without a seam. Values are either fully on or fully off,
+// which makes the expected result of multiplying them in exact.
+static uint8_t grain_value_at(int x, int y)
+{
+    return (x + 2 * y) % GRAIN_SIZE < GRAIN_SIZE / 2 ? 255 : 0;
+}
+
+static DP_StampMask *make_grain_mask(void)
+{
+    uint8_t data[GRAIN_SIZE * GRAIN_SIZE];
+    for (int y = 0; y < GRAIN_SIZE; ++y) {
+        for (int x = 0; x < GRAIN_SIZE; ++x) {
+            data[y * GRAIN_SIZE + x] = grain_value_at(x, y);
+        }
+    }
+    return DP_stamp_mask_new(GRAIN_SIZE, data);
+}
+
+static DP_CanvasHistory *make_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    return ch;
+}
+
+// Returns the layer's alpha values, CANVAS_SIZE * CANVAS_SIZE of them, to be
+// freed by the caller. Frees the canvas history.
+static uint16_t *take_alphas(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    uint16_t *alphas =
+        DP_malloc(sizeof(*alphas) * CANVAS_SIZE * CANVAS_SIZE);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            alphas[y * CANVAS_SIZE + x] = DP_layer_content_pixel_at(lc, x, y).a;
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    return alphas;
+}
+
+// Draws two hard dabs right next to each other, each in its own message so
+// that they don't share an origin.
+static uint16_t *draw_two_dabs(TEST_PARAMS, DP_DrawContext *dc,
+                               DP_StampMask *sm_or_null, uint32_t grain_id,
+                               uint16_t grain_scale, uint8_t grain_strength)
+{
+    DP_CanvasHistory *ch = make_canvas(TEST_ARGS, dc);
+    if (sm_or_null) {
+        handle(TEST_ARGS, ch, dc, DP_stamp_mask_to_message(sm_or_null, 1));
+    }
+    DP_GrainTestDab gtd = {20 * 256};
+    static const int xs[] = {23, 37};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(xs); ++i) {
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_draw_dabs_soft_new(
+                   1, LAYER_ID, xs[i] * 4, 30 * 4, 0xff000000,
+                   DP_BLEND_MODE_NORMAL, DP_CLASSIC_BRUSH_FALLOFF_RAMP,
+                   grain_id, grain_scale, grain_strength, set_soft_dab, 1,
+                   &gtd));
+    }
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+    return take_alphas(ch);
+}
+
+// With a grain that's either fully on or fully off, every pixel must either
+// match the grainless result exactly or be empty, depending on the grain at
+// that canvas position. Returns how many pixels the grain kept.
+static int check_grain_pattern(TEST_PARAMS, const uint16_t *plain,
+                               const uint16_t *grained, const char *title)
+{
+    int kept = 0;
+    bool matches = true;
+    for (int y = 0; y < CANVAS_SIZE && matches; ++y) {
+        for (int x = 0; x < CANVAS_SIZE && matches; ++x) {
+            uint16_t expected = grain_value_at(x % GRAIN_SIZE, y % GRAIN_SIZE)
+                                      == 255
+                                  ? plain[y * CANVAS_SIZE + x]
+                                  : 0;
+            uint16_t actual = grained[y * CANVAS_SIZE + x];
+            if (actual != expected) {
+                matches = false;
+                DIAG("pixel %d,%d has alpha %d, expected %d", x, y,
+                     (int)actual, (int)expected);
+            }
+            else if (actual != 0) {
+                ++kept;
+            }
+        }
+    }
+    OK(matches, "%s", title);
+    return kept;
+}
+
+
+static void grain_continuous_across_dabs(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_grain_mask();
+    uint32_t grain_id = DP_stamp_mask_id(sm);
+
+    uint16_t *plain = draw_two_dabs(TEST_ARGS, dc, NULL, 0, 0, 0);
+    uint16_t *grained = draw_two_dabs(TEST_ARGS, dc, sm, grain_id, 256, 255);
+    int kept = check_grain_pattern(TEST_ARGS, plain, grained,
+                                   "grain lines up across both dabs");
+
+    // The dabs meet around x = 30. Make sure the grain let something through
+    // on both sides of that, otherwise there's no seam being tested.
+    bool left = false;
+    bool right = false;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            if (grained[y * CANVAS_SIZE + x] != 0) {
+                if (x < 30) {
+                    left = true;
+                }
+                else {
+                    right = true;
+                }
+            }
+        }
+    }
+    OK(kept > 0 && left && right, "grain keeps pixels on both sides");
+
+    DP_free(grained);
+    DP_free(plain);
+    DP_stamp_mask_decref(sm);
+    DP_draw_context_free(dc);
+}
+
+static void grain_off_leaves_dabs_alone(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_grain_mask();
+    uint32_t grain_id = DP_stamp_mask_id(sm);
+
+    uint16_t *plain = draw_two_dabs(TEST_ARGS, dc, NULL, 0, 0, 0);
+    uint16_t *weak = draw_two_dabs(TEST_ARGS, dc, sm, grain_id, 256, 0);
+    uint16_t *unknown =
+        draw_two_dabs(TEST_ARGS, dc, NULL, 0xdeadbeef, 256, 255);
+    bool weak_matches = true;
+    bool unknown_matches = true;
+    for (int i = 0; i < CANVAS_SIZE * CANVAS_SIZE; ++i) {
+        weak_matches = weak_matches && weak[i] == plain[i];
+        unknown_matches = unknown_matches && unknown[i] == plain[i];
+    }
+    OK(weak_matches, "zero grain strength does nothing");
+    OK(unknown_matches, "unknown grain does nothing");
+
+    DP_free(unknown);
+    DP_free(weak);
+    DP_free(plain);
+    DP_stamp_mask_decref(sm);
+    DP_draw_context_free(dc);
+}
+
+static void stroke_brush(const DP_ClassicBrush *cb, DP_GrainTestMessages *gtms)
+{
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, gtms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){8.0f, 32.0f, 1.0f, 0.0f, 0.0f, 0.0f, 0}, NULL);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){56.0f, 32.0f, 1.0f, 0.0f, 0.0f, 0.0f, 10}, NULL);
+    DP_brush_engine_stroke_end(be, 20, NULL, true);
+    DP_brush_engine_free(be);
+}
+
+static uint16_t *replay(TEST_PARAMS, DP_DrawContext *dc,
+                        DP_GrainTestMessages *gtms)
+{
+    DP_CanvasHistory *ch = make_canvas(TEST_ARGS, dc);
+    for (int i = 0; i < gtms->count; ++i) {
+        handle(TEST_ARGS, ch, dc, gtms->msgs[i]);
+    }
+    gtms->count = 0;
+    return take_alphas(ch);
+}
+
+static void grain_brush_engine_sends_mask(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_grain_mask();
+    DP_stamp_mask_register(sm);
+
+    DP_ClassicBrush cb = {0};
+    cb.size.min = 12.0f;
+    cb.size.max = 12.0f;
+    cb.hardness.max = 1.0f;
+    cb.opacity.max = 1.0f;
+    cb.spacing = 0.25f;
+    cb.color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb.shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb.brush_mode = DP_BLEND_MODE_NORMAL;
+    cb.erase_mode = DP_BLEND_MODE_ERASE;
+    cb.incremental = true;
+
+    DP_GrainTestMessages plain_gtms = {0};
+    stroke_brush(&cb, &plain_gtms);
+    INT_EQ_OK(plain_gtms.stamp_masks, 0, "no grain, no mask sent");
+    OK(plain_gtms.classic_dabs > 0, "no grain sends classicdabs");
+
+    cb.grain = DP_stamp_mask_id(sm);
+    cb.grain_scale = 1.0f;
+    cb.grain_strength = 1.0f;
+    DP_GrainTestMessages grained_gtms = {0};
+    grained_gtms.grain_id = cb.grain;
+    stroke_brush(&cb, &grained_gtms);
+    INT_EQ_OK(grained_gtms.stamp_masks, 1, "grain mask sent once");
+    OK(grained_gtms.grain_dabs > 0, "dabs refer to the grain");
+    INT_EQ_OK(grained_gtms.classic_dabs, 0, "grain sends no classicdabs");
+
+    DP_stamp_mask_decref(sm);
+    uint16_t *plain = replay(TEST_ARGS, dc, &plain_gtms);
+    uint16_t *grained = replay(TEST_ARGS, dc, &grained_gtms);
+    int kept = check_grain_pattern(TEST_ARGS, plain, grained,
+                                   "stroke gets grain from the sent mask");
+    OK(kept > 0, "grain keeps some of the stroke");
+
+    DP_free(grained);
+    DP_free(plain);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(grain_continuous_across_dabs);
+    REGISTER_TEST(grain_off_leaves_dabs_alone);
+    REGISTER_TEST(grain_brush_engine_sends_mask);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -61,6 +61,9 @@ static void dump(DP_Output *output, DP_CanvasHistory *ch, const char *title)
                          DP_annotation_protect(a) ? "true" : "false");
         DP_output_format(output, "    valign = %s\n",
                          dump_valign(DP_annotation_valign(a)));
+        DP_output_format(output, "    border = %d\n", DP_annotation_border(a));
+        DP_output_format(output, "    auto_grow = %s\n",
+                         DP_annotation_auto_grow(a) ? "true" : "false");
         size_t length;
         const char *text = DP_annotation_text(a, &length);
         DP_output_format(output, "    text_length = %zu\n", length);
@@ -96,11 +99,12 @@ static void add_annotation_reshape(DP_Output *output, DP_CanvasHistory *ch,
 static void add_annotation_edit(DP_Output *output, DP_CanvasHistory *ch,
                                 DP_DrawContext *dc, uint16_t id,
                                 uint32_t background_color, uint8_t flags,
-                                const char *text)
+                                uint8_t border, const char *text)
 {
     add_message(output, ch, dc,
-                DP_msg_annotation_edit_new(1, id, background_color, flags, 0,
-                                           text, text ? strlen(text) : 0));
+                DP_msg_annotation_edit_new(1, id, background_color, flags,
+                                           border, text,
+                                           text ? strlen(text) : 0));
 }
 
 static void add_annotation_delete(DP_Output *output, DP_CanvasHistory *ch,
@@ -145,20 +149,32 @@ static void handle_annotations(DP_Output *output, DP_CanvasHistory *ch,
     add_annotation_edit(output, ch, dc, 257, 0xffffffffu,
                         DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT
                             | DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER,
-                        "first annotation");
+                        0, "first annotation");
     dump(output, ch, "first annotation edited");
 
     add_undo_point(output, ch, dc);
     add_annotation_edit(output, ch, dc, 257, 0xffabcdefu,
-                        DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM, NULL);
+                        DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM, 0, NULL);
     dump(output, ch, "first annotation edited with empty text");
 
     add_undo_point(output, ch, dc);
-    add_annotation_edit(output, ch, dc, 258, 0x0u, 0, "second annotation");
+    add_annotation_edit(output, ch, dc, 258, 0x0u, 0, 0, "second annotation");
     dump(output, ch, "second annotation edited");
 
     add_undo_point(output, ch, dc);
-    add_annotation_edit(output, ch, dc, 259, 0x0u, 0, NULL);
+    add_annotation_edit(output, ch, dc, 258, 0x80123456u, 0, 3,
+                        "second annotation");
+    dump(output, ch, "second annotation edited with translucent background "
+                     "and border");
+
+    add_undo_point(output, ch, dc);
+    add_annotation_edit(output, ch, dc, 258, 0x80123456u,
+                        DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW, 3,
+                        "second annotation");
+    dump(output, ch, "second annotation edited with auto-grow");
+
+    add_undo_point(output, ch, dc);
+    add_annotation_edit(output, ch, dc, 259, 0x0u, 0, 0, NULL);
     dump(output, ch, "nonexistent annotation edited with error");
 
     add_undo_point(output, ch, dc);
@@ -0,0 +1,511 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/image_transform.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/messages.h>
+#include <dptest_engine.h>
+#include <math.h>
+#include <stdlib.h>
+
+
+static const int modes[] = {
+    DP_MSG_TRANSFORM_REGION_MODE_NEAREST,
+    DP_MSG_TRANSFORM_REGION_MODE_BILINEAR,
+    DP_MSG_TRANSFORM_REGION_MODE_BICUBIC,
+};
+
+static const char *mode_name(int mode)
+{
+    return DP_msg_transform_region_mode_variant_name((unsigned int)mode);
+}
+
+// Deterministic noise, every channel is all over the place so that any pixel
+// getting skipped or smeared shows up.
+static uint8_t noise(int x, int y, int channel)
+{
+    unsigned int h = (unsigned int)x * 73856093u ^ (unsigned int)y * 19349663u
+                   ^ (unsigned int)channel * 83492791u;
+    h ^= h >> 13;
+    h *= 0x5bd1e995u;
+    h ^= h >> 15;
+    return (uint8_t)(h & 0xffu);
+}
+
+static DP_Image *make_noise_image(int width, int height)
+{
+    DP_Image *img = DP_image_new(width, height);
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            uint8_t a = noise(x, y, 3);
+            DP_image_pixel_at_set(
+                img, x, y,
+                (DP_Pixel8){.b = DP_uint_to_uint8(noise(x, y, 0) * a / 255u),
+                            .g = DP_uint_to_uint8(noise(x, y, 1) * a / 255u),
+                            .r = DP_uint_to_uint8(noise(x, y, 2) * a / 255u),
+                            .a = a});
+        }
+    }
+    return img;
+}
+
+static int channel_difference(DP_Pixel8 a, DP_Pixel8 b)
+{
+    int db = abs(a.b - b.b), dg = abs(a.g - b.g);
+    int dr = abs(a.r - b.r), da = abs(a.a - b.a);
+    return DP_max_int(DP_max_int(db, dg), DP_max_int(dr, da));
+}
+
+static DP_Image *transform(TEST_PARAMS, DP_Image *img, DP_DrawContext *dc,
+                           DP_Quad quad, int mode)
+{
+    int offset_x, offset_y;
+    DP_Image *result =
+        DP_image_transform(img, dc, &quad, mode, &offset_x, &offset_y);
+    if (NOT_NULL_OK(result, "%s transform succeeded", mode_name(mode))) {
+        INT_EQ_OK(offset_x, 0, "%s offset x", mode_name(mode));
+        INT_EQ_OK(offset_y, 0, "%s offset y", mode_name(mode));
+    }
+    return result;
+}
+
+
+static void rotate_90_degrees(TEST_PARAMS)
+{
+    int width = 24, height = 16;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Image *img = make_noise_image(width, height);
+    // Clockwise, the top-left corner ends up at the top-right.
+    DP_Quad quad = DP_quad_make(height, 0, height, width, 0, width, 0, 0);
+
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(modes); ++i) {
+        int mode = modes[i];
+        DP_Image *rotated = transform(TEST_ARGS, img, dc, quad, mode);
+        if (!rotated) {
+            continue;
+        }
+        // Quad bounds are inclusive, so there's an extra blank column and
+        // row at the end.
+        INT_EQ_OK(DP_image_width(rotated), height + 1, "%s rotated width",
+                  mode_name(mode));
+        INT_EQ_OK(DP_image_height(rotated), width + 1, "%s rotated height",
+                  mode_name(mode));
+
+        int max_difference = 0;
+        for (int y = 0; y < width; ++y) {
+            for (int x = 0; x < height; ++x) {
+                DP_Pixel8 expected = DP_image_pixel_at(img, y, height - 1 - x);
+                max_difference = DP_max_int(
+                    max_difference,
+                    channel_difference(DP_image_pixel_at(rotated, x, y),
+                                       expected));
+            }
+        }
+        // Bilinear interpolation runs in 8 bit fixed point, which truncates
+        // twice, so it may be a step further off than the rest.
+        OK(max_difference <= 2, "%s rotation matches reference",
+           mode_name(mode));
+        if (max_difference > 2) {
+            DIAG("maximum channel difference %d", max_difference);
+        }
+        DP_image_free(rotated);
+    }
+
+    DP_image_free(img);
+    DP_draw_context_free(dc);
+}
+
+// Squared difference against averaging each block of source pixels, which is
+// what an ideal downscale by a whole factor comes out as. Anything that skips
+// over source pixels instead picks up aliasing that makes this go up.
+static double aliasing_energy(DP_Image *img, DP_Image *scaled, int factor)
+{
+    int width = DP_image_width(img) / factor;
+    int height = DP_image_height(img) / factor;
+    double energy = 0.0;
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            int sum = 0;
+            for (int j = 0; j < factor; ++j) {
+                for (int i = 0; i < factor; ++i) {
+                    sum += DP_image_pixel_at(img, x * factor + i,
+                                             y * factor + j)
+                               .g;
+                }
+            }
+            double expected =
+                DP_int_to_double(sum) / DP_int_to_double(factor * factor);
+            double d = DP_uint8_to_double(DP_image_pixel_at(scaled, x, y).g)
+                     - expected;
+            energy += d * d;
+        }
+    }
+    return energy / DP_int_to_double(width * height);
+}
+
+static void downscale(TEST_PARAMS, int factor, double max_energy)
+{
+    int size = 64;
+    int scaled_size = size / factor;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Image *img = make_noise_image(size, size);
+    DP_Quad quad = DP_quad_make(0, 0, scaled_size, 0, scaled_size, scaled_size,
+                                0, scaled_size);
+
+    // Nearest neighbor samples a single pixel out of each block, so it
+    // aliases on purpose, which makes for a good point of comparison.
+    DP_Image *nearest_scaled = transform(
+        TEST_ARGS, img, dc, quad, DP_MSG_TRANSFORM_REGION_MODE_NEAREST);
+    DP_Image *bicubic_scaled = transform(TEST_ARGS, img, dc, quad,
+                                         DP_MSG_TRANSFORM_REGION_MODE_BICUBIC);
+    if (nearest_scaled && bicubic_scaled) {
+        double nearest = aliasing_energy(img, nearest_scaled, factor);
+        double bicubic = aliasing_energy(img, bicubic_scaled, factor);
+        bool ok = OK(bicubic <= max_energy,
+                     "bicubic downscale by %d is prefiltered", factor);
+        ok = OK(bicubic * 10.0 < nearest,
+                "bicubic downscale by %d aliases far less than nearest",
+                factor)
+          && ok;
+        if (!ok) {
+            DIAG("aliasing energy %f, nearest %f", bicubic, nearest);
+        }
+    }
+
+    DP_image_free(bicubic_scaled);
+    DP_image_free(nearest_scaled);
+    DP_image_free(img);
+    DP_draw_context_free(dc);
+}
+
+static void downscale_by_half(TEST_PARAMS)
+{
+    downscale(TEST_ARGS, 2, 4.0);
+}
+
+static void downscale_by_quarter(TEST_PARAMS)
+{
+    downscale(TEST_ARGS, 4, 4.0);
+}
+
+static int count_differences(DP_Image *a, DP_Image *b, int max_difference)
+{
+    int count = 0;
+    int width = DP_image_width(a);
+    int height = DP_image_height(a);
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            if (channel_difference(DP_image_pixel_at(a, x, y),
+                                   DP_image_pixel_at(b, x, y))
+                > max_difference) {
+                ++count;
+            }
+        }
+    }
+    return count;
+}
+
+// Clients without the prefilter draw bilinear transforms straight from the
+// source image, so the result has to stay exactly the same to match them.
+static void bilinear_is_not_prefiltered(TEST_PARAMS)
+{
+    int size = 64;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Image *img = make_noise_image(size, size);
+    DP_Quad quad = DP_quad_make(0, 0, 16, 0, 16, 16, 0, 16);
+    DP_Image *scaled = transform(TEST_ARGS, img, dc, quad,
+                                 DP_MSG_TRANSFORM_REGION_MODE_BILINEAR);
+    DP_MaybeTransform mtf = DP_transform_quad_to_quad(
+        DP_quad_make(0, 0, size, 0, size, size, 0, size), quad);
+    if (scaled && OK(mtf.valid, "got transform")) {
+        DP_Image *expected =
+            DP_image_new(DP_image_width(scaled), DP_image_height(scaled));
+        if (OK(DP_image_transform_draw(size, size, DP_image_pixels(img), dc,
+                                       expected, mtf.tf,
+                                       DP_MSG_TRANSFORM_REGION_MODE_BILINEAR),
+               "unfiltered transform succeeded")) {
+            INT_EQ_OK(count_differences(scaled, expected, 0), 0,
+                      "bilinear downscale matches unfiltered transform");
+        }
+        DP_image_free(expected);
+    }
+    DP_image_free(scaled);
+    DP_image_free(img);
+    DP_draw_context_free(dc);
+}
+
+// Shrinking only one axis must leave the other one alone. Each row is a
+// single color, so any blurring across rows would mix them up.
+static void bicubic_downscale_one_axis(TEST_PARAMS)
+{
+    int width = 64, height = 16;
+    DP_Image *img = DP_image_new(width, height);
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            DP_image_pixel_at_set(img, x, y,
+                                  y % 2 == 0 ? (DP_Pixel8){.b = 255,
+                                                           .g = 255,
+                                                           .r = 255,
+                                                           .a = 255}
+                                             : (DP_Pixel8){.a = 255});
+        }
+    }
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Quad quad = DP_quad_make(0, 0, 16, 0, 16, height, 0, height);
+    DP_Image *scaled = transform(TEST_ARGS, img, dc, quad,
+                                 DP_MSG_TRANSFORM_REGION_MODE_BICUBIC);
+    if (scaled) {
+        int wrong = 0;
+        for (int y = 0; y < height; ++y) {
+            for (int x = 0; x < 16; ++x) {
+                if (channel_difference(DP_image_pixel_at(scaled, x, y),
+                                       DP_image_pixel_at(img, 0, y))
+                    > 1) {
+                    ++wrong;
+                }
+            }
+        }
+        INT_EQ_OK(wrong, 0, "rows stay unblurred");
+        DP_image_free(scaled);
+    }
+    DP_draw_context_free(dc);
+    DP_image_free(img);
+}
+
+// Halving an odd size needs to repeat the edge pixels rather than padding
+// with transparency, which would leave a translucent fringe behind.
+static void bicubic_downscale_odd_size(TEST_PARAMS)
+{
+    int size = 67;
+    DP_Image *img = DP_image_new(size, size);
+    for (int y = 0; y < size; ++y) {
+        for (int x = 0; x < size; ++x) {
+            DP_image_pixel_at_set(
+                img, x, y, (DP_Pixel8){.b = 255, .g = 255, .r = 255, .a = 255});
+        }
+    }
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Quad quad = DP_quad_make(0, 0, 16, 0, 16, 16, 0, 16);
+    DP_Image *scaled = transform(TEST_ARGS, img, dc, quad,
+                                 DP_MSG_TRANSFORM_REGION_MODE_BICUBIC);
+    if (scaled) {
+        int translucent = 0;
+        for (int y = 0; y < 16; ++y) {
+            for (int x = 0; x < 16; ++x) {
+                if (DP_image_pixel_at(scaled, x, y).a != 255) {
+                    ++translucent;
+                }
+            }
+        }
+        INT_EQ_OK(translucent, 0, "no translucent fringe");
+        DP_image_free(scaled);
+    }
+    DP_draw_context_free(dc);
+    DP_image_free(img);
+}
+
+static void bicubic_stays_premultiplied(TEST_PARAMS)
+{
+    // Hard edges between opaque white and transparent make the negative lobes
+    // of the bicubic kernel overshoot.
+    DP_Image *img = DP_image_new(8, 8);
+    for (int y = 0; y < 8; ++y) {
+        for (int x = 0; x < 8; ++x) {
+            DP_image_pixel_at_set(img, x, y,
+                                  (x + y) % 2 == 0
+                                      ? (DP_Pixel8){.b = 255,
+                                                    .g = 255,
+                                                    .r = 255,
+                                                    .a = 255}
+                                      : (DP_Pixel8){.color = 0});
+        }
+    }
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Quad quad = DP_quad_make(1, 2, 21, 5, 17, 24, -2, 19);
+    int offset_x, offset_y;
+    DP_Image *result =
+        DP_image_transform(img, dc, &quad, DP_MSG_TRANSFORM_REGION_MODE_BICUBIC,
+                           &offset_x, &offset_y);
+    if (NOT_NULL_OK(result, "bicubic transform succeeded")) {
+        int invalid = 0;
+        int width = DP_image_width(result);
+        int height = DP_image_height(result);
+        for (int y = 0; y < height; ++y) {
+            for (int x = 0; x < width; ++x) {
+                DP_Pixel8 pixel = DP_image_pixel_at(result, x, y);
+                if (pixel.b > pixel.a || pixel.g > pixel.a
+                    || pixel.r > pixel.a) {
+                    ++invalid;
+                }
+            }
+        }
+        INT_EQ_OK(invalid, 0, "no color channel exceeds alpha");
+        DP_image_free(result);
+    }
+    DP_draw_context_free(dc);
+    DP_image_free(img);
+}
+
+
+static DP_Pixel8 checker_color(double u, double v)
+{
+    bool white = ((int)(u / 4.0) + (int)(v / 4.0)) % 2 == 0;
+    return white ? (DP_Pixel8){.b = 255, .g = 255, .r = 255, .a = 255}
+                 : (DP_Pixel8){.b = 0, .g = 0, .r = 0, .a = 255};
+}
+
+// Distance from the closest boundary between checkerboard cells.
+static double checker_edge_distance(double value)
+{
+    double offset = fmod(value, 4.0);
+    return offset < 2.0 ? offset : 4.0 - offset;
+}
+
+static DP_Image *make_checkerboard(int size)
+{
+    DP_Image *img = DP_image_new(size, size);
+    for (int y = 0; y < size; ++y) {
+        for (int x = 0; x < size; ++x) {
+            DP_image_pixel_at_set(img, x, y, checker_color(x + 0.5, y + 0.5));
+        }
+    }
+    return img;
+}
+
+static void perspective_trapezoid(TEST_PARAMS)
+{
+    int size = 32;
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Image *img = make_checkerboard(size);
+    // Narrower at the top, so it looks like it's tilted away.
+    DP_Quad quad = DP_quad_make(8, 0, 40, 0, 48, 32, 0, 32);
+    DP_Image *result = transform(TEST_ARGS, img, dc, quad,
+                                 DP_MSG_TRANSFORM_REGION_MODE_BILINEAR);
+    DP_MaybeTransform mtf = DP_transform_quad_to_quad(
+        DP_quad_make(0, 0, size, 0, size, size, 0, size), quad);
+    // The inverse comes out transposed, flip it back to map points with it.
+    DP_MaybeTransform minv = DP_transform_invert(mtf.tf);
+    if (!result || !OK(mtf.valid && minv.valid, "got transforms")) {
+        DP_image_free(result);
+        DP_image_free(img);
+        DP_draw_context_free(dc);
+        return;
+    }
+    DP_Transform inv = DP_transform_transpose(minv.tf);
+
+    // Pixels well within a mapped cell get its color exactly.
+    int wrong_cells = 0;
+    for (int y = 0; y < 32; ++y) {
+        for (int x = 0; x < 48; ++x) {
+            DP_Vec2 src = DP_transform_xy(inv, x + 0.5, y + 0.5);
+            if (src.x > 1.5 && src.x < size - 1.5 && src.y > 1.5
+                && src.y < size - 1.5 && checker_edge_distance(src.x) > 1.5
+                && checker_edge_distance(src.y) > 1.5) {
+                DP_Pixel8 expected = checker_color(src.x, src.y);
+                if (channel_difference(DP_image_pixel_at(result, x, y),
+                                       expected)
+                    > 1) {
+                    ++wrong_cells;
+                }
+            }
+        }
+    }
+    INT_EQ_OK(wrong_cells, 0, "cells get mapped to the right place");
+
+    // Vertical cell boundaries become slanted lines, find where each row
+    // crosses them and check that they're all on the same straight line.
+    double max_deviation = 0.0;
+    int crossings = 0;
+    for (int u = 4; u < size; u += 4) {
+        DP_Vec2 top = DP_transform_xy(mtf.tf, u, 0.0);
+        DP_Vec2 bottom = DP_transform_xy(mtf.tf, u, size);
+        for (int y = 0; y < 32; ++y) {
+            double cy = y + 0.5;
+            DP_Vec2 src = DP_transform_xy(inv, top.x, cy);
+            if (checker_edge_distance(src.y) < 1.0) {
+                continue;
+            }
+            double expected =
+                top.x + (bottom.x - top.x) * (cy - top.y) / (bottom.y - top.y);
+            int start = (int)expected - 3;
+            for (int x = start; x < start + 6; ++x) {
+                int g0 = DP_image_pixel_at(result, x, y).g;
+                int g1 = DP_image_pixel_at(result, x + 1, y).g;
+                if ((g0 - 128) * (g1 - 128) <= 0 && g0 != g1) {
+                    double crossing = x + 0.5 + (128.0 - g0) / (g1 - g0);
+                    max_deviation =
+                        DP_max_double(max_deviation, fabs(crossing - expected));
+                    ++crossings;
+                    break;
+                }
+            }
+        }
+    }
+    OK(crossings > 50, "found edge crossings");
+    OK(max_deviation < 0.5, "mapped edges are straight lines");
+    if (max_deviation >= 0.5) {
+        DIAG("maximum deviation %f", max_deviation);
+    }
+
+    DP_image_free(result);
+    DP_image_free(img);
+    DP_draw_context_free(dc);
+}
+
+static void degenerate_quads(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Image *img = make_checkerboard(16);
+    struct {
+        DP_Quad quad;
+        const char *title;
+    } cases[] = {
+        {DP_quad_make(0, 0, 10, 10, 10, 0, 0, 10), "self-intersecting"},
+        {DP_quad_make(0, 0, 10, 0, 20, 0, 5, 0), "zero area"},
+        {DP_quad_make(0, 0, 10, 0, 10, 10, 0, 10), "valid"},
+        {DP_quad_make(0, 0, 10, 0, 10, 10, 10, 10), "corners collapsed"},
+        {DP_quad_make(0, 0, 20, 0, 5, 5, 0, 20), "concave"},
+        {DP_quad_make(0, 10, 10, 10, 10, 0, 0, 0), "flipped"},
+    };
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        bool valid = i == 2 || i == 5;
+        DP_Image *result =
+            DP_image_transform(img, dc, &cases[i].quad,
+                               DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, NULL,
+                               NULL);
+        if (valid) {
+            NOT_NULL_OK(result, "%s quad gets transformed", cases[i].title);
+        }
+        else {
+            NULL_OK(result, "%s quad is an error", cases[i].title);
+        }
+        DP_image_free(result);
+    }
+    DP_image_free(img);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(rotate_90_degrees);
+    REGISTER_TEST(downscale_by_half);
+    REGISTER_TEST(downscale_by_quarter);
+    REGISTER_TEST(bilinear_is_not_prefiltered);
+    REGISTER_TEST(bicubic_downscale_one_axis);
+    REGISTER_TEST(bicubic_downscale_odd_size);
+    REGISTER_TEST(bicubic_stays_premultiplied);
+    REGISTER_TEST(perspective_trapezoid);
+    REGISTER_TEST(degenerate_quads);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_list.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  64
+#define MAX_MESSAGES 512
+
+typedef struct DP_IndirectTestMessages {
+    int count;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_IndirectTestMessages;
+
+typedef struct DP_IndirectTestAlphas {
+    int painted;
+    uint16_t min;
+    uint16_t max;
+} DP_IndirectTestAlphas;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_IndirectTestMessages *itms = user;
+    if (itms->count < MAX_MESSAGES) {
+        itms->msgs[itms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void dispose_messages(DP_IndirectTestMessages *itms)
+{
+    for (int i = 0; i < itms->count; ++i) {
+        DP_message_decref(itms->msgs[i]);
+    }
+    itms->count = 0;
+}
+
+static void init_brush(DP_ClassicBrush *cb, bool incremental, bool erase)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 6.0f;
+    cb->size.max = 6.0f;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 0.5f;
+    cb->spacing = 0.1f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->erase = erase;
+    cb->incremental = incremental;
+}
+
+// A stroke in the shape of an hourglass, which crosses over itself in the
+// middle and ends where it started. Finishes with a pen up, which is what
+// merges an indirect stroke into the layer.
+static void stroke_hourglass(const DP_ClassicBrush *cb,
+                             DP_IndirectTestMessages *itms)
+{
+    static const float points[][2] = {
+        {8.0f, 8.0f}, {56.0f, 56.0f}, {8.0f, 56.0f},
+        {56.0f, 8.0f}, {8.0f, 8.0f},
+    };
+    itms->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, itms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    int count = DP_ARRAY_LENGTH(points);
+    for (int i = 0; i < count; ++i) {
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){points[i][0], points[i][1], 1.0f, 0.0f, 0.0f, 0.0f,
+                            i * 100},
+            NULL);
+    }
+    DP_brush_engine_stroke_end(be, count * 100, NULL, true);
+    DP_brush_engine_free(be);
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc,
+                                      bool filled, bool alpha_lock)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    if (filled) {
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                    CANVAS_SIZE / 2, CANVAS_SIZE, 0xff00ff00));
+    }
+    if (alpha_lock) {
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_layer_attributes_new(
+                   1, LAYER_ID, 0, DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK,
+                   255, DP_BLEND_MODE_NORMAL));
+    }
+    return ch;
+}
+
+static void replay(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_IndirectTestMessages *itms)
+{
+    for (int i = 0; i < itms->count; ++i) {
+        handle(TEST_ARGS, ch, dc, DP_message_incref(itms->msgs[i]));
+    }
+}
+
+static DP_LayerContent *layer_content(DP_CanvasState *cs)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+// Gathers the alpha values of the pixels in the given half of the canvas that
+// aren't fully transparent or fully opaque.
+static DP_IndirectTestAlphas partial_alphas(DP_LayerContent *lc, bool left)
+{
+    DP_IndirectTestAlphas itas = {0, DP_BIT15, 0};
+    int x_start = left ? 0 : CANVAS_SIZE / 2;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = x_start; x < x_start + CANVAS_SIZE / 2; ++x) {
+            uint16_t a = DP_layer_content_pixel_at(lc, x, y).a;
+            if (a != 0 && a != DP_BIT15) {
+                ++itas.painted;
+                if (a < itas.min) {
+                    itas.min = a;
+                }
+                if (a > itas.max) {
+                    itas.max = a;
+                }
+            }
+        }
+    }
+    return itas;
+}
+
+static bool near_half_alpha(uint16_t a)
+{
+    return a > DP_BIT15 / 2 - 256 && a < DP_BIT15 / 2 + 256;
+}
+
+
+static void indirect_stroke_has_uniform_alpha(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    DP_IndirectTestMessages itms;
+
+    init_brush(&cb, true, false);
+    stroke_hourglass(&cb, &itms);
+    DP_CanvasHistory *direct_ch = make_history(TEST_ARGS, dc, false, false);
+    replay(TEST_ARGS, direct_ch, dc, &itms);
+    dispose_messages(&itms);
+    DP_CanvasState *direct_cs = DP_canvas_history_get(direct_ch);
+    DP_IndirectTestAlphas direct =
+        partial_alphas(layer_content(direct_cs), true);
+    OK(direct.painted > 0, "direct stroke painted %d pixels", direct.painted);
+    OK(direct.max > direct.min,
+       "direct stroke accumulates where dabs overlap, alpha %u to %u",
+       (unsigned int)direct.min, (unsigned int)direct.max);
+
+    init_brush(&cb, false, false);
+    stroke_hourglass(&cb, &itms);
+    DP_CanvasHistory *indirect_ch = make_history(TEST_ARGS, dc, false, false);
+    replay(TEST_ARGS, indirect_ch, dc, &itms);
+    dispose_messages(&itms);
+    DP_CanvasState *indirect_cs = DP_canvas_history_get(indirect_ch);
+    DP_LayerContent *indirect_lc = layer_content(indirect_cs);
+    DP_IndirectTestAlphas indirect = partial_alphas(indirect_lc, true);
+    OK(indirect.painted > 0, "indirect stroke painted %d pixels",
+       indirect.painted);
+    UINT_EQ_OK(indirect.min, indirect.max,
+               "indirect stroke has uniform alpha");
+    OK(near_half_alpha(indirect.min), "indirect stroke alpha %u is the opacity",
+       (unsigned int)indirect.min);
+    INT_EQ_OK(
+        DP_layer_list_count(DP_layer_content_sub_contents_noinc(indirect_lc)),
+        0, "sublayer got merged on pen up");
+
+    DP_canvas_state_decref(indirect_cs);
+    DP_canvas_history_free(indirect_ch);
+    DP_canvas_state_decref(direct_cs);
+    DP_canvas_history_free(direct_ch);
+    DP_draw_context_free(dc);
+}
+
+static void indirect_stroke_replays_identically(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    DP_IndirectTestMessages itms;
+    init_brush(&cb, false, false);
+    stroke_hourglass(&cb, &itms);
+
+    DP_CanvasHistory *a_ch = make_history(TEST_ARGS, dc, true, false);
+    replay(TEST_ARGS, a_ch, dc, &itms);
+    DP_CanvasHistory *b_ch = make_history(TEST_ARGS, dc, true, false);
+    replay(TEST_ARGS, b_ch, dc, &itms);
+    dispose_messages(&itms);
+
+    DP_CanvasState *a_cs = DP_canvas_history_get(a_ch);
+    DP_CanvasState *b_cs = DP_canvas_history_get(b_ch);
+    DP_LayerContent *a_lc = layer_content(a_cs);
+    DP_LayerContent *b_lc = layer_content(b_cs);
+    bool identical = true;
+    for (int y = 0; y < CANVAS_SIZE && identical; ++y) {
+        for (int x = 0; x < CANVAS_SIZE && identical; ++x) {
+            DP_Pixel15 a = DP_layer_content_pixel_at(a_lc, x, y);
+            DP_Pixel15 b = DP_layer_content_pixel_at(b_lc, x, y);
+            if (a.b != b.b || a.g != b.g || a.r != b.r || a.a != b.a) {
+                identical = false;
+                DIAG("pixel %d, %d differs", x, y);
+            }
+        }
+    }
+    OK(identical, "replaying the same messages gives the same pixels");
+
+    DP_canvas_state_decref(b_cs);
+    DP_canvas_state_decref(a_cs);
+    DP_canvas_history_free(b_ch);
+    DP_canvas_history_free(a_ch);
+    DP_draw_context_free(dc);
+}
+
+static void indirect_eraser_has_uniform_alpha(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    DP_IndirectTestMessages itms;
+    init_brush(&cb, false, true);
+    stroke_hourglass(&cb, &itms);
+
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, true, false);
+    replay(TEST_ARGS, ch, dc, &itms);
+    dispose_messages(&itms);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_IndirectTestAlphas erased = partial_alphas(layer_content(cs), true);
+    OK(erased.painted > 0, "indirect eraser erased %d pixels", erased.painted);
+    UINT_EQ_OK(erased.min, erased.max, "indirect eraser has uniform alpha");
+    OK(near_half_alpha(erased.min), "indirect eraser left alpha %u",
+       (unsigned int)erased.min);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void indirect_stroke_respects_alpha_lock(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    DP_IndirectTestMessages itms;
+    init_brush(&cb, false, false);
+    stroke_hourglass(&cb, &itms);
+
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, true, true);
+    replay(TEST_ARGS, ch, dc, &itms);
+    dispose_messages(&itms);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerContent *lc = layer_content(cs);
+    DP_IndirectTestAlphas left = partial_alphas(lc, true);
+    DP_IndirectTestAlphas right = partial_alphas(lc, false);
+    INT_EQ_OK(left.painted, 0, "filled half stays opaque");
+    INT_EQ_OK(right.painted, 0, "empty half stays transparent");
+    DP_Pixel15 crossing =
+        DP_layer_content_pixel_at(lc, CANVAS_SIZE / 4, CANVAS_SIZE / 4);
+    OK(crossing.r > 0 && crossing.g > 0,
+       "stroke recolored the filled half, got red %u and green %u",
+       (unsigned int)crossing.r, (unsigned int)crossing.g);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(indirect_stroke_has_uniform_alpha);
+    REGISTER_TEST(indirect_stroke_replays_identically);
+    REGISTER_TEST(indirect_eraser_has_uniform_alpha);
+    REGISTER_TEST(indirect_stroke_respects_alpha_lock);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/laser_overlay.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define ALICE 1
+#define BOB   2
+#define RED   0xffff0000u
+#define BLUE  0xff0000ffu
+
+static void handle(DP_LaserOverlay *lo, DP_Message *msg, long long time_ms)
+{
+    DP_laser_overlay_handle(lo, msg, time_ms);
+    DP_message_decref(msg);
+}
+
+static void laser(DP_LaserOverlay *lo, unsigned int context_id,
+                  uint32_t color, uint8_t persistence, long long time_ms)
+{
+    handle(lo, DP_msg_laser_trail_new(context_id, color, persistence),
+           time_ms);
+}
+
+static void point(DP_LaserOverlay *lo, unsigned int context_id, int x, int y,
+                  long long time_ms)
+{
+    handle(lo, DP_msg_move_pointer_new(context_id, x * 4, y * 4), time_ms);
+}
+
+#define MAX_TRAILS 8
+
+typedef struct DP_TrailResult {
+    int count;
+    unsigned int context_ids[MAX_TRAILS];
+    uint32_t colors[MAX_TRAILS];
+    double opacities[MAX_TRAILS];
+    int point_counts[MAX_TRAILS];
+    DP_LaserTrailPoint first_points[MAX_TRAILS];
+    DP_LaserTrailPoint last_points[MAX_TRAILS];
+} DP_TrailResult;
+
+static void collect_trail(void *user, unsigned int context_id, uint32_t color,
+                          double opacity, int count,
+                          const DP_LaserTrailPoint *points)
+{
+    DP_TrailResult *tr = user;
+    if (tr->count < MAX_TRAILS) {
+        int i = tr->count++;
+        tr->context_ids[i] = context_id;
+        tr->colors[i] = color;
+        tr->opacities[i] = opacity;
+        tr->point_counts[i] = count;
+        tr->first_points[i] = points[0];
+        tr->last_points[i] = points[count - 1];
+    }
+}
+
+static DP_TrailResult trails_at(DP_LaserOverlay *lo, long long time_ms)
+{
+    DP_TrailResult tr = {0};
+    DP_laser_overlay_trails_at(lo, time_ms, collect_trail, &tr);
+    return tr;
+}
+
+static bool near(double a, double b)
+{
+    return a > b - 0.0001 && a < b + 0.0001;
+}
+
+
+static void laser_trails_fade_and_expire(TEST_PARAMS)
+{
+    DP_LaserOverlay *lo = DP_laser_overlay_new(100);
+    laser(lo, ALICE, RED, 2, 0);
+    INT_EQ_OK(trails_at(lo, 0).count, 0, "trail without points isn't drawn");
+    point(lo, ALICE, 10, 20, 0);
+    point(lo, ALICE, 30, 40, 100);
+    point(lo, ALICE, 50, 60, 200);
+
+    DP_TrailResult tr = trails_at(lo, 1000);
+    if (INT_EQ_OK(tr.count, 1, "one trail")) {
+        UINT_EQ_OK(tr.context_ids[0], ALICE, "trail is by alice");
+        UINT_EQ_OK(tr.colors[0], RED, "trail is red");
+        INT_EQ_OK(tr.point_counts[0], 3, "trail has all points");
+        OK(near(tr.opacities[0], 1.0), "trail is fully opaque");
+        OK(near(tr.first_points[0].x, 10.0) && near(tr.first_points[0].y, 20.0),
+           "first point is in pixels");
+    }
+
+    tr = trails_at(lo, 2200 + DP_LASER_OVERLAY_FADE_MS / 2);
+    OK(tr.count == 1 && near(tr.opacities[0], 0.5),
+       "trail is half faded after persistence runs out");
+    NOK(DP_laser_overlay_expire(lo, 2200), "nothing expires before it fades out");
+
+    tr = trails_at(lo, 2200 + DP_LASER_OVERLAY_FADE_MS);
+    INT_EQ_OK(tr.count, 0, "faded trail isn't drawn");
+    point(lo, ALICE, 70, 80, 2200 + DP_LASER_OVERLAY_FADE_MS);
+    INT_EQ_OK(trails_at(lo, 2200 + DP_LASER_OVERLAY_FADE_MS).count, 0,
+              "faded trail doesn't come back from more points");
+    OK(DP_laser_overlay_expire(lo, 2200 + DP_LASER_OVERLAY_FADE_MS),
+       "faded trail expires");
+    NOK(DP_laser_overlay_expire(lo, 99999), "nothing left to expire");
+
+    DP_laser_overlay_free(lo);
+}
+
+static void laser_trail_points_are_capped(TEST_PARAMS)
+{
+    DP_LaserOverlay *lo = DP_laser_overlay_new(4);
+    laser(lo, ALICE, RED, 1, 0);
+    laser(lo, BOB, BLUE, 1, 0);
+    for (int i = 0; i < 10; ++i) {
+        point(lo, ALICE, i, 0, i);
+    }
+    point(lo, BOB, 100, 100, 10);
+
+    DP_TrailResult tr = trails_at(lo, 10);
+    if (INT_EQ_OK(tr.count, 2, "one trail per user")) {
+        INT_EQ_OK(tr.point_counts[0], 4, "alice's trail is capped");
+        OK(near(tr.first_points[0].x, 6.0), "oldest points were dropped");
+        OK(near(tr.last_points[0].x, 9.0), "newest point is kept");
+        INT_EQ_OK(tr.point_counts[1], 1, "bob's trail is separate");
+    }
+    DP_laser_overlay_free(lo);
+}
+
+static void laser_trail_ends_and_restarts(TEST_PARAMS)
+{
+    DP_LaserOverlay *lo = DP_laser_overlay_new(100);
+    laser(lo, ALICE, RED, 255, 0);
+    point(lo, ALICE, 1, 1, 0);
+    laser(lo, ALICE, 0, 0, 100);
+    point(lo, ALICE, 2, 2, 200);
+    laser(lo, ALICE, BLUE, 1, 300);
+    point(lo, ALICE, 3, 3, 300);
+
+    DP_TrailResult tr = trails_at(lo, 300);
+    if (INT_EQ_OK(tr.count, 2, "ended trail is still visible")) {
+        INT_EQ_OK(tr.point_counts[0], 1, "ended trail got no more points");
+        UINT_EQ_OK(tr.colors[1], BLUE, "new trail in new color");
+        INT_EQ_OK(tr.point_counts[1], 1, "new trail got the new point");
+    }
+
+    tr = trails_at(lo, DP_LASER_OVERLAY_MAX_PERSISTENCE_MS);
+    OK(tr.count == 1 && tr.colors[0] == RED,
+       "persistence is clamped, but still outlasts the shorter trail");
+    INT_EQ_OK(trails_at(lo, DP_LASER_OVERLAY_MAX_PERSISTENCE_MS
+                                + DP_LASER_OVERLAY_FADE_MS)
+                  .count,
+              0, "clamped trail fades out");
+    DP_laser_overlay_free(lo);
+}
+
+
+typedef struct DP_PointerResult {
+    int count;
+    unsigned int context_ids[4];
+    double xs[4];
+    long long times[4];
+} DP_PointerResult;
+
+static void collect_pointer(void *user, unsigned int context_id, double x,
+                            DP_UNUSED double y, long long time_ms)
+{
+    DP_PointerResult *pr = user;
+    if (pr->count < 4) {
+        int i = pr->count++;
+        pr->context_ids[i] = context_id;
+        pr->xs[i] = x;
+        pr->times[i] = time_ms;
+    }
+}
+
+static void laser_overlay_tracks_pointers(TEST_PARAMS)
+{
+    DP_LaserOverlay *lo = DP_laser_overlay_new(100);
+    point(lo, BOB, 5, 5, 10);
+    point(lo, ALICE, 1, 1, 20);
+    point(lo, ALICE, 2, 2, 30);
+    DP_Message *msg = DP_msg_undo_point_new(ALICE);
+    NOK(DP_laser_overlay_handle(lo, msg, 40), "other messages aren't handled");
+    DP_message_decref(msg);
+
+    DP_PointerResult pr = {0};
+    DP_laser_overlay_pointers(lo, collect_pointer, &pr);
+    if (INT_EQ_OK(pr.count, 2, "one pointer per user")) {
+        UINT_EQ_OK(pr.context_ids[0], ALICE, "alice's pointer");
+        OK(near(pr.xs[0], 2.0), "alice's pointer is where she last was");
+        OK(pr.times[0] == 30, "alice's pointer has the last time");
+        UINT_EQ_OK(pr.context_ids[1], BOB, "bob's pointer");
+    }
+    INT_EQ_OK(trails_at(lo, 30).count, 0, "pointing without a laser");
+    DP_laser_overlay_free(lo);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(laser_trails_fade_and_expire);
+    REGISTER_TEST(laser_trail_points_are_capped);
+    REGISTER_TEST(laser_trail_ends_and_restarts);
+    REGISTER_TEST(laser_overlay_tracks_pointers);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
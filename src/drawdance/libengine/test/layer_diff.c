@@ -0,0 +1,442 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_diff.h>
+#include <dpengine/layer_group.h>
+#include <dpengine/layer_list.h>
+#include <dpengine/layer_props.h>
+#include <dpengine/layer_props_list.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <stdio.h>
+
+
+#define USER       1
+#define A          257
+#define B          258
+#define G          259
+#define C          260
+#define MODEL_SIZE 16
+
+// Stand-in for the model a host would keep, only tracking the tree by ids.
+typedef struct ModelNode {
+    int id;
+    int count;
+    int children[MODEL_SIZE];
+} ModelNode;
+
+typedef struct Model {
+    int count;
+    ModelNode nodes[MODEL_SIZE];
+} Model;
+
+typedef struct LayerDiffTest {
+    DP_DrawContext *dc;
+    DP_CanvasHistory *ch;
+    DP_LayerDiff *ld;
+} LayerDiffTest;
+
+static void handle(TEST_PARAMS, LayerDiffTest *ldt, DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ldt->ch, ldt->dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static DP_Message *create(int layer_id, int target_id, unsigned int flags)
+{
+    return DP_msg_layer_tree_create_new(USER, DP_int_to_uint16(layer_id), 0,
+                                        DP_int_to_uint16(target_id), 0,
+                                        DP_uint_to_uint8(flags), "", 0);
+}
+
+static DP_Message *fill(int layer_id)
+{
+    return DP_msg_fill_rect_new(USER, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, 10, 10, 30, 30,
+                                0xff336699u);
+}
+
+// Layers A and B next to group G, which contains layer C, bottom to top.
+static void setup(TEST_PARAMS, LayerDiffTest *ldt)
+{
+    ldt->dc = DP_draw_context_new();
+    ldt->ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    ldt->ld = DP_layer_diff_new();
+    handle(TEST_ARGS, ldt, DP_msg_canvas_resize_new(USER, 0, 100, 100, 0));
+    handle(TEST_ARGS, ldt, create(A, 0, 0));
+    handle(TEST_ARGS, ldt, create(B, 0, 0));
+    handle(TEST_ARGS, ldt,
+           create(G, 0, DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP));
+    handle(TEST_ARGS, ldt,
+           create(C, G, DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO));
+}
+
+static void teardown(LayerDiffTest *ldt)
+{
+    DP_layer_diff_free(ldt->ld);
+    DP_canvas_history_free(ldt->ch);
+    DP_draw_context_free(ldt->dc);
+}
+
+
+static void model_build(Model *m, int parent, DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_props_list_count(lpl);
+    for (int i = 0; i < count; ++i) {
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        int node = m->count++;
+        m->nodes[node] = (ModelNode){DP_layer_props_id(lp), 0, {0}};
+        m->nodes[parent].children[m->nodes[parent].count++] = node;
+        DP_LayerPropsList *child_lpl = DP_layer_props_children_noinc(lp);
+        if (child_lpl) {
+            model_build(m, node, child_lpl);
+        }
+    }
+}
+
+static int model_search(Model *m, int layer_id)
+{
+    for (int i = 0; i < m->count; ++i) {
+        if (m->nodes[i].id == layer_id) {
+            return i;
+        }
+    }
+    return -1;
+}
+
+static bool model_take(Model *m, int parent_id, int index, int layer_id)
+{
+    int parent = model_search(m, parent_id);
+    if (parent == -1 || index < 0 || index >= m->nodes[parent].count
+        || m->nodes[m->nodes[parent].children[index]].id != layer_id) {
+        return false;
+    }
+    ModelNode *mn = &m->nodes[parent];
+    --mn->count;
+    memmove(&mn->children[index], &mn->children[index + 1],
+            sizeof(mn->children[0]) * DP_int_to_size(mn->count - index));
+    return true;
+}
+
+static bool model_put(Model *m, int parent_id, int index, int node)
+{
+    int parent = model_search(m, parent_id);
+    if (parent == -1 || index < 0 || index > m->nodes[parent].count) {
+        return false;
+    }
+    ModelNode *mn = &m->nodes[parent];
+    memmove(&mn->children[index + 1], &mn->children[index],
+            sizeof(mn->children[0]) * DP_int_to_size(mn->count - index));
+    mn->children[index] = node;
+    ++mn->count;
+    return true;
+}
+
+static void model_kill(Model *m, int node)
+{
+    m->nodes[node].id = -1;
+    for (int i = 0; i < m->nodes[node].count; ++i) {
+        model_kill(m, m->nodes[node].children[i]);
+    }
+}
+
+static bool model_apply(Model *m, const DP_LayerDiffEvent *event)
+{
+    int id = event->layer_id;
+    switch (event->type) {
+    case DP_LAYER_DIFF_ADDED:
+        if (model_search(m, id) != -1) {
+            return false;
+        }
+        m->nodes[m->count] = (ModelNode){id, 0, {0}};
+        return model_put(m, event->to_parent_id, event->to_index, m->count++);
+    case DP_LAYER_DIFF_REMOVED: {
+        int node = model_search(m, id);
+        if (!model_take(m, event->from_parent_id, event->from_index, id)) {
+            return false;
+        }
+        model_kill(m, node);
+        return true;
+    }
+    case DP_LAYER_DIFF_MOVED:
+        return model_take(m, event->from_parent_id, event->from_index, id)
+            && model_put(m, event->to_parent_id, event->to_index,
+                         model_search(m, id));
+    default:
+        return model_search(m, id) != -1;
+    }
+}
+
+static bool model_matches(Model *m, int node, DP_LayerPropsList *lpl)
+{
+    int count = DP_layer_props_list_count(lpl);
+    if (m->nodes[node].count != count) {
+        return false;
+    }
+    for (int i = 0; i < count; ++i) {
+        DP_LayerProps *lp = DP_layer_props_list_at_noinc(lpl, i);
+        int child = m->nodes[node].children[i];
+        if (m->nodes[child].id != DP_layer_props_id(lp)) {
+            return false;
+        }
+        DP_LayerPropsList *child_lpl = DP_layer_props_children_noinc(lp);
+        if (child_lpl ? !model_matches(m, child, child_lpl)
+                      : m->nodes[child].count != 0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+
+static void format_events(char *buffer, size_t size, int count,
+                          const DP_LayerDiffEvent *events)
+{
+    buffer[0] = '\0';
+    size_t used = 0;
+    for (int i = 0; i < count && used < size; ++i) {
+        const DP_LayerDiffEvent *e = &events[i];
+        const char *separator = i == 0 ? "" : ", ";
+        int written;
+        switch (e->type) {
+        case DP_LAYER_DIFF_ADDED:
+            written = snprintf(buffer + used, size - used, "%sadd %d to %d:%d",
+                               separator, e->layer_id, e->to_parent_id,
+                               e->to_index);
+            break;
+        case DP_LAYER_DIFF_REMOVED:
+            written = snprintf(buffer + used, size - used,
+                               "%sremove %d from %d:%d", separator,
+                               e->layer_id, e->from_parent_id, e->from_index);
+            break;
+        case DP_LAYER_DIFF_MOVED:
+            written = snprintf(buffer + used, size - used,
+                               "%smove %d from %d:%d to %d:%d", separator,
+                               e->layer_id, e->from_parent_id, e->from_index,
+                               e->to_parent_id, e->to_index);
+            break;
+        case DP_LAYER_DIFF_ATTRIBUTES_CHANGED:
+            written = snprintf(buffer + used, size - used, "%sattributes %d",
+                               separator, e->layer_id);
+            break;
+        case DP_LAYER_DIFF_CONTENT_CHANGED:
+            written = snprintf(buffer + used, size - used, "%scontent %d",
+                               separator, e->layer_id);
+            break;
+        default:
+            written = snprintf(buffer + used, size - used, "%s???", separator);
+            break;
+        }
+        used += DP_int_to_size(written);
+    }
+}
+
+// Handles the messages as one batch, then checks the events describing it and
+// that they turn a model of the previous layers into the current ones.
+static void expect_events(TEST_PARAMS, LayerDiffTest *ldt, int msg_count,
+                          DP_Message **msgs, const char *expected,
+                          const char *title)
+{
+    DP_CanvasState *prev = DP_canvas_history_get(ldt->ch);
+    for (int i = 0; i < msg_count; ++i) {
+        handle(TEST_ARGS, ldt, msgs[i]);
+    }
+    DP_CanvasState *cs = DP_canvas_history_get(ldt->ch);
+
+    int count = DP_layer_diff_update(ldt->ld, cs, prev);
+    INT_EQ_OK(DP_layer_diff_count(ldt->ld), count, "%s count", title);
+    const DP_LayerDiffEvent *events = DP_layer_diff_events(ldt->ld);
+    char buffer[1024];
+    format_events(buffer, sizeof(buffer), count, events);
+    STR_EQ_OK(buffer, expected, "%s events", title);
+
+    Model m = {1, {{0, 0, {0}}}};
+    model_build(&m, 0, DP_canvas_state_layer_props_noinc(prev));
+    bool applied = true;
+    for (int i = 0; i < count && applied; ++i) {
+        applied = model_apply(&m, &events[i]);
+    }
+    OK(applied, "%s events apply to the previous layers in order", title);
+    OK(applied && model_matches(&m, 0, DP_canvas_state_layer_props_noinc(cs)),
+       "%s events turn the previous layers into the current ones", title);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(prev);
+}
+
+
+static void layer_diff_create(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    ldt.dc = DP_draw_context_new();
+    ldt.ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    ldt.ld = DP_layer_diff_new();
+    handle(TEST_ARGS, &ldt, DP_msg_canvas_resize_new(USER, 0, 100, 100, 0));
+
+    DP_Message *msgs[] = {
+        create(A, 0, 0),
+        create(B, 0, 0),
+        create(G, 0, DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP),
+        create(C, G, DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(msgs), msgs,
+                  "add 257 to 0:0, add 258 to 0:1, add 259 to 0:2, "
+                  "add 260 to 259:0",
+                  "create");
+    expect_events(TEST_ARGS, &ldt, 0, NULL, "", "nothing");
+    teardown(&ldt);
+}
+
+static void layer_diff_reorder(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+
+    DP_Message *into_group[] = {DP_msg_layer_tree_move_new(USER, A, G, 0)};
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(into_group), into_group,
+                  "move 257 from 0:0 to 259:1", "move into group");
+
+    DP_Message *out_of_group[] = {DP_msg_layer_tree_move_new(USER, C, 0, B)};
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(out_of_group),
+                  out_of_group, "move 260 from 259:0 to 0:0",
+                  "move out of group");
+
+    DP_Message *shuffle[] = {
+        DP_msg_layer_tree_move_new(USER, G, 0, C),
+        DP_msg_layer_tree_move_new(USER, B, G, 0),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(shuffle), shuffle,
+                  "move 259 from 0:2 to 0:0, move 258 from 0:2 to 259:1",
+                  "shuffle");
+
+    teardown(&ldt);
+}
+
+static void layer_diff_reverse(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+
+    DP_Message *msgs[] = {
+        DP_msg_layer_tree_move_new(USER, G, 0, A),
+        DP_msg_layer_tree_move_new(USER, B, 0, A),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(msgs), msgs,
+                  "move 259 from 0:2 to 0:0, move 258 from 0:2 to 0:1",
+                  "reverse");
+
+    teardown(&ldt);
+}
+
+static void layer_diff_delete(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+
+    DP_Message *single[] = {DP_msg_layer_tree_delete_new(USER, A, 0)};
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(single), single,
+                  "remove 257 from 0:0", "delete layer");
+
+    DP_Message *group[] = {DP_msg_layer_tree_delete_new(USER, G, 0)};
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(group), group,
+                  "remove 259 from 0:1", "delete group with its contents");
+
+    teardown(&ldt);
+}
+
+static void layer_diff_delete_keeping_contents(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+
+    DP_Message *msgs[] = {
+        DP_msg_layer_tree_move_new(USER, C, 0, A),
+        DP_msg_layer_tree_delete_new(USER, G, 0),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(msgs), msgs,
+                  "move 260 from 259:0 to 0:0, remove 259 from 0:3",
+                  "delete group after moving its contents out");
+
+    teardown(&ldt);
+}
+
+static void layer_diff_merge(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+    handle(TEST_ARGS, &ldt, fill(B));
+
+    DP_Message *msgs[] = {DP_msg_layer_tree_delete_new(USER, B, A)};
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(msgs), msgs,
+                  "remove 258 from 0:1, content 257", "merge down");
+
+    teardown(&ldt);
+}
+
+static void layer_diff_changes(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+
+    DP_Message *msgs[] = {
+        DP_msg_layer_attributes_new(USER, A, 0, 0, 128,
+                                    DP_BLEND_MODE_MULTIPLY),
+        DP_msg_layer_retitle_new(USER, G, "Group", 5),
+        fill(C),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(msgs), msgs,
+                  "attributes 257, attributes 259, content 260",
+                  "attributes and content");
+
+    DP_Message *moved_and_filled[] = {
+        DP_msg_layer_tree_move_new(USER, A, G, 0),
+        fill(A),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(moved_and_filled),
+                  moved_and_filled,
+                  "move 257 from 0:0 to 259:1, content 257",
+                  "moved and filled");
+
+    teardown(&ldt);
+}
+
+static void layer_diff_replace(TEST_PARAMS)
+{
+    LayerDiffTest ldt;
+    setup(TEST_ARGS, &ldt);
+
+    DP_Message *msgs[] = {
+        DP_msg_layer_tree_delete_new(USER, A, 0),
+        create(A, 0, DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP),
+    };
+    expect_events(TEST_ARGS, &ldt, DP_ARRAY_LENGTH(msgs), msgs,
+                  "remove 257 from 0:0, add 257 to 0:2",
+                  "layer replaced by group");
+
+    teardown(&ldt);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(layer_diff_create);
+    REGISTER_TEST(layer_diff_reorder);
+    REGISTER_TEST(layer_diff_reverse);
+    REGISTER_TEST(layer_diff_delete);
+    REGISTER_TEST(layer_diff_delete_keeping_contents);
+    REGISTER_TEST(layer_diff_merge);
+    REGISTER_TEST(layer_diff_changes);
+    REGISTER_TEST(layer_diff_replace);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
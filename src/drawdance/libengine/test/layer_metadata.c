@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_props.h>
+#include <dpengine/layer_props_list.h>
+#include <dpengine/pixels.h>
+#include <dpengine/snapshots.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    (DP_TILE_SIZE * 2)
+#define HEIGHT   DP_TILE_SIZE
+#define USER     1
+#define LAYER_ID 0x101
+#define TOP_ID   0x102
+
+typedef struct Metadata {
+    const char *data;
+    size_t size;
+} Metadata;
+
+typedef struct Messages {
+    int count;
+    DP_Message *msgs[64];
+} Messages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void set_metadata(size_t size, unsigned char *out, void *user)
+{
+    Metadata *md = user;
+    memcpy(out, md->data, size);
+}
+
+#define METADATA(STRING) \
+    ((Metadata){(STRING), sizeof(STRING) - 1})
+
+static DP_Message *make_layer_metadata(int layer_id, int color_tag,
+                                       Metadata md)
+{
+    return DP_msg_layer_metadata_new(USER, DP_int_to_uint16(layer_id),
+                                     DP_int_to_uint8(color_tag), set_metadata,
+                                     md.size, &md);
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0xff336699u, 0,
+                                        "bottom", 6));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, TOP_ID, 0, 0, 0, 0, "top", 3));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(USER, TOP_ID, DP_BLEND_MODE_NORMAL, 10, 10,
+                                WIDTH - 20, HEIGHT - 20, 0xffcc8844u));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(USER, TOP_ID, 0, 0, 128,
+                                       DP_BLEND_MODE_MULTIPLY));
+    return ch;
+}
+
+static DP_LayerProps *search_props(DP_CanvasState *cs, int layer_id)
+{
+    DP_LayerPropsList *lpl = DP_canvas_state_layer_props_noinc(cs);
+    int index = DP_layer_props_list_index_by_id(lpl, layer_id);
+    return index == -1 ? NULL : DP_layer_props_list_at_noinc(lpl, index);
+}
+
+static void check_entry(TEST_PARAMS, DP_LayerProps *lp, int index,
+                        const char *expected_key, const char *expected_value)
+{
+    size_t key_length, value_length;
+    const char *key = DP_layer_props_metadata_key_at(lp, index, &key_length);
+    const char *value =
+        DP_layer_props_metadata_value_at(lp, index, &value_length);
+    STR_LEN_EQ_OK(key, key_length, expected_key, strlen(expected_key),
+                  "key of entry %d", index);
+    STR_LEN_EQ_OK(value, value_length, expected_value, strlen(expected_value),
+                  "value of entry %d", index);
+}
+
+
+static void metadata_message_sets_tag_and_entries(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_CanvasState *prev_cs = DP_canvas_history_get(ch);
+
+    handle(TEST_ARGS, ch, dc,
+           make_layer_metadata(LAYER_ID, DP_LAYER_COLOR_TAG_RED,
+                               METADATA("b\0two\0a\0one\0")));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerProps *lp = search_props(cs, LAYER_ID);
+    DP_LayerProps *prev_lp = search_props(prev_cs, LAYER_ID);
+    INT_EQ_OK(DP_layer_props_color_tag(lp), DP_LAYER_COLOR_TAG_RED,
+              "color tag set");
+    if (INT_EQ_OK(DP_layer_props_metadata_count(lp), 2, "two entries")) {
+        check_entry(TEST_ARGS, lp, 0, "a", "one");
+        check_entry(TEST_ARGS, lp, 1, "b", "two");
+    }
+    OK(DP_layer_props_differ(lp, prev_lp), "props differ after tagging");
+    OK(!DP_layer_props_differ(search_props(cs, TOP_ID),
+                              search_props(prev_cs, TOP_ID)),
+       "other layer's props don't differ");
+    DP_canvas_state_decref(prev_cs);
+    prev_cs = cs;
+
+    handle(TEST_ARGS, ch, dc,
+           make_layer_metadata(
+               LAYER_ID, 200,
+               METADATA("c\0three\0\0nokey\0empty\0\0dangling")));
+    cs = DP_canvas_history_get(ch);
+    lp = search_props(cs, LAYER_ID);
+    prev_lp = search_props(prev_cs, LAYER_ID);
+    INT_EQ_OK(DP_layer_props_color_tag(lp), DP_LAYER_COLOR_TAG_NONE,
+              "unknown color tag turns into none");
+    if (INT_EQ_OK(DP_layer_props_metadata_count(lp), 1,
+                  "entries replaced, empty and dangling ones skipped")) {
+        check_entry(TEST_ARGS, lp, 0, "c", "three");
+    }
+    OK(DP_layer_props_differ(lp, prev_lp), "props differ after retagging");
+    DP_canvas_state_decref(prev_cs);
+    DP_canvas_state_decref(cs);
+
+    DP_Message *msg =
+        make_layer_metadata(0x999, DP_LAYER_COLOR_TAG_BLUE, METADATA(""));
+    NOK(DP_canvas_history_handle(ch, dc, msg), "unknown layer rejected");
+    DP_message_decref(msg);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static DP_Image *flatten(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_Image *img = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return img;
+}
+
+static void tags_dont_affect_flatten(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_Image *before = flatten(ch);
+
+    handle(TEST_ARGS, ch, dc,
+           make_layer_metadata(LAYER_ID, DP_LAYER_COLOR_TAG_GREEN,
+                               METADATA("note\0background\0")));
+    handle(TEST_ARGS, ch, dc,
+           make_layer_metadata(TOP_ID, DP_LAYER_COLOR_TAG_PURPLE,
+                               METADATA("x\0y\0")));
+    DP_Image *after = flatten(ch);
+
+    if (NOT_NULL_OK(before, "flatten before tagging")
+        && NOT_NULL_OK(after, "flatten after tagging")) {
+        size_t size = sizeof(DP_Pixel8) * WIDTH * HEIGHT;
+        OK(DP_image_width(after) == WIDTH && DP_image_height(after) == HEIGHT
+               && memcmp(DP_image_pixels(before), DP_image_pixels(after), size)
+                      == 0,
+           "flattened pixels unchanged by tags");
+    }
+
+    DP_image_free(after);
+    DP_image_free(before);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void push_message(void *user, DP_Message *msg)
+{
+    Messages *m = user;
+    if (m->count < (int)DP_ARRAY_LENGTH(m->msgs)) {
+        m->msgs[m->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void reset_image_carries_metadata(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc,
+           make_layer_metadata(LAYER_ID, DP_LAYER_COLOR_TAG_ORANGE,
+                               METADATA("k\0v\0other\0thing\0")));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+
+    Messages m = {0};
+    DP_reset_image_build(cs, USER, push_message, &m);
+    DP_CanvasHistory *reset_ch =
+        DP_canvas_history_new(NULL, NULL, false, NULL);
+    for (int i = 0; i < m.count; ++i) {
+        handle(TEST_ARGS, reset_ch, dc, m.msgs[i]);
+    }
+
+    DP_CanvasState *reset_cs = DP_canvas_history_get(reset_ch);
+    DP_LayerProps *lp = search_props(reset_cs, LAYER_ID);
+    if (NOT_NULL_OK(lp, "layer exists after reset")) {
+        INT_EQ_OK(DP_layer_props_color_tag(lp), DP_LAYER_COLOR_TAG_ORANGE,
+                  "color tag survives reset");
+        if (INT_EQ_OK(DP_layer_props_metadata_count(lp), 2,
+                      "metadata survives reset")) {
+            check_entry(TEST_ARGS, lp, 0, "k", "v");
+            check_entry(TEST_ARGS, lp, 1, "other", "thing");
+        }
+        OK(!DP_layer_props_differ(lp, search_props(cs, LAYER_ID)),
+           "props equal after reset");
+    }
+    lp = search_props(reset_cs, TOP_ID);
+    if (NOT_NULL_OK(lp, "untagged layer exists after reset")) {
+        INT_EQ_OK(DP_layer_props_color_tag(lp), DP_LAYER_COLOR_TAG_NONE,
+                  "untagged layer stays untagged");
+        INT_EQ_OK(DP_layer_props_metadata_count(lp), 0,
+                  "untagged layer has no metadata");
+    }
+
+    DP_canvas_state_decref(reset_cs);
+    DP_canvas_history_free(reset_ch);
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(metadata_message_sets_tag_and_entries);
+    REGISTER_TEST(tags_dont_affect_flatten);
+    REGISTER_TEST(reset_image_carries_metadata);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
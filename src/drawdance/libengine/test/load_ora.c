@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_list.h>
+#include <dpengine/layer_props.h>
+#include <dpengine/layer_props_list.h>
+#include <dpengine/load.h>
+#include <dpengine/save.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// More layers than there are worker threads or slots in the job queue, so
+// that the layers are decoded out of order and have to wait for each other.
+#define WIDTH       300
+#define HEIGHT      200
+#define LAYER_COUNT 70
+#define ORA_PATH    "test/tmp/load_ora.ora"
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+// Opaque colors only, those survive the trip through 8 bit PNGs unchanged.
+static DP_CanvasState *make_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    for (int i = 0; i < LAYER_COUNT; ++i) {
+        int layer_id = 0x100 + i;
+        char *title = DP_format("Layer %d", i);
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_layer_tree_create_new(1, DP_int_to_uint16(layer_id), 0,
+                                            0, 0, 0, title, strlen(title)));
+        DP_free(title);
+        uint32_t rgb = DP_int_to_uint32(0x3a5f17 * (i + 1)) & 0xffffffu;
+        fill_rect(TEST_ARGS, ch, dc, layer_id, i * 3, i * 2, WIDTH - i * 4,
+                  HEIGHT - i * 2, 0xff000000u | rgb);
+        for (int x = i % 7; x < WIDTH; x += 7) {
+            fill_rect(TEST_ARGS, ch, dc, layer_id, x, 0, 1, HEIGHT,
+                      0xff000000u | ~rgb);
+        }
+    }
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+static DP_CanvasState *load(TEST_PARAMS, DP_DrawContext *dc,
+                            unsigned int flags, const char *title)
+{
+    DP_LoadResult result;
+    DP_CanvasState *cs = DP_load_ora(dc, ORA_PATH, flags, NULL, NULL, &result);
+    FATAL(NOT_NULL_OK(cs, "load %s (error: %s)", title, DP_error()));
+    return cs;
+}
+
+static bool tiles_equal(DP_Tile *a, DP_Tile *b)
+{
+    if (a && b) {
+        return memcmp(DP_tile_pixels(a), DP_tile_pixels(b), DP_TILE_BYTES)
+            == 0;
+    }
+    else {
+        return DP_tile_blank(a ? a : b);
+    }
+}
+
+static int count_mismatched_tiles(DP_LayerContent *a, DP_LayerContent *b)
+{
+    int mismatches = 0;
+    for (int y = 0; y < DP_tile_count_round(HEIGHT); ++y) {
+        for (int x = 0; x < DP_tile_count_round(WIDTH); ++x) {
+            if (!tiles_equal(DP_layer_content_tile_at_noinc(a, x, y),
+                             DP_layer_content_tile_at_noinc(b, x, y))) {
+                ++mismatches;
+            }
+        }
+    }
+    return mismatches;
+}
+
+static void check_layers(TEST_PARAMS, DP_CanvasState *expected,
+                         DP_CanvasState *actual, const char *title)
+{
+    DP_LayerList *expected_ll = DP_canvas_state_layers_noinc(expected);
+    DP_LayerList *actual_ll = DP_canvas_state_layers_noinc(actual);
+    DP_LayerPropsList *actual_lpl = DP_canvas_state_layer_props_noinc(actual);
+    FATAL(INT_EQ_OK(DP_layer_list_count(actual_ll), LAYER_COUNT,
+                    "%s layer count", title));
+
+    int misplaced = 0;
+    int mismatched = 0;
+    for (int i = 0; i < LAYER_COUNT; ++i) {
+        size_t length;
+        const char *layer_title = DP_layer_props_title(
+            DP_layer_props_list_at_noinc(actual_lpl, i), &length);
+        char *expected_title = DP_format("Layer %d", i);
+        if (length != strlen(expected_title)
+            || memcmp(layer_title, expected_title, length) != 0) {
+            ++misplaced;
+        }
+        DP_free(expected_title);
+
+        mismatched += count_mismatched_tiles(
+            DP_layer_list_entry_content_noinc(
+                DP_layer_list_at_noinc(expected_ll, i)),
+            DP_layer_list_entry_content_noinc(
+                DP_layer_list_at_noinc(actual_ll, i)));
+    }
+    INT_EQ_OK(misplaced, 0, "%s layers are in the original order", title);
+    INT_EQ_OK(mismatched, 0, "%s layer contents match the original", title);
+}
+
+
+static void load_ora_parallel_matches_serial(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+    FATAL(INT_EQ_OK(DP_save(cs, dc, DP_SAVE_IMAGE_ORA, ORA_PATH, NULL, NULL),
+                    DP_SAVE_RESULT_SUCCESS, "save %s", ORA_PATH));
+
+    DP_CanvasState *parallel = load(TEST_ARGS, dc, DP_LOAD_FLAG_NONE,
+                                    "with worker threads");
+    DP_CanvasState *serial = load(TEST_ARGS, dc, DP_LOAD_FLAG_SINGLE_THREAD,
+                                  "single-threaded");
+    check_layers(TEST_ARGS, cs, parallel, "parallel");
+    check_layers(TEST_ARGS, cs, serial, "serial");
+    OK(DP_canvas_state_checksum(parallel) == DP_canvas_state_checksum(serial),
+       "parallel canvas checksum equals serial one");
+
+    DP_canvas_state_decref(serial);
+    DP_canvas_state_decref(parallel);
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(load_ora_parallel_matches_serial);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH          100
+#define HEIGHT         100
+#define LAYER_ID       257
+#define LOCAL_USER     1
+#define REMOTE_USER    2
+#define MAX_FALLBEHIND 10000 // Same as in canvas_history.c.
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle remote %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void handle_local(TEST_PARAMS, DP_CanvasHistory *ch,
+                         DP_DrawContext *dc, DP_Message *msg)
+{
+    OK(DP_canvas_history_handle_local(ch, dc, msg), "handle local %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(REMOTE_USER, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(REMOTE_USER, LAYER_ID, 0, 0, 0, 0,
+                                        "Layer", 5));
+    return ch;
+}
+
+// Translucent, so the order that overlapping rectangles are drawn in matters.
+static DP_Message *fill_rect(unsigned int user, int x, int y, uint32_t color)
+{
+    return DP_msg_fill_rect_new(user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(x), DP_int_to_uint32(y), 30,
+                                30, color);
+}
+
+static DP_Message *local_rect(void)
+{
+    return fill_rect(LOCAL_USER, 10, 10, 0x80ff0000);
+}
+
+static DP_Message *overlapping_remote_rect(void)
+{
+    return fill_rect(REMOTE_USER, 20, 20, 0x800000ff);
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+// What the canvas looks like when the given messages arrive from the server.
+static uint64_t remote_checksum(TEST_PARAMS, DP_DrawContext *dc, int count,
+                                DP_Message **msgs)
+{
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    for (int i = 0; i < count; ++i) {
+        handle(TEST_ARGS, ch, dc, msgs[i]);
+    }
+    uint64_t value = checksum(ch);
+    DP_canvas_history_free(ch);
+    return value;
+}
+
+#define REMOTE_CHECKSUM(...)                                              \
+    remote_checksum(TEST_ARGS, dc,                                        \
+                    (int)DP_ARRAY_LENGTH(((DP_Message *[]){__VA_ARGS__})), \
+                    (DP_Message *[]){__VA_ARGS__})
+
+
+static void clean_ack(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+
+    handle_local(TEST_ARGS, ch, dc, DP_msg_undo_point_new(LOCAL_USER));
+    handle_local(TEST_ARGS, ch, dc, local_rect());
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 2,
+              "local messages are in the fork");
+    uint64_t expected = REMOTE_CHECKSUM(DP_msg_undo_point_new(LOCAL_USER),
+                                        local_rect());
+    OK(checksum(ch) == expected, "local messages are applied immediately");
+
+    DP_CanvasState *before = DP_canvas_history_get(ch);
+    handle(TEST_ARGS, ch, dc, DP_msg_undo_point_new(LOCAL_USER));
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 1,
+              "echoed undo point is acknowledged");
+    handle(TEST_ARGS, ch, dc, local_rect());
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 0,
+              "echoed rectangle is acknowledged");
+    DP_CanvasState *after = DP_canvas_history_get(ch);
+    OK(before == after, "acknowledging doesn't touch the canvas");
+    NOK(DP_canvas_history_local_fork_fell_behind_reset(ch),
+        "fork didn't fall behind");
+
+    DP_canvas_state_decref(after);
+    DP_canvas_state_decref(before);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void interleaved_concurrent_message(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+
+    handle_local(TEST_ARGS, ch, dc, local_rect());
+    // Doesn't overlap, so it can go on top of the local fork.
+    handle(TEST_ARGS, ch, dc, fill_rect(REMOTE_USER, 60, 60, 0x800000ff));
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 1,
+              "fork is kept for a concurrent foreign message");
+    handle(TEST_ARGS, ch, dc, local_rect());
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 0,
+              "local message is acknowledged after it");
+    OK(checksum(ch)
+           == REMOTE_CHECKSUM(fill_rect(REMOTE_USER, 60, 60, 0x800000ff),
+                              local_rect()),
+       "canvas matches server order");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void interleaved_conflicting_message(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+
+    handle_local(TEST_ARGS, ch, dc, local_rect());
+    uint64_t local_order =
+        REMOTE_CHECKSUM(local_rect(), overlapping_remote_rect());
+    uint64_t server_order =
+        REMOTE_CHECKSUM(overlapping_remote_rect(), local_rect());
+    OK(local_order != server_order, "order of the rectangles matters");
+
+    // The server got this one before the local rectangle.
+    handle(TEST_ARGS, ch, dc, overlapping_remote_rect());
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 0,
+              "conflict rolls back the local fork");
+    OK(checksum(ch) == REMOTE_CHECKSUM(overlapping_remote_rect()),
+       "only the foreign message is left after the rollback");
+    handle(TEST_ARGS, ch, dc, local_rect());
+    OK(checksum(ch) == server_order, "echo gets replayed in server order");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fork_abandoned(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    uint64_t empty = checksum(ch);
+
+    handle_local(TEST_ARGS, ch, dc, local_rect());
+    OK(DP_canvas_history_local_fork_clear(ch, dc), "clear local fork");
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 0, "fork is empty");
+    OK(checksum(ch) == empty, "local message is gone");
+    NOK(DP_canvas_history_local_fork_fell_behind_reset(ch),
+        "clearing isn't falling behind");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void fork_falls_behind(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    uint64_t empty = checksum(ch);
+
+    handle_local(TEST_ARGS, ch, dc, local_rect());
+    bool all_ok = true;
+    for (int i = 0; i < MAX_FALLBEHIND - 1; ++i) {
+        DP_Message *msg = DP_msg_undo_point_new(REMOTE_USER);
+        all_ok = DP_canvas_history_handle(ch, dc, msg) && all_ok;
+        DP_message_decref(msg);
+    }
+    OK(all_ok, "foreign messages are handled");
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 1,
+              "fork is still there just below the limit");
+    NOK(DP_canvas_history_local_fork_fell_behind_reset(ch),
+        "fork hasn't fallen behind yet");
+
+    handle(TEST_ARGS, ch, dc, DP_msg_undo_point_new(REMOTE_USER));
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 0,
+              "fork is thrown away at the limit");
+    OK(checksum(ch) == empty, "local message is rolled back");
+    OK(DP_canvas_history_local_fork_fell_behind_reset(ch),
+       "fork fell behind");
+    NOK(DP_canvas_history_local_fork_fell_behind_reset(ch),
+        "checking clears the flag");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(clean_ack);
+    REGISTER_TEST(interleaved_concurrent_message);
+    REGISTER_TEST(interleaved_conflicting_message);
+    REGISTER_TEST(fork_abandoned);
+    REGISTER_TEST(fork_falls_behind);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,279 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/memory_usage.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define DUPLICATE_ID 258
+#define USER_A       1
+#define USER_B       2
+#define CANVAS_SIZE  (DP_TILE_SIZE * 2)
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+static void draw_pixel(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                       int layer_id, int x, int y)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_pixel_square_new(1, (uint16_t)layer_id, x, y,
+                                             0xff000000, DP_BLEND_MODE_NORMAL,
+                                             set_pixel_dab, 1, NULL));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+}
+
+// A canvas of 4 tiles, with one layer that has pixels in two tiles.
+static DP_CanvasHistory *make_memory_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    draw_pixel(TEST_ARGS, ch, dc, LAYER_ID, 10, 10);
+    draw_pixel(TEST_ARGS, ch, dc, LAYER_ID, CANVAS_SIZE - 28, CANVAS_SIZE - 28);
+    return ch;
+}
+
+static void act(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                unsigned int user, int i)
+{
+    handle(TEST_ARGS, ch, dc, DP_msg_undo_point_new(user));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(
+               user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+               DP_int_to_uint32(i * 37 % (CANVAS_SIZE - 28)),
+               DP_int_to_uint32(i * 23 % (CANVAS_SIZE - 28)),
+               20, 20, 0x80000000u | DP_int_to_uint32(i * 0x10305)));
+}
+
+static bool undo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user,
+                 bool redo)
+{
+    DP_Message *msg = DP_msg_undo_new(user, 0, redo);
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+static DP_MemoryUsage *canvas_memory_usage(DP_CanvasHistory *ch)
+{
+    DP_MemoryUsage *mu = DP_memory_usage_new();
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_memory_usage_add_canvas_state(mu, cs);
+    DP_canvas_state_decref(cs);
+    return mu;
+}
+
+
+static void memory_usage_single_layer(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_memory_history(TEST_ARGS, dc);
+    DP_MemoryUsage *mu = canvas_memory_usage(ch);
+
+    const DP_LayerMemoryUsage *lmu = DP_memory_usage_layer_search(mu, LAYER_ID);
+    if (OK(lmu != NULL, "layer is reported")) {
+        INT_EQ_OK(lmu->counts.tile_count, 2, "layer has two tiles");
+        INT_EQ_OK(lmu->counts.unique_tile_count, 2, "both tiles are unique");
+        INT_EQ_OK(lmu->counts.shared_tile_count, 0, "no tiles are shared");
+        INT_EQ_OK(lmu->counts.blank_tile_count, 2, "two tiles are blank");
+        OK(lmu->counts.unique_bytes >= 2 * DP_TILE_LENGTH * sizeof(DP_Pixel15),
+           "unique bytes cover the pixels of two tiles");
+    }
+    NOK(DP_memory_usage_layer_search(mu, DUPLICATE_ID), "no duplicate yet");
+
+    DP_memory_usage_free(mu);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void memory_usage_duplicated_layer(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_memory_history(TEST_ARGS, dc);
+    DP_MemoryUsage *before_mu = canvas_memory_usage(ch);
+    size_t before_bytes = DP_memory_usage_totals(before_mu).unique_bytes;
+    DP_memory_usage_free(before_mu);
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, DUPLICATE_ID, LAYER_ID, 0, 0, 0,
+                                        "Duplicate", 9));
+    DP_MemoryUsage *mu = canvas_memory_usage(ch);
+    UINT_EQ_OK(DP_memory_usage_totals(mu).unique_bytes, before_bytes,
+               "duplicating a layer doesn't add unique bytes");
+    const DP_LayerMemoryUsage *lmu =
+        DP_memory_usage_layer_search(mu, DUPLICATE_ID);
+    if (OK(lmu != NULL, "duplicate is reported")) {
+        INT_EQ_OK(lmu->counts.shared_tile_count, 2, "duplicate shares tiles");
+        INT_EQ_OK(lmu->counts.unique_tile_count, 0, "duplicate has no tiles");
+    }
+    DP_memory_usage_free(mu);
+
+    draw_pixel(TEST_ARGS, ch, dc, DUPLICATE_ID, 11, 11);
+    mu = canvas_memory_usage(ch);
+    lmu = DP_memory_usage_layer_search(mu, DUPLICATE_ID);
+    if (OK(lmu != NULL, "edited duplicate is reported")) {
+        INT_EQ_OK(lmu->counts.shared_tile_count, 1,
+                  "edited duplicate still shares untouched tile");
+        INT_EQ_OK(lmu->counts.unique_tile_count, 1,
+                  "edited duplicate has its own copy of the edited tile");
+    }
+    DP_memory_usage_free(mu);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void memory_usage_history(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_memory_history(TEST_ARGS, dc);
+    DP_MemoryUsage *canvas_mu = canvas_memory_usage(ch);
+    DP_MemoryUsageCounts canvas_totals = DP_memory_usage_totals(canvas_mu);
+    DP_memory_usage_free(canvas_mu);
+
+    DP_MemoryUsage *mu = DP_memory_usage_new();
+    DP_canvas_history_memory_usage_add(ch, mu);
+    DP_MemoryUsageCounts totals = DP_memory_usage_totals(mu);
+    OK(totals.unique_bytes >= canvas_totals.unique_bytes,
+       "history uses at least as much as the current state");
+    OK(totals.tile_count >= canvas_totals.tile_count,
+       "history includes the current state's tiles");
+    INT_EQ_OK(totals.tile_count,
+              totals.unique_tile_count + totals.shared_tile_count,
+              "every tile is either unique or shared");
+    DP_memory_usage_free(mu);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void history_usage_per_user(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_memory_history(TEST_ARGS, dc);
+    DP_CanvasHistoryUsage before;
+    DP_canvas_history_usage_get(ch, &before);
+    for (int i = 0; i < 6; ++i) {
+        act(TEST_ARGS, ch, dc, i % 3 == 0 ? USER_B : USER_A, i);
+    }
+
+    DP_CanvasHistoryUsage usage;
+    DP_canvas_history_usage_get(ch, &usage);
+    INT_EQ_OK(usage.message_count, before.message_count + 12,
+              "every message is counted");
+    INT_EQ_OK(usage.users[USER_A].message_count,
+              before.users[USER_A].message_count + 8,
+              "user A's messages are counted");
+    INT_EQ_OK(usage.users[USER_B].message_count, 4,
+              "user B's messages are counted");
+    size_t user_bytes = 0;
+    for (int i = 0; i < DP_CANVAS_HISTORY_USAGE_USER_COUNT; ++i) {
+        user_bytes += usage.users[i].message_bytes;
+    }
+    UINT_EQ_OK(user_bytes, usage.message_bytes,
+               "user bytes add up to the total");
+    OK(usage.save_point_count > before.save_point_count,
+       "undo points make save points");
+    OK(usage.save_point_bytes > before.save_point_bytes,
+       "save points keep tiles that have since changed");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void history_trim_keeps_undo(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_memory_history(TEST_ARGS, dc);
+    DP_CanvasHistory *untrimmed = make_memory_history(TEST_ARGS, dc);
+    DP_canvas_history_undo_depth_limit_set(ch, dc, 5);
+    DP_canvas_history_undo_depth_limit_set(untrimmed, dc, 5);
+    for (int i = 0; i < 30; ++i) {
+        unsigned int user = i % 3 == 0 ? USER_B : USER_A;
+        act(TEST_ARGS, ch, dc, user, i);
+        act(TEST_ARGS, untrimmed, dc, user, i);
+    }
+
+    DP_CanvasHistoryUsage before;
+    DP_canvas_history_usage_get(ch, &before);
+    size_t before_bytes = before.message_bytes + before.save_point_bytes;
+    UINT_EQ_OK(DP_canvas_history_trim(ch, SIZE_MAX), before_bytes,
+               "trimming to more than is used reports the usage");
+    DP_CanvasHistoryUsage unchanged;
+    DP_canvas_history_usage_get(ch, &unchanged);
+    INT_EQ_OK(unchanged.save_point_count, before.save_point_count,
+              "trimming to more than is used doesn't drop save points");
+
+    size_t after_bytes = DP_canvas_history_trim(ch, 0);
+    OK(after_bytes < before_bytes, "trimming frees memory");
+    DP_CanvasHistoryUsage after;
+    DP_canvas_history_usage_get(ch, &after);
+    INT_EQ_OK(after.save_point_count, 1, "one save point is left");
+    UINT_EQ_OK(after.message_bytes + after.save_point_bytes, after_bytes,
+               "trimming reports what's left");
+    OK(checksum(ch) == checksum(untrimmed), "trimming doesn't change canvas");
+
+    int undos = 0;
+    while (undo(ch, dc, USER_A, false)) {
+        OK(undo(untrimmed, dc, USER_A, false), "untrimmed undo %d", undos);
+        OK(checksum(ch) == checksum(untrimmed), "undo %d after trimming",
+           undos);
+        ++undos;
+    }
+    OK(undos > 0, "trimmed history can be undone");
+    NOK(undo(untrimmed, dc, USER_A, false), "untrimmed has the same limit");
+    OK(undo(ch, dc, USER_B, false), "other user undoes");
+    OK(undo(untrimmed, dc, USER_B, false), "other user undoes untrimmed");
+    OK(checksum(ch) == checksum(untrimmed), "other user's undo after trimming");
+    OK(undo(ch, dc, USER_A, true), "redo");
+    OK(undo(untrimmed, dc, USER_A, true), "redo untrimmed");
+    OK(checksum(ch) == checksum(untrimmed), "redo after trimming");
+
+    DP_canvas_history_free(untrimmed);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(memory_usage_single_layer);
+    REGISTER_TEST(memory_usage_duplicated_layer);
+    REGISTER_TEST(memory_usage_history);
+    REGISTER_TEST(history_usage_per_user);
+    REGISTER_TEST(history_trim_keeps_undo);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
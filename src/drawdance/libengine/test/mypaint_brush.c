@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+#define MAX_DABS    1024
+#define POINT_COUNT 20
+#define POINT_MSEC  10
+
+typedef struct DP_MyPaintTestDab {
+    int x, y;
+    uint16_t size;
+    uint8_t hardness;
+    uint8_t opacity;
+    uint8_t angle;
+    uint8_t aspect_ratio;
+} DP_MyPaintTestDab;
+
+typedef struct DP_MyPaintTestDabs {
+    int count;
+    DP_MyPaintTestDab dabs[MAX_DABS];
+} DP_MyPaintTestDabs;
+
+static void collect_dabs(void *user, DP_Message *msg)
+{
+    DP_MyPaintTestDabs *mptds = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_MYPAINT) {
+        DP_MsgDrawDabsMyPaint *mddmp = DP_message_internal(msg);
+        int x = DP_msg_draw_dabs_mypaint_x(mddmp);
+        int y = DP_msg_draw_dabs_mypaint_y(mddmp);
+        int count;
+        const DP_MyPaintDab *dabs =
+            DP_msg_draw_dabs_mypaint_dabs(mddmp, &count);
+        for (int i = 0; i < count && mptds->count < MAX_DABS; ++i) {
+            const DP_MyPaintDab *mpd = DP_mypaint_dab_at(dabs, i);
+            x += DP_mypaint_dab_x(mpd);
+            y += DP_mypaint_dab_y(mpd);
+            mptds->dabs[mptds->count++] = (DP_MyPaintTestDab){
+                x,
+                y,
+                DP_mypaint_dab_size(mpd),
+                DP_mypaint_dab_hardness(mpd),
+                DP_mypaint_dab_opacity(mpd),
+                DP_mypaint_dab_angle(mpd),
+                DP_mypaint_dab_aspect_ratio(mpd),
+            };
+        }
+    }
+    DP_message_decref(msg);
+}
+
+static void set_base_value(DP_MyPaintSettings *settings, MyPaintBrushSetting s,
+                           float value)
+{
+    settings->mappings[s].base_value = value;
+}
+
+// A plain elliptical brush with a radius of 2 pixels and no dynamics, the
+// same as what MyPaint would load from a .myb with only these base values.
+static void init_brush(DP_MyPaintBrush *brush, DP_MyPaintSettings *settings)
+{
+    *brush = (DP_MyPaintBrush){{0.0f, 0.0f, 0.0f, 1.0f},
+                               false,
+                               false,
+                               true,
+                               DP_BRUSH_PICKUP_MODE_LAYER};
+    *settings = (DP_MyPaintSettings){0};
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_OPAQUE, 1.0f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_OPAQUE_MULTIPLY, 1.0f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_RADIUS_LOGARITHMIC,
+                   logf(2.0f));
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_HARDNESS, 0.8f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_DABS_PER_ACTUAL_RADIUS,
+                   2.0f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_ELLIPTICAL_DAB_RATIO, 3.0f);
+}
+
+// Strokes to the right with the pressure going from the first to the second
+// value, using a fresh brush engine.
+static void stroke_dabs(const DP_MyPaintBrush *brush,
+                        const DP_MyPaintSettings *settings, float pressure1,
+                        float pressure2, DP_MyPaintTestDabs *mptds)
+{
+    mptds->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_dabs, NULL, mptds);
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_mypaint_brush_set(be, brush, settings, &stroke, NULL,
+                                      false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    for (int i = 0; i < POINT_COUNT; ++i) {
+        float t = (float)i / (float)(POINT_COUNT - 1);
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){10.0f + (float)i * 5.0f, 32.0f,
+                            pressure1 + (pressure2 - pressure1) * t, 0.0f,
+                            0.0f, 0.0f, i * POINT_MSEC},
+            NULL);
+    }
+    DP_brush_engine_stroke_end(be, POINT_COUNT * POINT_MSEC, NULL, false);
+    DP_brush_engine_free(be);
+}
+
+
+static void mypaint_dab_fields(TEST_PARAMS)
+{
+    DP_MyPaintBrush brush;
+    DP_MyPaintSettings settings;
+    init_brush(&brush, &settings);
+    DP_MyPaintTestDabs mptds;
+    stroke_dabs(&brush, &settings, 1.0f, 1.0f, &mptds);
+    if (OK(mptds.count > 10, "stroke has %d dabs", mptds.count)) {
+        bool fields_ok = true;
+        for (int i = 0; i < mptds.count; ++i) {
+            const DP_MyPaintTestDab *mptd = &mptds.dabs[i];
+            // Diameter of 4 pixels in 256ths, hardness of 0.8 and an aspect
+            // ratio of 3, see get_mypaint_dab_aspect_ratio in brush_engine.c.
+            // The ellipse angle is zero, which ends up as a half turn.
+            if (mptd->size != 4 * 256 || mptd->hardness != 204
+                || mptd->opacity != 255 || mptd->aspect_ratio != 57
+                || mptd->angle != 128) {
+                fields_ok = false;
+                DIAG("dab %d has size %u, hardness %u, opacity %u, aspect "
+                     "ratio %u, angle %u",
+                     i, (unsigned int)mptd->size, (unsigned int)mptd->hardness,
+                     (unsigned int)mptd->opacity,
+                     (unsigned int)mptd->aspect_ratio,
+                     (unsigned int)mptd->angle);
+                break;
+            }
+        }
+        OK(fields_ok, "dabs carry the brush settings");
+    }
+}
+
+static void mypaint_pressure_dynamics(TEST_PARAMS)
+{
+    DP_MyPaintBrush brush;
+    DP_MyPaintSettings settings;
+    init_brush(&brush, &settings);
+    // Radius goes from 1 to 4 pixels depending on pressure.
+    DP_MyPaintControlPoints *cp =
+        &settings.mappings[MYPAINT_BRUSH_SETTING_RADIUS_LOGARITHMIC]
+             .inputs[MYPAINT_BRUSH_INPUT_PRESSURE];
+    cp->n = 2;
+    cp->xvalues[0] = 0.0f;
+    cp->yvalues[0] = -logf(2.0f);
+    cp->xvalues[1] = 1.0f;
+    cp->yvalues[1] = logf(2.0f);
+
+    DP_MyPaintTestDabs mptds;
+    stroke_dabs(&brush, &settings, 0.0f, 1.0f, &mptds);
+    if (OK(mptds.count > 10, "stroke has %d dabs", mptds.count)) {
+        uint16_t first = mptds.dabs[0].size;
+        uint16_t last = mptds.dabs[mptds.count - 1].size;
+        // The first dab lands a bit after the first point, where the pressure
+        // has already started rising, so it's slightly over 2 pixels wide.
+        OK(first <= 2 * 256 + 16, "light dab is small, got %u",
+           (unsigned int)first);
+        OK(last >= 8 * 256 - 64, "heavy dab is big, got %u",
+           (unsigned int)last);
+    }
+}
+
+static void mypaint_random_is_deterministic(TEST_PARAMS)
+{
+    DP_MyPaintBrush brush;
+    DP_MyPaintSettings settings;
+    init_brush(&brush, &settings);
+    set_base_value(&settings, MYPAINT_BRUSH_SETTING_OFFSET_BY_RANDOM, 1.0f);
+
+    DP_MyPaintTestDabs a, b;
+    stroke_dabs(&brush, &settings, 1.0f, 1.0f, &a);
+    stroke_dabs(&brush, &settings, 1.0f, 1.0f, &b);
+    if (OK(a.count > 10, "stroke has %d dabs", a.count)) {
+        bool scattered = false;
+        for (int i = 0; i < a.count; ++i) {
+            if (a.dabs[i].y != 128) {
+                scattered = true;
+            }
+        }
+        OK(scattered, "random offset scatters the dabs");
+
+        bool same = a.count == b.count;
+        for (int i = 0; same && i < a.count; ++i) {
+            same = a.dabs[i].x == b.dabs[i].x && a.dabs[i].y == b.dabs[i].y
+                && a.dabs[i].size == b.dabs[i].size;
+        }
+        OK(same, "fresh engines produce the same dabs, %d vs. %d", a.count,
+           b.count);
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(mypaint_dab_fields);
+    REGISTER_TEST(mypaint_pressure_dynamics);
+    REGISTER_TEST(mypaint_random_is_deterministic);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
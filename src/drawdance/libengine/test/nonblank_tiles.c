@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dptest_engine.h>
+
+
+// A sparse layer 5 by 4 tiles big. Only two tiles have pixels in them, the
+// rest are either null, explicitly blank or got drawn on and then cleared.
+#define XTILES      5
+#define YTILES      4
+#define WIDTH       (XTILES * DP_TILE_SIZE - 7)
+#define HEIGHT      (YTILES * DP_TILE_SIZE - 3)
+#define CONTEXT_ID  1
+#define EXPECTED    2
+#define MAX_VISITED (XTILES * YTILES)
+
+static const int expected_x[EXPECTED] = {1, 3};
+static const int expected_y[EXPECTED] = {0, 2};
+
+typedef struct Visited {
+    int count;
+    int x[MAX_VISITED];
+    int y[MAX_VISITED];
+} Visited;
+
+static DP_Pixel15 red(void)
+{
+    return (DP_Pixel15){0, 0, DP_BIT15, DP_BIT15};
+}
+
+static DP_TransientTile *set_tile(DP_TransientLayerContent *tlc, int x, int y)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(CONTEXT_ID);
+    DP_transient_layer_content_transient_tile_at_set_noinc(tlc, x, y, tt);
+    return tt;
+}
+
+static DP_LayerContent *make_sparse_layer(void)
+{
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_transient_tile_pixel_at_set(set_tile(tlc, 1, 0), 17, 3, red());
+    DP_transient_tile_pixel_at_set(set_tile(tlc, 3, 2), 0, 0, red());
+    set_tile(tlc, 0, 3);
+    DP_TransientTile *cleared = set_tile(tlc, 4, 3);
+    DP_transient_tile_pixel_at_set(cleared, 1, 1, red());
+    DP_transient_tile_clear(cleared);
+    return DP_transient_layer_content_persist(tlc);
+}
+
+static Visited visit_nonblank(DP_LayerContent *lc)
+{
+    Visited v = {0};
+    int index = 0, x, y;
+    while (v.count < MAX_VISITED
+           && DP_layer_content_next_nonblank_tile_noinc(lc, &index, &x, &y)) {
+        v.x[v.count] = x;
+        v.y[v.count] = y;
+        ++v.count;
+    }
+    return v;
+}
+
+static void check_visited(TEST_PARAMS, const Visited *v, const char *what)
+{
+    if (INT_EQ_OK(v->count, EXPECTED, "%s: visited only the non-blank tiles",
+                  what)) {
+        for (int i = 0; i < EXPECTED; ++i) {
+            INT_EQ_OK(v->x[i], expected_x[i], "%s: tile %d x", what, i);
+            INT_EQ_OK(v->y[i], expected_y[i], "%s: tile %d y", what, i);
+        }
+    }
+}
+
+
+static void nonblank_tiles_sparse(TEST_PARAMS)
+{
+    DP_LayerContent *lc = make_sparse_layer();
+    Visited first = visit_nonblank(lc);
+    check_visited(TEST_ARGS, &first, "first pass");
+    Visited second = visit_nonblank(lc);
+    check_visited(TEST_ARGS, &second, "second pass");
+
+    int index = XTILES * YTILES;
+    OK(!DP_layer_content_next_nonblank_tile_noinc(lc, &index, NULL, NULL),
+       "nothing past the end");
+    DP_layer_content_decref(lc);
+}
+
+static void nonblank_tiles_empty(TEST_PARAMS)
+{
+    DP_LayerContent *lc = DP_transient_layer_content_persist(
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL));
+    Visited v = visit_nonblank(lc);
+    INT_EQ_OK(v.count, 0, "empty layer has no non-blank tiles");
+    DP_layer_content_decref(lc);
+}
+
+static void nonblank_tiles_transient(TEST_PARAMS)
+{
+    DP_LayerContent *lc = make_sparse_layer();
+    DP_TransientLayerContent *tlc = DP_transient_layer_content_new(lc);
+
+    int index = 0, x, y, count = 0;
+    DP_TransientTile *tt;
+    while ((tt = DP_transient_layer_content_next_nonblank_transient_tile(
+                tlc, CONTEXT_ID, &index, &x, &y))) {
+        if (count < EXPECTED) {
+            INT_EQ_OK(x, expected_x[count], "transient tile %d x", count);
+            INT_EQ_OK(y, expected_y[count], "transient tile %d y", count);
+        }
+        ++count;
+        DP_transient_tile_clear(tt);
+    }
+    INT_EQ_OK(count, EXPECTED, "visited only the non-blank transient tiles");
+
+    OK(DP_tile_transient(DP_transient_layer_content_tile_at_noinc(tlc, 1, 0)),
+       "visited tile was made transient");
+    DP_Tile *blank = DP_transient_layer_content_tile_at_noinc(tlc, 0, 3);
+    OK(!blank || !DP_tile_transient(blank), "blank tile was left alone");
+    OK(!DP_transient_layer_content_tile_at_noinc(tlc, 2, 2),
+       "null tile stays null");
+
+    DP_LayerContent *cleared = DP_transient_layer_content_persist(tlc);
+    Visited v = visit_nonblank(cleared);
+    INT_EQ_OK(v.count, 0, "clearing the visited tiles leaves nothing");
+    Visited orig = visit_nonblank(lc);
+    check_visited(TEST_ARGS, &orig, "original layer");
+
+    DP_layer_content_decref(cleared);
+    DP_layer_content_decref(lc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(nonblank_tiles_sparse);
+    REGISTER_TEST(nonblank_tiles_empty);
+    REGISTER_TEST(nonblank_tiles_transient);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/ops.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// Neither dimension is a multiple of the tile size, so most offsets have to
+// stitch tiles together from pieces.
+#define WIDTH    150
+#define HEIGHT   100
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+
+// The smallest size in whole tiles that fits the above.
+#define TILED_WIDTH  (DP_TILE_SIZE * (WIDTH / DP_TILE_SIZE + 1))
+#define TILED_HEIGHT (DP_TILE_SIZE * (HEIGHT / DP_TILE_SIZE + 1))
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS, int width, int height)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_DrawContext *dc = DP_draw_context_new();
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, DP_int_to_int32(width),
+                                    DP_int_to_int32(height), 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 3, 5, 70, 20, 0xffff0000);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 100, 60, 45, 37, 0x800000ff);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, width - 1, height - 1, 1, 1,
+              0xff00ff00);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, GROUP_ID, 0, LAYER_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    fill_rect(TEST_ARGS, ch, dc, CHILD_ID, 60, 30, 9, 50, 0xff123456);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+    return cs;
+}
+
+static DP_LayerContent *layer_content(DP_CanvasState *cs, int layer_id)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return DP_layer_routes_entry_content(lre, cs);
+}
+
+static bool pixels_equal(DP_Pixel15 a, DP_Pixel15 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+static int wrap(int value, int size)
+{
+    return ((value % size) + size) % size;
+}
+
+static int count_unshifted(DP_LayerContent *lc, DP_LayerContent *original,
+                           int offset_x, int offset_y)
+{
+    int width = DP_layer_content_width(lc);
+    int height = DP_layer_content_height(lc);
+    int wrong = 0;
+    for (int y = 0; y < height; ++y) {
+        for (int x = 0; x < width; ++x) {
+            int src_x = wrap(x - offset_x, width);
+            int src_y = wrap(y - offset_y, height);
+            DP_Pixel15 expected =
+                DP_layer_content_pixel_at(original, src_x, src_y);
+            if (!pixels_equal(DP_layer_content_pixel_at(lc, x, y), expected)) {
+                ++wrong;
+            }
+        }
+    }
+    return wrong;
+}
+
+static const int offsets[][2] = {
+    {1, 0},     {0, 1},      {-7, 13},     {64, -64},
+    {149, 99},  {-150, 100}, {1000, -999}, {WIDTH * 3 + 5, -HEIGHT * 2 - 3},
+};
+
+
+static void offset_moves_pixels(TEST_PARAMS)
+{
+    DP_CanvasState *original = make_canvas(TEST_ARGS, WIDTH, HEIGHT);
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(offsets); ++i) {
+        int dx = offsets[i][0];
+        int dy = offsets[i][1];
+        DP_CanvasState *cs = DP_ops_canvas_offset_wrap(original, 1, dx, dy);
+        INT_EQ_OK(count_unshifted(layer_content(cs, LAYER_ID),
+                                  layer_content(original, LAYER_ID), dx, dy),
+                  0, "offset %d, %d wraps the layer around", dx, dy);
+        INT_EQ_OK(count_unshifted(layer_content(cs, CHILD_ID),
+                                  layer_content(original, CHILD_ID), dx, dy),
+                  0, "offset %d, %d wraps layers inside of groups", dx, dy);
+        DP_canvas_state_decref(cs);
+    }
+    DP_canvas_state_decref(original);
+}
+
+static void offset_back_is_identity(TEST_PARAMS)
+{
+    DP_CanvasState *original = make_canvas(TEST_ARGS, WIDTH, HEIGHT);
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(offsets); ++i) {
+        int dx = offsets[i][0];
+        int dy = offsets[i][1];
+        DP_CanvasState *shifted =
+            DP_ops_canvas_offset_wrap(original, 1, dx, dy);
+        DP_CanvasState *cs = DP_ops_canvas_offset_wrap(shifted, 1, -dx, -dy);
+        INT_EQ_OK(count_unshifted(layer_content(cs, LAYER_ID),
+                                  layer_content(original, LAYER_ID), 0, 0),
+                  0, "offset %d, %d and back is bit-exact", dx, dy);
+        OK(DP_canvas_state_checksum(cs) == DP_canvas_state_checksum(original),
+           "offset %d, %d and back gives the same canvas", dx, dy);
+        DP_canvas_state_decref(cs);
+        DP_canvas_state_decref(shifted);
+    }
+    DP_canvas_state_decref(original);
+}
+
+static void offset_reduces_modulo_size(TEST_PARAMS)
+{
+    DP_CanvasState *original = make_canvas(TEST_ARGS, WIDTH, HEIGHT);
+    DP_CanvasState *small = DP_ops_canvas_offset_wrap(original, 1, 7, -3);
+    DP_CanvasState *large =
+        DP_ops_canvas_offset_wrap(original, 1, 7 + WIDTH * 5, -3 - HEIGHT * 4);
+    OK(DP_canvas_state_checksum(small) == DP_canvas_state_checksum(large),
+       "offsets that differ by multiples of the size give the same result");
+
+    DP_CanvasState *whole =
+        DP_ops_canvas_offset_wrap(original, 1, WIDTH * 2, -HEIGHT);
+    OK(whole == original, "offset by whole canvas sizes does nothing");
+
+    DP_canvas_state_decref(whole);
+    DP_canvas_state_decref(large);
+    DP_canvas_state_decref(small);
+    DP_canvas_state_decref(original);
+}
+
+static void offset_by_whole_tiles(TEST_PARAMS)
+{
+    DP_CanvasState *original =
+        make_canvas(TEST_ARGS, TILED_WIDTH, TILED_HEIGHT);
+    DP_CanvasState *cs = DP_ops_canvas_offset_wrap(original, 1, DP_TILE_SIZE,
+                                                   -DP_TILE_SIZE * 3);
+    DP_LayerContent *lc = layer_content(cs, LAYER_ID);
+    DP_LayerContent *original_lc = layer_content(original, LAYER_ID);
+    INT_EQ_OK(count_unshifted(lc, original_lc, DP_TILE_SIZE, -DP_TILE_SIZE * 3),
+              0, "tile-aligned offset wraps the layer around");
+    OK(DP_layer_content_tile_at_noinc(lc, 1, 1)
+           == DP_layer_content_tile_at_noinc(original_lc, 0, 0),
+       "tile-aligned offset reuses tiles");
+    DP_canvas_state_decref(cs);
+    DP_canvas_state_decref(original);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(offset_moves_pixels);
+    REGISTER_TEST(offset_back_is_identity);
+    REGISTER_TEST(offset_reduces_modulo_size);
+    REGISTER_TEST(offset_by_whole_tiles);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,280 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/paint_engine.h>
+#include <dpengine/tile.h>
+#include <dpmsg/acl.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <time.h>
+
+
+// The paint thread handles messages on its own schedule, so the tests keep
+// ticking until the expected area has been reported or this many seconds pass.
+#define TIMEOUT_SECONDS 10
+#define WIDTH           300
+#define HEIGHT          200
+#define LAYER_ID        0x101
+
+typedef struct AreaRecord {
+    int calls;
+    bool outside_expected;
+    DP_Rect expected;
+    DP_Rect reported;
+} AreaRecord;
+
+static void on_area_changed(void *user, int x, int y, int width, int height)
+{
+    AreaRecord *ar = user;
+    ++ar->calls;
+    DP_Rect area = DP_rect_make(x, y, width, height);
+    if (!DP_rect_valid(ar->expected)
+        || !DP_rect_contains(ar->expected, DP_rect_left(area),
+                             DP_rect_top(area))
+        || !DP_rect_contains(ar->expected, DP_rect_right(area),
+                             DP_rect_bottom(area))) {
+        ar->outside_expected = true;
+    }
+    ar->reported = DP_rect_valid(ar->reported)
+                     ? DP_rect_union(ar->reported, area)
+                     : area;
+}
+
+static void on_renderer_tile(DP_UNUSED void *user, DP_UNUSED int x,
+                             DP_UNUSED int y, DP_UNUSED DP_Pixel8 *pixels)
+{
+}
+
+static void on_renderer_unlock(DP_UNUSED void *user)
+{
+}
+
+static void on_renderer_resize(DP_UNUSED void *user, DP_UNUSED int width,
+                               DP_UNUSED int height, DP_UNUSED int prev_width,
+                               DP_UNUSED int prev_height,
+                               DP_UNUSED int offset_x, DP_UNUSED int offset_y)
+{
+}
+
+static void on_save_point(DP_UNUSED void *user, DP_UNUSED DP_CanvasState *cs,
+                          DP_UNUSED bool snapshot_requested)
+{
+}
+
+static long long on_get_time_ms(DP_UNUSED void *user)
+{
+    return 0;
+}
+
+static void on_catchup(DP_UNUSED void *user, DP_UNUSED int progress)
+{
+}
+
+static void on_reset_lock_changed(DP_UNUSED void *user, DP_UNUSED bool locked)
+{
+}
+
+static void on_recorder_state_changed(DP_UNUSED void *user,
+                                      DP_UNUSED bool started)
+{
+}
+
+static void on_layer_props_changed(DP_UNUSED void *user,
+                                   DP_UNUSED DP_LayerPropsList *lpl)
+{
+}
+
+static void on_annotations_changed(DP_UNUSED void *user,
+                                   DP_UNUSED DP_AnnotationList *al)
+{
+}
+
+static void on_document_metadata_changed(DP_UNUSED void *user,
+                                         DP_UNUSED DP_DocumentMetadata *dm)
+{
+}
+
+static void on_timeline_changed(DP_UNUSED void *user,
+                                DP_UNUSED DP_Timeline *tl)
+{
+}
+
+static void on_cursor_moved(DP_UNUSED void *user, DP_UNUSED unsigned int flags,
+                            DP_UNUSED unsigned int context_id,
+                            DP_UNUSED int layer_id, DP_UNUSED int x,
+                            DP_UNUSED int y)
+{
+}
+
+static void on_default_layer_set(DP_UNUSED void *user, DP_UNUSED int layer_id)
+{
+}
+
+static void on_undo_depth_limit_set(DP_UNUSED void *user,
+                                    DP_UNUSED int undo_depth_limit)
+{
+}
+
+static void on_censored_layer_revealed(DP_UNUSED void *user,
+                                       DP_UNUSED int layer_id)
+{
+}
+
+static void tick(DP_PaintEngine *pe)
+{
+    DP_paint_engine_tick(
+        pe, DP_rect_make(0, 0, UINT16_MAX, UINT16_MAX), false, on_catchup,
+        on_reset_lock_changed, on_recorder_state_changed,
+        on_layer_props_changed, on_annotations_changed,
+        on_document_metadata_changed, on_timeline_changed, on_cursor_moved,
+        on_default_layer_set, on_undo_depth_limit_set,
+        on_censored_layer_revealed, NULL);
+}
+
+static void handle(TEST_PARAMS, DP_PaintEngine *pe, int count,
+                   DP_Message **msgs)
+{
+    INT_EQ_OK(DP_paint_engine_handle_inc(pe, false, true, count, msgs, NULL,
+                                         NULL, NULL, NULL, NULL),
+              count, "paint engine takes %d message(s)", count);
+    for (int i = 0; i < count; ++i) {
+        DP_message_decref(msgs[i]);
+    }
+}
+
+static DP_Message *fill_rect(int x, int y, int width, int height)
+{
+    return DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                (uint32_t)x, (uint32_t)y, (uint32_t)width,
+                                (uint32_t)height, 0xff336699u);
+}
+
+// The area rounded out to the tiles it touches and clipped to the canvas.
+static DP_Rect tile_area(int x, int y, int width, int height)
+{
+    int left = x / DP_TILE_SIZE * DP_TILE_SIZE;
+    int top = y / DP_TILE_SIZE * DP_TILE_SIZE;
+    int right =
+        DP_min_int(DP_tile_count_round(x + width) * DP_TILE_SIZE, WIDTH);
+    int bottom =
+        DP_min_int(DP_tile_count_round(y + height) * DP_TILE_SIZE, HEIGHT);
+    return DP_rect_make(left, top, right - left, bottom - top);
+}
+
+static void expect_area(TEST_PARAMS, DP_PaintEngine *pe, AreaRecord *ar,
+                        DP_Rect expected, const char *title)
+{
+    time_t deadline = time(NULL) + TIMEOUT_SECONDS;
+    while (!(DP_rect_valid(ar->reported)
+             && DP_rect_left(ar->reported) == DP_rect_left(expected)
+             && DP_rect_top(ar->reported) == DP_rect_top(expected)
+             && DP_rect_right(ar->reported) == DP_rect_right(expected)
+             && DP_rect_bottom(ar->reported) == DP_rect_bottom(expected))
+           && time(NULL) < deadline) {
+        tick(pe);
+    }
+
+    if (DP_rect_valid(ar->reported)) {
+        INT_EQ_OK(DP_rect_x(ar->reported), DP_rect_x(expected), "%s x",
+                  title);
+        INT_EQ_OK(DP_rect_y(ar->reported), DP_rect_y(expected), "%s y",
+                  title);
+        INT_EQ_OK(DP_rect_width(ar->reported), DP_rect_width(expected),
+                  "%s width", title);
+        INT_EQ_OK(DP_rect_height(ar->reported), DP_rect_height(expected),
+                  "%s height", title);
+    }
+    else {
+        FAIL("%s: no area reported", title);
+    }
+    NOK(ar->outside_expected, "%s: nothing reported outside of the area",
+        title);
+
+    ar->calls = 0;
+    ar->outside_expected = false;
+    ar->expected = (DP_Rect){0, 0, -1, -1};
+    ar->reported = (DP_Rect){0, 0, -1, -1};
+}
+
+
+static void paint_engine_reports_changed_area(TEST_PARAMS)
+{
+    DP_DrawContext *paint_dc = DP_draw_context_new();
+    DP_DrawContext *main_dc = DP_draw_context_new();
+    DP_DrawContext *preview_dc = DP_draw_context_new();
+    DP_AclState *acls = DP_acl_state_new();
+    DP_PaintEngine *pe = DP_paint_engine_new_inc(
+        paint_dc, main_dc, preview_dc, acls, NULL, on_renderer_tile,
+        on_renderer_unlock, on_renderer_resize, NULL, on_save_point, NULL, NULL,
+        NULL, false, NULL, on_get_time_ms, NULL, NULL, NULL, NULL, NULL);
+
+    AreaRecord ar = {0, false, {0, 0, -1, -1}, {0, 0, -1, -1}};
+    DP_paint_engine_area_changed_set(pe, on_area_changed, &ar);
+
+    ar.expected = DP_rect_make(0, 0, WIDTH, HEIGHT);
+    DP_Message *setup[] = {
+        DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0),
+        DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "", 0),
+    };
+    handle(TEST_ARGS, pe, DP_ARRAY_LENGTH(setup), setup);
+    expect_area(TEST_ARGS, pe, &ar, ar.expected, "resized canvas");
+
+    // Rounded out to the tiles the fill touches.
+    ar.expected = tile_area(70, 10, 20, 20);
+    DP_Message *single[] = {fill_rect(70, 10, 20, 20)};
+    handle(TEST_ARGS, pe, DP_ARRAY_LENGTH(single), single);
+    expect_area(TEST_ARGS, pe, &ar, ar.expected, "single fill");
+
+    // Rounded out to tiles, but the bottom ones get clipped to the canvas.
+    ar.expected = tile_area(100, 100, 100, 100);
+    DP_Message *clipped[] = {fill_rect(100, 100, 100, 100)};
+    handle(TEST_ARGS, pe, DP_ARRAY_LENGTH(clipped), clipped);
+    expect_area(TEST_ARGS, pe, &ar, ar.expected, "clipped fill");
+
+    // Two fills far apart in one batch coalesce into the rectangle around
+    // both, no matter how many ticks the paint thread spreads them over.
+    ar.expected =
+        DP_rect_union(tile_area(10, 10, 5, 5), tile_area(200, 190, 10, 10));
+    DP_Message *apart[] = {fill_rect(10, 10, 5, 5),
+                           fill_rect(200, 190, 10, 10)};
+    handle(TEST_ARGS, pe, DP_ARRAY_LENGTH(apart), apart);
+    expect_area(TEST_ARGS, pe, &ar, ar.expected, "fills far apart");
+
+    // Nothing gets reported after unregistering, ticking until the paint
+    // thread caught up and then some more to pick up its last message.
+    DP_paint_engine_area_changed_set(pe, NULL, NULL);
+    DP_Message *unregistered[] = {fill_rect(0, 0, WIDTH, HEIGHT)};
+    handle(TEST_ARGS, pe, DP_ARRAY_LENGTH(unregistered), unregistered);
+    time_t deadline = time(NULL) + TIMEOUT_SECONDS;
+    while (DP_paint_engine_queued_message_count(pe) != 0
+           && time(NULL) < deadline) {
+        tick(pe);
+    }
+    for (int i = 0; i < 100; ++i) {
+        tick(pe);
+    }
+    INT_EQ_OK(ar.calls, 0, "no calls after unregistering");
+
+    // Tearing down with a function registered and changes still pending.
+    DP_paint_engine_area_changed_set(pe, on_area_changed, &ar);
+    DP_Message *pending[] = {fill_rect(1, 1, 1, 1)};
+    handle(TEST_ARGS, pe, DP_ARRAY_LENGTH(pending), pending);
+    DP_paint_engine_free_join(pe);
+    DP_acl_state_free(acls);
+    DP_draw_context_free(preview_dc);
+    DP_draw_context_free(main_dc);
+    DP_draw_context_free(paint_dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(paint_engine_reports_changed_area);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
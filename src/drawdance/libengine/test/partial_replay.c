@@ -0,0 +1,372 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    300
+#define HEIGHT   200
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+#define OTHER_ID 260
+#define STEPS    300
+
+static const int layer_ids[] = {LAYER_ID, CHILD_ID, OTHER_ID};
+
+static const int blend_modes[] = {
+    DP_BLEND_MODE_NORMAL,
+    DP_BLEND_MODE_MULTIPLY,
+    DP_BLEND_MODE_ERASE,
+    DP_BLEND_MODE_BEHIND,
+};
+
+// Both histories get the same messages, one of them always does full replays.
+typedef struct Histories {
+    DP_CanvasHistory *partial;
+    DP_CanvasHistory *full;
+    uint32_t random;
+} Histories;
+
+static uint32_t next_random(Histories *h)
+{
+    // xorshift32, so that failures are reproducible.
+    uint32_t x = h->random;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    h->random = x;
+    return x;
+}
+
+static int random_int(Histories *h, int min, int max)
+{
+    return min + (int)(next_random(h) % (uint32_t)(max - min + 1));
+}
+
+static bool handle_one(DP_CanvasHistory *ch, DP_DrawContext *dc,
+                       DP_Message *msg)
+{
+    return DP_canvas_history_handle(ch, dc, msg);
+}
+
+static void handle(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    bool partial_ok = handle_one(h->partial, dc, msg);
+    bool full_ok = handle_one(h->full, dc, msg);
+    OK(partial_ok && full_ok, "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+static void init_histories(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                           uint32_t seed,
+                           const DP_CanvasHistorySavePointPolicy *policy)
+{
+    h->partial = DP_canvas_history_new(NULL, NULL, false, NULL);
+    h->full = DP_canvas_history_new(NULL, NULL, false, NULL);
+    h->random = seed;
+    DP_canvas_history_partial_replay_set(h->full, false);
+    DP_canvas_history_save_point_policy_set(h->partial, policy);
+    DP_canvas_history_save_point_policy_set(h->full, policy);
+    handle(TEST_ARGS, h, dc, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, h, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+    handle(TEST_ARGS, h, dc,
+           DP_msg_layer_tree_create_new(1, GROUP_ID, 0, LAYER_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, h, dc,
+           DP_msg_layer_tree_create_new(1, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    handle(TEST_ARGS, h, dc,
+           DP_msg_layer_tree_create_new(1, OTHER_ID, 0, GROUP_ID, 0, 0,
+                                        "Other", 5));
+}
+
+static void dispose_histories(Histories *h)
+{
+    DP_canvas_history_free(h->full);
+    DP_canvas_history_free(h->partial);
+}
+
+static uint16_t random_layer(Histories *h)
+{
+    return DP_int_to_uint16(
+        layer_ids[random_int(h, 0, (int)DP_ARRAY_LENGTH(layer_ids) - 1)]);
+}
+
+static int random_blend_mode(Histories *h)
+{
+    return blend_modes[random_int(h, 0, (int)DP_ARRAY_LENGTH(blend_modes) - 1)];
+}
+
+static uint32_t random_color(Histories *h)
+{
+    uint32_t alpha = DP_int_to_uint32(random_int(h, 0x20, 0xff));
+    return (next_random(h) & 0xffffffu) | alpha << 24u;
+}
+
+static DP_Message *random_fill_rect(Histories *h, unsigned int user)
+{
+    int w = random_int(h, 1, 90);
+    int hh = random_int(h, 1, 90);
+    // Can stick out over the right and bottom of the canvas.
+    int x = random_int(h, 0, WIDTH - 1);
+    int y = random_int(h, 0, HEIGHT - 1);
+    return DP_msg_fill_rect_new(user, random_layer(h),
+                                DP_int_to_uint8(random_blend_mode(h)),
+                                DP_int_to_uint32(x), DP_int_to_uint32(y),
+                                DP_int_to_uint32(w), DP_int_to_uint32(hh),
+                                random_color(h));
+}
+
+static void set_classic_dabs(int count, DP_ClassicDab *dabs, void *user)
+{
+    Histories *h = user;
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(dabs, i, DP_int_to_int8(random_int(h, -80, 80)),
+                            DP_int_to_int8(random_int(h, -80, 80)),
+                            DP_int_to_uint16(random_int(h, 256, 40 * 256)),
+                            DP_int_to_uint8(random_int(h, 0, 255)),
+                            DP_int_to_uint8(random_int(h, 1, 255)));
+    }
+}
+
+// Direct dabs have no alpha in their color, indirect ones do.
+static DP_Message *random_classic_dabs(Histories *h, unsigned int user,
+                                       bool indirect)
+{
+    uint32_t color = next_random(h) & 0xffffffu;
+    return DP_msg_draw_dabs_classic_new(
+        user, random_layer(h), random_int(h, 0, WIDTH * 4),
+        random_int(h, 0, HEIGHT * 4), indirect ? color | 0x80000000u : color,
+        DP_BLEND_MODE_NORMAL, set_classic_dabs, random_int(h, 1, 5), h);
+}
+
+static void set_pixel_dabs(int count, DP_PixelDab *dabs, void *user)
+{
+    Histories *h = user;
+    for (int i = 0; i < count; ++i) {
+        DP_pixel_dab_init(dabs, i, DP_int_to_int8(random_int(h, -20, 20)),
+                          DP_int_to_int8(random_int(h, -20, 20)),
+                          DP_int_to_uint8(random_int(h, 1, 30)),
+                          DP_int_to_uint8(random_int(h, 1, 255)));
+    }
+}
+
+static DP_Message *random_pixel_dabs(Histories *h, unsigned int user)
+{
+    return DP_msg_draw_dabs_pixel_new(
+        user, random_layer(h), random_int(h, 0, WIDTH),
+        random_int(h, 0, HEIGHT), next_random(h) & 0xffffffu,
+        DP_BLEND_MODE_NORMAL, set_pixel_dabs, random_int(h, 1, 5), h);
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_Message *random_put_tile(Histories *h, unsigned int user)
+{
+    uint32_t color = next_random(h) | 0xff000000u;
+    return DP_msg_put_tile_new(
+        user, random_layer(h), 0, DP_int_to_uint16(random_int(h, 0, 4)),
+        DP_int_to_uint16(random_int(h, 0, 2)),
+        DP_int_to_uint16(random_int(h, 0, 2)), set_color, 4, &color);
+}
+
+static bool undo_both(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                      unsigned int user, bool redo)
+{
+    DP_Message *msg = DP_msg_undo_new(user, 0, redo);
+    bool partial_ok = handle_one(h->partial, dc, msg);
+    bool full_ok = handle_one(h->full, dc, msg);
+    DP_message_decref(msg);
+    OK(partial_ok == full_ok, "%s by user %u works the same in both",
+       redo ? "redo" : "undo", user);
+    return partial_ok;
+}
+
+static void random_step(TEST_PARAMS, Histories *h, DP_DrawContext *dc)
+{
+    unsigned int user = DP_int_to_uint(random_int(h, 1, 3));
+    int r = random_int(h, 0, 99);
+    if (r < 20) {
+        undo_both(TEST_ARGS, h, dc, user, false);
+    }
+    else if (r < 32) {
+        undo_both(TEST_ARGS, h, dc, user, true);
+    }
+    else {
+        handle(TEST_ARGS, h, dc, DP_msg_undo_point_new(user));
+        int count = random_int(h, 1, 3);
+        for (int i = 0; i < count; ++i) {
+            if (r < 65) {
+                handle(TEST_ARGS, h, dc, random_fill_rect(h, user));
+            }
+            else if (r < 80) {
+                handle(TEST_ARGS, h, dc, random_classic_dabs(h, user, false));
+            }
+            else if (r < 90) {
+                handle(TEST_ARGS, h, dc, random_pixel_dabs(h, user));
+            }
+            else if (r < 95) {
+                handle(TEST_ARGS, h, dc, random_put_tile(h, user));
+            }
+            else if (r < 97) {
+                // Indirect strokes and layer changes force a full replay.
+                handle(TEST_ARGS, h, dc, random_classic_dabs(h, user, true));
+                handle(TEST_ARGS, h, dc, DP_msg_pen_up_new(user));
+            }
+            else {
+                handle(TEST_ARGS, h, dc,
+                       DP_msg_layer_attributes_new(
+                           user, random_layer(h), 0, 0,
+                           DP_int_to_uint8(random_int(h, 0, 255)),
+                           DP_BLEND_MODE_NORMAL));
+            }
+        }
+    }
+}
+
+static void run_random_steps(TEST_PARAMS, DP_DrawContext *dc, uint32_t seed,
+                             const DP_CanvasHistorySavePointPolicy *policy)
+{
+    Histories h;
+    init_histories(TEST_ARGS, &h, dc, seed, policy);
+    int first_mismatch = -1;
+    for (int i = 0; i < STEPS && first_mismatch < 0; ++i) {
+        random_step(TEST_ARGS, &h, dc);
+        if (checksum(h.partial) != checksum(h.full)) {
+            first_mismatch = i;
+        }
+    }
+    INT_EQ_OK(first_mismatch, -1, "seed %u gives the same canvas throughout",
+              seed);
+
+    // Undo everything that's left, which goes through all the save points.
+    for (unsigned int user = 1; user <= 3; ++user) {
+        while (undo_both(TEST_ARGS, &h, dc, user, false)) {
+            if (checksum(h.partial) != checksum(h.full)) {
+                first_mismatch = STEPS;
+                break;
+            }
+        }
+    }
+    INT_EQ_OK(first_mismatch, -1,
+              "seed %u gives the same canvas undoing it all", seed);
+    dispose_histories(&h);
+}
+
+
+static void random_matches_full_replay(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {0, 0, 0, NULL, NULL};
+    for (uint32_t seed = 1; seed <= 8; ++seed) {
+        run_random_steps(TEST_ARGS, dc, seed * 0x9e3779b9u, &policy);
+    }
+    DP_draw_context_free(dc);
+}
+
+static void sparse_save_points_match_full_replay(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {7, 0, 0, NULL, NULL};
+    for (uint32_t seed = 1; seed <= 8; ++seed) {
+        run_random_steps(TEST_ARGS, dc, seed * 0x85ebca6bu, &policy);
+    }
+    DP_draw_context_free(dc);
+}
+
+// Holds on to the tile, so that its address can't get reused.
+static DP_Tile *tile_at_inc(DP_CanvasHistory *ch, int layer_id, int x, int y)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(
+        DP_layer_routes_search(lr, layer_id), cs);
+    DP_Tile *t =
+        DP_tile_incref_nullable(DP_layer_content_tile_at_noinc(lc, x, y));
+    DP_canvas_state_decref(cs);
+    return t;
+}
+
+static void partial_replay_leaves_other_tiles_alone(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {0, 0, 0, NULL, NULL};
+    Histories h;
+    init_histories(TEST_ARGS, &h, dc, 1, &policy);
+    handle(TEST_ARGS, &h, dc, DP_msg_undo_point_new(1));
+    handle(TEST_ARGS, &h, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL, 5, 5, 20,
+                                20, 0xffff0000));
+    handle(TEST_ARGS, &h, dc, DP_msg_undo_point_new(2));
+    handle(TEST_ARGS, &h, dc,
+           DP_msg_fill_rect_new(2, LAYER_ID, DP_BLEND_MODE_NORMAL, 200, 130,
+                                90, 60, 0x800000ff));
+    // A tile that only the second fill touches.
+    int tx = 200 / DP_TILE_SIZE;
+    int ty = 130 / DP_TILE_SIZE;
+    DP_Tile *partial_before = tile_at_inc(h.partial, LAYER_ID, tx, ty);
+    DP_Tile *full_before = tile_at_inc(h.full, LAYER_ID, tx, ty);
+    NOT_NULL_OK(partial_before, "other action drew on the tile");
+
+    OK(undo_both(TEST_ARGS, &h, dc, 1, false), "user 1 undoes");
+    OK(checksum(h.partial) == checksum(h.full), "same canvas after undo");
+    DP_Tile *partial_after = tile_at_inc(h.partial, LAYER_ID, tx, ty);
+    DP_Tile *full_after = tile_at_inc(h.full, LAYER_ID, tx, ty);
+    DP_Tile *undone = tile_at_inc(h.partial, LAYER_ID, 0, 0);
+    OK(partial_after == partial_before,
+       "partial replay keeps tiles in other places as they were");
+    OK(full_after != full_before, "full replay renders them again");
+    NULL_OK(undone, "partial replay restores the undone action's tiles");
+
+    OK(undo_both(TEST_ARGS, &h, dc, 1, true), "user 1 redoes");
+    OK(checksum(h.partial) == checksum(h.full), "same canvas after redo");
+
+    DP_tile_decref_nullable(undone);
+    DP_tile_decref_nullable(full_after);
+    DP_tile_decref_nullable(partial_after);
+    DP_tile_decref_nullable(full_before);
+    DP_tile_decref_nullable(partial_before);
+    dispose_histories(&h);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(random_matches_full_replay);
+    REGISTER_TEST(sparse_save_points_match_full_replay);
+    REGISTER_TEST(partial_replay_leaves_other_tiles_alone);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
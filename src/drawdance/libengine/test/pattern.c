@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/pattern.h>
+#include <dpengine/pixels.h>
+#include <dpengine/selection.h>
+#include <dpmsg/blend_mode.h>
+#include <dptest_engine.h>
+#include <stdlib.h>
+
+
+// Three by two tiles, the pattern size doesn't divide the tile size of 64.
+#define WIDTH          150
+#define HEIGHT         100
+#define PATTERN_WIDTH  7
+#define PATTERN_HEIGHT 5
+
+
+static DP_Pixel8 pattern_color(int x, int y)
+{
+    uint8_t a = (x + y) % 2 == 0 ? 255 : 128;
+    return (DP_Pixel8){
+        .b = DP_int_to_uint8(x * a / PATTERN_WIDTH),
+        .g = DP_int_to_uint8(y * a / PATTERN_HEIGHT),
+        .r = DP_int_to_uint8((x * 3 + y * 5) % 16 * a / 16),
+        .a = a,
+    };
+}
+
+static DP_Image *make_pattern(void)
+{
+    DP_Image *pattern = DP_image_new(PATTERN_WIDTH, PATTERN_HEIGHT);
+    for (int y = 0; y < PATTERN_HEIGHT; ++y) {
+        for (int x = 0; x < PATTERN_WIDTH; ++x) {
+            DP_image_pixel_at_set(pattern, x, y, pattern_color(x, y));
+        }
+    }
+    return pattern;
+}
+
+static DP_Pixel8 expected_color(int x, int y, int offset_x, int offset_y)
+{
+    int px = (x - offset_x) % PATTERN_WIDTH;
+    int py = (y - offset_y) % PATTERN_HEIGHT;
+    return pattern_color(px < 0 ? px + PATTERN_WIDTH : px,
+                         py < 0 ? py + PATTERN_HEIGHT : py);
+}
+
+static DP_Pixel8 pixel8_at(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return DP_pixel15_to_8(
+        DP_layer_content_pixel_at((DP_LayerContent *)tlc, x, y));
+}
+
+static bool pixel8_equal(DP_Pixel8 a, DP_Pixel8 b)
+{
+    return a.b == b.b && a.g == b.g && a.r == b.r && a.a == b.a;
+}
+
+static int count_unexpected(DP_TransientLayerContent *tlc, DP_Rect rect,
+                            int offset_x, int offset_y)
+{
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel8 pixel = pixel8_at(tlc, x, y);
+            if (DP_rect_contains(rect, x, y)) {
+                if (!pixel8_equal(pixel,
+                                  expected_color(x, y, offset_x, offset_y))) {
+                    ++wrong;
+                }
+            }
+            else if (pixel.a != 0) {
+                ++wrong;
+            }
+        }
+    }
+    return wrong;
+}
+
+
+static void fill_whole_layer(TEST_PARAMS)
+{
+    DP_Image *pattern = make_pattern();
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Rect area = DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, DP_BIT15,
+                                   pattern, -3, 11, NULL, NULL);
+    OK(DP_rect_valid(area) && area.x1 == 0 && area.y1 == 0
+           && area.x2 == WIDTH - 1 && area.y2 == HEIGHT - 1,
+       "whole layer got filled");
+    INT_EQ_OK(count_unexpected(tlc, area, -3, 11), 0,
+              "pattern repeats across tile boundaries without drifting");
+    DP_transient_layer_content_decref(tlc);
+    DP_image_free(pattern);
+}
+
+static void adjacent_fills_line_up(TEST_PARAMS)
+{
+    DP_Image *pattern = make_pattern();
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    // The shared edge at x = 66 is past a tile boundary and not on a multiple
+    // of the pattern width, the one at y = 63 is right before one.
+    DP_Rect left = DP_rect_make(5, 10, 61, 54);
+    DP_Rect right = DP_rect_make(66, 10, 70, 54);
+    DP_Rect below = DP_rect_make(5, 64, 131, 30);
+    DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, DP_BIT15, pattern, 2, 2,
+                    &left, NULL);
+    DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, DP_BIT15, pattern, 2, 2,
+                    &right, NULL);
+    DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, DP_BIT15, pattern, 2, 2,
+                    &below, NULL);
+
+    int discontinuous = 0;
+    for (int y = 10; y < 64; ++y) {
+        if (!pixel8_equal(pixel8_at(tlc, 65, y), expected_color(65, y, 2, 2))
+            || !pixel8_equal(pixel8_at(tlc, 66, y),
+                             expected_color(66, y, 2, 2))) {
+            ++discontinuous;
+        }
+    }
+    for (int x = 5; x < 136; ++x) {
+        if (!pixel8_equal(pixel8_at(tlc, x, 63), expected_color(x, 63, 2, 2))
+            || !pixel8_equal(pixel8_at(tlc, x, 64),
+                             expected_color(x, 64, 2, 2))) {
+            ++discontinuous;
+        }
+    }
+    INT_EQ_OK(discontinuous, 0, "pattern continues across shared edges");
+    INT_EQ_OK(count_unexpected(tlc, DP_rect_make(5, 10, 131, 84), 2, 2), 0,
+              "fills look the same as a single one");
+    DP_transient_layer_content_decref(tlc);
+    DP_image_free(pattern);
+}
+
+static void fill_with_opacity(TEST_PARAMS)
+{
+    DP_Image *pattern = make_pattern();
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, DP_BIT15 / 2, pattern, 0, 0,
+                    NULL, NULL);
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel8 pixel = pixel8_at(tlc, x, y);
+            DP_Pixel8 expected = expected_color(x, y, 0, 0);
+            if (abs(pixel.a - expected.a / 2) > 1
+                || abs(pixel.g - expected.g / 2) > 1) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "half opacity halves the pattern");
+
+    DP_Rect area = DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, 0, pattern,
+                                   0, 0, NULL, NULL);
+    NOK(DP_rect_valid(area), "zero opacity draws nothing");
+    DP_transient_layer_content_decref(tlc);
+    DP_image_free(pattern);
+}
+
+static void fill_selection(TEST_PARAMS)
+{
+    DP_Image *pattern = make_pattern();
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(WIDTH, HEIGHT, NULL);
+    DP_Selection *sel = DP_selection_new_ellipse(40.0, 20.0, 80.0, 60.0);
+    DP_Rect area = DP_pattern_fill(tlc, 1, DP_BLEND_MODE_NORMAL, DP_BIT15,
+                                   pattern, 0, 0, NULL, sel);
+    DP_Rect bounds = DP_selection_bounds(sel);
+    OK(area.x1 == bounds.x1 && area.y1 == bounds.y1 && area.x2 == bounds.x2
+           && area.y2 == bounds.y2,
+       "area is the selection bounds");
+
+    int wrong = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            int coverage = DP_selection_contains(sel, x, y);
+            int expected = expected_color(x, y, 0, 0).a * coverage / 255;
+            if (abs(pixel8_at(tlc, x, y).a - expected) > 1) {
+                ++wrong;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "alpha follows selection coverage");
+    DP_selection_decref(sel);
+    DP_transient_layer_content_decref(tlc);
+    DP_image_free(pattern);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(fill_whole_layer);
+    REGISTER_TEST(adjacent_fills_line_up);
+    REGISTER_TEST(fill_with_opacity);
+    REGISTER_TEST(fill_selection);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
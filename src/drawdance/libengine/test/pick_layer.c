@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define BOTTOM_LAYER_ID 257
+#define FAINT_LAYER_ID  258
+#define GROUP_ID        259
+#define GROUPED_ID      260
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+static void draw_pixel(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                       int layer_id, int x, int y)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_pixel_square_new(1, DP_int_to_uint16(layer_id), x,
+                                             y, 0xff000000,
+                                             DP_BLEND_MODE_NORMAL,
+                                             set_pixel_dab, 1, NULL));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+}
+
+static int pick(DP_CanvasHistory *ch, int x, int y, uint8_t min_alpha,
+                bool reveal_censored)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int layer_id =
+        DP_canvas_state_pick_layer(cs, x, y, min_alpha, reveal_censored);
+    DP_canvas_state_decref(cs);
+    return layer_id;
+}
+
+// Builds a canvas with, from bottom to top, an opaque layer, a layer at about
+// a quarter opacity and a group containing another opaque layer. All of them
+// have a pixel at (10, 10), the bottom layer also has one at (20, 20).
+static DP_CanvasHistory *make_pick_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(1, 0, 64, 64, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, BOTTOM_LAYER_ID, 0, 0, 0, 0,
+                                        "Bottom", 6));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, FAINT_LAYER_ID, 0, 0, 0, 0, "Faint",
+                                        5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, GROUP_ID, 0, 0, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, GROUPED_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Grouped", 7));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(1, FAINT_LAYER_ID, 0, 0, 64,
+                                       DP_BLEND_MODE_NORMAL));
+    draw_pixel(TEST_ARGS, ch, dc, BOTTOM_LAYER_ID, 10, 10);
+    draw_pixel(TEST_ARGS, ch, dc, BOTTOM_LAYER_ID, 20, 20);
+    draw_pixel(TEST_ARGS, ch, dc, FAINT_LAYER_ID, 10, 10);
+    draw_pixel(TEST_ARGS, ch, dc, GROUPED_ID, 10, 10);
+    return ch;
+}
+
+
+static void pick_layer_topmost(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_pick_history(TEST_ARGS, dc);
+    INT_EQ_OK(pick(ch, 10, 10, 0, false), GROUPED_ID,
+              "topmost layer is the one in the group, not the group itself");
+    INT_EQ_OK(pick(ch, 20, 20, 0, false), BOTTOM_LAYER_ID,
+              "only the bottom layer has a pixel at (20, 20)");
+    INT_EQ_OK(pick(ch, 30, 30, 0, false), 0, "no layer at blank pixel");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void pick_layer_opacity_threshold(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_pick_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc, DP_msg_layer_visibility_new(1, GROUP_ID, false));
+    INT_EQ_OK(pick(ch, 10, 10, 32, false), FAINT_LAYER_ID,
+              "faint layer exceeds low threshold");
+    INT_EQ_OK(pick(ch, 10, 10, 128, false), BOTTOM_LAYER_ID,
+              "faint layer is below high threshold");
+    INT_EQ_OK(pick(ch, 10, 10, 255, false), 0,
+              "nothing exceeds maximum threshold");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void pick_layer_hidden_group(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_pick_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc, DP_msg_layer_visibility_new(1, GROUP_ID, false));
+    INT_EQ_OK(pick(ch, 10, 10, 0, false), FAINT_LAYER_ID,
+              "layer in hidden group is skipped");
+    handle(TEST_ARGS, ch, dc, DP_msg_layer_visibility_new(1, GROUP_ID, true));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_visibility_new(1, FAINT_LAYER_ID, false));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_visibility_new(1, GROUPED_ID, false));
+    INT_EQ_OK(pick(ch, 10, 10, 0, false), BOTTOM_LAYER_ID,
+              "hidden layers are skipped");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void pick_layer_censored(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_pick_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(1, GROUPED_ID, 0,
+                                       DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR,
+                                       255, DP_BLEND_MODE_NORMAL));
+    INT_EQ_OK(pick(ch, 10, 10, 0, false), FAINT_LAYER_ID,
+              "censored layer is skipped");
+    INT_EQ_OK(pick(ch, 10, 10, 0, true), GROUPED_ID,
+              "revealed censored layer is picked");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void pick_layer_out_of_bounds(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_pick_history(TEST_ARGS, dc);
+    INT_EQ_OK(pick(ch, -1, 10, 0, false), 0, "negative x");
+    INT_EQ_OK(pick(ch, 10, -1, 0, false), 0, "negative y");
+    INT_EQ_OK(pick(ch, 64, 10, 0, false), 0, "x past width");
+    INT_EQ_OK(pick(ch, 10, 64, 0, false), 0, "y past height");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(pick_layer_topmost);
+    REGISTER_TEST(pick_layer_opacity_threshold);
+    REGISTER_TEST(pick_layer_hidden_group);
+    REGISTER_TEST(pick_layer_censored);
+    REGISTER_TEST(pick_layer_out_of_bounds);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
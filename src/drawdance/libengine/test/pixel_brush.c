@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  16
+#define MAX_MESSAGES 64
+
+typedef struct DP_PixelTestPoint {
+    int x, y;
+} DP_PixelTestPoint;
+
+typedef struct DP_PixelTestMessages {
+    int count;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_PixelTestMessages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_PixelTestMessages *ptms = user;
+    if (ptms->count < MAX_MESSAGES) {
+        ptms->msgs[ptms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    return DP_draw_context_pool_require(user, length);
+}
+
+static int count_dabs(DP_Message *msg)
+{
+    switch (DP_message_type(msg)) {
+    case DP_MSG_DRAW_DABS_PIXEL:
+    case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+        return DP_msg_draw_dabs_pixel_dabs_count(DP_message_internal(msg));
+    default:
+        return 0;
+    }
+}
+
+static void init_brush(DP_ClassicBrush *cb, DP_BrushShape shape, float size,
+                       bool pixel_perfect)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = size;
+    cb->size.max = size;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.1f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = shape;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+    cb->pixel_perfect = pixel_perfect;
+}
+
+// Strokes through the given points, sends every resulting message through a
+// serialization round-trip and draws the deserialized messages onto an empty
+// canvas. Then checks that exactly the expected pixels got painted, each one
+// by a single dab.
+static void check_stroke(TEST_PARAMS, DP_DrawContext *dc, const char *title,
+                         const DP_ClassicBrush *cb,
+                         const DP_PixelTestPoint *points, int point_count,
+                         const DP_PixelTestPoint *expected, int expected_count,
+                         int expected_dabs)
+{
+    DP_PixelTestMessages ptms = {0, {0}};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, &ptms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    for (int i = 0; i < point_count; ++i) {
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){(float)points[i].x, (float)points[i].y, 1.0f, 0.0f,
+                            0.0f, 0.0f, i * 10},
+            NULL);
+    }
+    DP_brush_engine_stroke_end(be, point_count * 10, NULL, true);
+    DP_brush_engine_free(be);
+
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+
+    int dab_count = 0;
+    bool round_trip = true;
+    for (int i = 0; i < ptms.count; ++i) {
+        DP_Message *msg = ptms.msgs[i];
+        dab_count += count_dabs(msg);
+        size_t length = DP_message_serialize(msg, true, get_buffer, dc);
+        DP_Message *copy =
+            length == 0 ? NULL
+                        : DP_message_deserialize(DP_draw_context_pool(dc),
+                                                 length, true);
+        if (copy && DP_message_equals(msg, copy)) {
+            handle(TEST_ARGS, ch, dc, copy);
+        }
+        else {
+            round_trip = false;
+            DP_message_decref_nullable(copy);
+        }
+        DP_message_decref(msg);
+    }
+    OK(round_trip, "%s messages survive serialization", title);
+    INT_EQ_OK(dab_count, expected_dabs, "%s stroke dab count", title);
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    int painted = 0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            if (DP_layer_content_pixel_at(lc, x, y).a != 0) {
+                ++painted;
+            }
+        }
+    }
+    INT_EQ_OK(painted, expected_count, "%s stroke painted pixel count", title);
+    bool all_found = true;
+    for (int i = 0; i < expected_count; ++i) {
+        DP_Pixel15 pixel =
+            DP_layer_content_pixel_at(lc, expected[i].x, expected[i].y);
+        if (pixel.a != DP_BIT15) {
+            all_found = false;
+            DIAG("pixel %d, %d has alpha %u", expected[i].x, expected[i].y,
+                 (unsigned int)pixel.a);
+        }
+    }
+    OK(all_found, "%s stroke painted the expected pixels", title);
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+}
+
+
+static void pixel_line_straight(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    DP_PixelTestPoint points[] = {{2, 2}, {7, 2}};
+    DP_PixelTestPoint expected[] = {{2, 2}, {3, 2}, {4, 2},
+                                    {5, 2}, {6, 2}, {7, 2}};
+
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, false);
+    check_stroke(TEST_ARGS, dc, "horizontal", &cb, points,
+                 DP_ARRAY_LENGTH(points), expected, DP_ARRAY_LENGTH(expected),
+                 DP_ARRAY_LENGTH(expected));
+
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, true);
+    check_stroke(TEST_ARGS, dc, "pixel-perfect horizontal", &cb, points,
+                 DP_ARRAY_LENGTH(points), expected, DP_ARRAY_LENGTH(expected),
+                 DP_ARRAY_LENGTH(expected));
+
+    DP_draw_context_free(dc);
+}
+
+static void pixel_line_diagonal(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    // Bresenham steps diagonally whenever it has to change rows.
+    DP_PixelTestPoint points[] = {{2, 2}, {6, 4}};
+    DP_PixelTestPoint expected[] = {{2, 2}, {3, 3}, {4, 3}, {5, 4}, {6, 4}};
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, false);
+    check_stroke(TEST_ARGS, dc, "shallow", &cb, points,
+                 DP_ARRAY_LENGTH(points), expected, DP_ARRAY_LENGTH(expected),
+                 DP_ARRAY_LENGTH(expected));
+    DP_draw_context_free(dc);
+}
+
+static void pixel_perfect_removes_corners(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    // A shaky diagonal, going one pixel right, then one down, and so on.
+    DP_PixelTestPoint points[] = {{2, 2}, {3, 2}, {3, 3}, {4, 3}, {4, 4}};
+
+    DP_PixelTestPoint staircase[] = {{2, 2}, {3, 2}, {3, 3}, {4, 3}, {4, 4}};
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, false);
+    check_stroke(TEST_ARGS, dc, "staircase", &cb, points,
+                 DP_ARRAY_LENGTH(points), staircase,
+                 DP_ARRAY_LENGTH(staircase), DP_ARRAY_LENGTH(staircase));
+
+    DP_PixelTestPoint diagonal[] = {{2, 2}, {3, 3}, {4, 4}};
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, true);
+    check_stroke(TEST_ARGS, dc, "pixel-perfect staircase", &cb, points,
+                 DP_ARRAY_LENGTH(points), diagonal, DP_ARRAY_LENGTH(diagonal),
+                 DP_ARRAY_LENGTH(diagonal));
+
+    DP_draw_context_free(dc);
+}
+
+static void pixel_square_dabs(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_ClassicBrush cb;
+    // Three dabs of a 3x3 square, covering a 5x3 rectangle.
+    DP_PixelTestPoint points[] = {{5, 5}, {7, 5}};
+    DP_PixelTestPoint expected[] = {{4, 4}, {5, 4}, {6, 4}, {7, 4}, {8, 4},
+                                    {4, 5}, {5, 5}, {6, 5}, {7, 5}, {8, 5},
+                                    {4, 6}, {5, 6}, {6, 6}, {7, 6}, {8, 6}};
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE, 3.0f, false);
+    check_stroke(TEST_ARGS, dc, "square", &cb, points, DP_ARRAY_LENGTH(points),
+                 expected, DP_ARRAY_LENGTH(expected), 3);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(pixel_line_straight);
+    REGISTER_TEST(pixel_line_diagonal);
+    REGISTER_TEST(pixel_perfect_removes_corners);
+    REGISTER_TEST(pixel_square_dabs);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
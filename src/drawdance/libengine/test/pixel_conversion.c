@@ -47,8 +47,8 @@ static void channel15_to_8(TEST_PARAMS)
 static void pixels15_to_8_tile(TEST_PARAMS)
 {
     static_assert(DP_BIT15 % 4 == 0, "DP_BIT15 divisible by 4");
-    static_assert(DP_BIT15 / 4 == DP_TILE_LENGTH * 2,
-                  "Exactly two tiles to cover the whole channel range");
+    static_assert(DP_BIT15 / 4 % DP_TILE_LENGTH == 0,
+                  "Whole tiles to cover the whole channel range");
 
     int pixel_count = DP_BIT15 / 4;
     size_t pixel_scount = DP_int_to_size(pixel_count);
@@ -64,8 +64,9 @@ static void pixels15_to_8_tile(TEST_PARAMS)
     }
 
     DP_Pixel8 *dst = DP_malloc_simd(pixel_scount * sizeof(*dst));
-    DP_pixels15_to_8_tile(dst, src);
-    DP_pixels15_to_8_tile(dst + DP_TILE_LENGTH, src + DP_TILE_LENGTH);
+    for (int i = 0; i < pixel_count; i += DP_TILE_LENGTH) {
+        DP_pixels15_to_8_tile(dst + i, src + i);
+    }
 
     for (int i = 0; i < DP_BIT15; ++i) {
         uint16_t c = DP_int_to_uint16(i);
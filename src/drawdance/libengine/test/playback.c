@@ -0,0 +1,531 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/compress.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/playback.h>
+#include <dpengine/player.h>
+#include <dpmsg/binary_writer.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <parson.h>
+
+
+#define WIDTH    256
+#define HEIGHT   64
+#define LAYER_ID 257
+#define USER     1
+#define RED      0xffff0000u
+#define GREEN    0xff00ff00u
+#define BLUE     0xff0000ffu
+#define NONE     0x00000000u
+
+#define SEEK_PATH           "test/tmp/playback_seek.dprec"
+#define SEEK_MESSAGES       5000
+#define SEEK_SNAPSHOT_EVERY 500
+
+#define DEDUP_PATH       "test/tmp/playback_dedup.dprec"
+#define DEDUP_INDEX_PATH "test/tmp/playback_dedup.dpidx"
+#define DEDUP_COLUMNS    8
+#define DEDUP_ROWS       4
+#define DEDUP_TILES      (DEDUP_COLUMNS * DEDUP_ROWS)
+#define DEDUP_UNDO_DEPTH 40
+#define DEDUP_MESSAGES   (2 + DEDUP_TILES * 2 + DEDUP_UNDO_DEPTH)
+
+static DP_Message *fill_column(int column, uint32_t color)
+{
+    return DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(column * 64), 0, 64, HEIGHT,
+                                color);
+}
+
+static void write_recording(TEST_PARAMS, DP_BinaryWriter *bw, int count,
+                            DP_Message **msgs)
+{
+    JSON_Value *header_value = json_value_init_object();
+    json_object_set_string(json_value_get_object(header_value), "version",
+                           DP_PROTOCOL_VERSION);
+    OK(DP_binary_writer_write_header(bw, json_value_get_object(header_value)),
+       "write recording header");
+    json_value_free(header_value);
+    for (int i = 0; i < count; ++i) {
+        if (DP_binary_writer_write_message(bw, msgs[i]) == 0) {
+            FAIL("write recording message %d", i + 1);
+        }
+        DP_message_decref(msgs[i]);
+    }
+}
+
+// Message indexes are in the comments.
+static DP_Player *make_player(TEST_PARAMS)
+{
+    DP_Message *msgs[] = {
+        DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0), // 1
+        DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                     5),  // 2
+        DP_msg_interval_new(USER, 100),   // 3
+        DP_msg_undo_point_new(USER),      // 4
+        fill_column(0, RED),              // 5
+        DP_msg_interval_new(USER, 50),    // 6
+        DP_msg_undo_point_new(USER),      // 7
+        fill_column(1, BLUE),             // 8
+        DP_msg_interval_new(USER, 200),   // 9
+        fill_column(2, GREEN),            // 10
+    };
+
+    void **buffer_ptr;
+    size_t *size_ptr;
+    DP_BinaryWriter *bw = DP_binary_writer_new(
+        DP_mem_output_new(1024, false, &buffer_ptr, &size_ptr));
+    write_recording(TEST_ARGS, bw, (int)DP_ARRAY_LENGTH(msgs), msgs);
+    // The pointers go away with the writer, the buffer itself doesn't.
+    void *buffer = *buffer_ptr;
+    size_t size = *size_ptr;
+    DP_binary_writer_free(bw);
+
+    DP_Player *player =
+        DP_player_new(DP_PLAYER_TYPE_BINARY, NULL,
+                      DP_mem_input_new_free_on_close(buffer, size), NULL);
+    FATAL(NOT_NULL_OK(player, "open recording"));
+    return player;
+}
+
+static uint32_t column_color(DP_Playback *pb, int column)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(DP_playback_canvas_history(pb));
+    DP_Image *img =
+        DP_canvas_state_to_flat_image(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL,
+                                      NULL);
+    uint32_t color =
+        img ? DP_image_pixel_at(img, column * 64 + 32, HEIGHT / 2).color : NONE;
+    DP_image_free(img);
+    DP_canvas_state_decref(cs);
+    return color;
+}
+
+#define POLL(ELAPSED, WAIT, RESULT)                                          \
+    do {                                                                     \
+        long long _wait;                                                     \
+        INT_EQ_OK((int)DP_playback_poll(pb, dc, (ELAPSED), &_wait),          \
+                  (int)(RESULT), "poll %d result", (int)(ELAPSED));          \
+        INT_EQ_OK((int)_wait, (WAIT), "poll %d waits %d", (int)(ELAPSED),    \
+                  (int)(WAIT));                                              \
+    } while (0)
+
+#define AT(INDEX, TIME)                                                      \
+    do {                                                                     \
+        INT_EQ_OK((int)DP_playback_message_index(pb), (INDEX),               \
+                  "at message %d", (INDEX));                                 \
+        INT_EQ_OK((int)DP_playback_canvas_time_ms(pb), (TIME),               \
+                  "at canvas time %d", (TIME));                              \
+    } while (0)
+
+
+static void waits_scale_with_speed(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Playback *pb = DP_playback_new(make_player(TEST_ARGS));
+    NOK(DP_playback_playing(pb), "playback starts paused");
+    POLL(1000, -1, DP_PLAYER_SUCCESS);
+    AT(0, 0);
+
+    DP_playback_play(pb, 1.0);
+    POLL(0, 100, DP_PLAYER_SUCCESS);
+    AT(3, 0);
+    UINT_EQ_OK(column_color(pb, 0), NONE, "nothing drawn yet");
+    POLL(40, 60, DP_PLAYER_SUCCESS);
+    AT(3, 40);
+    POLL(60, 50, DP_PLAYER_SUCCESS);
+    AT(6, 100);
+    UINT_EQ_OK(column_color(pb, 0), RED, "first fill is drawn");
+    UINT_EQ_OK(column_color(pb, 1), NONE, "second fill isn't drawn yet");
+
+    DP_playback_play(pb, 2.0);
+    POLL(0, 25, DP_PLAYER_SUCCESS);
+    POLL(25, 100, DP_PLAYER_SUCCESS);
+    AT(9, 150);
+    UINT_EQ_OK(column_color(pb, 1), BLUE, "second fill is drawn");
+
+    DP_playback_pause(pb);
+    NOK(DP_playback_playing(pb), "paused");
+    POLL(1000, -1, DP_PLAYER_SUCCESS);
+    AT(9, 150);
+    UINT_EQ_OK(column_color(pb, 2), NONE, "paused playback doesn't go on");
+
+    DP_playback_play(pb, 0.5);
+    POLL(0, 400, DP_PLAYER_SUCCESS);
+    POLL(399, 1, DP_PLAYER_SUCCESS);
+    AT(9, 349);
+    POLL(1, -1, DP_PLAYER_RECORDING_END);
+    AT(10, 350);
+    UINT_EQ_OK(column_color(pb, 2), GREEN, "last fill is drawn");
+    OK(DP_playback_finished(pb), "playback is finished");
+    NOK(DP_playback_playing(pb), "finished playback isn't playing");
+    POLL(1000, -1, DP_PLAYER_RECORDING_END);
+
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+static void step_one_at_a_time(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Playback *pb = DP_playback_new(make_player(TEST_ARGS));
+
+    for (int i = 1; i <= 10; ++i) {
+        INT_EQ_OK((int)DP_playback_step_one(pb, dc), (int)DP_PLAYER_SUCCESS,
+                  "step %d", i);
+        INT_EQ_OK((int)DP_playback_message_index(pb), i, "at message %d", i);
+    }
+    AT(10, 350);
+    UINT_EQ_OK(column_color(pb, 2), GREEN, "last fill is drawn");
+    INT_EQ_OK((int)DP_playback_step_one(pb, dc),
+              (int)DP_PLAYER_RECORDING_END, "step past the end");
+    OK(DP_playback_finished(pb), "playback is finished");
+
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+static void step_one_skips_rest_of_interval(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Playback *pb = DP_playback_new(make_player(TEST_ARGS));
+
+    DP_playback_play(pb, 1.0);
+    POLL(0, 100, DP_PLAYER_SUCCESS);
+    POLL(30, 70, DP_PLAYER_SUCCESS);
+    OK(DP_playback_step_one(pb, dc) == DP_PLAYER_SUCCESS, "step");
+    AT(4, 100);
+    OK(DP_playback_playing(pb), "still playing after stepping");
+    POLL(0, 50, DP_PLAYER_SUCCESS);
+    AT(6, 100);
+    UINT_EQ_OK(column_color(pb, 0), RED, "first fill is drawn");
+
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+static void next_stop_at_undo_points_and_intervals(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Playback *pb = DP_playback_new(make_player(TEST_ARGS));
+
+    static const struct {
+        int index;
+        int time;
+        uint32_t colors[3];
+    } stops[] = {
+        {3, 100, {NONE, NONE, NONE}},  {4, 100, {NONE, NONE, NONE}},
+        {6, 150, {RED, NONE, NONE}},   {7, 150, {RED, NONE, NONE}},
+        {9, 350, {RED, BLUE, NONE}},
+    };
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(stops); ++i) {
+        OK(DP_playback_next_stop(pb, dc) == DP_PLAYER_SUCCESS, "stop %d", i);
+        AT(stops[i].index, stops[i].time);
+        for (int j = 0; j < 3; ++j) {
+            UINT_EQ_OK(column_color(pb, j), stops[i].colors[j],
+                       "stop %d column %d", i, j);
+        }
+    }
+    INT_EQ_OK((int)DP_playback_next_stop(pb, dc),
+              (int)DP_PLAYER_RECORDING_END, "next stop is the end");
+    AT(10, 350);
+    UINT_EQ_OK(column_color(pb, 2), GREEN, "last fill is drawn");
+
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+// Snapshots in the index are stored with 8 bits per channel, so translucent
+// colors wouldn't come out quite the same as replaying them. The undos make
+// the snapshots need their history and catch messages replayed twice. The
+// intervals check that seeking gets the canvas time right.
+static DP_Message *seek_message(int i)
+{
+    if (i == 0) {
+        return DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0);
+    }
+    else if (i == 1) {
+        return DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0,
+                                            "Layer", 5);
+    }
+    else if (i % 10 == 2) {
+        return DP_msg_undo_point_new(USER);
+    }
+    else if (i % 37 == 0) {
+        return DP_msg_undo_new(USER, 0, false);
+    }
+    else if (i % 50 == 25) {
+        return DP_msg_interval_new(USER, DP_int_to_uint16(i % 1000));
+    }
+    else {
+        uint32_t color = 0xff000000u | (DP_int_to_uint32(i) * 2654435761u >> 8);
+        return DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                    DP_int_to_uint32(i * 13 % 224),
+                                    DP_int_to_uint32(i * 7 % 48), 32, 16,
+                                    color);
+    }
+}
+
+static bool should_snapshot(DP_UNUSED void *user, long long messages,
+                            DP_UNUSED size_t bytes)
+{
+    return messages >= SEEK_SNAPSHOT_EVERY;
+}
+
+static DP_Player *open_seek_player(TEST_PARAMS)
+{
+    DP_Player *player =
+        DP_player_new(DP_PLAYER_TYPE_BINARY, SEEK_PATH,
+                      DP_file_input_new_from_path(SEEK_PATH), NULL);
+    FATAL(NOT_NULL_OK(player, "open seek recording"));
+    return player;
+}
+
+static uint64_t checksum(DP_Playback *pb)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(DP_playback_canvas_history(pb));
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+static void seek_matches_linear_replay(TEST_PARAMS)
+{
+    DP_Message **msgs = DP_malloc(sizeof(*msgs) * SEEK_MESSAGES);
+    for (int i = 0; i < SEEK_MESSAGES; ++i) {
+        msgs[i] = seek_message(i);
+    }
+    DP_Output *output = DP_file_output_new_from_path(SEEK_PATH);
+    FATAL(NOT_NULL_OK(output, "open %s", SEEK_PATH));
+    DP_BinaryWriter *bw = DP_binary_writer_new(output);
+    write_recording(TEST_ARGS, bw, SEEK_MESSAGES, msgs);
+    DP_binary_writer_free(bw);
+    DP_free(msgs);
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    uint64_t *expected = DP_malloc(sizeof(*expected) * (SEEK_MESSAGES + 1));
+    long long *expected_ms =
+        DP_malloc(sizeof(*expected_ms) * (SEEK_MESSAGES + 1));
+    DP_Playback *linear = DP_playback_new(open_seek_player(TEST_ARGS));
+    expected[0] = checksum(linear);
+    expected_ms[0] = DP_playback_canvas_time_ms(linear);
+    for (int i = 1; i <= SEEK_MESSAGES; ++i) {
+        DP_playback_step_one(linear, dc);
+        expected[i] = checksum(linear);
+        expected_ms[i] = DP_playback_canvas_time_ms(linear);
+    }
+    DP_playback_free(linear);
+
+    DP_Player *player = open_seek_player(TEST_ARGS);
+    FATAL(OK(DP_player_index_build(player, dc, should_snapshot, NULL, NULL),
+             "build index (error: %s)", DP_error()));
+    FATAL(OK(DP_player_index_load(player), "load index (error: %s)",
+             DP_error()));
+    DP_Playback *pb = DP_playback_new(player);
+
+    static const long long positions[] = {
+        2750, 4999, 1234, 500, 499, 5000, 0, 3001, 3020, 1, 4500, 2750,
+    };
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(positions); ++i) {
+        long long position = positions[i];
+        long long replayed;
+        unsigned int errors = DP_error_count();
+        DP_PlayerResult result =
+            DP_playback_seek_to(pb, dc, position, &replayed);
+        OK(result == DP_PLAYER_SUCCESS, "seek to %lld (error: %s)", position,
+           DP_error());
+        OK(DP_error_count_since(errors) == 0,
+           "seek to %lld left no error behind (got %s)", position,
+           DP_error_count_since(errors) == 0 ? "none" : DP_error());
+        OK(DP_playback_message_index(pb) == position, "seeked to %lld",
+           position);
+        OK(checksum(pb) == expected[position],
+           "canvas after seeking to %lld matches linear replay", position);
+        OK(DP_playback_canvas_time_ms(pb) == expected_ms[position],
+           "canvas time after seeking to %lld is %lldms", position,
+           expected_ms[position]);
+        OK(replayed <= position % SEEK_SNAPSHOT_EVERY,
+           "seek to %lld replayed %lld messages", position, replayed);
+    }
+    DP_free(expected_ms);
+    DP_free(expected);
+
+    long long replayed;
+    DP_playback_seek_to(pb, dc, 3000, &replayed);
+    DP_playback_seek_to(pb, dc, 3005, &replayed);
+    OK(replayed == 5, "seeking a little ahead replays just the difference");
+    DP_playback_seek_to(pb, dc, 2995, &replayed);
+    OK(replayed == 495, "seeking a little back replays from the snapshot");
+
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+
+static void set_tile_data(size_t size, unsigned char *out, void *user)
+{
+    memcpy(out, user, size);
+}
+
+static unsigned char *get_output_buffer(size_t size, void *user)
+{
+    unsigned char **buffer = user;
+    *buffer = DP_malloc(size);
+    return *buffer;
+}
+
+// Noise hardly compresses, so every copy of it that gets written shows up in
+// the size of the index.
+static unsigned char *make_noise_tile(uint32_t seed, size_t *out_size)
+{
+    DP_Pixel8 *pixels = DP_malloc(sizeof(*pixels) * DP_TILE_LENGTH);
+    uint32_t x = seed;
+    for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+        // xorshift32, so that the tiles are the same every time.
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        pixels[i] = DP_pixel8_premultiply((DP_UPixel8){.color = x});
+    }
+    unsigned char *buffer;
+    *out_size = DP_compress_deflate((const unsigned char *)pixels,
+                                    sizeof(*pixels) * DP_TILE_LENGTH,
+                                    get_output_buffer, &buffer);
+    DP_free(pixels);
+    return buffer;
+}
+
+static bool should_snapshot_at_end(DP_UNUSED void *user, long long messages,
+                                   DP_UNUSED size_t bytes)
+{
+    return messages >= DEDUP_MESSAGES;
+}
+
+static DP_Tile *snapshot_tile_at(DP_CanvasState *cs, int i)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return lre ? DP_layer_content_tile_at_noinc(
+                     DP_layer_routes_entry_content(lre, cs),
+                     i % DEDUP_COLUMNS, i / DEDUP_COLUMNS)
+               : NULL;
+}
+
+static void index_shares_identical_tiles(TEST_PARAMS)
+{
+    size_t sizes[2];
+    unsigned char *noise[2] = {make_noise_tile(0x12345678u, &sizes[0]),
+                               make_noise_tile(0x9abcdef0u, &sizes[1])};
+
+    // The undo points at the end push the tiles out of the undo history, so
+    // they only end up in the snapshot's canvas and not as messages.
+    DP_Message **msgs = DP_malloc(sizeof(*msgs) * DEDUP_MESSAGES);
+    int count = 0;
+    msgs[count++] = DP_msg_canvas_resize_new(
+        USER, 0, DEDUP_COLUMNS * DP_TILE_SIZE, DEDUP_ROWS * DP_TILE_SIZE, 0);
+    msgs[count++] = DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0,
+                                                 "Layer", 5);
+    for (int i = 0; i < DEDUP_TILES; ++i) {
+        msgs[count++] = DP_msg_undo_point_new(USER);
+        msgs[count++] = DP_msg_put_tile_new(
+            USER, LAYER_ID, 0, DP_int_to_uint16(i % DEDUP_COLUMNS),
+            DP_int_to_uint16(i / DEDUP_COLUMNS), 0, set_tile_data,
+            sizes[i % 2], noise[i % 2]);
+    }
+    for (int i = 0; i < DEDUP_UNDO_DEPTH; ++i) {
+        msgs[count++] = DP_msg_undo_point_new(USER);
+    }
+    DP_Output *output = DP_file_output_new_from_path(DEDUP_PATH);
+    FATAL(NOT_NULL_OK(output, "open %s", DEDUP_PATH));
+    DP_BinaryWriter *bw = DP_binary_writer_new(output);
+    write_recording(TEST_ARGS, bw, count, msgs);
+    DP_binary_writer_free(bw);
+    DP_free(msgs);
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Player *player =
+        DP_player_new(DP_PLAYER_TYPE_BINARY, DEDUP_PATH,
+                      DP_file_input_new_from_path(DEDUP_PATH), NULL);
+    FATAL(NOT_NULL_OK(player, "open dedup recording"));
+    FATAL(OK(DP_player_index_build(player, dc, should_snapshot_at_end, NULL,
+                                   NULL),
+             "build index (error: %s)", DP_error()));
+    FATAL(OK(DP_player_index_load(player), "load index (error: %s)",
+             DP_error()));
+
+    DP_PlayerIndexEntry entry =
+        DP_player_index_entry_search(player, DEDUP_MESSAGES, false);
+    INT_EQ_OK((int)entry.message_index, DEDUP_MESSAGES - 1,
+              "found snapshot after the last message");
+    DP_PlayerIndexEntrySnapshot *snapshot =
+        DP_player_index_entry_load(player, dc, entry);
+    FATAL(NOT_NULL_OK(snapshot, "load snapshot (error: %s)", DP_error()));
+    DP_CanvasState *cs =
+        DP_player_index_entry_snapshot_canvas_state_inc(snapshot);
+
+    // Tiles read from the same offset come out as the same tile.
+    DP_Tile *expected[2] = {
+        DP_tile_new_from_compressed(dc, 0, noise[0], sizes[0]),
+        DP_tile_new_from_compressed(dc, 0, noise[1], sizes[1])};
+    int shared = 0;
+    int equal = 0;
+    for (int i = 0; i < DEDUP_TILES; ++i) {
+        DP_Tile *t = snapshot_tile_at(cs, i);
+        if (t && t == snapshot_tile_at(cs, i % 2)) {
+            ++shared;
+        }
+        if (t
+            && memcmp(DP_tile_pixels(t), DP_tile_pixels(expected[i % 2]),
+                      DP_TILE_BYTES)
+                   == 0) {
+            ++equal;
+        }
+    }
+    INT_EQ_OK(shared, DEDUP_TILES, "identical tiles share a single copy");
+    INT_EQ_OK(equal, DEDUP_TILES, "tiles come out of the index bit-exact");
+
+    DP_Input *input = DP_file_input_new_from_path(DEDUP_INDEX_PATH);
+    FATAL(NOT_NULL_OK(input, "open %s", DEDUP_INDEX_PATH));
+    size_t index_size = DP_input_length(input, NULL);
+    DP_input_free(input);
+    DIAG("Index is %zu bytes, tile data is %zu bytes", index_size,
+         sizes[0] * DEDUP_TILES / 2 + sizes[1] * DEDUP_TILES / 2);
+    OK(index_size < (sizes[0] + sizes[1]) * 4,
+       "index is smaller than four copies of each tile");
+
+    DP_tile_decref(expected[1]);
+    DP_tile_decref(expected[0]);
+    DP_canvas_state_decref(cs);
+    DP_player_index_entry_snapshot_free(snapshot);
+    DP_player_free(player);
+    DP_draw_context_free(dc);
+    DP_free(noise[1]);
+    DP_free(noise[0]);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(waits_scale_with_speed);
+    REGISTER_TEST(step_one_at_a_time);
+    REGISTER_TEST(step_one_skips_rest_of_interval);
+    REGISTER_TEST(next_stop_at_undo_points_and_intervals);
+    REGISTER_TEST(seek_matches_linear_replay);
+    REGISTER_TEST(index_shares_identical_tiles);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
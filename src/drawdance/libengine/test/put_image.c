@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpengine/put_image.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID      257
+#define USER          1
+#define CANVAS_WIDTH  320
+#define CANVAS_HEIGHT 240
+
+typedef struct DP_PutImageTestState {
+    DP_CanvasHistory *ch;
+    DP_DrawContext *dc;
+    int count;
+    int undo_points;
+    int too_long;
+    int failed;
+} DP_PutImageTestState;
+
+static bool push_message(void *user, DP_Message *msg)
+{
+    DP_PutImageTestState *state = user;
+    ++state->count;
+    if (DP_message_type(msg) == DP_MSG_UNDO_POINT) {
+        ++state->undo_points;
+    }
+    if (DP_message_length(msg) > DP_MESSAGE_MAX_PAYLOAD_LENGTH) {
+        ++state->too_long;
+    }
+    if (!DP_canvas_history_handle(state->ch, state->dc, msg)) {
+        ++state->failed;
+    }
+    DP_message_decref(msg);
+    return true;
+}
+
+static DP_PutImageTestState set_up_canvas(void)
+{
+    DP_PutImageTestState state = {
+        DP_canvas_history_new(NULL, NULL, false, NULL), DP_draw_context_new(),
+        0, 0, 0, 0};
+    DP_Message *msgs[] = {
+        DP_msg_canvas_resize_new(USER, 0, CANVAS_WIDTH, CANVAS_HEIGHT, 0),
+        DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer", 5),
+    };
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(msgs); ++i) {
+        DP_canvas_history_handle(state.ch, state.dc, msgs[i]);
+        DP_message_decref(msgs[i]);
+    }
+    return state;
+}
+
+static void dispose_canvas(DP_PutImageTestState *state)
+{
+    DP_canvas_history_free(state->ch);
+    DP_draw_context_free(state->dc);
+}
+
+// Random opaque pixels, so that compression can't do much with them.
+static DP_Image *make_noise_image(int width, int height)
+{
+    DP_Image *img = DP_image_new(width, height);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    unsigned int seed = 1;
+    for (int i = 0; i < width * height; ++i) {
+        seed = seed * 1103515245u + 12345u;
+        pixels[i].color = 0xff000000u | (seed >> 8);
+    }
+    return img;
+}
+
+static DP_Image *flatten(DP_PutImageTestState *state)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(state->ch);
+    DP_Image *img = DP_canvas_state_to_flat_image(
+        cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+    DP_canvas_state_decref(cs);
+    return img;
+}
+
+// Counts canvas pixels that differ from the image placed at the given offset,
+// with everything outside of it expected to be transparent.
+static int count_mismatches(DP_Image *canvas, DP_Image *img, int x, int y)
+{
+    int mismatches = 0;
+    int width = DP_image_width(img);
+    int height = DP_image_height(img);
+    for (int cy = 0; cy < CANVAS_HEIGHT; ++cy) {
+        for (int cx = 0; cx < CANVAS_WIDTH; ++cx) {
+            int ix = cx - x;
+            int iy = cy - y;
+            bool inside = ix >= 0 && iy >= 0 && ix < width && iy < height;
+            uint32_t expected =
+                inside ? DP_image_pixel_at(img, ix, iy).color : 0;
+            if (DP_image_pixel_at(canvas, cx, cy).color != expected) {
+                ++mismatches;
+            }
+        }
+    }
+    return mismatches;
+}
+
+static void put_image_and_check(TEST_PARAMS, int x, int y, bool split)
+{
+    DP_PutImageTestState state = set_up_canvas();
+    DP_Image *img = make_noise_image(300, 200);
+
+    OK(DP_put_image_messages(USER, LAYER_ID, DP_BLEND_MODE_REPLACE, x, y, img,
+                             push_message, &state),
+       "put image at %d, %d", x, y);
+    if (split) {
+        OK(state.count > 2, "image was split into %d messages",
+           state.count - 1);
+    }
+    INT_EQ_OK(state.undo_points, 1, "one undo point");
+    INT_EQ_OK(state.too_long, 0, "no message exceeds the maximum length");
+    INT_EQ_OK(state.failed, 0, "all messages applied");
+
+    DP_Image *canvas = flatten(&state);
+    if (NOT_NULL_OK(canvas, "flatten canvas")) {
+        INT_EQ_OK(count_mismatches(canvas, img, x, y), 0,
+                  "canvas matches image");
+    }
+    DP_image_free(canvas);
+
+    DP_Message *undo = DP_msg_undo_new(USER, 0, false);
+    DP_canvas_history_handle(state.ch, state.dc, undo);
+    DP_message_decref(undo);
+    canvas = flatten(&state);
+    if (NOT_NULL_OK(canvas, "flatten canvas after undo")) {
+        DP_Image *blank = DP_image_new(1, 1);
+        INT_EQ_OK(count_mismatches(canvas, blank, -1, -1), 0,
+                  "single undo removes whole image");
+        DP_image_free(blank);
+    }
+    DP_image_free(canvas);
+
+    DP_image_free(img);
+    dispose_canvas(&state);
+}
+
+
+static void put_image_splits_large_image(TEST_PARAMS)
+{
+    put_image_and_check(TEST_ARGS, 10, 20, true);
+}
+
+static void put_image_crops_negative_coordinates(TEST_PARAMS)
+{
+    put_image_and_check(TEST_ARGS, -50, -30, true);
+}
+
+static void put_image_small_image_in_one_message(TEST_PARAMS)
+{
+    DP_PutImageTestState state = set_up_canvas();
+    DP_Image *img = DP_image_new(64, 64);
+    OK(DP_put_image_messages(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0, img,
+                             push_message, &state),
+       "put small image");
+    INT_EQ_OK(state.count, 2, "undo point and a single put image");
+
+    state.count = 0;
+    OK(DP_put_image_messages(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, -64, 0,
+                             img, push_message, &state),
+       "put image entirely off the canvas");
+    INT_EQ_OK(state.count, 0, "nothing to put");
+
+    DP_image_free(img);
+    dispose_canvas(&state);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(put_image_splits_large_image);
+    REGISTER_TEST(put_image_crops_negative_coordinates);
+    REGISTER_TEST(put_image_small_image_in_one_message);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/recorder.h>
+#include <dpmsg/binary_reader.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define USER 1
+
+#define RECORDING_PATH "test/tmp/recorder_bypass.dprec"
+
+static long long get_time(DP_UNUSED void *user)
+{
+    return 0;
+}
+
+static DP_Message *chat(unsigned int tflags, unsigned int oflags,
+                        const char *text)
+{
+    return DP_msg_chat_new(USER, (uint8_t)tflags, (uint8_t)oflags, text,
+                           strlen(text));
+}
+
+
+static void recorder_skips_bypass_chat(TEST_PARAMS)
+{
+    DP_Message *msgs[] = {
+        chat(0, 0, "recorded"),
+        chat(DP_MSG_CHAT_TFLAGS_BYPASS, 0, "oob"),
+        DP_msg_undo_point_new(USER),
+        chat(DP_MSG_CHAT_TFLAGS_BYPASS,
+             DP_MSG_CHAT_OFLAGS_SHOUT | DP_MSG_CHAT_OFLAGS_PIN, "oob shout"),
+        chat(0, DP_MSG_CHAT_OFLAGS_ACTION | DP_MSG_CHAT_OFLAGS_ALERT,
+             "recorded action"),
+    };
+
+    DP_Output *output = DP_file_output_new_from_path(RECORDING_PATH);
+    FATAL(NOT_NULL_OK(output, "open %s", RECORDING_PATH));
+    DP_Recorder *r = DP_recorder_new_inc(DP_RECORDER_TYPE_BINARY,
+                                         DP_recorder_header_new(NULL), NULL,
+                                         get_time, NULL, output);
+    FATAL(NOT_NULL_OK(r, "make recorder"));
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(msgs); ++i) {
+        OK(DP_recorder_message_push_noinc(r, msgs[i]), "push message %zu", i);
+    }
+    char *error;
+    DP_recorder_free_join(r, &error);
+    OK(!error, "recorder finished without error (%s)", error ? error : "");
+    DP_free(error);
+
+    DP_Input *input = DP_file_input_new_from_path(RECORDING_PATH);
+    FATAL(NOT_NULL_OK(input, "open %s for reading", RECORDING_PATH));
+    DP_BinaryReader *br = DP_binary_reader_new(input, 0);
+    FATAL(NOT_NULL_OK(br, "read recording header"));
+
+    const char *expected[] = {"recorded", "recorded action"};
+    int chat_count = 0;
+    int undo_point_count = 0;
+    DP_Message *msg;
+    while (DP_binary_reader_read_message(br, true, &msg)
+           == DP_BINARY_READER_SUCCESS) {
+        DP_MsgChat *mc = DP_msg_chat_cast(msg);
+        if (mc) {
+            NOK(DP_msg_chat_tflags(mc) & DP_MSG_CHAT_TFLAGS_BYPASS,
+                "recorded chat doesn't bypass");
+            if (chat_count < (int)DP_ARRAY_LENGTH(expected)) {
+                STR_EQ_OK(DP_msg_chat_message(mc, NULL), expected[chat_count],
+                          "chat %d text", chat_count);
+            }
+            ++chat_count;
+        }
+        else if (DP_message_type(msg) == DP_MSG_UNDO_POINT) {
+            ++undo_point_count;
+        }
+        DP_message_decref(msg);
+    }
+    DP_binary_reader_free(br);
+
+    INT_EQ_OK(chat_count, 2, "only regular chat recorded");
+    INT_EQ_OK(undo_point_count, 1, "other messages still recorded");
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(recorder_skips_bypass_chat);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
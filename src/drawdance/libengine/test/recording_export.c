@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_list.h>
+#include <dpengine/playback.h>
+#include <dpengine/player.h>
+#include <dpengine/recorder.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define BOTTOM_ID 257
+#define TOP_ID    258
+#define USER      1
+
+#define RECORDING_PATH "test/tmp/recording_export.dprec"
+
+static long long get_time(DP_UNUSED void *user)
+{
+    return 0;
+}
+
+static DP_Message *marker(const char *text)
+{
+    return DP_msg_marker_new(USER, text, strlen(text));
+}
+
+static DP_Message *fill(int layer_id, int x, uint32_t color)
+{
+    return DP_msg_fill_rect_new(USER, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x), 0,
+                                16, 16, color);
+}
+
+static void record(TEST_PARAMS)
+{
+    DP_Message *msgs[] = {
+        DP_msg_canvas_resize_new(USER, 0, 64, 64, 0),
+        DP_msg_layer_tree_create_new(USER, BOTTOM_ID, 0, 0, 0, 0, "Bottom", 6),
+        DP_msg_layer_tree_create_new(USER, TOP_ID, 0, 0, 0, 0, "Top", 3),
+        DP_msg_undo_point_new(USER),
+        fill(BOTTOM_ID, 0, 0xffff0000u),
+        DP_msg_undo_point_new(USER),
+        fill(TOP_ID, 16, 0xff00ff00u),
+        DP_msg_undo_point_new(USER),
+        fill(BOTTOM_ID, 32, 0xff0000ffu),
+        DP_msg_undo_new(USER, 0, false),
+        marker("halfway"),
+        DP_msg_undo_point_new(USER),
+        fill(TOP_ID, 48, 0xffffffffu),
+        DP_msg_undo_point_new(USER),
+        fill(BOTTOM_ID, 16, 0xff000000u),
+        marker("done"),
+    };
+
+    DP_Output *output = DP_file_output_new_from_path(RECORDING_PATH);
+    FATAL(NOT_NULL_OK(output, "open %s", RECORDING_PATH));
+    DP_Recorder *r = DP_recorder_new_inc(DP_RECORDER_TYPE_BINARY,
+                                         DP_recorder_header_new(NULL), NULL,
+                                         get_time, NULL, output);
+    FATAL(NOT_NULL_OK(r, "make recorder"));
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(msgs); ++i) {
+        OK(DP_recorder_message_push_noinc(r, msgs[i]), "record message %zu",
+           i);
+    }
+    char *error;
+    DP_recorder_free_join(r, &error);
+    OK(!error, "recorder finished without error (%s)", error ? error : "");
+    DP_free(error);
+}
+
+static DP_Player *open_player(TEST_PARAMS)
+{
+    DP_Player *player =
+        DP_player_new(DP_PLAYER_TYPE_BINARY, RECORDING_PATH,
+                      DP_file_input_new_from_path(RECORDING_PATH), NULL);
+    FATAL(NOT_NULL_OK(player, "open %s", RECORDING_PATH));
+    return player;
+}
+
+// Snapshot everywhere, so that seeking restores a snapshot instead of just
+// playing everything from the start.
+static bool should_snapshot(DP_UNUSED void *user, DP_UNUSED long long messages,
+                            DP_UNUSED size_t bytes)
+{
+    return true;
+}
+
+static DP_Player *open_indexed_player(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_Player *player = open_player(TEST_ARGS);
+    FATAL(OK(DP_player_index_build(player, dc, should_snapshot, NULL, NULL),
+             "build index (error: %s)", DP_error()));
+    DP_player_free(player);
+
+    player = open_player(TEST_ARGS);
+    FATAL(OK(DP_player_index_load(player), "load index (error: %s)",
+             DP_error()));
+    return player;
+}
+
+// Plays the first count messages of the recording straight into a canvas,
+// without going through playback or the index.
+static DP_CanvasState *replay_reference(TEST_PARAMS, DP_DrawContext *dc,
+                                        long long count)
+{
+    DP_Player *player = open_player(TEST_ARGS);
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    for (long long i = 0; i < count; ++i) {
+        DP_Message *msg;
+        FATAL(INT_EQ_OK(DP_player_step_raw(player, &msg), DP_PLAYER_SUCCESS,
+                        "reference step %lld", i));
+        if (DP_message_type(msg) >= 128) {
+            DP_canvas_history_handle(ch, dc, msg);
+        }
+        DP_message_decref(msg);
+    }
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    DP_player_free(player);
+    return cs;
+}
+
+static void check_same_canvas(TEST_PARAMS, DP_CanvasState *actual,
+                              DP_CanvasState *expected)
+{
+    UINT_EQ_OK(DP_canvas_state_checksum(actual),
+               DP_canvas_state_checksum(expected), "canvas checksum matches");
+
+    DP_LayerList *actual_ll = DP_canvas_state_layers_noinc(actual);
+    DP_LayerList *expected_ll = DP_canvas_state_layers_noinc(expected);
+    int count = DP_layer_list_count(expected_ll);
+    FATAL(INT_EQ_OK(DP_layer_list_count(actual_ll), count, "layer count"));
+    for (int i = 0; i < count; ++i) {
+        DP_LayerContent *actual_lc = DP_layer_list_entry_content_noinc(
+            DP_layer_list_at_noinc(actual_ll, i));
+        DP_LayerContent *expected_lc = DP_layer_list_entry_content_noinc(
+            DP_layer_list_at_noinc(expected_ll, i));
+        UINT_EQ_OK(DP_layer_content_checksum(actual_lc),
+                   DP_layer_content_checksum(expected_lc),
+                   "layer %d checksum matches", i);
+    }
+}
+
+static DP_CanvasState *playback_canvas(DP_Playback *pb)
+{
+    return DP_canvas_history_get(DP_playback_canvas_history(pb));
+}
+
+
+static void export_at_marker_matches_reference(TEST_PARAMS)
+{
+    record(TEST_ARGS);
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Player *player = open_indexed_player(TEST_ARGS, dc);
+
+    DP_PlayerIndexMarker halfway;
+    FATAL(OK(DP_player_index_marker_search(player, "halfway", &halfway),
+             "find halfway marker"));
+    STR_EQ_OK(halfway.text, "halfway", "found marker text");
+
+    // Markers don't change anything, so stopping right before it will do.
+    long long position = halfway.message_index;
+    DP_Playback *pb = DP_playback_new(player);
+    FATAL(INT_EQ_OK(DP_playback_replay_to(pb, dc, position), DP_PLAYER_SUCCESS,
+                    "replay to marker (error: %s)", DP_error()));
+    INT_EQ_OK(DP_playback_message_index(pb), position, "at marker position");
+
+    DP_CanvasState *actual = playback_canvas(pb);
+    DP_CanvasState *expected = replay_reference(TEST_ARGS, dc, position);
+    check_same_canvas(TEST_ARGS, actual, expected);
+
+    // The second half of the recording is on top of that.
+    DP_PlayerIndexMarker done;
+    FATAL(OK(DP_player_index_marker_search(player, "done", &done),
+             "find done marker"));
+    FATAL(INT_EQ_OK(DP_playback_replay_to(pb, dc, done.message_index),
+                    DP_PLAYER_SUCCESS, "replay to end (error: %s)",
+                    DP_error()));
+    DP_CanvasState *final = playback_canvas(pb);
+    OK(DP_canvas_state_checksum(final) != DP_canvas_state_checksum(actual),
+       "export at marker doesn't include the second half");
+
+    // Going back uses the index.
+    unsigned int errors = DP_error_count();
+    FATAL(INT_EQ_OK(DP_playback_replay_to(pb, dc, position), DP_PLAYER_SUCCESS,
+                    "replay back to marker (error: %s)", DP_error()));
+    OK(DP_error_count_since(errors) == 0,
+       "replaying back from the index left no error behind (got %s)",
+       DP_error_count_since(errors) == 0 ? "none" : DP_error());
+    DP_CanvasState *again = playback_canvas(pb);
+    check_same_canvas(TEST_ARGS, again, expected);
+
+    DP_canvas_state_decref(again);
+    DP_canvas_state_decref(final);
+    DP_canvas_state_decref(expected);
+    DP_canvas_state_decref(actual);
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+static void export_without_index_streams(TEST_PARAMS)
+{
+    record(TEST_ARGS);
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Playback *pb = DP_playback_new(open_player(TEST_ARGS));
+
+    FATAL(INT_EQ_OK(DP_playback_replay_to(pb, dc, 9), DP_PLAYER_SUCCESS,
+                    "replay without index (error: %s)", DP_error()));
+    DP_CanvasState *actual = playback_canvas(pb);
+    DP_CanvasState *expected = replay_reference(TEST_ARGS, dc, 9);
+    check_same_canvas(TEST_ARGS, actual, expected);
+    DP_canvas_state_decref(expected);
+    DP_canvas_state_decref(actual);
+
+    INT_EQ_OK(DP_playback_replay_to(pb, dc, 4), DP_PLAYER_ERROR_OPERATION,
+              "can't go back without an index");
+    INT_EQ_OK(DP_playback_replay_to(pb, dc, 1000), DP_PLAYER_RECORDING_END,
+              "can't go past the end");
+
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+}
+
+static void export_rejects_bad_positions(TEST_PARAMS)
+{
+    record(TEST_ARGS);
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Player *player = open_indexed_player(TEST_ARGS, dc);
+    long long message_count =
+        DP_uint_to_llong(DP_player_index_message_count(player));
+
+    DP_PlayerIndexMarker marker;
+    NOK(DP_player_index_marker_search(player, "nonexistent", &marker),
+        "unknown marker isn't found");
+    NOK(DP_player_index_marker_search(player, "Halfway", &marker),
+        "marker search is case-sensitive");
+
+    DP_Playback *pb = DP_playback_new(player);
+    INT_EQ_OK(DP_playback_replay_to(pb, dc, -1), DP_PLAYER_ERROR_OPERATION,
+              "negative position is rejected");
+    INT_EQ_OK(DP_playback_replay_to(pb, dc, message_count + 1),
+              DP_PLAYER_ERROR_OPERATION, "position past the end is rejected");
+    INT_EQ_OK(DP_playback_replay_to(pb, dc, message_count), DP_PLAYER_SUCCESS,
+              "position at the end is fine (error: %s)", DP_error());
+    DP_playback_free(pb);
+
+    DP_Player *unindexed = open_player(TEST_ARGS);
+    NOK(DP_player_index_marker_search(unindexed, "halfway", &marker),
+        "no markers without an index");
+    DP_player_free(unindexed);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(export_at_marker_matches_reference);
+    REGISTER_TEST(export_without_index_streams);
+    REGISTER_TEST(export_rejects_bad_positions);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpengine/playback.h>
+#include <dpengine/player.h>
+#include <dpengine/recorder.h>
+#include <dpengine/recording_filter.h>
+#include <dpmsg/binary_writer.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <parson.h>
+
+
+#define WIDTH    256
+#define HEIGHT   64
+#define LAYER_ID 257
+#define ALICE    1
+#define BOB      2
+#define RED      0xffff0000u
+#define GREEN    0xff00ff00u
+#define BLUE     0xff0000ffu
+#define NONE     0x00000000u
+
+#define INPUT_PATH     "test/tmp/recording_filter_input.dprec"
+#define FILTERED_PATH  "test/tmp/recording_filter_filtered.dprec"
+#define UNCHANGED_PATH "test/tmp/recording_filter_unchanged.dprec"
+
+static DP_Message *fill_column(unsigned int context_id, int column,
+                               uint32_t color)
+{
+    return DP_msg_fill_rect_new(context_id, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(column * 64), 0, 64, HEIGHT,
+                                color);
+}
+
+static DP_Message *chat(unsigned int context_id, const char *text)
+{
+    return DP_msg_chat_new(context_id, 0, 0, text, strlen(text));
+}
+
+// Alice's green column gets undone and stays that way, Bob's blue one gets
+// undone and redone, his red one over it gets undone again.
+static void write_input(TEST_PARAMS)
+{
+    DP_Message *msgs[] = {
+        DP_msg_canvas_resize_new(ALICE, 0, WIDTH, HEIGHT, 0),
+        DP_msg_layer_tree_create_new(ALICE, LAYER_ID, 0, 0, 0, 0, "Layer", 5),
+        chat(BOB, "hi"),
+        DP_msg_undo_point_new(ALICE),
+        fill_column(ALICE, 0, RED),
+        DP_msg_undo_point_new(BOB),
+        fill_column(BOB, 1, BLUE),
+        DP_msg_interval_new(ALICE, 300),
+        DP_msg_undo_point_new(ALICE),
+        fill_column(ALICE, 2, GREEN),
+        DP_msg_laser_trail_new(BOB, RED, 5),
+        DP_msg_move_pointer_new(BOB, 10, 10),
+        DP_msg_undo_new(ALICE, 0, false),
+        DP_msg_undo_new(BOB, 0, false),
+        DP_msg_interval_new(ALICE, 800),
+        DP_msg_interval_new(ALICE, 900),
+        DP_msg_undo_new(BOB, 0, true),
+        DP_msg_undo_point_new(ALICE),
+        fill_column(ALICE, 3, BLUE),
+        DP_msg_undo_point_new(BOB),
+        fill_column(BOB, 1, RED),
+        DP_msg_undo_new(ALICE, BOB, false),
+        chat(ALICE, "bye"),
+        DP_msg_interval_new(ALICE, 5000),
+    };
+
+    DP_Output *output = DP_file_output_new_from_path(INPUT_PATH);
+    FATAL(NOT_NULL_OK(output, "open %s", INPUT_PATH));
+    DP_BinaryWriter *bw = DP_binary_writer_new(output);
+    JSON_Value *header_value = json_value_init_object();
+    json_object_set_string(json_value_get_object(header_value), "version",
+                           DP_PROTOCOL_VERSION);
+    OK(DP_binary_writer_write_header(bw, json_value_get_object(header_value)),
+       "write recording header");
+    json_value_free(header_value);
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(msgs); ++i) {
+        if (DP_binary_writer_write_message(bw, msgs[i]) == 0) {
+            FAIL("write recording message %d", i + 1);
+        }
+        DP_message_decref(msgs[i]);
+    }
+    DP_binary_writer_free(bw);
+}
+
+static DP_Player *open_player(TEST_PARAMS, const char *path)
+{
+    DP_Player *player = DP_player_new(DP_PLAYER_TYPE_BINARY, path,
+                                      DP_file_input_new_from_path(path), NULL);
+    FATAL(NOT_NULL_OK(player, "open %s", path));
+    DP_player_acl_override_set(player, true);
+    return player;
+}
+
+static void filter(TEST_PARAMS, const char *path,
+                   const DP_RecordingFilterOptions *options)
+{
+    DP_Player *player = open_player(TEST_ARGS, INPUT_PATH);
+    DP_Output *output = DP_file_output_new_from_path(path);
+    FATAL(NOT_NULL_OK(output, "open %s", path));
+    DP_Recorder *r = DP_recorder_new_inc(
+        DP_RECORDER_TYPE_BINARY,
+        DP_recorder_header_clone(DP_player_header(player)), NULL, NULL, NULL,
+        output);
+    FATAL(NOT_NULL_OK(r, "make recorder"));
+    OK(DP_recording_filter(player, r, options), "filter recording (error: %s)",
+       DP_error());
+    char *error;
+    DP_recorder_free_join(r, &error);
+    OK(!error, "recorder finished without error (%s)", error ? error : "");
+    DP_free(error);
+    DP_player_free(player);
+}
+
+typedef struct DP_ReplayResult {
+    uint64_t checksum;
+    uint32_t colors[4];
+    long long message_count;
+    int type_counts[DP_MSG_TYPE_COUNT];
+    int longest_interval_ms;
+} DP_ReplayResult;
+
+static DP_ReplayResult replay(TEST_PARAMS, const char *path)
+{
+    DP_ReplayResult result = {0};
+    DP_Player *player = open_player(TEST_ARGS, path);
+    DP_Player *counting_player = open_player(TEST_ARGS, path);
+    DP_Message *msg;
+    while (DP_player_step(counting_player, &msg) == DP_PLAYER_SUCCESS) {
+        DP_MessageType type = DP_message_type(msg);
+        ++result.type_counts[type];
+        if (type == DP_MSG_INTERVAL) {
+            int msecs = DP_msg_interval_msecs(DP_message_internal(msg));
+            result.longest_interval_ms =
+                DP_max_int(result.longest_interval_ms, msecs);
+        }
+        DP_message_decref(msg);
+    }
+    DP_player_free(counting_player);
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Playback *pb = DP_playback_new(player);
+    while (DP_playback_step_one(pb, dc) == DP_PLAYER_SUCCESS) {
+        // Just replaying everything.
+    }
+    result.message_count = DP_playback_message_index(pb);
+
+    DP_CanvasState *cs = DP_canvas_history_get(DP_playback_canvas_history(pb));
+    result.checksum = DP_canvas_state_checksum(cs);
+    DP_Image *img =
+        DP_canvas_state_to_flat_image(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL,
+                                      NULL);
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(result.colors); ++i) {
+        result.colors[i] =
+            img ? DP_image_pixel_at(img, i * 64 + 32, HEIGHT / 2).color : NONE;
+    }
+    DP_image_free(img);
+    DP_canvas_state_decref(cs);
+    DP_playback_free(pb);
+    DP_draw_context_free(dc);
+    return result;
+}
+
+
+static void filter_removes_undone_and_noise(TEST_PARAMS)
+{
+    write_input(TEST_ARGS);
+    DP_ReplayResult original = replay(TEST_ARGS, INPUT_PATH);
+    UINT_EQ_OK(original.colors[0], RED, "original left column is red");
+    UINT_EQ_OK(original.colors[1], BLUE, "original red column was undone");
+    UINT_EQ_OK(original.colors[2], NONE, "original green column was undone");
+    UINT_EQ_OK(original.colors[3], BLUE, "original right column is blue");
+
+    DP_RecordingFilterOptions options = {true, true, true, 1000};
+    filter(TEST_ARGS, FILTERED_PATH, &options);
+    DP_ReplayResult filtered = replay(TEST_ARGS, FILTERED_PATH);
+    OK(filtered.checksum == original.checksum,
+       "filtered canvas checksum matches original");
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(filtered.colors); ++i) {
+        UINT_EQ_OK(filtered.colors[i], original.colors[i],
+                   "filtered column %d matches original", i);
+    }
+    OK(filtered.message_count < original.message_count,
+       "filtered recording has %lld < %lld messages", filtered.message_count,
+       original.message_count);
+    INT_EQ_OK(filtered.type_counts[DP_MSG_UNDO], 0, "no undos left");
+    INT_EQ_OK(filtered.type_counts[DP_MSG_UNDO_POINT], 3,
+              "only done undo points left");
+    INT_EQ_OK(filtered.type_counts[DP_MSG_FILL_RECT], 3,
+              "only done fills left");
+    INT_EQ_OK(filtered.type_counts[DP_MSG_CHAT], 0, "no chat left");
+    INT_EQ_OK(filtered.type_counts[DP_MSG_LASER_TRAIL], 0, "no lasers left");
+    INT_EQ_OK(filtered.type_counts[DP_MSG_MOVE_POINTER], 0,
+              "no pointers left");
+    INT_EQ_OK(filtered.type_counts[DP_MSG_INTERVAL], 2,
+              "intervals were merged across removed messages");
+    INT_EQ_OK(filtered.longest_interval_ms, 1000, "intervals were capped");
+}
+
+static void filter_without_options_keeps_everything(TEST_PARAMS)
+{
+    write_input(TEST_ARGS);
+    DP_ReplayResult original = replay(TEST_ARGS, INPUT_PATH);
+    DP_RecordingFilterOptions options = {false, false, false, 0};
+    filter(TEST_ARGS, UNCHANGED_PATH, &options);
+    DP_ReplayResult filtered = replay(TEST_ARGS, UNCHANGED_PATH);
+    OK(filtered.checksum == original.checksum,
+       "unfiltered canvas checksum matches original");
+    // The recorder starts off with a feature access message of its own.
+    OK(filtered.message_count == original.message_count + 1,
+       "unfiltered recording has all %lld messages", original.message_count);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(filter_removes_undone_and_noise);
+    REGISTER_TEST(filter_without_options_keeps_everything);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
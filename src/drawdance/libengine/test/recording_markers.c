@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/player.h>
+#include <dpengine/recorder.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <parson.h>
+
+
+#define LAYER_ID 257
+#define USER     1
+
+#define RECORDING_PATH "test/tmp/recording_markers.dprec"
+
+typedef struct DP_RecordingMarkersStep {
+    long long time_ms;
+    DP_Message *msg;
+} DP_RecordingMarkersStep;
+
+static long long get_time(void *user)
+{
+    return *(long long *)user;
+}
+
+static DP_Message *marker(const char *text)
+{
+    return DP_msg_marker_new(USER, text, strlen(text));
+}
+
+static DP_Message *fill(int x)
+{
+    return DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(x), 0, 8, 8, 0xff000000u);
+}
+
+// Records messages at the given clock times, the recorder fills in any pauses
+// longer than half a second on its own.
+static void record(TEST_PARAMS)
+{
+    DP_RecordingMarkersStep steps[] = {
+        {0, DP_msg_canvas_resize_new(USER, 0, 64, 64, 0)},
+        {100, DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                           5)},
+        {600, fill(0)},
+        {1101, marker("start of inking")},
+        {1200, fill(8)},
+        {101200, marker("colors")},
+        {101300, fill(16)},
+    };
+
+    long long time_ms = 0;
+    DP_Output *output = DP_file_output_new_from_path(RECORDING_PATH);
+    FATAL(NOT_NULL_OK(output, "open %s", RECORDING_PATH));
+    DP_Recorder *r =
+        DP_recorder_new_inc(DP_RECORDER_TYPE_BINARY,
+                            DP_recorder_header_new(NULL), NULL, get_time,
+                            &time_ms, output);
+    FATAL(NOT_NULL_OK(r, "make recorder"));
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(steps); ++i) {
+        time_ms = steps[i].time_ms;
+        OK(DP_recorder_message_push_noinc(r, steps[i].msg),
+           "record message %zu", i);
+    }
+    char *error;
+    DP_recorder_free_join(r, &error);
+    OK(!error, "recorder finished without error (%s)", error ? error : "");
+    DP_free(error);
+}
+
+static DP_Player *open_player(TEST_PARAMS)
+{
+    DP_Player *player =
+        DP_player_new(DP_PLAYER_TYPE_BINARY, RECORDING_PATH,
+                      DP_file_input_new_from_path(RECORDING_PATH), NULL);
+    FATAL(NOT_NULL_OK(player, "open %s", RECORDING_PATH));
+    return player;
+}
+
+// Markers don't depend on snapshots, the final entry is enough.
+static bool should_snapshot(DP_UNUSED void *user, DP_UNUSED long long messages,
+                            DP_UNUSED size_t bytes)
+{
+    return false;
+}
+
+
+static void recorder_inserts_intervals(TEST_PARAMS)
+{
+    record(TEST_ARGS);
+    DP_Player *player = open_player(TEST_ARGS);
+    int intervals[4];
+    int interval_count = 0;
+    int marker_count = 0;
+    DP_Message *msg;
+    while (DP_player_step_raw(player, &msg) == DP_PLAYER_SUCCESS) {
+        DP_MessageType type = DP_message_type(msg);
+        if (type == DP_MSG_INTERVAL) {
+            int msecs = DP_msg_interval_msecs(DP_message_internal(msg));
+            if (interval_count < (int)DP_ARRAY_LENGTH(intervals)) {
+                intervals[interval_count] = msecs;
+            }
+            ++interval_count;
+        }
+        else if (type == DP_MSG_MARKER) {
+            ++marker_count;
+        }
+        DP_message_decref(msg);
+    }
+    DP_player_free(player);
+
+    // Gaps of 100ms and exactly 500ms are too short to get an interval.
+    FATAL(INT_EQ_OK(interval_count, 2, "two pauses long enough to record"));
+    INT_EQ_OK(intervals[0], 501, "interval just above the threshold");
+    INT_EQ_OK(intervals[1], UINT16_MAX, "long pause is clamped");
+    INT_EQ_OK(marker_count, 2, "markers are recorded");
+}
+
+static void markers_listed_from_index(TEST_PARAMS)
+{
+    record(TEST_ARGS);
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Player *player = open_player(TEST_ARGS);
+    FATAL(OK(DP_player_index_build(player, dc, should_snapshot, NULL, NULL),
+             "build index (error: %s)", DP_error()));
+    DP_player_free(player);
+
+    // A fresh player that hasn't stepped through anything yet.
+    player = open_player(TEST_ARGS);
+    FATAL(OK(DP_player_index_load(player), "load index (error: %s)",
+             DP_error()));
+    FATAL(UINT_EQ_OK(DP_player_index_marker_count(player), 2u,
+                     "index has both markers"));
+
+    // The recorder starts off with a feature access message of its own and
+    // puts intervals in front of messages after a pause, hence the offsets.
+    DP_PlayerIndexMarker first = DP_player_index_marker_at(player, 0);
+    STR_EQ_OK(first.text, "start of inking", "first marker text");
+    INT_EQ_OK(first.message_index, 5, "first marker position");
+    INT_EQ_OK(first.elapsed_ms, 501, "first marker time");
+
+    DP_PlayerIndexMarker second = DP_player_index_marker_at(player, 1);
+    STR_EQ_OK(second.text, "colors", "second marker text");
+    INT_EQ_OK(second.message_index, 8, "second marker position");
+    INT_EQ_OK(second.elapsed_ms, 501 + UINT16_MAX, "second marker time");
+
+    INT_EQ_OK(DP_player_position(player), 0, "nothing was played back");
+
+    DP_Message *msg;
+    int markers_played = 0;
+    while (DP_player_step(player, &msg) == DP_PLAYER_SUCCESS) {
+        if (DP_message_type(msg) == DP_MSG_MARKER) {
+            ++markers_played;
+        }
+        DP_message_decref(msg);
+    }
+    INT_EQ_OK(markers_played, 0, "playback still doesn't emit markers");
+
+    DP_player_free(player);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(recorder_inserts_intervals);
+    REGISTER_TEST(markers_listed_from_index);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/file.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpengine/player.h>
+#include <dpengine/render_frames.h>
+#include <dpmsg/binary_writer.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <parson.h>
+
+
+#define WIDTH    64
+#define HEIGHT   16
+#define COLUMN   16
+#define LAYER_ID 257
+#define USER     1
+#define RED      0xffff0000u
+#define GREEN    0xff00ff00u
+#define BLUE     0xff0000ffu
+#define WHITE    0xffffffffu
+#define NONE     0x00000000u
+
+#define PNG_PREFIX "test/tmp/render_frames_"
+
+typedef struct DP_RenderFramesTestState {
+    int count;
+    int duplicates;
+    long long positions[16];
+    DP_Image *last;
+} DP_RenderFramesTestState;
+
+static DP_Message *fill_column(int column, uint32_t color)
+{
+    return DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(column * COLUMN), 0, COLUMN,
+                                HEIGHT, color);
+}
+
+// Message indexes are in the comments.
+static DP_Player *make_player(TEST_PARAMS)
+{
+    DP_Message *msgs[] = {
+        DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0), // 1
+        DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                     5), // 2
+        DP_msg_interval_new(USER, 100),  // 3
+        fill_column(0, RED),             // 4
+        DP_msg_interval_new(USER, 100),  // 5
+        DP_msg_undo_point_new(USER),     // 6
+        fill_column(1, GREEN),           // 7
+        DP_msg_interval_new(USER, 1000), // 8
+        fill_column(2, BLUE),            // 9
+        DP_msg_interval_new(USER, 100),  // 10
+        fill_column(3, WHITE),           // 11
+    };
+
+    void **buffer_ptr;
+    size_t *size_ptr;
+    DP_BinaryWriter *bw = DP_binary_writer_new(
+        DP_mem_output_new(1024, false, &buffer_ptr, &size_ptr));
+    JSON_Value *header_value = json_value_init_object();
+    json_object_set_string(json_value_get_object(header_value), "version",
+                           DP_PROTOCOL_VERSION);
+    OK(DP_binary_writer_write_header(bw, json_value_get_object(header_value)),
+       "write recording header");
+    json_value_free(header_value);
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(msgs); ++i) {
+        if (DP_binary_writer_write_message(bw, msgs[i]) == 0) {
+            FAIL("write recording message %zu", i + 1);
+        }
+        DP_message_decref(msgs[i]);
+    }
+    // The pointers go away with the writer, the buffer itself doesn't.
+    void *buffer = *buffer_ptr;
+    size_t size = *size_ptr;
+    DP_binary_writer_free(bw);
+
+    DP_Player *player =
+        DP_player_new(DP_PLAYER_TYPE_BINARY, NULL,
+                      DP_mem_input_new_free_on_close(buffer, size), NULL);
+    FATAL(NOT_NULL_OK(player, "open recording"));
+    return player;
+}
+
+static DP_RenderFramesOptions make_options(DP_RenderFramesEvery every,
+                                           long long step)
+{
+    return (DP_RenderFramesOptions){
+        every, step, 0, 0, {0, 0, -1, -1}, false, 0, -1, false};
+}
+
+static bool on_frame(void *user, DP_Image *img, long long position,
+                     bool duplicate)
+{
+    DP_RenderFramesTestState *state = user;
+    if (state->count < (int)DP_ARRAY_LENGTH(state->positions)) {
+        state->positions[state->count] = position;
+    }
+    ++state->count;
+    if (duplicate) {
+        ++state->duplicates;
+    }
+    DP_image_free(state->last);
+    state->last = DP_image_new_subimage(img, 0, 0, DP_image_width(img),
+                                        DP_image_height(img));
+    return true;
+}
+
+static DP_RenderFramesTestState render(TEST_PARAMS,
+                                       const DP_RenderFramesOptions *options)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_RenderFramesTestState state = {0, 0, {0}, NULL};
+    OK(DP_render_frames(make_player(TEST_ARGS), dc, options, on_frame, &state),
+       "render frames (error: %s)", DP_error());
+    DP_draw_context_free(dc);
+    return state;
+}
+
+static uint32_t checksum(DP_Image *img)
+{
+    uint32_t hash = 2166136261u;
+    int count = DP_image_width(img) * DP_image_height(img);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    for (int i = 0; i < count; ++i) {
+        hash = (hash ^ pixels[i].color) * 16777619u;
+    }
+    return hash;
+}
+
+static uint32_t expected_final_checksum(void)
+{
+    uint32_t colors[] = {RED, GREEN, BLUE, WHITE};
+    DP_Image *img = DP_image_new(WIDTH, HEIGHT);
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_image_pixel_at_set(img, x, y,
+                                  (DP_Pixel8){colors[x / COLUMN]});
+        }
+    }
+    uint32_t hash = checksum(img);
+    DP_image_free(img);
+    return hash;
+}
+
+static void check_final_frame(TEST_PARAMS, DP_RenderFramesTestState *state)
+{
+    if (NOT_NULL_OK(state->last, "got a final frame")) {
+        INT_EQ_OK(DP_image_width(state->last), WIDTH, "final frame width");
+        INT_EQ_OK(DP_image_height(state->last), HEIGHT, "final frame height");
+        UINT_EQ_OK(checksum(state->last), expected_final_checksum(),
+                   "final frame checksum");
+    }
+}
+
+
+static void render_frames_every_messages(TEST_PARAMS)
+{
+    DP_RenderFramesOptions options =
+        make_options(DP_RENDER_FRAMES_EVERY_MESSAGES, 2);
+    DP_RenderFramesTestState state = render(TEST_ARGS, &options);
+    // The interval and undo point between 4 and 6 don't change anything.
+    FATAL(INT_EQ_OK(state.count, 5, "frame count"));
+    INT_EQ_OK(state.duplicates, 0, "no duplicates");
+    INT_EQ_OK(state.positions[0], 2, "blank canvas frame");
+    INT_EQ_OK(state.positions[1], 4, "red frame");
+    INT_EQ_OK(state.positions[2], 8, "green frame");
+    INT_EQ_OK(state.positions[3], 10, "blue frame");
+    INT_EQ_OK(state.positions[4], 11, "final frame at the end");
+    check_final_frame(TEST_ARGS, &state);
+    DP_image_free(state.last);
+}
+
+static void render_frames_every_ms_duplicates(TEST_PARAMS)
+{
+    DP_RenderFramesOptions options =
+        make_options(DP_RENDER_FRAMES_EVERY_MS, 100);
+    options.duplicate_unchanged = true;
+    DP_RenderFramesTestState state = render(TEST_ARGS, &options);
+    // Red, green, nine frames through the pause, blue and the final white.
+    INT_EQ_OK(state.count, 13, "frame count");
+    INT_EQ_OK(state.duplicates, 9, "pause is filled with duplicates");
+    check_final_frame(TEST_ARGS, &state);
+    DP_image_free(state.last);
+}
+
+static void render_frames_every_ms_skip_pauses(TEST_PARAMS)
+{
+    DP_RenderFramesOptions options =
+        make_options(DP_RENDER_FRAMES_EVERY_MS, 100);
+    options.duplicate_unchanged = true;
+    options.skip_pauses = true;
+    DP_RenderFramesTestState state = render(TEST_ARGS, &options);
+    INT_EQ_OK(state.count, 4, "one frame per change");
+    INT_EQ_OK(state.duplicates, 0, "no duplicates");
+    check_final_frame(TEST_ARGS, &state);
+    DP_image_free(state.last);
+}
+
+static void render_frames_crop_and_scale(TEST_PARAMS)
+{
+    DP_RenderFramesOptions options =
+        make_options(DP_RENDER_FRAMES_EVERY_MESSAGES, 100);
+    options.crop = DP_rect_make(COLUMN, 0, COLUMN * 2, HEIGHT);
+    options.width = 8;
+    options.height = 8;
+    DP_RenderFramesTestState state = render(TEST_ARGS, &options);
+    FATAL(INT_EQ_OK(state.count, 1, "single frame"));
+    DP_Image *img = state.last;
+    INT_EQ_OK(DP_image_width(img), 8, "scaled width");
+    INT_EQ_OK(DP_image_height(img), 8, "scaled height");
+    UINT_EQ_OK(DP_image_pixel_at(img, 1, 4).color, GREEN,
+               "left half is green");
+    UINT_EQ_OK(DP_image_pixel_at(img, 6, 4).color, BLUE,
+               "right half is blue");
+    UINT_EQ_OK(DP_image_pixel_at(img, 4, 0).color, NONE,
+               "letterboxed on top");
+    UINT_EQ_OK(DP_image_pixel_at(img, 4, 7).color, NONE,
+               "letterboxed on bottom");
+    DP_image_free(img);
+}
+
+static void render_frames_start_and_end(TEST_PARAMS)
+{
+    DP_RenderFramesOptions options =
+        make_options(DP_RENDER_FRAMES_EVERY_MESSAGES, 1);
+    options.start = 6;
+    options.end = 9;
+    DP_RenderFramesTestState state = render(TEST_ARGS, &options);
+    FATAL(INT_EQ_OK(state.count, 2, "frame count"));
+    INT_EQ_OK(state.positions[0], 7, "green frame");
+    INT_EQ_OK(state.positions[1], 9, "blue frame at the end");
+    UINT_EQ_OK(DP_image_pixel_at(state.last, COLUMN * 3 + 1, 1).color, NONE,
+               "stopped before white");
+    DP_image_free(state.last);
+}
+
+static void render_frames_to_png(TEST_PARAMS)
+{
+    DP_RenderFramesOptions options =
+        make_options(DP_RENDER_FRAMES_EVERY_MESSAGES, 4);
+    DP_DrawContext *dc = DP_draw_context_new();
+    OK(DP_render_frames_to_png(make_player(TEST_ARGS), dc, &options,
+                               PNG_PREFIX),
+       "render frames to png (error: %s)", DP_error());
+    DP_draw_context_free(dc);
+
+    DP_Input *input = DP_file_input_new_from_path(PNG_PREFIX "000003.png");
+    if (NOT_NULL_OK(input, "third frame written")) {
+        DP_Image *img = DP_image_read_png(input);
+        DP_input_free(input);
+        if (NOT_NULL_OK(img, "read third frame")) {
+            UINT_EQ_OK(checksum(img), expected_final_checksum(),
+                       "third frame is the final canvas");
+        }
+        DP_image_free(img);
+    }
+    NOK(DP_file_exists(PNG_PREFIX "000004.png"), "no fourth frame");
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(render_frames_every_messages);
+    REGISTER_TEST(render_frames_every_ms_duplicates);
+    REGISTER_TEST(render_frames_every_ms_skip_pauses);
+    REGISTER_TEST(render_frames_crop_and_scale);
+    REGISTER_TEST(render_frames_start_and_end);
+    REGISTER_TEST(render_frames_to_png);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
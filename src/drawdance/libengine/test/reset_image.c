@@ -0,0 +1,428 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/compress.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/snapshots.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    (DP_TILE_SIZE * 5)
+#define HEIGHT   (DP_TILE_SIZE * 3)
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+#define FLAT_ID  260
+#define USER_A   1
+#define USER_B   2
+
+typedef struct Messages {
+    int count;
+    DP_Message *msgs[1024];
+} Messages;
+
+static void push_message(void *user, DP_Message *msg)
+{
+    Messages *m = user;
+    if (m->count < (int)DP_ARRAY_LENGTH(m->msgs)) {
+        m->msgs[m->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void messages_dispose(Messages *m)
+{
+    for (int i = 0; i < m->count; ++i) {
+        DP_message_decref(m->msgs[i]);
+    }
+}
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+// Applies the messages like the paint engine would.
+static DP_CanvasHistory *apply(TEST_PARAMS, DP_DrawContext *dc, Messages *m)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    for (int i = 0; i < m->count; ++i) {
+        DP_Message *msg = m->msgs[i];
+        if (DP_message_type(msg) == DP_MSG_UNDO_DEPTH) {
+            DP_canvas_history_undo_depth_limit_set(
+                ch, dc,
+                DP_msg_undo_depth_depth(DP_msg_undo_depth_cast(msg)));
+        }
+        else {
+            handle(TEST_ARGS, ch, dc, DP_message_incref(msg));
+        }
+    }
+    return ch;
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static void set_tile_data(size_t size, unsigned char *out, void *user)
+{
+    memcpy(out, user, size);
+}
+
+static unsigned char *get_output_buffer(size_t size, void *user)
+{
+    unsigned char **buffer = user;
+    *buffer = DP_malloc(size);
+    return *buffer;
+}
+
+// Noise hardly compresses, so every copy of it that gets encoded shows up in
+// the size of the reset image.
+static unsigned char *make_noise_tile(uint32_t seed, size_t *out_size)
+{
+    DP_Pixel8 *pixels = DP_malloc(sizeof(*pixels) * DP_TILE_LENGTH);
+    uint32_t x = seed;
+    for (int i = 0; i < DP_TILE_LENGTH; ++i) {
+        // xorshift32, so that the tiles are the same every time.
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        pixels[i] = DP_pixel8_premultiply((DP_UPixel8){.color = x});
+    }
+    unsigned char *buffer;
+    *out_size = DP_compress_deflate((const unsigned char *)pixels,
+                                    sizeof(*pixels) * DP_TILE_LENGTH,
+                                    get_output_buffer, &buffer);
+    DP_free(pixels);
+    return buffer;
+}
+
+static DP_Message *put_solid_tile(uint16_t layer_id, uint8_t sublayer_id,
+                                  uint16_t col, uint16_t row, uint16_t repeat,
+                                  uint32_t color)
+{
+    return DP_msg_put_tile_new(USER_A, layer_id, sublayer_id, col, row, repeat,
+                               set_color, 4, &color);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int layer_id, int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(USER_A, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(USER_A, 0, WIDTH, HEIGHT, 0));
+    uint32_t background = 0xffeeddcc;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_background_new(USER_A, set_color, 4, &background));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER_A, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                        5));
+    return ch;
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 3, 5, 170, 20, 0xffff0000);
+    fill_rect(TEST_ARGS, ch, dc, LAYER_ID, 100, 60, 145, 97, 0x800000ff);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(USER_A, LAYER_ID, 3, 0, 128,
+                                       DP_BLEND_MODE_MULTIPLY));
+    handle(TEST_ARGS, ch, dc, put_solid_tile(LAYER_ID, 3, 1, 1, 2, 0xff00ff00));
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER_A, GROUP_ID, 0, LAYER_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER_A, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    fill_rect(TEST_ARGS, ch, dc, CHILD_ID, 60, 30, 9, 50, 0xff123456);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(USER_A, CHILD_ID, 0,
+                                       DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR,
+                                       200, DP_BLEND_MODE_SCREEN));
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_create_new(USER_A, 0x101, 10, 20, 100, 50));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_edit_new(
+               USER_A, 0x101, 0x80ffffff,
+               DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM
+                   | DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW,
+               0, "Hello", 5));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+// Put tile messages only carry 8 bits per channel, so translucent strokes
+// blended on top of each other wouldn't survive the trip. Opaque ones will.
+static void act(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                unsigned int user, int i)
+{
+    handle(TEST_ARGS, ch, dc, DP_msg_undo_point_new(user));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(
+               user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+               DP_int_to_uint32(i * 37 % (WIDTH - 40)),
+               DP_int_to_uint32(i * 13 % (HEIGHT - 40)),
+               40, 40, 0xff000000u | DP_int_to_uint32(i * 0x10305)));
+}
+
+static bool undo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user,
+                 bool redo)
+{
+    DP_Message *msg = DP_msg_undo_new(user, 0, redo);
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+
+static void reset_image_round_trips(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+
+    Messages m = {0};
+    DP_reset_image_build(cs, USER_B, push_message, &m);
+    DP_CanvasHistory *ch = apply(TEST_ARGS, dc, &m);
+    OK(checksum(ch) == DP_canvas_state_checksum(cs),
+       "reset image rebuilds the canvas exactly");
+
+    DP_canvas_history_free(ch);
+    messages_dispose(&m);
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+static void flat_layer_uses_tile_runs(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER_A, FLAT_ID, 0, 0, 0, 0, "Flat",
+                                        4));
+    fill_rect(TEST_ARGS, ch, dc, FLAT_ID, 0, 0, WIDTH, HEIGHT, 0xff336699);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+
+    Messages m = {0};
+    DP_reset_image_build(cs, USER_B, push_message, &m);
+    int put_tiles = 0;
+    int repeat = -1;
+    for (int i = 0; i < m.count; ++i) {
+        if (DP_message_type(m.msgs[i]) == DP_MSG_PUT_TILE) {
+            DP_MsgPutTile *mpt = DP_msg_put_tile_cast(m.msgs[i]);
+            if (DP_msg_put_tile_layer(mpt) == FLAT_ID) {
+                ++put_tiles;
+                repeat = DP_msg_put_tile_repeat(mpt);
+            }
+        }
+    }
+    INT_EQ_OK(put_tiles, 1, "flat layer is a single put tile message");
+    INT_EQ_OK(repeat, 5 * 3 - 1, "which repeats over the whole layer");
+
+    DP_CanvasHistory *applied = apply(TEST_ARGS, dc, &m);
+    OK(checksum(applied) == DP_canvas_state_checksum(cs),
+       "tile runs rebuild the canvas exactly");
+
+    DP_canvas_history_free(applied);
+    messages_dispose(&m);
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void compact_keeps_undoable_tail(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_canvas_history_undo_depth_limit_set(ch, dc, 4);
+    for (int i = 0; i < 12; ++i) {
+        act(TEST_ARGS, ch, dc, i % 3 == 0 ? USER_B : USER_A, i);
+    }
+    OK(undo(ch, dc, USER_A, false), "A undoes before compacting");
+
+    Messages m = {0};
+    DP_canvas_history_compact(ch, USER_B, push_message, &m);
+    DP_CanvasHistory *compacted = apply(TEST_ARGS, dc, &m);
+    INT_EQ_OK(DP_canvas_history_undo_depth_limit(compacted), 4,
+              "compacted history keeps the undo depth");
+    OK(checksum(compacted) == checksum(ch),
+       "compacted history gives the same canvas");
+
+    OK(undo(compacted, dc, USER_A, true), "A redoes in compacted history");
+    OK(undo(ch, dc, USER_A, true), "A redoes in original history");
+    OK(checksum(compacted) == checksum(ch), "redo gives the same canvas");
+    // Both histories have the same undoable tail, so each undo has to either
+    // work in both of them or in neither.
+    for (int i = 0; i < 6; ++i) {
+        unsigned int user = i % 2 == 0 ? USER_A : USER_B;
+        bool ok = undo(ch, dc, user, false);
+        OK(undo(compacted, dc, user, false) == ok,
+           "undo %d does the same in both histories", i);
+        OK(checksum(compacted) == checksum(ch),
+           "undo %d gives the same canvas", i);
+    }
+
+    DP_canvas_history_free(compacted);
+    messages_dispose(&m);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void put_noise(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      unsigned int user, int layer_id, int sublayer_id, int i,
+                      unsigned char *data, size_t size)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_put_tile_new(user, DP_int_to_uint16(layer_id),
+                               DP_int_to_uint8(sublayer_id),
+                               DP_int_to_uint16(i % (WIDTH / DP_TILE_SIZE)),
+                               DP_int_to_uint16(i / (WIDTH / DP_TILE_SIZE)), 0,
+                               set_tile_data, size, data));
+}
+
+static unsigned char *compress_tile(DP_Tile *t, size_t *out_size)
+{
+    DP_Pixel8 *pixel_buffer = DP_malloc(sizeof(*pixel_buffer) * DP_TILE_LENGTH);
+    unsigned char *buffer = NULL;
+    *out_size = DP_tile_compress(t, pixel_buffer, get_output_buffer, &buffer);
+    DP_free(pixel_buffer);
+    return buffer;
+}
+
+static DP_Tile *layer_tile_at(DP_CanvasState *cs, int layer_id, int col,
+                              int row)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return lre ? DP_layer_content_tile_at_noinc(
+                     DP_layer_routes_entry_content(lre, cs), col, row)
+               : NULL;
+}
+
+static void identical_tiles_reuse_images(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER_A, FLAT_ID, 0, 0, 0, 0, "Flat",
+                                        4));
+
+    // Two patterns alternating over every tile of one layer, so they don't
+    // make runs, with some more of them on another layer and a sublayer.
+    size_t size_a, size_b;
+    unsigned char *noise_a = make_noise_tile(0x1234567u, &size_a);
+    unsigned char *noise_b = make_noise_tile(0x7654321u, &size_b);
+    int tile_total = (WIDTH / DP_TILE_SIZE) * (HEIGHT / DP_TILE_SIZE);
+    for (int i = 0; i < tile_total; ++i) {
+        put_noise(TEST_ARGS, ch, dc, USER_A, LAYER_ID, 0, i,
+                  i % 2 == 0 ? noise_a : noise_b,
+                  i % 2 == 0 ? size_a : size_b);
+    }
+    put_noise(TEST_ARGS, ch, dc, USER_A, FLAT_ID, 0, 2, noise_a, size_a);
+    put_noise(TEST_ARGS, ch, dc, USER_A, FLAT_ID, 0, 10, noise_b, size_b);
+    put_noise(TEST_ARGS, ch, dc, USER_A, FLAT_ID, 0, 14, noise_b, size_b);
+    put_noise(TEST_ARGS, ch, dc, USER_A, FLAT_ID, 3, 6, noise_a, size_a);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(USER_A, FLAT_ID, 3, 0, 128,
+                                       DP_BLEND_MODE_NORMAL));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+
+    Messages m = {0};
+    DP_reset_image_build(cs, USER_B, push_message, &m);
+    int put_tiles = 0;
+    int full_images = 0;
+    for (int i = 0; i < m.count; ++i) {
+        DP_MsgPutTile *mpt = DP_msg_put_tile_cast(m.msgs[i]);
+        if (!mpt || DP_msg_put_tile_sublayer(mpt) != 0) {
+            continue;
+        }
+        ++put_tiles;
+        size_t size, expected_size;
+        const unsigned char *image = DP_msg_put_tile_image(mpt, &size);
+        unsigned char *expected = compress_tile(
+            layer_tile_at(cs, DP_msg_put_tile_layer(mpt),
+                          DP_msg_put_tile_col(mpt), DP_msg_put_tile_row(mpt)),
+            &expected_size);
+        if (size == expected_size && memcmp(image, expected, size) == 0) {
+            ++full_images;
+        }
+        DP_free(expected);
+    }
+    INT_EQ_OK(put_tiles, tile_total + 3, "every tile gets put");
+    INT_EQ_OK(full_images, put_tiles,
+              "every put tile carries its whole compressed image");
+
+    DP_CanvasHistory *applied = apply(TEST_ARGS, dc, &m);
+    OK(checksum(applied) == DP_canvas_state_checksum(cs),
+       "reused images rebuild the canvas exactly");
+
+    // Other clients only know compressed images and solid colors, so there's
+    // no size that refers to a tile elsewhere instead.
+    DP_Message *bad = DP_msg_put_tile_new(USER_A, FLAT_ID, 0, 0, 0, 0,
+                                          set_tile_data, 6,
+                                          (unsigned char[]){1, 1, 0, 1, 0, 0});
+    NOK(DP_canvas_history_handle(applied, dc, bad),
+        "six byte image isn't taken as a reference");
+    DP_message_decref(bad);
+
+    DP_canvas_history_free(applied);
+    messages_dispose(&m);
+    DP_free(noise_b);
+    DP_free(noise_a);
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(reset_image_round_trips);
+    REGISTER_TEST(flat_layer_uses_tile_runs);
+    REGISTER_TEST(identical_tiles_reuse_images);
+    REGISTER_TEST(compact_keeps_undoable_tail);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
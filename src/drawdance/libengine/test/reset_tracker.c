@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpengine/reset_tracker.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH      256
+#define HEIGHT     64
+#define LAYER_ID   257
+#define REMOTE     1
+#define LOCAL      2
+#define RED        0xffff0000u
+#define GREEN      0xff00ff00u
+#define BLUE       0xff0000ffu
+#define NONE       0x00000000u
+#define MAX_STATES 16
+
+typedef struct Session {
+    DP_DrawContext *dc;
+    DP_CanvasHistory *ch;
+    DP_ResetTracker *rt;
+    int state_count;
+    DP_ResetTrackerState states[MAX_STATES];
+} Session;
+
+static void on_state_changed(void *user, DP_ResetTrackerState state)
+{
+    Session *s = user;
+    if (s->state_count < MAX_STATES) {
+        s->states[s->state_count++] = state;
+    }
+}
+
+static void remote(TEST_PARAMS, Session *s, DP_Message *msg)
+{
+    OK(DP_reset_tracker_handle(s->rt, s->dc, msg), "handle remote %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void local(TEST_PARAMS, Session *s, DP_Message *msg)
+{
+    OK(DP_reset_tracker_handle_local(s->rt, s->dc, msg), "handle local %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static DP_Message *fill_column(unsigned int context_id, int column,
+                               uint32_t color)
+{
+    return DP_msg_fill_rect_new(context_id, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(column * 64), 0, 64, HEIGHT,
+                                color);
+}
+
+static void setup_canvas(TEST_PARAMS, Session *s)
+{
+    remote(TEST_ARGS, s, DP_msg_canvas_resize_new(0, 0, WIDTH, HEIGHT, 0));
+    remote(TEST_ARGS, s,
+           DP_msg_layer_tree_create_new(0, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+}
+
+static void session_init(TEST_PARAMS, Session *s)
+{
+    *s = (Session){0};
+    s->dc = DP_draw_context_new();
+    s->ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    s->rt = DP_reset_tracker_new(s->ch, on_state_changed, s);
+    setup_canvas(TEST_ARGS, s);
+}
+
+static void session_dispose(Session *s)
+{
+    DP_reset_tracker_free(s->rt);
+    DP_canvas_history_free(s->ch);
+    DP_draw_context_free(s->dc);
+}
+
+static uint32_t column_color(Session *s, int column)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(s->ch);
+    DP_Image *img =
+        DP_canvas_state_to_flat_image(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL,
+                                      NULL);
+    uint32_t color =
+        img ? DP_image_pixel_at(img, column * 64 + 32, HEIGHT / 2).color : NONE;
+    DP_image_free(img);
+    DP_canvas_state_decref(cs);
+    return color;
+}
+
+#define STATE_IS(S, STATE, NAME)                                  \
+    INT_EQ_OK((int)DP_reset_tracker_state((S)->rt), (int)(STATE), \
+              "state is " NAME)
+
+
+static void reset_with_unacknowledged_strokes(TEST_PARAMS)
+{
+    Session s;
+    session_init(TEST_ARGS, &s);
+    remote(TEST_ARGS, &s, DP_msg_undo_point_new(REMOTE));
+    remote(TEST_ARGS, &s, fill_column(REMOTE, 3, RED));
+
+    // Local stroke the server hasn't echoed yet.
+    local(TEST_ARGS, &s, DP_msg_undo_point_new(LOCAL));
+    local(TEST_ARGS, &s, fill_column(LOCAL, 0, RED));
+    INT_EQ_OK(DP_canvas_history_local_fork_count(s.ch), 2,
+              "local stroke is in the fork");
+
+    DP_reset_tracker_begin(s.rt);
+    STATE_IS(&s, DP_RESET_TRACKER_PENDING, "pending");
+    INT_EQ_OK(s.state_count, 1, "state change is reported");
+    UINT_EQ_OK(column_color(&s, 0), RED, "local stroke is still visible");
+
+    remote(TEST_ARGS, &s, fill_column(REMOTE, 1, BLUE));
+    UINT_EQ_OK(column_color(&s, 1), NONE,
+               "message before the snapshot is discarded");
+
+    // Local stroke made while the reset is in progress.
+    DP_Message *during_undo_point = DP_msg_undo_point_new(LOCAL);
+    DP_Message *during_fill = fill_column(LOCAL, 2, GREEN);
+    local(TEST_ARGS, &s, DP_message_incref(during_undo_point));
+    local(TEST_ARGS, &s, DP_message_incref(during_fill));
+    UINT_EQ_OK(column_color(&s, 2), GREEN,
+               "local stroke during the reset is drawn right away");
+
+    // The snapshot has the earlier local stroke baked in.
+    setup_canvas(TEST_ARGS, &s);
+    STATE_IS(&s, DP_RESET_TRACKER_RECEIVING_SNAPSHOT, "receiving snapshot");
+    remote(TEST_ARGS, &s, fill_column(0, 0, RED));
+    remote(TEST_ARGS, &s, DP_msg_undo_point_new(REMOTE));
+    remote(TEST_ARGS, &s, fill_column(REMOTE, 3, BLUE));
+    UINT_EQ_OK(column_color(&s, 3), RED,
+               "old canvas is shown while receiving the snapshot");
+
+    long long change_count = DP_canvas_history_change_count(s.ch);
+    OK(DP_reset_tracker_end(s.rt, s.dc), "reset ends");
+    STATE_IS(&s, DP_RESET_TRACKER_NORMAL, "normal");
+    INT_EQ_OK(s.state_count, 3, "all state changes are reported");
+    OK(DP_canvas_history_change_count(s.ch) == change_count + 1,
+       "canvas is swapped in one go");
+    UINT_EQ_OK(column_color(&s, 3), BLUE, "snapshot is swapped in");
+    UINT_EQ_OK(column_color(&s, 0), RED, "snapshot has the earlier stroke");
+    UINT_EQ_OK(column_color(&s, 1), NONE, "discarded message isn't there");
+    UINT_EQ_OK(column_color(&s, 2), GREEN,
+               "local stroke during the reset is kept on top");
+    INT_EQ_OK(DP_canvas_history_local_fork_count(s.ch), 2,
+              "only the stroke during the reset remains in the fork");
+
+    remote(TEST_ARGS, &s, during_undo_point);
+    remote(TEST_ARGS, &s, during_fill);
+    INT_EQ_OK(DP_canvas_history_local_fork_count(s.ch), 0,
+              "echoed stroke is acknowledged");
+    UINT_EQ_OK(column_color(&s, 2), GREEN, "acknowledged stroke stays");
+
+    remote(TEST_ARGS, &s, DP_msg_undo_new(REMOTE, 0, false));
+    UINT_EQ_OK(column_color(&s, 3), NONE,
+               "history from the snapshot can be undone");
+    UINT_EQ_OK(column_color(&s, 0), RED, "rest of the snapshot stays");
+
+    session_dispose(&s);
+}
+
+static void truncated_reset_keeps_canvas(TEST_PARAMS)
+{
+    Session s;
+    session_init(TEST_ARGS, &s);
+    remote(TEST_ARGS, &s, fill_column(REMOTE, 0, RED));
+
+    DP_reset_tracker_begin(s.rt);
+    NOK(DP_reset_tracker_end(s.rt, s.dc), "reset without snapshot fails");
+    STATE_IS(&s, DP_RESET_TRACKER_NORMAL, "normal after failed reset");
+    UINT_EQ_OK(column_color(&s, 0), RED, "canvas is kept after failed reset");
+
+    DP_reset_tracker_begin(s.rt);
+    setup_canvas(TEST_ARGS, &s);
+    remote(TEST_ARGS, &s, fill_column(0, 1, BLUE));
+    DP_reset_tracker_abort(s.rt);
+    STATE_IS(&s, DP_RESET_TRACKER_NORMAL, "normal after abort");
+    UINT_EQ_OK(column_color(&s, 0), RED, "canvas is kept after abort");
+    UINT_EQ_OK(column_color(&s, 1), NONE, "partial snapshot is thrown away");
+
+    remote(TEST_ARGS, &s, fill_column(REMOTE, 2, GREEN));
+    UINT_EQ_OK(column_color(&s, 2), GREEN, "messages are handled normally");
+
+    int expected[] = {
+        DP_RESET_TRACKER_PENDING,
+        DP_RESET_TRACKER_NORMAL,
+        DP_RESET_TRACKER_PENDING,
+        DP_RESET_TRACKER_RECEIVING_SNAPSHOT,
+        DP_RESET_TRACKER_NORMAL,
+    };
+    int expected_count = DP_size_to_int(DP_ARRAY_LENGTH(expected));
+    if (INT_EQ_OK(s.state_count, expected_count, "state change count")) {
+        for (int i = 0; i < expected_count; ++i) {
+            INT_EQ_OK((int)s.states[i], expected[i], "state change %d", i);
+        }
+    }
+
+    session_dispose(&s);
+}
+
+static void restarted_reset_uses_new_snapshot(TEST_PARAMS)
+{
+    Session s;
+    session_init(TEST_ARGS, &s);
+    remote(TEST_ARGS, &s, fill_column(REMOTE, 0, RED));
+
+    DP_reset_tracker_begin(s.rt);
+    setup_canvas(TEST_ARGS, &s);
+    remote(TEST_ARGS, &s, fill_column(0, 1, BLUE));
+
+    // Cut off, the server starts over.
+    DP_reset_tracker_begin(s.rt);
+    STATE_IS(&s, DP_RESET_TRACKER_PENDING, "pending again");
+    setup_canvas(TEST_ARGS, &s);
+    remote(TEST_ARGS, &s, fill_column(0, 2, GREEN));
+    OK(DP_reset_tracker_end(s.rt, s.dc), "restarted reset ends");
+
+    UINT_EQ_OK(column_color(&s, 0), NONE, "old canvas is replaced");
+    UINT_EQ_OK(column_color(&s, 1), NONE, "cut off snapshot isn't used");
+    UINT_EQ_OK(column_color(&s, 2), GREEN, "new snapshot is used");
+
+    session_dispose(&s);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(reset_with_unacknowledged_strokes);
+    REGISTER_TEST(truncated_reset_keeps_canvas);
+    REGISTER_TEST(restarted_reset_uses_new_snapshot);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/input.h>
+#include <dpengine/annotation.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/pixels.h>
+#include <dpengine/save.h>
+#include <dpengine/zip_archive.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define USER       1
+#define WIDTH      64
+#define HEIGHT     48
+#define LAYER_ID   0x101
+#define BELOW_ID   0x101
+#define ABOVE_ID   0x102
+#define SKIPPED_ID 0x103
+#define HALF_ID    0x104
+#define CANVAS     0xff0000ffu
+#define BELOW      0xff00ff00u
+#define ABOVE      0xffff0000u
+#define HALF       0x80ff0000u
+#define PNG_PATH   "test/tmp/save_annotations.png"
+#define ORA_PATH   "test/tmp/save_annotations.ora"
+
+typedef struct BakeRecord {
+    int count;
+    int ids[8];
+} BakeRecord;
+
+// Stand-in for the host's text rendering, just fills the whole annotation with
+// its premultiplied background color. The annotation with SKIPPED_ID gets left
+// out.
+static bool bake_annotation(void *user, DP_Annotation *a, unsigned char *out)
+{
+    BakeRecord *br = user;
+    int id = DP_annotation_id(a);
+    if (br->count < (int)DP_ARRAY_LENGTH(br->ids)) {
+        br->ids[br->count] = id;
+    }
+    ++br->count;
+    if (id == SKIPPED_ID) {
+        return false;
+    }
+
+    DP_Pixel8 *pixels = (DP_Pixel8 *)out;
+    int count = DP_annotation_width(a) * DP_annotation_height(a);
+    DP_Pixel8 pixel =
+        DP_pixel8_premultiply((DP_UPixel8){DP_annotation_background_color(a)});
+    for (int i = 0; i < count; ++i) {
+        pixels[i] = pixel;
+    }
+    return true;
+}
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void add_annotation(TEST_PARAMS, DP_CanvasHistory *ch,
+                           DP_DrawContext *dc, int id, int x, int y,
+                           uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_create_new(USER, DP_int_to_uint16(id), x, y, 24,
+                                        24));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_edit_new(USER, DP_int_to_uint16(id), color, 0, 0,
+                                      "text", 4));
+}
+
+// A filled canvas with two overlapping annotations, the one with ABOVE_ID
+// coming later in the list, one that the baking function skips and one with a
+// translucent background.
+static DP_CanvasState *make_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "", 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                WIDTH, HEIGHT, CANVAS));
+    add_annotation(TEST_ARGS, ch, dc, BELOW_ID, 4, 4, BELOW);
+    add_annotation(TEST_ARGS, ch, dc, ABOVE_ID, 16, 16, ABOVE);
+    add_annotation(TEST_ARGS, ch, dc, SKIPPED_ID, 40, 20, ABOVE);
+    add_annotation(TEST_ARGS, ch, dc, HALF_ID, 0, 30, HALF);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+static void check_pixels(TEST_PARAMS, DP_Image *img, bool baked,
+                         const char *title)
+{
+    if (!img) {
+        FAIL("%s image read (error: %s)", title, DP_error());
+        return;
+    }
+    UINT_EQ_OK(DP_image_pixel_at(img, 8, 8).color, baked ? BELOW : CANVAS,
+               "%s annotation below", title);
+    UINT_EQ_OK(DP_image_pixel_at(img, 20, 20).color, baked ? ABOVE : CANVAS,
+               "%s overlap has the later annotation on top", title);
+    UINT_EQ_OK(DP_image_pixel_at(img, 50, 30).color, CANVAS,
+               "%s skipped annotation is left out", title);
+    UINT_EQ_OK(DP_image_pixel_at(img, 60, 4).color, CANVAS,
+               "%s canvas outside of annotations", title);
+    DP_Pixel8 half = DP_image_pixel_at(img, 4, 44);
+    if (baked) {
+        OK(half.a == 0xff && half.r >= 0x7f && half.r <= 0x81 && half.g == 0
+               && half.b >= 0x7e && half.b <= 0x80,
+           "%s translucent annotation blended over canvas (got #%08x)", title,
+           half.color);
+    }
+    else {
+        UINT_EQ_OK(half.color, CANVAS, "%s translucent annotation left out",
+                   title);
+    }
+    DP_image_free(img);
+}
+
+static void check_bake_order(TEST_PARAMS, BakeRecord *br, const char *title)
+{
+    INT_EQ_OK(br->count, 4, "%s bakes each annotation once", title);
+    OK(br->ids[0] == BELOW_ID && br->ids[1] == ABOVE_ID
+           && br->ids[2] == SKIPPED_ID && br->ids[3] == HALF_ID,
+       "%s bakes annotations in list order", title);
+}
+
+
+static void save_annotations_flat(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+
+    BakeRecord br = {0, {0}};
+    FATAL(INT_EQ_OK(DP_save(cs, dc, DP_SAVE_IMAGE_PNG, PNG_PATH,
+                            bake_annotation, &br),
+                    DP_SAVE_RESULT_SUCCESS, "save %s", PNG_PATH));
+    check_bake_order(TEST_ARGS, &br, "png");
+    DP_Input *input = DP_file_input_new_from_path(PNG_PATH);
+    FATAL(NOT_NULL_OK(input, "open %s", PNG_PATH));
+    check_pixels(TEST_ARGS, DP_image_read_png(input), true, "png");
+    DP_input_free(input);
+
+    FATAL(INT_EQ_OK(DP_save(cs, dc, DP_SAVE_IMAGE_PNG, PNG_PATH, NULL, NULL),
+                    DP_SAVE_RESULT_SUCCESS, "save %s without annotations",
+                    PNG_PATH));
+    input = DP_file_input_new_from_path(PNG_PATH);
+    FATAL(NOT_NULL_OK(input, "open %s", PNG_PATH));
+    check_pixels(TEST_ARGS, DP_image_read_png(input), false,
+                 "png without annotations");
+    DP_input_free(input);
+
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+static DP_Image *read_merged_image(const char *path)
+{
+    DP_ZipReader *zr = DP_zip_reader_new(path);
+    if (!zr) {
+        return NULL;
+    }
+
+    DP_ZipReaderFile *zrf = DP_zip_reader_read_file(zr, "mergedimage.png");
+    DP_Image *img = NULL;
+    if (zrf) {
+        DP_Input *input =
+            DP_mem_input_new_keep_on_close(DP_zip_reader_file_content(zrf),
+                                           DP_zip_reader_file_size(zrf));
+        img = DP_image_read_png(input);
+        DP_input_free(input);
+        DP_zip_reader_file_free(zrf);
+    }
+
+    DP_zip_reader_free(zr);
+    return img;
+}
+
+static void save_annotations_ora_merged_image(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+
+    BakeRecord br = {0, {0}};
+    FATAL(INT_EQ_OK(DP_save(cs, dc, DP_SAVE_IMAGE_ORA, ORA_PATH,
+                            bake_annotation, &br),
+                    DP_SAVE_RESULT_SUCCESS, "save %s", ORA_PATH));
+    check_bake_order(TEST_ARGS, &br, "ora");
+    check_pixels(TEST_ARGS, read_merged_image(ORA_PATH), true,
+                 "ora merged image");
+
+    FATAL(INT_EQ_OK(DP_save(cs, dc, DP_SAVE_IMAGE_ORA, ORA_PATH, NULL, NULL),
+                    DP_SAVE_RESULT_SUCCESS, "save %s without annotations",
+                    ORA_PATH));
+    check_pixels(TEST_ARGS, read_merged_image(ORA_PATH), false,
+                 "ora merged image without annotations");
+
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(save_annotations_flat);
+    REGISTER_TEST(save_annotations_ora_merged_image);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,371 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/memory_usage.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    300
+#define HEIGHT   200
+#define LAYER_ID 257
+#define STEPS    60
+
+// Both histories get the same messages, one of them never packs anything.
+typedef struct Histories {
+    DP_CanvasHistory *packed;
+    DP_CanvasHistory *unpacked;
+    uint32_t random;
+} Histories;
+
+static uint32_t next_random(uint32_t *state)
+{
+    // xorshift32, so that failures are reproducible.
+    uint32_t x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    return x;
+}
+
+static int random_int(uint32_t *state, int min, int max)
+{
+    return min + (int)(next_random(state) % (uint32_t)(max - min + 1));
+}
+
+static void handle(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    bool packed_ok = DP_canvas_history_handle(h->packed, dc, msg);
+    bool unpacked_ok = DP_canvas_history_handle(h->unpacked, dc, msg);
+    OK(packed_ok && unpacked_ok, "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static bool undo_both(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                      unsigned int user, bool redo)
+{
+    DP_Message *msg = DP_msg_undo_new(user, 0, redo);
+    bool packed_ok = DP_canvas_history_handle(h->packed, dc, msg);
+    bool unpacked_ok = DP_canvas_history_handle(h->unpacked, dc, msg);
+    DP_message_decref(msg);
+    OK(packed_ok == unpacked_ok, "%s by user %u works the same in both",
+       redo ? "redo" : "undo", user);
+    return packed_ok;
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+static DP_MemoryUsageCounts history_memory_usage(DP_CanvasHistory *ch)
+{
+    DP_MemoryUsage *mu = DP_memory_usage_new();
+    DP_canvas_history_memory_usage_add(ch, mu);
+    DP_MemoryUsageCounts totals = DP_memory_usage_totals(mu);
+    DP_memory_usage_free(mu);
+    return totals;
+}
+
+static void set_classic_dabs(int count, DP_ClassicDab *dabs, void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(dabs, i, DP_int_to_int8(random_int(user, -80, 80)),
+                            DP_int_to_int8(random_int(user, -80, 80)),
+                            DP_int_to_uint16(random_int(user, 256, 40 * 256)),
+                            DP_int_to_uint8(random_int(user, 0, 255)),
+                            DP_int_to_uint8(random_int(user, 1, 255)));
+    }
+}
+
+// Soft dabs, so that the tiles they touch have gradients that compress well
+// but aren't uniform, which wouldn't be worth packing.
+static DP_Message *random_dabs(uint32_t *state, unsigned int user)
+{
+    uint32_t color = next_random(state) & 0xffffffu;
+    return DP_msg_draw_dabs_classic_new(
+        user, LAYER_ID, random_int(state, 0, WIDTH * 4),
+        random_int(state, 0, HEIGHT * 4), color, DP_BLEND_MODE_NORMAL,
+        set_classic_dabs, random_int(state, 1, 5), state);
+}
+
+static void init_histories(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                           uint32_t seed)
+{
+    // A save point every few messages, so that there's plenty of them.
+    DP_CanvasHistorySavePointPolicy policy = {4, 0, 0, NULL, NULL};
+    h->packed = DP_canvas_history_new(NULL, NULL, false, NULL);
+    h->unpacked = DP_canvas_history_new(NULL, NULL, false, NULL);
+    h->random = seed;
+    DP_canvas_history_save_point_packing_set(h->unpacked, false);
+    DP_canvas_history_save_point_policy_set(h->packed, &policy);
+    DP_canvas_history_save_point_policy_set(h->unpacked, &policy);
+    handle(TEST_ARGS, h, dc, DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, h, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+}
+
+static void dispose_histories(Histories *h)
+{
+    DP_canvas_history_free(h->unpacked);
+    DP_canvas_history_free(h->packed);
+}
+
+static unsigned char *get_compress_buffer(size_t size, void *user)
+{
+    unsigned char **out = user;
+    *out = DP_malloc(size);
+    return *out;
+}
+
+static void draw_steps(TEST_PARAMS, Histories *h, DP_DrawContext *dc)
+{
+    for (int i = 0; i < STEPS; ++i) {
+        unsigned int user = DP_int_to_uint(random_int(&h->random, 1, 2));
+        handle(TEST_ARGS, h, dc, DP_msg_undo_point_new(user));
+        int count = random_int(&h->random, 1, 3);
+        for (int j = 0; j < count; ++j) {
+            handle(TEST_ARGS, h, dc, random_dabs(&h->random, user));
+        }
+    }
+}
+
+
+static void packed_tile_round_trip(TEST_PARAMS)
+{
+    uint32_t state = 0x2545f491u;
+    DP_TransientTile *tt = DP_transient_tile_new_blank(1);
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            // A gradient with some noise sprinkled in.
+            uint16_t a = DP_int_to_uint16((x + y) * DP_BIT15 / 126);
+            if (random_int(&state, 0, 9) == 0) {
+                a = DP_int_to_uint16(random_int(&state, 0, DP_BIT15));
+            }
+            DP_transient_tile_pixel_at_set(tt, x, y,
+                                           (DP_Pixel15){a / 2, a / 3, a, a});
+        }
+    }
+    DP_Tile *t = DP_transient_tile_persist(tt);
+
+    DP_TileMemoryStatistics before = DP_tile_memory_usage();
+    DP_Tile *pt = DP_tile_pack(t);
+    FATAL(NOT_NULL_OK(pt, "gradient tile gets packed"));
+    OK(DP_tile_packed(pt), "packed tile is packed");
+    NOK(DP_tile_packed(t), "original tile isn't packed");
+    NOK(DP_tile_resident(pt), "packed tile isn't resident");
+    OK(DP_tile_packed_size(pt) < DP_TILE_BYTES,
+       "packed tile is smaller than its pixels");
+    UINT_EQ_OK(DP_tile_packed_size(t), 0, "original tile has no packed size");
+    UINT_EQ_OK(DP_tile_context_id(pt), 1, "context id carried over");
+    NOK(DP_tile_blank(pt), "packed tile isn't blank");
+    NOK(DP_tile_same_pixel(pt, NULL), "packed tile isn't solid");
+    NOK(DP_tile_resident(pt), "solid checks don't unpack");
+
+    DP_TileMemoryStatistics packed = DP_tile_memory_usage();
+    UINT_EQ_OK(packed.tiles_packed, before.tiles_packed + 1,
+               "packed tile counted");
+    UINT_EQ_OK(packed.packed_bytes,
+               before.packed_bytes + DP_tile_packed_size(pt),
+               "packed bytes counted");
+
+    // Building reset images goes through compressing tiles, which shouldn't
+    // leave them unpacked afterwards.
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Pixel8 *buffer = DP_draw_context_tile8_buffer(dc);
+    unsigned char *expected = NULL;
+    unsigned char *actual = NULL;
+    size_t expected_size =
+        DP_tile_compress(t, buffer, get_compress_buffer, &expected);
+    size_t actual_size =
+        DP_tile_compress(pt, buffer, get_compress_buffer, &actual);
+    OK(expected_size == actual_size
+           && memcmp(expected, actual, expected_size) == 0,
+       "packed tile compresses the same as the original");
+    NOK(DP_tile_resident(pt), "compressing doesn't unpack");
+    DP_free(actual);
+    DP_free(expected);
+    DP_draw_context_free(dc);
+
+    OK(memcmp(DP_tile_pixels(pt), DP_tile_pixels(t), DP_TILE_BYTES) == 0,
+       "packed tile pixels equal the original");
+    OK(DP_tile_resident(pt), "packed tile resident after looking at pixels");
+    UINT_EQ_OK(DP_tile_memory_usage().tiles_unpacked,
+               before.tiles_unpacked + 1, "unpacked tile counted");
+    OK(DP_tile_checksum(pt) == DP_tile_checksum(t),
+       "packed tile checksum equals the original");
+
+    DP_TransientTile *copy = DP_transient_tile_new(pt, 0);
+    OK(memcmp(DP_transient_tile_pixels(copy), DP_tile_pixels(t), DP_TILE_BYTES)
+           == 0,
+       "transient copy of packed tile has the same pixels");
+    DP_transient_tile_decref(copy);
+
+    OK(DP_tile_pack(pt) == NULL, "packed tile doesn't get packed again");
+    DP_Tile *solid = DP_tile_new_from_bgra(0, 0xff336699u);
+    OK(DP_tile_pack(solid) == NULL, "solid tile doesn't get packed");
+    DP_tile_decref(solid);
+
+    DP_tile_decref(pt);
+    DP_tile_decref(t);
+    DP_TileMemoryStatistics after = DP_tile_memory_usage();
+    UINT_EQ_OK(after.tiles_packed, before.tiles_packed,
+               "packed tile uncounted after freeing");
+    UINT_EQ_OK(after.packed_bytes, before.packed_bytes,
+               "packed bytes uncounted after freeing");
+    UINT_EQ_OK(after.tiles_unpacked, before.tiles_unpacked,
+               "unpacked tile uncounted after freeing");
+}
+
+static void deep_undo_matches_unpacked(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    Histories h;
+    init_histories(TEST_ARGS, &h, dc, 0x9e3779b9u);
+    draw_steps(TEST_ARGS, &h, dc);
+    OK(checksum(h.packed) == checksum(h.unpacked),
+       "same canvas after drawing");
+
+    DP_MemoryUsageCounts packed_usage = history_memory_usage(h.packed);
+    DP_MemoryUsageCounts unpacked_usage = history_memory_usage(h.unpacked);
+    OK(packed_usage.packed_tile_count > 0, "save points got packed");
+    OK(packed_usage.packed_bytes > 0, "packed bytes reported");
+    INT_EQ_OK(unpacked_usage.packed_tile_count, 0,
+              "nothing packed with packing disabled");
+    UINT_EQ_OK(unpacked_usage.packed_bytes, 0,
+               "no packed bytes with packing disabled");
+    OK(packed_usage.unique_bytes < unpacked_usage.unique_bytes,
+       "packed history uses less memory (%zu < %zu)",
+       packed_usage.unique_bytes, unpacked_usage.unique_bytes);
+
+    // Undo as far as possible, restoring from packed save points on the way.
+    int mismatches = 0;
+    int undos = 0;
+    for (unsigned int user = 1; user <= 2; ++user) {
+        while (undo_both(TEST_ARGS, &h, dc, user, false)) {
+            ++undos;
+            if (checksum(h.packed) != checksum(h.unpacked)) {
+                ++mismatches;
+            }
+        }
+    }
+    OK(undos > 10, "undid a bunch (%d)", undos);
+    INT_EQ_OK(mismatches, 0, "same canvas after every undo");
+
+    // And redo some of it again for good measure.
+    for (int i = 0; i < 5; ++i) {
+        undo_both(TEST_ARGS, &h, dc, 1, true);
+        if (checksum(h.packed) != checksum(h.unpacked)) {
+            ++mismatches;
+        }
+    }
+    INT_EQ_OK(mismatches, 0, "same canvas after redos");
+
+    DP_CanvasState *packed_cs = DP_canvas_history_get(h.packed);
+    DP_CanvasState *unpacked_cs = DP_canvas_history_get(h.unpacked);
+    DP_Image *packed_img = DP_canvas_state_to_flat_image(
+        packed_cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+    DP_Image *unpacked_img = DP_canvas_state_to_flat_image(
+        unpacked_cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL, NULL);
+    FATAL(NOT_NULL_OK(packed_img, "flatten packed"));
+    FATAL(NOT_NULL_OK(unpacked_img, "flatten unpacked"));
+    OK(memcmp(DP_image_pixels(packed_img), DP_image_pixels(unpacked_img),
+              sizeof(DP_Pixel8) * WIDTH * HEIGHT)
+           == 0,
+       "flattened images are the same");
+    DP_image_free(unpacked_img);
+    DP_image_free(packed_img);
+    DP_canvas_state_decref(unpacked_cs);
+    DP_canvas_state_decref(packed_cs);
+
+    dispose_histories(&h);
+    DP_draw_context_free(dc);
+}
+
+static void set_soft_dab(DP_UNUSED int count, DP_ClassicDab *dabs,
+                         DP_UNUSED void *user)
+{
+    DP_classic_dab_init(dabs, 0, 0, 0, 40 * 256, 0, 255);
+}
+
+// Draws a soft dab in the top-left corner, which gives the tiles there a
+// gradient that's worth packing.
+static void draw_soft_dab(TEST_PARAMS, Histories *h, DP_DrawContext *dc,
+                          uint32_t color)
+{
+    handle(TEST_ARGS, h, dc, DP_msg_undo_point_new(1));
+    handle(TEST_ARGS, h, dc,
+           DP_msg_draw_dabs_classic_new(1, LAYER_ID, 32 * 4, 32 * 4, color,
+                                        DP_BLEND_MODE_NORMAL, set_soft_dab, 1,
+                                        NULL));
+}
+
+static void undo_leaves_old_save_points_packed(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    Histories h;
+    init_histories(TEST_ARGS, &h, dc, 0);
+    // A save point at every undo point.
+    DP_CanvasHistorySavePointPolicy policy = {0, 0, 0, NULL, NULL};
+    DP_canvas_history_save_point_policy_set(h.packed, &policy);
+    DP_canvas_history_save_point_policy_set(h.unpacked, &policy);
+    size_t unpacked_before = DP_tile_memory_usage().tiles_unpacked;
+
+    draw_soft_dab(TEST_ARGS, &h, dc, 0xff0000u);
+    draw_soft_dab(TEST_ARGS, &h, dc, 0x00ff00u);
+    draw_soft_dab(TEST_ARGS, &h, dc, 0x0000ffu);
+    OK(history_memory_usage(h.packed).packed_tile_count > 0,
+       "first dab got packed");
+
+    // Undoing back to the first dab restores its packed tiles, which get
+    // unpacked by looking at them.
+    undo_both(TEST_ARGS, &h, dc, 1, false);
+    undo_both(TEST_ARGS, &h, dc, 1, false);
+    OK(checksum(h.packed) == checksum(h.unpacked), "same canvas after undo");
+    OK(DP_tile_memory_usage().tiles_unpacked > unpacked_before,
+       "restored tiles got unpacked");
+
+    // Drawing over them again leaves the save point before it as the only one
+    // holding onto those tiles, so once that save point gets packed again,
+    // their unpacked pixels must not stick around.
+    draw_soft_dab(TEST_ARGS, &h, dc, 0xffff00u);
+    draw_soft_dab(TEST_ARGS, &h, dc, 0x00ffffu);
+    OK(checksum(h.packed) == checksum(h.unpacked), "same canvas after drawing");
+    UINT_EQ_OK(DP_tile_memory_usage().tiles_unpacked, unpacked_before,
+               "unpacked pixels dropped again");
+
+    DP_MemoryUsageCounts packed_usage = history_memory_usage(h.packed);
+    DP_MemoryUsageCounts unpacked_usage = history_memory_usage(h.unpacked);
+    OK(packed_usage.unique_bytes < unpacked_usage.unique_bytes,
+       "packed history still uses less memory (%zu < %zu)",
+       packed_usage.unique_bytes, unpacked_usage.unique_bytes);
+
+    dispose_histories(&h);
+    DP_draw_context_free(dc);
+}
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(packed_tile_round_trip);
+    REGISTER_TEST(deep_undo_matches_unpacked);
+    REGISTER_TEST(undo_leaves_old_save_points_packed);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    256
+#define HEIGHT   256
+#define LAYER_ID 257
+#define USER_A   1
+#define USER_B   2
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc,
+                                      const DP_CanvasHistorySavePointPolicy *p)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    if (p) {
+        DP_canvas_history_save_point_policy_set(ch, p);
+    }
+    OK(handle(ch, dc, DP_msg_canvas_resize_new(USER_A, 0, WIDTH, HEIGHT, 0)),
+       "resize canvas");
+    OK(handle(ch, dc,
+              DP_msg_layer_tree_create_new(USER_A, LAYER_ID, 0, 0, 0, 0,
+                                           "Layer", 5)),
+       "create layer");
+    return ch;
+}
+
+// Small translucent rectangles that all land in the top left tile.
+static void act(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                unsigned int user, int i)
+{
+    OK(handle(ch, dc, DP_msg_undo_point_new(user)), "undo point %d", i);
+    OK(handle(ch, dc,
+              DP_msg_fill_rect_new(
+                  user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                  DP_int_to_uint32(i * 7 % (DP_TILE_SIZE - 24)),
+                  DP_int_to_uint32(i * 13 % (DP_TILE_SIZE - 24)),
+                  20, 20, 0x80000000u | DP_int_to_uint32(i * 0x10305))),
+       "action %d", i);
+}
+
+static bool undo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user)
+{
+    return handle(ch, dc, DP_msg_undo_new(user, 0, false));
+}
+
+static bool redo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user)
+{
+    return handle(ch, dc, DP_msg_undo_new(user, 0, true));
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+// Not counting the initial entry.
+static int count_undo_point_save_points(DP_CanvasHistory *ch)
+{
+    DP_CanvasHistorySnapshot *chs = DP_canvas_history_snapshot_new(ch);
+    int count = DP_canvas_history_snapshot_history_count(chs);
+    int save_points = 0;
+    for (int i = 1; i < count; ++i) {
+        const DP_CanvasHistoryEntry *entry =
+            DP_canvas_history_snapshot_history_entry_at(chs, i);
+        if (entry->state && DP_message_type(entry->msg) == DP_MSG_UNDO_POINT) {
+            ++save_points;
+        }
+    }
+    DP_canvas_history_snapshot_decref(chs);
+    return save_points;
+}
+
+// How many entries an undo by the given user has to replay: everything from
+// the closest save point at or before their last done undo point onwards.
+static int undo_replay_count(DP_CanvasHistory *ch, unsigned int user)
+{
+    DP_CanvasHistorySnapshot *chs = DP_canvas_history_snapshot_new(ch);
+    int count = DP_canvas_history_snapshot_history_count(chs);
+    int i = count - 1;
+    for (; i >= 0; --i) {
+        const DP_CanvasHistoryEntry *entry =
+            DP_canvas_history_snapshot_history_entry_at(chs, i);
+        if (DP_message_type(entry->msg) == DP_MSG_UNDO_POINT
+            && DP_message_context_id(entry->msg) == user
+            && entry->undo == DP_UNDO_DONE) {
+            break;
+        }
+    }
+    for (; i >= 0; --i) {
+        if (DP_canvas_history_snapshot_history_entry_at(chs, i)->state) {
+            break;
+        }
+    }
+    DP_canvas_history_snapshot_decref(chs);
+    return i < 0 ? -1 : count - 1 - i;
+}
+
+// What the canvas looks like when only the given actions are drawn.
+static uint64_t replay_checksum(TEST_PARAMS, DP_DrawContext *dc, int count,
+                                const int *indexes)
+{
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, NULL);
+    for (int i = 0; i < count; ++i) {
+        act(TEST_ARGS, ch, dc, USER_A, indexes[i]);
+    }
+    uint64_t value = checksum(ch);
+    DP_canvas_history_free(ch);
+    return value;
+}
+
+#define REPLAY_CHECKSUM(...)                                      \
+    replay_checksum(TEST_ARGS, dc,                                \
+                    (int)DP_ARRAY_LENGTH(((int[]){__VA_ARGS__})), \
+                    (int[]){__VA_ARGS__})
+
+static long long get_time(void *user)
+{
+    return *(long long *)user;
+}
+
+
+static void default_saves_every_undo_point(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, NULL);
+    for (int i = 0; i < 10; ++i) {
+        act(TEST_ARGS, ch, dc, USER_A, i);
+    }
+    INT_EQ_OK(count_undo_point_save_points(ch), 10,
+              "every undo point gets a save point");
+    INT_EQ_OK(undo_replay_count(ch, USER_A), 1,
+              "undo only replays the undone action");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void message_count_spaces_out_save_points(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {6, 0, 0, NULL, NULL};
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, &policy);
+    // Each action is two entries, so every third undo point gets a state.
+    for (int i = 0; i < 8; ++i) {
+        act(TEST_ARGS, ch, dc, USER_A, i);
+    }
+    INT_EQ_OK(count_undo_point_save_points(ch), 2,
+              "only some undo points get a save point");
+    INT_EQ_OK(undo_replay_count(ch, USER_A), 5,
+              "undo replays from the last save point");
+
+    act(TEST_ARGS, ch, dc, USER_A, 8);
+    INT_EQ_OK(count_undo_point_save_points(ch), 3,
+              "threshold triggers another save point");
+    INT_EQ_OK(undo_replay_count(ch, USER_A), 1,
+              "undo after the new save point replays only the action");
+
+    OK(undo(ch, dc, USER_A), "undo");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3, 4, 5, 6, 7),
+       "undoing the newest action works");
+    OK(undo(ch, dc, USER_A), "undo again");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3, 4, 5, 6),
+       "undoing an action without a save point works");
+    OK(redo(ch, dc, USER_A), "redo");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3, 4, 5, 6, 7),
+       "redoing works");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void sparse_selective_undo(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {1000, 0, 0, NULL, NULL};
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, &policy);
+    act(TEST_ARGS, ch, dc, USER_A, 0);
+    act(TEST_ARGS, ch, dc, USER_B, 1);
+    OK(undo(ch, dc, USER_A), "A undoes");
+    // Makes A's undone action gone.
+    act(TEST_ARGS, ch, dc, USER_A, 2);
+    OK(undo(ch, dc, USER_B), "B undoes");
+    OK(checksum(ch) == REPLAY_CHECKSUM(2),
+       "only A's new action remains after B's undo");
+    OK(redo(ch, dc, USER_B), "B redoes");
+    OK(checksum(ch) == REPLAY_CHECKSUM(1, 2), "B's action is back");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void interval_spaces_out_save_points(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    long long time = 0;
+    DP_CanvasHistorySavePointPolicy policy = {0, 1000, 0, get_time, &time};
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, &policy);
+    for (int i = 0; i < 10; ++i) {
+        time += 300;
+        act(TEST_ARGS, ch, dc, USER_A, i);
+    }
+    // At 1200 and 2400 milliseconds.
+    INT_EQ_OK(count_undo_point_save_points(ch), 2,
+              "save points only get made once the interval has passed");
+    OK(undo(ch, dc, USER_A), "undo");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3, 4, 5, 6, 7, 8),
+       "undo works");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void changed_tiles_spaces_out_save_points(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {0, 0, 4, NULL, NULL};
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, &policy);
+    for (int i = 0; i < 5; ++i) {
+        act(TEST_ARGS, ch, dc, USER_A, i);
+    }
+    // Resizing the canvas changes every tile, so the first undo point after
+    // that gets one, but the small changes after that don't.
+    INT_EQ_OK(count_undo_point_save_points(ch), 1,
+              "small changes don't make save points");
+
+    OK(handle(ch, dc, DP_msg_undo_point_new(USER_A)), "undo point");
+    OK(handle(ch, dc,
+              DP_msg_fill_rect_new(USER_A, LAYER_ID, DP_BLEND_MODE_NORMAL, 0,
+                                   0, WIDTH, HEIGHT, 0xff00ff00)),
+       "fill whole layer");
+    act(TEST_ARGS, ch, dc, USER_A, 5);
+    INT_EQ_OK(count_undo_point_save_points(ch), 2,
+              "large change makes a save point");
+    OK(undo(ch, dc, USER_A), "undo");
+    OK(undo(ch, dc, USER_A), "undo fill");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3, 4),
+       "undoing the large change works");
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void explicit_save_point_shares_tiles(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistorySavePointPolicy policy = {1000, 0, 0, NULL, NULL};
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc, &policy);
+    for (int i = 0; i < 4; ++i) {
+        act(TEST_ARGS, ch, dc, USER_A, i);
+    }
+    OK(DP_canvas_history_save_point_make(ch), "request save point");
+
+    DP_CanvasHistorySnapshot *chs = DP_canvas_history_snapshot_new(ch);
+    int count = DP_canvas_history_snapshot_history_count(chs);
+    DP_CanvasState *saved =
+        DP_canvas_history_snapshot_history_entry_at(chs, count - 1)->state;
+    FATAL(NOT_NULL_OK(saved, "newest entry has a save point"));
+
+    OK(handle(ch, dc,
+              DP_msg_fill_rect_new(USER_A, LAYER_ID, DP_BLEND_MODE_NORMAL, 200,
+                                   200, 10, 10, 0xff0000ff)),
+       "draw into another tile");
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(
+        DP_layer_routes_search(lr, LAYER_ID), cs);
+    DP_LayerRoutes *saved_lr = DP_canvas_state_layer_routes_noinc(saved);
+    DP_LayerContent *saved_lc = DP_layer_routes_entry_content(
+        DP_layer_routes_search(saved_lr, LAYER_ID), saved);
+    OK(DP_layer_content_tile_at_noinc(lc, 0, 0)
+           == DP_layer_content_tile_at_noinc(saved_lc, 0, 0),
+       "untouched tiles are shared with the save point");
+    int changed = 200 / DP_TILE_SIZE;
+    OK(DP_layer_content_tile_at_noinc(lc, changed, changed)
+           != DP_layer_content_tile_at_noinc(saved_lc, changed, changed),
+       "changed tiles are not");
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_snapshot_decref(chs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(default_saves_every_undo_point);
+    REGISTER_TEST(message_count_spaces_out_save_points);
+    REGISTER_TEST(sparse_selective_undo);
+    REGISTER_TEST(interval_spaces_out_save_points);
+    REGISTER_TEST(changed_tiles_spaces_out_save_points);
+    REGISTER_TEST(explicit_save_point_shares_tiles);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
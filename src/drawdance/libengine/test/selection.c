@@ -0,0 +1,556 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/geom.h>
+#include <dpengine/brush.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/paint.h>
+#include <dpengine/selection.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+#define DAB_CANVAS_SIZE 128
+
+
+static bool rect_eq(DP_Rect a, DP_Rect b)
+{
+    return a.x1 == b.x1 && a.y1 == b.y1 && a.x2 == b.x2 && a.y2 == b.y2;
+}
+
+static bool bounds_ok(TEST_PARAMS, DP_Selection *sel, DP_Rect expected,
+                      const char *title)
+{
+    DP_Rect bounds = DP_selection_bounds(sel);
+    bool ok = OK(rect_eq(bounds, expected), "%s bounds", title);
+    if (!ok) {
+        DIAG("got %d,%d to %d,%d, expected %d,%d to %d,%d", bounds.x1,
+             bounds.y1, bounds.x2, bounds.y2, expected.x1, expected.y1,
+             expected.x2, expected.y2);
+    }
+    return ok;
+}
+
+static bool empty_ok(TEST_PARAMS, DP_Selection *sel, const char *title)
+{
+    DP_SelectionSpanIterator ssi = DP_selection_span_iterator_make(sel);
+    return OK(DP_selection_empty(sel), "%s is empty", title)
+         & OK(!DP_rect_valid(DP_selection_bounds(sel)),
+              "%s has invalid bounds", title)
+         & OK(!DP_selection_span_iterator_next(&ssi), "%s has no spans",
+              title)
+         & INT_EQ_OK(DP_selection_contains(sel, 0, 0), 0,
+                     "%s contains nothing", title);
+}
+
+// Checks that the selection is exactly the given rectangle at full coverage,
+// including one pixel around its edges.
+static bool matches_rect(DP_Selection *sel, DP_Rect rect)
+{
+    for (int y = rect.y1 - 1; y <= rect.y2 + 1; ++y) {
+        for (int x = rect.x1 - 1; x <= rect.x2 + 1; ++x) {
+            int expected = DP_rect_contains(rect, x, y) ? 255 : 0;
+            if (DP_selection_contains(sel, x, y) != expected) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+static double coverage_area(DP_Selection *sel)
+{
+    double area = 0.0;
+    DP_SelectionSpanIterator ssi = DP_selection_span_iterator_make(sel);
+    while (DP_selection_span_iterator_next(&ssi)) {
+        for (int i = 0; i < ssi.span.width; ++i) {
+            area += ssi.span.coverage[i] / 255.0;
+        }
+    }
+    return area;
+}
+
+static int count_spans_on_row(DP_Selection *sel, int y)
+{
+    int count = 0;
+    DP_SelectionSpanIterator ssi = DP_selection_span_iterator_make(sel);
+    while (DP_selection_span_iterator_next(&ssi)) {
+        if (ssi.span.y == y) {
+            ++count;
+        }
+    }
+    return count;
+}
+
+
+static void selection_rect(TEST_PARAMS)
+{
+    DP_Selection *sel = DP_selection_new_rect(DP_rect_make(3, 4, 5, 2));
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){3, 4, 7, 5}, "rect");
+    OK(!DP_selection_empty(sel), "rect isn't empty");
+    OK(matches_rect(sel, (DP_Rect){3, 4, 7, 5}), "rect is fully covered");
+
+    DP_SelectionSpanIterator ssi = DP_selection_span_iterator_make(sel);
+    OK(DP_selection_span_iterator_next(&ssi), "first span");
+    INT_EQ_OK(ssi.span.x, 3, "first span x");
+    INT_EQ_OK(ssi.span.y, 4, "first span y");
+    INT_EQ_OK(ssi.span.width, 5, "first span width");
+    INT_EQ_OK(ssi.span.coverage[4], 255, "first span coverage");
+    OK(DP_selection_span_iterator_next(&ssi), "second span");
+    INT_EQ_OK(ssi.span.y, 5, "second span y");
+    OK(!DP_selection_span_iterator_next(&ssi), "no third span");
+    DP_selection_decref(sel);
+
+    DP_Selection *invalid = DP_selection_new_rect((DP_Rect){5, 5, 4, 5});
+    empty_ok(TEST_ARGS, invalid, "invalid rect");
+    DP_selection_decref(invalid);
+
+    DP_Selection *empty = DP_selection_new_empty();
+    empty_ok(TEST_ARGS, empty, "new empty");
+    DP_selection_decref(empty);
+}
+
+static void selection_ellipse(TEST_PARAMS)
+{
+    DP_Selection *sel = DP_selection_new_ellipse(2.0, 3.0, 20.0, 12.0);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){2, 3, 21, 14}, "ellipse");
+    INT_EQ_OK(DP_selection_contains(sel, 11, 8), 255, "center is covered");
+    INT_EQ_OK(DP_selection_contains(sel, 2, 3), 0, "top left corner isn't");
+    INT_EQ_OK(DP_selection_contains(sel, 21, 14), 0,
+              "bottom right corner isn't");
+
+    uint8_t edge = DP_selection_contains(sel, 2, 8);
+    OK(edge > 0 && edge < 255, "left edge is anti-aliased (%d)", (int)edge);
+
+    bool symmetric = true;
+    for (int y = 3; y <= 14; ++y) {
+        for (int x = 2; x <= 21; ++x) {
+            uint8_t c = DP_selection_contains(sel, x, y);
+            if (c != DP_selection_contains(sel, 23 - x, y)
+                || c != DP_selection_contains(sel, x, 17 - y)) {
+                symmetric = false;
+            }
+        }
+    }
+    OK(symmetric, "ellipse is symmetric");
+
+    double expected = M_PI * 10.0 * 6.0;
+    double area = coverage_area(sel);
+    OK(fabs(area - expected) < expected * 0.01,
+       "ellipse area %f is close to %f", area, expected);
+    DP_selection_decref(sel);
+
+    DP_Selection *flat = DP_selection_new_ellipse(2.0, 3.0, 20.0, 0.0);
+    empty_ok(TEST_ARGS, flat, "zero height ellipse");
+    DP_selection_decref(flat);
+}
+
+static void selection_polygon(TEST_PARAMS)
+{
+    DP_Vec2 square[] = {{4.0, 2.0}, {10.0, 2.0}, {10.0, 7.0}, {4.0, 7.0}};
+    DP_Selection *sel = DP_selection_new_polygon(square, 4);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){4, 2, 9, 6}, "pixel-aligned square");
+    OK(matches_rect(sel, (DP_Rect){4, 2, 9, 6}),
+       "pixel-aligned square matches rect");
+    DP_selection_decref(sel);
+
+    DP_Vec2 triangle[] = {{0.0, 0.0}, {16.0, 0.0}, {0.0, 16.0}};
+    sel = DP_selection_new_polygon(triangle, 3);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 15, 15}, "triangle");
+    double area = coverage_area(sel);
+    OK(fabs(area - 128.0) < 1.0, "triangle area %f is close to 128", area);
+    INT_EQ_OK(DP_selection_contains(sel, 2, 2), 255, "inside is covered");
+    INT_EQ_OK(DP_selection_contains(sel, 14, 14), 0, "outside isn't");
+    uint8_t diagonal = DP_selection_contains(sel, 8, 7);
+    OK(diagonal > 96 && diagonal < 160, "diagonal is half covered (%d)",
+       (int)diagonal);
+    DP_selection_decref(sel);
+
+    // Five-pointed star drawn in one go, so its middle has a winding of 2.
+    DP_Vec2 star[5];
+    for (int i = 0; i < 5; ++i) {
+        double angle = M_PI * 2.0 * (i * 2 % 5) / 5.0 - M_PI / 2.0;
+        star[i] = (DP_Vec2){32.0 + cos(angle) * 30.0, 32.0 + sin(angle) * 30.0};
+    }
+    sel = DP_selection_new_polygon(star, 5);
+    INT_EQ_OK(DP_selection_contains(sel, 32, 32), 255,
+              "star center is covered under non-zero winding");
+    INT_EQ_OK(DP_selection_contains(sel, 31, 8), 255, "star tip is covered");
+    INT_EQ_OK(DP_selection_contains(sel, 10, 10), 0,
+              "between star tips isn't covered");
+    DP_selection_decref(sel);
+
+    sel = DP_selection_new_polygon(square, 2);
+    empty_ok(TEST_ARGS, sel, "two point polygon");
+    DP_selection_decref(sel);
+}
+
+static bool only_fully_covered(DP_Selection *sel)
+{
+    DP_SelectionSpanIterator ssi = DP_selection_span_iterator_make(sel);
+    while (DP_selection_span_iterator_next(&ssi)) {
+        for (int i = 0; i < ssi.span.width; ++i) {
+            if (ssi.span.coverage[i] != 255) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+static void selection_lasso(TEST_PARAMS)
+{
+    // Same star as above, but the even-odd rule leaves a hole in the middle.
+    DP_Vec2 star[5];
+    for (int i = 0; i < 5; ++i) {
+        double angle = M_PI * 2.0 * (i * 2 % 5) / 5.0 - M_PI / 2.0;
+        star[i] = (DP_Vec2){32.0 + cos(angle) * 30.0, 32.0 + sin(angle) * 30.0};
+    }
+    DP_Selection *sel = DP_selection_new_lasso(star, 5, 64, 64, true);
+    INT_EQ_OK(DP_selection_contains(sel, 32, 32), 0,
+              "star center isn't covered under even-odd");
+    INT_EQ_OK(DP_selection_contains(sel, 31, 8), 255, "star tip is covered");
+    INT_EQ_OK(DP_selection_contains(sel, 10, 10), 0,
+              "between star tips isn't covered");
+    DP_selection_decref(sel);
+
+    DP_Vec2 concave[] = {{0.0, 0.0},   {30.0, 0.0},  {30.0, 30.0},
+                         {20.0, 30.0}, {20.0, 10.0}, {10.0, 10.0},
+                         {10.0, 30.0}, {0.0, 30.0}};
+    sel = DP_selection_new_lasso(concave, 8, 64, 64, true);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 29, 29}, "concave polygon");
+    double area = coverage_area(sel);
+    OK(fabs(area - 700.0) < 0.5, "concave area %f is 700", area);
+    INT_EQ_OK(DP_selection_contains(sel, 15, 20), 0, "notch isn't covered");
+    INT_EQ_OK(DP_selection_contains(sel, 5, 20), 255, "left leg is covered");
+    INT_EQ_OK(DP_selection_contains(sel, 25, 20), 255, "right leg is covered");
+    DP_selection_decref(sel);
+
+    DP_Vec2 off_canvas[] = {{-100.0, -100.0}, {-50.0, -100.0}, {-50.0, -50.0}};
+    sel = DP_selection_new_lasso(off_canvas, 3, 64, 64, true);
+    empty_ok(TEST_ARGS, sel, "polygon entirely off-canvas");
+    DP_selection_decref(sel);
+
+    DP_Vec2 far_away[] = {{1e12, 1e12}, {2e12, 1e12}, {2e12, 2e12}};
+    sel = DP_selection_new_lasso(far_away, 3, 64, 64, true);
+    empty_ok(TEST_ARGS, sel, "polygon far off-canvas");
+    DP_selection_decref(sel);
+
+    DP_Vec2 overlapping[] = {
+        {-10.0, -10.0}, {20.0, -10.0}, {20.0, 20.0}, {-10.0, 20.0}};
+    sel = DP_selection_new_lasso(overlapping, 4, 64, 64, true);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 19, 19},
+              "polygon partially off-canvas");
+    OK(matches_rect(sel, (DP_Rect){0, 0, 19, 19}),
+       "polygon partially off-canvas gets clipped");
+    DP_selection_decref(sel);
+
+    DP_Vec2 triangle[] = {{0.0, 0.0}, {16.0, 0.0}, {0.0, 16.0}};
+    sel = DP_selection_new_lasso(triangle, 3, 64, 64, false);
+    OK(only_fully_covered(sel), "aliased triangle has no partial coverage");
+    area = coverage_area(sel);
+    OK(fabs(area - 128.0) < 16.0, "aliased triangle area %f is close to 128",
+       area);
+    INT_EQ_OK(DP_selection_contains(sel, 7, 7), 255,
+              "pixel with its center inside is covered");
+    INT_EQ_OK(DP_selection_contains(sel, 8, 8), 0,
+              "pixel with its center outside isn't");
+    DP_selection_decref(sel);
+
+    sel = DP_selection_new_lasso(triangle, 2, 64, 64, true);
+    empty_ok(TEST_ARGS, sel, "two point lasso");
+    DP_selection_decref(sel);
+
+    // A lasso with lots of points, which should come out like an ellipse.
+    int count = 4000;
+    DP_Vec2 *circle = DP_malloc(sizeof(*circle) * (size_t)count);
+    for (int i = 0; i < count; ++i) {
+        double angle = M_PI * 2.0 * i / count;
+        circle[i] =
+            (DP_Vec2){32.0 + cos(angle) * 20.0, 32.0 + sin(angle) * 20.0};
+    }
+    sel = DP_selection_new_lasso(circle, count, 64, 64, true);
+    DP_free(circle);
+    DP_Selection *ellipse = DP_selection_new_ellipse(12.0, 12.0, 40.0, 40.0);
+    int max_diff = 0;
+    for (int y = 0; y < 64; ++y) {
+        for (int x = 0; x < 64; ++x) {
+            max_diff = DP_max_int(max_diff,
+                                  abs(DP_selection_contains(sel, x, y)
+                                      - DP_selection_contains(ellipse, x, y)));
+        }
+    }
+    OK(max_diff <= 8, "many-point lasso matches ellipse (max difference %d)",
+       max_diff);
+    DP_selection_decref(ellipse);
+    DP_selection_decref(sel);
+}
+
+static void selection_from_mask(TEST_PARAMS)
+{
+    uint8_t mask[] = {
+        0, 0,   0,  0, //
+        0, 128, 0,  0, //
+        0, 0,   64, 0, //
+        0, 0,   0,  0, //
+    };
+    DP_Selection *sel = DP_selection_new_from_mask(10, 20, 4, 4, mask);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){11, 21, 12, 22}, "trimmed mask");
+    INT_EQ_OK(DP_selection_contains(sel, 11, 21), 128, "mask coverage");
+    INT_EQ_OK(DP_selection_contains(sel, 12, 22), 64, "more mask coverage");
+    INT_EQ_OK(DP_selection_contains(sel, 12, 21), 0, "mask gap");
+    INT_EQ_OK(count_spans_on_row(sel, 21), 1, "one span on first row");
+    INT_EQ_OK(count_spans_on_row(sel, 22), 1, "one span on second row");
+    DP_selection_decref(sel);
+
+    uint8_t blank[16] = {0};
+    sel = DP_selection_new_from_mask(10, 20, 4, 4, blank);
+    empty_ok(TEST_ARGS, sel, "blank mask");
+    DP_selection_decref(sel);
+}
+
+static void selection_union(TEST_PARAMS)
+{
+    DP_Selection *a = DP_selection_new_rect(DP_rect_make(0, 0, 4, 4));
+    DP_Selection *b = DP_selection_new_rect(DP_rect_make(10, 2, 4, 4));
+    DP_Selection *sel = DP_selection_union(a, b);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 13, 5}, "disjoint union");
+    INT_EQ_OK(count_spans_on_row(sel, 1), 1, "one span above b");
+    INT_EQ_OK(count_spans_on_row(sel, 2), 2, "two spans where both are");
+    INT_EQ_OK(count_spans_on_row(sel, 5), 1, "one span below a");
+    INT_EQ_OK(DP_selection_contains(sel, 6, 2), 0, "gap between them");
+    DP_selection_decref(sel);
+
+    DP_Selection *empty = DP_selection_new_empty();
+    sel = DP_selection_union(a, empty);
+    OK(sel == a, "union with empty gives back the same selection");
+    DP_selection_decref(sel);
+    sel = DP_selection_union(empty, empty);
+    empty_ok(TEST_ARGS, sel, "union of empties");
+    DP_selection_decref(sel);
+
+    DP_selection_decref(empty);
+    DP_selection_decref(b);
+    DP_selection_decref(a);
+}
+
+static void selection_intersect(TEST_PARAMS)
+{
+    DP_Selection *a = DP_selection_new_rect(DP_rect_make(0, 0, 8, 8));
+    DP_Selection *b = DP_selection_new_rect(DP_rect_make(5, 3, 8, 8));
+    DP_Selection *sel = DP_selection_intersect(a, b);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){5, 3, 7, 7}, "overlap");
+    OK(matches_rect(sel, (DP_Rect){5, 3, 7, 7}), "overlap is exact");
+    DP_selection_decref(sel);
+
+    DP_Selection *c = DP_selection_new_rect(DP_rect_make(20, 20, 4, 4));
+    sel = DP_selection_intersect(a, c);
+    empty_ok(TEST_ARGS, sel, "disjoint intersection");
+    DP_selection_decref(sel);
+
+    // Bounds overlap, but the covered pixels don't.
+    DP_Vec2 triangle[] = {{0.0, 0.0}, {8.0, 0.0}, {0.0, 8.0}};
+    DP_Selection *t = DP_selection_new_polygon(triangle, 3);
+    DP_Selection *corner = DP_selection_new_rect(DP_rect_make(6, 6, 2, 2));
+    sel = DP_selection_intersect(t, corner);
+    empty_ok(TEST_ARGS, sel, "intersection of overlapping bounds");
+    DP_selection_decref(sel);
+
+    DP_Selection *empty = DP_selection_new_empty();
+    sel = DP_selection_intersect(a, empty);
+    empty_ok(TEST_ARGS, sel, "intersection with empty");
+    DP_selection_decref(sel);
+
+    DP_Selection *e = DP_selection_new_ellipse(0.0, 0.0, 16.0, 16.0);
+    DP_Selection *half = DP_selection_new_rect(DP_rect_make(0, 0, 16, 8));
+    sel = DP_selection_intersect(e, half);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 15, 7}, "half ellipse");
+    INT_EQ_OK(DP_selection_contains(sel, 0, 7),
+              DP_selection_contains(e, 0, 7), "anti-aliasing is kept");
+    DP_selection_decref(sel);
+
+    DP_selection_decref(half);
+    DP_selection_decref(e);
+    DP_selection_decref(empty);
+    DP_selection_decref(corner);
+    DP_selection_decref(t);
+    DP_selection_decref(c);
+    DP_selection_decref(b);
+    DP_selection_decref(a);
+}
+
+static void selection_subtract(TEST_PARAMS)
+{
+    DP_Selection *a = DP_selection_new_rect(DP_rect_make(0, 0, 10, 10));
+    DP_Selection *hole = DP_selection_new_rect(DP_rect_make(3, 3, 4, 4));
+    DP_Selection *sel = DP_selection_subtract(a, hole);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 9, 9}, "hole keeps bounds");
+    INT_EQ_OK(DP_selection_contains(sel, 4, 4), 0, "hole is cut out");
+    INT_EQ_OK(DP_selection_contains(sel, 2, 4), 255, "left of hole remains");
+    INT_EQ_OK(count_spans_on_row(sel, 4), 2, "hole splits spans");
+    INT_EQ_OK(count_spans_on_row(sel, 2), 1, "above hole is one span");
+    DP_selection_decref(sel);
+
+    DP_Selection *right = DP_selection_new_rect(DP_rect_make(5, -5, 20, 20));
+    sel = DP_selection_subtract(a, right);
+    bounds_ok(TEST_ARGS, sel, (DP_Rect){0, 0, 4, 9}, "half removed");
+    OK(matches_rect(sel, (DP_Rect){0, 0, 4, 9}), "left half remains");
+    DP_selection_decref(sel);
+
+    DP_Selection *all = DP_selection_new_rect(DP_rect_make(-1, -1, 12, 12));
+    sel = DP_selection_subtract(a, all);
+    empty_ok(TEST_ARGS, sel, "fully subtracted");
+    DP_selection_decref(sel);
+
+    DP_Selection *far = DP_selection_new_rect(DP_rect_make(50, 50, 4, 4));
+    sel = DP_selection_subtract(a, far);
+    OK(sel == a, "subtracting something disjoint gives back the same");
+    DP_selection_decref(sel);
+
+    DP_Selection *empty = DP_selection_new_empty();
+    sel = DP_selection_subtract(empty, a);
+    empty_ok(TEST_ARGS, sel, "subtracting from empty");
+    DP_selection_decref(sel);
+
+    DP_selection_decref(empty);
+    DP_selection_decref(far);
+    DP_selection_decref(all);
+    DP_selection_decref(right);
+    DP_selection_decref(hole);
+    DP_selection_decref(a);
+}
+
+
+static void set_classic_dab(DP_UNUSED int count, DP_ClassicDab *dabs,
+                            DP_UNUSED void *user)
+{
+    DP_classic_dab_init(dabs, 0, 0, 0, 16 * 256, 255, 255);
+}
+
+// Draws a single hard classic dab 16 pixels across, centered on the given
+// pixel, onto a blank layer.
+static DP_TransientLayerContent *draw_dab(DP_DrawContext *dc,
+                                          DP_Selection *sel_or_null, int x,
+                                          int y)
+{
+    DP_Message *msg = DP_msg_draw_dabs_classic_new(
+        1, 1, x * 4 + 2, y * 4 + 2, 0xff000000, DP_BLEND_MODE_NORMAL,
+        set_classic_dab, 1, NULL);
+    DP_MsgDrawDabsClassic *mddc = DP_message_internal(msg);
+    DP_PaintDrawDabsParams params = {0};
+    params.type = DP_MSG_DRAW_DABS_CLASSIC;
+    params.context_id = 1;
+    params.layer_id = 1;
+    params.origin_x = DP_msg_draw_dabs_classic_x(mddc);
+    params.origin_y = DP_msg_draw_dabs_classic_y(mddc);
+    params.color = DP_msg_draw_dabs_classic_color(mddc);
+    params.blend_mode = DP_BLEND_MODE_NORMAL;
+    params.sel_or_null = sel_or_null;
+    params.classic.dabs =
+        DP_msg_draw_dabs_classic_dabs(mddc, &params.dab_count);
+
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(DAB_CANVAS_SIZE, DAB_CANVAS_SIZE,
+                                            NULL);
+    DP_paint_draw_dabs(dc, NULL, &params, tlc);
+    DP_message_decref(msg);
+    return tlc;
+}
+
+static uint16_t alpha_at(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return DP_layer_content_pixel_at((DP_LayerContent *)tlc, x, y).a;
+}
+
+static bool tile_blank(DP_TransientLayerContent *tlc, int x, int y)
+{
+    return !DP_layer_content_tile_at_noinc((DP_LayerContent *)tlc, x, y);
+}
+
+// Whether every tile from the given pixel column rightwards is blank.
+static bool tiles_blank_from(DP_TransientLayerContent *tlc, int left)
+{
+    int count = DP_tile_count_round(DAB_CANVAS_SIZE);
+    for (int y = 0; y < count; ++y) {
+        for (int x = left / DP_TILE_SIZE; x < count; ++x) {
+            if (!tile_blank(tlc, x, y)) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+static void selection_clips_dab(TEST_PARAMS)
+{
+    // Everything left of x = 63.5, so column 63 is half selected and the
+    // selection ends right at the tile boundary.
+    DP_Vec2 points[] = {{0.0, 0.0},
+                        {63.5, 0.0},
+                        {63.5, DAB_CANVAS_SIZE},
+                        {0.0, DAB_CANVAS_SIZE}};
+    DP_Selection *sel = DP_selection_new_polygon(points, 4);
+    INT_EQ_OK(DP_selection_contains(sel, 63, 10), 128,
+              "edge column is half selected");
+
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_TransientLayerContent *reference = draw_dab(dc, NULL, 64, 64);
+    DP_TransientLayerContent *tlc = draw_dab(dc, sel, 64, 64);
+
+    int wrong = 0;
+    int partial = 0;
+    for (int y = 48; y < 80; ++y) {
+        for (int x = 48; x < 80; ++x) {
+            int expected = alpha_at(reference, x, y);
+            int actual = alpha_at(tlc, x, y);
+            if (x < 63) {
+                wrong += actual != expected;
+            }
+            else if (x == 63) {
+                double half = expected * 128.0 / 255.0;
+                wrong += fabs(actual - half) > expected * 0.02 + 2.0;
+                partial += actual > 0 && actual < expected;
+            }
+            else {
+                wrong += actual != 0;
+            }
+        }
+    }
+    INT_EQ_OK(wrong, 0, "dab is multiplied by the selection's coverage");
+    OK(partial > 0, "%d pixels on the edge are partially covered", partial);
+    OK(!tile_blank(reference, 64 / DP_TILE_SIZE, 64 / DP_TILE_SIZE),
+       "unselected dab reaches the next tile");
+    OK(tiles_blank_from(tlc, 64),
+       "tiles outside of the selection aren't touched");
+    DP_transient_layer_content_decref(tlc);
+
+    tlc = draw_dab(dc, sel, 100, 64);
+    OK(tiles_blank_from(tlc, 0), "dab outside of the selection draws nothing");
+    DP_transient_layer_content_decref(tlc);
+
+    DP_transient_layer_content_decref(reference);
+    DP_draw_context_free(dc);
+    DP_selection_decref(sel);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(selection_rect);
+    REGISTER_TEST(selection_ellipse);
+    REGISTER_TEST(selection_polygon);
+    REGISTER_TEST(selection_lasso);
+    REGISTER_TEST(selection_from_mask);
+    REGISTER_TEST(selection_union);
+    REGISTER_TEST(selection_intersect);
+    REGISTER_TEST(selection_subtract);
+    REGISTER_TEST(selection_clips_dab);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define WIDTH    100
+#define HEIGHT   100
+#define LAYER_ID 257
+#define USER_A   1
+#define USER_B   2
+
+// Overlapping and translucent, so the order they're drawn in matters.
+typedef struct Action {
+    unsigned int user;
+    int x, y, w, h;
+    uint32_t color;
+} Action;
+
+static const Action actions[] = {
+    {USER_A, 10, 10, 50, 50, 0x80ff0000},
+    {USER_B, 30, 30, 50, 50, 0x8000ff00},
+    {USER_A, 20, 40, 60, 20, 0x800000ff},
+    {USER_B, 5, 5, 90, 10, 0xc0ffff00},
+};
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    OK(handle(ch, dc, DP_msg_canvas_resize_new(USER_A, 0, WIDTH, HEIGHT, 0)),
+       "resize canvas");
+    OK(handle(ch, dc,
+              DP_msg_layer_tree_create_new(USER_A, LAYER_ID, 0, 0, 0, 0,
+                                           "Layer", 5)),
+       "create layer");
+    return ch;
+}
+
+static void act(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc, int i)
+{
+    const Action *a = &actions[i];
+    OK(handle(ch, dc, DP_msg_undo_point_new(a->user)), "undo point %d", i);
+    OK(handle(ch, dc,
+              DP_msg_fill_rect_new(a->user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                   DP_int_to_uint32(a->x),
+                                   DP_int_to_uint32(a->y),
+                                   DP_int_to_uint32(a->w),
+                                   DP_int_to_uint32(a->h), a->color)),
+       "action %d", i);
+}
+
+static bool undo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user)
+{
+    return handle(ch, dc, DP_msg_undo_new(user, 0, false));
+}
+
+static bool redo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user)
+{
+    return handle(ch, dc, DP_msg_undo_new(user, 0, true));
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+// What the canvas looks like when only the given actions are drawn.
+static uint64_t replay_checksum(TEST_PARAMS, DP_DrawContext *dc, int count,
+                                const int *indexes)
+{
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    for (int i = 0; i < count; ++i) {
+        act(TEST_ARGS, ch, dc, indexes[i]);
+    }
+    uint64_t value = checksum(ch);
+    DP_canvas_history_free(ch);
+    return value;
+}
+
+#define REPLAY_CHECKSUM(...)                                      \
+    replay_checksum(TEST_ARGS, dc,                                \
+                    (int)DP_ARRAY_LENGTH(((int[]){__VA_ARGS__})), \
+                    (int[]){__VA_ARGS__})
+
+
+static void undo_own_action_under_others(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    act(TEST_ARGS, ch, dc, 0);
+    act(TEST_ARGS, ch, dc, 1);
+
+    OK(undo(ch, dc, USER_A), "A undoes");
+    OK(checksum(ch) == REPLAY_CHECKSUM(1),
+       "A's action is gone, B's is still there");
+    OK(undo(ch, dc, USER_B), "B undoes");
+    OK(checksum(ch) == replay_checksum(TEST_ARGS, dc, 0, NULL),
+       "both actions are gone");
+    OK(redo(ch, dc, USER_A), "A redoes");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0),
+       "A's action is back, B's is still undone");
+    NOK(redo(ch, dc, USER_A), "A has nothing more to redo");
+    OK(redo(ch, dc, USER_B), "B redoes");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1), "everything is back");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void interleaved_undos(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(actions); ++i) {
+        act(TEST_ARGS, ch, dc, i);
+    }
+
+    OK(undo(ch, dc, USER_A), "A undoes once");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 3),
+       "A's most recent action is undone");
+    OK(undo(ch, dc, USER_B), "B undoes once");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1),
+       "B's most recent action is undone");
+    OK(undo(ch, dc, USER_A), "A undoes twice");
+    OK(checksum(ch) == REPLAY_CHECKSUM(1), "A's first action is undone");
+    NOK(undo(ch, dc, USER_A), "A has nothing more to undo");
+    OK(redo(ch, dc, USER_A), "A redoes once");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1),
+       "A's oldest undone action comes back first");
+    OK(redo(ch, dc, USER_B), "B redoes");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 3), "B's action is back");
+    OK(redo(ch, dc, USER_A), "A redoes twice");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3),
+       "everything is back in its original order");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void new_action_discards_redo(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    act(TEST_ARGS, ch, dc, 0);
+    act(TEST_ARGS, ch, dc, 1);
+    OK(undo(ch, dc, USER_A), "A undoes");
+    act(TEST_ARGS, ch, dc, 2);
+    NOK(redo(ch, dc, USER_A), "A can't redo after a new action");
+    NOK(redo(ch, dc, USER_B), "B has nothing to redo either");
+    OK(checksum(ch) == REPLAY_CHECKSUM(1, 2), "undone action stays gone");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void undo_depth_limit(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_canvas_history_undo_depth_limit_set(ch, dc,
+                                           DP_CANVAS_HISTORY_UNDO_DEPTH_MIN);
+    // B's undo points count towards A's depth too.
+    act(TEST_ARGS, ch, dc, 0);
+    act(TEST_ARGS, ch, dc, 1);
+    act(TEST_ARGS, ch, dc, 2);
+    act(TEST_ARGS, ch, dc, 3);
+
+    OK(undo(ch, dc, USER_A), "A undoes their last action");
+    NOK(undo(ch, dc, USER_A), "A can't undo beyond the depth limit");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 3),
+       "only the action within the limit got undone");
+    OK(redo(ch, dc, USER_A), "A can still redo");
+    OK(checksum(ch) == REPLAY_CHECKSUM(0, 1, 2, 3), "action is back");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(undo_own_action_under_others);
+    REGISTER_TEST(interleaved_undos);
+    REGISTER_TEST(new_action_discards_redo);
+    REGISTER_TEST(undo_depth_limit);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
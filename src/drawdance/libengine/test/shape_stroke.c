@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <string.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  16
+#define MAX_MESSAGES 64
+
+typedef struct DP_ShapeTestMessages {
+    int count;
+    int dabs;
+    int fill_rects;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_ShapeTestMessages;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_ShapeTestMessages *stms = user;
+    switch (DP_message_type(msg)) {
+    case DP_MSG_DRAW_DABS_PIXEL:
+    case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+        stms->dabs +=
+            DP_msg_draw_dabs_pixel_dabs_count(DP_message_internal(msg));
+        break;
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        stms->dabs +=
+            DP_msg_draw_dabs_classic_dabs_count(DP_message_internal(msg));
+        break;
+    case DP_MSG_FILL_RECT:
+        ++stms->fill_rects;
+        break;
+    default:
+        break;
+    }
+    if (stms->count < MAX_MESSAGES) {
+        stms->msgs[stms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void init_brush(DP_ClassicBrush *cb, DP_BrushShape shape, float size,
+                       float spacing)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = size;
+    cb->size.max = size;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = spacing;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f};
+    cb->shape = shape;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+static void stroke_shape(const DP_ClassicBrush *cb, DP_StrokeShape shape,
+                         float x1, float y1, float x2, float y2, bool fill,
+                         DP_ShapeTestMessages *stms)
+{
+    *stms = (DP_ShapeTestMessages){0};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, stms);
+    DP_StrokeParams stroke = {LAYER_ID, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    long long time_msec =
+        DP_brush_engine_stroke_shape(be, shape, x1, y1, x2, y2, fill, 0, NULL);
+    DP_brush_engine_stroke_end(be, time_msec + 10, NULL, true);
+    DP_brush_engine_free(be);
+}
+
+// Replays the messages onto a fresh canvas and checks its layer against the
+// expected picture, with a # for every opaque pixel and a . for every
+// transparent one. Anything in between shows up as a +.
+static void check_pixels(TEST_PARAMS, DP_ShapeTestMessages *stms,
+                         const char *expected[CANVAS_SIZE], const char *title)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    for (int i = 0; i < stms->count; ++i) {
+        handle(TEST_ARGS, ch, dc, stms->msgs[i]);
+    }
+    stms->count = 0;
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    bool matches = true;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        char row[CANVAS_SIZE + 1];
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            uint16_t a = DP_layer_content_pixel_at(lc, x, y).a;
+            row[x] = a == DP_BIT15 ? '#' : a == 0 ? '.' : '+';
+        }
+        row[CANVAS_SIZE] = '\0';
+        if (strcmp(row, expected[y]) != 0) {
+            matches = false;
+            DIAG("row %2d is   %s", y, row);
+            DIAG("expected    %s", expected[y]);
+        }
+    }
+    OK(matches, "%s", title);
+
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void shape_line_45_degrees(TEST_PARAMS)
+{
+    static const char *expected[CANVAS_SIZE] = {
+        "................", "................", "..#.............",
+        "...#............", "....#...........", ".....#..........",
+        "......#.........", ".......#........", "........#.......",
+        ".........#......", "..........#.....", "...........#....",
+        "............#...", ".............#..", "................",
+        "................",
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, 0.1f);
+    DP_ShapeTestMessages stms;
+    stroke_shape(&cb, DP_STROKE_SHAPE_LINE, 2.5f, 2.5f, 13.5f, 13.5f, false,
+                 &stms);
+    INT_EQ_OK(stms.dabs, 12, "one dab per pixel of the line");
+    check_pixels(TEST_ARGS, &stms, expected, "45 degree line");
+}
+
+static void shape_rectangle_1px(TEST_PARAMS)
+{
+    static const char *expected[CANVAS_SIZE] = {
+        "................", "................", "................",
+        "..########......", "..#......#......", "..#......#......",
+        "..#......#......", "..########......", "................",
+        "................", "................", "................",
+        "................", "................", "................",
+        "................",
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE, 1.0f, 0.1f);
+    // The points can come in either order, the box comes out the same.
+    DP_ShapeTestMessages stms;
+    stroke_shape(&cb, DP_STROKE_SHAPE_RECTANGLE, 9.9f, 7.2f, 2.0f, 3.0f, false,
+                 &stms);
+    INT_EQ_OK(stms.dabs, 22, "one dab per pixel of the outline");
+    check_pixels(TEST_ARGS, &stms, expected, "one pixel rectangle");
+}
+
+static void shape_ellipse_filled(TEST_PARAMS)
+{
+    static const char *expected[CANVAS_SIZE] = {
+        "................", "................", "................",
+        ".....######.....", "...##########...", "..############..",
+        "..############..", "..############..", "..############..",
+        "...##########...", ".....######.....", "................",
+        "................", "................", "................",
+        "................",
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 4.0f, 0.1f);
+    DP_ShapeTestMessages stms;
+    stroke_shape(&cb, DP_STROKE_SHAPE_ELLIPSE, 2.0f, 3.0f, 13.0f, 10.0f, true,
+                 &stms);
+    INT_EQ_OK(stms.dabs, 0, "filled ellipse has no dabs");
+    INT_EQ_OK(stms.fill_rects, 5, "one fill rect per run of equal rows");
+    check_pixels(TEST_ARGS, &stms, expected, "filled ellipse");
+}
+
+static void shape_ellipse_1px(TEST_PARAMS)
+{
+    static const char *expected[CANVAS_SIZE] = {
+        "................", ".....######.....", "...##......##...",
+        "..#..........#..", ".#............#.", ".#............#.",
+        ".#............#.", ".#............#.", ".#............#.",
+        "..#..........#..", "...##......##...", ".....######.....",
+        "................", "................", "................",
+        "................",
+    };
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND, 1.0f, 0.1f);
+    DP_ShapeTestMessages stms;
+    stroke_shape(&cb, DP_STROKE_SHAPE_ELLIPSE, 1.0f, 1.0f, 14.0f, 11.0f, false,
+                 &stms);
+    INT_EQ_OK(stms.dabs, 34, "one dab per pixel of the outline");
+    check_pixels(TEST_ARGS, &stms, expected, "one pixel ellipse");
+}
+
+static void shape_spacing_continues_around_corners(TEST_PARAMS)
+{
+    // A perimeter of 124 pixels with a dab every 5 pixels, starting at 0.
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND, 10.0f, 0.5f);
+    DP_ShapeTestMessages stms;
+    stroke_shape(&cb, DP_STROKE_SHAPE_RECTANGLE, 10.0f, 10.0f, 51.0f, 31.0f,
+                 false, &stms);
+    INT_EQ_OK(stms.dabs, 25, "dabs are spaced evenly along the outline");
+    for (int i = 0; i < stms.count; ++i) {
+        DP_message_decref(stms.msgs[i]);
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(shape_line_45_degrees);
+    REGISTER_TEST(shape_rectangle_1px);
+    REGISTER_TEST(shape_ellipse_filled);
+    REGISTER_TEST(shape_ellipse_1px);
+    REGISTER_TEST(shape_spacing_continues_around_corners);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/tile.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <string.h>
+
+
+#define WIDTH    200
+#define HEIGHT   150
+#define LAYER_ID 257
+#define USER     1
+
+static const DP_CanvasHistorySizeLimits limits = {16, 16, 400, 300, 100000};
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_canvas_history_size_limits_set(ch, &limits);
+    OK(handle(ch, dc, DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0)),
+       "resize canvas");
+    OK(handle(ch, dc,
+              DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                           5)),
+       "create layer");
+    return ch;
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+static DP_Message *put_tile(uint16_t col, uint16_t row, uint32_t color)
+{
+    return DP_msg_put_tile_new(USER, LAYER_ID, 0, col, row, 0, set_color, 4,
+                               &color);
+}
+
+// The image data doesn't matter, it shouldn't get as far as decompressing it.
+static void set_garbage(size_t size, unsigned char *out,
+                        DP_UNUSED void *user)
+{
+    memset(out, 0xab, size);
+}
+
+static DP_Message *put_image(uint32_t x, uint32_t y, uint32_t w, uint32_t h)
+{
+    return DP_msg_put_image_new(USER, LAYER_ID, 0, x, y, w, h, set_garbage, 64,
+                                NULL);
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+static int history_count(DP_CanvasHistory *ch)
+{
+    DP_CanvasHistorySnapshot *chs = DP_canvas_history_snapshot_new(ch);
+    int count = DP_canvas_history_snapshot_history_count(chs);
+    DP_canvas_history_snapshot_decref(chs);
+    return count;
+}
+
+static void size_is(TEST_PARAMS, DP_CanvasHistory *ch, int width, int height)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    INT_EQ_OK(DP_canvas_state_width(cs), width, "canvas width is %d", width);
+    INT_EQ_OK(DP_canvas_state_height(cs), height, "canvas height is %d",
+              height);
+    DP_canvas_state_decref(cs);
+}
+
+static void rejected(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                     DP_Message *msg, const char *what)
+{
+    uint64_t before = checksum(ch);
+    int count = history_count(ch);
+    NOK(handle(ch, dc, msg), "%s is rejected (error: %s)", what, DP_error());
+    OK(checksum(ch) == before, "%s doesn't change the canvas", what);
+    INT_EQ_OK(history_count(ch), count, "%s doesn't go into the history",
+              what);
+}
+
+
+static void default_limits(TEST_PARAMS)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_CanvasHistorySizeLimits l = DP_canvas_history_size_limits(ch);
+    INT_EQ_OK(l.min_width, 1, "default minimum width");
+    INT_EQ_OK(l.min_height, 1, "default minimum height");
+    INT_EQ_OK(l.max_width, INT16_MAX, "default maximum width");
+    INT_EQ_OK(l.max_height, INT16_MAX, "default maximum height");
+    OK(l.max_pixels == (long long)INT16_MAX * (long long)INT16_MAX,
+       "default maximum pixels");
+
+    DP_canvas_history_size_limits_set(ch, &limits);
+    l = DP_canvas_history_size_limits(ch);
+    OK(l.min_width == limits.min_width && l.min_height == limits.min_height
+           && l.max_width == limits.max_width
+           && l.max_height == limits.max_height
+           && l.max_pixels == limits.max_pixels,
+       "limits can be queried after setting them");
+    DP_canvas_history_free(ch);
+}
+
+static void hostile_resizes(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    OK(handle(ch, dc, put_tile(0, 0, 0xff336699)), "put a tile");
+
+    rejected(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(USER, 0, -190, 0, 0),
+             "resize below minimum width");
+    rejected(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(USER, 0, 0, -140, 0),
+             "resize below minimum height");
+    rejected(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(USER, 0, 201, 0, 0),
+             "resize above maximum width");
+    rejected(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(USER, 151, 0, 0, 0),
+             "resize above maximum height");
+    rejected(TEST_ARGS, ch, dc,
+             DP_msg_canvas_resize_new(USER, INT32_MAX, INT32_MAX, INT32_MAX,
+                                      INT32_MAX),
+             "resize by absurd amounts");
+    rejected(TEST_ARGS, ch, dc,
+             DP_msg_canvas_resize_new(USER, 0, 150, 150, 0),
+             "resize above maximum pixels");
+    size_is(TEST_ARGS, ch, WIDTH, HEIGHT);
+
+    OK(handle(ch, dc, DP_msg_canvas_resize_new(USER, 100, 100, 0, 0)),
+       "resize within limits still works");
+    size_is(TEST_ARGS, ch, 300, 250);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void hostile_puts(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+
+    rejected(TEST_ARGS, ch, dc, put_image(WIDTH, 0, 10, 10),
+             "put image right of the canvas");
+    rejected(TEST_ARGS, ch, dc, put_image(0, HEIGHT, 10, 10),
+             "put image below the canvas");
+    rejected(TEST_ARGS, ch, dc, put_image(0, 0, 401, 1),
+             "put image wider than the limit");
+    rejected(TEST_ARGS, ch, dc, put_image(0, 0, 400, 300),
+             "put image with too many pixels");
+    rejected(TEST_ARGS, ch, dc, put_image(0, 0, UINT32_MAX, UINT32_MAX),
+             "put image of absurd size");
+
+    DP_TileCounts tile_counts = DP_tile_counts_round(WIDTH, HEIGHT);
+    rejected(TEST_ARGS, ch, dc,
+             put_tile(DP_int_to_uint16(tile_counts.x), 0, 0xff336699),
+             "put tile right of the canvas");
+    rejected(TEST_ARGS, ch, dc,
+             put_tile(0, DP_int_to_uint16(tile_counts.y), 0xff336699),
+             "put tile below the canvas");
+    rejected(TEST_ARGS, ch, dc, put_tile(UINT16_MAX, UINT16_MAX, 0xff336699),
+             "put tile far outside of the canvas");
+
+    uint64_t before = checksum(ch);
+    OK(handle(ch, dc,
+              put_tile(DP_int_to_uint16(tile_counts.x - 1),
+                       DP_int_to_uint16(tile_counts.y - 1), 0xff336699)),
+       "put tile in the last tile still works");
+    OK(checksum(ch) != before, "and changes the canvas");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void hostile_local_messages(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    uint64_t before = checksum(ch);
+
+    DP_Message *msg = DP_msg_canvas_resize_new(USER, 0, 1000, 1000, 0);
+    NOK(DP_canvas_history_handle_local(ch, dc, msg),
+        "local resize above the limits is rejected");
+    DP_message_decref(msg);
+    INT_EQ_OK(DP_canvas_history_local_fork_count(ch), 0,
+              "rejected message doesn't go into the local fork");
+    OK(checksum(ch) == before, "canvas is unchanged");
+    size_is(TEST_ARGS, ch, WIDTH, HEIGHT);
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(default_limits);
+    REGISTER_TEST(hostile_resizes);
+    REGISTER_TEST(hostile_puts);
+    REGISTER_TEST(hostile_local_messages);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
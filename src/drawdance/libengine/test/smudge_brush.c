@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define RED_LAYER_ID   257
+#define BLUE_LAYER_ID  258
+#define EMPTY_LAYER_ID 259
+#define CANVAS_WIDTH   128
+#define CANVAS_HEIGHT  32
+#define MAX_DABS       256
+
+typedef struct DP_SmudgeTestDabs {
+    int count;
+    uint32_t colors[MAX_DABS];
+} DP_SmudgeTestDabs;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_dabs(void *user, DP_Message *msg)
+{
+    DP_SmudgeTestDabs *stds = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_CLASSIC) {
+        DP_MsgDrawDabsClassic *mddc = DP_message_internal(msg);
+        int count = DP_msg_draw_dabs_classic_dabs_count(mddc);
+        uint32_t color = DP_msg_draw_dabs_classic_color(mddc);
+        for (int i = 0; i < count && stds->count < MAX_DABS; ++i) {
+            stds->colors[stds->count++] = color;
+        }
+    }
+    DP_message_decref(msg);
+}
+
+static int color_red(uint32_t color)
+{
+    return (int)((color >> 16) & 0xffu);
+}
+
+static int color_blue(uint32_t color)
+{
+    return (int)(color & 0xffu);
+}
+
+// Left half of the canvas is red, right half is blue. If the layers are
+// separate, the red is on the bottom layer. There's always an empty layer on
+// top of everything.
+static DP_CanvasState *make_red_blue_canvas(TEST_PARAMS, DP_DrawContext *dc,
+                                            bool separate_layers)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_WIDTH, CANVAS_HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, RED_LAYER_ID, 0, 0, 0, 0, "Red", 3));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, RED_LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                CANVAS_WIDTH / 2, CANVAS_HEIGHT, 0xffff0000));
+    int blue_layer_id = RED_LAYER_ID;
+    if (separate_layers) {
+        blue_layer_id = BLUE_LAYER_ID;
+        handle(TEST_ARGS, ch, dc,
+               DP_msg_layer_tree_create_new(1, BLUE_LAYER_ID, 0, 0, 0, 0,
+                                            "Blue", 4));
+    }
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, (uint16_t)blue_layer_id,
+                                DP_BLEND_MODE_NORMAL, CANVAS_WIDTH / 2, 0,
+                                CANVAS_WIDTH / 2, CANVAS_HEIGHT, 0xff0000ff));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, EMPTY_LAYER_ID, 0, 0, 0, 0, "Empty",
+                                        5));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+static void init_smudge_brush(DP_ClassicBrush *cb,
+                              DP_BrushPickupMode pickup_mode)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 8.0f;
+    cb->size.max = 8.0f;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->smudge.min = 0.5f;
+    cb->smudge.max = 0.5f;
+    cb->spacing = 0.25f;
+    cb->pickup_mode = pickup_mode;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+// Strokes on the given layer from the red into the blue half of the canvas.
+static void smudge_stroke(DP_CanvasState *cs, const DP_ClassicBrush *cb,
+                          int layer_id, DP_SmudgeTestDabs *stds)
+{
+    stds->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_dabs, NULL, stds);
+    DP_StrokeParams stroke = {layer_id, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    float y = CANVAS_HEIGHT / 2.0f;
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){16.0f, y, 1.0f, 0.0f, 0.0f, 0.0f, 0}, cs);
+    DP_brush_engine_stroke_to(
+        be,
+        (DP_BrushPoint){CANVAS_WIDTH - 16.0f, y, 1.0f, 0.0f, 0.0f, 0.0f, 100},
+        cs);
+    DP_brush_engine_stroke_end(be, 200, cs, false);
+    DP_brush_engine_free(be);
+}
+
+
+static void smudge_gradient(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_red_blue_canvas(TEST_ARGS, dc, false);
+    DP_ClassicBrush cb;
+    init_smudge_brush(&cb, DP_BRUSH_PICKUP_MODE_LAYER);
+    DP_SmudgeTestDabs stds;
+    smudge_stroke(cs, &cb, RED_LAYER_ID, &stds);
+
+    if (OK(stds.count > 2, "stroke has %d dabs", stds.count)) {
+        uint32_t first = stds.colors[0];
+        uint32_t last = stds.colors[stds.count - 1];
+        OK(color_red(first) == 255 && color_blue(first) == 0,
+           "first dab is red, got %06x", (unsigned int)(first & 0xffffffu));
+        OK(color_blue(last) > 200 && color_red(last) < 55,
+           "last dab is blue, got %06x", (unsigned int)(last & 0xffffffu));
+
+        bool monotonic = true;
+        bool blended = false;
+        for (int i = 1; i < stds.count; ++i) {
+            uint32_t prev = stds.colors[i - 1];
+            uint32_t color = stds.colors[i];
+            if (color_red(color) > color_red(prev)
+                || color_blue(color) < color_blue(prev)) {
+                monotonic = false;
+            }
+            if (color_red(color) > 64 && color_red(color) < 192
+                && color_blue(color) > 64 && color_blue(color) < 192) {
+                blended = true;
+            }
+        }
+        OK(monotonic, "color goes from red to blue without reversing");
+        OK(blended, "there's a blend of red and blue in between");
+    }
+
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+static void smudge_transparent_keeps_color(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_red_blue_canvas(TEST_ARGS, dc, true);
+    DP_ClassicBrush cb;
+    init_smudge_brush(&cb, DP_BRUSH_PICKUP_MODE_LAYER);
+    DP_SmudgeTestDabs stds;
+    smudge_stroke(cs, &cb, RED_LAYER_ID, &stds);
+
+    if (OK(stds.count > 2, "stroke has %d dabs", stds.count)) {
+        bool all_red = true;
+        for (int i = 0; i < stds.count; ++i) {
+            if (color_red(stds.colors[i]) != 255
+                || color_blue(stds.colors[i]) != 0) {
+                all_red = false;
+            }
+        }
+        OK(all_red, "smudging over transparency keeps the held color");
+    }
+
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+static void pickup_merged_samples_other_layers(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_red_blue_canvas(TEST_ARGS, dc, true);
+    DP_ClassicBrush cb;
+    init_smudge_brush(&cb, DP_BRUSH_PICKUP_MODE_MERGED);
+    DP_SmudgeTestDabs stds;
+    smudge_stroke(cs, &cb, RED_LAYER_ID, &stds);
+
+    if (OK(stds.count > 2, "stroke has %d dabs", stds.count)) {
+        uint32_t last = stds.colors[stds.count - 1];
+        OK(color_blue(last) > 200 && color_red(last) < 55,
+           "last dab picked up blue from the other layer, got %06x",
+           (unsigned int)(last & 0xffffffu));
+    }
+
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+static void pickup_merged_on_empty_layer(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_red_blue_canvas(TEST_ARGS, dc, false);
+    DP_ClassicBrush cb;
+    DP_SmudgeTestDabs stds;
+
+    // Green brush, so that any red or blue must have been picked up.
+    init_smudge_brush(&cb, DP_BRUSH_PICKUP_MODE_LAYER);
+    cb.color = (DP_UPixelFloat){0.0f, 1.0f, 0.0f, 1.0f};
+    smudge_stroke(cs, &cb, EMPTY_LAYER_ID, &stds);
+    if (OK(stds.count > 2, "layer pickup stroke has %d dabs", stds.count)) {
+        bool any_picked_up = false;
+        for (int i = 0; i < stds.count; ++i) {
+            if (color_red(stds.colors[i]) != 0
+                || color_blue(stds.colors[i]) != 0) {
+                any_picked_up = true;
+            }
+        }
+        OK(!any_picked_up, "layer pickup on an empty layer picks up nothing");
+    }
+
+    init_smudge_brush(&cb, DP_BRUSH_PICKUP_MODE_MERGED);
+    smudge_stroke(cs, &cb, EMPTY_LAYER_ID, &stds);
+    if (OK(stds.count > 2, "merged pickup stroke has %d dabs", stds.count)) {
+        uint32_t first = stds.colors[0];
+        uint32_t last = stds.colors[stds.count - 1];
+        OK(color_red(first) > 200,
+           "first dab picked up red from below, got %06x",
+           (unsigned int)(first & 0xffffffu));
+        OK(color_blue(last) > 200 && color_red(last) < 55,
+           "last dab picked up blue from below, got %06x",
+           (unsigned int)(last & 0xffffffu));
+    }
+
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(smudge_gradient);
+    REGISTER_TEST(smudge_transparent_keeps_color);
+    REGISTER_TEST(pickup_merged_samples_other_layers);
+    REGISTER_TEST(pickup_merged_on_empty_layer);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
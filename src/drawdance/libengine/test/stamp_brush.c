@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/stamp_mask.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define LAYER_ID    257
+#define CANVAS_SIZE 64
+#define MASK_SIZE   32
+
+typedef struct DP_StampTestDab {
+    uint16_t size;
+    uint8_t angle;
+} DP_StampTestDab;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_stamp_dab(DP_UNUSED int count, DP_StampDab *dabs, void *user)
+{
+    DP_StampTestDab *std = user;
+    DP_stamp_dab_init(dabs, 0, 0, 0, std->size, std->angle, 255);
+}
+
+// Only the left half of the mask is covered, so rotations are visible.
+static DP_StampMask *make_half_mask(void)
+{
+    uint8_t data[MASK_SIZE * MASK_SIZE];
+    for (int y = 0; y < MASK_SIZE; ++y) {
+        for (int x = 0; x < MASK_SIZE; ++x) {
+            data[y * MASK_SIZE + x] = x < MASK_SIZE / 2 ? 255 : 0;
+        }
+    }
+    return DP_stamp_mask_new(MASK_SIZE, data);
+}
+
+static DP_StampMask *make_disc_mask(void)
+{
+    uint8_t data[MASK_SIZE * MASK_SIZE];
+    double r = MASK_SIZE / 2.0;
+    for (int y = 0; y < MASK_SIZE; ++y) {
+        for (int x = 0; x < MASK_SIZE; ++x) {
+            double dx = x + 0.5 - r;
+            double dy = y + 0.5 - r;
+            data[y * MASK_SIZE + x] = dx * dx + dy * dy < r * r ? 255 : 0;
+        }
+    }
+    return DP_stamp_mask_new(MASK_SIZE, data);
+}
+
+// Sends the mask definition if given, then draws a single dab referring to
+// the given mask id in the middle of an empty canvas. Returns the layer's
+// alpha values, CANVAS_SIZE * CANVAS_SIZE of them, to be freed by the caller.
+static uint16_t *draw_dab(TEST_PARAMS, DP_DrawContext *dc,
+                          DP_StampMask *sm_or_null, uint32_t mask_id,
+                          double diameter, uint8_t angle)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    if (sm_or_null) {
+        handle(TEST_ARGS, ch, dc, DP_stamp_mask_to_message(sm_or_null, 1));
+    }
+
+    DP_StampTestDab std = {(uint16_t)(diameter * 256.0), angle};
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_draw_dabs_stamp_new(1, LAYER_ID, CANVAS_SIZE * 4 / 2,
+                                      CANVAS_SIZE * 4 / 2, 0xff000000,
+                                      DP_BLEND_MODE_NORMAL, mask_id, 0, 256,
+                                      0, set_stamp_dab, 1, &std));
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(1));
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    uint16_t *alphas =
+        DP_malloc(sizeof(*alphas) * CANVAS_SIZE * CANVAS_SIZE);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            alphas[y * CANVAS_SIZE + x] = DP_layer_content_pixel_at(lc, x, y).a;
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    return alphas;
+}
+
+static double sum_alphas(const uint16_t *alphas)
+{
+    double sum = 0.0;
+    for (int i = 0; i < CANVAS_SIZE * CANVAS_SIZE; ++i) {
+        sum += alphas[i] / (double)DP_BIT15;
+    }
+    return sum;
+}
+
+// Coverage-weighted mean x coordinate, relative to the middle of the canvas.
+static double centroid_x(const uint16_t *alphas)
+{
+    double sum = 0.0;
+    double weighted = 0.0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            double a = alphas[y * CANVAS_SIZE + x] / (double)DP_BIT15;
+            sum += a;
+            weighted += a * (x + 0.5 - CANVAS_SIZE / 2.0);
+        }
+    }
+    return sum > 0.0 ? weighted / sum : 0.0;
+}
+
+
+static void stamp_mask_id_is_checksum(TEST_PARAMS)
+{
+    DP_StampMask *sm = make_half_mask();
+    if (OK(sm != NULL, "mask created")) {
+        UINT_EQ_OK(DP_stamp_mask_id(sm),
+                   DP_stamp_mask_checksum(MASK_SIZE, DP_stamp_mask_data(sm)),
+                   "mask id is its checksum");
+
+        DP_Message *msg = DP_stamp_mask_to_message(sm, 1);
+        DP_MsgStampMask *msm = DP_message_internal(msg);
+        UINT_EQ_OK(DP_msg_stamp_mask_mask(msm), DP_stamp_mask_id(sm),
+                   "message carries mask id");
+        DP_StampMask *copy = DP_stamp_mask_new_from_message(msm);
+        if (OK(copy != NULL, "mask created from message")) {
+            UINT_EQ_OK(DP_stamp_mask_id(copy), DP_stamp_mask_id(sm),
+                       "mask from message has same id");
+            DP_stamp_mask_decref(copy);
+        }
+        DP_message_decref(msg);
+        DP_stamp_mask_decref(sm);
+    }
+}
+
+static void stamp_mask_bad_size_fails(TEST_PARAMS)
+{
+    uint8_t data[1] = {255};
+    NOK(DP_stamp_mask_new(0, data), "zero size mask fails");
+    NOK(DP_stamp_mask_new(DP_STAMP_MASK_MAX_SIZE + 1, data),
+        "oversized mask fails");
+}
+
+static void stamp_dab_draws_mask(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_half_mask();
+    uint16_t *alphas =
+        draw_dab(TEST_ARGS, dc, sm, DP_stamp_mask_id(sm), 32.0, 0);
+    OK(sum_alphas(alphas) > 0.0, "stamp dab is not empty");
+    OK(centroid_x(alphas) < -4.0, "unrotated coverage is on the left");
+    DP_free(alphas);
+    DP_stamp_mask_decref(sm);
+    DP_draw_context_free(dc);
+}
+
+static void stamp_dab_unknown_mask_draws_nothing(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_half_mask();
+    uint32_t unknown_id = DP_stamp_mask_id(sm) ^ 0xffu;
+    NOK(DP_stamp_mask_search_inc(unknown_id), "mask id is not registered");
+    uint16_t *alphas = draw_dab(TEST_ARGS, dc, NULL, unknown_id, 32.0, 0);
+    OK(sum_alphas(alphas) == 0.0, "unknown mask draws nothing");
+    DP_free(alphas);
+    DP_stamp_mask_decref(sm);
+    DP_draw_context_free(dc);
+}
+
+static void stamp_dab_rotates(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *half = make_half_mask();
+    uint16_t *alphas =
+        draw_dab(TEST_ARGS, dc, half, DP_stamp_mask_id(half), 32.0, 128);
+    OK(centroid_x(alphas) > 4.0, "half turn moves coverage to the right");
+    DP_free(alphas);
+    DP_stamp_mask_decref(half);
+
+    DP_StampMask *disc = make_disc_mask();
+    uint16_t *unrotated =
+        draw_dab(TEST_ARGS, dc, disc, DP_stamp_mask_id(disc), 32.0, 0);
+    uint16_t *rotated =
+        draw_dab(TEST_ARGS, dc, disc, DP_stamp_mask_id(disc), 32.0, 128);
+    double unrotated_sum = sum_alphas(unrotated);
+    double rotated_sum = sum_alphas(rotated);
+    OK(rotated_sum > unrotated_sum * 0.99 && rotated_sum < unrotated_sum * 1.01,
+       "rotated disc covers %g, unrotated covers %g", rotated_sum,
+       unrotated_sum);
+    DP_free(rotated);
+    DP_free(unrotated);
+    DP_stamp_mask_decref(disc);
+    DP_draw_context_free(dc);
+}
+
+static void stamp_dab_scales(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_StampMask *sm = make_disc_mask();
+    uint16_t *small =
+        draw_dab(TEST_ARGS, dc, sm, DP_stamp_mask_id(sm), 16.0, 0);
+    uint16_t *large =
+        draw_dab(TEST_ARGS, dc, sm, DP_stamp_mask_id(sm), 32.0, 0);
+    double small_sum = sum_alphas(small);
+    double large_sum = sum_alphas(large);
+    OK(large_sum > small_sum * 3.5 && large_sum < small_sum * 4.5,
+       "doubling the diameter quadruples coverage, %g vs. %g", large_sum,
+       small_sum);
+    DP_free(large);
+    DP_free(small);
+    DP_stamp_mask_decref(sm);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(stamp_mask_id_is_checksum);
+    REGISTER_TEST(stamp_mask_bad_size_fails);
+    REGISTER_TEST(stamp_dab_draws_mask);
+    REGISTER_TEST(stamp_dab_unknown_mask_draws_nothing);
+    REGISTER_TEST(stamp_dab_rotates);
+    REGISTER_TEST(stamp_dab_scales);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
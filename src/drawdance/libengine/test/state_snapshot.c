@@ -0,0 +1,259 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpengine/annotation.h>
+#include <dpengine/annotation_list.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/player.h>
+#include <dpengine/recorder.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+// A recorder given a canvas state starts its recording with the reset image
+// of it, so recording nothing else into memory gives a snapshot of the whole
+// canvas that a player can restore again, with a versioned header up front.
+
+#define USER     1
+#define LAYER_ID 257
+#define GROUP_ID 258
+#define CHILD_ID 259
+
+static long long get_time(DP_UNUSED void *user)
+{
+    return 0;
+}
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void fill(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                 int layer_id, int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(USER, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(w),
+                                DP_int_to_uint32(h), color));
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+// Opaque colors only, the reset image carries 8 bit pixels.
+static DP_CanvasState *make_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc, DP_msg_canvas_resize_new(USER, 0, 200, 150, 0));
+    uint32_t background = 0xff8090a0u;
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_background_new(USER, set_color, 4, &background));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                        5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, GROUP_ID, 0, 0, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                        "Group", 5));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER, CHILD_ID, 0, GROUP_ID, 0,
+                                        DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO,
+                                        "Child", 5));
+    fill(TEST_ARGS, ch, dc, LAYER_ID, 10, 10, 120, 80, 0xffcc2200u);
+    fill(TEST_ARGS, ch, dc, CHILD_ID, 70, 40, 100, 100, 0xff004488u);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_attributes_new(USER, CHILD_ID, 0, 0, 200,
+                                       DP_BLEND_MODE_MULTIPLY));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_create_new(USER, 0x101, 20, 30, 90, 40));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_annotation_edit_new(USER, 0x101, 0xff112233u, 0, 0,
+                                      "Hello", 5));
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+// The recorder closes its output when it's done, so it writes into a buffer
+// that outlives it instead of a regular memory output.
+typedef struct SnapshotBuffer {
+    unsigned char *data;
+    size_t size;
+    size_t capacity;
+} SnapshotBuffer;
+
+static size_t snapshot_buffer_write(void *internal, const void *data,
+                                    size_t size)
+{
+    SnapshotBuffer *sb = *(SnapshotBuffer **)internal;
+    if (sb->size + size > sb->capacity) {
+        sb->capacity = DP_max_size(sb->capacity * 2, sb->size + size);
+        sb->data = DP_realloc(sb->data, sb->capacity);
+    }
+    memcpy(sb->data + sb->size, data, size);
+    sb->size += size;
+    return size;
+}
+
+static const DP_OutputMethods snapshot_buffer_methods = {
+    snapshot_buffer_write, NULL, NULL, NULL, NULL, NULL,
+};
+
+static const DP_OutputMethods *snapshot_buffer_init(void *internal, void *arg)
+{
+    *(SnapshotBuffer **)internal = arg;
+    return &snapshot_buffer_methods;
+}
+
+static void *snapshot(TEST_PARAMS, DP_CanvasState *cs, size_t *out_size)
+{
+    SnapshotBuffer sb = {NULL, 0, 0};
+    DP_Output *output =
+        DP_output_new(snapshot_buffer_init, &sb, sizeof(SnapshotBuffer *));
+    DP_Recorder *r = DP_recorder_new_inc(DP_RECORDER_TYPE_BINARY,
+                                         DP_recorder_header_new(NULL), cs,
+                                         get_time, NULL, output);
+    FATAL(NOT_NULL_OK(r, "make recorder (error: %s)", DP_error()));
+    char *error;
+    DP_recorder_free_join(r, &error);
+    OK(!error, "recorder finished without error (%s)", error ? error : "");
+    DP_free(error);
+    *out_size = sb.size;
+    return sb.data;
+}
+
+// Takes the buffer, returns NULL if the player rejects it or chokes on it.
+static DP_CanvasState *restore(DP_DrawContext *dc, void *buffer, size_t size)
+{
+    DP_Player *player = DP_player_new(
+        DP_PLAYER_TYPE_BINARY, NULL,
+        DP_mem_input_new_free_on_close(buffer, size), NULL);
+    if (!player) {
+        return NULL;
+    }
+
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    DP_PlayerResult result;
+    DP_Message *msg;
+    while ((result = DP_player_step_raw(player, &msg)) == DP_PLAYER_SUCCESS) {
+        bool ok = DP_message_type(msg) < 128
+               || DP_canvas_history_handle(ch, dc, msg);
+        DP_message_decref(msg);
+        if (!ok) {
+            result = DP_PLAYER_ERROR_OPERATION;
+            break;
+        }
+    }
+    DP_player_free(player);
+
+    DP_CanvasState *cs =
+        result == DP_PLAYER_RECORDING_END ? DP_canvas_history_get(ch) : NULL;
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+static bool same_annotations(DP_CanvasState *a, DP_CanvasState *b)
+{
+    DP_AnnotationList *al = DP_canvas_state_annotations_noinc(a);
+    DP_AnnotationList *bl = DP_canvas_state_annotations_noinc(b);
+    int count = DP_annotation_list_count(al);
+    if (count != DP_annotation_list_count(bl)) {
+        return false;
+    }
+    for (int i = 0; i < count; ++i) {
+        if (!DP_annotation_equal(DP_annotation_list_at_noinc(al, i),
+                                 DP_annotation_list_at_noinc(bl, i))) {
+            return false;
+        }
+    }
+    return true;
+}
+
+static bool same_background(DP_CanvasState *a, DP_CanvasState *b)
+{
+    DP_Tile *at = DP_canvas_state_background_tile_noinc(a);
+    DP_Tile *bt = DP_canvas_state_background_tile_noinc(b);
+    if (at && bt) {
+        return memcmp(DP_tile_pixels(at), DP_tile_pixels(bt), DP_TILE_BYTES)
+            == 0;
+    }
+    else {
+        return !at && !bt;
+    }
+}
+
+
+static void snapshot_round_trips(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+
+    size_t size;
+    void *buffer = snapshot(TEST_ARGS, cs, &size);
+    DP_CanvasState *restored = restore(dc, buffer, size);
+    FATAL(NOT_NULL_OK(restored, "restore snapshot (error: %s)", DP_error()));
+
+    INT_EQ_OK(DP_canvas_state_width(restored), DP_canvas_state_width(cs),
+              "restored width");
+    INT_EQ_OK(DP_canvas_state_height(restored), DP_canvas_state_height(cs),
+              "restored height");
+    UINT_EQ_OK(DP_canvas_state_checksum(restored),
+               DP_canvas_state_checksum(cs), "restored checksum matches");
+    OK(same_annotations(restored, cs), "restored annotations match");
+    OK(same_background(restored, cs), "restored background matches");
+
+    DP_canvas_state_decref(restored);
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+static void snapshot_rejects_damage(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+    size_t size;
+    unsigned char *original = snapshot(TEST_ARGS, cs, &size);
+
+    unsigned char *garbage = DP_malloc(size);
+    memcpy(garbage, original, size);
+    garbage[0] ^= 0xffu;
+    OK(!restore(dc, garbage, size), "mangled header is rejected");
+
+    // Cut off in the middle of the reset image, so a message is incomplete.
+    unsigned char *truncated = DP_malloc(size);
+    memcpy(truncated, original, size);
+    OK(!restore(dc, truncated, size - 3), "truncated snapshot is rejected");
+
+    DP_free(original);
+    DP_canvas_state_decref(cs);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(snapshot_round_trips);
+    REGISTER_TEST(snapshot_rejects_damage);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+
+
+#define MAX_DABS       1024
+#define POINT_COUNT    50
+#define POINT_STEP     2.0f
+#define POINT_MSEC     10
+#define ZIGZAG_HEIGHT  4.0f
+#define MIDDLE_START   20.0f
+#define MIDDLE_END     80.0f
+
+typedef struct DP_StabilizerTestDab {
+    float x, y;
+} DP_StabilizerTestDab;
+
+typedef struct DP_StabilizerTestDabs {
+    int count;
+    DP_StabilizerTestDab dabs[MAX_DABS];
+} DP_StabilizerTestDabs;
+
+static void collect_positions(void *user, DP_Message *msg)
+{
+    DP_StabilizerTestDabs *stds = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_CLASSIC) {
+        DP_MsgDrawDabsClassic *mddc = DP_message_internal(msg);
+        int x = DP_msg_draw_dabs_classic_x(mddc);
+        int y = DP_msg_draw_dabs_classic_y(mddc);
+        int count;
+        const DP_ClassicDab *dabs =
+            DP_msg_draw_dabs_classic_dabs(mddc, &count);
+        for (int i = 0; i < count && stds->count < MAX_DABS; ++i) {
+            const DP_ClassicDab *cd = DP_classic_dab_at(dabs, i);
+            x += DP_classic_dab_x(cd);
+            y += DP_classic_dab_y(cd);
+            stds->dabs[stds->count++] = (DP_StabilizerTestDab){
+                DP_int_to_float(x) / 4.0f, DP_int_to_float(y) / 4.0f};
+        }
+    }
+    DP_message_decref(msg);
+}
+
+static void init_brush(DP_ClassicBrush *cb)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 4.0f;
+    cb->size.max = 4.0f;
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+static float zigzag_x(int i)
+{
+    return DP_int_to_float(i) * POINT_STEP;
+}
+
+static float zigzag_y(int i)
+{
+    return i % 2 == 0 ? -ZIGZAG_HEIGHT : ZIGZAG_HEIGHT;
+}
+
+// Strokes to the right, jumping up and down by twice the zigzag height on
+// every point, which is what a shaky hand looks like when exaggerated.
+static void stroke_zigzag(const DP_StrokeParams *stroke,
+                          DP_StabilizerTestDabs *stds)
+{
+    stds->count = 0;
+    DP_ClassicBrush cb;
+    init_brush(&cb);
+    DP_BrushEngine *be = DP_brush_engine_new(collect_positions, NULL, stds);
+    DP_brush_engine_classic_brush_set(be, &cb, stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    for (int i = 0; i < POINT_COUNT; ++i) {
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){zigzag_x(i), zigzag_y(i), 1.0f, 0.0f, 0.0f, 0.0f,
+                            i * POINT_MSEC},
+            NULL);
+    }
+    DP_brush_engine_stroke_end(be, POINT_COUNT * POINT_MSEC, NULL, false);
+    DP_brush_engine_free(be);
+}
+
+// Largest vertical distance from the middle line, only looking at the part of
+// the stroke where the smoothing has had time to get going.
+static float middle_deviation(const DP_StabilizerTestDabs *stds)
+{
+    float deviation = 0.0f;
+    for (int i = 0; i < stds->count; ++i) {
+        const DP_StabilizerTestDab *std = &stds->dabs[i];
+        if (std->x >= MIDDLE_START && std->x <= MIDDLE_END) {
+            deviation = DP_max_float(deviation, fabsf(std->y));
+        }
+    }
+    return deviation;
+}
+
+static void check_stroke(TEST_PARAMS, const char *title,
+                         const DP_StabilizerTestDabs *stds)
+{
+    if (OK(stds->count > 2, "%s stroke has %d dabs", title, stds->count)) {
+        const DP_StabilizerTestDab *first = &stds->dabs[0];
+        OK(first->x == zigzag_x(0) && first->y == zigzag_y(0),
+           "%s stroke starts at the first point without lag, got %g, %g",
+           title, (double)first->x, (double)first->y);
+        const DP_StabilizerTestDab *last = &stds->dabs[stds->count - 1];
+        float dx = last->x - zigzag_x(POINT_COUNT - 1);
+        float dy = last->y - zigzag_y(POINT_COUNT - 1);
+        OK(fabsf(dx) <= 1.0f && fabsf(dy) <= 1.0f,
+           "%s stroke ends at the last point, got %g, %g", title,
+           (double)last->x, (double)last->y);
+    }
+}
+
+
+static void raw_stroke_follows_zigzag(TEST_PARAMS)
+{
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_StabilizerTestDabs stds;
+    stroke_zigzag(&stroke, &stds);
+    check_stroke(TEST_ARGS, "raw", &stds);
+    float deviation = middle_deviation(&stds);
+    OK(deviation > ZIGZAG_HEIGHT - 1.0f,
+       "raw stroke goes up and down by %g", (double)deviation);
+}
+
+static void smoothing_flattens_zigzag(TEST_PARAMS)
+{
+    DP_StrokeParams stroke = {1, false, 4, true, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_StabilizerTestDabs stds;
+    stroke_zigzag(&stroke, &stds);
+    check_stroke(TEST_ARGS, "smoothed", &stds);
+    float deviation = middle_deviation(&stds);
+    OK(deviation < 1.0f, "smoothed stroke only goes up and down by %g",
+       (double)deviation);
+}
+
+static void pull_string_flattens_zigzag(TEST_PARAMS)
+{
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 10,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_StabilizerTestDabs stds;
+    stroke_zigzag(&stroke, &stds);
+    check_stroke(TEST_ARGS, "pulled", &stds);
+    float deviation = middle_deviation(&stds);
+    OK(deviation < 1.0f, "pulled stroke only goes up and down by %g",
+       (double)deviation);
+}
+
+static void pull_string_waits_for_distance(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb);
+    DP_StabilizerTestDabs stds = {0, {{0.0f, 0.0f}}};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_positions, NULL, &stds);
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 10,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, &cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){0.0f, 0.0f, 1.0f, 0.0f, 0.0f, 0.0f, 0}, NULL);
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){8.0f, 0.0f, 1.0f, 0.0f, 0.0f, 0.0f, 10}, NULL);
+    DP_brush_engine_dabs_flush(be);
+    INT_EQ_OK(stds.count, 1, "moving within the string only draws first dab");
+    DP_brush_engine_stroke_to(
+        be, (DP_BrushPoint){20.0f, 0.0f, 1.0f, 0.0f, 0.0f, 0.0f, 20}, NULL);
+    DP_brush_engine_dabs_flush(be);
+    if (OK(stds.count > 1, "moving past the string draws dabs")) {
+        float x = stds.dabs[stds.count - 1].x;
+        OK(x >= 9.0f && x <= 10.0f,
+           "brush trails the input by the string, got %g", (double)x);
+    }
+    DP_brush_engine_stroke_end(be, 30, NULL, false);
+    OK(stds.count > 1 && stds.dabs[stds.count - 1].x >= 19.0f,
+       "ending the stroke catches up to the input, got %g",
+       (double)stds.dabs[stds.count - 1].x);
+    DP_brush_engine_free(be);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(raw_stroke_follows_zigzag);
+    REGISTER_TEST(smoothing_flattens_zigzag);
+    REGISTER_TEST(pull_string_flattens_zigzag);
+    REGISTER_TEST(pull_string_waits_for_distance);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,354 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <math.h>
+#include <stdlib.h>
+
+
+#define LAYER_ID     257
+#define CANVAS_SIZE  64
+#define MAX_MESSAGES 1024
+
+typedef struct DP_SymmetryTestMessages {
+    int count;
+    int dab_messages;
+    int dabs;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_SymmetryTestMessages;
+
+typedef DP_Pixel15 DP_SymmetryTestLayer[CANVAS_SIZE][CANVAS_SIZE];
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void collect_messages(void *user, DP_Message *msg)
+{
+    DP_SymmetryTestMessages *stms = user;
+    switch (DP_message_type(msg)) {
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        ++stms->dab_messages;
+        stms->dabs +=
+            DP_msg_draw_dabs_classic_dabs_count(DP_message_internal(msg));
+        break;
+    case DP_MSG_DRAW_DABS_PIXEL:
+    case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+        ++stms->dab_messages;
+        stms->dabs +=
+            DP_msg_draw_dabs_pixel_dabs_count(DP_message_internal(msg));
+        break;
+    case DP_MSG_DRAW_DABS_MYPAINT:
+        ++stms->dab_messages;
+        stms->dabs +=
+            DP_msg_draw_dabs_mypaint_dabs_count(DP_message_internal(msg));
+        break;
+    default:
+        break;
+    }
+    if (stms->count < MAX_MESSAGES) {
+        stms->msgs[stms->count++] = msg;
+    }
+    else {
+        DP_message_decref(msg);
+    }
+}
+
+static void init_soft_brush(DP_ClassicBrush *cb)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = 8.0f;
+    cb->size.max = 8.0f;
+    cb->hardness.max = 0.8f;
+    cb->opacity.max = 0.5f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 1.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+}
+
+static void init_pixel_brush(DP_ClassicBrush *cb, float size)
+{
+    init_soft_brush(cb);
+    cb->size.min = size;
+    cb->size.max = size;
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND;
+}
+
+static void set_base_value(DP_MyPaintSettings *settings, MyPaintBrushSetting s,
+                           float value)
+{
+    settings->mappings[s].base_value = value;
+}
+
+// An elliptical brush tilted by 30 degrees, so its copies only line up if
+// their angle gets mirrored or rotated along with them.
+static void init_mypaint_brush(DP_MyPaintBrush *brush,
+                               DP_MyPaintSettings *settings)
+{
+    *brush = (DP_MyPaintBrush){{0.0f, 0.0f, 0.0f, 1.0f},
+                               false,
+                               false,
+                               true,
+                               DP_BRUSH_PICKUP_MODE_LAYER};
+    *settings = (DP_MyPaintSettings){0};
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_OPAQUE, 1.0f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_OPAQUE_MULTIPLY, 1.0f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_RADIUS_LOGARITHMIC,
+                   logf(4.0f));
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_HARDNESS, 0.8f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_DABS_PER_ACTUAL_RADIUS,
+                   0.5f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_ELLIPTICAL_DAB_RATIO, 3.0f);
+    set_base_value(settings, MYPAINT_BRUSH_SETTING_ELLIPTICAL_DAB_ANGLE,
+                   30.0f);
+}
+
+static DP_StrokeParams make_stroke(DP_SymmetryMode mode, float x, float y,
+                                   int count)
+{
+    return (DP_StrokeParams){LAYER_ID, false, 0, false, 0, false, 0,
+                             {mode, x, y, count}};
+}
+
+static void draw_line(DP_BrushEngine *be, float x1, float y1, float x2,
+                      float y2)
+{
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    for (int i = 0; i <= 32; ++i) {
+        float t = (float)i / 32.0f;
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){x1 + (x2 - x1) * t, y1 + (y2 - y1) * t, 1.0f,
+                            0.0f, 0.0f, 0.0f, i},
+            NULL);
+    }
+    DP_brush_engine_stroke_end(be, 33, NULL, true);
+}
+
+static void draw_classic_line(const DP_ClassicBrush *cb,
+                              const DP_StrokeParams *stroke, float x1,
+                              float y1, float x2, float y2,
+                              DP_SymmetryTestMessages *stms)
+{
+    *stms = (DP_SymmetryTestMessages){0};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, stms);
+    DP_brush_engine_classic_brush_set(be, cb, stroke, NULL, false);
+    draw_line(be, x1, y1, x2, y2);
+    DP_brush_engine_free(be);
+}
+
+static void draw_mypaint_line(const DP_StrokeParams *stroke, float x1,
+                              float y1, float x2, float y2,
+                              DP_SymmetryTestMessages *stms)
+{
+    DP_MyPaintBrush brush;
+    DP_MyPaintSettings settings;
+    init_mypaint_brush(&brush, &settings);
+    *stms = (DP_SymmetryTestMessages){0};
+    DP_BrushEngine *be = DP_brush_engine_new(collect_messages, NULL, stms);
+    DP_brush_engine_mypaint_brush_set(be, &brush, &settings, stroke, NULL,
+                                      false);
+    draw_line(be, x1, y1, x2, y2);
+    DP_brush_engine_free(be);
+}
+
+// Replays the messages onto an empty canvas and copies out the layer.
+static void replay(TEST_PARAMS, DP_SymmetryTestMessages *stms,
+                   DP_SymmetryTestLayer out)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, CANVAS_SIZE, CANVAS_SIZE, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer 1", 7));
+    for (int i = 0; i < stms->count; ++i) {
+        handle(TEST_ARGS, ch, dc, stms->msgs[i]);
+    }
+    stms->count = 0;
+
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    DP_LayerContent *lc = DP_layer_routes_entry_content(lre, cs);
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            out[y][x] = DP_layer_content_pixel_at(lc, x, y);
+        }
+    }
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+// Sum of alpha differences between each pixel and its counterpart, relative
+// to the total alpha, after mirroring across the middle of the canvas or
+// turning around it by a quarter.
+static double asymmetry(DP_SymmetryTestLayer layer, DP_SymmetryMode mode)
+{
+    double total = 0.0;
+    double difference = 0.0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 0; x < CANVAS_SIZE; ++x) {
+            int a = layer[y][x].a;
+            int b;
+            switch (mode) {
+            case DP_SYMMETRY_VERTICAL:
+                b = layer[y][CANVAS_SIZE - 1 - x].a;
+                break;
+            case DP_SYMMETRY_HORIZONTAL:
+                b = layer[CANVAS_SIZE - 1 - y][x].a;
+                break;
+            default:
+                b = layer[x][CANVAS_SIZE - 1 - y].a;
+                break;
+            }
+            total += a;
+            difference += abs(a - b);
+        }
+    }
+    return total > 0.0 ? difference / total : 1.0;
+}
+
+
+static void mirror_stroke_near_axis(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_soft_brush(&cb);
+    DP_StrokeParams plain = make_stroke(DP_SYMMETRY_NONE, 0.0f, 0.0f, 0);
+    DP_StrokeParams mirrored =
+        make_stroke(DP_SYMMETRY_VERTICAL, CANVAS_SIZE / 2, 0.0f, 0);
+
+    // The brush is 8 pixels across, so its copy overlaps it in the middle.
+    DP_SymmetryTestMessages stms;
+    DP_SymmetryTestLayer single, both;
+    draw_classic_line(&cb, &plain, 29.0f, 8.0f, 31.0f, 56.0f, &stms);
+    int single_dabs = stms.dabs;
+    replay(TEST_ARGS, &stms, single);
+    draw_classic_line(&cb, &mirrored, 29.0f, 8.0f, 31.0f, 56.0f, &stms);
+    INT_EQ_OK(stms.dabs, single_dabs * 2, "every dab gets a mirrored copy");
+    replay(TEST_ARGS, &stms, both);
+
+    double value = asymmetry(both, DP_SYMMETRY_VERTICAL);
+    OK(value < 0.001, "mirrored stroke is symmetric (asymmetry %f)", value);
+    int overlapping = 0;
+    for (int y = 0; y < CANVAS_SIZE; ++y) {
+        for (int x = 30; x < 34; ++x) {
+            if (both[y][x].a > single[y][x].a) {
+                ++overlapping;
+            }
+        }
+    }
+    OK(overlapping > 100, "copies blend into each other at the axis (%d)",
+       overlapping);
+
+    DP_StrokeParams horizontal =
+        make_stroke(DP_SYMMETRY_HORIZONTAL, 0.0f, CANVAS_SIZE / 2, 0);
+    draw_classic_line(&cb, &horizontal, 8.0f, 29.0f, 56.0f, 31.0f, &stms);
+    replay(TEST_ARGS, &stms, both);
+    value = asymmetry(both, DP_SYMMETRY_HORIZONTAL);
+    OK(value < 0.001, "horizontally mirrored stroke is symmetric (%f)", value);
+}
+
+static void mirror_pixel_dabs(TEST_PARAMS)
+{
+    DP_StrokeParams mirrored =
+        make_stroke(DP_SYMMETRY_VERTICAL, CANVAS_SIZE / 2, 0.0f, 0);
+    // Odd sizes are centered on the middle of a pixel, even ones in between.
+    float sizes[] = {1.0f, 3.0f, 4.0f};
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(sizes); ++i) {
+        DP_ClassicBrush cb;
+        init_pixel_brush(&cb, sizes[i]);
+        DP_SymmetryTestMessages stms;
+        DP_SymmetryTestLayer layer;
+        draw_classic_line(&cb, &mirrored, 20.0f, 10.0f, 31.0f, 50.0f, &stms);
+        replay(TEST_ARGS, &stms, layer);
+        double value = asymmetry(layer, DP_SYMMETRY_VERTICAL);
+        OK(value == 0.0, "pixel dabs of size %d mirror exactly (%f)",
+           (int)sizes[i], value);
+    }
+}
+
+static void mirror_elliptical_dabs(TEST_PARAMS)
+{
+    DP_StrokeParams mirrored =
+        make_stroke(DP_SYMMETRY_VERTICAL, CANVAS_SIZE / 2, 0.0f, 0);
+    DP_SymmetryTestMessages stms;
+    DP_SymmetryTestLayer layer;
+    // MyPaint dabs get snapped to whole pixels, rounding halves up, which
+    // skews mirrored copies at half pixel positions by one. A straight line
+    // along a pixel boundary steers clear of that.
+    draw_mypaint_line(&mirrored, 20.0f, 16.0f, 20.0f, 48.0f, &stms);
+    replay(TEST_ARGS, &stms, layer);
+    double value = asymmetry(layer, DP_SYMMETRY_VERTICAL);
+    OK(value < 0.02, "mirrored ellipses are tilted the other way (%f)", value);
+}
+
+static void rotational_dab_counts(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_soft_brush(&cb);
+    DP_StrokeParams plain = make_stroke(DP_SYMMETRY_NONE, 0.0f, 0.0f, 0);
+    DP_SymmetryTestMessages stms;
+    draw_classic_line(&cb, &plain, 36.0f, 20.0f, 40.0f, 8.0f, &stms);
+    DP_SymmetryTestLayer layer;
+    replay(TEST_ARGS, &stms, layer);
+    int single_dabs = stms.dabs;
+    int single_messages = stms.dab_messages;
+
+    DP_StrokeParams six_way = make_stroke(DP_SYMMETRY_ROTATIONAL,
+                                          CANVAS_SIZE / 2, CANVAS_SIZE / 2, 6);
+    draw_classic_line(&cb, &six_way, 36.0f, 20.0f, 40.0f, 8.0f, &stms);
+    INT_EQ_OK(stms.dabs, single_dabs * 6, "six-way symmetry has 6 times the "
+                                          "dabs");
+    INT_EQ_OK(stms.dab_messages, single_messages * 6,
+              "each copy goes into its own message");
+    replay(TEST_ARGS, &stms, layer);
+
+    DP_StrokeParams one_way = make_stroke(DP_SYMMETRY_ROTATIONAL,
+                                          CANVAS_SIZE / 2, CANVAS_SIZE / 2, 1);
+    draw_classic_line(&cb, &one_way, 36.0f, 20.0f, 40.0f, 8.0f, &stms);
+    INT_EQ_OK(stms.dabs, single_dabs, "one-way symmetry makes no copies");
+    replay(TEST_ARGS, &stms, layer);
+
+    // Quarter turns land exactly on the pixel grid, so the rendered result can
+    // be compared directly. The ellipses have to turn along with the stroke.
+    // This line's dabs land at x = 40.75, 45.25 and 50, staying off of half
+    // pixels, see mirror_elliptical_dabs. A longer line would hit one.
+    DP_StrokeParams four_way = make_stroke(DP_SYMMETRY_ROTATIONAL,
+                                           CANVAS_SIZE / 2, CANVAS_SIZE / 2, 4);
+    draw_mypaint_line(&four_way, 36.25f, 4.0f, 54.25f, 4.0f, &stms);
+    replay(TEST_ARGS, &stms, layer);
+    double value = asymmetry(layer, DP_SYMMETRY_ROTATIONAL);
+    OK(value < 0.02, "four-way rotated ellipses line up (%f)", value);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(mirror_stroke_near_axis);
+    REGISTER_TEST(mirror_pixel_dabs);
+    REGISTER_TEST(mirror_elliptical_dabs);
+    REGISTER_TEST(rotational_dab_counts);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/binary.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/thumbnailer.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <limits.h>
+
+
+#define WIDTH    (DP_TILE_SIZE * 4)
+#define HEIGHT   (DP_TILE_SIZE * 2)
+#define LAYER_ID 257
+#define USER     1
+#define RED      0xffff0000u
+#define BLUE     0xff0000ffu
+#define WHITE    0xffffffffu
+
+typedef struct Session {
+    DP_DrawContext *dc;
+    DP_CanvasHistory *ch;
+    DP_Thumbnailer *t;
+    long long now;
+    long long message_index;
+    int thumb_count;
+    int thumb_width;
+    int thumb_height;
+    long long thumb_message_index;
+    long long thumb_time_ms;
+    uint32_t left;
+    uint32_t right;
+} Session;
+
+static long long get_time(void *user)
+{
+    return ((Session *)user)->now;
+}
+
+static void on_thumbnail(void *user, DP_Image *thumb, long long message_index,
+                         long long time_ms)
+{
+    Session *s = user;
+    ++s->thumb_count;
+    s->thumb_width = DP_image_width(thumb);
+    s->thumb_height = DP_image_height(thumb);
+    s->thumb_message_index = message_index;
+    s->thumb_time_ms = time_ms;
+    int y = s->thumb_height / 2;
+    s->left = DP_image_pixel_at(thumb, 2, y).color;
+    s->right = DP_image_pixel_at(thumb, s->thumb_width - 3, y).color;
+}
+
+static void handle(TEST_PARAMS, Session *s, DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(s->ch, s->dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+    ++s->message_index;
+}
+
+static void set_color(size_t size, unsigned char *out, void *user)
+{
+    DP_ASSERT(size == 4);
+    DP_write_bigendian_uint32(*(uint32_t *)user, out);
+}
+
+// Fills the left half of the canvas.
+static void draw(TEST_PARAMS, Session *s, uint32_t color)
+{
+    handle(TEST_ARGS, s, DP_msg_undo_point_new(USER));
+    handle(TEST_ARGS, s,
+           DP_msg_fill_rect_new(USER, LAYER_ID, DP_BLEND_MODE_NORMAL, 0, 0,
+                                WIDTH / 2, HEIGHT, color));
+}
+
+static void session_init_flags(TEST_PARAMS, Session *s, unsigned int flags,
+                               long long interval_ms)
+{
+    *s = (Session){0};
+    s->dc = DP_draw_context_new();
+    s->ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    s->t = DP_thumbnailer_new(64, 64, flags, interval_ms, get_time, s,
+                              on_thumbnail, s);
+    handle(TEST_ARGS, s, DP_msg_canvas_resize_new(USER, 0, WIDTH, HEIGHT, 0));
+    uint32_t background = WHITE;
+    handle(TEST_ARGS, s,
+           DP_msg_canvas_background_new(USER, set_color, 4, &background));
+    handle(TEST_ARGS, s,
+           DP_msg_layer_tree_create_new(USER, LAYER_ID, 0, 0, 0, 0, "Layer",
+                                        5));
+}
+
+static void session_init(TEST_PARAMS, Session *s, long long interval_ms)
+{
+    session_init_flags(TEST_ARGS, s, DP_FLAT_IMAGE_RENDER_FLAGS, interval_ms);
+}
+
+static void session_dispose(Session *s)
+{
+    DP_thumbnailer_free(s->t);
+    DP_canvas_history_free(s->ch);
+    DP_draw_context_free(s->dc);
+}
+
+static void step(Session *s, long long now, int max_tiles)
+{
+    s->now = now;
+    DP_thumbnailer_step(s->t, s->ch, s->message_index, max_tiles);
+}
+
+
+static void thumbnails_follow_cadence(TEST_PARAMS)
+{
+    Session s;
+    session_init(TEST_ARGS, &s, 1000);
+
+    step(&s, 0, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 1, "first thumbnail is made right away");
+    INT_EQ_OK(s.thumb_width, 64, "thumbnail is scaled to the maximum width");
+    INT_EQ_OK(s.thumb_height, 32, "and keeps the aspect ratio");
+    UINT_EQ_OK(s.left, WHITE, "thumbnail shows the background on the left");
+    UINT_EQ_OK(s.right, WHITE, "and on the right");
+
+    step(&s, 5000, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 1, "no thumbnail when the canvas didn't change");
+
+    draw(TEST_ARGS, &s, RED);
+    step(&s, 5100, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 2, "thumbnail after the canvas changed");
+    UINT_EQ_OK(s.left, RED, "thumbnail shows the drawing");
+    UINT_EQ_OK(s.right, WHITE, "and the background next to it");
+    OK(s.thumb_message_index == s.message_index,
+       "thumbnail gets the message index");
+    OK(s.thumb_time_ms == 5100, "and the time");
+
+    draw(TEST_ARGS, &s, BLUE);
+    step(&s, 5500, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 2, "no thumbnail before the interval passed");
+    step(&s, 6099, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 2, "still none just before the interval is up");
+    step(&s, 6100, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 3, "thumbnail once the interval is up");
+    UINT_EQ_OK(s.left, BLUE, "thumbnail shows the change");
+
+    session_dispose(&s);
+}
+
+static void thumbnails_are_incremental(TEST_PARAMS)
+{
+    Session s;
+    session_init(TEST_ARGS, &s, 0);
+    draw(TEST_ARGS, &s, RED);
+    long long started_at = s.message_index;
+
+    // 8 tiles, 3 at a time.
+    step(&s, 100, 3);
+    OK(DP_thumbnailer_in_progress(s.t), "thumbnail is in progress");
+    INT_EQ_OK(s.thumb_count, 0, "no thumbnail after the first step");
+
+    // Drawing in between doesn't affect the one that's in progress.
+    draw(TEST_ARGS, &s, BLUE);
+    step(&s, 200, 3);
+    INT_EQ_OK(s.thumb_count, 0, "no thumbnail after the second step");
+    step(&s, 300, 3);
+    NOK(DP_thumbnailer_in_progress(s.t), "thumbnail is done");
+    INT_EQ_OK(s.thumb_count, 1, "thumbnail after the third step");
+    UINT_EQ_OK(s.left, RED, "thumbnail shows the canvas it was started with");
+    OK(s.thumb_message_index == started_at,
+       "and the message index from then");
+    OK(s.thumb_time_ms == 100, "and the time from then");
+
+    step(&s, 400, INT_MAX);
+    INT_EQ_OK(s.thumb_count, 2, "change in between gets its own thumbnail");
+    UINT_EQ_OK(s.left, BLUE, "which shows it");
+
+    session_dispose(&s);
+}
+
+static void small_canvas_keeps_size(TEST_PARAMS)
+{
+    Session s;
+    session_init(TEST_ARGS, &s, 0);
+    handle(TEST_ARGS, &s,
+           DP_msg_canvas_resize_new(USER, 0, 40 - WIDTH, 30 - HEIGHT, 0));
+    step(&s, 0, INT_MAX);
+    INT_EQ_OK(s.thumb_width, 40, "small canvas isn't scaled horizontally");
+    INT_EQ_OK(s.thumb_height, 30, "or vertically");
+    session_dispose(&s);
+}
+
+static void flags_exclude_background(TEST_PARAMS)
+{
+    Session s;
+    session_init_flags(TEST_ARGS, &s, DP_FLAT_IMAGE_INCLUDE_SUBLAYERS, 0);
+    draw(TEST_ARGS, &s, RED);
+    step(&s, 0, INT_MAX);
+    UINT_EQ_OK(s.left, RED, "thumbnail shows the drawing");
+    UINT_EQ_OK(s.right, 0, "but not the excluded background");
+    session_dispose(&s);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(thumbnails_follow_cadence);
+    REGISTER_TEST(thumbnails_are_incremental);
+    REGISTER_TEST(small_canvas_keeps_size);
+    REGISTER_TEST(flags_exclude_background);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,309 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dptest_engine.h>
+
+
+#define RED ((DP_Pixel15){0, 0, DP_BIT15, DP_BIT15})
+
+static uint32_t next_random(uint32_t *state)
+{
+    // xorshift32, so that failures are reproducible.
+    uint32_t x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    return x;
+}
+
+static int random_int(uint32_t *state, int min, int max)
+{
+    return min + (int)(next_random(state) % (uint32_t)(max - min + 1));
+}
+
+static unsigned char *get_encode_buffer(size_t size, void *user)
+{
+    unsigned char **out = user;
+    *out = DP_malloc(size);
+    return *out;
+}
+
+static DP_Tile *make_vertical_gradient(void)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        uint16_t a = DP_int_to_uint16(y * DP_BIT15 / (DP_TILE_SIZE - 1));
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            DP_transient_tile_pixel_at_set(tt, x, y,
+                                           (DP_Pixel15){a / 2, a / 3, a, a});
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+static DP_Tile *make_noise(uint32_t *state)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            uint16_t a = DP_int_to_uint16(random_int(state, 0, DP_BIT15));
+            DP_transient_tile_pixel_at_set(tt, x, y,
+                                           (DP_Pixel15){a, a / 2, a / 4, a});
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+// A line across an otherwise empty tile, which is what most tiles of a
+// sketch look like.
+static DP_Tile *make_stroke(void)
+{
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    for (int i = 0; i < DP_TILE_SIZE; ++i) {
+        DP_transient_tile_pixel_at_set(tt, i, i, RED);
+        if (i + 1 < DP_TILE_SIZE) {
+            DP_transient_tile_pixel_at_set(tt, i + 1, i, RED);
+        }
+    }
+    return DP_transient_tile_persist(tt);
+}
+
+static void check_round_trip(TEST_PARAMS, DP_Tile *t, int expected_encoding,
+                             size_t expected_size, const char *title)
+{
+    unsigned char *data = NULL;
+    size_t size = DP_tile_encode(t, get_encode_buffer, &data);
+    UINT_EQ_OK(size, expected_size, "%s encoded size", title);
+    UINT_EQ_OK(DP_tile_encoded_size(t), size, "%s predicted size", title);
+    if (size != 0) {
+        INT_EQ_OK(data[0], expected_encoding, "%s encoding", title);
+    }
+
+    DP_Tile *decoded = DP_tile_new_from_encoded(3, data, size);
+    if (NOT_NULL_OK(decoded, "%s decodes", title)) {
+        DP_Pixel15 *expected_pixels = DP_malloc(DP_TILE_BYTES);
+        if (t) {
+            memcpy(expected_pixels, DP_tile_pixels(t), DP_TILE_BYTES);
+        }
+        else {
+            memset(expected_pixels, 0, DP_TILE_BYTES);
+        }
+        OK(memcmp(DP_tile_pixels(decoded), expected_pixels, DP_TILE_BYTES)
+               == 0,
+           "%s pixels survive the round trip", title);
+        OK(DP_tile_checksum(decoded) == DP_tile_checksum(t),
+           "%s checksum survives the round trip", title);
+        UINT_EQ_OK(DP_tile_context_id(decoded), 3, "%s context id", title);
+        DP_free(expected_pixels);
+        DP_tile_decref(decoded);
+    }
+    DP_free(data);
+}
+
+
+static void tile_encoding_round_trip(TEST_PARAMS)
+{
+    check_round_trip(TEST_ARGS, NULL, DP_TILE_ENCODING_BLANK, 1, "null");
+
+    DP_TransientTile *blank = DP_transient_tile_new_blank(0);
+    DP_Tile *t = DP_transient_tile_persist(blank);
+    check_round_trip(TEST_ARGS, t, DP_TILE_ENCODING_BLANK, 1, "blank");
+    DP_tile_decref(t);
+
+    t = DP_tile_new_from_pixel15(0, RED);
+    check_round_trip(TEST_ARGS, t, DP_TILE_ENCODING_SOLID, 9, "solid");
+    DP_tile_decref(t);
+
+    // One run per row.
+    t = make_vertical_gradient();
+    check_round_trip(TEST_ARGS, t, DP_TILE_ENCODING_RLE,
+                     1 + DP_TILE_SIZE * 9, "vertical gradient");
+    DP_tile_decref(t);
+
+    // Each row is a run of blank, red, then blank again, except for the first
+    // row that starts with red and the last two that end with it.
+    t = make_stroke();
+    check_round_trip(TEST_ARGS, t, DP_TILE_ENCODING_RLE,
+                     1 + (DP_TILE_SIZE * 3 - 3) * 9, "stroke");
+    DP_tile_decref(t);
+
+    // Every pixel differs from the one before it, encoding runs would take
+    // more space than the pixels themselves.
+    t = DP_tile_new_zebra(0, RED, DP_pixel15_zero());
+    DP_TransientTile *tt = DP_transient_tile_new(t, 0);
+    DP_tile_decref(t);
+    for (int y = 0; y < DP_TILE_SIZE; ++y) {
+        for (int x = 0; x < DP_TILE_SIZE; ++x) {
+            DP_transient_tile_pixel_at_set(
+                tt, x, y,
+                (x + y) % 2 == 0 ? RED : (DP_Pixel15){0, 0, 0, DP_BIT15});
+        }
+    }
+    t = DP_transient_tile_persist(tt);
+    check_round_trip(TEST_ARGS, t, DP_TILE_ENCODING_RAW,
+                     1 + DP_TILE_LENGTH * 8, "checkerboard");
+    DP_tile_decref(t);
+
+    uint32_t state = 0x9e3779b9u;
+    t = make_noise(&state);
+    check_round_trip(TEST_ARGS, t, DP_TILE_ENCODING_RAW,
+                     1 + DP_TILE_LENGTH * 8, "noise");
+    DP_tile_decref(t);
+}
+
+static void check_rejected(TEST_PARAMS, const unsigned char *data, size_t size,
+                           const char *title)
+{
+    DP_Tile *t = DP_tile_new_from_encoded(0, data, size);
+    if (!OK(t == NULL, "%s is rejected", title)) {
+        DP_tile_decref(t);
+    }
+}
+
+static void tile_encoding_invalid(TEST_PARAMS)
+{
+    check_rejected(TEST_ARGS, NULL, 0, "empty data");
+    check_rejected(TEST_ARGS, (unsigned char[]){DP_TILE_ENCODING_BLANK, 0}, 2,
+                   "blank with trailing byte");
+    check_rejected(TEST_ARGS, (unsigned char[]){DP_TILE_ENCODING_SOLID, 0, 0},
+                   3, "truncated solid");
+    check_rejected(TEST_ARGS,
+                   (unsigned char[]){DP_TILE_ENCODING_SOLID, 0, 0, 0, 0, 0, 0,
+                                     0x01, 0x80},
+                   9, "solid with alpha out of range");
+    check_rejected(TEST_ARGS, (unsigned char[]){4}, 1, "unknown encoding");
+    check_rejected(TEST_ARGS, (unsigned char[]){DP_TILE_ENCODING_RAW}, 1,
+                   "raw without pixels");
+
+    DP_Tile *t = make_stroke();
+    unsigned char *data = NULL;
+    size_t size = DP_tile_encode(t, get_encode_buffer, &data);
+    DP_tile_decref(t);
+
+    int truncated_rejected = 0;
+    for (size_t i = 0; i < size; ++i) {
+        DP_Tile *truncated = DP_tile_new_from_encoded(0, data, i);
+        if (truncated) {
+            DP_tile_decref(truncated);
+        }
+        else {
+            ++truncated_rejected;
+        }
+    }
+    INT_EQ_OK(truncated_rejected, DP_size_to_int(size),
+              "every truncation of encoded runs is rejected");
+
+    unsigned char *longer = DP_malloc(size + 9);
+    memcpy(longer, data, size);
+    memcpy(longer + size, data + 1, 9);
+    check_rejected(TEST_ARGS, longer, size + 9, "runs with an extra run");
+    DP_free(longer);
+
+    // The first row is a run of two red pixels followed by a blank run.
+    unsigned char saved = data[10];
+    data[10] = DP_TILE_SIZE - 1;
+    check_rejected(TEST_ARGS, data, size, "run crossing the end of a row");
+    data[10] = saved;
+    data[9] = 0xff;
+    check_rejected(TEST_ARGS, data, size, "run with alpha out of range");
+    DP_free(data);
+}
+
+// Garbage and mutated encodings must never read out of bounds or produce
+// pixels outside of the valid range, whatever else they do.
+static void tile_encoding_fuzz(TEST_PARAMS)
+{
+    uint32_t state = 0xdeadbeefu;
+    DP_Tile *sources[] = {make_stroke(), make_vertical_gradient(),
+                          make_noise(&state)};
+    int decoded = 0;
+    int rejected = 0;
+    int out_of_range = 0;
+    for (int i = 0; i < 3000; ++i) {
+        unsigned char *data;
+        size_t size;
+        if (i % 3 == 0) {
+            size = DP_int_to_size(random_int(&state, 1, 2000));
+            data = DP_malloc(size);
+            for (size_t j = 0; j < size; ++j) {
+                data[j] = (unsigned char)next_random(&state);
+            }
+            data[0] = DP_int_to_uint8(random_int(&state, 0, 4));
+        }
+        else {
+            DP_Tile *source = sources[random_int(&state, 0, 2)];
+            size = DP_tile_encode(source, get_encode_buffer, &data);
+            int mutations = random_int(&state, 1, 4);
+            for (int j = 0; j < mutations; ++j) {
+                size_t k = DP_int_to_size(
+                    random_int(&state, 0, DP_size_to_int(size) - 1));
+                data[k] = (unsigned char)next_random(&state);
+            }
+            if (random_int(&state, 0, 3) == 0) {
+                size = DP_int_to_size(
+                    random_int(&state, 0, DP_size_to_int(size)));
+            }
+        }
+
+        DP_Tile *t = DP_tile_new_from_encoded(0, data, size);
+        if (t) {
+            ++decoded;
+            const DP_Pixel15 *pixels = DP_tile_pixels(t);
+            for (int j = 0; j < DP_TILE_LENGTH; ++j) {
+                if (pixels[j].b > DP_BIT15 || pixels[j].g > DP_BIT15
+                    || pixels[j].r > DP_BIT15 || pixels[j].a > DP_BIT15) {
+                    ++out_of_range;
+                    break;
+                }
+            }
+            DP_tile_decref(t);
+        }
+        else {
+            ++rejected;
+        }
+        DP_free(data);
+    }
+
+    for (int i = 0; i < 3; ++i) {
+        DP_tile_decref(sources[i]);
+    }
+    INT_EQ_OK(decoded + rejected, 3000, "all fuzzed inputs handled");
+    OK(rejected > 0, "some fuzzed inputs rejected (%d)", rejected);
+    INT_EQ_OK(out_of_range, 0, "no decoded pixels out of range");
+}
+
+static void tile_encoding_packing(TEST_PARAMS)
+{
+    DP_Tile *t = make_stroke();
+    DP_Tile *pt = DP_tile_pack(t);
+    FATAL(NOT_NULL_OK(pt, "stroke tile gets packed"));
+    size_t encoded_size = DP_tile_encoded_size(t);
+    OK(encoded_size <= DP_TILE_BYTES / 8,
+       "stroke tile is small enough to be run-length encoded");
+    OK(DP_tile_packed_size(pt) >= encoded_size
+           && DP_tile_packed_size(pt) < encoded_size + 128,
+       "packed size is the run-length encoding plus a header (got %zu)",
+       DP_tile_packed_size(pt));
+    NOK(DP_tile_resident(pt), "packed tile isn't resident");
+    OK(memcmp(DP_tile_pixels(pt), DP_tile_pixels(t), DP_TILE_BYTES) == 0,
+       "run-length packed tile unpacks to the original pixels");
+    DP_tile_decref(pt);
+    DP_tile_decref(t);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(tile_encoding_round_trip);
+    REGISTER_TEST(tile_encoding_invalid);
+    REGISTER_TEST(tile_encoding_fuzz);
+    REGISTER_TEST(tile_encoding_packing);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/atomic.h>
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/threading.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dptest_engine.h>
+
+
+#define TILE_COUNT    16
+#define THREAD_COUNT  4
+#define THREAD_ROUNDS 500
+
+#define GARBAGE ((DP_Pixel15){0x1234, 0x2345, 0x3456, 0x4567})
+#define RED     ((DP_Pixel15){0, 0, DP_BIT15, DP_BIT15})
+
+static size_t tile_bytes(int count)
+{
+    return DP_tile_memory_usage().tile_size * DP_int_to_size(count);
+}
+
+// Fills the tiles with junk and frees them, so that the next ones allocated
+// come out of the cache with stale contents.
+static void cache_garbage_tiles(void)
+{
+    DP_TransientTile *tts[TILE_COUNT];
+    for (int i = 0; i < TILE_COUNT; ++i) {
+        tts[i] = DP_transient_tile_new_blank(0);
+        DP_Pixel15 *pixels = DP_transient_tile_pixels(tts[i]);
+        for (int j = 0; j < DP_TILE_LENGTH; ++j) {
+            pixels[j] = j % 7 == 0 ? RED : GARBAGE;
+        }
+    }
+    for (int i = 0; i < TILE_COUNT; ++i) {
+        DP_transient_tile_decref(tts[i]);
+    }
+}
+
+static int count_nonzero_bytes(DP_Tile *tile)
+{
+    const unsigned char *bytes = (const unsigned char *)DP_tile_pixels(tile);
+    int count = 0;
+    for (size_t i = 0; i < DP_TILE_BYTES; ++i) {
+        if (bytes[i] != 0) {
+            ++count;
+        }
+    }
+    return count;
+}
+
+
+static void tile_memory_reuse(TEST_PARAMS)
+{
+    DP_tile_memory_cache_limit_set(DP_TILE_MEMORY_CACHE_LIMIT_DEFAULT);
+    DP_tile_memory_cache_drain();
+    DP_TileMemoryStatistics before = DP_tile_memory_usage();
+    UINT_EQ_OK(before.tiles_cached, 0, "nothing cached after draining");
+
+    cache_garbage_tiles();
+    DP_TileMemoryStatistics cached = DP_tile_memory_usage();
+    UINT_EQ_OK(cached.tiles_cached, TILE_COUNT, "freed tiles are cached");
+    UINT_EQ_OK(cached.tiles_used, before.tiles_used, "no tiles left in use");
+
+    DP_TransientTile *tts[TILE_COUNT];
+    for (int i = 0; i < TILE_COUNT; ++i) {
+        tts[i] = DP_transient_tile_new_blank(0);
+    }
+    DP_TileMemoryStatistics reused = DP_tile_memory_usage();
+    UINT_EQ_OK(reused.tiles_cached, 0, "cached tiles were reused");
+    UINT_EQ_OK(reused.allocations, cached.allocations,
+               "reusing tiles didn't allocate");
+
+    int dirty = 0;
+    int nonblank = 0;
+    for (int i = 0; i < TILE_COUNT; ++i) {
+        if (count_nonzero_bytes((DP_Tile *)tts[i]) != 0) {
+            ++dirty;
+        }
+        if (!DP_tile_blank((DP_Tile *)tts[i])) {
+            ++nonblank;
+        }
+        DP_transient_tile_decref(tts[i]);
+    }
+    INT_EQ_OK(dirty, 0, "reused blank tiles have no stale pixels");
+    INT_EQ_OK(nonblank, 0, "reused blank tiles are blank");
+
+    // The garbage tiles were solid-checked at some point, make sure that
+    // answer doesn't stick around with the memory.
+    cache_garbage_tiles();
+    int wrong = 0;
+    for (int i = 0; i < TILE_COUNT; ++i) {
+        DP_Tile *t = i % 2 == 0 ? DP_tile_new_from_pixel15(0, RED)
+                                : DP_tile_new_zebra(0, RED, GARBAGE);
+        DP_Pixel15 pixel;
+        bool solid = DP_tile_same_pixel(t, &pixel);
+        if (i % 2 == 0 ? !solid || !DP_pixel15_equal(pixel, RED) : solid) {
+            ++wrong;
+        }
+        DP_tile_decref(t);
+    }
+    INT_EQ_OK(wrong, 0, "reused tiles have the right solid color");
+
+    DP_tile_memory_cache_drain();
+    DP_TileMemoryStatistics drained = DP_tile_memory_usage();
+    UINT_EQ_OK(drained.tiles_cached, 0, "nothing cached after draining again");
+    UINT_EQ_OK(drained.tiles_used, before.tiles_used, "still no tiles in use");
+}
+
+static void tile_memory_limit(TEST_PARAMS)
+{
+    DP_tile_memory_cache_drain();
+    DP_tile_memory_cache_limit_set(tile_bytes(3));
+    cache_garbage_tiles();
+    UINT_EQ_OK(DP_tile_memory_usage().tiles_cached, 3,
+               "cache doesn't grow past its limit");
+
+    DP_tile_memory_cache_limit_set(tile_bytes(1));
+    UINT_EQ_OK(DP_tile_memory_usage().tiles_cached, 1,
+               "lowering the limit releases excess tiles");
+
+    DP_tile_memory_cache_limit_set(0);
+    UINT_EQ_OK(DP_tile_memory_usage().tiles_cached, 0,
+               "zero limit releases all tiles");
+    size_t allocations = DP_tile_memory_usage().allocations;
+    cache_garbage_tiles();
+    UINT_EQ_OK(DP_tile_memory_usage().tiles_cached, 0,
+               "zero limit caches nothing");
+    UINT_EQ_OK(DP_tile_memory_usage().allocations - allocations, TILE_COUNT,
+               "every tile gets allocated without a cache");
+
+    DP_tile_memory_cache_limit_set(DP_TILE_MEMORY_CACHE_LIMIT_DEFAULT);
+}
+
+static void churn_tiles(void *data)
+{
+    DP_Atomic *failures = data;
+    DP_Pixel15 pixel = {0, 0, 0, DP_BIT15};
+    for (int i = 0; i < THREAD_ROUNDS; ++i) {
+        pixel.g = DP_int_to_uint16(i % DP_BIT15);
+        DP_Tile *t = DP_tile_new_from_pixel15(0, pixel);
+        DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+        DP_Pixel15 actual;
+        if (!DP_tile_same_pixel(t, &actual) || !DP_pixel15_equal(actual, pixel)
+            || !DP_tile_blank((DP_Tile *)tt)) {
+            DP_atomic_inc(failures);
+        }
+        DP_transient_tile_pixel_at_set(tt, i % DP_TILE_SIZE, 0, pixel);
+        DP_transient_tile_decref(tt);
+        DP_tile_decref(t);
+    }
+}
+
+static void tile_memory_threads(TEST_PARAMS)
+{
+    DP_tile_memory_cache_limit_set(tile_bytes(THREAD_COUNT));
+    DP_tile_memory_cache_drain();
+    size_t used = DP_tile_memory_usage().tiles_used;
+
+    DP_Atomic failures = DP_ATOMIC_INIT(0);
+    DP_Thread *threads[THREAD_COUNT];
+    for (int i = 0; i < THREAD_COUNT; ++i) {
+        threads[i] = DP_thread_new(churn_tiles, &failures);
+    }
+    for (int i = 0; i < THREAD_COUNT; ++i) {
+        DP_thread_free_join(threads[i]);
+    }
+
+    INT_EQ_OK(DP_atomic_get(&failures), 0, "no wrong tiles across threads");
+    DP_TileMemoryStatistics tms = DP_tile_memory_usage();
+    UINT_EQ_OK(tms.tiles_used, used, "all tiles freed across threads");
+    OK(tms.tiles_cached <= THREAD_COUNT, "cache within limit across threads");
+
+    DP_tile_memory_cache_limit_set(DP_TILE_MEMORY_CACHE_LIMIT_DEFAULT);
+    DP_tile_memory_cache_drain();
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(tile_memory_reuse);
+    REGISTER_TEST(tile_memory_limit);
+    REGISTER_TEST(tile_memory_threads);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
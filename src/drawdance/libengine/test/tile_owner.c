@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/acl.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define USER_A   1
+#define USER_B   2
+#define LAYER_ID 0x101
+#define UPPER_ID 0x102
+#define GROUP_ID 0x103
+#define TILE     DP_TILE_SIZE
+#define WIDTH    (TILE * 3)
+#define HEIGHT   (TILE * 2)
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    if (!DP_canvas_history_handle(ch, dc, msg)) {
+        FAIL("handle %s (error: %s)",
+             DP_message_type_enum_name(DP_message_type(msg)), DP_error());
+    }
+    DP_message_decref(msg);
+}
+
+static void create_layer(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                         int layer_id, int target_id, unsigned int flags)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(USER_A, DP_int_to_uint16(layer_id), 0,
+                                        DP_int_to_uint16(target_id), 0,
+                                        DP_uint_to_uint8(flags), "", 0));
+}
+
+static void fill(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                 unsigned int user, int layer_id, int x, int y, int width,
+                 int height)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(user, DP_int_to_uint16(layer_id),
+                                DP_BLEND_MODE_NORMAL, DP_int_to_uint32(x),
+                                DP_int_to_uint32(y), DP_int_to_uint32(width),
+                                DP_int_to_uint32(height), 0xff000000u));
+}
+
+static void set_pixel_dab(DP_UNUSED int count, DP_PixelDab *dabs,
+                          DP_UNUSED void *user)
+{
+    DP_pixel_dab_init(dabs, 0, 0, 0, 1, 255);
+}
+
+// Dabs with alpha in their color are indirect, they go into a sublayer of the
+// user drawing them and get merged into the layer on pen up.
+static DP_Message *dab_new(unsigned int user, int x, int y, bool indirect)
+{
+    return DP_msg_draw_dabs_pixel_square_new(
+        user, LAYER_ID, x, y, indirect ? 0xffff0000u : 0x00ff0000u,
+        DP_BLEND_MODE_NORMAL, set_pixel_dab, 1, NULL);
+}
+
+static DP_LayerContent *layer_content(DP_CanvasState *cs, int layer_id)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, layer_id);
+    return lre ? DP_layer_routes_entry_content(lre, cs) : NULL;
+}
+
+// Returns -1 if there's no tile or no such layer.
+static int owner_in(DP_CanvasState *cs, int layer_id, int col, int row)
+{
+    DP_LayerContent *lc = layer_content(cs, layer_id);
+    unsigned int context_id;
+    if (lc && DP_layer_content_tile_context_id_at(lc, col, row, &context_id)) {
+        return DP_uint_to_int(context_id);
+    }
+    else {
+        return -1;
+    }
+}
+
+static int owner(DP_CanvasHistory *ch, int layer_id, int col, int row)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    int context_id = owner_in(cs, layer_id, col, row);
+    DP_canvas_state_decref(cs);
+    return context_id;
+}
+
+static int touched_by(DP_CanvasHistory *ch, int x, int y, int width,
+                      int height, uint8_t *out_users)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_Rect area = DP_rect_make(x, y, width, height);
+    int count = DP_canvas_state_touched_by(cs, &area, out_users);
+    DP_canvas_state_decref(cs);
+    return count;
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(USER_A, 0, WIDTH, HEIGHT, 0));
+    create_layer(TEST_ARGS, ch, dc, LAYER_ID, 0, 0);
+    return ch;
+}
+
+
+static void tile_owner_adjacent(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill(TEST_ARGS, ch, dc, USER_A, LAYER_ID, 0, 0, TILE, TILE);
+    fill(TEST_ARGS, ch, dc, USER_B, LAYER_ID, TILE, 0, TILE, TILE);
+
+    INT_EQ_OK(owner(ch, LAYER_ID, 0, 0), USER_A, "left tile is from A");
+    INT_EQ_OK(owner(ch, LAYER_ID, 1, 0), USER_B, "right tile is from B");
+    INT_EQ_OK(owner(ch, LAYER_ID, 2, 0), -1, "untouched tile has no owner");
+
+    DP_UserBits users;
+    INT_EQ_OK(touched_by(ch, 0, 0, TILE, TILE, users), 1,
+              "one user in left tile");
+    OK(DP_user_bit_get(users, USER_A) && !DP_user_bit_get(users, USER_B),
+       "left tile touched by A only");
+
+    INT_EQ_OK(touched_by(ch, TILE - 1, TILE / 2, 2, 1, users), 2,
+              "two users across the tile boundary");
+    OK(DP_user_bit_get(users, USER_A) && DP_user_bit_get(users, USER_B),
+       "tile boundary touched by A and B");
+
+    INT_EQ_OK(touched_by(ch, 0, TILE, WIDTH, TILE, users), 0,
+              "nobody touched the bottom row");
+    OK(!DP_user_bit_get(users, USER_A) && !DP_user_bit_get(users, USER_B),
+       "no bits set for the bottom row");
+    INT_EQ_OK(touched_by(ch, WIDTH, 0, TILE, TILE, users), 0,
+              "nobody outside of the canvas");
+    INT_EQ_OK(touched_by(ch, -TILE, -TILE, WIDTH * 2, HEIGHT * 2, users), 2,
+              "area bigger than the canvas gets clipped");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void tile_owner_overlapping(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    fill(TEST_ARGS, ch, dc, USER_A, LAYER_ID, 0, 0, TILE * 2, TILE);
+    DP_CanvasState *before = DP_canvas_history_get(ch);
+
+    handle(TEST_ARGS, ch, dc, dab_new(USER_B, TILE + TILE / 2, TILE / 2, true));
+    DP_UserBits users;
+    INT_EQ_OK(touched_by(ch, TILE, 0, 1, 1, users), 2,
+              "stroke in progress is found");
+    OK(DP_user_bit_get(users, USER_A) && DP_user_bit_get(users, USER_B),
+       "stroke in progress by B over the tile by A");
+    INT_EQ_OK(owner(ch, LAYER_ID, 1, 0), USER_A,
+              "tile is from A until the stroke is done");
+
+    handle(TEST_ARGS, ch, dc, DP_msg_pen_up_new(USER_B));
+    INT_EQ_OK(owner(ch, LAYER_ID, 0, 0), USER_A, "untouched tile is from A");
+    INT_EQ_OK(owner(ch, LAYER_ID, 1, 0), USER_B,
+              "tile drawn over by B is from B");
+    INT_EQ_OK(owner_in(before, LAYER_ID, 1, 0), USER_A,
+              "previous state still has the tile from A");
+
+    DP_CanvasState *after = DP_canvas_history_get(ch);
+    OK(DP_layer_content_tile_at_noinc(layer_content(before, LAYER_ID), 0, 0)
+           == DP_layer_content_tile_at_noinc(layer_content(after, LAYER_ID), 0,
+                                             0),
+       "untouched tile is shared with the previous state");
+    DP_canvas_state_decref(after);
+
+    INT_EQ_OK(touched_by(ch, TILE / 2, 0, TILE, 1, users), 2,
+              "both users in the overlapping area");
+    OK(DP_user_bit_get(users, USER_A) && DP_user_bit_get(users, USER_B),
+       "overlapping area touched by A and B");
+    INT_EQ_OK(touched_by(ch, TILE, 0, TILE, TILE, users), 1,
+              "only B in drawn tile");
+    OK(DP_user_bit_get(users, USER_B), "drawn tile touched by B");
+
+    fill(TEST_ARGS, ch, dc, USER_A, LAYER_ID, TILE + 4, 4, 4, 4);
+    INT_EQ_OK(owner(ch, LAYER_ID, 1, 0), USER_A, "A takes the tile back");
+
+    DP_canvas_state_decref(before);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void check_multidab(TEST_PARAMS, DP_CanvasState *cs, DP_DrawContext *dc,
+                           unsigned int first, unsigned int second)
+{
+    DP_Message *msgs[] = {dab_new(first, 4, 4, false),
+                          dab_new(second, 8, 8, false)};
+    DP_CanvasState *next_cs = DP_canvas_state_handle_multidab(
+        cs, dc, NULL, (int)DP_ARRAY_LENGTH(msgs), msgs);
+    if (NOT_NULL_OK(next_cs, "handle multidab")) {
+        INT_EQ_OK(owner_in(next_cs, LAYER_ID, 0, 0), DP_uint_to_int(second),
+                  "tile drawn on by %u then %u is from %u", first, second,
+                  second);
+        DP_canvas_state_decref(next_cs);
+    }
+    DP_message_decref(msgs[0]);
+    DP_message_decref(msgs[1]);
+}
+
+static void tile_owner_multidab(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    check_multidab(TEST_ARGS, cs, dc, USER_A, USER_B);
+    check_multidab(TEST_ARGS, cs, dc, USER_B, USER_A);
+    DP_canvas_state_decref(cs);
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void tile_owner_layers(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    create_layer(TEST_ARGS, ch, dc, UPPER_ID, 0, 0);
+    fill(TEST_ARGS, ch, dc, USER_A, LAYER_ID, 0, 0, 8, 8);
+    fill(TEST_ARGS, ch, dc, USER_B, UPPER_ID, 8, 8, 8, 8);
+
+    DP_UserBits users;
+    INT_EQ_OK(touched_by(ch, 0, 0, 1, 1, users), 2,
+              "users on both layers are found");
+    OK(DP_user_bit_get(users, USER_A) && DP_user_bit_get(users, USER_B),
+       "tile touched by A and B across layers");
+
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_visibility_new(USER_A, UPPER_ID, false));
+    INT_EQ_OK(touched_by(ch, 0, 0, 1, 1, users), 1, "hidden layer is skipped");
+    OK(DP_user_bit_get(users, USER_A) && !DP_user_bit_get(users, USER_B),
+       "only A with the upper layer hidden");
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_visibility_new(USER_A, UPPER_ID, true));
+
+    // Merging a layer into another one is a change by the merging user.
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_delete_new(USER_B, UPPER_ID, LAYER_ID));
+    INT_EQ_OK(owner(ch, LAYER_ID, 0, 0), USER_B,
+              "merged layer's tile is from the merging user");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void tile_owner_group_merge(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    create_layer(TEST_ARGS, ch, dc, GROUP_ID, 0,
+                 DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP);
+    create_layer(TEST_ARGS, ch, dc, UPPER_ID, GROUP_ID,
+                 DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO);
+    fill(TEST_ARGS, ch, dc, USER_A, UPPER_ID, 0, 0, 8, 8);
+    fill(TEST_ARGS, ch, dc, USER_B, UPPER_ID, 8, 8, 8, 8);
+    fill(TEST_ARGS, ch, dc, USER_B, UPPER_ID, TILE, 0, 8, 8);
+    INT_EQ_OK(owner(ch, UPPER_ID, 0, 0), USER_B, "grouped tile is from B");
+
+    // Flattening a group mixes everyone's work, so it belongs to nobody.
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_delete_new(USER_A, GROUP_ID, LAYER_ID));
+    INT_EQ_OK(owner(ch, LAYER_ID, 0, 0), 0, "flattened group tile has id 0");
+    INT_EQ_OK(owner(ch, LAYER_ID, 1, 0), 0,
+              "flattened tile of a single user has id 0 too");
+
+    DP_UserBits users;
+    INT_EQ_OK(touched_by(ch, 0, 0, WIDTH, HEIGHT, users), 1,
+              "one id after flattening");
+    OK(DP_user_bit_get(users, 0), "flattened tiles set bit 0");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void tile_owner_not_in_checksum(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch_a = make_history(TEST_ARGS, dc);
+    DP_CanvasHistory *ch_b = make_history(TEST_ARGS, dc);
+    fill(TEST_ARGS, ch_a, dc, USER_A, LAYER_ID, 4, 4, TILE, TILE);
+    fill(TEST_ARGS, ch_b, dc, USER_B, LAYER_ID, 4, 4, TILE, TILE);
+
+    DP_CanvasState *cs_a = DP_canvas_history_get(ch_a);
+    DP_CanvasState *cs_b = DP_canvas_history_get(ch_b);
+    INT_EQ_OK(owner_in(cs_a, LAYER_ID, 0, 0), USER_A, "canvas a is from A");
+    INT_EQ_OK(owner_in(cs_b, LAYER_ID, 0, 0), USER_B, "canvas b is from B");
+    OK(DP_tile_checksum(
+           DP_layer_content_tile_at_noinc(layer_content(cs_a, LAYER_ID), 0, 0))
+           == DP_tile_checksum(DP_layer_content_tile_at_noinc(
+               layer_content(cs_b, LAYER_ID), 0, 0)),
+       "tile checksums match");
+    OK(DP_canvas_state_checksum(cs_a) == DP_canvas_state_checksum(cs_b),
+       "canvas checksums match");
+    DP_canvas_state_decref(cs_b);
+    DP_canvas_state_decref(cs_a);
+
+    DP_canvas_history_free(ch_b);
+    DP_canvas_history_free(ch_a);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(tile_owner_adjacent);
+    REGISTER_TEST(tile_owner_overlapping);
+    REGISTER_TEST(tile_owner_multidab);
+    REGISTER_TEST(tile_owner_layers);
+    REGISTER_TEST(tile_owner_group_merge);
+    REGISTER_TEST(tile_owner_not_in_checksum);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/pixels.h>
+#include <dpengine/tile.h>
+#include <dpmsg/blend_mode.h>
+#include <dptest_engine.h>
+
+
+#define RED   ((DP_Pixel15){0, 0, DP_BIT15, DP_BIT15})
+#define BLUE  ((DP_Pixel15){8192, 0, 0, 16384})
+#define GREEN ((DP_Pixel15){0, 20000, 0, 24000})
+
+static bool solid_is(DP_Tile *t, DP_Pixel15 expected)
+{
+    DP_Pixel15 pixel;
+    return DP_tile_same_pixel(t, &pixel) && DP_pixel15_equal(pixel, expected);
+}
+
+static bool pixels_equal(const DP_Pixel15 *a, const DP_Pixel15 *b)
+{
+    return memcmp(a, b, DP_TILE_BYTES) == 0;
+}
+
+
+static void solid_tile_cache(TEST_PARAMS)
+{
+    OK(DP_tile_same_pixel(NULL, NULL), "null tile is solid");
+    OK(solid_is(NULL, DP_pixel15_zero()), "null tile is blank");
+
+    DP_Tile *red = DP_tile_new_from_pixel15(0, RED);
+    OK(solid_is(red, RED), "tile from pixel is solid red");
+    NOK(DP_tile_blank(red), "red tile isn't blank");
+
+    DP_Tile *zebra = DP_tile_new_zebra(0, RED, BLUE);
+    NOK(DP_tile_same_pixel(zebra, NULL), "zebra tile isn't solid");
+    DP_Tile *plain_zebra = DP_tile_new_zebra(0, BLUE, BLUE);
+    OK(solid_is(plain_zebra, BLUE), "zebra of one color is solid");
+
+    DP_TransientTile *tt = DP_transient_tile_new_blank(0);
+    OK(DP_tile_blank((DP_Tile *)tt), "new blank tile is blank");
+    OK(solid_is((DP_Tile *)tt, DP_pixel15_zero()), "new blank tile is solid");
+
+    int last = DP_TILE_SIZE - 1;
+    DP_transient_tile_pixel_at_set(tt, last, last, RED);
+    NOK(DP_tile_same_pixel((DP_Tile *)tt, NULL), "not solid after pixel set");
+    NOK(DP_tile_blank((DP_Tile *)tt), "not blank after pixel set");
+    DP_transient_tile_pixel_at_set(tt, last, last, DP_pixel15_zero());
+    OK(DP_tile_blank((DP_Tile *)tt), "blank again after pixel reset");
+
+    DP_transient_tile_pixels(tt)[DP_TILE_LENGTH / 2] = BLUE;
+    NOK(DP_tile_same_pixel((DP_Tile *)tt, NULL),
+        "not solid after raw pixel write");
+    DP_transient_tile_clear(tt);
+    OK(DP_tile_blank((DP_Tile *)tt), "blank after clearing");
+
+    DP_transient_tile_copy(tt, red);
+    OK(solid_is((DP_Tile *)tt, RED), "solid red after copying red tile");
+    DP_transient_tile_brush_apply(tt, (DP_UPixel15){DP_BIT15, 0, 0, DP_BIT15},
+                                  DP_BLEND_MODE_NORMAL, DP_tile_opaque_mask(),
+                                  DP_BIT15, 10, 20, 3, 4, 0);
+    NOK(DP_tile_same_pixel((DP_Tile *)tt, NULL),
+        "not solid after partial brush apply");
+    DP_transient_tile_brush_apply(tt, (DP_UPixel15){DP_BIT15, 0, 0, DP_BIT15},
+                                  DP_BLEND_MODE_NORMAL, DP_tile_opaque_mask(),
+                                  DP_BIT15, 0, 0, DP_TILE_SIZE, DP_TILE_SIZE,
+                                  0);
+    OK(solid_is((DP_Tile *)tt, (DP_Pixel15){DP_BIT15, 0, 0, DP_BIT15}),
+       "solid blue after brush apply covering everything");
+
+    DP_transient_tile_copy(tt, zebra);
+    NOK(DP_tile_same_pixel((DP_Tile *)tt, NULL),
+        "not solid after copying zebra tile");
+    DP_transient_tile_merge(tt, red, DP_BIT15, DP_BLEND_MODE_NORMAL);
+    OK(solid_is((DP_Tile *)tt, RED), "solid after opaque merge over zebra");
+
+    DP_TransientTile *copy = DP_transient_tile_new(zebra, 0);
+    NOK(DP_tile_same_pixel((DP_Tile *)copy, NULL), "zebra copy isn't solid");
+    DP_transient_tile_pixel_at_put(copy, DP_BLEND_MODE_NORMAL, 0, 0, RED);
+    DP_Tile *persisted = DP_transient_tile_persist(copy);
+    NOK(DP_tile_same_pixel(persisted, NULL),
+        "persisted zebra copy still isn't solid");
+
+    DP_tile_decref(persisted);
+    DP_transient_tile_decref(tt);
+    DP_tile_decref(plain_zebra);
+    DP_tile_decref(zebra);
+    DP_tile_decref(red);
+}
+
+static void check_merge(TEST_PARAMS, DP_Tile *dst, DP_Tile *src,
+                        uint16_t opacity, int blend_mode)
+{
+    // The general path, blending every pixel of the raw buffers.
+    DP_Pixel15 *expected = DP_malloc_simd(DP_TILE_BYTES);
+    memcpy(expected, DP_tile_pixels(dst), DP_TILE_BYTES);
+    DP_blend_tile(expected, DP_tile_pixels(src), opacity, blend_mode);
+
+    DP_TransientTile *tt = DP_transient_tile_new(dst, 0);
+    DP_transient_tile_merge(tt, src, opacity, blend_mode);
+    OK(pixels_equal(DP_transient_tile_pixels(tt), expected),
+       "%s merge with opacity %d matches blending every pixel",
+       DP_blend_mode_enum_name(blend_mode), opacity);
+
+    DP_transient_tile_decref(tt);
+    DP_free_simd(expected);
+}
+
+static void solid_tile_merge(TEST_PARAMS)
+{
+    DP_Tile *red = DP_tile_new_from_pixel15(0, RED);
+    DP_Tile *blue = DP_tile_new_from_pixel15(0, BLUE);
+    DP_Tile *green = DP_tile_new_from_pixel15(0, GREEN);
+    DP_Tile *blank = DP_tile_new_from_pixel15(0, DP_pixel15_zero());
+    DP_Tile *zebra = DP_tile_new_zebra(0, RED, GREEN);
+    DP_Tile *tiles[][2] = {
+        {red, blue},    {blue, green}, {green, red}, {blank, blue},
+        {blue, blank},  {zebra, blue}, {blue, zebra}, {zebra, red},
+    };
+    uint16_t opacities[] = {0, 1, 10000, DP_BIT15 - 1, DP_BIT15};
+
+    for (int mode = 0; mode < DP_BLEND_MODE_COUNT; ++mode) {
+        if (DP_blend_mode_exists(mode)) {
+            for (size_t i = 0; i < DP_ARRAY_LENGTH(tiles); ++i) {
+                for (size_t j = 0; j < DP_ARRAY_LENGTH(opacities); ++j) {
+                    check_merge(TEST_ARGS, tiles[i][0], tiles[i][1],
+                                opacities[j], mode);
+                }
+            }
+        }
+    }
+
+    DP_tile_decref(zebra);
+    DP_tile_decref(blank);
+    DP_tile_decref(green);
+    DP_tile_decref(blue);
+    DP_tile_decref(red);
+}
+
+static DP_TransientTile *fill_tile_expected(DP_Tile *dst, int blend_mode,
+                                           DP_UPixel15 pixel)
+{
+    DP_TransientTile *tt = DP_transient_tile_new(dst, 0);
+    DP_transient_tile_brush_apply(tt, pixel, blend_mode, DP_tile_opaque_mask(),
+                                  pixel.a, 0, 0, DP_TILE_SIZE, DP_TILE_SIZE, 0);
+    return tt;
+}
+
+static void check_fill(TEST_PARAMS, DP_Tile *base, int blend_mode,
+                       DP_UPixel15 pixel)
+{
+    const char *name = DP_blend_mode_enum_name(blend_mode);
+    int size = DP_TILE_SIZE * 4;
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(size, size, base);
+    // Covers the two middle tiles in each direction entirely and the outer
+    // ones partially.
+    DP_transient_layer_content_fill_rect(tlc, 1, blend_mode, 10, 20, size - 10,
+                                         size - 20, pixel);
+    DP_LayerContent *lc = DP_transient_layer_content_persist(tlc);
+
+    DP_TransientTile *expected = fill_tile_expected(base, blend_mode, pixel);
+    DP_Tile *covered = DP_layer_content_tile_at_noinc(lc, 1, 1);
+    OK(pixels_equal(DP_tile_pixels(covered),
+                    DP_transient_tile_pixels(expected)),
+       "%s fill of covered tile matches brush apply", name);
+    OK(DP_layer_content_tile_at_noinc(lc, 2, 1) == covered
+           && DP_layer_content_tile_at_noinc(lc, 1, 2) == covered
+           && DP_layer_content_tile_at_noinc(lc, 2, 2) == covered,
+       "%s fill shares covered tiles", name);
+    NOK(DP_layer_content_tile_at_noinc(lc, 0, 1) == covered,
+        "%s fill doesn't share partially covered tile", name);
+    UINT_EQ_OK(DP_tile_context_id(covered), 1,
+               "%s fill sets context id on covered tile", name);
+
+    DP_Pixel15 inside = DP_layer_content_pixel_at(lc, 12, 24);
+    DP_Pixel15 outside = DP_layer_content_pixel_at(lc, 9, 24);
+    OK(DP_pixel15_equal(inside, DP_transient_tile_pixels(expected)[0]),
+       "%s fill of partially covered tile matches inside", name);
+    OK(DP_pixel15_equal(outside, DP_tile_pixels(base)[9 + 24 * DP_TILE_SIZE]),
+       "%s fill of partially covered tile leaves outside alone", name);
+
+    DP_transient_tile_decref(expected);
+    DP_layer_content_decref(lc);
+}
+
+static void solid_tile_fill(TEST_PARAMS)
+{
+    DP_Tile *zebra = DP_tile_new_zebra(0, RED, GREEN);
+    DP_Tile *blue = DP_tile_new_from_pixel15(0, BLUE);
+    DP_UPixel15 opaque = {DP_BIT15, 4000, 0, DP_BIT15};
+    DP_UPixel15 translucent = {0, DP_BIT15, 0, 12000};
+    DP_Tile *bases[] = {zebra, blue};
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(bases); ++i) {
+        check_fill(TEST_ARGS, bases[i], DP_BLEND_MODE_NORMAL, opaque);
+        check_fill(TEST_ARGS, bases[i], DP_BLEND_MODE_REPLACE, opaque);
+        check_fill(TEST_ARGS, bases[i], DP_BLEND_MODE_REPLACE, translucent);
+    }
+    DP_tile_decref(blue);
+    DP_tile_decref(zebra);
+}
+
+// A 4K canvas that a flood fill covered with white, which arrives as a big
+// put image. The tiles that end up uniform share their memory.
+static void solid_tile_flood_fill_memory(TEST_PARAMS)
+{
+    int width = 3840;
+    int height = 2160;
+    int tile_total = DP_tile_total_round(width, height);
+    DP_Image *img = DP_image_new(width, height);
+    DP_Pixel8 *pixels = DP_image_pixels(img);
+    for (int i = 0; i < width * height; ++i) {
+        pixels[i].color = 0xffffffffu;
+    }
+
+    DP_tile_memory_cache_limit_set(0);
+    size_t before = DP_tile_memory_usage().tiles_used;
+    DP_TransientLayerContent *tlc =
+        DP_transient_layer_content_new_init(width, height, NULL);
+    DP_transient_layer_content_put_image(tlc, 1, DP_BLEND_MODE_NORMAL, 0, 0,
+                                         img);
+    DP_image_free(img);
+    DP_LayerContent *lc = DP_transient_layer_content_persist(tlc);
+    size_t used = DP_tile_memory_usage().tiles_used - before;
+
+    // The bottom row of tiles sticks out past the canvas, so it's only partly
+    // white, everything else is the same white tile.
+    int row = DP_tile_counts_round(width, height).x;
+    UINT_EQ_OK(used, DP_int_to_size(row + 1),
+               "flood filled canvas uses %d tiles instead of %d", row + 1,
+               tile_total);
+    OK(used * 20 < DP_int_to_size(tile_total),
+       "less than a twentieth of the memory without sharing");
+    UINT_EQ_OK(DP_layer_content_pixel_at(lc, 1000, 1000).a, DP_BIT15,
+               "filled pixel is opaque");
+
+    // Drawing on one of the shared tiles copies it, the rest stay as they were.
+    DP_Tile *shared = DP_layer_content_tile_at_noinc(lc, 1, 1);
+    tlc = DP_transient_layer_content_new(lc);
+    DP_UPixel15 black = {0, 0, 0, DP_BIT15};
+    int x = DP_TILE_SIZE + 6;
+    DP_transient_layer_content_fill_rect(tlc, 1, DP_BLEND_MODE_NORMAL, x, x,
+                                         x + 2, x + 2, black);
+    DP_LayerContent *drawn = DP_transient_layer_content_persist(tlc);
+    NOK(DP_layer_content_tile_at_noinc(drawn, 1, 1) == shared,
+        "tile drawn on got copied");
+    OK(DP_layer_content_tile_at_noinc(drawn, 2, 1) == shared,
+       "neighboring tile is still shared");
+    UINT_EQ_OK(DP_layer_content_pixel_at(drawn, x, x).r, 0,
+               "drawn pixel changed");
+    UINT_EQ_OK(DP_layer_content_pixel_at(drawn, x + 2, x + 2).r, DP_BIT15,
+               "pixel beside it didn't");
+    UINT_EQ_OK(DP_layer_content_pixel_at(lc, x, x).r, DP_BIT15,
+               "original layer is untouched");
+
+    DP_layer_content_decref(drawn);
+    DP_layer_content_decref(lc);
+    DP_tile_memory_cache_limit_set(DP_TILE_MEMORY_CACHE_LIMIT_DEFAULT);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(solid_tile_cache);
+    REGISTER_TEST(solid_tile_merge);
+    REGISTER_TEST(solid_tile_fill);
+    REGISTER_TEST(solid_tile_flood_fill_memory);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,265 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/geom.h>
+#include <dpcommon/threading.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpengine/image.h>
+#include <dpengine/layer_content.h>
+#include <dpengine/layer_routes.h>
+#include <dpengine/ops.h>
+#include <dpengine/pixels.h>
+#include <dpengine/preview.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <stdlib.h>
+#include <string.h>
+
+
+#define WIDTH     150
+#define HEIGHT    100
+#define LAYER_ID  257
+#define TOLERANCE 2
+
+typedef struct SourcePixels {
+    DP_Image *img;
+    int gets;
+    int disposes;
+} SourcePixels;
+
+static void handle(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                   DP_Message *msg)
+{
+    OK(DP_canvas_history_handle(ch, dc, msg), "handle %s",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void fill_rect(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                      int x, int y, int w, int h, uint32_t color)
+{
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_fill_rect_new(1, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                                DP_int_to_uint32(x), DP_int_to_uint32(y),
+                                DP_int_to_uint32(w), DP_int_to_uint32(h),
+                                color));
+}
+
+static DP_CanvasState *make_canvas(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_canvas_resize_new(1, 0, WIDTH, HEIGHT, 0));
+    handle(TEST_ARGS, ch, dc,
+           DP_msg_layer_tree_create_new(1, LAYER_ID, 0, 0, 0, 0, "Layer", 5));
+    fill_rect(TEST_ARGS, ch, dc, 10, 5, 60, 40, 0xffff0000);
+    fill_rect(TEST_ARGS, ch, dc, 30, 15, 20, 20, 0xff0000ff);
+    fill_rect(TEST_ARGS, ch, dc, 40, 25, 35, 30, 0x8000ff00);
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    DP_canvas_history_free(ch);
+    return cs;
+}
+
+static DP_Image *select_source(DP_CanvasState *cs, const DP_Rect *src_rect)
+{
+    DP_LayerRoutes *lr = DP_canvas_state_layer_routes_noinc(cs);
+    DP_LayerRoutesEntry *lre = DP_layer_routes_search(lr, LAYER_ID);
+    return DP_layer_content_select(DP_layer_routes_entry_content(lre, cs),
+                                   src_rect, NULL);
+}
+
+static const DP_Pixel8 *get_source_pixels(void *user)
+{
+    SourcePixels *sp = user;
+    ++sp->gets;
+    return DP_image_pixels(sp->img);
+}
+
+static void dispose_source_pixels(void *user)
+{
+    SourcePixels *sp = user;
+    ++sp->disposes;
+}
+
+static void rendered(void *user, DP_UNUSED DP_Preview *pv)
+{
+    DP_semaphore_post(user);
+}
+
+static void cleared(void *user, DP_UNUSED DP_PreviewType type)
+{
+    DP_semaphore_post(user);
+}
+
+// Renders the preview on the render thread, then puts it and a cut preview of
+// the source rectangle on top of the canvas, like the paint engine would.
+static DP_CanvasState *apply_preview(DP_PreviewRenderer *pvr, DP_Semaphore *sem,
+                                     DP_CanvasState *cs, DP_Preview *cut,
+                                     DP_Preview *pv)
+{
+    DP_preview_renderer_push_noinc(pvr, DP_preview_incref(cut), WIDTH, HEIGHT,
+                                   0, 0);
+    DP_semaphore_wait(sem);
+    DP_preview_renderer_push_noinc(pvr, DP_preview_incref(pv), WIDTH, HEIGHT,
+                                   0, 0);
+    DP_semaphore_wait(sem);
+    DP_CanvasState *next_cs =
+        DP_preview_apply(cut, DP_canvas_state_incref(cs), pvr);
+    return DP_preview_apply(pv, next_cs, pvr);
+}
+
+static DP_Preview *new_preview(SourcePixels *sp, DP_Preview *prev_or_null,
+                               DP_Quad dst_quad)
+{
+    DP_Rect bounds = DP_quad_bounds(dst_quad);
+    if (prev_or_null) {
+        return DP_preview_new_transform_moved(
+            prev_or_null, 0, 0, bounds.x1, bounds.y1, &dst_quad,
+            DP_MSG_TRANSFORM_REGION_MODE_BILINEAR);
+    }
+    else {
+        return DP_preview_new_transform(
+            0, 0, LAYER_ID, bounds.x1, bounds.y1, DP_image_width(sp->img),
+            DP_image_height(sp->img), &dst_quad,
+            DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, get_source_pixels,
+            dispose_source_pixels, sp);
+    }
+}
+
+static int count_different(DP_Image *a, DP_Image *b)
+{
+    int different = 0;
+    for (int y = 0; y < HEIGHT; ++y) {
+        for (int x = 0; x < WIDTH; ++x) {
+            DP_Pixel8 pa = DP_image_pixel_at(a, x, y);
+            DP_Pixel8 pb = DP_image_pixel_at(b, x, y);
+            if (abs(pa.b - pb.b) > TOLERANCE || abs(pa.g - pb.g) > TOLERANCE
+                || abs(pa.r - pb.r) > TOLERANCE
+                || abs(pa.a - pb.a) > TOLERANCE) {
+                ++different;
+            }
+        }
+    }
+    return different;
+}
+
+static DP_Image *flatten(DP_CanvasState *cs)
+{
+    return DP_canvas_state_to_flat_image(cs, DP_FLAT_IMAGE_RENDER_FLAGS, NULL,
+                                         NULL);
+}
+
+
+static void preview_matches_commit(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Semaphore *sem = DP_semaphore_new(0);
+    DP_PreviewRenderer *pvr =
+        DP_preview_renderer_new(dc, rendered, NULL, cleared, sem);
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+
+    DP_Rect src_rect = DP_rect_make(20, 10, 40, 30);
+    SourcePixels sp = {select_source(cs, &src_rect), 0, 0};
+    DP_Preview *cut = DP_preview_new_cut(0, 0, LAYER_ID, src_rect.x1,
+                                         src_rect.y1, 40, 30, NULL);
+
+    DP_Quad quads[] = {
+        DP_quad_make(30, 20, 80, 28, 74, 62, 25, 55),
+        DP_quad_make(70, 40, 140, 35, 130, 95, 85, 90),
+        DP_quad_make(5, 50, 45, 60, 35, 98, 1, 80),
+    };
+    DP_Preview *pv = NULL;
+    for (int i = 0; i < (int)DP_ARRAY_LENGTH(quads); ++i) {
+        DP_Preview *next_pv = new_preview(&sp, pv, quads[i]);
+        DP_preview_decref_nullable(pv);
+        pv = next_pv;
+
+        DP_CanvasState *preview_cs = apply_preview(pvr, sem, cs, cut, pv);
+        DP_CanvasState *committed_cs = DP_ops_move_region(
+            cs, dc, NULL, 1, LAYER_ID, LAYER_ID, &src_rect, &quads[i],
+            DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, NULL);
+        FATAL(NOT_NULL_OK(committed_cs, "move region %d", i));
+
+        DP_Image *preview_img = flatten(preview_cs);
+        DP_Image *committed_img = flatten(committed_cs);
+        INT_EQ_OK(count_different(preview_img, committed_img), 0,
+                  "preview %d looks like the committed transform", i);
+        DP_image_free(committed_img);
+        DP_image_free(preview_img);
+        DP_canvas_state_decref(committed_cs);
+        DP_canvas_state_decref(preview_cs);
+    }
+    INT_EQ_OK(sp.gets, 1, "source pixels only get fetched once");
+    INT_EQ_OK(sp.disposes, 0, "source pixels are kept while previewing");
+
+    DP_preview_decref(pv);
+    DP_preview_decref(cut);
+    INT_EQ_OK(sp.disposes, 1, "source pixels get disposed at the end");
+
+    DP_image_free(sp.img);
+    DP_canvas_state_decref(cs);
+    DP_preview_renderer_free(pvr);
+    DP_semaphore_free(sem);
+    DP_draw_context_free(dc);
+}
+
+static void cancel_restores_original(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_Semaphore *sem = DP_semaphore_new(0);
+    DP_PreviewRenderer *pvr =
+        DP_preview_renderer_new(dc, rendered, NULL, cleared, sem);
+    DP_CanvasState *cs = make_canvas(TEST_ARGS, dc);
+    uint64_t checksum = DP_canvas_state_checksum(cs);
+    DP_Image *original_img = flatten(cs);
+
+    DP_Rect src_rect = DP_rect_make(20, 10, 40, 30);
+    SourcePixels sp = {select_source(cs, &src_rect), 0, 0};
+    DP_Preview *cut = DP_preview_new_cut(0, 0, LAYER_ID, src_rect.x1,
+                                         src_rect.y1, 40, 30, NULL);
+    DP_Preview *pv =
+        new_preview(&sp, NULL, DP_quad_make(30, 20, 80, 28, 74, 62, 25, 55));
+    DP_CanvasState *preview_cs = apply_preview(pvr, sem, cs, cut, pv);
+    DP_Image *preview_img = flatten(preview_cs);
+    OK(count_different(preview_img, original_img) != 0,
+       "preview changes what the canvas looks like");
+
+    OK(DP_canvas_state_checksum(cs) == checksum,
+       "preview doesn't touch the canvas state it's applied to");
+    DP_preview_renderer_cancel(pvr, DP_PREVIEW_TRANSFORM);
+    DP_semaphore_wait(sem);
+    DP_preview_renderer_cancel(pvr, DP_PREVIEW_CUT);
+    DP_semaphore_wait(sem);
+    DP_Image *cancelled_img = flatten(cs);
+    INT_EQ_OK(memcmp(DP_image_pixels(cancelled_img),
+                     DP_image_pixels(original_img),
+                     sizeof(DP_Pixel8) * WIDTH * HEIGHT),
+              0, "cancelling gives back the original exactly");
+
+    DP_image_free(cancelled_img);
+    DP_image_free(preview_img);
+    DP_canvas_state_decref(preview_cs);
+    DP_preview_decref(pv);
+    DP_preview_decref(cut);
+    DP_image_free(sp.img);
+    DP_image_free(original_img);
+    DP_canvas_state_decref(cs);
+    DP_preview_renderer_free(pvr);
+    DP_semaphore_free(sem);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(preview_matches_commit);
+    REGISTER_TEST(cancel_restores_original);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/canvas_history.h>
+#include <dpengine/canvas_state.h>
+#include <dpengine/draw_context.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+#include <string.h>
+
+
+#define WIDTH    100
+#define HEIGHT   100
+#define LAYER_ID 257
+#define USER_A   1
+#define USER_B   2
+
+static bool handle(DP_CanvasHistory *ch, DP_DrawContext *dc, DP_Message *msg)
+{
+    bool ok = DP_canvas_history_handle(ch, dc, msg);
+    DP_message_decref(msg);
+    return ok;
+}
+
+static DP_CanvasHistory *make_history(TEST_PARAMS, DP_DrawContext *dc)
+{
+    DP_CanvasHistory *ch = DP_canvas_history_new(NULL, NULL, false, NULL);
+    OK(handle(ch, dc, DP_msg_canvas_resize_new(USER_A, 0, WIDTH, HEIGHT, 0)),
+       "resize canvas");
+    OK(handle(ch, dc,
+              DP_msg_layer_tree_create_new(USER_A, LAYER_ID, 0, 0, 0, 0,
+                                           "Layer", 5)),
+       "create layer");
+    return ch;
+}
+
+// Every action gets a different spot and color, so undoing the wrong one
+// would show up in the checksum.
+static void act(TEST_PARAMS, DP_CanvasHistory *ch, DP_DrawContext *dc,
+                unsigned int user, int i)
+{
+    OK(handle(ch, dc, DP_msg_undo_point_new(user)), "undo point %d", i);
+    OK(handle(ch, dc,
+              DP_msg_fill_rect_new(
+                  user, LAYER_ID, DP_BLEND_MODE_NORMAL,
+                  DP_int_to_uint32(i * 7 % 80), DP_int_to_uint32(i * 13 % 80),
+                  20, 20, 0x80000000u | DP_int_to_uint32(i * 0x10305))),
+       "action %d", i);
+}
+
+static bool undo(DP_CanvasHistory *ch, DP_DrawContext *dc, unsigned int user)
+{
+    return handle(ch, dc, DP_msg_undo_new(user, 0, false));
+}
+
+static uint64_t checksum(DP_CanvasHistory *ch)
+{
+    DP_CanvasState *cs = DP_canvas_history_get(ch);
+    uint64_t value = DP_canvas_state_checksum(cs);
+    DP_canvas_state_decref(cs);
+    return value;
+}
+
+static int history_count(DP_CanvasHistory *ch)
+{
+    DP_CanvasHistorySnapshot *chs = DP_canvas_history_snapshot_new(ch);
+    int count = DP_canvas_history_snapshot_history_count(chs);
+    DP_canvas_history_snapshot_decref(chs);
+    return count;
+}
+
+
+static void undo_beyond_limit_is_rejected(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_canvas_history_undo_depth_limit_set(ch, dc, 5);
+    INT_EQ_OK(DP_canvas_history_undo_depth_limit(ch), 5, "undo depth is 5");
+    for (int i = 0; i < 12; ++i) {
+        act(TEST_ARGS, ch, dc, USER_A, i);
+    }
+
+    int undone = 0;
+    while (undo(ch, dc, USER_A)) {
+        ++undone;
+    }
+    INT_EQ_OK(undone, 5, "only as many undos as the limit");
+    OK(strstr(DP_error(), "beyond history limit 5") != NULL,
+       "rejection says why (error: %s)", DP_error());
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void history_stops_growing(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_canvas_history_undo_depth_limit_set(ch, dc, 4);
+
+    int max_count = 0;
+    int count_at_half = 0;
+    for (int i = 0; i < 200; ++i) {
+        act(TEST_ARGS, ch, dc, i % 3 == 0 ? USER_B : USER_A, i);
+        int count = history_count(ch);
+        if (count > max_count) {
+            max_count = count;
+        }
+        if (i == 99) {
+            count_at_half = count;
+        }
+    }
+    // Two entries per action, the limit's worth of them plus the one before,
+    // which holds the save point to replay from.
+    OK(max_count <= (4 + 1) * 2,
+       "history is bounded by the undo depth (at most %d entries)", max_count);
+    INT_EQ_OK(history_count(ch), count_at_half,
+              "history doesn't grow on long sessions");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+static void lowering_limit_keeps_canvas(TEST_PARAMS)
+{
+    DP_DrawContext *dc = DP_draw_context_new();
+    DP_CanvasHistory *ch = make_history(TEST_ARGS, dc);
+    DP_canvas_history_undo_depth_limit_set(ch, dc, 10);
+    for (int i = 0; i < 8; ++i) {
+        act(TEST_ARGS, ch, dc, i % 2 == 0 ? USER_A : USER_B, i);
+    }
+    OK(undo(ch, dc, USER_A), "A undoes once");
+    OK(undo(ch, dc, USER_B), "B undoes once");
+    uint64_t before = checksum(ch);
+
+    // This is what the paint engine does when it gets an undo depth message.
+    DP_canvas_history_undo_depth_limit_set(ch, dc, 3);
+    INT_EQ_OK(DP_canvas_history_undo_depth_limit(ch), 3, "undo depth is 3");
+    OK(checksum(ch) == before, "lowering the limit doesn't change the canvas");
+    NOK(handle(ch, dc, DP_msg_undo_new(USER_A, 0, true)),
+        "in-flight undos can't be redone past the limit change");
+    OK(checksum(ch) == before, "failed redo doesn't change the canvas either");
+
+    act(TEST_ARGS, ch, dc, USER_A, 8);
+    uint64_t after_action = checksum(ch);
+    OK(undo(ch, dc, USER_A), "A can undo new actions");
+    OK(checksum(ch) == before, "undoing that gives back the previous canvas");
+    NOK(undo(ch, dc, USER_A), "A can't undo beyond the limit change");
+    OK(handle(ch, dc, DP_msg_undo_new(USER_A, 0, true)), "A redoes");
+    OK(checksum(ch) == after_action, "redo brings back the new action");
+
+    DP_canvas_history_free(ch);
+    DP_draw_context_free(dc);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(undo_beyond_limit_is_rejected);
+    REGISTER_TEST(history_stops_growing);
+    REGISTER_TEST(lowering_limit_keeps_canvas);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
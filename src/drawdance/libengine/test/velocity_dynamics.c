@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpengine/brush.h>
+#include <dpengine/brush_engine.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest_engine.h>
+
+
+#define MAX_DABS       1024
+#define POINT_COUNT    40
+#define POINT_MSEC     10
+#define BRUSH_SIZE_MIN 1.0f
+#define BRUSH_SIZE_MAX 21.0f
+#define MAX_VELOCITY   5.0f
+
+typedef struct DP_VelocityTestDabs {
+    int count;
+    uint16_t sizes[MAX_DABS];
+} DP_VelocityTestDabs;
+
+static void collect_sizes(void *user, DP_Message *msg)
+{
+    DP_VelocityTestDabs *vtds = user;
+    if (DP_message_type(msg) == DP_MSG_DRAW_DABS_CLASSIC) {
+        int count;
+        const DP_ClassicDab *dabs =
+            DP_msg_draw_dabs_classic_dabs(DP_message_internal(msg), &count);
+        for (int i = 0; i < count && vtds->count < MAX_DABS; ++i) {
+            vtds->sizes[vtds->count++] =
+                DP_classic_dab_size(DP_classic_dab_at(dabs, i));
+        }
+    }
+    DP_message_decref(msg);
+}
+
+static void init_brush(DP_ClassicBrush *cb, DP_ClassicBrushDynamicType type)
+{
+    *cb = (DP_ClassicBrush){0};
+    cb->size.min = BRUSH_SIZE_MIN;
+    cb->size.max = BRUSH_SIZE_MAX;
+    for (int i = 0; i < DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT; ++i) {
+        cb->size.curve.values[i] =
+            DP_int_to_float(i)
+            / DP_int_to_float(DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT - 1);
+    }
+    cb->hardness.max = 1.0f;
+    cb->opacity.max = 1.0f;
+    cb->spacing = 0.25f;
+    cb->color = (DP_UPixelFloat){0.0f, 0.0f, 0.0f, 1.0f};
+    cb->shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
+    cb->brush_mode = DP_BLEND_MODE_NORMAL;
+    cb->erase_mode = DP_BLEND_MODE_ERASE;
+    cb->incremental = true;
+    cb->size_dynamic.type = type;
+    cb->size_dynamic.max_velocity = MAX_VELOCITY;
+}
+
+// Strokes to the right at a constant pressure, with a fixed time between the
+// points. Each step gives the distance to the next point, so the speed of each
+// segment is the step divided by POINT_MSEC.
+static void stroke_sizes(const DP_ClassicBrush *cb, const float *steps,
+                         float pressure, DP_VelocityTestDabs *vtds)
+{
+    vtds->count = 0;
+    DP_BrushEngine *be = DP_brush_engine_new(collect_sizes, NULL, vtds);
+    DP_StrokeParams stroke = {1, false, 0, false, 0, false, 0,
+                              {DP_SYMMETRY_NONE, 0.0f, 0.0f, 0}};
+    DP_brush_engine_classic_brush_set(be, cb, &stroke, NULL, false);
+    DP_brush_engine_stroke_begin(be, 1, false, 1.0f);
+    float x = 0.0f;
+    for (int i = 0; i < POINT_COUNT; ++i) {
+        DP_brush_engine_stroke_to(
+            be,
+            (DP_BrushPoint){x, 0.0f, pressure, 0.0f, 0.0f, 0.0f,
+                            i * POINT_MSEC},
+            NULL);
+        x += steps[i];
+    }
+    DP_brush_engine_stroke_end(be, POINT_COUNT * POINT_MSEC, NULL, false);
+    DP_brush_engine_free(be);
+}
+
+static void constant_steps(float *steps, float step)
+{
+    for (int i = 0; i < POINT_COUNT; ++i) {
+        steps[i] = step;
+    }
+}
+
+static uint16_t last_size(const DP_VelocityTestDabs *vtds)
+{
+    return vtds->count == 0 ? 0 : vtds->sizes[vtds->count - 1];
+}
+
+static bool sizes_non_decreasing(const DP_VelocityTestDabs *vtds)
+{
+    for (int i = 1; i < vtds->count; ++i) {
+        if (vtds->sizes[i] < vtds->sizes[i - 1]) {
+            return false;
+        }
+    }
+    return true;
+}
+
+static bool sizes_non_increasing(const DP_VelocityTestDabs *vtds)
+{
+    for (int i = 1; i < vtds->count; ++i) {
+        if (vtds->sizes[i] > vtds->sizes[i - 1]) {
+            return false;
+        }
+    }
+    return true;
+}
+
+
+static void velocity_size_follows_speed(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY);
+    float steps[POINT_COUNT];
+    DP_VelocityTestDabs slow, fast;
+
+    // 500 pixels per second.
+    constant_steps(steps, 5.0f);
+    stroke_sizes(&cb, steps, 1.0f, &slow);
+    if (OK(slow.count > 10, "slow stroke has %d dabs", slow.count)) {
+        UINT_EQ_OK(slow.sizes[0], (unsigned int)(BRUSH_SIZE_MIN * 256.0f),
+                   "stroke starts at minimum size");
+        OK(sizes_non_decreasing(&slow), "dabs grow as the stroke speeds up");
+        uint16_t size = last_size(&slow);
+        OK(size > BRUSH_SIZE_MIN * 256.0f && size < BRUSH_SIZE_MAX * 256.0f,
+           "steady size %u is between minimum and maximum", (unsigned int)size);
+        int settled = slow.sizes[slow.count - 10];
+        OK(settled >= size - 1 && settled <= size + 1,
+           "size settles at constant speed, %d vs. %u", settled,
+           (unsigned int)size);
+    }
+
+    // 2000 pixels per second, faster than the maximum velocity.
+    constant_steps(steps, 20.0f);
+    stroke_sizes(&cb, steps, 1.0f, &fast);
+    if (OK(fast.count > 10, "fast stroke has %d dabs", fast.count)) {
+        OK(last_size(&fast) > last_size(&slow),
+           "fast stroke ends up bigger (%u) than slow one (%u)",
+           (unsigned int)last_size(&fast), (unsigned int)last_size(&slow));
+        UINT_EQ_OK(last_size(&fast), (unsigned int)(BRUSH_SIZE_MAX * 256.0f),
+                   "fast stroke reaches maximum size");
+    }
+}
+
+static void velocity_size_follows_slowdown(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY);
+
+    // Fast for the first half, then slowing down to a crawl.
+    float steps[POINT_COUNT];
+    for (int i = 0; i < POINT_COUNT; ++i) {
+        steps[i] = i < POINT_COUNT / 2 ? 10.0f : 0.5f;
+    }
+    DP_VelocityTestDabs vtds;
+    stroke_sizes(&cb, steps, 1.0f, &vtds);
+    if (OK(vtds.count > 10, "stroke has %d dabs", vtds.count)) {
+        int peak = 0;
+        for (int i = 1; i < vtds.count; ++i) {
+            if (vtds.sizes[i] > vtds.sizes[peak]) {
+                peak = i;
+            }
+        }
+        DP_VelocityTestDabs tail = {0, {0}};
+        for (int i = peak; i < vtds.count; ++i) {
+            tail.sizes[tail.count++] = vtds.sizes[i];
+        }
+        OK(sizes_non_increasing(&tail), "dabs shrink after slowing down");
+        OK(last_size(&vtds) < vtds.sizes[peak] / 2,
+           "size drops from %u to %u", (unsigned int)vtds.sizes[peak],
+           (unsigned int)last_size(&vtds));
+    }
+}
+
+static void pressure_velocity_combines_both(TEST_PARAMS)
+{
+    DP_ClassicBrush cb;
+    init_brush(&cb, DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY);
+    float steps[POINT_COUNT];
+    DP_VelocityTestDabs full, half, fast;
+
+    // 50 pixels per second, which is slow enough to let most pressure through.
+    constant_steps(steps, 0.5f);
+    stroke_sizes(&cb, steps, 1.0f, &full);
+    stroke_sizes(&cb, steps, 0.5f, &half);
+    if (OK(full.count > 2 && half.count > 2, "slow strokes have dabs")) {
+        UINT_EQ_OK(full.sizes[0], (unsigned int)(BRUSH_SIZE_MAX * 256.0f),
+                   "stroke starts out at full pressure size");
+        OK(last_size(&full) > BRUSH_SIZE_MAX * 256.0f * 0.8f,
+           "slow stroke at full pressure stays big, got %u",
+           (unsigned int)last_size(&full));
+        OK(last_size(&half) < last_size(&full) * 0.6f,
+           "slow stroke at half pressure is smaller, got %u vs. %u",
+           (unsigned int)last_size(&half), (unsigned int)last_size(&full));
+    }
+
+    constant_steps(steps, 20.0f);
+    stroke_sizes(&cb, steps, 1.0f, &fast);
+    if (OK(fast.count > 2, "fast stroke has %d dabs", fast.count)) {
+        OK(sizes_non_increasing(&fast), "dabs get thinner as stroke speeds up");
+        UINT_EQ_OK(last_size(&fast), (unsigned int)(BRUSH_SIZE_MIN * 256.0f),
+                   "fast stroke thins out to minimum size");
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(velocity_size_follows_speed);
+    REGISTER_TEST(velocity_size_follows_slowdown);
+    REGISTER_TEST(pressure_velocity_combines_both);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
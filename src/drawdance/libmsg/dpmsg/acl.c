@@ -804,6 +804,18 @@ static bool handle_annotation_reshape(DP_AclState *acls, DP_Message *msg,
         || !DP_acl_state_annotation_locked(acls, annotation_id);
 }
 
+static bool handle_annotation_order(DP_AclState *acls, DP_Message *msg,
+                                    uint8_t user_id, bool override)
+{
+    if (override) {
+        return true;
+    }
+    DP_MsgAnnotationOrder *mao = DP_msg_annotation_order_cast(msg);
+    int annotation_id = DP_msg_annotation_order_id(mao);
+    return owns_id(user_id, annotation_id) || DP_acl_state_is_op(acls, user_id)
+        || !DP_acl_state_annotation_locked(acls, annotation_id);
+}
+
 static bool handle_annotation_edit(DP_AclState *acls, DP_Message *msg,
                                    uint8_t user_id, bool override)
 {
@@ -947,6 +959,11 @@ static bool handle_command_message(DP_AclState *acls, DP_Message *msg,
             || can_edit_layer(
                    acls, user_id,
                    DP_msg_layer_retitle_id(DP_msg_layer_retitle_cast(msg)));
+    case DP_MSG_LAYER_METADATA:
+        return override
+            || can_edit_layer(
+                   acls, user_id,
+                   DP_msg_layer_metadata_id(DP_msg_layer_metadata_cast(msg)));
     case DP_MSG_LAYER_ORDER:
         return override
             || DP_acl_state_can_use_feature(acls, DP_FEATURE_EDIT_LAYERS,
@@ -972,6 +989,22 @@ static bool handle_command_message(DP_AclState *acls, DP_Message *msg,
                 && !DP_acl_state_layer_locked_for(
                     acls, user_id,
                     DP_msg_fill_rect_layer(DP_msg_fill_rect_cast(msg))));
+    case DP_MSG_FILL_GRADIENT:
+        return override
+            || (DP_acl_state_can_use_feature(acls, DP_FEATURE_PUT_IMAGE,
+                                             user_id)
+                && !DP_acl_state_layer_locked_for(
+                    acls, user_id,
+                    DP_msg_fill_gradient_layer(
+                        DP_msg_fill_gradient_cast(msg))));
+    case DP_MSG_FILTER_REGION:
+        return override
+            || (DP_acl_state_can_use_feature(acls, DP_FEATURE_PUT_IMAGE,
+                                             user_id)
+                && !DP_acl_state_layer_locked_for(
+                    acls, user_id,
+                    DP_msg_filter_region_layer(
+                        DP_msg_filter_region_cast(msg))));
     case DP_MSG_ANNOTATION_CREATE:
         return handle_annotation_create(acls, msg, user_id, override);
     case DP_MSG_ANNOTATION_RESHAPE:
@@ -980,6 +1013,8 @@ static bool handle_command_message(DP_AclState *acls, DP_Message *msg,
         return handle_annotation_edit(acls, msg, user_id, override);
     case DP_MSG_ANNOTATION_DELETE:
         return handle_annotation_delete(acls, msg, user_id, override);
+    case DP_MSG_ANNOTATION_ORDER:
+        return handle_annotation_order(acls, msg, user_id, override);
     case DP_MSG_MOVE_REGION:
         return handle_move_region(acls, msg, user_id, override);
     case DP_MSG_PUT_TILE:
@@ -1006,6 +1041,18 @@ static bool handle_command_message(DP_AclState *acls, DP_Message *msg,
                    acls, user_id,
                    DP_msg_draw_dabs_pixel_layer(
                        DP_msg_draw_dabs_pixel_square_cast(msg)));
+    case DP_MSG_DRAW_DABS_STAMP:
+        return override
+            || !DP_acl_state_layer_locked_for(
+                   acls, user_id,
+                   DP_msg_draw_dabs_stamp_layer(
+                       DP_msg_draw_dabs_stamp_cast(msg)));
+    case DP_MSG_DRAW_DABS_SOFT:
+        return override
+            || !DP_acl_state_layer_locked_for(
+                   acls, user_id,
+                   DP_msg_draw_dabs_soft_layer(
+                       DP_msg_draw_dabs_soft_cast(msg)));
     case DP_MSG_DRAW_DABS_MYPAINT:
         return override
             || (DP_acl_state_can_use_feature(acls, DP_FEATURE_MYPAINT, user_id)
@@ -1154,15 +1201,22 @@ DP_Message *DP_acl_state_msg_feature_access_all_new(unsigned int context_id)
                                             DP_FEATURE_COUNT, tiers);
 }
 
+// User 0 is the server, which always counts as an operator and never as
+// locked, so it gets left out of these lists without changing anything. That
+// keeps them within the 255 ids the messages can hold. Playback makes every
+// user an operator, which would otherwise be one id too many.
 static bool reset_image_push_users(
     unsigned int context_id, DP_UserBits users,
     DP_Message *(*make_message)(unsigned int, void (*)(int, uint8_t *, void *),
                                 int, void *),
     bool (*push_message)(void *, DP_Message *), void *user)
 {
-    int count = count_user_bits(users);
+    DP_UserBits client_users;
+    memcpy(client_users, users, sizeof(client_users));
+    DP_user_bit_unset(client_users, 0);
+    int count = count_user_bits(client_users);
     DP_Message *user_acl_message =
-        make_message(context_id, set_message_user_bits, count, users);
+        make_message(context_id, set_message_user_bits, count, client_users);
     return push_message(user, user_acl_message);
 }
 
@@ -317,6 +317,20 @@ bool DP_blend_mode_preserves_alpha(int blend_mode)
     return get_attributes(blend_mode)->flags & PRESERVES_ALPHA;
 }
 
+int DP_blend_mode_to_alpha_preserving(int blend_mode)
+{
+    int flags = get_attributes(blend_mode)->flags;
+    if (flags & PRESERVES_ALPHA) {
+        return blend_mode;
+    }
+    else if ((flags & INCREASE_OPACITY) && blend_mode != DP_BLEND_MODE_BEHIND) {
+        return DP_BLEND_MODE_RECOLOR;
+    }
+    else {
+        return -1;
+    }
+}
+
 DP_BlendMode DP_blend_mode_by_svg_name(const char *svg_name,
                                        DP_BlendMode not_found_value)
 {
@@ -81,6 +81,12 @@ bool DP_blend_mode_blend_blank(int blend_mode);
 
 bool DP_blend_mode_preserves_alpha(int blend_mode);
 
+// Returns the blend mode to use when drawing on an alpha-locked layer. Modes
+// that already preserve alpha are returned as-is, painting modes turn into
+// recolor. Modes that can only change the layer's alpha, like erasing or
+// painting behind, return -1, meaning the operation is a no-op.
+int DP_blend_mode_to_alpha_preserving(int blend_mode);
+
 DP_BlendMode DP_blend_mode_by_svg_name(const char *svg_name,
                                        DP_BlendMode not_found_value);
 
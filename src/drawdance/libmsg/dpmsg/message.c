@@ -315,6 +315,24 @@ bool DP_message_equals(DP_Message *msg, DP_Message *other)
     }
 }
 
+bool DP_message_bypasses_recording(DP_Message *msg)
+{
+    DP_ASSERT(msg);
+    DP_ASSERT(DP_atomic_get(&msg->refcount) > 0);
+    if (msg->type != DP_MSG_CHAT) {
+        return false;
+    }
+    else if (msg->flags & FLAG_OPAQUE) {
+        // The transport flags are the first byte of the body.
+        DP_OpaqueMessage *om = (void *)msg->internal;
+        return om->length != 0 && (om->body[0] & DP_MSG_CHAT_TFLAGS_BYPASS);
+    }
+    else {
+        DP_MsgChat *mc = (void *)msg->internal;
+        return DP_msg_chat_tflags(mc) & DP_MSG_CHAT_TFLAGS_BYPASS;
+    }
+}
+
 
 DP_Message *DP_message_deserialize_length(const unsigned char *buf,
                                           size_t bufsize, size_t body_length,
@@ -380,3 +398,15 @@ bool DP_msg_draw_dabs_pixel_indirect(DP_MsgDrawDabsPixel *mddp)
     DP_ASSERT(mddp);
     return has_alpha(DP_msg_draw_dabs_pixel_color(mddp));
 }
+
+bool DP_msg_draw_dabs_stamp_indirect(DP_MsgDrawDabsStamp *mdds)
+{
+    DP_ASSERT(mdds);
+    return has_alpha(DP_msg_draw_dabs_stamp_color(mdds));
+}
+
+bool DP_msg_draw_dabs_soft_indirect(DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return has_alpha(DP_msg_draw_dabs_soft_color(mdds));
+}
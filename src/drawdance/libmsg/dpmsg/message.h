@@ -86,6 +86,10 @@ bool DP_message_write_text(DP_Message *msg,
 
 bool DP_message_equals(DP_Message *msg, DP_Message *other);
 
+// Chat with the bypass flag is meant to stay out of the session history, so
+// it doesn't get recorded either.
+bool DP_message_bypasses_recording(DP_Message *msg);
+
 
 DP_Message *DP_message_deserialize_length(const unsigned char *buf,
                                           size_t bufsize, size_t body_length,
@@ -103,5 +107,9 @@ bool DP_msg_draw_dabs_classic_indirect(DP_MsgDrawDabsClassic *mddc);
 
 bool DP_msg_draw_dabs_pixel_indirect(DP_MsgDrawDabsPixel *mddp);
 
+bool DP_msg_draw_dabs_stamp_indirect(DP_MsgDrawDabsStamp *mdds);
+
+bool DP_msg_draw_dabs_soft_indirect(DP_MsgDrawDabsSoft *mdds);
+
 
 #endif
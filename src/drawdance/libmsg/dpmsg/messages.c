@@ -108,6 +108,9 @@ bool DP_message_type_command(DP_MessageType type)
     case DP_MSG_DRAW_DABS_PIXEL:
     case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
     case DP_MSG_DRAW_DABS_MYPAINT:
+    case DP_MSG_STAMP_MASK:
+    case DP_MSG_DRAW_DABS_STAMP:
+    case DP_MSG_DRAW_DABS_SOFT:
     case DP_MSG_MOVE_RECT:
     case DP_MSG_SET_METADATA_INT:
     case DP_MSG_LAYER_TREE_CREATE:
@@ -122,6 +125,10 @@ bool DP_message_type_command(DP_MessageType type)
     case DP_MSG_KEY_FRAME_RETITLE:
     case DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES:
     case DP_MSG_KEY_FRAME_DELETE:
+    case DP_MSG_FILTER_REGION:
+    case DP_MSG_FILL_GRADIENT:
+    case DP_MSG_ANNOTATION_ORDER:
+    case DP_MSG_LAYER_METADATA:
     case DP_MSG_UNDO:
         return true;
     default:
@@ -230,6 +237,12 @@ const char *DP_message_type_name(DP_MessageType type)
         return "squarepixeldabs";
     case DP_MSG_DRAW_DABS_MYPAINT:
         return "mypaintdabs";
+    case DP_MSG_STAMP_MASK:
+        return "stampmask";
+    case DP_MSG_DRAW_DABS_STAMP:
+        return "stampdabs";
+    case DP_MSG_DRAW_DABS_SOFT:
+        return "softdabs";
     case DP_MSG_MOVE_RECT:
         return "moverect";
     case DP_MSG_SET_METADATA_INT:
@@ -258,6 +271,14 @@ const char *DP_message_type_name(DP_MessageType type)
         return "keyframelayerattributes";
     case DP_MSG_KEY_FRAME_DELETE:
         return "keyframedelete";
+    case DP_MSG_FILTER_REGION:
+        return "filterregion";
+    case DP_MSG_FILL_GRADIENT:
+        return "gradientfill";
+    case DP_MSG_ANNOTATION_ORDER:
+        return "orderannotation";
+    case DP_MSG_LAYER_METADATA:
+        return "layermeta";
     case DP_MSG_UNDO:
         return "undo";
     default:
@@ -366,6 +387,12 @@ const char *DP_message_type_enum_name(DP_MessageType type)
         return "DP_MSG_DRAW_DABS_PIXEL_SQUARE";
     case DP_MSG_DRAW_DABS_MYPAINT:
         return "DP_MSG_DRAW_DABS_MYPAINT";
+    case DP_MSG_STAMP_MASK:
+        return "DP_MSG_STAMP_MASK";
+    case DP_MSG_DRAW_DABS_STAMP:
+        return "DP_MSG_DRAW_DABS_STAMP";
+    case DP_MSG_DRAW_DABS_SOFT:
+        return "DP_MSG_DRAW_DABS_SOFT";
     case DP_MSG_MOVE_RECT:
         return "DP_MSG_MOVE_RECT";
     case DP_MSG_SET_METADATA_INT:
@@ -394,6 +421,14 @@ const char *DP_message_type_enum_name(DP_MessageType type)
         return "DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES";
     case DP_MSG_KEY_FRAME_DELETE:
         return "DP_MSG_KEY_FRAME_DELETE";
+    case DP_MSG_FILTER_REGION:
+        return "DP_MSG_FILTER_REGION";
+    case DP_MSG_FILL_GRADIENT:
+        return "DP_MSG_FILL_GRADIENT";
+    case DP_MSG_ANNOTATION_ORDER:
+        return "DP_MSG_ANNOTATION_ORDER";
+    case DP_MSG_LAYER_METADATA:
+        return "DP_MSG_LAYER_METADATA";
     case DP_MSG_UNDO:
         return "DP_MSG_UNDO";
     default:
@@ -544,6 +579,15 @@ DP_MessageType DP_message_type_from_name(const char *type_name,
     else if (DP_str_equal(type_name, "mypaintdabs")) {
         return DP_MSG_DRAW_DABS_MYPAINT;
     }
+    else if (DP_str_equal(type_name, "stampmask")) {
+        return DP_MSG_STAMP_MASK;
+    }
+    else if (DP_str_equal(type_name, "stampdabs")) {
+        return DP_MSG_DRAW_DABS_STAMP;
+    }
+    else if (DP_str_equal(type_name, "softdabs")) {
+        return DP_MSG_DRAW_DABS_SOFT;
+    }
     else if (DP_str_equal(type_name, "moverect")) {
         return DP_MSG_MOVE_RECT;
     }
@@ -586,6 +630,18 @@ DP_MessageType DP_message_type_from_name(const char *type_name,
     else if (DP_str_equal(type_name, "keyframedelete")) {
         return DP_MSG_KEY_FRAME_DELETE;
     }
+    else if (DP_str_equal(type_name, "filterregion")) {
+        return DP_MSG_FILTER_REGION;
+    }
+    else if (DP_str_equal(type_name, "gradientfill")) {
+        return DP_MSG_FILL_GRADIENT;
+    }
+    else if (DP_str_equal(type_name, "orderannotation")) {
+        return DP_MSG_ANNOTATION_ORDER;
+    }
+    else if (DP_str_equal(type_name, "layermeta")) {
+        return DP_MSG_LAYER_METADATA;
+    }
     else if (DP_str_equal(type_name, "undo")) {
         return DP_MSG_UNDO;
     }
@@ -601,6 +657,9 @@ bool DP_message_type_parse_multiline_tuples(DP_MessageType type)
     case DP_MSG_DRAW_DABS_PIXEL:
     case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
     case DP_MSG_DRAW_DABS_MYPAINT:
+    case DP_MSG_DRAW_DABS_STAMP:
+    case DP_MSG_DRAW_DABS_SOFT:
+    case DP_MSG_FILL_GRADIENT:
         return true;
     default:
         return false;
@@ -728,6 +787,12 @@ DP_Message *DP_message_deserialize_body(int type, unsigned int context_id,
         case DP_MSG_DRAW_DABS_MYPAINT:
             return DP_msg_draw_dabs_mypaint_deserialize(context_id, buf,
                                                         length);
+        case DP_MSG_STAMP_MASK:
+            return DP_msg_stamp_mask_deserialize(context_id, buf, length);
+        case DP_MSG_DRAW_DABS_STAMP:
+            return DP_msg_draw_dabs_stamp_deserialize(context_id, buf, length);
+        case DP_MSG_DRAW_DABS_SOFT:
+            return DP_msg_draw_dabs_soft_deserialize(context_id, buf, length);
         case DP_MSG_MOVE_RECT:
             return DP_msg_move_rect_deserialize(context_id, buf, length);
         case DP_MSG_SET_METADATA_INT:
@@ -760,6 +825,14 @@ DP_Message *DP_message_deserialize_body(int type, unsigned int context_id,
                                                                  buf, length);
         case DP_MSG_KEY_FRAME_DELETE:
             return DP_msg_key_frame_delete_deserialize(context_id, buf, length);
+        case DP_MSG_FILTER_REGION:
+            return DP_msg_filter_region_deserialize(context_id, buf, length);
+        case DP_MSG_FILL_GRADIENT:
+            return DP_msg_fill_gradient_deserialize(context_id, buf, length);
+        case DP_MSG_ANNOTATION_ORDER:
+            return DP_msg_annotation_order_deserialize(context_id, buf, length);
+        case DP_MSG_LAYER_METADATA:
+            return DP_msg_layer_metadata_deserialize(context_id, buf, length);
         case DP_MSG_UNDO:
             return DP_msg_undo_deserialize(context_id, buf, length);
         default:
@@ -880,6 +953,12 @@ DP_Message *DP_message_parse_body(DP_MessageType type, unsigned int context_id,
         return DP_msg_draw_dabs_pixel_square_parse(context_id, reader);
     case DP_MSG_DRAW_DABS_MYPAINT:
         return DP_msg_draw_dabs_mypaint_parse(context_id, reader);
+    case DP_MSG_STAMP_MASK:
+        return DP_msg_stamp_mask_parse(context_id, reader);
+    case DP_MSG_DRAW_DABS_STAMP:
+        return DP_msg_draw_dabs_stamp_parse(context_id, reader);
+    case DP_MSG_DRAW_DABS_SOFT:
+        return DP_msg_draw_dabs_soft_parse(context_id, reader);
     case DP_MSG_MOVE_RECT:
         return DP_msg_move_rect_parse(context_id, reader);
     case DP_MSG_SET_METADATA_INT:
@@ -908,6 +987,14 @@ DP_Message *DP_message_parse_body(DP_MessageType type, unsigned int context_id,
         return DP_msg_key_frame_layer_attributes_parse(context_id, reader);
     case DP_MSG_KEY_FRAME_DELETE:
         return DP_msg_key_frame_delete_parse(context_id, reader);
+    case DP_MSG_FILTER_REGION:
+        return DP_msg_filter_region_parse(context_id, reader);
+    case DP_MSG_FILL_GRADIENT:
+        return DP_msg_fill_gradient_parse(context_id, reader);
+    case DP_MSG_ANNOTATION_ORDER:
+        return DP_msg_annotation_order_parse(context_id, reader);
+    case DP_MSG_LAYER_METADATA:
+        return DP_msg_layer_metadata_parse(context_id, reader);
     case DP_MSG_UNDO:
         return DP_msg_undo_parse(context_id, reader);
     default:
@@ -3843,6 +3930,8 @@ const char *DP_msg_layer_attributes_flags_flag_name(unsigned int value)
         return "fixed";
     case DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED:
         return "isolated";
+    case DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK:
+        return "alpha_lock";
     default:
         return NULL;
     }
@@ -3881,11 +3970,12 @@ static bool msg_layer_attributes_write_payload_text(DP_Message *msg,
     DP_MsgLayerAttributes *mla = DP_message_internal(msg);
     return DP_text_writer_write_blend_mode(writer, "blend", mla->blend)
         && DP_text_writer_write_flags(
-               writer, "flags", mla->flags, 3,
-               (const char *[]){"censor", "fixed", "isolated"},
+               writer, "flags", mla->flags, 4,
+               (const char *[]){"censor", "fixed", "isolated", "alpha_lock"},
                (unsigned int[]){DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR,
                                 DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED,
-                                DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED})
+                                DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED,
+                                DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK})
         && DP_text_writer_write_uint(writer, "id", mla->id, true)
         && DP_text_writer_write_uint(writer, "opacity", mla->opacity, false)
         && DP_text_writer_write_uint(writer, "sublayer", mla->sublayer, false);
@@ -3951,10 +4041,12 @@ DP_Message *DP_msg_layer_attributes_parse(unsigned int context_id,
     uint8_t sublayer =
         (uint8_t)DP_text_reader_get_ulong(reader, "sublayer", UINT8_MAX);
     uint8_t flags = (uint8_t)DP_text_reader_get_flags(
-        reader, "flags", 3, (const char *[]){"censor", "fixed", "isolated"},
+        reader, "flags", 4,
+        (const char *[]){"censor", "fixed", "isolated", "alpha_lock"},
         (unsigned int[]){DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR,
                          DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED,
-                         DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED});
+                         DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED,
+                         DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK});
     uint8_t opacity =
         (uint8_t)DP_text_reader_get_ulong(reader, "opacity", UINT8_MAX);
     uint8_t blend = DP_text_reader_get_blend_mode(reader, "blend");
@@ -5109,6 +5201,8 @@ const char *DP_msg_annotation_edit_flags_flag_name(unsigned int value)
         return "valign_center";
     case DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM:
         return "valign_bottom";
+    case DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW:
+        return "auto_grow";
     default:
         return NULL;
     }
@@ -5150,11 +5244,13 @@ static bool msg_annotation_edit_write_payload_text(DP_Message *msg,
     return DP_text_writer_write_argb_color(writer, "bg", mae->bg)
         && DP_text_writer_write_uint(writer, "border", mae->border, false)
         && DP_text_writer_write_flags(
-               writer, "flags", mae->flags, 3,
-               (const char *[]){"protect", "valign_center", "valign_bottom"},
+               writer, "flags", mae->flags, 4,
+               (const char *[]){"protect", "valign_center", "valign_bottom",
+                                "auto_grow"},
                (unsigned int[]){DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT,
                                 DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER,
-                                DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM})
+                                DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM,
+                                DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW})
         && DP_text_writer_write_uint(writer, "id", mae->id, true)
         && DP_text_writer_write_string(writer, "text", mae->text);
 }
@@ -5223,11 +5319,13 @@ DP_Message *DP_msg_annotation_edit_parse(unsigned int context_id,
         (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
     uint32_t bg = DP_text_reader_get_argb_color(reader, "bg");
     uint8_t flags = (uint8_t)DP_text_reader_get_flags(
-        reader, "flags", 3,
-        (const char *[]){"protect", "valign_center", "valign_bottom"},
+        reader, "flags", 4,
+        (const char *[]){"protect", "valign_center", "valign_bottom",
+                         "auto_grow"},
         (unsigned int[]){DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT,
                          DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER,
-                         DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM});
+                         DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM,
+                         DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW});
     uint8_t border =
         (uint8_t)DP_text_reader_get_ulong(reader, "border", UINT8_MAX);
     uint16_t text_len;
@@ -7103,2146 +7201,4005 @@ int DP_msg_draw_dabs_mypaint_dabs_count(const DP_MsgDrawDabsMyPaint *mddmp)
 }
 
 
-/* DP_MSG_MOVE_RECT */
+/* DP_MSG_STAMP_MASK */
 
-struct DP_MsgMoveRect {
-    uint16_t layer;
-    uint16_t source;
-    int32_t sx;
-    int32_t sy;
-    int32_t tx;
-    int32_t ty;
-    int32_t w;
-    int32_t h;
-    uint16_t mask_size;
-    unsigned char mask[];
+struct DP_MsgStampMask {
+    uint32_t mask;
+    uint16_t size;
+    uint16_t image_size;
+    unsigned char image[];
 };
 
-static size_t msg_move_rect_payload_length(DP_Message *msg)
+static size_t msg_stamp_mask_payload_length(DP_Message *msg)
 {
-    DP_MsgMoveRect *mmr = DP_message_internal(msg);
-    return ((size_t)28) + mmr->mask_size;
+    DP_MsgStampMask *msm = DP_message_internal(msg);
+    return ((size_t)6) + msm->image_size;
 }
 
-static size_t msg_move_rect_serialize_payload(DP_Message *msg,
-                                              unsigned char *data)
+static size_t msg_stamp_mask_serialize_payload(DP_Message *msg,
+                                               unsigned char *data)
 {
-    DP_MsgMoveRect *mmr = DP_message_internal(msg);
+    DP_MsgStampMask *msm = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mmr->layer, data + written);
-    written += DP_write_bigendian_uint16(mmr->source, data + written);
-    written += DP_write_bigendian_int32(mmr->sx, data + written);
-    written += DP_write_bigendian_int32(mmr->sy, data + written);
-    written += DP_write_bigendian_int32(mmr->tx, data + written);
-    written += DP_write_bigendian_int32(mmr->ty, data + written);
-    written += DP_write_bigendian_int32(mmr->w, data + written);
-    written += DP_write_bigendian_int32(mmr->h, data + written);
-    written += write_bytes(mmr->mask, mmr->mask_size, data + written);
-    DP_ASSERT(written == msg_move_rect_payload_length(msg));
+    written += DP_write_bigendian_uint32(msm->mask, data + written);
+    written += DP_write_bigendian_uint16(msm->size, data + written);
+    written += write_bytes(msm->image, msm->image_size, data + written);
+    DP_ASSERT(written == msg_stamp_mask_payload_length(msg));
     return written;
 }
 
-static bool msg_move_rect_write_payload_text(DP_Message *msg,
-                                             DP_TextWriter *writer)
+static bool msg_stamp_mask_write_payload_text(DP_Message *msg,
+                                              DP_TextWriter *writer)
 {
-    DP_MsgMoveRect *mmr = DP_message_internal(msg);
-    return DP_text_writer_write_int(writer, "h", mmr->h)
-        && DP_text_writer_write_uint(writer, "layer", mmr->layer, true)
-        && DP_text_writer_write_base64(writer, "mask", mmr->mask,
-                                       mmr->mask_size)
-        && DP_text_writer_write_uint(writer, "source", mmr->source, true)
-        && DP_text_writer_write_int(writer, "sx", mmr->sx)
-        && DP_text_writer_write_int(writer, "sy", mmr->sy)
-        && DP_text_writer_write_int(writer, "tx", mmr->tx)
-        && DP_text_writer_write_int(writer, "ty", mmr->ty)
-        && DP_text_writer_write_int(writer, "w", mmr->w);
+    DP_MsgStampMask *msm = DP_message_internal(msg);
+    return DP_text_writer_write_base64(writer, "image", msm->image,
+                                       msm->image_size)
+        && DP_text_writer_write_uint(writer, "mask", msm->mask, true)
+        && DP_text_writer_write_uint(writer, "size", msm->size, false);
 }
 
-static bool msg_move_rect_equals(DP_Message *DP_RESTRICT msg,
-                                 DP_Message *DP_RESTRICT other)
+static bool msg_stamp_mask_equals(DP_Message *DP_RESTRICT msg,
+                                  DP_Message *DP_RESTRICT other)
 {
-    DP_MsgMoveRect *a = DP_message_internal(msg);
-    DP_MsgMoveRect *b = DP_message_internal(other);
-    return a->layer == b->layer && a->source == b->source && a->sx == b->sx
-        && a->sy == b->sy && a->tx == b->tx && a->ty == b->ty && a->w == b->w
-        && a->h == b->h && a->mask_size == b->mask_size
-        && memcmp(a->mask, b->mask, DP_uint16_to_size(a->mask_size)) == 0;
+    DP_MsgStampMask *a = DP_message_internal(msg);
+    DP_MsgStampMask *b = DP_message_internal(other);
+    return a->mask == b->mask && a->size == b->size
+        && a->image_size == b->image_size
+        && memcmp(a->image, b->image, DP_uint16_to_size(a->image_size)) == 0;
 }
 
-static const DP_MessageMethods msg_move_rect_methods = {
-    msg_move_rect_payload_length,
-    msg_move_rect_serialize_payload,
-    msg_move_rect_write_payload_text,
-    msg_move_rect_equals,
+static const DP_MessageMethods msg_stamp_mask_methods = {
+    msg_stamp_mask_payload_length,
+    msg_stamp_mask_serialize_payload,
+    msg_stamp_mask_write_payload_text,
+    msg_stamp_mask_equals,
 };
 
-DP_Message *DP_msg_move_rect_new(unsigned int context_id, uint16_t layer,
-                                 uint16_t source, int32_t sx, int32_t sy,
-                                 int32_t tx, int32_t ty, int32_t w, int32_t h,
-                                 void (*set_mask)(size_t, unsigned char *,
-                                                  void *),
-                                 size_t mask_size, void *mask_user)
+DP_Message *DP_msg_stamp_mask_new(unsigned int context_id, uint32_t mask,
+                                  uint16_t size,
+                                  void (*set_image)(size_t, unsigned char *,
+                                                    void *),
+                                  size_t image_size, void *image_user)
 {
     DP_Message *msg =
-        DP_message_new(DP_MSG_MOVE_RECT, context_id, &msg_move_rect_methods,
-                       DP_FLEX_SIZEOF(DP_MsgMoveRect, mask, mask_size));
-    DP_MsgMoveRect *mmr = DP_message_internal(msg);
-    mmr->layer = layer;
-    mmr->source = source;
-    mmr->sx = sx;
-    mmr->sy = sy;
-    mmr->tx = tx;
-    mmr->ty = ty;
-    mmr->w = w;
-    mmr->h = h;
-    mmr->mask_size = DP_size_to_uint16(mask_size);
-    if (set_mask) {
-        set_mask(mmr->mask_size, mmr->mask, mask_user);
+        DP_message_new(DP_MSG_STAMP_MASK, context_id, &msg_stamp_mask_methods,
+                       DP_FLEX_SIZEOF(DP_MsgStampMask, image, image_size));
+    DP_MsgStampMask *msm = DP_message_internal(msg);
+    msm->mask = mask;
+    msm->size = size;
+    msm->image_size = DP_size_to_uint16(image_size);
+    if (set_image) {
+        set_image(msm->image_size, msm->image, image_user);
     }
     return msg;
 }
 
-DP_Message *DP_msg_move_rect_deserialize(unsigned int context_id,
-                                         const unsigned char *buffer,
-                                         size_t length)
+DP_Message *DP_msg_stamp_mask_deserialize(unsigned int context_id,
+                                          const unsigned char *buffer,
+                                          size_t length)
 {
-    if (length < 28 || length > 65535) {
-        DP_error_set("Wrong length for moverect message; "
-                     "expected between 28 and 65535, got %zu",
+    if (length < 6 || length > 65535) {
+        DP_error_set("Wrong length for stampmask message; "
+                     "expected between 6 and 65535, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t layer = read_uint16(buffer + read, &read);
-    uint16_t source = read_uint16(buffer + read, &read);
-    int32_t sx = read_int32(buffer + read, &read);
-    int32_t sy = read_int32(buffer + read, &read);
-    int32_t tx = read_int32(buffer + read, &read);
-    int32_t ty = read_int32(buffer + read, &read);
-    int32_t w = read_int32(buffer + read, &read);
-    int32_t h = read_int32(buffer + read, &read);
-    size_t mask_bytes = length - read;
-    uint16_t mask_size = DP_size_to_uint16(mask_bytes);
-    void *mask_user = (void *)(buffer + read);
-    return DP_msg_move_rect_new(context_id, layer, source, sx, sy, tx, ty, w, h,
-                                read_bytes, mask_size, mask_user);
+    uint32_t mask = read_uint32(buffer + read, &read);
+    uint16_t size = read_uint16(buffer + read, &read);
+    size_t image_bytes = length - read;
+    uint16_t image_size = DP_size_to_uint16(image_bytes);
+    void *image_user = (void *)(buffer + read);
+    return DP_msg_stamp_mask_new(context_id, mask, size, read_bytes, image_size,
+                                 image_user);
 }
 
-DP_Message *DP_msg_move_rect_parse(unsigned int context_id,
-                                   DP_TextReader *reader)
+DP_Message *DP_msg_stamp_mask_parse(unsigned int context_id,
+                                    DP_TextReader *reader)
 {
-    uint16_t layer =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
-    uint16_t source =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source", UINT16_MAX);
-    int32_t sx =
-        (int32_t)DP_text_reader_get_long(reader, "sx", INT32_MIN, INT32_MAX);
-    int32_t sy =
-        (int32_t)DP_text_reader_get_long(reader, "sy", INT32_MIN, INT32_MAX);
-    int32_t tx =
-        (int32_t)DP_text_reader_get_long(reader, "tx", INT32_MIN, INT32_MAX);
-    int32_t ty =
-        (int32_t)DP_text_reader_get_long(reader, "ty", INT32_MIN, INT32_MAX);
-    int32_t w =
-        (int32_t)DP_text_reader_get_long(reader, "w", INT32_MIN, INT32_MAX);
-    int32_t h =
-        (int32_t)DP_text_reader_get_long(reader, "h", INT32_MIN, INT32_MAX);
-    size_t mask_size;
-    DP_TextReaderParseParams mask_params =
-        DP_text_reader_get_base64_string(reader, "mask", &mask_size);
-    return DP_msg_move_rect_new(context_id, layer, source, sx, sy, tx, ty, w, h,
-                                DP_text_reader_parse_base64, mask_size,
-                                &mask_params);
+    uint32_t mask =
+        (uint32_t)DP_text_reader_get_ulong_hex(reader, "mask", UINT32_MAX);
+    uint16_t size =
+        (uint16_t)DP_text_reader_get_ulong(reader, "size", UINT16_MAX);
+    size_t image_size;
+    DP_TextReaderParseParams image_params =
+        DP_text_reader_get_base64_string(reader, "image", &image_size);
+    return DP_msg_stamp_mask_new(context_id, mask, size,
+                                 DP_text_reader_parse_base64, image_size,
+                                 &image_params);
 }
 
-DP_MsgMoveRect *DP_msg_move_rect_cast(DP_Message *msg)
+DP_MsgStampMask *DP_msg_stamp_mask_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_MOVE_RECT);
+    return DP_message_cast(msg, DP_MSG_STAMP_MASK);
 }
 
-uint16_t DP_msg_move_rect_layer(const DP_MsgMoveRect *mmr)
+uint32_t DP_msg_stamp_mask_mask(const DP_MsgStampMask *msm)
 {
-    DP_ASSERT(mmr);
-    return mmr->layer;
+    DP_ASSERT(msm);
+    return msm->mask;
 }
 
-uint16_t DP_msg_move_rect_source(const DP_MsgMoveRect *mmr)
+uint16_t DP_msg_stamp_mask_size(const DP_MsgStampMask *msm)
 {
-    DP_ASSERT(mmr);
-    return mmr->source;
+    DP_ASSERT(msm);
+    return msm->size;
 }
 
-int32_t DP_msg_move_rect_sx(const DP_MsgMoveRect *mmr)
+const unsigned char *DP_msg_stamp_mask_image(const DP_MsgStampMask *msm,
+                                             size_t *out_size)
 {
-    DP_ASSERT(mmr);
-    return mmr->sx;
+    DP_ASSERT(msm);
+    if (out_size) {
+        *out_size = msm->image_size;
+    }
+    return msm->image;
 }
 
-int32_t DP_msg_move_rect_sy(const DP_MsgMoveRect *mmr)
+size_t DP_msg_stamp_mask_image_size(const DP_MsgStampMask *msm)
 {
-    DP_ASSERT(mmr);
-    return mmr->sy;
+    return msm->image_size;
 }
 
-int32_t DP_msg_move_rect_tx(const DP_MsgMoveRect *mmr)
-{
-    DP_ASSERT(mmr);
-    return mmr->tx;
-}
 
-int32_t DP_msg_move_rect_ty(const DP_MsgMoveRect *mmr)
+/* DP_MSG_DRAW_DABS_STAMP */
+
+struct DP_StampDab {
+    int8_t x;
+    int8_t y;
+    uint16_t size;
+    uint8_t angle;
+    uint8_t opacity;
+};
+
+static size_t stamp_dab_serialize_payload(DP_StampDab *sd, unsigned char *data)
 {
-    DP_ASSERT(mmr);
-    return mmr->ty;
+    size_t written = 0;
+    written += DP_write_bigendian_int8(sd->x, data + written);
+    written += DP_write_bigendian_int8(sd->y, data + written);
+    written += DP_write_bigendian_uint16(sd->size, data + written);
+    written += DP_write_bigendian_uint8(sd->angle, data + written);
+    written += DP_write_bigendian_uint8(sd->opacity, data + written);
+    return written;
 }
 
-int32_t DP_msg_move_rect_w(const DP_MsgMoveRect *mmr)
+static size_t stamp_dab_serialize_payloads(DP_StampDab *sd, int count,
+                                           unsigned char *data)
 {
-    DP_ASSERT(mmr);
-    return mmr->w;
+    size_t written = 0;
+    for (int i = 0; i < count; ++i) {
+        written += stamp_dab_serialize_payload(&sd[i], data + written);
+    }
+    return written;
 }
 
-int32_t DP_msg_move_rect_h(const DP_MsgMoveRect *mmr)
+static bool stamp_dab_write_payload_text(DP_StampDab *sd, DP_TextWriter *writer)
 {
-    DP_ASSERT(mmr);
-    return mmr->h;
+    return DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "   ")
+        && DP_text_writer_write_subfield_decimal(writer, (double)sd->x / 4.0)
+        && DP_text_writer_write_subfield_decimal(writer, (double)sd->y / 4.0)
+        && DP_text_writer_write_subfield_decimal(writer,
+                                                 (double)sd->size / 256.0)
+        && DP_text_writer_write_subfield_uint(writer, sd->angle)
+        && DP_text_writer_write_subfield_uint(writer, sd->opacity)
+        && DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "\n");
 }
 
-const unsigned char *DP_msg_move_rect_mask(const DP_MsgMoveRect *mmr,
-                                           size_t *out_size)
+static size_t stamp_dab_write_payload_texts(DP_StampDab *sd, int count,
+                                            DP_TextWriter *writer)
 {
-    DP_ASSERT(mmr);
-    if (out_size) {
-        *out_size = mmr->mask_size;
+    if (!DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, " {\n")) {
+        return false;
     }
-    return mmr->mask;
+    for (int i = 0; i < count; ++i) {
+        if (!stamp_dab_write_payload_text(&sd[i], writer)) {
+            return false;
+        }
+    }
+    return DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "}");
 }
 
-size_t DP_msg_move_rect_mask_size(const DP_MsgMoveRect *mmr)
+static bool stamp_dab_equals(DP_StampDab *DP_RESTRICT a,
+                             DP_StampDab *DP_RESTRICT b)
 {
-    return mmr->mask_size;
+    return a->x == b->x && a->y == b->y && a->size == b->size
+        && a->angle == b->angle && a->opacity == b->opacity;
 }
 
-
-/* DP_MSG_SET_METADATA_INT */
-
-const char *DP_msg_set_metadata_int_field_variant_name(unsigned int value)
+static bool stamp_dabs_equal(DP_StampDab *DP_RESTRICT a,
+                             DP_StampDab *DP_RESTRICT b, int count)
 {
-    switch (value) {
-    case DP_MSG_SET_METADATA_INT_FIELD_DPIX:
-        return "Dpix";
-    case DP_MSG_SET_METADATA_INT_FIELD_DPIY:
-        return "Dpiy";
-    case DP_MSG_SET_METADATA_INT_FIELD_FRAMERATE:
-        return "Framerate";
-    case DP_MSG_SET_METADATA_INT_FIELD_FRAME_COUNT:
-        return "FrameCount";
-    default:
-        return NULL;
+    for (int i = 0; i < count; ++i) {
+        if (!stamp_dab_equals(&a[i], &b[i])) {
+            return false;
+        }
     }
+    return true;
 }
 
-struct DP_MsgSetMetadataInt {
-    uint8_t field;
-    int32_t value;
-};
-
-static size_t msg_set_metadata_int_payload_length(DP_UNUSED DP_Message *msg)
+void DP_stamp_dab_init(DP_StampDab *sds, int i, int8_t x, int8_t y,
+                       uint16_t size, uint8_t angle, uint8_t opacity)
 {
-    return ((size_t)5);
+    DP_ASSERT(sds);
+    DP_StampDab *sd = &sds[i];
+    sd->x = x;
+    sd->y = y;
+    sd->size = size;
+    sd->angle = angle;
+    sd->opacity = opacity;
 }
 
-static size_t msg_set_metadata_int_serialize_payload(DP_Message *msg,
-                                                     unsigned char *data)
+static void stamp_dab_deserialize(int count, DP_StampDab *sds, void *user)
 {
-    DP_MsgSetMetadataInt *msmi = DP_message_internal(msg);
-    size_t written = 0;
-    written += DP_write_bigendian_uint8(msmi->field, data + written);
-    written += DP_write_bigendian_int32(msmi->value, data + written);
-    DP_ASSERT(written == msg_set_metadata_int_payload_length(msg));
-    return written;
+    const unsigned char *buffer = user;
+    size_t read = 0;
+    for (int i = 0; i < count; ++i) {
+        int8_t x = read_int8(buffer + read, &read);
+        int8_t y = read_int8(buffer + read, &read);
+        uint16_t size = read_uint16(buffer + read, &read);
+        uint8_t angle = read_uint8(buffer + read, &read);
+        uint8_t opacity = read_uint8(buffer + read, &read);
+        DP_stamp_dab_init(sds, i, x, y, size, angle, opacity);
+    }
 }
 
-static bool msg_set_metadata_int_write_payload_text(DP_Message *msg,
-                                                    DP_TextWriter *writer)
+static void stamp_dab_parse(int count, DP_StampDab *sds, void *user)
 {
-    DP_MsgSetMetadataInt *msmi = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "field", msmi->field, false)
-        && DP_text_writer_write_int(writer, "value", msmi->value);
-}
-
-static bool msg_set_metadata_int_equals(DP_Message *DP_RESTRICT msg,
-                                        DP_Message *DP_RESTRICT other)
-{
-    DP_MsgSetMetadataInt *a = DP_message_internal(msg);
-    DP_MsgSetMetadataInt *b = DP_message_internal(other);
-    return a->field == b->field && a->value == b->value;
-}
-
-static const DP_MessageMethods msg_set_metadata_int_methods = {
-    msg_set_metadata_int_payload_length,
-    msg_set_metadata_int_serialize_payload,
-    msg_set_metadata_int_write_payload_text,
-    msg_set_metadata_int_equals,
-};
-
-DP_Message *DP_msg_set_metadata_int_new(unsigned int context_id, uint8_t field,
-                                        int32_t value)
-{
-    DP_Message *msg = DP_message_new(DP_MSG_SET_METADATA_INT, context_id,
-                                     &msg_set_metadata_int_methods,
-                                     sizeof(DP_MsgSetMetadataInt));
-    DP_MsgSetMetadataInt *msmi = DP_message_internal(msg);
-    msmi->field = field;
-    msmi->value = value;
-    return msg;
+    DP_TextReader *reader = user;
+    for (int i = 0; i < count; ++i) {
+        int8_t x = (int8_t)DP_text_reader_get_subfield_decimal(
+            reader, i, 0, 4.0, INT8_MIN, INT8_MAX);
+        int8_t y = (int8_t)DP_text_reader_get_subfield_decimal(
+            reader, i, 1, 4.0, INT8_MIN, INT8_MAX);
+        uint16_t size = (uint16_t)DP_text_reader_get_subfield_decimal(
+            reader, i, 2, 256.0, 0, UINT16_MAX);
+        uint8_t angle =
+            (uint8_t)DP_text_reader_get_subfield_ulong(reader, i, 3, UINT8_MAX);
+        uint8_t opacity =
+            (uint8_t)DP_text_reader_get_subfield_ulong(reader, i, 4, UINT8_MAX);
+        DP_stamp_dab_init(sds, i, x, y, size, angle, opacity);
+    }
 }
 
-DP_Message *DP_msg_set_metadata_int_deserialize(unsigned int context_id,
-                                                const unsigned char *buffer,
-                                                size_t length)
+int8_t DP_stamp_dab_x(const DP_StampDab *sd)
 {
-    if (length != 5) {
-        DP_error_set("Wrong length for setmetadataint message; "
-                     "expected 5, got %zu",
-                     length);
-        return NULL;
-    }
-    size_t read = 0;
-    uint8_t field = read_uint8(buffer + read, &read);
-    int32_t value = read_int32(buffer + read, &read);
-    return DP_msg_set_metadata_int_new(context_id, field, value);
+    DP_ASSERT(sd);
+    return sd->x;
 }
 
-DP_Message *DP_msg_set_metadata_int_parse(unsigned int context_id,
-                                          DP_TextReader *reader)
+int8_t DP_stamp_dab_y(const DP_StampDab *sd)
 {
-    uint8_t field =
-        (uint8_t)DP_text_reader_get_ulong(reader, "field", UINT8_MAX);
-    int32_t value =
-        (int32_t)DP_text_reader_get_long(reader, "value", INT32_MIN, INT32_MAX);
-    return DP_msg_set_metadata_int_new(context_id, field, value);
+    DP_ASSERT(sd);
+    return sd->y;
 }
 
-DP_MsgSetMetadataInt *DP_msg_set_metadata_int_cast(DP_Message *msg)
+uint16_t DP_stamp_dab_size(const DP_StampDab *sd)
 {
-    return DP_message_cast(msg, DP_MSG_SET_METADATA_INT);
+    DP_ASSERT(sd);
+    return sd->size;
 }
 
-uint8_t DP_msg_set_metadata_int_field(const DP_MsgSetMetadataInt *msmi)
+uint8_t DP_stamp_dab_angle(const DP_StampDab *sd)
 {
-    DP_ASSERT(msmi);
-    return msmi->field;
+    DP_ASSERT(sd);
+    return sd->angle;
 }
 
-int32_t DP_msg_set_metadata_int_value(const DP_MsgSetMetadataInt *msmi)
+uint8_t DP_stamp_dab_opacity(const DP_StampDab *sd)
 {
-    DP_ASSERT(msmi);
-    return msmi->value;
+    DP_ASSERT(sd);
+    return sd->opacity;
 }
 
-
-/* DP_MSG_LAYER_TREE_CREATE */
-
-const char *DP_msg_layer_tree_create_flags_flag_name(unsigned int value)
+const DP_StampDab *DP_stamp_dab_at(const DP_StampDab *sd, int i)
 {
-    switch (value) {
-    case DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP:
-        return "group";
-    case DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO:
-        return "into";
-    default:
-        return NULL;
-    }
+    DP_ASSERT(sd);
+    return &sd[i];
 }
 
-struct DP_MsgLayerTreeCreate {
-    uint16_t id;
-    uint16_t source;
-    uint16_t target;
-    uint32_t fill;
-    uint8_t flags;
-    uint16_t title_len;
-    char title[];
+struct DP_MsgDrawDabsStamp {
+    uint16_t layer;
+    int32_t x;
+    int32_t y;
+    uint32_t color;
+    uint8_t mode;
+    uint32_t mask;
+    uint32_t grain;
+    uint16_t grainscale;
+    uint8_t grainstrength;
+    uint16_t dabs_count;
+    DP_StampDab dabs[];
 };
 
-static size_t msg_layer_tree_create_payload_length(DP_Message *msg)
+static size_t msg_draw_dabs_stamp_payload_length(DP_Message *msg)
 {
-    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
-    return ((size_t)11) + DP_uint16_to_size(mltc->title_len);
+    DP_MsgDrawDabsStamp *mdds = DP_message_internal(msg);
+    return ((size_t)26) + DP_int_to_size(mdds->dabs_count) * 6;
 }
 
-static size_t msg_layer_tree_create_serialize_payload(DP_Message *msg,
-                                                      unsigned char *data)
+static size_t msg_draw_dabs_stamp_serialize_payload(DP_Message *msg,
+                                                    unsigned char *data)
 {
-    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
+    DP_MsgDrawDabsStamp *mdds = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mltc->id, data + written);
-    written += DP_write_bigendian_uint16(mltc->source, data + written);
-    written += DP_write_bigendian_uint16(mltc->target, data + written);
-    written += DP_write_bigendian_uint32(mltc->fill, data + written);
-    written += DP_write_bigendian_uint8(mltc->flags, data + written);
-    written += DP_write_bytes(mltc->title, 1, mltc->title_len, data + written);
-    DP_ASSERT(written == msg_layer_tree_create_payload_length(msg));
+    written += DP_write_bigendian_uint16(mdds->layer, data + written);
+    written += DP_write_bigendian_int32(mdds->x, data + written);
+    written += DP_write_bigendian_int32(mdds->y, data + written);
+    written += DP_write_bigendian_uint32(mdds->color, data + written);
+    written += DP_write_bigendian_uint8(mdds->mode, data + written);
+    written += DP_write_bigendian_uint32(mdds->mask, data + written);
+    written += DP_write_bigendian_uint32(mdds->grain, data + written);
+    written += DP_write_bigendian_uint16(mdds->grainscale, data + written);
+    written += DP_write_bigendian_uint8(mdds->grainstrength, data + written);
+    written += stamp_dab_serialize_payloads(mdds->dabs, mdds->dabs_count,
+                                            data + written);
+    DP_ASSERT(written == msg_draw_dabs_stamp_payload_length(msg));
     return written;
 }
 
-static bool msg_layer_tree_create_write_payload_text(DP_Message *msg,
-                                                     DP_TextWriter *writer)
+static bool msg_draw_dabs_stamp_write_payload_text(DP_Message *msg,
+                                                   DP_TextWriter *writer)
 {
-    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
-    return DP_text_writer_write_argb_color(writer, "fill", mltc->fill)
-        && DP_text_writer_write_flags(
-               writer, "flags", mltc->flags, 2,
-               (const char *[]){"group", "into"},
-               (unsigned int[]){DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
-                                DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO})
-        && DP_text_writer_write_uint(writer, "id", mltc->id, true)
-        && DP_text_writer_write_uint(writer, "source", mltc->source, true)
-        && DP_text_writer_write_uint(writer, "target", mltc->target, true)
-        && DP_text_writer_write_string(writer, "title", mltc->title);
-}
-
-static bool msg_layer_tree_create_equals(DP_Message *DP_RESTRICT msg,
-                                         DP_Message *DP_RESTRICT other)
+    DP_MsgDrawDabsStamp *mdds = DP_message_internal(msg);
+    return DP_text_writer_write_argb_color(writer, "color", mdds->color)
+        && DP_text_writer_write_uint(writer, "grain", mdds->grain, true)
+        && DP_text_writer_write_decimal(writer, "grainscale",
+                                        (double)mdds->grainscale / 256.0)
+        && DP_text_writer_write_uint(writer, "grainstrength",
+                                     mdds->grainstrength, false)
+        && DP_text_writer_write_uint(writer, "layer", mdds->layer, true)
+        && DP_text_writer_write_uint(writer, "mask", mdds->mask, true)
+        && DP_text_writer_write_blend_mode(writer, "mode", mdds->mode)
+        && DP_text_writer_write_decimal(writer, "x", (double)mdds->x / 4.0)
+        && DP_text_writer_write_decimal(writer, "y", (double)mdds->y / 4.0)
+        && stamp_dab_write_payload_texts(mdds->dabs, mdds->dabs_count, writer);
+}
+
+static bool msg_draw_dabs_stamp_equals(DP_Message *DP_RESTRICT msg,
+                                       DP_Message *DP_RESTRICT other)
 {
-    DP_MsgLayerTreeCreate *a = DP_message_internal(msg);
-    DP_MsgLayerTreeCreate *b = DP_message_internal(other);
-    return a->id == b->id && a->source == b->source && a->target == b->target
-        && a->fill == b->fill && a->flags == b->flags
-        && a->title_len == b->title_len
-        && memcmp(a->title, b->title, a->title_len) == 0;
+    DP_MsgDrawDabsStamp *a = DP_message_internal(msg);
+    DP_MsgDrawDabsStamp *b = DP_message_internal(other);
+    return a->layer == b->layer && a->x == b->x && a->y == b->y
+        && a->color == b->color && a->mode == b->mode
+        && a->mask == b->mask && a->grain == b->grain
+        && a->grainscale == b->grainscale
+        && a->grainstrength == b->grainstrength
+        && a->dabs_count == b->dabs_count
+        && stamp_dabs_equal(a->dabs, b->dabs, a->dabs_count);
 }
 
-static const DP_MessageMethods msg_layer_tree_create_methods = {
-    msg_layer_tree_create_payload_length,
-    msg_layer_tree_create_serialize_payload,
-    msg_layer_tree_create_write_payload_text,
-    msg_layer_tree_create_equals,
+static const DP_MessageMethods msg_draw_dabs_stamp_methods = {
+    msg_draw_dabs_stamp_payload_length,
+    msg_draw_dabs_stamp_serialize_payload,
+    msg_draw_dabs_stamp_write_payload_text,
+    msg_draw_dabs_stamp_equals,
 };
 
-DP_Message *DP_msg_layer_tree_create_new(unsigned int context_id, uint16_t id,
-                                         uint16_t source, uint16_t target,
-                                         uint32_t fill, uint8_t flags,
-                                         const char *title_value,
-                                         size_t title_len)
+DP_Message *
+DP_msg_draw_dabs_stamp_new(unsigned int context_id, uint16_t layer, int32_t x,
+                           int32_t y, uint32_t color, uint8_t mode,
+                           uint32_t mask, uint32_t grain, uint16_t grainscale,
+                           uint8_t grainstrength,
+                           void (*set_dabs)(int, DP_StampDab *, void *),
+                           int dabs_count, void *dabs_user)
 {
     DP_Message *msg = DP_message_new(
-        DP_MSG_LAYER_TREE_CREATE, context_id, &msg_layer_tree_create_methods,
-        DP_FLEX_SIZEOF(DP_MsgLayerTreeCreate, title, title_len + 1));
-    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
-    mltc->id = id;
-    mltc->source = source;
-    mltc->target = target;
-    mltc->fill = fill;
-    mltc->flags = flags;
-    mltc->title_len = DP_size_to_uint16(title_len);
-    assign_string(mltc->title, title_value, mltc->title_len);
+        DP_MSG_DRAW_DABS_STAMP, context_id, &msg_draw_dabs_stamp_methods,
+        DP_FLEX_SIZEOF(DP_MsgDrawDabsStamp, dabs,
+                       DP_int_to_size(dabs_count) * sizeof(DP_StampDab)));
+    DP_MsgDrawDabsStamp *mdds = DP_message_internal(msg);
+    mdds->layer = layer;
+    mdds->x = x;
+    mdds->y = y;
+    mdds->color = color;
+    mdds->mode = mode;
+    mdds->mask = mask;
+    mdds->grain = grain;
+    mdds->grainscale = grainscale;
+    mdds->grainstrength = grainstrength;
+    mdds->dabs_count = DP_int_to_uint16(dabs_count);
+    set_dabs(mdds->dabs_count, mdds->dabs, dabs_user);
     return msg;
 }
 
-DP_Message *DP_msg_layer_tree_create_deserialize(unsigned int context_id,
-                                                 const unsigned char *buffer,
-                                                 size_t length)
+DP_Message *DP_msg_draw_dabs_stamp_deserialize(unsigned int context_id,
+                                               const unsigned char *buffer,
+                                               size_t length)
 {
-    if (length < 11 || length > 65535) {
-        DP_error_set("Wrong length for layertreecreate message; "
-                     "expected between 11 and 65535, got %zu",
+    if (length < 32 || length > 65534) {
+        DP_error_set("Wrong length for stampdabs message; "
+                     "expected between 32 and 65534, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t id = read_uint16(buffer + read, &read);
-    uint16_t source = read_uint16(buffer + read, &read);
-    uint16_t target = read_uint16(buffer + read, &read);
-    uint32_t fill = read_uint32(buffer + read, &read);
-    uint8_t flags = read_uint8(buffer + read, &read);
-    size_t title_bytes = length - read;
-    uint16_t title_len = DP_size_to_uint16(title_bytes);
-    const char *title = (const char *)buffer + read;
-    return DP_msg_layer_tree_create_new(context_id, id, source, target, fill,
-                                        flags, title, title_len);
+    uint16_t layer = read_uint16(buffer + read, &read);
+    int32_t x = read_int32(buffer + read, &read);
+    int32_t y = read_int32(buffer + read, &read);
+    uint32_t color = read_uint32(buffer + read, &read);
+    uint8_t mode = read_uint8(buffer + read, &read);
+    uint32_t mask = read_uint32(buffer + read, &read);
+    uint32_t grain = read_uint32(buffer + read, &read);
+    uint16_t grainscale = read_uint16(buffer + read, &read);
+    uint8_t grainstrength = read_uint8(buffer + read, &read);
+    size_t dabs_bytes = length - read;
+    if ((dabs_bytes % 6) != 0) {
+        DP_error_set("Wrong length for dabs field in stampdabs message; "
+                     "%zu not divisible by 6",
+                     dabs_bytes);
+        return NULL;
+    }
+    int dabs_count = DP_size_to_int(dabs_bytes) / 6;
+    void *dabs_user = (void *)(buffer + read);
+    return DP_msg_draw_dabs_stamp_new(
+        context_id, layer, x, y, color, mode, mask, grain, grainscale,
+        grainstrength, stamp_dab_deserialize, dabs_count, dabs_user);
 }
 
-DP_Message *DP_msg_layer_tree_create_parse(unsigned int context_id,
-                                           DP_TextReader *reader)
+DP_Message *DP_msg_draw_dabs_stamp_parse(unsigned int context_id,
+                                         DP_TextReader *reader)
 {
-    uint16_t id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
-    uint16_t source =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source", UINT16_MAX);
-    uint16_t target =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "target", UINT16_MAX);
-    uint32_t fill = DP_text_reader_get_argb_color(reader, "fill");
-    uint8_t flags = (uint8_t)DP_text_reader_get_flags(
-        reader, "flags", 2, (const char *[]){"group", "into"},
-        (unsigned int[]){DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
-                         DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO});
-    uint16_t title_len;
-    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
-    return DP_msg_layer_tree_create_new(context_id, id, source, target, fill,
-                                        flags, title, title_len);
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    int32_t x = (int32_t)DP_text_reader_get_decimal(reader, "x", 4.0, INT32_MIN,
+                                                    INT32_MAX);
+    int32_t y = (int32_t)DP_text_reader_get_decimal(reader, "y", 4.0, INT32_MIN,
+                                                    INT32_MAX);
+    uint32_t color = DP_text_reader_get_argb_color(reader, "color");
+    uint8_t mode = DP_text_reader_get_blend_mode(reader, "mode");
+    uint32_t mask =
+        (uint32_t)DP_text_reader_get_ulong_hex(reader, "mask", UINT32_MAX);
+    uint32_t grain =
+        (uint32_t)DP_text_reader_get_ulong_hex(reader, "grain", UINT32_MAX);
+    uint16_t grainscale = (uint16_t)DP_text_reader_get_decimal(
+        reader, "grainscale", 256.0, 0, UINT16_MAX);
+    uint8_t grainstrength =
+        (uint8_t)DP_text_reader_get_ulong(reader, "grainstrength", UINT8_MAX);
+    int dabs_count = DP_text_reader_get_tuple_count(reader);
+    void *dabs_user = reader;
+    return DP_msg_draw_dabs_stamp_new(
+        context_id, layer, x, y, color, mode, mask, grain, grainscale,
+        grainstrength, stamp_dab_parse, dabs_count, dabs_user);
 }
 
-DP_MsgLayerTreeCreate *DP_msg_layer_tree_create_cast(DP_Message *msg)
+DP_MsgDrawDabsStamp *DP_msg_draw_dabs_stamp_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_LAYER_TREE_CREATE);
+    return DP_message_cast(msg, DP_MSG_DRAW_DABS_STAMP);
 }
 
-uint16_t DP_msg_layer_tree_create_id(const DP_MsgLayerTreeCreate *mltc)
+uint16_t DP_msg_draw_dabs_stamp_layer(const DP_MsgDrawDabsStamp *mdds)
 {
-    DP_ASSERT(mltc);
-    return mltc->id;
+    DP_ASSERT(mdds);
+    return mdds->layer;
 }
 
-uint16_t DP_msg_layer_tree_create_source(const DP_MsgLayerTreeCreate *mltc)
+int32_t DP_msg_draw_dabs_stamp_x(const DP_MsgDrawDabsStamp *mdds)
 {
-    DP_ASSERT(mltc);
-    return mltc->source;
+    DP_ASSERT(mdds);
+    return mdds->x;
 }
 
-uint16_t DP_msg_layer_tree_create_target(const DP_MsgLayerTreeCreate *mltc)
+int32_t DP_msg_draw_dabs_stamp_y(const DP_MsgDrawDabsStamp *mdds)
 {
-    DP_ASSERT(mltc);
-    return mltc->target;
+    DP_ASSERT(mdds);
+    return mdds->y;
 }
 
-uint32_t DP_msg_layer_tree_create_fill(const DP_MsgLayerTreeCreate *mltc)
+uint32_t DP_msg_draw_dabs_stamp_color(const DP_MsgDrawDabsStamp *mdds)
 {
-    DP_ASSERT(mltc);
-    return mltc->fill;
+    DP_ASSERT(mdds);
+    return mdds->color;
 }
 
-uint8_t DP_msg_layer_tree_create_flags(const DP_MsgLayerTreeCreate *mltc)
+uint8_t DP_msg_draw_dabs_stamp_mode(const DP_MsgDrawDabsStamp *mdds)
 {
-    DP_ASSERT(mltc);
-    return mltc->flags;
+    DP_ASSERT(mdds);
+    return mdds->mode;
 }
 
-const char *DP_msg_layer_tree_create_title(const DP_MsgLayerTreeCreate *mltc,
-                                           size_t *out_len)
+uint32_t DP_msg_draw_dabs_stamp_mask(const DP_MsgDrawDabsStamp *mdds)
 {
-    DP_ASSERT(mltc);
-    if (out_len) {
-        *out_len = mltc->title_len;
-    }
-    return mltc->title;
+    DP_ASSERT(mdds);
+    return mdds->mask;
 }
 
-size_t DP_msg_layer_tree_create_title_len(const DP_MsgLayerTreeCreate *mltc)
+uint32_t DP_msg_draw_dabs_stamp_grain(const DP_MsgDrawDabsStamp *mdds)
 {
-    return mltc->title_len;
+    DP_ASSERT(mdds);
+    return mdds->grain;
 }
 
+uint16_t DP_msg_draw_dabs_stamp_grainscale(const DP_MsgDrawDabsStamp *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->grainscale;
+}
 
-/* DP_MSG_LAYER_TREE_MOVE */
+uint8_t DP_msg_draw_dabs_stamp_grainstrength(const DP_MsgDrawDabsStamp *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->grainstrength;
+}
 
-struct DP_MsgLayerTreeMove {
-    uint16_t layer;
-    uint16_t parent;
-    uint16_t sibling;
-};
+const DP_StampDab *DP_msg_draw_dabs_stamp_dabs(const DP_MsgDrawDabsStamp *mdds,
+                                               int *out_count)
+{
+    DP_ASSERT(mdds);
+    if (out_count) {
+        *out_count = mdds->dabs_count;
+    }
+    return mdds->dabs;
+}
 
-static size_t msg_layer_tree_move_payload_length(DP_UNUSED DP_Message *msg)
+int DP_msg_draw_dabs_stamp_dabs_count(const DP_MsgDrawDabsStamp *mdds)
 {
-    return ((size_t)6);
+    return mdds->dabs_count;
 }
 
-static size_t msg_layer_tree_move_serialize_payload(DP_Message *msg,
-                                                    unsigned char *data)
+
+/* DP_MSG_DRAW_DABS_SOFT */
+
+struct DP_SoftDab {
+    int8_t x;
+    int8_t y;
+    uint16_t size;
+    uint8_t hardness;
+    uint8_t opacity;
+};
+
+static size_t soft_dab_serialize_payload(DP_SoftDab *sd, unsigned char *data)
 {
-    DP_MsgLayerTreeMove *mltm = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mltm->layer, data + written);
-    written += DP_write_bigendian_uint16(mltm->parent, data + written);
-    written += DP_write_bigendian_uint16(mltm->sibling, data + written);
-    DP_ASSERT(written == msg_layer_tree_move_payload_length(msg));
+    written += DP_write_bigendian_int8(sd->x, data + written);
+    written += DP_write_bigendian_int8(sd->y, data + written);
+    written += DP_write_bigendian_uint16(sd->size, data + written);
+    written += DP_write_bigendian_uint8(sd->hardness, data + written);
+    written += DP_write_bigendian_uint8(sd->opacity, data + written);
     return written;
 }
 
-static bool msg_layer_tree_move_write_payload_text(DP_Message *msg,
-                                                   DP_TextWriter *writer)
+static size_t soft_dab_serialize_payloads(DP_SoftDab *sd, int count,
+                                          unsigned char *data)
 {
-    DP_MsgLayerTreeMove *mltm = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "layer", mltm->layer, true)
-        && DP_text_writer_write_uint(writer, "parent", mltm->parent, true)
-        && DP_text_writer_write_uint(writer, "sibling", mltm->sibling, true);
+    size_t written = 0;
+    for (int i = 0; i < count; ++i) {
+        written += soft_dab_serialize_payload(&sd[i], data + written);
+    }
+    return written;
 }
 
-static bool msg_layer_tree_move_equals(DP_Message *DP_RESTRICT msg,
-                                       DP_Message *DP_RESTRICT other)
+static bool soft_dab_write_payload_text(DP_SoftDab *sd, DP_TextWriter *writer)
 {
-    DP_MsgLayerTreeMove *a = DP_message_internal(msg);
-    DP_MsgLayerTreeMove *b = DP_message_internal(other);
-    return a->layer == b->layer && a->parent == b->parent
-        && a->sibling == b->sibling;
+    return DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "   ")
+        && DP_text_writer_write_subfield_decimal(writer, (double)sd->x / 4.0)
+        && DP_text_writer_write_subfield_decimal(writer, (double)sd->y / 4.0)
+        && DP_text_writer_write_subfield_decimal(writer,
+                                                 (double)sd->size / 256.0)
+        && DP_text_writer_write_subfield_uint(writer, sd->hardness)
+        && DP_text_writer_write_subfield_uint(writer, sd->opacity)
+        && DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "\n");
 }
 
-static const DP_MessageMethods msg_layer_tree_move_methods = {
-    msg_layer_tree_move_payload_length,
-    msg_layer_tree_move_serialize_payload,
-    msg_layer_tree_move_write_payload_text,
-    msg_layer_tree_move_equals,
-};
+static size_t soft_dab_write_payload_texts(DP_SoftDab *sd, int count,
+                                           DP_TextWriter *writer)
+{
+    if (!DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, " {\n")) {
+        return false;
+    }
+    for (int i = 0; i < count; ++i) {
+        if (!soft_dab_write_payload_text(&sd[i], writer)) {
+            return false;
+        }
+    }
+    return DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "}");
+}
 
-DP_Message *DP_msg_layer_tree_move_new(unsigned int context_id, uint16_t layer,
-                                       uint16_t parent, uint16_t sibling)
+static bool soft_dab_equals(DP_SoftDab *DP_RESTRICT a,
+                            DP_SoftDab *DP_RESTRICT b)
 {
-    DP_Message *msg = DP_message_new(DP_MSG_LAYER_TREE_MOVE, context_id,
-                                     &msg_layer_tree_move_methods,
-                                     sizeof(DP_MsgLayerTreeMove));
-    DP_MsgLayerTreeMove *mltm = DP_message_internal(msg);
-    mltm->layer = layer;
-    mltm->parent = parent;
-    mltm->sibling = sibling;
-    return msg;
+    return a->x == b->x && a->y == b->y && a->size == b->size
+        && a->hardness == b->hardness && a->opacity == b->opacity;
 }
 
-DP_Message *DP_msg_layer_tree_move_deserialize(unsigned int context_id,
-                                               const unsigned char *buffer,
-                                               size_t length)
+static bool soft_dabs_equal(DP_SoftDab *DP_RESTRICT a,
+                            DP_SoftDab *DP_RESTRICT b, int count)
 {
-    if (length != 6) {
-        DP_error_set("Wrong length for layertreemove message; "
-                     "expected 6, got %zu",
-                     length);
-        return NULL;
+    for (int i = 0; i < count; ++i) {
+        if (!soft_dab_equals(&a[i], &b[i])) {
+            return false;
+        }
     }
+    return true;
+}
+
+void DP_soft_dab_init(DP_SoftDab *sds, int i, int8_t x, int8_t y, uint16_t size,
+                      uint8_t hardness, uint8_t opacity)
+{
+    DP_ASSERT(sds);
+    DP_SoftDab *sd = &sds[i];
+    sd->x = x;
+    sd->y = y;
+    sd->size = size;
+    sd->hardness = hardness;
+    sd->opacity = opacity;
+}
+
+static void soft_dab_deserialize(int count, DP_SoftDab *sds, void *user)
+{
+    const unsigned char *buffer = user;
     size_t read = 0;
-    uint16_t layer = read_uint16(buffer + read, &read);
-    uint16_t parent = read_uint16(buffer + read, &read);
-    uint16_t sibling = read_uint16(buffer + read, &read);
-    return DP_msg_layer_tree_move_new(context_id, layer, parent, sibling);
+    for (int i = 0; i < count; ++i) {
+        int8_t x = read_int8(buffer + read, &read);
+        int8_t y = read_int8(buffer + read, &read);
+        uint16_t size = read_uint16(buffer + read, &read);
+        uint8_t hardness = read_uint8(buffer + read, &read);
+        uint8_t opacity = read_uint8(buffer + read, &read);
+        DP_soft_dab_init(sds, i, x, y, size, hardness, opacity);
+    }
 }
 
-DP_Message *DP_msg_layer_tree_move_parse(unsigned int context_id,
-                                         DP_TextReader *reader)
+static void soft_dab_parse(int count, DP_SoftDab *sds, void *user)
 {
-    uint16_t layer =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
-    uint16_t parent =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "parent", UINT16_MAX);
-    uint16_t sibling =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "sibling", UINT16_MAX);
-    return DP_msg_layer_tree_move_new(context_id, layer, parent, sibling);
+    DP_TextReader *reader = user;
+    for (int i = 0; i < count; ++i) {
+        int8_t x = (int8_t)DP_text_reader_get_subfield_decimal(
+            reader, i, 0, 4.0, INT8_MIN, INT8_MAX);
+        int8_t y = (int8_t)DP_text_reader_get_subfield_decimal(
+            reader, i, 1, 4.0, INT8_MIN, INT8_MAX);
+        uint16_t size = (uint16_t)DP_text_reader_get_subfield_decimal(
+            reader, i, 2, 256.0, 0, UINT16_MAX);
+        uint8_t hardness =
+            (uint8_t)DP_text_reader_get_subfield_ulong(reader, i, 3, UINT8_MAX);
+        uint8_t opacity =
+            (uint8_t)DP_text_reader_get_subfield_ulong(reader, i, 4, UINT8_MAX);
+        DP_soft_dab_init(sds, i, x, y, size, hardness, opacity);
+    }
 }
 
-DP_MsgLayerTreeMove *DP_msg_layer_tree_move_cast(DP_Message *msg)
+int8_t DP_soft_dab_x(const DP_SoftDab *sd)
 {
-    return DP_message_cast(msg, DP_MSG_LAYER_TREE_MOVE);
+    DP_ASSERT(sd);
+    return sd->x;
 }
 
-uint16_t DP_msg_layer_tree_move_layer(const DP_MsgLayerTreeMove *mltm)
+int8_t DP_soft_dab_y(const DP_SoftDab *sd)
 {
-    DP_ASSERT(mltm);
-    return mltm->layer;
+    DP_ASSERT(sd);
+    return sd->y;
 }
 
-uint16_t DP_msg_layer_tree_move_parent(const DP_MsgLayerTreeMove *mltm)
+uint16_t DP_soft_dab_size(const DP_SoftDab *sd)
 {
-    DP_ASSERT(mltm);
-    return mltm->parent;
+    DP_ASSERT(sd);
+    return sd->size;
 }
 
-uint16_t DP_msg_layer_tree_move_sibling(const DP_MsgLayerTreeMove *mltm)
+uint8_t DP_soft_dab_hardness(const DP_SoftDab *sd)
 {
-    DP_ASSERT(mltm);
-    return mltm->sibling;
+    DP_ASSERT(sd);
+    return sd->hardness;
 }
 
+uint8_t DP_soft_dab_opacity(const DP_SoftDab *sd)
+{
+    DP_ASSERT(sd);
+    return sd->opacity;
+}
 
-/* DP_MSG_LAYER_TREE_DELETE */
+const DP_SoftDab *DP_soft_dab_at(const DP_SoftDab *sd, int i)
+{
+    DP_ASSERT(sd);
+    return &sd[i];
+}
 
-struct DP_MsgLayerTreeDelete {
-    uint16_t id;
-    uint16_t merge_to;
+struct DP_MsgDrawDabsSoft {
+    uint16_t layer;
+    int32_t x;
+    int32_t y;
+    uint32_t color;
+    uint8_t mode;
+    uint8_t falloff;
+    uint32_t grain;
+    uint16_t grainscale;
+    uint8_t grainstrength;
+    uint16_t dabs_count;
+    DP_SoftDab dabs[];
 };
 
-static size_t msg_layer_tree_delete_payload_length(DP_UNUSED DP_Message *msg)
+static size_t msg_draw_dabs_soft_payload_length(DP_Message *msg)
 {
-    return ((size_t)4);
+    DP_MsgDrawDabsSoft *mdds = DP_message_internal(msg);
+    return ((size_t)23) + DP_int_to_size(mdds->dabs_count) * 6;
 }
 
-static size_t msg_layer_tree_delete_serialize_payload(DP_Message *msg,
-                                                      unsigned char *data)
+static size_t msg_draw_dabs_soft_serialize_payload(DP_Message *msg,
+                                                   unsigned char *data)
 {
-    DP_MsgLayerTreeDelete *mltd = DP_message_internal(msg);
+    DP_MsgDrawDabsSoft *mdds = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mltd->id, data + written);
-    written += DP_write_bigendian_uint16(mltd->merge_to, data + written);
-    DP_ASSERT(written == msg_layer_tree_delete_payload_length(msg));
+    written += DP_write_bigendian_uint16(mdds->layer, data + written);
+    written += DP_write_bigendian_int32(mdds->x, data + written);
+    written += DP_write_bigendian_int32(mdds->y, data + written);
+    written += DP_write_bigendian_uint32(mdds->color, data + written);
+    written += DP_write_bigendian_uint8(mdds->mode, data + written);
+    written += DP_write_bigendian_uint8(mdds->falloff, data + written);
+    written += DP_write_bigendian_uint32(mdds->grain, data + written);
+    written += DP_write_bigendian_uint16(mdds->grainscale, data + written);
+    written += DP_write_bigendian_uint8(mdds->grainstrength, data + written);
+    written += soft_dab_serialize_payloads(mdds->dabs, mdds->dabs_count,
+                                           data + written);
+    DP_ASSERT(written == msg_draw_dabs_soft_payload_length(msg));
     return written;
 }
 
-static bool msg_layer_tree_delete_write_payload_text(DP_Message *msg,
-                                                     DP_TextWriter *writer)
+static bool msg_draw_dabs_soft_write_payload_text(DP_Message *msg,
+                                                  DP_TextWriter *writer)
 {
-    DP_MsgLayerTreeDelete *mltd = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "id", mltd->id, true)
-        && DP_text_writer_write_uint(writer, "merge_to", mltd->merge_to, true);
-}
-
-static bool msg_layer_tree_delete_equals(DP_Message *DP_RESTRICT msg,
-                                         DP_Message *DP_RESTRICT other)
+    DP_MsgDrawDabsSoft *mdds = DP_message_internal(msg);
+    return DP_text_writer_write_argb_color(writer, "color", mdds->color)
+        && DP_text_writer_write_uint(writer, "falloff", mdds->falloff, false)
+        && DP_text_writer_write_uint(writer, "grain", mdds->grain, true)
+        && DP_text_writer_write_decimal(writer, "grainscale",
+                                        (double)mdds->grainscale / 256.0)
+        && DP_text_writer_write_uint(writer, "grainstrength",
+                                     mdds->grainstrength, false)
+        && DP_text_writer_write_uint(writer, "layer", mdds->layer, true)
+        && DP_text_writer_write_blend_mode(writer, "mode", mdds->mode)
+        && DP_text_writer_write_decimal(writer, "x", (double)mdds->x / 4.0)
+        && DP_text_writer_write_decimal(writer, "y", (double)mdds->y / 4.0)
+        && soft_dab_write_payload_texts(mdds->dabs, mdds->dabs_count, writer);
+}
+
+static bool msg_draw_dabs_soft_equals(DP_Message *DP_RESTRICT msg,
+                                      DP_Message *DP_RESTRICT other)
 {
-    DP_MsgLayerTreeDelete *a = DP_message_internal(msg);
-    DP_MsgLayerTreeDelete *b = DP_message_internal(other);
-    return a->id == b->id && a->merge_to == b->merge_to;
+    DP_MsgDrawDabsSoft *a = DP_message_internal(msg);
+    DP_MsgDrawDabsSoft *b = DP_message_internal(other);
+    return a->layer == b->layer && a->x == b->x && a->y == b->y
+        && a->color == b->color && a->mode == b->mode
+        && a->falloff == b->falloff && a->grain == b->grain
+        && a->grainscale == b->grainscale
+        && a->grainstrength == b->grainstrength
+        && a->dabs_count == b->dabs_count
+        && soft_dabs_equal(a->dabs, b->dabs, a->dabs_count);
 }
 
-static const DP_MessageMethods msg_layer_tree_delete_methods = {
-    msg_layer_tree_delete_payload_length,
-    msg_layer_tree_delete_serialize_payload,
-    msg_layer_tree_delete_write_payload_text,
-    msg_layer_tree_delete_equals,
+static const DP_MessageMethods msg_draw_dabs_soft_methods = {
+    msg_draw_dabs_soft_payload_length,
+    msg_draw_dabs_soft_serialize_payload,
+    msg_draw_dabs_soft_write_payload_text,
+    msg_draw_dabs_soft_equals,
 };
 
-DP_Message *DP_msg_layer_tree_delete_new(unsigned int context_id, uint16_t id,
-                                         uint16_t merge_to)
+DP_Message *
+DP_msg_draw_dabs_soft_new(unsigned int context_id, uint16_t layer, int32_t x,
+                          int32_t y, uint32_t color, uint8_t mode,
+                          uint8_t falloff, uint32_t grain, uint16_t grainscale,
+                          uint8_t grainstrength,
+                          void (*set_dabs)(int, DP_SoftDab *, void *),
+                          int dabs_count, void *dabs_user)
 {
-    DP_Message *msg = DP_message_new(DP_MSG_LAYER_TREE_DELETE, context_id,
-                                     &msg_layer_tree_delete_methods,
-                                     sizeof(DP_MsgLayerTreeDelete));
-    DP_MsgLayerTreeDelete *mltd = DP_message_internal(msg);
-    mltd->id = id;
-    mltd->merge_to = merge_to;
+    DP_Message *msg = DP_message_new(
+        DP_MSG_DRAW_DABS_SOFT, context_id, &msg_draw_dabs_soft_methods,
+        DP_FLEX_SIZEOF(DP_MsgDrawDabsSoft, dabs,
+                       DP_int_to_size(dabs_count) * sizeof(DP_SoftDab)));
+    DP_MsgDrawDabsSoft *mdds = DP_message_internal(msg);
+    mdds->layer = layer;
+    mdds->x = x;
+    mdds->y = y;
+    mdds->color = color;
+    mdds->mode = mode;
+    mdds->falloff = falloff;
+    mdds->grain = grain;
+    mdds->grainscale = grainscale;
+    mdds->grainstrength = grainstrength;
+    mdds->dabs_count = DP_int_to_uint16(dabs_count);
+    set_dabs(mdds->dabs_count, mdds->dabs, dabs_user);
     return msg;
 }
 
-DP_Message *DP_msg_layer_tree_delete_deserialize(unsigned int context_id,
-                                                 const unsigned char *buffer,
-                                                 size_t length)
+DP_Message *DP_msg_draw_dabs_soft_deserialize(unsigned int context_id,
+                                              const unsigned char *buffer,
+                                              size_t length)
 {
-    if (length != 4) {
-        DP_error_set("Wrong length for layertreedelete message; "
-                     "expected 4, got %zu",
+    if (length < 29 || length > 65531) {
+        DP_error_set("Wrong length for softdabs message; "
+                     "expected between 29 and 65531, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t id = read_uint16(buffer + read, &read);
-    uint16_t merge_to = read_uint16(buffer + read, &read);
-    return DP_msg_layer_tree_delete_new(context_id, id, merge_to);
+    uint16_t layer = read_uint16(buffer + read, &read);
+    int32_t x = read_int32(buffer + read, &read);
+    int32_t y = read_int32(buffer + read, &read);
+    uint32_t color = read_uint32(buffer + read, &read);
+    uint8_t mode = read_uint8(buffer + read, &read);
+    uint8_t falloff = read_uint8(buffer + read, &read);
+    uint32_t grain = read_uint32(buffer + read, &read);
+    uint16_t grainscale = read_uint16(buffer + read, &read);
+    uint8_t grainstrength = read_uint8(buffer + read, &read);
+    size_t dabs_bytes = length - read;
+    if ((dabs_bytes % 6) != 0) {
+        DP_error_set("Wrong length for dabs field in softdabs message; "
+                     "%zu not divisible by 6",
+                     dabs_bytes);
+        return NULL;
+    }
+    int dabs_count = DP_size_to_int(dabs_bytes) / 6;
+    void *dabs_user = (void *)(buffer + read);
+    return DP_msg_draw_dabs_soft_new(
+        context_id, layer, x, y, color, mode, falloff, grain, grainscale,
+        grainstrength, soft_dab_deserialize, dabs_count, dabs_user);
 }
 
-DP_Message *DP_msg_layer_tree_delete_parse(unsigned int context_id,
-                                           DP_TextReader *reader)
+DP_Message *DP_msg_draw_dabs_soft_parse(unsigned int context_id,
+                                        DP_TextReader *reader)
 {
-    uint16_t id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
-    uint16_t merge_to =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "merge_to", UINT16_MAX);
-    return DP_msg_layer_tree_delete_new(context_id, id, merge_to);
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    int32_t x = (int32_t)DP_text_reader_get_decimal(reader, "x", 4.0, INT32_MIN,
+                                                    INT32_MAX);
+    int32_t y = (int32_t)DP_text_reader_get_decimal(reader, "y", 4.0, INT32_MIN,
+                                                    INT32_MAX);
+    uint32_t color = DP_text_reader_get_argb_color(reader, "color");
+    uint8_t mode = DP_text_reader_get_blend_mode(reader, "mode");
+    uint8_t falloff =
+        (uint8_t)DP_text_reader_get_ulong(reader, "falloff", UINT8_MAX);
+    uint32_t grain =
+        (uint32_t)DP_text_reader_get_ulong_hex(reader, "grain", UINT32_MAX);
+    uint16_t grainscale = (uint16_t)DP_text_reader_get_decimal(
+        reader, "grainscale", 256.0, 0, UINT16_MAX);
+    uint8_t grainstrength =
+        (uint8_t)DP_text_reader_get_ulong(reader, "grainstrength", UINT8_MAX);
+    int dabs_count = DP_text_reader_get_tuple_count(reader);
+    void *dabs_user = reader;
+    return DP_msg_draw_dabs_soft_new(
+        context_id, layer, x, y, color, mode, falloff, grain, grainscale,
+        grainstrength, soft_dab_parse, dabs_count, dabs_user);
 }
 
-DP_MsgLayerTreeDelete *DP_msg_layer_tree_delete_cast(DP_Message *msg)
+DP_MsgDrawDabsSoft *DP_msg_draw_dabs_soft_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_LAYER_TREE_DELETE);
+    return DP_message_cast(msg, DP_MSG_DRAW_DABS_SOFT);
 }
 
-uint16_t DP_msg_layer_tree_delete_id(const DP_MsgLayerTreeDelete *mltd)
+uint16_t DP_msg_draw_dabs_soft_layer(const DP_MsgDrawDabsSoft *mdds)
 {
-    DP_ASSERT(mltd);
-    return mltd->id;
+    DP_ASSERT(mdds);
+    return mdds->layer;
 }
 
-uint16_t DP_msg_layer_tree_delete_merge_to(const DP_MsgLayerTreeDelete *mltd)
+int32_t DP_msg_draw_dabs_soft_x(const DP_MsgDrawDabsSoft *mdds)
 {
-    DP_ASSERT(mltd);
-    return mltd->merge_to;
+    DP_ASSERT(mdds);
+    return mdds->x;
 }
 
+int32_t DP_msg_draw_dabs_soft_y(const DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->y;
+}
 
-/* DP_MSG_TRANSFORM_REGION */
+uint32_t DP_msg_draw_dabs_soft_color(const DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->color;
+}
 
-const char *DP_msg_transform_region_mode_variant_name(unsigned int value)
+uint8_t DP_msg_draw_dabs_soft_mode(const DP_MsgDrawDabsSoft *mdds)
 {
-    switch (value) {
-    case DP_MSG_TRANSFORM_REGION_MODE_NEAREST:
-        return "Nearest";
-    case DP_MSG_TRANSFORM_REGION_MODE_BILINEAR:
-        return "Bilinear";
-    default:
-        return NULL;
-    }
+    DP_ASSERT(mdds);
+    return mdds->mode;
 }
 
-struct DP_MsgTransformRegion {
+uint8_t DP_msg_draw_dabs_soft_falloff(const DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->falloff;
+}
+
+uint32_t DP_msg_draw_dabs_soft_grain(const DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->grain;
+}
+
+uint16_t DP_msg_draw_dabs_soft_grainscale(const DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->grainscale;
+}
+
+uint8_t DP_msg_draw_dabs_soft_grainstrength(const DP_MsgDrawDabsSoft *mdds)
+{
+    DP_ASSERT(mdds);
+    return mdds->grainstrength;
+}
+
+const DP_SoftDab *DP_msg_draw_dabs_soft_dabs(const DP_MsgDrawDabsSoft *mdds,
+                                             int *out_count)
+{
+    DP_ASSERT(mdds);
+    if (out_count) {
+        *out_count = mdds->dabs_count;
+    }
+    return mdds->dabs;
+}
+
+int DP_msg_draw_dabs_soft_dabs_count(const DP_MsgDrawDabsSoft *mdds)
+{
+    return mdds->dabs_count;
+}
+
+
+/* DP_MSG_MOVE_RECT */
+
+struct DP_MsgMoveRect {
     uint16_t layer;
     uint16_t source;
-    int32_t bx;
-    int32_t by;
-    int32_t bw;
-    int32_t bh;
-    int32_t x1;
-    int32_t y1;
-    int32_t x2;
-    int32_t y2;
-    int32_t x3;
-    int32_t y3;
-    int32_t x4;
-    int32_t y4;
-    uint8_t mode;
+    int32_t sx;
+    int32_t sy;
+    int32_t tx;
+    int32_t ty;
+    int32_t w;
+    int32_t h;
     uint16_t mask_size;
     unsigned char mask[];
 };
 
-static size_t msg_transform_region_payload_length(DP_Message *msg)
+static size_t msg_move_rect_payload_length(DP_Message *msg)
 {
-    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
-    return ((size_t)53) + mtr->mask_size;
+    DP_MsgMoveRect *mmr = DP_message_internal(msg);
+    return ((size_t)28) + mmr->mask_size;
 }
 
-static size_t msg_transform_region_serialize_payload(DP_Message *msg,
-                                                     unsigned char *data)
+static size_t msg_move_rect_serialize_payload(DP_Message *msg,
+                                              unsigned char *data)
 {
-    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
+    DP_MsgMoveRect *mmr = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mtr->layer, data + written);
-    written += DP_write_bigendian_uint16(mtr->source, data + written);
-    written += DP_write_bigendian_int32(mtr->bx, data + written);
-    written += DP_write_bigendian_int32(mtr->by, data + written);
-    written += DP_write_bigendian_int32(mtr->bw, data + written);
-    written += DP_write_bigendian_int32(mtr->bh, data + written);
-    written += DP_write_bigendian_int32(mtr->x1, data + written);
-    written += DP_write_bigendian_int32(mtr->y1, data + written);
-    written += DP_write_bigendian_int32(mtr->x2, data + written);
-    written += DP_write_bigendian_int32(mtr->y2, data + written);
-    written += DP_write_bigendian_int32(mtr->x3, data + written);
-    written += DP_write_bigendian_int32(mtr->y3, data + written);
-    written += DP_write_bigendian_int32(mtr->x4, data + written);
-    written += DP_write_bigendian_int32(mtr->y4, data + written);
-    written += DP_write_bigendian_uint8(mtr->mode, data + written);
-    written += write_bytes(mtr->mask, mtr->mask_size, data + written);
-    DP_ASSERT(written == msg_transform_region_payload_length(msg));
+    written += DP_write_bigendian_uint16(mmr->layer, data + written);
+    written += DP_write_bigendian_uint16(mmr->source, data + written);
+    written += DP_write_bigendian_int32(mmr->sx, data + written);
+    written += DP_write_bigendian_int32(mmr->sy, data + written);
+    written += DP_write_bigendian_int32(mmr->tx, data + written);
+    written += DP_write_bigendian_int32(mmr->ty, data + written);
+    written += DP_write_bigendian_int32(mmr->w, data + written);
+    written += DP_write_bigendian_int32(mmr->h, data + written);
+    written += write_bytes(mmr->mask, mmr->mask_size, data + written);
+    DP_ASSERT(written == msg_move_rect_payload_length(msg));
     return written;
 }
 
-static bool msg_transform_region_write_payload_text(DP_Message *msg,
-                                                    DP_TextWriter *writer)
+static bool msg_move_rect_write_payload_text(DP_Message *msg,
+                                             DP_TextWriter *writer)
 {
-    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
-    return DP_text_writer_write_int(writer, "bh", mtr->bh)
-        && DP_text_writer_write_int(writer, "bw", mtr->bw)
-        && DP_text_writer_write_int(writer, "bx", mtr->bx)
-        && DP_text_writer_write_int(writer, "by", mtr->by)
-        && DP_text_writer_write_uint(writer, "layer", mtr->layer, true)
-        && DP_text_writer_write_base64(writer, "mask", mtr->mask,
-                                       mtr->mask_size)
-        && DP_text_writer_write_uint(writer, "mode", mtr->mode, false)
-        && DP_text_writer_write_uint(writer, "source", mtr->source, true)
-        && DP_text_writer_write_int(writer, "x1", mtr->x1)
-        && DP_text_writer_write_int(writer, "x2", mtr->x2)
-        && DP_text_writer_write_int(writer, "x3", mtr->x3)
-        && DP_text_writer_write_int(writer, "x4", mtr->x4)
-        && DP_text_writer_write_int(writer, "y1", mtr->y1)
-        && DP_text_writer_write_int(writer, "y2", mtr->y2)
-        && DP_text_writer_write_int(writer, "y3", mtr->y3)
-        && DP_text_writer_write_int(writer, "y4", mtr->y4);
+    DP_MsgMoveRect *mmr = DP_message_internal(msg);
+    return DP_text_writer_write_int(writer, "h", mmr->h)
+        && DP_text_writer_write_uint(writer, "layer", mmr->layer, true)
+        && DP_text_writer_write_base64(writer, "mask", mmr->mask,
+                                       mmr->mask_size)
+        && DP_text_writer_write_uint(writer, "source", mmr->source, true)
+        && DP_text_writer_write_int(writer, "sx", mmr->sx)
+        && DP_text_writer_write_int(writer, "sy", mmr->sy)
+        && DP_text_writer_write_int(writer, "tx", mmr->tx)
+        && DP_text_writer_write_int(writer, "ty", mmr->ty)
+        && DP_text_writer_write_int(writer, "w", mmr->w);
 }
 
-static bool msg_transform_region_equals(DP_Message *DP_RESTRICT msg,
-                                        DP_Message *DP_RESTRICT other)
+static bool msg_move_rect_equals(DP_Message *DP_RESTRICT msg,
+                                 DP_Message *DP_RESTRICT other)
 {
-    DP_MsgTransformRegion *a = DP_message_internal(msg);
-    DP_MsgTransformRegion *b = DP_message_internal(other);
-    return a->layer == b->layer && a->source == b->source && a->bx == b->bx
-        && a->by == b->by && a->bw == b->bw && a->bh == b->bh && a->x1 == b->x1
-        && a->y1 == b->y1 && a->x2 == b->x2 && a->y2 == b->y2 && a->x3 == b->x3
-        && a->y3 == b->y3 && a->x4 == b->x4 && a->y4 == b->y4
-        && a->mode == b->mode && a->mask_size == b->mask_size
+    DP_MsgMoveRect *a = DP_message_internal(msg);
+    DP_MsgMoveRect *b = DP_message_internal(other);
+    return a->layer == b->layer && a->source == b->source && a->sx == b->sx
+        && a->sy == b->sy && a->tx == b->tx && a->ty == b->ty && a->w == b->w
+        && a->h == b->h && a->mask_size == b->mask_size
         && memcmp(a->mask, b->mask, DP_uint16_to_size(a->mask_size)) == 0;
 }
 
-static const DP_MessageMethods msg_transform_region_methods = {
-    msg_transform_region_payload_length,
-    msg_transform_region_serialize_payload,
-    msg_transform_region_write_payload_text,
-    msg_transform_region_equals,
+static const DP_MessageMethods msg_move_rect_methods = {
+    msg_move_rect_payload_length,
+    msg_move_rect_serialize_payload,
+    msg_move_rect_write_payload_text,
+    msg_move_rect_equals,
 };
 
-DP_Message *DP_msg_transform_region_new(
-    unsigned int context_id, uint16_t layer, uint16_t source, int32_t bx,
-    int32_t by, int32_t bw, int32_t bh, int32_t x1, int32_t y1, int32_t x2,
-    int32_t y2, int32_t x3, int32_t y3, int32_t x4, int32_t y4, uint8_t mode,
-    void (*set_mask)(size_t, unsigned char *, void *), size_t mask_size,
-    void *mask_user)
+DP_Message *DP_msg_move_rect_new(unsigned int context_id, uint16_t layer,
+                                 uint16_t source, int32_t sx, int32_t sy,
+                                 int32_t tx, int32_t ty, int32_t w, int32_t h,
+                                 void (*set_mask)(size_t, unsigned char *,
+                                                  void *),
+                                 size_t mask_size, void *mask_user)
 {
-    DP_Message *msg = DP_message_new(
-        DP_MSG_TRANSFORM_REGION, context_id, &msg_transform_region_methods,
-        DP_FLEX_SIZEOF(DP_MsgTransformRegion, mask, mask_size));
-    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
-    mtr->layer = layer;
-    mtr->source = source;
-    mtr->bx = bx;
-    mtr->by = by;
-    mtr->bw = bw;
-    mtr->bh = bh;
-    mtr->x1 = x1;
-    mtr->y1 = y1;
-    mtr->x2 = x2;
-    mtr->y2 = y2;
-    mtr->x3 = x3;
-    mtr->y3 = y3;
-    mtr->x4 = x4;
-    mtr->y4 = y4;
-    mtr->mode = mode;
-    mtr->mask_size = DP_size_to_uint16(mask_size);
+    DP_Message *msg =
+        DP_message_new(DP_MSG_MOVE_RECT, context_id, &msg_move_rect_methods,
+                       DP_FLEX_SIZEOF(DP_MsgMoveRect, mask, mask_size));
+    DP_MsgMoveRect *mmr = DP_message_internal(msg);
+    mmr->layer = layer;
+    mmr->source = source;
+    mmr->sx = sx;
+    mmr->sy = sy;
+    mmr->tx = tx;
+    mmr->ty = ty;
+    mmr->w = w;
+    mmr->h = h;
+    mmr->mask_size = DP_size_to_uint16(mask_size);
     if (set_mask) {
-        set_mask(mtr->mask_size, mtr->mask, mask_user);
+        set_mask(mmr->mask_size, mmr->mask, mask_user);
+    }
+    return msg;
+}
+
+DP_Message *DP_msg_move_rect_deserialize(unsigned int context_id,
+                                         const unsigned char *buffer,
+                                         size_t length)
+{
+    if (length < 28 || length > 65535) {
+        DP_error_set("Wrong length for moverect message; "
+                     "expected between 28 and 65535, got %zu",
+                     length);
+        return NULL;
     }
+    size_t read = 0;
+    uint16_t layer = read_uint16(buffer + read, &read);
+    uint16_t source = read_uint16(buffer + read, &read);
+    int32_t sx = read_int32(buffer + read, &read);
+    int32_t sy = read_int32(buffer + read, &read);
+    int32_t tx = read_int32(buffer + read, &read);
+    int32_t ty = read_int32(buffer + read, &read);
+    int32_t w = read_int32(buffer + read, &read);
+    int32_t h = read_int32(buffer + read, &read);
+    size_t mask_bytes = length - read;
+    uint16_t mask_size = DP_size_to_uint16(mask_bytes);
+    void *mask_user = (void *)(buffer + read);
+    return DP_msg_move_rect_new(context_id, layer, source, sx, sy, tx, ty, w, h,
+                                read_bytes, mask_size, mask_user);
+}
+
+DP_Message *DP_msg_move_rect_parse(unsigned int context_id,
+                                   DP_TextReader *reader)
+{
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    uint16_t source =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source", UINT16_MAX);
+    int32_t sx =
+        (int32_t)DP_text_reader_get_long(reader, "sx", INT32_MIN, INT32_MAX);
+    int32_t sy =
+        (int32_t)DP_text_reader_get_long(reader, "sy", INT32_MIN, INT32_MAX);
+    int32_t tx =
+        (int32_t)DP_text_reader_get_long(reader, "tx", INT32_MIN, INT32_MAX);
+    int32_t ty =
+        (int32_t)DP_text_reader_get_long(reader, "ty", INT32_MIN, INT32_MAX);
+    int32_t w =
+        (int32_t)DP_text_reader_get_long(reader, "w", INT32_MIN, INT32_MAX);
+    int32_t h =
+        (int32_t)DP_text_reader_get_long(reader, "h", INT32_MIN, INT32_MAX);
+    size_t mask_size;
+    DP_TextReaderParseParams mask_params =
+        DP_text_reader_get_base64_string(reader, "mask", &mask_size);
+    return DP_msg_move_rect_new(context_id, layer, source, sx, sy, tx, ty, w, h,
+                                DP_text_reader_parse_base64, mask_size,
+                                &mask_params);
+}
+
+DP_MsgMoveRect *DP_msg_move_rect_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_MOVE_RECT);
+}
+
+uint16_t DP_msg_move_rect_layer(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->layer;
+}
+
+uint16_t DP_msg_move_rect_source(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->source;
+}
+
+int32_t DP_msg_move_rect_sx(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->sx;
+}
+
+int32_t DP_msg_move_rect_sy(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->sy;
+}
+
+int32_t DP_msg_move_rect_tx(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->tx;
+}
+
+int32_t DP_msg_move_rect_ty(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->ty;
+}
+
+int32_t DP_msg_move_rect_w(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->w;
+}
+
+int32_t DP_msg_move_rect_h(const DP_MsgMoveRect *mmr)
+{
+    DP_ASSERT(mmr);
+    return mmr->h;
+}
+
+const unsigned char *DP_msg_move_rect_mask(const DP_MsgMoveRect *mmr,
+                                           size_t *out_size)
+{
+    DP_ASSERT(mmr);
+    if (out_size) {
+        *out_size = mmr->mask_size;
+    }
+    return mmr->mask;
+}
+
+size_t DP_msg_move_rect_mask_size(const DP_MsgMoveRect *mmr)
+{
+    return mmr->mask_size;
+}
+
+
+/* DP_MSG_SET_METADATA_INT */
+
+const char *DP_msg_set_metadata_int_field_variant_name(unsigned int value)
+{
+    switch (value) {
+    case DP_MSG_SET_METADATA_INT_FIELD_DPIX:
+        return "Dpix";
+    case DP_MSG_SET_METADATA_INT_FIELD_DPIY:
+        return "Dpiy";
+    case DP_MSG_SET_METADATA_INT_FIELD_FRAMERATE:
+        return "Framerate";
+    case DP_MSG_SET_METADATA_INT_FIELD_FRAME_COUNT:
+        return "FrameCount";
+    default:
+        return NULL;
+    }
+}
+
+struct DP_MsgSetMetadataInt {
+    uint8_t field;
+    int32_t value;
+};
+
+static size_t msg_set_metadata_int_payload_length(DP_UNUSED DP_Message *msg)
+{
+    return ((size_t)5);
+}
+
+static size_t msg_set_metadata_int_serialize_payload(DP_Message *msg,
+                                                     unsigned char *data)
+{
+    DP_MsgSetMetadataInt *msmi = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint8(msmi->field, data + written);
+    written += DP_write_bigendian_int32(msmi->value, data + written);
+    DP_ASSERT(written == msg_set_metadata_int_payload_length(msg));
+    return written;
+}
+
+static bool msg_set_metadata_int_write_payload_text(DP_Message *msg,
+                                                    DP_TextWriter *writer)
+{
+    DP_MsgSetMetadataInt *msmi = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "field", msmi->field, false)
+        && DP_text_writer_write_int(writer, "value", msmi->value);
+}
+
+static bool msg_set_metadata_int_equals(DP_Message *DP_RESTRICT msg,
+                                        DP_Message *DP_RESTRICT other)
+{
+    DP_MsgSetMetadataInt *a = DP_message_internal(msg);
+    DP_MsgSetMetadataInt *b = DP_message_internal(other);
+    return a->field == b->field && a->value == b->value;
+}
+
+static const DP_MessageMethods msg_set_metadata_int_methods = {
+    msg_set_metadata_int_payload_length,
+    msg_set_metadata_int_serialize_payload,
+    msg_set_metadata_int_write_payload_text,
+    msg_set_metadata_int_equals,
+};
+
+DP_Message *DP_msg_set_metadata_int_new(unsigned int context_id, uint8_t field,
+                                        int32_t value)
+{
+    DP_Message *msg = DP_message_new(DP_MSG_SET_METADATA_INT, context_id,
+                                     &msg_set_metadata_int_methods,
+                                     sizeof(DP_MsgSetMetadataInt));
+    DP_MsgSetMetadataInt *msmi = DP_message_internal(msg);
+    msmi->field = field;
+    msmi->value = value;
+    return msg;
+}
+
+DP_Message *DP_msg_set_metadata_int_deserialize(unsigned int context_id,
+                                                const unsigned char *buffer,
+                                                size_t length)
+{
+    if (length != 5) {
+        DP_error_set("Wrong length for setmetadataint message; "
+                     "expected 5, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint8_t field = read_uint8(buffer + read, &read);
+    int32_t value = read_int32(buffer + read, &read);
+    return DP_msg_set_metadata_int_new(context_id, field, value);
+}
+
+DP_Message *DP_msg_set_metadata_int_parse(unsigned int context_id,
+                                          DP_TextReader *reader)
+{
+    uint8_t field =
+        (uint8_t)DP_text_reader_get_ulong(reader, "field", UINT8_MAX);
+    int32_t value =
+        (int32_t)DP_text_reader_get_long(reader, "value", INT32_MIN, INT32_MAX);
+    return DP_msg_set_metadata_int_new(context_id, field, value);
+}
+
+DP_MsgSetMetadataInt *DP_msg_set_metadata_int_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_SET_METADATA_INT);
+}
+
+uint8_t DP_msg_set_metadata_int_field(const DP_MsgSetMetadataInt *msmi)
+{
+    DP_ASSERT(msmi);
+    return msmi->field;
+}
+
+int32_t DP_msg_set_metadata_int_value(const DP_MsgSetMetadataInt *msmi)
+{
+    DP_ASSERT(msmi);
+    return msmi->value;
+}
+
+
+/* DP_MSG_LAYER_TREE_CREATE */
+
+const char *DP_msg_layer_tree_create_flags_flag_name(unsigned int value)
+{
+    switch (value) {
+    case DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP:
+        return "group";
+    case DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO:
+        return "into";
+    default:
+        return NULL;
+    }
+}
+
+struct DP_MsgLayerTreeCreate {
+    uint16_t id;
+    uint16_t source;
+    uint16_t target;
+    uint32_t fill;
+    uint8_t flags;
+    uint16_t title_len;
+    char title[];
+};
+
+static size_t msg_layer_tree_create_payload_length(DP_Message *msg)
+{
+    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
+    return ((size_t)11) + DP_uint16_to_size(mltc->title_len);
+}
+
+static size_t msg_layer_tree_create_serialize_payload(DP_Message *msg,
+                                                      unsigned char *data)
+{
+    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mltc->id, data + written);
+    written += DP_write_bigendian_uint16(mltc->source, data + written);
+    written += DP_write_bigendian_uint16(mltc->target, data + written);
+    written += DP_write_bigendian_uint32(mltc->fill, data + written);
+    written += DP_write_bigendian_uint8(mltc->flags, data + written);
+    written += DP_write_bytes(mltc->title, 1, mltc->title_len, data + written);
+    DP_ASSERT(written == msg_layer_tree_create_payload_length(msg));
+    return written;
+}
+
+static bool msg_layer_tree_create_write_payload_text(DP_Message *msg,
+                                                     DP_TextWriter *writer)
+{
+    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
+    return DP_text_writer_write_argb_color(writer, "fill", mltc->fill)
+        && DP_text_writer_write_flags(
+               writer, "flags", mltc->flags, 2,
+               (const char *[]){"group", "into"},
+               (unsigned int[]){DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                                DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO})
+        && DP_text_writer_write_uint(writer, "id", mltc->id, true)
+        && DP_text_writer_write_uint(writer, "source", mltc->source, true)
+        && DP_text_writer_write_uint(writer, "target", mltc->target, true)
+        && DP_text_writer_write_string(writer, "title", mltc->title);
+}
+
+static bool msg_layer_tree_create_equals(DP_Message *DP_RESTRICT msg,
+                                         DP_Message *DP_RESTRICT other)
+{
+    DP_MsgLayerTreeCreate *a = DP_message_internal(msg);
+    DP_MsgLayerTreeCreate *b = DP_message_internal(other);
+    return a->id == b->id && a->source == b->source && a->target == b->target
+        && a->fill == b->fill && a->flags == b->flags
+        && a->title_len == b->title_len
+        && memcmp(a->title, b->title, a->title_len) == 0;
+}
+
+static const DP_MessageMethods msg_layer_tree_create_methods = {
+    msg_layer_tree_create_payload_length,
+    msg_layer_tree_create_serialize_payload,
+    msg_layer_tree_create_write_payload_text,
+    msg_layer_tree_create_equals,
+};
+
+DP_Message *DP_msg_layer_tree_create_new(unsigned int context_id, uint16_t id,
+                                         uint16_t source, uint16_t target,
+                                         uint32_t fill, uint8_t flags,
+                                         const char *title_value,
+                                         size_t title_len)
+{
+    DP_Message *msg = DP_message_new(
+        DP_MSG_LAYER_TREE_CREATE, context_id, &msg_layer_tree_create_methods,
+        DP_FLEX_SIZEOF(DP_MsgLayerTreeCreate, title, title_len + 1));
+    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
+    mltc->id = id;
+    mltc->source = source;
+    mltc->target = target;
+    mltc->fill = fill;
+    mltc->flags = flags;
+    mltc->title_len = DP_size_to_uint16(title_len);
+    assign_string(mltc->title, title_value, mltc->title_len);
+    return msg;
+}
+
+DP_Message *DP_msg_layer_tree_create_deserialize(unsigned int context_id,
+                                                 const unsigned char *buffer,
+                                                 size_t length)
+{
+    if (length < 11 || length > 65535) {
+        DP_error_set("Wrong length for layertreecreate message; "
+                     "expected between 11 and 65535, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t id = read_uint16(buffer + read, &read);
+    uint16_t source = read_uint16(buffer + read, &read);
+    uint16_t target = read_uint16(buffer + read, &read);
+    uint32_t fill = read_uint32(buffer + read, &read);
+    uint8_t flags = read_uint8(buffer + read, &read);
+    size_t title_bytes = length - read;
+    uint16_t title_len = DP_size_to_uint16(title_bytes);
+    const char *title = (const char *)buffer + read;
+    return DP_msg_layer_tree_create_new(context_id, id, source, target, fill,
+                                        flags, title, title_len);
+}
+
+DP_Message *DP_msg_layer_tree_create_parse(unsigned int context_id,
+                                           DP_TextReader *reader)
+{
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    uint16_t source =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source", UINT16_MAX);
+    uint16_t target =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "target", UINT16_MAX);
+    uint32_t fill = DP_text_reader_get_argb_color(reader, "fill");
+    uint8_t flags = (uint8_t)DP_text_reader_get_flags(
+        reader, "flags", 2, (const char *[]){"group", "into"},
+        (unsigned int[]){DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP,
+                         DP_MSG_LAYER_TREE_CREATE_FLAGS_INTO});
+    uint16_t title_len;
+    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
+    return DP_msg_layer_tree_create_new(context_id, id, source, target, fill,
+                                        flags, title, title_len);
+}
+
+DP_MsgLayerTreeCreate *DP_msg_layer_tree_create_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_LAYER_TREE_CREATE);
+}
+
+uint16_t DP_msg_layer_tree_create_id(const DP_MsgLayerTreeCreate *mltc)
+{
+    DP_ASSERT(mltc);
+    return mltc->id;
+}
+
+uint16_t DP_msg_layer_tree_create_source(const DP_MsgLayerTreeCreate *mltc)
+{
+    DP_ASSERT(mltc);
+    return mltc->source;
+}
+
+uint16_t DP_msg_layer_tree_create_target(const DP_MsgLayerTreeCreate *mltc)
+{
+    DP_ASSERT(mltc);
+    return mltc->target;
+}
+
+uint32_t DP_msg_layer_tree_create_fill(const DP_MsgLayerTreeCreate *mltc)
+{
+    DP_ASSERT(mltc);
+    return mltc->fill;
+}
+
+uint8_t DP_msg_layer_tree_create_flags(const DP_MsgLayerTreeCreate *mltc)
+{
+    DP_ASSERT(mltc);
+    return mltc->flags;
+}
+
+const char *DP_msg_layer_tree_create_title(const DP_MsgLayerTreeCreate *mltc,
+                                           size_t *out_len)
+{
+    DP_ASSERT(mltc);
+    if (out_len) {
+        *out_len = mltc->title_len;
+    }
+    return mltc->title;
+}
+
+size_t DP_msg_layer_tree_create_title_len(const DP_MsgLayerTreeCreate *mltc)
+{
+    return mltc->title_len;
+}
+
+
+/* DP_MSG_LAYER_TREE_MOVE */
+
+struct DP_MsgLayerTreeMove {
+    uint16_t layer;
+    uint16_t parent;
+    uint16_t sibling;
+};
+
+static size_t msg_layer_tree_move_payload_length(DP_UNUSED DP_Message *msg)
+{
+    return ((size_t)6);
+}
+
+static size_t msg_layer_tree_move_serialize_payload(DP_Message *msg,
+                                                    unsigned char *data)
+{
+    DP_MsgLayerTreeMove *mltm = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mltm->layer, data + written);
+    written += DP_write_bigendian_uint16(mltm->parent, data + written);
+    written += DP_write_bigendian_uint16(mltm->sibling, data + written);
+    DP_ASSERT(written == msg_layer_tree_move_payload_length(msg));
+    return written;
+}
+
+static bool msg_layer_tree_move_write_payload_text(DP_Message *msg,
+                                                   DP_TextWriter *writer)
+{
+    DP_MsgLayerTreeMove *mltm = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "layer", mltm->layer, true)
+        && DP_text_writer_write_uint(writer, "parent", mltm->parent, true)
+        && DP_text_writer_write_uint(writer, "sibling", mltm->sibling, true);
+}
+
+static bool msg_layer_tree_move_equals(DP_Message *DP_RESTRICT msg,
+                                       DP_Message *DP_RESTRICT other)
+{
+    DP_MsgLayerTreeMove *a = DP_message_internal(msg);
+    DP_MsgLayerTreeMove *b = DP_message_internal(other);
+    return a->layer == b->layer && a->parent == b->parent
+        && a->sibling == b->sibling;
+}
+
+static const DP_MessageMethods msg_layer_tree_move_methods = {
+    msg_layer_tree_move_payload_length,
+    msg_layer_tree_move_serialize_payload,
+    msg_layer_tree_move_write_payload_text,
+    msg_layer_tree_move_equals,
+};
+
+DP_Message *DP_msg_layer_tree_move_new(unsigned int context_id, uint16_t layer,
+                                       uint16_t parent, uint16_t sibling)
+{
+    DP_Message *msg = DP_message_new(DP_MSG_LAYER_TREE_MOVE, context_id,
+                                     &msg_layer_tree_move_methods,
+                                     sizeof(DP_MsgLayerTreeMove));
+    DP_MsgLayerTreeMove *mltm = DP_message_internal(msg);
+    mltm->layer = layer;
+    mltm->parent = parent;
+    mltm->sibling = sibling;
+    return msg;
+}
+
+DP_Message *DP_msg_layer_tree_move_deserialize(unsigned int context_id,
+                                               const unsigned char *buffer,
+                                               size_t length)
+{
+    if (length != 6) {
+        DP_error_set("Wrong length for layertreemove message; "
+                     "expected 6, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t layer = read_uint16(buffer + read, &read);
+    uint16_t parent = read_uint16(buffer + read, &read);
+    uint16_t sibling = read_uint16(buffer + read, &read);
+    return DP_msg_layer_tree_move_new(context_id, layer, parent, sibling);
+}
+
+DP_Message *DP_msg_layer_tree_move_parse(unsigned int context_id,
+                                         DP_TextReader *reader)
+{
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    uint16_t parent =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "parent", UINT16_MAX);
+    uint16_t sibling =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "sibling", UINT16_MAX);
+    return DP_msg_layer_tree_move_new(context_id, layer, parent, sibling);
+}
+
+DP_MsgLayerTreeMove *DP_msg_layer_tree_move_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_LAYER_TREE_MOVE);
+}
+
+uint16_t DP_msg_layer_tree_move_layer(const DP_MsgLayerTreeMove *mltm)
+{
+    DP_ASSERT(mltm);
+    return mltm->layer;
+}
+
+uint16_t DP_msg_layer_tree_move_parent(const DP_MsgLayerTreeMove *mltm)
+{
+    DP_ASSERT(mltm);
+    return mltm->parent;
+}
+
+uint16_t DP_msg_layer_tree_move_sibling(const DP_MsgLayerTreeMove *mltm)
+{
+    DP_ASSERT(mltm);
+    return mltm->sibling;
+}
+
+
+/* DP_MSG_LAYER_TREE_DELETE */
+
+struct DP_MsgLayerTreeDelete {
+    uint16_t id;
+    uint16_t merge_to;
+};
+
+static size_t msg_layer_tree_delete_payload_length(DP_UNUSED DP_Message *msg)
+{
+    return ((size_t)4);
+}
+
+static size_t msg_layer_tree_delete_serialize_payload(DP_Message *msg,
+                                                      unsigned char *data)
+{
+    DP_MsgLayerTreeDelete *mltd = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mltd->id, data + written);
+    written += DP_write_bigendian_uint16(mltd->merge_to, data + written);
+    DP_ASSERT(written == msg_layer_tree_delete_payload_length(msg));
+    return written;
+}
+
+static bool msg_layer_tree_delete_write_payload_text(DP_Message *msg,
+                                                     DP_TextWriter *writer)
+{
+    DP_MsgLayerTreeDelete *mltd = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "id", mltd->id, true)
+        && DP_text_writer_write_uint(writer, "merge_to", mltd->merge_to, true);
+}
+
+static bool msg_layer_tree_delete_equals(DP_Message *DP_RESTRICT msg,
+                                         DP_Message *DP_RESTRICT other)
+{
+    DP_MsgLayerTreeDelete *a = DP_message_internal(msg);
+    DP_MsgLayerTreeDelete *b = DP_message_internal(other);
+    return a->id == b->id && a->merge_to == b->merge_to;
+}
+
+static const DP_MessageMethods msg_layer_tree_delete_methods = {
+    msg_layer_tree_delete_payload_length,
+    msg_layer_tree_delete_serialize_payload,
+    msg_layer_tree_delete_write_payload_text,
+    msg_layer_tree_delete_equals,
+};
+
+DP_Message *DP_msg_layer_tree_delete_new(unsigned int context_id, uint16_t id,
+                                         uint16_t merge_to)
+{
+    DP_Message *msg = DP_message_new(DP_MSG_LAYER_TREE_DELETE, context_id,
+                                     &msg_layer_tree_delete_methods,
+                                     sizeof(DP_MsgLayerTreeDelete));
+    DP_MsgLayerTreeDelete *mltd = DP_message_internal(msg);
+    mltd->id = id;
+    mltd->merge_to = merge_to;
+    return msg;
+}
+
+DP_Message *DP_msg_layer_tree_delete_deserialize(unsigned int context_id,
+                                                 const unsigned char *buffer,
+                                                 size_t length)
+{
+    if (length != 4) {
+        DP_error_set("Wrong length for layertreedelete message; "
+                     "expected 4, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t id = read_uint16(buffer + read, &read);
+    uint16_t merge_to = read_uint16(buffer + read, &read);
+    return DP_msg_layer_tree_delete_new(context_id, id, merge_to);
+}
+
+DP_Message *DP_msg_layer_tree_delete_parse(unsigned int context_id,
+                                           DP_TextReader *reader)
+{
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    uint16_t merge_to =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "merge_to", UINT16_MAX);
+    return DP_msg_layer_tree_delete_new(context_id, id, merge_to);
+}
+
+DP_MsgLayerTreeDelete *DP_msg_layer_tree_delete_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_LAYER_TREE_DELETE);
+}
+
+uint16_t DP_msg_layer_tree_delete_id(const DP_MsgLayerTreeDelete *mltd)
+{
+    DP_ASSERT(mltd);
+    return mltd->id;
+}
+
+uint16_t DP_msg_layer_tree_delete_merge_to(const DP_MsgLayerTreeDelete *mltd)
+{
+    DP_ASSERT(mltd);
+    return mltd->merge_to;
+}
+
+
+/* DP_MSG_TRANSFORM_REGION */
+
+const char *DP_msg_transform_region_mode_variant_name(unsigned int value)
+{
+    switch (value) {
+    case DP_MSG_TRANSFORM_REGION_MODE_NEAREST:
+        return "Nearest";
+    case DP_MSG_TRANSFORM_REGION_MODE_BILINEAR:
+        return "Bilinear";
+    case DP_MSG_TRANSFORM_REGION_MODE_BICUBIC:
+        return "Bicubic";
+    default:
+        return NULL;
+    }
+}
+
+struct DP_MsgTransformRegion {
+    uint16_t layer;
+    uint16_t source;
+    int32_t bx;
+    int32_t by;
+    int32_t bw;
+    int32_t bh;
+    int32_t x1;
+    int32_t y1;
+    int32_t x2;
+    int32_t y2;
+    int32_t x3;
+    int32_t y3;
+    int32_t x4;
+    int32_t y4;
+    uint8_t mode;
+    uint16_t mask_size;
+    unsigned char mask[];
+};
+
+static size_t msg_transform_region_payload_length(DP_Message *msg)
+{
+    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
+    return ((size_t)53) + mtr->mask_size;
+}
+
+static size_t msg_transform_region_serialize_payload(DP_Message *msg,
+                                                     unsigned char *data)
+{
+    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mtr->layer, data + written);
+    written += DP_write_bigendian_uint16(mtr->source, data + written);
+    written += DP_write_bigendian_int32(mtr->bx, data + written);
+    written += DP_write_bigendian_int32(mtr->by, data + written);
+    written += DP_write_bigendian_int32(mtr->bw, data + written);
+    written += DP_write_bigendian_int32(mtr->bh, data + written);
+    written += DP_write_bigendian_int32(mtr->x1, data + written);
+    written += DP_write_bigendian_int32(mtr->y1, data + written);
+    written += DP_write_bigendian_int32(mtr->x2, data + written);
+    written += DP_write_bigendian_int32(mtr->y2, data + written);
+    written += DP_write_bigendian_int32(mtr->x3, data + written);
+    written += DP_write_bigendian_int32(mtr->y3, data + written);
+    written += DP_write_bigendian_int32(mtr->x4, data + written);
+    written += DP_write_bigendian_int32(mtr->y4, data + written);
+    written += DP_write_bigendian_uint8(mtr->mode, data + written);
+    written += write_bytes(mtr->mask, mtr->mask_size, data + written);
+    DP_ASSERT(written == msg_transform_region_payload_length(msg));
+    return written;
+}
+
+static bool msg_transform_region_write_payload_text(DP_Message *msg,
+                                                    DP_TextWriter *writer)
+{
+    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
+    return DP_text_writer_write_int(writer, "bh", mtr->bh)
+        && DP_text_writer_write_int(writer, "bw", mtr->bw)
+        && DP_text_writer_write_int(writer, "bx", mtr->bx)
+        && DP_text_writer_write_int(writer, "by", mtr->by)
+        && DP_text_writer_write_uint(writer, "layer", mtr->layer, true)
+        && DP_text_writer_write_base64(writer, "mask", mtr->mask,
+                                       mtr->mask_size)
+        && DP_text_writer_write_uint(writer, "mode", mtr->mode, false)
+        && DP_text_writer_write_uint(writer, "source", mtr->source, true)
+        && DP_text_writer_write_int(writer, "x1", mtr->x1)
+        && DP_text_writer_write_int(writer, "x2", mtr->x2)
+        && DP_text_writer_write_int(writer, "x3", mtr->x3)
+        && DP_text_writer_write_int(writer, "x4", mtr->x4)
+        && DP_text_writer_write_int(writer, "y1", mtr->y1)
+        && DP_text_writer_write_int(writer, "y2", mtr->y2)
+        && DP_text_writer_write_int(writer, "y3", mtr->y3)
+        && DP_text_writer_write_int(writer, "y4", mtr->y4);
+}
+
+static bool msg_transform_region_equals(DP_Message *DP_RESTRICT msg,
+                                        DP_Message *DP_RESTRICT other)
+{
+    DP_MsgTransformRegion *a = DP_message_internal(msg);
+    DP_MsgTransformRegion *b = DP_message_internal(other);
+    return a->layer == b->layer && a->source == b->source && a->bx == b->bx
+        && a->by == b->by && a->bw == b->bw && a->bh == b->bh && a->x1 == b->x1
+        && a->y1 == b->y1 && a->x2 == b->x2 && a->y2 == b->y2 && a->x3 == b->x3
+        && a->y3 == b->y3 && a->x4 == b->x4 && a->y4 == b->y4
+        && a->mode == b->mode && a->mask_size == b->mask_size
+        && memcmp(a->mask, b->mask, DP_uint16_to_size(a->mask_size)) == 0;
+}
+
+static const DP_MessageMethods msg_transform_region_methods = {
+    msg_transform_region_payload_length,
+    msg_transform_region_serialize_payload,
+    msg_transform_region_write_payload_text,
+    msg_transform_region_equals,
+};
+
+DP_Message *DP_msg_transform_region_new(
+    unsigned int context_id, uint16_t layer, uint16_t source, int32_t bx,
+    int32_t by, int32_t bw, int32_t bh, int32_t x1, int32_t y1, int32_t x2,
+    int32_t y2, int32_t x3, int32_t y3, int32_t x4, int32_t y4, uint8_t mode,
+    void (*set_mask)(size_t, unsigned char *, void *), size_t mask_size,
+    void *mask_user)
+{
+    DP_Message *msg = DP_message_new(
+        DP_MSG_TRANSFORM_REGION, context_id, &msg_transform_region_methods,
+        DP_FLEX_SIZEOF(DP_MsgTransformRegion, mask, mask_size));
+    DP_MsgTransformRegion *mtr = DP_message_internal(msg);
+    mtr->layer = layer;
+    mtr->source = source;
+    mtr->bx = bx;
+    mtr->by = by;
+    mtr->bw = bw;
+    mtr->bh = bh;
+    mtr->x1 = x1;
+    mtr->y1 = y1;
+    mtr->x2 = x2;
+    mtr->y2 = y2;
+    mtr->x3 = x3;
+    mtr->y3 = y3;
+    mtr->x4 = x4;
+    mtr->y4 = y4;
+    mtr->mode = mode;
+    mtr->mask_size = DP_size_to_uint16(mask_size);
+    if (set_mask) {
+        set_mask(mtr->mask_size, mtr->mask, mask_user);
+    }
+    return msg;
+}
+
+DP_Message *DP_msg_transform_region_deserialize(unsigned int context_id,
+                                                const unsigned char *buffer,
+                                                size_t length)
+{
+    if (length < 53 || length > 65535) {
+        DP_error_set("Wrong length for transformregion message; "
+                     "expected between 53 and 65535, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t layer = read_uint16(buffer + read, &read);
+    uint16_t source = read_uint16(buffer + read, &read);
+    int32_t bx = read_int32(buffer + read, &read);
+    int32_t by = read_int32(buffer + read, &read);
+    int32_t bw = read_int32(buffer + read, &read);
+    int32_t bh = read_int32(buffer + read, &read);
+    int32_t x1 = read_int32(buffer + read, &read);
+    int32_t y1 = read_int32(buffer + read, &read);
+    int32_t x2 = read_int32(buffer + read, &read);
+    int32_t y2 = read_int32(buffer + read, &read);
+    int32_t x3 = read_int32(buffer + read, &read);
+    int32_t y3 = read_int32(buffer + read, &read);
+    int32_t x4 = read_int32(buffer + read, &read);
+    int32_t y4 = read_int32(buffer + read, &read);
+    uint8_t mode = read_uint8(buffer + read, &read);
+    size_t mask_bytes = length - read;
+    uint16_t mask_size = DP_size_to_uint16(mask_bytes);
+    void *mask_user = (void *)(buffer + read);
+    return DP_msg_transform_region_new(context_id, layer, source, bx, by, bw,
+                                       bh, x1, y1, x2, y2, x3, y3, x4, y4, mode,
+                                       read_bytes, mask_size, mask_user);
+}
+
+DP_Message *DP_msg_transform_region_parse(unsigned int context_id,
+                                          DP_TextReader *reader)
+{
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    uint16_t source =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source", UINT16_MAX);
+    int32_t bx =
+        (int32_t)DP_text_reader_get_long(reader, "bx", INT32_MIN, INT32_MAX);
+    int32_t by =
+        (int32_t)DP_text_reader_get_long(reader, "by", INT32_MIN, INT32_MAX);
+    int32_t bw =
+        (int32_t)DP_text_reader_get_long(reader, "bw", INT32_MIN, INT32_MAX);
+    int32_t bh =
+        (int32_t)DP_text_reader_get_long(reader, "bh", INT32_MIN, INT32_MAX);
+    int32_t x1 =
+        (int32_t)DP_text_reader_get_long(reader, "x1", INT32_MIN, INT32_MAX);
+    int32_t y1 =
+        (int32_t)DP_text_reader_get_long(reader, "y1", INT32_MIN, INT32_MAX);
+    int32_t x2 =
+        (int32_t)DP_text_reader_get_long(reader, "x2", INT32_MIN, INT32_MAX);
+    int32_t y2 =
+        (int32_t)DP_text_reader_get_long(reader, "y2", INT32_MIN, INT32_MAX);
+    int32_t x3 =
+        (int32_t)DP_text_reader_get_long(reader, "x3", INT32_MIN, INT32_MAX);
+    int32_t y3 =
+        (int32_t)DP_text_reader_get_long(reader, "y3", INT32_MIN, INT32_MAX);
+    int32_t x4 =
+        (int32_t)DP_text_reader_get_long(reader, "x4", INT32_MIN, INT32_MAX);
+    int32_t y4 =
+        (int32_t)DP_text_reader_get_long(reader, "y4", INT32_MIN, INT32_MAX);
+    uint8_t mode = (uint8_t)DP_text_reader_get_ulong(reader, "mode", UINT8_MAX);
+    size_t mask_size;
+    DP_TextReaderParseParams mask_params =
+        DP_text_reader_get_base64_string(reader, "mask", &mask_size);
+    return DP_msg_transform_region_new(
+        context_id, layer, source, bx, by, bw, bh, x1, y1, x2, y2, x3, y3, x4,
+        y4, mode, DP_text_reader_parse_base64, mask_size, &mask_params);
+}
+
+DP_MsgTransformRegion *DP_msg_transform_region_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_TRANSFORM_REGION);
+}
+
+uint16_t DP_msg_transform_region_layer(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->layer;
+}
+
+uint16_t DP_msg_transform_region_source(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->source;
+}
+
+int32_t DP_msg_transform_region_bx(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->bx;
+}
+
+int32_t DP_msg_transform_region_by(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->by;
+}
+
+int32_t DP_msg_transform_region_bw(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->bw;
+}
+
+int32_t DP_msg_transform_region_bh(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->bh;
+}
+
+int32_t DP_msg_transform_region_x1(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->x1;
+}
+
+int32_t DP_msg_transform_region_y1(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->y1;
+}
+
+int32_t DP_msg_transform_region_x2(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->x2;
+}
+
+int32_t DP_msg_transform_region_y2(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->y2;
+}
+
+int32_t DP_msg_transform_region_x3(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->x3;
+}
+
+int32_t DP_msg_transform_region_y3(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->y3;
+}
+
+int32_t DP_msg_transform_region_x4(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->x4;
+}
+
+int32_t DP_msg_transform_region_y4(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->y4;
+}
+
+uint8_t DP_msg_transform_region_mode(const DP_MsgTransformRegion *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->mode;
+}
+
+const unsigned char *
+DP_msg_transform_region_mask(const DP_MsgTransformRegion *mtr, size_t *out_size)
+{
+    DP_ASSERT(mtr);
+    if (out_size) {
+        *out_size = mtr->mask_size;
+    }
+    return mtr->mask;
+}
+
+size_t DP_msg_transform_region_mask_size(const DP_MsgTransformRegion *mtr)
+{
+    return mtr->mask_size;
+}
+
+
+/* DP_MSG_TRACK_CREATE */
+
+struct DP_MsgTrackCreate {
+    uint16_t id;
+    uint16_t insert_id;
+    uint16_t source_id;
+    uint16_t title_len;
+    char title[];
+};
+
+static size_t msg_track_create_payload_length(DP_Message *msg)
+{
+    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
+    return ((size_t)6) + DP_uint16_to_size(mtc->title_len);
+}
+
+static size_t msg_track_create_serialize_payload(DP_Message *msg,
+                                                 unsigned char *data)
+{
+    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mtc->id, data + written);
+    written += DP_write_bigendian_uint16(mtc->insert_id, data + written);
+    written += DP_write_bigendian_uint16(mtc->source_id, data + written);
+    written += DP_write_bytes(mtc->title, 1, mtc->title_len, data + written);
+    DP_ASSERT(written == msg_track_create_payload_length(msg));
+    return written;
+}
+
+static bool msg_track_create_write_payload_text(DP_Message *msg,
+                                                DP_TextWriter *writer)
+{
+    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "id", mtc->id, true)
+        && DP_text_writer_write_uint(writer, "insert_id", mtc->insert_id, true)
+        && DP_text_writer_write_uint(writer, "source_id", mtc->source_id, true)
+        && DP_text_writer_write_string(writer, "title", mtc->title);
+}
+
+static bool msg_track_create_equals(DP_Message *DP_RESTRICT msg,
+                                    DP_Message *DP_RESTRICT other)
+{
+    DP_MsgTrackCreate *a = DP_message_internal(msg);
+    DP_MsgTrackCreate *b = DP_message_internal(other);
+    return a->id == b->id && a->insert_id == b->insert_id
+        && a->source_id == b->source_id && a->title_len == b->title_len
+        && memcmp(a->title, b->title, a->title_len) == 0;
+}
+
+static const DP_MessageMethods msg_track_create_methods = {
+    msg_track_create_payload_length,
+    msg_track_create_serialize_payload,
+    msg_track_create_write_payload_text,
+    msg_track_create_equals,
+};
+
+DP_Message *DP_msg_track_create_new(unsigned int context_id, uint16_t id,
+                                    uint16_t insert_id, uint16_t source_id,
+                                    const char *title_value, size_t title_len)
+{
+    DP_Message *msg = DP_message_new(
+        DP_MSG_TRACK_CREATE, context_id, &msg_track_create_methods,
+        DP_FLEX_SIZEOF(DP_MsgTrackCreate, title, title_len + 1));
+    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
+    mtc->id = id;
+    mtc->insert_id = insert_id;
+    mtc->source_id = source_id;
+    mtc->title_len = DP_size_to_uint16(title_len);
+    assign_string(mtc->title, title_value, mtc->title_len);
+    return msg;
+}
+
+DP_Message *DP_msg_track_create_deserialize(unsigned int context_id,
+                                            const unsigned char *buffer,
+                                            size_t length)
+{
+    if (length < 6 || length > 65535) {
+        DP_error_set("Wrong length for trackcreate message; "
+                     "expected between 6 and 65535, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t id = read_uint16(buffer + read, &read);
+    uint16_t insert_id = read_uint16(buffer + read, &read);
+    uint16_t source_id = read_uint16(buffer + read, &read);
+    size_t title_bytes = length - read;
+    uint16_t title_len = DP_size_to_uint16(title_bytes);
+    const char *title = (const char *)buffer + read;
+    return DP_msg_track_create_new(context_id, id, insert_id, source_id, title,
+                                   title_len);
+}
+
+DP_Message *DP_msg_track_create_parse(unsigned int context_id,
+                                      DP_TextReader *reader)
+{
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    uint16_t insert_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "insert_id", UINT16_MAX);
+    uint16_t source_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source_id", UINT16_MAX);
+    uint16_t title_len;
+    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
+    return DP_msg_track_create_new(context_id, id, insert_id, source_id, title,
+                                   title_len);
+}
+
+DP_MsgTrackCreate *DP_msg_track_create_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_TRACK_CREATE);
+}
+
+uint16_t DP_msg_track_create_id(const DP_MsgTrackCreate *mtc)
+{
+    DP_ASSERT(mtc);
+    return mtc->id;
+}
+
+uint16_t DP_msg_track_create_insert_id(const DP_MsgTrackCreate *mtc)
+{
+    DP_ASSERT(mtc);
+    return mtc->insert_id;
+}
+
+uint16_t DP_msg_track_create_source_id(const DP_MsgTrackCreate *mtc)
+{
+    DP_ASSERT(mtc);
+    return mtc->source_id;
+}
+
+const char *DP_msg_track_create_title(const DP_MsgTrackCreate *mtc,
+                                      size_t *out_len)
+{
+    DP_ASSERT(mtc);
+    if (out_len) {
+        *out_len = mtc->title_len;
+    }
+    return mtc->title;
+}
+
+size_t DP_msg_track_create_title_len(const DP_MsgTrackCreate *mtc)
+{
+    return mtc->title_len;
+}
+
+
+/* DP_MSG_TRACK_RETITLE */
+
+struct DP_MsgTrackRetitle {
+    uint16_t id;
+    uint16_t title_len;
+    char title[];
+};
+
+static size_t msg_track_retitle_payload_length(DP_Message *msg)
+{
+    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
+    return ((size_t)2) + DP_uint16_to_size(mtr->title_len);
+}
+
+static size_t msg_track_retitle_serialize_payload(DP_Message *msg,
+                                                  unsigned char *data)
+{
+    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mtr->id, data + written);
+    written += DP_write_bytes(mtr->title, 1, mtr->title_len, data + written);
+    DP_ASSERT(written == msg_track_retitle_payload_length(msg));
+    return written;
+}
+
+static bool msg_track_retitle_write_payload_text(DP_Message *msg,
+                                                 DP_TextWriter *writer)
+{
+    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "id", mtr->id, true)
+        && DP_text_writer_write_string(writer, "title", mtr->title);
+}
+
+static bool msg_track_retitle_equals(DP_Message *DP_RESTRICT msg,
+                                     DP_Message *DP_RESTRICT other)
+{
+    DP_MsgTrackRetitle *a = DP_message_internal(msg);
+    DP_MsgTrackRetitle *b = DP_message_internal(other);
+    return a->id == b->id && a->title_len == b->title_len
+        && memcmp(a->title, b->title, a->title_len) == 0;
+}
+
+static const DP_MessageMethods msg_track_retitle_methods = {
+    msg_track_retitle_payload_length,
+    msg_track_retitle_serialize_payload,
+    msg_track_retitle_write_payload_text,
+    msg_track_retitle_equals,
+};
+
+DP_Message *DP_msg_track_retitle_new(unsigned int context_id, uint16_t id,
+                                     const char *title_value, size_t title_len)
+{
+    DP_Message *msg = DP_message_new(
+        DP_MSG_TRACK_RETITLE, context_id, &msg_track_retitle_methods,
+        DP_FLEX_SIZEOF(DP_MsgTrackRetitle, title, title_len + 1));
+    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
+    mtr->id = id;
+    mtr->title_len = DP_size_to_uint16(title_len);
+    assign_string(mtr->title, title_value, mtr->title_len);
+    return msg;
+}
+
+DP_Message *DP_msg_track_retitle_deserialize(unsigned int context_id,
+                                             const unsigned char *buffer,
+                                             size_t length)
+{
+    if (length < 2 || length > 65535) {
+        DP_error_set("Wrong length for trackretitle message; "
+                     "expected between 2 and 65535, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t id = read_uint16(buffer + read, &read);
+    size_t title_bytes = length - read;
+    uint16_t title_len = DP_size_to_uint16(title_bytes);
+    const char *title = (const char *)buffer + read;
+    return DP_msg_track_retitle_new(context_id, id, title, title_len);
+}
+
+DP_Message *DP_msg_track_retitle_parse(unsigned int context_id,
+                                       DP_TextReader *reader)
+{
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    uint16_t title_len;
+    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
+    return DP_msg_track_retitle_new(context_id, id, title, title_len);
+}
+
+DP_MsgTrackRetitle *DP_msg_track_retitle_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_TRACK_RETITLE);
+}
+
+uint16_t DP_msg_track_retitle_id(const DP_MsgTrackRetitle *mtr)
+{
+    DP_ASSERT(mtr);
+    return mtr->id;
+}
+
+const char *DP_msg_track_retitle_title(const DP_MsgTrackRetitle *mtr,
+                                       size_t *out_len)
+{
+    DP_ASSERT(mtr);
+    if (out_len) {
+        *out_len = mtr->title_len;
+    }
+    return mtr->title;
+}
+
+size_t DP_msg_track_retitle_title_len(const DP_MsgTrackRetitle *mtr)
+{
+    return mtr->title_len;
+}
+
+
+/* DP_MSG_TRACK_DELETE */
+
+struct DP_MsgTrackDelete {
+    uint16_t id;
+};
+
+static size_t msg_track_delete_payload_length(DP_UNUSED DP_Message *msg)
+{
+    return ((size_t)2);
+}
+
+static size_t msg_track_delete_serialize_payload(DP_Message *msg,
+                                                 unsigned char *data)
+{
+    DP_MsgTrackDelete *mtd = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mtd->id, data + written);
+    DP_ASSERT(written == msg_track_delete_payload_length(msg));
+    return written;
+}
+
+static bool msg_track_delete_write_payload_text(DP_Message *msg,
+                                                DP_TextWriter *writer)
+{
+    DP_MsgTrackDelete *mtd = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "id", mtd->id, true);
+}
+
+static bool msg_track_delete_equals(DP_Message *DP_RESTRICT msg,
+                                    DP_Message *DP_RESTRICT other)
+{
+    DP_MsgTrackDelete *a = DP_message_internal(msg);
+    DP_MsgTrackDelete *b = DP_message_internal(other);
+    return a->id == b->id;
+}
+
+static const DP_MessageMethods msg_track_delete_methods = {
+    msg_track_delete_payload_length,
+    msg_track_delete_serialize_payload,
+    msg_track_delete_write_payload_text,
+    msg_track_delete_equals,
+};
+
+DP_Message *DP_msg_track_delete_new(unsigned int context_id, uint16_t id)
+{
+    DP_Message *msg =
+        DP_message_new(DP_MSG_TRACK_DELETE, context_id,
+                       &msg_track_delete_methods, sizeof(DP_MsgTrackDelete));
+    DP_MsgTrackDelete *mtd = DP_message_internal(msg);
+    mtd->id = id;
+    return msg;
+}
+
+DP_Message *DP_msg_track_delete_deserialize(unsigned int context_id,
+                                            const unsigned char *buffer,
+                                            size_t length)
+{
+    if (length != 2) {
+        DP_error_set("Wrong length for trackdelete message; "
+                     "expected 2, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t id = read_uint16(buffer + read, &read);
+    return DP_msg_track_delete_new(context_id, id);
+}
+
+DP_Message *DP_msg_track_delete_parse(unsigned int context_id,
+                                      DP_TextReader *reader)
+{
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    return DP_msg_track_delete_new(context_id, id);
+}
+
+DP_MsgTrackDelete *DP_msg_track_delete_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_TRACK_DELETE);
+}
+
+uint16_t DP_msg_track_delete_id(const DP_MsgTrackDelete *mtd)
+{
+    DP_ASSERT(mtd);
+    return mtd->id;
+}
+
+
+/* DP_MSG_TRACK_ORDER */
+
+struct DP_MsgTrackOrder {
+    uint16_t tracks_count;
+    uint16_t tracks[];
+};
+
+static size_t msg_track_order_payload_length(DP_Message *msg)
+{
+    DP_MsgTrackOrder *mto = DP_message_internal(msg);
+    return DP_int_to_size(mto->tracks_count) * 2;
+}
+
+static size_t msg_track_order_serialize_payload(DP_Message *msg,
+                                                unsigned char *data)
+{
+    DP_MsgTrackOrder *mto = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16_array(mto->tracks, mto->tracks_count,
+                                               data + written);
+    DP_ASSERT(written == msg_track_order_payload_length(msg));
+    return written;
+}
+
+static bool msg_track_order_write_payload_text(DP_Message *msg,
+                                               DP_TextWriter *writer)
+{
+    DP_MsgTrackOrder *mto = DP_message_internal(msg);
+    return DP_text_writer_write_uint16_list(writer, "tracks", mto->tracks,
+                                            mto->tracks_count, true);
+}
+
+static bool msg_track_order_equals(DP_Message *DP_RESTRICT msg,
+                                   DP_Message *DP_RESTRICT other)
+{
+    DP_MsgTrackOrder *a = DP_message_internal(msg);
+    DP_MsgTrackOrder *b = DP_message_internal(other);
+    return a->tracks_count == b->tracks_count
+        && memcmp(a->tracks, b->tracks, DP_uint16_to_size(a->tracks_count) * 2)
+               == 0;
+}
+
+static const DP_MessageMethods msg_track_order_methods = {
+    msg_track_order_payload_length,
+    msg_track_order_serialize_payload,
+    msg_track_order_write_payload_text,
+    msg_track_order_equals,
+};
+
+DP_Message *DP_msg_track_order_new(unsigned int context_id,
+                                   void (*set_tracks)(int, uint16_t *, void *),
+                                   int tracks_count, void *tracks_user)
+{
+    DP_Message *msg =
+        DP_message_new(DP_MSG_TRACK_ORDER, context_id, &msg_track_order_methods,
+                       DP_FLEX_SIZEOF(DP_MsgTrackOrder, tracks,
+                                      DP_int_to_size(tracks_count) * 2));
+    DP_MsgTrackOrder *mto = DP_message_internal(msg);
+    mto->tracks_count = DP_int_to_uint16(tracks_count);
+    if (set_tracks) {
+        set_tracks(mto->tracks_count, mto->tracks, tracks_user);
+    }
+    return msg;
+}
+
+DP_Message *DP_msg_track_order_deserialize(unsigned int context_id,
+                                           const unsigned char *buffer,
+                                           size_t length)
+{
+    if (length > 65535) {
+        DP_error_set("Wrong length for trackorder message; "
+                     "expected between 0 and 65535, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    size_t tracks_bytes = length - read;
+    if ((tracks_bytes % 2) != 0) {
+        DP_error_set("Wrong length for tracks field in trackorder message; "
+                     "%zu not divisible by 2",
+                     tracks_bytes);
+        return NULL;
+    }
+    uint16_t tracks_count = DP_size_to_uint16(tracks_bytes / 2);
+    void *tracks_user = (void *)(buffer + read);
+    return DP_msg_track_order_new(context_id, read_uint16_array, tracks_count,
+                                  tracks_user);
+}
+
+DP_Message *DP_msg_track_order_parse(unsigned int context_id,
+                                     DP_TextReader *reader)
+{
+    int tracks_count;
+    DP_TextReaderParseParams tracks_params =
+        DP_text_reader_get_comma_separated(reader, "tracks", &tracks_count);
+    return DP_msg_track_order_new(context_id,
+                                  DP_text_reader_parse_uint16_array_hex,
+                                  tracks_count, &tracks_params);
+}
+
+DP_MsgTrackOrder *DP_msg_track_order_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_TRACK_ORDER);
+}
+
+const uint16_t *DP_msg_track_order_tracks(const DP_MsgTrackOrder *mto,
+                                          int *out_count)
+{
+    DP_ASSERT(mto);
+    if (out_count) {
+        *out_count = mto->tracks_count;
+    }
+    return mto->tracks;
+}
+
+int DP_msg_track_order_tracks_count(const DP_MsgTrackOrder *mto)
+{
+    return mto->tracks_count;
+}
+
+
+/* DP_MSG_KEY_FRAME_SET */
+
+const char *DP_msg_key_frame_set_source_variant_name(unsigned int value)
+{
+    switch (value) {
+    case DP_MSG_KEY_FRAME_SET_SOURCE_LAYER:
+        return "Layer";
+    case DP_MSG_KEY_FRAME_SET_SOURCE_KEY_FRAME:
+        return "KeyFrame";
+    default:
+        return NULL;
+    }
+}
+
+struct DP_MsgKeyFrameSet {
+    uint16_t track_id;
+    uint16_t frame_index;
+    uint16_t source_id;
+    uint16_t source_index;
+    uint8_t source;
+};
+
+static size_t msg_key_frame_set_payload_length(DP_UNUSED DP_Message *msg)
+{
+    return ((size_t)9);
+}
+
+static size_t msg_key_frame_set_serialize_payload(DP_Message *msg,
+                                                  unsigned char *data)
+{
+    DP_MsgKeyFrameSet *mkfs = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mkfs->track_id, data + written);
+    written += DP_write_bigendian_uint16(mkfs->frame_index, data + written);
+    written += DP_write_bigendian_uint16(mkfs->source_id, data + written);
+    written += DP_write_bigendian_uint16(mkfs->source_index, data + written);
+    written += DP_write_bigendian_uint8(mkfs->source, data + written);
+    DP_ASSERT(written == msg_key_frame_set_payload_length(msg));
+    return written;
+}
+
+static bool msg_key_frame_set_write_payload_text(DP_Message *msg,
+                                                 DP_TextWriter *writer)
+{
+    DP_MsgKeyFrameSet *mkfs = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "frame_index", mkfs->frame_index,
+                                     false)
+        && DP_text_writer_write_uint(writer, "source", mkfs->source, false)
+        && DP_text_writer_write_uint(writer, "source_id", mkfs->source_id, true)
+        && DP_text_writer_write_uint(writer, "source_index", mkfs->source_index,
+                                     false)
+        && DP_text_writer_write_uint(writer, "track_id", mkfs->track_id, true);
+}
+
+static bool msg_key_frame_set_equals(DP_Message *DP_RESTRICT msg,
+                                     DP_Message *DP_RESTRICT other)
+{
+    DP_MsgKeyFrameSet *a = DP_message_internal(msg);
+    DP_MsgKeyFrameSet *b = DP_message_internal(other);
+    return a->track_id == b->track_id && a->frame_index == b->frame_index
+        && a->source_id == b->source_id && a->source_index == b->source_index
+        && a->source == b->source;
+}
+
+static const DP_MessageMethods msg_key_frame_set_methods = {
+    msg_key_frame_set_payload_length,
+    msg_key_frame_set_serialize_payload,
+    msg_key_frame_set_write_payload_text,
+    msg_key_frame_set_equals,
+};
+
+DP_Message *DP_msg_key_frame_set_new(unsigned int context_id, uint16_t track_id,
+                                     uint16_t frame_index, uint16_t source_id,
+                                     uint16_t source_index, uint8_t source)
+{
+    DP_Message *msg =
+        DP_message_new(DP_MSG_KEY_FRAME_SET, context_id,
+                       &msg_key_frame_set_methods, sizeof(DP_MsgKeyFrameSet));
+    DP_MsgKeyFrameSet *mkfs = DP_message_internal(msg);
+    mkfs->track_id = track_id;
+    mkfs->frame_index = frame_index;
+    mkfs->source_id = source_id;
+    mkfs->source_index = source_index;
+    mkfs->source = source;
+    return msg;
+}
+
+DP_Message *DP_msg_key_frame_set_deserialize(unsigned int context_id,
+                                             const unsigned char *buffer,
+                                             size_t length)
+{
+    if (length != 9) {
+        DP_error_set("Wrong length for keyframeset message; "
+                     "expected 9, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t track_id = read_uint16(buffer + read, &read);
+    uint16_t frame_index = read_uint16(buffer + read, &read);
+    uint16_t source_id = read_uint16(buffer + read, &read);
+    uint16_t source_index = read_uint16(buffer + read, &read);
+    uint8_t source = read_uint8(buffer + read, &read);
+    return DP_msg_key_frame_set_new(context_id, track_id, frame_index,
+                                    source_id, source_index, source);
+}
+
+DP_Message *DP_msg_key_frame_set_parse(unsigned int context_id,
+                                       DP_TextReader *reader)
+{
+    uint16_t track_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
+    uint16_t frame_index =
+        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
+    uint16_t source_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source_id", UINT16_MAX);
+    uint16_t source_index =
+        (uint16_t)DP_text_reader_get_ulong(reader, "source_index", UINT16_MAX);
+    uint8_t source =
+        (uint8_t)DP_text_reader_get_ulong(reader, "source", UINT8_MAX);
+    return DP_msg_key_frame_set_new(context_id, track_id, frame_index,
+                                    source_id, source_index, source);
+}
+
+DP_MsgKeyFrameSet *DP_msg_key_frame_set_cast(DP_Message *msg)
+{
+    return DP_message_cast(msg, DP_MSG_KEY_FRAME_SET);
+}
+
+uint16_t DP_msg_key_frame_set_track_id(const DP_MsgKeyFrameSet *mkfs)
+{
+    DP_ASSERT(mkfs);
+    return mkfs->track_id;
+}
+
+uint16_t DP_msg_key_frame_set_frame_index(const DP_MsgKeyFrameSet *mkfs)
+{
+    DP_ASSERT(mkfs);
+    return mkfs->frame_index;
+}
+
+uint16_t DP_msg_key_frame_set_source_id(const DP_MsgKeyFrameSet *mkfs)
+{
+    DP_ASSERT(mkfs);
+    return mkfs->source_id;
+}
+
+uint16_t DP_msg_key_frame_set_source_index(const DP_MsgKeyFrameSet *mkfs)
+{
+    DP_ASSERT(mkfs);
+    return mkfs->source_index;
+}
+
+uint8_t DP_msg_key_frame_set_source(const DP_MsgKeyFrameSet *mkfs)
+{
+    DP_ASSERT(mkfs);
+    return mkfs->source;
+}
+
+
+/* DP_MSG_KEY_FRAME_RETITLE */
+
+struct DP_MsgKeyFrameRetitle {
+    uint16_t track_id;
+    uint16_t frame_index;
+    uint16_t title_len;
+    char title[];
+};
+
+static size_t msg_key_frame_retitle_payload_length(DP_Message *msg)
+{
+    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
+    return ((size_t)4) + DP_uint16_to_size(mkfr->title_len);
+}
+
+static size_t msg_key_frame_retitle_serialize_payload(DP_Message *msg,
+                                                      unsigned char *data)
+{
+    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mkfr->track_id, data + written);
+    written += DP_write_bigendian_uint16(mkfr->frame_index, data + written);
+    written += DP_write_bytes(mkfr->title, 1, mkfr->title_len, data + written);
+    DP_ASSERT(written == msg_key_frame_retitle_payload_length(msg));
+    return written;
+}
+
+static bool msg_key_frame_retitle_write_payload_text(DP_Message *msg,
+                                                     DP_TextWriter *writer)
+{
+    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "frame_index", mkfr->frame_index,
+                                     false)
+        && DP_text_writer_write_string(writer, "title", mkfr->title)
+        && DP_text_writer_write_uint(writer, "track_id", mkfr->track_id, true);
+}
+
+static bool msg_key_frame_retitle_equals(DP_Message *DP_RESTRICT msg,
+                                         DP_Message *DP_RESTRICT other)
+{
+    DP_MsgKeyFrameRetitle *a = DP_message_internal(msg);
+    DP_MsgKeyFrameRetitle *b = DP_message_internal(other);
+    return a->track_id == b->track_id && a->frame_index == b->frame_index
+        && a->title_len == b->title_len
+        && memcmp(a->title, b->title, a->title_len) == 0;
+}
+
+static const DP_MessageMethods msg_key_frame_retitle_methods = {
+    msg_key_frame_retitle_payload_length,
+    msg_key_frame_retitle_serialize_payload,
+    msg_key_frame_retitle_write_payload_text,
+    msg_key_frame_retitle_equals,
+};
+
+DP_Message *DP_msg_key_frame_retitle_new(unsigned int context_id,
+                                         uint16_t track_id,
+                                         uint16_t frame_index,
+                                         const char *title_value,
+                                         size_t title_len)
+{
+    DP_Message *msg = DP_message_new(
+        DP_MSG_KEY_FRAME_RETITLE, context_id, &msg_key_frame_retitle_methods,
+        DP_FLEX_SIZEOF(DP_MsgKeyFrameRetitle, title, title_len + 1));
+    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
+    mkfr->track_id = track_id;
+    mkfr->frame_index = frame_index;
+    mkfr->title_len = DP_size_to_uint16(title_len);
+    assign_string(mkfr->title, title_value, mkfr->title_len);
     return msg;
 }
 
-DP_Message *DP_msg_transform_region_deserialize(unsigned int context_id,
-                                                const unsigned char *buffer,
-                                                size_t length)
+DP_Message *DP_msg_key_frame_retitle_deserialize(unsigned int context_id,
+                                                 const unsigned char *buffer,
+                                                 size_t length)
 {
-    if (length < 53 || length > 65535) {
-        DP_error_set("Wrong length for transformregion message; "
-                     "expected between 53 and 65535, got %zu",
+    if (length < 4 || length > 65535) {
+        DP_error_set("Wrong length for keyframeretitle message; "
+                     "expected between 4 and 65535, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t layer = read_uint16(buffer + read, &read);
-    uint16_t source = read_uint16(buffer + read, &read);
-    int32_t bx = read_int32(buffer + read, &read);
-    int32_t by = read_int32(buffer + read, &read);
-    int32_t bw = read_int32(buffer + read, &read);
-    int32_t bh = read_int32(buffer + read, &read);
-    int32_t x1 = read_int32(buffer + read, &read);
-    int32_t y1 = read_int32(buffer + read, &read);
-    int32_t x2 = read_int32(buffer + read, &read);
-    int32_t y2 = read_int32(buffer + read, &read);
-    int32_t x3 = read_int32(buffer + read, &read);
-    int32_t y3 = read_int32(buffer + read, &read);
-    int32_t x4 = read_int32(buffer + read, &read);
-    int32_t y4 = read_int32(buffer + read, &read);
-    uint8_t mode = read_uint8(buffer + read, &read);
-    size_t mask_bytes = length - read;
-    uint16_t mask_size = DP_size_to_uint16(mask_bytes);
-    void *mask_user = (void *)(buffer + read);
-    return DP_msg_transform_region_new(context_id, layer, source, bx, by, bw,
-                                       bh, x1, y1, x2, y2, x3, y3, x4, y4, mode,
-                                       read_bytes, mask_size, mask_user);
+    uint16_t track_id = read_uint16(buffer + read, &read);
+    uint16_t frame_index = read_uint16(buffer + read, &read);
+    size_t title_bytes = length - read;
+    uint16_t title_len = DP_size_to_uint16(title_bytes);
+    const char *title = (const char *)buffer + read;
+    return DP_msg_key_frame_retitle_new(context_id, track_id, frame_index,
+                                        title, title_len);
 }
 
-DP_Message *DP_msg_transform_region_parse(unsigned int context_id,
-                                          DP_TextReader *reader)
+DP_Message *DP_msg_key_frame_retitle_parse(unsigned int context_id,
+                                           DP_TextReader *reader)
 {
-    uint16_t layer =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
-    uint16_t source =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source", UINT16_MAX);
-    int32_t bx =
-        (int32_t)DP_text_reader_get_long(reader, "bx", INT32_MIN, INT32_MAX);
-    int32_t by =
-        (int32_t)DP_text_reader_get_long(reader, "by", INT32_MIN, INT32_MAX);
-    int32_t bw =
-        (int32_t)DP_text_reader_get_long(reader, "bw", INT32_MIN, INT32_MAX);
-    int32_t bh =
-        (int32_t)DP_text_reader_get_long(reader, "bh", INT32_MIN, INT32_MAX);
-    int32_t x1 =
-        (int32_t)DP_text_reader_get_long(reader, "x1", INT32_MIN, INT32_MAX);
-    int32_t y1 =
-        (int32_t)DP_text_reader_get_long(reader, "y1", INT32_MIN, INT32_MAX);
-    int32_t x2 =
-        (int32_t)DP_text_reader_get_long(reader, "x2", INT32_MIN, INT32_MAX);
-    int32_t y2 =
-        (int32_t)DP_text_reader_get_long(reader, "y2", INT32_MIN, INT32_MAX);
-    int32_t x3 =
-        (int32_t)DP_text_reader_get_long(reader, "x3", INT32_MIN, INT32_MAX);
-    int32_t y3 =
-        (int32_t)DP_text_reader_get_long(reader, "y3", INT32_MIN, INT32_MAX);
-    int32_t x4 =
-        (int32_t)DP_text_reader_get_long(reader, "x4", INT32_MIN, INT32_MAX);
-    int32_t y4 =
-        (int32_t)DP_text_reader_get_long(reader, "y4", INT32_MIN, INT32_MAX);
-    uint8_t mode = (uint8_t)DP_text_reader_get_ulong(reader, "mode", UINT8_MAX);
-    size_t mask_size;
-    DP_TextReaderParseParams mask_params =
-        DP_text_reader_get_base64_string(reader, "mask", &mask_size);
-    return DP_msg_transform_region_new(
-        context_id, layer, source, bx, by, bw, bh, x1, y1, x2, y2, x3, y3, x4,
-        y4, mode, DP_text_reader_parse_base64, mask_size, &mask_params);
+    uint16_t track_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
+    uint16_t frame_index =
+        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
+    uint16_t title_len;
+    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
+    return DP_msg_key_frame_retitle_new(context_id, track_id, frame_index,
+                                        title, title_len);
 }
 
-DP_MsgTransformRegion *DP_msg_transform_region_cast(DP_Message *msg)
+DP_MsgKeyFrameRetitle *DP_msg_key_frame_retitle_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_TRANSFORM_REGION);
+    return DP_message_cast(msg, DP_MSG_KEY_FRAME_RETITLE);
 }
 
-uint16_t DP_msg_transform_region_layer(const DP_MsgTransformRegion *mtr)
+uint16_t DP_msg_key_frame_retitle_track_id(const DP_MsgKeyFrameRetitle *mkfr)
 {
-    DP_ASSERT(mtr);
-    return mtr->layer;
+    DP_ASSERT(mkfr);
+    return mkfr->track_id;
 }
 
-uint16_t DP_msg_transform_region_source(const DP_MsgTransformRegion *mtr)
+uint16_t DP_msg_key_frame_retitle_frame_index(const DP_MsgKeyFrameRetitle *mkfr)
 {
-    DP_ASSERT(mtr);
-    return mtr->source;
+    DP_ASSERT(mkfr);
+    return mkfr->frame_index;
 }
 
-int32_t DP_msg_transform_region_bx(const DP_MsgTransformRegion *mtr)
+const char *DP_msg_key_frame_retitle_title(const DP_MsgKeyFrameRetitle *mkfr,
+                                           size_t *out_len)
 {
-    DP_ASSERT(mtr);
-    return mtr->bx;
+    DP_ASSERT(mkfr);
+    if (out_len) {
+        *out_len = mkfr->title_len;
+    }
+    return mkfr->title;
 }
 
-int32_t DP_msg_transform_region_by(const DP_MsgTransformRegion *mtr)
+size_t DP_msg_key_frame_retitle_title_len(const DP_MsgKeyFrameRetitle *mkfr)
 {
-    DP_ASSERT(mtr);
-    return mtr->by;
+    return mkfr->title_len;
 }
 
-int32_t DP_msg_transform_region_bw(const DP_MsgTransformRegion *mtr)
-{
-    DP_ASSERT(mtr);
-    return mtr->bw;
-}
 
-int32_t DP_msg_transform_region_bh(const DP_MsgTransformRegion *mtr)
+/* DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES */
+
+struct DP_MsgKeyFrameLayerAttributes {
+    uint16_t track_id;
+    uint16_t frame_index;
+    uint16_t layers_count;
+    uint16_t layers[];
+};
+
+static size_t msg_key_frame_layer_attributes_payload_length(DP_Message *msg)
 {
-    DP_ASSERT(mtr);
-    return mtr->bh;
+    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
+    return ((size_t)4) + DP_int_to_size(mkfla->layers_count) * 2;
 }
 
-int32_t DP_msg_transform_region_x1(const DP_MsgTransformRegion *mtr)
+static size_t
+msg_key_frame_layer_attributes_serialize_payload(DP_Message *msg,
+                                                 unsigned char *data)
 {
-    DP_ASSERT(mtr);
-    return mtr->x1;
+    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
+    size_t written = 0;
+    written += DP_write_bigendian_uint16(mkfla->track_id, data + written);
+    written += DP_write_bigendian_uint16(mkfla->frame_index, data + written);
+    written += DP_write_bigendian_uint16_array(
+        mkfla->layers, mkfla->layers_count, data + written);
+    DP_ASSERT(written == msg_key_frame_layer_attributes_payload_length(msg));
+    return written;
 }
 
-int32_t DP_msg_transform_region_y1(const DP_MsgTransformRegion *mtr)
+static bool
+msg_key_frame_layer_attributes_write_payload_text(DP_Message *msg,
+                                                  DP_TextWriter *writer)
 {
-    DP_ASSERT(mtr);
-    return mtr->y1;
+    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "frame_index", mkfla->frame_index,
+                                     false)
+        && DP_text_writer_write_uint16_list(writer, "layers", mkfla->layers,
+                                            mkfla->layers_count, true)
+        && DP_text_writer_write_uint(writer, "track_id", mkfla->track_id, true);
 }
 
-int32_t DP_msg_transform_region_x2(const DP_MsgTransformRegion *mtr)
+static bool msg_key_frame_layer_attributes_equals(DP_Message *DP_RESTRICT msg,
+                                                  DP_Message *DP_RESTRICT other)
 {
-    DP_ASSERT(mtr);
-    return mtr->x2;
+    DP_MsgKeyFrameLayerAttributes *a = DP_message_internal(msg);
+    DP_MsgKeyFrameLayerAttributes *b = DP_message_internal(other);
+    return a->track_id == b->track_id && a->frame_index == b->frame_index
+        && a->layers_count == b->layers_count
+        && memcmp(a->layers, b->layers, DP_uint16_to_size(a->layers_count) * 2)
+               == 0;
 }
 
-int32_t DP_msg_transform_region_y2(const DP_MsgTransformRegion *mtr)
+static const DP_MessageMethods msg_key_frame_layer_attributes_methods = {
+    msg_key_frame_layer_attributes_payload_length,
+    msg_key_frame_layer_attributes_serialize_payload,
+    msg_key_frame_layer_attributes_write_payload_text,
+    msg_key_frame_layer_attributes_equals,
+};
+
+DP_Message *DP_msg_key_frame_layer_attributes_new(
+    unsigned int context_id, uint16_t track_id, uint16_t frame_index,
+    void (*set_layers)(int, uint16_t *, void *), int layers_count,
+    void *layers_user)
 {
-    DP_ASSERT(mtr);
-    return mtr->y2;
+    DP_Message *msg =
+        DP_message_new(DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES, context_id,
+                       &msg_key_frame_layer_attributes_methods,
+                       DP_FLEX_SIZEOF(DP_MsgKeyFrameLayerAttributes, layers,
+                                      DP_int_to_size(layers_count) * 2));
+    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
+    mkfla->track_id = track_id;
+    mkfla->frame_index = frame_index;
+    mkfla->layers_count = DP_int_to_uint16(layers_count);
+    if (set_layers) {
+        set_layers(mkfla->layers_count, mkfla->layers, layers_user);
+    }
+    return msg;
 }
 
-int32_t DP_msg_transform_region_x3(const DP_MsgTransformRegion *mtr)
+DP_Message *DP_msg_key_frame_layer_attributes_deserialize(
+    unsigned int context_id, const unsigned char *buffer, size_t length)
 {
-    DP_ASSERT(mtr);
-    return mtr->x3;
+    if (length < 4 || length > 65535) {
+        DP_error_set("Wrong length for keyframelayerattributes message; "
+                     "expected between 4 and 65535, got %zu",
+                     length);
+        return NULL;
+    }
+    size_t read = 0;
+    uint16_t track_id = read_uint16(buffer + read, &read);
+    uint16_t frame_index = read_uint16(buffer + read, &read);
+    size_t layers_bytes = length - read;
+    if ((layers_bytes % 2) != 0) {
+        DP_error_set(
+            "Wrong length for layers field in keyframelayerattributes message; "
+            "%zu not divisible by 2",
+            layers_bytes);
+        return NULL;
+    }
+    uint16_t layers_count = DP_size_to_uint16(layers_bytes / 2);
+    void *layers_user = (void *)(buffer + read);
+    return DP_msg_key_frame_layer_attributes_new(context_id, track_id,
+                                                 frame_index, read_uint16_array,
+                                                 layers_count, layers_user);
 }
 
-int32_t DP_msg_transform_region_y3(const DP_MsgTransformRegion *mtr)
+DP_Message *DP_msg_key_frame_layer_attributes_parse(unsigned int context_id,
+                                                    DP_TextReader *reader)
 {
-    DP_ASSERT(mtr);
-    return mtr->y3;
+    uint16_t track_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
+    uint16_t frame_index =
+        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
+    int layers_count;
+    DP_TextReaderParseParams layers_params =
+        DP_text_reader_get_comma_separated(reader, "layers", &layers_count);
+    return DP_msg_key_frame_layer_attributes_new(
+        context_id, track_id, frame_index,
+        DP_text_reader_parse_uint16_array_hex, layers_count, &layers_params);
 }
 
-int32_t DP_msg_transform_region_x4(const DP_MsgTransformRegion *mtr)
+DP_MsgKeyFrameLayerAttributes *
+DP_msg_key_frame_layer_attributes_cast(DP_Message *msg)
 {
-    DP_ASSERT(mtr);
-    return mtr->x4;
+    return DP_message_cast(msg, DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES);
 }
 
-int32_t DP_msg_transform_region_y4(const DP_MsgTransformRegion *mtr)
+uint16_t DP_msg_key_frame_layer_attributes_track_id(
+    const DP_MsgKeyFrameLayerAttributes *mkfla)
 {
-    DP_ASSERT(mtr);
-    return mtr->y4;
+    DP_ASSERT(mkfla);
+    return mkfla->track_id;
 }
 
-uint8_t DP_msg_transform_region_mode(const DP_MsgTransformRegion *mtr)
+uint16_t DP_msg_key_frame_layer_attributes_frame_index(
+    const DP_MsgKeyFrameLayerAttributes *mkfla)
 {
-    DP_ASSERT(mtr);
-    return mtr->mode;
+    DP_ASSERT(mkfla);
+    return mkfla->frame_index;
 }
 
-const unsigned char *
-DP_msg_transform_region_mask(const DP_MsgTransformRegion *mtr, size_t *out_size)
+const uint16_t *DP_msg_key_frame_layer_attributes_layers(
+    const DP_MsgKeyFrameLayerAttributes *mkfla, int *out_count)
 {
-    DP_ASSERT(mtr);
-    if (out_size) {
-        *out_size = mtr->mask_size;
+    DP_ASSERT(mkfla);
+    if (out_count) {
+        *out_count = mkfla->layers_count;
     }
-    return mtr->mask;
+    return mkfla->layers;
 }
 
-size_t DP_msg_transform_region_mask_size(const DP_MsgTransformRegion *mtr)
+int DP_msg_key_frame_layer_attributes_layers_count(
+    const DP_MsgKeyFrameLayerAttributes *mkfla)
 {
-    return mtr->mask_size;
+    return mkfla->layers_count;
 }
 
 
-/* DP_MSG_TRACK_CREATE */
+/* DP_MSG_KEY_FRAME_DELETE */
 
-struct DP_MsgTrackCreate {
-    uint16_t id;
-    uint16_t insert_id;
-    uint16_t source_id;
-    uint16_t title_len;
-    char title[];
+struct DP_MsgKeyFrameDelete {
+    uint16_t track_id;
+    uint16_t frame_index;
+    uint16_t move_track_id;
+    uint16_t move_frame_index;
 };
 
-static size_t msg_track_create_payload_length(DP_Message *msg)
+static size_t msg_key_frame_delete_payload_length(DP_UNUSED DP_Message *msg)
 {
-    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
-    return ((size_t)6) + DP_uint16_to_size(mtc->title_len);
+    return ((size_t)8);
 }
 
-static size_t msg_track_create_serialize_payload(DP_Message *msg,
-                                                 unsigned char *data)
+static size_t msg_key_frame_delete_serialize_payload(DP_Message *msg,
+                                                     unsigned char *data)
 {
-    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
+    DP_MsgKeyFrameDelete *mkfd = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mtc->id, data + written);
-    written += DP_write_bigendian_uint16(mtc->insert_id, data + written);
-    written += DP_write_bigendian_uint16(mtc->source_id, data + written);
-    written += DP_write_bytes(mtc->title, 1, mtc->title_len, data + written);
-    DP_ASSERT(written == msg_track_create_payload_length(msg));
+    written += DP_write_bigendian_uint16(mkfd->track_id, data + written);
+    written += DP_write_bigendian_uint16(mkfd->frame_index, data + written);
+    written += DP_write_bigendian_uint16(mkfd->move_track_id, data + written);
+    written +=
+        DP_write_bigendian_uint16(mkfd->move_frame_index, data + written);
+    DP_ASSERT(written == msg_key_frame_delete_payload_length(msg));
     return written;
 }
 
-static bool msg_track_create_write_payload_text(DP_Message *msg,
-                                                DP_TextWriter *writer)
+static bool msg_key_frame_delete_write_payload_text(DP_Message *msg,
+                                                    DP_TextWriter *writer)
 {
-    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "id", mtc->id, true)
-        && DP_text_writer_write_uint(writer, "insert_id", mtc->insert_id, true)
-        && DP_text_writer_write_uint(writer, "source_id", mtc->source_id, true)
-        && DP_text_writer_write_string(writer, "title", mtc->title);
+    DP_MsgKeyFrameDelete *mkfd = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "frame_index", mkfd->frame_index,
+                                     false)
+        && DP_text_writer_write_uint(writer, "move_frame_index",
+                                     mkfd->move_frame_index, false)
+        && DP_text_writer_write_uint(writer, "move_track_id",
+                                     mkfd->move_track_id, true)
+        && DP_text_writer_write_uint(writer, "track_id", mkfd->track_id, true);
 }
 
-static bool msg_track_create_equals(DP_Message *DP_RESTRICT msg,
-                                    DP_Message *DP_RESTRICT other)
+static bool msg_key_frame_delete_equals(DP_Message *DP_RESTRICT msg,
+                                        DP_Message *DP_RESTRICT other)
 {
-    DP_MsgTrackCreate *a = DP_message_internal(msg);
-    DP_MsgTrackCreate *b = DP_message_internal(other);
-    return a->id == b->id && a->insert_id == b->insert_id
-        && a->source_id == b->source_id && a->title_len == b->title_len
-        && memcmp(a->title, b->title, a->title_len) == 0;
+    DP_MsgKeyFrameDelete *a = DP_message_internal(msg);
+    DP_MsgKeyFrameDelete *b = DP_message_internal(other);
+    return a->track_id == b->track_id && a->frame_index == b->frame_index
+        && a->move_track_id == b->move_track_id
+        && a->move_frame_index == b->move_frame_index;
 }
 
-static const DP_MessageMethods msg_track_create_methods = {
-    msg_track_create_payload_length,
-    msg_track_create_serialize_payload,
-    msg_track_create_write_payload_text,
-    msg_track_create_equals,
+static const DP_MessageMethods msg_key_frame_delete_methods = {
+    msg_key_frame_delete_payload_length,
+    msg_key_frame_delete_serialize_payload,
+    msg_key_frame_delete_write_payload_text,
+    msg_key_frame_delete_equals,
 };
 
-DP_Message *DP_msg_track_create_new(unsigned int context_id, uint16_t id,
-                                    uint16_t insert_id, uint16_t source_id,
-                                    const char *title_value, size_t title_len)
+DP_Message *DP_msg_key_frame_delete_new(unsigned int context_id,
+                                        uint16_t track_id, uint16_t frame_index,
+                                        uint16_t move_track_id,
+                                        uint16_t move_frame_index)
 {
-    DP_Message *msg = DP_message_new(
-        DP_MSG_TRACK_CREATE, context_id, &msg_track_create_methods,
-        DP_FLEX_SIZEOF(DP_MsgTrackCreate, title, title_len + 1));
-    DP_MsgTrackCreate *mtc = DP_message_internal(msg);
-    mtc->id = id;
-    mtc->insert_id = insert_id;
-    mtc->source_id = source_id;
-    mtc->title_len = DP_size_to_uint16(title_len);
-    assign_string(mtc->title, title_value, mtc->title_len);
+    DP_Message *msg = DP_message_new(DP_MSG_KEY_FRAME_DELETE, context_id,
+                                     &msg_key_frame_delete_methods,
+                                     sizeof(DP_MsgKeyFrameDelete));
+    DP_MsgKeyFrameDelete *mkfd = DP_message_internal(msg);
+    mkfd->track_id = track_id;
+    mkfd->frame_index = frame_index;
+    mkfd->move_track_id = move_track_id;
+    mkfd->move_frame_index = move_frame_index;
     return msg;
 }
 
-DP_Message *DP_msg_track_create_deserialize(unsigned int context_id,
-                                            const unsigned char *buffer,
-                                            size_t length)
+DP_Message *DP_msg_key_frame_delete_deserialize(unsigned int context_id,
+                                                const unsigned char *buffer,
+                                                size_t length)
 {
-    if (length < 6 || length > 65535) {
-        DP_error_set("Wrong length for trackcreate message; "
-                     "expected between 6 and 65535, got %zu",
+    if (length != 8) {
+        DP_error_set("Wrong length for keyframedelete message; "
+                     "expected 8, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t id = read_uint16(buffer + read, &read);
-    uint16_t insert_id = read_uint16(buffer + read, &read);
-    uint16_t source_id = read_uint16(buffer + read, &read);
-    size_t title_bytes = length - read;
-    uint16_t title_len = DP_size_to_uint16(title_bytes);
-    const char *title = (const char *)buffer + read;
-    return DP_msg_track_create_new(context_id, id, insert_id, source_id, title,
-                                   title_len);
+    uint16_t track_id = read_uint16(buffer + read, &read);
+    uint16_t frame_index = read_uint16(buffer + read, &read);
+    uint16_t move_track_id = read_uint16(buffer + read, &read);
+    uint16_t move_frame_index = read_uint16(buffer + read, &read);
+    return DP_msg_key_frame_delete_new(context_id, track_id, frame_index,
+                                       move_track_id, move_frame_index);
 }
 
-DP_Message *DP_msg_track_create_parse(unsigned int context_id,
-                                      DP_TextReader *reader)
+DP_Message *DP_msg_key_frame_delete_parse(unsigned int context_id,
+                                          DP_TextReader *reader)
 {
-    uint16_t id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
-    uint16_t insert_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "insert_id", UINT16_MAX);
-    uint16_t source_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source_id", UINT16_MAX);
-    uint16_t title_len;
-    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
-    return DP_msg_track_create_new(context_id, id, insert_id, source_id, title,
-                                   title_len);
+    uint16_t track_id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
+    uint16_t frame_index =
+        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
+    uint16_t move_track_id = (uint16_t)DP_text_reader_get_ulong_hex(
+        reader, "move_track_id", UINT16_MAX);
+    uint16_t move_frame_index = (uint16_t)DP_text_reader_get_ulong(
+        reader, "move_frame_index", UINT16_MAX);
+    return DP_msg_key_frame_delete_new(context_id, track_id, frame_index,
+                                       move_track_id, move_frame_index);
 }
 
-DP_MsgTrackCreate *DP_msg_track_create_cast(DP_Message *msg)
+DP_MsgKeyFrameDelete *DP_msg_key_frame_delete_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_TRACK_CREATE);
+    return DP_message_cast(msg, DP_MSG_KEY_FRAME_DELETE);
 }
 
-uint16_t DP_msg_track_create_id(const DP_MsgTrackCreate *mtc)
+uint16_t DP_msg_key_frame_delete_track_id(const DP_MsgKeyFrameDelete *mkfd)
 {
-    DP_ASSERT(mtc);
-    return mtc->id;
+    DP_ASSERT(mkfd);
+    return mkfd->track_id;
 }
 
-uint16_t DP_msg_track_create_insert_id(const DP_MsgTrackCreate *mtc)
+uint16_t DP_msg_key_frame_delete_frame_index(const DP_MsgKeyFrameDelete *mkfd)
 {
-    DP_ASSERT(mtc);
-    return mtc->insert_id;
+    DP_ASSERT(mkfd);
+    return mkfd->frame_index;
 }
 
-uint16_t DP_msg_track_create_source_id(const DP_MsgTrackCreate *mtc)
+uint16_t DP_msg_key_frame_delete_move_track_id(const DP_MsgKeyFrameDelete *mkfd)
 {
-    DP_ASSERT(mtc);
-    return mtc->source_id;
+    DP_ASSERT(mkfd);
+    return mkfd->move_track_id;
 }
 
-const char *DP_msg_track_create_title(const DP_MsgTrackCreate *mtc,
-                                      size_t *out_len)
+uint16_t
+DP_msg_key_frame_delete_move_frame_index(const DP_MsgKeyFrameDelete *mkfd)
 {
-    DP_ASSERT(mtc);
-    if (out_len) {
-        *out_len = mtc->title_len;
-    }
-    return mtc->title;
+    DP_ASSERT(mkfd);
+    return mkfd->move_frame_index;
 }
 
-size_t DP_msg_track_create_title_len(const DP_MsgTrackCreate *mtc)
-{
-    return mtc->title_len;
-}
 
+/* DP_MSG_FILTER_REGION */
 
-/* DP_MSG_TRACK_RETITLE */
+const char *DP_msg_filter_region_filter_variant_name(unsigned int value)
+{
+    switch (value) {
+    case DP_MSG_FILTER_REGION_FILTER_BRIGHTNESS_CONTRAST:
+        return "BrightnessContrast";
+    case DP_MSG_FILTER_REGION_FILTER_HSL:
+        return "Hsl";
+    case DP_MSG_FILTER_REGION_FILTER_INVERT:
+        return "Invert";
+    default:
+        return NULL;
+    }
+}
 
-struct DP_MsgTrackRetitle {
-    uint16_t id;
-    uint16_t title_len;
-    char title[];
+struct DP_MsgFilterRegion {
+    uint16_t layer;
+    uint32_t x;
+    uint32_t y;
+    uint32_t w;
+    uint32_t h;
+    uint8_t filter;
+    int32_t param1;
+    int32_t param2;
+    int32_t param3;
+    int32_t param4;
 };
 
-static size_t msg_track_retitle_payload_length(DP_Message *msg)
+static size_t msg_filter_region_payload_length(DP_UNUSED DP_Message *msg)
 {
-    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
-    return ((size_t)2) + DP_uint16_to_size(mtr->title_len);
+    return ((size_t)35);
 }
 
-static size_t msg_track_retitle_serialize_payload(DP_Message *msg,
+static size_t msg_filter_region_serialize_payload(DP_Message *msg,
                                                   unsigned char *data)
 {
-    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
+    DP_MsgFilterRegion *mfr = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mtr->id, data + written);
-    written += DP_write_bytes(mtr->title, 1, mtr->title_len, data + written);
-    DP_ASSERT(written == msg_track_retitle_payload_length(msg));
+    written += DP_write_bigendian_uint16(mfr->layer, data + written);
+    written += DP_write_bigendian_uint32(mfr->x, data + written);
+    written += DP_write_bigendian_uint32(mfr->y, data + written);
+    written += DP_write_bigendian_uint32(mfr->w, data + written);
+    written += DP_write_bigendian_uint32(mfr->h, data + written);
+    written += DP_write_bigendian_uint8(mfr->filter, data + written);
+    written += DP_write_bigendian_int32(mfr->param1, data + written);
+    written += DP_write_bigendian_int32(mfr->param2, data + written);
+    written += DP_write_bigendian_int32(mfr->param3, data + written);
+    written += DP_write_bigendian_int32(mfr->param4, data + written);
+    DP_ASSERT(written == msg_filter_region_payload_length(msg));
     return written;
 }
 
-static bool msg_track_retitle_write_payload_text(DP_Message *msg,
+static bool msg_filter_region_write_payload_text(DP_Message *msg,
                                                  DP_TextWriter *writer)
 {
-    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "id", mtr->id, true)
-        && DP_text_writer_write_string(writer, "title", mtr->title);
+    DP_MsgFilterRegion *mfr = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "filter", mfr->filter, false)
+        && DP_text_writer_write_uint(writer, "h", mfr->h, false)
+        && DP_text_writer_write_uint(writer, "layer", mfr->layer, true)
+        && DP_text_writer_write_int(writer, "param1", mfr->param1)
+        && DP_text_writer_write_int(writer, "param2", mfr->param2)
+        && DP_text_writer_write_int(writer, "param3", mfr->param3)
+        && DP_text_writer_write_int(writer, "param4", mfr->param4)
+        && DP_text_writer_write_uint(writer, "w", mfr->w, false)
+        && DP_text_writer_write_uint(writer, "x", mfr->x, false)
+        && DP_text_writer_write_uint(writer, "y", mfr->y, false);
 }
 
-static bool msg_track_retitle_equals(DP_Message *DP_RESTRICT msg,
+static bool msg_filter_region_equals(DP_Message *DP_RESTRICT msg,
                                      DP_Message *DP_RESTRICT other)
 {
-    DP_MsgTrackRetitle *a = DP_message_internal(msg);
-    DP_MsgTrackRetitle *b = DP_message_internal(other);
-    return a->id == b->id && a->title_len == b->title_len
-        && memcmp(a->title, b->title, a->title_len) == 0;
+    DP_MsgFilterRegion *a = DP_message_internal(msg);
+    DP_MsgFilterRegion *b = DP_message_internal(other);
+    return a->layer == b->layer && a->x == b->x && a->y == b->y
+        && a->w == b->w && a->h == b->h && a->filter == b->filter
+        && a->param1 == b->param1 && a->param2 == b->param2
+        && a->param3 == b->param3 && a->param4 == b->param4;
 }
 
-static const DP_MessageMethods msg_track_retitle_methods = {
-    msg_track_retitle_payload_length,
-    msg_track_retitle_serialize_payload,
-    msg_track_retitle_write_payload_text,
-    msg_track_retitle_equals,
+static const DP_MessageMethods msg_filter_region_methods = {
+    msg_filter_region_payload_length,
+    msg_filter_region_serialize_payload,
+    msg_filter_region_write_payload_text,
+    msg_filter_region_equals,
 };
 
-DP_Message *DP_msg_track_retitle_new(unsigned int context_id, uint16_t id,
-                                     const char *title_value, size_t title_len)
+DP_Message *DP_msg_filter_region_new(unsigned int context_id, uint16_t layer,
+                                     uint32_t x, uint32_t y, uint32_t w,
+                                     uint32_t h, uint8_t filter, int32_t param1,
+                                     int32_t param2, int32_t param3,
+                                     int32_t param4)
 {
-    DP_Message *msg = DP_message_new(
-        DP_MSG_TRACK_RETITLE, context_id, &msg_track_retitle_methods,
-        DP_FLEX_SIZEOF(DP_MsgTrackRetitle, title, title_len + 1));
-    DP_MsgTrackRetitle *mtr = DP_message_internal(msg);
-    mtr->id = id;
-    mtr->title_len = DP_size_to_uint16(title_len);
-    assign_string(mtr->title, title_value, mtr->title_len);
+    DP_Message *msg = DP_message_new(DP_MSG_FILTER_REGION, context_id,
+                                     &msg_filter_region_methods,
+                                     sizeof(DP_MsgFilterRegion));
+    DP_MsgFilterRegion *mfr = DP_message_internal(msg);
+    mfr->layer = layer;
+    mfr->x = x;
+    mfr->y = y;
+    mfr->w = w;
+    mfr->h = h;
+    mfr->filter = filter;
+    mfr->param1 = param1;
+    mfr->param2 = param2;
+    mfr->param3 = param3;
+    mfr->param4 = param4;
     return msg;
 }
 
-DP_Message *DP_msg_track_retitle_deserialize(unsigned int context_id,
+DP_Message *DP_msg_filter_region_deserialize(unsigned int context_id,
                                              const unsigned char *buffer,
                                              size_t length)
 {
-    if (length < 2 || length > 65535) {
-        DP_error_set("Wrong length for trackretitle message; "
-                     "expected between 2 and 65535, got %zu",
+    if (length != 35) {
+        DP_error_set("Wrong length for filterregion message; "
+                     "expected 35, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t id = read_uint16(buffer + read, &read);
-    size_t title_bytes = length - read;
-    uint16_t title_len = DP_size_to_uint16(title_bytes);
-    const char *title = (const char *)buffer + read;
-    return DP_msg_track_retitle_new(context_id, id, title, title_len);
+    uint16_t layer = read_uint16(buffer + read, &read);
+    uint32_t x = read_uint32(buffer + read, &read);
+    uint32_t y = read_uint32(buffer + read, &read);
+    uint32_t w = read_uint32(buffer + read, &read);
+    uint32_t h = read_uint32(buffer + read, &read);
+    uint8_t filter = read_uint8(buffer + read, &read);
+    int32_t param1 = read_int32(buffer + read, &read);
+    int32_t param2 = read_int32(buffer + read, &read);
+    int32_t param3 = read_int32(buffer + read, &read);
+    int32_t param4 = read_int32(buffer + read, &read);
+    return DP_msg_filter_region_new(context_id, layer, x, y, w, h, filter,
+                                    param1, param2, param3, param4);
 }
 
-DP_Message *DP_msg_track_retitle_parse(unsigned int context_id,
+DP_Message *DP_msg_filter_region_parse(unsigned int context_id,
                                        DP_TextReader *reader)
 {
-    uint16_t id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
-    uint16_t title_len;
-    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
-    return DP_msg_track_retitle_new(context_id, id, title, title_len);
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    uint32_t x = (uint32_t)DP_text_reader_get_ulong(reader, "x", UINT32_MAX);
+    uint32_t y = (uint32_t)DP_text_reader_get_ulong(reader, "y", UINT32_MAX);
+    uint32_t w = (uint32_t)DP_text_reader_get_ulong(reader, "w", UINT32_MAX);
+    uint32_t h = (uint32_t)DP_text_reader_get_ulong(reader, "h", UINT32_MAX);
+    uint8_t filter =
+        (uint8_t)DP_text_reader_get_ulong(reader, "filter", UINT8_MAX);
+    int32_t param1 = (int32_t)DP_text_reader_get_long(reader, "param1",
+                                                      INT32_MIN, INT32_MAX);
+    int32_t param2 = (int32_t)DP_text_reader_get_long(reader, "param2",
+                                                      INT32_MIN, INT32_MAX);
+    int32_t param3 = (int32_t)DP_text_reader_get_long(reader, "param3",
+                                                      INT32_MIN, INT32_MAX);
+    int32_t param4 = (int32_t)DP_text_reader_get_long(reader, "param4",
+                                                      INT32_MIN, INT32_MAX);
+    return DP_msg_filter_region_new(context_id, layer, x, y, w, h, filter,
+                                    param1, param2, param3, param4);
 }
 
-DP_MsgTrackRetitle *DP_msg_track_retitle_cast(DP_Message *msg)
+DP_MsgFilterRegion *DP_msg_filter_region_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_TRACK_RETITLE);
+    return DP_message_cast(msg, DP_MSG_FILTER_REGION);
 }
 
-uint16_t DP_msg_track_retitle_id(const DP_MsgTrackRetitle *mtr)
+uint16_t DP_msg_filter_region_layer(const DP_MsgFilterRegion *mfr)
 {
-    DP_ASSERT(mtr);
-    return mtr->id;
+    DP_ASSERT(mfr);
+    return mfr->layer;
 }
 
-const char *DP_msg_track_retitle_title(const DP_MsgTrackRetitle *mtr,
-                                       size_t *out_len)
+uint32_t DP_msg_filter_region_x(const DP_MsgFilterRegion *mfr)
 {
-    DP_ASSERT(mtr);
-    if (out_len) {
-        *out_len = mtr->title_len;
-    }
-    return mtr->title;
+    DP_ASSERT(mfr);
+    return mfr->x;
 }
 
-size_t DP_msg_track_retitle_title_len(const DP_MsgTrackRetitle *mtr)
+uint32_t DP_msg_filter_region_y(const DP_MsgFilterRegion *mfr)
 {
-    return mtr->title_len;
+    DP_ASSERT(mfr);
+    return mfr->y;
 }
 
-
-/* DP_MSG_TRACK_DELETE */
-
-struct DP_MsgTrackDelete {
-    uint16_t id;
-};
-
-static size_t msg_track_delete_payload_length(DP_UNUSED DP_Message *msg)
+uint32_t DP_msg_filter_region_w(const DP_MsgFilterRegion *mfr)
 {
-    return ((size_t)2);
+    DP_ASSERT(mfr);
+    return mfr->w;
 }
 
-static size_t msg_track_delete_serialize_payload(DP_Message *msg,
-                                                 unsigned char *data)
+uint32_t DP_msg_filter_region_h(const DP_MsgFilterRegion *mfr)
 {
-    DP_MsgTrackDelete *mtd = DP_message_internal(msg);
-    size_t written = 0;
-    written += DP_write_bigendian_uint16(mtd->id, data + written);
-    DP_ASSERT(written == msg_track_delete_payload_length(msg));
-    return written;
+    DP_ASSERT(mfr);
+    return mfr->h;
 }
 
-static bool msg_track_delete_write_payload_text(DP_Message *msg,
-                                                DP_TextWriter *writer)
+uint8_t DP_msg_filter_region_filter(const DP_MsgFilterRegion *mfr)
 {
-    DP_MsgTrackDelete *mtd = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "id", mtd->id, true);
+    DP_ASSERT(mfr);
+    return mfr->filter;
 }
 
-static bool msg_track_delete_equals(DP_Message *DP_RESTRICT msg,
-                                    DP_Message *DP_RESTRICT other)
+int32_t DP_msg_filter_region_param1(const DP_MsgFilterRegion *mfr)
 {
-    DP_MsgTrackDelete *a = DP_message_internal(msg);
-    DP_MsgTrackDelete *b = DP_message_internal(other);
-    return a->id == b->id;
+    DP_ASSERT(mfr);
+    return mfr->param1;
 }
 
-static const DP_MessageMethods msg_track_delete_methods = {
-    msg_track_delete_payload_length,
-    msg_track_delete_serialize_payload,
-    msg_track_delete_write_payload_text,
-    msg_track_delete_equals,
-};
-
-DP_Message *DP_msg_track_delete_new(unsigned int context_id, uint16_t id)
+int32_t DP_msg_filter_region_param2(const DP_MsgFilterRegion *mfr)
 {
-    DP_Message *msg =
-        DP_message_new(DP_MSG_TRACK_DELETE, context_id,
-                       &msg_track_delete_methods, sizeof(DP_MsgTrackDelete));
-    DP_MsgTrackDelete *mtd = DP_message_internal(msg);
-    mtd->id = id;
-    return msg;
+    DP_ASSERT(mfr);
+    return mfr->param2;
 }
 
-DP_Message *DP_msg_track_delete_deserialize(unsigned int context_id,
-                                            const unsigned char *buffer,
-                                            size_t length)
+int32_t DP_msg_filter_region_param3(const DP_MsgFilterRegion *mfr)
 {
-    if (length != 2) {
-        DP_error_set("Wrong length for trackdelete message; "
-                     "expected 2, got %zu",
-                     length);
-        return NULL;
-    }
-    size_t read = 0;
-    uint16_t id = read_uint16(buffer + read, &read);
-    return DP_msg_track_delete_new(context_id, id);
+    DP_ASSERT(mfr);
+    return mfr->param3;
 }
 
-DP_Message *DP_msg_track_delete_parse(unsigned int context_id,
-                                      DP_TextReader *reader)
+int32_t DP_msg_filter_region_param4(const DP_MsgFilterRegion *mfr)
 {
-    uint16_t id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
-    return DP_msg_track_delete_new(context_id, id);
+    DP_ASSERT(mfr);
+    return mfr->param4;
 }
 
-DP_MsgTrackDelete *DP_msg_track_delete_cast(DP_Message *msg)
+
+/* DP_MSG_FILL_GRADIENT */
+
+const char *DP_msg_fill_gradient_shape_variant_name(unsigned int value)
 {
-    return DP_message_cast(msg, DP_MSG_TRACK_DELETE);
+    switch (value) {
+    case DP_MSG_FILL_GRADIENT_SHAPE_LINEAR:
+        return "Linear";
+    case DP_MSG_FILL_GRADIENT_SHAPE_RADIAL:
+        return "Radial";
+    default:
+        return NULL;
+    }
 }
 
-uint16_t DP_msg_track_delete_id(const DP_MsgTrackDelete *mtd)
+const char *DP_msg_fill_gradient_flags_flag_name(unsigned int value)
 {
-    DP_ASSERT(mtd);
-    return mtd->id;
+    switch (value) {
+    case DP_MSG_FILL_GRADIENT_FLAGS_DITHER:
+        return "dither";
+    default:
+        return NULL;
+    }
 }
 
-
-/* DP_MSG_TRACK_ORDER */
-
-struct DP_MsgTrackOrder {
-    uint16_t tracks_count;
-    uint16_t tracks[];
+struct DP_FillGradientStop {
+    uint16_t position;
+    uint32_t color;
 };
 
-static size_t msg_track_order_payload_length(DP_Message *msg)
-{
-    DP_MsgTrackOrder *mto = DP_message_internal(msg);
-    return DP_int_to_size(mto->tracks_count) * 2;
-}
-
-static size_t msg_track_order_serialize_payload(DP_Message *msg,
-                                                unsigned char *data)
+static size_t fill_gradient_stop_serialize_payload(DP_FillGradientStop *fgs,
+                                                   unsigned char *data)
 {
-    DP_MsgTrackOrder *mto = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16_array(mto->tracks, mto->tracks_count,
-                                               data + written);
-    DP_ASSERT(written == msg_track_order_payload_length(msg));
+    written += DP_write_bigendian_uint16(fgs->position, data + written);
+    written += DP_write_bigendian_uint32(fgs->color, data + written);
     return written;
 }
 
-static bool msg_track_order_write_payload_text(DP_Message *msg,
-                                               DP_TextWriter *writer)
+static size_t fill_gradient_stop_serialize_payloads(DP_FillGradientStop *fgs,
+                                                    int count,
+                                                    unsigned char *data)
 {
-    DP_MsgTrackOrder *mto = DP_message_internal(msg);
-    return DP_text_writer_write_uint16_list(writer, "tracks", mto->tracks,
-                                            mto->tracks_count, true);
+    size_t written = 0;
+    for (int i = 0; i < count; ++i) {
+        written +=
+            fill_gradient_stop_serialize_payload(&fgs[i], data + written);
+    }
+    return written;
 }
 
-static bool msg_track_order_equals(DP_Message *DP_RESTRICT msg,
-                                   DP_Message *DP_RESTRICT other)
+static bool fill_gradient_stop_write_payload_text(DP_FillGradientStop *fgs,
+                                                  DP_TextWriter *writer)
 {
-    DP_MsgTrackOrder *a = DP_message_internal(msg);
-    DP_MsgTrackOrder *b = DP_message_internal(other);
-    return a->tracks_count == b->tracks_count
-        && memcmp(a->tracks, b->tracks, DP_uint16_to_size(a->tracks_count) * 2)
-               == 0;
+    return DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "   ")
+        && DP_text_writer_write_subfield_uint(writer, fgs->position)
+        && DP_text_writer_write_subfield_argb_color(writer, fgs->color)
+        && DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "\n");
 }
 
-static const DP_MessageMethods msg_track_order_methods = {
-    msg_track_order_payload_length,
-    msg_track_order_serialize_payload,
-    msg_track_order_write_payload_text,
-    msg_track_order_equals,
-};
-
-DP_Message *DP_msg_track_order_new(unsigned int context_id,
-                                   void (*set_tracks)(int, uint16_t *, void *),
-                                   int tracks_count, void *tracks_user)
+static size_t fill_gradient_stop_write_payload_texts(DP_FillGradientStop *fgs,
+                                                     int count,
+                                                     DP_TextWriter *writer)
 {
-    DP_Message *msg =
-        DP_message_new(DP_MSG_TRACK_ORDER, context_id, &msg_track_order_methods,
-                       DP_FLEX_SIZEOF(DP_MsgTrackOrder, tracks,
-                                      DP_int_to_size(tracks_count) * 2));
-    DP_MsgTrackOrder *mto = DP_message_internal(msg);
-    mto->tracks_count = DP_int_to_uint16(tracks_count);
-    if (set_tracks) {
-        set_tracks(mto->tracks_count, mto->tracks, tracks_user);
+    if (!DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, " {\n")) {
+        return false;
     }
-    return msg;
+    for (int i = 0; i < count; ++i) {
+        if (!fill_gradient_stop_write_payload_text(&fgs[i], writer)) {
+            return false;
+        }
+    }
+    return DP_TEXT_WRITER_RAW_PRINT_LITERAL(writer, "}");
 }
 
-DP_Message *DP_msg_track_order_deserialize(unsigned int context_id,
-                                           const unsigned char *buffer,
-                                           size_t length)
+static bool fill_gradient_stop_equals(DP_FillGradientStop *DP_RESTRICT a,
+                                      DP_FillGradientStop *DP_RESTRICT b)
 {
-    if (length > 65535) {
-        DP_error_set("Wrong length for trackorder message; "
-                     "expected between 0 and 65535, got %zu",
-                     length);
-        return NULL;
-    }
-    size_t read = 0;
-    size_t tracks_bytes = length - read;
-    if ((tracks_bytes % 2) != 0) {
-        DP_error_set("Wrong length for tracks field in trackorder message; "
-                     "%zu not divisible by 2",
-                     tracks_bytes);
-        return NULL;
-    }
-    uint16_t tracks_count = DP_size_to_uint16(tracks_bytes / 2);
-    void *tracks_user = (void *)(buffer + read);
-    return DP_msg_track_order_new(context_id, read_uint16_array, tracks_count,
-                                  tracks_user);
+    return a->position == b->position && a->color == b->color;
 }
 
-DP_Message *DP_msg_track_order_parse(unsigned int context_id,
-                                     DP_TextReader *reader)
+static bool fill_gradient_stops_equal(DP_FillGradientStop *DP_RESTRICT a,
+                                      DP_FillGradientStop *DP_RESTRICT b,
+                                      int count)
 {
-    int tracks_count;
-    DP_TextReaderParseParams tracks_params =
-        DP_text_reader_get_comma_separated(reader, "tracks", &tracks_count);
-    return DP_msg_track_order_new(context_id,
-                                  DP_text_reader_parse_uint16_array_hex,
-                                  tracks_count, &tracks_params);
+    for (int i = 0; i < count; ++i) {
+        if (!fill_gradient_stop_equals(&a[i], &b[i])) {
+            return false;
+        }
+    }
+    return true;
 }
 
-DP_MsgTrackOrder *DP_msg_track_order_cast(DP_Message *msg)
+void DP_fill_gradient_stop_init(DP_FillGradientStop *fgss, int i,
+                                uint16_t position, uint32_t color)
 {
-    return DP_message_cast(msg, DP_MSG_TRACK_ORDER);
+    DP_ASSERT(fgss);
+    DP_FillGradientStop *fgs = &fgss[i];
+    fgs->position = position;
+    fgs->color = color;
 }
 
-const uint16_t *DP_msg_track_order_tracks(const DP_MsgTrackOrder *mto,
-                                          int *out_count)
+static void fill_gradient_stop_deserialize(int count, DP_FillGradientStop *fgss,
+                                           void *user)
 {
-    DP_ASSERT(mto);
-    if (out_count) {
-        *out_count = mto->tracks_count;
+    const unsigned char *buffer = user;
+    size_t read = 0;
+    for (int i = 0; i < count; ++i) {
+        uint16_t position = read_uint16(buffer + read, &read);
+        uint32_t color = read_uint32(buffer + read, &read);
+        DP_fill_gradient_stop_init(fgss, i, position, color);
     }
-    return mto->tracks;
 }
 
-int DP_msg_track_order_tracks_count(const DP_MsgTrackOrder *mto)
+static void fill_gradient_stop_parse(int count, DP_FillGradientStop *fgss,
+                                     void *user)
 {
-    return mto->tracks_count;
+    DP_TextReader *reader = user;
+    for (int i = 0; i < count; ++i) {
+        uint16_t position = (uint16_t)DP_text_reader_get_subfield_ulong(
+            reader, i, 0, UINT16_MAX);
+        uint32_t color =
+            (uint32_t)DP_text_reader_get_subfield_argb_color(reader, i, 1);
+        DP_fill_gradient_stop_init(fgss, i, position, color);
+    }
 }
 
+uint16_t DP_fill_gradient_stop_position(const DP_FillGradientStop *fgs)
+{
+    DP_ASSERT(fgs);
+    return fgs->position;
+}
 
-/* DP_MSG_KEY_FRAME_SET */
+uint32_t DP_fill_gradient_stop_color(const DP_FillGradientStop *fgs)
+{
+    DP_ASSERT(fgs);
+    return fgs->color;
+}
 
-const char *DP_msg_key_frame_set_source_variant_name(unsigned int value)
+const DP_FillGradientStop *
+DP_fill_gradient_stop_at(const DP_FillGradientStop *fgs, int i)
 {
-    switch (value) {
-    case DP_MSG_KEY_FRAME_SET_SOURCE_LAYER:
-        return "Layer";
-    case DP_MSG_KEY_FRAME_SET_SOURCE_KEY_FRAME:
-        return "KeyFrame";
-    default:
-        return NULL;
-    }
+    DP_ASSERT(fgs);
+    return &fgs[i];
 }
 
-struct DP_MsgKeyFrameSet {
-    uint16_t track_id;
-    uint16_t frame_index;
-    uint16_t source_id;
-    uint16_t source_index;
-    uint8_t source;
+struct DP_MsgFillGradient {
+    uint16_t layer;
+    uint32_t x;
+    uint32_t y;
+    uint32_t w;
+    uint32_t h;
+    uint8_t mode;
+    uint8_t shape;
+    uint8_t flags;
+    int32_t x1;
+    int32_t y1;
+    int32_t x2;
+    int32_t y2;
+    int32_t radius;
+    uint16_t stops_count;
+    DP_FillGradientStop stops[];
 };
 
-static size_t msg_key_frame_set_payload_length(DP_UNUSED DP_Message *msg)
+static size_t msg_fill_gradient_payload_length(DP_Message *msg)
 {
-    return ((size_t)9);
+    DP_MsgFillGradient *mfg = DP_message_internal(msg);
+    return ((size_t)41) + DP_int_to_size(mfg->stops_count) * 6;
 }
 
-static size_t msg_key_frame_set_serialize_payload(DP_Message *msg,
+static size_t msg_fill_gradient_serialize_payload(DP_Message *msg,
                                                   unsigned char *data)
 {
-    DP_MsgKeyFrameSet *mkfs = DP_message_internal(msg);
+    DP_MsgFillGradient *mfg = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mkfs->track_id, data + written);
-    written += DP_write_bigendian_uint16(mkfs->frame_index, data + written);
-    written += DP_write_bigendian_uint16(mkfs->source_id, data + written);
-    written += DP_write_bigendian_uint16(mkfs->source_index, data + written);
-    written += DP_write_bigendian_uint8(mkfs->source, data + written);
-    DP_ASSERT(written == msg_key_frame_set_payload_length(msg));
+    written += DP_write_bigendian_uint16(mfg->layer, data + written);
+    written += DP_write_bigendian_uint32(mfg->x, data + written);
+    written += DP_write_bigendian_uint32(mfg->y, data + written);
+    written += DP_write_bigendian_uint32(mfg->w, data + written);
+    written += DP_write_bigendian_uint32(mfg->h, data + written);
+    written += DP_write_bigendian_uint8(mfg->mode, data + written);
+    written += DP_write_bigendian_uint8(mfg->shape, data + written);
+    written += DP_write_bigendian_uint8(mfg->flags, data + written);
+    written += DP_write_bigendian_int32(mfg->x1, data + written);
+    written += DP_write_bigendian_int32(mfg->y1, data + written);
+    written += DP_write_bigendian_int32(mfg->x2, data + written);
+    written += DP_write_bigendian_int32(mfg->y2, data + written);
+    written += DP_write_bigendian_int32(mfg->radius, data + written);
+    written += fill_gradient_stop_serialize_payloads(
+        mfg->stops, mfg->stops_count, data + written);
+    DP_ASSERT(written == msg_fill_gradient_payload_length(msg));
     return written;
 }
 
-static bool msg_key_frame_set_write_payload_text(DP_Message *msg,
+static bool msg_fill_gradient_write_payload_text(DP_Message *msg,
                                                  DP_TextWriter *writer)
 {
-    DP_MsgKeyFrameSet *mkfs = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "frame_index", mkfs->frame_index,
-                                     false)
-        && DP_text_writer_write_uint(writer, "source", mkfs->source, false)
-        && DP_text_writer_write_uint(writer, "source_id", mkfs->source_id, true)
-        && DP_text_writer_write_uint(writer, "source_index", mkfs->source_index,
-                                     false)
-        && DP_text_writer_write_uint(writer, "track_id", mkfs->track_id, true);
-}
-
-static bool msg_key_frame_set_equals(DP_Message *DP_RESTRICT msg,
+    DP_MsgFillGradient *mfg = DP_message_internal(msg);
+    return DP_text_writer_write_flags(
+               writer, "flags", mfg->flags, 1, (const char *[]){"dither"},
+               (unsigned int[]){DP_MSG_FILL_GRADIENT_FLAGS_DITHER})
+        && DP_text_writer_write_uint(writer, "h", mfg->h, false)
+        && DP_text_writer_write_uint(writer, "layer", mfg->layer, true)
+        && DP_text_writer_write_blend_mode(writer, "mode", mfg->mode)
+        && DP_text_writer_write_decimal(writer, "radius",
+                                        (double)mfg->radius / 4.0)
+        && DP_text_writer_write_uint(writer, "shape", mfg->shape, false)
+        && DP_text_writer_write_uint(writer, "w", mfg->w, false)
+        && DP_text_writer_write_uint(writer, "x", mfg->x, false)
+        && DP_text_writer_write_decimal(writer, "x1", (double)mfg->x1 / 4.0)
+        && DP_text_writer_write_decimal(writer, "x2", (double)mfg->x2 / 4.0)
+        && DP_text_writer_write_uint(writer, "y", mfg->y, false)
+        && DP_text_writer_write_decimal(writer, "y1", (double)mfg->y1 / 4.0)
+        && DP_text_writer_write_decimal(writer, "y2", (double)mfg->y2 / 4.0)
+        && fill_gradient_stop_write_payload_texts(mfg->stops,
+                                                  mfg->stops_count, writer);
+}
+
+static bool msg_fill_gradient_equals(DP_Message *DP_RESTRICT msg,
                                      DP_Message *DP_RESTRICT other)
 {
-    DP_MsgKeyFrameSet *a = DP_message_internal(msg);
-    DP_MsgKeyFrameSet *b = DP_message_internal(other);
-    return a->track_id == b->track_id && a->frame_index == b->frame_index
-        && a->source_id == b->source_id && a->source_index == b->source_index
-        && a->source == b->source;
-}
-
-static const DP_MessageMethods msg_key_frame_set_methods = {
-    msg_key_frame_set_payload_length,
-    msg_key_frame_set_serialize_payload,
-    msg_key_frame_set_write_payload_text,
-    msg_key_frame_set_equals,
+    DP_MsgFillGradient *a = DP_message_internal(msg);
+    DP_MsgFillGradient *b = DP_message_internal(other);
+    return a->layer == b->layer && a->x == b->x && a->y == b->y
+        && a->w == b->w && a->h == b->h && a->mode == b->mode
+        && a->shape == b->shape && a->flags == b->flags && a->x1 == b->x1
+        && a->y1 == b->y1 && a->x2 == b->x2 && a->y2 == b->y2
+        && a->radius == b->radius && a->stops_count == b->stops_count
+        && fill_gradient_stops_equal(a->stops, b->stops, a->stops_count);
+}
+
+static const DP_MessageMethods msg_fill_gradient_methods = {
+    msg_fill_gradient_payload_length,
+    msg_fill_gradient_serialize_payload,
+    msg_fill_gradient_write_payload_text,
+    msg_fill_gradient_equals,
 };
 
-DP_Message *DP_msg_key_frame_set_new(unsigned int context_id, uint16_t track_id,
-                                     uint16_t frame_index, uint16_t source_id,
-                                     uint16_t source_index, uint8_t source)
+DP_Message *DP_msg_fill_gradient_new(
+    unsigned int context_id, uint16_t layer, uint32_t x, uint32_t y,
+    uint32_t w, uint32_t h, uint8_t mode, uint8_t shape, uint8_t flags,
+    int32_t x1, int32_t y1, int32_t x2, int32_t y2, int32_t radius,
+    void (*set_stops)(int, DP_FillGradientStop *, void *), int stops_count,
+    void *stops_user)
 {
-    DP_Message *msg =
-        DP_message_new(DP_MSG_KEY_FRAME_SET, context_id,
-                       &msg_key_frame_set_methods, sizeof(DP_MsgKeyFrameSet));
-    DP_MsgKeyFrameSet *mkfs = DP_message_internal(msg);
-    mkfs->track_id = track_id;
-    mkfs->frame_index = frame_index;
-    mkfs->source_id = source_id;
-    mkfs->source_index = source_index;
-    mkfs->source = source;
+    DP_Message *msg = DP_message_new(
+        DP_MSG_FILL_GRADIENT, context_id, &msg_fill_gradient_methods,
+        DP_FLEX_SIZEOF(DP_MsgFillGradient, stops,
+                       DP_int_to_size(stops_count)
+                           * sizeof(DP_FillGradientStop)));
+    DP_MsgFillGradient *mfg = DP_message_internal(msg);
+    mfg->layer = layer;
+    mfg->x = x;
+    mfg->y = y;
+    mfg->w = w;
+    mfg->h = h;
+    mfg->mode = mode;
+    mfg->shape = shape;
+    mfg->flags = flags;
+    mfg->x1 = x1;
+    mfg->y1 = y1;
+    mfg->x2 = x2;
+    mfg->y2 = y2;
+    mfg->radius = radius;
+    mfg->stops_count = DP_int_to_uint16(stops_count);
+    set_stops(mfg->stops_count, mfg->stops, stops_user);
     return msg;
 }
 
-DP_Message *DP_msg_key_frame_set_deserialize(unsigned int context_id,
+DP_Message *DP_msg_fill_gradient_deserialize(unsigned int context_id,
                                              const unsigned char *buffer,
                                              size_t length)
 {
-    if (length != 9) {
-        DP_error_set("Wrong length for keyframeset message; "
-                     "expected 9, got %zu",
+    if (length < 47 || length > 65531) {
+        DP_error_set("Wrong length for gradientfill message; "
+                     "expected between 47 and 65531, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t track_id = read_uint16(buffer + read, &read);
-    uint16_t frame_index = read_uint16(buffer + read, &read);
-    uint16_t source_id = read_uint16(buffer + read, &read);
-    uint16_t source_index = read_uint16(buffer + read, &read);
-    uint8_t source = read_uint8(buffer + read, &read);
-    return DP_msg_key_frame_set_new(context_id, track_id, frame_index,
-                                    source_id, source_index, source);
+    uint16_t layer = read_uint16(buffer + read, &read);
+    uint32_t x = read_uint32(buffer + read, &read);
+    uint32_t y = read_uint32(buffer + read, &read);
+    uint32_t w = read_uint32(buffer + read, &read);
+    uint32_t h = read_uint32(buffer + read, &read);
+    uint8_t mode = read_uint8(buffer + read, &read);
+    uint8_t shape = read_uint8(buffer + read, &read);
+    uint8_t flags = read_uint8(buffer + read, &read);
+    int32_t x1 = read_int32(buffer + read, &read);
+    int32_t y1 = read_int32(buffer + read, &read);
+    int32_t x2 = read_int32(buffer + read, &read);
+    int32_t y2 = read_int32(buffer + read, &read);
+    int32_t radius = read_int32(buffer + read, &read);
+    size_t stops_bytes = length - read;
+    if ((stops_bytes % 6) != 0) {
+        DP_error_set("Wrong length for stops field in gradientfill message; "
+                     "%zu not divisible by 6",
+                     stops_bytes);
+        return NULL;
+    }
+    int stops_count = DP_size_to_int(stops_bytes) / 6;
+    void *stops_user = (void *)(buffer + read);
+    return DP_msg_fill_gradient_new(context_id, layer, x, y, w, h, mode, shape,
+                                    flags, x1, y1, x2, y2, radius,
+                                    fill_gradient_stop_deserialize,
+                                    stops_count, stops_user);
 }
 
-DP_Message *DP_msg_key_frame_set_parse(unsigned int context_id,
+DP_Message *DP_msg_fill_gradient_parse(unsigned int context_id,
                                        DP_TextReader *reader)
 {
-    uint16_t track_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
-    uint16_t frame_index =
-        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
-    uint16_t source_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "source_id", UINT16_MAX);
-    uint16_t source_index =
-        (uint16_t)DP_text_reader_get_ulong(reader, "source_index", UINT16_MAX);
-    uint8_t source =
-        (uint8_t)DP_text_reader_get_ulong(reader, "source", UINT8_MAX);
-    return DP_msg_key_frame_set_new(context_id, track_id, frame_index,
-                                    source_id, source_index, source);
-}
-
-DP_MsgKeyFrameSet *DP_msg_key_frame_set_cast(DP_Message *msg)
-{
-    return DP_message_cast(msg, DP_MSG_KEY_FRAME_SET);
-}
-
-uint16_t DP_msg_key_frame_set_track_id(const DP_MsgKeyFrameSet *mkfs)
-{
-    DP_ASSERT(mkfs);
-    return mkfs->track_id;
+    uint16_t layer =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "layer", UINT16_MAX);
+    uint32_t x = (uint32_t)DP_text_reader_get_ulong(reader, "x", UINT32_MAX);
+    uint32_t y = (uint32_t)DP_text_reader_get_ulong(reader, "y", UINT32_MAX);
+    uint32_t w = (uint32_t)DP_text_reader_get_ulong(reader, "w", UINT32_MAX);
+    uint32_t h = (uint32_t)DP_text_reader_get_ulong(reader, "h", UINT32_MAX);
+    uint8_t mode = DP_text_reader_get_blend_mode(reader, "mode");
+    uint8_t shape =
+        (uint8_t)DP_text_reader_get_ulong(reader, "shape", UINT8_MAX);
+    uint8_t flags = (uint8_t)DP_text_reader_get_flags(
+        reader, "flags", 1, (const char *[]){"dither"},
+        (unsigned int[]){DP_MSG_FILL_GRADIENT_FLAGS_DITHER});
+    int32_t x1 = (int32_t)DP_text_reader_get_decimal(reader, "x1", 4.0,
+                                                     INT32_MIN, INT32_MAX);
+    int32_t y1 = (int32_t)DP_text_reader_get_decimal(reader, "y1", 4.0,
+                                                     INT32_MIN, INT32_MAX);
+    int32_t x2 = (int32_t)DP_text_reader_get_decimal(reader, "x2", 4.0,
+                                                     INT32_MIN, INT32_MAX);
+    int32_t y2 = (int32_t)DP_text_reader_get_decimal(reader, "y2", 4.0,
+                                                     INT32_MIN, INT32_MAX);
+    int32_t radius = (int32_t)DP_text_reader_get_decimal(
+        reader, "radius", 4.0, INT32_MIN, INT32_MAX);
+    int stops_count = DP_text_reader_get_tuple_count(reader);
+    void *stops_user = reader;
+    return DP_msg_fill_gradient_new(context_id, layer, x, y, w, h, mode, shape,
+                                    flags, x1, y1, x2, y2, radius,
+                                    fill_gradient_stop_parse, stops_count,
+                                    stops_user);
 }
 
-uint16_t DP_msg_key_frame_set_frame_index(const DP_MsgKeyFrameSet *mkfs)
+DP_MsgFillGradient *DP_msg_fill_gradient_cast(DP_Message *msg)
 {
-    DP_ASSERT(mkfs);
-    return mkfs->frame_index;
+    return DP_message_cast(msg, DP_MSG_FILL_GRADIENT);
 }
 
-uint16_t DP_msg_key_frame_set_source_id(const DP_MsgKeyFrameSet *mkfs)
+uint16_t DP_msg_fill_gradient_layer(const DP_MsgFillGradient *mfg)
 {
-    DP_ASSERT(mkfs);
-    return mkfs->source_id;
+    DP_ASSERT(mfg);
+    return mfg->layer;
 }
 
-uint16_t DP_msg_key_frame_set_source_index(const DP_MsgKeyFrameSet *mkfs)
+uint32_t DP_msg_fill_gradient_x(const DP_MsgFillGradient *mfg)
 {
-    DP_ASSERT(mkfs);
-    return mkfs->source_index;
+    DP_ASSERT(mfg);
+    return mfg->x;
 }
 
-uint8_t DP_msg_key_frame_set_source(const DP_MsgKeyFrameSet *mkfs)
+uint32_t DP_msg_fill_gradient_y(const DP_MsgFillGradient *mfg)
 {
-    DP_ASSERT(mkfs);
-    return mkfs->source;
+    DP_ASSERT(mfg);
+    return mfg->y;
 }
 
-
-/* DP_MSG_KEY_FRAME_RETITLE */
-
-struct DP_MsgKeyFrameRetitle {
-    uint16_t track_id;
-    uint16_t frame_index;
-    uint16_t title_len;
-    char title[];
-};
-
-static size_t msg_key_frame_retitle_payload_length(DP_Message *msg)
+uint32_t DP_msg_fill_gradient_w(const DP_MsgFillGradient *mfg)
 {
-    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
-    return ((size_t)4) + DP_uint16_to_size(mkfr->title_len);
+    DP_ASSERT(mfg);
+    return mfg->w;
 }
 
-static size_t msg_key_frame_retitle_serialize_payload(DP_Message *msg,
-                                                      unsigned char *data)
+uint32_t DP_msg_fill_gradient_h(const DP_MsgFillGradient *mfg)
 {
-    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
-    size_t written = 0;
-    written += DP_write_bigendian_uint16(mkfr->track_id, data + written);
-    written += DP_write_bigendian_uint16(mkfr->frame_index, data + written);
-    written += DP_write_bytes(mkfr->title, 1, mkfr->title_len, data + written);
-    DP_ASSERT(written == msg_key_frame_retitle_payload_length(msg));
-    return written;
+    DP_ASSERT(mfg);
+    return mfg->h;
 }
 
-static bool msg_key_frame_retitle_write_payload_text(DP_Message *msg,
-                                                     DP_TextWriter *writer)
+uint8_t DP_msg_fill_gradient_mode(const DP_MsgFillGradient *mfg)
 {
-    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "frame_index", mkfr->frame_index,
-                                     false)
-        && DP_text_writer_write_string(writer, "title", mkfr->title)
-        && DP_text_writer_write_uint(writer, "track_id", mkfr->track_id, true);
+    DP_ASSERT(mfg);
+    return mfg->mode;
 }
 
-static bool msg_key_frame_retitle_equals(DP_Message *DP_RESTRICT msg,
-                                         DP_Message *DP_RESTRICT other)
+uint8_t DP_msg_fill_gradient_shape(const DP_MsgFillGradient *mfg)
 {
-    DP_MsgKeyFrameRetitle *a = DP_message_internal(msg);
-    DP_MsgKeyFrameRetitle *b = DP_message_internal(other);
-    return a->track_id == b->track_id && a->frame_index == b->frame_index
-        && a->title_len == b->title_len
-        && memcmp(a->title, b->title, a->title_len) == 0;
+    DP_ASSERT(mfg);
+    return mfg->shape;
 }
 
-static const DP_MessageMethods msg_key_frame_retitle_methods = {
-    msg_key_frame_retitle_payload_length,
-    msg_key_frame_retitle_serialize_payload,
-    msg_key_frame_retitle_write_payload_text,
-    msg_key_frame_retitle_equals,
-};
-
-DP_Message *DP_msg_key_frame_retitle_new(unsigned int context_id,
-                                         uint16_t track_id,
-                                         uint16_t frame_index,
-                                         const char *title_value,
-                                         size_t title_len)
+uint8_t DP_msg_fill_gradient_flags(const DP_MsgFillGradient *mfg)
 {
-    DP_Message *msg = DP_message_new(
-        DP_MSG_KEY_FRAME_RETITLE, context_id, &msg_key_frame_retitle_methods,
-        DP_FLEX_SIZEOF(DP_MsgKeyFrameRetitle, title, title_len + 1));
-    DP_MsgKeyFrameRetitle *mkfr = DP_message_internal(msg);
-    mkfr->track_id = track_id;
-    mkfr->frame_index = frame_index;
-    mkfr->title_len = DP_size_to_uint16(title_len);
-    assign_string(mkfr->title, title_value, mkfr->title_len);
-    return msg;
+    DP_ASSERT(mfg);
+    return mfg->flags;
 }
 
-DP_Message *DP_msg_key_frame_retitle_deserialize(unsigned int context_id,
-                                                 const unsigned char *buffer,
-                                                 size_t length)
+int32_t DP_msg_fill_gradient_x1(const DP_MsgFillGradient *mfg)
 {
-    if (length < 4 || length > 65535) {
-        DP_error_set("Wrong length for keyframeretitle message; "
-                     "expected between 4 and 65535, got %zu",
-                     length);
-        return NULL;
-    }
-    size_t read = 0;
-    uint16_t track_id = read_uint16(buffer + read, &read);
-    uint16_t frame_index = read_uint16(buffer + read, &read);
-    size_t title_bytes = length - read;
-    uint16_t title_len = DP_size_to_uint16(title_bytes);
-    const char *title = (const char *)buffer + read;
-    return DP_msg_key_frame_retitle_new(context_id, track_id, frame_index,
-                                        title, title_len);
+    DP_ASSERT(mfg);
+    return mfg->x1;
 }
 
-DP_Message *DP_msg_key_frame_retitle_parse(unsigned int context_id,
-                                           DP_TextReader *reader)
+int32_t DP_msg_fill_gradient_y1(const DP_MsgFillGradient *mfg)
 {
-    uint16_t track_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
-    uint16_t frame_index =
-        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
-    uint16_t title_len;
-    const char *title = DP_text_reader_get_string(reader, "title", &title_len);
-    return DP_msg_key_frame_retitle_new(context_id, track_id, frame_index,
-                                        title, title_len);
+    DP_ASSERT(mfg);
+    return mfg->y1;
 }
 
-DP_MsgKeyFrameRetitle *DP_msg_key_frame_retitle_cast(DP_Message *msg)
+int32_t DP_msg_fill_gradient_x2(const DP_MsgFillGradient *mfg)
 {
-    return DP_message_cast(msg, DP_MSG_KEY_FRAME_RETITLE);
+    DP_ASSERT(mfg);
+    return mfg->x2;
 }
 
-uint16_t DP_msg_key_frame_retitle_track_id(const DP_MsgKeyFrameRetitle *mkfr)
+int32_t DP_msg_fill_gradient_y2(const DP_MsgFillGradient *mfg)
 {
-    DP_ASSERT(mkfr);
-    return mkfr->track_id;
+    DP_ASSERT(mfg);
+    return mfg->y2;
 }
 
-uint16_t DP_msg_key_frame_retitle_frame_index(const DP_MsgKeyFrameRetitle *mkfr)
+int32_t DP_msg_fill_gradient_radius(const DP_MsgFillGradient *mfg)
 {
-    DP_ASSERT(mkfr);
-    return mkfr->frame_index;
+    DP_ASSERT(mfg);
+    return mfg->radius;
 }
 
-const char *DP_msg_key_frame_retitle_title(const DP_MsgKeyFrameRetitle *mkfr,
-                                           size_t *out_len)
+const DP_FillGradientStop *
+DP_msg_fill_gradient_stops(const DP_MsgFillGradient *mfg, int *out_count)
 {
-    DP_ASSERT(mkfr);
-    if (out_len) {
-        *out_len = mkfr->title_len;
+    DP_ASSERT(mfg);
+    if (out_count) {
+        *out_count = mfg->stops_count;
     }
-    return mkfr->title;
+    return mfg->stops;
 }
 
-size_t DP_msg_key_frame_retitle_title_len(const DP_MsgKeyFrameRetitle *mkfr)
+int DP_msg_fill_gradient_stops_count(const DP_MsgFillGradient *mfg)
 {
-    return mkfr->title_len;
+    return mfg->stops_count;
 }
 
 
-/* DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES */
+/* DP_MSG_ANNOTATION_ORDER */
 
-struct DP_MsgKeyFrameLayerAttributes {
-    uint16_t track_id;
-    uint16_t frame_index;
-    uint16_t layers_count;
-    uint16_t layers[];
+const char *DP_msg_annotation_order_direction_variant_name(unsigned int value)
+{
+    switch (value) {
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE:
+        return "Raise";
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER:
+        return "Lower";
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT:
+        return "Front";
+    case DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK:
+        return "Back";
+    default:
+        return NULL;
+    }
+}
+
+struct DP_MsgAnnotationOrder {
+    uint16_t id;
+    uint8_t direction;
 };
 
-static size_t msg_key_frame_layer_attributes_payload_length(DP_Message *msg)
+static size_t msg_annotation_order_payload_length(DP_UNUSED DP_Message *msg)
 {
-    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
-    return ((size_t)4) + DP_int_to_size(mkfla->layers_count) * 2;
+    return ((size_t)3);
 }
 
-static size_t
-msg_key_frame_layer_attributes_serialize_payload(DP_Message *msg,
-                                                 unsigned char *data)
+static size_t msg_annotation_order_serialize_payload(DP_Message *msg,
+                                                     unsigned char *data)
 {
-    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
+    DP_MsgAnnotationOrder *mao = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mkfla->track_id, data + written);
-    written += DP_write_bigendian_uint16(mkfla->frame_index, data + written);
-    written += DP_write_bigendian_uint16_array(
-        mkfla->layers, mkfla->layers_count, data + written);
-    DP_ASSERT(written == msg_key_frame_layer_attributes_payload_length(msg));
+    written += DP_write_bigendian_uint16(mao->id, data + written);
+    written += DP_write_bigendian_uint8(mao->direction, data + written);
+    DP_ASSERT(written == msg_annotation_order_payload_length(msg));
     return written;
 }
 
-static bool
-msg_key_frame_layer_attributes_write_payload_text(DP_Message *msg,
-                                                  DP_TextWriter *writer)
+static bool msg_annotation_order_write_payload_text(DP_Message *msg,
+                                                    DP_TextWriter *writer)
 {
-    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "frame_index", mkfla->frame_index,
-                                     false)
-        && DP_text_writer_write_uint16_list(writer, "layers", mkfla->layers,
-                                            mkfla->layers_count, true)
-        && DP_text_writer_write_uint(writer, "track_id", mkfla->track_id, true);
+    DP_MsgAnnotationOrder *mao = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "direction", mao->direction, false)
+        && DP_text_writer_write_uint(writer, "id", mao->id, true);
 }
 
-static bool msg_key_frame_layer_attributes_equals(DP_Message *DP_RESTRICT msg,
-                                                  DP_Message *DP_RESTRICT other)
+static bool msg_annotation_order_equals(DP_Message *DP_RESTRICT msg,
+                                        DP_Message *DP_RESTRICT other)
 {
-    DP_MsgKeyFrameLayerAttributes *a = DP_message_internal(msg);
-    DP_MsgKeyFrameLayerAttributes *b = DP_message_internal(other);
-    return a->track_id == b->track_id && a->frame_index == b->frame_index
-        && a->layers_count == b->layers_count
-        && memcmp(a->layers, b->layers, DP_uint16_to_size(a->layers_count) * 2)
-               == 0;
+    DP_MsgAnnotationOrder *a = DP_message_internal(msg);
+    DP_MsgAnnotationOrder *b = DP_message_internal(other);
+    return a->id == b->id && a->direction == b->direction;
 }
 
-static const DP_MessageMethods msg_key_frame_layer_attributes_methods = {
-    msg_key_frame_layer_attributes_payload_length,
-    msg_key_frame_layer_attributes_serialize_payload,
-    msg_key_frame_layer_attributes_write_payload_text,
-    msg_key_frame_layer_attributes_equals,
+static const DP_MessageMethods msg_annotation_order_methods = {
+    msg_annotation_order_payload_length,
+    msg_annotation_order_serialize_payload,
+    msg_annotation_order_write_payload_text,
+    msg_annotation_order_equals,
 };
 
-DP_Message *DP_msg_key_frame_layer_attributes_new(
-    unsigned int context_id, uint16_t track_id, uint16_t frame_index,
-    void (*set_layers)(int, uint16_t *, void *), int layers_count,
-    void *layers_user)
+DP_Message *DP_msg_annotation_order_new(unsigned int context_id, uint16_t id,
+                                        uint8_t direction)
 {
-    DP_Message *msg =
-        DP_message_new(DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES, context_id,
-                       &msg_key_frame_layer_attributes_methods,
-                       DP_FLEX_SIZEOF(DP_MsgKeyFrameLayerAttributes, layers,
-                                      DP_int_to_size(layers_count) * 2));
-    DP_MsgKeyFrameLayerAttributes *mkfla = DP_message_internal(msg);
-    mkfla->track_id = track_id;
-    mkfla->frame_index = frame_index;
-    mkfla->layers_count = DP_int_to_uint16(layers_count);
-    if (set_layers) {
-        set_layers(mkfla->layers_count, mkfla->layers, layers_user);
-    }
+    DP_Message *msg = DP_message_new(DP_MSG_ANNOTATION_ORDER, context_id,
+                                     &msg_annotation_order_methods,
+                                     sizeof(DP_MsgAnnotationOrder));
+    DP_MsgAnnotationOrder *mao = DP_message_internal(msg);
+    mao->id = id;
+    mao->direction = direction;
     return msg;
 }
 
-DP_Message *DP_msg_key_frame_layer_attributes_deserialize(
-    unsigned int context_id, const unsigned char *buffer, size_t length)
+DP_Message *DP_msg_annotation_order_deserialize(unsigned int context_id,
+                                                const unsigned char *buffer,
+                                                size_t length)
 {
-    if (length < 4 || length > 65535) {
-        DP_error_set("Wrong length for keyframelayerattributes message; "
-                     "expected between 4 and 65535, got %zu",
+    if (length != 3) {
+        DP_error_set("Wrong length for orderannotation message; "
+                     "expected 3, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t track_id = read_uint16(buffer + read, &read);
-    uint16_t frame_index = read_uint16(buffer + read, &read);
-    size_t layers_bytes = length - read;
-    if ((layers_bytes % 2) != 0) {
-        DP_error_set(
-            "Wrong length for layers field in keyframelayerattributes message; "
-            "%zu not divisible by 2",
-            layers_bytes);
-        return NULL;
-    }
-    uint16_t layers_count = DP_size_to_uint16(layers_bytes / 2);
-    void *layers_user = (void *)(buffer + read);
-    return DP_msg_key_frame_layer_attributes_new(context_id, track_id,
-                                                 frame_index, read_uint16_array,
-                                                 layers_count, layers_user);
-}
-
-DP_Message *DP_msg_key_frame_layer_attributes_parse(unsigned int context_id,
-                                                    DP_TextReader *reader)
-{
-    uint16_t track_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
-    uint16_t frame_index =
-        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
-    int layers_count;
-    DP_TextReaderParseParams layers_params =
-        DP_text_reader_get_comma_separated(reader, "layers", &layers_count);
-    return DP_msg_key_frame_layer_attributes_new(
-        context_id, track_id, frame_index,
-        DP_text_reader_parse_uint16_array_hex, layers_count, &layers_params);
-}
-
-DP_MsgKeyFrameLayerAttributes *
-DP_msg_key_frame_layer_attributes_cast(DP_Message *msg)
-{
-    return DP_message_cast(msg, DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES);
+    uint16_t id = read_uint16(buffer + read, &read);
+    uint8_t direction = read_uint8(buffer + read, &read);
+    return DP_msg_annotation_order_new(context_id, id, direction);
 }
 
-uint16_t DP_msg_key_frame_layer_attributes_track_id(
-    const DP_MsgKeyFrameLayerAttributes *mkfla)
+DP_Message *DP_msg_annotation_order_parse(unsigned int context_id,
+                                          DP_TextReader *reader)
 {
-    DP_ASSERT(mkfla);
-    return mkfla->track_id;
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    uint8_t direction =
+        (uint8_t)DP_text_reader_get_ulong(reader, "direction", UINT8_MAX);
+    return DP_msg_annotation_order_new(context_id, id, direction);
 }
 
-uint16_t DP_msg_key_frame_layer_attributes_frame_index(
-    const DP_MsgKeyFrameLayerAttributes *mkfla)
+DP_MsgAnnotationOrder *DP_msg_annotation_order_cast(DP_Message *msg)
 {
-    DP_ASSERT(mkfla);
-    return mkfla->frame_index;
+    return DP_message_cast(msg, DP_MSG_ANNOTATION_ORDER);
 }
 
-const uint16_t *DP_msg_key_frame_layer_attributes_layers(
-    const DP_MsgKeyFrameLayerAttributes *mkfla, int *out_count)
+uint16_t DP_msg_annotation_order_id(const DP_MsgAnnotationOrder *mao)
 {
-    DP_ASSERT(mkfla);
-    if (out_count) {
-        *out_count = mkfla->layers_count;
-    }
-    return mkfla->layers;
+    DP_ASSERT(mao);
+    return mao->id;
 }
 
-int DP_msg_key_frame_layer_attributes_layers_count(
-    const DP_MsgKeyFrameLayerAttributes *mkfla)
+uint8_t DP_msg_annotation_order_direction(const DP_MsgAnnotationOrder *mao)
 {
-    return mkfla->layers_count;
+    DP_ASSERT(mao);
+    return mao->direction;
 }
 
 
-/* DP_MSG_KEY_FRAME_DELETE */
+/* DP_MSG_LAYER_METADATA */
 
-struct DP_MsgKeyFrameDelete {
-    uint16_t track_id;
-    uint16_t frame_index;
-    uint16_t move_track_id;
-    uint16_t move_frame_index;
+struct DP_MsgLayerMetadata {
+    uint16_t id;
+    uint8_t color_tag;
+    uint16_t metadata_size;
+    unsigned char metadata[];
 };
 
-static size_t msg_key_frame_delete_payload_length(DP_UNUSED DP_Message *msg)
+static size_t msg_layer_metadata_payload_length(DP_Message *msg)
 {
-    return ((size_t)8);
+    DP_MsgLayerMetadata *mlm = DP_message_internal(msg);
+    return ((size_t)3) + mlm->metadata_size;
 }
 
-static size_t msg_key_frame_delete_serialize_payload(DP_Message *msg,
-                                                     unsigned char *data)
+static size_t msg_layer_metadata_serialize_payload(DP_Message *msg,
+                                                   unsigned char *data)
 {
-    DP_MsgKeyFrameDelete *mkfd = DP_message_internal(msg);
+    DP_MsgLayerMetadata *mlm = DP_message_internal(msg);
     size_t written = 0;
-    written += DP_write_bigendian_uint16(mkfd->track_id, data + written);
-    written += DP_write_bigendian_uint16(mkfd->frame_index, data + written);
-    written += DP_write_bigendian_uint16(mkfd->move_track_id, data + written);
-    written +=
-        DP_write_bigendian_uint16(mkfd->move_frame_index, data + written);
-    DP_ASSERT(written == msg_key_frame_delete_payload_length(msg));
+    written += DP_write_bigendian_uint16(mlm->id, data + written);
+    written += DP_write_bigendian_uint8(mlm->color_tag, data + written);
+    written += write_bytes(mlm->metadata, mlm->metadata_size, data + written);
+    DP_ASSERT(written == msg_layer_metadata_payload_length(msg));
     return written;
 }
 
-static bool msg_key_frame_delete_write_payload_text(DP_Message *msg,
-                                                    DP_TextWriter *writer)
+static bool msg_layer_metadata_write_payload_text(DP_Message *msg,
+                                                  DP_TextWriter *writer)
 {
-    DP_MsgKeyFrameDelete *mkfd = DP_message_internal(msg);
-    return DP_text_writer_write_uint(writer, "frame_index", mkfd->frame_index,
-                                     false)
-        && DP_text_writer_write_uint(writer, "move_frame_index",
-                                     mkfd->move_frame_index, false)
-        && DP_text_writer_write_uint(writer, "move_track_id",
-                                     mkfd->move_track_id, true)
-        && DP_text_writer_write_uint(writer, "track_id", mkfd->track_id, true);
+    DP_MsgLayerMetadata *mlm = DP_message_internal(msg);
+    return DP_text_writer_write_uint(writer, "color_tag", mlm->color_tag, false)
+        && DP_text_writer_write_uint(writer, "id", mlm->id, true)
+        && DP_text_writer_write_base64(writer, "metadata", mlm->metadata,
+                                       mlm->metadata_size);
 }
 
-static bool msg_key_frame_delete_equals(DP_Message *DP_RESTRICT msg,
-                                        DP_Message *DP_RESTRICT other)
+static bool msg_layer_metadata_equals(DP_Message *DP_RESTRICT msg,
+                                      DP_Message *DP_RESTRICT other)
 {
-    DP_MsgKeyFrameDelete *a = DP_message_internal(msg);
-    DP_MsgKeyFrameDelete *b = DP_message_internal(other);
-    return a->track_id == b->track_id && a->frame_index == b->frame_index
-        && a->move_track_id == b->move_track_id
-        && a->move_frame_index == b->move_frame_index;
+    DP_MsgLayerMetadata *a = DP_message_internal(msg);
+    DP_MsgLayerMetadata *b = DP_message_internal(other);
+    return a->id == b->id && a->color_tag == b->color_tag
+        && a->metadata_size == b->metadata_size
+        && memcmp(a->metadata, b->metadata,
+                  DP_uint16_to_size(a->metadata_size))
+               == 0;
 }
 
-static const DP_MessageMethods msg_key_frame_delete_methods = {
-    msg_key_frame_delete_payload_length,
-    msg_key_frame_delete_serialize_payload,
-    msg_key_frame_delete_write_payload_text,
-    msg_key_frame_delete_equals,
+static const DP_MessageMethods msg_layer_metadata_methods = {
+    msg_layer_metadata_payload_length,
+    msg_layer_metadata_serialize_payload,
+    msg_layer_metadata_write_payload_text,
+    msg_layer_metadata_equals,
 };
 
-DP_Message *DP_msg_key_frame_delete_new(unsigned int context_id,
-                                        uint16_t track_id, uint16_t frame_index,
-                                        uint16_t move_track_id,
-                                        uint16_t move_frame_index)
+DP_Message *DP_msg_layer_metadata_new(
+    unsigned int context_id, uint16_t id, uint8_t color_tag,
+    void (*set_metadata)(size_t, unsigned char *, void *),
+    size_t metadata_size, void *metadata_user)
 {
-    DP_Message *msg = DP_message_new(DP_MSG_KEY_FRAME_DELETE, context_id,
-                                     &msg_key_frame_delete_methods,
-                                     sizeof(DP_MsgKeyFrameDelete));
-    DP_MsgKeyFrameDelete *mkfd = DP_message_internal(msg);
-    mkfd->track_id = track_id;
-    mkfd->frame_index = frame_index;
-    mkfd->move_track_id = move_track_id;
-    mkfd->move_frame_index = move_frame_index;
+    DP_Message *msg = DP_message_new(
+        DP_MSG_LAYER_METADATA, context_id, &msg_layer_metadata_methods,
+        DP_FLEX_SIZEOF(DP_MsgLayerMetadata, metadata, metadata_size));
+    DP_MsgLayerMetadata *mlm = DP_message_internal(msg);
+    mlm->id = id;
+    mlm->color_tag = color_tag;
+    mlm->metadata_size = DP_size_to_uint16(metadata_size);
+    if (set_metadata) {
+        set_metadata(mlm->metadata_size, mlm->metadata, metadata_user);
+    }
     return msg;
 }
 
-DP_Message *DP_msg_key_frame_delete_deserialize(unsigned int context_id,
-                                                const unsigned char *buffer,
-                                                size_t length)
+DP_Message *DP_msg_layer_metadata_deserialize(unsigned int context_id,
+                                              const unsigned char *buffer,
+                                              size_t length)
 {
-    if (length != 8) {
-        DP_error_set("Wrong length for keyframedelete message; "
-                     "expected 8, got %zu",
+    if (length < 3 || length > 65535) {
+        DP_error_set("Wrong length for layermeta message; "
+                     "expected between 3 and 65535, got %zu",
                      length);
         return NULL;
     }
     size_t read = 0;
-    uint16_t track_id = read_uint16(buffer + read, &read);
-    uint16_t frame_index = read_uint16(buffer + read, &read);
-    uint16_t move_track_id = read_uint16(buffer + read, &read);
-    uint16_t move_frame_index = read_uint16(buffer + read, &read);
-    return DP_msg_key_frame_delete_new(context_id, track_id, frame_index,
-                                       move_track_id, move_frame_index);
+    uint16_t id = read_uint16(buffer + read, &read);
+    uint8_t color_tag = read_uint8(buffer + read, &read);
+    size_t metadata_bytes = length - read;
+    uint16_t metadata_size = DP_size_to_uint16(metadata_bytes);
+    void *metadata_user = (void *)(buffer + read);
+    return DP_msg_layer_metadata_new(context_id, id, color_tag, read_bytes,
+                                     metadata_size, metadata_user);
 }
 
-DP_Message *DP_msg_key_frame_delete_parse(unsigned int context_id,
-                                          DP_TextReader *reader)
+DP_Message *DP_msg_layer_metadata_parse(unsigned int context_id,
+                                        DP_TextReader *reader)
 {
-    uint16_t track_id =
-        (uint16_t)DP_text_reader_get_ulong_hex(reader, "track_id", UINT16_MAX);
-    uint16_t frame_index =
-        (uint16_t)DP_text_reader_get_ulong(reader, "frame_index", UINT16_MAX);
-    uint16_t move_track_id = (uint16_t)DP_text_reader_get_ulong_hex(
-        reader, "move_track_id", UINT16_MAX);
-    uint16_t move_frame_index = (uint16_t)DP_text_reader_get_ulong(
-        reader, "move_frame_index", UINT16_MAX);
-    return DP_msg_key_frame_delete_new(context_id, track_id, frame_index,
-                                       move_track_id, move_frame_index);
+    uint16_t id =
+        (uint16_t)DP_text_reader_get_ulong_hex(reader, "id", UINT16_MAX);
+    uint8_t color_tag =
+        (uint8_t)DP_text_reader_get_ulong(reader, "color_tag", UINT8_MAX);
+    size_t metadata_size;
+    DP_TextReaderParseParams metadata_params =
+        DP_text_reader_get_base64_string(reader, "metadata", &metadata_size);
+    return DP_msg_layer_metadata_new(context_id, id, color_tag,
+                                     DP_text_reader_parse_base64,
+                                     metadata_size, &metadata_params);
 }
 
-DP_MsgKeyFrameDelete *DP_msg_key_frame_delete_cast(DP_Message *msg)
+DP_MsgLayerMetadata *DP_msg_layer_metadata_cast(DP_Message *msg)
 {
-    return DP_message_cast(msg, DP_MSG_KEY_FRAME_DELETE);
+    return DP_message_cast(msg, DP_MSG_LAYER_METADATA);
 }
 
-uint16_t DP_msg_key_frame_delete_track_id(const DP_MsgKeyFrameDelete *mkfd)
+uint16_t DP_msg_layer_metadata_id(const DP_MsgLayerMetadata *mlm)
 {
-    DP_ASSERT(mkfd);
-    return mkfd->track_id;
+    DP_ASSERT(mlm);
+    return mlm->id;
 }
 
-uint16_t DP_msg_key_frame_delete_frame_index(const DP_MsgKeyFrameDelete *mkfd)
+uint8_t DP_msg_layer_metadata_color_tag(const DP_MsgLayerMetadata *mlm)
 {
-    DP_ASSERT(mkfd);
-    return mkfd->frame_index;
+    DP_ASSERT(mlm);
+    return mlm->color_tag;
 }
 
-uint16_t DP_msg_key_frame_delete_move_track_id(const DP_MsgKeyFrameDelete *mkfd)
+const unsigned char *
+DP_msg_layer_metadata_metadata(const DP_MsgLayerMetadata *mlm,
+                               size_t *out_size)
 {
-    DP_ASSERT(mkfd);
-    return mkfd->move_track_id;
+    DP_ASSERT(mlm);
+    if (out_size) {
+        *out_size = mlm->metadata_size;
+    }
+    return mlm->metadata;
 }
 
-uint16_t
-DP_msg_key_frame_delete_move_frame_index(const DP_MsgKeyFrameDelete *mkfd)
+size_t DP_msg_layer_metadata_metadata_size(const DP_MsgLayerMetadata *mlm)
 {
-    DP_ASSERT(mkfd);
-    return mkfd->move_frame_index;
+    return mlm->metadata_size;
 }
 
 
@@ -30,8 +30,8 @@ typedef struct DP_TextWriter DP_TextWriter;
 #define DP_PROTOCOL_VERSION_NAMESPACE "dp"
 #define DP_PROTOCOL_VERSION_SERVER    4
 #define DP_PROTOCOL_VERSION_MAJOR     24
-#define DP_PROTOCOL_VERSION_MINOR     0
-#define DP_PROTOCOL_VERSION           "dp:4.24.0"
+#define DP_PROTOCOL_VERSION_MINOR     1
+#define DP_PROTOCOL_VERSION           "dp:4.24.1"
 #define DP_UNDO_DEPTH_DEFAULT         30
 
 typedef struct DP_MessageMethods {
@@ -91,6 +91,9 @@ typedef enum DP_MessageType {
     DP_MSG_DRAW_DABS_PIXEL = 149,
     DP_MSG_DRAW_DABS_PIXEL_SQUARE = 150,
     DP_MSG_DRAW_DABS_MYPAINT = 151,
+    DP_MSG_STAMP_MASK = 152,
+    DP_MSG_DRAW_DABS_STAMP = 153,
+    DP_MSG_DRAW_DABS_SOFT = 154,
     DP_MSG_MOVE_RECT = 160,
     DP_MSG_SET_METADATA_INT = 161,
     DP_MSG_LAYER_TREE_CREATE = 162,
@@ -105,6 +108,10 @@ typedef enum DP_MessageType {
     DP_MSG_KEY_FRAME_RETITLE = 171,
     DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES = 172,
     DP_MSG_KEY_FRAME_DELETE = 173,
+    DP_MSG_FILTER_REGION = 174,
+    DP_MSG_FILL_GRADIENT = 175,
+    DP_MSG_ANNOTATION_ORDER = 176,
+    DP_MSG_LAYER_METADATA = 177,
     DP_MSG_UNDO = 255,
     DP_MSG_TYPE_COUNT,
 } DP_MessageType;
@@ -303,8 +310,8 @@ DP_Message *DP_msg_keep_alive_parse(unsigned int context_id,
 
 const char *DP_msg_join_flags_flag_name(unsigned int value);
 
-#define DP_MSG_JOIN_NAME_MIN_LEN 1
-#define DP_MSG_JOIN_NAME_MAX_LEN 256
+#define DP_MSG_JOIN_NAME_MIN_LEN 0
+#define DP_MSG_JOIN_NAME_MAX_LEN 255
 
 #define DP_MSG_JOIN_AVATAR_MIN_SIZE 0
 #define DP_MSG_JOIN_AVATAR_MAX_SIZE 65533
@@ -1160,14 +1167,16 @@ size_t DP_msg_layer_create_title_len(const DP_MsgLayerCreate *mlc);
 
 #define DP_MSG_LAYER_ATTRIBUTES_STATIC_LENGTH 6
 
-#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR   0x1
-#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED    0x2
-#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED 0x4
+#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR     0x1
+#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED      0x2
+#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED   0x4
+#define DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK 0x8
 
-#define DP_MSG_LAYER_ATTRIBUTES_NUM_FLAGS 3
+#define DP_MSG_LAYER_ATTRIBUTES_NUM_FLAGS 4
 #define DP_MSG_LAYER_ATTRIBUTES_ALL_FLAGS                                      \
     DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR, DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED, \
-        DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED
+        DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED,                                \
+        DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK
 
 const char *DP_msg_layer_attributes_flags_flag_name(unsigned int value);
 
@@ -1474,8 +1483,9 @@ DP_Message *DP_msg_pen_up_parse(unsigned int context_id, DP_TextReader *reader);
  *
  * Create a new annotation
  *
- * Annotations are floating text layers. They are drawn over the image layers
- * and have no defined stacking order.
+ * Annotations are floating text layers. They are drawn over the image layers,
+ * in list order, so later annotations are on top. New annotations get added
+ * on top of the existing ones, the AnnotationOrder command rearranges them.
  *
  * The new annotation created with this command is initally empy with a
  * transparent background
@@ -1552,6 +1562,10 @@ uint16_t DP_msg_annotation_reshape_h(const DP_MsgAnnotationReshape *mar);
  *
  * If an annotation is flagged as protected, it cannot be modified by users
  * other than the one who created it, or session operators.
+ *
+ * The auto_grow flag tells the client editing the annotation to fit its
+ * height to the content. Since only the client can lay out the text, it
+ * does so by sending a reshape along with the edit.
  */
 
 #define DP_MSG_ANNOTATION_EDIT_STATIC_LENGTH 8
@@ -1559,12 +1573,14 @@ uint16_t DP_msg_annotation_reshape_h(const DP_MsgAnnotationReshape *mar);
 #define DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT       0x1
 #define DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER 0x2
 #define DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM 0x4
+#define DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW     0x8
 
-#define DP_MSG_ANNOTATION_EDIT_NUM_FLAGS 3
+#define DP_MSG_ANNOTATION_EDIT_NUM_FLAGS 4
 #define DP_MSG_ANNOTATION_EDIT_ALL_FLAGS            \
     DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT,           \
         DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER, \
-        DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM
+        DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM, \
+        DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW
 
 const char *DP_msg_annotation_edit_flags_flag_name(unsigned int value);
 
@@ -2041,6 +2057,217 @@ DP_msg_draw_dabs_mypaint_dabs(const DP_MsgDrawDabsMyPaint *mddmp,
 int DP_msg_draw_dabs_mypaint_dabs_count(const DP_MsgDrawDabsMyPaint *mddmp);
 
 
+/*
+ * DP_MSG_STAMP_MASK
+ *
+ * Define a mask for stamp brush dabs
+ *
+ * The mask is a square 8 bit alpha image of size*size pixels, at most
+ * 256 pixels across, DEFLATEd the same way as the mask in moveregion.
+ * It is identified by its checksum, which stampdabs refer to. Clients
+ * send this before every stroke that uses the mask, so that
+ * collaborators that joined later also know about it. Defining a
+ * mask that is already known does nothing.
+ */
+
+#define DP_MSG_STAMP_MASK_STATIC_LENGTH 6
+
+#define DP_MSG_STAMP_MASK_IMAGE_MIN_SIZE 0
+#define DP_MSG_STAMP_MASK_IMAGE_MAX_SIZE 65529
+
+typedef struct DP_MsgStampMask DP_MsgStampMask;
+
+DP_Message *DP_msg_stamp_mask_new(unsigned int context_id, uint32_t mask,
+                                  uint16_t size,
+                                  void (*set_image)(size_t, unsigned char *,
+                                                    void *),
+                                  size_t image_size, void *image_user);
+
+DP_Message *DP_msg_stamp_mask_deserialize(unsigned int context_id,
+                                          const unsigned char *buffer,
+                                          size_t length);
+
+DP_Message *DP_msg_stamp_mask_parse(unsigned int context_id,
+                                    DP_TextReader *reader);
+
+DP_MsgStampMask *DP_msg_stamp_mask_cast(DP_Message *msg);
+
+uint32_t DP_msg_stamp_mask_mask(const DP_MsgStampMask *msm);
+
+uint16_t DP_msg_stamp_mask_size(const DP_MsgStampMask *msm);
+
+const unsigned char *DP_msg_stamp_mask_image(const DP_MsgStampMask *msm,
+                                             size_t *out_size);
+
+size_t DP_msg_stamp_mask_image_size(const DP_MsgStampMask *msm);
+
+
+/*
+ * DP_MSG_DRAW_DABS_STAMP
+ *
+ * Draw stamp brush dabs
+ *
+ * The same kind of delta compression is used as in classicdabs. The
+ * mask field refers to a mask previously defined with stampmask,
+ * which gets scaled to the size of each dab and rotated by its
+ * angle, where 256 would be a full turn. Dabs with an unknown mask
+ * are not drawn. The grain fields work the same as in softdabs.
+ */
+
+#define DP_MSG_DRAW_DABS_STAMP_STATIC_LENGTH 26
+
+#define DP_MSG_DRAW_DABS_STAMP_DABS_MIN_COUNT 1
+#define DP_MSG_DRAW_DABS_STAMP_DABS_MAX_COUNT 10918
+
+#define DP_MSG_DRAW_DABS_STAMP_DABS_MAX 10918
+
+typedef struct DP_StampDab DP_StampDab;
+
+void DP_stamp_dab_init(DP_StampDab *sds, int i, int8_t x, int8_t y,
+                       uint16_t size, uint8_t angle, uint8_t opacity);
+
+int8_t DP_stamp_dab_x(const DP_StampDab *sd);
+
+int8_t DP_stamp_dab_y(const DP_StampDab *sd);
+
+uint16_t DP_stamp_dab_size(const DP_StampDab *sd);
+
+uint8_t DP_stamp_dab_angle(const DP_StampDab *sd);
+
+uint8_t DP_stamp_dab_opacity(const DP_StampDab *sd);
+
+const DP_StampDab *DP_stamp_dab_at(const DP_StampDab *sd, int i);
+
+
+typedef struct DP_MsgDrawDabsStamp DP_MsgDrawDabsStamp;
+
+DP_Message *
+DP_msg_draw_dabs_stamp_new(unsigned int context_id, uint16_t layer, int32_t x,
+                           int32_t y, uint32_t color, uint8_t mode,
+                           uint32_t mask, uint32_t grain, uint16_t grainscale,
+                           uint8_t grainstrength,
+                           void (*set_dabs)(int, DP_StampDab *, void *),
+                           int dabs_count, void *dabs_user);
+
+DP_Message *DP_msg_draw_dabs_stamp_deserialize(unsigned int context_id,
+                                               const unsigned char *buffer,
+                                               size_t length);
+
+DP_Message *DP_msg_draw_dabs_stamp_parse(unsigned int context_id,
+                                         DP_TextReader *reader);
+
+DP_MsgDrawDabsStamp *DP_msg_draw_dabs_stamp_cast(DP_Message *msg);
+
+uint16_t DP_msg_draw_dabs_stamp_layer(const DP_MsgDrawDabsStamp *mdds);
+
+int32_t DP_msg_draw_dabs_stamp_x(const DP_MsgDrawDabsStamp *mdds);
+
+int32_t DP_msg_draw_dabs_stamp_y(const DP_MsgDrawDabsStamp *mdds);
+
+uint32_t DP_msg_draw_dabs_stamp_color(const DP_MsgDrawDabsStamp *mdds);
+
+uint8_t DP_msg_draw_dabs_stamp_mode(const DP_MsgDrawDabsStamp *mdds);
+
+uint32_t DP_msg_draw_dabs_stamp_mask(const DP_MsgDrawDabsStamp *mdds);
+
+uint32_t DP_msg_draw_dabs_stamp_grain(const DP_MsgDrawDabsStamp *mdds);
+
+uint16_t DP_msg_draw_dabs_stamp_grainscale(const DP_MsgDrawDabsStamp *mdds);
+
+uint8_t DP_msg_draw_dabs_stamp_grainstrength(const DP_MsgDrawDabsStamp *mdds);
+
+const DP_StampDab *DP_msg_draw_dabs_stamp_dabs(const DP_MsgDrawDabsStamp *mdds,
+                                               int *out_count);
+
+int DP_msg_draw_dabs_stamp_dabs_count(const DP_MsgDrawDabsStamp *mdds);
+
+
+/*
+ * DP_MSG_DRAW_DABS_SOFT
+ *
+ * Draw classic brush dabs with a falloff profile or grain
+ *
+ * Works the same as classicdabs, with additional fields for things
+ * that it can't express. The falloff field is the profile of the
+ * dabs' hardness falloff: 0 is the classic ramp, 1 is gaussian and 2
+ * is smoothstep. The grain field refers to a mask previously defined
+ * with stampmask, which gets tiled across the canvas and multiplied
+ * into the coverage of the dabs. The grainscale field is the size of
+ * a grain pixel in canvas pixels multiplied by 256 and the
+ * grainstrength field how much of the coverage the grain can take
+ * away, 255 being all of it. A grain of zero or an unknown one means
+ * no grain. Clients send classicdabs for a classic ramp without
+ * grain, so this is only used when needed.
+ */
+
+#define DP_MSG_DRAW_DABS_SOFT_STATIC_LENGTH 23
+
+#define DP_MSG_DRAW_DABS_SOFT_DABS_MIN_COUNT 1
+#define DP_MSG_DRAW_DABS_SOFT_DABS_MAX_COUNT 10918
+
+#define DP_MSG_DRAW_DABS_SOFT_DABS_MAX 10918
+
+typedef struct DP_SoftDab DP_SoftDab;
+
+void DP_soft_dab_init(DP_SoftDab *sds, int i, int8_t x, int8_t y, uint16_t size,
+                      uint8_t hardness, uint8_t opacity);
+
+int8_t DP_soft_dab_x(const DP_SoftDab *sd);
+
+int8_t DP_soft_dab_y(const DP_SoftDab *sd);
+
+uint16_t DP_soft_dab_size(const DP_SoftDab *sd);
+
+uint8_t DP_soft_dab_hardness(const DP_SoftDab *sd);
+
+uint8_t DP_soft_dab_opacity(const DP_SoftDab *sd);
+
+const DP_SoftDab *DP_soft_dab_at(const DP_SoftDab *sd, int i);
+
+
+typedef struct DP_MsgDrawDabsSoft DP_MsgDrawDabsSoft;
+
+DP_Message *
+DP_msg_draw_dabs_soft_new(unsigned int context_id, uint16_t layer, int32_t x,
+                          int32_t y, uint32_t color, uint8_t mode,
+                          uint8_t falloff, uint32_t grain, uint16_t grainscale,
+                          uint8_t grainstrength,
+                          void (*set_dabs)(int, DP_SoftDab *, void *),
+                          int dabs_count, void *dabs_user);
+
+DP_Message *DP_msg_draw_dabs_soft_deserialize(unsigned int context_id,
+                                              const unsigned char *buffer,
+                                              size_t length);
+
+DP_Message *DP_msg_draw_dabs_soft_parse(unsigned int context_id,
+                                        DP_TextReader *reader);
+
+DP_MsgDrawDabsSoft *DP_msg_draw_dabs_soft_cast(DP_Message *msg);
+
+uint16_t DP_msg_draw_dabs_soft_layer(const DP_MsgDrawDabsSoft *mdds);
+
+int32_t DP_msg_draw_dabs_soft_x(const DP_MsgDrawDabsSoft *mdds);
+
+int32_t DP_msg_draw_dabs_soft_y(const DP_MsgDrawDabsSoft *mdds);
+
+uint32_t DP_msg_draw_dabs_soft_color(const DP_MsgDrawDabsSoft *mdds);
+
+uint8_t DP_msg_draw_dabs_soft_mode(const DP_MsgDrawDabsSoft *mdds);
+
+uint8_t DP_msg_draw_dabs_soft_falloff(const DP_MsgDrawDabsSoft *mdds);
+
+uint32_t DP_msg_draw_dabs_soft_grain(const DP_MsgDrawDabsSoft *mdds);
+
+uint16_t DP_msg_draw_dabs_soft_grainscale(const DP_MsgDrawDabsSoft *mdds);
+
+uint8_t DP_msg_draw_dabs_soft_grainstrength(const DP_MsgDrawDabsSoft *mdds);
+
+const DP_SoftDab *DP_msg_draw_dabs_soft_dabs(const DP_MsgDrawDabsSoft *mdds,
+                                             int *out_count);
+
+int DP_msg_draw_dabs_soft_dabs_count(const DP_MsgDrawDabsSoft *mdds);
+
+
 /*
  * DP_MSG_MOVE_RECT
  *
@@ -2321,10 +2548,13 @@ uint16_t DP_msg_layer_tree_delete_merge_to(const DP_MsgLayerTreeDelete *mltd);
 
 #define DP_MSG_TRANSFORM_REGION_MODE_NEAREST  0
 #define DP_MSG_TRANSFORM_REGION_MODE_BILINEAR 1
+#define DP_MSG_TRANSFORM_REGION_MODE_BICUBIC  2
 
-#define DP_MSG_TRANSFORM_REGION_NUM_MODE 2
-#define DP_MSG_TRANSFORM_REGION_ALL_MODE \
-    DP_MSG_TRANSFORM_REGION_MODE_NEAREST, DP_MSG_TRANSFORM_REGION_MODE_BILINEAR
+#define DP_MSG_TRANSFORM_REGION_NUM_MODE 3
+#define DP_MSG_TRANSFORM_REGION_ALL_MODE       \
+    DP_MSG_TRANSFORM_REGION_MODE_NEAREST,      \
+        DP_MSG_TRANSFORM_REGION_MODE_BILINEAR, \
+        DP_MSG_TRANSFORM_REGION_MODE_BICUBIC
 
 const char *DP_msg_transform_region_mode_variant_name(unsigned int value);
 
@@ -2700,6 +2930,279 @@ uint16_t
 DP_msg_key_frame_delete_move_frame_index(const DP_MsgKeyFrameDelete *mkfd);
 
 
+/*
+ * DP_MSG_FILTER_REGION
+ *
+ * Apply a filter to a rectangle of a layer.
+ *
+ * The rectangle gets clipped to the canvas. Blank tiles are left
+ * alone unless the filter turns transparent pixels into visible
+ * ones. What the parameters mean depends on the filter, fractional
+ * values are given in units of 1/65536:
+ *
+ * * `BrightnessContrast`: brightness offset, contrast factor.
+ *
+ * * `Hsl`: hue shift in degrees, saturation factor, lightness offset.
+ *
+ * * `Invert`: 1 to invert alpha as well, 0 to leave it alone.
+ *
+ * Unused parameters should be 0.
+ */
+
+#define DP_MSG_FILTER_REGION_STATIC_LENGTH 35
+
+#define DP_MSG_FILTER_REGION_FILTER_BRIGHTNESS_CONTRAST 0
+#define DP_MSG_FILTER_REGION_FILTER_HSL                 1
+#define DP_MSG_FILTER_REGION_FILTER_INVERT              2
+
+#define DP_MSG_FILTER_REGION_NUM_FILTER 3
+#define DP_MSG_FILTER_REGION_ALL_FILTER              \
+    DP_MSG_FILTER_REGION_FILTER_BRIGHTNESS_CONTRAST, \
+        DP_MSG_FILTER_REGION_FILTER_HSL, DP_MSG_FILTER_REGION_FILTER_INVERT
+
+const char *DP_msg_filter_region_filter_variant_name(unsigned int value);
+
+typedef struct DP_MsgFilterRegion DP_MsgFilterRegion;
+
+DP_Message *DP_msg_filter_region_new(unsigned int context_id, uint16_t layer,
+                                     uint32_t x, uint32_t y, uint32_t w,
+                                     uint32_t h, uint8_t filter, int32_t param1,
+                                     int32_t param2, int32_t param3,
+                                     int32_t param4);
+
+DP_Message *DP_msg_filter_region_deserialize(unsigned int context_id,
+                                             const unsigned char *buffer,
+                                             size_t length);
+
+DP_Message *DP_msg_filter_region_parse(unsigned int context_id,
+                                       DP_TextReader *reader);
+
+DP_MsgFilterRegion *DP_msg_filter_region_cast(DP_Message *msg);
+
+uint16_t DP_msg_filter_region_layer(const DP_MsgFilterRegion *mfr);
+
+uint32_t DP_msg_filter_region_x(const DP_MsgFilterRegion *mfr);
+
+uint32_t DP_msg_filter_region_y(const DP_MsgFilterRegion *mfr);
+
+uint32_t DP_msg_filter_region_w(const DP_MsgFilterRegion *mfr);
+
+uint32_t DP_msg_filter_region_h(const DP_MsgFilterRegion *mfr);
+
+uint8_t DP_msg_filter_region_filter(const DP_MsgFilterRegion *mfr);
+
+int32_t DP_msg_filter_region_param1(const DP_MsgFilterRegion *mfr);
+
+int32_t DP_msg_filter_region_param2(const DP_MsgFilterRegion *mfr);
+
+int32_t DP_msg_filter_region_param3(const DP_MsgFilterRegion *mfr);
+
+int32_t DP_msg_filter_region_param4(const DP_MsgFilterRegion *mfr);
+
+
+/*
+ * DP_MSG_FILL_GRADIENT
+ *
+ * Fill a rectangle of a layer with a gradient.
+ *
+ * The rectangle gets clipped to the canvas. Linear gradients run
+ * from x1, y1 to x2, y2, radial ones from the center at x1, y1 out to
+ * the radius, the x2 and y2 fields are unused for those. These
+ * coordinates and the radius have 1/4 pixel resolution, the points
+ * may lie outside of the rectangle and the radius must not be
+ * negative. Pixels before the start and past the end get the color
+ * of the first or last stop respectively.
+ *
+ * The position of each stop is given in units of 1/65535 along the
+ * gradient. They must be in increasing order, but consecutive stops
+ * at the same position are allowed, which makes a hard edge. There
+ * must be at least one stop. The colors are interpolated in linear
+ * light and blended using the mode. If the dither flag is set,
+ * noise gets added to hide banding.
+ */
+
+#define DP_MSG_FILL_GRADIENT_STATIC_LENGTH 41
+
+#define DP_MSG_FILL_GRADIENT_SHAPE_LINEAR 0
+#define DP_MSG_FILL_GRADIENT_SHAPE_RADIAL 1
+
+#define DP_MSG_FILL_GRADIENT_NUM_SHAPE 2
+#define DP_MSG_FILL_GRADIENT_ALL_SHAPE \
+    DP_MSG_FILL_GRADIENT_SHAPE_LINEAR, DP_MSG_FILL_GRADIENT_SHAPE_RADIAL
+
+const char *DP_msg_fill_gradient_shape_variant_name(unsigned int value);
+
+#define DP_MSG_FILL_GRADIENT_FLAGS_DITHER 0x1
+
+#define DP_MSG_FILL_GRADIENT_NUM_FLAGS 1
+#define DP_MSG_FILL_GRADIENT_ALL_FLAGS DP_MSG_FILL_GRADIENT_FLAGS_DITHER
+
+const char *DP_msg_fill_gradient_flags_flag_name(unsigned int value);
+
+#define DP_MSG_FILL_GRADIENT_STOPS_MIN_COUNT 1
+#define DP_MSG_FILL_GRADIENT_STOPS_MAX_COUNT 10915
+
+#define DP_MSG_FILL_GRADIENT_STOPS_MAX 10915
+
+typedef struct DP_FillGradientStop DP_FillGradientStop;
+
+void DP_fill_gradient_stop_init(DP_FillGradientStop *fgss, int i,
+                                uint16_t position, uint32_t color);
+
+uint16_t DP_fill_gradient_stop_position(const DP_FillGradientStop *fgs);
+
+uint32_t DP_fill_gradient_stop_color(const DP_FillGradientStop *fgs);
+
+const DP_FillGradientStop *
+DP_fill_gradient_stop_at(const DP_FillGradientStop *fgs, int i);
+
+
+typedef struct DP_MsgFillGradient DP_MsgFillGradient;
+
+DP_Message *DP_msg_fill_gradient_new(
+    unsigned int context_id, uint16_t layer, uint32_t x, uint32_t y,
+    uint32_t w, uint32_t h, uint8_t mode, uint8_t shape, uint8_t flags,
+    int32_t x1, int32_t y1, int32_t x2, int32_t y2, int32_t radius,
+    void (*set_stops)(int, DP_FillGradientStop *, void *), int stops_count,
+    void *stops_user);
+
+DP_Message *DP_msg_fill_gradient_deserialize(unsigned int context_id,
+                                             const unsigned char *buffer,
+                                             size_t length);
+
+DP_Message *DP_msg_fill_gradient_parse(unsigned int context_id,
+                                       DP_TextReader *reader);
+
+DP_MsgFillGradient *DP_msg_fill_gradient_cast(DP_Message *msg);
+
+uint16_t DP_msg_fill_gradient_layer(const DP_MsgFillGradient *mfg);
+
+uint32_t DP_msg_fill_gradient_x(const DP_MsgFillGradient *mfg);
+
+uint32_t DP_msg_fill_gradient_y(const DP_MsgFillGradient *mfg);
+
+uint32_t DP_msg_fill_gradient_w(const DP_MsgFillGradient *mfg);
+
+uint32_t DP_msg_fill_gradient_h(const DP_MsgFillGradient *mfg);
+
+uint8_t DP_msg_fill_gradient_mode(const DP_MsgFillGradient *mfg);
+
+uint8_t DP_msg_fill_gradient_shape(const DP_MsgFillGradient *mfg);
+
+uint8_t DP_msg_fill_gradient_flags(const DP_MsgFillGradient *mfg);
+
+int32_t DP_msg_fill_gradient_x1(const DP_MsgFillGradient *mfg);
+
+int32_t DP_msg_fill_gradient_y1(const DP_MsgFillGradient *mfg);
+
+int32_t DP_msg_fill_gradient_x2(const DP_MsgFillGradient *mfg);
+
+int32_t DP_msg_fill_gradient_y2(const DP_MsgFillGradient *mfg);
+
+int32_t DP_msg_fill_gradient_radius(const DP_MsgFillGradient *mfg);
+
+const DP_FillGradientStop *
+DP_msg_fill_gradient_stops(const DP_MsgFillGradient *mfg, int *out_count);
+
+int DP_msg_fill_gradient_stops_count(const DP_MsgFillGradient *mfg);
+
+
+/*
+ * DP_MSG_ANNOTATION_ORDER
+ *
+ * Change the stacking order of an annotation.
+ *
+ * Raise and Lower swap the annotation with the one directly above or
+ * below it, Front and Back move it on top of or below all others.
+ * Moving an annotation past the end of the stack does nothing.
+ */
+
+#define DP_MSG_ANNOTATION_ORDER_STATIC_LENGTH 3
+
+#define DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE 0
+#define DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER 1
+#define DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT 2
+#define DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK  3
+
+#define DP_MSG_ANNOTATION_ORDER_NUM_DIRECTION 4
+#define DP_MSG_ANNOTATION_ORDER_ALL_DIRECTION                                 \
+    DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE,                                  \
+        DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER,                              \
+        DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT,                              \
+        DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK
+
+const char *DP_msg_annotation_order_direction_variant_name(unsigned int value);
+
+typedef struct DP_MsgAnnotationOrder DP_MsgAnnotationOrder;
+
+DP_Message *DP_msg_annotation_order_new(unsigned int context_id, uint16_t id,
+                                        uint8_t direction);
+
+DP_Message *DP_msg_annotation_order_deserialize(unsigned int context_id,
+                                                const unsigned char *buffer,
+                                                size_t length);
+
+DP_Message *DP_msg_annotation_order_parse(unsigned int context_id,
+                                          DP_TextReader *reader);
+
+DP_MsgAnnotationOrder *DP_msg_annotation_order_cast(DP_Message *msg);
+
+uint16_t DP_msg_annotation_order_id(const DP_MsgAnnotationOrder *mao);
+
+uint8_t DP_msg_annotation_order_direction(const DP_MsgAnnotationOrder *mao);
+
+
+/*
+ * DP_MSG_LAYER_METADATA
+ *
+ * Set the color tag and metadata of a layer.
+ *
+ * Neither of these affects rendering, they're only there to help
+ * organize layers. The color tag is one of none, blue, green,
+ * yellow, orange, brown, red, purple or gray, in that order, which
+ * matches Krita's color labels. Unknown values are treated as none.
+ *
+ * The metadata replaces the layer's entire key-value map. It
+ * consists of alternating keys and values, each one terminated by a
+ * NUL byte. Entries with an empty key or value are skipped, as is a
+ * trailing key without a value.
+ *
+ * If the target layer is locked, this command requires session operator
+ * privileges.
+ */
+
+#define DP_MSG_LAYER_METADATA_STATIC_LENGTH 3
+
+#define DP_MSG_LAYER_METADATA_METADATA_MIN_SIZE 0
+#define DP_MSG_LAYER_METADATA_METADATA_MAX_SIZE 65532
+
+typedef struct DP_MsgLayerMetadata DP_MsgLayerMetadata;
+
+DP_Message *DP_msg_layer_metadata_new(
+    unsigned int context_id, uint16_t id, uint8_t color_tag,
+    void (*set_metadata)(size_t, unsigned char *, void *),
+    size_t metadata_size, void *metadata_user);
+
+DP_Message *DP_msg_layer_metadata_deserialize(unsigned int context_id,
+                                              const unsigned char *buffer,
+                                              size_t length);
+
+DP_Message *DP_msg_layer_metadata_parse(unsigned int context_id,
+                                        DP_TextReader *reader);
+
+DP_MsgLayerMetadata *DP_msg_layer_metadata_cast(DP_Message *msg);
+
+uint16_t DP_msg_layer_metadata_id(const DP_MsgLayerMetadata *mlm);
+
+uint8_t DP_msg_layer_metadata_color_tag(const DP_MsgLayerMetadata *mlm);
+
+const unsigned char *
+DP_msg_layer_metadata_metadata(const DP_MsgLayerMetadata *mlm,
+                               size_t *out_size);
+
+size_t DP_msg_layer_metadata_metadata_size(const DP_MsgLayerMetadata *mlm);
+
+
 /*
  * DP_MSG_UNDO
  *
@@ -7,11 +7,23 @@ use drawdance::{
 use once_cell::sync::Lazy;
 use regex::Regex;
 use std::{
-    ffi::{c_char, c_int, CStr, CString},
+    cmp::Ordering,
+    ffi::{CStr, CString},
+    fmt,
+    os::raw::{c_char, c_int},
     ptr::{null, null_mut},
 };
 
-#[derive(Clone)]
+// Versions other than the current one that can still be handled, given as
+// server, major and minor version in the current namespace. Sessions on these
+// can be joined and recordings of them can be played back, both get converted
+// on the fly. Changing this list changes the compatibility matrix, so the
+// protocol_version tests need to be updated along with it.
+const PAST_COMPATIBLE_VERSIONS: &[(i32, i32, i32)] = &[
+    (4, 21, 2), // Drawpile 2.1
+];
+
+#[derive(Clone, Debug)]
 pub struct ProtocolVersion {
     pub ns: CString,
     pub server: i32,
@@ -20,6 +32,7 @@ pub struct ProtocolVersion {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ProtocolCompatibility {
     Compatible,
     MinorIncompatibility,
@@ -89,19 +102,45 @@ impl ProtocolVersion {
     }
 
     pub fn is_future(&self) -> bool {
-        self.ns.as_bytes_with_nul() == DP_PROTOCOL_VERSION_NAMESPACE
-            && (self.server > Self::CURRENT_SERVER
-                || (self.server == Self::CURRENT_SERVER
-                    && (self.major > Self::CURRENT_MAJOR
-                        || (self.major == Self::CURRENT_MAJOR
-                            && self.minor > Self::CURRENT_MINOR))))
+        self.partial_cmp(&Self::new_current()) == Some(Ordering::Greater)
     }
 
     pub fn is_past_compatible(&self) -> bool {
         self.ns.as_bytes_with_nul() == DP_PROTOCOL_VERSION_NAMESPACE
-            && self.server == 4
-            && self.major == 21
-            && self.minor == 2
+            && PAST_COMPATIBLE_VERSIONS.contains(&(self.server, self.major, self.minor))
+    }
+
+    // Whether a client speaking this version can join a session hosted with
+    // the given one. The server version has to match exactly, since that's
+    // what servers relay without looking at. The current version can also join
+    // sessions of past compatible versions.
+    pub fn can_join(&self, server_version: &Self) -> bool {
+        self.ns == server_version.ns
+            && self.server == server_version.server
+            && ((self.major == server_version.major && self.minor == server_version.minor)
+                || (self.is_current() && server_version.is_past_compatible()))
+    }
+
+    // How well a client speaking this version can play back a recording made
+    // with the given one. The server version doesn't matter for recordings.
+    pub fn recording_compatibility(&self, recording_version: &Self) -> ProtocolCompatibility {
+        if self.ns != recording_version.ns {
+            ProtocolCompatibility::Incompatible
+        } else if self.major == recording_version.major {
+            if self.minor == recording_version.minor {
+                ProtocolCompatibility::Compatible
+            } else {
+                ProtocolCompatibility::MinorIncompatibility
+            }
+        } else if self.is_current() && recording_version.is_past_compatible() {
+            ProtocolCompatibility::BackwardCompatible
+        } else {
+            ProtocolCompatibility::Incompatible
+        }
+    }
+
+    pub fn is_compatible_recording(&self, recording_version: &Self) -> bool {
+        self.recording_compatibility(recording_version) != ProtocolCompatibility::Incompatible
     }
 
     pub fn should_have_system_id(&self) -> bool {
@@ -129,21 +168,7 @@ impl ProtocolVersion {
     }
 
     pub fn client_compatibility(&self) -> ProtocolCompatibility {
-        if self.ns.as_ref() == Self::current_namespace() {
-            if self.major == Self::CURRENT_MAJOR {
-                if self.minor == Self::CURRENT_MINOR {
-                    ProtocolCompatibility::Compatible
-                } else {
-                    ProtocolCompatibility::MinorIncompatibility
-                }
-            } else if self.major == 21 && self.minor == 2 {
-                ProtocolCompatibility::BackwardCompatible // Drawpile 2.1 recording
-            } else {
-                ProtocolCompatibility::Incompatible
-            }
-        } else {
-            ProtocolCompatibility::Incompatible
-        }
+        Self::new_current().recording_compatibility(self)
     }
 
     fn current_namespace() -> &'static CStr {
@@ -151,18 +176,11 @@ impl ProtocolVersion {
     }
 
     pub fn equals(&self, other: &Self) -> bool {
-        self.ns == other.ns
-            && self.server == other.server
-            && self.major == other.major
-            && self.minor == other.minor
+        self == other
     }
 
     pub fn greater_or_equal(&self, other: &Self) -> bool {
-        self.ns == other.ns
-            && (self.server > other.server
-                || (self.server == other.server
-                    && (self.major > other.major
-                        || (self.major == other.major && self.minor >= other.minor))))
+        self >= other
     }
 
     pub fn as_integer(&self) -> u64 {
@@ -174,8 +192,45 @@ impl ProtocolVersion {
     }
 }
 
+impl PartialEq for ProtocolVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+// Versions in different namespaces have no meaningful order.
+impl PartialOrd for ProtocolVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.ns == other.ns {
+            Some((self.server, self.major, self.minor).cmp(&(
+                other.server,
+                other.major,
+                other.minor,
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}.{}.{}",
+            self.ns.to_string_lossy(),
+            self.server,
+            self.major,
+            self.minor
+        )
+    }
+}
+
+/// # Safety
+///
+/// `ns` must point to a nul-terminated string.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_new(
+pub unsafe extern "C" fn DP_protocol_version_new(
     ns: *const c_char,
     server: c_int,
     major: c_int,
@@ -202,8 +257,11 @@ pub extern "C" fn DP_protocol_version_new_current() -> *mut ProtocolVersion {
     Box::into_raw(Box::new(ProtocolVersion::new_current()))
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_new_clone(
+pub unsafe extern "C" fn DP_protocol_version_new_clone(
     protover: *const ProtocolVersion,
 ) -> *mut ProtocolVersion {
     match unsafe { protover.as_ref() } {
@@ -212,8 +270,11 @@ pub extern "C" fn DP_protocol_version_new_clone(
     }
 }
 
+/// # Safety
+///
+/// `s` must be null or point to a nul-terminated string.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_parse(s: *const c_char) -> *mut ProtocolVersion {
+pub unsafe extern "C" fn DP_protocol_version_parse(s: *const c_char) -> *mut ProtocolVersion {
     if s.is_null() {
         null_mut()
     } else {
@@ -228,47 +289,66 @@ pub extern "C" fn DP_protocol_version_parse(s: *const c_char) -> *mut ProtocolVe
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or come from one of the constructors here. It
+/// must not be used again afterwards.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_free(protover: *mut ProtocolVersion) {
+pub unsafe extern "C" fn DP_protocol_version_free(protover: *mut ProtocolVersion) {
     if !protover.is_null() {
         drop(unsafe { Box::from_raw(protover) });
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_valid(protover: *const ProtocolVersion) -> bool {
+pub unsafe extern "C" fn DP_protocol_version_valid(protover: *const ProtocolVersion) -> bool {
     match unsafe { protover.as_ref() } {
         Some(p) => p.is_valid(),
         None => false,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_is_current(protover: *const ProtocolVersion) -> bool {
+pub unsafe extern "C" fn DP_protocol_version_is_current(protover: *const ProtocolVersion) -> bool {
     match unsafe { protover.as_ref() } {
         Some(p) => p.is_current(),
         None => false,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_is_future(protover: *const ProtocolVersion) -> bool {
+pub unsafe extern "C" fn DP_protocol_version_is_future(protover: *const ProtocolVersion) -> bool {
     match unsafe { protover.as_ref() } {
         Some(p) => p.is_future(),
         None => false,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_is_past_compatible(protover: *const ProtocolVersion) -> bool {
+pub unsafe extern "C" fn DP_protocol_version_is_past_compatible(protover: *const ProtocolVersion) -> bool {
     match unsafe { protover.as_ref() } {
         Some(p) => p.is_past_compatible(),
         None => false,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_should_have_system_id(
+pub unsafe extern "C" fn DP_protocol_version_should_have_system_id(
     protover: *const ProtocolVersion,
 ) -> bool {
     match unsafe { protover.as_ref() } {
@@ -277,40 +357,55 @@ pub extern "C" fn DP_protocol_version_should_have_system_id(
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_ns(protover: *const ProtocolVersion) -> *const c_char {
+pub unsafe extern "C" fn DP_protocol_version_ns(protover: *const ProtocolVersion) -> *const c_char {
     match unsafe { protover.as_ref() } {
         Some(p) => p.ns.as_ptr(),
         None => null(),
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_server(protover: *const ProtocolVersion) -> c_int {
+pub unsafe extern "C" fn DP_protocol_version_server(protover: *const ProtocolVersion) -> c_int {
     match unsafe { protover.as_ref() } {
         Some(p) => p.server,
         None => -1,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_major(protover: *const ProtocolVersion) -> c_int {
+pub unsafe extern "C" fn DP_protocol_version_major(protover: *const ProtocolVersion) -> c_int {
     match unsafe { protover.as_ref() } {
         Some(p) => p.major,
         None => -1,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_minor(protover: *const ProtocolVersion) -> c_int {
+pub unsafe extern "C" fn DP_protocol_version_minor(protover: *const ProtocolVersion) -> c_int {
     match unsafe { protover.as_ref() } {
         Some(p) => p.minor,
         None => -1,
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_name(protover: *const ProtocolVersion) -> *const c_char {
+pub unsafe extern "C" fn DP_protocol_version_name(protover: *const ProtocolVersion) -> *const c_char {
     match unsafe { protover.as_ref() }
         .and_then(ProtocolVersion::version_name)
         .and_then(|bytes| CStr::from_bytes_with_nul(bytes).ok())
@@ -320,8 +415,11 @@ pub extern "C" fn DP_protocol_version_name(protover: *const ProtocolVersion) ->
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_client_compatibility(
+pub unsafe extern "C" fn DP_protocol_version_client_compatibility(
     protover: *const ProtocolVersion,
 ) -> ProtocolCompatibility {
     match unsafe { protover.as_ref() } {
@@ -330,8 +428,53 @@ pub extern "C" fn DP_protocol_version_client_compatibility(
     }
 }
 
+/// # Safety
+///
+/// `protover` and `server_protover` must each be null or point to a live protocol version.
+#[no_mangle]
+pub unsafe extern "C" fn DP_protocol_version_can_join(
+    protover: *const ProtocolVersion,
+    server_protover: *const ProtocolVersion,
+) -> bool {
+    match unsafe { (protover.as_ref(), server_protover.as_ref()) } {
+        (Some(p), Some(s)) => p.can_join(s),
+        _ => false,
+    }
+}
+
+/// # Safety
+///
+/// `protover` and `recording_protover` must each be null or point to a live protocol version.
+#[no_mangle]
+pub unsafe extern "C" fn DP_protocol_version_recording_compatibility(
+    protover: *const ProtocolVersion,
+    recording_protover: *const ProtocolVersion,
+) -> ProtocolCompatibility {
+    match unsafe { (protover.as_ref(), recording_protover.as_ref()) } {
+        (Some(p), Some(r)) => p.recording_compatibility(r),
+        _ => ProtocolCompatibility::Incompatible,
+    }
+}
+
+/// # Safety
+///
+/// `protover` and `recording_protover` must each be null or point to a live protocol version.
+#[no_mangle]
+pub unsafe extern "C" fn DP_protocol_version_is_compatible_recording(
+    protover: *const ProtocolVersion,
+    recording_protover: *const ProtocolVersion,
+) -> bool {
+    match unsafe { (protover.as_ref(), recording_protover.as_ref()) } {
+        (Some(p), Some(r)) => p.is_compatible_recording(r),
+        _ => false,
+    }
+}
+
+/// # Safety
+///
+/// `a` and `b` must each be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_equals(
+pub unsafe extern "C" fn DP_protocol_version_equals(
     a: *const ProtocolVersion,
     b: *const ProtocolVersion,
 ) -> bool {
@@ -344,8 +487,11 @@ pub extern "C" fn DP_protocol_version_equals(
     }
 }
 
+/// # Safety
+///
+/// `a` and `b` must each be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_greater_or_equal(
+pub unsafe extern "C" fn DP_protocol_version_greater_or_equal(
     a: *const ProtocolVersion,
     b: *const ProtocolVersion,
 ) -> bool {
@@ -358,8 +504,11 @@ pub extern "C" fn DP_protocol_version_greater_or_equal(
     }
 }
 
+/// # Safety
+///
+/// `protover` must be null or point to a live protocol version.
 #[no_mangle]
-pub extern "C" fn DP_protocol_version_as_integer(protover: *const ProtocolVersion) -> u64 {
+pub unsafe extern "C" fn DP_protocol_version_as_integer(protover: *const ProtocolVersion) -> u64 {
     match unsafe { protover.as_ref() } {
         Some(p) => p.as_integer(),
         None => 0,
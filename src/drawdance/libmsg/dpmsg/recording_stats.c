@@ -0,0 +1,398 @@
+// SPDX-License-Identifier: MIT
+#include "recording_stats.h"
+#include "binary_reader.h"
+#include "message.h"
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/vector.h>
+#include <parson.h>
+
+#define USER_COUNT 256
+
+struct DP_RecordingStats {
+    DP_RecordingStatsTotals totals;
+    // Recording time of the last command by anyone, or -1 if none yet.
+    long long last_command_ms;
+    long long last_user_command_ms[USER_COUNT];
+    DP_RecordingStatsUser *users[USER_COUNT];
+    DP_Vector resizes;
+    DP_Vector layers;
+};
+
+
+DP_RecordingStats *DP_recording_stats_new(void)
+{
+    DP_RecordingStats *rs = DP_malloc(sizeof(*rs));
+    rs->totals = (DP_RecordingStatsTotals){0, 0, 0, 0, 0, 0, false};
+    rs->last_command_ms = -1;
+    for (int i = 0; i < USER_COUNT; ++i) {
+        rs->last_user_command_ms[i] = -1;
+        rs->users[i] = NULL;
+    }
+    DP_VECTOR_INIT_TYPE(&rs->resizes, DP_RecordingStatsResize, 8);
+    DP_VECTOR_INIT_TYPE(&rs->layers, DP_RecordingStatsLayer, 8);
+    return rs;
+}
+
+static void dispose_layer(void *element)
+{
+    DP_RecordingStatsLayer *layer = element;
+    DP_free(layer->title);
+}
+
+void DP_recording_stats_free(DP_RecordingStats *rs)
+{
+    if (rs) {
+        DP_VECTOR_CLEAR_DISPOSE_TYPE(&rs->layers, DP_RecordingStatsLayer,
+                                     dispose_layer);
+        DP_vector_dispose(&rs->resizes);
+        for (int i = 0; i < USER_COUNT; ++i) {
+            DP_free(rs->users[i]);
+        }
+        DP_free(rs);
+    }
+}
+
+
+static DP_RecordingStatsUser *get_user(DP_RecordingStats *rs,
+                                       unsigned int context_id)
+{
+    DP_RecordingStatsUser *user = rs->users[context_id];
+    if (!user) {
+        user = DP_malloc_zeroed(sizeof(*user));
+        user->context_id = context_id;
+        user->first_ms = rs->totals.duration_ms;
+        rs->users[context_id] = user;
+    }
+    return user;
+}
+
+static long long active_gap(long long last_ms, long long now_ms)
+{
+    if (last_ms < 0) {
+        return 0;
+    }
+    else {
+        long long gap = now_ms - last_ms;
+        return gap <= DP_RECORDING_STATS_IDLE_MS ? gap : 0;
+    }
+}
+
+static void handle_command(DP_RecordingStats *rs, DP_RecordingStatsUser *user)
+{
+    long long now_ms = rs->totals.duration_ms;
+    rs->totals.active_ms += active_gap(rs->last_command_ms, now_ms);
+    rs->last_command_ms = now_ms;
+
+    long long *last_user_ms = &rs->last_user_command_ms[user->context_id];
+    user->active_ms += active_gap(*last_user_ms, now_ms);
+    *last_user_ms = now_ms;
+}
+
+static int count_dabs(DP_Message *msg, DP_MessageType type)
+{
+    switch (type) {
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        return DP_msg_draw_dabs_classic_dabs_count(
+            DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_PIXEL:
+    case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+        return DP_msg_draw_dabs_pixel_dabs_count(DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_MYPAINT:
+        return DP_msg_draw_dabs_mypaint_dabs_count(
+            DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_STAMP:
+        return DP_msg_draw_dabs_stamp_dabs_count(DP_message_internal(msg));
+    case DP_MSG_DRAW_DABS_SOFT:
+        return DP_msg_draw_dabs_soft_dabs_count(DP_message_internal(msg));
+    default:
+        return 0;
+    }
+}
+
+static void handle_resize(DP_RecordingStats *rs, DP_Message *msg,
+                          unsigned int context_id)
+{
+    DP_MsgCanvasResize *mcr = DP_message_internal(msg);
+    int top = DP_msg_canvas_resize_top(mcr);
+    int right = DP_msg_canvas_resize_right(mcr);
+    int bottom = DP_msg_canvas_resize_bottom(mcr);
+    int left = DP_msg_canvas_resize_left(mcr);
+    DP_RecordingStatsTotals *totals = &rs->totals;
+    totals->width = DP_max_int(0, totals->width + left + right);
+    totals->height = DP_max_int(0, totals->height + top + bottom);
+    DP_VECTOR_PUSH_TYPE(
+        &rs->resizes, DP_RecordingStatsResize,
+        ((DP_RecordingStatsResize){totals->message_count - 1,
+                                   totals->duration_ms, context_id, top, right,
+                                   bottom, left, totals->width,
+                                   totals->height}));
+}
+
+static void push_layer(DP_RecordingStats *rs, unsigned int context_id,
+                       int layer_id, bool group, const char *title,
+                       size_t title_length)
+{
+    DP_RecordingStatsTotals *totals = &rs->totals;
+    char *title_copy = DP_malloc(title_length + 1);
+    memcpy(title_copy, title, title_length);
+    title_copy[title_length] = '\0';
+    DP_VECTOR_PUSH_TYPE(&rs->layers, DP_RecordingStatsLayer,
+                        ((DP_RecordingStatsLayer){
+                            totals->message_count - 1, totals->duration_ms,
+                            context_id, layer_id, group, title_copy}));
+}
+
+static void handle_layer_create(DP_RecordingStats *rs, DP_Message *msg,
+                                unsigned int context_id)
+{
+    DP_MsgLayerCreate *mlc = DP_message_internal(msg);
+    size_t title_length;
+    const char *title = DP_msg_layer_create_title(mlc, &title_length);
+    push_layer(rs, context_id, DP_msg_layer_create_id(mlc), false, title,
+               title_length);
+}
+
+static void handle_layer_tree_create(DP_RecordingStats *rs, DP_Message *msg,
+                                     unsigned int context_id)
+{
+    DP_MsgLayerTreeCreate *mltc = DP_message_internal(msg);
+    size_t title_length;
+    const char *title = DP_msg_layer_tree_create_title(mltc, &title_length);
+    bool group = DP_msg_layer_tree_create_flags(mltc)
+               & DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP;
+    push_layer(rs, context_id, DP_msg_layer_tree_create_id(mltc), group, title,
+               title_length);
+}
+
+void DP_recording_stats_handle(DP_RecordingStats *rs, DP_Message *msg)
+{
+    DP_ASSERT(rs);
+    DP_ASSERT(msg);
+    DP_RecordingStatsTotals *totals = &rs->totals;
+    ++totals->message_count;
+
+    DP_MessageType type = DP_message_type(msg);
+    unsigned int context_id = DP_message_context_id(msg) % USER_COUNT;
+    DP_RecordingStatsUser *user = get_user(rs, context_id);
+    ++user->message_count;
+    ++user->message_counts[type];
+    user->last_ms = totals->duration_ms;
+
+    if (type >= DP_MSG_UNDO_POINT) {
+        handle_command(rs, user);
+    }
+
+    switch (type) {
+    case DP_MSG_INTERVAL:
+        totals->duration_ms += DP_msg_interval_msecs(DP_message_internal(msg));
+        break;
+    case DP_MSG_CANVAS_RESIZE:
+        handle_resize(rs, msg, context_id);
+        break;
+    case DP_MSG_LAYER_CREATE:
+        handle_layer_create(rs, msg, context_id);
+        break;
+    case DP_MSG_LAYER_TREE_CREATE:
+        handle_layer_tree_create(rs, msg, context_id);
+        break;
+    case DP_MSG_UNDO:
+        if (DP_msg_undo_redo(DP_message_internal(msg))) {
+            ++user->redo_count;
+        }
+        else {
+            ++user->undo_count;
+        }
+        break;
+    default:
+        user->dab_count += count_dabs(msg, type);
+        break;
+    }
+}
+
+void DP_recording_stats_handle_unparseable(DP_RecordingStats *rs)
+{
+    DP_ASSERT(rs);
+    ++rs->totals.message_count;
+    ++rs->totals.unparseable_count;
+}
+
+void DP_recording_stats_truncated_set(DP_RecordingStats *rs)
+{
+    DP_ASSERT(rs);
+    rs->totals.truncated = true;
+}
+
+DP_RecordingStats *DP_recording_stats_analyze(DP_BinaryReader *br)
+{
+    DP_ASSERT(br);
+    DP_RecordingStats *rs = DP_recording_stats_new();
+    while (true) {
+        DP_Message *msg;
+        DP_BinaryReaderResult result =
+            DP_binary_reader_read_message(br, true, &msg);
+        switch (result) {
+        case DP_BINARY_READER_SUCCESS:
+            DP_recording_stats_handle(rs, msg);
+            DP_message_decref(msg);
+            break;
+        case DP_BINARY_READER_ERROR_PARSE:
+            DP_recording_stats_handle_unparseable(rs);
+            break;
+        case DP_BINARY_READER_INPUT_END:
+            return rs;
+        default:
+            DP_debug("Recording stats stopped at input error: %s", DP_error());
+            DP_recording_stats_truncated_set(rs);
+            return rs;
+        }
+    }
+}
+
+
+const DP_RecordingStatsTotals *DP_recording_stats_totals(DP_RecordingStats *rs)
+{
+    DP_ASSERT(rs);
+    return &rs->totals;
+}
+
+const DP_RecordingStatsUser *DP_recording_stats_user(DP_RecordingStats *rs,
+                                                     unsigned int context_id)
+{
+    DP_ASSERT(rs);
+    return context_id < USER_COUNT ? rs->users[context_id] : NULL;
+}
+
+int DP_recording_stats_resize_count(DP_RecordingStats *rs)
+{
+    DP_ASSERT(rs);
+    return DP_size_to_int(rs->resizes.used);
+}
+
+const DP_RecordingStatsResize *
+DP_recording_stats_resize_at(DP_RecordingStats *rs, int index)
+{
+    DP_ASSERT(rs);
+    DP_ASSERT(index >= 0);
+    DP_ASSERT(index < DP_recording_stats_resize_count(rs));
+    return &DP_VECTOR_AT_TYPE(&rs->resizes, DP_RecordingStatsResize, index);
+}
+
+int DP_recording_stats_layer_count(DP_RecordingStats *rs)
+{
+    DP_ASSERT(rs);
+    return DP_size_to_int(rs->layers.used);
+}
+
+const DP_RecordingStatsLayer *DP_recording_stats_layer_at(DP_RecordingStats *rs,
+                                                          int index)
+{
+    DP_ASSERT(rs);
+    DP_ASSERT(index >= 0);
+    DP_ASSERT(index < DP_recording_stats_layer_count(rs));
+    return &DP_VECTOR_AT_TYPE(&rs->layers, DP_RecordingStatsLayer, index);
+}
+
+
+static void set_number(JSON_Object *o, const char *name, long long value)
+{
+    json_object_set_number(o, name, DP_llong_to_double(value));
+}
+
+static JSON_Value *user_to_json(const DP_RecordingStatsUser *user)
+{
+    JSON_Value *value = json_value_init_object();
+    JSON_Object *o = json_value_get_object(value);
+    set_number(o, "id", user->context_id);
+    set_number(o, "messages", user->message_count);
+    set_number(o, "dabs", user->dab_count);
+    set_number(o, "undos", user->undo_count);
+    set_number(o, "redos", user->redo_count);
+    set_number(o, "active_ms", user->active_ms);
+    set_number(o, "first_ms", user->first_ms);
+    set_number(o, "last_ms", user->last_ms);
+
+    JSON_Value *types_value = json_value_init_object();
+    JSON_Object *types = json_value_get_object(types_value);
+    for (int i = 0; i < DP_MSG_TYPE_COUNT; ++i) {
+        long long count = user->message_counts[i];
+        if (count != 0) {
+            set_number(types, DP_message_type_name((DP_MessageType)i), count);
+        }
+    }
+    json_object_set_value(o, "message_types", types_value);
+    return value;
+}
+
+static JSON_Value *resize_to_json(const DP_RecordingStatsResize *resize)
+{
+    JSON_Value *value = json_value_init_object();
+    JSON_Object *o = json_value_get_object(value);
+    set_number(o, "index", resize->message_index);
+    set_number(o, "elapsed_ms", resize->elapsed_ms);
+    set_number(o, "user", resize->context_id);
+    set_number(o, "top", resize->top);
+    set_number(o, "right", resize->right);
+    set_number(o, "bottom", resize->bottom);
+    set_number(o, "left", resize->left);
+    set_number(o, "width", resize->width);
+    set_number(o, "height", resize->height);
+    return value;
+}
+
+static JSON_Value *layer_to_json(const DP_RecordingStatsLayer *layer)
+{
+    JSON_Value *value = json_value_init_object();
+    JSON_Object *o = json_value_get_object(value);
+    set_number(o, "index", layer->message_index);
+    set_number(o, "elapsed_ms", layer->elapsed_ms);
+    set_number(o, "user", layer->context_id);
+    set_number(o, "id", layer->layer_id);
+    json_object_set_boolean(o, "group", layer->group);
+    json_object_set_string(o, "title", layer->title);
+    return value;
+}
+
+JSON_Value *DP_recording_stats_to_json(DP_RecordingStats *rs)
+{
+    DP_ASSERT(rs);
+    JSON_Value *value = json_value_init_object();
+    JSON_Object *o = json_value_get_object(value);
+    const DP_RecordingStatsTotals *totals = &rs->totals;
+    set_number(o, "messages", totals->message_count);
+    set_number(o, "unparseable", totals->unparseable_count);
+    set_number(o, "duration_ms", totals->duration_ms);
+    set_number(o, "active_ms", totals->active_ms);
+    set_number(o, "width", totals->width);
+    set_number(o, "height", totals->height);
+    json_object_set_boolean(o, "truncated", totals->truncated);
+
+    JSON_Value *users_value = json_value_init_array();
+    JSON_Array *users = json_value_get_array(users_value);
+    for (int i = 0; i < USER_COUNT; ++i) {
+        if (rs->users[i]) {
+            json_array_append_value(users, user_to_json(rs->users[i]));
+        }
+    }
+    json_object_set_value(o, "users", users_value);
+
+    JSON_Value *resizes_value = json_value_init_array();
+    JSON_Array *resizes = json_value_get_array(resizes_value);
+    int resize_count = DP_recording_stats_resize_count(rs);
+    for (int i = 0; i < resize_count; ++i) {
+        json_array_append_value(
+            resizes, resize_to_json(DP_recording_stats_resize_at(rs, i)));
+    }
+    json_object_set_value(o, "resizes", resizes_value);
+
+    JSON_Value *layers_value = json_value_init_array();
+    JSON_Array *layers = json_value_get_array(layers_value);
+    int layer_count = DP_recording_stats_layer_count(rs);
+    for (int i = 0; i < layer_count; ++i) {
+        json_array_append_value(
+            layers, layer_to_json(DP_recording_stats_layer_at(rs, i)));
+    }
+    json_object_set_value(o, "layers", layers_value);
+
+    return value;
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: MIT
+#ifndef DPMSG_RECORDING_STATS_H
+#define DPMSG_RECORDING_STATS_H
+#include "messages.h"
+#include <dpcommon/common.h>
+
+typedef struct DP_BinaryReader DP_BinaryReader;
+typedef struct DP_Message DP_Message;
+typedef struct json_value_t JSON_Value;
+
+// Gaps between drawing commands longer than this don't count as active time.
+#define DP_RECORDING_STATS_IDLE_MS 5000
+
+typedef struct DP_RecordingStats DP_RecordingStats;
+
+typedef struct DP_RecordingStatsTotals {
+    // Messages read, including ones that couldn't be parsed.
+    long long message_count;
+    long long unparseable_count;
+    // Sum of all intervals in the recording.
+    long long duration_ms;
+    // Time spent drawing by anyone, see DP_RECORDING_STATS_IDLE_MS.
+    long long active_ms;
+    // Canvas size after the last resize, starting from an empty canvas.
+    int width, height;
+    // Whether the recording ends partway through a message.
+    bool truncated;
+} DP_RecordingStatsTotals;
+
+typedef struct DP_RecordingStatsUser {
+    unsigned int context_id;
+    long long message_count;
+    long long message_counts[DP_MSG_TYPE_COUNT];
+    // Individual dabs across all kinds of dab messages.
+    long long dab_count;
+    long long undo_count;
+    long long redo_count;
+    // Time spent drawing, see DP_RECORDING_STATS_IDLE_MS.
+    long long active_ms;
+    // Recording time of this user's first and last message.
+    long long first_ms;
+    long long last_ms;
+} DP_RecordingStatsUser;
+
+typedef struct DP_RecordingStatsResize {
+    long long message_index;
+    long long elapsed_ms;
+    unsigned int context_id;
+    int top, right, bottom, left;
+    // Canvas size after the resize.
+    int width, height;
+} DP_RecordingStatsResize;
+
+typedef struct DP_RecordingStatsLayer {
+    long long message_index;
+    long long elapsed_ms;
+    unsigned int context_id;
+    int layer_id;
+    bool group;
+    char *title;
+} DP_RecordingStatsLayer;
+
+
+// Collects statistics over a recording from the messages alone, without
+// handling them on a canvas. Time comes from the intervals in the recording,
+// which is the only clock it has.
+DP_RecordingStats *DP_recording_stats_new(void);
+
+void DP_recording_stats_free(DP_RecordingStats *rs);
+
+void DP_recording_stats_handle(DP_RecordingStats *rs, DP_Message *msg);
+
+// Counts a message that couldn't be parsed, which still takes up a position.
+void DP_recording_stats_handle_unparseable(DP_RecordingStats *rs);
+
+void DP_recording_stats_truncated_set(DP_RecordingStats *rs);
+
+// Reads the rest of the recording and returns statistics over it. Messages
+// that can't be parsed are skipped and reading stops at the first input
+// error, marking the result as truncated, so this never fails.
+DP_RecordingStats *DP_recording_stats_analyze(DP_BinaryReader *br);
+
+const DP_RecordingStatsTotals *
+DP_recording_stats_totals(DP_RecordingStats *rs);
+
+// Returns NULL if the user didn't send any messages.
+const DP_RecordingStatsUser *DP_recording_stats_user(DP_RecordingStats *rs,
+                                                     unsigned int context_id);
+
+int DP_recording_stats_resize_count(DP_RecordingStats *rs);
+
+const DP_RecordingStatsResize *
+DP_recording_stats_resize_at(DP_RecordingStats *rs, int index);
+
+int DP_recording_stats_layer_count(DP_RecordingStats *rs);
+
+const DP_RecordingStatsLayer *DP_recording_stats_layer_at(DP_RecordingStats *rs,
+                                                          int index);
+
+// Message type counts are keyed by the message name, as in text recordings.
+JSON_Value *DP_recording_stats_to_json(DP_RecordingStats *rs);
+
+
+#endif
@@ -60,6 +60,18 @@ const char *DP_protocol_version_name(const struct DP_ProtocolVersion *protover);
 enum DP_ProtocolCompatibility DP_protocol_version_client_compatibility(
     const struct DP_ProtocolVersion *protover);
 
+bool DP_protocol_version_can_join(
+    const struct DP_ProtocolVersion *protover,
+    const struct DP_ProtocolVersion *server_protover);
+
+enum DP_ProtocolCompatibility DP_protocol_version_recording_compatibility(
+    const struct DP_ProtocolVersion *protover,
+    const struct DP_ProtocolVersion *recording_protover);
+
+bool DP_protocol_version_is_compatible_recording(
+    const struct DP_ProtocolVersion *protover,
+    const struct DP_ProtocolVersion *recording_protover);
+
 bool DP_protocol_version_equals(const struct DP_ProtocolVersion *a,
                                 const struct DP_ProtocolVersion *b);
 
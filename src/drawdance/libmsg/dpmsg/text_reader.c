@@ -37,6 +37,8 @@
 typedef struct DP_TextReaderField {
     size_t key_offset;
     size_t value_offset;
+    size_t line;
+    bool used;
 } DP_TextReaderField;
 
 typedef struct DP_TextReaderTupleRow {
@@ -49,7 +51,10 @@ struct DP_TextReader {
     size_t input_length;
     size_t input_offset;
     size_t body_offset;
+    size_t body_line;
     size_t line_end;
+    size_t line;
+    DP_TextReaderPosition error_position;
     struct {
         size_t capacity;
         size_t used;
@@ -82,7 +87,10 @@ DP_TextReader *DP_text_reader_new(DP_Input *input)
                               input_length,
                               0,
                               0,
+                              1,
                               0,
+                              1,
+                              {0, 0},
                               {0, 0, NULL},
                               {0, 0, NULL, DP_VECTOR_NULL},
                               {DP_VECTOR_NULL, DP_VECTOR_NULL}};
@@ -112,6 +120,18 @@ size_t DP_text_reader_body_offset(DP_TextReader *reader)
     return reader->body_offset;
 }
 
+size_t DP_text_reader_line(DP_TextReader *reader)
+{
+    DP_ASSERT(reader);
+    return reader->line;
+}
+
+DP_TextReaderPosition DP_text_reader_error_position(DP_TextReader *reader)
+{
+    DP_ASSERT(reader);
+    return reader->error_position;
+}
+
 size_t DP_text_reader_tell(DP_TextReader *reader)
 {
     DP_ASSERT(reader);
@@ -126,6 +146,10 @@ bool DP_text_reader_seek(DP_TextReader *reader, size_t offset)
         DP_error_set("Seek offset %zu beyond end %zu", offset, input_length);
         return false;
     }
+    else if (!reader->input) {
+        DP_error_set("Can't seek after an input error");
+        return false;
+    }
     else if (!DP_input_seek(reader->input, offset)) {
         return false;
     }
@@ -133,6 +157,16 @@ bool DP_text_reader_seek(DP_TextReader *reader, size_t offset)
         reader->input_offset = offset;
         reader->read.used = 0;
         reader->line_end = 0;
+        // Line numbers are only known when starting from a line we've seen.
+        if (offset == 0) {
+            reader->line = 1;
+        }
+        else if (offset == reader->body_offset) {
+            reader->line = reader->body_line;
+        }
+        else {
+            reader->line = 0;
+        }
         return true;
     }
 }
@@ -166,6 +200,7 @@ static bool buffer_more(DP_TextReader *reader)
                                     capacity_left, &error);
         if (error) {
             DP_input_free(input);
+            reader->input = NULL;
             reader->read.used = 0;
             return false;
         }
@@ -213,6 +248,9 @@ static void consume_line(DP_TextReader *reader)
     size_t new_used = reader->read.used - consume;
     reader->input_offset += consume;
     reader->line_end = 0;
+    if (consume != 0 && reader->line != 0) {
+        ++reader->line;
+    }
     reader->read.used = new_used;
     if (new_used != 0) {
         memmove(reader->read.buffer, reader->read.buffer + consume, new_used);
@@ -338,6 +376,7 @@ DP_TextReaderResult DP_text_reader_read_header_field(DP_TextReader *reader,
     if (end == 0 || buffer[start] != '!') {
         regurgitate_line(reader); // This isn't a header line, don't consume it.
         reader->body_offset = reader->input_offset;
+        reader->body_line = reader->line;
         return DP_TEXT_READER_HEADER_END;
     }
 
@@ -438,7 +477,7 @@ static void push_field(DP_TextReader *reader, const char *key, size_t key_len,
     memcpy(buffer + value_start, value, value_len);
     buffer[value_end] = '\0';
 
-    DP_TextReaderField trf = {key_start, value_start};
+    DP_TextReaderField trf = {key_start, value_start, reader->line, false};
     DP_VECTOR_PUSH_TYPE(&reader->field.offsets, DP_TextReaderField, trf);
 }
 
@@ -525,6 +564,12 @@ static bool parse_multiline_field(DP_TextReader *reader, size_t field_start,
     return true;
 }
 
+static void set_error_position(DP_TextReader *reader, size_t line,
+                               size_t index)
+{
+    reader->error_position = (DP_TextReaderPosition){line, index + 1};
+}
+
 static DP_TextReaderResult parse_multiline(DP_TextReader *reader,
                                            bool parse_tuples)
 {
@@ -537,6 +582,7 @@ static DP_TextReaderResult parse_multiline(DP_TextReader *reader,
 
         if (end == 0) {
             DP_error_set("Expected }, but got end of input");
+            set_error_position(reader, reader->line, 0);
             return DP_TEXT_READER_ERROR_INPUT;
         }
 
@@ -598,6 +644,22 @@ static DP_TextReaderResult parse_fields(DP_TextReader *reader,
     }
 }
 
+// Fields that the message doesn't know about are skipped, since they may be
+// from a newer version, but it may also just be a typo.
+static void warn_unused_fields(DP_TextReader *reader, DP_MessageType type)
+{
+    size_t used = reader->field.offsets.used;
+    for (size_t i = 0; i < used; ++i) {
+        DP_TextReaderField trf =
+            DP_VECTOR_AT_TYPE(&reader->field.offsets, DP_TextReaderField, i);
+        if (!trf.used) {
+            DP_warn("Unknown field '%s' in %s on line %zu",
+                    reader->field.buffer + trf.key_offset,
+                    DP_message_type_name(type), trf.line);
+        }
+    }
+}
+
 DP_TextReaderResult DP_text_reader_read_message(DP_TextReader *reader,
                                                 DP_Message **out_msg)
 {
@@ -607,6 +669,7 @@ DP_TextReaderResult DP_text_reader_read_message(DP_TextReader *reader,
     reader->field.offsets.used = 0;
     reader->tuple.rows.used = 0;
     reader->tuple.offsets.used = 0;
+    reader->error_position = (DP_TextReaderPosition){0, 0};
 
     while (true) {
         size_t start, end;
@@ -619,11 +682,13 @@ DP_TextReaderResult DP_text_reader_read_message(DP_TextReader *reader,
         }
 
         char *buffer = reader->read.buffer;
+        size_t line = reader->line;
         size_t context_id_end = skip_non_ws(buffer, start, end);
         buffer[context_id_end] = '\0';
         unsigned int context_id;
         if (!parse_uint(buffer, start, context_id_end, 0, 255, &context_id)) {
             DP_error_set("Error parsing context id in %s", buffer);
+            set_error_position(reader, line, start);
             return DP_TEXT_READER_ERROR_PARSE;
         }
 
@@ -636,6 +701,7 @@ DP_TextReaderResult DP_text_reader_read_message(DP_TextReader *reader,
             DP_message_type_from_name(buffer + type_start, DP_MSG_TYPE_COUNT);
         if (type == DP_MSG_TYPE_COUNT) {
             DP_error_set("Unknown message type '%s'", buffer + type_start);
+            set_error_position(reader, line, type_start);
             discard_message(reader, field_offset, end);
             return DP_TEXT_READER_ERROR_PARSE;
         }
@@ -648,10 +714,12 @@ DP_TextReaderResult DP_text_reader_read_message(DP_TextReader *reader,
 
         DP_Message *msg = DP_message_parse_body(type, context_id, reader);
         if (msg) {
+            warn_unused_fields(reader, type);
             *out_msg = msg;
             return DP_TEXT_READER_SUCCESS;
         }
         else {
+            set_error_position(reader, line, start);
             return DP_TEXT_READER_ERROR_PARSE;
         }
     }
@@ -663,10 +731,11 @@ static const char *search_field(DP_TextReader *reader, const char *key)
     size_t used = reader->field.offsets.used;
     const char *buffer = reader->field.buffer;
     for (size_t i = 0; i < used; ++i) {
-        DP_TextReaderField trf =
-            DP_VECTOR_AT_TYPE(&reader->field.offsets, DP_TextReaderField, i);
-        if (DP_str_equal(buffer + trf.key_offset, key)) {
-            return buffer + trf.value_offset;
+        DP_TextReaderField *trf =
+            &DP_VECTOR_AT_TYPE(&reader->field.offsets, DP_TextReaderField, i);
+        if (DP_str_equal(buffer + trf->key_offset, key)) {
+            trf->used = true;
+            return buffer + trf->value_offset;
         }
     }
     return NULL;
@@ -840,11 +909,8 @@ static uint32_t parse_argb(const char *value, uint32_t base)
     return DP_ulong_to_uint32(result) | base;
 }
 
-uint32_t DP_text_reader_get_argb_color(DP_TextReader *reader, const char *key)
+static uint32_t parse_argb_color(const char *value)
 {
-    DP_ASSERT(reader);
-    DP_ASSERT(key);
-    const char *value = search_field(reader, key);
     if (value) {
         size_t len = strlen(value);
         if (len == 7) {
@@ -860,6 +926,13 @@ uint32_t DP_text_reader_get_argb_color(DP_TextReader *reader, const char *key)
     return 0;
 }
 
+uint32_t DP_text_reader_get_argb_color(DP_TextReader *reader, const char *key)
+{
+    DP_ASSERT(reader);
+    DP_ASSERT(key);
+    return parse_argb_color(search_field(reader, key));
+}
+
 uint8_t DP_text_reader_get_blend_mode(DP_TextReader *reader, const char *key)
 {
     DP_ASSERT(reader);
@@ -1115,3 +1188,11 @@ double DP_text_reader_get_subfield_decimal(DP_TextReader *reader, int row,
         get_tuple_field(reader, DP_int_to_size(row), DP_int_to_size(col));
     return parse_decimal(value, multiplier, min, max);
 }
+
+uint32_t DP_text_reader_get_subfield_argb_color(DP_TextReader *reader,
+                                                int row, int col)
+{
+    DP_ASSERT(reader);
+    return parse_argb_color(
+        get_tuple_field(reader, DP_int_to_size(row), DP_int_to_size(col)));
+}
@@ -37,6 +37,13 @@ typedef enum DP_TextReaderResult {
     DP_TEXT_READER_ERROR_PARSE,
 } DP_TextReaderResult;
 
+// Where the last parse error happened, both starting at 1. The line is 0 if
+// it's not known, like after seeking to an arbitrary offset.
+typedef struct DP_TextReaderPosition {
+    size_t line;
+    size_t column;
+} DP_TextReaderPosition;
+
 typedef struct DP_TextReaderParseParams {
     const char *value;
     size_t length;
@@ -48,6 +55,11 @@ void DP_text_reader_free(DP_TextReader *reader);
 
 size_t DP_text_reader_body_offset(DP_TextReader *reader);
 
+// Number of the line currently being read, starting at 1, or 0 if unknown.
+size_t DP_text_reader_line(DP_TextReader *reader);
+
+DP_TextReaderPosition DP_text_reader_error_position(DP_TextReader *reader);
+
 size_t DP_text_reader_tell(DP_TextReader *reader);
 
 bool DP_text_reader_seek(DP_TextReader *reader, size_t offset);
@@ -115,5 +127,8 @@ double DP_text_reader_get_subfield_decimal(DP_TextReader *reader, int row,
                                            int col, double multiplier,
                                            double min, double max);
 
+uint32_t DP_text_reader_get_subfield_argb_color(DP_TextReader *reader,
+                                                int row, int col);
+
 
 #endif
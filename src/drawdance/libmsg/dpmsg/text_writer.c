@@ -446,6 +446,18 @@ bool DP_text_writer_write_subfield_decimal(DP_TextWriter *writer, double value)
     return format_argument(writer, " %s", format_decimal(writer, value));
 }
 
+bool DP_text_writer_write_subfield_argb_color(DP_TextWriter *writer,
+                                              uint32_t bgra)
+{
+    DP_ASSERT(writer);
+    if ((bgra & ALPHA_MASK) == ALPHA_MASK) {
+        return format_argument(writer, " #%06" PRIx32, bgra & RGB_MASK);
+    }
+    else {
+        return format_argument(writer, " #%08" PRIx32, bgra);
+    }
+}
+
 
 bool DP_text_writer_raw_write(DP_TextWriter *writer, const char *buffer,
                               size_t size)
@@ -93,6 +93,9 @@ bool DP_text_writer_write_subfield_uint(DP_TextWriter *writer,
 bool DP_text_writer_write_subfield_decimal(DP_TextWriter *writer,
                                            double value) DP_MUST_CHECK;
 
+bool DP_text_writer_write_subfield_argb_color(DP_TextWriter *writer,
+                                              uint32_t bgra) DP_MUST_CHECK;
+
 
 bool DP_text_writer_raw_write(DP_TextWriter *writer, const char *buffer,
                               size_t size) DP_MUST_CHECK;
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpmsg/message.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+
+// libFuzzer target for the binary message parser. Anything that parses must
+// serialize again and parse back into an equal message. Seed it with the
+// corpus that libmsg/test/message_roundtrip.c writes to test/tmp/fuzz_corpus.
+
+int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size);
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    unsigned char **buffer = user;
+    *buffer = DP_malloc(length);
+    return *buffer;
+}
+
+int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size)
+{
+    DP_Message *msg = DP_message_deserialize(data, size, true);
+    if (msg) {
+        unsigned char *buffer = NULL;
+        size_t length = DP_message_serialize(msg, true, get_buffer, &buffer);
+        if (length == 0) {
+            fprintf(stderr, "Can't serialize parsed %s: %s\n",
+                    DP_message_type_enum_name(DP_message_type(msg)),
+                    DP_error());
+            abort();
+        }
+
+        DP_Message *reparsed = DP_message_deserialize(buffer, length, true);
+        if (!reparsed || !DP_message_equals(msg, reparsed)) {
+            fprintf(stderr, "Serialized %s doesn't parse back equal: %s\n",
+                    DP_message_type_enum_name(DP_message_type(msg)),
+                    reparsed ? "not equal" : DP_error());
+            abort();
+        }
+
+        DP_message_decref(reparsed);
+        DP_free(buffer);
+        DP_message_decref(msg);
+    }
+    return 0;
+}
@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpmsg/acl.h>
+#include <dpmsg/message.h>
+#include <dptest.h>
+
+
+#define MAX_MESSAGES 16
+
+typedef struct DP_AclResetImageTestMessages {
+    int count;
+    DP_Message *msgs[MAX_MESSAGES];
+} DP_AclResetImageTestMessages;
+
+static bool push_message(void *user, DP_Message *msg)
+{
+    DP_AclResetImageTestMessages *artm = user;
+    if (artm->count < MAX_MESSAGES) {
+        artm->msgs[artm->count++] = msg;
+        return true;
+    }
+    else {
+        DP_message_decref(msg);
+        return false;
+    }
+}
+
+static void build_reset_image(TEST_PARAMS, DP_AclState *acls,
+                              DP_AclResetImageTestMessages *artm)
+{
+    artm->count = 0;
+    OK(DP_acl_state_reset_image_build(acls, 0,
+                                      DP_ACL_STATE_RESET_IMAGE_RECORDING_FLAGS,
+                                      push_message, artm),
+       "built reset image");
+}
+
+static void free_reset_image(DP_AclResetImageTestMessages *artm)
+{
+    for (int i = 0; i < artm->count; ++i) {
+        DP_message_decref(artm->msgs[i]);
+    }
+}
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    unsigned char *buffer = DP_malloc(length);
+    *(unsigned char **)user = buffer;
+    return buffer;
+}
+
+static const uint8_t *find_users(TEST_PARAMS,
+                                 DP_AclResetImageTestMessages *artm,
+                                 DP_MessageType type, int *out_count)
+{
+    for (int i = 0; i < artm->count; ++i) {
+        DP_Message *msg = artm->msgs[i];
+        if (DP_message_type(msg) == type) {
+            void *internal = DP_message_internal(msg);
+            switch (type) {
+            case DP_MSG_SESSION_OWNER:
+                return DP_msg_session_owner_users(internal, out_count);
+            case DP_MSG_TRUSTED_USERS:
+                return DP_msg_trusted_users_users(internal, out_count);
+            case DP_MSG_USER_ACL:
+                return DP_msg_user_acl_users(internal, out_count);
+            default:
+                break;
+            }
+        }
+    }
+    FAIL("no message of type %d in reset image", (int)type);
+    *out_count = 0;
+    return NULL;
+}
+
+static void check_users(TEST_PARAMS, DP_AclResetImageTestMessages *artm,
+                        DP_MessageType type, int expected_count,
+                        const uint8_t *expected)
+{
+    int count;
+    const uint8_t *users = find_users(TEST_ARGS, artm, type, &count);
+    if (INT_EQ_OK(count, expected_count, "%s user count is %d",
+                  DP_message_type_enum_name(type), expected_count)) {
+        OK(count == 0 || memcmp(users, expected, DP_int_to_size(count)) == 0,
+           "%s lists the expected users", DP_message_type_enum_name(type));
+    }
+}
+
+static void handle_users(TEST_PARAMS, DP_AclState *acls, DP_Message *msg)
+{
+    uint8_t result = DP_acl_state_handle(acls, msg, false);
+    OK(!(result & DP_ACL_STATE_FILTERED_BIT), "%s from the server passes",
+       DP_message_type_enum_name(DP_message_type(msg)));
+    DP_message_decref(msg);
+}
+
+static void set_user_ids(int count, uint8_t *out, void *user)
+{
+    memcpy(out, user, DP_int_to_size(count));
+}
+
+static DP_AclState *restore(TEST_PARAMS, DP_AclResetImageTestMessages *artm)
+{
+    DP_AclState *acls = DP_acl_state_new();
+    for (int i = 0; i < artm->count; ++i) {
+        // Go through the wire format, since that's where a list that's too
+        // long would fall apart.
+        DP_Message *msg = artm->msgs[i];
+        unsigned char *buffer = NULL;
+        size_t length = DP_message_serialize(msg, true, get_buffer, &buffer);
+        DP_Message *deserialized =
+            length == 0 ? NULL : DP_message_deserialize(buffer, length, true);
+        DP_free(buffer);
+        if (NOT_NULL_OK(deserialized, "%s deserializes (%s)",
+                        DP_message_type_enum_name(DP_message_type(msg)),
+                        deserialized ? "ok" : DP_error())) {
+            uint8_t result = DP_acl_state_handle(acls, deserialized, true);
+            OK(!(result & DP_ACL_STATE_FILTERED_BIT), "%s gets applied",
+               DP_message_type_enum_name(DP_message_type(msg)));
+            DP_message_decref(deserialized);
+        }
+    }
+    return acls;
+}
+
+
+// The server's own id 0 can end up in the user lists, but it's always an
+// operator and never locked. Leaving it out of the reset image must not
+// change what any user, the server included, is allowed to do.
+static void reset_image_leaves_out_server(TEST_PARAMS)
+{
+    DP_AclState *acls = DP_acl_state_new();
+    uint8_t operators[] = {0, 1, 5};
+    uint8_t trusted[] = {0, 3};
+    uint8_t locked[] = {0, 4};
+    handle_users(TEST_ARGS, acls,
+                 DP_msg_session_owner_new(0, set_user_ids,
+                                          DP_ARRAY_LENGTH(operators),
+                                          operators));
+    handle_users(TEST_ARGS, acls,
+                 DP_msg_trusted_users_new(0, set_user_ids,
+                                          DP_ARRAY_LENGTH(trusted), trusted));
+    handle_users(TEST_ARGS, acls,
+                 DP_msg_user_acl_new(0, set_user_ids, DP_ARRAY_LENGTH(locked),
+                                     locked));
+
+    DP_AclResetImageTestMessages artm;
+    build_reset_image(TEST_ARGS, acls, &artm);
+    check_users(TEST_ARGS, &artm, DP_MSG_SESSION_OWNER, 2, operators + 1);
+    check_users(TEST_ARGS, &artm, DP_MSG_TRUSTED_USERS, 1, trusted + 1);
+    check_users(TEST_ARGS, &artm, DP_MSG_USER_ACL, 1, locked + 1);
+
+    DP_AclState *restored = restore(TEST_ARGS, &artm);
+    DP_UserAcls users = DP_acl_state_users(acls);
+    DP_UserAcls restored_users = DP_acl_state_users(restored);
+    bool same = true;
+    for (int i = 0; i < 256; ++i) {
+        uint8_t user_id = DP_int_to_uint8(i);
+        if (DP_acl_state_user_tier(acls, user_id)
+                != DP_acl_state_user_tier(restored, user_id)
+            || (user_id != 0
+                && DP_user_acls_is_locked(&users, user_id)
+                       != DP_user_acls_is_locked(&restored_users, user_id))) {
+            DIAG("user %d differs", i);
+            same = false;
+        }
+    }
+    OK(same, "restored state treats every user the same");
+    OK(DP_acl_state_is_op(restored, 0), "server is still an operator");
+
+    // The server's commands aren't held back by user locks either way.
+    DP_Message *msg = DP_msg_undo_point_new(0);
+    OK(!(DP_acl_state_handle(acls, msg, false) & DP_ACL_STATE_FILTERED_BIT),
+       "server command passes in the original state");
+    OK(!(DP_acl_state_handle(restored, msg, false)
+         & DP_ACL_STATE_FILTERED_BIT),
+       "server command passes in the restored state");
+    DP_message_decref(msg);
+
+    DP_acl_state_free(restored);
+    free_reset_image(&artm);
+    DP_acl_state_free(acls);
+}
+
+// Playback makes everyone an operator. With the server in there, that would
+// be 256 users, one more than a session owner message can hold.
+static void reset_image_from_playback(TEST_PARAMS)
+{
+    DP_AclState *acls = DP_acl_state_new_playback();
+    DP_AclResetImageTestMessages artm;
+    build_reset_image(TEST_ARGS, acls, &artm);
+
+    uint8_t clients[255];
+    for (int i = 0; i < 255; ++i) {
+        clients[i] = DP_int_to_uint8(i + 1);
+    }
+    check_users(TEST_ARGS, &artm, DP_MSG_SESSION_OWNER, 255, clients);
+
+    DP_AclState *restored = restore(TEST_ARGS, &artm);
+    bool all_ops = true;
+    for (int i = 0; i < 256; ++i) {
+        if (!DP_acl_state_is_op(restored, DP_int_to_uint8(i))) {
+            DIAG("user %d is not an operator", i);
+            all_ops = false;
+        }
+    }
+    OK(all_ops, "every user is an operator after restoring");
+
+    DP_acl_state_free(restored);
+    free_reset_image(&artm);
+    DP_acl_state_free(acls);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(reset_image_leaves_out_server);
+    REGISTER_TEST(reset_image_from_playback);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
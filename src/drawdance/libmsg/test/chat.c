@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpmsg/message.h>
+#include <dpmsg/text_reader.h>
+#include <dpmsg/text_writer.h>
+#include <dptest.h>
+
+
+#define USER 7
+#define TEXT "hello /me \"quoted\"\nnext line"
+
+typedef struct DP_ChatTestBuffer {
+    unsigned char *data;
+    size_t size;
+} DP_ChatTestBuffer;
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    DP_ChatTestBuffer *buffer = user;
+    buffer->data = DP_malloc(length);
+    buffer->size = length;
+    return buffer->data;
+}
+
+static DP_Message *make_chat(unsigned int tflags, unsigned int oflags,
+                             const char *text, size_t length)
+{
+    return DP_msg_chat_new(USER, (uint8_t)tflags, (uint8_t)oflags, text,
+                           length);
+}
+
+static void check_chat(TEST_PARAMS, DP_Message *msg, unsigned int tflags,
+                       unsigned int oflags, const char *what)
+{
+    DP_MsgChat *mc = DP_msg_chat_cast(msg);
+    FATAL(NOT_NULL_OK(mc, "%s is a chat message", what));
+    UINT_EQ_OK(DP_message_context_id(msg), USER, "%s context id", what);
+    UINT_EQ_OK(DP_msg_chat_tflags(mc), tflags, "%s tflags", what);
+    UINT_EQ_OK(DP_msg_chat_oflags(mc), oflags, "%s oflags", what);
+    size_t length;
+    const char *text = DP_msg_chat_message(mc, &length);
+    UINT_EQ_OK(length, strlen(TEXT), "%s text length", what);
+    STR_EQ_OK(text, TEXT, "%s text", what);
+}
+
+static DP_Message *binary_round_trip(TEST_PARAMS, DP_Message *msg)
+{
+    DP_ChatTestBuffer buffer = {NULL, 0};
+    size_t size = DP_message_serialize(msg, true, get_buffer, &buffer);
+    FATAL(OK(size != 0, "serialize chat (error: %s)", DP_error()));
+    DP_Message *result = DP_message_deserialize(buffer.data, size, false);
+    DP_free(buffer.data);
+    return result;
+}
+
+static DP_Message *text_round_trip(TEST_PARAMS, DP_Message *msg)
+{
+    void **buffer_ptr;
+    size_t *size_ptr;
+    DP_Output *output = DP_mem_output_new(64, false, &buffer_ptr, &size_ptr);
+    DP_TextWriter *tw = DP_text_writer_new(output);
+    FATAL(OK(DP_message_write_text(msg, tw), "write chat as text (error: %s)",
+             DP_error()));
+    // The pointers go away with the writer, the buffer itself doesn't.
+    void *buffer = *buffer_ptr;
+    size_t size = *size_ptr;
+    DP_text_writer_free(tw);
+
+    DP_TextReader *tr =
+        DP_text_reader_new(DP_mem_input_new_free_on_close(buffer, size));
+    DP_Message *result;
+    DP_TextReaderResult read = DP_text_reader_read_message(tr, &result);
+    DP_text_reader_free(tr);
+    return read == DP_TEXT_READER_SUCCESS ? result : NULL;
+}
+
+
+static void chat_flags_round_trip(TEST_PARAMS)
+{
+    for (unsigned int tflags = 0; tflags <= DP_MSG_CHAT_TFLAGS_BYPASS;
+         ++tflags) {
+        for (unsigned int oflags = 0; oflags <= 0xf; ++oflags) {
+            DP_Message *msg = make_chat(tflags, oflags, TEXT, strlen(TEXT));
+
+            DP_Message *binary = binary_round_trip(TEST_ARGS, msg);
+            if (NOT_NULL_OK(binary, "binary round trip of %u/%u (error: %s)",
+                            tflags, oflags, DP_error())) {
+                check_chat(TEST_ARGS, binary, tflags, oflags, "binary");
+                OK(DP_message_equals(msg, binary), "binary %u/%u equal",
+                   tflags, oflags);
+                DP_message_decref(binary);
+            }
+
+            DP_Message *text = text_round_trip(TEST_ARGS, msg);
+            if (NOT_NULL_OK(text, "text round trip of %u/%u (error: %s)",
+                            tflags, oflags, DP_error())) {
+                check_chat(TEST_ARGS, text, tflags, oflags, "text");
+                OK(DP_message_equals(msg, text), "text %u/%u equal", tflags,
+                   oflags);
+                DP_message_decref(text);
+            }
+
+            DP_message_decref(msg);
+        }
+    }
+}
+
+static void chat_text_length(TEST_PARAMS)
+{
+    size_t length = DP_MSG_CHAT_MESSAGE_MAX_LEN;
+    char *text = DP_malloc(length);
+    memset(text, 'x', length);
+    DP_Message *msg = make_chat(0, DP_MSG_CHAT_OFLAGS_SHOUT, text, length);
+    DP_free(text);
+    UINT_EQ_OK(DP_message_length(msg),
+               DP_MESSAGE_HEADER_LENGTH + DP_MESSAGE_MAX_PAYLOAD_LENGTH,
+               "longest chat fills the body");
+
+    DP_Message *binary = binary_round_trip(TEST_ARGS, msg);
+    if (NOT_NULL_OK(binary, "longest chat round trips (error: %s)",
+                    DP_error())) {
+        OK(DP_message_equals(msg, binary), "longest chat equal");
+        DP_message_decref(binary);
+    }
+    DP_message_decref(msg);
+
+    // Flags without any text at all.
+    unsigned char body[] = {0, 0, DP_MSG_CHAT, USER, 0, 0};
+    NULL_OK(DP_message_deserialize(body, sizeof(body), false),
+            "chat without flags rejected");
+}
+
+static void chat_bypasses_recording(TEST_PARAMS)
+{
+    for (unsigned int oflags = 0; oflags <= 0xf; ++oflags) {
+        DP_Message *msg = make_chat(0, oflags, TEXT, strlen(TEXT));
+        NOK(DP_message_bypasses_recording(msg), "%u chat gets recorded",
+            oflags);
+        DP_message_decref(msg);
+
+        msg = make_chat(DP_MSG_CHAT_TFLAGS_BYPASS, oflags, TEXT, strlen(TEXT));
+        OK(DP_message_bypasses_recording(msg), "%u bypass chat doesn't",
+           oflags);
+        DP_message_decref(msg);
+    }
+
+    unsigned char bypass[] = {DP_MSG_CHAT_TFLAGS_BYPASS, 0, 'h', 'i'};
+    DP_Message *opaque =
+        DP_message_new_opaque(DP_MSG_CHAT, USER, bypass, sizeof(bypass));
+    OK(DP_message_bypasses_recording(opaque), "opaque bypass chat doesn't");
+    DP_message_decref(opaque);
+
+    DP_Message *private_chat = DP_msg_private_chat_new(USER, 1, 0, "hi", 2);
+    NOK(DP_message_bypasses_recording(private_chat),
+        "private chat isn't flagged");
+    DP_message_decref(private_chat);
+
+    DP_Message *undo_point = DP_msg_undo_point_new(USER);
+    NOK(DP_message_bypasses_recording(undo_point), "commands aren't flagged");
+    DP_message_decref(undo_point);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(chat_flags_round_trip);
+    REGISTER_TEST(chat_text_length);
+    REGISTER_TEST(chat_bypasses_recording);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
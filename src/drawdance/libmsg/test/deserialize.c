@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dptest.h>
+
+
+typedef struct DP_DeserializeTestBuffer {
+    unsigned char *data;
+    size_t size;
+} DP_DeserializeTestBuffer;
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    DP_DeserializeTestBuffer *buffer = user;
+    buffer->data = DP_malloc(length);
+    buffer->size = length;
+    return buffer->data;
+}
+
+static void set_bytes(size_t size, unsigned char *out, void *user)
+{
+    memset(out, *(int *)user, size);
+}
+
+static DP_Message *make_message(int i)
+{
+    int fill = 0x5a;
+    switch (i) {
+    case 0:
+        return DP_msg_undo_point_new(1);
+    case 1:
+        return DP_msg_fill_rect_new(1, 0x101, DP_BLEND_MODE_NORMAL, 1, 2, 3, 4,
+                                    0xff112233u);
+    case 2:
+        return DP_msg_chat_new(2, 0, 0, "hello", 5);
+    case 3:
+        return DP_msg_join_new(3, 0, "alice", 5, set_bytes, 40, &fill);
+    case 4:
+        return DP_msg_layer_create_new(1, 0x102, 0, 0, 0, "Layer", 5);
+    case 5:
+        return DP_msg_put_image_new(1, 0x101, DP_BLEND_MODE_NORMAL, 0, 0, 8, 8,
+                                    set_bytes, 100, &fill);
+    default:
+        return NULL;
+    }
+}
+
+// Every prefix of a serialized message is missing part of it, so none of
+// them may produce a message, they must all fail cleanly instead.
+static void deserialize_truncated(TEST_PARAMS)
+{
+    DP_Message *msg;
+    for (int i = 0; (msg = make_message(i)) != NULL; ++i) {
+        const char *name = DP_message_type_name(DP_message_type(msg));
+        DP_DeserializeTestBuffer buffer = {NULL, 0};
+        size_t size = DP_message_serialize(msg, true, get_buffer, &buffer);
+        FATAL(OK(size != 0, "serialize %s", name));
+
+        size_t accepted = 0;
+        for (size_t length = 0; length < size; ++length) {
+            DP_Message *result =
+                DP_message_deserialize(buffer.data, length, true);
+            if (result) {
+                ++accepted;
+                DP_message_decref(result);
+            }
+        }
+        UINT_EQ_OK(accepted, 0u, "all %zu truncations of %s rejected", size,
+                   name);
+
+        DP_Message *result = DP_message_deserialize(buffer.data, size, true);
+        if (NOT_NULL_OK(result, "whole %s deserialized (error: %s)", name,
+                        DP_error())) {
+            OK(DP_message_equals(msg, result), "%s round trips", name);
+            DP_message_decref(result);
+        }
+
+        DP_free(buffer.data);
+        DP_message_decref(msg);
+    }
+}
+
+
+typedef struct DP_DeserializeFixture {
+    const char *title;
+    size_t size;
+    const unsigned char *data;
+} DP_DeserializeFixture;
+
+#define FIXTURE(TITLE, ...)                                           \
+    {TITLE, sizeof((const unsigned char[]){__VA_ARGS__}),             \
+     (const unsigned char[]){__VA_ARGS__}}
+
+static const DP_DeserializeFixture malformed_fixtures[] = {
+    FIXTURE("header too short", 0x00, 0x00, 0x80),
+    FIXTURE("body length beyond buffer", 0xff, 0xff, 0x80, 0x01),
+    FIXTURE("undo point with body", 0x00, 0x01, 0x80, 0x01, 0x00),
+    FIXTURE("fill rect too short", 0x00, 0x02, 0x89, 0x01, 0x01, 0x01),
+    FIXTURE("join name longer than body", 0x00, 0x04, 0x20, 0x01, 0x00, 0xff,
+            'a', 'b'),
+    FIXTURE("layer create too short", 0x00, 0x08, 0x82, 0x01, 0x01, 0x02,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00),
+    FIXTURE("classic dabs with partial dab", 0x00, 0x1e, 0x94, 0x01, 0x01,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07),
+    FIXTURE("unknown message type", 0x00, 0x00, 0x0a, 0x01),
+};
+
+static void deserialize_malformed(TEST_PARAMS)
+{
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(malformed_fixtures); ++i) {
+        const DP_DeserializeFixture *fixture = &malformed_fixtures[i];
+        DP_Message *msg =
+            DP_message_deserialize(fixture->data, fixture->size, true);
+        if (!NOK(msg, "%s rejected (%s)", fixture->title, DP_error())) {
+            DP_message_decref(msg);
+        }
+    }
+
+    unsigned char *oversized = DP_malloc_zeroed(70000);
+    NOK(DP_message_deserialize_body(DP_MSG_CHAT, 1, oversized, 70000, true),
+        "oversized chat body rejected (%s)", DP_error());
+    NOK(DP_message_deserialize_body(DP_MSG_DRAW_DABS_CLASSIC, 1, oversized,
+                                    70000, true),
+        "oversized dabs body rejected (%s)", DP_error());
+    DP_free(oversized);
+}
+
+
+static unsigned int next_random(unsigned int *state)
+{
+    *state = *state * 1103515245u + 12345u;
+    return *state >> 16;
+}
+
+// Throws deterministic garbage at every message type. Anything is allowed to
+// be rejected, but what does get accepted must be a message of that type.
+static void deserialize_garbage(TEST_PARAMS)
+{
+    unsigned int state = 1;
+    unsigned char body[512];
+    for (int type = 0; type <= DP_MESSAGE_MAX; ++type) {
+        int accepted = 0;
+        int wrong = 0;
+        for (int attempt = 0; attempt < 200; ++attempt) {
+            size_t length = next_random(&state) % sizeof(body);
+            // Small byte values make for plausible lengths and counts.
+            unsigned int range = attempt % 2 == 0 ? 4 : 256;
+            for (size_t i = 0; i < length; ++i) {
+                body[i] = (unsigned char)(next_random(&state) % range);
+            }
+
+            DP_Message *msg = DP_message_deserialize_body(
+                type, 1, body, length, attempt % 4 < 2);
+            if (msg) {
+                ++accepted;
+                if ((int)DP_message_type(msg) != type) {
+                    ++wrong;
+                }
+                DP_message_decref(msg);
+            }
+        }
+        INT_EQ_OK(wrong, 0, "%d garbage messages of type %d accepted as such",
+                  accepted, type);
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(deserialize_truncated);
+    REGISTER_TEST(deserialize_malformed);
+    REGISTER_TEST(deserialize_garbage);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,589 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dpmsg/text_reader.h>
+#include <dpmsg/text_writer.h>
+#include <dptest.h>
+
+
+// Round-trips the smallest and largest values of every message type through
+// the binary and the text format. The random instances that
+// read_write_roundtrip.c generates rarely hit the edges, this does on purpose.
+// The serialized messages get written to test/tmp/fuzz_corpus, where they
+// serve as seeds for libmsg/fuzz/deserialize.c.
+
+#define CORPUS_DIR "test/tmp/fuzz_corpus"
+
+#define VARIANT_MIN 0
+#define VARIANT_MAX 1
+#define VARIANT_COUNT 2
+
+static char text[DP_MESSAGE_MAX_PAYLOAD_LENGTH + 1];
+
+typedef struct DP_RoundtripBuffer {
+    unsigned char *data;
+    size_t size;
+} DP_RoundtripBuffer;
+
+static unsigned char *get_buffer(void *user, size_t length)
+{
+    DP_RoundtripBuffer *buffer = user;
+    buffer->data = DP_malloc(length);
+    buffer->size = length;
+    return buffer->data;
+}
+
+
+static unsigned int context_id(bool max)
+{
+    return max ? UINT8_MAX : 0;
+}
+
+static uint8_t u8(bool max)
+{
+    return max ? UINT8_MAX : 0;
+}
+
+static uint16_t u16(bool max)
+{
+    return max ? UINT16_MAX : 0;
+}
+
+static uint32_t u32(bool max)
+{
+    return max ? UINT32_MAX : 0;
+}
+
+static int32_t i32(bool max)
+{
+    return max ? INT32_MAX : INT32_MIN;
+}
+
+static int8_t i8(bool max)
+{
+    return max ? INT8_MAX : INT8_MIN;
+}
+
+static uint8_t blend_mode(bool max)
+{
+    return max ? DP_BLEND_MODE_REPLACE : 0;
+}
+
+static uint8_t pick_variant(bool max, const unsigned int *variants, int count)
+{
+    return DP_uint_to_uint8(max ? variants[count - 1] : variants[0]);
+}
+
+static uint8_t pick_flags(bool max, const unsigned int *flags, int count)
+{
+    unsigned int result = 0;
+    if (max) {
+        for (int i = 0; i < count; ++i) {
+            result |= flags[i];
+        }
+    }
+    return DP_uint_to_uint8(result);
+}
+
+static size_t pick_size(bool max, size_t min_size, size_t max_size)
+{
+    return max ? max_size : min_size;
+}
+
+static int pick_count(bool max, int min_count, int max_count)
+{
+    return max ? max_count : min_count;
+}
+
+static void set_bytes(size_t size, unsigned char *out, void *user)
+{
+    memset(out, *(bool *)user ? 0xff : 0, size);
+}
+
+static void set_uint8s(int count, uint8_t *out, void *user)
+{
+    memset(out, *(bool *)user ? 0xff : 0, DP_int_to_size(count));
+}
+
+static void set_uint16s(int count, uint16_t *out, void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        out[i] = u16(*(bool *)user);
+    }
+}
+
+static void set_classic_dabs(int count, DP_ClassicDab *out, void *user)
+{
+    bool max = *(bool *)user;
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(out, i, i8(max), i8(max), u16(max), u8(max),
+                            u8(max));
+    }
+}
+
+static void set_pixel_dabs(int count, DP_PixelDab *out, void *user)
+{
+    bool max = *(bool *)user;
+    for (int i = 0; i < count; ++i) {
+        DP_pixel_dab_init(out, i, i8(max), i8(max), u8(max), u8(max));
+    }
+}
+
+static void set_mypaint_dabs(int count, DP_MyPaintDab *out, void *user)
+{
+    bool max = *(bool *)user;
+    for (int i = 0; i < count; ++i) {
+        DP_mypaint_dab_init(out, i, i8(max), i8(max), u16(max), u8(max),
+                            u8(max), u8(max), u8(max));
+    }
+}
+
+static void set_stamp_dabs(int count, DP_StampDab *out, void *user)
+{
+    bool max = *(bool *)user;
+    for (int i = 0; i < count; ++i) {
+        DP_stamp_dab_init(out, i, i8(max), i8(max), u16(max), u8(max),
+                          u8(max));
+    }
+}
+
+static void set_soft_dabs(int count, DP_SoftDab *out, void *user)
+{
+    bool max = *(bool *)user;
+    for (int i = 0; i < count; ++i) {
+        DP_soft_dab_init(out, i, i8(max), i8(max), u16(max), u8(max), u8(max));
+    }
+}
+
+static void set_fill_gradient_stops(int count, DP_FillGradientStop *out,
+                                    void *user)
+{
+    bool max = *(bool *)user;
+    for (int i = 0; i < count; ++i) {
+        DP_fill_gradient_stop_init(out, i, u16(max), u32(max));
+    }
+}
+
+#define PICK_VARIANT(MAX, ALL, NUM) \
+    pick_variant(MAX, (unsigned int[]){ALL}, NUM)
+#define PICK_FLAGS(MAX, ALL, NUM) pick_flags(MAX, (unsigned int[]){ALL}, NUM)
+#define PICK_LEN(MAX, PREFIX) \
+    pick_size(MAX, PREFIX##_MIN_LEN, PREFIX##_MAX_LEN)
+#define PICK_SIZE(MAX, PREFIX) \
+    pick_size(MAX, PREFIX##_MIN_SIZE, PREFIX##_MAX_SIZE)
+#define PICK_COUNT(MAX, PREFIX) \
+    pick_count(MAX, PREFIX##_MIN_COUNT, PREFIX##_MAX_COUNT)
+
+// Returns NULL for types that don't have a constructor. There's deliberately
+// no default case, so that adding a message type without handling it here
+// triggers a -Wswitch warning as well as a test failure.
+static DP_Message *make_message(DP_MessageType type, int variant)
+{
+    bool max = variant == VARIANT_MAX;
+    void *user = &max;
+    switch (type) {
+    case DP_MSG_SERVER_COMMAND:
+        return DP_msg_server_command_new(
+            context_id(max), text,
+            PICK_LEN(max, DP_MSG_SERVER_COMMAND_MSG));
+    case DP_MSG_DISCONNECT:
+        return DP_msg_disconnect_new(
+            context_id(max),
+            PICK_VARIANT(max, DP_MSG_DISCONNECT_ALL_REASON,
+                         DP_MSG_DISCONNECT_NUM_REASON),
+            text, PICK_LEN(max, DP_MSG_DISCONNECT_MESSAGE));
+    case DP_MSG_PING:
+        return DP_msg_ping_new(context_id(max), max);
+    case DP_MSG_KEEP_ALIVE:
+        return DP_msg_keep_alive_new(context_id(max));
+    case DP_MSG_JOIN: {
+        size_t name_len = PICK_LEN(max, DP_MSG_JOIN_NAME);
+        return DP_msg_join_new(
+            context_id(max),
+            PICK_FLAGS(max, DP_MSG_JOIN_ALL_FLAGS, DP_MSG_JOIN_NUM_FLAGS),
+            text, name_len, set_bytes,
+            pick_size(max, DP_MSG_JOIN_AVATAR_MIN_SIZE,
+                      DP_MESSAGE_MAX_PAYLOAD_LENGTH - DP_MSG_JOIN_STATIC_LENGTH
+                          - name_len),
+            user);
+    }
+    case DP_MSG_LEAVE:
+        return DP_msg_leave_new(context_id(max));
+    case DP_MSG_SESSION_OWNER:
+        return DP_msg_session_owner_new(
+            context_id(max), set_uint8s,
+            PICK_COUNT(max, DP_MSG_SESSION_OWNER_USERS), user);
+    case DP_MSG_CHAT:
+        return DP_msg_chat_new(
+            context_id(max),
+            PICK_FLAGS(max, DP_MSG_CHAT_ALL_TFLAGS, DP_MSG_CHAT_NUM_TFLAGS),
+            PICK_FLAGS(max, DP_MSG_CHAT_ALL_OFLAGS, DP_MSG_CHAT_NUM_OFLAGS),
+            text, PICK_LEN(max, DP_MSG_CHAT_MESSAGE));
+    case DP_MSG_TRUSTED_USERS:
+        return DP_msg_trusted_users_new(
+            context_id(max), set_uint8s,
+            PICK_COUNT(max, DP_MSG_TRUSTED_USERS_USERS), user);
+    case DP_MSG_SOFT_RESET:
+        return DP_msg_soft_reset_new(context_id(max));
+    case DP_MSG_PRIVATE_CHAT:
+        return DP_msg_private_chat_new(
+            context_id(max), u8(max), u8(max), text,
+            PICK_LEN(max, DP_MSG_PRIVATE_CHAT_MESSAGE));
+    case DP_MSG_INTERVAL:
+        return DP_msg_interval_new(context_id(max), u16(max));
+    case DP_MSG_LASER_TRAIL:
+        return DP_msg_laser_trail_new(context_id(max), u32(max), u8(max));
+    case DP_MSG_MOVE_POINTER:
+        return DP_msg_move_pointer_new(context_id(max), i32(max), i32(max));
+    case DP_MSG_MARKER:
+        return DP_msg_marker_new(context_id(max), text,
+                                 PICK_LEN(max, DP_MSG_MARKER_TEXT));
+    case DP_MSG_USER_ACL:
+        return DP_msg_user_acl_new(context_id(max), set_uint8s,
+                                   PICK_COUNT(max, DP_MSG_USER_ACL_USERS),
+                                   user);
+    case DP_MSG_LAYER_ACL:
+        return DP_msg_layer_acl_new(
+            context_id(max), u16(max), u8(max), set_uint8s,
+            PICK_COUNT(max, DP_MSG_LAYER_ACL_EXCLUSIVE), user);
+    case DP_MSG_FEATURE_ACCESS_LEVELS:
+        return DP_msg_feature_access_levels_new(
+            context_id(max), set_uint8s,
+            PICK_COUNT(max, DP_MSG_FEATURE_ACCESS_LEVELS_FEATURE_TIERS), user);
+    case DP_MSG_DEFAULT_LAYER:
+        return DP_msg_default_layer_new(context_id(max), u16(max));
+    case DP_MSG_FILTERED:
+        return DP_msg_filtered_new(context_id(max), set_bytes,
+                                   PICK_SIZE(max, DP_MSG_FILTERED_MESSAGE),
+                                   user);
+    case DP_MSG_UNDO_DEPTH:
+        return DP_msg_undo_depth_new(context_id(max), u8(max));
+    case DP_MSG_DATA:
+        return DP_msg_data_new(
+            context_id(max),
+            PICK_VARIANT(max, DP_MSG_DATA_ALL_TYPE, DP_MSG_DATA_NUM_TYPE),
+            u8(max), set_bytes, PICK_SIZE(max, DP_MSG_DATA_BODY), user);
+    case DP_MSG_LOCAL_CHANGE:
+        return DP_msg_local_change_new(
+            context_id(max),
+            PICK_VARIANT(max, DP_MSG_LOCAL_CHANGE_ALL_TYPE,
+                         DP_MSG_LOCAL_CHANGE_NUM_TYPE),
+            set_bytes, PICK_SIZE(max, DP_MSG_LOCAL_CHANGE_BODY), user);
+    case DP_MSG_UNDO_POINT:
+        return DP_msg_undo_point_new(context_id(max));
+    case DP_MSG_CANVAS_RESIZE:
+        return DP_msg_canvas_resize_new(context_id(max), i32(max), i32(max),
+                                        i32(max), i32(max));
+    case DP_MSG_LAYER_CREATE:
+        return DP_msg_layer_create_new(
+            context_id(max), u16(max), u16(max), u32(max),
+            PICK_FLAGS(max, DP_MSG_LAYER_CREATE_ALL_FLAGS,
+                       DP_MSG_LAYER_CREATE_NUM_FLAGS),
+            text, PICK_LEN(max, DP_MSG_LAYER_CREATE_TITLE));
+    case DP_MSG_LAYER_ATTRIBUTES:
+        return DP_msg_layer_attributes_new(
+            context_id(max), u16(max), u8(max),
+            PICK_FLAGS(max, DP_MSG_LAYER_ATTRIBUTES_ALL_FLAGS,
+                       DP_MSG_LAYER_ATTRIBUTES_NUM_FLAGS),
+            u8(max), blend_mode(max));
+    case DP_MSG_LAYER_RETITLE:
+        return DP_msg_layer_retitle_new(
+            context_id(max), u16(max), text,
+            PICK_LEN(max, DP_MSG_LAYER_RETITLE_TITLE));
+    case DP_MSG_LAYER_ORDER:
+        return DP_msg_layer_order_new(
+            context_id(max), set_uint16s,
+            PICK_COUNT(max, DP_MSG_LAYER_ORDER_LAYERS), user);
+    case DP_MSG_LAYER_DELETE:
+        return DP_msg_layer_delete_new(context_id(max), u16(max), max);
+    case DP_MSG_LAYER_VISIBILITY:
+        return DP_msg_layer_visibility_new(context_id(max), u16(max), max);
+    case DP_MSG_PUT_IMAGE:
+        return DP_msg_put_image_new(
+            context_id(max), u16(max), blend_mode(max), u32(max), u32(max),
+            u32(max), u32(max), set_bytes,
+            PICK_SIZE(max, DP_MSG_PUT_IMAGE_IMAGE), user);
+    case DP_MSG_FILL_RECT:
+        return DP_msg_fill_rect_new(context_id(max), u16(max), blend_mode(max),
+                                    u32(max), u32(max), u32(max), u32(max),
+                                    u32(max));
+    case DP_MSG_PEN_UP:
+        return DP_msg_pen_up_new(context_id(max));
+    case DP_MSG_ANNOTATION_CREATE:
+        return DP_msg_annotation_create_new(context_id(max), u16(max),
+                                            i32(max), i32(max), u16(max),
+                                            u16(max));
+    case DP_MSG_ANNOTATION_RESHAPE:
+        return DP_msg_annotation_reshape_new(context_id(max), u16(max),
+                                             i32(max), i32(max), u16(max),
+                                             u16(max));
+    case DP_MSG_ANNOTATION_EDIT:
+        return DP_msg_annotation_edit_new(
+            context_id(max), u16(max), u32(max),
+            PICK_FLAGS(max, DP_MSG_ANNOTATION_EDIT_ALL_FLAGS,
+                       DP_MSG_ANNOTATION_EDIT_NUM_FLAGS),
+            u8(max), text, PICK_LEN(max, DP_MSG_ANNOTATION_EDIT_TEXT));
+    case DP_MSG_ANNOTATION_DELETE:
+        return DP_msg_annotation_delete_new(context_id(max), u16(max));
+    case DP_MSG_MOVE_REGION:
+        return DP_msg_move_region_new(
+            context_id(max), u16(max), i32(max), i32(max), i32(max), i32(max),
+            i32(max), i32(max), i32(max), i32(max), i32(max), i32(max),
+            i32(max), i32(max), set_bytes,
+            PICK_SIZE(max, DP_MSG_MOVE_REGION_MASK), user);
+    case DP_MSG_PUT_TILE:
+        return DP_msg_put_tile_new(context_id(max), u16(max), u8(max),
+                                   u16(max), u16(max), u16(max), set_bytes,
+                                   PICK_SIZE(max, DP_MSG_PUT_TILE_IMAGE),
+                                   user);
+    case DP_MSG_CANVAS_BACKGROUND:
+        return DP_msg_canvas_background_new(
+            context_id(max), set_bytes,
+            PICK_SIZE(max, DP_MSG_CANVAS_BACKGROUND_IMAGE), user);
+    case DP_MSG_DRAW_DABS_CLASSIC:
+        return DP_msg_draw_dabs_classic_new(
+            context_id(max), u16(max), i32(max), i32(max), u32(max),
+            blend_mode(max), set_classic_dabs,
+            PICK_COUNT(max, DP_MSG_DRAW_DABS_CLASSIC_DABS), user);
+    case DP_MSG_DRAW_DABS_PIXEL:
+        return DP_msg_draw_dabs_pixel_new(
+            context_id(max), u16(max), i32(max), i32(max), u32(max),
+            blend_mode(max), set_pixel_dabs,
+            PICK_COUNT(max, DP_MSG_DRAW_DABS_PIXEL_DABS), user);
+    case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
+        return DP_msg_draw_dabs_pixel_square_new(
+            context_id(max), u16(max), i32(max), i32(max), u32(max),
+            blend_mode(max), set_pixel_dabs,
+            PICK_COUNT(max, DP_MSG_DRAW_DABS_PIXEL_DABS), user);
+    case DP_MSG_DRAW_DABS_MYPAINT:
+        return DP_msg_draw_dabs_mypaint_new(
+            context_id(max), u16(max), i32(max), i32(max), u32(max), u8(max),
+            u8(max), u8(max), u8(max), set_mypaint_dabs,
+            PICK_COUNT(max, DP_MSG_DRAW_DABS_MYPAINT_DABS), user);
+    case DP_MSG_STAMP_MASK:
+        return DP_msg_stamp_mask_new(context_id(max), u32(max), u16(max),
+                                     set_bytes,
+                                     PICK_SIZE(max, DP_MSG_STAMP_MASK_IMAGE),
+                                     user);
+    case DP_MSG_DRAW_DABS_STAMP:
+        return DP_msg_draw_dabs_stamp_new(
+            context_id(max), u16(max), i32(max), i32(max), u32(max),
+            blend_mode(max), u32(max), u32(max), u16(max), u8(max),
+            set_stamp_dabs, PICK_COUNT(max, DP_MSG_DRAW_DABS_STAMP_DABS),
+            user);
+    case DP_MSG_DRAW_DABS_SOFT:
+        return DP_msg_draw_dabs_soft_new(
+            context_id(max), u16(max), i32(max), i32(max), u32(max),
+            blend_mode(max), u8(max), u32(max), u16(max), u8(max), set_soft_dabs,
+            PICK_COUNT(max, DP_MSG_DRAW_DABS_SOFT_DABS), user);
+    case DP_MSG_MOVE_RECT:
+        return DP_msg_move_rect_new(
+            context_id(max), u16(max), u16(max), i32(max), i32(max), i32(max),
+            i32(max), i32(max), i32(max), set_bytes,
+            PICK_SIZE(max, DP_MSG_MOVE_RECT_MASK), user);
+    case DP_MSG_SET_METADATA_INT:
+        return DP_msg_set_metadata_int_new(
+            context_id(max),
+            PICK_VARIANT(max, DP_MSG_SET_METADATA_INT_ALL_FIELD,
+                         DP_MSG_SET_METADATA_INT_NUM_FIELD),
+            i32(max));
+    case DP_MSG_LAYER_TREE_CREATE:
+        return DP_msg_layer_tree_create_new(
+            context_id(max), u16(max), u16(max), u16(max), u32(max),
+            PICK_FLAGS(max, DP_MSG_LAYER_TREE_CREATE_ALL_FLAGS,
+                       DP_MSG_LAYER_TREE_CREATE_NUM_FLAGS),
+            text, PICK_LEN(max, DP_MSG_LAYER_TREE_CREATE_TITLE));
+    case DP_MSG_LAYER_TREE_MOVE:
+        return DP_msg_layer_tree_move_new(context_id(max), u16(max), u16(max),
+                                          u16(max));
+    case DP_MSG_LAYER_TREE_DELETE:
+        return DP_msg_layer_tree_delete_new(context_id(max), u16(max),
+                                            u16(max));
+    case DP_MSG_TRANSFORM_REGION:
+        return DP_msg_transform_region_new(
+            context_id(max), u16(max), u16(max), i32(max), i32(max), i32(max),
+            i32(max), i32(max), i32(max), i32(max), i32(max), i32(max),
+            i32(max), i32(max), i32(max),
+            PICK_VARIANT(max, DP_MSG_TRANSFORM_REGION_ALL_MODE,
+                         DP_MSG_TRANSFORM_REGION_NUM_MODE),
+            set_bytes, PICK_SIZE(max, DP_MSG_TRANSFORM_REGION_MASK), user);
+    case DP_MSG_TRACK_CREATE:
+        return DP_msg_track_create_new(
+            context_id(max), u16(max), u16(max), u16(max), text,
+            PICK_LEN(max, DP_MSG_TRACK_CREATE_TITLE));
+    case DP_MSG_TRACK_RETITLE:
+        return DP_msg_track_retitle_new(
+            context_id(max), u16(max), text,
+            PICK_LEN(max, DP_MSG_TRACK_RETITLE_TITLE));
+    case DP_MSG_TRACK_DELETE:
+        return DP_msg_track_delete_new(context_id(max), u16(max));
+    case DP_MSG_TRACK_ORDER:
+        return DP_msg_track_order_new(
+            context_id(max), set_uint16s,
+            PICK_COUNT(max, DP_MSG_TRACK_ORDER_TRACKS), user);
+    case DP_MSG_KEY_FRAME_SET:
+        return DP_msg_key_frame_set_new(
+            context_id(max), u16(max), u16(max), u16(max), u16(max),
+            PICK_VARIANT(max, DP_MSG_KEY_FRAME_SET_ALL_SOURCE,
+                         DP_MSG_KEY_FRAME_SET_NUM_SOURCE));
+    case DP_MSG_KEY_FRAME_RETITLE:
+        return DP_msg_key_frame_retitle_new(
+            context_id(max), u16(max), u16(max), text,
+            PICK_LEN(max, DP_MSG_KEY_FRAME_RETITLE_TITLE));
+    case DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES:
+        return DP_msg_key_frame_layer_attributes_new(
+            context_id(max), u16(max), u16(max), set_uint16s,
+            PICK_COUNT(max, DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES_LAYERS), user);
+    case DP_MSG_KEY_FRAME_DELETE:
+        return DP_msg_key_frame_delete_new(context_id(max), u16(max), u16(max),
+                                           u16(max), u16(max));
+    case DP_MSG_FILTER_REGION:
+        return DP_msg_filter_region_new(
+            context_id(max), u16(max), u32(max), u32(max), u32(max), u32(max),
+            PICK_VARIANT(max, DP_MSG_FILTER_REGION_ALL_FILTER,
+                         DP_MSG_FILTER_REGION_NUM_FILTER),
+            i32(max), i32(max), i32(max), i32(max));
+    case DP_MSG_FILL_GRADIENT:
+        return DP_msg_fill_gradient_new(
+            context_id(max), u16(max), u32(max), u32(max), u32(max), u32(max),
+            blend_mode(max),
+            PICK_VARIANT(max, DP_MSG_FILL_GRADIENT_ALL_SHAPE,
+                         DP_MSG_FILL_GRADIENT_NUM_SHAPE),
+            PICK_FLAGS(max, DP_MSG_FILL_GRADIENT_ALL_FLAGS,
+                       DP_MSG_FILL_GRADIENT_NUM_FLAGS),
+            i32(max), i32(max), i32(max), i32(max), i32(max),
+            set_fill_gradient_stops,
+            PICK_COUNT(max, DP_MSG_FILL_GRADIENT_STOPS), user);
+    case DP_MSG_ANNOTATION_ORDER:
+        return DP_msg_annotation_order_new(
+            context_id(max), u16(max),
+            PICK_VARIANT(max, DP_MSG_ANNOTATION_ORDER_ALL_DIRECTION,
+                         DP_MSG_ANNOTATION_ORDER_NUM_DIRECTION));
+    case DP_MSG_LAYER_METADATA:
+        return DP_msg_layer_metadata_new(
+            context_id(max), u16(max), u8(max), set_bytes,
+            PICK_SIZE(max, DP_MSG_LAYER_METADATA_METADATA), user);
+    case DP_MSG_UNDO:
+        return DP_msg_undo_new(context_id(max), u8(max), max);
+    // Internal messages never leave the client, extensions and the old tool
+    // change and pen move messages only exist as opaque messages.
+    case DP_MSG_INTERNAL:
+    case DP_MSG_EXTENSION:
+    case DP_MSG_TOOL_CHANGE:
+    case DP_MSG_PEN_MOVE:
+    case DP_MSG_TYPE_COUNT:
+        return NULL;
+    }
+    return NULL;
+}
+
+static bool is_skipped(DP_MessageType type)
+{
+    return type == DP_MSG_INTERNAL || type == DP_MSG_EXTENSION
+        || type == DP_MSG_TOOL_CHANGE || type == DP_MSG_PEN_MOVE;
+}
+
+
+static DP_Message *binary_round_trip(TEST_PARAMS, DP_Message *msg,
+                                     const char *corpus_path)
+{
+    DP_RoundtripBuffer buffer = {NULL, 0};
+    size_t size = DP_message_serialize(msg, true, get_buffer, &buffer);
+    if (!OK(size != 0, "serialize %s (error: %s)", corpus_path, DP_error())) {
+        return NULL;
+    }
+
+    DP_Output *output = DP_file_output_new_from_path(corpus_path);
+    if (NOT_NULL_OK(output, "open %s (error: %s)", corpus_path, DP_error())) {
+        OK(DP_output_write(output, buffer.data, size)
+               && DP_output_free(output),
+           "write %s (error: %s)", corpus_path, DP_error());
+    }
+
+    DP_Message *result = DP_message_deserialize(buffer.data, size, true);
+    DP_free(buffer.data);
+    return result;
+}
+
+static DP_Message *text_round_trip(TEST_PARAMS, DP_Message *msg,
+                                   const char *name)
+{
+    void **buffer_ptr;
+    size_t *size_ptr;
+    DP_Output *output = DP_mem_output_new(1024, false, &buffer_ptr, &size_ptr);
+    DP_TextWriter *tw = DP_text_writer_new(output);
+    if (!OK(DP_message_write_text(msg, tw), "write %s as text (error: %s)",
+            name, DP_error())) {
+        DP_free(*buffer_ptr);
+        DP_text_writer_free(tw);
+        return NULL;
+    }
+    // The pointers go away with the writer, the buffer itself doesn't.
+    void *buffer = *buffer_ptr;
+    size_t size = *size_ptr;
+    DP_text_writer_free(tw);
+
+    DP_TextReader *tr =
+        DP_text_reader_new(DP_mem_input_new_free_on_close(buffer, size));
+    DP_Message *result;
+    DP_TextReaderResult read = DP_text_reader_read_message(tr, &result);
+    DP_text_reader_free(tr);
+    return read == DP_TEXT_READER_SUCCESS ? result : NULL;
+}
+
+static void check_round_trip(TEST_PARAMS, DP_Message *msg, const char *name)
+{
+    char *corpus_path = DP_format("%s/%s", CORPUS_DIR, name);
+    DP_Message *binary = binary_round_trip(TEST_ARGS, msg, corpus_path);
+    DP_free(corpus_path);
+    if (NOT_NULL_OK(binary, "binary round trip of %s (error: %s)", name,
+                    DP_error())) {
+        OK(DP_message_equals(msg, binary), "binary %s equal", name);
+        DP_message_decref(binary);
+    }
+
+    DP_Message *from_text = text_round_trip(TEST_ARGS, msg, name);
+    if (NOT_NULL_OK(from_text, "text round trip of %s (error: %s)", name,
+                    DP_error())) {
+        OK(DP_message_equals(msg, from_text), "text %s equal", name);
+        DP_message_decref(from_text);
+    }
+}
+
+
+static void message_boundaries_round_trip(TEST_PARAMS)
+{
+    memset(text, 'x', sizeof(text) - 1);
+    for (int i = 0; i <= DP_MESSAGE_MAX; ++i) {
+        DP_MessageType type = (DP_MessageType)i;
+        bool known = !DP_str_equal(DP_message_type_name(type), "unknown");
+        for (int variant = 0; variant < VARIANT_COUNT; ++variant) {
+            DP_Message *msg = make_message(type, variant);
+            if (msg) {
+                char *name = DP_format("%s_%s", DP_message_type_enum_name(type),
+                                       variant == VARIANT_MIN ? "min" : "max");
+                check_round_trip(TEST_ARGS, msg, name);
+                DP_free(name);
+                DP_message_decref(msg);
+            }
+            else if (known && !is_skipped(type)) {
+                FAIL("message type %s is covered",
+                     DP_message_type_enum_name(type));
+            }
+        }
+    }
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(message_boundaries_round_trip);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpmsg/messages.h>
+#include <dpmsg/rust.h>
+#include <dptest.h>
+
+
+#define NS     DP_PROTOCOL_VERSION_NAMESPACE
+#define SERVER DP_PROTOCOL_VERSION_SERVER
+#define MAJOR  DP_PROTOCOL_VERSION_MAJOR
+#define MINOR  DP_PROTOCOL_VERSION_MINOR
+
+typedef struct DP_ProtocolVersionTestVersion {
+    const char *ns;
+    int server, major, minor;
+} DP_ProtocolVersionTestVersion;
+
+#define CURRENT      {NS, SERVER, MAJOR, MINOR}
+#define DRAWPILE_2_1 {NS, 4, 21, 2}
+#define DRAWPILE_2_0 {NS, 4, 20, 1}
+
+static DP_ProtocolVersion *make(DP_ProtocolVersionTestVersion v)
+{
+    return DP_protocol_version_new(v.ns, v.server, v.major, v.minor);
+}
+
+static const char *compatibility_name(DP_ProtocolCompatibility compat)
+{
+    switch (compat) {
+    case DP_PROTOCOL_COMPATIBILITY_COMPATIBLE:
+        return "compatible";
+    case DP_PROTOCOL_COMPATIBILITY_MINOR_INCOMPATIBILITY:
+        return "minor incompatibility";
+    case DP_PROTOCOL_COMPATIBILITY_BACKWARD_COMPATIBLE:
+        return "backward compatible";
+    case DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE:
+        return "incompatible";
+    }
+    return "unknown";
+}
+
+
+static void protocol_version_parse(TEST_PARAMS)
+{
+    struct {
+        const char *input;
+        bool valid;
+        DP_ProtocolVersionTestVersion expected;
+    } cases[] = {
+        {DP_PROTOCOL_VERSION, true, CURRENT},
+        {"dp:4.21.2", true, DRAWPILE_2_1},
+        {"xy:1.2.3", true, {"xy", 1, 2, 3}},
+        {"dp:4.120.0", true, {NS, 4, 120, 0}},
+        {"", false, {NULL, 0, 0, 0}},
+        {"dp", false, {NULL, 0, 0, 0}},
+        {"dp:4.24", false, {NULL, 0, 0, 0}},
+        {"dp:4.24.1.0", false, {NULL, 0, 0, 0}},
+        {"DP:4.24.1", false, {NULL, 0, 0, 0}},
+        {"dp:-4.24.1", false, {NULL, 0, 0, 0}},
+        {" dp:4.24.1", false, {NULL, 0, 0, 0}},
+        {"dp:4.24.1 ", false, {NULL, 0, 0, 0}},
+    };
+
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        const char *input = cases[i].input;
+        DP_ProtocolVersion *protover = DP_protocol_version_parse(input);
+        if (cases[i].valid) {
+            DP_ProtocolVersionTestVersion e = cases[i].expected;
+            if (NOT_NULL_OK(protover, "parse '%s'", input)) {
+                STR_EQ_OK(DP_protocol_version_ns(protover), e.ns,
+                          "namespace of '%s'", input);
+                INT_EQ_OK(DP_protocol_version_server(protover), e.server,
+                          "server version of '%s'", input);
+                INT_EQ_OK(DP_protocol_version_major(protover), e.major,
+                          "major version of '%s'", input);
+                INT_EQ_OK(DP_protocol_version_minor(protover), e.minor,
+                          "minor version of '%s'", input);
+            }
+        }
+        else {
+            NULL_OK(protover, "'%s' doesn't parse", input);
+        }
+        DP_protocol_version_free(protover);
+    }
+}
+
+static void protocol_version_ordering(TEST_PARAMS)
+{
+    struct {
+        DP_ProtocolVersionTestVersion a, b;
+        bool equal, greater_or_equal;
+    } cases[] = {
+        {CURRENT, CURRENT, true, true},
+        {CURRENT, DRAWPILE_2_1, false, true},
+        {DRAWPILE_2_1, CURRENT, false, false},
+        {DRAWPILE_2_1, DRAWPILE_2_0, false, true},
+        {{NS, 4, 21, 3}, DRAWPILE_2_1, false, true},
+        {{NS, 4, 21, 3}, {NS, 4, 22, 0}, false, false},
+        {{NS, 5, 0, 0}, {NS, 4, 99, 99}, false, true},
+        {{"xy", SERVER, MAJOR, MINOR}, CURRENT, false, false},
+        {CURRENT, {"xy", SERVER, MAJOR, MINOR}, false, false},
+    };
+
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        DP_ProtocolVersion *a = make(cases[i].a);
+        DP_ProtocolVersion *b = make(cases[i].b);
+        OK(DP_protocol_version_equals(a, b) == cases[i].equal,
+           "case %zu is %sequal", i, cases[i].equal ? "" : "not ");
+        OK(DP_protocol_version_greater_or_equal(a, b)
+               == cases[i].greater_or_equal,
+           "case %zu is %sgreater or equal", i,
+           cases[i].greater_or_equal ? "" : "not ");
+        DP_protocol_version_free(a);
+        DP_protocol_version_free(b);
+    }
+}
+
+static void protocol_version_join_matrix(TEST_PARAMS)
+{
+    struct {
+        DP_ProtocolVersionTestVersion client, server;
+        bool can_join;
+    } cases[] = {
+        {CURRENT, CURRENT, true},
+        {CURRENT, DRAWPILE_2_1, true},
+        {CURRENT, DRAWPILE_2_0, false},
+        {CURRENT, {NS, SERVER, MAJOR, MINOR + 1}, false},
+        {CURRENT, {NS, SERVER, MAJOR + 1, MINOR}, false},
+        {CURRENT, {NS, SERVER + 1, MAJOR, MINOR}, false},
+        {CURRENT, {"xy", SERVER, MAJOR, MINOR}, false},
+        {DRAWPILE_2_1, DRAWPILE_2_1, true},
+        {DRAWPILE_2_1, CURRENT, false},
+        {DRAWPILE_2_0, DRAWPILE_2_1, false},
+    };
+
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        DP_ProtocolVersion *client = make(cases[i].client);
+        DP_ProtocolVersion *server = make(cases[i].server);
+        OK(DP_protocol_version_can_join(client, server) == cases[i].can_join,
+           "case %zu: %d.%d.%d %s join %s:%d.%d.%d", i, cases[i].client.server,
+           cases[i].client.major, cases[i].client.minor,
+           cases[i].can_join ? "can" : "can't", cases[i].server.ns,
+           cases[i].server.server, cases[i].server.major,
+           cases[i].server.minor);
+        DP_protocol_version_free(client);
+        DP_protocol_version_free(server);
+    }
+
+    NOK(DP_protocol_version_can_join(NULL, NULL),
+        "can't join without versions");
+}
+
+static void protocol_version_recording_matrix(TEST_PARAMS)
+{
+    struct {
+        DP_ProtocolVersionTestVersion recording;
+        DP_ProtocolCompatibility expected;
+    } cases[] = {
+        {CURRENT, DP_PROTOCOL_COMPATIBILITY_COMPATIBLE},
+        {{NS, SERVER + 1, MAJOR, MINOR}, DP_PROTOCOL_COMPATIBILITY_COMPATIBLE},
+        {{NS, SERVER, MAJOR, MINOR + 1},
+         DP_PROTOCOL_COMPATIBILITY_MINOR_INCOMPATIBILITY},
+        {{NS, SERVER, MAJOR, 0},
+         MINOR == 0 ? DP_PROTOCOL_COMPATIBILITY_COMPATIBLE
+                    : DP_PROTOCOL_COMPATIBILITY_MINOR_INCOMPATIBILITY},
+        {DRAWPILE_2_1, DP_PROTOCOL_COMPATIBILITY_BACKWARD_COMPATIBLE},
+        {{NS, 4, 21, 1}, DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE},
+        {DRAWPILE_2_0, DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE},
+        {{NS, SERVER, MAJOR + 1, MINOR},
+         DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE},
+        {{"xy", SERVER, MAJOR, MINOR},
+         DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE},
+    };
+
+    DP_ProtocolVersion *current = DP_protocol_version_new_current();
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        DP_ProtocolVersion *recording = make(cases[i].recording);
+        DP_ProtocolCompatibility expected = cases[i].expected;
+        DP_ProtocolCompatibility actual =
+            DP_protocol_version_recording_compatibility(current, recording);
+        OK(actual == expected, "case %zu is %s (got %s)", i,
+           compatibility_name(expected), compatibility_name(actual));
+        OK(DP_protocol_version_client_compatibility(recording) == expected,
+           "case %zu client compatibility agrees", i);
+        OK(DP_protocol_version_is_compatible_recording(current, recording)
+               == (expected != DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE),
+           "case %zu predicate agrees", i);
+        DP_protocol_version_free(recording);
+    }
+
+    // Only the current version knows how to convert past versions.
+    DP_ProtocolVersion *past =
+        make((DP_ProtocolVersionTestVersion)DRAWPILE_2_1);
+    DP_ProtocolVersion *older =
+        make((DP_ProtocolVersionTestVersion)DRAWPILE_2_0);
+    NOK(DP_protocol_version_is_compatible_recording(older, past),
+        "older client can't play back past compatible recording");
+    OK(DP_protocol_version_is_compatible_recording(past, past),
+       "past client can play back its own recording");
+    DP_protocol_version_free(older);
+    DP_protocol_version_free(past);
+    DP_protocol_version_free(current);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(protocol_version_parse);
+    REGISTER_TEST(protocol_version_ordering);
+    REGISTER_TEST(protocol_version_join_matrix);
+    REGISTER_TEST(protocol_version_recording_matrix);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
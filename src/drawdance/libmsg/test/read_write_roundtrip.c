@@ -250,6 +250,14 @@ static void generate_pixel_dabs(int count, DP_PixelDab *out,
     }
 }
 
+static void generate_fill_gradient_stops(int count, DP_FillGradientStop *out,
+                                         DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_fill_gradient_stop_init(out, i, random_uint16(), random_uint32());
+    }
+}
+
 static void generate_mypaint_dabs(int count, DP_MyPaintDab *out,
                                   DP_UNUSED void *user)
 {
@@ -260,6 +268,23 @@ static void generate_mypaint_dabs(int count, DP_MyPaintDab *out,
     }
 }
 
+static void generate_stamp_dabs(int count, DP_StampDab *out,
+                                DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_stamp_dab_init(out, i, random_int8(), random_int8(),
+                          random_uint16(), random_uint8(), random_uint8());
+    }
+}
+
+static void generate_soft_dabs(int count, DP_SoftDab *out, DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_soft_dab_init(out, i, random_int8(), random_int8(), random_uint16(),
+                         random_uint8(), random_uint8());
+    }
+}
+
 static DP_Message *generate_server_command(void)
 {
     size_t message_len;
@@ -294,6 +319,11 @@ static DP_Message *generate_disconnect(void)
     return msg;
 }
 
+static DP_Message *generate_keep_alive(void)
+{
+    return DP_msg_keep_alive_new(generate_context_id());
+}
+
 static DP_Message *generate_join(void)
 {
     size_t name_len;
@@ -326,6 +356,23 @@ static DP_Message *generate_session_owner(void)
         NULL);
 }
 
+static DP_Message *generate_chat(void)
+{
+    size_t message_len;
+    char *message = generate_string(size_between(DP_MSG_CHAT_MESSAGE_MIN_LEN,
+                                                 DP_MSG_CHAT_MESSAGE_MAX_LEN),
+                                    &message_len);
+    DP_Message *msg = DP_msg_chat_new(
+        generate_context_id(),
+        generate_flags((unsigned int[]){DP_MSG_CHAT_ALL_TFLAGS},
+                       DP_MSG_CHAT_NUM_TFLAGS),
+        generate_flags((unsigned int[]){DP_MSG_CHAT_ALL_OFLAGS},
+                       DP_MSG_CHAT_NUM_OFLAGS),
+        message, message_len);
+    DP_free(message);
+    return msg;
+}
+
 static DP_Message *generate_trusted_users(void)
 {
     return DP_msg_trusted_users_new(
@@ -652,6 +699,38 @@ static DP_Message *generate_draw_dabs_mypaint(void)
         NULL);
 }
 
+static DP_Message *generate_stamp_mask(void)
+{
+    return DP_msg_stamp_mask_new(
+        generate_context_id(), random_uint32(), random_uint16(),
+        generate_bytes,
+        size_between(DP_MSG_STAMP_MASK_IMAGE_MIN_SIZE,
+                     DP_MSG_STAMP_MASK_IMAGE_MAX_SIZE),
+        NULL);
+}
+
+static DP_Message *generate_draw_dabs_stamp(void)
+{
+    return DP_msg_draw_dabs_stamp_new(
+        generate_context_id(), random_uint16(), random_int32(), random_int32(),
+        random_uint32(), generate_blend_mode(), random_uint32(),
+        random_uint32(), random_uint16(), random_uint8(), generate_stamp_dabs,
+        int_between(DP_MSG_DRAW_DABS_STAMP_DABS_MIN_COUNT,
+                    DP_MSG_DRAW_DABS_STAMP_DABS_MAX_COUNT),
+        NULL);
+}
+
+static DP_Message *generate_draw_dabs_soft(void)
+{
+    return DP_msg_draw_dabs_soft_new(
+        generate_context_id(), random_uint16(), random_int32(), random_int32(),
+        random_uint32(), generate_blend_mode(), random_uint8(),
+        random_uint32(), random_uint16(), random_uint8(), generate_soft_dabs,
+        int_between(DP_MSG_DRAW_DABS_SOFT_DABS_MIN_COUNT,
+                    DP_MSG_DRAW_DABS_SOFT_DABS_MAX_COUNT),
+        NULL);
+}
+
 static DP_Message *generate_move_rect(void)
 {
     return DP_msg_move_rect_new(generate_context_id(), random_uint16(),
@@ -797,6 +876,52 @@ static DP_Message *generate_key_frame_delete(void)
                                        random_uint16());
 }
 
+static DP_Message *generate_filter_region(void)
+{
+    return DP_msg_filter_region_new(
+        generate_context_id(), random_uint16(), random_uint32(),
+        random_uint32(), random_uint32(), random_uint32(),
+        generate_variant((unsigned int[]){DP_MSG_FILTER_REGION_ALL_FILTER},
+                         DP_MSG_FILTER_REGION_NUM_FILTER),
+        random_int32(), random_int32(), random_int32(), random_int32());
+}
+
+static DP_Message *generate_fill_gradient(void)
+{
+    return DP_msg_fill_gradient_new(
+        generate_context_id(), random_uint16(), random_uint32(),
+        random_uint32(), random_uint32(), random_uint32(),
+        generate_blend_mode(),
+        generate_variant((unsigned int[]){DP_MSG_FILL_GRADIENT_ALL_SHAPE},
+                         DP_MSG_FILL_GRADIENT_NUM_SHAPE),
+        generate_flags((unsigned int[]){DP_MSG_FILL_GRADIENT_ALL_FLAGS},
+                       DP_MSG_FILL_GRADIENT_NUM_FLAGS),
+        random_int32(), random_int32(), random_int32(), random_int32(),
+        random_int32(), generate_fill_gradient_stops,
+        int_between(DP_MSG_FILL_GRADIENT_STOPS_MIN_COUNT,
+                    DP_MSG_FILL_GRADIENT_STOPS_MAX_COUNT),
+        NULL);
+}
+
+static DP_Message *generate_annotation_order(void)
+{
+    return DP_msg_annotation_order_new(
+        generate_context_id(), random_uint16(),
+        generate_variant(
+            (unsigned int[]){DP_MSG_ANNOTATION_ORDER_ALL_DIRECTION},
+            DP_MSG_ANNOTATION_ORDER_NUM_DIRECTION));
+}
+
+static DP_Message *generate_layer_metadata(void)
+{
+    return DP_msg_layer_metadata_new(
+        generate_context_id(), random_uint16(), random_uint8(),
+        generate_bytes,
+        size_between(DP_MSG_LAYER_METADATA_METADATA_MIN_SIZE,
+                     DP_MSG_LAYER_METADATA_METADATA_MAX_SIZE),
+        NULL);
+}
+
 static DP_Message *generate_undo(void)
 {
     return DP_msg_undo_new(generate_context_id(), random_uint8(),
@@ -812,9 +937,11 @@ static void write_messages(TEST_PARAMS, DP_BinaryWriter *bw, DP_TextWriter *tw)
         generate_server_command,
         generate_disconnect,
         generate_ping,
+        generate_keep_alive,
         generate_join,
         generate_leave,
         generate_session_owner,
+        generate_chat,
         generate_trusted_users,
         generate_soft_reset,
         generate_private_chat,
@@ -852,6 +979,9 @@ static void write_messages(TEST_PARAMS, DP_BinaryWriter *bw, DP_TextWriter *tw)
         generate_draw_dabs_pixel,
         generate_draw_dabs_pixel_square,
         generate_draw_dabs_mypaint,
+        generate_stamp_mask,
+        generate_draw_dabs_stamp,
+        generate_draw_dabs_soft,
         generate_move_rect,
         generate_set_metadata_int,
         generate_layer_tree_create,
@@ -866,15 +996,34 @@ static void write_messages(TEST_PARAMS, DP_BinaryWriter *bw, DP_TextWriter *tw)
         generate_key_frame_retitle,
         generate_key_frame_layer_attributes,
         generate_key_frame_delete,
+        generate_filter_region,
+        generate_fill_gradient,
+        generate_annotation_order,
+        generate_layer_metadata,
         generate_undo,
     };
+    bool covered[DP_MESSAGE_MAX + 1] = {0};
     int count = DP_ARRAY_LENGTH(fns);
     for (int i = 0; i < count; ++i) {
         DP_Message *msg = fns[i]();
+        covered[DP_message_type(msg)] = true;
         write_message_binary(TEST_ARGS, msg, bw);
         write_message_text(TEST_ARGS, msg, tw);
         DP_message_decref(msg);
     }
+
+    // Every message type must have a text form, except for the ones that
+    // never go into recordings and the ones that can only be opaque.
+    for (int i = 0; i <= DP_MESSAGE_MAX; ++i) {
+        DP_MessageType type = (DP_MessageType)i;
+        bool known = !DP_str_equal(DP_message_type_name(type), "unknown");
+        bool skipped = type == DP_MSG_INTERNAL || type == DP_MSG_EXTENSION
+                    || type == DP_MSG_TOOL_CHANGE || type == DP_MSG_PEN_MOVE;
+        if (known && !skipped) {
+            OK(covered[i], "message type %s is covered",
+               DP_message_type_enum_name(type));
+        }
+    }
 }
 
 static void write_initial_messages(TEST_PARAMS)
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpmsg/binary_reader.h>
+#include <dpmsg/binary_writer.h>
+#include <dpmsg/message.h>
+#include <dpmsg/rust.h>
+#include <dptest.h>
+#include <parson.h>
+
+
+// A recording as written by the client recorder: the magic, the JSON header
+// behind its big-endian length, then an undo point from user 1, a layer
+// retitle from user 2 and a pen up from user 1.
+#define FIXTURE_VERSION       "dp:4.24.1"
+#define FIXTURE_WRITER        "Drawpile"
+#define FIXTURE_WRITERVERSION "2.2.0"
+#define FIXTURE_MESSAGES      3
+#define FIXTURE_BODY_OFFSET   75
+#define FIXTURE_RETITLE_AT    79
+#define FIXTURE_PEN_UP_AT     88
+
+static const unsigned char fixture[] = {
+    'D',  'P',  'R',  'E',  'C',  '\0', 0x00, 0x43, '{',  '"',  'v',  'e',
+    'r',  's',  'i',  'o',  'n',  '"',  ':',  '"',  'd',  'p',  ':',  '4',
+    '.',  '2',  '4',  '.',  '1',  '"',  ',',  '"',  'w',  'r',  'i',  't',
+    'e',  'r',  '"',  ':',  '"',  'D',  'r',  'a',  'w',  'p',  'i',  'l',
+    'e',  '"',  ',',  '"',  'w',  'r',  'i',  't',  'e',  'r',  'v',  'e',
+    'r',  's',  'i',  'o',  'n',  '"',  ':',  '"',  '2',  '.',  '2',  '.',
+    '0',  '"',  '}',  0x00, 0x00, 0x80, 0x01, 0x00, 0x05, 0x84, 0x02, 0x01,
+    0x02, 'I',  'n',  'k',  0x00, 0x00, 0x8c, 0x01,
+};
+
+typedef struct DP_RecordingFormatTestBuffer {
+    void *data;
+    size_t size;
+} DP_RecordingFormatTestBuffer;
+
+static void make_fixture_messages(DP_Message **msgs)
+{
+    msgs[0] = DP_msg_undo_point_new(1);
+    msgs[1] = DP_msg_layer_retitle_new(2, 0x0102, "Ink", 3);
+    msgs[2] = DP_msg_pen_up_new(1);
+}
+
+static void free_messages(int count, DP_Message **msgs)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_message_decref(msgs[i]);
+    }
+}
+
+static DP_RecordingFormatTestBuffer
+write_recording(TEST_PARAMS, const char *version, int count, DP_Message **msgs)
+{
+    void **buffer_ptr;
+    size_t *size_ptr;
+    DP_Output *output = DP_mem_output_new(64, false, &buffer_ptr, &size_ptr);
+    DP_BinaryWriter *bw = DP_binary_writer_new(output);
+
+    JSON_Value *header_value = json_value_init_object();
+    JSON_Object *header = json_value_get_object(header_value);
+    json_object_set_string(header, "version", version);
+    json_object_set_string(header, "writer", FIXTURE_WRITER);
+    json_object_set_string(header, "writerversion", FIXTURE_WRITERVERSION);
+    OK(DP_binary_writer_write_header(bw, header), "wrote header");
+    json_value_free(header_value);
+
+    for (int i = 0; i < count; ++i) {
+        OK(DP_binary_writer_write_message(bw, msgs[i]) != 0,
+           "wrote message %d", i);
+    }
+
+    DP_RecordingFormatTestBuffer buffer = {*buffer_ptr, *size_ptr};
+    DP_binary_writer_free(bw);
+    return buffer;
+}
+
+static DP_BinaryReader *open_reader(TEST_PARAMS, const void *data, size_t size)
+{
+    DP_Input *input = DP_mem_input_new_keep_on_close(data, size);
+    DP_BinaryReader *br = DP_binary_reader_new(input, 0);
+    FATAL(NOT_NULL_OK(br, "got binary reader (%s)", br ? "ok" : DP_error()));
+    return br;
+}
+
+static void check_header_string(TEST_PARAMS, DP_BinaryReader *br,
+                                const char *key, const char *expected)
+{
+    JSON_Object *header = json_value_get_object(DP_binary_reader_header(br));
+    const char *value = json_object_get_string(header, key);
+    OK(value && strcmp(value, expected) == 0, "header %s is %s (got %s)", key,
+       expected, value ? value : "nothing");
+}
+
+static void check_messages(TEST_PARAMS, DP_BinaryReader *br, int count,
+                           DP_Message **expected)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_Message *msg;
+        DP_BinaryReaderResult result =
+            DP_binary_reader_read_message(br, true, &msg);
+        if (INT_EQ_OK(result, DP_BINARY_READER_SUCCESS, "read message %d",
+                      i)) {
+            OK(DP_message_equals(msg, expected[i]), "message %d equal", i);
+            DP_message_decref(msg);
+        }
+    }
+    DP_Message *msg;
+    INT_EQ_OK(DP_binary_reader_read_message(br, true, &msg),
+              DP_BINARY_READER_INPUT_END, "input ends after %d messages",
+              count);
+}
+
+
+static void recording_roundtrip(TEST_PARAMS)
+{
+    DP_Message *msgs[FIXTURE_MESSAGES];
+    make_fixture_messages(msgs);
+    DP_RecordingFormatTestBuffer buffer = write_recording(
+        TEST_ARGS, DP_PROTOCOL_VERSION, FIXTURE_MESSAGES, msgs);
+
+    DP_BinaryReader *br = open_reader(TEST_ARGS, buffer.data, buffer.size);
+    check_header_string(TEST_ARGS, br, "version", DP_PROTOCOL_VERSION);
+    check_header_string(TEST_ARGS, br, "writer", FIXTURE_WRITER);
+    check_header_string(TEST_ARGS, br, "writerversion", FIXTURE_WRITERVERSION);
+    check_messages(TEST_ARGS, br, FIXTURE_MESSAGES, msgs);
+    UINT_EQ_OK(DP_binary_reader_tell(br), buffer.size,
+               "reader consumed the whole recording");
+
+    DP_binary_reader_free(br);
+    DP_free(buffer.data);
+    free_messages(FIXTURE_MESSAGES, msgs);
+}
+
+static void recording_written_like_fixture(TEST_PARAMS)
+{
+    DP_Message *msgs[FIXTURE_MESSAGES];
+    make_fixture_messages(msgs);
+    DP_RecordingFormatTestBuffer buffer =
+        write_recording(TEST_ARGS, FIXTURE_VERSION, FIXTURE_MESSAGES, msgs);
+
+    if (UINT_EQ_OK(buffer.size, sizeof(fixture), "size matches fixture")) {
+        OK(memcmp(buffer.data, fixture, sizeof(fixture)) == 0,
+           "bytes match fixture");
+    }
+
+    DP_free(buffer.data);
+    free_messages(FIXTURE_MESSAGES, msgs);
+}
+
+static void recording_read_from_fixture(TEST_PARAMS)
+{
+    DP_Message *msgs[FIXTURE_MESSAGES];
+    make_fixture_messages(msgs);
+
+    DP_BinaryReader *br = open_reader(TEST_ARGS, fixture, sizeof(fixture));
+    check_header_string(TEST_ARGS, br, "version", FIXTURE_VERSION);
+    check_header_string(TEST_ARGS, br, "writer", FIXTURE_WRITER);
+    check_header_string(TEST_ARGS, br, "writerversion", FIXTURE_WRITERVERSION);
+    UINT_EQ_OK(DP_binary_reader_body_offset(br), FIXTURE_BODY_OFFSET,
+               "body starts after the header");
+    check_messages(TEST_ARGS, br, FIXTURE_MESSAGES, msgs);
+
+    DP_binary_reader_free(br);
+    free_messages(FIXTURE_MESSAGES, msgs);
+}
+
+static void recording_version_mismatch(TEST_PARAMS)
+{
+    struct {
+        const char *version;
+        bool valid;
+        DP_ProtocolCompatibility expected;
+        bool future;
+    } cases[] = {
+        {"dp:4.24.1", true, DP_PROTOCOL_COMPATIBILITY_COMPATIBLE, false},
+        {"dp:4.24.0", true, DP_PROTOCOL_COMPATIBILITY_MINOR_INCOMPATIBILITY,
+         false},
+        {"dp:4.24.7", true, DP_PROTOCOL_COMPATIBILITY_MINOR_INCOMPATIBILITY,
+         true},
+        {"dp:4.21.2", true, DP_PROTOCOL_COMPATIBILITY_BACKWARD_COMPATIBLE,
+         false},
+        {"dp:4.20.1", true, DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE, false},
+        {"dp:4.25.0", true, DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE, true},
+        {"xy:4.24.1", true, DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE, false},
+        {"dp:4.24", false, DP_PROTOCOL_COMPATIBILITY_INCOMPATIBLE, false},
+    };
+
+    DP_Message *msgs[FIXTURE_MESSAGES];
+    make_fixture_messages(msgs);
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        const char *version = cases[i].version;
+        DP_RecordingFormatTestBuffer buffer =
+            write_recording(TEST_ARGS, version, FIXTURE_MESSAGES, msgs);
+
+        // The reader itself doesn't care about the version, it's up to the
+        // caller to decide whether to play back a mismatched recording.
+        DP_BinaryReader *br = open_reader(TEST_ARGS, buffer.data, buffer.size);
+        check_header_string(TEST_ARGS, br, "version", version);
+        JSON_Object *header =
+            json_value_get_object(DP_binary_reader_header(br));
+        const char *header_version = json_object_get_string(header, "version");
+        DP_ProtocolVersion *protover =
+            DP_protocol_version_parse(header_version);
+        if (cases[i].valid) {
+            if (NOT_NULL_OK(protover, "parse %s", version)) {
+                INT_EQ_OK(DP_protocol_version_client_compatibility(protover),
+                          cases[i].expected, "compatibility of %s", version);
+                OK(DP_protocol_version_is_future(protover) == cases[i].future,
+                   "%s is %sfrom the future", version,
+                   cases[i].future ? "" : "not ");
+            }
+        }
+        else {
+            NULL_OK(protover, "%s doesn't parse", version);
+        }
+        check_messages(TEST_ARGS, br, FIXTURE_MESSAGES, msgs);
+
+        DP_protocol_version_free(protover);
+        DP_binary_reader_free(br);
+        DP_free(buffer.data);
+    }
+    free_messages(FIXTURE_MESSAGES, msgs);
+}
+
+static void recording_truncated(TEST_PARAMS)
+{
+    struct {
+        size_t length;
+        int complete;
+    } cases[] = {
+        {FIXTURE_RETITLE_AT, 1},     // Cut cleanly between messages.
+        {FIXTURE_RETITLE_AT + 2, 1}, // Cut in the middle of a message header.
+        {FIXTURE_RETITLE_AT + 6, 1}, // Cut in the middle of a message body.
+        {FIXTURE_PEN_UP_AT + 3, 2},  // Last byte missing.
+    };
+
+    DP_Message *msgs[FIXTURE_MESSAGES];
+    make_fixture_messages(msgs);
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(cases); ++i) {
+        size_t length = cases[i].length;
+        int complete = cases[i].complete;
+        DP_BinaryReader *br = open_reader(TEST_ARGS, fixture, length);
+
+        for (int j = 0; j < complete; ++j) {
+            DP_Message *msg;
+            if (INT_EQ_OK(DP_binary_reader_read_message(br, true, &msg),
+                          DP_BINARY_READER_SUCCESS,
+                          "cut at %zu: read message %d", length, j)) {
+                OK(DP_message_equals(msg, msgs[j]),
+                   "cut at %zu: message %d equal", length, j);
+                DP_message_decref(msg);
+            }
+        }
+
+        size_t offset = DP_binary_reader_tell(br);
+        DP_Message *msg;
+        DP_BinaryReaderResult result =
+            DP_binary_reader_read_message(br, true, &msg);
+        if (offset == length) {
+            INT_EQ_OK(result, DP_BINARY_READER_INPUT_END,
+                      "cut at %zu: clean end", length);
+        }
+        else {
+            INT_EQ_OK(result, DP_BINARY_READER_ERROR_INPUT,
+                      "cut at %zu: partial message is an input error",
+                      length);
+            UINT_EQ_OK(DP_binary_reader_tell(br), length,
+                       "cut at %zu: read up to the end", length);
+            // Going back to the start of the partial message is how readers
+            // recover, in case the rest of it gets written later.
+            OK(DP_binary_reader_seek(br, offset),
+               "cut at %zu: seek back to %zu", length, offset);
+            UINT_EQ_OK(DP_binary_reader_tell(br), offset,
+                       "cut at %zu: back at the partial message", length);
+            INT_EQ_OK(DP_binary_reader_read_message(br, true, &msg),
+                      DP_BINARY_READER_ERROR_INPUT,
+                      "cut at %zu: partial message still incomplete", length);
+        }
+
+        DP_binary_reader_free(br);
+    }
+    free_messages(FIXTURE_MESSAGES, msgs);
+
+    DP_Input *input =
+        DP_mem_input_new_keep_on_close(fixture, FIXTURE_BODY_OFFSET - 10);
+    DP_BinaryReader *br = DP_binary_reader_new(input, 0);
+    NULL_OK(br, "recording cut off in the header can't be opened");
+    DP_binary_reader_free(br);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(recording_roundtrip);
+    REGISTER_TEST(recording_written_like_fixture);
+    REGISTER_TEST(recording_read_from_fixture);
+    REGISTER_TEST(recording_version_mismatch);
+    REGISTER_TEST(recording_truncated);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
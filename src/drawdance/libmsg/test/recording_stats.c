@@ -0,0 +1,284 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/conversions.h>
+#include <dpcommon/input.h>
+#include <dpcommon/output.h>
+#include <dpmsg/binary_reader.h>
+#include <dpmsg/binary_writer.h>
+#include <dpmsg/blend_mode.h>
+#include <dpmsg/message.h>
+#include <dpmsg/recording_stats.h>
+#include <dptest.h>
+#include <parson.h>
+
+
+#define RECORDER 0
+#define ALICE    1
+#define BOB      2
+
+typedef struct DP_RecordingStatsTestBuffer {
+    unsigned char *data;
+    size_t size;
+} DP_RecordingStatsTestBuffer;
+
+static void set_classic_dabs(int count, DP_ClassicDab *cds,
+                             DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_classic_dab_init(cds, i, 1, 1, 256, 255, 255);
+    }
+}
+
+static void set_pixel_dabs(int count, DP_PixelDab *pds, DP_UNUSED void *user)
+{
+    for (int i = 0; i < count; ++i) {
+        DP_pixel_dab_init(pds, i, 1, 1, 4, 255);
+    }
+}
+
+static DP_Message *classic_dabs(unsigned int context_id, int count)
+{
+    return DP_msg_draw_dabs_classic_new(context_id, 257, 0, 0, 0xff000000u,
+                                        DP_BLEND_MODE_NORMAL,
+                                        set_classic_dabs, count, NULL);
+}
+
+static DP_Message *pixel_dabs(unsigned int context_id, int count)
+{
+    return DP_msg_draw_dabs_pixel_new(context_id, 257, 0, 0, 0xff000000u,
+                                      DP_BLEND_MODE_NORMAL, set_pixel_dabs,
+                                      count, NULL);
+}
+
+static DP_Message *layer(unsigned int context_id, int layer_id, bool group,
+                         const char *title)
+{
+    return DP_msg_layer_tree_create_new(
+        context_id, DP_int_to_uint16(layer_id), 0, 0, 0,
+        group ? DP_MSG_LAYER_TREE_CREATE_FLAGS_GROUP : 0, title, strlen(title));
+}
+
+// Elapsed recording time of each message is in the comments.
+static DP_RecordingStatsTestBuffer write_recording(TEST_PARAMS)
+{
+    DP_Message *msgs[] = {
+        DP_msg_canvas_resize_new(ALICE, 0, 64, 32, 0),        // 0
+        layer(ALICE, 257, false, "Background"),               // 0
+        layer(BOB, 513, true, "Folder"),                      // 0
+        DP_msg_undo_point_new(ALICE),                         // 0
+        classic_dabs(ALICE, 3),                               // 0
+        DP_msg_interval_new(RECORDER, 1000),                  // 0
+        DP_msg_undo_point_new(BOB),                           // 1000
+        pixel_dabs(BOB, 2),                                   // 1000
+        DP_msg_interval_new(RECORDER, 2000),                  // 1000
+        classic_dabs(ALICE, 4),                               // 3000
+        DP_msg_interval_new(RECORDER, 10000),                 // 3000
+        DP_msg_undo_new(BOB, 0, false),                       // 13000
+        DP_msg_interval_new(RECORDER, 500),                   // 13000
+        DP_msg_undo_new(BOB, 0, true),                        // 13500
+        DP_msg_canvas_resize_new(ALICE, 10, 0, 0, 20),        // 13500
+        DP_msg_layer_create_new(BOB, 514, 0, 0, 0, "Old", 3), // 13500
+    };
+
+    void **buffer_ptr;
+    size_t *size_ptr;
+    DP_BinaryWriter *bw = DP_binary_writer_new(
+        DP_mem_output_new(1024, false, &buffer_ptr, &size_ptr));
+    JSON_Value *header_value = json_value_init_object();
+    json_object_set_string(json_value_get_object(header_value), "version",
+                           DP_PROTOCOL_VERSION);
+    OK(DP_binary_writer_write_header(bw, json_value_get_object(header_value)),
+       "write recording header");
+    json_value_free(header_value);
+    for (size_t i = 0; i < DP_ARRAY_LENGTH(msgs); ++i) {
+        if (DP_binary_writer_write_message(bw, msgs[i]) == 0) {
+            FAIL("write recording message %zu", i);
+        }
+        DP_message_decref(msgs[i]);
+    }
+    // The pointers go away with the writer, the buffer itself doesn't.
+    DP_RecordingStatsTestBuffer buffer = {*buffer_ptr, *size_ptr};
+    DP_binary_writer_free(bw);
+    return buffer;
+}
+
+static DP_RecordingStats *analyze(TEST_PARAMS,
+                                  DP_RecordingStatsTestBuffer buffer)
+{
+    DP_BinaryReader *br = DP_binary_reader_new(
+        DP_mem_input_new_free_on_close(buffer.data, buffer.size), 0);
+    FATAL(NOT_NULL_OK(br, "open recording (error: %s)", DP_error()));
+    DP_RecordingStats *rs = DP_recording_stats_analyze(br);
+    DP_binary_reader_free(br);
+    return rs;
+}
+
+
+static void recording_stats_totals(TEST_PARAMS)
+{
+    DP_RecordingStats *rs = analyze(TEST_ARGS, write_recording(TEST_ARGS));
+    const DP_RecordingStatsTotals *totals = DP_recording_stats_totals(rs);
+    INT_EQ_OK(totals->message_count, 16, "message count");
+    INT_EQ_OK(totals->unparseable_count, 0, "nothing unparseable");
+    INT_EQ_OK(totals->duration_ms, 13500, "duration is the sum of intervals");
+    // The ten second pause is idle, everything else is under the threshold.
+    INT_EQ_OK(totals->active_ms, 3500, "active time");
+    INT_EQ_OK(totals->width, 84, "final width");
+    INT_EQ_OK(totals->height, 42, "final height");
+    NOK(totals->truncated, "not truncated");
+    DP_recording_stats_free(rs);
+}
+
+static void recording_stats_users(TEST_PARAMS)
+{
+    DP_RecordingStats *rs = analyze(TEST_ARGS, write_recording(TEST_ARGS));
+
+    const DP_RecordingStatsUser *alice = DP_recording_stats_user(rs, ALICE);
+    if (NOT_NULL_OK(alice, "alice has stats")) {
+        INT_EQ_OK(alice->message_count, 6, "alice message count");
+        INT_EQ_OK(alice->message_counts[DP_MSG_DRAW_DABS_CLASSIC], 2,
+                  "alice classic dab messages");
+        INT_EQ_OK(alice->message_counts[DP_MSG_CANVAS_RESIZE], 2,
+                  "alice resizes");
+        INT_EQ_OK(alice->dab_count, 7, "alice dab count");
+        INT_EQ_OK(alice->undo_count, 0, "alice undo count");
+        INT_EQ_OK(alice->active_ms, 3000, "alice active time");
+        INT_EQ_OK(alice->first_ms, 0, "alice first message time");
+        INT_EQ_OK(alice->last_ms, 13500, "alice last message time");
+    }
+
+    const DP_RecordingStatsUser *bob = DP_recording_stats_user(rs, BOB);
+    if (NOT_NULL_OK(bob, "bob has stats")) {
+        INT_EQ_OK(bob->message_count, 6, "bob message count");
+        INT_EQ_OK(bob->message_counts[DP_MSG_UNDO], 2, "bob undo messages");
+        INT_EQ_OK(bob->dab_count, 2, "bob dab count");
+        INT_EQ_OK(bob->undo_count, 1, "bob undo count");
+        INT_EQ_OK(bob->redo_count, 1, "bob redo count");
+        INT_EQ_OK(bob->active_ms, 1500, "bob active time");
+    }
+
+    const DP_RecordingStatsUser *recorder =
+        DP_recording_stats_user(rs, RECORDER);
+    if (NOT_NULL_OK(recorder, "recorder has stats")) {
+        INT_EQ_OK(recorder->message_counts[DP_MSG_INTERVAL], 4, "intervals");
+        INT_EQ_OK(recorder->active_ms, 0, "intervals aren't drawing");
+    }
+
+    NULL_OK(DP_recording_stats_user(rs, 3), "no stats for absent user");
+    NULL_OK(DP_recording_stats_user(rs, 256), "no stats for invalid user");
+    DP_recording_stats_free(rs);
+}
+
+static void recording_stats_timelines(TEST_PARAMS)
+{
+    DP_RecordingStats *rs = analyze(TEST_ARGS, write_recording(TEST_ARGS));
+
+    FATAL(INT_EQ_OK(DP_recording_stats_resize_count(rs), 2, "resize count"));
+    const DP_RecordingStatsResize *first = DP_recording_stats_resize_at(rs, 0);
+    INT_EQ_OK(first->message_index, 0, "first resize index");
+    INT_EQ_OK(first->width, 64, "first resize width");
+    INT_EQ_OK(first->height, 32, "first resize height");
+    const DP_RecordingStatsResize *second =
+        DP_recording_stats_resize_at(rs, 1);
+    INT_EQ_OK(second->message_index, 14, "second resize index");
+    INT_EQ_OK(second->elapsed_ms, 13500, "second resize time");
+    INT_EQ_OK(second->top, 10, "second resize top");
+    INT_EQ_OK(second->left, 20, "second resize left");
+    INT_EQ_OK(second->width, 84, "second resize width");
+    INT_EQ_OK(second->height, 42, "second resize height");
+
+    FATAL(INT_EQ_OK(DP_recording_stats_layer_count(rs), 3, "layer count"));
+    const DP_RecordingStatsLayer *background =
+        DP_recording_stats_layer_at(rs, 0);
+    INT_EQ_OK(background->layer_id, 257, "background layer id");
+    UINT_EQ_OK(background->context_id, ALICE, "background created by alice");
+    STR_EQ_OK(background->title, "Background", "background title");
+    NOK(background->group, "background isn't a group");
+    const DP_RecordingStatsLayer *folder = DP_recording_stats_layer_at(rs, 1);
+    INT_EQ_OK(folder->message_index, 2, "folder index");
+    OK(folder->group, "folder is a group");
+    const DP_RecordingStatsLayer *old = DP_recording_stats_layer_at(rs, 2);
+    INT_EQ_OK(old->layer_id, 514, "legacy layer create is included");
+    INT_EQ_OK(old->elapsed_ms, 13500, "legacy layer creation time");
+    DP_recording_stats_free(rs);
+}
+
+static void recording_stats_truncated(TEST_PARAMS)
+{
+    DP_RecordingStatsTestBuffer buffer = write_recording(TEST_ARGS);
+    buffer.size -= 2;
+    DP_RecordingStats *rs = analyze(TEST_ARGS, buffer);
+    const DP_RecordingStatsTotals *totals = DP_recording_stats_totals(rs);
+    OK(totals->truncated, "truncated");
+    INT_EQ_OK(totals->message_count, 15, "everything before the cut is read");
+    INT_EQ_OK(totals->width, 84, "resize before the cut is there");
+    INT_EQ_OK(DP_recording_stats_layer_count(rs), 2, "last layer is cut off");
+    DP_recording_stats_free(rs);
+}
+
+static void recording_stats_unparseable(TEST_PARAMS)
+{
+    // An interval with a one byte body, which should be two bytes.
+    unsigned char garbage[] = {0, 1, DP_MSG_INTERVAL, RECORDER, 0xff};
+    DP_RecordingStatsTestBuffer buffer = write_recording(TEST_ARGS);
+    buffer.data = DP_realloc(buffer.data, buffer.size + sizeof(garbage));
+    memcpy(buffer.data + buffer.size, garbage, sizeof(garbage));
+    buffer.size += sizeof(garbage);
+
+    DP_RecordingStats *rs = analyze(TEST_ARGS, buffer);
+    const DP_RecordingStatsTotals *totals = DP_recording_stats_totals(rs);
+    NOK(totals->truncated, "not truncated");
+    INT_EQ_OK(totals->message_count, 17, "unparseable message is counted");
+    INT_EQ_OK(totals->unparseable_count, 1, "unparseable count");
+    INT_EQ_OK(totals->duration_ms, 13500, "bad interval doesn't add time");
+    DP_recording_stats_free(rs);
+}
+
+static void recording_stats_json(TEST_PARAMS)
+{
+    DP_RecordingStats *rs = analyze(TEST_ARGS, write_recording(TEST_ARGS));
+    JSON_Value *value = DP_recording_stats_to_json(rs);
+    DP_recording_stats_free(rs);
+
+    JSON_Object *o = json_value_get_object(value);
+    INT_EQ_OK((int)json_object_get_number(o, "messages"), 16,
+              "json message count");
+    INT_EQ_OK((int)json_object_get_number(o, "duration_ms"), 13500,
+              "json duration");
+    INT_EQ_OK(json_object_get_boolean(o, "truncated"), 0, "json truncated");
+
+    JSON_Array *users = json_object_get_array(o, "users");
+    FATAL(UINT_EQ_OK(json_array_get_count(users), 3u, "json users"));
+    JSON_Object *alice = json_array_get_object(users, 1);
+    INT_EQ_OK((int)json_object_get_number(alice, "id"), ALICE, "json alice");
+    INT_EQ_OK((int)json_object_get_number(alice, "dabs"), 7,
+              "json alice dabs");
+    INT_EQ_OK((int)json_object_dotget_number(
+                  alice, "message_types.classicdabs"),
+              2, "json alice message types by name");
+
+    JSON_Array *resizes = json_object_get_array(o, "resizes");
+    UINT_EQ_OK(json_array_get_count(resizes), 2u, "json resizes");
+    JSON_Array *layers = json_object_get_array(o, "layers");
+    FATAL(UINT_EQ_OK(json_array_get_count(layers), 3u, "json layers"));
+    STR_EQ_OK(json_object_get_string(json_array_get_object(layers, 1),
+                                     "title"),
+              "Folder", "json layer title");
+    json_value_free(value);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(recording_stats_totals);
+    REGISTER_TEST(recording_stats_users);
+    REGISTER_TEST(recording_stats_timelines);
+    REGISTER_TEST(recording_stats_truncated);
+    REGISTER_TEST(recording_stats_unparseable);
+    REGISTER_TEST(recording_stats_json);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: MIT
+#include <dpcommon/common.h>
+#include <dpcommon/input.h>
+#include <dpmsg/message.h>
+#include <dpmsg/text_reader.h>
+#include <dptest.h>
+
+
+static DP_TextReader *open_text_reader(TEST_PARAMS, const char *text)
+{
+    DP_Input *input = DP_mem_input_new_keep_on_close(text, strlen(text));
+    FATAL(NOT_NULL_OK(input, "got text input"));
+    DP_TextReader *tr = DP_text_reader_new(input);
+    FATAL(NOT_NULL_OK(tr, "got text reader"));
+    return tr;
+}
+
+static DP_Message *read_message(TEST_PARAMS, DP_TextReader *tr,
+                                DP_MessageType expected_type)
+{
+    DP_Message *msg;
+    DP_TextReaderResult result = DP_text_reader_read_message(tr, &msg);
+    if (OK(result == DP_TEXT_READER_SUCCESS, "read %s (error: %s)",
+           DP_message_type_enum_name(expected_type), DP_error())) {
+        INT_EQ_OK((int)DP_message_type(msg), (int)expected_type,
+                  "message has type %s",
+                  DP_message_type_enum_name(expected_type));
+        return msg;
+    }
+    else {
+        return NULL;
+    }
+}
+
+static void read_error(TEST_PARAMS, DP_TextReader *tr,
+                       DP_TextReaderResult expected_result, size_t line,
+                       size_t column)
+{
+    DP_Message *msg;
+    DP_TextReaderResult result = DP_text_reader_read_message(tr, &msg);
+    INT_EQ_OK((int)result, (int)expected_result, "got error result");
+    DP_TextReaderPosition pos = DP_text_reader_error_position(tr);
+    UINT_EQ_OK(pos.line, line, "error on line %zu (%s)", line, DP_error());
+    UINT_EQ_OK(pos.column, column, "error in column %zu", column);
+}
+
+
+static void text_reader_tracks_lines(TEST_PARAMS)
+{
+    DP_TextReader *tr = open_text_reader(
+        TEST_ARGS, "!version=dp:4.24.1\n"
+                   "# A comment.\n"
+                   "\n"
+                   "1 undopoint\n"
+                   "    # An indented comment.\n"
+                   "2 fillrect color=#ff112233 h=4 layer=0x0101 "
+                   "mode=svg:src-over w=3 x=1 y=2 bogus=1\n"
+                   "3 classicdabs color=#ff000000 layer=0x0101 "
+                   "mode=svg:src-over x=0 y=0 {\n"
+                   "    1 2 3 4 5\n"
+                   "}\n"
+                   "4 undopoint");
+
+    const char *key, *value;
+    OK(DP_text_reader_read_header_field(tr, &key, &value)
+           == DP_TEXT_READER_SUCCESS,
+       "read header field");
+    OK(DP_text_reader_read_header_field(tr, &key, &value)
+           == DP_TEXT_READER_HEADER_END,
+       "read header end");
+
+    DP_Message *msg = read_message(TEST_ARGS, tr, DP_MSG_UNDO_POINT);
+    UINT_EQ_OK(DP_text_reader_line(tr), 4u, "skipped comments and blanks");
+    DP_message_decref_nullable(msg);
+
+    msg = read_message(TEST_ARGS, tr, DP_MSG_FILL_RECT);
+    UINT_EQ_OK(DP_text_reader_line(tr), 6u, "skipped indented comment");
+    if (msg) {
+        DP_MsgFillRect *mfr = DP_message_internal(msg);
+        UINT_EQ_OK(DP_msg_fill_rect_x(mfr), 1u, "unknown field is skipped");
+        UINT_EQ_OK(DP_msg_fill_rect_color(mfr), 0xff112233u, "color is read");
+    }
+    DP_message_decref_nullable(msg);
+
+    msg = read_message(TEST_ARGS, tr, DP_MSG_DRAW_DABS_CLASSIC);
+    UINT_EQ_OK(DP_text_reader_line(tr), 9u, "read multiline message");
+    if (msg) {
+        int count;
+        DP_msg_draw_dabs_classic_dabs(DP_message_internal(msg), &count);
+        INT_EQ_OK(count, 1, "dab is read");
+    }
+    DP_message_decref_nullable(msg);
+
+    msg = read_message(TEST_ARGS, tr, DP_MSG_UNDO_POINT);
+    UINT_EQ_OK(DP_text_reader_line(tr), 10u, "read line without newline");
+    DP_message_decref_nullable(msg);
+
+    DP_Message *end;
+    OK(DP_text_reader_read_message(tr, &end) == DP_TEXT_READER_INPUT_END,
+       "read input end");
+
+    OK(DP_text_reader_seek(tr, DP_text_reader_body_offset(tr)),
+       "seek to body");
+    msg = read_message(TEST_ARGS, tr, DP_MSG_UNDO_POINT);
+    UINT_EQ_OK(DP_text_reader_line(tr), 4u, "line is known after seek");
+    DP_message_decref_nullable(msg);
+
+    DP_text_reader_free(tr);
+}
+
+static void text_reader_error_positions(TEST_PARAMS)
+{
+    DP_TextReader *tr =
+        open_text_reader(TEST_ARGS, "1 undopoint\n"
+                                    "x undopoint\n"
+                                    "  5   nosuchmessage a=b\n"
+                                    "6 undopoint\n"
+                                    "7 classicdabs layer=0x0101 {\n"
+                                    "    1 2 3 4 5\n");
+
+    DP_message_decref_nullable(read_message(TEST_ARGS, tr, DP_MSG_UNDO_POINT));
+    read_error(TEST_ARGS, tr, DP_TEXT_READER_ERROR_PARSE, 2, 1);
+    read_error(TEST_ARGS, tr, DP_TEXT_READER_ERROR_PARSE, 3, 7);
+    DP_message_decref_nullable(read_message(TEST_ARGS, tr, DP_MSG_UNDO_POINT));
+    UINT_EQ_OK(DP_text_reader_line(tr), 4u, "reading continues after errors");
+    read_error(TEST_ARGS, tr, DP_TEXT_READER_ERROR_INPUT, 7, 1);
+
+    DP_text_reader_free(tr);
+}
+
+
+static void register_tests(REGISTER_PARAMS)
+{
+    REGISTER_TEST(text_reader_tracks_lines);
+    REGISTER_TEST(text_reader_error_positions);
+}
+
+int main(int argc, char **argv)
+{
+    return DP_test_main(argc, argv, register_tests, NULL);
+}
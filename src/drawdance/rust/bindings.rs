@@ -5,11 +5,18 @@ pub const DP_BIG_ENDIAN: u32 = 2;
 pub const DP_BYTE_ORDER: u32 = 1;
 pub const DP_BYTE_ORDER_LITTLE_ENDIAN: u32 = 1;
 pub const DP_BIT15: u32 = 32768;
-pub const DP_TILE_SIZE: u32 = 64;
-pub const DP_TILE_LENGTH: u32 = 4096;
+pub const DP_TILE_CHECKSUM_BLANK: u32 = 0;
+pub const DP_TILE_ENCODING_BLANK: u32 = 0;
+pub const DP_TILE_ENCODING_SOLID: u32 = 1;
+pub const DP_TILE_ENCODING_RLE: u32 = 2;
+pub const DP_TILE_ENCODING_RAW: u32 = 3;
 pub const DP_FLAT_IMAGE_INCLUDE_BACKGROUND: u32 = 1;
 pub const DP_FLAT_IMAGE_INCLUDE_SUBLAYERS: u32 = 2;
+pub const DP_FLAT_IMAGE_SINGLE_THREADED: u32 = 4;
+pub const DP_FLAT_IMAGE_REVEAL_CENSORED: u32 = 8;
 pub const DP_FLAT_IMAGE_RENDER_FLAGS: u32 = 3;
+pub const DP_COMPOSITOR_SCALE_MAX: u32 = 16;
+pub const DP_COMPOSITOR_BASE_LAYERS_AUTO: i32 = -1;
 pub const DP_DOCUMENT_METADATA_DPIX_DEFAULT: u32 = 72;
 pub const DP_DOCUMENT_METADATA_DPIY_DEFAULT: u32 = 72;
 pub const DP_DOCUMENT_METADATA_FRAMERATE_DEFAULT: u32 = 24;
@@ -48,12 +55,16 @@ pub const DP_ACL_STATE_RESET_IMAGE_INCLUDE_LAYER_ACL_EXCLUSIVE: u32 = 8;
 pub const DP_ACL_STATE_RESET_IMAGE_SESSION_RESET_FLAGS: u32 = 12;
 pub const DP_ACL_STATE_RESET_IMAGE_RECORDING_FLAGS: u32 = 15;
 pub const DP_ACL_STATE_RESET_IMAGE_TEMPLATE_FLAGS: u32 = 0;
+pub const DP_DPREC_MAGIC: &[u8; 6] = b"DPREC\0";
+pub const DP_DPREC_MAGIC_LENGTH: u32 = 6;
+pub const DP_BINARY_READER_FLAG_NO_LENGTH: u32 = 1;
+pub const DP_BINARY_READER_FLAG_NO_HEADER: u32 = 2;
 pub const DP_BLEND_MODE_MAX: u32 = 255;
 pub const DP_PROTOCOL_VERSION_NAMESPACE: &[u8; 3] = b"dp\0";
 pub const DP_PROTOCOL_VERSION_SERVER: u32 = 4;
 pub const DP_PROTOCOL_VERSION_MAJOR: u32 = 24;
-pub const DP_PROTOCOL_VERSION_MINOR: u32 = 0;
-pub const DP_PROTOCOL_VERSION: &[u8; 10] = b"dp:4.24.0\0";
+pub const DP_PROTOCOL_VERSION_MINOR: u32 = 1;
+pub const DP_PROTOCOL_VERSION: &[u8; 10] = b"dp:4.24.1\0";
 pub const DP_UNDO_DEPTH_DEFAULT: u32 = 30;
 pub const DP_MSG_SERVER_COMMAND_STATIC_LENGTH: u32 = 0;
 pub const DP_MSG_SERVER_COMMAND_MSG_MIN_LEN: u32 = 0;
@@ -73,8 +84,8 @@ pub const DP_MSG_JOIN_FLAGS_AUTH: u32 = 1;
 pub const DP_MSG_JOIN_FLAGS_MOD: u32 = 2;
 pub const DP_MSG_JOIN_FLAGS_BOT: u32 = 4;
 pub const DP_MSG_JOIN_NUM_FLAGS: u32 = 3;
-pub const DP_MSG_JOIN_NAME_MIN_LEN: u32 = 1;
-pub const DP_MSG_JOIN_NAME_MAX_LEN: u32 = 256;
+pub const DP_MSG_JOIN_NAME_MIN_LEN: u32 = 0;
+pub const DP_MSG_JOIN_NAME_MAX_LEN: u32 = 255;
 pub const DP_MSG_JOIN_AVATAR_MIN_SIZE: u32 = 0;
 pub const DP_MSG_JOIN_AVATAR_MAX_SIZE: u32 = 65533;
 pub const DP_MSG_LEAVE_STATIC_LENGTH: u32 = 0;
@@ -149,7 +160,8 @@ pub const DP_MSG_LAYER_ATTRIBUTES_STATIC_LENGTH: u32 = 6;
 pub const DP_MSG_LAYER_ATTRIBUTES_FLAGS_CENSOR: u32 = 1;
 pub const DP_MSG_LAYER_ATTRIBUTES_FLAGS_FIXED: u32 = 2;
 pub const DP_MSG_LAYER_ATTRIBUTES_FLAGS_ISOLATED: u32 = 4;
-pub const DP_MSG_LAYER_ATTRIBUTES_NUM_FLAGS: u32 = 3;
+pub const DP_MSG_LAYER_ATTRIBUTES_FLAGS_ALPHA_LOCK: u32 = 8;
+pub const DP_MSG_LAYER_ATTRIBUTES_NUM_FLAGS: u32 = 4;
 pub const DP_MSG_LAYER_RETITLE_STATIC_LENGTH: u32 = 2;
 pub const DP_MSG_LAYER_RETITLE_TITLE_MIN_LEN: u32 = 0;
 pub const DP_MSG_LAYER_RETITLE_TITLE_MAX_LEN: u32 = 65533;
@@ -169,7 +181,8 @@ pub const DP_MSG_ANNOTATION_EDIT_STATIC_LENGTH: u32 = 8;
 pub const DP_MSG_ANNOTATION_EDIT_FLAGS_PROTECT: u32 = 1;
 pub const DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_CENTER: u32 = 2;
 pub const DP_MSG_ANNOTATION_EDIT_FLAGS_VALIGN_BOTTOM: u32 = 4;
-pub const DP_MSG_ANNOTATION_EDIT_NUM_FLAGS: u32 = 3;
+pub const DP_MSG_ANNOTATION_EDIT_FLAGS_AUTO_GROW: u32 = 8;
+pub const DP_MSG_ANNOTATION_EDIT_NUM_FLAGS: u32 = 4;
 pub const DP_MSG_ANNOTATION_EDIT_TEXT_MIN_LEN: u32 = 0;
 pub const DP_MSG_ANNOTATION_EDIT_TEXT_MAX_LEN: u32 = 65527;
 pub const DP_MSG_ANNOTATION_DELETE_STATIC_LENGTH: u32 = 2;
@@ -195,6 +208,17 @@ pub const DP_MSG_DRAW_DABS_MYPAINT_STATIC_LENGTH: u32 = 18;
 pub const DP_MSG_DRAW_DABS_MYPAINT_DABS_MIN_COUNT: u32 = 1;
 pub const DP_MSG_DRAW_DABS_MYPAINT_DABS_MAX_COUNT: u32 = 8189;
 pub const DP_MSG_DRAW_DABS_MYPAINT_DABS_MAX: u32 = 8189;
+pub const DP_MSG_STAMP_MASK_STATIC_LENGTH: u32 = 6;
+pub const DP_MSG_STAMP_MASK_IMAGE_MIN_SIZE: u32 = 0;
+pub const DP_MSG_STAMP_MASK_IMAGE_MAX_SIZE: u32 = 65529;
+pub const DP_MSG_DRAW_DABS_STAMP_STATIC_LENGTH: u32 = 26;
+pub const DP_MSG_DRAW_DABS_STAMP_DABS_MIN_COUNT: u32 = 1;
+pub const DP_MSG_DRAW_DABS_STAMP_DABS_MAX_COUNT: u32 = 10918;
+pub const DP_MSG_DRAW_DABS_STAMP_DABS_MAX: u32 = 10918;
+pub const DP_MSG_DRAW_DABS_SOFT_STATIC_LENGTH: u32 = 23;
+pub const DP_MSG_DRAW_DABS_SOFT_DABS_MIN_COUNT: u32 = 1;
+pub const DP_MSG_DRAW_DABS_SOFT_DABS_MAX_COUNT: u32 = 10918;
+pub const DP_MSG_DRAW_DABS_SOFT_DABS_MAX: u32 = 10918;
 pub const DP_MSG_MOVE_RECT_STATIC_LENGTH: u32 = 28;
 pub const DP_MSG_MOVE_RECT_MASK_MIN_SIZE: u32 = 0;
 pub const DP_MSG_MOVE_RECT_MASK_MAX_SIZE: u32 = 65507;
@@ -215,6 +239,7 @@ pub const DP_MSG_LAYER_TREE_DELETE_STATIC_LENGTH: u32 = 4;
 pub const DP_MSG_TRANSFORM_REGION_STATIC_LENGTH: u32 = 53;
 pub const DP_MSG_TRANSFORM_REGION_MODE_NEAREST: u32 = 0;
 pub const DP_MSG_TRANSFORM_REGION_MODE_BILINEAR: u32 = 1;
+pub const DP_MSG_TRANSFORM_REGION_MODE_BICUBIC: u32 = 2;
 pub const DP_MSG_TRANSFORM_REGION_NUM_MODE: u32 = 2;
 pub const DP_MSG_TRANSFORM_REGION_MASK_MIN_SIZE: u32 = 0;
 pub const DP_MSG_TRANSFORM_REGION_MASK_MAX_SIZE: u32 = 65482;
@@ -239,6 +264,29 @@ pub const DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES_STATIC_LENGTH: u32 = 4;
 pub const DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES_LAYERS_MIN_COUNT: u32 = 0;
 pub const DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES_LAYERS_MAX_COUNT: u32 = 32765;
 pub const DP_MSG_KEY_FRAME_DELETE_STATIC_LENGTH: u32 = 8;
+pub const DP_MSG_FILTER_REGION_STATIC_LENGTH: u32 = 35;
+pub const DP_MSG_FILTER_REGION_FILTER_BRIGHTNESS_CONTRAST: u32 = 0;
+pub const DP_MSG_FILTER_REGION_FILTER_HSL: u32 = 1;
+pub const DP_MSG_FILTER_REGION_FILTER_INVERT: u32 = 2;
+pub const DP_MSG_FILTER_REGION_NUM_FILTER: u32 = 3;
+pub const DP_MSG_FILL_GRADIENT_STATIC_LENGTH: u32 = 41;
+pub const DP_MSG_FILL_GRADIENT_SHAPE_LINEAR: u32 = 0;
+pub const DP_MSG_FILL_GRADIENT_SHAPE_RADIAL: u32 = 1;
+pub const DP_MSG_FILL_GRADIENT_NUM_SHAPE: u32 = 2;
+pub const DP_MSG_FILL_GRADIENT_FLAGS_DITHER: u32 = 1;
+pub const DP_MSG_FILL_GRADIENT_NUM_FLAGS: u32 = 1;
+pub const DP_MSG_FILL_GRADIENT_STOPS_MIN_COUNT: u32 = 1;
+pub const DP_MSG_FILL_GRADIENT_STOPS_MAX_COUNT: u32 = 10915;
+pub const DP_MSG_FILL_GRADIENT_STOPS_MAX: u32 = 10915;
+pub const DP_MSG_ANNOTATION_ORDER_STATIC_LENGTH: u32 = 3;
+pub const DP_MSG_ANNOTATION_ORDER_DIRECTION_RAISE: u32 = 0;
+pub const DP_MSG_ANNOTATION_ORDER_DIRECTION_LOWER: u32 = 1;
+pub const DP_MSG_ANNOTATION_ORDER_DIRECTION_FRONT: u32 = 2;
+pub const DP_MSG_ANNOTATION_ORDER_DIRECTION_BACK: u32 = 3;
+pub const DP_MSG_ANNOTATION_ORDER_NUM_DIRECTION: u32 = 4;
+pub const DP_MSG_LAYER_METADATA_STATIC_LENGTH: u32 = 3;
+pub const DP_MSG_LAYER_METADATA_METADATA_MIN_SIZE: u32 = 0;
+pub const DP_MSG_LAYER_METADATA_METADATA_MAX_SIZE: u32 = 65532;
 pub const DP_MSG_UNDO_STATIC_LENGTH: u32 = 2;
 pub const DP_MESSAGE_MAX: u32 = 255;
 pub const DP_MESSAGE_HEADER_LENGTH: u32 = 4;
@@ -1031,6 +1079,14 @@ extern "C" {
         annotation_id: ::std::os::raw::c_int,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn DP_annotation_list_index_at(
+        al: *mut DP_AnnotationList,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        expand: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn DP_transient_annotation_list_new(
         al: *mut DP_AnnotationList,
@@ -1097,6 +1153,13 @@ extern "C" {
         index: ::std::os::raw::c_int,
     );
 }
+extern "C" {
+    pub fn DP_transient_annotation_list_move(
+        tal: *mut DP_TransientAnnotationList,
+        from_index: ::std::os::raw::c_int,
+        to_index: ::std::os::raw::c_int,
+    );
+}
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub union DP_Pixel8 {
@@ -1545,6 +1608,47 @@ pub struct DP_Timeline {
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
+pub struct DP_SketchLayer {
+    pub layer_id: ::std::os::raw::c_int,
+    pub opacity: u16,
+}
+#[test]
+fn bindgen_test_layout_DP_SketchLayer() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_SketchLayer> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_SketchLayer>(),
+        8usize,
+        concat!("Size of: ", stringify!(DP_SketchLayer))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_SketchLayer>(),
+        4usize,
+        concat!("Alignment of ", stringify!(DP_SketchLayer))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).layer_id) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_SketchLayer),
+            "::",
+            stringify!(layer_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).opacity) as usize - ptr as usize },
+        4usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_SketchLayer),
+            "::",
+            stringify!(opacity)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct DP_CanvasState {
     _unused: [u8; 0],
 }
@@ -1658,10 +1762,40 @@ extern "C" {
         out_height: *mut ::std::os::raw::c_int,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn DP_canvas_state_pick_layer(
+        cs: *mut DP_CanvasState,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        min_alpha: u8,
+        reveal_censored: bool,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_canvas_state_touched_by(
+        cs: *mut DP_CanvasState,
+        area: *const DP_Rect,
+        out_users: *mut u8,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_canvas_state_checksum(cs: *mut DP_CanvasState) -> u64;
+}
+extern "C" {
+    pub fn DP_canvas_state_tile_checksums(cs: *mut DP_CanvasState, out_checksums: *mut u64);
+}
+extern "C" {
+    pub fn DP_canvas_state_sketch_inc(
+        cs: *mut DP_CanvasState,
+        count: ::std::os::raw::c_int,
+        sketches: *const DP_SketchLayer,
+    ) -> *mut DP_CanvasState;
+}
 extern "C" {
     pub fn DP_canvas_state_to_flat_layer(
         cs: *mut DP_CanvasState,
         flags: ::std::os::raw::c_uint,
+        area_or_null: *const DP_Rect,
         vmf_or_null: *const DP_ViewModeFilter,
     ) -> *mut DP_TransientLayerContent;
 }
@@ -1673,6 +1807,17 @@ extern "C" {
         vmf_or_null: *const DP_ViewModeFilter,
     ) -> *mut DP_Image;
 }
+extern "C" {
+    pub fn DP_canvas_state_flatten_into(
+        cs: *mut DP_CanvasState,
+        flags: ::std::os::raw::c_uint,
+        area_or_null: *const DP_Rect,
+        vmf_or_null: *const DP_ViewModeFilter,
+        pixels: *mut DP_Pixel8,
+        length: usize,
+        stride: usize,
+    ) -> bool;
+}
 extern "C" {
     pub fn DP_canvas_state_to_flat_separated_urgba8(
         cs: *mut DP_CanvasState,
@@ -1699,6 +1844,16 @@ extern "C" {
         vmf_or_null: *const DP_ViewModeFilter,
     ) -> *mut DP_TransientTile;
 }
+extern "C" {
+    pub fn DP_canvas_state_flatten_tile_layers(
+        cs: *mut DP_CanvasState,
+        tile_index: ::std::os::raw::c_int,
+        tt_or_null: *mut DP_TransientTile,
+        flags: ::std::os::raw::c_uint,
+        start: ::std::os::raw::c_int,
+        end: ::std::os::raw::c_int,
+    ) -> *mut DP_TransientTile;
+}
 extern "C" {
     pub fn DP_canvas_state_flatten_tile_at(
         cs: *mut DP_CanvasState,
@@ -1900,6 +2055,75 @@ extern "C" {
 extern "C" {
     pub fn DP_transient_canvas_state_intuit_background(tcs: *mut DP_TransientCanvasState);
 }
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_Compositor {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_compositor_new(flags: ::std::os::raw::c_uint) -> *mut DP_Compositor;
+}
+extern "C" {
+    pub fn DP_compositor_free(c: *mut DP_Compositor);
+}
+extern "C" {
+    pub fn DP_compositor_width(c: *mut DP_Compositor) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_compositor_height(c: *mut DP_Compositor) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_compositor_invalidate(c: *mut DP_Compositor);
+}
+extern "C" {
+    pub fn DP_compositor_update(
+        c: *mut DP_Compositor,
+        cs: *mut DP_CanvasState,
+        aa: *const DP_AffectedArea,
+        out_bounds_or_null: *mut DP_Rect,
+    ) -> bool;
+}
+extern "C" {
+    pub fn DP_compositor_tile_at_noinc(
+        c: *mut DP_Compositor,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_compositor_to_pixels8(
+        c: *mut DP_Compositor,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        width: ::std::os::raw::c_int,
+        height: ::std::os::raw::c_int,
+    ) -> *mut DP_Pixel8;
+}
+extern "C" {
+    pub fn DP_compositor_base_layers_set(c: *mut DP_Compositor, count: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn DP_compositor_base_layers(c: *mut DP_Compositor) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_compositor_scale_cache_budget_set(c: *mut DP_Compositor, budget: usize);
+}
+extern "C" {
+    pub fn DP_compositor_scale_cache_bytes(c: *mut DP_Compositor) -> usize;
+}
+extern "C" {
+    pub fn DP_compositor_scale_cache_count(c: *mut DP_Compositor) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_compositor_to_scaled_pixels8(
+        c: *mut DP_Compositor,
+        scale: ::std::os::raw::c_int,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        width: ::std::os::raw::c_int,
+        height: ::std::os::raw::c_int,
+    ) -> *mut DP_Pixel8;
+}
 extern "C" {
     pub fn DP_document_metadata_new() -> *mut DP_DocumentMetadata;
 }
@@ -2549,6 +2773,22 @@ extern "C" {
         y: ::std::os::raw::c_int,
     ) -> *mut DP_Tile;
 }
+extern "C" {
+    pub fn DP_layer_content_tile_context_id_at(
+        lc: *mut DP_LayerContent,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        out_context_id: *mut ::std::os::raw::c_uint,
+    ) -> bool;
+}
+extern "C" {
+    pub fn DP_layer_content_next_nonblank_tile_noinc(
+        lc: *mut DP_LayerContent,
+        in_out_index: *mut ::std::os::raw::c_int,
+        out_x: *mut ::std::os::raw::c_int,
+        out_y: *mut ::std::os::raw::c_int,
+    ) -> *mut DP_Tile;
+}
 extern "C" {
     pub fn DP_layer_content_pixel_at(
         lc: *mut DP_LayerContent,
@@ -2587,6 +2827,9 @@ extern "C" {
         out_pixel: *mut DP_Pixel15,
     ) -> bool;
 }
+extern "C" {
+    pub fn DP_layer_content_checksum(lc: *mut DP_LayerContent) -> u64;
+}
 extern "C" {
     pub fn DP_layer_content_search_change_bounds(
         lc: *mut DP_LayerContent,
@@ -2645,6 +2888,15 @@ extern "C" {
         left: ::std::os::raw::c_int,
     ) -> *mut DP_TransientLayerContent;
 }
+extern "C" {
+    pub fn DP_layer_content_resize_anchored(
+        lc: *mut DP_LayerContent,
+        top: ::std::os::raw::c_int,
+        right: ::std::os::raw::c_int,
+        bottom: ::std::os::raw::c_int,
+        left: ::std::os::raw::c_int,
+    ) -> *mut DP_TransientLayerContent;
+}
 extern "C" {
     pub fn DP_layer_content_merge_sublayers(lc: *mut DP_LayerContent) -> *mut DP_LayerContent;
 }
@@ -2715,6 +2967,15 @@ extern "C" {
         y: ::std::os::raw::c_int,
     ) -> *mut DP_Tile;
 }
+extern "C" {
+    pub fn DP_transient_layer_content_next_nonblank_transient_tile(
+        tlc: *mut DP_TransientLayerContent,
+        context_id: ::std::os::raw::c_uint,
+        in_out_index: *mut ::std::os::raw::c_int,
+        out_x: *mut ::std::os::raw::c_int,
+        out_y: *mut ::std::os::raw::c_int,
+    ) -> *mut DP_TransientTile;
+}
 extern "C" {
     pub fn DP_transient_layer_content_transient_tile_at_set_noinc(
         tlc: *mut DP_TransientLayerContent,
@@ -2937,6 +3198,7 @@ extern "C" {
 extern "C" {
     pub fn DP_layer_group_resize(
         lg: *mut DP_LayerGroup,
+        lp: *mut DP_LayerProps,
         context_id: ::std::os::raw::c_uint,
         top: ::std::os::raw::c_int,
         right: ::std::os::raw::c_int,
@@ -3122,6 +3384,7 @@ extern "C" {
 extern "C" {
     pub fn DP_layer_list_resize(
         ll: *mut DP_LayerList,
+        lpl: *mut DP_LayerPropsList,
         context_id: ::std::os::raw::c_uint,
         top: ::std::os::raw::c_int,
         right: ::std::os::raw::c_int,
@@ -3307,6 +3570,17 @@ extern "C" {
         index: ::std::os::raw::c_int,
     );
 }
+pub const DP_LAYER_COLOR_TAG_NONE: DP_LayerColorTag = 0;
+pub const DP_LAYER_COLOR_TAG_BLUE: DP_LayerColorTag = 1;
+pub const DP_LAYER_COLOR_TAG_GREEN: DP_LayerColorTag = 2;
+pub const DP_LAYER_COLOR_TAG_YELLOW: DP_LayerColorTag = 3;
+pub const DP_LAYER_COLOR_TAG_ORANGE: DP_LayerColorTag = 4;
+pub const DP_LAYER_COLOR_TAG_BROWN: DP_LayerColorTag = 5;
+pub const DP_LAYER_COLOR_TAG_RED: DP_LayerColorTag = 6;
+pub const DP_LAYER_COLOR_TAG_PURPLE: DP_LayerColorTag = 7;
+pub const DP_LAYER_COLOR_TAG_GRAY: DP_LayerColorTag = 8;
+pub const DP_LAYER_COLOR_TAG_COUNT: DP_LayerColorTag = 9;
+pub type DP_LayerColorTag = ::std::os::raw::c_uint;
 extern "C" {
     pub fn DP_layer_props_incref(lp: *mut DP_LayerProps) -> *mut DP_LayerProps;
 }
@@ -3343,6 +3617,12 @@ extern "C" {
 extern "C" {
     pub fn DP_layer_props_isolated(lp: *mut DP_LayerProps) -> bool;
 }
+extern "C" {
+    pub fn DP_layer_props_alpha_lock(lp: *mut DP_LayerProps) -> bool;
+}
+extern "C" {
+    pub fn DP_layer_props_fixed(lp: *mut DP_LayerProps) -> bool;
+}
 extern "C" {
     pub fn DP_layer_props_visible(lp: *mut DP_LayerProps) -> bool;
 }
@@ -3352,6 +3632,34 @@ extern "C" {
         out_length: *mut usize,
     ) -> *const ::std::os::raw::c_char;
 }
+extern "C" {
+    pub fn DP_layer_props_color_tag(lp: *mut DP_LayerProps) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_layer_props_metadata_count(lp: *mut DP_LayerProps) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_layer_props_metadata_key_at(
+        lp: *mut DP_LayerProps,
+        index: ::std::os::raw::c_int,
+        out_length: *mut usize,
+    ) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn DP_layer_props_metadata_value_at(
+        lp: *mut DP_LayerProps,
+        index: ::std::os::raw::c_int,
+        out_length: *mut usize,
+    ) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn DP_layer_props_metadata_get(
+        lp: *mut DP_LayerProps,
+        key: *const ::std::os::raw::c_char,
+        key_length: usize,
+        out_length: *mut usize,
+    ) -> *const ::std::os::raw::c_char;
+}
 extern "C" {
     pub fn DP_layer_props_children_noinc(lp: *mut DP_LayerProps) -> *mut DP_LayerPropsList;
 }
@@ -3421,6 +3729,12 @@ extern "C" {
 extern "C" {
     pub fn DP_transient_layer_props_isolated(tlp: *mut DP_TransientLayerProps) -> bool;
 }
+extern "C" {
+    pub fn DP_transient_layer_props_alpha_lock(tlp: *mut DP_TransientLayerProps) -> bool;
+}
+extern "C" {
+    pub fn DP_transient_layer_props_fixed(tlp: *mut DP_TransientLayerProps) -> bool;
+}
 extern "C" {
     pub fn DP_transient_layer_props_visible(tlp: *mut DP_TransientLayerProps) -> bool;
 }
@@ -3465,6 +3779,15 @@ extern "C" {
 extern "C" {
     pub fn DP_transient_layer_props_isolated_set(tlp: *mut DP_TransientLayerProps, isolated: bool);
 }
+extern "C" {
+    pub fn DP_transient_layer_props_alpha_lock_set(
+        tlp: *mut DP_TransientLayerProps,
+        alpha_lock: bool,
+    );
+}
+extern "C" {
+    pub fn DP_transient_layer_props_fixed_set(tlp: *mut DP_TransientLayerProps, fixed: bool);
+}
 extern "C" {
     pub fn DP_transient_layer_props_title_set(
         tlp: *mut DP_TransientLayerProps,
@@ -3472,6 +3795,24 @@ extern "C" {
         length: usize,
     );
 }
+extern "C" {
+    pub fn DP_transient_layer_props_color_tag_set(
+        tlp: *mut DP_TransientLayerProps,
+        color_tag: ::std::os::raw::c_int,
+    );
+}
+extern "C" {
+    pub fn DP_transient_layer_props_metadata_set(
+        tlp: *mut DP_TransientLayerProps,
+        key: *const ::std::os::raw::c_char,
+        key_length: usize,
+        value_or_null: *const ::std::os::raw::c_char,
+        value_length: usize,
+    );
+}
+extern "C" {
+    pub fn DP_transient_layer_props_metadata_clear(tlp: *mut DP_TransientLayerProps);
+}
 extern "C" {
     pub fn DP_layer_props_list_new() -> *mut DP_LayerPropsList;
 }
@@ -3627,92 +3968,263 @@ extern "C" {
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-pub struct DP_Quad {
-    pub x1: ::std::os::raw::c_int,
-    pub y1: ::std::os::raw::c_int,
-    pub x2: ::std::os::raw::c_int,
-    pub y2: ::std::os::raw::c_int,
-    pub x3: ::std::os::raw::c_int,
-    pub y3: ::std::os::raw::c_int,
-    pub x4: ::std::os::raw::c_int,
-    pub y4: ::std::os::raw::c_int,
+pub struct DP_MemoryUsage {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MemoryUsageCounts {
+    pub unique_bytes: usize,
+    pub tile_count: ::std::os::raw::c_int,
+    pub unique_tile_count: ::std::os::raw::c_int,
+    pub shared_tile_count: ::std::os::raw::c_int,
+    pub blank_tile_count: ::std::os::raw::c_int,
+    pub packed_bytes: usize,
+    pub packed_tile_count: ::std::os::raw::c_int,
 }
 #[test]
-fn bindgen_test_layout_DP_Quad() {
-    const UNINIT: ::std::mem::MaybeUninit<DP_Quad> = ::std::mem::MaybeUninit::uninit();
+fn bindgen_test_layout_DP_MemoryUsageCounts() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_MemoryUsageCounts> =
+        ::std::mem::MaybeUninit::uninit();
     let ptr = UNINIT.as_ptr();
     assert_eq!(
-        ::std::mem::size_of::<DP_Quad>(),
-        32usize,
-        concat!("Size of: ", stringify!(DP_Quad))
+        ::std::mem::size_of::<DP_MemoryUsageCounts>(),
+        40usize,
+        concat!("Size of: ", stringify!(DP_MemoryUsageCounts))
     );
     assert_eq!(
-        ::std::mem::align_of::<DP_Quad>(),
-        4usize,
-        concat!("Alignment of ", stringify!(DP_Quad))
+        ::std::mem::align_of::<DP_MemoryUsageCounts>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_MemoryUsageCounts))
     );
     assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).x1) as usize - ptr as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).unique_bytes) as usize - ptr as usize },
         0usize,
         concat!(
             "Offset of field: ",
-            stringify!(DP_Quad),
-            "::",
-            stringify!(x1)
-        )
-    );
-    assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).y1) as usize - ptr as usize },
-        4usize,
-        concat!(
-            "Offset of field: ",
-            stringify!(DP_Quad),
+            stringify!(DP_MemoryUsageCounts),
             "::",
-            stringify!(y1)
+            stringify!(unique_bytes)
         )
     );
     assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).x2) as usize - ptr as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).tile_count) as usize - ptr as usize },
         8usize,
         concat!(
             "Offset of field: ",
-            stringify!(DP_Quad),
+            stringify!(DP_MemoryUsageCounts),
             "::",
-            stringify!(x2)
+            stringify!(tile_count)
         )
     );
     assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).y2) as usize - ptr as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).unique_tile_count) as usize - ptr as usize },
         12usize,
         concat!(
             "Offset of field: ",
-            stringify!(DP_Quad),
+            stringify!(DP_MemoryUsageCounts),
             "::",
-            stringify!(y2)
+            stringify!(unique_tile_count)
         )
     );
     assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).x3) as usize - ptr as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).shared_tile_count) as usize - ptr as usize },
         16usize,
         concat!(
             "Offset of field: ",
-            stringify!(DP_Quad),
+            stringify!(DP_MemoryUsageCounts),
             "::",
-            stringify!(x3)
+            stringify!(shared_tile_count)
         )
     );
     assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).y3) as usize - ptr as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).blank_tile_count) as usize - ptr as usize },
         20usize,
         concat!(
             "Offset of field: ",
-            stringify!(DP_Quad),
+            stringify!(DP_MemoryUsageCounts),
             "::",
-            stringify!(y3)
+            stringify!(blank_tile_count)
         )
     );
     assert_eq!(
-        unsafe { ::std::ptr::addr_of!((*ptr).x4) as usize - ptr as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).packed_bytes) as usize - ptr as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_MemoryUsageCounts),
+            "::",
+            stringify!(packed_bytes)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).packed_tile_count) as usize - ptr as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_MemoryUsageCounts),
+            "::",
+            stringify!(packed_tile_count)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_LayerMemoryUsage {
+    pub layer_id: ::std::os::raw::c_int,
+    pub counts: DP_MemoryUsageCounts,
+}
+#[test]
+fn bindgen_test_layout_DP_LayerMemoryUsage() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_LayerMemoryUsage> =
+        ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_LayerMemoryUsage>(),
+        48usize,
+        concat!("Size of: ", stringify!(DP_LayerMemoryUsage))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_LayerMemoryUsage>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_LayerMemoryUsage))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).layer_id) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerMemoryUsage),
+            "::",
+            stringify!(layer_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).counts) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerMemoryUsage),
+            "::",
+            stringify!(counts)
+        )
+    );
+}
+extern "C" {
+    pub fn DP_memory_usage_new() -> *mut DP_MemoryUsage;
+}
+extern "C" {
+    pub fn DP_memory_usage_free(mu: *mut DP_MemoryUsage);
+}
+extern "C" {
+    pub fn DP_memory_usage_add_canvas_state(mu: *mut DP_MemoryUsage, cs: *mut DP_CanvasState);
+}
+extern "C" {
+    pub fn DP_memory_usage_totals(mu: *mut DP_MemoryUsage) -> DP_MemoryUsageCounts;
+}
+extern "C" {
+    pub fn DP_memory_usage_layer_count(mu: *mut DP_MemoryUsage) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_memory_usage_layer_at(
+        mu: *mut DP_MemoryUsage,
+        index: ::std::os::raw::c_int,
+    ) -> *const DP_LayerMemoryUsage;
+}
+extern "C" {
+    pub fn DP_memory_usage_layer_search(
+        mu: *mut DP_MemoryUsage,
+        layer_id: ::std::os::raw::c_int,
+    ) -> *const DP_LayerMemoryUsage;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_Quad {
+    pub x1: ::std::os::raw::c_int,
+    pub y1: ::std::os::raw::c_int,
+    pub x2: ::std::os::raw::c_int,
+    pub y2: ::std::os::raw::c_int,
+    pub x3: ::std::os::raw::c_int,
+    pub y3: ::std::os::raw::c_int,
+    pub x4: ::std::os::raw::c_int,
+    pub y4: ::std::os::raw::c_int,
+}
+#[test]
+fn bindgen_test_layout_DP_Quad() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_Quad> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_Quad>(),
+        32usize,
+        concat!("Size of: ", stringify!(DP_Quad))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_Quad>(),
+        4usize,
+        concat!("Alignment of ", stringify!(DP_Quad))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).x1) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_Quad),
+            "::",
+            stringify!(x1)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).y1) as usize - ptr as usize },
+        4usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_Quad),
+            "::",
+            stringify!(y1)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).x2) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_Quad),
+            "::",
+            stringify!(x2)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).y2) as usize - ptr as usize },
+        12usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_Quad),
+            "::",
+            stringify!(y2)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).x3) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_Quad),
+            "::",
+            stringify!(x3)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).y3) as usize - ptr as usize },
+        20usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_Quad),
+            "::",
+            stringify!(y3)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).x4) as usize - ptr as usize },
         24usize,
         concat!(
             "Offset of field: ",
@@ -4046,12 +4558,32 @@ extern "C" {
         aia: *mut DP_AffectedIndirectAreas,
     ) -> DP_AffectedArea;
 }
+extern "C" {
+    pub fn DP_affected_area_make_visible(
+        msg: *mut DP_Message,
+        aia: *mut DP_AffectedIndirectAreas,
+    ) -> DP_AffectedArea;
+}
 extern "C" {
     pub fn DP_affected_area_concurrent_with(
         aa: *const DP_AffectedArea,
         other: *const DP_AffectedArea,
     ) -> bool;
 }
+extern "C" {
+    pub fn DP_affected_area_bounds(
+        aa: *const DP_AffectedArea,
+        canvas_width: ::std::os::raw::c_int,
+        canvas_height: ::std::os::raw::c_int,
+        out_bounds: *mut DP_Rect,
+    ) -> bool;
+}
+extern "C" {
+    pub fn DP_affected_area_merge(
+        a: *const DP_AffectedArea,
+        b: *const DP_AffectedArea,
+    ) -> DP_AffectedArea;
+}
 extern "C" {
     pub fn DP_affected_indirect_areas_clear(aia: *mut DP_AffectedIndirectAreas);
 }
@@ -4760,6 +5292,9 @@ extern "C" {
 extern "C" {
     pub fn DP_canvas_history_save_point_make(ch: *mut DP_CanvasHistory) -> bool;
 }
+extern "C" {
+    pub fn DP_canvas_history_memory_usage_add(ch: *mut DP_CanvasHistory, mu: *mut DP_MemoryUsage);
+}
 extern "C" {
     pub fn DP_canvas_history_cleanup(
         ch: *mut DP_CanvasHistory,
@@ -5898,6 +6433,7 @@ pub struct DP_PlayerIndexEntry {
     pub message_offset: usize,
     pub snapshot_offset: usize,
     pub thumbnail_offset: usize,
+    pub elapsed_ms: ::std::os::raw::c_longlong,
 }
 #[test]
 fn bindgen_test_layout_DP_PlayerIndexEntry() {
@@ -5905,7 +6441,7 @@ fn bindgen_test_layout_DP_PlayerIndexEntry() {
     let ptr = UNINIT.as_ptr();
     assert_eq!(
         ::std::mem::size_of::<DP_PlayerIndexEntry>(),
-        32usize,
+        40usize,
         concat!("Size of: ", stringify!(DP_PlayerIndexEntry))
     );
     assert_eq!(
@@ -5953,14 +6489,81 @@ fn bindgen_test_layout_DP_PlayerIndexEntry() {
             stringify!(thumbnail_offset)
         )
     );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).elapsed_ms) as usize - ptr as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_PlayerIndexEntry),
+            "::",
+            stringify!(elapsed_ms)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_PlayerIndexMarker {
+    pub message_index: ::std::os::raw::c_longlong,
+    pub elapsed_ms: ::std::os::raw::c_longlong,
+    pub text: *mut ::std::os::raw::c_char,
+}
+#[test]
+fn bindgen_test_layout_DP_PlayerIndexMarker() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_PlayerIndexMarker> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_PlayerIndexMarker>(),
+        24usize,
+        concat!("Size of: ", stringify!(DP_PlayerIndexMarker))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_PlayerIndexMarker>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_PlayerIndexMarker))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).message_index) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_PlayerIndexMarker),
+            "::",
+            stringify!(message_index)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).elapsed_ms) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_PlayerIndexMarker),
+            "::",
+            stringify!(elapsed_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).text) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_PlayerIndexMarker),
+            "::",
+            stringify!(text)
+        )
+    );
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct DP_PlayerIndexEntrySnapshot {
     _unused: [u8; 0],
 }
-pub type DP_PlayerIndexShouldSnapshotFn =
-    ::std::option::Option<unsafe extern "C" fn(user: *mut ::std::os::raw::c_void) -> bool>;
+pub type DP_PlayerIndexShouldSnapshotFn = ::std::option::Option<
+    unsafe extern "C" fn(
+        user: *mut ::std::os::raw::c_void,
+        messages: ::std::os::raw::c_longlong,
+        bytes: usize,
+    ) -> bool,
+>;
 pub type DP_PlayerIndexProgressFn = ::std::option::Option<
     unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, percent: ::std::os::raw::c_int),
 >;
@@ -6006,6 +6609,12 @@ extern "C" {
     pub fn DP_player_step(player: *mut DP_Player, out_msg: *mut *mut DP_Message)
         -> DP_PlayerResult;
 }
+extern "C" {
+    pub fn DP_player_step_raw(
+        player: *mut DP_Player,
+        out_msg: *mut *mut DP_Message,
+    ) -> DP_PlayerResult;
+}
 extern "C" {
     pub fn DP_player_step_dump(
         player: *mut DP_Player,
@@ -6088,45 +6697,140 @@ extern "C" {
         out_error: *mut bool,
     ) -> *mut DP_Image;
 }
-pub const DP_PREVIEW_CUT: DP_PreviewType = 0;
-pub const DP_PREVIEW_TRANSFORM: DP_PreviewType = 1;
-pub const DP_PREVIEW_DABS: DP_PreviewType = 2;
-pub const DP_PREVIEW_COUNT: DP_PreviewType = 3;
-pub type DP_PreviewType = ::std::os::raw::c_uint;
-#[repr(C)]
-#[derive(Debug, Copy, Clone)]
-pub struct DP_Preview {
-    _unused: [u8; 0],
-}
-pub type DP_PreviewTransformGetPixelsFn = ::std::option::Option<
-    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void) -> *const DP_Pixel8,
->;
-pub type DP_PreviewTransformDisposePixelsFn =
-    ::std::option::Option<unsafe extern "C" fn(user: *mut ::std::os::raw::c_void)>;
-#[repr(C)]
-#[derive(Debug, Copy, Clone)]
-pub struct DP_PreviewRenderer {
-    _unused: [u8; 0],
-}
-pub type DP_PreviewRenderedFn = ::std::option::Option<
-    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, pv: *mut DP_Preview),
->;
-pub type DP_PreviewRerenderedFn =
-    ::std::option::Option<unsafe extern "C" fn(user: *mut ::std::os::raw::c_void)>;
-pub type DP_PreviewClearFn = ::std::option::Option<
-    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, type_: DP_PreviewType),
->;
-extern "C" {
-    pub static mut DP_preview_null: DP_Preview;
-}
 extern "C" {
-    pub fn DP_preview_incref(pv: *mut DP_Preview) -> *mut DP_Preview;
+    pub fn DP_player_index_marker_count(player: *mut DP_Player) -> usize;
 }
 extern "C" {
-    pub fn DP_preview_incref_nullable(pv_or_null: *mut DP_Preview) -> *mut DP_Preview;
+    pub fn DP_player_index_marker_at(
+        player: *mut DP_Player,
+        index: usize,
+    ) -> DP_PlayerIndexMarker;
 }
+pub type DP_PutImagePushMessageFn = ::std::option::Option<
+    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, msg: *mut DP_Message) -> bool,
+>;
 extern "C" {
-    pub fn DP_preview_decref(pv: *mut DP_Preview);
+    pub fn DP_put_image_messages(
+        context_id: ::std::os::raw::c_uint,
+        layer_id: ::std::os::raw::c_int,
+        blend_mode: ::std::os::raw::c_int,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        img: *mut DP_Image,
+        push_message: DP_PutImagePushMessageFn,
+        user: *mut ::std::os::raw::c_void,
+    ) -> bool;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_RecordingFilterOptions {
+    pub remove_undone: bool,
+    pub remove_chat: bool,
+    pub remove_lasers: bool,
+    pub max_interval_ms: ::std::os::raw::c_int,
+}
+#[test]
+fn bindgen_test_layout_DP_RecordingFilterOptions() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_RecordingFilterOptions> =
+        ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_RecordingFilterOptions>(),
+        8usize,
+        concat!("Size of: ", stringify!(DP_RecordingFilterOptions))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_RecordingFilterOptions>(),
+        4usize,
+        concat!("Alignment of ", stringify!(DP_RecordingFilterOptions))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).remove_undone) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingFilterOptions),
+            "::",
+            stringify!(remove_undone)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).remove_chat) as usize - ptr as usize },
+        1usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingFilterOptions),
+            "::",
+            stringify!(remove_chat)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).remove_lasers) as usize - ptr as usize },
+        2usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingFilterOptions),
+            "::",
+            stringify!(remove_lasers)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).max_interval_ms) as usize - ptr as usize },
+        4usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingFilterOptions),
+            "::",
+            stringify!(max_interval_ms)
+        )
+    );
+}
+extern "C" {
+    pub fn DP_recording_filter(
+        player: *mut DP_Player,
+        r: *mut DP_Recorder,
+        options: *const DP_RecordingFilterOptions,
+    ) -> bool;
+}
+pub const DP_PREVIEW_CUT: DP_PreviewType = 0;
+pub const DP_PREVIEW_TRANSFORM: DP_PreviewType = 1;
+pub const DP_PREVIEW_DABS: DP_PreviewType = 2;
+pub const DP_PREVIEW_COUNT: DP_PreviewType = 3;
+pub type DP_PreviewType = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_Preview {
+    _unused: [u8; 0],
+}
+pub type DP_PreviewTransformGetPixelsFn = ::std::option::Option<
+    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void) -> *const DP_Pixel8,
+>;
+pub type DP_PreviewTransformDisposePixelsFn =
+    ::std::option::Option<unsafe extern "C" fn(user: *mut ::std::os::raw::c_void)>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_PreviewRenderer {
+    _unused: [u8; 0],
+}
+pub type DP_PreviewRenderedFn = ::std::option::Option<
+    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, pv: *mut DP_Preview),
+>;
+pub type DP_PreviewRerenderedFn =
+    ::std::option::Option<unsafe extern "C" fn(user: *mut ::std::os::raw::c_void)>;
+pub type DP_PreviewClearFn = ::std::option::Option<
+    unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, type_: DP_PreviewType),
+>;
+extern "C" {
+    pub static mut DP_preview_null: DP_Preview;
+}
+extern "C" {
+    pub fn DP_preview_incref(pv: *mut DP_Preview) -> *mut DP_Preview;
+}
+extern "C" {
+    pub fn DP_preview_incref_nullable(pv_or_null: *mut DP_Preview) -> *mut DP_Preview;
+}
+extern "C" {
+    pub fn DP_preview_decref(pv: *mut DP_Preview);
 }
 extern "C" {
     pub fn DP_preview_decref_nullable(pv_or_null: *mut DP_Preview);
@@ -6283,6 +6987,121 @@ extern "C" {
         mode: DP_RendererMode,
     );
 }
+pub const DP_LAYER_DIFF_ADDED: DP_LayerDiffType = 0;
+pub const DP_LAYER_DIFF_REMOVED: DP_LayerDiffType = 1;
+pub const DP_LAYER_DIFF_MOVED: DP_LayerDiffType = 2;
+pub const DP_LAYER_DIFF_ATTRIBUTES_CHANGED: DP_LayerDiffType = 3;
+pub const DP_LAYER_DIFF_CONTENT_CHANGED: DP_LayerDiffType = 4;
+pub type DP_LayerDiffType = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_LayerDiffEvent {
+    pub type_: DP_LayerDiffType,
+    pub layer_id: ::std::os::raw::c_int,
+    pub from_parent_id: ::std::os::raw::c_int,
+    pub from_index: ::std::os::raw::c_int,
+    pub to_parent_id: ::std::os::raw::c_int,
+    pub to_index: ::std::os::raw::c_int,
+}
+#[test]
+fn bindgen_test_layout_DP_LayerDiffEvent() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_LayerDiffEvent> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_LayerDiffEvent>(),
+        24usize,
+        concat!("Size of: ", stringify!(DP_LayerDiffEvent))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_LayerDiffEvent>(),
+        4usize,
+        concat!("Alignment of ", stringify!(DP_LayerDiffEvent))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).type_) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerDiffEvent),
+            "::",
+            stringify!(type_)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).layer_id) as usize - ptr as usize },
+        4usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerDiffEvent),
+            "::",
+            stringify!(layer_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).from_parent_id) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerDiffEvent),
+            "::",
+            stringify!(from_parent_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).from_index) as usize - ptr as usize },
+        12usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerDiffEvent),
+            "::",
+            stringify!(from_index)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).to_parent_id) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerDiffEvent),
+            "::",
+            stringify!(to_parent_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).to_index) as usize - ptr as usize },
+        20usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_LayerDiffEvent),
+            "::",
+            stringify!(to_index)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_LayerDiff {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_layer_diff_new() -> *mut DP_LayerDiff;
+}
+extern "C" {
+    pub fn DP_layer_diff_free(ld: *mut DP_LayerDiff);
+}
+extern "C" {
+    pub fn DP_layer_diff_update(
+        ld: *mut DP_LayerDiff,
+        cs: *mut DP_CanvasState,
+        prev: *mut DP_CanvasState,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_layer_diff_count(ld: *mut DP_LayerDiff) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_layer_diff_events(ld: *mut DP_LayerDiff) -> *const DP_LayerDiffEvent;
+}
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct DP_AclState {
@@ -6320,6 +7139,16 @@ pub type DP_PaintEngineMovePointerFn = ::std::option::Option<
         y: ::std::os::raw::c_int,
     ),
 >;
+pub type DP_PaintEngineChatFn = ::std::option::Option<
+    unsafe extern "C" fn(
+        user: *mut ::std::os::raw::c_void,
+        context_id: ::std::os::raw::c_uint,
+        tflags: ::std::os::raw::c_uint,
+        oflags: ::std::os::raw::c_uint,
+        text: *const ::std::os::raw::c_char,
+        length: usize,
+    ),
+>;
 pub type DP_PaintEngineDefaultLayerSetFn = ::std::option::Option<
     unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, layer_id: ::std::os::raw::c_int),
 >;
@@ -6364,6 +7193,23 @@ pub type DP_PaintEngineCursorMovedFn = ::std::option::Option<
 pub type DP_PaintEnginePushMessageFn = ::std::option::Option<
     unsafe extern "C" fn(user: *mut ::std::os::raw::c_void, msg: *mut DP_Message),
 >;
+pub type DP_PaintEngineAreaChangedFn = ::std::option::Option<
+    unsafe extern "C" fn(
+        user: *mut ::std::os::raw::c_void,
+        x: ::std::os::raw::c_int,
+        y: ::std::os::raw::c_int,
+        width: ::std::os::raw::c_int,
+        height: ::std::os::raw::c_int,
+    ),
+>;
+pub type DP_PaintEngineLayerDiffFn = ::std::option::Option<
+    unsafe extern "C" fn(
+        user: *mut ::std::os::raw::c_void,
+        lpl: *mut DP_LayerPropsList,
+        count: ::std::os::raw::c_int,
+        events: *const DP_LayerDiffEvent,
+    ),
+>;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct DP_PaintEngine {
@@ -6400,6 +7246,20 @@ extern "C" {
 extern "C" {
     pub fn DP_paint_engine_render_thread_count(pe: *mut DP_PaintEngine) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn DP_paint_engine_area_changed_set(
+        pe: *mut DP_PaintEngine,
+        fn_: DP_PaintEngineAreaChangedFn,
+        user: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn DP_paint_engine_layer_diff_set(
+        pe: *mut DP_PaintEngine,
+        fn_: DP_PaintEngineLayerDiffFn,
+        user: *mut ::std::os::raw::c_void,
+    );
+}
 extern "C" {
     pub fn DP_paint_engine_local_drawing_in_progress_set(
         pe: *mut DP_PaintEngine,
@@ -6596,6 +7456,7 @@ extern "C" {
         acls_changed: DP_PaintEngineAclsChangedFn,
         laser_trail: DP_PaintEngineLaserTrailFn,
         move_pointer: DP_PaintEngineMovePointerFn,
+        chat: DP_PaintEngineChatFn,
         user: *mut ::std::os::raw::c_void,
     ) -> ::std::os::raw::c_int;
 }
@@ -6936,63 +7797,181 @@ fn bindgen_test_layout_DP_TileCounts() {
 extern "C" {
     pub fn DP_tile_opaque_mask() -> *const u16;
 }
-extern "C" {
-    pub fn DP_tile_memory_usage() -> DP_MemoryPoolStatistics;
-}
-extern "C" {
-    pub fn DP_tile_new(context_id: ::std::os::raw::c_uint) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_from_pixel15(
-        context_id: ::std::os::raw::c_uint,
-        pixel: DP_Pixel15,
-    ) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_from_upixel15(
-        context_id: ::std::os::raw::c_uint,
-        pixel: DP_UPixel15,
-    ) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_from_pixels8(
-        context_id: ::std::os::raw::c_uint,
-        pixels: *const DP_Pixel8,
-    ) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_from_bgra(context_id: ::std::os::raw::c_uint, bgra: u32) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_from_compressed(
-        dc: *mut DP_DrawContext,
-        context_id: ::std::os::raw::c_uint,
-        image: *const ::std::os::raw::c_uchar,
-        image_size: usize,
-    ) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_checker(
-        context_id: ::std::os::raw::c_uint,
-        pixel1: DP_Pixel15,
-        pixel2: DP_Pixel15,
-    ) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_new_zebra(
-        context_id: ::std::os::raw::c_uint,
-        pixel1: DP_Pixel15,
-        pixel2: DP_Pixel15,
-    ) -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_censored_noinc() -> *mut DP_Tile;
-}
-extern "C" {
-    pub fn DP_tile_censored_inc() -> *mut DP_Tile;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_TileMemoryStatistics {
+    pub tile_size: usize,
+    pub tiles_used: usize,
+    pub tiles_cached: usize,
+    pub allocations: usize,
+    pub tiles_packed: usize,
+    pub packed_bytes: usize,
+    pub tiles_unpacked: usize,
 }
-extern "C" {
-    pub fn DP_tile_incref(tile: *mut DP_Tile) -> *mut DP_Tile;
+#[test]
+fn bindgen_test_layout_DP_TileMemoryStatistics() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_TileMemoryStatistics> =
+        ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_TileMemoryStatistics>(),
+        56usize,
+        concat!("Size of: ", stringify!(DP_TileMemoryStatistics))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_TileMemoryStatistics>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_TileMemoryStatistics))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).tile_size) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(tile_size)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).tiles_used) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(tiles_used)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).tiles_cached) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(tiles_cached)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).allocations) as usize - ptr as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(allocations)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).tiles_packed) as usize - ptr as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(tiles_packed)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).packed_bytes) as usize - ptr as usize },
+        40usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(packed_bytes)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).tiles_unpacked) as usize - ptr as usize },
+        48usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TileMemoryStatistics),
+            "::",
+            stringify!(tiles_unpacked)
+        )
+    );
+}
+extern "C" {
+    pub fn DP_tile_memory_usage() -> DP_TileMemoryStatistics;
+}
+extern "C" {
+    pub fn DP_tile_memory_cache_limit_set(bytes: usize);
+}
+extern "C" {
+    pub fn DP_tile_memory_cache_drain();
+}
+extern "C" {
+    pub fn DP_tile_new(context_id: ::std::os::raw::c_uint) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_from_pixel15(
+        context_id: ::std::os::raw::c_uint,
+        pixel: DP_Pixel15,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_from_upixel15(
+        context_id: ::std::os::raw::c_uint,
+        pixel: DP_UPixel15,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_from_pixels8(
+        context_id: ::std::os::raw::c_uint,
+        pixels: *const DP_Pixel8,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_from_bgra(context_id: ::std::os::raw::c_uint, bgra: u32) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_from_compressed(
+        dc: *mut DP_DrawContext,
+        context_id: ::std::os::raw::c_uint,
+        image: *const ::std::os::raw::c_uchar,
+        image_size: usize,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_checker(
+        context_id: ::std::os::raw::c_uint,
+        pixel1: DP_Pixel15,
+        pixel2: DP_Pixel15,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_new_zebra(
+        context_id: ::std::os::raw::c_uint,
+        pixel1: DP_Pixel15,
+        pixel2: DP_Pixel15,
+    ) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_censored_noinc() -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_censored_inc() -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_pack(tile: *mut DP_Tile) -> *mut DP_Tile;
+}
+extern "C" {
+    pub fn DP_tile_packed(tile: *mut DP_Tile) -> bool;
+}
+extern "C" {
+    pub fn DP_tile_packed_size(tile: *mut DP_Tile) -> usize;
+}
+extern "C" {
+    pub fn DP_tile_evict(tile: *mut DP_Tile);
+}
+extern "C" {
+    pub fn DP_tile_resident(tile: *mut DP_Tile) -> bool;
+}
+extern "C" {
+    pub fn DP_tile_incref(tile: *mut DP_Tile) -> *mut DP_Tile;
 }
 extern "C" {
     pub fn DP_tile_incref_nullable(tile_or_null: *mut DP_Tile) -> *mut DP_Tile;
@@ -7040,6 +8019,9 @@ extern "C" {
 extern "C" {
     pub fn DP_tile_same_pixel(tile_or_null: *mut DP_Tile, out_pixel: *mut DP_Pixel15) -> bool;
 }
+extern "C" {
+    pub fn DP_tile_checksum(tile_or_null: *mut DP_Tile) -> u64;
+}
 extern "C" {
     pub fn DP_tile_compress(
         tile: *mut DP_Tile,
@@ -7053,6 +8035,28 @@ extern "C" {
         user: *mut ::std::os::raw::c_void,
     ) -> usize;
 }
+extern "C" {
+    pub fn DP_tile_encoded_size(tile_or_null: *mut DP_Tile) -> usize;
+}
+extern "C" {
+    pub fn DP_tile_encode(
+        tile_or_null: *mut DP_Tile,
+        get_output_buffer: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: usize,
+                arg2: *mut ::std::os::raw::c_void,
+            ) -> *mut ::std::os::raw::c_uchar,
+        >,
+        user: *mut ::std::os::raw::c_void,
+    ) -> usize;
+}
+extern "C" {
+    pub fn DP_tile_new_from_encoded(
+        context_id: ::std::os::raw::c_uint,
+        data: *const ::std::os::raw::c_uchar,
+        size: usize,
+    ) -> *mut DP_Tile;
+}
 extern "C" {
     pub fn DP_tile_copy_to_image(
         tile_or_null: *mut DP_Tile,
@@ -7127,6 +8131,12 @@ extern "C" {
 extern "C" {
     pub fn DP_transient_tile_context_id(tt: *mut DP_Tile) -> ::std::os::raw::c_uint;
 }
+extern "C" {
+    pub fn DP_transient_tile_context_id_set(
+        tt: *mut DP_TransientTile,
+        context_id: ::std::os::raw::c_uint,
+    );
+}
 extern "C" {
     pub fn DP_transient_tile_pixels(tt: *mut DP_TransientTile) -> *mut DP_Pixel15;
 }
@@ -7827,6 +8837,77 @@ extern "C" {
         user: *mut ::std::os::raw::c_void,
     ) -> bool;
 }
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_BinaryReader {
+    _unused: [u8; 0],
+}
+pub const DP_BINARY_READER_SUCCESS: DP_BinaryReaderResult = 0;
+pub const DP_BINARY_READER_INPUT_END: DP_BinaryReaderResult = 1;
+pub const DP_BINARY_READER_ERROR_INPUT: DP_BinaryReaderResult = 2;
+pub const DP_BINARY_READER_ERROR_PARSE: DP_BinaryReaderResult = 3;
+pub type DP_BinaryReaderResult = ::std::os::raw::c_uint;
+extern "C" {
+    pub fn DP_binary_reader_new(
+        input: *mut DP_Input,
+        flags: ::std::os::raw::c_uint,
+    ) -> *mut DP_BinaryReader;
+}
+extern "C" {
+    pub fn DP_binary_reader_free(reader: *mut DP_BinaryReader);
+}
+extern "C" {
+    pub fn DP_binary_reader_header(reader: *mut DP_BinaryReader) -> *mut JSON_Value;
+}
+extern "C" {
+    pub fn DP_binary_reader_body_offset(reader: *mut DP_BinaryReader) -> usize;
+}
+extern "C" {
+    pub fn DP_binary_reader_tell(reader: *mut DP_BinaryReader) -> usize;
+}
+extern "C" {
+    pub fn DP_binary_reader_seek(reader: *mut DP_BinaryReader, offset: usize) -> bool;
+}
+extern "C" {
+    pub fn DP_binary_reader_progress(reader: *mut DP_BinaryReader) -> f64;
+}
+extern "C" {
+    pub fn DP_binary_reader_read_message(
+        reader: *mut DP_BinaryReader,
+        decode_opaque: bool,
+        out_msg: *mut *mut DP_Message,
+    ) -> DP_BinaryReaderResult;
+}
+extern "C" {
+    pub fn DP_binary_reader_skip_message(
+        reader: *mut DP_BinaryReader,
+        out_type: *mut u8,
+        out_context_id: *mut u8,
+    ) -> ::std::os::raw::c_int;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_BinaryWriter {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_binary_writer_new(output: *mut DP_Output) -> *mut DP_BinaryWriter;
+}
+extern "C" {
+    pub fn DP_binary_writer_free(writer: *mut DP_BinaryWriter);
+}
+extern "C" {
+    pub fn DP_binary_writer_write_header(
+        writer: *mut DP_BinaryWriter,
+        header: *mut JSON_Object,
+    ) -> bool;
+}
+extern "C" {
+    pub fn DP_binary_writer_write_message(
+        writer: *mut DP_BinaryWriter,
+        msg: *mut DP_Message,
+    ) -> usize;
+}
 pub const DP_BLEND_MODE_ERASE: DP_BlendMode = 0;
 pub const DP_BLEND_MODE_NORMAL: DP_BlendMode = 1;
 pub const DP_BLEND_MODE_MULTIPLY: DP_BlendMode = 2;
@@ -7898,6 +8979,11 @@ extern "C" {
 extern "C" {
     pub fn DP_blend_mode_preserves_alpha(blend_mode: ::std::os::raw::c_int) -> bool;
 }
+extern "C" {
+    pub fn DP_blend_mode_to_alpha_preserving(
+        blend_mode: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn DP_blend_mode_by_svg_name(
         svg_name: *const ::std::os::raw::c_char,
@@ -8032,6 +9118,9 @@ pub const DP_MSG_DRAW_DABS_CLASSIC: DP_MessageType = 148;
 pub const DP_MSG_DRAW_DABS_PIXEL: DP_MessageType = 149;
 pub const DP_MSG_DRAW_DABS_PIXEL_SQUARE: DP_MessageType = 150;
 pub const DP_MSG_DRAW_DABS_MYPAINT: DP_MessageType = 151;
+pub const DP_MSG_STAMP_MASK: DP_MessageType = 152;
+pub const DP_MSG_DRAW_DABS_STAMP: DP_MessageType = 153;
+pub const DP_MSG_DRAW_DABS_SOFT: DP_MessageType = 154;
 pub const DP_MSG_MOVE_RECT: DP_MessageType = 160;
 pub const DP_MSG_SET_METADATA_INT: DP_MessageType = 161;
 pub const DP_MSG_LAYER_TREE_CREATE: DP_MessageType = 162;
@@ -8046,6 +9135,10 @@ pub const DP_MSG_KEY_FRAME_SET: DP_MessageType = 170;
 pub const DP_MSG_KEY_FRAME_RETITLE: DP_MessageType = 171;
 pub const DP_MSG_KEY_FRAME_LAYER_ATTRIBUTES: DP_MessageType = 172;
 pub const DP_MSG_KEY_FRAME_DELETE: DP_MessageType = 173;
+pub const DP_MSG_FILTER_REGION: DP_MessageType = 174;
+pub const DP_MSG_FILL_GRADIENT: DP_MessageType = 175;
+pub const DP_MSG_ANNOTATION_ORDER: DP_MessageType = 176;
+pub const DP_MSG_LAYER_METADATA: DP_MessageType = 177;
 pub const DP_MSG_UNDO: DP_MessageType = 255;
 pub const DP_MSG_TYPE_COUNT: DP_MessageType = 256;
 pub type DP_MessageType = ::std::os::raw::c_uint;
@@ -10192,98 +11285,388 @@ extern "C" {
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-pub struct DP_MsgMoveRect {
+pub struct DP_MsgStampMask {
     _unused: [u8; 0],
 }
 extern "C" {
-    pub fn DP_msg_move_rect_new(
+    pub fn DP_msg_stamp_mask_new(
         context_id: ::std::os::raw::c_uint,
-        layer: u16,
-        source: u16,
-        sx: i32,
-        sy: i32,
-        tx: i32,
-        ty: i32,
-        w: i32,
-        h: i32,
-        set_mask: ::std::option::Option<
+        mask: u32,
+        size: u16,
+        set_image: ::std::option::Option<
             unsafe extern "C" fn(
                 arg1: usize,
                 arg2: *mut ::std::os::raw::c_uchar,
                 arg3: *mut ::std::os::raw::c_void,
             ),
         >,
-        mask_size: usize,
-        mask_user: *mut ::std::os::raw::c_void,
+        image_size: usize,
+        image_user: *mut ::std::os::raw::c_void,
     ) -> *mut DP_Message;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_deserialize(
+    pub fn DP_msg_stamp_mask_deserialize(
         context_id: ::std::os::raw::c_uint,
         buffer: *const ::std::os::raw::c_uchar,
         length: usize,
     ) -> *mut DP_Message;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_parse(
+    pub fn DP_msg_stamp_mask_parse(
         context_id: ::std::os::raw::c_uint,
         reader: *mut DP_TextReader,
     ) -> *mut DP_Message;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_cast(msg: *mut DP_Message) -> *mut DP_MsgMoveRect;
+    pub fn DP_msg_stamp_mask_cast(msg: *mut DP_Message) -> *mut DP_MsgStampMask;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_layer(mmr: *const DP_MsgMoveRect) -> u16;
+    pub fn DP_msg_stamp_mask_mask(msm: *const DP_MsgStampMask) -> u32;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_source(mmr: *const DP_MsgMoveRect) -> u16;
+    pub fn DP_msg_stamp_mask_size(msm: *const DP_MsgStampMask) -> u16;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_sx(mmr: *const DP_MsgMoveRect) -> i32;
+    pub fn DP_msg_stamp_mask_image(
+        msm: *const DP_MsgStampMask,
+        out_size: *mut usize,
+    ) -> *const ::std::os::raw::c_uchar;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_sy(mmr: *const DP_MsgMoveRect) -> i32;
+    pub fn DP_msg_stamp_mask_image_size(msm: *const DP_MsgStampMask) -> usize;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_StampDab {
+    _unused: [u8; 0],
 }
 extern "C" {
-    pub fn DP_msg_move_rect_tx(mmr: *const DP_MsgMoveRect) -> i32;
+    pub fn DP_stamp_dab_init(
+        sds: *mut DP_StampDab,
+        i: ::std::os::raw::c_int,
+        x: i8,
+        y: i8,
+        size: u16,
+        angle: u8,
+        opacity: u8,
+    );
 }
 extern "C" {
-    pub fn DP_msg_move_rect_ty(mmr: *const DP_MsgMoveRect) -> i32;
+    pub fn DP_stamp_dab_x(sd: *const DP_StampDab) -> i8;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_w(mmr: *const DP_MsgMoveRect) -> i32;
+    pub fn DP_stamp_dab_y(sd: *const DP_StampDab) -> i8;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_h(mmr: *const DP_MsgMoveRect) -> i32;
+    pub fn DP_stamp_dab_size(sd: *const DP_StampDab) -> u16;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_mask(
-        mmr: *const DP_MsgMoveRect,
-        out_size: *mut usize,
-    ) -> *const ::std::os::raw::c_uchar;
+    pub fn DP_stamp_dab_angle(sd: *const DP_StampDab) -> u8;
 }
 extern "C" {
-    pub fn DP_msg_move_rect_mask_size(mmr: *const DP_MsgMoveRect) -> usize;
+    pub fn DP_stamp_dab_opacity(sd: *const DP_StampDab) -> u8;
 }
 extern "C" {
-    pub fn DP_msg_set_metadata_int_field_variant_name(
-        value: ::std::os::raw::c_uint,
-    ) -> *const ::std::os::raw::c_char;
+    pub fn DP_stamp_dab_at(
+        sd: *const DP_StampDab,
+        i: ::std::os::raw::c_int,
+    ) -> *const DP_StampDab;
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-pub struct DP_MsgSetMetadataInt {
+pub struct DP_MsgDrawDabsStamp {
     _unused: [u8; 0],
 }
 extern "C" {
-    pub fn DP_msg_set_metadata_int_new(
+    pub fn DP_msg_draw_dabs_stamp_new(
         context_id: ::std::os::raw::c_uint,
-        field: u8,
-        value: i32,
-    ) -> *mut DP_Message;
-}
-extern "C" {
+        layer: u16,
+        x: i32,
+        y: i32,
+        color: u32,
+        mode: u8,
+        mask: u32,
+        grain: u32,
+        grainscale: u16,
+        grainstrength: u8,
+        set_dabs: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: ::std::os::raw::c_int,
+                arg2: *mut DP_StampDab,
+                arg3: *mut ::std::os::raw::c_void,
+            ),
+        >,
+        dabs_count: ::std::os::raw::c_int,
+        dabs_user: *mut ::std::os::raw::c_void,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_cast(msg: *mut DP_Message) -> *mut DP_MsgDrawDabsStamp;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_layer(mdds: *const DP_MsgDrawDabsStamp) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_x(mdds: *const DP_MsgDrawDabsStamp) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_y(mdds: *const DP_MsgDrawDabsStamp) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_color(mdds: *const DP_MsgDrawDabsStamp) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_mode(mdds: *const DP_MsgDrawDabsStamp) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_mask(mdds: *const DP_MsgDrawDabsStamp) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_grain(mdds: *const DP_MsgDrawDabsStamp) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_grainscale(mdds: *const DP_MsgDrawDabsStamp) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_grainstrength(mdds: *const DP_MsgDrawDabsStamp) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_dabs(
+        mdds: *const DP_MsgDrawDabsStamp,
+        out_count: *mut ::std::os::raw::c_int,
+    ) -> *const DP_StampDab;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_dabs_count(
+        mdds: *const DP_MsgDrawDabsStamp,
+    ) -> ::std::os::raw::c_int;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_SoftDab {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_soft_dab_init(
+        sds: *mut DP_SoftDab,
+        i: ::std::os::raw::c_int,
+        x: i8,
+        y: i8,
+        size: u16,
+        hardness: u8,
+        opacity: u8,
+    );
+}
+extern "C" {
+    pub fn DP_soft_dab_x(sd: *const DP_SoftDab) -> i8;
+}
+extern "C" {
+    pub fn DP_soft_dab_y(sd: *const DP_SoftDab) -> i8;
+}
+extern "C" {
+    pub fn DP_soft_dab_size(sd: *const DP_SoftDab) -> u16;
+}
+extern "C" {
+    pub fn DP_soft_dab_hardness(sd: *const DP_SoftDab) -> u8;
+}
+extern "C" {
+    pub fn DP_soft_dab_opacity(sd: *const DP_SoftDab) -> u8;
+}
+extern "C" {
+    pub fn DP_soft_dab_at(
+        sd: *const DP_SoftDab,
+        i: ::std::os::raw::c_int,
+    ) -> *const DP_SoftDab;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgDrawDabsSoft {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_new(
+        context_id: ::std::os::raw::c_uint,
+        layer: u16,
+        x: i32,
+        y: i32,
+        color: u32,
+        mode: u8,
+        falloff: u8,
+        grain: u32,
+        grainscale: u16,
+        grainstrength: u8,
+        set_dabs: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: ::std::os::raw::c_int,
+                arg2: *mut DP_SoftDab,
+                arg3: *mut ::std::os::raw::c_void,
+            ),
+        >,
+        dabs_count: ::std::os::raw::c_int,
+        dabs_user: *mut ::std::os::raw::c_void,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_cast(msg: *mut DP_Message) -> *mut DP_MsgDrawDabsSoft;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_layer(mdds: *const DP_MsgDrawDabsSoft) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_x(mdds: *const DP_MsgDrawDabsSoft) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_y(mdds: *const DP_MsgDrawDabsSoft) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_color(mdds: *const DP_MsgDrawDabsSoft) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_mode(mdds: *const DP_MsgDrawDabsSoft) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_falloff(mdds: *const DP_MsgDrawDabsSoft) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_grain(mdds: *const DP_MsgDrawDabsSoft) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_grainscale(mdds: *const DP_MsgDrawDabsSoft) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_grainstrength(mdds: *const DP_MsgDrawDabsSoft) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_dabs(
+        mdds: *const DP_MsgDrawDabsSoft,
+        out_count: *mut ::std::os::raw::c_int,
+    ) -> *const DP_SoftDab;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_dabs_count(
+        mdds: *const DP_MsgDrawDabsSoft,
+    ) -> ::std::os::raw::c_int;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgMoveRect {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_move_rect_new(
+        context_id: ::std::os::raw::c_uint,
+        layer: u16,
+        source: u16,
+        sx: i32,
+        sy: i32,
+        tx: i32,
+        ty: i32,
+        w: i32,
+        h: i32,
+        set_mask: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: usize,
+                arg2: *mut ::std::os::raw::c_uchar,
+                arg3: *mut ::std::os::raw::c_void,
+            ),
+        >,
+        mask_size: usize,
+        mask_user: *mut ::std::os::raw::c_void,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_cast(msg: *mut DP_Message) -> *mut DP_MsgMoveRect;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_layer(mmr: *const DP_MsgMoveRect) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_source(mmr: *const DP_MsgMoveRect) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_sx(mmr: *const DP_MsgMoveRect) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_sy(mmr: *const DP_MsgMoveRect) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_tx(mmr: *const DP_MsgMoveRect) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_ty(mmr: *const DP_MsgMoveRect) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_w(mmr: *const DP_MsgMoveRect) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_h(mmr: *const DP_MsgMoveRect) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_mask(
+        mmr: *const DP_MsgMoveRect,
+        out_size: *mut usize,
+    ) -> *const ::std::os::raw::c_uchar;
+}
+extern "C" {
+    pub fn DP_msg_move_rect_mask_size(mmr: *const DP_MsgMoveRect) -> usize;
+}
+extern "C" {
+    pub fn DP_msg_set_metadata_int_field_variant_name(
+        value: ::std::os::raw::c_uint,
+    ) -> *const ::std::os::raw::c_char;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgSetMetadataInt {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_set_metadata_int_new(
+        context_id: ::std::os::raw::c_uint,
+        field: u8,
+        value: i32,
+    ) -> *mut DP_Message;
+}
+extern "C" {
     pub fn DP_msg_set_metadata_int_deserialize(
         context_id: ::std::os::raw::c_uint,
         buffer: *const ::std::os::raw::c_uchar,
@@ -10907,49 +12290,344 @@ extern "C" {
 extern "C" {
     pub fn DP_msg_key_frame_delete_move_frame_index(mkfd: *const DP_MsgKeyFrameDelete) -> u16;
 }
+extern "C" {
+    pub fn DP_msg_filter_region_filter_variant_name(
+        value: ::std::os::raw::c_uint,
+    ) -> *const ::std::os::raw::c_char;
+}
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-pub struct DP_MsgUndo {
+pub struct DP_MsgFilterRegion {
     _unused: [u8; 0],
 }
 extern "C" {
-    pub fn DP_msg_undo_new(
+    pub fn DP_msg_filter_region_new(
         context_id: ::std::os::raw::c_uint,
-        override_user: u8,
-        redo: bool,
+        layer: u16,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        filter: u8,
+        param1: i32,
+        param2: i32,
+        param3: i32,
+        param4: i32,
     ) -> *mut DP_Message;
 }
 extern "C" {
-    pub fn DP_msg_undo_deserialize(
+    pub fn DP_msg_filter_region_deserialize(
         context_id: ::std::os::raw::c_uint,
         buffer: *const ::std::os::raw::c_uchar,
         length: usize,
     ) -> *mut DP_Message;
 }
 extern "C" {
-    pub fn DP_msg_undo_parse(
+    pub fn DP_msg_filter_region_parse(
         context_id: ::std::os::raw::c_uint,
         reader: *mut DP_TextReader,
     ) -> *mut DP_Message;
 }
 extern "C" {
-    pub fn DP_msg_undo_cast(msg: *mut DP_Message) -> *mut DP_MsgUndo;
+    pub fn DP_msg_filter_region_cast(msg: *mut DP_Message) -> *mut DP_MsgFilterRegion;
 }
 extern "C" {
-    pub fn DP_msg_undo_override_user(mu: *const DP_MsgUndo) -> u8;
+    pub fn DP_msg_filter_region_layer(mfr: *const DP_MsgFilterRegion) -> u16;
 }
 extern "C" {
-    pub fn DP_msg_undo_redo(mu: *const DP_MsgUndo) -> bool;
+    pub fn DP_msg_filter_region_x(mfr: *const DP_MsgFilterRegion) -> u32;
 }
-pub type DP_GetMessageBufferFn = ::std::option::Option<
-    unsafe extern "C" fn(
-        user: *mut ::std::os::raw::c_void,
-        length: usize,
-    ) -> *mut ::std::os::raw::c_uchar,
->;
 extern "C" {
-    pub fn DP_message_new(
-        type_: DP_MessageType,
+    pub fn DP_msg_filter_region_y(mfr: *const DP_MsgFilterRegion) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_w(mfr: *const DP_MsgFilterRegion) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_h(mfr: *const DP_MsgFilterRegion) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_filter(mfr: *const DP_MsgFilterRegion) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_param1(mfr: *const DP_MsgFilterRegion) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_param2(mfr: *const DP_MsgFilterRegion) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_param3(mfr: *const DP_MsgFilterRegion) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_filter_region_param4(mfr: *const DP_MsgFilterRegion) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_shape_variant_name(
+        value: ::std::os::raw::c_uint,
+    ) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_flags_flag_name(
+        value: ::std::os::raw::c_uint,
+    ) -> *const ::std::os::raw::c_char;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_FillGradientStop {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_fill_gradient_stop_init(
+        fgss: *mut DP_FillGradientStop,
+        i: ::std::os::raw::c_int,
+        position: u16,
+        color: u32,
+    );
+}
+extern "C" {
+    pub fn DP_fill_gradient_stop_position(fgs: *const DP_FillGradientStop) -> u16;
+}
+extern "C" {
+    pub fn DP_fill_gradient_stop_color(fgs: *const DP_FillGradientStop) -> u32;
+}
+extern "C" {
+    pub fn DP_fill_gradient_stop_at(
+        fgs: *const DP_FillGradientStop,
+        i: ::std::os::raw::c_int,
+    ) -> *const DP_FillGradientStop;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgFillGradient {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_new(
+        context_id: ::std::os::raw::c_uint,
+        layer: u16,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        mode: u8,
+        shape: u8,
+        flags: u8,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        radius: i32,
+        set_stops: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: ::std::os::raw::c_int,
+                arg2: *mut DP_FillGradientStop,
+                arg3: *mut ::std::os::raw::c_void,
+            ),
+        >,
+        stops_count: ::std::os::raw::c_int,
+        stops_user: *mut ::std::os::raw::c_void,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_cast(msg: *mut DP_Message) -> *mut DP_MsgFillGradient;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_layer(mfg: *const DP_MsgFillGradient) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_x(mfg: *const DP_MsgFillGradient) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_y(mfg: *const DP_MsgFillGradient) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_w(mfg: *const DP_MsgFillGradient) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_h(mfg: *const DP_MsgFillGradient) -> u32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_mode(mfg: *const DP_MsgFillGradient) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_shape(mfg: *const DP_MsgFillGradient) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_flags(mfg: *const DP_MsgFillGradient) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_x1(mfg: *const DP_MsgFillGradient) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_y1(mfg: *const DP_MsgFillGradient) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_x2(mfg: *const DP_MsgFillGradient) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_y2(mfg: *const DP_MsgFillGradient) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_radius(mfg: *const DP_MsgFillGradient) -> i32;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_stops(
+        mfg: *const DP_MsgFillGradient,
+        out_count: *mut ::std::os::raw::c_int,
+    ) -> *const DP_FillGradientStop;
+}
+extern "C" {
+    pub fn DP_msg_fill_gradient_stops_count(
+        mfg: *const DP_MsgFillGradient,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_direction_variant_name(
+        value: ::std::os::raw::c_uint,
+    ) -> *const ::std::os::raw::c_char;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgAnnotationOrder {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_new(
+        context_id: ::std::os::raw::c_uint,
+        id: u16,
+        direction: u8,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_cast(msg: *mut DP_Message) -> *mut DP_MsgAnnotationOrder;
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_id(mao: *const DP_MsgAnnotationOrder) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_annotation_order_direction(mao: *const DP_MsgAnnotationOrder) -> u8;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgLayerMetadata {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_new(
+        context_id: ::std::os::raw::c_uint,
+        id: u16,
+        color_tag: u8,
+        set_metadata: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: usize,
+                arg2: *mut ::std::os::raw::c_uchar,
+                arg3: *mut ::std::os::raw::c_void,
+            ),
+        >,
+        metadata_size: usize,
+        metadata_user: *mut ::std::os::raw::c_void,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_cast(msg: *mut DP_Message) -> *mut DP_MsgLayerMetadata;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_id(mlm: *const DP_MsgLayerMetadata) -> u16;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_color_tag(mlm: *const DP_MsgLayerMetadata) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_metadata(
+        mlm: *const DP_MsgLayerMetadata,
+        out_size: *mut usize,
+    ) -> *const ::std::os::raw::c_uchar;
+}
+extern "C" {
+    pub fn DP_msg_layer_metadata_metadata_size(mlm: *const DP_MsgLayerMetadata) -> usize;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_MsgUndo {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn DP_msg_undo_new(
+        context_id: ::std::os::raw::c_uint,
+        override_user: u8,
+        redo: bool,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_undo_deserialize(
+        context_id: ::std::os::raw::c_uint,
+        buffer: *const ::std::os::raw::c_uchar,
+        length: usize,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_undo_parse(
+        context_id: ::std::os::raw::c_uint,
+        reader: *mut DP_TextReader,
+    ) -> *mut DP_Message;
+}
+extern "C" {
+    pub fn DP_msg_undo_cast(msg: *mut DP_Message) -> *mut DP_MsgUndo;
+}
+extern "C" {
+    pub fn DP_msg_undo_override_user(mu: *const DP_MsgUndo) -> u8;
+}
+extern "C" {
+    pub fn DP_msg_undo_redo(mu: *const DP_MsgUndo) -> bool;
+}
+pub type DP_GetMessageBufferFn = ::std::option::Option<
+    unsafe extern "C" fn(
+        user: *mut ::std::os::raw::c_void,
+        length: usize,
+    ) -> *mut ::std::os::raw::c_uchar,
+>;
+extern "C" {
+    pub fn DP_message_new(
+        type_: DP_MessageType,
         context_id: ::std::os::raw::c_uint,
         methods: *const DP_MessageMethods,
         internal_size: usize,
@@ -11025,6 +12703,9 @@ extern "C" {
 extern "C" {
     pub fn DP_message_equals(msg: *mut DP_Message, other: *mut DP_Message) -> bool;
 }
+extern "C" {
+    pub fn DP_message_bypasses_recording(msg: *mut DP_Message) -> bool;
+}
 extern "C" {
     pub fn DP_message_deserialize_length(
         buf: *const ::std::os::raw::c_uchar,
@@ -11052,6 +12733,573 @@ extern "C" {
 extern "C" {
     pub fn DP_msg_draw_dabs_pixel_indirect(mddp: *mut DP_MsgDrawDabsPixel) -> bool;
 }
+extern "C" {
+    pub fn DP_msg_draw_dabs_stamp_indirect(mdds: *mut DP_MsgDrawDabsStamp) -> bool;
+}
+extern "C" {
+    pub fn DP_msg_draw_dabs_soft_indirect(mdds: *mut DP_MsgDrawDabsSoft) -> bool;
+}
+pub const DP_RECORDING_STATS_IDLE_MS: u32 = 5000;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_RecordingStats {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_RecordingStatsTotals {
+    pub message_count: ::std::os::raw::c_longlong,
+    pub unparseable_count: ::std::os::raw::c_longlong,
+    pub duration_ms: ::std::os::raw::c_longlong,
+    pub active_ms: ::std::os::raw::c_longlong,
+    pub width: ::std::os::raw::c_int,
+    pub height: ::std::os::raw::c_int,
+    pub truncated: bool,
+}
+#[test]
+fn bindgen_test_layout_DP_RecordingStatsTotals() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_RecordingStatsTotals> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_RecordingStatsTotals>(),
+        48usize,
+        concat!("Size of: ", stringify!(DP_RecordingStatsTotals))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_RecordingStatsTotals>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_RecordingStatsTotals))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).message_count) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(message_count)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).unparseable_count) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(unparseable_count)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).duration_ms) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(duration_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).active_ms) as usize - ptr as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(active_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).width) as usize - ptr as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(width)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).height) as usize - ptr as usize },
+        36usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(height)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).truncated) as usize - ptr as usize },
+        40usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsTotals),
+            "::",
+            stringify!(truncated)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_RecordingStatsUser {
+    pub context_id: ::std::os::raw::c_uint,
+    pub message_count: ::std::os::raw::c_longlong,
+    pub message_counts: [::std::os::raw::c_longlong; 256usize],
+    pub dab_count: ::std::os::raw::c_longlong,
+    pub undo_count: ::std::os::raw::c_longlong,
+    pub redo_count: ::std::os::raw::c_longlong,
+    pub active_ms: ::std::os::raw::c_longlong,
+    pub first_ms: ::std::os::raw::c_longlong,
+    pub last_ms: ::std::os::raw::c_longlong,
+}
+#[test]
+fn bindgen_test_layout_DP_RecordingStatsUser() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_RecordingStatsUser> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_RecordingStatsUser>(),
+        2112usize,
+        concat!("Size of: ", stringify!(DP_RecordingStatsUser))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_RecordingStatsUser>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_RecordingStatsUser))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).context_id) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(context_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).message_count) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(message_count)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).message_counts) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(message_counts)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).dab_count) as usize - ptr as usize },
+        2064usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(dab_count)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).undo_count) as usize - ptr as usize },
+        2072usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(undo_count)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).redo_count) as usize - ptr as usize },
+        2080usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(redo_count)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).active_ms) as usize - ptr as usize },
+        2088usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(active_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).first_ms) as usize - ptr as usize },
+        2096usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(first_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).last_ms) as usize - ptr as usize },
+        2104usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsUser),
+            "::",
+            stringify!(last_ms)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_RecordingStatsResize {
+    pub message_index: ::std::os::raw::c_longlong,
+    pub elapsed_ms: ::std::os::raw::c_longlong,
+    pub context_id: ::std::os::raw::c_uint,
+    pub top: ::std::os::raw::c_int,
+    pub right: ::std::os::raw::c_int,
+    pub bottom: ::std::os::raw::c_int,
+    pub left: ::std::os::raw::c_int,
+    pub width: ::std::os::raw::c_int,
+    pub height: ::std::os::raw::c_int,
+}
+#[test]
+fn bindgen_test_layout_DP_RecordingStatsResize() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_RecordingStatsResize> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_RecordingStatsResize>(),
+        48usize,
+        concat!("Size of: ", stringify!(DP_RecordingStatsResize))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_RecordingStatsResize>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_RecordingStatsResize))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).message_index) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(message_index)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).elapsed_ms) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(elapsed_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).context_id) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(context_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).top) as usize - ptr as usize },
+        20usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(top)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).right) as usize - ptr as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(right)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).bottom) as usize - ptr as usize },
+        28usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(bottom)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).left) as usize - ptr as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(left)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).width) as usize - ptr as usize },
+        36usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(width)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).height) as usize - ptr as usize },
+        40usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsResize),
+            "::",
+            stringify!(height)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_RecordingStatsLayer {
+    pub message_index: ::std::os::raw::c_longlong,
+    pub elapsed_ms: ::std::os::raw::c_longlong,
+    pub context_id: ::std::os::raw::c_uint,
+    pub layer_id: ::std::os::raw::c_int,
+    pub group: bool,
+    pub title: *mut ::std::os::raw::c_char,
+}
+#[test]
+fn bindgen_test_layout_DP_RecordingStatsLayer() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_RecordingStatsLayer> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_RecordingStatsLayer>(),
+        40usize,
+        concat!("Size of: ", stringify!(DP_RecordingStatsLayer))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_RecordingStatsLayer>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_RecordingStatsLayer))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).message_index) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsLayer),
+            "::",
+            stringify!(message_index)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).elapsed_ms) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsLayer),
+            "::",
+            stringify!(elapsed_ms)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).context_id) as usize - ptr as usize },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsLayer),
+            "::",
+            stringify!(context_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).layer_id) as usize - ptr as usize },
+        20usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsLayer),
+            "::",
+            stringify!(layer_id)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).group) as usize - ptr as usize },
+        24usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsLayer),
+            "::",
+            stringify!(group)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).title) as usize - ptr as usize },
+        32usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_RecordingStatsLayer),
+            "::",
+            stringify!(title)
+        )
+    );
+}
+extern "C" {
+    pub fn DP_recording_stats_new() -> *mut DP_RecordingStats;
+}
+extern "C" {
+    pub fn DP_recording_stats_free(rs: *mut DP_RecordingStats);
+}
+extern "C" {
+    pub fn DP_recording_stats_handle(rs: *mut DP_RecordingStats, msg: *mut DP_Message);
+}
+extern "C" {
+    pub fn DP_recording_stats_handle_unparseable(rs: *mut DP_RecordingStats);
+}
+extern "C" {
+    pub fn DP_recording_stats_truncated_set(rs: *mut DP_RecordingStats);
+}
+extern "C" {
+    pub fn DP_recording_stats_analyze(br: *mut DP_BinaryReader) -> *mut DP_RecordingStats;
+}
+extern "C" {
+    pub fn DP_recording_stats_totals(rs: *mut DP_RecordingStats) -> *const DP_RecordingStatsTotals;
+}
+extern "C" {
+    pub fn DP_recording_stats_user(
+        rs: *mut DP_RecordingStats,
+        context_id: ::std::os::raw::c_uint,
+    ) -> *const DP_RecordingStatsUser;
+}
+extern "C" {
+    pub fn DP_recording_stats_resize_count(rs: *mut DP_RecordingStats) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_recording_stats_resize_at(
+        rs: *mut DP_RecordingStats,
+        index: ::std::os::raw::c_int,
+    ) -> *const DP_RecordingStatsResize;
+}
+extern "C" {
+    pub fn DP_recording_stats_layer_count(rs: *mut DP_RecordingStats) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn DP_recording_stats_layer_at(
+        rs: *mut DP_RecordingStats,
+        index: ::std::os::raw::c_int,
+    ) -> *const DP_RecordingStatsLayer;
+}
+extern "C" {
+    pub fn DP_recording_stats_to_json(rs: *mut DP_RecordingStats) -> *mut JSON_Value;
+}
+pub const DP_TEXT_READER_SUCCESS: DP_TextReaderResult = 0;
+pub const DP_TEXT_READER_HEADER_END: DP_TextReaderResult = 1;
+pub const DP_TEXT_READER_INPUT_END: DP_TextReaderResult = 2;
+pub const DP_TEXT_READER_ERROR_INPUT: DP_TextReaderResult = 3;
+pub const DP_TEXT_READER_ERROR_PARSE: DP_TextReaderResult = 4;
+pub type DP_TextReaderResult = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct DP_TextReaderPosition {
+    pub line: usize,
+    pub column: usize,
+}
+#[test]
+fn bindgen_test_layout_DP_TextReaderPosition() {
+    const UNINIT: ::std::mem::MaybeUninit<DP_TextReaderPosition> =
+        ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
+    assert_eq!(
+        ::std::mem::size_of::<DP_TextReaderPosition>(),
+        16usize,
+        concat!("Size of: ", stringify!(DP_TextReaderPosition))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<DP_TextReaderPosition>(),
+        8usize,
+        concat!("Alignment of ", stringify!(DP_TextReaderPosition))
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).line) as usize - ptr as usize },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TextReaderPosition),
+            "::",
+            stringify!(line)
+        )
+    );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).column) as usize - ptr as usize },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(DP_TextReaderPosition),
+            "::",
+            stringify!(column)
+        )
+    );
+}
+extern "C" {
+    pub fn DP_text_reader_new(input: *mut DP_Input) -> *mut DP_TextReader;
+}
+extern "C" {
+    pub fn DP_text_reader_free(reader: *mut DP_TextReader);
+}
+extern "C" {
+    pub fn DP_text_reader_line(reader: *mut DP_TextReader) -> usize;
+}
+extern "C" {
+    pub fn DP_text_reader_error_position(reader: *mut DP_TextReader) -> DP_TextReaderPosition;
+}
+extern "C" {
+    pub fn DP_text_reader_read_header_field(
+        reader: *mut DP_TextReader,
+        out_key: *mut *const ::std::os::raw::c_char,
+        out_value: *mut *const ::std::os::raw::c_char,
+    ) -> DP_TextReaderResult;
+}
+extern "C" {
+    pub fn DP_text_reader_read_message(
+        reader: *mut DP_TextReader,
+        out_msg: *mut *mut DP_Message,
+    ) -> DP_TextReaderResult;
+}
+extern "C" {
+    pub fn DP_text_writer_new(output: *mut DP_Output) -> *mut DP_TextWriter;
+}
+extern "C" {
+    pub fn DP_text_writer_free(writer: *mut DP_TextWriter);
+}
+extern "C" {
+    pub fn DP_text_writer_write_header(writer: *mut DP_TextWriter, header: *mut JSON_Object)
+        -> bool;
+}
+extern "C" {
+    pub fn DP_text_writer_raw_write(
+        writer: *mut DP_TextWriter,
+        buffer: *const ::std::os::raw::c_char,
+        size: usize,
+    ) -> bool;
+}
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct json_array_t {
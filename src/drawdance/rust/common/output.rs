@@ -11,6 +11,7 @@ pub struct Output {
 }
 
 impl Output {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn new_from_path(path: *const c_char) -> Result<Output> {
         let output = unsafe { DP_file_output_new_from_path(path) };
         if output.is_null() {
@@ -3,30 +3,29 @@ use super::{
     types::Persistable, Attached, AttachedDocumentMetadata, AttachedLayerList,
     AttachedLayerPropsList, AttachedTile, AttachedTransientDocumentMetadata, BaseTile, CArc,
     Detached, DetachedTransientLayerList, DetachedTransientLayerPropsList,
-    DetachedTransientTimeline, DocumentMetadata, LayerList, LayerPropsList, Tile,
-    TransientDocumentMetadata,
+    DetachedTransientTimeline, DocumentMetadata, FlattenOptions, Image, LayerList, LayerPropsList,
+    MemoryReport, Tile, TransientDocumentMetadata,
 };
 use crate::{
-    dp_error_anyhow, DP_CanvasState, DP_DrawContext, DP_TransientCanvasState,
+    dp_error_anyhow, DP_CanvasState, DP_DrawContext, DP_Pixel8, DP_TransientCanvasState,
     DP_canvas_state_background_opaque, DP_canvas_state_background_tile_noinc,
-    DP_canvas_state_decref, DP_canvas_state_height, DP_canvas_state_incref,
-    DP_canvas_state_layer_props_noinc, DP_canvas_state_layers_noinc,
-    DP_canvas_state_metadata_noinc, DP_canvas_state_to_flat_separated_urgba8,
-    DP_canvas_state_transient, DP_canvas_state_width, DP_tile_incref,
-    DP_transient_canvas_state_background_tile_set_noinc, DP_transient_canvas_state_decref,
-    DP_transient_canvas_state_height_set, DP_transient_canvas_state_incref,
-    DP_transient_canvas_state_layer_routes_reindex, DP_transient_canvas_state_new,
-    DP_transient_canvas_state_persist, DP_transient_canvas_state_transient_layer_props_set_noinc,
+    DP_canvas_state_checksum, DP_canvas_state_decref, DP_canvas_state_flatten_into,
+    DP_canvas_state_height, DP_canvas_state_incref, DP_canvas_state_layer_props_noinc,
+    DP_canvas_state_layers_noinc, DP_canvas_state_metadata_noinc, DP_canvas_state_pick_layer,
+    DP_canvas_state_tile_checksums, DP_canvas_state_to_flat_image,
+    DP_canvas_state_to_flat_separated_urgba8, DP_canvas_state_transient, DP_canvas_state_width,
+    DP_tile_incref, DP_transient_canvas_state_background_tile_set_noinc,
+    DP_transient_canvas_state_decref, DP_transient_canvas_state_height_set,
+    DP_transient_canvas_state_incref, DP_transient_canvas_state_layer_routes_reindex,
+    DP_transient_canvas_state_new, DP_transient_canvas_state_persist,
+    DP_transient_canvas_state_transient_layer_props_set_noinc,
     DP_transient_canvas_state_transient_layers_set_noinc,
     DP_transient_canvas_state_transient_metadata,
     DP_transient_canvas_state_transient_timeline_set_noinc, DP_transient_canvas_state_width_set,
-    DP_FLAT_IMAGE_RENDER_FLAGS,
+    DP_TILE_SIZE,
 };
 use anyhow::Result;
-use std::{
-    ffi::c_int,
-    ptr::{null, null_mut},
-};
+use std::{collections::HashMap, ffi::c_int, ptr::null_mut};
 
 pub trait BaseCanvasState {
     fn persistent_ptr(&self) -> *mut DP_CanvasState;
@@ -43,7 +42,7 @@ pub trait BaseCanvasState {
         unsafe { DP_canvas_state_height(self.persistent_ptr()) }
     }
 
-    fn background_tile(&self) -> Option<AttachedTile<Self>>
+    fn background_tile(&self) -> Option<AttachedTile<'_, Self>>
     where
         Self: Sized,
     {
@@ -55,7 +54,7 @@ pub trait BaseCanvasState {
         unsafe { DP_canvas_state_background_opaque(self.persistent_ptr()) }
     }
 
-    fn layers(&self) -> AttachedLayerList<Self>
+    fn layers(&self) -> AttachedLayerList<'_, Self>
     where
         Self: Sized,
     {
@@ -63,7 +62,7 @@ pub trait BaseCanvasState {
         LayerList::new_attached(unsafe { &mut *data })
     }
 
-    fn layer_props(&self) -> AttachedLayerPropsList<Self>
+    fn layer_props(&self) -> AttachedLayerPropsList<'_, Self>
     where
         Self: Sized,
     {
@@ -71,7 +70,7 @@ pub trait BaseCanvasState {
         LayerPropsList::new_attached(unsafe { &mut *data })
     }
 
-    fn metadata(&self) -> AttachedDocumentMetadata<Self>
+    fn metadata(&self) -> AttachedDocumentMetadata<'_, Self>
     where
         Self: Sized,
     {
@@ -79,23 +78,106 @@ pub trait BaseCanvasState {
         DocumentMetadata::new_attached(unsafe { &mut *data })
     }
 
-    fn to_flat_separated_urgba8(&self) -> Result<Vec<u8>> {
-        let mut buffer = vec![0_u8; self.width() as usize * self.height() as usize * 4];
-        let ok = unsafe {
+    // Topmost visible leaf layer at the given point whose alpha exceeds
+    // min_alpha, or None if there isn't any or the point is out of bounds.
+    fn pick_layer(
+        &self,
+        x: c_int,
+        y: c_int,
+        min_alpha: u8,
+        reveal_censored: bool,
+    ) -> Option<c_int> {
+        let layer_id = unsafe {
+            DP_canvas_state_pick_layer(self.persistent_ptr(), x, y, min_alpha, reveal_censored)
+        };
+        if layer_id == 0 {
+            None
+        } else {
+            Some(layer_id)
+        }
+    }
+
+    // Checksum of the canvas contents, layer attributes and annotations that
+    // can be compared between clients to detect desyncs.
+    fn checksum(&self) -> u64 {
+        unsafe { DP_canvas_state_checksum(self.persistent_ptr()) }
+    }
+
+    // Checksums per tile position, keyed by tile column and row, so that a
+    // checksum mismatch can be narrowed down to the offending area.
+    fn checksums_per_tile(&self) -> HashMap<(c_int, c_int), u64> {
+        let tile_size = DP_TILE_SIZE as c_int;
+        let xtiles = (self.width() + tile_size - 1) / tile_size;
+        let ytiles = (self.height() + tile_size - 1) / tile_size;
+        let mut checksums = vec![0_u64; xtiles as usize * ytiles as usize];
+        unsafe { DP_canvas_state_tile_checksums(self.persistent_ptr(), checksums.as_mut_ptr()) };
+        checksums
+            .into_iter()
+            .enumerate()
+            .map(|(i, checksum)| {
+                let i = i as c_int;
+                ((i % xtiles, i / xtiles), checksum)
+            })
+            .collect()
+    }
+
+    // Memory used by the tiles of this canvas state, per layer and in total.
+    // Tiles shared between layers, like after duplicating one, count once.
+    fn memory_usage(&self) -> MemoryReport {
+        MemoryReport::new_from_canvas_states(&[self.persistent_ptr()])
+    }
+
+    fn to_flat_separated_urgba8(&self, options: &FlattenOptions) -> Result<Vec<u8>> {
+        let (width, height) = options.size(self);
+        let mut buffer = vec![0_u8; width.max(0) as usize * height.max(0) as usize * 4];
+        let ok = options.with_flattening(self, |cs, vmf| unsafe {
             DP_canvas_state_to_flat_separated_urgba8(
-                self.persistent_ptr(),
-                DP_FLAT_IMAGE_RENDER_FLAGS,
-                null(),
-                null(),
+                cs,
+                options.flags(),
+                options.area_ptr(),
+                vmf,
                 buffer.as_mut_ptr(),
             )
-        };
+        });
         if ok {
             Ok(buffer)
         } else {
             Err(dp_error_anyhow())
         }
     }
+
+    // Flattens into an existing buffer, rows are stride pixels apart. The
+    // padding at the end of each row is left untouched.
+    fn flatten_into(
+        &self,
+        dest: &mut [DP_Pixel8],
+        stride: usize,
+        options: &FlattenOptions,
+    ) -> Result<()> {
+        let ok = options.with_flattening(self, |cs, vmf| unsafe {
+            DP_canvas_state_flatten_into(
+                cs,
+                options.flags(),
+                options.area_ptr(),
+                vmf,
+                dest.as_mut_ptr(),
+                dest.len(),
+                stride,
+            )
+        });
+        if ok {
+            Ok(())
+        } else {
+            Err(dp_error_anyhow())
+        }
+    }
+
+    fn to_flat_image(&self, options: &FlattenOptions) -> Result<Image> {
+        let img = options.with_flattening(self, |cs, vmf| unsafe {
+            DP_canvas_state_to_flat_image(cs, options.flags(), options.area_ptr(), vmf)
+        });
+        Image::new_noinc_nullable(img).ok_or_else(dp_error_anyhow)
+    }
 }
 
 pub struct CanvasState {
@@ -106,7 +188,7 @@ pub type AttachedCanvasState<'a, P> = Attached<'a, CanvasState, P>;
 pub type DetachedCanvasState = Detached<DP_CanvasState, CanvasState>;
 
 impl CanvasState {
-    pub fn new_attached(data: &mut DP_CanvasState) -> AttachedCanvasState<()> {
+    pub fn new_attached(data: &mut DP_CanvasState) -> AttachedCanvasState<'_, ()> {
         Attached::new(Self { data })
     }
 
@@ -118,10 +200,12 @@ impl CanvasState {
         Detached::new_noinc(Self { data })
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn new_detached_inc_nullable(data: *mut DP_CanvasState) -> Option<DetachedCanvasState> {
         unsafe { data.as_mut() }.map(Self::new_detached_inc)
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn new_detached_noinc_nullable(data: *mut DP_CanvasState) -> Option<DetachedCanvasState> {
         unsafe { data.as_mut() }.map(Self::new_detached_noinc)
     }
@@ -160,7 +244,7 @@ impl TransientCanvasState {
         Detached::new_noinc(Self { data })
     }
 
-    pub fn transient_metadata(&mut self) -> AttachedTransientDocumentMetadata<Self> {
+    pub fn transient_metadata(&mut self) -> AttachedTransientDocumentMetadata<'_, Self> {
         let data = unsafe { DP_transient_canvas_state_transient_metadata(self.data) };
         TransientDocumentMetadata::new_attached(unsafe { &mut *data })
     }
@@ -201,6 +285,7 @@ impl TransientCanvasState {
         }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn reindex_layer_routes(&mut self, dc: *mut DP_DrawContext) {
         unsafe { DP_transient_canvas_state_layer_routes_reindex(self.data, dc) }
     }
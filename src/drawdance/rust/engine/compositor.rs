@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use super::{AttachedTile, BaseCanvasState, FlattenOptions, Tile};
+use crate::{
+    DP_AffectedArea, DP_Compositor, DP_Pixel8, DP_Rect, DP_compositor_base_layers,
+    DP_compositor_base_layers_set, DP_compositor_free, DP_compositor_height,
+    DP_compositor_invalidate, DP_compositor_new, DP_compositor_scale_cache_budget_set,
+    DP_compositor_scale_cache_bytes, DP_compositor_scale_cache_count, DP_compositor_tile_at_noinc,
+    DP_compositor_to_pixels8, DP_compositor_to_scaled_pixels8, DP_compositor_update,
+    DP_compositor_width, DP_free, DP_COMPOSITOR_SCALE_MAX,
+};
+use std::{ffi::c_int, mem::MaybeUninit, slice::from_raw_parts};
+
+// Persistent flattened copy of the canvas that only recomposites the tiles an
+// affected area touches, see DP_affected_area_make_visible. Only the
+// background, sublayer and censor options apply, the area, view mode and
+// sketch layers are ignored.
+pub struct Compositor {
+    c: *mut DP_Compositor,
+}
+
+impl Compositor {
+    pub fn new(options: &FlattenOptions) -> Self {
+        let c = unsafe { DP_compositor_new(options.flags()) };
+        Self { c }
+    }
+
+    pub fn width(&self) -> c_int {
+        unsafe { DP_compositor_width(self.c) }
+    }
+
+    pub fn height(&self) -> c_int {
+        unsafe { DP_compositor_height(self.c) }
+    }
+
+    pub fn invalidate(&mut self) {
+        unsafe { DP_compositor_invalidate(self.c) }
+    }
+
+    // Returns the recomposited pixel bounds, rounded out to whole tiles, or
+    // None if the affected area didn't touch any pixels.
+    pub fn update(&mut self, cs: &impl BaseCanvasState, aa: &DP_AffectedArea) -> Option<DP_Rect> {
+        let mut bounds = MaybeUninit::<DP_Rect>::uninit();
+        if unsafe { DP_compositor_update(self.c, cs.persistent_ptr(), aa, bounds.as_mut_ptr()) } {
+            Some(unsafe { bounds.assume_init() })
+        } else {
+            None
+        }
+    }
+
+    pub fn tile_at(&self, x: c_int, y: c_int) -> Option<AttachedTile<'_, Self>> {
+        Tile::new_attached_nullable(unsafe { DP_compositor_tile_at_noinc(self.c, x, y) })
+    }
+
+    pub fn to_pixels8(&self, x: c_int, y: c_int, width: c_int, height: c_int) -> Vec<DP_Pixel8> {
+        if width <= 0 || height <= 0 {
+            return Vec::new();
+        }
+        let data = unsafe { DP_compositor_to_pixels8(self.c, x, y, width, height) };
+        Self::take_pixels(data, width, height)
+    }
+
+    // Number of bottom top-level layers to keep flattened with the background,
+    // 0 turns that off, DP_COMPOSITOR_BASE_LAYERS_AUTO follows what changes.
+    pub fn set_base_layers(&mut self, count: c_int) {
+        unsafe { DP_compositor_base_layers_set(self.c, count) }
+    }
+
+    pub fn base_layers(&self) -> c_int {
+        unsafe { DP_compositor_base_layers(self.c) }
+    }
+
+    // Byte budget for caching downscaled tiles, 0 turns the cache off.
+    pub fn set_scale_cache_budget(&mut self, budget: usize) {
+        unsafe { DP_compositor_scale_cache_budget_set(self.c, budget) }
+    }
+
+    pub fn scale_cache_bytes(&self) -> usize {
+        unsafe { DP_compositor_scale_cache_bytes(self.c) }
+    }
+
+    pub fn scale_cache_count(&self) -> c_int {
+        unsafe { DP_compositor_scale_cache_count(self.c) }
+    }
+
+    // Reads a region of the composite scaled down by a power of two up to
+    // DP_COMPOSITOR_SCALE_MAX, in scaled coordinates. Pixels are averages of
+    // the blocks they cover. Returns None if the scale isn't valid.
+    pub fn read_scaled_region(&mut self, rect: &DP_Rect, scale: c_int) -> Option<Vec<DP_Pixel8>> {
+        if scale <= 0 || scale > DP_COMPOSITOR_SCALE_MAX as c_int || scale & (scale - 1) != 0 {
+            return None;
+        }
+        let width = rect.x2 - rect.x1 + 1;
+        let height = rect.y2 - rect.y1 + 1;
+        if width <= 0 || height <= 0 {
+            return Some(Vec::new());
+        }
+        let data = unsafe {
+            DP_compositor_to_scaled_pixels8(self.c, scale, rect.x1, rect.y1, width, height)
+        };
+        Some(Self::take_pixels(data, width, height))
+    }
+
+    fn take_pixels(data: *mut DP_Pixel8, width: c_int, height: c_int) -> Vec<DP_Pixel8> {
+        let len = width as usize * height as usize;
+        let pixels = unsafe { from_raw_parts(data, len) }.to_vec();
+        unsafe { DP_free(data.cast()) };
+        pixels
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        unsafe { DP_compositor_free(self.c) }
+    }
+}
@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use super::BaseCanvasState;
+use crate::{
+    DP_CanvasState, DP_OnionSkins, DP_Rect, DP_SketchLayer, DP_UPixel15, DP_ViewModeBuffer,
+    DP_ViewModeFilter, DP_canvas_state_decref, DP_canvas_state_sketch_inc, DP_onion_skins_free,
+    DP_onion_skins_new, DP_onion_skins_skin_above_at_set, DP_onion_skins_skin_below_at_set,
+    DP_view_mode_buffer_dispose, DP_view_mode_buffer_init, DP_view_mode_filter_make,
+    DP_FLAT_IMAGE_INCLUDE_BACKGROUND, DP_FLAT_IMAGE_INCLUDE_SUBLAYERS,
+    DP_FLAT_IMAGE_REVEAL_CENSORED, DP_FLAT_IMAGE_SINGLE_THREADED, DP_VIEW_MODE_FRAME,
+    DP_VIEW_MODE_LAYER, DP_VIEW_MODE_NORMAL,
+};
+use std::{ffi::c_int, mem::MaybeUninit, ptr::null_mut};
+
+#[derive(Clone, Copy, Debug)]
+pub struct OnionSkin {
+    pub opacity: u16,
+    pub tint: DP_UPixel15,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlattenView {
+    Normal,
+    Layer(c_int),
+    Frame(c_int),
+}
+
+// Options shared by all the canvas flattening functions. The defaults match
+// DP_FLAT_IMAGE_RENDER_FLAGS with no area or view mode, so the whole canvas
+// gets flattened in normal view mode with background and sublayers. Censored
+// layers stay censored and sketch layers get applied. It's spread over all
+// cores unless the parallel-flatten feature is turned off, like for
+// WebAssembly builds without threads.
+#[derive(Clone, Debug)]
+pub struct FlattenOptions {
+    include_background: bool,
+    include_sublayers: bool,
+    single_threaded: bool,
+    reveal_censored: bool,
+    apply_sketch: bool,
+    sketch_layers: Vec<DP_SketchLayer>,
+    area: Option<DP_Rect>,
+    view: FlattenView,
+    onion_skins_wrap: bool,
+    onion_skins_below: Vec<OnionSkin>,
+    onion_skins_above: Vec<OnionSkin>,
+}
+
+impl FlattenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include_background(mut self, include_background: bool) -> Self {
+        self.include_background = include_background;
+        self
+    }
+
+    pub fn include_sublayers(mut self, include_sublayers: bool) -> Self {
+        self.include_sublayers = include_sublayers;
+        self
+    }
+
+    pub fn single_threaded(mut self, single_threaded: bool) -> Self {
+        self.single_threaded = single_threaded;
+        self
+    }
+
+    pub fn reveal_censored(mut self, reveal_censored: bool) -> Self {
+        self.reveal_censored = reveal_censored;
+        self
+    }
+
+    // Flattens the given layer at the given opacity instead of its own, like
+    // sketch mode shows it locally. Setting the same layer again replaces it.
+    pub fn sketch_layer(mut self, layer_id: c_int, opacity: u16) -> Self {
+        self.sketch_layers.retain(|sl| sl.layer_id != layer_id);
+        self.sketch_layers
+            .push(DP_SketchLayer { layer_id, opacity });
+        self
+    }
+
+    // Whether to use the sketch layers at all, so that the same options can
+    // be used for showing the canvas and for exporting it without them.
+    pub fn apply_sketch(mut self, apply_sketch: bool) -> Self {
+        self.apply_sketch = apply_sketch;
+        self
+    }
+
+    pub fn area(mut self, x: c_int, y: c_int, width: c_int, height: c_int) -> Self {
+        self.area = Some(DP_Rect {
+            x1: x,
+            y1: y,
+            x2: x + width - 1,
+            y2: y + height - 1,
+        });
+        self
+    }
+
+    pub fn full_canvas(mut self) -> Self {
+        self.area = None;
+        self
+    }
+
+    pub fn layer(mut self, layer_id: c_int) -> Self {
+        self.view = FlattenView::Layer(layer_id);
+        self
+    }
+
+    pub fn frame(mut self, frame_index: c_int) -> Self {
+        self.view = FlattenView::Frame(frame_index);
+        self
+    }
+
+    pub fn onion_skins(mut self, wrap: bool, below: &[OnionSkin], above: &[OnionSkin]) -> Self {
+        self.onion_skins_wrap = wrap;
+        self.onion_skins_below = below.to_vec();
+        self.onion_skins_above = above.to_vec();
+        self
+    }
+
+    pub fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.include_background {
+            flags |= DP_FLAT_IMAGE_INCLUDE_BACKGROUND;
+        }
+        if self.include_sublayers {
+            flags |= DP_FLAT_IMAGE_INCLUDE_SUBLAYERS;
+        }
+        if self.single_threaded {
+            flags |= DP_FLAT_IMAGE_SINGLE_THREADED;
+        }
+        if self.reveal_censored {
+            flags |= DP_FLAT_IMAGE_REVEAL_CENSORED;
+        }
+        flags
+    }
+
+    pub fn sketch_layers(&self) -> &[DP_SketchLayer] {
+        if self.apply_sketch {
+            &self.sketch_layers
+        } else {
+            &[]
+        }
+    }
+
+    pub fn view(&self) -> FlattenView {
+        self.view
+    }
+
+    // Width and height of the flattened result for the given canvas.
+    pub fn size<T: BaseCanvasState + ?Sized>(&self, cs: &T) -> (c_int, c_int) {
+        match self.area {
+            Some(DP_Rect { x1, y1, x2, y2 }) => (x2 - x1 + 1, y2 - y1 + 1),
+            None => (cs.width(), cs.height()),
+        }
+    }
+
+    pub(crate) fn area_ptr(&self) -> *const DP_Rect {
+        self.area
+            .as_ref()
+            .map_or(std::ptr::null(), |area| area as *const DP_Rect)
+    }
+
+    fn has_onion_skins(&self) -> bool {
+        !self.onion_skins_below.is_empty() || !self.onion_skins_above.is_empty()
+    }
+
+    fn make_onion_skins(&self) -> *mut DP_OnionSkins {
+        if !self.has_onion_skins() {
+            return null_mut();
+        }
+        let below = self.onion_skins_below.len() as c_int;
+        let above = self.onion_skins_above.len() as c_int;
+        let oss = unsafe { DP_onion_skins_new(self.onion_skins_wrap, below, above) };
+        for (i, os) in self.onion_skins_below.iter().enumerate() {
+            unsafe { DP_onion_skins_skin_below_at_set(oss, i as c_int, os.opacity, os.tint) };
+        }
+        for (i, os) in self.onion_skins_above.iter().enumerate() {
+            unsafe { DP_onion_skins_skin_above_at_set(oss, i as c_int, os.opacity, os.tint) };
+        }
+        oss
+    }
+
+    // Applies the sketch layers to the given canvas, builds the view mode
+    // filter for it and passes both to the given function. The canvas state
+    // may be a temporary copy and the filter may point into a temporary
+    // buffer, so neither must escape the callback.
+    pub(crate) fn with_flattening<T: BaseCanvasState + ?Sized, R>(
+        &self,
+        cs: &T,
+        f: impl FnOnce(*mut DP_CanvasState, *const DP_ViewModeFilter) -> R,
+    ) -> R {
+        let sketch_layers = self.sketch_layers();
+        let flat_cs = if sketch_layers.is_empty() {
+            null_mut()
+        } else {
+            unsafe {
+                DP_canvas_state_sketch_inc(
+                    cs.persistent_ptr(),
+                    sketch_layers.len() as c_int,
+                    sketch_layers.as_ptr(),
+                )
+            }
+        };
+        let cs_ptr = if flat_cs.is_null() {
+            cs.persistent_ptr()
+        } else {
+            flat_cs
+        };
+        let (vm, layer_id, frame_index) = match self.view {
+            FlattenView::Normal => (DP_VIEW_MODE_NORMAL, 0, 0),
+            FlattenView::Layer(layer_id) => (DP_VIEW_MODE_LAYER, layer_id, 0),
+            FlattenView::Frame(frame_index) => (DP_VIEW_MODE_FRAME, 0, frame_index),
+        };
+        let mut vmb = MaybeUninit::<DP_ViewModeBuffer>::uninit();
+        unsafe { DP_view_mode_buffer_init(vmb.as_mut_ptr()) };
+        let oss = self.make_onion_skins();
+        let vmf = unsafe {
+            DP_view_mode_filter_make(vmb.as_mut_ptr(), vm, cs_ptr, layer_id, frame_index, oss)
+        };
+        let result = f(cs_ptr, &vmf);
+        unsafe {
+            if !oss.is_null() {
+                DP_onion_skins_free(oss);
+            }
+            DP_view_mode_buffer_dispose(vmb.as_mut_ptr());
+            if !flat_cs.is_null() {
+                DP_canvas_state_decref(flat_cs);
+            }
+        }
+        result
+    }
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self {
+            include_background: true,
+            include_sublayers: true,
+            single_threaded: !cfg!(feature = "parallel-flatten"),
+            reveal_censored: false,
+            apply_sketch: true,
+            sketch_layers: Vec::new(),
+            area: None,
+            view: FlattenView::Normal,
+            onion_skins_wrap: false,
+            onion_skins_below: Vec::new(),
+            onion_skins_above: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DP_FLAT_IMAGE_RENDER_FLAGS;
+
+    fn without_threading(flags: u32) -> u32 {
+        flags & !DP_FLAT_IMAGE_SINGLE_THREADED
+    }
+
+    #[test]
+    fn default_flags_match_render_flags() {
+        let flags = without_threading(FlattenOptions::default().flags());
+        assert_eq!(flags, DP_FLAT_IMAGE_RENDER_FLAGS);
+    }
+
+    #[test]
+    fn toggles_map_to_flags() {
+        let options = FlattenOptions::new()
+            .include_background(false)
+            .reveal_censored(true)
+            .single_threaded(true);
+        assert_eq!(
+            options.flags(),
+            DP_FLAT_IMAGE_INCLUDE_SUBLAYERS
+                | DP_FLAT_IMAGE_REVEAL_CENSORED
+                | DP_FLAT_IMAGE_SINGLE_THREADED
+        );
+        let options = options.include_sublayers(false).reveal_censored(false);
+        assert_eq!(options.flags(), DP_FLAT_IMAGE_SINGLE_THREADED);
+    }
+
+    #[test]
+    fn area_is_inclusive() {
+        let options = FlattenOptions::new().area(10, 20, 30, 40);
+        let area = unsafe { *options.area_ptr() };
+        assert_eq!((area.x1, area.y1, area.x2, area.y2), (10, 20, 39, 59));
+        assert!(options.full_canvas().area_ptr().is_null());
+    }
+
+    #[test]
+    fn sketch_layers_can_be_ignored() {
+        let options = FlattenOptions::new()
+            .sketch_layer(1, 100)
+            .sketch_layer(2, 200)
+            .sketch_layer(1, 300);
+        let sketches: Vec<(c_int, u16)> = options
+            .sketch_layers()
+            .iter()
+            .map(|sl| (sl.layer_id, sl.opacity))
+            .collect();
+        assert_eq!(sketches, [(2, 200), (1, 300)]);
+        assert!(options.apply_sketch(false).sketch_layers().is_empty());
+    }
+}
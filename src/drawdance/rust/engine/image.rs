@@ -1,14 +1,15 @@
 use super::DrawContext;
 use crate::{
-    dp_error_anyhow, DP_Image, DP_Output, DP_Quad, DP_UPixel8, DP_blend_color8_to,
-    DP_file_output_new_from_path, DP_image_free, DP_image_height, DP_image_new,
+    dp_error_anyhow, msg::Message, DP_Image, DP_Message, DP_Output, DP_Quad, DP_UPixel8,
+    DP_blend_color8_to, DP_file_output_new_from_path, DP_image_free, DP_image_height, DP_image_new,
     DP_image_new_subimage, DP_image_pixels, DP_image_transform_pixels, DP_image_width,
-    DP_image_write_jpeg, DP_image_write_png, DP_output_free, DP_MSG_TRANSFORM_REGION_MODE_BILINEAR,
+    DP_image_write_jpeg, DP_image_write_png, DP_mem_output_new, DP_output_free,
+    DP_put_image_messages, DP_MSG_TRANSFORM_REGION_MODE_BILINEAR,
 };
 use anyhow::{anyhow, Result};
 use core::slice;
 use std::{
-    ffi::{c_int, CString},
+    ffi::{c_int, c_uint, c_void, CString},
     io::{self},
     mem::size_of,
     ptr::{self, copy_nonoverlapping},
@@ -30,6 +31,14 @@ impl Image {
         }
     }
 
+    pub fn new_noinc_nullable(image: *mut DP_Image) -> Option<Self> {
+        if image.is_null() {
+            None
+        } else {
+            Some(Self { image })
+        }
+    }
+
     pub fn new_from_pixels(width: usize, height: usize, pixels: &[u32]) -> Result<Self> {
         let count = width * height;
         if pixels.len() >= count {
@@ -141,6 +150,20 @@ impl Image {
         }
     }
 
+    pub fn to_scaled(
+        &self,
+        scale_width: usize,
+        scale_height: usize,
+        expand: bool,
+        dc: &mut DrawContext,
+    ) -> Result<Self> {
+        let width = self.width();
+        let height = self.height();
+        let pixels =
+            unsafe { slice::from_raw_parts(DP_image_pixels(self.image).cast(), width * height) };
+        Self::new_from_pixels_scaled(width, height, pixels, scale_width, scale_height, expand, dc)
+    }
+
     pub fn width(&self) -> usize {
         unsafe { DP_image_width(self.image) as usize }
     }
@@ -163,6 +186,32 @@ impl Image {
         self.write(path, DP_image_write_jpeg)
     }
 
+    // Encoded in memory, for the clipboard or uploading without a file.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        self.encode(DP_image_write_png)
+    }
+
+    pub fn to_jpeg(&self) -> Result<Vec<u8>> {
+        self.encode(DP_image_write_jpeg)
+    }
+
+    fn encode(
+        &self,
+        func: unsafe extern "C" fn(*mut DP_Image, *mut DP_Output) -> bool,
+    ) -> Result<Vec<u8>> {
+        let mut buffer_ptr: *mut *mut c_void = ptr::null_mut();
+        let mut size_ptr: *mut usize = ptr::null_mut();
+        let output = unsafe { DP_mem_output_new(1024, true, &mut buffer_ptr, &mut size_ptr) };
+        let result = if unsafe { func(self.image, output) } {
+            let bytes = unsafe { slice::from_raw_parts((*buffer_ptr).cast::<u8>(), *size_ptr) };
+            Ok(bytes.to_vec())
+        } else {
+            Err(dp_error_anyhow())
+        };
+        unsafe { DP_output_free(output) };
+        result
+    }
+
     fn write(
         &self,
         path: &str,
@@ -200,6 +249,44 @@ impl Image {
         }
         Ok(())
     }
+
+    /// Splits the image into an undo point followed by put image messages
+    /// that each fit into the maximum message size, so that pasting it undoes
+    /// as a single action. Parts at negative coordinates are cropped off.
+    pub fn to_put_image_messages(
+        &self,
+        context_id: u8,
+        layer_id: u16,
+        blend_mode: u8,
+        x: i32,
+        y: i32,
+    ) -> Result<Vec<Message>> {
+        let mut messages: Vec<Message> = Vec::new();
+        let user: *mut Vec<Message> = &mut messages;
+        let ok = unsafe {
+            DP_put_image_messages(
+                c_uint::from(context_id),
+                c_int::from(layer_id),
+                c_int::from(blend_mode),
+                x,
+                y,
+                self.image,
+                Some(Self::on_push_message),
+                user.cast(),
+            )
+        };
+        if ok {
+            Ok(messages)
+        } else {
+            Err(dp_error_anyhow())
+        }
+    }
+
+    extern "C" fn on_push_message(user: *mut c_void, msg: *mut DP_Message) -> bool {
+        let messages = unsafe { user.cast::<Vec<Message>>().as_mut().unwrap_unchecked() };
+        messages.push(Message::new_noinc(msg));
+        true
+    }
 }
 
 impl Drop for Image {
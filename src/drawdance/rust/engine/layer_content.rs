@@ -1,12 +1,20 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
-use super::{Attached, BaseTile, CArc, Detached, UPixels8};
+use super::{
+    Attached, AttachedTile, AttachedTransientTile, BaseTile, CArc, Detached, Tile, TransientTile,
+    UPixels8,
+};
 use crate::{
-    DP_LayerContent, DP_TransientLayerContent, DP_layer_content_decref, DP_layer_content_incref,
+    DP_LayerContent, DP_TransientLayerContent, DP_layer_content_checksum, DP_layer_content_decref,
+    DP_layer_content_height, DP_layer_content_incref, DP_layer_content_next_nonblank_tile_noinc,
     DP_layer_content_to_upixels8, DP_layer_content_to_upixels8_cropped, DP_layer_content_transient,
-    DP_transient_layer_content_decref, DP_transient_layer_content_incref,
-    DP_transient_layer_content_new_init,
+    DP_layer_content_width, DP_transient_layer_content_decref, DP_transient_layer_content_incref,
+    DP_transient_layer_content_new_init, DP_transient_layer_content_next_nonblank_transient_tile,
+};
+use std::{
+    ffi::{c_int, c_uint},
+    marker::PhantomData,
+    ptr::null_mut,
 };
-use std::{ffi::c_int, ptr::null_mut};
 
 pub trait BaseLayerContent {
     fn persistent_ptr(&self) -> *mut DP_LayerContent;
@@ -15,6 +23,43 @@ pub trait BaseLayerContent {
         unsafe { DP_layer_content_transient(self.persistent_ptr()) }
     }
 
+    fn width(&self) -> c_int {
+        unsafe { DP_layer_content_width(self.persistent_ptr()) }
+    }
+
+    fn height(&self) -> c_int {
+        unsafe { DP_layer_content_height(self.persistent_ptr()) }
+    }
+
+    // Visits tiles that have any pixels in them in row-major order, skipping
+    // both null tiles and tiles that are fully transparent. Blankness is cached
+    // on the tiles, so iterating again only looks at tiles that changed.
+    fn nonblank_tiles(&self) -> NonBlankTiles<'_, Self>
+    where
+        Self: Sized,
+    {
+        NonBlankTiles::new(self.persistent_ptr())
+    }
+
+    fn nonblank_tile_count(&self) -> usize
+    where
+        Self: Sized,
+    {
+        self.nonblank_tiles().count()
+    }
+
+    fn has_nonblank_tiles(&self) -> bool
+    where
+        Self: Sized,
+    {
+        self.nonblank_tiles().next().is_some()
+    }
+
+    // Combines the checksums of all non-blank tiles with their positions.
+    fn checksum(&self) -> u64 {
+        unsafe { DP_layer_content_checksum(self.persistent_ptr()) }
+    }
+
     fn to_upixels8(&self, x: c_int, y: c_int, width: c_int, height: c_int) -> UPixels8 {
         let data =
             unsafe { DP_layer_content_to_upixels8(self.persistent_ptr(), x, y, width, height) };
@@ -46,6 +91,63 @@ pub trait BaseLayerContent {
     }
 }
 
+pub struct NonBlankTiles<'a, P> {
+    lc: *mut DP_LayerContent,
+    index: c_int,
+    phantom: PhantomData<&'a P>,
+}
+
+impl<'a, P> NonBlankTiles<'a, P> {
+    fn new(lc: *mut DP_LayerContent) -> Self {
+        Self {
+            lc,
+            index: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, P> Iterator for NonBlankTiles<'a, P> {
+    type Item = (c_int, c_int, AttachedTile<'a, P>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut x: c_int = 0;
+        let mut y: c_int = 0;
+        let t = unsafe {
+            DP_layer_content_next_nonblank_tile_noinc(self.lc, &mut self.index, &mut x, &mut y)
+        };
+        Tile::new_attached_nullable(t).map(|tile| (x, y, tile))
+    }
+}
+
+// Like NonBlankTiles, but each tile visited is made transient so that it can
+// be edited in place.
+pub struct NonBlankTransientTiles<'a, P> {
+    tlc: *mut DP_TransientLayerContent,
+    context_id: c_uint,
+    index: c_int,
+    phantom: PhantomData<&'a mut P>,
+}
+
+impl<'a, P> Iterator for NonBlankTransientTiles<'a, P> {
+    type Item = (c_int, c_int, AttachedTransientTile<'a, P>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut x: c_int = 0;
+        let mut y: c_int = 0;
+        let tt = unsafe {
+            DP_transient_layer_content_next_nonblank_transient_tile(
+                self.tlc,
+                self.context_id,
+                &mut self.index,
+                &mut x,
+                &mut y,
+            )
+        };
+        unsafe { tt.as_mut() }.map(|tt| (x, y, TransientTile::new_attached(tt)))
+    }
+}
+
 pub struct LayerContent {
     data: *mut DP_LayerContent,
 }
@@ -54,7 +156,7 @@ pub type AttachedLayerContent<'a, P> = Attached<'a, LayerContent, P>;
 pub type DetachedLayerContent = Detached<DP_LayerContent, LayerContent>;
 
 impl LayerContent {
-    pub fn new_attached<P>(data: &mut DP_LayerContent) -> AttachedLayerContent<P> {
+    pub fn new_attached<P>(data: &mut DP_LayerContent) -> AttachedLayerContent<'_, P> {
         Attached::new(Self { data })
     }
 }
@@ -96,6 +198,17 @@ impl TransientLayerContent {
         let data = unsafe { DP_transient_layer_content_new_init(width, height, t) };
         Detached::new_noinc(Self { data })
     }
+
+    // Visits the same tiles as nonblank_tiles, but makes them transient for
+    // modification, attributed to the given context id.
+    pub fn nonblank_tiles_mut(&mut self, context_id: c_uint) -> NonBlankTransientTiles<'_, Self> {
+        NonBlankTransientTiles {
+            tlc: self.data,
+            context_id,
+            index: 0,
+            phantom: PhantomData,
+        }
+    }
 }
 
 impl BaseLayerContent for TransientLayerContent {
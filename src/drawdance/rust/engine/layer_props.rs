@@ -3,16 +3,22 @@ use super::{
     LayerPropsList,
 };
 use crate::{
-    DP_BlendMode, DP_LayerProps, DP_TransientLayerProps, DP_channel15_to_8,
-    DP_layer_props_blend_mode, DP_layer_props_censored, DP_layer_props_children_noinc,
-    DP_layer_props_decref, DP_layer_props_hidden, DP_layer_props_id, DP_layer_props_incref,
-    DP_layer_props_isolated, DP_layer_props_opacity, DP_layer_props_title,
-    DP_layer_props_transient, DP_transient_layer_props_decref, DP_transient_layer_props_id_set,
+    DP_BlendMode, DP_LayerColorTag, DP_LayerProps, DP_TransientLayerProps, DP_channel15_to_8,
+    DP_layer_props_alpha_lock, DP_layer_props_blend_mode, DP_layer_props_censored,
+    DP_layer_props_children_noinc, DP_layer_props_color_tag, DP_layer_props_decref,
+    DP_layer_props_fixed, DP_layer_props_hidden, DP_layer_props_id, DP_layer_props_incref,
+    DP_layer_props_isolated, DP_layer_props_metadata_count, DP_layer_props_metadata_get,
+    DP_layer_props_metadata_key_at, DP_layer_props_metadata_value_at, DP_layer_props_opacity,
+    DP_layer_props_title, DP_layer_props_transient, DP_transient_layer_props_alpha_lock_set,
+    DP_transient_layer_props_color_tag_set, DP_transient_layer_props_decref,
+    DP_transient_layer_props_fixed_set, DP_transient_layer_props_id_set,
     DP_transient_layer_props_incref, DP_transient_layer_props_isolated_set,
-    DP_transient_layer_props_new, DP_transient_layer_props_new_init_with_transient_children_noinc,
+    DP_transient_layer_props_metadata_set, DP_transient_layer_props_new,
+    DP_transient_layer_props_new_init_with_transient_children_noinc,
 };
 use std::{
     ffi::{c_char, c_int},
+    ptr::null,
     slice::from_raw_parts,
 };
 
@@ -63,11 +69,60 @@ pub trait BaseLayerProps {
         unsafe { DP_layer_props_isolated(self.persistent_ptr()) }
     }
 
+    fn alpha_lock(&self) -> bool {
+        unsafe { DP_layer_props_alpha_lock(self.persistent_ptr()) }
+    }
+
+    fn fixed(&self) -> bool {
+        unsafe { DP_layer_props_fixed(self.persistent_ptr()) }
+    }
+
+    fn color_tag(&self) -> DP_LayerColorTag {
+        unsafe { DP_layer_props_color_tag(self.persistent_ptr()) as DP_LayerColorTag }
+    }
+
+    fn metadata_count(&self) -> c_int {
+        unsafe { DP_layer_props_metadata_count(self.persistent_ptr()) }
+    }
+
+    fn metadata_at(&self, index: c_int) -> (&[u8], &[u8]) {
+        let mut key_len = 0_usize;
+        let mut value_len = 0_usize;
+        let key =
+            unsafe { DP_layer_props_metadata_key_at(self.persistent_ptr(), index, &mut key_len) };
+        let value = unsafe {
+            DP_layer_props_metadata_value_at(self.persistent_ptr(), index, &mut value_len)
+        };
+        unsafe {
+            (
+                from_raw_parts(key.cast(), key_len),
+                from_raw_parts(value.cast(), value_len),
+            )
+        }
+    }
+
+    fn metadata_get(&self, key: &[u8]) -> Option<&[u8]> {
+        let mut len = 0_usize;
+        let value = unsafe {
+            DP_layer_props_metadata_get(
+                self.persistent_ptr(),
+                key.as_ptr().cast(),
+                key.len(),
+                &mut len,
+            )
+        };
+        if value.is_null() {
+            None
+        } else {
+            Some(unsafe { from_raw_parts(value.cast(), len) })
+        }
+    }
+
     fn is_group(&self) -> bool {
         !unsafe { DP_layer_props_children_noinc(self.persistent_ptr()) }.is_null()
     }
 
-    fn children(&self) -> Option<AttachedLayerPropsList<Self>>
+    fn children(&self) -> Option<AttachedLayerPropsList<'_, Self>>
     where
         Self: Sized,
     {
@@ -88,7 +143,7 @@ pub type AttachedLayerProps<'a, P> = Attached<'a, LayerProps, P>;
 pub type DetachedLayerProps = Detached<DP_LayerProps, LayerProps>;
 
 impl LayerProps {
-    pub fn new_attached<P>(data: &mut DP_LayerProps) -> AttachedLayerProps<P> {
+    pub fn new_attached<P>(data: &mut DP_LayerProps) -> AttachedLayerProps<'_, P> {
         Attached::new(Self { data })
     }
 }
@@ -147,6 +202,31 @@ impl TransientLayerProps {
     pub fn set_isolated(&mut self, isolated: bool) {
         unsafe { DP_transient_layer_props_isolated_set(self.data, isolated) }
     }
+
+    pub fn set_alpha_lock(&mut self, alpha_lock: bool) {
+        unsafe { DP_transient_layer_props_alpha_lock_set(self.data, alpha_lock) }
+    }
+
+    pub fn set_fixed(&mut self, fixed: bool) {
+        unsafe { DP_transient_layer_props_fixed_set(self.data, fixed) }
+    }
+
+    pub fn set_color_tag(&mut self, color_tag: DP_LayerColorTag) {
+        unsafe { DP_transient_layer_props_color_tag_set(self.data, color_tag as c_int) }
+    }
+
+    pub fn set_metadata(&mut self, key: &[u8], value: Option<&[u8]>) {
+        let (value_ptr, value_len) = value.map_or((null(), 0), |v| (v.as_ptr(), v.len()));
+        unsafe {
+            DP_transient_layer_props_metadata_set(
+                self.data,
+                key.as_ptr().cast(),
+                key.len(),
+                value_ptr.cast(),
+                value_len,
+            )
+        }
+    }
 }
 
 impl BaseLayerProps for TransientLayerProps {
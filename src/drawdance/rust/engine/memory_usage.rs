@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::{
+    DP_CanvasState, DP_LayerMemoryUsage, DP_MemoryUsage, DP_MemoryUsageCounts,
+    DP_memory_usage_add_canvas_state, DP_memory_usage_free, DP_memory_usage_layer_at,
+    DP_memory_usage_layer_count, DP_memory_usage_new, DP_memory_usage_totals,
+};
+use std::ffi::c_int;
+
+// Snapshot of how much memory the tiles of one or more canvas states take up,
+// with tiles shared between them only counted once. Plain values, so it can be
+// cloned around and shown in a status display without touching the canvas.
+#[derive(Debug, Clone)]
+pub struct MemoryReport {
+    pub totals: DP_MemoryUsageCounts,
+    pub layers: Vec<DP_LayerMemoryUsage>,
+}
+
+impl MemoryReport {
+    pub fn new_from_canvas_states(states: &[*mut DP_CanvasState]) -> Self {
+        Self::new_with(|mu| {
+            for cs in states {
+                unsafe { DP_memory_usage_add_canvas_state(mu, *cs) };
+            }
+        })
+    }
+
+    pub fn new_with<F: FnOnce(*mut DP_MemoryUsage)>(add: F) -> Self {
+        let mu = unsafe { DP_memory_usage_new() };
+        add(mu);
+        let totals = unsafe { DP_memory_usage_totals(mu) };
+        let count = unsafe { DP_memory_usage_layer_count(mu) };
+        let layers = (0..count)
+            .map(|i| unsafe { *DP_memory_usage_layer_at(mu, i) })
+            .collect();
+        unsafe { DP_memory_usage_free(mu) };
+        Self { totals, layers }
+    }
+
+    pub fn layer(&self, layer_id: c_int) -> Option<&DP_LayerMemoryUsage> {
+        self.layers.iter().find(|lmu| lmu.layer_id == layer_id)
+    }
+}
@@ -2,8 +2,10 @@
 
 mod acl;
 mod canvas_state;
+mod compositor;
 mod document_metadata;
 mod draw_context;
+mod flatten_options;
 mod image;
 mod key_frame;
 mod layer_content;
@@ -11,10 +13,12 @@ mod layer_group;
 mod layer_list;
 mod layer_props;
 mod layer_props_list;
+mod memory_usage;
 mod paint_engine;
 mod pixels;
 mod player;
 mod recorder;
+mod recording_filter;
 mod tile;
 mod timeline;
 mod track;
@@ -25,19 +29,22 @@ pub use canvas_state::{
     AttachedCanvasState, AttachedTransientCanvasState, BaseCanvasState, CanvasState,
     DetachedCanvasState, DetachedTransientCanvasState, TransientCanvasState,
 };
+pub use compositor::Compositor;
 pub use document_metadata::{
     AttachedDocumentMetadata, AttachedTransientDocumentMetadata, BaseDocumentMetadata,
     DetachedDocumentMetadata, DetachedTransientDocumentMetadata, DocumentMetadata,
     TransientDocumentMetadata,
 };
 pub use draw_context::DrawContext;
+pub use flatten_options::{FlattenOptions, FlattenView, OnionSkin};
 pub use image::Image;
 pub use key_frame::{
     AttachedTransientKeyFrame, BaseKeyFrame, DetachedTransientKeyFrame, TransientKeyFrame,
 };
 pub use layer_content::{
     AttachedLayerContent, AttachedTransientLayerContent, BaseLayerContent, DetachedLayerContent,
-    DetachedTransientLayerContent, LayerContent, TransientLayerContent,
+    DetachedTransientLayerContent, LayerContent, NonBlankTiles, NonBlankTransientTiles,
+    TransientLayerContent,
 };
 pub use layer_group::{
     AttachedLayerGroup, AttachedTransientLayerGroup, BaseLayerGroup, DetachedLayerGroup,
@@ -57,11 +64,16 @@ pub use layer_props_list::{
     DetachedLayerPropsList, DetachedTransientLayerPropsList, LayerPropsList,
     TransientLayerPropsList,
 };
+pub use memory_usage::MemoryReport;
 pub use paint_engine::PaintEngine;
 pub use pixels::UPixels8;
 pub use player::Player;
 pub use recorder::Recorder;
-pub use tile::{AttachedTile, BaseTile, DetachedTile, Tile};
+pub use recording_filter::{filter_recording, FilterOptions};
+pub use tile::{
+    tile_memory_cache_drain, tile_memory_cache_limit_set, tile_memory_usage, AttachedTile,
+    AttachedTransientTile, BaseTile, DetachedTile, DetachedTransientTile, Tile, TransientTile,
+};
 pub use timeline::{
     AttachedTransientTimeline, BaseTimeline, DetachedTransientTimeline, TransientTimeline,
 };
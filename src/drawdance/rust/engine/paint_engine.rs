@@ -1,18 +1,20 @@
-use super::{AclState, DrawContext, Image, Player};
+use super::{AclState, BaseCanvasState, CanvasState, DrawContext, FlattenOptions, Image, Player};
 use crate::{
-    dp_error_anyhow, msg::Message, DP_AnnotationList, DP_CanvasState, DP_DocumentMetadata,
-    DP_LayerPropsList, DP_Message, DP_PaintEngine, DP_Pixel8, DP_PlayerResult, DP_Rect,
-    DP_Timeline, DP_canvas_state_decref, DP_paint_engine_free_join, DP_paint_engine_handle_inc,
-    DP_paint_engine_new_inc, DP_paint_engine_playback_begin, DP_paint_engine_playback_play,
+    dp_error_anyhow,
+    msg::{Chat, Message},
+    DP_AnnotationList, DP_CanvasState, DP_DocumentMetadata, DP_LayerDiffEvent, DP_LayerPropsList,
+    DP_Message, DP_PaintEngine, DP_Pixel8, DP_PlayerResult, DP_Rect, DP_Timeline,
+    DP_canvas_state_decref, DP_paint_engine_area_changed_set, DP_paint_engine_free_join,
+    DP_paint_engine_handle_inc, DP_paint_engine_layer_diff_set, DP_paint_engine_new_inc,
+    DP_paint_engine_playback_begin, DP_paint_engine_playback_play,
     DP_paint_engine_playback_skip_by, DP_paint_engine_playback_step,
     DP_paint_engine_render_everything, DP_paint_engine_tick, DP_paint_engine_view_canvas_state_inc,
     DP_save, DP_PLAYER_RECORDING_END, DP_PLAYER_SUCCESS, DP_SAVE_IMAGE_ORA, DP_SAVE_RESULT_SUCCESS,
-    DP_TILE_SIZE,
 };
 use anyhow::Result;
 use std::{
-    ffi::{c_int, c_longlong, c_uint, c_void, CString},
-    ptr,
+    ffi::{c_char, c_int, c_longlong, c_uint, c_void, CString},
+    mem, ptr, slice,
     sync::{
         mpsc::{sync_channel, Receiver, SyncSender},
         Barrier,
@@ -20,6 +22,8 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+// Neither Send nor Sync, since the engine wants all calls from the thread that
+// created it. Its own threads call back into it through the user pointer.
 pub struct PaintEngine {
     paint_dc: DrawContext,
     main_dc: DrawContext,
@@ -27,15 +31,13 @@ pub struct PaintEngine {
     acls: AclState,
     paint_engine: *mut DP_PaintEngine,
     render_barrier: Barrier,
-    render_width: usize,
-    render_height: usize,
-    render_image: Vec<u32>,
     playback_channel: (SyncSender<c_longlong>, Receiver<c_longlong>),
+    chat: Vec<Chat>,
+    changed_area: Option<DP_Rect>,
+    layer_events: Vec<DP_LayerDiffEvent>,
 }
 
 impl PaintEngine {
-    const TILE_SIZE: usize = DP_TILE_SIZE as usize;
-
     pub fn new(player: Option<Player>) -> Box<Self> {
         let mut pe = Box::new(Self {
             paint_dc: DrawContext::default(),
@@ -44,10 +46,10 @@ impl PaintEngine {
             acls: AclState::default(),
             paint_engine: ptr::null_mut(),
             render_barrier: Barrier::new(2),
-            render_width: 0,
-            render_height: 0,
-            render_image: Vec::new(),
             playback_channel: sync_channel(1),
+            chat: Vec::new(),
+            changed_area: None,
+            layer_events: Vec::new(),
         });
         let user: *mut Self = &mut *pe;
         pe.paint_engine = unsafe {
@@ -78,41 +80,25 @@ impl PaintEngine {
                 user.cast(),
             )
         };
+        unsafe {
+            DP_paint_engine_area_changed_set(
+                pe.paint_engine,
+                Some(Self::on_area_changed),
+                user.cast(),
+            );
+            DP_paint_engine_layer_diff_set(pe.paint_engine, Some(Self::on_layer_diff), user.cast());
+        };
         pe
     }
 
-    pub fn render_width(&self) -> usize {
-        self.render_width
-    }
-
-    pub fn render_height(&self) -> usize {
-        self.render_height
-    }
-
+    // Exports flatten the view canvas state themselves, so the rendered tiles
+    // only matter for knowing when rendering is done.
     extern "C" fn on_renderer_tile(
-        user: *mut c_void,
-        tile_x: c_int,
-        tile_y: c_int,
-        pixels: *mut DP_Pixel8,
+        _user: *mut c_void,
+        _tile_x: c_int,
+        _tile_y: c_int,
+        _pixels: *mut DP_Pixel8,
     ) {
-        let pe = unsafe { user.cast::<Self>().as_mut().unwrap_unchecked() };
-        let from_x = tile_x as usize * Self::TILE_SIZE;
-        let from_y = tile_y as usize * Self::TILE_SIZE;
-        let to_x = (from_x + Self::TILE_SIZE).min(pe.render_width);
-        let to_y = (from_y + Self::TILE_SIZE).min(pe.render_height);
-        let width = to_x - from_x;
-        let height = to_y - from_y;
-        for i in 0..height {
-            let src = i * Self::TILE_SIZE;
-            let dst = (from_y + i) * pe.render_width + from_x;
-            unsafe {
-                ptr::copy_nonoverlapping(
-                    pixels.cast::<u32>().add(src),
-                    pe.render_image.as_mut_ptr().add(dst),
-                    width,
-                );
-            }
-        }
     }
 
     extern "C" fn on_renderer_unlock(user: *mut c_void) {
@@ -121,20 +107,14 @@ impl PaintEngine {
     }
 
     extern "C" fn on_renderer_resize(
-        user: *mut c_void,
-        width: c_int,
-        height: c_int,
+        _user: *mut c_void,
+        _width: c_int,
+        _height: c_int,
         _prev_width: c_int,
         _prev_height: c_int,
         _offset_x: c_int,
         _offset_y: c_int,
     ) {
-        let pe = unsafe { user.cast::<Self>().as_mut().unwrap_unchecked() };
-        let w = width as usize;
-        let h = height as usize;
-        pe.render_width = w;
-        pe.render_height = h;
-        pe.render_image.resize(w * h, 0);
     }
 
     extern "C" fn on_save_point(
@@ -213,6 +193,7 @@ impl PaintEngine {
                 Some(Self::on_acls_changed),
                 Some(Self::on_laser_trail),
                 Some(Self::on_move_pointer),
+                Some(Self::on_chat),
                 user.cast(),
             )
         }
@@ -235,6 +216,29 @@ impl PaintEngine {
 
     extern "C" fn on_move_pointer(_user: *mut c_void, _context_id: c_uint, _x: c_int, _y: c_int) {}
 
+    extern "C" fn on_chat(
+        user: *mut c_void,
+        context_id: c_uint,
+        tflags: c_uint,
+        oflags: c_uint,
+        text: *const c_char,
+        length: usize,
+    ) {
+        let pe = unsafe { user.cast::<Self>().as_mut().unwrap_unchecked() };
+        pe.chat.push(Chat::from_raw(
+            context_id as u8,
+            tflags as u8,
+            oflags as u8,
+            text,
+            length,
+        ));
+    }
+
+    // Chat messages handled since the last call, oldest first.
+    pub fn take_chat(&mut self) -> Vec<Chat> {
+        mem::take(&mut self.chat)
+    }
+
     pub fn render(&mut self) {
         let user: *mut Self = self;
         let tile_bounds = DP_Rect {
@@ -266,6 +270,54 @@ impl PaintEngine {
         self.render_barrier.wait();
     }
 
+    extern "C" fn on_area_changed(
+        user: *mut c_void,
+        x: c_int,
+        y: c_int,
+        width: c_int,
+        height: c_int,
+    ) {
+        let pe = unsafe { user.cast::<Self>().as_mut().unwrap_unchecked() };
+        let (x2, y2) = (x + width - 1, y + height - 1);
+        pe.changed_area = Some(match pe.changed_area {
+            Some(r) => DP_Rect {
+                x1: r.x1.min(x),
+                y1: r.y1.min(y),
+                x2: r.x2.max(x2),
+                y2: r.y2.max(y2),
+            },
+            None => DP_Rect {
+                x1: x,
+                y1: y,
+                x2,
+                y2,
+            },
+        });
+    }
+
+    // Bounds of the canvas area that changed in the renders since the last
+    // call, in pixels and rounded out to whole tiles.
+    pub fn take_changed_area(&mut self) -> Option<DP_Rect> {
+        self.changed_area.take()
+    }
+
+    extern "C" fn on_layer_diff(
+        user: *mut c_void,
+        _lpl: *mut DP_LayerPropsList,
+        count: c_int,
+        events: *const DP_LayerDiffEvent,
+    ) {
+        let pe = unsafe { user.cast::<Self>().as_mut().unwrap_unchecked() };
+        pe.layer_events
+            .extend_from_slice(unsafe { slice::from_raw_parts(events, count as usize) });
+    }
+
+    // Changes to the layer tree in the renders since the last call, in the
+    // order they have to be applied, see DP_layer_diff_update.
+    pub fn take_layer_events(&mut self) -> Vec<DP_LayerDiffEvent> {
+        mem::take(&mut self.layer_events)
+    }
+
     extern "C" fn on_catchup(_user: *mut c_void, _progress: c_int) {}
     extern "C" fn on_reset_lock_changed(_user: *mut c_void, _locked: bool) {}
     extern "C" fn on_recorder_state_changed(_user: *mut c_void, _started: bool) {}
@@ -307,20 +359,24 @@ impl PaintEngine {
         }
     }
 
-    pub fn to_image(&self) -> Result<Image> {
-        Image::new_from_pixels(self.render_width, self.render_height, &self.render_image)
+    // Flattens what the view shows, so local changes like hidden layers apply,
+    // with the given options on top of that.
+    pub fn to_image(&self, options: &FlattenOptions) -> Result<Image> {
+        let cs = unsafe { DP_paint_engine_view_canvas_state_inc(self.paint_engine) };
+        CanvasState::new_detached_noinc_nullable(cs)
+            .ok_or_else(dp_error_anyhow)?
+            .to_flat_image(options)
     }
 
-    pub fn to_scaled_image(&mut self, width: usize, height: usize, expand: bool) -> Result<Image> {
-        Image::new_from_pixels_scaled(
-            self.render_width,
-            self.render_height,
-            &self.render_image,
-            width,
-            height,
-            expand,
-            &mut self.main_dc,
-        )
+    pub fn to_scaled_image(
+        &mut self,
+        options: &FlattenOptions,
+        width: usize,
+        height: usize,
+        expand: bool,
+    ) -> Result<Image> {
+        self.to_image(options)?
+            .to_scaled(width, height, expand, &mut self.main_dc)
     }
 
     fn check_player_result(result: DP_PlayerResult) -> Result<()> {
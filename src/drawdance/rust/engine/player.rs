@@ -53,6 +53,10 @@ impl Player {
         player
     }
 
+    pub(crate) fn as_ptr(&mut self) -> *mut DP_Player {
+        self.player
+    }
+
     pub fn player_type(&self) -> DP_PlayerType {
         unsafe { DP_player_type(self.player) }
     }
@@ -63,6 +63,10 @@ impl Recorder {
         }
     }
 
+    pub(crate) fn as_ptr(&mut self) -> *mut DP_Recorder {
+        self.recorder
+    }
+
     pub fn push_noinc(&mut self, msg: Message) -> bool {
         unsafe { DP_recorder_message_push_noinc(self.recorder, msg.move_to_ptr()) }
     }
@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use super::{Player, Recorder};
+use crate::{dp_error_anyhow, DP_RecordingFilterOptions, DP_recording_filter};
+use anyhow::Result;
+use std::ffi::c_int;
+
+// What to leave out when filtering a recording. The defaults leave everything
+// in, so the filtered recording is just a copy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FilterOptions {
+    // Drops undone actions, along with all undos and redos.
+    pub remove_undone: bool,
+    // Drops chat and server chatter.
+    pub remove_chat: bool,
+    // Drops laser trails and pointer movements.
+    pub remove_lasers: bool,
+    // Merges intervals and caps them at this many milliseconds, if any.
+    pub max_interval_ms: Option<u16>,
+}
+
+impl FilterOptions {
+    fn to_c(self) -> DP_RecordingFilterOptions {
+        DP_RecordingFilterOptions {
+            remove_undone: self.remove_undone,
+            remove_chat: self.remove_chat,
+            remove_lasers: self.remove_lasers,
+            max_interval_ms: self.max_interval_ms.map_or(0, c_int::from),
+        }
+    }
+}
+
+// Writes the player's recording into the recorder, minus what the options say
+// to leave out. The player has to be able to rewind, so it can't be reading
+// from stdin if undone actions are being removed.
+pub fn filter_recording(
+    player: &mut Player,
+    recorder: &mut Recorder,
+    options: &FilterOptions,
+) -> Result<()> {
+    let coptions = options.to_c();
+    if unsafe { DP_recording_filter(player.as_ptr(), recorder.as_ptr(), &coptions) } {
+        Ok(())
+    } else {
+        Err(dp_error_anyhow())
+    }
+}
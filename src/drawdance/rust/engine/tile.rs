@@ -1,6 +1,27 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
-use super::{Attached, Detached};
-use crate::{DP_Tile, DP_tile_transient};
+use super::{Attached, CArc, Detached};
+use crate::{
+    DP_Pixel15, DP_Tile, DP_TileMemoryStatistics, DP_TransientTile, DP_tile_blank,
+    DP_tile_checksum, DP_tile_memory_cache_drain, DP_tile_memory_cache_limit_set,
+    DP_tile_memory_usage, DP_tile_same_pixel, DP_tile_transient, DP_transient_tile_clear,
+    DP_transient_tile_decref, DP_transient_tile_incref, DP_transient_tile_pixel_at_set,
+};
+use std::ffi::c_int;
+use std::mem::MaybeUninit;
+
+// Tile memory is global, freed tiles get cached for reuse up to a limit.
+pub fn tile_memory_usage() -> DP_TileMemoryStatistics {
+    unsafe { DP_tile_memory_usage() }
+}
+
+pub fn tile_memory_cache_limit_set(bytes: usize) {
+    unsafe { DP_tile_memory_cache_limit_set(bytes) }
+}
+
+// Gives all cached tile memory back to the system.
+pub fn tile_memory_cache_drain() {
+    unsafe { DP_tile_memory_cache_drain() }
+}
 
 pub trait BaseTile {
     fn persistent_ptr(&self) -> *mut DP_Tile;
@@ -8,6 +29,29 @@ pub trait BaseTile {
     fn transient(&self) -> bool {
         unsafe { DP_tile_transient(self.persistent_ptr()) }
     }
+
+    // Whether every pixel is fully transparent, cached the same way as the
+    // solid color below.
+    fn blank(&self) -> bool {
+        unsafe { DP_tile_blank(self.persistent_ptr()) }
+    }
+
+    // The premultiplied color if every pixel in the tile is the same. Cached
+    // on the tile, so it's only expensive the first time around.
+    fn solid_color(&self) -> Option<DP_Pixel15> {
+        let mut pixel = MaybeUninit::<DP_Pixel15>::uninit();
+        if unsafe { DP_tile_same_pixel(self.persistent_ptr(), pixel.as_mut_ptr()) } {
+            Some(unsafe { pixel.assume_init() })
+        } else {
+            None
+        }
+    }
+
+    // Platform-independent hash of the pixel data, blank tiles always give
+    // DP_TILE_CHECKSUM_BLANK. Not cryptographically secure.
+    fn checksum(&self) -> u64 {
+        unsafe { DP_tile_checksum(self.persistent_ptr()) }
+    }
 }
 
 pub struct Tile {
@@ -18,10 +62,11 @@ pub type AttachedTile<'a, P> = Attached<'a, Tile, P>;
 pub type DetachedTile = Detached<DP_Tile, Tile>;
 
 impl Tile {
-    pub fn new_attached<P>(data: &mut DP_Tile) -> AttachedTile<P> {
+    pub fn new_attached<P>(data: &mut DP_Tile) -> AttachedTile<'_, P> {
         Attached::new(Self { data })
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn new_attached_nullable<'a, P>(data: *mut DP_Tile) -> Option<AttachedTile<'a, P>> {
         unsafe { data.as_mut() }.map(Tile::new_attached)
     }
@@ -32,3 +77,44 @@ impl BaseTile for Tile {
         self.data
     }
 }
+
+pub struct TransientTile {
+    data: *mut DP_TransientTile,
+}
+
+pub type AttachedTransientTile<'a, P> = Attached<'a, TransientTile, P>;
+pub type DetachedTransientTile = Detached<DP_TransientTile, TransientTile>;
+
+impl TransientTile {
+    pub fn new_attached<P>(data: &mut DP_TransientTile) -> AttachedTransientTile<'_, P> {
+        Attached::new(Self { data })
+    }
+
+    pub fn set_pixel_at(&mut self, x: c_int, y: c_int, pixel: DP_Pixel15) {
+        unsafe { DP_transient_tile_pixel_at_set(self.data, x, y, pixel) }
+    }
+
+    pub fn clear(&mut self) {
+        unsafe { DP_transient_tile_clear(self.data) }
+    }
+}
+
+impl BaseTile for TransientTile {
+    fn persistent_ptr(&self) -> *mut DP_Tile {
+        self.data.cast()
+    }
+}
+
+impl CArc<DP_TransientTile> for TransientTile {
+    unsafe fn incref(&mut self) {
+        DP_transient_tile_incref(self.data);
+    }
+
+    unsafe fn decref(&mut self) {
+        DP_transient_tile_decref(self.data);
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut DP_TransientTile {
+        self.data
+    }
+}
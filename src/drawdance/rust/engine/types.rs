@@ -8,7 +8,14 @@ use std::{
 // C-side atomic reference counted types.
 
 pub trait CArc<T> {
+    /// # Safety
+    ///
+    /// The wrapped pointer must point to a live object.
     unsafe fn incref(&mut self);
+    /// # Safety
+    ///
+    /// The wrapped pointer must point to a live object and must not be used
+    /// again if this drops the last reference.
     unsafe fn decref(&mut self);
     fn as_mut_ptr(&mut self) -> *mut T;
 }
@@ -1,9 +1,24 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 #![allow(non_camel_case_types)]
 #![allow(clippy::unseparated_literal_suffix)]
-use std::ffi::{c_char, CStr, CString};
+use std::{
+    any::Any,
+    ffi::{c_char, CStr, CString},
+};
 
-include!("bindings.rs");
+// The generated layout tests are named after the C types.
+#[allow(non_snake_case)]
+mod bindings {
+    include!("bindings.rs");
+}
+pub use bindings::*;
+
+// The tile size is a build option, so it's left out of the generated bindings.
+#[cfg(not(feature = "tile-32"))]
+pub const DP_TILE_SIZE: u32 = 64;
+#[cfg(feature = "tile-32")]
+pub const DP_TILE_SIZE: u32 = 32;
+pub const DP_TILE_LENGTH: u32 = DP_TILE_SIZE * DP_TILE_SIZE;
 
 extern "C" {
     pub fn DP_cpu_support_init();
@@ -34,6 +49,20 @@ pub fn dp_error_set(message: &str) {
     unsafe { DP_error_set(b"%s\0".as_ptr().cast(), cstring.as_ptr()) }
 }
 
+// For exported functions that catch panics, so that the C side gets the panic
+// message in DP_error instead of just a generic internal error result.
+pub fn dp_error_set_panic(doing: &str, payload: &(dyn Any + Send)) {
+    let message = if let Some(s) = payload.downcast_ref::<&str>() {
+        Some(*s)
+    } else {
+        payload.downcast_ref::<String>().map(String::as_str)
+    };
+    match message {
+        Some(m) => dp_error_set(&format!("Panic while {doing}: {m}")),
+        None => dp_error_set(&format!("Panic while {doing}")),
+    }
+}
+
 pub fn dp_cmake_config_version() -> String {
     unsafe { CStr::from_ptr(DP_cmake_config_version()) }
         .to_str()
@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use super::Message;
+use crate::{
+    DP_message_opaque, DP_msg_chat_cast, DP_msg_chat_message, DP_msg_chat_new, DP_msg_chat_oflags,
+    DP_msg_chat_tflags, DP_MSG_CHAT, DP_MSG_CHAT_MESSAGE_MAX_LEN, DP_MSG_CHAT_OFLAGS_ACTION,
+    DP_MSG_CHAT_OFLAGS_ALERT, DP_MSG_CHAT_OFLAGS_PIN, DP_MSG_CHAT_OFLAGS_SHOUT,
+    DP_MSG_CHAT_TFLAGS_BYPASS,
+};
+use anyhow::{anyhow, Result};
+use std::{ffi::c_char, slice};
+
+// Bypass is a transport flag: the server doesn't put the message into the
+// session history and recorders leave it out. The rest are about how the
+// message is shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChatFlags {
+    pub bypass: bool,
+    // Shouts are shown over the canvas, not just in the chat box.
+    pub shout: bool,
+    // A /me message.
+    pub action: bool,
+    pub pin: bool,
+    pub alert: bool,
+}
+
+impl ChatFlags {
+    pub fn from_bits(tflags: u8, oflags: u8) -> Self {
+        let has = |flags: u8, flag: u32| u32::from(flags) & flag != 0;
+        ChatFlags {
+            bypass: has(tflags, DP_MSG_CHAT_TFLAGS_BYPASS),
+            shout: has(oflags, DP_MSG_CHAT_OFLAGS_SHOUT),
+            action: has(oflags, DP_MSG_CHAT_OFLAGS_ACTION),
+            pin: has(oflags, DP_MSG_CHAT_OFLAGS_PIN),
+            alert: has(oflags, DP_MSG_CHAT_OFLAGS_ALERT),
+        }
+    }
+
+    pub fn tflags(self) -> u8 {
+        bits(&[(self.bypass, DP_MSG_CHAT_TFLAGS_BYPASS)])
+    }
+
+    pub fn oflags(self) -> u8 {
+        bits(&[
+            (self.shout, DP_MSG_CHAT_OFLAGS_SHOUT),
+            (self.action, DP_MSG_CHAT_OFLAGS_ACTION),
+            (self.pin, DP_MSG_CHAT_OFLAGS_PIN),
+            (self.alert, DP_MSG_CHAT_OFLAGS_ALERT),
+        ])
+    }
+}
+
+fn bits(flags: &[(bool, u32)]) -> u8 {
+    flags
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |acc, (_, flag)| acc | *flag as u8)
+}
+
+// A chat message. Ones from context id 0 come from the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chat {
+    context_id: u8,
+    flags: ChatFlags,
+    text: String,
+}
+
+impl Chat {
+    // In bytes of UTF-8, what's left of a message body after the flags.
+    pub const MAX_TEXT_LENGTH: usize = DP_MSG_CHAT_MESSAGE_MAX_LEN as usize;
+
+    pub fn new(context_id: u8, flags: ChatFlags, text: &str) -> Result<Self> {
+        if text.len() > Self::MAX_TEXT_LENGTH {
+            Err(anyhow!(
+                "Chat message too long, {} bytes, maximum is {}",
+                text.len(),
+                Self::MAX_TEXT_LENGTH
+            ))
+        } else {
+            Ok(Chat {
+                context_id,
+                flags,
+                text: text.to_owned(),
+            })
+        }
+    }
+
+    pub fn context_id(&self) -> u8 {
+        self.context_id
+    }
+
+    pub fn flags(&self) -> ChatFlags {
+        self.flags
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Text that isn't valid UTF-8 gets replacement characters.
+    pub(crate) fn from_raw(
+        context_id: u8,
+        tflags: u8,
+        oflags: u8,
+        text: *const c_char,
+        length: usize,
+    ) -> Self {
+        let bytes = if length == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(text.cast::<u8>(), length) }
+        };
+        Chat {
+            context_id,
+            flags: ChatFlags::from_bits(tflags, oflags),
+            text: String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    // Returns None if this isn't a chat message or it wasn't decoded.
+    pub fn from_message(msg: &Message) -> Option<Self> {
+        if msg.message_type() != DP_MSG_CHAT || unsafe { DP_message_opaque(msg.as_ptr()) } {
+            return None;
+        }
+        let mc = unsafe { DP_msg_chat_cast(msg.as_ptr()) };
+        let mut length = 0;
+        let text = unsafe { DP_msg_chat_message(mc, &mut length) };
+        Some(Self::from_raw(
+            msg.context_id(),
+            unsafe { DP_msg_chat_tflags(mc) },
+            unsafe { DP_msg_chat_oflags(mc) },
+            text,
+            length,
+        ))
+    }
+
+    pub fn to_message(&self) -> Message {
+        Message::new_noinc(unsafe {
+            DP_msg_chat_new(
+                u32::from(self.context_id),
+                self.flags.tflags(),
+                self.flags.oflags(),
+                self.text.as_ptr().cast(),
+                self.text.len(),
+            )
+        })
+    }
+}
@@ -1,9 +1,47 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
+use super::{TextReader, TextWriter};
 use crate::{
-    DP_Message, DP_MessageType, DP_message_decref_nullable, DP_message_length, DP_message_type,
-    DP_message_type_name,
+    dp_error, DP_Message, DP_MessageType, DP_message_context_id, DP_message_decref_nullable,
+    DP_message_deserialize, DP_message_equals, DP_message_length, DP_message_serialize,
+    DP_message_type, DP_message_type_name, DP_MESSAGE_HEADER_LENGTH,
 };
-use std::{ffi::CStr, ptr};
+use anyhow::{anyhow, Result};
+use std::{
+    error::Error,
+    ffi::{c_uchar, c_void, CStr},
+    fmt, ptr,
+};
+
+// Bytes that don't make up a valid message in the binary protocol format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    // Fewer bytes than the header or the length in it call for.
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    // The body doesn't fit the message type, or the type is unknown.
+    Malformed {
+        message_type: DP_MessageType,
+        message: String,
+    },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { expected, actual } => {
+                write!(f, "Message truncated, need {expected} bytes, got {actual}")
+            }
+            Self::Malformed {
+                message_type,
+                message,
+            } => write!(f, "Malformed message of type {message_type}: {message}"),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
 
 #[repr(C)]
 pub struct Message {
@@ -22,6 +60,44 @@ impl Message {
         Message { msg }
     }
 
+    // Reads one message in the binary protocol format off the front of the
+    // given bytes, returning it along with the bytes after it. Lengths are
+    // checked before anything is read, so any input gives either a message or
+    // an error. Unless decode_opaque is set, messages that the client doesn't
+    // look into are kept as opaque blobs without parsing their bodies.
+    pub fn deserialize(bytes: &[u8], decode_opaque: bool) -> Result<(Self, &[u8]), ProtocolError> {
+        let header_length = DP_MESSAGE_HEADER_LENGTH as usize;
+        if bytes.len() < header_length {
+            return Err(ProtocolError::Truncated {
+                expected: header_length,
+                actual: bytes.len(),
+            });
+        }
+
+        let body_length = usize::from(u16::from_be_bytes([bytes[0], bytes[1]]));
+        let total_length = header_length + body_length;
+        if bytes.len() < total_length {
+            return Err(ProtocolError::Truncated {
+                expected: total_length,
+                actual: bytes.len(),
+            });
+        }
+
+        let msg = unsafe { DP_message_deserialize(bytes.as_ptr(), total_length, decode_opaque) };
+        if msg.is_null() {
+            Err(ProtocolError::Malformed {
+                message_type: DP_MessageType::from(bytes[2]),
+                message: dp_error(),
+            })
+        } else {
+            Ok((Message::new_noinc(msg), &bytes[total_length..]))
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut DP_Message {
+        self.msg
+    }
+
     pub fn message_type(&self) -> DP_MessageType {
         unsafe { DP_message_type(self.msg) }
     }
@@ -30,6 +106,49 @@ impl Message {
         unsafe { DP_message_length(self.msg) }
     }
 
+    pub fn context_id(&self) -> u8 {
+        unsafe { DP_message_context_id(self.msg) as u8 }
+    }
+
+    // The message in the binary protocol format, header included. This is the
+    // inverse of deserialize.
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let user: *mut Vec<u8> = &mut bytes;
+        let written =
+            unsafe { DP_message_serialize(self.msg, true, Some(Self::get_buffer), user.cast()) };
+        if written == 0 {
+            Err(anyhow!(dp_error()))
+        } else {
+            bytes.truncate(written);
+            Ok(bytes)
+        }
+    }
+
+    extern "C" fn get_buffer(user: *mut c_void, length: usize) -> *mut c_uchar {
+        let bytes = unsafe { user.cast::<Vec<u8>>().as_mut().unwrap_unchecked() };
+        bytes.resize(length, 0);
+        bytes.as_mut_ptr()
+    }
+
+    // The message in the text recording format, including the line break at
+    // the end. Fails for messages that have no text form, like opaque ones.
+    pub fn to_text(&self) -> Result<String> {
+        let mut tw = TextWriter::new_without_header(Vec::new())?;
+        tw.add_message(self)?;
+        String::from_utf8(tw.close()?).map_err(|e| anyhow!(e))
+    }
+
+    // Parses a single message in the text recording format.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut tr = TextReader::new(text.as_bytes())?;
+        match (tr.read_message()?, tr.read_message()?) {
+            (Some(msg), None) => Ok(msg),
+            (None, _) => Err(anyhow!("No message in text")),
+            (Some(_), Some(_)) => Err(anyhow!("More than one message in text")),
+        }
+    }
+
     pub fn move_to_ptr(mut self) -> *mut DP_Message {
         let msg = self.msg;
         self.msg = ptr::null_mut();
@@ -37,6 +156,23 @@ impl Message {
     }
 }
 
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        unsafe { DP_message_equals(self.msg, other.msg) }
+    }
+}
+
+// Shows the text form where there is one, otherwise just the type.
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = Self::message_type_name(self.message_type());
+        match self.to_text() {
+            Ok(text) => write!(f, "Message({name}: {})", text.trim_end()),
+            Err(_) => write!(f, "Message({name}, {} bytes)", self.length()),
+        }
+    }
+}
+
 impl Drop for Message {
     fn drop(&mut self) {
         unsafe { DP_message_decref_nullable(self.msg) }
@@ -1,5 +1,20 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+mod chat;
 mod message;
+mod recording_reader;
+mod recording_stats;
+mod recording_writer;
+mod sink;
+mod text_reader;
+mod text_writer;
 
-pub use message::Message;
+pub use chat::{Chat, ChatFlags};
+pub use message::{Message, ProtocolError};
+pub use recording_reader::{
+    RecordingCompatibility, RecordingEnd, RecordingPosition, RecordingReader,
+};
+pub use recording_stats::{analyze_recording, RecordingStats};
+pub use recording_writer::RecordingWriter;
+pub use text_reader::{TextParseError, TextReader};
+pub use text_writer::TextWriter;
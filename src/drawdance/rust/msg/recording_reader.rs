@@ -0,0 +1,379 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::{
+    dp_error_anyhow, dp_error_set, json_object_get_string, json_value_get_object, msg::Message,
+    DP_BinaryReader, DP_InputMethods, DP_Message, DP_binary_reader_free, DP_binary_reader_header,
+    DP_binary_reader_new, DP_binary_reader_read_message, DP_binary_reader_seek,
+    DP_binary_reader_tell, DP_input_new, JSON_Value, DP_BINARY_READER_ERROR_INPUT,
+    DP_BINARY_READER_INPUT_END, DP_BINARY_READER_SUCCESS,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    mem, ptr,
+};
+
+// Defined by the dpmsg crate, which depends on this one.
+extern "C" {
+    fn DP_protocol_version_parse(s: *const c_char) -> *mut c_void;
+    fn DP_protocol_version_free(protover: *mut c_void);
+    fn DP_protocol_version_is_future(protover: *const c_void) -> bool;
+    fn DP_protocol_version_client_compatibility(protover: *const c_void) -> c_int;
+}
+
+// Order of DP_ProtocolCompatibility.
+const PROTOCOL_COMPATIBLE: c_int = 0;
+const PROTOCOL_MINOR_INCOMPATIBILITY: c_int = 1;
+const PROTOCOL_BACKWARD_COMPATIBLE: c_int = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingCompatibility {
+    Compatible,
+    // Same major version, but a different minor one. Can be read, but may not
+    // play back exactly the same.
+    OlderMinor,
+    NewerMinor,
+    // From Drawpile 2.1, can be read and gets converted on playback.
+    BackwardCompatible,
+    // Unknown or missing version, or one that's too far off.
+    Incompatible,
+}
+
+impl RecordingCompatibility {
+    // Whether the version is in the future only matters for minor mismatches,
+    // so it's only asked for then.
+    fn from_protocol(compat: c_int, is_future: impl FnOnce() -> bool) -> Self {
+        match compat {
+            PROTOCOL_COMPATIBLE => RecordingCompatibility::Compatible,
+            PROTOCOL_MINOR_INCOMPATIBILITY => {
+                if is_future() {
+                    RecordingCompatibility::NewerMinor
+                } else {
+                    RecordingCompatibility::OlderMinor
+                }
+            }
+            PROTOCOL_BACKWARD_COMPATIBLE => RecordingCompatibility::BackwardCompatible,
+            _ => RecordingCompatibility::Incompatible,
+        }
+    }
+
+    pub fn is_readable(self) -> bool {
+        self != RecordingCompatibility::Incompatible
+    }
+}
+
+// Byte offset of a message in the recording and how many messages came
+// before it. Can be used to seek back to it later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordingPosition {
+    pub offset: usize,
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingEnd {
+    Complete,
+    // The file ends partway through a message, usually because whatever was
+    // writing it crashed. Everything before that position was read fine.
+    Truncated(RecordingPosition),
+}
+
+struct Source<R: Read + Seek> {
+    inner: R,
+    error: Option<io::Error>,
+}
+
+impl<R: Read + Seek> Source<R> {
+    fn fail(&mut self, error: io::Error) {
+        dp_error_set(&error.to_string());
+        self.error = Some(error);
+    }
+
+    unsafe fn from_internal<'a>(internal: *mut c_void) -> &'a mut Self {
+        &mut **internal.cast::<*mut Self>()
+    }
+
+    unsafe extern "C" fn init(internal: *mut c_void, arg: *mut c_void) -> *const DP_InputMethods {
+        *internal.cast::<*mut Self>() = arg.cast();
+        Methods::<R>::METHODS
+    }
+
+    // Fills the whole buffer unless the input ends first, so that a short
+    // read always means the end of the file rather than an error.
+    unsafe extern "C" fn read(
+        internal: *mut c_void,
+        buffer: *mut c_void,
+        size: usize,
+        out_error: *mut bool,
+    ) -> usize {
+        let source = Self::from_internal(internal);
+        let bytes = std::slice::from_raw_parts_mut(buffer.cast::<u8>(), size);
+        let mut done = 0;
+        while done < size {
+            match source.inner.read(&mut bytes[done..]) {
+                Ok(0) => break,
+                Ok(read) => done += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    source.fail(e);
+                    *out_error = true;
+                    break;
+                }
+            }
+        }
+        done
+    }
+
+    fn try_length(&mut self) -> io::Result<u64> {
+        let pos = self.inner.stream_position()?;
+        let length = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(pos))?;
+        Ok(length)
+    }
+
+    unsafe extern "C" fn length(internal: *mut c_void, out_error: *mut bool) -> usize {
+        let source = Self::from_internal(internal);
+        let result = source.try_length().and_then(|length| {
+            usize::try_from(length).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+        });
+        match result {
+            Ok(length) => length,
+            Err(e) => {
+                source.fail(e);
+                *out_error = true;
+                0
+            }
+        }
+    }
+
+    unsafe extern "C" fn seek(internal: *mut c_void, offset: usize) -> bool {
+        let source = Self::from_internal(internal);
+        match source.inner.seek(SeekFrom::Start(offset as u64)) {
+            Ok(_) => true,
+            Err(e) => {
+                source.fail(e);
+                false
+            }
+        }
+    }
+}
+
+struct Methods<R: Read + Seek>(PhantomData<R>);
+
+impl<R: Read + Seek> Methods<R> {
+    const METHODS: &'static DP_InputMethods = &DP_InputMethods {
+        read: Some(Source::<R>::read),
+        length: Some(Source::<R>::length),
+        rewind: None,
+        rewind_by: None,
+        seek: Some(Source::<R>::seek),
+        seek_by: None,
+        dispose: None,
+    };
+}
+
+// Reads binary Drawpile recordings (.dprec) from anything that can be read
+// and seeked, one message at a time. The header is checked when opening, but
+// an incompatible version isn't an error, the caller decides what to do.
+// Reading truncated and mismatched recordings is tested in
+// libmsg/test/recording_format.c, since that needs the C side linked.
+pub struct RecordingReader<R: Read + Seek> {
+    reader: *mut DP_BinaryReader,
+    source: Box<Source<R>>,
+    index: usize,
+    end: Option<RecordingEnd>,
+}
+
+impl<R: Read + Seek> RecordingReader<R> {
+    // Fails if the magic or header can't be read.
+    pub fn new(inner: R) -> Result<Self> {
+        let mut source = Box::new(Source { inner, error: None });
+        let source_ptr: *mut Source<R> = &mut *source;
+        let input = unsafe {
+            DP_input_new(
+                Some(Source::<R>::init),
+                source_ptr.cast(),
+                mem::size_of::<*mut Source<R>>(),
+            )
+        };
+        if input.is_null() {
+            return Err(dp_error_anyhow());
+        }
+
+        let reader = unsafe { DP_binary_reader_new(input, 0) };
+        if reader.is_null() {
+            Err(dp_error_anyhow())
+        } else {
+            Ok(RecordingReader {
+                reader,
+                source,
+                index: 0,
+                end: None,
+            })
+        }
+    }
+
+    pub fn header(&self) -> *mut JSON_Value {
+        unsafe { DP_binary_reader_header(self.reader) }
+    }
+
+    pub fn header_string(&self, key: &str) -> Option<String> {
+        let ckey = CString::new(key).ok()?;
+        let value =
+            unsafe { json_object_get_string(json_value_get_object(self.header()), ckey.as_ptr()) };
+        if value.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(value) }.to_str().ok()?.to_owned())
+        }
+    }
+
+    pub fn format_version(&self) -> Option<String> {
+        self.header_string("version")
+    }
+
+    pub fn writer_version(&self) -> Option<String> {
+        self.header_string("writerversion")
+    }
+
+    pub fn compatibility(&self) -> RecordingCompatibility {
+        let version = match self.format_version().and_then(|v| CString::new(v).ok()) {
+            Some(version) => version,
+            None => return RecordingCompatibility::Incompatible,
+        };
+
+        let protover = unsafe { DP_protocol_version_parse(version.as_ptr()) };
+        if protover.is_null() {
+            return RecordingCompatibility::Incompatible;
+        }
+
+        let compat = RecordingCompatibility::from_protocol(
+            unsafe { DP_protocol_version_client_compatibility(protover) },
+            || unsafe { DP_protocol_version_is_future(protover) },
+        );
+        unsafe { DP_protocol_version_free(protover) };
+        compat
+    }
+
+    // Where the next message will be read from.
+    pub fn tell(&self) -> RecordingPosition {
+        RecordingPosition {
+            offset: unsafe { DP_binary_reader_tell(self.reader) },
+            index: self.index,
+        }
+    }
+
+    // Set once reading is done, either because the file ended cleanly or
+    // because it got cut off. Seeking clears it again.
+    pub fn end(&self) -> Option<RecordingEnd> {
+        self.end
+    }
+
+    pub fn seek(&mut self, position: RecordingPosition) -> Result<()> {
+        if unsafe { DP_binary_reader_seek(self.reader, position.offset) } {
+            self.index = position.index;
+            self.end = None;
+            Ok(())
+        } else {
+            Err(self.take_error())
+        }
+    }
+
+    // Returns the next message with its position, or None at the end. Messages
+    // that can't be parsed are errors, but reading can continue after them.
+    pub fn read_message(&mut self) -> Result<Option<(RecordingPosition, Message)>> {
+        if self.end.is_some() {
+            return Ok(None);
+        }
+
+        let position = self.tell();
+        let mut msg: *mut DP_Message = ptr::null_mut();
+        let result = unsafe { DP_binary_reader_read_message(self.reader, true, &mut msg) };
+        if result == DP_BINARY_READER_SUCCESS {
+            self.index += 1;
+            Ok(Some((position, Message::new_noinc(msg))))
+        } else if result == DP_BINARY_READER_INPUT_END {
+            self.end = Some(RecordingEnd::Complete);
+            Ok(None)
+        } else if result == DP_BINARY_READER_ERROR_INPUT {
+            if let Some(e) = self.source.error.take() {
+                Err(anyhow!(e))
+            } else {
+                // Short read, the file ends partway through this message. Go
+                // back to its start, in case more gets written to it later.
+                unsafe { DP_binary_reader_seek(self.reader, position.offset) };
+                self.end = Some(RecordingEnd::Truncated(position));
+                Ok(None)
+            }
+        } else {
+            self.index += 1;
+            Err(dp_error_anyhow())
+        }
+    }
+
+    fn take_error(&mut self) -> anyhow::Error {
+        match self.source.error.take() {
+            Some(e) => anyhow!(e),
+            None => dp_error_anyhow(),
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for RecordingReader<R> {
+    type Item = Result<(RecordingPosition, Message)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+impl<R: Read + Seek> Drop for RecordingReader<R> {
+    fn drop(&mut self) {
+        unsafe { DP_binary_reader_free(self.reader) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compatibility_from_protocol() {
+        let from = |compat, future| RecordingCompatibility::from_protocol(compat, || future);
+        assert_eq!(
+            from(PROTOCOL_COMPATIBLE, true),
+            RecordingCompatibility::Compatible
+        );
+        assert_eq!(
+            from(PROTOCOL_MINOR_INCOMPATIBILITY, false),
+            RecordingCompatibility::OlderMinor
+        );
+        assert_eq!(
+            from(PROTOCOL_MINOR_INCOMPATIBILITY, true),
+            RecordingCompatibility::NewerMinor
+        );
+        assert_eq!(
+            from(PROTOCOL_BACKWARD_COMPATIBLE, false),
+            RecordingCompatibility::BackwardCompatible
+        );
+        assert_eq!(from(3, false), RecordingCompatibility::Incompatible);
+        assert_eq!(from(-1, false), RecordingCompatibility::Incompatible);
+    }
+
+    #[test]
+    fn only_incompatible_is_unreadable() {
+        assert!(RecordingCompatibility::Compatible.is_readable());
+        assert!(RecordingCompatibility::OlderMinor.is_readable());
+        assert!(RecordingCompatibility::NewerMinor.is_readable());
+        assert!(RecordingCompatibility::BackwardCompatible.is_readable());
+        assert!(!RecordingCompatibility::Incompatible.is_readable());
+    }
+
+    #[test]
+    fn future_only_asked_for_minor_mismatch() {
+        for compat in [PROTOCOL_COMPATIBLE, PROTOCOL_BACKWARD_COMPATIBLE, 3] {
+            RecordingCompatibility::from_protocol(compat, || panic!("asked for {compat}"));
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::{
+    json_free_serialized_string, json_serialize_to_string_pretty, json_value_free,
+    msg::{RecordingEnd, RecordingReader},
+    DP_RecordingStats, DP_RecordingStatsLayer, DP_RecordingStatsResize, DP_RecordingStatsTotals,
+    DP_RecordingStatsUser, DP_recording_stats_free, DP_recording_stats_handle,
+    DP_recording_stats_handle_unparseable, DP_recording_stats_layer_at,
+    DP_recording_stats_layer_count, DP_recording_stats_new, DP_recording_stats_resize_at,
+    DP_recording_stats_resize_count, DP_recording_stats_to_json, DP_recording_stats_totals,
+    DP_recording_stats_truncated_set, DP_recording_stats_user,
+};
+use anyhow::Result;
+use std::{
+    ffi::{c_uint, CStr},
+    io::{Read, Seek},
+};
+
+// Statistics over the messages in a recording, see recording_stats.h.
+pub struct RecordingStats {
+    stats: *mut DP_RecordingStats,
+}
+
+impl RecordingStats {
+    pub fn new() -> Self {
+        RecordingStats {
+            stats: unsafe { DP_recording_stats_new() },
+        }
+    }
+
+    pub fn totals(&self) -> &DP_RecordingStatsTotals {
+        unsafe { &*DP_recording_stats_totals(self.stats) }
+    }
+
+    pub fn user(&self, context_id: u8) -> Option<&DP_RecordingStatsUser> {
+        unsafe { DP_recording_stats_user(self.stats, c_uint::from(context_id)).as_ref() }
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &DP_RecordingStatsUser> {
+        (0..=u8::MAX).filter_map(|context_id| self.user(context_id))
+    }
+
+    pub fn resizes(&self) -> impl Iterator<Item = &DP_RecordingStatsResize> {
+        let count = unsafe { DP_recording_stats_resize_count(self.stats) };
+        (0..count).map(|i| unsafe { &*DP_recording_stats_resize_at(self.stats, i) })
+    }
+
+    pub fn layers(&self) -> impl Iterator<Item = &DP_RecordingStatsLayer> {
+        let count = unsafe { DP_recording_stats_layer_count(self.stats) };
+        (0..count).map(|i| unsafe { &*DP_recording_stats_layer_at(self.stats, i) })
+    }
+
+    pub fn to_json(&self) -> String {
+        unsafe {
+            let value = DP_recording_stats_to_json(self.stats);
+            let buffer = json_serialize_to_string_pretty(value);
+            let json = CStr::from_ptr(buffer).to_string_lossy().into_owned();
+            json_free_serialized_string(buffer);
+            json_value_free(value);
+            json
+        }
+    }
+}
+
+impl Default for RecordingStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for RecordingStats {
+    fn drop(&mut self) {
+        unsafe { DP_recording_stats_free(self.stats) }
+    }
+}
+
+// Reads the rest of the recording. Messages that can't be parsed are
+// counted and skipped, errors reading the input itself are returned.
+pub fn analyze_recording<R: Read + Seek>(
+    reader: &mut RecordingReader<R>,
+) -> Result<RecordingStats> {
+    let stats = RecordingStats::new();
+    loop {
+        let index = reader.tell().index;
+        match reader.read_message() {
+            Ok(Some((_, msg))) => unsafe { DP_recording_stats_handle(stats.stats, msg.as_ptr()) },
+            Ok(None) => break,
+            Err(_) if reader.tell().index != index => unsafe {
+                DP_recording_stats_handle_unparseable(stats.stats)
+            },
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some(RecordingEnd::Truncated(_)) = reader.end() {
+        unsafe { DP_recording_stats_truncated_set(stats.stats) };
+    }
+    Ok(stats)
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use super::sink::Sink;
+use crate::{
+    dp_error_anyhow, json_object_set_string, json_value_free, json_value_get_object,
+    json_value_init_object, msg::Message, DP_BinaryWriter, DP_binary_writer_free,
+    DP_binary_writer_new, DP_binary_writer_write_header, DP_binary_writer_write_message,
+    DP_message_bypasses_recording, JSON_Value, DP_PROTOCOL_VERSION,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    ffi::{CStr, CString},
+    io::Write,
+    ptr,
+};
+
+// Writes binary Drawpile recordings (.dprec) to anything that can be written
+// to, using the same writer as the client and server recorders. The format has
+// no compression, so there's none here either. The format it produces is
+// tested in libmsg/test/recording_format.c, since that needs the C side linked.
+pub struct RecordingWriter<W: Write> {
+    writer: *mut DP_BinaryWriter,
+    sink: Option<Box<Sink<W>>>,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    // Writes the header right away. The protocol version is filled in, the
+    // given metadata is added after it, like the writer and writerversion.
+    pub fn new(inner: W, metadata: &[(&str, &str)]) -> Result<Self> {
+        let (sink, output) = Sink::new_output(inner)?;
+        let rw = RecordingWriter {
+            writer: unsafe { DP_binary_writer_new(output) },
+            sink: Some(sink),
+        };
+        let header = make_header(metadata)?;
+        let ok = unsafe { DP_binary_writer_write_header(rw.writer, json_value_get_object(header)) };
+        unsafe { json_value_free(header) };
+        if ok {
+            Ok(rw)
+        } else {
+            Err(dp_error_anyhow())
+        }
+    }
+
+    // Messages that bypass recording, like out-of-band chat, are skipped.
+    pub fn add_message(&mut self, msg: &Message) -> Result<()> {
+        if unsafe { DP_message_bypasses_recording(msg.as_ptr()) } {
+            Ok(())
+        } else if unsafe { DP_binary_writer_write_message(self.writer, msg.as_ptr()) } == 0 {
+            Err(dp_error_anyhow())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Flushes everything out and gives back what was written to.
+    pub fn close(mut self) -> Result<W> {
+        unsafe { DP_binary_writer_free(self.writer) };
+        self.writer = ptr::null_mut();
+        self.sink.take().unwrap().into_inner()
+    }
+}
+
+impl<W: Write> Drop for RecordingWriter<W> {
+    fn drop(&mut self) {
+        unsafe { DP_binary_writer_free(self.writer) }
+    }
+}
+
+fn set_header_string(header: *mut JSON_Value, key: &str, value: &str) -> Result<()> {
+    let ckey = CString::new(key)?;
+    let cvalue = CString::new(value)?;
+    let result = unsafe {
+        json_object_set_string(
+            json_value_get_object(header),
+            ckey.as_ptr(),
+            cvalue.as_ptr(),
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("Error setting recording header {key} = {value}"))
+    }
+}
+
+pub(super) fn make_header(metadata: &[(&str, &str)]) -> Result<*mut JSON_Value> {
+    let header = unsafe { json_value_init_object() };
+    if header.is_null() {
+        return Err(anyhow!("Error creating recording header object"));
+    }
+    let version = CStr::from_bytes_with_nul(DP_PROTOCOL_VERSION)?.to_str()?;
+    let result = set_header_string(header, "version", version).and_then(|()| {
+        metadata
+            .iter()
+            .try_for_each(|(key, value)| set_header_string(header, key, value))
+    });
+    match result {
+        Ok(()) => Ok(header),
+        Err(e) => {
+            unsafe { json_value_free(header) };
+            Err(e)
+        }
+    }
+}
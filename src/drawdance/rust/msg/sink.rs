@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::{dp_error_anyhow, dp_error_set, DP_Output, DP_OutputMethods, DP_output_new};
+use anyhow::{anyhow, Result};
+use std::{
+    ffi::c_void,
+    io::{self, Write},
+    marker::PhantomData,
+    mem,
+};
+
+// Lets the C writers output to anything that implements Write. The DP_Output
+// only holds a pointer to this, the sink itself stays owned by the Rust side.
+pub(super) struct Sink<W: Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Sink<W> {
+    // The output must be freed before the sink is dropped.
+    pub(super) fn new_output(inner: W) -> Result<(Box<Self>, *mut DP_Output)> {
+        let mut sink = Box::new(Sink { inner, error: None });
+        let sink_ptr: *mut Self = &mut *sink;
+        let output = unsafe {
+            DP_output_new(
+                Some(Self::init),
+                sink_ptr.cast(),
+                mem::size_of::<*mut Self>(),
+            )
+        };
+        if output.is_null() {
+            Err(dp_error_anyhow())
+        } else {
+            Ok((sink, output))
+        }
+    }
+
+    // Gives back what was written to, unless writing to it failed.
+    pub(super) fn into_inner(self) -> Result<W> {
+        match self.error {
+            Some(e) => Err(anyhow!(e)),
+            None => Ok(self.inner),
+        }
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        dp_error_set(&error.to_string());
+        self.error = Some(error);
+    }
+
+    unsafe fn from_internal<'a>(internal: *mut c_void) -> &'a mut Self {
+        &mut **internal.cast::<*mut Self>()
+    }
+
+    unsafe extern "C" fn init(internal: *mut c_void, arg: *mut c_void) -> *const DP_OutputMethods {
+        *internal.cast::<*mut Self>() = arg.cast();
+        Methods::<W>::METHODS
+    }
+
+    unsafe extern "C" fn write(internal: *mut c_void, buffer: *const c_void, size: usize) -> usize {
+        let sink = Self::from_internal(internal);
+        let bytes = std::slice::from_raw_parts(buffer.cast::<u8>(), size);
+        match sink.inner.write_all(bytes) {
+            Ok(()) => size,
+            Err(e) => {
+                sink.fail(e);
+                0
+            }
+        }
+    }
+
+    unsafe extern "C" fn flush(internal: *mut c_void) -> bool {
+        let sink = Self::from_internal(internal);
+        match sink.inner.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                sink.fail(e);
+                false
+            }
+        }
+    }
+}
+
+struct Methods<W: Write>(PhantomData<W>);
+
+impl<W: Write> Methods<W> {
+    const METHODS: &'static DP_OutputMethods = &DP_OutputMethods {
+        write: Some(Sink::<W>::write),
+        clear: None,
+        flush: Some(Sink::<W>::flush),
+        tell: None,
+        seek: None,
+        dispose: Some(Sink::<W>::flush),
+    };
+}
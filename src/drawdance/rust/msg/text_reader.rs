@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use crate::{
+    dp_error, dp_error_anyhow, dp_error_set, msg::Message, DP_InputMethods, DP_Message,
+    DP_TextReader, DP_input_new, DP_text_reader_error_position, DP_text_reader_free,
+    DP_text_reader_line, DP_text_reader_new, DP_text_reader_read_header_field,
+    DP_text_reader_read_message, DP_TEXT_READER_ERROR_INPUT, DP_TEXT_READER_HEADER_END,
+    DP_TEXT_READER_INPUT_END, DP_TEXT_READER_SUCCESS,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    error::Error,
+    ffi::{c_char, c_void, CStr},
+    fmt,
+    io::{self, BufRead, ErrorKind},
+    marker::PhantomData,
+    mem, ptr,
+};
+
+// Malformed input in a text recording. Lines and columns start at 1, a line
+// of 0 means the position isn't known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TextParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(
+                f,
+                "Line {}, column {}: {}",
+                self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+impl Error for TextParseError {}
+
+struct Source<R: BufRead> {
+    inner: R,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> Source<R> {
+    unsafe fn from_internal<'a>(internal: *mut c_void) -> &'a mut Self {
+        &mut **internal.cast::<*mut Self>()
+    }
+
+    unsafe extern "C" fn init(internal: *mut c_void, arg: *mut c_void) -> *const DP_InputMethods {
+        *internal.cast::<*mut Self>() = arg.cast();
+        Methods::<R>::METHODS
+    }
+
+    unsafe extern "C" fn read(
+        internal: *mut c_void,
+        buffer: *mut c_void,
+        size: usize,
+        out_error: *mut bool,
+    ) -> usize {
+        let source = Self::from_internal(internal);
+        let bytes = std::slice::from_raw_parts_mut(buffer.cast::<u8>(), size);
+        loop {
+            match source.inner.read(bytes) {
+                Ok(read) => return read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    dp_error_set(&e.to_string());
+                    source.error = Some(e);
+                    *out_error = true;
+                    return 0;
+                }
+            }
+        }
+    }
+
+    // The input is streamed, so the length isn't known. That only affects
+    // progress reporting, which this doesn't do.
+    unsafe extern "C" fn length(_internal: *mut c_void, _out_error: *mut bool) -> usize {
+        0
+    }
+}
+
+struct Methods<R: BufRead>(PhantomData<R>);
+
+impl<R: BufRead> Methods<R> {
+    const METHODS: &'static DP_InputMethods = &DP_InputMethods {
+        read: Some(Source::<R>::read),
+        length: Some(Source::<R>::length),
+        rewind: None,
+        rewind_by: None,
+        seek: None,
+        seek_by: None,
+        dispose: None,
+    };
+}
+
+// Reads text Drawpile recordings (.dptxt) one message at a time. Comments and
+// blank lines are skipped, so are fields that a message doesn't know about,
+// with a warning. Malformed messages give a TextParseError, reading can
+// continue after them.
+pub struct TextReader<R: BufRead> {
+    reader: *mut DP_TextReader,
+    source: Box<Source<R>>,
+    header: Vec<(String, String)>,
+}
+
+impl<R: BufRead> TextReader<R> {
+    // Reads the header right away, it's empty if there's none.
+    pub fn new(inner: R) -> Result<Self> {
+        let mut source = Box::new(Source { inner, error: None });
+        let source_ptr: *mut Source<R> = &mut *source;
+        let input = unsafe {
+            DP_input_new(
+                Some(Source::<R>::init),
+                source_ptr.cast(),
+                mem::size_of::<*mut Source<R>>(),
+            )
+        };
+        if input.is_null() {
+            return Err(dp_error_anyhow());
+        }
+
+        let reader = unsafe { DP_text_reader_new(input) };
+        if reader.is_null() {
+            return Err(dp_error_anyhow());
+        }
+
+        let mut tr = TextReader {
+            reader,
+            source,
+            header: Vec::new(),
+        };
+        tr.read_header()?;
+        Ok(tr)
+    }
+
+    pub fn header(&self) -> &[(String, String)] {
+        &self.header
+    }
+
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // The line last read from, starting at 1.
+    pub fn line(&self) -> usize {
+        unsafe { DP_text_reader_line(self.reader) }
+    }
+
+    // Returns the next message, or None at the end.
+    pub fn read_message(&mut self) -> Result<Option<Message>> {
+        let mut msg: *mut DP_Message = ptr::null_mut();
+        let result = unsafe { DP_text_reader_read_message(self.reader, &mut msg) };
+        if result == DP_TEXT_READER_SUCCESS {
+            Ok(Some(Message::new_noinc(msg)))
+        } else if result == DP_TEXT_READER_INPUT_END {
+            Ok(None)
+        } else if let Some(e) = self.source.error.take() {
+            Err(anyhow!(e))
+        } else {
+            Err(anyhow!(self.parse_error()))
+        }
+    }
+
+    fn read_header(&mut self) -> Result<()> {
+        loop {
+            let mut key: *const c_char = ptr::null();
+            let mut value: *const c_char = ptr::null();
+            let result =
+                unsafe { DP_text_reader_read_header_field(self.reader, &mut key, &mut value) };
+            if result == DP_TEXT_READER_SUCCESS {
+                self.header.push((to_string(key)?, to_string(value)?));
+            } else if result == DP_TEXT_READER_HEADER_END {
+                return Ok(());
+            } else if result == DP_TEXT_READER_ERROR_INPUT {
+                return Err(match self.source.error.take() {
+                    Some(e) => anyhow!(e),
+                    None => dp_error_anyhow(),
+                });
+            } else {
+                return Err(anyhow!(self.parse_error()));
+            }
+        }
+    }
+
+    fn parse_error(&self) -> TextParseError {
+        let pos = unsafe { DP_text_reader_error_position(self.reader) };
+        TextParseError {
+            line: pos.line,
+            column: pos.column,
+            message: dp_error(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TextReader<R> {
+    type Item = Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+impl<R: BufRead> Drop for TextReader<R> {
+    fn drop(&mut self) {
+        unsafe { DP_text_reader_free(self.reader) }
+    }
+}
+
+fn to_string(s: *const c_char) -> Result<String> {
+    Ok(unsafe { CStr::from_ptr(s) }.to_str()?.to_owned())
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+use super::{recording_writer::make_header, sink::Sink};
+use crate::{
+    dp_error_anyhow, json_value_free, json_value_get_object, msg::Message, DP_TextWriter,
+    DP_message_write_text, DP_text_writer_free, DP_text_writer_new, DP_text_writer_raw_write,
+    DP_text_writer_write_header,
+};
+use anyhow::Result;
+use std::{io::Write, ptr};
+
+// Writes text Drawpile recordings (.dptxt), one message per line, with
+// multi-line blocks for dabs and such. Uses the same writer as the C tools,
+// so the output can be read back by them and the other way round.
+pub struct TextWriter<W: Write> {
+    writer: *mut DP_TextWriter,
+    sink: Option<Box<Sink<W>>>,
+}
+
+impl<W: Write> TextWriter<W> {
+    // Writes the header right away, same as for binary recordings.
+    pub fn new(inner: W, metadata: &[(&str, &str)]) -> Result<Self> {
+        let tw = Self::new_without_header(inner)?;
+        let header = make_header(metadata)?;
+        let ok = unsafe { DP_text_writer_write_header(tw.writer, json_value_get_object(header)) };
+        unsafe { json_value_free(header) };
+        if ok {
+            Ok(tw)
+        } else {
+            Err(dp_error_anyhow())
+        }
+    }
+
+    // For writing loose messages that aren't a whole recording.
+    pub fn new_without_header(inner: W) -> Result<Self> {
+        let (sink, output) = Sink::new_output(inner)?;
+        Ok(TextWriter {
+            writer: unsafe { DP_text_writer_new(output) },
+            sink: Some(sink),
+        })
+    }
+
+    // Each line of the comment gets a # in front of it, readers skip those.
+    pub fn write_comment(&mut self, comment: &str) -> Result<()> {
+        comment
+            .lines()
+            .try_for_each(|line| self.write_raw(&format!("# {line}\n")))
+    }
+
+    pub fn add_message(&mut self, msg: &Message) -> Result<()> {
+        if unsafe { DP_message_write_text(msg.as_ptr(), self.writer) } {
+            Ok(())
+        } else {
+            Err(dp_error_anyhow())
+        }
+    }
+
+    // Flushes everything out and gives back what was written to.
+    pub fn close(mut self) -> Result<W> {
+        unsafe { DP_text_writer_free(self.writer) };
+        self.writer = ptr::null_mut();
+        self.sink.take().unwrap().into_inner()
+    }
+
+    fn write_raw(&mut self, text: &str) -> Result<()> {
+        if unsafe { DP_text_writer_raw_write(self.writer, text.as_ptr().cast(), text.len()) } {
+            Ok(())
+        } else {
+            Err(dp_error_anyhow())
+        }
+    }
+}
+
+impl<W: Write> Drop for TextWriter<W> {
+    fn drop(&mut self) {
+        unsafe { DP_text_writer_free(self.writer) }
+    }
+}
@@ -2,6 +2,7 @@
 #include <dpcommon/input.h>
 #include <dpcommon/output.h>
 #include <dpengine/canvas_state.h>
+#include <dpengine/compositor.h>
 #include <dpengine/document_metadata.h>
 #include <dpengine/draw_context.h>
 #include <dpengine/image.h>
@@ -11,13 +12,21 @@
 #include <dpengine/layer_list.h>
 #include <dpengine/layer_props.h>
 #include <dpengine/layer_props_list.h>
+#include <dpengine/memory_usage.h>
 #include <dpengine/paint_engine.h>
 #include <dpengine/player.h>
+#include <dpengine/put_image.h>
+#include <dpengine/recording_filter.h>
 #include <dpengine/save.h>
 #include <dpengine/tile.h>
 #include <dpengine/timeline.h>
 #include <dpengine/track.h>
 #include <dpmsg/acl.h>
+#include <dpmsg/binary_reader.h>
+#include <dpmsg/binary_writer.h>
 #include <dpmsg/blend_mode.h>
 #include <dpmsg/message.h>
+#include <dpmsg/recording_stats.h>
+#include <dpmsg/text_reader.h>
+#include <dpmsg/text_writer.h>
 #include <parson/parson.h>
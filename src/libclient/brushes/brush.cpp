@@ -1,8 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 #include "libclient/brushes/brush.h"
+extern "C" {
+#include <dpengine/paint.h>
+#include <dpengine/stamp_mask.h>
+}
 #include "cmake-config/config.h"
 #include "libclient/canvas/blendmodes.h"
 #include "libclient/drawdance/brushengine.h"
+#include "libclient/drawdance/global.h"
 #include "libshared/util/qtcompat.h"
 #include <QJsonArray>
 #include <QJsonDocument>
@@ -23,6 +28,41 @@ QColor drawdanceColorToQColor(const DP_UPixelFloat &color)
 	return QColor::fromRgbF(color.r, color.g, color.b, color.a);
 }
 
+DP_BrushPickupMode pickupModeFromJson(const QJsonValue &value)
+{
+	return value == "merged" ? DP_BRUSH_PICKUP_MODE_MERGED
+							 : DP_BRUSH_PICKUP_MODE_LAYER;
+}
+
+QString pickupModeToJson(DP_BrushPickupMode pickupMode)
+{
+	return pickupMode == DP_BRUSH_PICKUP_MODE_MERGED ? QStringLiteral("merged")
+													 : QStringLiteral("layer");
+}
+
+QColor colorFromJson(const QJsonValue &value, const QColor &fallback)
+{
+	QColor color(value.toString());
+	return color.isValid() ? color : fallback;
+}
+
+DP_ClassicBrushEraseTarget eraseTargetFromJson(const QJsonValue &value)
+{
+	return value == "color" ? DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR
+							: DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT;
+}
+
+DP_ClassicBrushFalloff falloffFromJson(const QJsonObject &o)
+{
+	if(o["falloff"] == "gaussian") {
+		return DP_CLASSIC_BRUSH_FALLOFF_GAUSSIAN;
+	} else if(o["falloff"] == "smoothstep") {
+		return DP_CLASSIC_BRUSH_FALLOFF_SMOOTHSTEP;
+	} else {
+		return DP_CLASSIC_BRUSH_FALLOFF_RAMP;
+	}
+}
+
 }
 
 namespace brushes {
@@ -35,13 +75,28 @@ ClassicBrush::ClassicBrush()
 		{0.0f, 0.0f, {}},
 		0.1f,
 		0,
+		0.0f,
+		1.0f,
+		DP_BRUSH_PICKUP_MODE_LAYER,
 		{0.0f, 0.0f, 0.0f, 1.0f},
+		{0.0f, 0.0f, 0.0f, 0.0f},
 		DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND,
+		DP_CLASSIC_BRUSH_FALLOFF_RAMP,
+		0,
+		0.0f,
+		DP_CLASSIC_BRUSH_ANGLE_MODE_FIXED,
+		0,
+		1.0f,
+		1.0f,
 		DP_BLEND_MODE_NORMAL,
 		DP_BLEND_MODE_ERASE,
+		DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT,
+		{1.0f, 1.0f, 1.0f, 1.0f},
 		false,
 		true,
 		false,
+		false,
+		{DP_CLASSIC_BRUSH_DYNAMIC_NONE, DEFAULT_VELOCITY, DEFAULT_DISTANCE},
 		{DP_CLASSIC_BRUSH_DYNAMIC_NONE, DEFAULT_VELOCITY, DEFAULT_DISTANCE},
 		{DP_CLASSIC_BRUSH_DYNAMIC_NONE, DEFAULT_VELOCITY, DEFAULT_DISTANCE},
 		{DP_CLASSIC_BRUSH_DYNAMIC_NONE, DEFAULT_VELOCITY, DEFAULT_DISTANCE},
@@ -91,6 +146,55 @@ QColor ClassicBrush::qColor() const
 	return drawdanceColorToQColor(color);
 }
 
+void ClassicBrush::setQColor2(const QColor &c)
+{
+	setDrawdanceColorToQColor(color2, c);
+}
+
+QColor ClassicBrush::qColor2() const
+{
+	return drawdanceColorToQColor(color2);
+}
+
+void ClassicBrush::setEraseQColor(const QColor &c)
+{
+	setDrawdanceColorToQColor(erase_color, c);
+}
+
+QColor ClassicBrush::eraseQColor() const
+{
+	return drawdanceColorToQColor(erase_color);
+}
+
+QByteArray ClassicBrush::toJsonBytes() const
+{
+	return QJsonDocument{toJson()}.toJson(QJsonDocument::Compact);
+}
+
+bool ClassicBrush::fromJsonBytes(
+	const QByteArray &bytes, ClassicBrush &outBrush, QString *outError)
+{
+	QJsonParseError error;
+	QJsonDocument doc = QJsonDocument::fromJson(bytes, &error);
+	if(error.error != QJsonParseError::NoError) {
+		if(outError) {
+			*outError = error.errorString();
+		}
+		return false;
+	}
+
+	const QJsonObject json = doc.object();
+	if(json["type"] != "dp-classic") {
+		if(outError) {
+			*outError = QStringLiteral("type is not dp-classic");
+		}
+		return false;
+	}
+
+	outBrush = fromJson(json);
+	return true;
+}
+
 QJsonObject ClassicBrush::toJson() const
 {
 	return QJsonObject{
@@ -114,64 +218,7 @@ ClassicBrush ClassicBrush::fromJson(const QJsonObject &json)
 		return b;
 	}
 
-	const QJsonObject o = json["settings"].toObject();
-
-	if(o["shape"] == "round-pixel")
-		b.shape = DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND;
-	else if(o["shape"] == "square-pixel")
-		b.shape = DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE;
-	else
-		b.shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
-
-	b.size.max = o["size"].toDouble();
-	b.size.min = o["size2"].toDouble();
-	b.m_sizeCurve.fromString(o["sizecurve"].toString());
-	b.updateCurve(b.m_sizeCurve, b.size.curve);
-
-	b.opacity.max = o["opacity"].toDouble();
-	b.opacity.min = o["opacity2"].toDouble();
-	b.m_opacityCurve.fromString(o["opacitycurve"].toString());
-	b.updateCurve(b.m_opacityCurve, b.opacity.curve);
-
-	b.hardness.max = o["hard"].toDouble();
-	b.hardness.min = o["hard2"].toDouble();
-	b.m_hardnessCurve.fromString(o["hardcurve"].toString());
-	b.updateCurve(b.m_hardnessCurve, b.hardness.curve);
-
-	b.smudge.max = o["smudge"].toDouble();
-	b.smudge.min = o["smudge2"].toDouble();
-	b.m_smudgeCurve.fromString(o["smudgecurve"].toString());
-	b.updateCurve(b.m_smudgeCurve, b.smudge.curve);
-
-	b.spacing = o["spacing"].toDouble();
-	b.resmudge = o["resmudge"].toInt();
-
-	b.incremental = !o["indirect"].toBool();
-	b.colorpick = o["colorpick"].toBool();
-
-	b.size_dynamic = dynamicFromJson(o, QStringLiteral("size"));
-	b.m_lastSizeDynamicType =
-		lastDynamicTypeFromJson(o, QStringLiteral("size"));
-	b.hardness_dynamic = dynamicFromJson(o, QStringLiteral("hard"));
-	b.m_lastHardnessDynamicType =
-		lastDynamicTypeFromJson(o, QStringLiteral("hard"));
-	b.opacity_dynamic = dynamicFromJson(o, QStringLiteral("opacity"));
-	b.m_lastOpacityDynamicType =
-		lastDynamicTypeFromJson(o, QStringLiteral("opacity"));
-	b.smudge_dynamic = dynamicFromJson(o, QStringLiteral("smudge"));
-	b.m_lastSmudgeDynamicType =
-		lastDynamicTypeFromJson(o, QStringLiteral("smudge"));
-
-	b.brush_mode = canvas::blendmode::fromSvgName(o["blend"].toString());
-	b.erase_mode = canvas::blendmode::fromSvgName(
-		o["blenderase"].toString(), DP_BLEND_MODE_ERASE);
-	b.erase = o["erase"].toBool();
-
-	b.stabilizationMode =
-		o["stabilizationmode"].toInt() == Smoothing ? Smoothing : Stabilizer;
-	b.stabilizerSampleCount = o["stabilizer"].toInt();
-	b.smoothing = o["smoothing"].toInt();
-
+	b.loadSettingsFromJson(json["settings"].toObject());
 	return b;
 }
 
@@ -186,6 +233,168 @@ bool ClassicBrush::fromExportJson(const QJsonObject &json)
 	}
 }
 
+bool ClassicBrush::setStampMask(int size, const QByteArray &data)
+{
+	if(size <= 0 || data.size() != size * size) {
+		qWarning("ClassicBrush::setStampMask: bad mask size %d", size);
+		return false;
+	}
+
+	DP_StampMask *sm = DP_stamp_mask_new(
+		size, reinterpret_cast<const uint8_t *>(data.constData()));
+	if(!sm) {
+		qWarning("ClassicBrush::setStampMask: %s", DP_error());
+		return false;
+	}
+
+	DP_stamp_mask_register(sm);
+	stamp_mask = DP_stamp_mask_id(sm);
+	m_stampMask.reset(sm, DP_stamp_mask_decref);
+	return true;
+}
+
+void ClassicBrush::clearStampMask()
+{
+	stamp_mask = 0;
+	m_stampMask.reset();
+}
+
+bool ClassicBrush::setGrainMask(int size, const QByteArray &data)
+{
+	if(size <= 0 || data.size() != size * size) {
+		qWarning("ClassicBrush::setGrainMask: bad mask size %d", size);
+		return false;
+	}
+
+	DP_StampMask *sm = DP_stamp_mask_new(
+		size, reinterpret_cast<const uint8_t *>(data.constData()));
+	if(!sm) {
+		qWarning("ClassicBrush::setGrainMask: %s", DP_error());
+		return false;
+	}
+
+	DP_stamp_mask_register(sm);
+	grain = DP_stamp_mask_id(sm);
+	m_grainMask.reset(sm, DP_stamp_mask_decref);
+	return true;
+}
+
+void ClassicBrush::clearGrainMask()
+{
+	grain = 0;
+	m_grainMask.reset();
+}
+
+void ClassicBrush::registerStampMask() const
+{
+	if(m_stampMask) {
+		DP_stamp_mask_register(m_stampMask.data());
+	}
+	if(m_grainMask) {
+		DP_stamp_mask_register(m_grainMask.data());
+	}
+}
+
+BrushOutline ClassicBrush::outline(const QPointF &pos, float pressure) const
+{
+	registerStampMask();
+	drawdance::DrawContext dc = drawdance::DrawContextPool::acquire();
+	DP_BrushOutline bo = DP_paint_classic_brush_outline(
+		dc.get(), this, float(pos.x()), float(pos.y()), pressure);
+
+	BrushOutline result;
+	if(DP_rect_valid(bo.bounds)) {
+		result.bounds = QRect(
+			QPoint(bo.bounds.x1, bo.bounds.y1),
+			QPoint(bo.bounds.x2, bo.bounds.y2));
+		result.mask = QImage(result.bounds.size(), QImage::Format_Alpha8);
+		int diameter = bo.mask.diameter;
+		int offsetX = bo.bounds.x1 - bo.mask.left;
+		int offsetY = bo.bounds.y1 - bo.mask.top;
+		for(int y = 0; y < result.mask.height(); ++y) {
+			const uint16_t *src =
+				bo.mask.data + (y + offsetY) * diameter + offsetX;
+			uchar *dst = result.mask.scanLine(y);
+			for(int x = 0; x < result.mask.width(); ++x) {
+				dst[x] = DP_channel15_to_8(src[x]);
+			}
+		}
+	}
+	return result;
+}
+
+void ClassicBrush::stampFromJson(const QJsonObject &o)
+{
+	stamp_angle = o["stampangle"].toDouble();
+	QJsonValue angleMode = o["stampanglemode"];
+	if(angleMode == "direction") {
+		stamp_angle_mode = DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION;
+	} else if(angleMode == "tilt") {
+		stamp_angle_mode = DP_CLASSIC_BRUSH_ANGLE_MODE_TILT;
+	} else {
+		stamp_angle_mode = DP_CLASSIC_BRUSH_ANGLE_MODE_FIXED;
+	}
+	QByteArray data =
+		QByteArray::fromBase64(o["stampmask"].toString().toLatin1());
+	if(data.isEmpty() || !setStampMask(o["stampmasksize"].toInt(), data)) {
+		clearStampMask();
+	}
+}
+
+void ClassicBrush::grainFromJson(const QJsonObject &o)
+{
+	grain_scale = o["grainscale"].toDouble(1.0);
+	grain_strength = o["grainstrength"].toDouble(1.0);
+	QByteArray data =
+		QByteArray::fromBase64(o["grainmask"].toString().toLatin1());
+	if(data.isEmpty() || !setGrainMask(o["grainmasksize"].toInt(), data)) {
+		clearGrainMask();
+	}
+}
+
+void ClassicBrush::grainToJson(QJsonObject &o) const
+{
+	if(m_grainMask) {
+		DP_StampMask *sm = m_grainMask.data();
+		int maskSize = DP_stamp_mask_size(sm);
+		o["grainmasksize"] = maskSize;
+		o["grainmask"] = QString::fromLatin1(
+			QByteArray::fromRawData(
+				reinterpret_cast<const char *>(DP_stamp_mask_data(sm)),
+				maskSize * maskSize)
+				.toBase64());
+		o["grainscale"] = grain_scale;
+		o["grainstrength"] = grain_strength;
+	}
+}
+
+void ClassicBrush::stampToJson(QJsonObject &o) const
+{
+	if(stamp_angle != 0.0f) {
+		o["stampangle"] = stamp_angle;
+	}
+	switch(stamp_angle_mode) {
+	case DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION:
+		o["stampanglemode"] = "direction";
+		break;
+	case DP_CLASSIC_BRUSH_ANGLE_MODE_TILT:
+		o["stampanglemode"] = "tilt";
+		break;
+	default:
+		break;
+	}
+	if(m_stampMask) {
+		DP_StampMask *sm = m_stampMask.data();
+		int maskSize = DP_stamp_mask_size(sm);
+		o["stampmasksize"] = maskSize;
+		o["stampmask"] = QString::fromLatin1(
+			QByteArray::fromRawData(
+				reinterpret_cast<const char *>(DP_stamp_mask_data(sm)),
+				maskSize * maskSize)
+				.toBase64());
+	}
+}
+
 QPixmap ClassicBrush::presetThumbnail() const
 {
 	QColor c =
@@ -211,6 +420,7 @@ void ClassicBrush::setDynamicType(
 	case DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE:
 	case DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY:
 	case DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE:
+	case DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY:
 		outLastType = type;
 		[[fallthrough]];
 	case DP_CLASSIC_BRUSH_DYNAMIC_NONE:
@@ -226,35 +436,47 @@ void ClassicBrush::loadSettingsFromJson(const QJsonObject &settings)
 		shape = DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND;
 	} else if(settings["shape"] == "square-pixel") {
 		shape = DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE;
+	} else if(settings["shape"] == "round-stamp") {
+		shape = DP_BRUSH_SHAPE_CLASSIC_STAMP;
 	} else {
 		shape = DP_BRUSH_SHAPE_CLASSIC_SOFT_ROUND;
 	}
+	falloff = falloffFromJson(settings);
+	stampFromJson(settings);
+	grainFromJson(settings);
 
-	size.max = settings["size"].toDouble();
+	size.max = settings["size"].toDouble(size.max);
 	size.min = settings["size2"].toDouble();
 	m_sizeCurve.fromString(settings["sizecurve"].toString());
 	updateCurve(m_sizeCurve, size.curve);
 
-	opacity.max = settings["opacity"].toDouble();
+	opacity.max = settings["opacity"].toDouble(opacity.max);
 	opacity.min = settings["opacity2"].toDouble();
 	m_opacityCurve.fromString(settings["opacitycurve"].toString());
 	updateCurve(m_opacityCurve, opacity.curve);
 
-	hardness.max = settings["hard"].toDouble();
+	hardness.max = settings["hard"].toDouble(hardness.max);
 	hardness.min = settings["hard2"].toDouble();
 	m_hardnessCurve.fromString(settings["hardcurve"].toString());
 	updateCurve(m_hardnessCurve, hardness.curve);
 
 	smudge.max = settings["smudge"].toDouble();
-	smudge.min = settings["smudge2"].toDouble();
+	// Older versions wrote the minimum as smudgeratio.
+	smudge.min = settings.contains(QStringLiteral("smudge2"))
+					 ? settings["smudge2"].toDouble()
+					 : settings["smudgeratio"].toDouble();
 	m_smudgeCurve.fromString(settings["smudgecurve"].toString());
 	updateCurve(m_smudgeCurve, smudge.curve);
 
-	spacing = settings["spacing"].toDouble();
+	spacing = settings["spacing"].toDouble(spacing);
 	resmudge = settings["resmudge"].toInt();
+	airbrush_rate = settings["airbrushrate"].toDouble();
+	airbrush_flow = settings["airbrushflow"].toDouble(1.0);
+	pickup_mode = pickupModeFromJson(settings["pickupmode"]);
 
 	incremental = !settings["indirect"].toBool();
 	colorpick = settings["colorpick"].toBool();
+	pixel_perfect = settings["pixelperfect"].toBool();
 	size_dynamic = dynamicFromJson(settings, QStringLiteral("size"));
 	m_lastSizeDynamicType =
 		lastDynamicTypeFromJson(settings, QStringLiteral("size"));
@@ -267,10 +489,16 @@ void ClassicBrush::loadSettingsFromJson(const QJsonObject &settings)
 	smudge_dynamic = dynamicFromJson(settings, QStringLiteral("smudge"));
 	m_lastSmudgeDynamicType =
 		lastDynamicTypeFromJson(settings, QStringLiteral("smudge"));
+	color_dynamic = dynamicFromJson(settings, QStringLiteral("color"));
+	m_lastColorDynamicType =
+		lastDynamicTypeFromJson(settings, QStringLiteral("color"));
+	setQColor2(colorFromJson(settings["color2"], Qt::transparent));
 
 	brush_mode = canvas::blendmode::fromSvgName(settings["blend"].toString());
 	erase_mode = canvas::blendmode::fromSvgName(
 		settings["blenderase"].toString(), DP_BLEND_MODE_ERASE);
+	erase_target = eraseTargetFromJson(settings["erasetarget"]);
+	setEraseQColor(colorFromJson(settings["erasecolor"], Qt::white));
 	erase = settings["erase"].toBool();
 
 	stabilizationMode = settings["stabilizationmode"].toInt() == Smoothing
@@ -290,11 +518,27 @@ QJsonObject ClassicBrush::settingsToJson() const
 	case DP_BRUSH_SHAPE_CLASSIC_PIXEL_SQUARE:
 		o["shape"] = "square-pixel";
 		break;
+	case DP_BRUSH_SHAPE_CLASSIC_STAMP:
+		o["shape"] = "round-stamp";
+		break;
 	default:
 		o["shape"] = "round-soft";
 		break;
 	}
 
+	switch(falloff) {
+	case DP_CLASSIC_BRUSH_FALLOFF_GAUSSIAN:
+		o["falloff"] = "gaussian";
+		break;
+	case DP_CLASSIC_BRUSH_FALLOFF_SMOOTHSTEP:
+		o["falloff"] = "smoothstep";
+		break;
+	default:
+		break;
+	}
+	stampToJson(o);
+	grainToJson(o);
+
 	o["size"] = size.max;
 	if(size.min > 0)
 		o["size2"] = size.min;
@@ -313,17 +557,25 @@ QJsonObject ClassicBrush::settingsToJson() const
 	if(smudge.max > 0)
 		o["smudge"] = smudge.max;
 	if(smudge.min > 0)
-		o["smudgeratio"] = smudge.min;
+		o["smudge2"] = smudge.min;
 	o["smudgecurve"] = m_smudgeCurve.toString();
 
 	o["spacing"] = spacing;
 	if(resmudge > 0)
 		o["resmudge"] = resmudge;
+	if(airbrush_rate > 0.0f) {
+		o["airbrushrate"] = airbrush_rate;
+		o["airbrushflow"] = airbrush_flow;
+	}
+	if(pickup_mode == DP_BRUSH_PICKUP_MODE_MERGED)
+		o["pickupmode"] = "merged";
 
 	if(!incremental)
 		o["indirect"] = true;
 	if(colorpick)
 		o["colorpick"] = true;
+	if(pixel_perfect)
+		o["pixelperfect"] = true;
 	dynamicToJson(
 		size_dynamic, m_lastSizeDynamicType, QStringLiteral("size"), o);
 	dynamicToJson(
@@ -333,16 +585,25 @@ QJsonObject ClassicBrush::settingsToJson() const
 		o);
 	dynamicToJson(
 		smudge_dynamic, m_lastSmudgeDynamicType, QStringLiteral("smudge"), o);
+	dynamicToJson(
+		color_dynamic, m_lastColorDynamicType, QStringLiteral("color"), o);
+	if(color2.a > 0.0f)
+		o["color2"] = qColor2().name(QColor::HexArgb);
 
 	o["blend"] = canvas::blendmode::svgName(brush_mode);
 	o["blenderase"] = canvas::blendmode::svgName(erase_mode);
+	if(erase_target == DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR) {
+		o["erasetarget"] = "color";
+		o["erasecolor"] = eraseQColor().name();
+	}
 	o["erase"] = erase;
 
 	o["stabilizationmode"] = stabilizationMode;
 	o["stabilizer"] = stabilizerSampleCount;
 	o["smoothing"] = smoothing;
 
-	// Note: color is intentionally omitted
+	// Note: color is intentionally omitted, but the secondary color for the
+	// color dynamic is part of the brush.
 
 	return o;
 }
@@ -357,6 +618,8 @@ ClassicBrush::dynamicFromJson(const QJsonObject &o, const QString &prefix)
 		type = DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY;
 	} else if(o[prefix + QStringLiteral("d")].toBool()) {
 		type = DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE;
+	} else if(o[prefix + QStringLiteral("pv")].toBool()) {
+		type = DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY;
 	} else {
 		type = DP_CLASSIC_BRUSH_DYNAMIC_NONE;
 	}
@@ -376,6 +639,8 @@ DP_ClassicBrushDynamicType ClassicBrush::lastDynamicTypeFromJson(
 		return DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY;
 	} else if(o[prefix + QStringLiteral("lastd")].toBool()) {
 		return DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE;
+	} else if(o[prefix + QStringLiteral("lastpv")].toBool()) {
+		return DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY;
 	} else {
 		return DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
 	}
@@ -397,6 +662,9 @@ void ClassicBrush::dynamicToJson(
 	case DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE:
 		o[prefix + QStringLiteral("d")] = true;
 		break;
+	case DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY:
+		o[prefix + QStringLiteral("pv")] = true;
+		break;
 	}
 	switch(lastType) {
 	case DP_CLASSIC_BRUSH_DYNAMIC_NONE:
@@ -408,6 +676,9 @@ void ClassicBrush::dynamicToJson(
 	case DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE:
 		o[prefix + QStringLiteral("lastd")] = true;
 		break;
+	case DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY:
+		o[prefix + QStringLiteral("lastpv")] = true;
+		break;
 	}
 	o[prefix + QStringLiteral("vmax")] = dynamic.max_velocity;
 	o[prefix + QStringLiteral("dmax")] = dynamic.max_distance;
@@ -415,7 +686,9 @@ void ClassicBrush::dynamicToJson(
 
 
 MyPaintBrush::MyPaintBrush()
-	: m_brush{{0.0f, 0.0f, 0.0f, 1.0f}, false, false, true}
+	: m_brush{
+		  {0.0f, 0.0f, 0.0f, 1.0f}, false, false, true,
+		  DP_BRUSH_PICKUP_MODE_LAYER}
 	, m_settings{nullptr}
 	, m_stabilizationMode{Stabilizer}
 	, m_stabilizerSampleCount{0}
@@ -530,6 +803,7 @@ QJsonObject MyPaintBrush::toJson() const
 		{"settings",
 		 QJsonObject{
 			 {"lock_alpha", m_brush.lock_alpha},
+			 {"pickup_mode", pickupModeToJson(m_brush.pickup_mode)},
 			 {"erase", m_brush.erase},
 			 {"indirect", !m_brush.incremental},
 			 {"stabilizationmode", m_stabilizationMode},
@@ -544,6 +818,7 @@ void MyPaintBrush::exportToJson(QJsonObject &json) const
 {
 	json["drawpile_settings"] = QJsonObject{
 		{"lock_alpha", m_brush.lock_alpha},
+		{"pickup_mode", pickupModeToJson(m_brush.pickup_mode)},
 		{"erase", m_brush.erase},
 		{"indirect", !m_brush.incremental},
 		{"stabilizationmode", m_stabilizationMode},
@@ -563,6 +838,7 @@ MyPaintBrush MyPaintBrush::fromJson(const QJsonObject &json)
 
 	const QJsonObject o = json["settings"].toObject();
 	b.m_brush.lock_alpha = o["lock_alpha"].toBool();
+	b.m_brush.pickup_mode = pickupModeFromJson(o["pickup_mode"]);
 	b.m_brush.erase = o["erase"].toBool();
 	b.m_brush.incremental = !o["indirect"].toBool();
 	b.loadJsonSettings(o["mapping"].toObject());
@@ -611,6 +887,7 @@ bool MyPaintBrush::fromExportJson(const QJsonObject &json)
 	}
 
 	m_brush.lock_alpha = drawpileSettings["lock_alpha"].toBool(false);
+	m_brush.pickup_mode = pickupModeFromJson(drawpileSettings["pickup_mode"]);
 	m_brush.erase = drawpileSettings["erase"].toBool(false);
 	m_brush.incremental = !drawpileSettings["indirect"].toBool(false);
 	m_stabilizationMode =
@@ -964,6 +1241,7 @@ void ActiveBrush::setInBrushEngine(
 	drawdance::BrushEngine &be, const DP_StrokeParams &stroke) const
 {
 	if(m_activeType == CLASSIC) {
+		m_classic.registerStampMask();
 		be.setClassicBrush(m_classic, stroke, isEraserOverride());
 	} else {
 		be.setMyPaintBrush(
@@ -9,12 +9,17 @@ extern "C" {
 #include "libclient/utils/kis_cubic_curve.h"
 #include <QColor>
 #include <QHash>
+#include <QImage>
 #include <QJsonObject>
 #include <QMetaType>
 #include <QPair>
 #include <QPixmap>
+#include <QPointF>
+#include <QRect>
+#include <QSharedPointer>
 #include <limits>
 
+struct DP_StampMask;
 struct DP_StrokeParams;
 struct MyPaintBrush;
 
@@ -30,6 +35,14 @@ enum StabilizationMode {
 	LastStabilizationMode = Smoothing,
 };
 
+//! A single classic brush dab, for showing the brush outline at the cursor
+struct BrushOutline {
+	//! Canvas pixels covered by the dab, null if the brush leaves no dab
+	QRect bounds;
+	//! Coverage of the dab as an alpha mask the size of the bounds
+	QImage mask;
+};
+
 //! A convenience wrapper for classic brush settings
 class ClassicBrush final : public DP_ClassicBrush {
 public:
@@ -127,9 +140,63 @@ public:
 		smudge_dynamic.max_distance = maxDistance;
 	}
 
+	DP_ClassicBrushDynamicType lastColorDynamicType() const
+	{
+		return m_lastColorDynamicType;
+	}
+
+	void setColorDynamicType(DP_ClassicBrushDynamicType type)
+	{
+		setDynamicType(type, color_dynamic.type, m_lastColorDynamicType);
+	}
+
+	void setColorMaxVelocity(float maxVelocity)
+	{
+		color_dynamic.max_velocity = maxVelocity;
+	}
+
+	void setColorMaxDistance(float maxDistance)
+	{
+		color_dynamic.max_distance = maxDistance;
+	}
+
 	void setQColor(const QColor &c);
 	QColor qColor() const;
 
+	//! Secondary color that the color dynamic blends towards
+	void setQColor2(const QColor &c);
+	QColor qColor2() const;
+
+	//! Color that erasing paints with when erasing to a color
+	void setEraseQColor(const QColor &c);
+	QColor eraseQColor() const;
+
+	//! Sets the mask for stamp brushes from size*size coverage values
+	bool setStampMask(int size, const QByteArray &data);
+	void clearStampMask();
+	bool hasStampMask() const { return !m_stampMask.isNull(); }
+
+	//! Sets the paper grain texture from size*size coverage values
+	bool setGrainMask(int size, const QByteArray &data);
+	void clearGrainMask();
+	bool hasGrainMask() const { return !m_grainMask.isNull(); }
+
+	//! Puts the masks back into the engine's registry, in case they got evicted
+	void registerStampMask() const;
+
+	//! The dab the stroke engine would stamp at the given canvas position
+	BrushOutline outline(const QPointF &pos, float pressure) const;
+
+	//! Serialized preset, as a compact JSON document
+	QByteArray toJsonBytes() const;
+
+	//! Parses a preset from toJsonBytes. Unknown fields are ignored and
+	//! missing ones keep their defaults, so presets from older and newer
+	//! versions load too. Only fails on broken JSON or the wrong brush type.
+	static bool fromJsonBytes(
+		const QByteArray &bytes, ClassicBrush &outBrush,
+		QString *outError = nullptr);
+
 	QJsonObject toJson() const;
 	void exportToJson(QJsonObject &json) const;
 	static ClassicBrush fromJson(const QJsonObject &json);
@@ -153,6 +220,12 @@ private:
 	void loadSettingsFromJson(const QJsonObject &settings);
 	QJsonObject settingsToJson() const;
 
+	void stampFromJson(const QJsonObject &o);
+	void stampToJson(QJsonObject &o) const;
+
+	void grainFromJson(const QJsonObject &o);
+	void grainToJson(QJsonObject &o) const;
+
 	static DP_ClassicBrushDynamic
 	dynamicFromJson(const QJsonObject &o, const QString &prefix);
 	static DP_ClassicBrushDynamicType
@@ -166,6 +239,8 @@ private:
 	KisCubicCurve m_opacityCurve;
 	KisCubicCurve m_hardnessCurve;
 	KisCubicCurve m_smudgeCurve;
+	QSharedPointer<DP_StampMask> m_stampMask;
+	QSharedPointer<DP_StampMask> m_grainMask;
 	DP_ClassicBrushDynamicType m_lastSizeDynamicType =
 		DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
 	DP_ClassicBrushDynamicType m_lastOpacityDynamicType =
@@ -174,6 +249,8 @@ private:
 		DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
 	DP_ClassicBrushDynamicType m_lastSmudgeDynamicType =
 		DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE;
+	DP_ClassicBrushDynamicType m_lastColorDynamicType =
+		DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE;
 };
 
 struct MyPaintCurve final {
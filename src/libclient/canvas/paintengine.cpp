@@ -150,7 +150,8 @@ int PaintEngine::receiveMessages(
 	return DP_paint_engine_handle_inc(
 		m_paintEngine.get(), local, overrideAcls, count,
 		net::Message::asRawMessages(msgs), &PaintEngine::onAclsChanged,
-		&PaintEngine::onLaserTrail, &PaintEngine::onMovePointer, this);
+		&PaintEngine::onLaserTrail, &PaintEngine::onMovePointer, nullptr,
+		this);
 }
 
 void PaintEngine::enqueueReset()
@@ -255,28 +256,9 @@ uint16_t PaintEngine::findAvailableAnnotationId(uint8_t forUser) const
 drawdance::Annotation
 PaintEngine::getAnnotationAt(int x, int y, int expand) const
 {
-	QPoint point{x, y};
-	QMargins margins{expand, expand, expand, expand};
-
 	drawdance::AnnotationList annotations = viewCanvasState().annotations();
-	int count = annotations.count();
-	int closestIndex = -1;
-	int closestDistance = INT_MAX;
-
-	for(int i = 0; i < count; ++i) {
-		drawdance::Annotation annotation = annotations.at(i);
-		QRect bounds = annotation.bounds().marginsAdded(margins);
-		if(bounds.contains(point)) {
-			int distance = (point - bounds.center()).manhattanLength();
-			if(closestIndex == -1 || distance < closestDistance) {
-				closestIndex = i;
-				closestDistance = distance;
-			}
-		}
-	}
-
-	return closestIndex == -1 ? drawdance::Annotation::null()
-							  : annotations.at(closestIndex);
+	int index = annotations.indexAt(x, y, expand);
+	return index == -1 ? drawdance::Annotation::null() : annotations.at(index);
 }
 
 bool PaintEngine::needsOpenRaster() const
@@ -105,6 +105,16 @@ int Annotation::valign() const
     return DP_annotation_valign(m_data);
 }
 
+int Annotation::border() const
+{
+    return DP_annotation_border(m_data);
+}
+
+bool Annotation::autoGrow() const
+{
+    return DP_annotation_auto_grow(m_data);
+}
+
 QColor Annotation::backgroundColor() const
 {
     return QColor::fromRgba(DP_annotation_background_color(m_data));
@@ -40,6 +40,8 @@ public:
     bool protect() const;
 
     int valign() const;
+    int border() const;
+    bool autoGrow() const;
 
     QColor backgroundColor() const;
 
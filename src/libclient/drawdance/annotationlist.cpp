@@ -64,6 +64,11 @@ Annotation AnnotationList::at(int index) const
     return Annotation::inc(DP_annotation_list_at_noinc(m_data, index));
 }
 
+int AnnotationList::indexAt(int x, int y, int expand) const
+{
+    return DP_annotation_list_index_at(m_data, x, y, expand);
+}
+
 AnnotationList::AnnotationList(DP_AnnotationList *al)
     : m_data{al}
 {
@@ -28,6 +28,8 @@ public:
 
     Annotation at(int index) const;
 
+    int indexAt(int x, int y, int expand = 0) const;
+
 private:
     explicit AnnotationList(DP_AnnotationList *al);
 
@@ -62,6 +62,15 @@ void BrushEngine::strokeTo(
 	DP_brush_engine_stroke_to(m_data, bp, cs.get());
 }
 
+long long BrushEngine::strokeShape(
+	DP_StrokeShape shape, const QPointF &p1, const QPointF &p2, bool fill,
+	long long timeMsec, const drawdance::CanvasState &cs)
+{
+	return DP_brush_engine_stroke_shape(
+		m_data, shape, float(p1.x()), float(p1.y()), float(p2.x()),
+		float(p2.y()), fill, timeMsec, cs.get());
+}
+
 void BrushEngine::poll(long long timeMsec, const drawdance::CanvasState &cs)
 {
 	DP_brush_engine_poll(m_data, timeMsec, cs.get());
@@ -81,7 +90,6 @@ void BrushEngine::addOffset(float x, float y)
 void BrushEngine::sendMessagesTo(net::Client *client)
 {
 	Q_ASSERT(client);
-	flushDabs();
 	client->sendMessages(m_messages.count(), m_messages.constData());
 	clearMessages();
 }
@@ -5,6 +5,7 @@ extern "C" {
 #include <dpengine/brush_engine.h>
 }
 #include "libclient/net/message.h"
+#include <QPointF>
 #include <functional>
 
 struct DP_ClassicBrush;
@@ -53,6 +54,11 @@ public:
 
 	void strokeTo(const canvas::Point &point, const drawdance::CanvasState &cs);
 
+	// Returns the time of the last point of the shape.
+	long long strokeShape(
+		DP_StrokeShape shape, const QPointF &p1, const QPointF &p2, bool fill,
+		long long timeMsec, const drawdance::CanvasState &cs);
+
 	void poll(long long timeMsec, const drawdance::CanvasState &cs);
 
 	void endStroke(
@@ -60,7 +66,9 @@ public:
 
 	void addOffset(float x, float y);
 
-	// Flushes dabs and sends accumulated messages to the client.
+	// Sends accumulated messages to the client. Dabs that the engine is still
+	// holding back to merge with later ones stay there until it lets go of
+	// them, which happens by the end of the stroke at the latest.
 	void sendMessagesTo(net::Client *client);
 
 private:
@@ -253,17 +253,32 @@ CanvasState::searchLayerContent(int layerId, bool showCensored) const
 }
 
 DP_FloodFillResult CanvasState::floodFill(
-	int x, int y, const QColor &fillColor, double tolerance, int layerId,
-	int sizeLimit, int gap, int expand, int featherRadius, DP_ViewMode viewMode,
-	int activeLayerId, int activeFrameIndex, const QAtomicInt &cancel,
+	int x, int y, const QColor &fillColor, const QImage &pattern, bool erase,
+	double tolerance,
+	DP_FloodFillSample sample, int layerId, int sizeLimit, int gap, int expand,
+	int featherRadius, DP_ViewMode viewMode, int activeLayerId, int activeFrameIndex, const QAtomicInt &cancel,
 	QImage &outImg, int &outX, int &outY) const
 {
-	DP_UPixelFloat fillPixel = DP_upixel_float_from_color(fillColor.rgba());
+	// The pattern is placed at the canvas origin, so that separate fills
+	// with it line up. It's expected to be premultiplied ARGB32 already.
+	DP_FloodFillSource source;
+	if(erase) {
+		source = DP_flood_fill_source_erase();
+	} else if(pattern.isNull()) {
+		source = DP_flood_fill_source_color(
+			DP_upixel_float_from_color(fillColor.rgba()));
+	} else {
+		Q_ASSERT(pattern.format() == QImage::Format_ARGB32_Premultiplied);
+		source = DP_flood_fill_source_pattern(
+			reinterpret_cast<const DP_Pixel8 *>(pattern.constBits()),
+			pattern.width(), pattern.height(), 0, 0);
+	}
 	DP_Image *img;
 	DP_FloodFillResult result = DP_flood_fill(
-		m_data, x, y, fillPixel, tolerance, layerId, sizeLimit, gap, expand,
-		featherRadius, viewMode, activeLayerId, activeFrameIndex, &img, &outX,
-		&outY, shouldCancelFloodFill, const_cast<QAtomicInt *>(&cancel));
+		m_data, x, y, source, tolerance, DP_FLOOD_FILL_METRIC_PERCEPTUAL,
+		sample, layerId, nullptr, sizeLimit, gap, expand, featherRadius, viewMode,
+		activeLayerId, activeFrameIndex, 0, &img, &outX, &outY, nullptr,
+		shouldCancelFloodFill, const_cast<QAtomicInt *>(&cancel));
 	if(result == DP_FLOOD_FILL_SUCCESS) {
 		outImg = wrapImage(img);
 	}
@@ -334,7 +349,8 @@ void CanvasState::addLayerVisibleInFrame(void *user, int layerId, bool visible)
 	}
 }
 
-bool CanvasState::shouldCancelFloodFill(void *user)
+bool CanvasState::shouldCancelFloodFill(
+	void *user, DP_FloodFillStage, size_t, size_t)
 {
 	return *static_cast<const QAtomicInt *>(user);
 }
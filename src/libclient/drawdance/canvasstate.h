@@ -87,9 +87,10 @@ public:
 	LayerContent searchLayerContent(int layerId, bool showCensored) const;
 
 	DP_FloodFillResult floodFill(
-		int x, int y, const QColor &fillColor, double tolerance, int layerId,
-		int sizeLimit, int gap, int expand, int featherRadius,
-		DP_ViewMode viewMode, int activeLayerId, int activeFrameIndex,
+		int x, int y, const QColor &fillColor, const QImage &pattern,
+		bool erase, double tolerance,
+		DP_FloodFillSample sample, int layerId, int sizeLimit, int gap,
+		int expand, int featherRadius, DP_ViewMode viewMode, int activeLayerId, int activeFrameIndex,
 		const QAtomicInt &cancel, QImage &outImg, int &outX, int &outY) const;
 
 	drawdance::CanvasState makeBackwardCompatible() const;
@@ -103,7 +104,8 @@ private:
 
 	static void addLayerVisibleInFrame(void *user, int layerId, bool visible);
 
-	static bool shouldCancelFloodFill(void *user);
+	static bool shouldCancelFloodFill(
+		void *user, DP_FloodFillStage stage, size_t filled, size_t area);
 
 	DP_CanvasState *m_data;
 };
@@ -274,7 +274,6 @@ namespace {
 
 struct BuildIndexParams {
 	PaintEngine::BuildIndexProgressFn progressFn;
-	long long messagesSinceLastSnapshot;
 };
 
 }
@@ -282,7 +281,7 @@ struct BuildIndexParams {
 bool PaintEngine::buildPlaybackIndex(BuildIndexProgressFn progressFn)
 {
 	DrawContext drawContext = DrawContextPool::acquire();
-	BuildIndexParams params = {progressFn, 0};
+	BuildIndexParams params = {progressFn};
 	return DP_paint_engine_playback_index_build(
 		m_data, drawContext.get(), PaintEngine::shouldSnapshot,
 		PaintEngine::indexProgress, &params);
@@ -381,10 +380,20 @@ void PaintEngine::previewTransform(
 		QPoint p4 = dstPolygon.point(3);
 		DP_Quad dstQuad = DP_quad_make(
 			p1.x(), p1.y(), p2.x(), p2.y(), p3.x(), p3.y(), p4.x(), p4.y());
-		DP_paint_engine_preview_transform(
-			m_data, layerId, x, y, img.width(), img.height(), &dstQuad,
-			interpolation, getTransformPreviewPixels,
-			disposeTransformPreviewPixels, new QImage{img});
+		// If only the destination changed, keep the source pixels around.
+		bool moved = !img.isNull() &&
+					 img.cacheKey() == m_transformPreviewImageKey &&
+					 layerId == m_transformPreviewLayerId &&
+					 DP_paint_engine_preview_transform_move(
+						 m_data, x, y, &dstQuad, interpolation);
+		if(!moved) {
+			DP_paint_engine_preview_transform(
+				m_data, layerId, x, y, img.width(), img.height(), &dstQuad,
+				interpolation, getTransformPreviewPixels,
+				disposeTransformPreviewPixels, new QImage{img});
+			m_transformPreviewImageKey = img.cacheKey();
+			m_transformPreviewLayerId = layerId;
+		}
 	} else {
 		qWarning("Preview transform destination is not a quad");
 	}
@@ -392,6 +401,7 @@ void PaintEngine::previewTransform(
 
 void PaintEngine::clearTransformPreview()
 {
+	m_transformPreviewImageKey = 0;
 	DP_paint_engine_preview_clear(m_data, DP_PREVIEW_TRANSFORM);
 }
 
@@ -446,16 +456,13 @@ bool PaintEngine::pushResetMessage(void *user, DP_Message *msg)
 	return true;
 }
 
-bool PaintEngine::shouldSnapshot(void *user)
+bool PaintEngine::shouldSnapshot(void *, long long messages, size_t bytes)
 {
+	// Big messages like image puts are slow to replay, so snapshot more often
+	// when the recording has lots of those.
 	static constexpr long long MESSAGE_INDEX_INTERVAL = 10000;
-	BuildIndexParams *params = static_cast<BuildIndexParams *>(user);
-	if(params->messagesSinceLastSnapshot++ > MESSAGE_INDEX_INTERVAL) {
-		params->messagesSinceLastSnapshot = 0;
-		return true;
-	} else {
-		return false;
-	}
+	static constexpr size_t BYTE_INDEX_INTERVAL = 16 * 1024 * 1024;
+	return messages > MESSAGE_INDEX_INTERVAL || bytes > BYTE_INDEX_INTERVAL;
 }
 
 void PaintEngine::addLayerVisibleInFrame(void *user, int layerId, bool visible)
@@ -134,12 +134,14 @@ private:
 	DrawContext m_mainDc;
 	DrawContext m_previewDc;
 	DP_PaintEngine *m_data;
+	qint64 m_transformPreviewImageKey = 0;
+	int m_transformPreviewLayerId = 0;
 
 	static QString getDumpDir();
 	static long long getTimeMs(void *);
 	static void pushMessage(void *user, DP_Message *msg);
 	static bool pushResetMessage(void *user, DP_Message *msg);
-	static bool shouldSnapshot(void *user);
+	static bool shouldSnapshot(void *user, long long messages, size_t bytes);
 	static void indexProgress(void *user, int percent);
 	static void addLayerVisibleInFrame(void *user, int layerId, bool visible);
 
@@ -60,6 +60,6 @@ bool CanvasSaverRunnable::bakeAnnotation(
 	QPainter painter(&img);
 	utils::paintAnnotation(
 		&painter, annotation.size(), annotation.backgroundColor(),
-		annotation.text(), annotation.valign());
+		annotation.text(), annotation.valign(), annotation.border());
 	return true;
 }
@@ -708,7 +708,7 @@ LoginSession LoginHandler::updateSession(const QJsonObject &js)
 		protocol::ProtocolVersion::fromString(js["protocol"].toString());
 
 	QString incompatibleSeries;
-	if(!protoVer.isCompatible()) {
+	if(!protoVer.canJoin()) {
 		if(protoVer.isFuture()) {
 			incompatibleSeries = tr("New version");
 		} else {
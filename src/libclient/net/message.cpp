@@ -39,6 +39,12 @@ Message makeAnnotationEditMessage(
 		contextId, id, bg, flags, border, bytes.constData(), bytes.length()));
 }
 
+Message
+makeAnnotationOrderMessage(uint8_t contextId, uint16_t id, uint8_t direction)
+{
+	return Message::noinc(DP_msg_annotation_order_new(contextId, id, direction));
+}
+
 Message makeAnnotationReshapeMessage(
 	uint8_t contextId, uint16_t id, int32_t x, int32_t y, uint16_t w,
 	uint16_t h)
@@ -82,6 +88,38 @@ Message makeFillRectMessage(
 		DP_msg_fill_rect_new(contextId, layer, mode, x, y, w, h, color.rgba()));
 }
 
+static void
+setFillGradientStops(int count, DP_FillGradientStop *out, void *user)
+{
+	const QVector<QPair<uint16_t, QColor>> &stops =
+		*static_cast<const QVector<QPair<uint16_t, QColor>> *>(user);
+	for(int i = 0; i < count; ++i) {
+		const QPair<uint16_t, QColor> &stop = stops[i];
+		DP_fill_gradient_stop_init(out, i, stop.first, stop.second.rgba());
+	}
+}
+
+Message makeFillGradientMessage(
+	uint8_t contextId, uint16_t layer, uint32_t x, uint32_t y, uint32_t w,
+	uint32_t h, uint8_t mode, uint8_t shape, uint8_t flags, int32_t x1,
+	int32_t y1, int32_t x2, int32_t y2, int32_t radius,
+	const QVector<QPair<uint16_t, QColor>> &stops)
+{
+	return Message::noinc(DP_msg_fill_gradient_new(
+		contextId, layer, x, y, w, h, mode, shape, flags, x1, y1, x2, y2,
+		radius, setFillGradientStops, stops.size(),
+		const_cast<QVector<QPair<uint16_t, QColor>> *>(&stops)));
+}
+
+Message makeFilterRegionMessage(
+	uint8_t contextId, uint16_t layer, uint32_t x, uint32_t y, uint32_t w,
+	uint32_t h, uint8_t filter, int32_t param1, int32_t param2, int32_t param3,
+	int32_t param4)
+{
+	return Message::noinc(DP_msg_filter_region_new(
+		contextId, layer, x, y, w, h, filter, param1, param2, param3, param4));
+}
+
 Message makeInternalCatchupMessage(uint8_t contextId, int progress)
 {
 	return Message::noinc(DP_msg_internal_catchup_new(contextId, progress));
@@ -27,6 +27,9 @@ Message makeAnnotationEditMessage(
 	uint8_t contextId, uint16_t id, uint32_t bg, uint8_t flags, uint8_t border,
 	const QString &text);
 
+Message
+makeAnnotationOrderMessage(uint8_t contextId, uint16_t id, uint8_t direction);
+
 Message makeAnnotationReshapeMessage(
 	uint8_t contextId, uint16_t id, int32_t x, int32_t y, uint16_t w,
 	uint16_t h);
@@ -46,6 +49,17 @@ Message makeFillRectMessage(
 	uint8_t contextId, uint16_t layer, uint8_t mode, uint32_t x, uint32_t y,
 	uint32_t w, uint32_t h, const QColor &color);
 
+Message makeFillGradientMessage(
+	uint8_t contextId, uint16_t layer, uint32_t x, uint32_t y, uint32_t w,
+	uint32_t h, uint8_t mode, uint8_t shape, uint8_t flags, int32_t x1,
+	int32_t y1, int32_t x2, int32_t y2, int32_t radius,
+	const QVector<QPair<uint16_t, QColor>> &stops);
+
+Message makeFilterRegionMessage(
+	uint8_t contextId, uint16_t layer, uint32_t x, uint32_t y, uint32_t w,
+	uint32_t h, uint8_t filter, int32_t param1, int32_t param2, int32_t param3,
+	int32_t param4);
+
 Message makeInternalCatchupMessage(uint8_t contextId, int progress);
 
 Message makeInternalCleanupMessage(uint8_t contextId);
@@ -306,7 +306,7 @@ Qt::ItemFlags SessionListingModel::flags(const QModelIndex &index) const
 		return Qt::ItemIsEnabled | Qt::ItemNeverHasChildren;
 
 	const auto &session = listing.sessions.at(index.row());
-	if(!session.protocol.isCompatible())
+	if(!session.protocol.canJoin())
 		return Qt::ItemNeverHasChildren;
 
 	return QAbstractItemModel::flags(index) | Qt::ItemNeverHasChildren;
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+#include "libclient/utils/annotations.h"
+
+#include <QColor>
+#include <QImage>
+#include <QPainter>
+#include <QtTest/QtTest>
+
+class TestAnnotationUtils final : public QObject
+{
+	Q_OBJECT
+private slots:
+	void testContentHeightGrowsWithText()
+	{
+		int oneLine = utils::annotationContentHeight(200, lines(1));
+		int tenLines = utils::annotationContentHeight(200, lines(10));
+		QVERIFY(oneLine > 0);
+		QVERIFY(tenLines > oneLine * 5);
+	}
+
+	void testContentHeightWrapsToWidth()
+	{
+		QString text = QStringLiteral(
+			"<p>The quick brown fox jumps over the lazy dog, then it jumps "
+			"over the lazy dog again and again until it gets tired.</p>");
+		int wide = utils::annotationContentHeight(1000, text);
+		int narrow = utils::annotationContentHeight(60, text);
+		QVERIFY(narrow > wide);
+	}
+
+	void testFittedHeightShowsLastLine()
+	{
+		const int width = 120;
+		const QString text = lines(12);
+		int height = utils::annotationContentHeight(width, text);
+
+		int unclipped = inkBottom(render(width, height * 2, text));
+		if(unclipped < 0) {
+			QSKIP("No fonts to render text with");
+		}
+		QVERIFY(unclipped < height);
+		QCOMPARE(inkBottom(render(width, height, text)), unclipped);
+
+		// Sanity check that cutting the height off actually loses text.
+		int cut = height - height / 4;
+		QVERIFY(inkBottom(render(width, cut, text)) < unclipped);
+	}
+
+private:
+	static QString lines(int count)
+	{
+		QString text;
+		for(int i = 0; i < count; ++i) {
+			text += QStringLiteral("<p>Line %1</p>").arg(i + 1);
+		}
+		return text;
+	}
+
+	static QImage render(int width, int height, const QString &text)
+	{
+		QImage img(width, height, QImage::Format_ARGB32_Premultiplied);
+		img.fill(0);
+		QPainter painter(&img);
+		utils::paintAnnotation(
+			&painter, img.size(), Qt::transparent, text, 0, 0);
+		return img;
+	}
+
+	// Lowest row with anything drawn on it, or -1 if the image is blank.
+	static int inkBottom(const QImage &img)
+	{
+		for(int y = img.height() - 1; y >= 0; --y) {
+			for(int x = 0; x < img.width(); ++x) {
+				if(qAlpha(img.pixel(x, y)) != 0) {
+					return y;
+				}
+			}
+		}
+		return -1;
+	}
+};
+
+
+QTEST_MAIN(TestAnnotationUtils)
+#include "annotations.moc"
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+#include "libclient/brushes/brush.h"
+
+#include <QtTest/QtTest>
+
+class TestClassicBrush final : public QObject
+{
+	Q_OBJECT
+private slots:
+	void testRoundTripEverything()
+	{
+		brushes::ClassicBrush b;
+		b.shape = DP_BRUSH_SHAPE_CLASSIC_STAMP;
+		b.falloff = DP_CLASSIC_BRUSH_FALLOFF_SMOOTHSTEP;
+		b.stamp_angle = 0.25f;
+		b.stamp_angle_mode = DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION;
+		QVERIFY(b.setStampMask(2, QByteArray("\x00\x40\x80\xff", 4)));
+		QVERIFY(b.setGrainMask(2, QByteArray("\xff\x10\x20\x30", 4)));
+		b.grain_scale = 3.5f;
+		b.grain_strength = 0.75f;
+
+		b.size.max = 42.0f;
+		b.size.min = 3.0f;
+		b.setSizeCurve(curve("0,0;0.25,0.75;1,1"));
+		b.opacity.max = 0.8f;
+		b.opacity.min = 0.125f;
+		b.setOpacityCurve(curve("0,0.5;1,1"));
+		b.hardness.max = 0.9f;
+		b.hardness.min = 0.25f;
+		b.setHardnessCurve(curve("0,0;0.5,0.25;1,1"));
+		b.smudge.max = 0.5f;
+		b.smudge.min = 0.375f;
+		b.setSmudgeCurve(curve("0,1;1,0"));
+
+		b.spacing = 0.375f;
+		b.resmudge = 3;
+		b.airbrush_rate = 30.0f;
+		b.airbrush_flow = 0.5f;
+		b.pickup_mode = DP_BRUSH_PICKUP_MODE_MERGED;
+		b.incremental = false;
+		b.colorpick = true;
+		b.pixel_perfect = true;
+
+		b.setSizeDynamicType(DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY);
+		b.setSizeMaxVelocity(7.5f);
+		b.setHardnessDynamicType(DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE);
+		b.setHardnessMaxDistance(100.0f);
+		b.setOpacityDynamicType(DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY);
+		b.setSmudgeDynamicType(DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE);
+		b.setSmudgeDynamicType(DP_CLASSIC_BRUSH_DYNAMIC_NONE);
+		b.setColorDynamicType(DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE);
+		b.setQColor2(QColor(0x80, 0x40, 0x20, 0xc0));
+
+		b.brush_mode = DP_BLEND_MODE_MULTIPLY;
+		b.erase_mode = DP_BLEND_MODE_COLOR_ERASE;
+		b.erase_target = DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR;
+		b.setEraseQColor(QColor(0x10, 0x20, 0x30));
+		b.erase = true;
+
+		b.stabilizationMode = brushes::Smoothing;
+		b.stabilizerSampleCount = 12;
+		b.smoothing = 4;
+
+		QByteArray bytes = b.toJsonBytes();
+		brushes::ClassicBrush r;
+		QString error;
+		QVERIFY2(
+			brushes::ClassicBrush::fromJsonBytes(bytes, r, &error),
+			qUtf8Printable(error));
+		QCOMPARE(r.toJsonBytes(), bytes);
+
+		QCOMPARE(r.shape, DP_BRUSH_SHAPE_CLASSIC_STAMP);
+		QCOMPARE(r.falloff, DP_CLASSIC_BRUSH_FALLOFF_SMOOTHSTEP);
+		QCOMPARE(r.stamp_angle, 0.25f);
+		QCOMPARE(r.stamp_angle_mode, DP_CLASSIC_BRUSH_ANGLE_MODE_DIRECTION);
+		QCOMPARE(r.stamp_mask, b.stamp_mask);
+		QVERIFY(r.hasStampMask());
+		QCOMPARE(r.grain, b.grain);
+		QVERIFY(r.hasGrainMask());
+		QCOMPARE(r.grain_scale, 3.5f);
+		QCOMPARE(r.grain_strength, 0.75f);
+
+		QCOMPARE(r.size.max, 42.0f);
+		QCOMPARE(r.size.min, 3.0f);
+		QVERIFY(r.sizeCurve() == b.sizeCurve());
+		QCOMPARE(r.opacity.max, 0.8f);
+		QCOMPARE(r.opacity.min, 0.125f);
+		QVERIFY(r.opacityCurve() == b.opacityCurve());
+		QCOMPARE(r.hardness.max, 0.9f);
+		QCOMPARE(r.hardness.min, 0.25f);
+		QVERIFY(r.hardnessCurve() == b.hardnessCurve());
+		QCOMPARE(r.smudge.max, 0.5f);
+		QCOMPARE(r.smudge.min, 0.375f);
+		QVERIFY(r.smudgeCurve() == b.smudgeCurve());
+		for(int i = 0; i < DP_CLASSIC_BRUSH_CURVE_VALUE_COUNT; ++i) {
+			QCOMPARE(r.size.curve.values[i], b.size.curve.values[i]);
+		}
+
+		QCOMPARE(r.spacing, 0.375f);
+		QCOMPARE(r.resmudge, 3);
+		QCOMPARE(r.airbrush_rate, 30.0f);
+		QCOMPARE(r.airbrush_flow, 0.5f);
+		QCOMPARE(r.pickup_mode, DP_BRUSH_PICKUP_MODE_MERGED);
+		QVERIFY(!r.incremental);
+		QVERIFY(r.colorpick);
+		QVERIFY(r.pixel_perfect);
+
+		QCOMPARE(r.size_dynamic.type, DP_CLASSIC_BRUSH_DYNAMIC_VELOCITY);
+		QCOMPARE(r.size_dynamic.max_velocity, 7.5f);
+		QCOMPARE(r.hardness_dynamic.type, DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE);
+		QCOMPARE(r.hardness_dynamic.max_distance, 100.0f);
+		QCOMPARE(
+			r.opacity_dynamic.type, DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE_VELOCITY);
+		QCOMPARE(r.smudge_dynamic.type, DP_CLASSIC_BRUSH_DYNAMIC_NONE);
+		QCOMPARE(
+			r.lastSmudgeDynamicType(), DP_CLASSIC_BRUSH_DYNAMIC_DISTANCE);
+		QCOMPARE(r.color_dynamic.type, DP_CLASSIC_BRUSH_DYNAMIC_PRESSURE);
+		QCOMPARE(r.qColor2(), b.qColor2());
+
+		QCOMPARE(r.brush_mode, DP_BLEND_MODE_MULTIPLY);
+		QCOMPARE(r.erase_mode, DP_BLEND_MODE_COLOR_ERASE);
+		QCOMPARE(r.erase_target, DP_CLASSIC_BRUSH_ERASE_TARGET_COLOR);
+		QCOMPARE(r.eraseQColor(), b.eraseQColor());
+		QVERIFY(r.erase);
+
+		QCOMPARE(r.stabilizationMode, brushes::Smoothing);
+		QCOMPARE(r.stabilizerSampleCount, 12);
+		QCOMPARE(r.smoothing, 4);
+	}
+
+	void testMinimalOldPreset()
+	{
+		// No version, hardly any settings and a field nobody knows about.
+		QByteArray bytes = QByteArrayLiteral(
+			"{\"type\":\"dp-classic\",\"settings\":{\"shape\":\"round-pixel\","
+			"\"size\":5,\"smudge\":0.5,\"smudgeratio\":0.25,"
+			"\"frobnicate\":true}}");
+		brushes::ClassicBrush r;
+		QString error;
+		QVERIFY2(
+			brushes::ClassicBrush::fromJsonBytes(bytes, r, &error),
+			qUtf8Printable(error));
+
+		brushes::ClassicBrush d;
+		QCOMPARE(r.shape, DP_BRUSH_SHAPE_CLASSIC_PIXEL_ROUND);
+		QCOMPARE(r.size.max, 5.0f);
+		QCOMPARE(r.size.min, 0.0f);
+		QCOMPARE(r.smudge.max, 0.5f);
+		QCOMPARE(r.smudge.min, 0.25f);
+		QCOMPARE(r.opacity.max, d.opacity.max);
+		QCOMPARE(r.hardness.max, d.hardness.max);
+		QCOMPARE(r.spacing, d.spacing);
+		QCOMPARE(r.falloff, d.falloff);
+		QCOMPARE(r.airbrush_flow, d.airbrush_flow);
+		QCOMPARE(r.grain, 0u);
+		QCOMPARE(r.grain_scale, d.grain_scale);
+		QCOMPARE(r.brush_mode, DP_BLEND_MODE_NORMAL);
+		QCOMPARE(r.erase_mode, DP_BLEND_MODE_ERASE);
+		QCOMPARE(r.erase_target, DP_CLASSIC_BRUSH_ERASE_TARGET_TRANSPARENT);
+		QVERIFY(r.incremental);
+		QVERIFY(r.sizeCurve() == d.sizeCurve());
+	}
+
+	void testRejectsBadInput()
+	{
+		brushes::ClassicBrush r;
+		QString error;
+		QVERIFY(!brushes::ClassicBrush::fromJsonBytes("{nope", r, &error));
+		QVERIFY(!error.isEmpty());
+
+		error.clear();
+		QVERIFY(!brushes::ClassicBrush::fromJsonBytes(
+			"{\"type\":\"dp-mypaint\",\"settings\":{}}", r, &error));
+		QVERIFY(!error.isEmpty());
+	}
+
+private:
+	static KisCubicCurve curve(const QString &points)
+	{
+		KisCubicCurve c;
+		c.fromString(points);
+		return c;
+	}
+};
+
+
+QTEST_MAIN(TestClassicBrush)
+#include "classicbrush.moc"
@@ -14,15 +14,20 @@ public:
 	Task(
 		FloodFill *tool, const QAtomicInt &cancel,
 		const drawdance::CanvasState &canvasState, const QPointF &point,
-		const QColor &fillColor, double tolerance, int sourceLayerId, int size,
-		int gap, int expansion, int featherRadius, int targetLayerId,
-		DP_ViewMode viewMode, int activeLayerId, int activeFrameIndex)
+		const QColor &fillColor, const QImage &pattern, bool erase,
+		double tolerance, DP_FloodFillSample sample,
+		int sourceLayerId, int size, int gap, int expansion, int featherRadius,
+		int targetLayerId, DP_ViewMode viewMode, int activeLayerId,
+		int activeFrameIndex)
 		: m_tool{tool}
 		, m_cancel{cancel}
 		, m_canvasState{canvasState}
 		, m_point{point}
 		, m_fillColor{fillColor}
+		, m_pattern{pattern}
+		, m_erase{erase}
 		, m_tolerance{tolerance}
+		, m_sample{sample}
 		, m_sourceLayerId{sourceLayerId}
 		, m_size{size}
 		, m_gap{gap}
@@ -38,9 +43,11 @@ public:
 	void run() override
 	{
 		m_result = m_canvasState.floodFill(
-			m_point.x(), m_point.y(), m_fillColor, m_tolerance, m_sourceLayerId,
-			m_size, m_gap, m_expansion, m_featherRadius, m_viewMode,
-			m_activeLayerId, m_activeFrameIndex, m_cancel, m_img, m_x, m_y);
+			m_point.x(), m_point.y(), m_fillColor, m_pattern, m_erase,
+			m_tolerance, m_sample,
+			m_sourceLayerId, m_size, m_gap, m_expansion, m_featherRadius,
+			m_viewMode, m_activeLayerId, m_activeFrameIndex, m_cancel, m_img,
+			m_x, m_y);
 	}
 
 	void finished() override { m_tool->floodFillFinished(this); }
@@ -57,7 +64,10 @@ private:
 	drawdance::CanvasState m_canvasState;
 	QPointF m_point;
 	QColor m_fillColor;
+	QImage m_pattern;
+	bool m_erase;
 	double m_tolerance;
+	DP_FloodFillSample m_sample;
 	int m_sourceLayerId;
 	int m_size;
 	int m_gap;
@@ -82,6 +92,7 @@ FloodFill::FloodFill(ToolController &owner)
 	, m_featherRadius(0)
 	, m_size(500)
 	, m_gap{0}
+	, m_sample{DP_FLOOD_FILL_SAMPLE_MERGED}
 	, m_layerId{0}
 	, m_blendMode(DP_BLEND_MODE_NORMAL)
 	, m_running{false}
@@ -96,20 +107,37 @@ void FloodFill::begin(const canvas::Point &point, bool right, float zoom)
 		cancelMultipart();
 	} else if(!m_running) {
 		canvas::CanvasModel *model = m_owner.model();
-		QColor fillColor = m_blendMode == DP_BLEND_MODE_ERASE
-							   ? Qt::black
-							   : m_owner.activeBrush().qColor();
+		bool erase = m_blendMode == DP_BLEND_MODE_ERASE;
+		QColor fillColor = erase ? Qt::black : m_owner.activeBrush().qColor();
 		m_running = true;
 		m_cancel = false;
 		canvas::PaintEngine *paintEngine = model->paintEngine();
 		m_owner.executeAsync(new Task{
 			this, m_cancel, paintEngine->viewCanvasState(), point, fillColor,
-			m_tolerance, m_layerId, m_size, m_gap, m_expansion, m_featherRadius,
-			m_owner.activeLayer(), paintEngine->viewMode(),
+			m_pattern, erase, m_tolerance, m_sample, m_layerId, m_size, m_gap, m_expansion,
+			m_featherRadius, m_owner.activeLayer(), paintEngine->viewMode(),
 			paintEngine->viewLayer(), paintEngine->viewFrame()});
 	}
 }
 
+void FloodFill::setPattern(const QImage &pattern)
+{
+	if(pattern.isNull()) {
+		m_pattern = QImage{};
+	} else {
+		// The engine would crop oversized patterns, scaling them down keeps
+		// all of it instead. Either way, the fill result gets sent as an
+		// image, so other users don't need the pattern to reproduce it.
+		int maxSize = DP_FLOOD_FILL_PATTERN_MAX_SIZE;
+		QImage img = pattern.width() > maxSize || pattern.height() > maxSize
+						 ? pattern.scaled(
+							   maxSize, maxSize, Qt::KeepAspectRatio,
+							   Qt::SmoothTransformation)
+						 : pattern;
+		m_pattern = img.convertToFormat(QImage::Format_ARGB32_Premultiplied);
+	}
+}
+
 void FloodFill::motion(const canvas::Point &point, bool constrain, bool center)
 {
 	Q_UNUSED(point);
@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 #ifndef TOOLS_FLOODFILL_H
 #define TOOLS_FLOODFILL_H
+extern "C" {
+#include <dpengine/flood_fill.h>
+}
 #include "libclient/tools/tool.h"
 #include <QAtomicInt>
 #include <QCoreApplication>
+#include <QImage>
 
 namespace tools {
 
@@ -26,8 +30,12 @@ public:
 	}
 	void setSize(int size) { m_size = size; }
 	void setGap(int gap) { m_gap = gap; }
+	void setSample(DP_FloodFillSample sample) { m_sample = sample; }
 	void setLayerId(int layerId) { m_layerId = layerId; }
 	void setBlendMode(int blendMode) { m_blendMode = blendMode; }
+	// Fills with the given pattern instead of the brush color, unless it's
+	// a null image. Has no effect when erasing.
+	void setPattern(const QImage &pattern);
 
 private:
 	class Task;
@@ -40,8 +48,10 @@ private:
 	int m_featherRadius;
 	int m_size;
 	int m_gap;
+	DP_FloodFillSample m_sample;
 	int m_layerId;
 	int m_blendMode;
+	QImage m_pattern;
 	bool m_running;
 	QAtomicInt m_cancel;
 };
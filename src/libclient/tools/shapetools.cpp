@@ -13,51 +13,6 @@
 
 namespace tools {
 
-class PointVectorGenerator {
-public:
-	PointVectorGenerator()
-		: m_time{0}
-		, m_pv{}
-	{
-	}
-
-	void append(const QPointF &point)
-	{
-		if(!m_pv.isEmpty()) {
-			// MyPaint brushes don't take kindly to unnatural delta times. Very
-			// short ones cause weird brush behavior and long ones (5 seconds+)
-			// cause them to not draw the stroke at all. So we interpolate.
-			QLineF line{m_pv.last(), point};
-			int segments = qCeil(line.length() / SEGMENT_LENGTH);
-			for(int i = 1; i < segments; ++i) {
-				appendPoint(line.pointAt(1.0 / qreal(segments) * qreal(i)));
-			}
-		}
-		appendPoint(point);
-	}
-
-	const canvas::PointVector &pv()
-	{
-		return m_pv;
-	}
-
-private:
-	static constexpr qreal SEGMENT_LENGTH = 10.0;
-	static constexpr qreal DTIME = 10.0;
-
-	void appendPoint(const QPointF &point)
-	{
-		canvas::Point cp{m_time, point, 1.0};
-		if(!m_pv.isEmpty()) {
-			m_time += canvas::Point::distance(m_pv.last(), cp) * DTIME;
-		}
-		m_pv.append(cp);
-	}
-
-	long long m_time;
-	canvas::PointVector m_pv;
-};
-
 void ShapeTool::begin(const canvas::Point& point, bool right, float zoom)
 {
 	Q_ASSERT(!m_drawing);
@@ -111,12 +66,10 @@ void ShapeTool::end()
 
 	m_owner.setBrushEngineBrush(m_brushEngine, false);
 
-	const canvas::PointVector pv = pointVector();
 	m_brushEngine.beginStroke(client->myId(), true, m_zoom);
-	for(const canvas::Point &p : pv) {
-		m_brushEngine.strokeTo(p, canvasState);
-	}
-	m_brushEngine.endStroke(pv.last().timeMsec() + 10, canvasState, true);
+	long long timeMsec = m_brushEngine.strokeShape(
+		strokeShape(), m_p1, m_p2, false, 0, canvasState);
+	m_brushEngine.endStroke(timeMsec + 10, canvasState, true);
 
 	paintEngine->clearDabsPreview();
 	m_brushEngine.sendMessagesTo(client);
@@ -128,13 +81,10 @@ void ShapeTool::updatePreview()
 	canvas::PaintEngine *paintEngine = m_owner.model()->paintEngine();
 	drawdance::CanvasState canvasState = paintEngine->sampleCanvasState();
 
-	const canvas::PointVector pv = pointVector();
-	Q_ASSERT(pv.count() > 1);
 	m_brushEngine.beginStroke(0, false, m_zoom);
-	for(const canvas::Point &p : pv) {
-		m_brushEngine.strokeTo(p, canvasState);
-	}
-	m_brushEngine.endStroke(pv.last().timeMsec() + 10, canvasState, false);
+	long long timeMsec = m_brushEngine.strokeShape(
+		strokeShape(), m_p1, m_p2, false, 0, canvasState);
+	m_brushEngine.endStroke(timeMsec + 10, canvasState, false);
 
 	paintEngine->previewDabs(m_owner.activeLayer(), m_brushEngine.messages());
 	m_brushEngine.clearMessages();
@@ -163,12 +113,9 @@ void Line::motion(const canvas::Point& point, bool constrain, bool center)
 	updatePreview();
 }
 
-canvas::PointVector Line::pointVector() const
+DP_StrokeShape Line::strokeShape() const
 {
-	PointVectorGenerator gen;
-	gen.append(m_p1);
-	gen.append(m_p2);
-	return gen.pv();
+	return DP_STROKE_SHAPE_LINE;
 }
 
 Rectangle::Rectangle(ToolController &owner)
@@ -176,15 +123,9 @@ Rectangle::Rectangle(ToolController &owner)
 {
 }
 
-canvas::PointVector Rectangle::pointVector() const
+DP_StrokeShape Rectangle::strokeShape() const
 {
-	PointVectorGenerator gen;
-	gen.append(m_p1);
-	gen.append(QPointF{m_p1.x(), m_p2.y()});
-	gen.append(m_p2);
-	gen.append(QPointF{m_p2.x(), m_p1.y()});
-	gen.append(QPointF{m_p1.x(), m_p1.y()});
-	return gen.pv();
+	return DP_STROKE_SHAPE_RECTANGLE;
 }
 
 Ellipse::Ellipse(ToolController &owner)
@@ -192,30 +133,9 @@ Ellipse::Ellipse(ToolController &owner)
 {
 }
 
-canvas::PointVector Ellipse::pointVector() const
+DP_StrokeShape Ellipse::strokeShape() const
 {
-	const auto r = rect();
-	const qreal a = r.width() / 2.0;
-	const qreal b = r.height() / 2.0;
-	const qreal cx = r.x() + a;
-	const qreal cy = r.y() + b;
-	PointVectorGenerator gen;
-
-	qreal radius = (a + b) / 2.0;
-	qreal step;
-	if (radius > 2.0) {
-		qreal t = 1.0 - 0.33 / radius;
-		step = std::acos(2.0 * t * t - 1.0);
-	} else {
-		step = M_PI / 4.0;
-	}
-
-	for(qreal t = 0.0; t < 2.0 * M_PI; t += step) {
-		gen.append(QPointF{cx + a*cos(t), cy + b*sin(t)});
-	}
-	gen.append(QPointF{cx+a, cy});
-
-	return gen.pv();
+	return DP_STROKE_SHAPE_ELLIPSE;
 }
 
 }
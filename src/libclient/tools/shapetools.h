@@ -6,8 +6,6 @@
 #include "libclient/tools/tool.h"
 #include "libclient/drawdance/brushengine.h"
 
-#include <QRectF>
-
 namespace tools {
 
 /**
@@ -28,9 +26,8 @@ public:
 	bool usesBrushColor() const override { return true; }
 
 protected:
-	virtual canvas::PointVector pointVector() const = 0;
+	virtual DP_StrokeShape strokeShape() const = 0;
 	void updatePreview();
-	QRectF rect() const { return QRectF(m_p1, m_p2).normalized(); }
 
 	QPointF m_start, m_p1, m_p2;
 	bool m_drawing;
@@ -52,7 +49,7 @@ public:
 	void motion(const canvas::Point& point, bool constrain, bool center) override;
 
 protected:
-	canvas::PointVector pointVector() const override;
+	DP_StrokeShape strokeShape() const override;
 };
 
 /**
@@ -65,7 +62,7 @@ public:
 	Rectangle(ToolController &owner);
 
 protected:
-	canvas::PointVector pointVector() const override;
+	DP_StrokeShape strokeShape() const override;
 };
 
 /**
@@ -78,7 +75,7 @@ public:
 	Ellipse(ToolController &owner);
 
 protected:
-	canvas::PointVector pointVector() const override;
+	DP_StrokeShape strokeShape() const override;
 };
 
 }
@@ -429,6 +429,8 @@ void ToolController::setBrushEngineBrush(
 		m_stabilizationMode != brushes::Smoothing || m_finishStrokes,
 		0,
 		m_finishStrokes,
+		0,
+		{DP_SYMMETRY_NONE, 0.0f, 0.0f, 0},
 	};
 	if(freehand) {
 		stroke.interpolate = m_interpolateInputs;
@@ -1,19 +1,48 @@
 #include "libclient/utils/annotations.h"
 #include <QPainter>
 #include <QTextDocument>
+#include <QtMath>
 
 namespace utils {
 
+// Rendering and measuring must lay out the text identically, otherwise an
+// auto-grown annotation still cuts off its last line.
+static void layOutAnnotationText(
+	QTextDocument &doc, const QString &text, qreal width)
+{
+	doc.setHtml(text);
+	doc.setTextWidth(width);
+}
+
+int annotationContentHeight(int width, const QString &text)
+{
+	QTextDocument doc;
+	layOutAnnotationText(doc, text, width);
+	return qCeil(doc.size().height());
+}
+
 void paintAnnotation(
 	QPainter *painter, const QSize &size, const QColor &background,
-	const QString &text, int valign)
+	const QString &text, int valign, int border)
 {
 	QRectF rect(QPointF(), size);
 	painter->fillRect(rect, background);
 
+	if(border > 0) {
+		QColor borderColor = background;
+		borderColor.setAlpha(255);
+		qreal half = border / 2.0;
+		QPen pen(borderColor, border);
+		pen.setJoinStyle(Qt::MiterJoin);
+		painter->save();
+		painter->setPen(pen);
+		painter->setBrush(Qt::NoBrush);
+		painter->drawRect(rect.adjusted(half, half, -half, -half));
+		painter->restore();
+	}
+
 	QTextDocument doc;
-	doc.setHtml(text);
-	doc.setTextWidth(rect.width());
+	layOutAnnotationText(doc, text, rect.width());
 
 	QPointF offset;
 	switch(valign) {
@@ -6,8 +6,12 @@ class QString;
 
 namespace utils {
 
+// Height needed to show all of the given text at the given width, laid out the
+// same way as paintAnnotation does it.
+int annotationContentHeight(int width, const QString &text);
+
 void paintAnnotation(
 	QPainter *painter, const QSize &size, const QColor &background,
-	const QString &text, int valign);
+	const QString &text, int valign, int border);
 
 }
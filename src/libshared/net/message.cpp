@@ -272,6 +272,8 @@ bool Message::shouldSmoothe() const
 	case DP_MSG_DRAW_DABS_PIXEL:
 	case DP_MSG_DRAW_DABS_PIXEL_SQUARE:
 	case DP_MSG_DRAW_DABS_MYPAINT:
+	case DP_MSG_DRAW_DABS_STAMP:
+	case DP_MSG_DRAW_DABS_SOFT:
 	case DP_MSG_MOVE_POINTER:
 		return true;
 	default:
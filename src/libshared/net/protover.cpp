@@ -97,6 +97,14 @@ bool ProtocolVersion::isPastCompatible() const
 	return DP_protocol_version_is_past_compatible(m_protocolVersion);
 }
 
+bool ProtocolVersion::canJoin() const
+{
+	DP_ProtocolVersion *current = DP_protocol_version_new_current();
+	bool result = DP_protocol_version_can_join(current, m_protocolVersion);
+	DP_protocol_version_free(current);
+	return result;
+}
+
 bool ProtocolVersion::shouldHaveSystemId() const
 {
 	return DP_protocol_version_should_have_system_id(m_protocolVersion);
@@ -69,9 +69,9 @@ public:
 	bool isPastCompatible() const;
 
 	/**
-	 * Is this some kind of compatible recording?
+	 * Can this build join a session hosted with this protocol version?
 	 */
-	bool isCompatible() const { return isCurrent() || isPastCompatible(); }
+	bool canJoin() const;
 
 	bool shouldHaveSystemId() const;
 
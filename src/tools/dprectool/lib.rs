@@ -2,7 +2,7 @@
 use anyhow::Result;
 use drawdance::{
     dp_cmake_config_version,
-    engine::{Player, Recorder},
+    engine::{filter_recording, FilterOptions, Player, Recorder},
     msg::Message,
     DP_MessageType, DP_PlayerType, DP_RecorderType, DP_PLAYER_BACKWARD_COMPATIBLE,
     DP_PLAYER_COMPATIBLE, DP_PLAYER_MINOR_INCOMPATIBILITY, DP_PLAYER_TYPE_BINARY,
@@ -138,6 +138,17 @@ pub extern "C" fn dprectool_main() -> c_int {
         /// that they didn't have permission to draw on. The Drawpile client
         /// would also filter these out when playing back a recording.
         optional -A,--acl
+        /// Removes actions that ended up undone, along with all undos and
+        /// redos. The result replays to the same canvas. Doesn't work when
+        /// reading from stdin, since the input has to be read twice.
+        optional --remove-undone
+        /// Removes chat messages and server chatter.
+        optional --remove-chat
+        /// Removes laser trails and pointer movements.
+        optional --remove-lasers
+        /// Merges consecutive intervals and caps them at the given number of
+        /// milliseconds, to cut down on long pauses during playback.
+        optional --max-interval max_interval: u16
         /// Print message frequency table and exit.
         optional --msg-freq
         /// Input recording file.
@@ -197,6 +208,17 @@ pub extern "C" fn dprectool_main() -> c_int {
         return 2;
     }
 
+    let filter_options = FilterOptions {
+        remove_undone: flags.remove_undone,
+        remove_chat: flags.remove_chat,
+        remove_lasers: flags.remove_lasers,
+        max_interval_ms: flags.max_interval.filter(|ms| *ms > 0),
+    };
+    if filter_options.remove_undone && input_path == "-" {
+        eprintln!("Can't remove undone actions when reading from stdin");
+        return 2;
+    }
+
     match convert_recording(
         input_format,
         input_path,
@@ -204,6 +226,7 @@ pub extern "C" fn dprectool_main() -> c_int {
         output_path,
         output_path_is_default,
         acl_override,
+        &filter_options,
     ) {
         Ok(_) => 0,
         Err(e) => {
@@ -293,6 +316,7 @@ fn convert_recording(
     output_path: String,
     output_path_is_default: bool,
     acl_override: bool,
+    filter_options: &FilterOptions,
 ) -> Result<()> {
     let mut player = make_player(input_format, input_path).and_then(Player::check_compatible)?;
 
@@ -304,10 +328,14 @@ fn convert_recording(
     }?;
 
     player.set_acl_override(acl_override);
-    while let Some(msg) = player.step()? {
-        if !recorder.push_noinc(msg) {
-            break;
+    if *filter_options == FilterOptions::default() {
+        while let Some(msg) = player.step()? {
+            if !recorder.push_noinc(msg) {
+                break;
+            }
         }
+    } else {
+        filter_recording(&mut player, &mut recorder, filter_options)?;
     }
 
     recorder.dispose()?;
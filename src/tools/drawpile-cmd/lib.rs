@@ -2,12 +2,13 @@
 use anyhow::Result;
 use drawdance::{
     dp_cmake_config_version,
-    engine::{PaintEngine, Player},
+    engine::{DrawContext, FlattenOptions, PaintEngine, Player},
     DP_PLAYER_TYPE_GUESS, DP_PROTOCOL_VERSION,
 };
 use std::{
     ffi::{c_int, CStr, OsStr},
     fs::metadata,
+    io::{self, Write},
     path::Path,
     str::FromStr,
 };
@@ -259,6 +260,7 @@ fn get_dir_prefix(path: &Path) -> String {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn dump_recordings(
     input_paths: &Vec<String>,
     acl_override: bool,
@@ -286,6 +288,7 @@ fn dump_recordings(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn dump_recording(
     index: &mut i32,
     input_path: &String,
@@ -352,18 +355,31 @@ fn write_flat_image(
     format: OutputFormat,
     path: &str,
 ) -> Result<()> {
+    let options = FlattenOptions::default();
     let result = if let Some(ImageSize { width, height }) = max_size {
         if fixed_size {
-            pe.to_scaled_image(width, height, true)
-        } else if pe.render_width() <= width && pe.render_height() < height {
-            pe.to_image()
+            pe.to_scaled_image(&options, width, height, true)
         } else {
-            pe.to_scaled_image(width, height, false)
+            pe.to_image(&options).and_then(|img| {
+                if img.width() <= width && img.height() < height {
+                    Ok(img)
+                } else {
+                    img.to_scaled(width, height, false, &mut DrawContext::default())
+                }
+            })
         }
     } else {
-        pe.to_image()
+        pe.to_image(&options)
     };
     match result {
+        Ok(img) if path == "-" => {
+            let bytes = match format {
+                OutputFormat::Png => img.to_png()?,
+                OutputFormat::Jpg | OutputFormat::Jpeg => img.to_jpeg()?,
+                _ => panic!("Unhandled output format"),
+            };
+            io::stdout().write_all(&bytes)?;
+        }
         Ok(img) => match format {
             OutputFormat::Png => img.write_png(path)?,
             OutputFormat::Jpg | OutputFormat::Jpeg => img.write_jpeg(path)?,
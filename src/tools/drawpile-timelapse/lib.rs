@@ -2,7 +2,7 @@
 use anyhow::{anyhow, Result};
 use drawdance::{
     dp_cmake_config_version,
-    engine::{Image, PaintEngine, Player},
+    engine::{FlattenOptions, Image, PaintEngine, Player},
     DP_UPixel8, DP_PLAYER_TYPE_GUESS, DP_PROTOCOL_VERSION,
 };
 use regex::Regex;
@@ -157,8 +157,11 @@ impl FromStr for LogoLocation {
     }
 }
 
+/// # Safety
+///
+/// `default_logo_path` must point to a nul-terminated string.
 #[no_mangle]
-pub extern "C" fn drawpile_timelapse_main(default_logo_path: *const c_char) -> c_int {
+pub unsafe extern "C" fn drawpile_timelapse_main(default_logo_path: *const c_char) -> c_int {
     drawdance::init();
 
     let flags = xflags::parse_or_exit! {
@@ -428,6 +431,7 @@ pub extern "C" fn drawpile_timelapse_main(default_logo_path: *const c_char) -> c
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn make_timelapse_command(
     mut command: Command,
     input_paths: &Vec<String>,
@@ -473,6 +477,7 @@ fn make_timelapse_command(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn make_timelapse_raw(
     path: &String,
     input_paths: &Vec<String>,
@@ -518,6 +523,7 @@ impl<'a> TimelapseContext<'a> {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn timelapse(
     writer: &mut dyn io::Write,
     input_paths: &Vec<String>,
@@ -569,6 +575,7 @@ fn timelapse_recording(
     let mut pe = PaintEngine::new(Some(player));
     pe.begin_playback()?;
 
+    let options = FlattenOptions::default();
     let mut initial = true;
     loop {
         let pos = if initial {
@@ -578,7 +585,7 @@ fn timelapse_recording(
         }?;
 
         pe.render();
-        match pe.to_scaled_image(width, height, true) {
+        match pe.to_scaled_image(&options, width, height, true) {
             Ok(img) => {
                 ctx.push(img)?;
                 initial = false;